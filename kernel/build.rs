@@ -12,12 +12,33 @@ fn main() {
     println!("cargo:rerun-if-changed=src/include/bindings/wrapper.h");
 
     generate_bindings();
-    CFilesBuilder::build();
+    // 目前只有x86_64架构有需要由cc编译的c文件
+    if target_arch() == "x86_64" {
+        CFilesBuilder::build();
+    }
+}
+
+/// 内核的目标架构。
+///
+/// build.rs本身是为宿主机编译的，因此不能用`#[cfg(target_arch)]`判断内核的目标架构，
+/// 而要读取cargo传入的`CARGO_CFG_TARGET_ARCH`环境变量
+fn target_arch() -> String {
+    std::env::var("CARGO_CFG_TARGET_ARCH").expect("CARGO_CFG_TARGET_ARCH is not set")
+}
+
+/// 传给clang的目标三元组
+fn clang_target(arch: &str) -> &'static str {
+    match arch {
+        "x86_64" => "--target=x86_64-none-none",
+        "riscv64" => "--target=riscv64-unknown-none-elf",
+        _ => panic!("Unsupported target arch: {}", arch),
+    }
 }
 
 fn generate_bindings() {
     // let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let out_path = PathBuf::from(String::from("src/include/bindings/"));
+    let arch = target_arch();
 
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
@@ -26,12 +47,13 @@ fn generate_bindings() {
         let bindings = bindgen::Builder::default()
             .clang_arg("-I./src")
             .clang_arg("-I./src/include")
-            .clang_arg("-I./src/arch/x86_64/include") // todo: 当引入多种架构之后，需要修改这里，对于不同的架构编译时，include不同的路径
+            // 不同的架构include不同的路径。注意：c头文件依赖的asm/asm.h等文件目前只有x86_64架构的版本
+            .clang_arg(format!("-I./src/arch/{}/include", arch))
             // The input header we would like to generate
             // bindings for.
             .header("src/include/bindings/wrapper.h")
             .blocklist_file("src/include/bindings/bindings.h")
-            .clang_arg("--target=x86_64-none-none")
+            .clang_arg(clang_target(&arch))
             .clang_arg("-v")
             // 使用core，并将c语言的类型改为core::ffi，而不是使用std库。
             .use_core()
@@ -86,10 +108,7 @@ impl CFilesBuilder {
             c.define("EMULATOR", "__NO_EMULATION__");
         }
 
-        {
-            #[cfg(target_arch = "x86_64")]
-            c.define("__x86_64__", None);
-        }
+        c.define("__x86_64__", None);
 
         c.define("PIC", "_INTR_APIC_");
     }
@@ -98,8 +117,6 @@ impl CFilesBuilder {
         c.include("src/include");
         c.include("src");
         c.include(".");
-
-        #[cfg(target_arch = "x86_64")]
         c.include("src/arch/x86_64/include");
     }
 
@@ -0,0 +1,31 @@
+use crate::arch::io::PortIOArch;
+
+/// AArch64没有端口I/O，外设都通过MMIO访问。这里的实现只是为了满足通用代码的接口：
+/// 与访问不存在的设备的端口一样，读取时返回全1，写入时什么也不做
+pub struct AArch64PortIOArch;
+
+impl PortIOArch for AArch64PortIOArch {
+    #[inline(always)]
+    unsafe fn in8(_port: u16) -> u8 {
+        return u8::MAX;
+    }
+
+    #[inline(always)]
+    unsafe fn in16(_port: u16) -> u16 {
+        return u16::MAX;
+    }
+
+    #[inline(always)]
+    unsafe fn in32(_port: u16) -> u32 {
+        return u32::MAX;
+    }
+
+    #[inline(always)]
+    unsafe fn out8(_port: u16, _data: u8) {}
+
+    #[inline(always)]
+    unsafe fn out16(_port: u16, _data: u16) {}
+
+    #[inline(always)]
+    unsafe fn out32(_port: u16, _data: u32) {}
+}
@@ -0,0 +1,46 @@
+use core::{arch::asm, hint::spin_loop};
+
+use crate::{arch::CurrentIrqArch, exception::InterruptArch};
+
+/// 获取当前cpu的逻辑id
+///
+/// MPIDR_EL1中的亲和性值不一定是连续的，因此在内核中用TPIDR_EL1保存当前cpu的逻辑id(引导核为0)
+#[inline]
+pub fn current_cpu_id() -> u32 {
+    let id: usize;
+    unsafe { asm!("mrs {}, tpidr_el1", out(reg) id, options(nomem, nostack, preserves_flags)) };
+    return id as u32;
+}
+
+/// 重置cpu
+///
+/// TODO: 通过PSCI的SYSTEM_RESET重启
+pub fn cpu_reset() -> ! {
+    cpu_halt();
+}
+
+/// 默认的空闲方式：等待中断，然后打开中断
+///
+/// 调用时中断必须已经关闭。即使PSTATE.I被置位，WFI也会被挂起的中断唤醒，因此不会错过唤醒
+#[inline]
+pub fn arch_cpu_idle() {
+    unsafe { asm!("wfi", options(nomem, nostack)) };
+    unsafe { CurrentIrqArch::interrupt_enable() };
+}
+
+/// 获取当前函数的帧指针(x29)，用于回溯调用栈
+#[inline(always)]
+pub fn current_frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { asm!("mov {}, x29", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+    return fp;
+}
+
+/// 停止当前cpu的运行
+pub fn cpu_halt() -> ! {
+    unsafe { CurrentIrqArch::interrupt_disable() };
+    loop {
+        unsafe { asm!("wfi", options(nomem, nostack)) };
+        spin_loop();
+    }
+}
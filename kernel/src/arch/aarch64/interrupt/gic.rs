@@ -0,0 +1,134 @@
+//! GICv2中断控制器
+//!
+//! 只实现了分发器与cpu接口的基本操作，足够使能私有外设中断(如通用定时器)。
+//! 寄存器的基地址来自设备树中兼容`arm,cortex-a15-gic`或`arm,gic-400`的节点。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/irqchip/irq-gic.c
+
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::{
+    arch::MMArch,
+    driver::open_firmware::of_find_compatible_node,
+    kinfo,
+    mm::{MemoryManagementArch, PhysAddr, VirtAddr},
+    syscall::SystemError,
+};
+
+/// 设备树中GICv2的compatible
+const GIC_COMPATIBLE: [&str; 2] = ["arm,cortex-a15-gic", "arm,gic-400"];
+
+/// 分发器控制寄存器
+const GICD_CTLR: usize = 0x000;
+/// 中断使能寄存器，每个寄存器对应32个中断
+const GICD_ISENABLER: usize = 0x100;
+/// 中断禁止寄存器
+const GICD_ICENABLER: usize = 0x180;
+
+/// cpu接口控制寄存器
+const GICC_CTLR: usize = 0x000;
+/// 优先级屏蔽寄存器
+const GICC_PMR: usize = 0x004;
+/// 中断确认寄存器
+const GICC_IAR: usize = 0x00c;
+/// 中断结束寄存器
+const GICC_EOIR: usize = 0x010;
+
+/// 没有挂起的中断时，GICC_IAR返回的中断号
+pub const GIC_SPURIOUS_IRQ: u32 = 1023;
+
+#[derive(Debug, Clone, Copy)]
+struct Gic {
+    dist_base: VirtAddr,
+    cpu_base: VirtAddr,
+}
+
+impl Gic {
+    unsafe fn dist_write(&self, offset: usize, value: u32) {
+        write_volatile((self.dist_base.data() + offset) as *mut u32, value);
+    }
+
+    unsafe fn cpu_read(&self, offset: usize) -> u32 {
+        return read_volatile((self.cpu_base.data() + offset) as *const u32);
+    }
+
+    unsafe fn cpu_write(&self, offset: usize, value: u32) {
+        write_volatile((self.cpu_base.data() + offset) as *mut u32, value);
+    }
+}
+
+static mut GIC: Option<Gic> = None;
+
+fn gic() -> Option<Gic> {
+    unsafe { GIC }
+}
+
+/// 从设备树中找到GIC，使能分发器与当前cpu的接口
+///
+/// 需要在设备树展开之后调用
+///
+/// ## 错误
+///
+/// - `ENODEV`：设备树中没有GICv2
+pub fn gic_init() -> Result<(), SystemError> {
+    let node = GIC_COMPATIBLE
+        .iter()
+        .find_map(|c| of_find_compatible_node(c))
+        .ok_or(SystemError::ENODEV)?;
+    let reg = node.reg();
+    if reg.len() < 2 {
+        return Err(SystemError::ENODEV);
+    }
+    // TODO: 通过mmio_pool映射寄存器，而不是依赖物理内存的直接映射
+    let map = |paddr: u64| unsafe { MMArch::phys_2_virt(PhysAddr::new(paddr as usize)) };
+    let gic = Gic {
+        dist_base: map(reg[0].0).ok_or(SystemError::EINVAL)?,
+        cpu_base: map(reg[1].0).ok_or(SystemError::EINVAL)?,
+    };
+
+    unsafe {
+        gic.dist_write(GICD_CTLR, 1);
+        // 不屏蔽任何优先级的中断
+        gic.cpu_write(GICC_PMR, 0xff);
+        gic.cpu_write(GICC_CTLR, 1);
+        GIC = Some(gic);
+    }
+    kinfo!("gic: GICv2 at {}", node.full_name());
+    return Ok(());
+}
+
+/// 使能中断
+pub fn gic_enable_irq(irq: u32) {
+    if let Some(gic) = gic() {
+        let irq = irq as usize;
+        unsafe { gic.dist_write(GICD_ISENABLER + (irq / 32) * 4, 1 << (irq % 32)) };
+    }
+}
+
+/// 禁止中断
+#[allow(dead_code)]
+pub fn gic_disable_irq(irq: u32) {
+    if let Some(gic) = gic() {
+        let irq = irq as usize;
+        unsafe { gic.dist_write(GICD_ICENABLER + (irq / 32) * 4, 1 << (irq % 32)) };
+    }
+}
+
+/// 确认当前cpu上优先级最高的挂起中断，返回它的中断号
+///
+/// 没有挂起的中断时返回[`GIC_SPURIOUS_IRQ`]
+#[allow(dead_code)]
+pub fn gic_ack() -> u32 {
+    match gic() {
+        Some(gic) => unsafe { gic.cpu_read(GICC_IAR) & 0x3ff },
+        None => GIC_SPURIOUS_IRQ,
+    }
+}
+
+/// 通知GIC中断处理完毕
+#[allow(dead_code)]
+pub fn gic_eoi(irq: u32) {
+    if let Some(gic) = gic() {
+        unsafe { gic.cpu_write(GICC_EOIR, irq) };
+    }
+}
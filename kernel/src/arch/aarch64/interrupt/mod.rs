@@ -0,0 +1,92 @@
+pub mod gic;
+
+use core::{
+    arch::asm,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+use crate::exception::{InterruptArch, IrqFlags, IrqFlagsGuard};
+
+/// DAIF中的I位，置位时屏蔽IRQ
+const DAIF_I: usize = 1 << 7;
+/// SPSR_EL1中表示陷入之前的异常级别与栈指针选择的位
+const SPSR_MODE_MASK: usize = 0xf;
+/// EL0，使用SP_EL0
+const SPSR_MODE_EL0T: usize = 0;
+
+pub struct AArch64InterruptArch;
+
+impl InterruptArch for AArch64InterruptArch {
+    unsafe fn interrupt_enable() {
+        asm!("msr daifclr, #2", options(nomem, nostack));
+    }
+
+    unsafe fn interrupt_disable() {
+        asm!("msr daifset, #2", options(nomem, nostack));
+    }
+
+    fn is_irq_enabled() -> bool {
+        return read_daif() & DAIF_I == 0;
+    }
+
+    unsafe fn save_and_disable_irq() -> IrqFlagsGuard {
+        compiler_fence(Ordering::SeqCst);
+        let daif = read_daif();
+        Self::interrupt_disable();
+        let guard = IrqFlagsGuard::new(IrqFlags::new(daif & DAIF_I));
+        compiler_fence(Ordering::SeqCst);
+        return guard;
+    }
+
+    unsafe fn restore_irq(flags: IrqFlags) {
+        compiler_fence(Ordering::SeqCst);
+        if flags.flags() & DAIF_I == 0 {
+            Self::interrupt_enable();
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+#[inline(always)]
+fn read_daif() -> usize {
+    let daif: usize;
+    unsafe { asm!("mrs {}, daif", out(reg) daif, options(nomem, nostack, preserves_flags)) };
+    return daif;
+}
+
+/// 中断栈帧结构体
+///
+/// TODO: 异常向量表尚未实现，这里只确定了栈帧的布局
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TrapFrame {
+    /// x0~x30
+    pub regs: [usize; 31],
+    /// 用户态的栈指针
+    pub sp_el0: usize,
+    /// 异常返回地址
+    pub elr: usize,
+    /// 陷入之前的PSTATE
+    pub spsr: usize,
+}
+
+impl TrapFrame {
+    pub fn new() -> Self {
+        Self {
+            regs: [0; 31],
+            sp_el0: 0,
+            elr: 0,
+            spsr: 0,
+        }
+    }
+
+    /// 设置中断栈帧返回值
+    pub fn set_return_value(&mut self, value: usize) {
+        self.regs[0] = value;
+    }
+
+    /// 判断当前中断是否来自用户模式
+    pub fn from_user(&self) -> bool {
+        return self.spsr & SPSR_MODE_MASK == SPSR_MODE_EL0T;
+    }
+}
@@ -0,0 +1,240 @@
+use core::arch::asm;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::arch::MMArch;
+use crate::init::boot_info::{boot_info, BootMemoryType};
+use crate::libs::spinlock::SpinLock;
+use crate::mm::allocator::buddy::BuddyAllocator;
+use crate::mm::allocator::page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage};
+use crate::mm::page::PageFlags;
+use crate::mm::{MemoryManagementArch, PageTableKind, PhysAddr, PhysMemoryArea, VirtAddr};
+use crate::syscall::SystemError;
+use crate::{kerror, kwarn};
+
+pub type PageMapper =
+    crate::mm::page::PageMapper<crate::arch::aarch64::mm::AArch64MMArch, LockedFrameAllocator>;
+
+/// 用于存储物理内存区域的数组
+static mut PHYS_MEMORY_AREAS: [PhysMemoryArea; 512] = [PhysMemoryArea {
+    base: PhysAddr::new(0),
+    size: 0,
+}; 512];
+
+/// 内存管理初始化时，创建的第一个内核页表的物理地址
+static mut INITIAL_TTBR1_TABLE: PhysAddr = PhysAddr::new(0);
+
+static INNER_ALLOCATOR: SpinLock<Option<BuddyAllocator<MMArch>>> = SpinLock::new(None);
+
+/// AArch64的内存管理架构结构体(4K页，48位虚拟地址)
+///
+/// 用户空间与内核空间分别使用TTBR0_EL1与TTBR1_EL1中的页表，因此用户页表中不需要复制内核的映射
+#[derive(Debug, Clone, Copy, Hash)]
+pub struct AArch64MMArch;
+
+impl AArch64MMArch {
+    /// 页表项中，表示这是页表(或者最后一级的页)的标志位
+    pub const ENTRY_FLAG_TABLE: usize = 1 << 1;
+    /// 页表项中，表示已被访问的标志位。为0时访问会产生异常
+    pub const ENTRY_FLAG_ACCESSED: usize = 1 << 10;
+    /// 页表项中，表示内部共享的标志位
+    pub const ENTRY_FLAG_INNER_SHAREABLE: usize = 3 << 8;
+    /// 特权级不可执行
+    const ENTRY_FLAG_PXN: usize = 1 << 53;
+    /// 用户态不可执行
+    const ENTRY_FLAG_UXN: usize = 1 << 54;
+}
+
+impl MemoryManagementArch for AArch64MMArch {
+    /// 4K页
+    const PAGE_SHIFT: usize = 12;
+
+    /// 每个页表项占8字节，总共有512个页表项
+    const PAGE_ENTRY_SHIFT: usize = 9;
+
+    /// 四级页表(L0~L3)
+    const PAGE_LEVELS: usize = 4;
+
+    /// 页表项的第[12, 47]位是物理地址，第[48, 63]位是属性
+    const ENTRY_ADDRESS_SHIFT: usize = 48;
+
+    /// MAIR_EL1的第0项为普通内存
+    const ENTRY_FLAG_DEFAULT_PAGE: usize = Self::ENTRY_FLAG_PRESENT
+        | Self::ENTRY_FLAG_TABLE
+        | Self::ENTRY_FLAG_ACCESSED
+        | Self::ENTRY_FLAG_INNER_SHAREABLE;
+
+    const ENTRY_FLAG_DEFAULT_TABLE: usize = Self::ENTRY_FLAG_PRESENT | Self::ENTRY_FLAG_TABLE;
+
+    const ENTRY_FLAG_PRESENT: usize = 1 << 0;
+
+    /// AP[2]，置位时只读
+    const ENTRY_FLAG_READONLY: usize = 1 << 7;
+
+    const ENTRY_FLAG_READWRITE: usize = 0;
+
+    /// AP[1]，置位时用户态可以访问
+    const ENTRY_FLAG_USER: usize = 1 << 6;
+
+    /// 缓存属性由MAIR_EL1中的项决定，目前只使用普通内存
+    const ENTRY_FLAG_WRITE_THROUGH: usize = 0;
+
+    const ENTRY_FLAG_CACHE_DISABLE: usize = 0;
+
+    const ENTRY_FLAG_NO_EXEC: usize = Self::ENTRY_FLAG_PXN | Self::ENTRY_FLAG_UXN;
+
+    /// AArch64不存在EXEC标志位，只有不可执行标志位
+    const ENTRY_FLAG_EXEC: usize = 0;
+
+    /// 物理地址与虚拟地址的偏移量
+    /// 0xffff_8000_0000_0000
+    const PHYS_OFFSET: usize = Self::PAGE_NEGATIVE_MASK + (Self::PAGE_ADDRESS_SIZE >> 1);
+
+    const USER_END_VADDR: VirtAddr = VirtAddr::new(0x0000_7eff_ffff_ffff);
+    const USER_BRK_START: VirtAddr = VirtAddr::new(0x700000000000);
+    const USER_STACK_START: VirtAddr = VirtAddr::new(0x6ffff0a00000);
+    const USER_STACK_RND_BITS: usize = 22;
+    const USER_MMAP_RND_BITS: usize = 28;
+
+    /// 获取物理内存区域
+    ///
+    /// 与RISC-V相同，内存布局来自启动信息。AArch64还没有填写启动信息的启动代码，
+    /// 因此目前得到的是空的内存区域
+    unsafe fn init() -> &'static [PhysMemoryArea] {
+        let boot_info = boot_info();
+        let mut areas_count = 0usize;
+        for region in boot_info
+            .memory_regions()
+            .iter()
+            .filter(|r| r.mem_type == BootMemoryType::Usable)
+        {
+            if areas_count == PHYS_MEMORY_AREAS.len() {
+                kwarn!("Too many memory regions, the rest are ignored");
+                break;
+            }
+            PHYS_MEMORY_AREAS[areas_count].base = region.base;
+            PHYS_MEMORY_AREAS[areas_count].size = region.size;
+            areas_count += 1;
+        }
+        if areas_count == 0 {
+            kerror!("AArch64MMArch::init: no usable memory region in boot info");
+        }
+        return &PHYS_MEMORY_AREAS[0..areas_count];
+    }
+
+    /// 刷新TLB中，关于指定虚拟地址的条目
+    unsafe fn invalidate_page(address: VirtAddr) {
+        compiler_fence(Ordering::SeqCst);
+        asm!(
+            "dsb ishst",
+            "tlbi vaae1is, {}",
+            "dsb ish",
+            "isb",
+            in(reg) address.data() >> Self::PAGE_SHIFT,
+            options(nostack)
+        );
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// 刷新TLB中，所有的条目
+    unsafe fn invalidate_all() {
+        compiler_fence(Ordering::SeqCst);
+        asm!(
+            "dsb ishst",
+            "tlbi vmalle1is",
+            "dsb ish",
+            "isb",
+            options(nostack)
+        );
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// 获取顶级页表的物理地址
+    unsafe fn table(table_kind: PageTableKind) -> PhysAddr {
+        let ttbr: usize;
+        match table_kind {
+            PageTableKind::Kernel => {
+                asm!("mrs {}, ttbr1_el1", out(reg) ttbr, options(nomem, nostack, preserves_flags))
+            }
+            _ => asm!("mrs {}, ttbr0_el1", out(reg) ttbr, options(nomem, nostack, preserves_flags)),
+        }
+        return PhysAddr::new(ttbr & Self::PAGE_ADDRESS_MASK);
+    }
+
+    /// 设置顶级页表的物理地址到处理器中
+    unsafe fn set_table(table_kind: PageTableKind, table: PhysAddr) {
+        compiler_fence(Ordering::SeqCst);
+        match table_kind {
+            PageTableKind::Kernel => {
+                asm!("msr ttbr1_el1, {}", "isb", in(reg) table.data(), options(nostack))
+            }
+            _ => asm!("msr ttbr0_el1, {}", "isb", in(reg) table.data(), options(nostack)),
+        }
+        Self::invalidate_all();
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// 判断虚拟地址是否合法：第[48, 63]位必须全为0或者全为1
+    fn virt_is_valid(virt: VirtAddr) -> bool {
+        let x = virt.data() & Self::PAGE_NEGATIVE_MASK;
+        return x == 0 || x == Self::PAGE_NEGATIVE_MASK;
+    }
+
+    /// 获取内存管理初始化时，创建的第一个内核页表的地址
+    fn initial_page_table() -> PhysAddr {
+        unsafe {
+            return INITIAL_TTBR1_TABLE;
+        }
+    }
+
+    /// 创建新的用户页表
+    ///
+    /// 内核空间使用TTBR1_EL1中的页表，因此不需要复制内核的映射
+    fn setup_new_usermapper() -> Result<crate::mm::ucontext::UserMapper, SystemError> {
+        let new_umapper: crate::mm::page::PageMapper<AArch64MMArch, LockedFrameAllocator> = unsafe {
+            PageMapper::create(PageTableKind::User, LockedFrameAllocator)
+                .ok_or(SystemError::ENOMEM)?
+        };
+        return Ok(crate::mm::ucontext::UserMapper::new(new_umapper));
+    }
+}
+
+/// 全局的页帧分配器
+#[derive(Debug, Clone, Copy, Hash)]
+pub struct LockedFrameAllocator;
+
+impl FrameAllocator for LockedFrameAllocator {
+    unsafe fn allocate(&mut self, count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
+        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
+            return allocator.allocate(count);
+        }
+        return None;
+    }
+
+    unsafe fn free(&mut self, address: PhysAddr, count: PageFrameCount) {
+        assert!(count.data().is_power_of_two());
+        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
+            return allocator.free(address, count);
+        }
+    }
+
+    unsafe fn usage(&self) -> PageFrameUsage {
+        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
+            return allocator.usage();
+        } else {
+            panic!("usage error");
+        }
+    }
+}
+
+impl LockedFrameAllocator {
+    pub fn get_usage(&self) -> PageFrameUsage {
+        unsafe { self.usage() }
+    }
+}
+
+/// 获取内核地址默认的页面标志
+///
+/// TODO: 与其他架构一样，按照内核的代码段、只读数据段设置权限
+pub unsafe fn kernel_page_flags<A: MemoryManagementArch>(_virt: VirtAddr) -> PageFlags<A> {
+    return PageFlags::new().set_write(true).set_execute(true);
+}
@@ -0,0 +1,24 @@
+//! AArch64架构的骨架
+//!
+//! 这里只实现了与具体机器无关的架构接口：通过DAIF开关中断、通用定时器、GICv2的基本操作，以及4级页表的常量与TLB操作。
+//! 以下功能尚未实现，因此这个架构目前还不能启动：
+//!
+//! - 启动代码、链接脚本与异常向量表
+//! - 物理内存的初始化(需要从设备树中获取内存布局)
+//! - 进程的上下文切换、系统调用与信号
+//! - 多核的启动(PSCI)
+
+pub mod asm;
+pub mod cpu;
+pub mod interrupt;
+pub mod mm;
+pub mod time;
+
+/// 导出内存管理的Arch结构体
+pub use self::mm::AArch64MMArch as MMArch;
+
+pub use interrupt::AArch64InterruptArch as CurrentIrqArch;
+
+pub use crate::arch::asm::pio::AArch64PortIOArch as CurrentPortIOArch;
+
+pub use crate::arch::time::AArch64TimeArch as CurrentTimeArch;
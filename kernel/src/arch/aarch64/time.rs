@@ -0,0 +1,120 @@
+//! ARM通用定时器
+//!
+//! 使用虚拟计数器(CNTVCT_EL0)作为时钟周期，虚拟定时器(CNTV_*)作为时钟事件设备。
+//! 计数器的频率由固件写入CNTFRQ_EL0。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/clocksource/arm_arch_timer.c
+
+use core::{arch::asm, cmp::max};
+
+use alloc::sync::Arc;
+
+use crate::{
+    syscall::SystemError,
+    time::{
+        hrtimer::{
+            clockevent_register, hrtimer_cpu_init, hrtimer_interrupt, ktime_get, ClockEventDevice,
+            Ktime,
+        },
+        TimeArch,
+    },
+};
+
+use super::interrupt::gic::gic_enable_irq;
+
+/// 虚拟定时器的私有外设中断号
+const GENERIC_TIMER_VIRT_PPI: u32 = 27;
+/// CNTV_CTL_EL0：使能定时器
+const CNTV_CTL_ENABLE: usize = 1 << 0;
+/// 允许设置的最小间隔(ns)
+const MIN_DELTA_NS: u64 = 1000;
+const NSEC_PER_SEC: u128 = 1000000000;
+
+/// 计数器的频率(Hz)
+#[inline(always)]
+fn timer_freq() -> u64 {
+    let freq: usize;
+    unsafe { asm!("mrs {}, cntfrq_el0", out(reg) freq, options(nomem, nostack, preserves_flags)) };
+    return freq as u64;
+}
+
+pub struct AArch64TimeArch;
+
+impl TimeArch for AArch64TimeArch {
+    fn get_cycles() -> usize {
+        let cycles: usize;
+        unsafe {
+            asm!("isb", "mrs {}, cntvct_el0", out(reg) cycles, options(nomem, nostack, preserves_flags))
+        };
+        return cycles;
+    }
+
+    fn cycles2ns(cycles: usize) -> usize {
+        let freq = timer_freq();
+        if freq == 0 {
+            return 0;
+        }
+        return (cycles as u128 * NSEC_PER_SEC / freq as u128) as usize;
+    }
+}
+
+/// 通用定时器中的虚拟定时器
+#[derive(Debug)]
+struct GenericTimer;
+
+impl ClockEventDevice for GenericTimer {
+    fn name(&self) -> &str {
+        "arm-generic-timer"
+    }
+
+    fn set_oneshot(&self) {
+        // 比较值定时器本身就是单次触发的
+    }
+
+    fn set_next_event(&self, expires: Option<Ktime>) {
+        let ns = match expires {
+            Some(ns) => ns,
+            None => {
+                unsafe { asm!("msr cntv_ctl_el0, {}", in(reg) 0usize, options(nomem, nostack)) };
+                return;
+            }
+        };
+        let delta = max(ns.saturating_sub(ktime_get()), MIN_DELTA_NS);
+        let cycles = (delta as u128 * timer_freq() as u128 / NSEC_PER_SEC) as usize;
+        let cval = AArch64TimeArch::get_cycles().saturating_add(cycles);
+        unsafe {
+            asm!(
+                "msr cntv_cval_el0, {cval}",
+                "msr cntv_ctl_el0, {ctl}",
+                "isb",
+                cval = in(reg) cval,
+                ctl = in(reg) CNTV_CTL_ENABLE,
+                options(nomem, nostack)
+            )
+        };
+    }
+}
+
+/// 虚拟定时器中断的处理函数
+///
+/// TODO: 异常向量表实现之后，由IRQ的处理函数调用
+#[allow(dead_code)]
+pub fn generic_timer_interrupt(user: bool) {
+    if !hrtimer_interrupt(user) {
+        // 还没有切换到高精度模式，关闭定时器，清除中断
+        unsafe { asm!("msr cntv_ctl_el0, {}", in(reg) 0usize, options(nomem, nostack)) };
+    }
+}
+
+/// 注册时钟事件设备，使能定时器中断，然后把当前cpu切换到高精度模式
+///
+/// 需要在[`gic_init`](super::interrupt::gic::gic_init)之后调用
+#[allow(dead_code)]
+pub fn generic_timer_init() -> Result<(), SystemError> {
+    if timer_freq() == 0 {
+        return Err(SystemError::ENODEV);
+    }
+    clockevent_register(Arc::new(GenericTimer))?;
+    gic_enable_irq(GENERIC_TIMER_VIRT_PPI);
+    return hrtimer_cpu_init();
+}
@@ -1,4 +1,13 @@
+#[cfg(target_arch = "aarch64")]
+pub mod aarch64;
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
+#[cfg(target_arch = "x86_64")]
 pub mod x86_64;
+#[cfg(target_arch = "aarch64")]
+pub use self::aarch64::*; //公开aarch64架构下的函数，使外界接口统一
+#[cfg(target_arch = "riscv64")]
+pub use self::riscv64::*; //公开riscv64架构下的函数，使外界接口统一
 #[cfg(target_arch = "x86_64")]
 pub use self::x86_64::*; //公开x86_64架构下的函数，使外界接口统一
 
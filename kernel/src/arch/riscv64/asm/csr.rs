@@ -0,0 +1,79 @@
+//! 控制与状态寄存器(CSR)的访问
+//!
+//! 参考 https://github.com/riscv/riscv-isa-manual (特权级规范，第4章)
+
+/// sstatus: S模式中断使能
+pub const SSTATUS_SIE: usize = 1 << 1;
+/// sstatus: 陷入之前的中断使能状态
+pub const SSTATUS_SPIE: usize = 1 << 5;
+/// sstatus: 陷入之前的特权级，为1时表示来自S模式
+pub const SSTATUS_SPP: usize = 1 << 8;
+/// sstatus: 允许S模式访问用户页
+pub const SSTATUS_SUM: usize = 1 << 18;
+
+/// sie: 软件中断使能
+pub const SIE_SSIE: usize = 1 << 1;
+/// sie: 时钟中断使能
+pub const SIE_STIE: usize = 1 << 5;
+/// sie: 外部中断使能
+pub const SIE_SEIE: usize = 1 << 9;
+
+/// scause的最高位为1时，表示陷入是由中断引起的
+pub const SCAUSE_INTERRUPT: usize = 1 << 63;
+
+/// satp: Sv39分页模式
+pub const SATP_MODE_SV39: usize = 8 << 60;
+/// satp: 根页表的物理页号所占的位
+pub const SATP_PPN_MASK: usize = (1 << 44) - 1;
+
+/// 读取CSR的值
+macro_rules! csr_read {
+    ($csr:literal) => {{
+        let value: usize;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!(concat!("csrr {0}, ", $csr), out(reg) value, options(nomem, nostack))
+        };
+        value
+    }};
+}
+
+/// 写入CSR
+macro_rules! csr_write {
+    ($csr:literal, $value:expr) => {{
+        let value: usize = $value;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!(concat!("csrw ", $csr, ", {0}"), in(reg) value, options(nostack))
+        };
+    }};
+}
+
+/// 把CSR中的指定位置为1
+macro_rules! csr_set {
+    ($csr:literal, $bits:expr) => {{
+        let bits: usize = $bits;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!(concat!("csrs ", $csr, ", {0}"), in(reg) bits, options(nostack))
+        };
+    }};
+}
+
+/// 把CSR中的指定位清零，返回清零之前的值
+macro_rules! csr_read_clear {
+    ($csr:literal, $bits:expr) => {{
+        let bits: usize = $bits;
+        let value: usize;
+        #[allow(unused_unsafe)]
+        unsafe {
+            core::arch::asm!(
+                concat!("csrrc {0}, ", $csr, ", {1}"),
+                out(reg) value,
+                in(reg) bits,
+                options(nostack)
+            )
+        };
+        value
+    }};
+}
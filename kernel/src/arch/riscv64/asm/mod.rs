@@ -0,0 +1,3 @@
+#[macro_use]
+pub mod csr;
+pub mod pio;
@@ -0,0 +1,73 @@
+//! 解析SBI固件传入的设备树
+//!
+//! OpenSBI跳转到内核时，a0中是启动核心的hart id，a1中是设备树的物理地址。
+//! 设备树所在的内存没有被保留，因此在内存管理初始化之前，把它复制到内核的数据段中。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    arch::MMArch,
+    driver::open_firmware::fdt::{early_init_dt_scan_memory, fdt_total_size},
+    init::boot_info::{BootInfo, BootMemoryRegion, BootMemoryType, BootProtocol},
+    kwarn,
+    mm::{MemoryManagementArch, PhysAddr},
+};
+
+/// 设备树的最大长度
+const FDT_MAX_SIZE: usize = 1024 * 1024;
+
+/// 启动核心的hart id
+pub static BOOT_HART_ID: AtomicUsize = AtomicUsize::new(0);
+/// 固件传入的设备树的物理地址
+pub static BOOT_FDT_PADDR: AtomicUsize = AtomicUsize::new(0);
+
+/// 设备树的副本
+static mut FDT_BUF: [u8; FDT_MAX_SIZE] = [0; FDT_MAX_SIZE];
+
+/// 从设备树中解析启动信息
+///
+/// 此时内核仍然在使用启动时建立的页表，它以1G大页映射了[`MemoryManagementArch::PHYS_OFFSET`]开始的256G空间，
+/// 因此可以通过物理地址直接访问设备树
+pub fn arch_boot_info_init(info: &mut BootInfo) {
+    info.protocol = BootProtocol::DeviceTree;
+
+    let paddr = BOOT_FDT_PADDR.load(Ordering::SeqCst);
+    if paddr == 0 {
+        kwarn!("riscv64: no device tree from firmware");
+        return;
+    }
+    let vaddr = unsafe { MMArch::phys_2_virt(PhysAddr::new(paddr)) }.unwrap();
+    let header = unsafe { core::slice::from_raw_parts(vaddr.data() as *const u8, 8) };
+    let size = match fdt_total_size(header) {
+        Ok(size) if size <= FDT_MAX_SIZE => size,
+        Ok(size) => {
+            kwarn!(
+                "riscv64: device tree is too large ({} bytes, max {}), ignored",
+                size,
+                FDT_MAX_SIZE
+            );
+            return;
+        }
+        Err(e) => {
+            kwarn!("riscv64: invalid device tree at {:#x}: {:?}", paddr, e);
+            return;
+        }
+    };
+
+    let fdt = unsafe {
+        let src = core::slice::from_raw_parts(vaddr.data() as *const u8, size);
+        FDT_BUF[..size].copy_from_slice(src);
+        &FDT_BUF[..size]
+    };
+    info.fdt = Some(fdt);
+
+    // OpenSBI位于内核之前的内存中，页帧分配器从内核的结束地址开始分配，因此不会覆盖它
+    early_init_dt_scan_memory(fdt, &mut |base, size| {
+        info.add_memory_region(BootMemoryRegion::new(
+            PhysAddr::new(base as usize),
+            size as usize,
+            BootMemoryType::Usable,
+        ));
+    })
+    .unwrap_or_else(|e| kwarn!("riscv64: failed to scan memory nodes: {:?}", e));
+}
@@ -0,0 +1,49 @@
+use core::{arch::asm, hint::spin_loop};
+
+use crate::{arch::CurrentIrqArch, exception::InterruptArch};
+
+use super::sbi;
+
+/// 获取当前cpu的逻辑id
+///
+/// S模式无法读取hart id，因此在内核中用tp寄存器保存当前cpu的逻辑id(引导核为0)，
+/// 从用户态陷入时由陷入处理程序恢复
+#[inline]
+pub fn current_cpu_id() -> u32 {
+    let tp: usize;
+    unsafe { asm!("mv {}, tp", out(reg) tp, options(nomem, nostack, preserves_flags)) };
+    return tp as u32;
+}
+
+/// 重置cpu：请求固件重启，固件不支持时停机
+pub fn cpu_reset() -> ! {
+    unsafe { CurrentIrqArch::interrupt_disable() };
+    sbi::reboot().ok();
+    cpu_halt();
+}
+
+/// 默认的空闲方式：等待中断，然后打开中断
+///
+/// 调用时中断必须已经关闭。即使sstatus.SIE为0，WFI也会被sie中使能的中断唤醒，因此不会错过唤醒
+#[inline]
+pub fn arch_cpu_idle() {
+    unsafe { asm!("wfi", options(nomem, nostack)) };
+    unsafe { CurrentIrqArch::interrupt_enable() };
+}
+
+/// 获取当前函数的帧指针(s0)，用于回溯调用栈
+#[inline(always)]
+pub fn current_frame_pointer() -> usize {
+    let fp: usize;
+    unsafe { asm!("mv {}, s0", out(reg) fp, options(nomem, nostack, preserves_flags)) };
+    return fp;
+}
+
+/// 停止当前cpu的运行
+pub fn cpu_halt() -> ! {
+    unsafe { CurrentIrqArch::interrupt_disable() };
+    loop {
+        unsafe { asm!("wfi", options(nomem, nostack)) };
+        spin_loop();
+    }
+}
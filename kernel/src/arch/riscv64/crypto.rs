@@ -0,0 +1,8 @@
+use alloc::vec::Vec;
+
+use crate::crypto::CryptoAlg;
+
+/// 返回当前cpu支持的密码学算法实现。目前没有使用RISC-V密码学扩展的实现
+pub fn arch_crypto_algs() -> Vec<CryptoAlg> {
+    return Vec::new();
+}
@@ -0,0 +1,172 @@
+//! 内核的入口
+//!
+//! OpenSBI在M模式完成初始化之后，以S模式跳转到内核的物理地址(QEMU virt上为0x80200000)，此时分页未开启。
+//! `_start`建立一个临时的页表，开启分页并跳转到内核的链接地址，然后进入[`kernel_main`]。
+
+use core::{arch::global_asm, sync::atomic::Ordering};
+
+use crate::{exception::InterruptArch, kinfo, mm::MemoryManagementArch};
+
+use super::{
+    asm::csr::SATP_MODE_SV39,
+    boot::{BOOT_FDT_PADDR, BOOT_HART_ID},
+    interrupt::trap::arch_trap_init,
+    mm::RiscV64MMArch,
+    time::riscv_time_init,
+    CurrentIrqArch,
+};
+
+/// 启动时使用的Sv39根页表
+///
+/// 第[256, 512)项以1G大页把物理地址[0, 256G)映射到`PHYS_OFFSET`开始的地址，另外恒等映射内核所在的1G。
+/// 内存管理初始化之后，内核会切换到新的页表，不再使用它
+#[repr(C, align(4096))]
+struct BootPageTable([usize; 512]);
+
+#[link_section = ".data.boot_page_table"]
+#[no_mangle]
+static mut BOOT_PAGE_TABLE: BootPageTable = BootPageTable([0; 512]);
+
+/// 1G大页的页表项的标志位：V|R|W|X|A|D
+const BOOT_GIGAPAGE_FLAGS: usize = 0xcf;
+
+global_asm!(
+    "
+    .section .text.entry
+    .global _start
+_start:
+    # 关闭S模式的中断，a0为hart id，a1为设备树的物理地址
+    csrw sie, zero
+    mv s0, a0
+    mv s1, a1
+
+    # 此时pc为物理地址，la通过pc相对寻址得到启动页表的物理地址
+    la t0, BOOT_PAGE_TABLE
+
+    # 第i项(i >= 256)映射物理地址(i - 256) << 30
+    li t1, 256
+    li t2, 512
+    li t3, 2048
+    add t3, t0, t3
+    li t4, {flags}
+    li t5, 1 << 28
+2:
+    sd t4, 0(t3)
+    add t4, t4, t5
+    addi t3, t3, 8
+    addi t1, t1, 1
+    blt t1, t2, 2b
+
+    # 恒等映射内核所在的1G，使得开启分页后的下一条指令仍然能够执行
+    la t1, _start
+    srli t1, t1, 30
+    slli t2, t1, 3
+    add t2, t0, t2
+    slli t1, t1, 28
+    ori t1, t1, {flags}
+    sd t1, 0(t2)
+
+    # 开启Sv39分页
+    srli t1, t0, 12
+    li t2, {satp_mode}
+    or t1, t1, t2
+    sfence.vma
+    csrw satp, t1
+    sfence.vma
+
+    # 跳转到链接地址
+    li t1, {phys_offset}
+    la t2, 3f
+    add t2, t2, t1
+    jr t2
+3:
+    # 使用idle进程的栈
+    la sp, BSP_IDLE_STACK_SPACE
+    li t0, 32768
+    add sp, sp, t0
+
+    # 清零bss
+    la t0, _bss
+    la t1, _ebss
+4:
+    bgeu t0, t1, 5f
+    sd zero, 0(t0)
+    addi t0, t0, 8
+    j 4b
+5:
+    # tp中存放当前cpu的逻辑id
+    li tp, 0
+    mv a0, s0
+    mv a1, s1
+    call kernel_main
+6:
+    wfi
+    j 6b
+    ",
+    flags = const(BOOT_GIGAPAGE_FLAGS),
+    satp_mode = const(SATP_MODE_SV39),
+    phys_offset = const(RiscV64MMArch::PHYS_OFFSET),
+);
+
+extern "C" {
+    fn rs_init_before_mem_init();
+    fn rs_mm_init();
+    fn rs_init_intertrait();
+    fn rs_dynamic_debug_init();
+    fn rs_panic_init();
+    fn rs_printk_init();
+    fn vfs_init() -> i32;
+    fn rs_driver_init() -> i32;
+    fn rs_process_init();
+    fn sched_init();
+    fn rs_softirq_init();
+    fn rs_timekeeping_init();
+    fn rs_timer_init();
+    fn rs_jiffies_init();
+    fn rs_kthread_init();
+    fn rs_clocksource_boot_finish();
+    fn rs_cpu_idle();
+}
+
+/// 内核的Rust入口，此时已经运行在链接地址上
+///
+/// 初始化的顺序与x86_64上的`main.c`中的`start_kernel`相同，只是跳过了RISC-V上不存在的硬件
+///
+/// ## 参数
+///
+/// - `hartid`：启动核心的hart id
+/// - `fdt_paddr`：设备树的物理地址
+#[no_mangle]
+unsafe extern "C" fn kernel_main(hartid: usize, fdt_paddr: usize) -> ! {
+    BOOT_HART_ID.store(hartid, Ordering::SeqCst);
+    BOOT_FDT_PADDR.store(fdt_paddr, Ordering::SeqCst);
+    arch_trap_init();
+
+    rs_init_before_mem_init();
+    rs_mm_init();
+    rs_init_intertrait();
+    rs_dynamic_debug_init();
+    rs_panic_init();
+    rs_printk_init();
+    kinfo!("riscv64: booting on hart {}", hartid);
+
+    vfs_init();
+    rs_driver_init();
+    rs_process_init();
+    sched_init();
+    CurrentIrqArch::interrupt_enable();
+
+    rs_softirq_init();
+    rs_timekeeping_init();
+    rs_timer_init();
+    rs_jiffies_init();
+    rs_kthread_init();
+    rs_clocksource_boot_finish();
+
+    // 时钟的频率来自设备树，因此需要在驱动模型初始化(展开设备树)之后初始化
+    riscv_time_init().expect("riscv64: failed to init timer");
+
+    loop {
+        rs_cpu_idle();
+    }
+}
@@ -0,0 +1,7 @@
+use crate::exception::ipi::{IpiKind, IpiTarget};
+
+/// 发送IPI
+///
+/// 目前只启动了一个核心，不需要向其他核心发送IPI。多核支持需要通过SBI的IPI扩展实现
+#[inline(always)]
+pub fn send_ipi(_kind: IpiKind, _target: IpiTarget) {}
@@ -0,0 +1,139 @@
+pub mod ipi;
+pub mod trap;
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::exception::{InterruptArch, IrqFlags, IrqFlagsGuard};
+
+use super::asm::csr::{SSTATUS_SIE, SSTATUS_SPP};
+
+pub struct RiscV64InterruptArch;
+
+impl InterruptArch for RiscV64InterruptArch {
+    unsafe fn interrupt_enable() {
+        csr_set!("sstatus", SSTATUS_SIE);
+    }
+
+    unsafe fn interrupt_disable() {
+        csr_read_clear!("sstatus", SSTATUS_SIE);
+    }
+
+    fn is_irq_enabled() -> bool {
+        return csr_read!("sstatus") & SSTATUS_SIE != 0;
+    }
+
+    unsafe fn save_and_disable_irq() -> IrqFlagsGuard {
+        compiler_fence(Ordering::SeqCst);
+        let sstatus = csr_read_clear!("sstatus", SSTATUS_SIE);
+        let guard = IrqFlagsGuard::new(IrqFlags::new(sstatus & SSTATUS_SIE));
+        compiler_fence(Ordering::SeqCst);
+        return guard;
+    }
+
+    unsafe fn restore_irq(flags: IrqFlags) {
+        compiler_fence(Ordering::SeqCst);
+        csr_set!("sstatus", flags.flags() & SSTATUS_SIE);
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// 中断栈帧结构体
+///
+/// 前31项依次为x1~x31寄存器，陷入处理程序按照这个顺序保存与恢复它们
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TrapFrame {
+    pub ra: usize,
+    pub sp: usize,
+    pub gp: usize,
+    pub tp: usize,
+    pub t0: usize,
+    pub t1: usize,
+    pub t2: usize,
+    pub s0: usize,
+    pub s1: usize,
+    pub a0: usize,
+    pub a1: usize,
+    pub a2: usize,
+    pub a3: usize,
+    pub a4: usize,
+    pub a5: usize,
+    pub a6: usize,
+    pub a7: usize,
+    pub s2: usize,
+    pub s3: usize,
+    pub s4: usize,
+    pub s5: usize,
+    pub s6: usize,
+    pub s7: usize,
+    pub s8: usize,
+    pub s9: usize,
+    pub s10: usize,
+    pub s11: usize,
+    pub t3: usize,
+    pub t4: usize,
+    pub t5: usize,
+    pub t6: usize,
+    pub sepc: usize,
+    pub sstatus: usize,
+    pub stval: usize,
+    pub scause: usize,
+    /// 返回用户态时保存的内核tp(当前cpu的逻辑id)，从用户态陷入时用它恢复tp
+    pub kernel_tp: usize,
+    /// 使栈帧的大小为16字节的整数倍
+    _pad: usize,
+}
+
+impl TrapFrame {
+    pub fn new() -> Self {
+        Self {
+            ra: 0,
+            sp: 0,
+            gp: 0,
+            tp: 0,
+            t0: 0,
+            t1: 0,
+            t2: 0,
+            s0: 0,
+            s1: 0,
+            a0: 0,
+            a1: 0,
+            a2: 0,
+            a3: 0,
+            a4: 0,
+            a5: 0,
+            a6: 0,
+            a7: 0,
+            s2: 0,
+            s3: 0,
+            s4: 0,
+            s5: 0,
+            s6: 0,
+            s7: 0,
+            s8: 0,
+            s9: 0,
+            s10: 0,
+            s11: 0,
+            t3: 0,
+            t4: 0,
+            t5: 0,
+            t6: 0,
+            sepc: 0,
+            sstatus: 0,
+            stval: 0,
+            scause: 0,
+            kernel_tp: 0,
+            _pad: 0,
+        }
+    }
+
+    /// 设置中断栈帧返回值
+    pub fn set_return_value(&mut self, value: usize) {
+        self.a0 = value;
+    }
+
+    /// 判断当前中断是否来自用户模式
+    pub fn from_user(&self) -> bool {
+        return self.sstatus & SSTATUS_SPP == 0;
+    }
+}
@@ -0,0 +1,200 @@
+//! 陷入(中断与异常)的入口与分发
+//!
+//! 所有的陷入都进入[`riscv64_trap_entry`]。约定在内核态时sscratch为0，在用户态时sscratch为当前进程的内核栈顶，
+//! 因此入口处交换sp与sscratch之后，可以判断陷入来自哪个特权级。
+
+use core::arch::global_asm;
+
+use memoffset::offset_of;
+
+use crate::{
+    arch::sched::sched,
+    kwarn,
+    process::{ProcessFlags, ProcessManager},
+};
+
+use super::{
+    super::{
+        asm::csr::{SCAUSE_INTERRUPT, SIE_STIE, SSTATUS_SIE, SSTATUS_SPP},
+        mm::extable::fixup_exception,
+        syscall::syscall_handler,
+        time::riscv_timer_interrupt,
+    },
+    TrapFrame,
+};
+
+/// S模式时钟中断
+const IRQ_S_TIMER: usize = 5;
+/// 来自用户态的ecall
+const EXC_U_ECALL: usize = 8;
+/// 读取内存时的缺页异常
+const EXC_LOAD_PAGE_FAULT: usize = 13;
+/// 写入内存时的缺页异常
+const EXC_STORE_PAGE_FAULT: usize = 15;
+
+extern "C" {
+    fn riscv64_trap_entry();
+    /// 从栈顶的TrapFrame恢复寄存器，然后返回陷入之前的特权级。调用时sp必须指向TrapFrame
+    pub fn riscv64_ret_from_trap();
+}
+
+global_asm!(
+    "
+    .section .text
+    .balign 4
+    .global riscv64_trap_entry
+riscv64_trap_entry:
+    csrrw sp, sscratch, sp
+    bnez sp, 1f
+    # 来自内核态，取回原来的sp
+    csrr sp, sscratch
+1:
+    addi sp, sp, -{frame_size}
+    sd x1, 0(sp)
+    sd x3, 16(sp)
+    sd x4, 24(sp)
+    sd x5, 32(sp)
+    sd x6, 40(sp)
+    sd x7, 48(sp)
+    sd x8, 56(sp)
+    sd x9, 64(sp)
+    sd x10, 72(sp)
+    sd x11, 80(sp)
+    sd x12, 88(sp)
+    sd x13, 96(sp)
+    sd x14, 104(sp)
+    sd x15, 112(sp)
+    sd x16, 120(sp)
+    sd x17, 128(sp)
+    sd x18, 136(sp)
+    sd x19, 144(sp)
+    sd x20, 152(sp)
+    sd x21, 160(sp)
+    sd x22, 168(sp)
+    sd x23, 176(sp)
+    sd x24, 184(sp)
+    sd x25, 192(sp)
+    sd x26, 200(sp)
+    sd x27, 208(sp)
+    sd x28, 216(sp)
+    sd x29, 224(sp)
+    sd x30, 232(sp)
+    sd x31, 240(sp)
+    # 保存陷入之前的sp，并把sscratch清零，表示已经位于内核态
+    csrrw t0, sscratch, zero
+    sd t0, {off_sp}(sp)
+    csrr t0, sepc
+    sd t0, {off_sepc}(sp)
+    csrr t1, sstatus
+    sd t1, {off_sstatus}(sp)
+    csrr t0, stval
+    sd t0, {off_stval}(sp)
+    csrr t0, scause
+    sd t0, {off_scause}(sp)
+    # 来自用户态时，恢复内核的tp
+    andi t1, t1, {sstatus_spp}
+    bnez t1, 2f
+    ld tp, {off_kernel_tp}(sp)
+2:
+    mv a0, sp
+    call {handler}
+
+    .global riscv64_ret_from_trap
+riscv64_ret_from_trap:
+    csrci sstatus, {sstatus_sie}
+    ld t0, {off_sepc}(sp)
+    csrw sepc, t0
+    ld t1, {off_sstatus}(sp)
+    csrw sstatus, t1
+    andi t1, t1, {sstatus_spp}
+    bnez t1, 3f
+    # 返回用户态：保存内核的tp，并把内核栈顶写入sscratch
+    sd tp, {off_kernel_tp}(sp)
+    addi t0, sp, {frame_size}
+    csrw sscratch, t0
+3:
+    ld x1, 0(sp)
+    ld x3, 16(sp)
+    ld x4, 24(sp)
+    ld x5, 32(sp)
+    ld x6, 40(sp)
+    ld x7, 48(sp)
+    ld x8, 56(sp)
+    ld x9, 64(sp)
+    ld x10, 72(sp)
+    ld x11, 80(sp)
+    ld x12, 88(sp)
+    ld x13, 96(sp)
+    ld x14, 104(sp)
+    ld x15, 112(sp)
+    ld x16, 120(sp)
+    ld x17, 128(sp)
+    ld x18, 136(sp)
+    ld x19, 144(sp)
+    ld x20, 152(sp)
+    ld x21, 160(sp)
+    ld x22, 168(sp)
+    ld x23, 176(sp)
+    ld x24, 184(sp)
+    ld x25, 192(sp)
+    ld x26, 200(sp)
+    ld x27, 208(sp)
+    ld x28, 216(sp)
+    ld x29, 224(sp)
+    ld x30, 232(sp)
+    ld x31, 240(sp)
+    ld sp, {off_sp}(sp)
+    sret
+",
+    frame_size = const(core::mem::size_of::<TrapFrame>()),
+    off_sp = const(offset_of!(TrapFrame, sp)),
+    off_sepc = const(offset_of!(TrapFrame, sepc)),
+    off_sstatus = const(offset_of!(TrapFrame, sstatus)),
+    off_stval = const(offset_of!(TrapFrame, stval)),
+    off_scause = const(offset_of!(TrapFrame, scause)),
+    off_kernel_tp = const(offset_of!(TrapFrame, kernel_tp)),
+    sstatus_spp = const(SSTATUS_SPP),
+    sstatus_sie = const(SSTATUS_SIE),
+    handler = sym riscv64_do_trap,
+);
+
+/// 陷入的分发
+unsafe extern "C" fn riscv64_do_trap(frame: &mut TrapFrame) {
+    let scause = frame.scause;
+    if scause & SCAUSE_INTERRUPT != 0 {
+        match scause & !SCAUSE_INTERRUPT {
+            IRQ_S_TIMER => riscv_timer_interrupt(frame.from_user()),
+            irq => kwarn!("riscv64: unhandled interrupt {}", irq),
+        }
+    } else {
+        match scause {
+            EXC_U_ECALL => {
+                // 返回到ecall的下一条指令
+                frame.sepc += 4;
+                syscall_handler(frame);
+            }
+            // 内核访问用户内存时出错，跳转到异常表中登记的修复地址
+            EXC_LOAD_PAGE_FAULT | EXC_STORE_PAGE_FAULT
+                if !frame.from_user() && fixup_exception(frame) => {}
+            _ => panic!(
+                "riscv64: unhandled exception: scause={:#x}, sepc={:#x}, stval={:#x}",
+                scause, frame.sepc, frame.stval
+            ),
+        }
+    }
+
+    // 时钟中断可能设置了NEED_SCHEDULE标志
+    if ProcessManager::current_pcb()
+        .flags()
+        .contains(ProcessFlags::NEED_SCHEDULE)
+    {
+        sched();
+    }
+}
+
+/// 设置陷入的入口，并使能时钟中断
+pub fn arch_trap_init() {
+    csr_write!("sscratch", 0);
+    csr_write!("stvec", riscv64_trap_entry as usize);
+    csr_set!("sie", SIE_STIE);
+}
@@ -0,0 +1,31 @@
+use crate::{
+    arch::{interrupt::TrapFrame, CurrentIrqArch},
+    exception::InterruptArch,
+    ipc::signal_types::SignalArch,
+    kwarn,
+    process::ProcessManager,
+    syscall::SystemError,
+};
+
+pub struct RiscV64SignalArch;
+
+impl SignalArch for RiscV64SignalArch {
+    /// 信号栈帧的布局还没有实现，有待处理的信号时只打印警告
+    unsafe fn do_signal(frame: &mut TrapFrame) {
+        CurrentIrqArch::interrupt_enable();
+        if !frame.from_user() {
+            return;
+        }
+        let pcb = ProcessManager::current_pcb();
+        if pcb.sig_info().sig_pending().signal().bits() != 0 {
+            kwarn!(
+                "riscv64: signal delivery is not implemented, pending signals of pid {:?} ignored",
+                pcb.pid()
+            );
+        }
+    }
+
+    fn sys_rt_sigreturn(_trap_frame: &mut TrapFrame) -> u64 {
+        return SystemError::ENOSYS.to_posix_errno() as u64;
+    }
+}
@@ -0,0 +1,128 @@
+//! 内核异常表(exception table)
+//!
+//! 与x86_64相同，所有可能访问用户内存的指令都在`__ex_table`段中登记一条记录（出错指令地址, 修复地址）。
+//! S模式下发生缺页异常时，陷入处理函数查找异常表，找到对应的记录就把sepc修改为修复地址。
+
+use core::arch::asm;
+
+use crate::arch::interrupt::TrapFrame;
+
+/// 异常表的表项
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionTableEntry {
+    /// 可能出错的指令的地址
+    pub insn: usize,
+    /// 出错后跳转到的修复代码的地址
+    pub fixup: usize,
+}
+
+extern "C" {
+    static __start___ex_table: ExceptionTableEntry;
+    static __stop___ex_table: ExceptionTableEntry;
+}
+
+/// 获取链接器生成的异常表
+fn exception_table() -> &'static [ExceptionTableEntry] {
+    unsafe {
+        let start = &__start___ex_table as *const ExceptionTableEntry;
+        let end = &__stop___ex_table as *const ExceptionTableEntry;
+        let len = (end as usize - start as usize) / core::mem::size_of::<ExceptionTableEntry>();
+        return core::slice::from_raw_parts(start, len);
+    }
+}
+
+/// 在异常表中查找指令地址对应的表项
+pub fn search_exception_table(insn: usize) -> Option<&'static ExceptionTableEntry> {
+    return exception_table().iter().find(|entry| entry.insn == insn);
+}
+
+/// 尝试修复S模式下的缺页异常
+///
+/// ## 返回值
+///
+/// - `true`：找到了修复地址，并已修改了栈帧中的sepc
+/// - `false`：出错指令不在异常表中
+pub fn fixup_exception(frame: &mut TrapFrame) -> bool {
+    if let Some(entry) = search_exception_table(frame.sepc) {
+        frame.sepc = entry.fixup;
+        return true;
+    }
+    return false;
+}
+
+/// 在内核与用户空间之间拷贝数据，访问出错时不会导致内核崩溃
+///
+/// ## 参数
+///
+/// - `dst`：目标地址
+/// - `src`：源地址
+/// - `len`：要拷贝的字节数
+///
+/// ## 返回值
+///
+/// 返回未能拷贝的字节数。返回0表示全部拷贝成功
+///
+/// ## Safety
+///
+/// 调用者需要保证`dst`和`src`中，位于内核空间的那一段内存是合法的
+#[inline(never)]
+pub unsafe fn copy_user_generic(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    let mut remain = len;
+    asm!(
+        "2:",
+        "beqz {len}, 5f",
+        "3:",
+        "lb {tmp}, 0({src})",
+        "4:",
+        "sb {tmp}, 0({dst})",
+        "addi {src}, {src}, 1",
+        "addi {dst}, {dst}, 1",
+        "addi {len}, {len}, -1",
+        "j 2b",
+        "5:",
+        ".pushsection __ex_table, \"a\"",
+        ".balign 8",
+        ".dword 3b, 5b",
+        ".dword 4b, 5b",
+        ".popsection",
+        len = inout(reg) remain,
+        dst = inout(reg) dst => _,
+        src = inout(reg) src => _,
+        tmp = out(reg) _,
+        options(nostack)
+    );
+    return remain;
+}
+
+/// 把用户空间的一段内存清零，访问出错时不会导致内核崩溃
+///
+/// ## 返回值
+///
+/// 返回未能清零的字节数。返回0表示全部清零成功
+///
+/// ## Safety
+///
+/// 调用者需要保证`dst`指向用户空间
+#[inline(never)]
+pub unsafe fn clear_user_generic(dst: *mut u8, len: usize) -> usize {
+    let mut remain = len;
+    asm!(
+        "2:",
+        "beqz {len}, 4f",
+        "3:",
+        "sb zero, 0({dst})",
+        "addi {dst}, {dst}, 1",
+        "addi {len}, {len}, -1",
+        "j 2b",
+        "4:",
+        ".pushsection __ex_table, \"a\"",
+        ".balign 8",
+        ".dword 3b, 4b",
+        ".popsection",
+        len = inout(reg) remain,
+        dst = inout(reg) dst => _,
+        options(nostack)
+    );
+    return remain;
+}
@@ -0,0 +1,453 @@
+pub mod extable;
+
+use core::arch::asm;
+use core::fmt::Debug;
+use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+
+use crate::arch::MMArch;
+use crate::init::boot_info::{boot_info, BootMemoryType};
+use crate::libs::align::page_align_up;
+use crate::libs::spinlock::SpinLock;
+use crate::mm::allocator::page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage};
+use crate::mm::allocator::{buddy::BuddyAllocator, bump::BumpAllocator};
+use crate::mm::kasan::{kasan_alloc_pages, kasan_free_pages, kasan_init};
+use crate::mm::kernel_mapper::KernelMapper;
+use crate::mm::mmio_buddy::mmio_init;
+use crate::mm::page::{PageEntry, PageFlags};
+use crate::mm::{MemoryManagementArch, PageTableKind, PhysAddr, PhysMemoryArea, VirtAddr};
+use crate::syscall::SystemError;
+use crate::{kdebug, kinfo, kwarn};
+
+use super::asm::csr::{SATP_MODE_SV39, SATP_PPN_MASK, SSTATUS_SUM};
+
+pub type PageMapper =
+    crate::mm::page::PageMapper<crate::arch::riscv64::mm::RiscV64MMArch, LockedFrameAllocator>;
+
+/// 内核访问用户内存的窗口
+///
+/// 内核在初始化时置位了sstatus.SUM，始终可以访问用户页，因此这里什么也不做
+#[derive(Debug)]
+pub struct UserAccessGuard;
+
+impl UserAccessGuard {
+    #[inline(always)]
+    pub fn new() -> Self {
+        return Self;
+    }
+}
+
+/// 用于存储物理内存区域的数组
+static mut PHYS_MEMORY_AREAS: [PhysMemoryArea; 512] = [PhysMemoryArea {
+    base: PhysAddr::new(0),
+    size: 0,
+}; 512];
+
+/// 内存管理初始化时，创建的第一个内核页表的物理地址
+static mut INITIAL_SATP_TABLE: PhysAddr = PhysAddr::new(0);
+
+/// 内核的第一个页表在顶级页表中的索引
+/// 顶级页表的[256, 512)项是内核的页表
+const KERNEL_ROOT_ENTRY_NO: usize = 256;
+
+static INNER_ALLOCATOR: SpinLock<Option<BuddyAllocator<MMArch>>> = SpinLock::new(None);
+
+#[derive(Clone, Copy)]
+pub struct RiscV64MMBootstrapInfo {
+    kernel_code_start: usize,
+    kernel_code_end: usize,
+    kernel_data_end: usize,
+    kernel_rodata_end: usize,
+    start_brk: usize,
+}
+
+impl Debug for RiscV64MMBootstrapInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "kernel_code_start: {:x}, kernel_code_end: {:x}, kernel_data_end: {:x}, kernel_rodata_end: {:x}, start_brk: {:x}",
+            self.kernel_code_start, self.kernel_code_end, self.kernel_data_end, self.kernel_rodata_end, self.start_brk)
+    }
+}
+
+pub static mut BOOTSTRAP_MM_INFO: Option<RiscV64MMBootstrapInfo> = None;
+
+/// RISC-V 64的内存管理架构结构体(Sv39分页)
+#[derive(Debug, Clone, Copy, Hash)]
+pub struct RiscV64MMArch;
+
+impl RiscV64MMArch {
+    /// 页表项中，表示可读的标志位
+    pub const ENTRY_FLAG_READ: usize = 1 << 1;
+    /// 页表项中，表示已被访问的标志位
+    pub const ENTRY_FLAG_ACCESSED: usize = 1 << 6;
+    /// 页表项中，表示已被写入的标志位
+    pub const ENTRY_FLAG_DIRTY: usize = 1 << 7;
+    /// 页表项中，物理页号的起始位
+    const ENTRY_PPN_SHIFT: usize = 10;
+}
+
+impl MemoryManagementArch for RiscV64MMArch {
+    /// 4K页
+    const PAGE_SHIFT: usize = 12;
+
+    /// 每个页表项占8字节，总共有512个页表项
+    const PAGE_ENTRY_SHIFT: usize = 9;
+
+    /// Sv39的三级页表
+    const PAGE_LEVELS: usize = 3;
+
+    /// 页表项的第[10, 53]位是物理页号，第[54, 63]位是保留位
+    const ENTRY_ADDRESS_SHIFT: usize = 54;
+
+    /// 物理页号在页表项中的位置与物理地址中的位置不同，因此需要重写
+    const ENTRY_ADDRESS_MASK: usize = ((1 << 44) - 1) << Self::ENTRY_PPN_SHIFT;
+
+    const ENTRY_FLAGS_MASK: usize = !Self::ENTRY_ADDRESS_MASK;
+
+    /// 硬件不会自动设置A/D位(QEMU会，但是其他实现可能会产生异常)，因此映射时直接设置
+    const ENTRY_FLAG_DEFAULT_PAGE: usize = Self::ENTRY_FLAG_PRESENT
+        | Self::ENTRY_FLAG_READ
+        | Self::ENTRY_FLAG_ACCESSED
+        | Self::ENTRY_FLAG_DIRTY;
+
+    /// 指向下一级页表的页表项，R/W/X位必须全为0
+    const ENTRY_FLAG_DEFAULT_TABLE: usize = Self::ENTRY_FLAG_PRESENT;
+
+    const ENTRY_FLAG_PRESENT: usize = 1 << 0;
+
+    const ENTRY_FLAG_READONLY: usize = 0;
+
+    const ENTRY_FLAG_READWRITE: usize = 1 << 2;
+
+    const ENTRY_FLAG_USER: usize = 1 << 4;
+
+    /// 缓存属性由PMA决定，页表项中没有对应的标志位
+    const ENTRY_FLAG_WRITE_THROUGH: usize = 0;
+
+    const ENTRY_FLAG_CACHE_DISABLE: usize = 0;
+
+    /// RISC-V不存在NO_EXEC标志位，只有EXEC标志位
+    const ENTRY_FLAG_NO_EXEC: usize = 0;
+
+    const ENTRY_FLAG_EXEC: usize = 1 << 3;
+
+    /// 物理地址与虚拟地址的偏移量
+    /// 0xffff_ffc0_0000_0000
+    const PHYS_OFFSET: usize = Self::PAGE_NEGATIVE_MASK + (Self::PAGE_ADDRESS_SIZE >> 1);
+
+    const USER_END_VADDR: VirtAddr = VirtAddr::new(0x0000_003f_ffff_ffff);
+    const USER_BRK_START: VirtAddr = VirtAddr::new(0x0000_0020_0000_0000);
+    const USER_STACK_START: VirtAddr = VirtAddr::new(0x0000_003f_f000_0000);
+    const USER_STACK_RND_BITS: usize = 18;
+    const USER_MMAP_RND_BITS: usize = 18;
+
+    /// 获取物理内存区域
+    unsafe fn init() -> &'static [PhysMemoryArea] {
+        extern "C" {
+            fn _text();
+            fn _etext();
+            fn _edata();
+            fn _erodata();
+            fn _end();
+        }
+
+        let bootstrap_info = RiscV64MMBootstrapInfo {
+            kernel_code_start: _text as usize,
+            kernel_code_end: _etext as usize,
+            kernel_data_end: _edata as usize,
+            kernel_rodata_end: _erodata as usize,
+            start_brk: _end as usize,
+        };
+        unsafe {
+            BOOTSTRAP_MM_INFO = Some(bootstrap_info);
+        }
+
+        let areas_count = Self::init_memory_area_from_boot_info().expect("init memory area failed");
+        return &PHYS_MEMORY_AREAS[0..areas_count];
+    }
+
+    /// 刷新TLB中，关于指定虚拟地址的条目
+    unsafe fn invalidate_page(address: VirtAddr) {
+        compiler_fence(Ordering::SeqCst);
+        asm!("sfence.vma {0}, zero", in(reg) address.data(), options(nostack));
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// 刷新TLB中，所有的条目
+    unsafe fn invalidate_all() {
+        compiler_fence(Ordering::SeqCst);
+        asm!("sfence.vma", options(nostack));
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// 获取顶级页表的物理地址
+    unsafe fn table(_table_kind: PageTableKind) -> PhysAddr {
+        let satp: usize = csr_read!("satp");
+        return PhysAddr::new((satp & SATP_PPN_MASK) << Self::PAGE_SHIFT);
+    }
+
+    /// 设置顶级页表的物理地址到处理器中
+    unsafe fn set_table(_table_kind: PageTableKind, table: PhysAddr) {
+        let satp = SATP_MODE_SV39 | (table.data() >> Self::PAGE_SHIFT);
+        compiler_fence(Ordering::SeqCst);
+        csr_write!("satp", satp);
+        asm!("sfence.vma", options(nostack));
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// 判断虚拟地址是否合法：第[38, 63]位必须全为0或者全为1
+    fn virt_is_valid(virt: VirtAddr) -> bool {
+        let x = virt.data() & Self::PAGE_NEGATIVE_MASK;
+        return x == 0 || x == Self::PAGE_NEGATIVE_MASK;
+    }
+
+    /// 获取内存管理初始化时，创建的第一个内核页表的地址
+    fn initial_page_table() -> PhysAddr {
+        unsafe {
+            return INITIAL_SATP_TABLE;
+        }
+    }
+
+    /// 创建新的顶层页表
+    ///
+    /// 该函数会创建页表并复制内核的映射到新的页表中
+    fn setup_new_usermapper() -> Result<crate::mm::ucontext::UserMapper, SystemError> {
+        let new_umapper: crate::mm::page::PageMapper<RiscV64MMArch, LockedFrameAllocator> = unsafe {
+            PageMapper::create(PageTableKind::User, LockedFrameAllocator)
+                .ok_or(SystemError::ENOMEM)?
+        };
+
+        let current_ktable: KernelMapper = KernelMapper::lock();
+        let copy_mapping = |entry_no| unsafe {
+            let entry: PageEntry<RiscV64MMArch> = current_ktable
+                .table()
+                .entry(entry_no)
+                .unwrap_or_else(|| panic!("entry {} not found", entry_no));
+            new_umapper.table().set_entry(entry_no, entry)
+        };
+
+        // 复制内核的映射
+        for entry_no in KERNEL_ROOT_ENTRY_NO..Self::PAGE_ENTRY_NUM {
+            copy_mapping(entry_no);
+        }
+
+        return Ok(crate::mm::ucontext::UserMapper::new(new_umapper));
+    }
+
+    /// 页表项中存放的是物理页号，位于第[10, 53]位
+    #[inline(always)]
+    fn make_entry(paddr: PhysAddr, page_flags: usize) -> usize {
+        return ((paddr.data() >> Self::PAGE_SHIFT) << Self::ENTRY_PPN_SHIFT) | page_flags;
+    }
+
+    #[inline(always)]
+    fn entry_address(entry: usize) -> PhysAddr {
+        return PhysAddr::new(
+            ((entry & Self::ENTRY_ADDRESS_MASK) >> Self::ENTRY_PPN_SHIFT) << Self::PAGE_SHIFT,
+        );
+    }
+}
+
+impl RiscV64MMArch {
+    /// 从启动信息中获取可用的物理内存区域
+    unsafe fn init_memory_area_from_boot_info() -> Result<usize, SystemError> {
+        let boot_info = boot_info();
+        let mut areas_count = 0usize;
+        let mut total_mem_size = 0usize;
+        for region in boot_info
+            .memory_regions()
+            .iter()
+            .filter(|r| r.mem_type == BootMemoryType::Usable)
+        {
+            if areas_count == PHYS_MEMORY_AREAS.len() {
+                break;
+            }
+            total_mem_size += region.size;
+            PHYS_MEMORY_AREAS[areas_count].base = region.base;
+            PHYS_MEMORY_AREAS[areas_count].size = region.size;
+            areas_count += 1;
+        }
+        if areas_count == 0 {
+            return Err(SystemError::ENOMEM);
+        }
+        if boot_info.dropped_memory_regions != 0 {
+            kwarn!(
+                "Too many memory regions from bootloader, {} regions dropped",
+                boot_info.dropped_memory_regions
+            );
+        }
+        kinfo!(
+            "Total memory size: {} MB, boot protocol: {:?}, total areas: {}, valid areas: {areas_count}",
+            total_mem_size / 1024 / 1024,
+            boot_info.protocol,
+            boot_info.memory_regions().len()
+        );
+
+        return Ok(areas_count);
+    }
+}
+
+/// 初始化内存管理模块
+pub fn mm_init() {
+    static _CALL_ONCE: AtomicBool = AtomicBool::new(false);
+    if _CALL_ONCE
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        panic!("mm_init() can only be called once");
+    }
+
+    unsafe { RiscV64MMArch::init() };
+    kdebug!("bootstrap info: {:?}", unsafe { BOOTSTRAP_MM_INFO });
+
+    // 初始化内存管理器
+    unsafe { allocator_init() };
+    // 允许内核访问用户页(copy_from_user等操作依赖这一点)
+    csr_set!("sstatus", SSTATUS_SUM);
+    kasan_init();
+    // enable mmio
+    mmio_init();
+}
+
+unsafe fn allocator_init() {
+    let virt_offset = BOOTSTRAP_MM_INFO.unwrap().start_brk;
+    let phy_offset =
+        unsafe { MMArch::virt_2_phys(VirtAddr::new(page_align_up(virt_offset))) }.unwrap();
+
+    let mut bump_allocator =
+        BumpAllocator::<RiscV64MMArch>::new(&PHYS_MEMORY_AREAS, phy_offset.data());
+    kdebug!(
+        "BumpAllocator created, offset={:?}",
+        bump_allocator.offset()
+    );
+
+    let new_page_table: PhysAddr;
+    // 使用bump分配器，把所有的内存页都映射到页表。
+    // 启动时在init.rs中建立的页表使用1G的大页，并且包含一个恒等映射，这里不再使用它
+    {
+        let mut mapper: crate::mm::page::PageMapper<MMArch, &mut BumpAllocator<MMArch>> =
+            crate::mm::page::PageMapper::<MMArch, _>::create(
+                PageTableKind::Kernel,
+                &mut bump_allocator,
+            )
+            .expect("Failed to create page mapper");
+        new_page_table = mapper.table().phys();
+        kdebug!("PageMapper created");
+
+        for area in PHYS_MEMORY_AREAS.iter() {
+            for i in 0..((area.size + MMArch::PAGE_SIZE - 1) / MMArch::PAGE_SIZE) {
+                let paddr = area.base.add(i * MMArch::PAGE_SIZE);
+                let vaddr = unsafe { MMArch::phys_2_virt(paddr) }.unwrap();
+                let flags = kernel_page_flags::<MMArch>(vaddr);
+
+                let flusher = mapper
+                    .map_phys(vaddr, paddr, flags)
+                    .expect("Failed to map frame");
+                // 暂时不刷新TLB
+                flusher.ignore();
+            }
+        }
+    }
+
+    unsafe {
+        INITIAL_SATP_TABLE = new_page_table;
+    }
+    kdebug!(
+        "After mapping all physical memory, DragonOS used: {} KB",
+        bump_allocator.offset() / 1024
+    );
+
+    // 初始化buddy_allocator
+    let buddy_allocator = unsafe { BuddyAllocator::<RiscV64MMArch>::new(bump_allocator).unwrap() };
+    // 设置全局的页帧分配器
+    unsafe { set_inner_allocator(buddy_allocator) };
+    kinfo!("Successfully initialized buddy allocator");
+
+    // make the new page table current
+    {
+        let mut binding = INNER_ALLOCATOR.lock();
+        let mut allocator_guard = binding.as_mut().unwrap();
+        compiler_fence(Ordering::SeqCst);
+        let mapper = crate::mm::page::PageMapper::<MMArch, _>::new(
+            PageTableKind::Kernel,
+            new_page_table,
+            &mut allocator_guard,
+        );
+        compiler_fence(Ordering::SeqCst);
+        mapper.make_current();
+        compiler_fence(Ordering::SeqCst);
+    }
+    kdebug!("Successfully enabled new page table");
+}
+
+/// 全局的页帧分配器
+#[derive(Debug, Clone, Copy, Hash)]
+pub struct LockedFrameAllocator;
+
+impl FrameAllocator for LockedFrameAllocator {
+    unsafe fn allocate(&mut self, count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
+        let r = if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
+            allocator.allocate(count)
+        } else {
+            None
+        };
+        // 注意：kasan的操作可能会再次申请物理页，因此需要在释放锁之后进行
+        if let Some((paddr, count)) = r {
+            kasan_alloc_pages(paddr, count);
+        }
+        return r;
+    }
+
+    unsafe fn free(&mut self, address: PhysAddr, count: PageFrameCount) {
+        assert!(count.data().is_power_of_two());
+        kasan_free_pages(address, count);
+        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
+            return allocator.free(address, count);
+        }
+    }
+
+    unsafe fn usage(&self) -> PageFrameUsage {
+        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
+            return allocator.usage();
+        } else {
+            panic!("usage error");
+        }
+    }
+}
+
+impl LockedFrameAllocator {
+    pub fn get_usage(&self) -> PageFrameUsage {
+        unsafe { self.usage() }
+    }
+}
+
+/// 获取内核地址默认的页面标志
+pub unsafe fn kernel_page_flags<A: MemoryManagementArch>(virt: VirtAddr) -> PageFlags<A> {
+    let info: RiscV64MMBootstrapInfo = BOOTSTRAP_MM_INFO.clone().unwrap();
+
+    if virt.data() >= info.kernel_code_start && virt.data() < info.kernel_code_end {
+        // Remap kernel code  execute
+        return PageFlags::new().set_execute(true).set_write(true);
+    } else if virt.data() >= info.kernel_data_end && virt.data() < info.kernel_rodata_end {
+        // Remap kernel rodata read only
+        return PageFlags::new().set_execute(true);
+    } else {
+        return PageFlags::new().set_write(true).set_execute(true);
+    }
+}
+
+unsafe fn set_inner_allocator(allocator: BuddyAllocator<MMArch>) {
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    if FLAG
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        panic!("Cannot set inner allocator twice!");
+    }
+    *INNER_ALLOCATOR.lock() = Some(allocator);
+}
+
+#[no_mangle]
+pub extern "C" fn rs_mm_init() {
+    mm_init();
+}
@@ -0,0 +1,40 @@
+//! RISC-V 64架构的支持
+//!
+//! 内核运行在S模式，由OpenSBI引导，使用Sv39分页。机器的信息(内存布局、时钟频率等)全部来自设备树。
+//! 目前这里只是移植的骨架，还不能编译出可以启动的内核，以下功能尚未实现：
+//!
+//! - 多核的启动(SBI的HSM扩展)与IPI
+//! - PLIC外部中断，因此除了时钟中断以外没有其他中断
+//! - 信号的投递，以及信号相关的类型定义
+//! - PCI、KVM等依赖x86_64硬件的子系统
+//! - C代码的移植：c头文件依赖的`asm/asm.h`等文件只有x86_64的版本，因此bindgen还无法为riscv64生成绑定
+//! - 链接与生成内核镜像的构建规则(`kernel/src/Makefile`中只有`kernel_rust`支持`KERNEL_ARCH=riscv64`)
+//!
+//! S模式下浮点单元是关闭的，因此内核以`riscv64imac-unknown-none-elf.json`为目标编译，链接脚本为`link.ld`。
+
+#[macro_use]
+pub mod asm;
+pub mod boot;
+pub mod cpu;
+pub mod crypto;
+pub mod init;
+pub mod interrupt;
+pub mod ipc;
+pub mod mm;
+pub mod process;
+pub mod rand;
+pub mod sbi;
+pub mod sched;
+pub mod smp;
+pub mod syscall;
+pub mod time;
+
+/// 导出内存管理的Arch结构体
+pub use self::mm::RiscV64MMArch as MMArch;
+
+pub use interrupt::RiscV64InterruptArch as CurrentIrqArch;
+
+pub use crate::arch::asm::pio::RiscV64PortIOArch as CurrentPortIOArch;
+
+pub use crate::arch::ipc::signal::RiscV64SignalArch as CurrentSignalArch;
+pub use crate::arch::time::RiscV64TimeArch as CurrentTimeArch;
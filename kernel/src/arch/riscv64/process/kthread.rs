@@ -0,0 +1,73 @@
+use core::arch::asm;
+
+use alloc::sync::Arc;
+
+use memoffset::offset_of;
+
+use crate::{
+    arch::{
+        asm::csr::{SSTATUS_SIE, SSTATUS_SPIE, SSTATUS_SPP},
+        interrupt::TrapFrame,
+    },
+    process::{
+        fork::CloneFlags,
+        kthread::{kernel_thread_bootstrap_stage2, KernelThreadCreateInfo, KernelThreadMechanism},
+        Pid, ProcessManager,
+    },
+    syscall::SystemError,
+};
+
+impl KernelThreadMechanism {
+    /// 伪造trapframe，创建内核线程
+    ///
+    /// ## 返回值
+    ///
+    /// 返回创建的内核线程的pid
+    pub fn __inner_create(
+        info: &Arc<KernelThreadCreateInfo>,
+        clone_flags: CloneFlags,
+    ) -> Result<Pid, SystemError> {
+        // WARNING: If create failed, we must drop the info manually or it will cause memory leak. (refcount will not decrease when create failed)
+        let create_info: *const KernelThreadCreateInfo =
+            KernelThreadCreateInfo::generate_unsafe_arc_ptr(info.clone());
+
+        let mut frame = TrapFrame::new();
+        frame.s0 = create_info as usize;
+        // 内核线程运行在S模式，并且使能中断
+        frame.sstatus = SSTATUS_SPP | SSTATUS_SPIE;
+        frame.sepc = kernel_thread_bootstrap_stage1 as usize;
+
+        // fork失败的话，子线程不会执行。否则将导致内存安全问题。
+        let pid = ProcessManager::fork(&mut frame, clone_flags).map_err(|e| {
+            unsafe { KernelThreadCreateInfo::parse_unsafe_arc_ptr(create_info) };
+            e
+        })?;
+
+        ProcessManager::find(pid)
+            .unwrap()
+            .set_name(info.name().clone());
+
+        return Ok(pid);
+    }
+}
+
+/// 内核线程引导函数的第一阶段
+///
+/// 当内核线程开始执行时，sp指向伪造的trapframe。这个函数从中取出指向Box<KernelThreadClosure>的指针，
+/// 丢弃trapframe并使能中断，然后跳转到第二阶段
+#[naked]
+pub(super) unsafe extern "C" fn kernel_thread_bootstrap_stage1() {
+    asm!(
+        "
+        ld a0, {off_s0}(sp)
+        addi sp, sp, {frame_size}
+        csrsi sstatus, {sie}
+        tail {stage2_func}
+        ",
+        off_s0 = const(offset_of!(TrapFrame, s0)),
+        frame_size = const(core::mem::size_of::<TrapFrame>()),
+        sie = const(SSTATUS_SIE),
+        stage2_func = sym kernel_thread_bootstrap_stage2,
+        options(noreturn)
+    )
+}
@@ -0,0 +1,338 @@
+use core::{
+    arch::asm,
+    intrinsics::unlikely,
+    mem::ManuallyDrop,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use memoffset::offset_of;
+
+use crate::{
+    exception::InterruptArch,
+    libs::spinlock::SpinLockGuard,
+    mm::{
+        percpu::{PerCpu, PerCpuVar},
+        VirtAddr,
+    },
+    process::{
+        fork::{CloneFlags, KernelCloneArgs},
+        KernelStack, ProcessControlBlock, ProcessFlags, ProcessManager, SwitchResult,
+        SWITCH_RESULT,
+    },
+    syscall::{Syscall, SystemError},
+};
+
+use self::kthread::kernel_thread_bootstrap_stage1;
+
+use super::{
+    interrupt::{trap::riscv64_ret_from_trap, TrapFrame},
+    CurrentIrqArch,
+};
+
+pub mod kthread;
+pub mod ptrace;
+pub mod syscall;
+
+#[allow(dead_code)]
+#[repr(align(32768))]
+union InitProcUnion {
+    /// 用于存放idle进程的内核栈
+    idle_stack: [u8; 32768],
+}
+
+#[link_section = ".data.init_proc_union"]
+#[no_mangle]
+static BSP_IDLE_STACK_SPACE: InitProcUnion = InitProcUnion {
+    idle_stack: [0; 32768],
+};
+
+/// PCB中与架构相关的信息
+///
+/// 进程切换发生在函数调用中，因此只需要保存ra、sp以及被调用者保存的寄存器s0~s11
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct ArchPCBInfo {
+    ra: usize,
+    sp: usize,
+    s0: usize,
+    s1: usize,
+    s2: usize,
+    s3: usize,
+    s4: usize,
+    s5: usize,
+    s6: usize,
+    s7: usize,
+    s8: usize,
+    s9: usize,
+    s10: usize,
+    s11: usize,
+}
+
+#[allow(dead_code)]
+impl ArchPCBInfo {
+    /// 创建一个新的ArchPCBInfo
+    ///
+    /// ## 参数
+    ///
+    /// - `kstack`：内核栈的引用，如果为None，则不会设置sp和s0(帧指针)。如果为Some，则会设置sp和s0为内核栈的最高地址。
+    ///
+    /// ## 返回值
+    ///
+    /// 返回一个新的ArchPCBInfo
+    pub fn new(kstack: Option<&KernelStack>) -> Self {
+        let mut r = Self {
+            ra: 0,
+            sp: 0,
+            s0: 0,
+            s1: 0,
+            s2: 0,
+            s3: 0,
+            s4: 0,
+            s5: 0,
+            s6: 0,
+            s7: 0,
+            s8: 0,
+            s9: 0,
+            s10: 0,
+            s11: 0,
+        };
+
+        if let Some(kstack) = kstack {
+            r.sp = kstack.stack_max_address().data();
+            r.s0 = kstack.stack_max_address().data();
+        }
+
+        return r;
+    }
+
+    pub fn set_stack(&mut self, stack: VirtAddr) {
+        self.sp = stack.data();
+    }
+
+    pub fn set_stack_base(&mut self, stack_base: VirtAddr) {
+        self.s0 = stack_base.data();
+    }
+}
+
+impl ProcessControlBlock {
+    /// 获取当前进程的pcb
+    pub fn arch_current_pcb() -> Arc<Self> {
+        // 获取栈指针
+        let sp: usize;
+        unsafe { asm!("mv {}, sp", out(reg) sp, options(nomem, nostack)) };
+        let stack_base = VirtAddr::new(sp & (!(KernelStack::ALIGN - 1)));
+        // 从内核栈的最低地址处取出pcb的地址
+        let p = stack_base.data() as *const *const ProcessControlBlock;
+        if unlikely((unsafe { *p }).is_null()) {
+            panic!("current_pcb is null");
+        }
+        unsafe {
+            // 为了防止内核栈的pcb指针被释放，这里需要将其包装一下，使得Arc的drop不会被调用
+            let arc_wrapper: ManuallyDrop<Arc<ProcessControlBlock>> =
+                ManuallyDrop::new(Arc::from_raw(*p));
+
+            let new_arc: Arc<ProcessControlBlock> = Arc::clone(&arc_wrapper);
+            return new_arc;
+        }
+    }
+}
+
+impl ProcessManager {
+    pub fn arch_init() {
+        {
+            // 初始化进程切换结果 per cpu变量
+            let mut switch_res_vec: Vec<SwitchResult> = Vec::new();
+            for _ in 0..PerCpu::MAX_CPU_NUM {
+                switch_res_vec.push(SwitchResult::new());
+            }
+            unsafe {
+                SWITCH_RESULT = Some(PerCpuVar::new(switch_res_vec).unwrap());
+            }
+        }
+    }
+
+    /// fork的过程中复制线程
+    ///
+    /// 由于这个过程与具体的架构相关，所以放在这里
+    pub fn copy_thread(
+        clone_args: &KernelCloneArgs,
+        _current_pcb: &Arc<ProcessControlBlock>,
+        new_pcb: &Arc<ProcessControlBlock>,
+        current_trapframe: &TrapFrame,
+    ) -> Result<(), SystemError> {
+        let mut child_trapframe = *current_trapframe;
+
+        // 子进程的返回值为0
+        child_trapframe.set_return_value(0);
+
+        // riscv64使用tp寄存器作为线程指针
+        if clone_args.flags.contains(CloneFlags::CLONE_SETTLS) {
+            child_trapframe.tp = clone_args.tls;
+        }
+
+        let mut new_arch_guard = new_pcb.arch_info();
+        let kernel_stack_guard = new_pcb.kernel_stack();
+
+        // 设置子进程在内核态开始执行时的sp、s0
+        new_arch_guard.set_stack_base(kernel_stack_guard.stack_max_address());
+
+        let trap_frame_vaddr: VirtAddr =
+            kernel_stack_guard.stack_max_address() - core::mem::size_of::<TrapFrame>();
+        new_arch_guard.set_stack(trap_frame_vaddr);
+
+        // 拷贝栈帧
+        unsafe {
+            let trap_frame_ptr = trap_frame_vaddr.data() as *mut TrapFrame;
+            *trap_frame_ptr = child_trapframe;
+        }
+
+        // 设置返回地址（子进程开始执行的指令地址）
+        if new_pcb.flags().contains(ProcessFlags::KTHREAD) {
+            new_arch_guard.ra = kernel_thread_bootstrap_stage1 as usize;
+        } else {
+            new_arch_guard.ra = riscv64_ret_from_trap as usize;
+        }
+
+        return Ok(());
+    }
+
+    /// 切换进程
+    ///
+    /// ## 参数
+    ///
+    /// - `prev`：上一个进程的pcb
+    /// - `next`：下一个进程的pcb
+    pub unsafe fn switch_process(prev: Arc<ProcessControlBlock>, next: Arc<ProcessControlBlock>) {
+        assert!(CurrentIrqArch::is_irq_enabled() == false);
+
+        // 切换地址空间
+        let next_addr_space = next.basic().user_vm().as_ref().unwrap().clone();
+        compiler_fence(Ordering::SeqCst);
+
+        next_addr_space.read().user_mapper.utable.make_current();
+        compiler_fence(Ordering::SeqCst);
+
+        // 获取arch info的锁，并强制泄露其守卫（切换上下文后，在switch_finish_hook中会释放锁）
+        let next_arch = SpinLockGuard::leak(next.arch_info());
+        let prev_arch = SpinLockGuard::leak(prev.arch_info());
+
+        // 恢复当前的 preempt count*2
+        ProcessManager::current_pcb().preempt_enable();
+        ProcessManager::current_pcb().preempt_enable();
+        SWITCH_RESULT.as_mut().unwrap().get_mut().prev_pcb = Some(prev.clone());
+        SWITCH_RESULT.as_mut().unwrap().get_mut().next_pcb = Some(next.clone());
+
+        compiler_fence(Ordering::SeqCst);
+        // 正式切换上下文
+        switch_to_inner(prev_arch, next_arch);
+    }
+}
+
+/// 保存上下文，然后切换进程，接着跳转到`switch_finish_hook`钩子函数
+///
+/// 保存的ra是这个函数的返回地址，因此进程再次被调度时，`switch_finish_hook`会直接返回到`switch_process`中
+#[naked]
+unsafe extern "C" fn switch_to_inner(prev: &mut ArchPCBInfo, next: &mut ArchPCBInfo) {
+    asm!(
+        "
+        # a0为prev，a1为next。保存prev的寄存器，然后加载next的寄存器
+        sd ra, {off_ra}(a0)
+        ld ra, {off_ra}(a1)
+
+        sd sp, {off_sp}(a0)
+        ld sp, {off_sp}(a1)
+
+        sd s0, {off_s0}(a0)
+        ld s0, {off_s0}(a1)
+        sd s1, {off_s1}(a0)
+        ld s1, {off_s1}(a1)
+        sd s2, {off_s2}(a0)
+        ld s2, {off_s2}(a1)
+        sd s3, {off_s3}(a0)
+        ld s3, {off_s3}(a1)
+        sd s4, {off_s4}(a0)
+        ld s4, {off_s4}(a1)
+        sd s5, {off_s5}(a0)
+        ld s5, {off_s5}(a1)
+        sd s6, {off_s6}(a0)
+        ld s6, {off_s6}(a1)
+        sd s7, {off_s7}(a0)
+        ld s7, {off_s7}(a1)
+        sd s8, {off_s8}(a0)
+        ld s8, {off_s8}(a1)
+        sd s9, {off_s9}(a0)
+        ld s9, {off_s9}(a1)
+        sd s10, {off_s10}(a0)
+        ld s10, {off_s10}(a1)
+        sd s11, {off_s11}(a0)
+        ld s11, {off_s11}(a1)
+
+        # switch_finish_hook释放两个进程的arch info的锁，然后返回到next的ra
+        tail {switch_hook}
+        ",
+        off_ra = const(offset_of!(ArchPCBInfo, ra)),
+        off_sp = const(offset_of!(ArchPCBInfo, sp)),
+        off_s0 = const(offset_of!(ArchPCBInfo, s0)),
+        off_s1 = const(offset_of!(ArchPCBInfo, s1)),
+        off_s2 = const(offset_of!(ArchPCBInfo, s2)),
+        off_s3 = const(offset_of!(ArchPCBInfo, s3)),
+        off_s4 = const(offset_of!(ArchPCBInfo, s4)),
+        off_s5 = const(offset_of!(ArchPCBInfo, s5)),
+        off_s6 = const(offset_of!(ArchPCBInfo, s6)),
+        off_s7 = const(offset_of!(ArchPCBInfo, s7)),
+        off_s8 = const(offset_of!(ArchPCBInfo, s8)),
+        off_s9 = const(offset_of!(ArchPCBInfo, s9)),
+        off_s10 = const(offset_of!(ArchPCBInfo, s10)),
+        off_s11 = const(offset_of!(ArchPCBInfo, s11)),
+        switch_hook = sym crate::process::switch_finish_hook,
+        options(noreturn),
+    );
+}
+
+pub unsafe fn arch_switch_to_user(path: String, argv: Vec<String>, envp: Vec<String>) -> ! {
+    // 以下代码不能发生中断
+    CurrentIrqArch::interrupt_disable();
+
+    let current_pcb = ProcessManager::current_pcb();
+    let trap_frame_vaddr = VirtAddr::new(
+        current_pcb.kernel_stack().stack_max_address().data() - core::mem::size_of::<TrapFrame>(),
+    );
+
+    // 删除kthread的标志
+    current_pcb.flags().remove(ProcessFlags::KTHREAD);
+    current_pcb.worker_private().take();
+
+    let mut trap_frame = TrapFrame::new();
+
+    compiler_fence(Ordering::SeqCst);
+    Syscall::do_execve(path, argv, envp, &mut trap_frame).unwrap_or_else(|e| {
+        panic!(
+            "arch_switch_to_user(): pid: {pid:?}, Failed to execve: , error: {e:?}",
+            pid = current_pcb.pid(),
+            e = e
+        );
+    });
+    compiler_fence(Ordering::SeqCst);
+
+    // 重要！在这里之后，一定要保证上面的引用计数变量、动态申请的变量、锁的守卫都被drop了，否则可能导致内存安全问题！
+
+    drop(current_pcb);
+
+    compiler_fence(Ordering::SeqCst);
+    ready_to_switch_to_user(trap_frame, trap_frame_vaddr.data());
+}
+
+/// 把栈帧放到内核栈的顶部，然后通过陷入返回的流程进入用户态
+#[inline(never)]
+unsafe extern "C" fn ready_to_switch_to_user(trap_frame: TrapFrame, trapframe_vaddr: usize) -> ! {
+    *(trapframe_vaddr as *mut TrapFrame) = trap_frame;
+    asm!(
+        "mv sp, {trapframe_vaddr}",
+        "tail {ret_from_trap}",
+        trapframe_vaddr = in(reg) trapframe_vaddr,
+        ret_from_trap = sym riscv64_ret_from_trap,
+        options(noreturn)
+    );
+}
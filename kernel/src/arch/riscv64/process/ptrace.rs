@@ -0,0 +1,86 @@
+use crate::arch::interrupt::TrapFrame;
+
+/// 用户态的通用寄存器，与Linux的`struct user_regs_struct`相同，是PTRACE_GETREGSET(NT_PRSTATUS)读取到的内容
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/riscv/include/uapi/asm/ptrace.h
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserRegsStruct {
+    pub pc: usize,
+    pub ra: usize,
+    pub sp: usize,
+    pub gp: usize,
+    pub tp: usize,
+    pub t0: usize,
+    pub t1: usize,
+    pub t2: usize,
+    pub s0: usize,
+    pub s1: usize,
+    pub a0: usize,
+    pub a1: usize,
+    pub a2: usize,
+    pub a3: usize,
+    pub a4: usize,
+    pub a5: usize,
+    pub a6: usize,
+    pub a7: usize,
+    pub s2: usize,
+    pub s3: usize,
+    pub s4: usize,
+    pub s5: usize,
+    pub s6: usize,
+    pub s7: usize,
+    pub s8: usize,
+    pub s9: usize,
+    pub s10: usize,
+    pub s11: usize,
+    pub t3: usize,
+    pub t4: usize,
+    pub t5: usize,
+    pub t6: usize,
+}
+
+impl UserRegsStruct {
+    /// 获取当前进程在用户态的寄存器
+    ///
+    /// ## 参数
+    ///
+    /// - `frame`：当前进程进入内核时保存的栈帧
+    /// - `_syscall_nr`：正在执行的系统调用号。riscv64的系统调用号保存在a7中，因此不需要单独记录
+    pub fn from_current(frame: &TrapFrame, _syscall_nr: usize) -> Self {
+        return Self {
+            pc: frame.sepc,
+            ra: frame.ra,
+            sp: frame.sp,
+            gp: frame.gp,
+            tp: frame.tp,
+            t0: frame.t0,
+            t1: frame.t1,
+            t2: frame.t2,
+            s0: frame.s0,
+            s1: frame.s1,
+            a0: frame.a0,
+            a1: frame.a1,
+            a2: frame.a2,
+            a3: frame.a3,
+            a4: frame.a4,
+            a5: frame.a5,
+            a6: frame.a6,
+            a7: frame.a7,
+            s2: frame.s2,
+            s3: frame.s3,
+            s4: frame.s4,
+            s5: frame.s5,
+            s6: frame.s6,
+            s7: frame.s7,
+            s8: frame.s8,
+            s9: frame.s9,
+            s10: frame.s10,
+            s11: frame.s11,
+            t3: frame.t3,
+            t4: frame.t4,
+            t5: frame.t5,
+            t6: frame.t6,
+        };
+    }
+}
@@ -0,0 +1,11 @@
+pub fn rand() -> usize {
+    let time: usize = csr_read!("time");
+    return time
+        .wrapping_mul(time)
+        .wrapping_add(998244353_usize.wrapping_mul(time));
+}
+
+/// 从硬件随机数发生器获取一个随机数。目前不支持，总是返回None
+pub fn arch_get_random_seed() -> Option<u64> {
+    return None;
+}
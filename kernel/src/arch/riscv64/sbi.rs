@@ -0,0 +1,94 @@
+//! SBI(Supervisor Binary Interface)调用
+//!
+//! 内核运行在S模式，时钟、关机与早期的控制台输出都需要通过`ecall`请求M模式的固件(如OpenSBI)完成。
+//!
+//! 参考 https://github.com/riscv-non-isa/riscv-sbi-doc
+
+use core::arch::asm;
+
+use crate::syscall::SystemError;
+
+/// 旧版扩展：向控制台输出一个字符
+const SBI_EXT_LEGACY_CONSOLE_PUTCHAR: usize = 0x01;
+/// 时钟扩展("TIME")
+const SBI_EXT_TIME: usize = 0x5449_4d45;
+/// 系统复位扩展("SRST")
+const SBI_EXT_SRST: usize = 0x5352_5354;
+
+const SBI_SRST_TYPE_SHUTDOWN: usize = 0;
+const SBI_SRST_TYPE_COLD_REBOOT: usize = 1;
+const SBI_SRST_REASON_NONE: usize = 0;
+
+/// 固件不支持这个调用
+const SBI_ERR_NOT_SUPPORTED: isize = -2;
+
+/// 发起SBI调用
+///
+/// ## 参数
+///
+/// - `eid`：扩展号
+/// - `fid`：扩展内的功能号
+/// - `args`：参数，依次放在a0、a1、a2中
+///
+/// ## 返回值
+///
+/// 成功时返回a1中的值
+#[inline(always)]
+unsafe fn sbi_call(eid: usize, fid: usize, args: [usize; 3]) -> Result<usize, SystemError> {
+    let (error, value): (isize, usize);
+    asm!(
+        "ecall",
+        inlateout("a0") args[0] => error,
+        inlateout("a1") args[1] => value,
+        in("a2") args[2],
+        in("a6") fid,
+        in("a7") eid,
+        options(nostack)
+    );
+    return match error {
+        0 => Ok(value),
+        SBI_ERR_NOT_SUPPORTED => Err(SystemError::ENOSYS),
+        _ => Err(SystemError::EINVAL),
+    };
+}
+
+/// 通过固件向控制台输出字符串，用于串口驱动初始化之前的输出。遇到'\0'时停止
+pub fn console_putstr(s: &[u8]) {
+    for c in s.iter().take_while(|c| **c != 0) {
+        // 旧版扩展没有返回值，a0中是未定义的值
+        unsafe { sbi_call(SBI_EXT_LEGACY_CONSOLE_PUTCHAR, 0, [*c as usize, 0, 0]).ok() };
+    }
+}
+
+/// 设置下一次时钟中断的时刻，同时清除当前挂起的时钟中断
+///
+/// ## 参数
+///
+/// - `stime`：`time`寄存器的值达到它时产生中断。设置为`u64::MAX`时不会再产生中断
+pub fn set_timer(stime: u64) {
+    unsafe { sbi_call(SBI_EXT_TIME, 0, [stime as usize, 0, 0]).ok() };
+}
+
+/// 请求固件关机，固件不支持时返回错误
+pub fn shutdown() -> Result<(), SystemError> {
+    unsafe {
+        sbi_call(
+            SBI_EXT_SRST,
+            0,
+            [SBI_SRST_TYPE_SHUTDOWN, SBI_SRST_REASON_NONE, 0],
+        )?
+    };
+    return Ok(());
+}
+
+/// 请求固件重启，固件不支持时返回错误
+pub fn reboot() -> Result<(), SystemError> {
+    unsafe {
+        sbi_call(
+            SBI_EXT_SRST,
+            0,
+            [SBI_SRST_TYPE_COLD_REBOOT, SBI_SRST_REASON_NONE, 0],
+        )?
+    };
+    return Ok(());
+}
@@ -0,0 +1,9 @@
+use crate::syscall::Syscall;
+
+/// 运行调度器
+///
+/// RISC-V的进程切换不依赖陷入时的栈帧，因此可以在内核上下文中直接调用调度器，不需要像x86_64那样发起系统调用
+#[no_mangle]
+pub extern "C" fn sched() {
+    Syscall::sched(false).ok();
+}
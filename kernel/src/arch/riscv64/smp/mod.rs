@@ -0,0 +1,6 @@
+/// 获取cpu的数量
+///
+/// 目前只启动引导核，其它核在固件中保持停止状态
+pub fn cpu_count() -> usize {
+    1
+}
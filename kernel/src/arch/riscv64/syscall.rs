@@ -0,0 +1,49 @@
+use core::intrinsics::unlikely;
+
+use crate::{
+    arch::{interrupt::TrapFrame, CurrentIrqArch},
+    exception::InterruptArch,
+    process::{
+        ptrace::{ptrace_report_syscall, ptrace_syscall_traced, PtraceStop},
+        seccomp::secure_computing,
+    },
+    syscall::{Syscall, SystemError},
+};
+
+/// 当前架构在seccomp过滤器的输入中的标识(AUDIT_ARCH_RISCV64)
+pub const AUDIT_ARCH: u32 = 0xc000_00f3;
+
+/// 处理来自用户态的ecall
+///
+/// 系统调用号位于a7，参数位于a0~a5，返回值写入a0
+pub fn syscall_handler(frame: &mut TrapFrame) {
+    let syscall_num = frame.a7;
+    let args = [frame.a0, frame.a1, frame.a2, frame.a3, frame.a4, frame.a5];
+
+    // 与x86_64的系统调用门一致，执行系统调用时允许中断
+    unsafe { CurrentIrqArch::interrupt_enable() };
+    // 被跟踪时，在系统调用的入口与出口停下
+    if unlikely(ptrace_syscall_traced()) {
+        ptrace_report_syscall(PtraceStop::SyscallEntry, frame, syscall_num);
+    }
+    let ret = do_syscall(syscall_num, &args, frame);
+    frame.set_return_value(ret);
+    if unlikely(ptrace_syscall_traced()) {
+        ptrace_report_syscall(PtraceStop::SyscallExit, frame, syscall_num);
+    }
+}
+
+/// 执行系统调用，返回它的返回值
+fn do_syscall(syscall_num: usize, args: &[usize; 6], frame: &mut TrapFrame) -> usize {
+    // 被seccomp过滤器拦截的系统调用不会被执行
+    if let Some(ret) = secure_computing(syscall_num, args, frame.sepc) {
+        return ret;
+    }
+    return Syscall::handle(syscall_num, args, frame)
+        .unwrap_or_else(|e| e.to_posix_errno() as usize);
+}
+
+/// 系统调用初始化。ecall总是进入陷入入口，因此不需要额外的设置
+pub fn arch_syscall_init() -> Result<(), SystemError> {
+    return Ok(());
+}
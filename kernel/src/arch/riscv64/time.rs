@@ -0,0 +1,104 @@
+//! time寄存器与基于SBI的时钟事件设备
+//!
+//! time寄存器以固定的频率递增，频率由设备树中`/cpus`节点的`timebase-frequency`属性给出(QEMU virt上为10MHz)。
+//! 定时器通过SBI的TIME扩展设置，只支持单次触发。
+
+use core::{
+    cmp::max,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::sync::Arc;
+
+use crate::{
+    driver::open_firmware::of_find_node_by_path,
+    kinfo,
+    syscall::SystemError,
+    time::{
+        hrtimer::{
+            clockevent_register, hrtimer_cpu_init, hrtimer_interrupt, ktime_get, ClockEventDevice,
+            Ktime,
+        },
+        TimeArch,
+    },
+};
+
+use super::sbi;
+
+/// 允许设置的最小间隔(ns)
+const MIN_DELTA_NS: u64 = 1000;
+const NSEC_PER_SEC: u128 = 1000000000;
+
+/// time寄存器的频率(Hz)，未知时为0
+static TIMEBASE_FREQ: AtomicU64 = AtomicU64::new(0);
+
+pub struct RiscV64TimeArch;
+
+impl TimeArch for RiscV64TimeArch {
+    fn get_cycles() -> usize {
+        csr_read!("time")
+    }
+
+    fn cycles2ns(cycles: usize) -> usize {
+        let freq = TIMEBASE_FREQ.load(Ordering::Relaxed);
+        if freq == 0 {
+            return 0;
+        }
+        return (cycles as u128 * NSEC_PER_SEC / freq as u128) as usize;
+    }
+}
+
+/// 通过SBI设置定时器的时钟事件设备
+#[derive(Debug)]
+struct SbiTimer;
+
+impl ClockEventDevice for SbiTimer {
+    fn name(&self) -> &str {
+        "riscv-sbi-timer"
+    }
+
+    fn set_oneshot(&self) {
+        // SBI定时器本身就是单次触发的
+    }
+
+    fn set_next_event(&self, expires: Option<Ktime>) {
+        let stime = match expires {
+            Some(ns) => {
+                let delta = max(ns.saturating_sub(ktime_get()), MIN_DELTA_NS);
+                let freq = TIMEBASE_FREQ.load(Ordering::Relaxed);
+                let cycles = delta as u128 * freq as u128 / NSEC_PER_SEC;
+                (RiscV64TimeArch::get_cycles() as u64).saturating_add(cycles as u64)
+            }
+            None => u64::MAX,
+        };
+        sbi::set_timer(stime);
+    }
+}
+
+/// 时钟中断的处理函数
+pub fn riscv_timer_interrupt(user: bool) {
+    // 切换到高精度模式之后，调度时钟由hrtimer模拟
+    if !hrtimer_interrupt(user) {
+        // 还没有切换到高精度模式，停止定时器，清除挂起的中断
+        sbi::set_timer(u64::MAX);
+    }
+}
+
+/// 从设备树中读取time寄存器的频率，注册时钟事件设备，然后把当前cpu切换到高精度模式
+///
+/// 需要在设备树展开之后调用
+///
+/// ## 错误
+///
+/// - `ENODEV`：设备树中没有`timebase-frequency`属性
+pub fn riscv_time_init() -> Result<(), SystemError> {
+    let freq = of_find_node_by_path("/cpus")
+        .and_then(|cpus| cpus.property_u32("timebase-frequency"))
+        .filter(|freq| *freq != 0)
+        .ok_or(SystemError::ENODEV)?;
+    TIMEBASE_FREQ.store(freq as u64, Ordering::Relaxed);
+    kinfo!("riscv64: timebase frequency {} Hz", freq);
+
+    clockevent_register(Arc::new(SbiTimer))?;
+    return hrtimer_cpu_init();
+}
@@ -1,31 +1,45 @@
-use crate::{driver::acpi::acpi_manager, kinfo, mm::percpu::PerCpu, syscall::SystemError};
+use crate::{
+    arch::cpu::current_cpu_id, driver::acpi::acpi_manager, kinfo, kwarn, mm::percpu::PerCpu,
+    syscall::SystemError,
+};
 
 use super::smp::SMP_BOOT_DATA;
 
 pub(super) fn early_acpi_boot_init() -> Result<(), SystemError> {
     // 在这里解析madt，初始化smp boot data
-
-    let platform_info = acpi_manager().platform_info().ok_or(SystemError::ENODEV)?;
-    let processor_info = platform_info.processor_info.ok_or(SystemError::ENODEV)?;
+    let madt = acpi_manager().madt_info()?;
+    let bsp_apic_id = current_cpu_id() as usize;
 
     unsafe {
-        SMP_BOOT_DATA.set_phys_id(0, processor_info.boot_processor.local_apic_id as usize);
+        SMP_BOOT_DATA.set_phys_id(0, bsp_apic_id);
         let mut cnt = 1;
-        for ap in processor_info.application_processors.iter() {
-            if cnt >= PerCpu::MAX_CPU_NUM {
-                break;
+        for cpu in madt.cpus.iter() {
+            let apic_id = cpu.apic_id as usize;
+            if apic_id == bsp_apic_id {
+                continue;
+            }
+            // 目前Local APIC ID被直接用作cpu id，超出范围的处理器无法启动
+            if apic_id >= PerCpu::MAX_CPU_NUM || cnt >= PerCpu::MAX_CPU_NUM {
+                kwarn!(
+                    "early_acpi_boot_init: ignore cpu, uid: {}, apic id: {}",
+                    cpu.processor_uid,
+                    apic_id
+                );
+                continue;
             }
-            SMP_BOOT_DATA.set_phys_id(cnt, ap.local_apic_id as usize);
+            SMP_BOOT_DATA.set_phys_id(cnt, apic_id);
             cnt += 1;
         }
         SMP_BOOT_DATA.set_cpu_count(cnt);
         SMP_BOOT_DATA.mark_initialized();
     }
     kinfo!(
-        "early_acpi_boot_init: cpu_count: {}\n",
-        SMP_BOOT_DATA.cpu_count()
+        "early_acpi_boot_init: cpu_count: {}, bsp apic id: {}, lapic: {:?}, ioapic count: {}\n",
+        SMP_BOOT_DATA.cpu_count(),
+        bsp_apic_id,
+        madt.local_apic_address,
+        madt.io_apics.len()
     );
 
-    // todo!("early_acpi_boot_init")
     return Ok(());
 }
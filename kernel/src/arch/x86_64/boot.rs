@@ -0,0 +1,247 @@
+//! 从multiboot2启动信息中解析[`BootInfo`]
+//!
+//! BIOS与UEFI下都通过multiboot2协议启动。UEFI下引导程序会额外提供UEFI内存映射、UEFI系统表等标签，
+//! 并且通常只提供ACPI 2.0的RSDP。
+//!
+//! 参考 https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html#Boot-information-format
+
+use crate::{
+    arch::MMArch,
+    init::boot_info::{
+        BootFramebuffer, BootFramebufferType, BootInfo, BootMemoryRegion, BootMemoryType,
+        BootProtocol,
+    },
+    kwarn,
+    mm::{MemoryManagementArch, PhysAddr},
+};
+
+extern "C" {
+    fn multiboot2_raw_info(size: *mut u32) -> *const u8;
+}
+
+const MULTIBOOT_TAG_TYPE_END: u32 = 0;
+const MULTIBOOT_TAG_TYPE_MODULE: u32 = 3;
+const MULTIBOOT_TAG_TYPE_MMAP: u32 = 6;
+const MULTIBOOT_TAG_TYPE_FRAMEBUFFER: u32 = 8;
+const MULTIBOOT_TAG_TYPE_EFI64: u32 = 12;
+const MULTIBOOT_TAG_TYPE_ACPI_OLD: u32 = 14;
+const MULTIBOOT_TAG_TYPE_ACPI_NEW: u32 = 15;
+const MULTIBOOT_TAG_TYPE_EFI_MMAP: u32 = 17;
+
+/// multiboot2内存映射中的内存类型
+const MULTIBOOT_MEMORY_AVAILABLE: u32 = 1;
+const MULTIBOOT_MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+const MULTIBOOT_MEMORY_NVS: u32 = 4;
+const MULTIBOOT_MEMORY_BADRAM: u32 = 5;
+
+/// UEFI内存描述符中的内存类型
+const EFI_LOADER_CODE: u32 = 1;
+const EFI_LOADER_DATA: u32 = 2;
+const EFI_BOOT_SERVICES_CODE: u32 = 3;
+const EFI_BOOT_SERVICES_DATA: u32 = 4;
+const EFI_CONVENTIONAL_MEMORY: u32 = 7;
+const EFI_UNUSABLE_MEMORY: u32 = 8;
+const EFI_ACPI_RECLAIM_MEMORY: u32 = 9;
+const EFI_ACPI_MEMORY_NVS: u32 = 10;
+/// UEFI内存描述符中的页大小
+const EFI_PAGE_SIZE: usize = 4096;
+
+/// 命令行为这个字符串的multiboot2模块被当作设备树
+const MODULE_CMDLINE_DTB: &str = "dtb";
+/// 设备树的最大长度
+const FDT_MAX_SIZE: usize = 128 * 1024;
+/// 设备树的副本。模块所在的内存会在内存管理初始化之后被分配出去，因此需要在这之前复制
+static mut FDT_BUF: [u8; FDT_MAX_SIZE] = [0; FDT_MAX_SIZE];
+
+/// multiboot2启动信息中的一个标签
+struct Multiboot2Tag<'a> {
+    type_: u32,
+    /// 标签的全部内容，包括开头的类型与大小
+    data: &'a [u8],
+}
+
+impl Multiboot2Tag<'_> {
+    fn read_u32(&self, off: usize) -> Option<u32> {
+        let bytes = self.data.get(off..off + 4)?;
+        return Some(u32::from_ne_bytes(bytes.try_into().unwrap()));
+    }
+
+    fn read_u64(&self, off: usize) -> Option<u64> {
+        let bytes = self.data.get(off..off + 8)?;
+        return Some(u64::from_ne_bytes(bytes.try_into().unwrap()));
+    }
+}
+
+/// 遍历multiboot2启动信息中的标签
+fn multiboot2_tags(raw: &[u8]) -> impl Iterator<Item = Multiboot2Tag<'_>> {
+    let read_u32 = |off: usize| u32::from_ne_bytes(raw[off..off + 4].try_into().unwrap());
+    // 前8字节是启动信息的总大小与保留的0
+    let mut off = 8;
+    return core::iter::from_fn(move || {
+        if off + 8 > raw.len() {
+            return None;
+        }
+        let (type_, size) = (read_u32(off), read_u32(off + 4) as usize);
+        if type_ == MULTIBOOT_TAG_TYPE_END || size < 8 || off + size > raw.len() {
+            return None;
+        }
+        let tag = Multiboot2Tag {
+            type_,
+            data: &raw[off..off + size],
+        };
+        off += (size + 7) & !7;
+        return Some(tag);
+    });
+}
+
+/// 解析multiboot2的内存映射标签
+fn parse_mmap(info: &mut BootInfo, tag: &Multiboot2Tag) {
+    let entry_size = tag.read_u32(8).unwrap_or(0) as usize;
+    if entry_size < 24 {
+        return;
+    }
+    let mut off = 16;
+    while off + entry_size <= tag.data.len() {
+        let (addr, len, type_) = (
+            tag.read_u64(off).unwrap(),
+            tag.read_u64(off + 8).unwrap(),
+            tag.read_u32(off + 16).unwrap(),
+        );
+        let mem_type = match type_ {
+            MULTIBOOT_MEMORY_AVAILABLE => BootMemoryType::Usable,
+            MULTIBOOT_MEMORY_ACPI_RECLAIMABLE => BootMemoryType::AcpiReclaimable,
+            MULTIBOOT_MEMORY_NVS => BootMemoryType::AcpiNvs,
+            MULTIBOOT_MEMORY_BADRAM => BootMemoryType::Unusable,
+            _ => BootMemoryType::Reserved,
+        };
+        info.add_memory_region(BootMemoryRegion::new(
+            PhysAddr::new(addr as usize),
+            len as usize,
+            mem_type,
+        ));
+        off += entry_size;
+    }
+}
+
+/// 解析UEFI内存映射标签。引导程序已经退出了启动服务，因此启动服务使用的内存也可以使用
+fn parse_efi_mmap(info: &mut BootInfo, tag: &Multiboot2Tag) {
+    let desc_size = tag.read_u32(8).unwrap_or(0) as usize;
+    if desc_size < 40 {
+        return;
+    }
+    let mut off = 16;
+    while off + desc_size <= tag.data.len() {
+        let (type_, addr, pages) = (
+            tag.read_u32(off).unwrap(),
+            tag.read_u64(off + 8).unwrap(),
+            tag.read_u64(off + 24).unwrap(),
+        );
+        let mem_type = match type_ {
+            EFI_LOADER_CODE
+            | EFI_LOADER_DATA
+            | EFI_BOOT_SERVICES_CODE
+            | EFI_BOOT_SERVICES_DATA
+            | EFI_CONVENTIONAL_MEMORY => BootMemoryType::Usable,
+            EFI_ACPI_RECLAIM_MEMORY => BootMemoryType::AcpiReclaimable,
+            EFI_ACPI_MEMORY_NVS => BootMemoryType::AcpiNvs,
+            EFI_UNUSABLE_MEMORY => BootMemoryType::Unusable,
+            _ => BootMemoryType::Reserved,
+        };
+        info.add_memory_region(BootMemoryRegion::new(
+            PhysAddr::new(addr as usize),
+            pages as usize * EFI_PAGE_SIZE,
+            mem_type,
+        ));
+        off += desc_size;
+    }
+}
+
+/// 解析帧缓冲区标签
+fn parse_framebuffer(tag: &Multiboot2Tag) -> Option<BootFramebuffer> {
+    let fb_type = match *tag.data.get(29)? {
+        0 => BootFramebufferType::Indexed,
+        1 => BootFramebufferType::Rgb,
+        2 => BootFramebufferType::Text,
+        _ => return None,
+    };
+    return Some(BootFramebuffer {
+        addr: PhysAddr::new(tag.read_u64(8)? as usize),
+        pitch: tag.read_u32(16)?,
+        width: tag.read_u32(20)?,
+        height: tag.read_u32(24)?,
+        bpp: *tag.data.get(28)?,
+        fb_type,
+    });
+}
+
+/// 解析模块标签。命令行为[`MODULE_CMDLINE_DTB`]的模块会被复制到[`FDT_BUF`]，作为设备树
+fn parse_module(info: &mut BootInfo, tag: &Multiboot2Tag) {
+    let (start, end) = match (tag.read_u32(8), tag.read_u32(12)) {
+        (Some(start), Some(end)) if end > start => (start as usize, end as usize),
+        _ => return,
+    };
+    let cmdline = tag.data.get(16..).unwrap_or_default();
+    let cmdline = &cmdline[..cmdline
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(cmdline.len())];
+    if cmdline != MODULE_CMDLINE_DTB.as_bytes() {
+        return;
+    }
+
+    let size = end - start;
+    if size > FDT_MAX_SIZE {
+        kwarn!(
+            "multiboot2: dtb module is too large ({} bytes, max {}), ignored",
+            size,
+            FDT_MAX_SIZE
+        );
+        return;
+    }
+    unsafe {
+        let vaddr = MMArch::phys_2_virt(PhysAddr::new(start)).unwrap();
+        let src = core::slice::from_raw_parts(vaddr.data() as *const u8, size);
+        FDT_BUF[..size].copy_from_slice(src);
+        info.fdt = Some(&FDT_BUF[..size]);
+    }
+}
+
+/// 从multiboot2启动信息中解析启动信息
+///
+/// 两种内存映射都存在时使用multiboot2的内存映射，只有UEFI内存映射时使用UEFI内存映射；
+/// 两种RSDP都存在时使用ACPI 2.0的RSDP，它可以提供64位的XSDT地址
+pub fn arch_boot_info_init(info: &mut BootInfo) {
+    let raw = unsafe {
+        let mut size = 0u32;
+        let ptr = multiboot2_raw_info(&mut size);
+        core::slice::from_raw_parts(ptr, size as usize)
+    };
+    info.protocol = BootProtocol::Multiboot2;
+
+    let mut efi_mmap = None;
+    let mut rsdp_v2 = false;
+    for tag in multiboot2_tags(raw) {
+        match tag.type_ {
+            MULTIBOOT_TAG_TYPE_MODULE => parse_module(info, &tag),
+            MULTIBOOT_TAG_TYPE_MMAP => parse_mmap(info, &tag),
+            MULTIBOOT_TAG_TYPE_EFI_MMAP => efi_mmap = Some(tag),
+            MULTIBOOT_TAG_TYPE_FRAMEBUFFER => info.framebuffer = parse_framebuffer(&tag),
+            MULTIBOOT_TAG_TYPE_EFI64 => {
+                info.protocol = BootProtocol::Multiboot2Efi;
+                info.efi_system_table = tag.read_u64(8).map(|addr| PhysAddr::new(addr as usize));
+            }
+            MULTIBOOT_TAG_TYPE_ACPI_OLD if !rsdp_v2 => info.set_acpi_rsdp(&tag.data[8..]),
+            MULTIBOOT_TAG_TYPE_ACPI_NEW => {
+                info.set_acpi_rsdp(&tag.data[8..]);
+                rsdp_v2 = true;
+            }
+            _ => {}
+        }
+    }
+
+    if !info.has_memory_regions() {
+        if let Some(tag) = efi_mmap {
+            parse_efi_mmap(info, &tag);
+        }
+    }
+}
@@ -1,4 +1,18 @@
-use x86::cpuid::{cpuid, CpuIdResult};
+use core::{arch::asm, hint::spin_loop};
+
+use x86::{
+    cpuid::{cpuid, CpuIdResult},
+    dtables::{lidt, DescriptorTablePointer},
+};
+
+use crate::{
+    arch::CurrentIrqArch,
+    driver::acpi::{acpi_manager, fadt::AddressSpace},
+    exception::InterruptArch,
+};
+
+/// 每种复位方式失败之后，等待的轮询次数
+const CPU_RESET_WAIT_LOOPS: usize = 1000000;
 
 /// @brief 获取当前cpu的apic id
 #[inline]
@@ -9,8 +23,62 @@ pub fn current_cpu_id() -> u32 {
 }
 
 /// 重置cpu
+///
+/// 依次尝试：FADT中的复位寄存器、键盘控制器、三重错误
 pub fn cpu_reset() -> ! {
-    // 重启计算机
+    unsafe { CurrentIrqArch::interrupt_disable() };
+
+    if let Ok(fadt) = acpi_manager().fadt_info() {
+        if let Some(reg) = fadt.reset_reg {
+            if reg.space == AddressSpace::SystemIo && reg.is_valid() {
+                unsafe { x86::io::outb(reg.address as u16, fadt.reset_value) };
+                for _ in 0..CPU_RESET_WAIT_LOOPS {
+                    spin_loop();
+                }
+            }
+        }
+    }
+
+    // 通过键盘控制器重启计算机
     unsafe { x86::io::outb(0x64, 0xfe) };
-    loop {}
+    for _ in 0..CPU_RESET_WAIT_LOOPS {
+        spin_loop();
+    }
+
+    // 加载一个空的IDT，然后触发异常，产生三重错误
+    unsafe {
+        let idt: DescriptorTablePointer<u64> = DescriptorTablePointer {
+            limit: 0,
+            base: core::ptr::null(),
+        };
+        lidt(&idt);
+        asm!("int3");
+    }
+    loop {
+        spin_loop();
+    }
+}
+
+/// 默认的空闲方式：打开中断并执行HLT，直到被中断唤醒
+///
+/// 调用时中断必须已经关闭，STI的下一条指令执行完之前不会响应中断，因此不会错过唤醒
+#[inline]
+pub fn arch_cpu_idle() {
+    unsafe { asm!("sti; hlt", options(nomem, nostack)) };
+}
+
+/// 获取当前函数的帧指针(rbp)，用于回溯调用栈
+#[inline(always)]
+pub fn current_frame_pointer() -> usize {
+    let rbp: usize;
+    unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    return rbp;
+}
+
+/// 停止当前cpu的运行
+pub fn cpu_halt() -> ! {
+    unsafe { CurrentIrqArch::interrupt_disable() };
+    loop {
+        unsafe { x86::halt() };
+    }
 }
@@ -0,0 +1,162 @@
+//! x86_64架构的密码学算法实现：使用AES-NI指令的AES
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/crypto/aesni-intel_glue.c
+
+use core::arch::asm;
+
+use alloc::{boxed::Box, vec::Vec};
+use x86::cpuid::CpuId;
+
+use crate::{
+    crypto::{
+        aes::{as_aes_block, AesKey, AES_BLOCK_SIZE},
+        Cipher, CryptoAlg, CryptoAlgType,
+    },
+    syscall::SystemError,
+};
+
+use super::fpu::kernel_fpu_begin;
+
+/// 返回当前cpu支持的密码学算法实现
+pub fn arch_crypto_algs() -> Vec<CryptoAlg> {
+    let mut algs = Vec::new();
+    let has_aesni = CpuId::new()
+        .get_feature_info()
+        .map_or(false, |f| f.has_aesni());
+    if has_aesni {
+        algs.push(CryptoAlg {
+            name: "aes",
+            driver_name: "aes-aesni",
+            priority: 300,
+            alg_type: CryptoAlgType::Cipher(|| Box::new(AesNi::new())),
+        });
+    }
+    return algs;
+}
+
+/// AES-NI使用的轮密钥
+#[derive(Debug)]
+struct AesNiKey {
+    /// 加密的轮密钥
+    enc: [[u8; AES_BLOCK_SIZE]; 15],
+    /// 解密的轮密钥(等价逆密码)：逆序排列，并且除了第一轮与最后一轮以外都经过InvMixColumns变换
+    dec: [[u8; AES_BLOCK_SIZE]; 15],
+    rounds: usize,
+}
+
+impl Drop for AesNiKey {
+    fn drop(&mut self) {
+        self.enc = [[0; AES_BLOCK_SIZE]; 15];
+        self.dec = [[0; AES_BLOCK_SIZE]; 15];
+    }
+}
+
+/// aes-aesni：使用AES-NI指令的AES
+#[derive(Debug, Default)]
+pub struct AesNi {
+    key: Option<AesNiKey>,
+}
+
+impl AesNi {
+    pub fn new() -> Self {
+        return Self { key: None };
+    }
+}
+
+impl Cipher for AesNi {
+    fn block_size(&self) -> usize {
+        return AES_BLOCK_SIZE;
+    }
+
+    fn set_key(&mut self, key: &[u8]) -> Result<(), SystemError> {
+        // 密钥展开不是性能关键路径，复用软件实现
+        let expanded = AesKey::expand(key)?;
+        let rounds = expanded.rounds();
+        let mut ni_key = AesNiKey {
+            enc: [[0; AES_BLOCK_SIZE]; 15],
+            dec: [[0; AES_BLOCK_SIZE]; 15],
+            rounds,
+        };
+        for i in 0..=rounds {
+            ni_key.enc[i].copy_from_slice(expanded.round_key(i));
+        }
+
+        ni_key.dec[0] = ni_key.enc[rounds];
+        ni_key.dec[rounds] = ni_key.enc[0];
+        let _fpu = kernel_fpu_begin();
+        for i in 1..rounds {
+            unsafe {
+                asm!(
+                    "movdqu xmm0, [{src}]",
+                    "aesimc xmm0, xmm0",
+                    "movdqu [{dst}], xmm0",
+                    src = in(reg) ni_key.enc[rounds - i].as_ptr(),
+                    dst = in(reg) ni_key.dec[i].as_mut_ptr(),
+                    out("xmm0") _,
+                    options(nostack),
+                );
+            }
+        }
+
+        self.key = Some(ni_key);
+        return Ok(());
+    }
+
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), SystemError> {
+        let key = self.key.as_ref().ok_or(SystemError::ENOKEY)?;
+        let block = as_aes_block(block)?;
+        let _fpu = kernel_fpu_begin();
+        unsafe {
+            asm!(
+                "movdqu xmm0, [{blk}]",
+                "movdqu xmm1, [{rk}]",
+                "pxor xmm0, xmm1",
+                "2:",
+                "add {rk}, 16",
+                "movdqu xmm1, [{rk}]",
+                "aesenc xmm0, xmm1",
+                "dec {n}",
+                "jnz 2b",
+                "movdqu xmm1, [{rk} + 16]",
+                "aesenclast xmm0, xmm1",
+                "movdqu [{blk}], xmm0",
+                blk = in(reg) block.as_mut_ptr(),
+                rk = inout(reg) key.enc.as_ptr() => _,
+                n = inout(reg) key.rounds - 1 => _,
+                out("xmm0") _,
+                out("xmm1") _,
+                options(nostack),
+            );
+        }
+        return Ok(());
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), SystemError> {
+        let key = self.key.as_ref().ok_or(SystemError::ENOKEY)?;
+        let block = as_aes_block(block)?;
+        let _fpu = kernel_fpu_begin();
+        unsafe {
+            asm!(
+                "movdqu xmm0, [{blk}]",
+                "movdqu xmm1, [{rk}]",
+                "pxor xmm0, xmm1",
+                "2:",
+                "add {rk}, 16",
+                "movdqu xmm1, [{rk}]",
+                "aesdec xmm0, xmm1",
+                "dec {n}",
+                "jnz 2b",
+                "movdqu xmm1, [{rk} + 16]",
+                "aesdeclast xmm0, xmm1",
+                "movdqu [{blk}], xmm0",
+                blk = in(reg) block.as_mut_ptr(),
+                rk = inout(reg) key.dec.as_ptr() => _,
+                n = inout(reg) key.rounds - 1 => _,
+                out("xmm0") _,
+                out("xmm1") _,
+                options(nostack),
+            );
+        }
+        return Ok(());
+    }
+}
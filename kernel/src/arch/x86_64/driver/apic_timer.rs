@@ -0,0 +1,146 @@
+//! Local APIC定时器作为时钟事件设备：支持TSC-deadline时使用TSC-deadline模式，否则使用单次触发模式
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/apic/apic.c#460
+
+use alloc::sync::Arc;
+use x86::cpuid::CpuId;
+
+use crate::{
+    arch::CurrentTimeArch,
+    kinfo,
+    syscall::SystemError,
+    time::{
+        hrtimer::{clockevent_register, ktime_get, ClockEventDevice, Ktime},
+        TimeArch,
+    },
+};
+
+use super::tsc::TSCManager;
+
+extern "C" {
+    fn apic_timer_set_mode(mode: u32, masked: bool);
+    fn apic_timer_program_count(cnt: u32);
+    fn apic_timer_program_tsc_deadline(tsc: u64);
+    fn apic_timer_current_count() -> u32;
+}
+
+/// Local APIC定时器的计时模式，与apic.h中的定义一致
+const APIC_LVT_TIMER_ONE_SHOT: u32 = 0;
+const APIC_LVT_TIMER_TSC_DEADLINE: u32 = 2;
+
+/// 校准单次触发模式时的采样时间(ms)
+const CALIBRATE_MS: u64 = 10;
+/// 单次触发模式下允许设置的最小间隔(ns)
+const MIN_DELTA_NS: u64 = 1000;
+
+#[derive(Debug)]
+pub struct LocalApicTimer {
+    /// 是否使用TSC-deadline模式
+    tsc_deadline: bool,
+    /// 单次触发模式下，每毫秒递减的计数值
+    counts_per_ms: u64,
+}
+
+impl LocalApicTimer {
+    /// 探测Local APIC定时器的工作模式，必要时校准它的频率
+    ///
+    /// 调用时中断必须已经关闭
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：TSC的频率未知，或者校准失败
+    fn probe() -> Result<Self, SystemError> {
+        let tsc_khz = TSCManager::tsc_khz();
+        if tsc_khz == 0 {
+            return Err(SystemError::ENODEV);
+        }
+
+        let tsc_deadline = CpuId::new()
+            .get_feature_info()
+            .map_or(false, |f| f.has_tsc_deadline());
+        if tsc_deadline {
+            return Ok(Self {
+                tsc_deadline,
+                counts_per_ms: 0,
+            });
+        }
+
+        // 以TSC为基准，测量定时器在一段时间内递减的计数值
+        let counts_per_ms = unsafe {
+            apic_timer_set_mode(APIC_LVT_TIMER_ONE_SHOT, true);
+            apic_timer_program_count(u32::MAX);
+            let start = CurrentTimeArch::get_cycles() as u64;
+            while (CurrentTimeArch::get_cycles() as u64).wrapping_sub(start)
+                < tsc_khz * CALIBRATE_MS
+            {
+                core::hint::spin_loop();
+            }
+            let remain = apic_timer_current_count();
+            apic_timer_program_count(0);
+            (u32::MAX - remain) as u64 / CALIBRATE_MS
+        };
+        if counts_per_ms == 0 {
+            return Err(SystemError::ENODEV);
+        }
+
+        return Ok(Self {
+            tsc_deadline,
+            counts_per_ms,
+        });
+    }
+}
+
+impl ClockEventDevice for LocalApicTimer {
+    fn name(&self) -> &str {
+        if self.tsc_deadline {
+            "lapic-deadline"
+        } else {
+            "lapic-oneshot"
+        }
+    }
+
+    fn set_oneshot(&self) {
+        let mode = if self.tsc_deadline {
+            APIC_LVT_TIMER_TSC_DEADLINE
+        } else {
+            APIC_LVT_TIMER_ONE_SHOT
+        };
+        unsafe { apic_timer_set_mode(mode, false) };
+    }
+
+    fn set_next_event(&self, expires: Option<Ktime>) {
+        if self.tsc_deadline {
+            // 写入0时停止定时器
+            let tsc = match expires {
+                Some(ns) => {
+                    let tsc = ns as u128 * TSCManager::tsc_khz() as u128 / 1000000;
+                    core::cmp::max(tsc as u64, 1)
+                }
+                None => 0,
+            };
+            unsafe { apic_timer_program_tsc_deadline(tsc) };
+            return;
+        }
+
+        let cnt = match expires {
+            Some(ns) => {
+                let delta = core::cmp::max(ns.saturating_sub(ktime_get()), MIN_DELTA_NS);
+                let cnt = delta as u128 * self.counts_per_ms as u128 / 1000000;
+                cnt.clamp(1, u32::MAX as u128) as u32
+            }
+            None => 0,
+        };
+        unsafe { apic_timer_program_count(cnt) };
+    }
+}
+
+/// 探测Local APIC定时器，并注册为时钟事件设备
+pub fn x86_clockevent_init() -> Result<(), SystemError> {
+    let timer = LocalApicTimer::probe()?;
+    kinfo!(
+        "lapic timer: {} mode, {} counts/ms",
+        timer.name(),
+        timer.counts_per_ms
+    );
+    clockevent_register(Arc::new(timer))
+}
@@ -1,4 +1,7 @@
 use super::{
+    apic_timer::x86_clockevent_init,
+    cpufreq::x86_cpufreq_init,
+    cpuidle::x86_cpuidle_init,
     hpet::{hpet_init, hpet_instance},
     tsc::TSCManager,
 };
@@ -29,3 +32,24 @@ unsafe extern "C" fn rs_tsc_init() -> i32 {
 unsafe extern "C" fn rs_handle_hpet_irq(timer_num: u32) {
     hpet_instance().handle_irq(timer_num);
 }
+
+#[no_mangle]
+unsafe extern "C" fn rs_cpuidle_init() -> i32 {
+    x86_cpuidle_init()
+        .map(|_| 0)
+        .unwrap_or_else(|e| e.to_posix_errno())
+}
+
+#[no_mangle]
+unsafe extern "C" fn rs_cpufreq_init() -> i32 {
+    x86_cpufreq_init()
+        .map(|_| 0)
+        .unwrap_or_else(|e| e.to_posix_errno())
+}
+
+#[no_mangle]
+unsafe extern "C" fn rs_clockevent_init() -> i32 {
+    x86_clockevent_init()
+        .map(|_| 0)
+        .unwrap_or_else(|e| e.to_posix_errno())
+}
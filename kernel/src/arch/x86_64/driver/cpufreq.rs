@@ -0,0 +1,90 @@
+//! x86_64的调频驱动：通过IA32_PERF_CTL设置处理器的倍频（Intel EIST）
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/cpufreq/acpi-cpufreq.c
+
+use alloc::{sync::Arc, vec::Vec};
+use x86::{
+    cpuid::CpuId,
+    msr::{rdmsr, wrmsr},
+};
+
+use crate::{
+    driver::cpufreq::{cpufreq_manager, CpuFreqDriver, CpuFreqPState},
+    syscall::SystemError,
+};
+
+/// 保存了最大非睿频倍频和最小倍频的MSR
+const MSR_PLATFORM_INFO: u32 = 0xce;
+/// 性能控制寄存器，bit[15:8]为目标倍频
+const MSR_IA32_PERF_CTL: u32 = 0x199;
+/// 性能控制寄存器中目标倍频字段的掩码
+const PERF_CTL_RATIO_MASK: u64 = 0xff00;
+/// 总线频率(kHz)
+const BUS_CLOCK_KHZ: u64 = 100000;
+
+#[derive(Debug)]
+pub struct X86PerfCtlDriver {
+    pstates: Vec<CpuFreqPState>,
+}
+
+impl X86PerfCtlDriver {
+    /// 探测处理器支持的倍频范围
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：不是Intel处理器，或者不支持EIST（例如大部分虚拟机）
+    fn probe() -> Result<Self, SystemError> {
+        let cpuid = CpuId::new();
+        let is_intel = cpuid
+            .get_vendor_info()
+            .map_or(false, |v| v.as_str() == "GenuineIntel");
+        let has_eist = cpuid.get_feature_info().map_or(false, |f| f.has_eist());
+        if !is_intel || !has_eist {
+            return Err(SystemError::ENODEV);
+        }
+
+        let platform_info = unsafe { rdmsr(MSR_PLATFORM_INFO) };
+        let max_ratio = (platform_info >> 8) & 0xff;
+        let min_ratio = (platform_info >> 40) & 0xff;
+        if max_ratio == 0 || min_ratio == 0 || min_ratio > max_ratio {
+            return Err(SystemError::ENODEV);
+        }
+
+        let pstates = (min_ratio..=max_ratio)
+            .rev()
+            .map(|ratio| CpuFreqPState {
+                freq_khz: ratio * BUS_CLOCK_KHZ,
+                control: ratio << 8,
+            })
+            .collect();
+        return Ok(Self { pstates });
+    }
+}
+
+impl CpuFreqDriver for X86PerfCtlDriver {
+    fn name(&self) -> &str {
+        "x86_perf_ctl"
+    }
+
+    fn pstates(&self) -> &[CpuFreqPState] {
+        &self.pstates
+    }
+
+    fn target(&self, index: usize) -> Result<(), SystemError> {
+        let pstate = self.pstates.get(index).ok_or(SystemError::EINVAL)?;
+        unsafe {
+            let val = rdmsr(MSR_IA32_PERF_CTL);
+            wrmsr(
+                MSR_IA32_PERF_CTL,
+                (val & !PERF_CTL_RATIO_MASK) | pstate.control,
+            );
+        }
+        return Ok(());
+    }
+}
+
+/// 探测并注册x86_64的调频驱动
+pub fn x86_cpufreq_init() -> Result<(), SystemError> {
+    let driver = X86PerfCtlDriver::probe()?;
+    cpufreq_manager().register_driver(Arc::new(driver))
+}
@@ -0,0 +1,145 @@
+//! x86_64的空闲驱动：支持MWAIT时使用MWAIT进入各级C-state，否则只使用HLT
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/idle/intel_idle.c
+
+use core::arch::asm;
+
+use alloc::{sync::Arc, vec::Vec};
+use x86::cpuid::CpuId;
+
+use crate::{
+    arch::cpu::arch_cpu_idle,
+    driver::cpuidle::{cpuidle_manager, CpuIdleDriver, CpuIdleState, CPUIDLE_STATE_MAX},
+    mm::percpu::PerCpu,
+    smp::core::smp_get_processor_id,
+    syscall::SystemError,
+};
+
+/// MWAIT的ECX参数：即使中断被屏蔽，也能被中断唤醒
+const MWAIT_ECX_INTERRUPT_BREAK: u32 = 1 << 0;
+
+/// MONITOR监视的地址，每个cpu独占一个cache line
+#[repr(C, align(64))]
+struct MonitorLine([u8; 64]);
+
+const MONITOR_LINE_INIT: MonitorLine = MonitorLine([0; 64]);
+static MONITOR_LINES: [MonitorLine; PerCpu::MAX_CPU_NUM] = [MONITOR_LINE_INIT; PerCpu::MAX_CPU_NUM];
+
+/// 各级C-state的名称、退出延迟和最小驻留时间(us)
+const MWAIT_CSTATE_TABLE: [(&str, u64, u64); 7] = [
+    ("C1", 2, 2),
+    ("C2", 20, 80),
+    ("C3", 100, 400),
+    ("C4", 150, 600),
+    ("C5", 200, 800),
+    ("C6", 250, 1000),
+    ("C7", 300, 1200),
+];
+
+/// 进入空闲状态的方式
+#[derive(Debug, Clone, Copy)]
+enum IdleMethod {
+    Hlt,
+    /// MWAIT，参数为EAX中的hint
+    Mwait(u32),
+}
+
+#[derive(Debug)]
+pub struct X86CpuIdleDriver {
+    states: Vec<CpuIdleState>,
+    methods: Vec<IdleMethod>,
+}
+
+impl X86CpuIdleDriver {
+    /// 根据CPUID探测支持的C-state
+    fn probe() -> Self {
+        let mut states = Vec::new();
+        let mut methods = Vec::new();
+
+        let cpuid = CpuId::new();
+        let has_mwait = cpuid
+            .get_feature_info()
+            .map_or(false, |f| f.has_monitor_mwait());
+        let mwait_info = cpuid
+            .get_monitor_mwait_info()
+            .filter(|info| has_mwait && info.interrupts_as_break_event());
+
+        if let Some(info) = mwait_info {
+            let substates = [
+                info.supported_c1_states(),
+                info.supported_c2_states(),
+                info.supported_c3_states(),
+                info.supported_c4_states(),
+                info.supported_c5_states(),
+                info.supported_c6_states(),
+                info.supported_c7_states(),
+            ];
+            for (i, cnt) in substates.iter().enumerate() {
+                if *cnt == 0 || states.len() >= CPUIDLE_STATE_MAX {
+                    continue;
+                }
+                let (name, exit_latency, target_residency) = MWAIT_CSTATE_TABLE[i];
+                states.push(CpuIdleState {
+                    name,
+                    exit_latency,
+                    target_residency,
+                });
+                // Cn对应的hint为 (n-1) << 4
+                methods.push(IdleMethod::Mwait((i as u32) << 4));
+            }
+        }
+
+        if states.is_empty() {
+            states.push(CpuIdleState {
+                name: "HLT",
+                exit_latency: 2,
+                target_residency: 2,
+            });
+            methods.push(IdleMethod::Hlt);
+        }
+
+        return Self { states, methods };
+    }
+
+    /// 通过MWAIT进入空闲状态
+    fn mwait_idle(hint: u32) {
+        let cpu = smp_get_processor_id() as usize % PerCpu::MAX_CPU_NUM;
+        let addr = MONITOR_LINES[cpu].0.as_ptr();
+        unsafe {
+            asm!("monitor", in("rax") addr, in("ecx") 0, in("edx") 0, options(nostack));
+            asm!(
+                "mwait",
+                "sti",
+                in("eax") hint,
+                in("ecx") MWAIT_ECX_INTERRUPT_BREAK,
+                options(nostack)
+            );
+        }
+    }
+}
+
+impl CpuIdleDriver for X86CpuIdleDriver {
+    fn name(&self) -> &str {
+        match self.methods.first() {
+            Some(IdleMethod::Mwait(_)) => "x86_mwait_idle",
+            _ => "x86_hlt_idle",
+        }
+    }
+
+    fn states(&self) -> &[CpuIdleState] {
+        &self.states
+    }
+
+    fn enter(&self, index: usize) {
+        match self.methods.get(index) {
+            Some(IdleMethod::Mwait(hint)) => Self::mwait_idle(*hint),
+            _ => arch_cpu_idle(),
+        }
+    }
+}
+
+/// 探测并注册x86_64的空闲驱动
+pub fn x86_cpuidle_init() -> Result<(), SystemError> {
+    let driver = X86CpuIdleDriver::probe();
+    cpuidle_manager().register_driver(Arc::new(driver))
+}
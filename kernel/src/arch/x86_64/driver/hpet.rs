@@ -7,10 +7,17 @@ use core::{
 };
 
 use acpi::HpetInfo;
+use alloc::{sync::Arc, vec};
 
 use crate::{
     driver::{
         acpi::acpi_manager,
+        base::platform::{
+            platform_device::{
+                platform_device_manager, GenericPlatformDevice, PlatformDevice, PLATFORM_DEVID_NONE,
+            },
+            resource::{Resource, ResourceFlags},
+        },
         timers::hpet::{HpetRegisters, HpetTimerRegisters},
     },
     exception::softirq::{softirq_vectors, SoftirqNumber},
@@ -40,6 +47,8 @@ pub fn hpet_instance() -> &'static Hpet {
 
 pub struct Hpet {
     info: HpetInfo,
+    /// HPET在platform总线上对应的设备
+    _pdev: Arc<GenericPlatformDevice>,
     _mmio_guard: MMIOSpaceGuard,
     inner: RwLock<InnerHpet>,
     enabled: AtomicBool,
@@ -54,8 +63,11 @@ impl Hpet {
     /// HPET0 中断间隔为500us
     pub const HPET0_INTERVAL_USEC: u64 = 500;
 
-    fn new(mut hpet_info: HpetInfo) -> Result<Self, SystemError> {
-        let paddr = PhysAddr::new(hpet_info.base_address);
+    fn new(mut hpet_info: HpetInfo, pdev: Arc<GenericPlatformDevice>) -> Result<Self, SystemError> {
+        let res = (pdev.clone() as Arc<dyn PlatformDevice>)
+            .get_resource(ResourceFlags::MEM, 0)
+            .ok_or(SystemError::ENXIO)?;
+        let paddr = PhysAddr::new(res.start());
         let map_size = size_of::<HpetRegisters>();
         let mmio = mmio_pool().create_mmio(map_size)?;
         unsafe { mmio.map_phys(paddr, map_size)? };
@@ -86,6 +98,7 @@ impl Hpet {
 
         let hpet = Hpet {
             info: hpet_info,
+            _pdev: pdev,
             _mmio_guard: mmio,
             inner: RwLock::new(InnerHpet {
                 registers_ptr: ptr,
@@ -104,6 +117,31 @@ impl Hpet {
     /// 使能HPET
     pub(super) fn hpet_enable(&self) -> Result<(), SystemError> {
         // ！！！这里是临时糊代码的，需要在apic重构的时候修改！！！
+        self.setup_timer0()?;
+
+        // todo!("register irq in C");
+        unsafe { c_hpet_register_irq() };
+        self.enabled.store(true, Ordering::SeqCst);
+
+        self.start();
+        kinfo!("HPET enabled");
+        return Ok(());
+    }
+
+    /// 从睡眠中恢复之后，重新设置HPET的寄存器
+    ///
+    /// 睡眠时HPET断电，寄存器回到了复位时的状态。中断的路由保存在IO APIC中，由IO APIC自己恢复
+    pub fn hpet_resume(&self) -> Result<(), SystemError> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        self.setup_timer0()?;
+        self.start();
+        return Ok(());
+    }
+
+    /// 清零主计数器，并把定时器0设置为每隔`HPET0_INTERVAL_USEC`产生一次中断
+    fn setup_timer0(&self) -> Result<(), SystemError> {
         let (inner_guard, regs) = unsafe { self.hpet_regs_mut() };
         let freq = regs.frequency();
         kdebug!("HPET frequency: {} Hz", freq);
@@ -132,11 +170,11 @@ impl Hpet {
         }
         drop(timer_reg);
         drop(inner_guard);
+        return Ok(());
+    }
 
-        // todo!("register irq in C");
-        unsafe { c_hpet_register_irq() };
-        self.enabled.store(true, Ordering::SeqCst);
-
+    /// 让主计数器开始计数
+    fn start(&self) {
         let (inner_guard, regs) = unsafe { self.hpet_regs_mut() };
 
         // 置位旧设备中断路由兼容标志位、定时器组使能标志位
@@ -144,9 +182,6 @@ impl Hpet {
 
         drop(regs);
         drop(inner_guard);
-
-        kinfo!("HPET enabled");
-        return Ok(());
     }
 
     fn inner(&self) -> RwLockReadGuard<InnerHpet> {
@@ -242,7 +277,14 @@ pub fn hpet_init() -> Result<(), SystemError> {
         SystemError::ENODEV
     })?;
 
-    let hpet_instance = Hpet::new(hpet_info)?;
+    // HPET的寄存器区域大小为1KB
+    let pdev = platform_device_manager().register_simple(
+        "hpet",
+        PLATFORM_DEVID_NONE,
+        vec![Resource::mem(hpet_info.base_address, 1024)],
+    )?;
+
+    let hpet_instance = Hpet::new(hpet_info, pdev)?;
     unsafe {
         HPET_INSTANCE = Some(hpet_instance);
     }
@@ -0,0 +1,50 @@
+//! x86上地址固定的传统设备
+//!
+//! 这些设备无法被枚举，因此在这里按照PC的约定把它们注册到platform总线上，
+//! 由platform驱动通过资源获取它们的I/O端口以及中断号。
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::{
+    driver::base::platform::{
+        platform_device::{platform_device_manager, GenericPlatformDevice, PLATFORM_DEVID_NONE},
+        resource::Resource,
+    },
+    kerror,
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+};
+
+/// 已经注册的传统设备
+static LEGACY_DEVICES: SpinLock<Vec<Arc<GenericPlatformDevice>>> = SpinLock::new(Vec::new());
+
+/// 把x86上的传统设备(CMOS RTC、i8042键盘控制器)注册到platform总线上
+///
+/// 串口以及HPET分别在它们自己的初始化函数中注册
+pub fn x86_legacy_devices_init() -> Result<(), SystemError> {
+    let devices = [
+        ("rtc_cmos", vec![Resource::io(0x70, 2), Resource::irq(8)]),
+        (
+            "i8042",
+            vec![
+                Resource::io(0x60, 1),
+                Resource::io(0x64, 1),
+                Resource::irq(1),
+                Resource::irq(12),
+            ],
+        ),
+    ];
+
+    let mut guard = LEGACY_DEVICES.lock();
+    for (name, resources) in devices {
+        let pdev = platform_device_manager()
+            .register_simple(name, PLATFORM_DEVID_NONE, resources)
+            .map_err(|e| {
+                kerror!("Failed to register legacy device '{}': {:?}", name, e);
+                e
+            })?;
+        guard.push(pdev);
+    }
+
+    return Ok(());
+}
@@ -1,3 +1,7 @@
+pub mod apic_timer;
 mod c_adapter;
+pub mod cpufreq;
+pub mod cpuidle;
 pub mod hpet;
+pub mod legacy;
 pub mod tsc;
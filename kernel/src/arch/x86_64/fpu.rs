@@ -1,5 +1,9 @@
 use core::arch::x86_64::{_fxrstor64, _fxsave64};
 
+use crate::exception::{InterruptArch, IrqFlagsGuard};
+
+use super::CurrentIrqArch;
+
 /// https://www.felixcloutier.com/x86/fxsave#tbl-3-47
 #[repr(C, align(16))]
 #[derive(Debug, Copy, Clone)]
@@ -67,3 +71,31 @@ impl FpState {
         self.restore();
     }
 }
+
+/// 内核使用SSE寄存器期间持有的守卫，被drop时恢复进入之前的浮点寄存器
+///
+/// 内核编译时禁用了SSE，进程切换时保存、恢复的是用户态的浮点寄存器。内核在使用SSE寄存器
+/// (例如AES-NI指令)之前，需要先保存当前的浮点寄存器，并且在使用期间关闭中断，防止被抢占。
+pub struct KernelFpuGuard {
+    saved: FpState,
+    _irq_guard: IrqFlagsGuard,
+}
+
+/// 开始在内核中使用SSE寄存器
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/fpu/core.c#kernel_fpu_begin_mask
+pub fn kernel_fpu_begin() -> KernelFpuGuard {
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let mut saved = FpState::new();
+    saved.save();
+    return KernelFpuGuard {
+        saved,
+        _irq_guard: irq_guard,
+    };
+}
+
+impl Drop for KernelFpuGuard {
+    fn drop(&mut self) {
+        self.saved.restore();
+    }
+}
@@ -88,16 +88,28 @@ pub fn send_ipi(kind: IpiKind, target: IpiTarget) {
     // kdebug!("send_ipi: {:?} {:?}", kind, target);
 
     let ipi_vec = ArchIpiKind::from(kind) as u8;
+    send_icr(ipi_vec, x86::apic::DeliveryMode::Fixed, target);
+}
+
+/// 向目标cpu发送NMI
+///
+/// NMI不能被屏蔽，即使目标cpu关闭了中断也能收到，用于在重启前等场景下强制停止其他cpu
+pub fn send_nmi_ipi(target: IpiTarget) {
+    send_icr(0, x86::apic::DeliveryMode::NMI, target);
+}
+
+/// 写入ICR寄存器，发送处理器间中断
+fn send_icr(vector: u8, delivery_mode: x86::apic::DeliveryMode, target: IpiTarget) {
     let target = ArchIpiTarget::from(target);
     let shorthand: x86::apic::DestinationShorthand = target.into();
     let destination: x86::apic::ApicId = target.into();
     if unsafe { apic_x2apic_enabled() } {
         // kdebug!("send_ipi: x2apic");
         let icr = x86::apic::Icr::for_x2apic(
-            ipi_vec,
+            vector,
             destination,
             shorthand,
-            x86::apic::DeliveryMode::Fixed,
+            delivery_mode,
             x86::apic::DestinationMode::Physical,
             x86::apic::DeliveryStatus::Idle,
             x86::apic::Level::Assert,
@@ -110,10 +122,10 @@ pub fn send_ipi(kind: IpiKind, target: IpiTarget) {
     } else {
         // kdebug!("send_ipi: xapic");
         let icr = x86::apic::Icr::for_xapic(
-            ipi_vec,
+            vector,
             destination,
             shorthand,
-            x86::apic::DeliveryMode::Fixed,
+            delivery_mode,
             x86::apic::DestinationMode::Physical,
             x86::apic::DeliveryStatus::Idle,
             x86::apic::Level::Assert,
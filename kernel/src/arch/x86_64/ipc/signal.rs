@@ -1,5 +1,7 @@
 use core::{ffi::c_void, mem::size_of};
 
+use memoffset::offset_of;
+
 use crate::{
     arch::{
         fpu::FpState,
@@ -15,8 +17,8 @@ use crate::{
     },
     kerror,
     mm::MemoryManagementArch,
-    process::ProcessManager,
-    syscall::{user_access::UserBufferWriter, Syscall, SystemError},
+    process::{freezer::try_to_freeze, ProcessManager},
+    syscall::{user_access::UserPtr, Syscall, SystemError},
 };
 
 /// 信号处理的栈的栈指针的最小对齐数量
@@ -367,6 +369,10 @@ pub struct SigStack {
 
 #[no_mangle]
 unsafe extern "C" fn do_signal(frame: &mut TrapFrame) {
+    // 系统挂起之前，用户进程在返回用户态之前被冻结
+    if frame.from_user() {
+        try_to_freeze();
+    }
     X86_64SignalArch::do_signal(frame);
 }
 
@@ -444,19 +450,22 @@ impl SignalArch for X86_64SignalArch {
     }
 
     fn sys_rt_sigreturn(trap_frame: &mut TrapFrame) -> u64 {
-        let frame = (trap_frame.rsp as usize) as *mut SigFrame;
-
-        // 如果当前的rsp不来自用户态，则认为产生了错误（或被SROP攻击）
-        if UserBufferWriter::new(frame, size_of::<SigFrame>(), true).is_err() {
-            kerror!("rsp doesn't from user level");
-            let _r = Syscall::kill(ProcessManager::current_pcb().pid(), Signal::SIGSEGV as i32)
-                .map_err(|e| e.to_posix_errno());
-            return trap_frame.rax;
-        }
-        let mut sigmask: SigSet = unsafe { (*frame).context.oldmask };
+        // 信号栈帧位于用户栈上，先拷贝到内核中。
+        // rsp不指向用户空间(例如被SROP攻击)或者栈帧所在的内存没有映射时，产生SIGSEGV
+        let frame_ptr = UserPtr::<SigFrame>::new(trap_frame.rsp as usize);
+        let mut frame = match frame_ptr.read() {
+            Ok(frame) => frame,
+            Err(_) => {
+                kerror!("rt_sigreturn: bad signal frame at {:#x}", trap_frame.rsp);
+                let _r = Syscall::kill(ProcessManager::current_pcb().pid(), Signal::SIGSEGV as i32)
+                    .map_err(|e| e.to_posix_errno());
+                return trap_frame.rax;
+            }
+        };
+        let mut sigmask: SigSet = frame.context.oldmask;
         set_current_sig_blocked(&mut sigmask);
         // 从用户栈恢复sigcontext
-        if !unsafe { &mut (*frame).context }.restore_sigcontext(trap_frame) {
+        if !frame.context.restore_sigcontext(trap_frame) {
             kerror!("unable to restore sigcontext");
             let _r = Syscall::kill(ProcessManager::current_pcb().pid(), Signal::SIGSEGV as i32)
                 .map_err(|e| e.to_posix_errno());
@@ -561,57 +570,48 @@ fn setup_frame(
             return Err(SystemError::EINVAL);
         }
     }
-    let frame: *mut SigFrame = get_stack(&trap_frame, size_of::<SigFrame>());
-    // kdebug!("frame=0x{:016x}", frame as usize);
-    // 要求这个frame的地址位于用户空间，因此进行校验
-    let r = UserBufferWriter::new(frame, size_of::<SigFrame>(), true);
-    if r.is_err() {
-        // 如果地址区域位于内核空间，则直接报错
-        // todo: 生成一个sigsegv
+    // 先在内核中构造信号栈帧，再整体拷贝到用户栈上
+    let mut frame = SigFrame {
+        // 在开头检验过sigaction.restorer是否为空了，实际上libc会保证 restorer始终不为空
+        ret_code_ptr,
+        handler: temp_handler,
+        info: *info,
+        context: SigContext {
+            sc_flags: 0,
+            // todo: 拷贝处理程序备用栈的地址、大小、ss_flags
+            sc_stack: SigStack {
+                sp: core::ptr::null_mut(),
+                flags: 0,
+                size: 0,
+                fpstate: FpState::default(),
+            },
+            frame: *trap_frame,
+            oldmask: *oldset,
+            cr2: 0,
+            reserved_for_x87_state: None,
+            reserved: [0; 8],
+        },
+    };
+    frame.context.setup_sigcontext(oldset, trap_frame)?;
+
+    let frame_ptr = UserPtr::<SigFrame>::new(get_stack(trap_frame, size_of::<SigFrame>()) as usize);
+    // kdebug!("frame=0x{:016x}", frame_ptr.addr().data());
+    // 栈帧不在用户空间，或者用户栈没有映射时，拷贝返回EFAULT，这时产生SIGSEGV
+    if let Err(e) = frame_ptr.write(&frame) {
         let r = Syscall::kill(ProcessManager::current_pcb().pid(), Signal::SIGSEGV as i32);
         if r.is_err() {
             kerror!("In setup frame: generate SIGSEGV signal failed");
         }
-        kerror!("In setup frame: access check failed");
-        return Err(SystemError::EFAULT);
-    }
-
-    // 将siginfo拷贝到用户栈
-    info.copy_siginfo_to_user(unsafe { &mut ((*frame).info) as *mut SigInfo })
-        .map_err(|e| -> SystemError {
-            let r = Syscall::kill(ProcessManager::current_pcb().pid(), Signal::SIGSEGV as i32);
-            if r.is_err() {
-                kerror!("In copy_siginfo_to_user: generate SIGSEGV signal failed");
-            }
-            return e;
-        })?;
-
-    // todo: 拷贝处理程序备用栈的地址、大小、ss_flags
-
-    unsafe {
-        (*frame)
-            .context
-            .setup_sigcontext(oldset, &trap_frame)
-            .map_err(|e: SystemError| -> SystemError {
-                let r = Syscall::kill(ProcessManager::current_pcb().pid(), Signal::SIGSEGV as i32);
-                if r.is_err() {
-                    kerror!("In setup_sigcontext: generate SIGSEGV signal failed");
-                }
-                return e;
-            })?
-    };
-
-    unsafe {
-        // 在开头检验过sigaction.restorer是否为空了，实际上libc会保证 restorer始终不为空
-        (*frame).ret_code_ptr = ret_code_ptr;
+        kerror!("In setup frame: unable to write the signal frame to the user stack");
+        return Err(e);
     }
+    let frame_addr = frame_ptr.addr().data();
 
-    unsafe { (*frame).handler = temp_handler };
     // 传入信号处理函数的第一个参数
     trap_frame.rdi = sig as u64;
-    trap_frame.rsi = unsafe { &(*frame).info as *const SigInfo as u64 };
-    trap_frame.rsp = frame as u64;
-    trap_frame.rip = unsafe { (*frame).handler as u64 };
+    trap_frame.rsi = (frame_addr + offset_of!(SigFrame, info)) as u64;
+    trap_frame.rsp = frame_addr as u64;
+    trap_frame.rip = temp_handler as u64;
     // 设置cs和ds寄存器
     trap_frame.cs = (USER_CS.bits() | 0x3) as u64;
     trap_frame.ds = (USER_DS.bits() | 0x3) as u64;
@@ -628,7 +628,8 @@ fn get_stack(frame: &TrapFrame, size: usize) -> *mut SigFrame {
     // pcb中也没有这个备用堆栈
 
     // 默认使用 用户栈的栈顶指针-128字节的红区-sigframe的大小 并且16字节对齐
-    let mut rsp: usize = (frame.rsp as usize) - 128 - size;
+    // 用户可以把rsp设置为任意值，这里不能溢出，得到的非法地址在拷贝时被拒绝
+    let mut rsp: usize = (frame.rsp as usize).wrapping_sub(128 + size);
     // 按照要求进行对齐，别问为什么减8，不减8就是错的，可以看
     // https://sourcegraph.com/github.com/torvalds/linux@dd72f9c7e512da377074d47d990564959b772643/-/blob/arch/x86/kernel/signal.c?L124
     // 我猜测是跟x86汇编的某些弹栈行为有关系，它可能会出于某种原因递增 rsp
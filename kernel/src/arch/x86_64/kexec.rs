@@ -0,0 +1,286 @@
+//! kexec在x86_64上的实现
+//!
+//! 新内核与当前内核一样，由multiboot2协议启动：入口是32位保护模式下的代码，
+//! 要求关闭分页，eax中是multiboot2的魔数，ebx中是启动信息的物理地址。
+//!
+//! 跳转的过程：
+//! 1. 用NMI让其他cpu停下来，关闭中断
+//! 2. 构造一个临时页表：低4GB恒等映射，高半部分与当前内核相同
+//! 3. 把跳板代码复制到控制页中，切换到临时页表后跳转到跳板
+//! 4. 跳板切换到兼容模式，关闭分页与长模式，回到32位保护模式
+//! 5. 按照页列表把新内核从暂存页复制到目标地址，最后跳转到新内核的入口
+//!
+//! 跳板运行时，当前内核的代码与数据可能已经被新内核覆盖，因此跳板只能使用控制页中的数据。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/machine_kexec_64.c
+
+use core::{
+    arch::{asm, global_asm},
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use x86::controlregs::cr3;
+
+use crate::{
+    arch::{interrupt::TrapFrame, CurrentIrqArch, MMArch},
+    exception::{ipi::IpiTarget, InterruptArch},
+    kwarn,
+    mm::{MemoryManagementArch, PhysAddr},
+    smp::core::smp_cpu_count,
+};
+
+use super::interrupt::ipi::send_nmi_ipi;
+
+extern "C" {
+    fn apic_write_lvt_pmc(value: u32);
+    fn kexec_trampoline_start();
+    fn kexec_trampoline_end();
+}
+
+/// 新内核的ELF文件的机器类型
+pub const KEXEC_ELF_MACHINE: u16 = elf::abi::EM_X86_64;
+
+/// 需要的控制页的数量：跳板、PML4、PDPT，以及映射低4GB的4个PD
+pub const KEXEC_CONTROL_PAGES: usize = 7;
+
+/// 跳板的参数在跳板页中的偏移量
+const PARAM_OFFSET: usize = 0x800;
+/// GDTR在跳板页中的偏移量
+const GDTR_OFFSET: usize = 0x900;
+/// GDT在跳板页中的偏移量
+const GDT_OFFSET: usize = 0x910;
+
+/// 跳板使用的GDT：空描述符、32位代码段、32位数据段
+const KEXEC_GDT: [u64; 3] = [0, 0x00cf_9a00_0000_ffff, 0x00cf_9200_0000_ffff];
+
+/// multiboot2引导程序传给内核的魔数
+const MULTIBOOT2_BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+/// 页表项：存在、可写
+const PTE_PRESENT_RW: u64 = 0x3;
+/// 页表项：映射2MB的大页
+const PTE_HUGE: u64 = 1 << 7;
+
+/// 等待其他cpu停下来的轮询次数
+const KEXEC_STOP_WAIT_LOOPS: usize = 10000000;
+
+/// 跳板的参数，位于跳板页的`PARAM_OFFSET`处
+#[repr(C)]
+struct KexecTrampolineParams {
+    entry: u32,
+    mbi: u32,
+    nr_pages: u32,
+    page_list: u32,
+}
+
+global_asm!(
+    ".pushsection .text",
+    ".global kexec_trampoline_start",
+    ".global kexec_trampoline_end",
+    // 跳板被复制到恒等映射的控制页中运行，必须与位置无关。rdi是跳板页的物理地址
+    "kexec_trampoline_start:",
+    "cli",
+    "mov rbp, rdi",
+    "lea rsp, [rdi + {page_size}]",
+    "lgdt [rdi + {gdtr}]",
+    // 关闭分页之前必须先关闭PCID
+    "mov rax, cr4",
+    "btr rax, 17",
+    "mov cr4, rax",
+    // 通过远返回切换到32位的兼容模式
+    "lea rax, [rip + .Lkexec_compat]",
+    "push 0x08",
+    "push rax",
+    "retfq",
+    ".code32",
+    ".Lkexec_compat:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    // 关闭分页，然后关闭长模式，回到32位保护模式
+    "mov eax, cr0",
+    "btr eax, 31",
+    "mov cr0, eax",
+    "mov ecx, 0xc0000080",
+    "rdmsr",
+    "btr eax, 8",
+    "wrmsr",
+    "xor eax, eax",
+    "mov cr4, eax",
+    // 按照页列表，把每个暂存页复制到它的目标地址
+    "cld",
+    "mov edx, [ebp + {param} + 8]",
+    "mov ebx, [ebp + {param} + 12]",
+    ".Lkexec_copy:",
+    "test edx, edx",
+    "jz .Lkexec_jump",
+    "mov edi, [ebx]",
+    "mov esi, [ebx + 4]",
+    "mov ecx, {page_size}",
+    "rep movsb",
+    "add ebx, 8",
+    "dec edx",
+    "jmp .Lkexec_copy",
+    ".Lkexec_jump:",
+    "mov ecx, [ebp + {param}]",
+    "mov ebx, [ebp + {param} + 4]",
+    "mov eax, {magic}",
+    "jmp ecx",
+    "kexec_trampoline_end:",
+    ".code64",
+    ".popsection",
+    page_size = const MMArch::PAGE_SIZE,
+    gdtr = const GDTR_OFFSET,
+    param = const PARAM_OFFSET,
+    magic = const MULTIBOOT2_BOOTLOADER_MAGIC,
+);
+
+/// 是否正在让其他cpu停下来
+static KEXEC_STOPPING: AtomicBool = AtomicBool::new(false);
+/// 已经停下来的cpu的数量
+static KEXEC_STOPPED_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// 把物理地址转换为可以访问的指针
+unsafe fn phys_ptr<T>(paddr: PhysAddr) -> *mut T {
+    return MMArch::phys_2_virt(paddr).unwrap().data() as *mut T;
+}
+
+/// 让其他cpu停在NMI的处理函数中
+fn kexec_stop_other_cpus() {
+    let others = smp_cpu_count() - 1;
+    if others == 0 {
+        return;
+    }
+    KEXEC_STOPPING.store(true, Ordering::SeqCst);
+    send_nmi_ipi(IpiTarget::Other);
+    for _ in 0..KEXEC_STOP_WAIT_LOOPS {
+        if KEXEC_STOPPED_CPUS.load(Ordering::SeqCst) >= others {
+            return;
+        }
+        spin_loop();
+    }
+    kwarn!(
+        "kexec: only {} of {} cpus stopped",
+        KEXEC_STOPPED_CPUS.load(Ordering::SeqCst),
+        others
+    );
+}
+
+/// 构造跳板使用的页表：低4GB用2MB的大页恒等映射，高半部分复制当前的PML4
+///
+/// ## 返回值
+///
+/// PML4的物理地址
+unsafe fn kexec_build_page_table(control_pages: &[PhysAddr]) -> PhysAddr {
+    let pml4 = control_pages[1];
+    let pdpt = control_pages[2];
+    let pds = &control_pages[3..KEXEC_CONTROL_PAGES];
+
+    let pdpt_ptr = phys_ptr::<u64>(pdpt);
+    core::ptr::write_bytes(pdpt_ptr, 0, 512);
+    for (i, pd) in pds.iter().enumerate() {
+        let pd_ptr = phys_ptr::<u64>(*pd);
+        for j in 0..512 {
+            let paddr = ((i * 512 + j) as u64) << 21;
+            pd_ptr.add(j).write(paddr | PTE_PRESENT_RW | PTE_HUGE);
+        }
+        pdpt_ptr.add(i).write(pd.data() as u64 | PTE_PRESENT_RW);
+    }
+
+    let pml4_ptr = phys_ptr::<u64>(pml4);
+    let current = phys_ptr::<u64>(PhysAddr::new(cr3() as usize & !(MMArch::PAGE_SIZE - 1)));
+    core::ptr::write_bytes(pml4_ptr, 0, 256);
+    core::ptr::copy_nonoverlapping(current.add(256), pml4_ptr.add(256), 256);
+    pml4_ptr.write(pdpt.data() as u64 | PTE_PRESENT_RW);
+    return pml4;
+}
+
+/// 把跳板复制到跳板页中，并写入参数与GDT
+unsafe fn kexec_setup_trampoline(page: PhysAddr, params: KexecTrampolineParams) {
+    let start = kexec_trampoline_start as usize;
+    let len = kexec_trampoline_end as usize - start;
+    assert!(len <= PARAM_OFFSET);
+
+    let base = phys_ptr::<u8>(page);
+    core::ptr::copy_nonoverlapping(start as *const u8, base, len);
+    (base.add(PARAM_OFFSET) as *mut KexecTrampolineParams).write(params);
+
+    core::ptr::copy_nonoverlapping(
+        KEXEC_GDT.as_ptr(),
+        base.add(GDT_OFFSET) as *mut u64,
+        KEXEC_GDT.len(),
+    );
+    // GDTR：16位的界限，之后是64位的基地址
+    (base.add(GDTR_OFFSET) as *mut u16).write((KEXEC_GDT.len() * 8 - 1) as u16);
+    (base.add(GDTR_OFFSET + 2) as *mut u64).write_unaligned((page.data() + GDT_OFFSET) as u64);
+}
+
+/// 跳转到新内核，不会返回
+///
+/// ## 参数
+///
+/// - `control_pages`：[`KEXEC_CONTROL_PAGES`]个控制页，物理地址必须在4GB以下，且不与新内核的目标地址重叠
+/// - `entry`：新内核的入口的物理地址
+/// - `mbi`：传给新内核的multiboot2启动信息的物理地址
+/// - `page_list`：页列表的物理地址，每一项是`(目标地址, 暂存页地址)`两个u32
+/// - `nr_pages`：页列表的项数
+///
+/// ## 安全性
+///
+/// 调用之前必须已经停止所有设备的DMA
+pub unsafe fn machine_kexec(
+    control_pages: &[PhysAddr],
+    entry: u32,
+    mbi: u32,
+    page_list: u32,
+    nr_pages: u32,
+) -> ! {
+    assert!(control_pages.len() == KEXEC_CONTROL_PAGES);
+    CurrentIrqArch::interrupt_disable();
+    kexec_stop_other_cpus();
+    // 性能计数器溢出的NMI不能打断跳板
+    apic_write_lvt_pmc(1 << 16);
+
+    let pml4 = kexec_build_page_table(control_pages);
+    let trampoline = control_pages[0];
+    kexec_setup_trampoline(
+        trampoline,
+        KexecTrampolineParams {
+            entry,
+            mbi,
+            nr_pages,
+            page_list,
+        },
+    );
+
+    // 临时页表保留了内核的高半部分，切换之后仍然可以继续执行，然后跳转到恒等映射的跳板
+    asm!(
+        "mov cr3, {pml4}",
+        "jmp rdi",
+        pml4 = in(reg) pml4.data(),
+        in("rdi") trampoline.data(),
+        options(noreturn)
+    );
+}
+
+/// NMI的处理函数首先调用它，判断这个NMI是否是kexec让当前cpu停下来
+///
+/// ## 返回值
+///
+/// - `false`：不是kexec发送的NMI
+/// - 否则当前cpu停止运行，不会返回
+#[no_mangle]
+pub unsafe extern "C" fn rs_kexec_nmi_handler(_regs: *mut TrapFrame) -> bool {
+    if !KEXEC_STOPPING.load(Ordering::SeqCst) {
+        return false;
+    }
+    KEXEC_STOPPED_CPUS.fetch_add(1, Ordering::SeqCst);
+    // NMI处理函数返回之前不会再收到NMI，hlt之后不会被唤醒
+    loop {
+        x86::halt();
+    }
+}
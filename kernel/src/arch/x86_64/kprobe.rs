@@ -0,0 +1,88 @@
+//! kprobe在x86_64上的实现
+//!
+//! 探测点只能放在函数入口。内核编译时保留了帧指针，函数的第一条指令总是单字节的`push rbp`，
+//! 把它替换为`int3`之后，断点异常的处理函数记录下这次调用，然后跳转到一段跳板代码：
+//! 由跳板代码执行被替换掉的`push rbp`，再跳回到函数的第二条指令继续执行。
+//!
+//! 跳回的地址通过r11传递。按照调用约定，r11在函数入口处不保存任何有效的值，可以随意修改，
+//! 因此多个cpu可以同时经过同一个探测点，不需要为每次命中分配单独的跳板。
+
+use core::arch::global_asm;
+
+use x86::controlregs::{cr0, cr0_write, Cr0};
+
+use crate::{
+    arch::{interrupt::TrapFrame, CurrentIrqArch},
+    debug::kprobe::kprobe_handler,
+    exception::InterruptArch,
+};
+
+/// 断点指令int3
+pub const BREAKPOINT_INSN: u8 = 0xcc;
+/// 函数入口处的第一条指令：push rbp
+pub const FUNC_ENTRY_INSN: u8 = 0x55;
+
+extern "C" {
+    fn kprobe_push_rbp_trampoline();
+}
+
+global_asm!(
+    ".pushsection .text",
+    ".global kprobe_push_rbp_trampoline",
+    "kprobe_push_rbp_trampoline:",
+    "push rbp",
+    "jmp r11",
+    ".popsection",
+);
+
+/// 修改内核代码段中的一个字节
+///
+/// 代码段是只读的，修改时需要暂时关闭CR0的写保护。单字节的写入是原子的，
+/// 其他cpu要么执行原来的指令，要么执行int3，不会看到被写了一半的指令
+///
+/// ## 安全性
+///
+/// `addr`必须指向一条指令的起始字节
+pub unsafe fn text_poke(addr: usize, byte: u8) {
+    let irq_guard = CurrentIrqArch::save_and_disable_irq();
+    let old = cr0();
+    cr0_write(old - Cr0::CR0_WRITE_PROTECT);
+    core::ptr::write_volatile(addr as *mut u8, byte);
+    cr0_write(old);
+    // 执行序列化指令，保证当前cpu之后取到的是修改之后的指令
+    core::arch::x86_64::__cpuid(0);
+    drop(irq_guard);
+}
+
+/// 从断点异常返回之后，执行被替换掉的`push rbp`，然后从函数的第二条指令继续执行
+///
+/// ## 参数
+///
+/// - `addr`：探测点所在的函数的入口地址
+pub fn kprobe_resume(frame: &mut TrapFrame, addr: usize) {
+    frame.r11 = (addr + 1) as u64;
+    frame.rip = kprobe_push_rbp_trampoline as usize as u64;
+}
+
+/// 函数的前6个整数参数
+pub fn kprobe_args(frame: &TrapFrame) -> [u64; 6] {
+    [
+        frame.rdi, frame.rsi, frame.rdx, frame.rcx, frame.r8, frame.r9,
+    ]
+}
+
+/// 断点异常的处理函数调用它判断这个断点是否是kprobe设置的
+///
+/// ## 返回值
+///
+/// - `true`：断点由kprobe处理，异常处理函数直接返回即可
+/// - `false`：不是kprobe设置的断点
+#[no_mangle]
+pub unsafe extern "C" fn rs_kprobe_handler(regs: *mut TrapFrame) -> bool {
+    let frame = regs.as_mut().unwrap();
+    // 只处理内核态的断点
+    if frame.cs & 0x3 != 0 {
+        return false;
+    }
+    return kprobe_handler(frame, frame.rip as usize - 1);
+}
@@ -0,0 +1,124 @@
+//! 内核异常表(exception table)
+//!
+//! 内核在访问用户空间内存时，可能会因为用户传入了未映射的地址而触发缺页异常。
+//! 所有可能访问用户内存的指令，都会在`__ex_table`段中登记一条记录（出错指令地址, 修复地址）。
+//! 当内核态发生缺页异常时，缺页处理函数会查找异常表，若找到对应的记录，
+//! 就把返回地址修改为修复地址，从而让访问函数返回错误，而不是让内核崩溃。
+
+use core::arch::asm;
+
+use crate::arch::interrupt::TrapFrame;
+
+use super::smap::UserAccessGuard;
+
+/// 异常表的表项
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionTableEntry {
+    /// 可能出错的指令的地址
+    pub insn: usize,
+    /// 出错后跳转到的修复代码的地址
+    pub fixup: usize,
+}
+
+extern "C" {
+    static __start___ex_table: ExceptionTableEntry;
+    static __stop___ex_table: ExceptionTableEntry;
+}
+
+/// 获取链接器生成的异常表
+fn exception_table() -> &'static [ExceptionTableEntry] {
+    unsafe {
+        let start = &__start___ex_table as *const ExceptionTableEntry;
+        let end = &__stop___ex_table as *const ExceptionTableEntry;
+        let len = (end as usize - start as usize) / core::mem::size_of::<ExceptionTableEntry>();
+        return core::slice::from_raw_parts(start, len);
+    }
+}
+
+/// 在异常表中查找指令地址对应的表项
+///
+/// 异常表的表项数量很少，因此直接线性查找即可
+pub fn search_exception_table(insn: usize) -> Option<&'static ExceptionTableEntry> {
+    return exception_table().iter().find(|entry| entry.insn == insn);
+}
+
+/// 尝试修复内核态的缺页异常
+///
+/// ## 返回值
+///
+/// - `true`：找到了修复地址，并已修改了中断栈帧的返回地址
+/// - `false`：出错指令不在异常表中，调用者需要按照原有流程处理该异常
+#[no_mangle]
+pub unsafe extern "C" fn rs_fixup_exception(regs: *mut TrapFrame) -> bool {
+    let regs = regs.as_mut().unwrap();
+    if let Some(entry) = search_exception_table(regs.rip as usize) {
+        regs.rip = entry.fixup as u64;
+        return true;
+    }
+    return false;
+}
+
+/// 在内核与用户空间之间拷贝数据，访问出错时不会导致内核崩溃
+///
+/// ## 参数
+///
+/// - `dst`：目标地址
+/// - `src`：源地址
+/// - `len`：要拷贝的字节数
+///
+/// ## 返回值
+///
+/// 返回未能拷贝的字节数。返回0表示全部拷贝成功
+///
+/// ## Safety
+///
+/// 调用者需要保证`dst`和`src`中，位于内核空间的那一段内存是合法的
+#[inline(never)]
+pub unsafe fn copy_user_generic(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    let mut remain = len;
+    let _guard = UserAccessGuard::new();
+    asm!(
+        "2:",
+        "rep movsb",
+        "3:",
+        ".pushsection __ex_table, \"a\"",
+        ".balign 8",
+        ".quad 2b, 3b",
+        ".popsection",
+        inout("rcx") remain,
+        inout("rdi") dst => _,
+        inout("rsi") src => _,
+        options(nostack)
+    );
+    return remain;
+}
+
+/// 把用户空间的一段内存清零，访问出错时不会导致内核崩溃
+///
+/// ## 返回值
+///
+/// 返回未能清零的字节数。返回0表示全部清零成功
+///
+/// ## Safety
+///
+/// 调用者需要保证`dst`指向用户空间
+#[inline(never)]
+pub unsafe fn clear_user_generic(dst: *mut u8, len: usize) -> usize {
+    let mut remain = len;
+    let _guard = UserAccessGuard::new();
+    asm!(
+        "2:",
+        "rep stosb",
+        "3:",
+        ".pushsection __ex_table, \"a\"",
+        ".balign 8",
+        ".quad 2b, 3b",
+        ".popsection",
+        inout("rcx") remain,
+        inout("rdi") dst => _,
+        in("al") 0u8,
+        options(nostack)
+    );
+    return remain;
+}
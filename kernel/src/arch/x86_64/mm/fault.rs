@@ -0,0 +1,78 @@
+//! x86_64的缺页异常入口
+//!
+//! 根据错误码判断异常的原因，然后交给通用的缺页异常处理函数。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/mm/fault.c
+
+use crate::{
+    arch::interrupt::TrapFrame,
+    mm::{
+        fault::{handle_mm_fault, FaultFlags},
+        VirtAddr,
+    },
+};
+
+use super::smap::is_smap_violation;
+
+bitflags! {
+    /// 缺页异常的错误码
+    pub struct X86PfErrorCode: u64 {
+        /// 为1时，页存在，异常是由权限不足导致的
+        const X86_PF_PROT = 1 << 0;
+        /// 写操作导致的异常
+        const X86_PF_WRITE = 1 << 1;
+        /// 用户态发生的异常
+        const X86_PF_USER = 1 << 2;
+        /// 页表项的保留位被置位
+        const X86_PF_RSVD = 1 << 3;
+        /// 取指令导致的异常
+        const X86_PF_INSTR = 1 << 4;
+    }
+}
+
+/// 尝试处理缺页异常
+///
+/// ## 参数
+///
+/// - `regs`：发生异常时的中断栈帧
+/// - `error_code`：cpu压入的错误码
+/// - `address`：导致异常的虚拟地址(cr2)
+///
+/// ## 返回值
+///
+/// - `true`：异常已经被处理，返回之后重新执行出错的指令即可
+/// - `false`：无法处理这个异常，调用者需要按照原有流程处理
+#[no_mangle]
+pub unsafe extern "C" fn rs_handle_page_fault(
+    regs: *const TrapFrame,
+    error_code: u64,
+    address: u64,
+) -> bool {
+    let regs = regs.as_ref().unwrap();
+    let error_code = X86PfErrorCode::from_bits_truncate(error_code);
+    let address = VirtAddr::new(address as usize);
+
+    if !error_code.contains(X86PfErrorCode::X86_PF_USER) && address.check_user() {
+        // 内核执行用户页中的指令(SMEP)，或者在访问窗口之外访问了用户页(SMAP)，都是内核的错误
+        if error_code.contains(X86PfErrorCode::X86_PF_INSTR) || is_smap_violation(regs.rflags) {
+            return false;
+        }
+    }
+
+    // 需要处理的是：写入只读的匿名页(零页以及fork之后共享的页)，以及访问不存在的页(用户栈的扩展)
+    if error_code.contains(X86PfErrorCode::X86_PF_RSVD)
+        || (error_code.contains(X86PfErrorCode::X86_PF_PROT)
+            && !error_code.contains(X86PfErrorCode::X86_PF_WRITE))
+    {
+        return false;
+    }
+
+    let mut flags = FaultFlags::empty();
+    if error_code.contains(X86PfErrorCode::X86_PF_WRITE) {
+        flags.insert(FaultFlags::FAULT_FLAG_WRITE);
+    }
+    if error_code.contains(X86PfErrorCode::X86_PF_USER) {
+        flags.insert(FaultFlags::FAULT_FLAG_USER);
+    }
+    return handle_mm_fault(address, flags).is_ok();
+}
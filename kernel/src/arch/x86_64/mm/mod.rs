@@ -1,36 +1,41 @@
 pub mod barrier;
+pub mod extable;
+pub mod fault;
+pub mod smap;
+
+pub use self::smap::UserAccessGuard;
 
 use alloc::vec::Vec;
 use hashbrown::HashSet;
-use x86::time::rdtsc;
-use x86_64::registers::model_specific::EferFlags;
+use x86::{cpuid::CpuId, time::rdtsc};
+use x86_64::registers::model_specific::{Efer, EferFlags};
 
 use crate::driver::tty::serial::serial8250::send_to_default_serial8250_port;
-use crate::include::bindings::bindings::{
-    multiboot2_get_memory, multiboot2_iter, multiboot_mmap_entry_t,
-};
-use crate::libs::align::page_align_up;
+use crate::init::boot_info::{boot_info, BootMemoryType};
+use crate::libs::align::{page_align_down, page_align_up};
 use crate::libs::lib_ui::screen_manager::scm_disable_put_to_window;
 use crate::libs::printk::PrintkWriter;
 use crate::libs::spinlock::SpinLock;
 
+use crate::driver::acpi::srat::{acpi_numa_init, EarlyPhysReader};
+use crate::init::boot_info::BOOT_RSDP_MAX_LEN;
 use crate::mm::allocator::page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage};
+use crate::mm::kasan::{kasan_alloc_pages, kasan_free_pages, kasan_init};
 use crate::mm::mmio_buddy::mmio_init;
+use crate::mm::numa::{numa_dump, numa_node_id, numa_split_range};
 use crate::{
     arch::MMArch,
-    mm::allocator::{buddy::BuddyAllocator, bump::BumpAllocator},
+    mm::allocator::{bump::BumpAllocator, zone::ZonedAllocator},
 };
 
 use crate::mm::kernel_mapper::KernelMapper;
-use crate::mm::page::{PageEntry, PageFlags};
+use crate::mm::page::{InactiveFlusher, PageEntry, PageFlags, PageFlushAll};
 use crate::mm::{MemoryManagementArch, PageTableKind, PhysAddr, PhysMemoryArea, VirtAddr};
 use crate::syscall::SystemError;
-use crate::{kdebug, kinfo};
+use crate::{kdebug, kinfo, kwarn};
 
 use core::arch::asm;
-use core::ffi::c_void;
 use core::fmt::{Debug, Write};
-use core::mem::{self};
 
 use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
 
@@ -53,7 +58,7 @@ static mut INITIAL_CR3_VALUE: PhysAddr = PhysAddr::new(0);
 /// 顶级页表的[256, 512)项是内核的页表
 static KERNEL_PML4E_NO: usize = (X86_64MMArch::PHYS_OFFSET & ((1 << 48) - 1)) >> 39;
 
-static INNER_ALLOCATOR: SpinLock<Option<BuddyAllocator<MMArch>>> = SpinLock::new(None);
+static INNER_ALLOCATOR: SpinLock<Option<ZonedAllocator<MMArch>>> = SpinLock::new(None);
 
 #[derive(Clone, Copy)]
 pub struct X86_64MMBootstrapInfo {
@@ -82,6 +87,9 @@ pub struct X86_64MMArch;
 /// XD标志位是否被保留
 static XD_RESERVED: AtomicBool = AtomicBool::new(false);
 
+/// 初始化是否已经完成，内核的直接映射区是否已经遵循W^X
+static KERNEL_RO_AFTER_INIT: AtomicBool = AtomicBool::new(false);
+
 impl MemoryManagementArch for X86_64MMArch {
     /// 4K页
     const PAGE_SHIFT: usize = 12;
@@ -124,6 +132,8 @@ impl MemoryManagementArch for X86_64MMArch {
     const USER_END_VADDR: VirtAddr = VirtAddr::new(0x0000_7eff_ffff_ffff);
     const USER_BRK_START: VirtAddr = VirtAddr::new(0x700000000000);
     const USER_STACK_START: VirtAddr = VirtAddr::new(0x6ffff0a00000);
+    const USER_STACK_RND_BITS: usize = 22;
+    const USER_MMAP_RND_BITS: usize = 28;
 
     /// @brief 获取物理内存区域
     unsafe fn init() -> &'static [crate::mm::PhysMemoryArea] {
@@ -148,9 +158,8 @@ impl MemoryManagementArch for X86_64MMArch {
             BOOTSTRAP_MM_INFO = Some(bootstrap_info);
         }
 
-        // 初始化物理内存区域(从multiboot2中获取)
-        let areas_count =
-            Self::init_memory_area_from_multiboot2().expect("init memory area failed");
+        // 初始化物理内存区域(从启动信息中获取)
+        let areas_count = Self::init_memory_area_from_boot_info().expect("init memory area failed");
         send_to_default_serial8250_port("x86 64 init end\n\0".as_bytes());
 
         return &PHYS_MEMORY_AREAS[0..areas_count];
@@ -238,44 +247,51 @@ impl MemoryManagementArch for X86_64MMArch {
 }
 
 impl X86_64MMArch {
-    unsafe fn init_memory_area_from_multiboot2() -> Result<usize, SystemError> {
-        // 这个数组用来存放内存区域的信息（从C获取）
-        let mut mb2_mem_info: [multiboot_mmap_entry_t; 512] = mem::zeroed();
-        send_to_default_serial8250_port("init_memory_area_from_multiboot2 begin\n\0".as_bytes());
-
-        let mut mb2_count: u32 = 0;
-        multiboot2_iter(
-            Some(multiboot2_get_memory),
-            &mut mb2_mem_info as *mut [multiboot_mmap_entry_t; 512] as usize as *mut c_void,
-            &mut mb2_count,
-        );
-        send_to_default_serial8250_port("init_memory_area_from_multiboot2 2\n\0".as_bytes());
-
-        let mb2_count = mb2_count as usize;
+    /// 从启动信息中获取可用的物理内存区域
+    unsafe fn init_memory_area_from_boot_info() -> Result<usize, SystemError> {
+        let boot_info = boot_info();
         let mut areas_count = 0usize;
         let mut total_mem_size = 0usize;
-        for i in 0..mb2_count {
-            // Only use the memory area if its type is 1 (RAM)
-            if mb2_mem_info[i].type_ == 1 {
-                // Skip the memory area if its len is 0
-                if mb2_mem_info[i].len == 0 {
-                    continue;
-                }
-                total_mem_size += mb2_mem_info[i].len as usize;
-                PHYS_MEMORY_AREAS[areas_count].base = PhysAddr::new(mb2_mem_info[i].addr as usize);
-                PHYS_MEMORY_AREAS[areas_count].size = mb2_mem_info[i].len as usize;
-                areas_count += 1;
+        for region in boot_info
+            .memory_regions()
+            .iter()
+            .filter(|r| r.mem_type == BootMemoryType::Usable)
+        {
+            if areas_count == PHYS_MEMORY_AREAS.len() {
+                break;
             }
+            total_mem_size += region.size;
+            PHYS_MEMORY_AREAS[areas_count].base = region.base;
+            PHYS_MEMORY_AREAS[areas_count].size = region.size;
+            areas_count += 1;
+        }
+        if boot_info.dropped_memory_regions != 0 {
+            kwarn!(
+                "Too many memory regions from bootloader, {} regions dropped",
+                boot_info.dropped_memory_regions
+            );
         }
-        send_to_default_serial8250_port("init_memory_area_from_multiboot2 end\n\0".as_bytes());
-        kinfo!("Total memory size: {} MB, total areas from multiboot2: {mb2_count}, valid areas: {areas_count}", total_mem_size / 1024 / 1024);
+        kinfo!(
+            "Total memory size: {} MB, boot protocol: {:?}, total areas: {}, valid areas: {areas_count}",
+            total_mem_size / 1024 / 1024,
+            boot_info.protocol,
+            boot_info.memory_regions().len()
+        );
 
         return Ok(areas_count);
     }
 
     fn init_xd_rsvd() {
+        // cpu支持NX时开启EFER.NXE，之后页表项的XD位才有效。ap在加载内核页表之前也会开启它(见head.S)
+        let has_nx = CpuId::new()
+            .get_extended_processor_and_feature_identifiers()
+            .map_or(false, |f| f.has_execute_disable());
+        if has_nx {
+            unsafe { Efer::update(|efer| efer.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+        }
+
         // 读取ia32-EFER寄存器的值
-        let efer: EferFlags = x86_64::registers::model_specific::Efer::read();
+        let efer: EferFlags = Efer::read();
         if !efer.contains(EferFlags::NO_EXECUTE_ENABLE) {
             // NO_EXECUTE_ENABLE是false，那么就设置xd_reserved为true
             kdebug!("NO_EXECUTE_ENABLE is false, set XD_RESERVED to true");
@@ -325,6 +341,7 @@ pub fn mm_init() {
 
     // 初始化内存管理器
     unsafe { allocator_init() };
+    kasan_init();
     // enable mmio
     mmio_init();
 }
@@ -399,24 +416,17 @@ unsafe fn allocator_init() {
         bump_allocator.offset() / 1024
     );
 
-    // 初始化buddy_allocator
-    let buddy_allocator = unsafe { BuddyAllocator::<X86_64MMArch>::new(bump_allocator).unwrap() };
-    // 设置全局的页帧分配器
-    unsafe { set_inner_allocator(buddy_allocator) };
-    kinfo!("Successfully initialized buddy allocator");
-    // 关闭显示输出
+    // 关闭显示输出：新的页表中没有映射帧缓冲区
     scm_disable_put_to_window();
 
     // make the new page table current
     {
-        let mut binding = INNER_ALLOCATOR.lock();
-        let mut allocator_guard = binding.as_mut().unwrap();
         kdebug!("To enable new page table.");
         compiler_fence(Ordering::SeqCst);
         let mapper = crate::mm::page::PageMapper::<MMArch, _>::new(
             PageTableKind::Kernel,
             new_page_table,
-            &mut allocator_guard,
+            &mut bump_allocator,
         );
         compiler_fence(Ordering::SeqCst);
         mapper.make_current();
@@ -424,6 +434,92 @@ unsafe fn allocator_init() {
         kdebug!("New page table enabled");
     }
     kdebug!("Successfully enabled new page table");
+
+    // 伙伴分配器按照NUMA节点划分内存区，因此要在建立伙伴分配器之前解析SRAT
+    {
+        let mut rsdp_buf = [0u8; BOOT_RSDP_MAX_LEN];
+        let rsdp_len = boot_info().acpi_rsdp().map(|rsdp| {
+            rsdp_buf[0..rsdp.len()].copy_from_slice(rsdp);
+            rsdp.len()
+        });
+        let boot_rsdp = rsdp_len.map(|len| &rsdp_buf[0..len]);
+        let mut reader = EarlyPhysMemReader::new(&mut bump_allocator);
+        if let Err(e) = acpi_numa_init(&mut reader, boot_rsdp) {
+            kinfo!("No usable NUMA topology ({:?}), use a single node", e);
+        }
+    }
+    numa_dump();
+
+    // 初始化各个节点的内存区。bump分配器已经分配出去的内存(页表等)不再交给伙伴分配器
+    set_inner_allocator(ZonedAllocator::new());
+    {
+        let offset = bump_allocator.offset();
+        let mut binding = INNER_ALLOCATOR.lock();
+        let allocator = binding.as_mut().unwrap();
+        for area in PHYS_MEMORY_AREAS.iter().filter(|a| a.size != 0) {
+            let start = PhysAddr::new(core::cmp::max(area.base.data(), offset));
+            let end = area.base + area.size;
+            if start >= end {
+                continue;
+            }
+            numa_split_range(start, end, |start, end, node| {
+                allocator.add_zone(node, start, end)
+            });
+        }
+    }
+    kinfo!("Successfully initialized buddy allocator");
+}
+
+/// 内存管理初始化时，读取物理内存的方法
+///
+/// 新的页表只映射了可用的内存，ACPI表所在的页需要临时映射到直接映射区，读取完之后立即取消映射。
+/// 中间级的页表从bump分配器分配，取消映射时不释放它们
+struct EarlyPhysMemReader<'a> {
+    mapper: crate::mm::page::PageMapper<MMArch, &'a mut BumpAllocator<MMArch>>,
+}
+
+impl<'a> EarlyPhysMemReader<'a> {
+    unsafe fn new(bump_allocator: &'a mut BumpAllocator<MMArch>) -> Self {
+        return Self {
+            mapper: crate::mm::page::PageMapper::current(PageTableKind::Kernel, bump_allocator),
+        };
+    }
+}
+
+impl EarlyPhysReader for EarlyPhysMemReader<'_> {
+    fn read(&mut self, paddr: PhysAddr, buf: &mut [u8]) -> Result<(), SystemError> {
+        let mut done = 0;
+        while done < buf.len() {
+            let cur = paddr + done;
+            let page = PhysAddr::new(page_align_down(cur.data()));
+            let len = core::cmp::min(
+                buf.len() - done,
+                page.data() + MMArch::PAGE_SIZE - cur.data(),
+            );
+            let vaddr = unsafe { MMArch::phys_2_virt(page) }.ok_or(SystemError::EFAULT)?;
+
+            let mapped = self.mapper.translate(vaddr).is_some();
+            if !mapped {
+                unsafe { self.mapper.map_phys(vaddr, page, PageFlags::new()) }
+                    .ok_or(SystemError::ENOMEM)?
+                    .flush();
+            }
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    (vaddr.data() + (cur.data() - page.data())) as *const u8,
+                    buf[done..].as_mut_ptr(),
+                    len,
+                )
+            };
+            if !mapped {
+                let (_, _, flush) = unsafe { self.mapper.unmap_phys(vaddr, false) }
+                    .expect("Failed to unmap early ACPI page");
+                flush.flush();
+            }
+            done += len;
+        }
+        return Ok(());
+    }
 }
 
 #[no_mangle]
@@ -524,16 +620,24 @@ pub fn test_buddy() {
 pub struct LockedFrameAllocator;
 
 impl FrameAllocator for LockedFrameAllocator {
+    /// 优先从当前CPU所在的NUMA节点(或者通过[`crate::mm::numa::numa_prefer_node`]指定的节点)分配
     unsafe fn allocate(&mut self, count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
-        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
-            return allocator.allocate(count);
+        let node = numa_node_id();
+        let r = if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
+            allocator.allocate_node(count, node)
         } else {
-            return None;
+            None
+        };
+        // 注意：kasan的操作可能会再次申请物理页，因此需要在释放锁之后进行
+        if let Some((paddr, count)) = r {
+            kasan_alloc_pages(paddr, count);
         }
+        return r;
     }
 
     unsafe fn free(&mut self, address: crate::mm::PhysAddr, count: PageFrameCount) {
         assert!(count.data().is_power_of_two());
+        kasan_free_pages(address, count);
         if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
             return allocator.free(address, count);
         }
@@ -555,21 +659,56 @@ impl LockedFrameAllocator {
 }
 
 /// 获取内核地址默认的页面标志
+///
+/// 初始化完成之前，除了只读数据段，内核地址都是可写、可执行的。
+/// 初始化完成之后([`mark_rodata_ro`])，代码段只读、可执行，只读数据段只读、不可执行，其余的地址可写、不可执行
 pub unsafe fn kernel_page_flags<A: MemoryManagementArch>(virt: VirtAddr) -> PageFlags<A> {
     let info: X86_64MMBootstrapInfo = BOOTSTRAP_MM_INFO.clone().unwrap();
+    let ro_after_init = KERNEL_RO_AFTER_INIT.load(Ordering::Relaxed);
 
     if virt.data() >= info.kernel_code_start && virt.data() < info.kernel_code_end {
         // Remap kernel code  execute
-        return PageFlags::new().set_execute(true).set_write(true);
+        return PageFlags::new().set_execute(true).set_write(!ro_after_init);
     } else if virt.data() >= info.kernel_data_end && virt.data() < info.kernel_rodata_end {
         // Remap kernel rodata read only
-        return PageFlags::new().set_execute(true);
+        return PageFlags::new().set_execute(!ro_after_init);
     } else {
-        return PageFlags::new().set_write(true).set_execute(true);
+        return PageFlags::new().set_write(true).set_execute(!ro_after_init);
     }
 }
 
-unsafe fn set_inner_allocator(allocator: BuddyAllocator<MMArch>) {
+/// 初始化完成之后，按照W^X重新设置内核的直接映射区中所有页的权限
+///
+/// 之后修改内核代码需要暂时关闭CR0的写保护(见kprobe的`text_poke`)，
+/// 执行动态加载的代码需要先通过[`crate::mm::set_memory`]把它所在的页设为可执行
+pub fn mark_rodata_ro() {
+    if KERNEL_RO_AFTER_INIT.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut kernel_mapper = KernelMapper::lock();
+    let mapper = kernel_mapper
+        .as_mut()
+        .expect("Kernel mapper is locked by current cpu");
+    for area in unsafe { PHYS_MEMORY_AREAS.iter() } {
+        for i in 0..((area.size + MMArch::PAGE_SIZE - 1) / MMArch::PAGE_SIZE) {
+            let paddr = area.base.add(i * MMArch::PAGE_SIZE);
+            let vaddr = unsafe { MMArch::phys_2_virt(paddr) }.unwrap();
+            let flags = unsafe { kernel_page_flags::<MMArch>(vaddr) };
+            if let Some(flush) = unsafe { mapper.remap(vaddr, flags) } {
+                // 最后统一刷新TLB
+                unsafe { flush.ignore() };
+            }
+        }
+    }
+    drop(kernel_mapper);
+
+    PageFlushAll::<MMArch>::new().flush();
+    drop(InactiveFlusher::new());
+    kinfo!("Kernel text is read-only, kernel data is non-executable");
+}
+
+unsafe fn set_inner_allocator(allocator: ZonedAllocator<MMArch>) {
     static FLAG: AtomicBool = AtomicBool::new(false);
     if FLAG
         .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
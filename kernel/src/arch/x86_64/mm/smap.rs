@@ -0,0 +1,96 @@
+//! SMEP/SMAP：禁止内核执行、访问用户空间的页
+//!
+//! SMEP开启后，内核执行位于用户页中的指令会触发缺页异常。SMAP开启后，只有在RFLAGS.AC被置位时，
+//! 内核才能访问用户页。内核访问用户内存的函数通过[`UserAccessGuard`]在访问前后使用stac/clac打开、
+//! 关闭访问窗口，其它时候内核误用用户指针会立即触发缺页异常，而不是悄悄读写用户控制的数据。
+//!
+//! 进入内核时(异常、中断、系统调用)，入口代码会清除RFLAGS.AC，因此用户态无法通过置位AC来绕过SMAP。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/include/asm/smap.h
+
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use x86::{
+    bits64::rflags::{self, RFlags},
+    controlregs::{cr4, cr4_write, Cr4},
+    cpuid::CpuId,
+};
+
+use crate::kinfo;
+
+/// SMAP是否已经开启。所有的cpu具有相同的特性，因此只记录一次
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 开启当前cpu支持的SMEP与SMAP，每个cpu都需要调用一次
+pub fn smep_smap_init() {
+    let (smep, smap) = CpuId::new()
+        .get_extended_feature_info()
+        .map_or((false, false), |f| (f.has_smep(), f.has_smap()));
+
+    let mut flags = unsafe { cr4() };
+    if smep {
+        flags.insert(Cr4::CR4_ENABLE_SMEP);
+    }
+    if smap {
+        flags.insert(Cr4::CR4_ENABLE_SMAP);
+    }
+    unsafe { cr4_write(flags) };
+
+    if smap && !SMAP_ENABLED.swap(true, Ordering::SeqCst) {
+        kinfo!("SMEP: {}, SMAP: {}", smep, smap);
+    }
+}
+
+/// SMAP是否已经开启
+#[inline(always)]
+pub fn smap_enabled() -> bool {
+    return SMAP_ENABLED.load(Ordering::Relaxed);
+}
+
+/// 内核访问用户内存的窗口
+///
+/// 创建时置位RFLAGS.AC，允许内核访问用户页；销毁时恢复原来的状态。窗口可以嵌套，
+/// 只有最外层的窗口销毁时才会清除AC。窗口内可以发生调度，RFLAGS在切换进程时会被保存。
+///
+/// 窗口应当尽量小，只包含真正访问用户内存的代码
+#[derive(Debug)]
+pub struct UserAccessGuard {
+    /// 打开窗口之前，AC是否已经被置位
+    was_open: bool,
+}
+
+impl UserAccessGuard {
+    #[inline(always)]
+    pub fn new() -> Self {
+        if !smap_enabled() {
+            return Self { was_open: true };
+        }
+        let was_open = rflags::read().contains(RFlags::FLAGS_AC);
+        if !was_open {
+            unsafe { asm!("stac", options(nostack)) };
+        }
+        return Self { was_open };
+    }
+}
+
+impl Drop for UserAccessGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if !self.was_open {
+            unsafe { asm!("clac", options(nostack)) };
+        }
+    }
+}
+
+/// 判断内核态发生的缺页异常是否违反了SMAP，也就是在AC没有被置位时访问了用户页
+///
+/// ## 参数
+///
+/// - `rflags`：发生异常时的RFLAGS
+#[inline(always)]
+pub fn is_smap_violation(rflags: u64) -> bool {
+    return smap_enabled() && rflags & RFlags::FLAGS_AC.bits() == 0;
+}
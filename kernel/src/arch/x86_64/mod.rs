@@ -1,22 +1,29 @@
 #[macro_use]
 pub mod asm;
 mod acpi;
+pub mod boot;
 mod c_adapter;
 pub mod cpu;
+pub mod crypto;
 pub mod driver;
 pub mod fpu;
 pub mod interrupt;
 pub mod ipc;
+pub mod kexec;
+pub mod kprobe;
 pub mod kvm;
 pub mod libs;
 pub mod mm;
+pub mod module;
 pub mod msi;
 pub mod pci;
+pub mod pmu;
 pub mod process;
 pub mod rand;
 pub mod sched;
 pub mod setup;
 pub mod smp;
+pub mod suspend;
 pub mod syscall;
 pub mod time;
 
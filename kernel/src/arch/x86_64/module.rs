@@ -0,0 +1,72 @@
+//! 可加载模块在x86_64上的重定位
+//!
+//! 模块使用默认的代码模型(small/kernel)编译，调用内核函数时使用32位的相对地址，
+//! 因此模块必须加载到与内核代码相距2GB以内的位置。内核位于直接映射区的开头，
+//! 模块也放在直接映射区中，只要求它所在的物理内存位于较低的地址。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/module.c
+
+use elf::abi::{
+    EM_X86_64, R_X86_64_32, R_X86_64_32S, R_X86_64_64, R_X86_64_NONE, R_X86_64_PC32, R_X86_64_PC64,
+    R_X86_64_PLT32,
+};
+
+use crate::{kerror, syscall::SystemError};
+
+/// 模块的ELF文件的机器类型
+pub const MODULE_ELF_MACHINE: u16 = EM_X86_64;
+
+/// 模块所在的物理内存必须低于这个地址，以保证模块与内核代码之间的距离不超过2GB
+pub const MODULE_PHYS_LIMIT: usize = 1 << 30;
+
+/// 应用一条RELA重定位
+///
+/// ## 参数
+///
+/// - `r_type`：重定位的类型
+/// - `loc`：需要修改的位置
+/// - `val`：符号的地址加上addend
+///
+/// ## 安全性
+///
+/// `loc`必须指向模块中已经分配的内存
+pub unsafe fn apply_relocate_add(r_type: u32, loc: usize, val: u64) -> Result<(), SystemError> {
+    match r_type {
+        R_X86_64_NONE => {}
+        R_X86_64_64 => (loc as *mut u64).write_unaligned(val),
+        R_X86_64_32 => {
+            if val != val as u32 as u64 {
+                return Err(relocate_overflow(r_type, val));
+            }
+            (loc as *mut u32).write_unaligned(val as u32);
+        }
+        R_X86_64_32S => {
+            if val as i64 != val as i32 as i64 {
+                return Err(relocate_overflow(r_type, val));
+            }
+            (loc as *mut u32).write_unaligned(val as u32);
+        }
+        R_X86_64_PC32 | R_X86_64_PLT32 => {
+            let val = val.wrapping_sub(loc as u64);
+            if val as i64 != val as i32 as i64 {
+                return Err(relocate_overflow(r_type, val));
+            }
+            (loc as *mut u32).write_unaligned(val as u32);
+        }
+        R_X86_64_PC64 => (loc as *mut u64).write_unaligned(val.wrapping_sub(loc as u64)),
+        _ => {
+            kerror!("module: unknown relocation type {}", r_type);
+            return Err(SystemError::ENOEXEC);
+        }
+    }
+    return Ok(());
+}
+
+fn relocate_overflow(r_type: u32, val: u64) -> SystemError {
+    kerror!(
+        "module: relocation type {} overflow, value {:#x}",
+        r_type,
+        val
+    );
+    return SystemError::ENOEXEC;
+}
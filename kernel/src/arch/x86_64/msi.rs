@@ -1,9 +1,17 @@
 use crate::driver::pci::pci_irq::TriggerMode;
+
+use super::smp::SMP_BOOT_DATA;
 /// @brief 获得MSI Message Address
 /// @param processor 目标CPU ID号
 /// @return MSI Message Address
 pub fn ia64_pci_get_arch_msi_message_address(processor: u16) -> u32 {
-    0xfee00000 | ((processor as u32) << 12)
+    // Destination ID字段需要填写目标CPU的Local APIC ID
+    let apic_id = if (processor as usize) < SMP_BOOT_DATA.cpu_count() {
+        SMP_BOOT_DATA.phys_id(processor as usize)
+    } else {
+        SMP_BOOT_DATA.bsp_phys_id()
+    };
+    0xfee00000 | (((apic_id & 0xff) as u32) << 12)
 }
 /// @brief 获得MSI Message Data
 /// @param vector 分配的中断向量号
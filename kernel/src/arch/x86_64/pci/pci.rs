@@ -1,16 +1,12 @@
 use crate::arch::TraitPciArch;
-use crate::driver::acpi::old::mcfg_find_segment;
+use crate::driver::acpi::acpi_manager;
 use crate::driver::pci::pci::{
     BusDeviceFunction, PciAddr, PciError, PciRoot, SegmentGroupNumber, PORT_PCI_CONFIG_ADDRESS,
     PORT_PCI_CONFIG_DATA,
 };
-use crate::include::bindings::bindings::{
-    acpi_get_MCFG, acpi_iter_SDT, acpi_system_description_table_header_t, io_in32, io_out32,
-};
+use crate::include::bindings::bindings::{io_in32, io_out32};
 use crate::mm::PhysAddr;
 
-use core::ffi::c_void;
-use core::ptr::NonNull;
 pub struct X86_64PciArch {}
 impl TraitPciArch for X86_64PciArch {
     fn read_config(bus_device_function: &BusDeviceFunction, offset: u8) -> u32 {
@@ -46,32 +42,22 @@ impl TraitPciArch for X86_64PciArch {
     }
 
     fn ecam_root(segement: SegmentGroupNumber) -> Result<PciRoot, PciError> {
-        let mut data: usize = 0;
-        let data_point = &mut data;
-        unsafe {
-            acpi_iter_SDT(Some(acpi_get_MCFG), data_point as *mut usize as *mut c_void);
-        };
         // 防止无PCIE的机器找不到MCFG Table导致的错误
-        if data == 0 {
-            return Err(PciError::McfgTableNotFound);
-        }
-        //kdebug!("{}",data);
-        //loop{}
-        let head = NonNull::new(data as *mut acpi_system_description_table_header_t).unwrap();
-        let outcome = unsafe { mcfg_find_segment(head).as_ref() };
-        for segmentgroupconfiguration in outcome {
-            if segmentgroupconfiguration.segement_group_number == segement {
-                return Ok(PciRoot {
-                    physical_address_base: PhysAddr::new(
-                        segmentgroupconfiguration.base_address as usize,
-                    ),
-                    mmio_guard: None,
-                    segement_group_number: segement,
-                    bus_begin: segmentgroupconfiguration.bus_begin,
-                    bus_end: segmentgroupconfiguration.bus_end,
-                });
-            }
-        }
-        return Err(PciError::SegmentNotFound);
+        let entries = acpi_manager()
+            .mcfg_info()
+            .map_err(|_| PciError::McfgTableNotFound)?;
+
+        let entry = entries
+            .iter()
+            .find(|e| e.segment == segement)
+            .ok_or(PciError::SegmentNotFound)?;
+
+        return Ok(PciRoot {
+            physical_address_base: entry.base_address,
+            mmio_guard: None,
+            segement_group_number: segement,
+            bus_begin: entry.bus_begin,
+            bus_end: entry.bus_end,
+        });
     }
 }
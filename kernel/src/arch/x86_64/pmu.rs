@@ -0,0 +1,240 @@
+//! x86_64的性能监视单元(PMU)
+//!
+//! 只支持Intel的架构性能监视(architectural performance monitoring)，通过CPUID 0xA检测。
+//! 使用通用计数器IA32_PMCx，由IA32_PERFEVTSELx选择要计数的事件。
+//!
+//! 计数器溢出时，local APIC通过LVT性能监视寄存器发出中断。这里把它配置为NMI，
+//! 这样关中断的代码也能被采样，也不需要占用一个中断向量。
+//! local APIC发出中断之后会自动屏蔽LVT，处理完成后需要重新解除屏蔽。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/events/intel/core.c
+
+use core::arch::x86_64::{__cpuid, __get_cpuid_max};
+
+use x86::msr::{rdmsr, wrmsr};
+
+use crate::{
+    arch::interrupt::TrapFrame,
+    perf::{perf_event_overflow, PerfHwId},
+};
+
+extern "C" {
+    fn apic_write_lvt_pmc(value: u32);
+}
+
+const MSR_IA32_PMC0: u32 = 0xc1;
+const MSR_IA32_PERFEVTSEL0: u32 = 0x186;
+const MSR_IA32_PERF_GLOBAL_STATUS: u32 = 0x38e;
+const MSR_IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+const MSR_IA32_PERF_GLOBAL_OVF_CTRL: u32 = 0x390;
+
+/// 在用户态计数
+const EVTSEL_USR: u64 = 1 << 16;
+/// 在内核态计数
+const EVTSEL_OS: u64 = 1 << 17;
+/// 溢出时产生中断
+const EVTSEL_INT: u64 = 1 << 20;
+/// 启用计数器
+const EVTSEL_EN: u64 = 1 << 22;
+
+/// LVT的投递模式：NMI
+const APIC_DM_NMI: u32 = 0x400;
+
+/// 写入IA32_PMCx时只有低32位有效，高位是第31位的符号扩展，因此采样周期不能超过这个值
+const MAX_PERIOD: u64 = (1 << 31) - 1;
+
+/// 架构性能监视的通用计数器
+#[derive(Debug)]
+pub struct X86Pmu {
+    /// 架构性能监视的版本号
+    version: u8,
+    /// 通用计数器的数量
+    num_counters: usize,
+    /// 计数器的位宽对应的掩码
+    counter_mask: u64,
+    /// CPUID.0xA:EBX，置位的事件不可用
+    unavailable: u32,
+    /// CPUID.0xA:EBX中有效的位数
+    events_len: u32,
+}
+
+lazy_static! {
+    static ref X86_PMU: Option<X86Pmu> = X86Pmu::probe();
+}
+
+/// 获取当前机器的PMU，不支持架构性能监视时返回None
+pub fn arch_pmu() -> Option<&'static X86Pmu> {
+    return X86_PMU.as_ref();
+}
+
+impl X86Pmu {
+    fn probe() -> Option<Self> {
+        let (max_leaf, _) = unsafe { __get_cpuid_max(0) };
+        if max_leaf < 0xa {
+            return None;
+        }
+        let r = unsafe { __cpuid(0xa) };
+        let version = (r.eax & 0xff) as u8;
+        let num_counters = ((r.eax >> 8) & 0xff) as usize;
+        let width = (r.eax >> 16) & 0xff;
+        if version == 0 || num_counters == 0 || width <= 32 {
+            return None;
+        }
+        return Some(Self {
+            version,
+            num_counters,
+            counter_mask: if width >= 64 {
+                u64::MAX
+            } else {
+                (1 << width) - 1
+            },
+            unavailable: r.ebx,
+            events_len: (r.eax >> 24) & 0xff,
+        });
+    }
+
+    pub fn num_counters(&self) -> usize {
+        return self.num_counters;
+    }
+
+    /// 计数器的值的有效位
+    pub fn counter_mask(&self) -> u64 {
+        return self.counter_mask;
+    }
+
+    /// 允许的最大采样周期
+    pub fn max_period(&self) -> u64 {
+        return MAX_PERIOD;
+    }
+
+    /// 把通用的硬件事件转换为IA32_PERFEVTSELx中的事件编号与umask
+    ///
+    /// ## 返回值
+    ///
+    /// 当前处理器不支持这个事件时返回None
+    pub fn hw_event(&self, id: PerfHwId) -> Option<u64> {
+        // (CPUID.0xA:EBX中的位, 事件编号, umask)
+        let (bit, event, umask) = match id {
+            PerfHwId::CpuCycles => (0, 0x3c, 0x00),
+            PerfHwId::Instructions => (1, 0xc0, 0x00),
+            PerfHwId::CacheReferences => (3, 0x2e, 0x4f),
+            PerfHwId::CacheMisses => (4, 0x2e, 0x41),
+            PerfHwId::BranchInstructions => (5, 0xc4, 0x00),
+            PerfHwId::BranchMisses => (6, 0xc5, 0x00),
+        };
+        if bit >= self.events_len || self.unavailable & (1 << bit) != 0 {
+            return None;
+        }
+        return Some(event | (umask << 8));
+    }
+
+    /// 启动一个计数器
+    ///
+    /// ## 参数
+    ///
+    /// - `idx`：计数器的编号
+    /// - `event`：[`X86Pmu::hw_event`]返回的事件
+    /// - `user`：是否在用户态计数
+    /// - `kernel`：是否在内核态计数
+    /// - `interrupt`：溢出时是否产生中断
+    /// - `initial`：计数器的初始值
+    ///
+    /// ## 安全性
+    ///
+    /// 需要在关中断的情况下调用，且同一个计数器不能同时被两个事件使用
+    pub unsafe fn start(
+        &self,
+        idx: usize,
+        event: u64,
+        user: bool,
+        kernel: bool,
+        interrupt: bool,
+        initial: u64,
+    ) {
+        let mut evtsel = event | EVTSEL_EN;
+        if user {
+            evtsel |= EVTSEL_USR;
+        }
+        if kernel {
+            evtsel |= EVTSEL_OS;
+        }
+        if interrupt {
+            evtsel |= EVTSEL_INT;
+            apic_write_lvt_pmc(APIC_DM_NMI);
+        }
+        wrmsr(MSR_IA32_PERFEVTSEL0 + idx as u32, 0);
+        self.write(idx, initial);
+        if self.version >= 2 {
+            let ctrl = rdmsr(MSR_IA32_PERF_GLOBAL_CTRL);
+            wrmsr(MSR_IA32_PERF_GLOBAL_CTRL, ctrl | (1 << idx));
+        }
+        wrmsr(MSR_IA32_PERFEVTSEL0 + idx as u32, evtsel);
+    }
+
+    /// 停止一个计数器，计数器的值保持不变
+    ///
+    /// ## 安全性
+    ///
+    /// 需要在关中断的情况下调用
+    pub unsafe fn stop(&self, idx: usize) {
+        wrmsr(MSR_IA32_PERFEVTSEL0 + idx as u32, 0);
+        if self.version >= 2 {
+            let ctrl = rdmsr(MSR_IA32_PERF_GLOBAL_CTRL);
+            wrmsr(MSR_IA32_PERF_GLOBAL_CTRL, ctrl & !(1 << idx));
+        }
+    }
+
+    /// 读取计数器的值
+    pub fn read(&self, idx: usize) -> u64 {
+        return unsafe { rdmsr(MSR_IA32_PMC0 + idx as u32) } & self.counter_mask;
+    }
+
+    /// 设置计数器的值，只有低32位有效
+    ///
+    /// ## 安全性
+    ///
+    /// 需要在关中断的情况下调用
+    pub unsafe fn write(&self, idx: usize, value: u64) {
+        wrmsr(MSR_IA32_PMC0 + idx as u32, value & 0xffff_ffff);
+    }
+
+    /// 判断一个采样用的计数器是否已经溢出
+    ///
+    /// 采样用的计数器的初始值是采样周期的相反数，最高位为1。溢出之后从0开始重新计数，最高位变为0
+    pub fn overflowed(&self, idx: usize) -> bool {
+        return self.read(idx) & (self.counter_mask ^ (self.counter_mask >> 1)) == 0;
+    }
+
+    /// 处理完溢出之后调用：清除溢出状态，重新解除LVT的屏蔽
+    ///
+    /// ## 安全性
+    ///
+    /// 只能在NMI处理函数中调用
+    unsafe fn ack(&self) {
+        if self.version >= 2 {
+            let status = rdmsr(MSR_IA32_PERF_GLOBAL_STATUS);
+            if status != 0 {
+                wrmsr(MSR_IA32_PERF_GLOBAL_OVF_CTRL, status);
+            }
+        }
+        apic_write_lvt_pmc(APIC_DM_NMI);
+    }
+}
+
+/// NMI的处理函数首先调用它，判断这个NMI是否是计数器溢出产生的
+///
+/// ## 返回值
+///
+/// - `true`：NMI由perf处理，处理函数直接返回即可
+/// - `false`：不是计数器溢出产生的NMI
+#[no_mangle]
+pub unsafe extern "C" fn rs_perf_nmi_handler(regs: *mut TrapFrame) -> bool {
+    let frame = regs.as_ref().unwrap();
+    let handled = perf_event_overflow(frame.rip as usize, frame.cs & 0x3 != 0);
+    if handled {
+        if let Some(pmu) = arch_pmu() {
+            pmu.ack();
+        }
+    }
+    return handled;
+}
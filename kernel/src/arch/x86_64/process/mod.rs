@@ -8,7 +8,11 @@ use core::{
 use alloc::{string::String, sync::Arc, vec::Vec};
 
 use memoffset::offset_of;
-use x86::{controlregs::Cr4, segmentation::SegmentSelector};
+use x86::{
+    controlregs::Cr4,
+    msr::{rdmsr, wrmsr, IA32_FS_BASE},
+    segmentation::SegmentSelector,
+};
 
 use crate::{
     arch::process::table::TSSManager,
@@ -20,8 +24,9 @@ use crate::{
         VirtAddr,
     },
     process::{
-        fork::CloneFlags, KernelStack, ProcessControlBlock, ProcessFlags, ProcessManager,
-        SwitchResult, SWITCH_RESULT,
+        fork::{CloneFlags, KernelCloneArgs},
+        KernelStack, ProcessControlBlock, ProcessFlags, ProcessManager, SwitchResult,
+        SWITCH_RESULT,
     },
     syscall::{Syscall, SystemError},
 };
@@ -35,6 +40,7 @@ use super::{fpu::FpState, interrupt::TrapFrame, CurrentIrqArch};
 
 mod c_adapter;
 pub mod kthread;
+pub mod ptrace;
 pub mod syscall;
 pub mod table;
 
@@ -171,11 +177,14 @@ impl ArchPCBInfo {
 
         self.fp_state.as_mut().unwrap().clear();
     }
+    /// 保存当前CPU的fs基地址
+    ///
+    /// 用户程序的线程指针保存在fs基地址中，因此没有开启FSGSBASE时，通过MSR读取
     pub unsafe fn save_fsbase(&mut self) {
         if x86::controlregs::cr4().contains(Cr4::CR4_ENABLE_FSGSBASE) {
             self.fsbase = x86::current::segmentation::rdfsbase() as usize;
         } else {
-            self.fsbase = 0;
+            self.fsbase = rdmsr(IA32_FS_BASE) as usize;
         }
     }
 
@@ -187,9 +196,12 @@ impl ArchPCBInfo {
         }
     }
 
+    /// 把保存的fs基地址加载到当前CPU
     pub unsafe fn restore_fsbase(&mut self) {
         if x86::controlregs::cr4().contains(Cr4::CR4_ENABLE_FSGSBASE) {
             x86::current::segmentation::wrfsbase(self.fsbase as u64);
+        } else {
+            wrmsr(IA32_FS_BASE, self.fsbase as u64);
         }
     }
 
@@ -203,6 +215,13 @@ impl ArchPCBInfo {
         self.fsbase
     }
 
+    /// 设置保存的fs基地址
+    ///
+    /// 如果这是当前进程的pcb，还需要调用[`ArchPCBInfo::restore_fsbase`]，使新的值立即生效
+    pub fn set_fsbase(&mut self, fsbase: usize) {
+        self.fsbase = fsbase;
+    }
+
     pub fn gsbase(&self) -> usize {
         self.gsbase
     }
@@ -255,7 +274,7 @@ impl ProcessManager {
     ///
     /// 由于这个过程与具体的架构相关，所以放在这里
     pub fn copy_thread(
-        _clone_flags: &CloneFlags,
+        clone_args: &KernelCloneArgs,
         current_pcb: &Arc<ProcessControlBlock>,
         new_pcb: &Arc<ProcessControlBlock>,
         current_trapframe: &TrapFrame,
@@ -288,6 +307,10 @@ impl ProcessManager {
         new_arch_guard.fs = current_arch_guard.fs;
         new_arch_guard.gs = current_arch_guard.gs;
         new_arch_guard.fp_state = current_arch_guard.fp_state.clone();
+        // 子线程使用调用者指定的线程指针
+        if clone_args.flags.contains(CloneFlags::CLONE_SETTLS) {
+            new_arch_guard.fsbase = clone_args.tls;
+        }
 
         // 拷贝浮点寄存器的状态
         if let Some(fp_state) = current_arch_guard.fp_state.as_ref() {
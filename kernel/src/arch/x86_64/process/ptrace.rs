@@ -0,0 +1,82 @@
+use crate::{arch::interrupt::TrapFrame, process::ProcessManager};
+
+/// 用户态的通用寄存器，与Linux的`struct user_regs_struct`相同，是PTRACE_GETREGSET(NT_PRSTATUS)读取到的内容
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/include/asm/user_64.h
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserRegsStruct {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    /// 系统调用号
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub eflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+impl UserRegsStruct {
+    /// 获取当前进程在用户态的寄存器
+    ///
+    /// ## 参数
+    ///
+    /// - `frame`：当前进程进入内核时保存的栈帧
+    /// - `syscall_nr`：正在执行的系统调用号，作为orig_rax
+    pub fn from_current(frame: &TrapFrame, syscall_nr: usize) -> Self {
+        let pcb = ProcessManager::current_pcb();
+        let mut arch_guard = pcb.arch_info_irqsave();
+        // 保存的fs基地址只在进程切换时更新，这里读取最新的值
+        unsafe { arch_guard.save_fsbase() };
+
+        return Self {
+            r15: frame.r15,
+            r14: frame.r14,
+            r13: frame.r13,
+            r12: frame.r12,
+            rbp: frame.rbp,
+            rbx: frame.rbx,
+            r11: frame.r11,
+            r10: frame.r10,
+            r9: frame.r9,
+            r8: frame.r8,
+            rax: frame.rax,
+            rcx: frame.rcx,
+            rdx: frame.rdx,
+            rsi: frame.rsi,
+            rdi: frame.rdi,
+            orig_rax: syscall_nr as u64,
+            rip: frame.rip,
+            cs: frame.cs,
+            eflags: frame.rflags,
+            rsp: frame.rsp,
+            ss: frame.ss,
+            fs_base: arch_guard.fsbase as u64,
+            gs_base: arch_guard.gsbase as u64,
+            ds: frame.ds,
+            es: frame.es,
+            fs: arch_guard.fs as u64,
+            gs: arch_guard.gs as u64,
+        };
+    }
+}
@@ -4,17 +4,22 @@ use crate::{
     arch::{
         interrupt::TrapFrame,
         process::table::{USER_CS, USER_DS},
-        CurrentIrqArch,
+        CurrentIrqArch, MMArch,
     },
     exception::InterruptArch,
-    mm::ucontext::AddressSpace,
+    mm::{aslr::should_randomize, ucontext::AddressSpace, MemoryManagementArch},
     process::{
         exec::{load_binary_file, ExecParam, ExecParamFlags},
-        ProcessManager,
+        ProcessFlags, ProcessManager,
     },
-    syscall::{Syscall, SystemError},
+    syscall::{user_access::UserPtr, Syscall, SystemError},
 };
 
+/// arch_prctl设置fs基地址
+pub const ARCH_SET_FS: usize = 0x1002;
+/// arch_prctl获取fs基地址
+pub const ARCH_GET_FS: usize = 0x1003;
+
 impl Syscall {
     pub fn do_execve(
         path: String,
@@ -40,6 +45,12 @@ impl Syscall {
         unsafe {
             basic_info.set_user_vm(None);
         }
+        // 根据personality以及randomize_va_space，决定新的地址空间的布局是否随机化
+        if should_randomize(basic_info.personality()) {
+            pcb.flags().insert(ProcessFlags::RANDOMIZE);
+        } else {
+            pcb.flags().remove(ProcessFlags::RANDOMIZE);
+        }
         // 创建新的地址空间并设置为当前地址空间
         let address_space = AddressSpace::new(true).expect("Failed to create new address space");
         unsafe {
@@ -67,6 +78,14 @@ impl Syscall {
         let load_result = load_binary_file(&mut param)
             .unwrap_or_else(|e| panic!("Failed to load binary file: {:?}, path: {:?}", e, path));
         // kdebug!("load binary file done");
+
+        // 设置初始线程的线程指针。程序没有TLS时清零，不能沿用exec之前的值
+        {
+            let fsbase = load_result.thread_pointer().map_or(0, |tp| tp.data());
+            let mut arch_guard = pcb.arch_info_irqsave();
+            arch_guard.set_fsbase(fsbase);
+            unsafe { arch_guard.restore_fsbase() };
+        }
         // kdebug!("argv: {:?}, envp: {:?}", argv, envp);
         param.init_info_mut().args = argv;
         param.init_info_mut().envs = envp;
@@ -114,4 +133,37 @@ impl Syscall {
 
         return Ok(());
     }
+
+    /// @brief 设置或获取架构相关的线程状态
+    ///
+    /// 目前只支持fs基地址，用户程序通过它设置线程指针
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/process_64.c
+    ///
+    /// @param code ARCH_SET_FS或ARCH_GET_FS
+    /// @param addr ARCH_SET_FS时为新的fs基地址，ARCH_GET_FS时为保存结果的用户空间地址
+    ///
+    /// @return Err(SystemError::EPERM) 新的fs基地址不在用户空间内
+    /// @return Err(SystemError::EINVAL) 不支持的code
+    pub fn arch_prctl(code: usize, addr: usize) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        match code {
+            ARCH_SET_FS => {
+                if addr >= MMArch::USER_END_VADDR.data() {
+                    return Err(SystemError::EPERM);
+                }
+                // 持有锁期间中断是关闭的，不会在写入寄存器之前被调度出去
+                let mut arch_guard = pcb.arch_info_irqsave();
+                arch_guard.set_fsbase(addr);
+                unsafe { arch_guard.restore_fsbase() };
+                return Ok(0);
+            }
+            ARCH_GET_FS => {
+                let fsbase = pcb.arch_info_irqsave().fsbase() as u64;
+                UserPtr::<u64>::new(addr).write(&fsbase)?;
+                return Ok(0);
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
 }
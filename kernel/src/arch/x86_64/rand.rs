@@ -3,3 +3,22 @@ use core::arch::x86_64::_rdtsc;
 pub fn rand() -> usize {
     return unsafe { (_rdtsc() * _rdtsc() + 998244353_u64 * _rdtsc()) as usize };
 }
+
+/// 从CPU的硬件随机数发生器(RDRAND)获取一个随机数，CPU不支持或者暂时无法提供时返回None
+pub fn arch_get_random_seed() -> Option<u64> {
+    let has_rdrand = x86::cpuid::CpuId::new()
+        .get_feature_info()
+        .map_or(false, |feat| feat.has_rdrand());
+    if !has_rdrand {
+        return None;
+    }
+
+    let mut seed = 0u64;
+    // RDRAND可能暂时无法提供随机数，Intel建议最多重试10次
+    for _ in 0..10 {
+        if unsafe { x86::random::rdrand64(&mut seed) } {
+            return Some(seed);
+        }
+    }
+    return None;
+}
@@ -1,10 +1,15 @@
 use crate::syscall::SystemError;
 
-use super::{acpi::early_acpi_boot_init, smp::X86_64_SMP_MANAGER};
+use super::{
+    acpi::early_acpi_boot_init, driver::legacy::x86_legacy_devices_init, mm::smap::smep_smap_init,
+    smp::X86_64_SMP_MANAGER,
+};
 
 /// 进行架构相关的初始化工作
 pub fn setup_arch() -> Result<(), SystemError> {
+    smep_smap_init();
     early_acpi_boot_init()?;
     X86_64_SMP_MANAGER.build_cpu_map()?;
+    x86_legacy_devices_init()?;
     return Ok(());
 }
@@ -7,9 +7,15 @@ use core::{
 use memoffset::offset_of;
 
 use crate::{
-    arch::process::table::TSSManager, exception::InterruptArch,
-    include::bindings::bindings::cpu_core_info, kdebug, libs::rwlock::RwLock, mm::percpu::PerCpu,
-    process::ProcessManager, smp::core::smp_get_processor_id, syscall::SystemError,
+    arch::{mm::smap::smep_smap_init, process::table::TSSManager},
+    exception::InterruptArch,
+    include::bindings::bindings::cpu_core_info,
+    kdebug,
+    libs::rwlock::RwLock,
+    mm::percpu::PerCpu,
+    process::ProcessManager,
+    smp::core::smp_get_processor_id,
+    syscall::SystemError,
 };
 
 use super::CurrentIrqArch;
@@ -59,6 +65,7 @@ unsafe extern "C" fn smp_ap_start_stage1() -> ! {
         current_idle.kernel_stack().stack_max_address().data() as u64,
     );
     TSSManager::load_tr();
+    smep_smap_init();
 
     smp_ap_start_stage2();
     loop {
@@ -113,6 +120,23 @@ impl SmpBootData {
     }
 }
 
+/// 获取系统中CPU的数量（ACPI表解析完成之前为1）
+pub fn cpu_count() -> usize {
+    SMP_BOOT_DATA.cpu_count().max(1)
+}
+
+/// 获取要启动的CPU数量（包括BSP），供C代码使用
+#[no_mangle]
+pub extern "C" fn rs_smp_cpu_count() -> u32 {
+    cpu_count() as u32
+}
+
+/// 获取第`cpu`个CPU的Local APIC ID（第0个为BSP），供C代码使用
+#[no_mangle]
+pub extern "C" fn rs_smp_cpu_phys_id(cpu: u32) -> u32 {
+    SMP_BOOT_DATA.phys_id(cpu as usize) as u32
+}
+
 pub(super) static SMP_BOOT_DATA: SmpBootData = SmpBootData {
     initialized: AtomicBool::new(false),
     cpu_count: 0,
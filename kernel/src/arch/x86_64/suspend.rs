@@ -0,0 +1,341 @@
+//! 挂起到内存(ACPI S3)在x86_64上的实现
+//!
+//! 在S3中，cpu、中断控制器与定时器都会断电，只有内存中的数据被保留下来。唤醒之后，
+//! 固件在实模式下跳转到FACS中的唤醒向量。因此进入S3之前：
+//! 1. 保存callee-saved寄存器、栈指针、控制寄存器、GDTR与IDTR，以及MSR、TSC与浮点寄存器
+//! 2. 把唤醒跳板复制到1MB以下的固定位置，把它设置为唤醒向量
+//!
+//! 唤醒之后：
+//! 1. 跳板从实模式切换到保护模式，再使用临时页表(低地址恒等映射，高半部分与内核相同)切换到长模式，
+//!    然后跳转到内核中的恢复代码
+//! 2. 恢复代码恢复控制寄存器、GDT、IDT与栈，然后从[`x86_suspend_enter`]返回0，就像进入S3的调用刚刚结束一样
+//! 3. 恢复MSR、TSC、TSS、浮点寄存器，重新初始化中断控制器与HPET
+//!
+//! TSC被恢复为进入S3时的值，因此单调时间不包含睡眠的时间。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/acpi/sleep.c
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/realmode/rm/wakeup_asm.S
+
+use core::arch::{asm, global_asm};
+
+use x86::{
+    controlregs::cr3,
+    msr::{
+        rdmsr, wrmsr, IA32_EFER, IA32_FS_BASE, IA32_GS_BASE, IA32_KERNEL_GSBASE,
+        IA32_TIME_STAMP_COUNTER,
+    },
+};
+
+use crate::{
+    arch::{fpu::FpState, process::table::TSSManager, MMArch},
+    driver::acpi::acpi_manager,
+    kerror,
+    mm::{MemoryManagementArch, PhysAddr},
+    syscall::SystemError,
+};
+
+use super::driver::hpet::hpet_instance;
+
+extern "C" {
+    fn apic_suspend();
+    fn apic_resume();
+    fn wakeup_trampoline_start();
+    fn wakeup_trampoline_end();
+    fn x86_suspend_enter(ctx: *mut u64, enter: extern "C" fn() -> i64) -> i64;
+    fn x86_suspend_resume();
+}
+
+/// 唤醒跳板所在的物理地址。必须在1MB以下，并且不与AP的启动代码(0x20000)重叠
+const WAKEUP_TRAMPOLINE_PADDR: usize = 0x10000;
+/// 临时页表的PML4、PDPT与PD，紧跟在跳板页之后
+const WAKEUP_PML4_PADDR: usize = WAKEUP_TRAMPOLINE_PADDR + 0x1000;
+const WAKEUP_PDPT_PADDR: usize = WAKEUP_TRAMPOLINE_PADDR + 0x2000;
+const WAKEUP_PD_PADDR: usize = WAKEUP_TRAMPOLINE_PADDR + 0x3000;
+
+/// 跳板的参数在跳板页中的偏移量
+const PARAM_OFFSET: usize = 0x800;
+/// GDTR在跳板页中的偏移量
+const GDTR_OFFSET: usize = 0x900;
+/// GDT在跳板页中的偏移量
+const GDT_OFFSET: usize = 0x910;
+
+/// 跳板使用的GDT：空描述符、32位代码段、32位数据段、64位代码段
+const WAKEUP_GDT: [u64; 4] = [
+    0,
+    0x00cf_9a00_0000_ffff,
+    0x00cf_9200_0000_ffff,
+    0x00af_9a00_0000_ffff,
+];
+
+/// 页表项：存在、可写
+const PTE_PRESENT_RW: u64 = 0x3;
+/// 页表项：映射2MB的大页
+const PTE_HUGE: u64 = 1 << 7;
+/// EFER中只读的LMA位
+const EFER_LMA: u64 = 1 << 10;
+
+// 保存的cpu状态中各个字段的偏移量(字节)
+const CTX_RSP: usize = 0;
+const CTX_RBX: usize = 8;
+const CTX_RBP: usize = 16;
+const CTX_R12: usize = 24;
+const CTX_R13: usize = 32;
+const CTX_R14: usize = 40;
+const CTX_R15: usize = 48;
+const CTX_CR0: usize = 56;
+const CTX_CR3: usize = 64;
+const CTX_CR4: usize = 72;
+/// GDTR与IDTR各占10字节，按照16字节存放
+const CTX_GDTR: usize = 80;
+const CTX_IDTR: usize = 96;
+/// fs与gs的段选择子各占2字节
+const CTX_FS: usize = 112;
+const CTX_GS: usize = 114;
+/// 保存的cpu状态的大小(u64的个数)
+const CTX_WORDS: usize = 15;
+
+/// 进入S3时保存的cpu状态，由汇编代码读写
+static mut SUSPEND_CONTEXT: [u64; CTX_WORDS] = [0; CTX_WORDS];
+
+/// 跳板的参数，位于跳板页的`PARAM_OFFSET`处
+#[repr(C)]
+struct WakeupTrampolineParams {
+    /// 临时页表的物理地址
+    pml4: u32,
+    /// 切换到长模式时写入EFER的值
+    efer: u32,
+    /// 恢复代码的虚拟地址
+    resume: u64,
+    /// 保存的cpu状态的虚拟地址
+    ctx: u64,
+}
+
+global_asm!(
+    ".pushsection .text",
+    ".global wakeup_trampoline_start",
+    ".global wakeup_trampoline_end",
+    // 固件以(paddr >> 4):0作为CS:IP跳转到这里
+    "wakeup_trampoline_start:",
+    ".code16",
+    "cli",
+    "cld",
+    "mov ax, cs",
+    "mov ds, ax",
+    "lgdt [{gdtr}]",
+    "mov eax, cr0",
+    "or eax, 1",
+    "mov cr0, eax",
+    // 32位的远跳转，切换到保护模式
+    ".byte 0x66, 0xea",
+    ".long {paddr} + (.Lwakeup_protected - wakeup_trampoline_start)",
+    ".word 0x08",
+    ".code32",
+    ".Lwakeup_protected:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "mov esp, {paddr} + {page_size}",
+    // 开启PAE，加载临时页表，开启长模式(以及进入S3之前开启的NXE)，然后开启分页
+    "mov eax, cr4",
+    "or eax, 1 << 5",
+    "mov cr4, eax",
+    "mov eax, [{paddr} + {param}]",
+    "mov cr3, eax",
+    "mov ecx, 0xc0000080",
+    "mov eax, [{paddr} + {param} + 4]",
+    "xor edx, edx",
+    "wrmsr",
+    "mov eax, cr0",
+    "or eax, 0x80000001",
+    "mov cr0, eax",
+    // 通过远跳转切换到64位代码段
+    ".byte 0xea",
+    ".long {paddr} + (.Lwakeup_long - wakeup_trampoline_start)",
+    ".word 0x18",
+    ".code64",
+    ".Lwakeup_long:",
+    "mov rdi, [{paddr} + {param} + 16]",
+    "mov rax, [{paddr} + {param} + 8]",
+    "jmp rax",
+    "wakeup_trampoline_end:",
+    // 保存cpu的状态，然后调用rsi中的函数进入S3。rdi是保存cpu状态的缓冲区
+    // 进入S3失败时返回这个函数的返回值，被唤醒之后由x86_suspend_resume返回0
+    ".global x86_suspend_enter",
+    "x86_suspend_enter:",
+    "mov [rdi + {rsp}], rsp",
+    "mov [rdi + {rbx}], rbx",
+    "mov [rdi + {rbp}], rbp",
+    "mov [rdi + {r12}], r12",
+    "mov [rdi + {r13}], r13",
+    "mov [rdi + {r14}], r14",
+    "mov [rdi + {r15}], r15",
+    "mov rax, cr0",
+    "mov [rdi + {cr0}], rax",
+    "mov rax, cr3",
+    "mov [rdi + {cr3}], rax",
+    "mov rax, cr4",
+    "mov [rdi + {cr4}], rax",
+    "sgdt [rdi + {gdtr_ctx}]",
+    "sidt [rdi + {idtr_ctx}]",
+    "mov word ptr [rdi + {fs}], fs",
+    "mov word ptr [rdi + {gs}], gs",
+    "sub rsp, 8",
+    "call rsi",
+    "add rsp, 8",
+    "ret",
+    // 跳板切换到长模式之后跳转到这里，此时使用的是临时页表与跳板页中的栈，rdi是保存的cpu状态
+    ".global x86_suspend_resume",
+    "x86_suspend_resume:",
+    "mov rax, [rdi + {cr4}]",
+    "mov cr4, rax",
+    "mov rax, [rdi + {cr3}]",
+    "mov cr3, rax",
+    "mov rax, [rdi + {cr0}]",
+    "mov cr0, rax",
+    "lgdt [rdi + {gdtr_ctx}]",
+    "lidt [rdi + {idtr_ctx}]",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "mov fs, word ptr [rdi + {fs}]",
+    "mov gs, word ptr [rdi + {gs}]",
+    "mov rsp, [rdi + {rsp}]",
+    // 通过远返回重新加载内核的代码段
+    "lea rax, [rip + .Lwakeup_kernel_cs]",
+    "push 0x08",
+    "push rax",
+    "retfq",
+    ".Lwakeup_kernel_cs:",
+    "mov rbx, [rdi + {rbx}]",
+    "mov rbp, [rdi + {rbp}]",
+    "mov r12, [rdi + {r12}]",
+    "mov r13, [rdi + {r13}]",
+    "mov r14, [rdi + {r14}]",
+    "mov r15, [rdi + {r15}]",
+    "xor eax, eax",
+    "ret",
+    ".popsection",
+    paddr = const WAKEUP_TRAMPOLINE_PADDR,
+    page_size = const MMArch::PAGE_SIZE,
+    gdtr = const GDTR_OFFSET,
+    param = const PARAM_OFFSET,
+    rsp = const CTX_RSP,
+    rbx = const CTX_RBX,
+    rbp = const CTX_RBP,
+    r12 = const CTX_R12,
+    r13 = const CTX_R13,
+    r14 = const CTX_R14,
+    r15 = const CTX_R15,
+    cr0 = const CTX_CR0,
+    cr3 = const CTX_CR3,
+    cr4 = const CTX_CR4,
+    gdtr_ctx = const CTX_GDTR,
+    idtr_ctx = const CTX_IDTR,
+    fs = const CTX_FS,
+    gs = const CTX_GS,
+);
+
+/// 把物理地址转换为可以访问的指针
+unsafe fn phys_ptr<T>(paddr: usize) -> *mut T {
+    return MMArch::phys_2_virt(PhysAddr::new(paddr)).unwrap().data() as *mut T;
+}
+
+/// 构造跳板使用的临时页表：低1GB用2MB的大页恒等映射，高半部分复制当前的PML4
+unsafe fn wakeup_build_page_table() {
+    let pd_ptr = phys_ptr::<u64>(WAKEUP_PD_PADDR);
+    for i in 0..512 {
+        pd_ptr
+            .add(i)
+            .write(((i as u64) << 21) | PTE_PRESENT_RW | PTE_HUGE);
+    }
+
+    let pdpt_ptr = phys_ptr::<u64>(WAKEUP_PDPT_PADDR);
+    core::ptr::write_bytes(pdpt_ptr, 0, 512);
+    pdpt_ptr.write(WAKEUP_PD_PADDR as u64 | PTE_PRESENT_RW);
+
+    let pml4_ptr = phys_ptr::<u64>(WAKEUP_PML4_PADDR);
+    let current = phys_ptr::<u64>(cr3() as usize & !(MMArch::PAGE_SIZE - 1));
+    core::ptr::write_bytes(pml4_ptr, 0, 256);
+    core::ptr::copy_nonoverlapping(current.add(256), pml4_ptr.add(256), 256);
+    pml4_ptr.write(WAKEUP_PDPT_PADDR as u64 | PTE_PRESENT_RW);
+}
+
+/// 把跳板复制到跳板页中，并写入参数与GDT
+unsafe fn wakeup_setup_trampoline(efer: u64) {
+    let start = wakeup_trampoline_start as usize;
+    let len = wakeup_trampoline_end as usize - start;
+    assert!(len <= PARAM_OFFSET);
+
+    let base = phys_ptr::<u8>(WAKEUP_TRAMPOLINE_PADDR);
+    core::ptr::copy_nonoverlapping(start as *const u8, base, len);
+    (base.add(PARAM_OFFSET) as *mut WakeupTrampolineParams).write(WakeupTrampolineParams {
+        pml4: WAKEUP_PML4_PADDR as u32,
+        efer: (efer & !EFER_LMA) as u32,
+        resume: x86_suspend_resume as usize as u64,
+        ctx: SUSPEND_CONTEXT.as_ptr() as u64,
+    });
+
+    core::ptr::copy_nonoverlapping(
+        WAKEUP_GDT.as_ptr(),
+        base.add(GDT_OFFSET) as *mut u64,
+        WAKEUP_GDT.len(),
+    );
+    // 实模式下的GDTR：16位的界限，之后是32位的基地址
+    (base.add(GDTR_OFFSET) as *mut u16).write((WAKEUP_GDT.len() * 8 - 1) as u16);
+    (base.add(GDTR_OFFSET + 2) as *mut u32)
+        .write_unaligned((WAKEUP_TRAMPOLINE_PADDR + GDT_OFFSET) as u32);
+}
+
+/// 把cpu的缓存写回内存，然后进入S3。成功的话不会返回
+extern "C" fn x86_enter_sleep() -> i64 {
+    unsafe { asm!("wbinvd", options(nostack, preserves_flags)) };
+    let err = acpi_manager().enter_s3().err().unwrap_or(SystemError::EIO);
+    return err.to_posix_errno() as i64;
+}
+
+/// 保存cpu的状态，进入S3，被唤醒之后恢复cpu的状态
+///
+/// ## 安全性
+///
+/// 调用时中断必须已经关闭，并且只有当前cpu在运行
+///
+/// ## 错误
+///
+/// 没能进入S3时返回错误，此时cpu与中断控制器的状态没有改变
+pub unsafe fn arch_suspend_enter() -> Result<(), SystemError> {
+    acpi_manager().set_waking_vector(WAKEUP_TRAMPOLINE_PADDR as u32)?;
+
+    let efer = rdmsr(IA32_EFER);
+    let fs_base = rdmsr(IA32_FS_BASE);
+    let gs_base = rdmsr(IA32_GS_BASE);
+    let kernel_gs_base = rdmsr(IA32_KERNEL_GSBASE);
+    let mut fp_state = FpState::new();
+    fp_state.save();
+
+    wakeup_build_page_table();
+    wakeup_setup_trampoline(efer);
+    apic_suspend();
+
+    let tsc = rdmsr(IA32_TIME_STAMP_COUNTER);
+    let r = x86_suspend_enter(SUSPEND_CONTEXT.as_mut_ptr(), x86_enter_sleep);
+    if r != 0 {
+        return Err(SystemError::from_posix_errno(r as i32).unwrap_or(SystemError::EIO));
+    }
+
+    // 从这里开始，cpu刚刚从S3中被唤醒
+    wrmsr(IA32_TIME_STAMP_COUNTER, tsc);
+    wrmsr(IA32_FS_BASE, fs_base);
+    wrmsr(IA32_GS_BASE, gs_base);
+    wrmsr(IA32_KERNEL_GSBASE, kernel_gs_base);
+    // TSS描述符仍然带有忙标志，load_tr会重新设置描述符
+    TSSManager::load_tr();
+    fp_state.restore();
+
+    apic_resume();
+    if let Err(e) = hpet_instance().hpet_resume() {
+        kerror!("Failed to resume HPET: {:?}", e);
+    }
+    return Ok(());
+}
@@ -1,4 +1,4 @@
-use core::ffi::c_void;
+use core::{ffi::c_void, intrinsics::unlikely};
 
 use alloc::string::String;
 
@@ -6,6 +6,10 @@ use crate::{
     arch::ipc::signal::X86_64SignalArch,
     include::bindings::bindings::set_system_trap_gate,
     ipc::signal_types::SignalArch,
+    process::{
+        ptrace::{ptrace_report_syscall, ptrace_syscall_traced, PtraceStop},
+        seccomp::secure_computing,
+    },
     syscall::{Syscall, SystemError, SYS_RT_SIGRETURN},
 };
 
@@ -15,6 +19,9 @@ extern "C" {
     fn syscall_int();
 }
 
+/// 当前架构在seccomp过滤器的输入中的标识(AUDIT_ARCH_X86_64)
+pub const AUDIT_ARCH: u32 = 0xc000_003e;
+
 macro_rules! syscall_return {
     ($val:expr, $regs:expr) => {{
         let ret = $val;
@@ -36,6 +43,27 @@ pub extern "C" fn syscall_handler(frame: &mut TrapFrame) -> () {
     ];
     mfence();
 
+    // 被跟踪时，在系统调用的入口停下。与Linux相同，此时rax为-ENOSYS，系统调用号在orig_rax中
+    if unlikely(ptrace_syscall_traced()) {
+        frame.rax = SystemError::ENOSYS.to_posix_errno() as i64 as u64;
+        ptrace_report_syscall(PtraceStop::SyscallEntry, frame, syscall_num);
+    }
+
+    do_syscall(syscall_num, &args, frame);
+
+    // 被跟踪时，在系统调用的出口停下，此时rax为系统调用的返回值
+    if unlikely(ptrace_syscall_traced()) {
+        ptrace_report_syscall(PtraceStop::SyscallExit, frame, syscall_num);
+    }
+}
+
+/// 执行系统调用，并把返回值写入rax
+fn do_syscall(syscall_num: usize, args: &[usize; 6], frame: &mut TrapFrame) {
+    // 被seccomp过滤器拦截的系统调用不会被执行
+    if let Some(ret) = secure_computing(syscall_num, args, frame.rip as usize) {
+        syscall_return!(ret as u64, frame);
+    }
+
     // 由于进程管理未完成重构，有些系统调用需要在这里临时处理，以后这里的特殊处理要删掉。
     match syscall_num {
         SYS_RT_SIGRETURN => {
@@ -44,7 +72,7 @@ pub extern "C" fn syscall_handler(frame: &mut TrapFrame) -> () {
         _ => {}
     }
     syscall_return!(
-        Syscall::handle(syscall_num, &args, frame).unwrap_or_else(|e| e.to_posix_errno() as usize)
+        Syscall::handle(syscall_num, args, frame).unwrap_or_else(|e| e.to_posix_errno() as usize)
             as u64,
         frame
     );
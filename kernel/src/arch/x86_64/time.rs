@@ -1,9 +1,19 @@
 use crate::time::TimeArch;
 
+use super::driver::tsc::TSCManager;
+
 pub struct X86_64TimeArch;
 
 impl TimeArch for X86_64TimeArch {
     fn get_cycles() -> usize {
         unsafe { x86::time::rdtsc() as usize }
     }
+
+    fn cycles2ns(cycles: usize) -> usize {
+        let khz = TSCManager::tsc_khz();
+        if khz == 0 {
+            return 0;
+        }
+        return (cycles as u128 * 1000000 / khz as u128) as usize;
+    }
 }
@@ -0,0 +1,230 @@
+//! 审计子系统
+//!
+//! 发生需要审计的事件(execve、以写方式打开文件、setuid)时，内核生成一条带有进程身份信息的审计记录。
+//! 记录以文本的形式保存在固定容量的环形缓冲区中，缓冲区满时丢弃最早的记录，并计入丢失的记录数。
+//!
+//! 用户态的审计守护进程通过/dev/audit逐条读取记录，通过ioctl查询状态、设置要审计的事件。
+//! 启动参数`audit=`可以设置启动时要审计的事件(事件掩码，见[`AuditEvents`])，默认不审计任何事件。
+//!
+//! 记录的格式参考Linux的审计日志：
+//! `type=EXECVE msg=audit(秒.毫秒:序号): pid=... ppid=... uid=... comm="..." ... res=success`
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/audit.c
+
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use alloc::{collections::VecDeque, string::String};
+
+use crate::{
+    kernel_param,
+    libs::{once::Once, spinlock::SpinLock, wait_queue::WaitQueue},
+    process::ProcessManager,
+    syscall::{user_access::UserPtr, SystemError},
+    time::timekeeping::getnstimeofday,
+};
+
+/// 缓冲区最多保存的记录数量
+pub const AUDIT_BACKLOG_LIMIT: usize = 256;
+
+/// ioctl：获取审计子系统的状态，参数为指向[`AuditStatus`]的指针。与Linux的审计消息类型AUDIT_GET相同
+pub const AUDIT_GET: u32 = 1000;
+/// ioctl：设置要审计的事件，参数为指向事件掩码(u32)的指针。与Linux的审计消息类型AUDIT_SET相同
+pub const AUDIT_SET: u32 = 1001;
+
+bitflags! {
+    /// 可以审计的事件
+    pub struct AuditEvents: u32 {
+        /// 执行程序
+        const EXECVE = 1 << 0;
+        /// 以写方式(O_WRONLY或O_RDWR)打开文件
+        const OPEN_WRITE = 1 << 1;
+        /// 改变进程的uid
+        const SETUID = 1 << 2;
+    }
+}
+
+impl AuditEvents {
+    /// 事件在记录中的类型名
+    fn type_name(&self) -> &'static str {
+        if *self == AuditEvents::EXECVE {
+            "EXECVE"
+        } else if *self == AuditEvents::OPEN_WRITE {
+            "OPEN"
+        } else if *self == AuditEvents::SETUID {
+            "SETUID"
+        } else {
+            "UNKNOWN"
+        }
+    }
+}
+
+/// 与用户态交换的审计子系统状态
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditStatus {
+    /// 正在审计的事件
+    pub events: u32,
+    /// 缓冲区的容量
+    pub backlog_limit: u32,
+    /// 缓冲区中的记录数量
+    pub backlog: u32,
+    /// 缓冲区满而被丢弃的记录数量
+    pub lost: u32,
+}
+
+/// 审计记录的环形缓冲区。序号为seq的记录保存在`records[seq - first_seq]`
+#[derive(Debug)]
+pub struct AuditBuf {
+    records: VecDeque<String>,
+    /// 缓冲区中最早的记录的序号
+    first_seq: u64,
+}
+
+impl AuditBuf {
+    const fn new() -> Self {
+        Self {
+            records: VecDeque::new(),
+            first_seq: 0,
+        }
+    }
+
+    fn push(&mut self, record: String) {
+        if self.records.len() >= AUDIT_BACKLOG_LIMIT {
+            self.records.pop_front();
+            self.first_seq += 1;
+            AUDIT_LOST.fetch_add(1, Ordering::Relaxed);
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn first_seq(&self) -> u64 {
+        self.first_seq
+    }
+
+    /// 下一条记录的序号
+    pub fn end_seq(&self) -> u64 {
+        self.first_seq + self.records.len() as u64
+    }
+
+    /// 获取指定序号的记录，记录已经被丢弃或者还没有产生时返回None
+    pub fn record(&self, seq: u64) -> Option<&String> {
+        if seq < self.first_seq {
+            return None;
+        }
+        return self.records.get((seq - self.first_seq) as usize);
+    }
+}
+
+/// 审计记录的缓冲区
+pub static AUDIT_BUF: SpinLock<AuditBuf> = SpinLock::new(AuditBuf::new());
+/// 等待新的审计记录的进程
+pub static AUDIT_WAIT: WaitQueue = WaitQueue::INIT;
+
+/// 正在审计的事件
+static AUDIT_EVENTS: AtomicU32 = AtomicU32::new(0);
+/// 下一条记录的序号
+static AUDIT_SEQ: AtomicU64 = AtomicU64::new(0);
+/// 被丢弃的记录数量
+static AUDIT_LOST: AtomicU64 = AtomicU64::new(0);
+
+kernel_param!(AUDIT_PARAM: u32 = "audit");
+/// 保证启动参数只被读取一次
+static AUDIT_PARAM_INIT: Once = Once::new();
+
+/// 是否正在审计指定的事件
+pub fn audit_enabled(event: AuditEvents) -> bool {
+    AUDIT_PARAM_INIT.call_once(|| {
+        if let Some(events) = AUDIT_PARAM.get() {
+            AUDIT_EVENTS.store(
+                AuditEvents::from_bits_truncate(events).bits(),
+                Ordering::SeqCst,
+            );
+        }
+    });
+    return AuditEvents::from_bits_truncate(AUDIT_EVENTS.load(Ordering::Relaxed)).contains(event);
+}
+
+/// 记录一个事件。事件没有被审计时什么也不做
+///
+/// ## 参数
+///
+/// - `event`：事件的种类
+/// - `result`：事件的结果，失败时记录错误码
+/// - `args`：与事件有关的字段，例如`path="/bin/sh"`
+pub fn audit_log<T>(event: AuditEvents, result: &Result<T, SystemError>, args: fmt::Arguments) {
+    if !audit_enabled(event) {
+        return;
+    }
+
+    let pcb = ProcessManager::current_pcb();
+    let basic = pcb.basic();
+    let seq = AUDIT_SEQ.fetch_add(1, Ordering::SeqCst);
+    let now = getnstimeofday();
+
+    let mut record = String::new();
+    write!(
+        record,
+        "type={} msg=audit({}.{:03}:{}): pid={} ppid={} uid={} comm=\"{}\" ",
+        event.type_name(),
+        now.tv_sec,
+        now.tv_nsec / 1000000,
+        seq,
+        pcb.pid().data(),
+        basic.ppid().data(),
+        basic.uid(),
+        basic.name(),
+    )
+    .ok();
+    drop(basic);
+    record.write_fmt(args).ok();
+    match result {
+        Ok(_) => record.push_str(" res=success\n"),
+        Err(e) => {
+            write!(record, " res=failed errno={}\n", e.to_posix_errno().abs()).ok();
+        }
+    }
+
+    AUDIT_BUF.lock_irqsave().push(record);
+    AUDIT_WAIT.wakeup_all(None);
+}
+
+/// 处理/dev/audit的ioctl
+///
+/// ## 参数
+///
+/// - `cmd`：[`AUDIT_GET`]或[`AUDIT_SET`]
+/// - `data`：用户空间的参数指针
+///
+/// ## 错误
+///
+/// - `EINVAL`：事件掩码中有不支持的事件
+/// - `ENOTTY`：不支持的命令
+pub fn audit_ioctl(cmd: u32, data: usize) -> Result<usize, SystemError> {
+    match cmd {
+        AUDIT_GET => {
+            // 确保启动参数已经被读取
+            audit_enabled(AuditEvents::empty());
+            let backlog = AUDIT_BUF.lock_irqsave().records.len();
+            let status = AuditStatus {
+                events: AUDIT_EVENTS.load(Ordering::SeqCst),
+                backlog_limit: AUDIT_BACKLOG_LIMIT as u32,
+                backlog: backlog as u32,
+                lost: AUDIT_LOST.load(Ordering::SeqCst) as u32,
+            };
+            UserPtr::<AuditStatus>::new(data).write(&status)?;
+            return Ok(0);
+        }
+        AUDIT_SET => {
+            let events = UserPtr::<u32>::new(data).read()?;
+            let events = AuditEvents::from_bits(events).ok_or(SystemError::EINVAL)?;
+            // 先读取启动参数，避免之后读取时覆盖这里的设置
+            audit_enabled(AuditEvents::empty());
+            AUDIT_EVENTS.store(events.bits(), Ordering::SeqCst);
+            return Ok(0);
+        }
+        _ => return Err(SystemError::ENOTTY),
+    }
+}
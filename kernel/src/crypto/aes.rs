@@ -0,0 +1,267 @@
+//! AES的软件实现(FIPS 197)，支持128、192、256位的密钥
+//!
+//! 这里的实现使用查表的方式计算S盒，查表的时间与数据有关，因此在CPU支持AES-NI时，
+//! 会优先使用aes-aesni实现(见`arch::crypto`)。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/crypto/aes.c
+
+use crate::syscall::SystemError;
+
+use super::Cipher;
+
+pub const AES_BLOCK_SIZE: usize = 16;
+pub const AES_MIN_KEY_SIZE: usize = 16;
+pub const AES_MAX_KEY_SIZE: usize = 32;
+/// 256位密钥的轮数
+const AES_MAX_ROUNDS: usize = 14;
+
+const AES_SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const AES_INV_SBOX: [u8; 256] = [
+    0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb,
+    0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f, 0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb,
+    0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b, 0x42, 0xfa, 0xc3, 0x4e,
+    0x08, 0x2e, 0xa1, 0x66, 0x28, 0xd9, 0x24, 0xb2, 0x76, 0x5b, 0xa2, 0x49, 0x6d, 0x8b, 0xd1, 0x25,
+    0x72, 0xf8, 0xf6, 0x64, 0x86, 0x68, 0x98, 0x16, 0xd4, 0xa4, 0x5c, 0xcc, 0x5d, 0x65, 0xb6, 0x92,
+    0x6c, 0x70, 0x48, 0x50, 0xfd, 0xed, 0xb9, 0xda, 0x5e, 0x15, 0x46, 0x57, 0xa7, 0x8d, 0x9d, 0x84,
+    0x90, 0xd8, 0xab, 0x00, 0x8c, 0xbc, 0xd3, 0x0a, 0xf7, 0xe4, 0x58, 0x05, 0xb8, 0xb3, 0x45, 0x06,
+    0xd0, 0x2c, 0x1e, 0x8f, 0xca, 0x3f, 0x0f, 0x02, 0xc1, 0xaf, 0xbd, 0x03, 0x01, 0x13, 0x8a, 0x6b,
+    0x3a, 0x91, 0x11, 0x41, 0x4f, 0x67, 0xdc, 0xea, 0x97, 0xf2, 0xcf, 0xce, 0xf0, 0xb4, 0xe6, 0x73,
+    0x96, 0xac, 0x74, 0x22, 0xe7, 0xad, 0x35, 0x85, 0xe2, 0xf9, 0x37, 0xe8, 0x1c, 0x75, 0xdf, 0x6e,
+    0x47, 0xf1, 0x1a, 0x71, 0x1d, 0x29, 0xc5, 0x89, 0x6f, 0xb7, 0x62, 0x0e, 0xaa, 0x18, 0xbe, 0x1b,
+    0xfc, 0x56, 0x3e, 0x4b, 0xc6, 0xd2, 0x79, 0x20, 0x9a, 0xdb, 0xc0, 0xfe, 0x78, 0xcd, 0x5a, 0xf4,
+    0x1f, 0xdd, 0xa8, 0x33, 0x88, 0x07, 0xc7, 0x31, 0xb1, 0x12, 0x10, 0x59, 0x27, 0x80, 0xec, 0x5f,
+    0x60, 0x51, 0x7f, 0xa9, 0x19, 0xb5, 0x4a, 0x0d, 0x2d, 0xe5, 0x7a, 0x9f, 0x93, 0xc9, 0x9c, 0xef,
+    0xa0, 0xe0, 0x3b, 0x4d, 0xae, 0x2a, 0xf5, 0xb0, 0xc8, 0xeb, 0xbb, 0x3c, 0x83, 0x53, 0x99, 0x61,
+    0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d,
+];
+
+/// 展开后的AES密钥
+#[derive(Debug, Clone)]
+pub struct AesKey {
+    /// 各轮的轮密钥，第i轮的轮密钥为`round_keys[i * 16..(i + 1) * 16]`
+    round_keys: [u8; AES_BLOCK_SIZE * (AES_MAX_ROUNDS + 1)],
+    /// 轮数：10、12或14
+    rounds: usize,
+}
+
+impl AesKey {
+    /// 展开密钥
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：密钥的长度不是16、24或32字节
+    pub fn expand(key: &[u8]) -> Result<Self, SystemError> {
+        let nk = match key.len() {
+            16 | 24 | 32 => key.len() / 4,
+            _ => return Err(SystemError::EINVAL),
+        };
+        let rounds = nk + 6;
+        let mut rk = [0u8; AES_BLOCK_SIZE * (AES_MAX_ROUNDS + 1)];
+        rk[..key.len()].copy_from_slice(key);
+
+        let mut rcon: u8 = 1;
+        for i in nk..4 * (rounds + 1) {
+            let mut temp = [0u8; 4];
+            temp.copy_from_slice(&rk[(i - 1) * 4..i * 4]);
+            if i % nk == 0 {
+                temp.rotate_left(1);
+                for b in temp.iter_mut() {
+                    *b = AES_SBOX[*b as usize];
+                }
+                temp[0] ^= rcon;
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                for b in temp.iter_mut() {
+                    *b = AES_SBOX[*b as usize];
+                }
+            }
+            for j in 0..4 {
+                rk[i * 4 + j] = rk[(i - nk) * 4 + j] ^ temp[j];
+            }
+        }
+
+        return Ok(Self {
+            round_keys: rk,
+            rounds,
+        });
+    }
+
+    pub fn rounds(&self) -> usize {
+        return self.rounds;
+    }
+
+    /// 第`round`轮的轮密钥
+    pub fn round_key(&self, round: usize) -> &[u8] {
+        return &self.round_keys[round * AES_BLOCK_SIZE..(round + 1) * AES_BLOCK_SIZE];
+    }
+
+    /// 加密一个分组
+    pub fn encrypt(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+        add_round_key(block, self.round_key(0));
+        for round in 1..self.rounds {
+            sub_bytes(block, &AES_SBOX);
+            shift_rows(block);
+            mix_columns(block);
+            add_round_key(block, self.round_key(round));
+        }
+        sub_bytes(block, &AES_SBOX);
+        shift_rows(block);
+        add_round_key(block, self.round_key(self.rounds));
+    }
+
+    /// 解密一个分组
+    pub fn decrypt(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+        add_round_key(block, self.round_key(self.rounds));
+        for round in (1..self.rounds).rev() {
+            inv_shift_rows(block);
+            sub_bytes(block, &AES_INV_SBOX);
+            add_round_key(block, self.round_key(round));
+            inv_mix_columns(block);
+        }
+        inv_shift_rows(block);
+        sub_bytes(block, &AES_INV_SBOX);
+        add_round_key(block, self.round_key(0));
+    }
+}
+
+impl Drop for AesKey {
+    fn drop(&mut self) {
+        self.round_keys.fill(0);
+    }
+}
+
+/// 在GF(2^8)中乘以x
+#[inline(always)]
+fn xtime(b: u8) -> u8 {
+    return (b << 1) ^ (((b >> 7) & 1) * 0x1b);
+}
+
+/// GF(2^8)中的乘法
+#[inline(always)]
+fn gmul(mut a: u8, mut b: u8) -> u8 {
+    let mut p = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            p ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    return p;
+}
+
+#[inline(always)]
+fn add_round_key(state: &mut [u8; AES_BLOCK_SIZE], round_key: &[u8]) {
+    for (s, k) in state.iter_mut().zip(round_key) {
+        *s ^= k;
+    }
+}
+
+#[inline(always)]
+fn sub_bytes(state: &mut [u8; AES_BLOCK_SIZE], sbox: &[u8; 256]) {
+    for s in state.iter_mut() {
+        *s = sbox[*s as usize];
+    }
+}
+
+/// 状态按列存放：第c列第r行的字节为`state[c * 4 + r]`。第r行循环左移r个字节
+#[inline(always)]
+fn shift_rows(state: &mut [u8; AES_BLOCK_SIZE]) {
+    let old = *state;
+    for c in 0..4 {
+        for r in 1..4 {
+            state[c * 4 + r] = old[((c + r) % 4) * 4 + r];
+        }
+    }
+}
+
+#[inline(always)]
+fn inv_shift_rows(state: &mut [u8; AES_BLOCK_SIZE]) {
+    let old = *state;
+    for c in 0..4 {
+        for r in 1..4 {
+            state[((c + r) % 4) * 4 + r] = old[c * 4 + r];
+        }
+    }
+}
+
+#[inline(always)]
+fn mix_columns(state: &mut [u8; AES_BLOCK_SIZE]) {
+    for col in state.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [col[0], col[1], col[2], col[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        col[0] ^= all ^ xtime(a0 ^ a1);
+        col[1] ^= all ^ xtime(a1 ^ a2);
+        col[2] ^= all ^ xtime(a2 ^ a3);
+        col[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+#[inline(always)]
+fn inv_mix_columns(state: &mut [u8; AES_BLOCK_SIZE]) {
+    for col in state.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [col[0], col[1], col[2], col[3]];
+        col[0] = gmul(a0, 14) ^ gmul(a1, 11) ^ gmul(a2, 13) ^ gmul(a3, 9);
+        col[1] = gmul(a0, 9) ^ gmul(a1, 14) ^ gmul(a2, 11) ^ gmul(a3, 13);
+        col[2] = gmul(a0, 13) ^ gmul(a1, 9) ^ gmul(a2, 14) ^ gmul(a3, 11);
+        col[3] = gmul(a0, 11) ^ gmul(a1, 13) ^ gmul(a2, 9) ^ gmul(a3, 14);
+    }
+}
+
+/// 检查分组的长度，并转换为定长数组
+pub fn as_aes_block(block: &mut [u8]) -> Result<&mut [u8; AES_BLOCK_SIZE], SystemError> {
+    return block.try_into().map_err(|_| SystemError::EINVAL);
+}
+
+/// aes-generic：AES的软件实现
+#[derive(Debug, Default)]
+pub struct Aes {
+    key: Option<AesKey>,
+}
+
+impl Aes {
+    pub fn new() -> Self {
+        return Self { key: None };
+    }
+}
+
+impl Cipher for Aes {
+    fn block_size(&self) -> usize {
+        return AES_BLOCK_SIZE;
+    }
+
+    fn set_key(&mut self, key: &[u8]) -> Result<(), SystemError> {
+        self.key = Some(AesKey::expand(key)?);
+        return Ok(());
+    }
+
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), SystemError> {
+        let key = self.key.as_ref().ok_or(SystemError::ENOKEY)?;
+        key.encrypt(as_aes_block(block)?);
+        return Ok(());
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), SystemError> {
+        let key = self.key.as_ref().ok_or(SystemError::ENOKEY)?;
+        key.decrypt(as_aes_block(block)?);
+        return Ok(());
+    }
+}
@@ -0,0 +1,94 @@
+//! HMAC模板(RFC 2104)，可以与任意哈希算法组合，例如`hmac(sha256)`
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/crypto/hmac.c
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::syscall::SystemError;
+
+use super::Hash;
+
+const HMAC_IPAD: u8 = 0x36;
+const HMAC_OPAD: u8 = 0x5c;
+
+#[derive(Debug)]
+pub struct Hmac {
+    /// 计算内层摘要的哈希实例
+    inner: Box<dyn Hash>,
+    /// 计算外层摘要的哈希实例
+    outer: Box<dyn Hash>,
+    /// 密钥与ipad异或的结果，长度为底层哈希的分组大小
+    ipad: Vec<u8>,
+    /// 密钥与opad异或的结果，长度为底层哈希的分组大小
+    opad: Vec<u8>,
+}
+
+impl Hmac {
+    /// 创建HMAC实例，`inner`与`outer`是同一种哈希算法的两个实例。密钥默认为空
+    pub fn new(inner: Box<dyn Hash>, outer: Box<dyn Hash>) -> Self {
+        let block_size = inner.block_size();
+        let mut hmac = Self {
+            inner,
+            outer,
+            ipad: vec![HMAC_IPAD; block_size],
+            opad: vec![HMAC_OPAD; block_size],
+        };
+        hmac.reset();
+        return hmac;
+    }
+}
+
+impl Hash for Hmac {
+    fn digest_size(&self) -> usize {
+        return self.inner.digest_size();
+    }
+
+    fn block_size(&self) -> usize {
+        return self.inner.block_size();
+    }
+
+    /// 设置密钥。密钥比分组长时，使用它的摘要作为密钥
+    fn set_key(&mut self, key: &[u8]) -> Result<(), SystemError> {
+        let block_size = self.block_size();
+        let mut padded = vec![0u8; block_size];
+        if key.len() > block_size {
+            self.inner.digest(key, &mut padded)?;
+        } else {
+            padded[..key.len()].copy_from_slice(key);
+        }
+
+        for i in 0..block_size {
+            self.ipad[i] = padded[i] ^ HMAC_IPAD;
+            self.opad[i] = padded[i] ^ HMAC_OPAD;
+        }
+        padded.fill(0);
+        self.reset();
+        return Ok(());
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.inner.update(&self.ipad);
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    fn finalize(&mut self, out: &mut [u8]) -> Result<(), SystemError> {
+        let digest_size = self.digest_size();
+        if out.len() < digest_size {
+            return Err(SystemError::EINVAL);
+        }
+        let mut inner_digest = vec![0u8; digest_size];
+        self.inner.finalize(&mut inner_digest)?;
+
+        self.outer.reset();
+        self.outer.update(&self.opad);
+        self.outer.update(&inner_digest);
+        self.outer.finalize(out)?;
+
+        self.reset();
+        return Ok(());
+    }
+}
@@ -0,0 +1,254 @@
+//! 内核密码学接口
+//!
+//! 密码学算法按种类抽象为[`Hash`](哈希、带密钥的哈希)与[`Cipher`](分组密码)，算法的实现注册为[`CryptoAlg`]。
+//! 同一个算法可以有多个实现(例如软件实现的aes-generic与使用AES-NI指令的aes-aesni)，
+//! 按算法名称分配时，选择优先级最高的实现；也可以通过实现的驱动名称指定某一个实现。
+//!
+//! 算法名称可以是模板，例如`hmac(sha256)`表示以sha256为底层哈希的HMAC。
+//! 分组密码的工作模式(例如XTS)也通过模板与分组密码组合。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/crypto/api.c
+
+use core::fmt::Debug;
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{libs::rwlock::RwLock, syscall::SystemError};
+
+use self::{aes::Aes, hmac::Hmac, sha256::Sha256, testmgr::crypto_alg_selftest, xts::Xts};
+
+pub mod aes;
+pub mod hmac;
+pub mod sha256;
+mod testmgr;
+pub mod xts;
+
+/// 哈希算法
+pub trait Hash: Send + Sync + Debug {
+    /// 摘要的字节数
+    fn digest_size(&self) -> usize;
+
+    /// 算法内部分组的字节数
+    fn block_size(&self) -> usize;
+
+    /// 设置密钥，只有带密钥的哈希(例如HMAC)支持
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOSYS`：算法不需要密钥
+    /// - `EINVAL`：密钥的长度不合法
+    fn set_key(&mut self, _key: &[u8]) -> Result<(), SystemError> {
+        return Err(SystemError::ENOSYS);
+    }
+
+    /// 丢弃已经输入的数据，重新开始计算
+    fn reset(&mut self);
+
+    /// 输入数据
+    fn update(&mut self, data: &[u8]);
+
+    /// 输出摘要，之后状态被重置，可以开始计算下一个摘要
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：`out`的长度小于摘要的字节数
+    fn finalize(&mut self, out: &mut [u8]) -> Result<(), SystemError>;
+
+    /// 计算一段数据的摘要
+    fn digest(&mut self, data: &[u8], out: &mut [u8]) -> Result<(), SystemError> {
+        self.reset();
+        self.update(data);
+        return self.finalize(out);
+    }
+}
+
+/// 分组密码，每次加密、解密一个分组
+pub trait Cipher: Send + Sync + Debug {
+    /// 分组的字节数
+    fn block_size(&self) -> usize;
+
+    /// 设置密钥
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：密钥的长度不合法
+    fn set_key(&mut self, key: &[u8]) -> Result<(), SystemError>;
+
+    /// 原地加密一个分组
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：`block`的长度不等于分组的字节数
+    /// - `ENOKEY`：还没有设置密钥
+    fn encrypt_block(&self, block: &mut [u8]) -> Result<(), SystemError>;
+
+    /// 原地解密一个分组
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：`block`的长度不等于分组的字节数
+    /// - `ENOKEY`：还没有设置密钥
+    fn decrypt_block(&self, block: &mut [u8]) -> Result<(), SystemError>;
+}
+
+/// 算法的种类，以及创建算法实例的函数
+#[derive(Debug, Clone, Copy)]
+pub enum CryptoAlgType {
+    Hash(fn() -> Box<dyn Hash>),
+    Cipher(fn() -> Box<dyn Cipher>),
+}
+
+/// 一个算法实现
+#[derive(Debug, Clone, Copy)]
+pub struct CryptoAlg {
+    /// 算法名称，例如"aes"
+    pub name: &'static str,
+    /// 实现的名称，例如"aes-aesni"，在所有实现中唯一
+    pub driver_name: &'static str,
+    /// 优先级，按算法名称分配时选择优先级最高的实现
+    pub priority: u32,
+    pub alg_type: CryptoAlgType,
+}
+
+lazy_static! {
+    static ref CRYPTO_ALGS: RwLock<Vec<CryptoAlg>> = RwLock::new(builtin_algs());
+}
+
+/// 内核自带的算法实现
+fn builtin_algs() -> Vec<CryptoAlg> {
+    let mut algs = vec![
+        CryptoAlg {
+            name: "sha256",
+            driver_name: "sha256-generic",
+            priority: 100,
+            alg_type: CryptoAlgType::Hash(|| Box::new(Sha256::new())),
+        },
+        CryptoAlg {
+            name: "aes",
+            driver_name: "aes-generic",
+            priority: 100,
+            alg_type: CryptoAlgType::Cipher(|| Box::new(Aes::new())),
+        },
+    ];
+    algs.extend(crate::arch::crypto::arch_crypto_algs());
+    // 没有通过自检的实现不能使用
+    algs.retain(|alg| crypto_alg_selftest(alg).is_ok());
+    return algs;
+}
+
+/// 初始化内核自带的算法实现，它们在这时完成自检，而不是推迟到第一次被使用时
+pub fn crypto_init() {
+    lazy_static::initialize(&CRYPTO_ALGS);
+}
+
+/// 注册一个算法实现，注册之前先通过已知答案检查实现的正确性
+///
+/// ## 错误
+///
+/// - `EEXIST`：已经有相同驱动名称的实现
+/// - `ELIBBAD`：实现没有通过自检
+pub fn crypto_register_alg(alg: CryptoAlg) -> Result<(), SystemError> {
+    crypto_alg_selftest(&alg)?;
+    let mut algs = CRYPTO_ALGS.write();
+    if algs.iter().any(|a| a.driver_name == alg.driver_name) {
+        return Err(SystemError::EEXIST);
+    }
+    algs.push(alg);
+    return Ok(());
+}
+
+/// 注销一个算法实现
+///
+/// ## 错误
+///
+/// - `ENOENT`：没有这个驱动名称的实现
+pub fn crypto_unregister_alg(driver_name: &str) -> Result<(), SystemError> {
+    let mut algs = CRYPTO_ALGS.write();
+    let idx = algs
+        .iter()
+        .position(|a| a.driver_name == driver_name)
+        .ok_or(SystemError::ENOENT)?;
+    algs.remove(idx);
+    return Ok(());
+}
+
+/// 查找名称或者驱动名称为`name`的实现中，优先级最高的一个
+fn crypto_find_alg(name: &str) -> Option<CryptoAlg> {
+    return CRYPTO_ALGS
+        .read()
+        .iter()
+        .filter(|a| a.driver_name == name || a.name == name)
+        .max_by_key(|a| {
+            // 精确匹配驱动名称的实现总是优先
+            (a.driver_name == name, a.priority)
+        })
+        .copied();
+}
+
+/// 解析`template(alg)`形式的算法名称
+fn parse_template(name: &str) -> Option<(&str, &str)> {
+    let (template, rest) = name.split_once('(')?;
+    let inner = rest.strip_suffix(')')?;
+    return Some((template, inner));
+}
+
+/// 分配一个哈希算法的实例
+///
+/// ## 参数
+///
+/// - `name`：算法名称(例如`sha256`、`hmac(sha256)`)或者驱动名称(例如`sha256-generic`)
+///
+/// ## 错误
+///
+/// - `ENOENT`：没有这个算法
+pub fn crypto_alloc_hash(name: &str) -> Result<Box<dyn Hash>, SystemError> {
+    if let Some((template, inner)) = parse_template(name) {
+        return match template {
+            "hmac" => Ok(Box::new(Hmac::new(
+                crypto_alloc_hash(inner)?,
+                crypto_alloc_hash(inner)?,
+            ))),
+            _ => Err(SystemError::ENOENT),
+        };
+    }
+
+    match crypto_find_alg(name).map(|a| a.alg_type) {
+        Some(CryptoAlgType::Hash(new)) => return Ok(new()),
+        _ => return Err(SystemError::ENOENT),
+    }
+}
+
+/// 分配一个分组密码的实例
+///
+/// ## 参数
+///
+/// - `name`：算法名称(例如`aes`)或者驱动名称(例如`aes-generic`)
+///
+/// ## 错误
+///
+/// - `ENOENT`：没有这个算法
+pub fn crypto_alloc_cipher(name: &str) -> Result<Box<dyn Cipher>, SystemError> {
+    match crypto_find_alg(name).map(|a| a.alg_type) {
+        Some(CryptoAlgType::Cipher(new)) => return Ok(new()),
+        _ => return Err(SystemError::ENOENT),
+    }
+}
+
+/// 分配一个XTS模式的实例
+///
+/// ## 参数
+///
+/// - `name`：`xts(cipher)`形式的名称，例如`xts(aes)`
+///
+/// ## 错误
+///
+/// - `ENOENT`：名称不是XTS模板，或者没有底层的分组密码
+/// - `EINVAL`：底层分组密码的分组大小不是16字节
+pub fn crypto_alloc_xts(name: &str) -> Result<Xts, SystemError> {
+    match parse_template(name) {
+        Some(("xts", inner)) => {
+            return Xts::new(crypto_alloc_cipher(inner)?, crypto_alloc_cipher(inner)?);
+        }
+        _ => return Err(SystemError::ENOENT),
+    }
+}
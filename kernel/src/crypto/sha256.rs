@@ -0,0 +1,172 @@
+//! SHA-256的软件实现(FIPS 180-4)
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/crypto/sha256.c
+
+use crate::syscall::SystemError;
+
+use super::Hash;
+
+pub const SHA256_DIGEST_SIZE: usize = 32;
+pub const SHA256_BLOCK_SIZE: usize = 64;
+
+const SHA256_H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+#[derive(Debug, Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    /// 还没有凑满一个分组的数据
+    buf: [u8; SHA256_BLOCK_SIZE],
+    /// 已经输入的总字节数
+    count: u64,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        return Self {
+            state: SHA256_H0,
+            buf: [0; SHA256_BLOCK_SIZE],
+            count: 0,
+        };
+    }
+
+    /// 计算一段数据的摘要
+    pub fn digest_of(data: &[u8]) -> [u8; SHA256_DIGEST_SIZE] {
+        let mut sha = Self::new();
+        sha.update(data);
+        let mut out = [0u8; SHA256_DIGEST_SIZE];
+        sha.finalize_into(&mut out);
+        return out;
+    }
+
+    /// 输出摘要，之后状态被重置
+    pub fn finalize_into(&mut self, out: &mut [u8; SHA256_DIGEST_SIZE]) {
+        let bit_count = self.count.wrapping_mul(8);
+        // 填充一个0x80，然后填充0直到剩下8个字节，最后是以比特为单位的长度
+        self.update(&[0x80]);
+        while self.count % SHA256_BLOCK_SIZE as u64 != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_count.to_be_bytes());
+
+        for (i, word) in self.state.iter().enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+        }
+        self.reset();
+    }
+
+    fn transform(state: &mut [u32; 8], block: &[u8]) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([
+                block[i * 4],
+                block[i * 4 + 1],
+                block[i * 4 + 2],
+                block[i * 4 + 3],
+            ]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Hash for Sha256 {
+    fn digest_size(&self) -> usize {
+        return SHA256_DIGEST_SIZE;
+    }
+
+    fn block_size(&self) -> usize {
+        return SHA256_BLOCK_SIZE;
+    }
+
+    fn reset(&mut self) {
+        self.state = SHA256_H0;
+        self.buf.fill(0);
+        self.count = 0;
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        let mut partial = (self.count % SHA256_BLOCK_SIZE as u64) as usize;
+        self.count = self.count.wrapping_add(data.len() as u64);
+
+        // 先把缓冲区中不完整的分组凑满
+        if partial != 0 {
+            let n = core::cmp::min(SHA256_BLOCK_SIZE - partial, data.len());
+            self.buf[partial..partial + n].copy_from_slice(&data[..n]);
+            data = &data[n..];
+            partial += n;
+            if partial < SHA256_BLOCK_SIZE {
+                return;
+            }
+            Self::transform(&mut self.state, &self.buf);
+        }
+
+        let mut blocks = data.chunks_exact(SHA256_BLOCK_SIZE);
+        for block in &mut blocks {
+            Self::transform(&mut self.state, block);
+        }
+        let rest = blocks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+    }
+
+    fn finalize(&mut self, out: &mut [u8]) -> Result<(), SystemError> {
+        if out.len() < SHA256_DIGEST_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+        let mut digest = [0u8; SHA256_DIGEST_SIZE];
+        self.finalize_into(&mut digest);
+        out[..SHA256_DIGEST_SIZE].copy_from_slice(&digest);
+        return Ok(());
+    }
+}
@@ -0,0 +1,232 @@
+//! 算法实现的自检
+//!
+//! 注册算法实现之前，用公开标准中的已知答案(known-answer test)检查它的输出。dm-crypt与dm-verity完全依赖
+//! 这些算法的正确性，实现出错时不会报告任何错误，只会悄悄地写坏数据，因此未通过自检的实现不会被注册。
+//!
+//! 同时检查以该实现为底层算法的模板：sha256的实现同时检查`hmac(sha256)`，aes的实现同时检查`xts(aes)`。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/crypto/testmgr.c
+
+use alloc::boxed::Box;
+
+use crate::{kernel_test, kerror, kwarn, syscall::SystemError};
+
+use super::{
+    hmac::Hmac,
+    xts::{Xts, XTS_BLOCK_SIZE},
+    Cipher, CryptoAlg, CryptoAlgType, Hash, CRYPTO_ALGS,
+};
+
+/// 哈希算法的测试向量，`key`为空表示不带密钥
+struct HashTestVec {
+    key: &'static [u8],
+    plaintext: &'static [u8],
+    digest: &'static [u8],
+}
+
+/// 分组密码与XTS模式的测试向量
+struct CipherTestVec {
+    key: &'static [u8],
+    /// XTS模式的初始向量，单个分组的测试中不使用
+    iv: &'static [u8],
+    plaintext: &'static [u8],
+    ciphertext: &'static [u8],
+}
+
+/// FIPS 180-2 附录B
+const SHA256_TV: &[HashTestVec] = &[
+    HashTestVec {
+        key: b"",
+        plaintext: b"",
+        digest: b"\xe3\xb0\xc4\x42\x98\xfc\x1c\x14\x9a\xfb\xf4\xc8\x99\x6f\xb9\x24\
+            \x27\xae\x41\xe4\x64\x9b\x93\x4c\xa4\x95\x99\x1b\x78\x52\xb8\x55",
+    },
+    HashTestVec {
+        key: b"",
+        plaintext: b"abc",
+        digest: b"\xba\x78\x16\xbf\x8f\x01\xcf\xea\x41\x41\x40\xde\x5d\xae\x22\x23\
+            \xb0\x03\x61\xa3\x96\x17\x7a\x9c\xb4\x10\xff\x61\xf2\x00\x15\xad",
+    },
+    HashTestVec {
+        key: b"",
+        plaintext: b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        digest: b"\x24\x8d\x6a\x61\xd2\x06\x38\xb8\xe5\xc0\x26\x93\x0c\x3e\x60\x39\
+            \xa3\x3c\xe4\x59\x64\xff\x21\x67\xf6\xec\xed\xd4\x19\xdb\x06\xc1",
+    },
+];
+
+/// RFC 4231 第4.2、4.3、4.7节
+const HMAC_SHA256_TV: &[HashTestVec] = &[
+    HashTestVec {
+        key: &[0x0b; 20],
+        plaintext: b"Hi There",
+        digest: b"\xb0\x34\x4c\x61\xd8\xdb\x38\x53\x5c\xa8\xaf\xce\xaf\x0b\xf1\x2b\
+            \x88\x1d\xc2\x00\xc9\x83\x3d\xa7\x26\xe9\x37\x6c\x2e\x32\xcf\xf7",
+    },
+    HashTestVec {
+        key: b"Jefe",
+        plaintext: b"what do ya want for nothing?",
+        digest: b"\x5b\xdc\xc1\x46\xbf\x60\x75\x4e\x6a\x04\x24\x26\x08\x95\x75\xc7\
+            \x5a\x00\x3f\x08\x9d\x27\x39\x83\x9d\xec\x58\xb9\x64\xec\x38\x43",
+    },
+    HashTestVec {
+        // 密钥比分组长，使用它的摘要作为密钥
+        key: &[0xaa; 131],
+        plaintext: b"Test Using Larger Than Block-Size Key - Hash Key First",
+        digest: b"\x60\xe4\x31\x59\x1e\xe0\xb6\x7f\x0d\x8a\x26\xaa\xcb\xf5\xb7\x7f\
+            \x8e\x0b\xc6\x21\x37\x28\xc5\x14\x05\x46\x04\x0f\x0e\xe3\x7f\x54",
+    },
+];
+
+/// FIPS-197 附录C，分别使用128、192、256位的密钥
+const AES_TV: &[CipherTestVec] = &[
+    CipherTestVec {
+        key: b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f",
+        iv: b"",
+        plaintext: b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99\xaa\xbb\xcc\xdd\xee\xff",
+        ciphertext: b"\x69\xc4\xe0\xd8\x6a\x7b\x04\x30\xd8\xcd\xb7\x80\x70\xb4\xc5\x5a",
+    },
+    CipherTestVec {
+        key: b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\
+            \x10\x11\x12\x13\x14\x15\x16\x17",
+        iv: b"",
+        plaintext: b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99\xaa\xbb\xcc\xdd\xee\xff",
+        ciphertext: b"\xdd\xa9\x7c\xa4\x86\x4c\xdf\xe0\x6e\xaf\x70\xa0\xec\x0d\x71\x91",
+    },
+    CipherTestVec {
+        key: b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\
+            \x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c\x1d\x1e\x1f",
+        iv: b"",
+        plaintext: b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99\xaa\xbb\xcc\xdd\xee\xff",
+        ciphertext: b"\x8e\xa2\xb7\xca\x51\x67\x45\xbf\xea\xfc\x49\x90\x4b\x49\x60\x89",
+    },
+];
+
+/// IEEE 1619-2007 附录B 向量1、2。初始向量是按小端字节序表示的数据单元序号
+const XTS_AES_TV: &[CipherTestVec] = &[
+    CipherTestVec {
+        key: &[0; 32],
+        iv: &[0; 16],
+        plaintext: &[0; 32],
+        ciphertext: b"\x91\x7c\xf6\x9e\xbd\x68\xb2\xec\x9b\x9f\xe9\xa3\xea\xdd\xa6\x92\
+            \xcd\x43\xd2\xf5\x95\x98\xed\x85\x8c\x02\xc2\x65\x2f\xbf\x92\x2e",
+    },
+    CipherTestVec {
+        key: b"\x11\x11\x11\x11\x11\x11\x11\x11\x11\x11\x11\x11\x11\x11\x11\x11\
+            \x22\x22\x22\x22\x22\x22\x22\x22\x22\x22\x22\x22\x22\x22\x22\x22",
+        iv: b"\x33\x33\x33\x33\x33\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00",
+        plaintext: &[0x44; 32],
+        ciphertext: b"\xc4\x54\x18\x5e\x6a\x16\x93\x6e\x39\x33\x40\x38\xac\xef\x83\x8b\
+            \xfb\x18\x6f\xff\x74\x80\xad\xc4\x28\x93\x82\xec\xd6\xd3\x94\xf0",
+    },
+];
+
+/// 用已知答案检查一个算法实现
+///
+/// ## 错误
+///
+/// - `ELIBBAD`：实现的输出与测试向量不符
+pub fn crypto_alg_selftest(alg: &CryptoAlg) -> Result<(), SystemError> {
+    return match (alg.name, alg.alg_type) {
+        ("sha256", CryptoAlgType::Hash(new)) => {
+            test_hash(alg, "hash", new(), SHA256_TV).and_then(|_| {
+                let hmac = Box::new(Hmac::new(new(), new()));
+                test_hash(alg, "hmac", hmac, HMAC_SHA256_TV)
+            })
+        }
+        ("aes", CryptoAlgType::Cipher(new)) => test_cipher(alg, new(), AES_TV)
+            .and_then(|_| test_xts(alg, Xts::new(new(), new())?, XTS_AES_TV)),
+        _ => {
+            kwarn!("crypto: no test for {} ({})", alg.name, alg.driver_name);
+            Ok(())
+        }
+    };
+}
+
+/// 报告一个没有通过的测试向量
+fn test_failed(alg: &CryptoAlg, what: &str, idx: usize) -> SystemError {
+    kerror!(
+        "crypto: {} ({}): {} test vector {} failed",
+        alg.name,
+        alg.driver_name,
+        what,
+        idx
+    );
+    return SystemError::ELIBBAD;
+}
+
+fn test_hash(
+    alg: &CryptoAlg,
+    what: &str,
+    mut hash: Box<dyn Hash>,
+    vecs: &[HashTestVec],
+) -> Result<(), SystemError> {
+    let mut out = vec![0u8; hash.digest_size()];
+    for (i, v) in vecs.iter().enumerate() {
+        if !v.key.is_empty() {
+            hash.set_key(v.key)?;
+        }
+        hash.digest(v.plaintext, &mut out)?;
+        if out != v.digest {
+            return Err(test_failed(alg, what, i));
+        }
+
+        // 分两次输入，检查跨越分组边界时保存的中间状态
+        let (a, b) = v.plaintext.split_at(v.plaintext.len() / 2);
+        hash.reset();
+        hash.update(a);
+        hash.update(b);
+        hash.finalize(&mut out)?;
+        if out != v.digest {
+            return Err(test_failed(alg, what, i));
+        }
+    }
+    return Ok(());
+}
+
+fn test_cipher(
+    alg: &CryptoAlg,
+    mut cipher: Box<dyn Cipher>,
+    vecs: &[CipherTestVec],
+) -> Result<(), SystemError> {
+    for (i, v) in vecs.iter().enumerate() {
+        cipher.set_key(v.key)?;
+        let mut block = v.plaintext.to_vec();
+        cipher.encrypt_block(&mut block)?;
+        if block != v.ciphertext {
+            return Err(test_failed(alg, "encryption", i));
+        }
+        cipher.decrypt_block(&mut block)?;
+        if block != v.plaintext {
+            return Err(test_failed(alg, "decryption", i));
+        }
+    }
+    return Ok(());
+}
+
+fn test_xts(alg: &CryptoAlg, mut xts: Xts, vecs: &[CipherTestVec]) -> Result<(), SystemError> {
+    for (i, v) in vecs.iter().enumerate() {
+        xts.set_key(v.key)?;
+        let iv: &[u8; XTS_BLOCK_SIZE] = v.iv.try_into().map_err(|_| SystemError::EINVAL)?;
+        let mut buf = v.plaintext.to_vec();
+        xts.encrypt(iv, &mut buf)?;
+        if buf != v.ciphertext {
+            return Err(test_failed(alg, "xts encryption", i));
+        }
+        xts.decrypt(iv, &mut buf)?;
+        if buf != v.plaintext {
+            return Err(test_failed(alg, "xts decryption", i));
+        }
+    }
+    return Ok(());
+}
+
+/// 重新检查所有已注册的算法实现
+fn crypto_selftest() -> Result<(), SystemError> {
+    let algs = CRYPTO_ALGS.read().clone();
+    for alg in algs.iter() {
+        crypto_alg_selftest(alg)?;
+    }
+    return Ok(());
+}
+kernel_test!(crypto_selftest);
@@ -0,0 +1,116 @@
+//! XTS模式(IEEE 1619)，用于磁盘加密
+//!
+//! 密钥分为两半：后一半加密初始向量得到tweak，前一半加密数据。每个分组在加密前后都与tweak异或，
+//! 处理完一个分组后tweak在GF(2^128)中乘以x。因此相同的数据位于不同的扇区(初始向量不同)时，密文也不同。
+//!
+//! 这里只支持长度为分组大小整数倍的数据，不实现密文窃取，磁盘扇区总是满足这个条件。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/crypto/xts.c
+
+use alloc::boxed::Box;
+
+use crate::syscall::SystemError;
+
+use super::Cipher;
+
+/// XTS只能与分组大小为16字节的分组密码组合
+pub const XTS_BLOCK_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub struct Xts {
+    /// 加密数据的分组密码
+    data: Box<dyn Cipher>,
+    /// 加密初始向量的分组密码
+    tweak: Box<dyn Cipher>,
+}
+
+impl Xts {
+    /// 创建XTS实例，`data`与`tweak`是同一种分组密码的两个实例
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：分组密码的分组大小不是16字节
+    pub fn new(data: Box<dyn Cipher>, tweak: Box<dyn Cipher>) -> Result<Self, SystemError> {
+        if data.block_size() != XTS_BLOCK_SIZE || tweak.block_size() != XTS_BLOCK_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Self { data, tweak });
+    }
+
+    /// 设置密钥，密钥的长度是底层分组密码密钥长度的两倍
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：密钥的长度不合法
+    pub fn set_key(&mut self, key: &[u8]) -> Result<(), SystemError> {
+        if key.len() % 2 != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let (data_key, tweak_key) = key.split_at(key.len() / 2);
+        self.data.set_key(data_key)?;
+        self.tweak.set_key(tweak_key)?;
+        return Ok(());
+    }
+
+    /// 原地加密一段数据
+    ///
+    /// ## 参数
+    ///
+    /// - `iv`：初始向量，磁盘加密中由扇区号生成
+    /// - `buf`：要加密的数据，长度必须是16字节的整数倍
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：数据的长度不是16字节的整数倍
+    /// - `ENOKEY`：还没有设置密钥
+    pub fn encrypt(&self, iv: &[u8; XTS_BLOCK_SIZE], buf: &mut [u8]) -> Result<(), SystemError> {
+        return self.crypt(iv, buf, true);
+    }
+
+    /// 原地解密一段数据，参数与错误同[`Xts::encrypt`]
+    pub fn decrypt(&self, iv: &[u8; XTS_BLOCK_SIZE], buf: &mut [u8]) -> Result<(), SystemError> {
+        return self.crypt(iv, buf, false);
+    }
+
+    fn crypt(
+        &self,
+        iv: &[u8; XTS_BLOCK_SIZE],
+        buf: &mut [u8],
+        encrypt: bool,
+    ) -> Result<(), SystemError> {
+        if buf.len() % XTS_BLOCK_SIZE != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut t = *iv;
+        self.tweak.encrypt_block(&mut t)?;
+        for block in buf.chunks_exact_mut(XTS_BLOCK_SIZE) {
+            xor_block(block, &t);
+            if encrypt {
+                self.data.encrypt_block(block)?;
+            } else {
+                self.data.decrypt_block(block)?;
+            }
+            xor_block(block, &t);
+            gf128mul_x_ble(&mut t);
+        }
+        t.fill(0);
+        return Ok(());
+    }
+}
+
+#[inline(always)]
+fn xor_block(block: &mut [u8], t: &[u8; XTS_BLOCK_SIZE]) {
+    for (b, t) in block.iter_mut().zip(t) {
+        *b ^= t;
+    }
+}
+
+/// 在GF(2^128)中乘以x，按小端字节序表示，即IEEE 1619中的tweak更新
+fn gf128mul_x_ble(t: &mut [u8; XTS_BLOCK_SIZE]) {
+    let carry = t[XTS_BLOCK_SIZE - 1] >> 7;
+    for i in (1..XTS_BLOCK_SIZE).rev() {
+        t[i] = (t[i] << 1) | (t[i - 1] >> 7);
+    }
+    t[0] = (t[0] << 1) ^ (carry * 0x87);
+}
@@ -0,0 +1,87 @@
+//! 内核符号表
+//!
+//! 符号表在链接内核之后，由debug/kallsyms.c根据`nm -n -C`的输出生成，再重新链接进内核。
+//! 其中只包含代码段中的符号，按照地址升序排列，符号名是还原(demangle)之后的名字。
+
+use core::ffi::CStr;
+
+// 第一次链接内核时符号表还没有生成，因此使用弱符号，符号不存在时地址为0
+extern "C" {
+    #[linkage = "extern_weak"]
+    static kallsyms_address: *const u64;
+    #[linkage = "extern_weak"]
+    static kallsyms_num: *const u64;
+    #[linkage = "extern_weak"]
+    static kallsyms_names_index: *const u64;
+    #[linkage = "extern_weak"]
+    static kallsyms_names: *const u8;
+}
+
+/// 符号表中的地址数组、名字的偏移量数组，以及名字所在的字符串区
+fn kallsyms() -> Option<(&'static [u64], &'static [u64], *const u8)> {
+    unsafe {
+        if kallsyms_num.is_null() || kallsyms_address.is_null() {
+            return None;
+        }
+        let num = *kallsyms_num as usize;
+        return Some((
+            core::slice::from_raw_parts(kallsyms_address, num),
+            core::slice::from_raw_parts(kallsyms_names_index, num),
+            kallsyms_names,
+        ));
+    }
+}
+
+/// 获取第index个符号的名字
+fn symbol_name(names_index: &[u64], names: *const u8, index: usize) -> &'static str {
+    let name = unsafe { CStr::from_ptr(names.add(names_index[index] as usize) as *const _) };
+    return name.to_str().unwrap_or("<invalid>");
+}
+
+/// 去掉Rust符号名末尾的哈希值，例如`foo::bar::h0123456789abcdef`中的`::h0123456789abcdef`
+pub fn strip_symbol_hash(name: &str) -> &str {
+    if let Some((prefix, hash)) = name.rsplit_once("::h") {
+        if hash.len() == 16 && hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return prefix;
+        }
+    }
+    return name;
+}
+
+/// 在符号表中查找地址所在的函数
+///
+/// ## 返回值
+///
+/// 函数名，以及地址相对于函数起始地址的偏移量。符号表不存在或者地址不在任何函数中时返回None
+pub fn lookup_symbol(addr: usize) -> Option<(&'static str, usize)> {
+    let (addresses, names_index, names) = kallsyms()?;
+
+    // 找到起始地址不大于addr的最后一个符号
+    let index = addresses
+        .partition_point(|&a| a as usize <= addr)
+        .checked_sub(1)?;
+    // 最后一个符号是_etext，地址在它之后说明不在代码段中
+    if index == addresses.len() - 1 {
+        return None;
+    }
+
+    return Some((
+        symbol_name(names_index, names, index),
+        addr - addresses[index] as usize,
+    ));
+}
+
+/// 根据函数名查找函数的起始地址
+///
+/// ## 参数
+///
+/// - `name`：函数名。Rust函数可以省略末尾的哈希值，同名的函数有多个时返回地址最小的一个
+pub fn lookup_name(name: &str) -> Option<usize> {
+    let (addresses, names_index, names) = kallsyms()?;
+    return (0..addresses.len())
+        .find(|&i| {
+            let sym = symbol_name(names_index, names, i);
+            sym == name || strip_symbol_hash(sym) == name
+        })
+        .map(|i| addresses[i] as usize);
+}
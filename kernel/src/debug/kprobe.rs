@@ -0,0 +1,183 @@
+//! kprobe：在运行时跟踪内核函数的调用
+//!
+//! 在白名单中的内核函数的入口处放置断点，每次函数被调用时，把函数名和参数记录到跟踪缓冲区中，
+//! 不需要重新编译内核就能观察驱动的行为，例如排查AHCI命令没有完成的问题。
+//!
+//! 通过/sys/kernel/tracing/kprobe_events控制，每行一条命令：
+//!
+//! ```text
+//! cd /sys/kernel/tracing
+//! echo "p <dragonos_kernel::driver::disk::ahci::ahcidisk::LockedAhciDisk as dragonos_kernel::driver::base::block::block_device::BlockDevice>::read_at" > kprobe_events
+//! echo "- <dragonos_kernel::driver::disk::ahci::ahcidisk::LockedAhciDisk as dragonos_kernel::driver::base::block::block_device::BlockDevice>::read_at" > kprobe_events
+//! cat trace
+//! ```
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/kprobes.c
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::{collections::BTreeMap, string::String};
+
+use crate::{
+    arch::{
+        interrupt::TrapFrame,
+        kprobe::{kprobe_args, kprobe_resume, text_poke, BREAKPOINT_INSN, FUNC_ENTRY_INSN},
+    },
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+};
+
+use super::{
+    kallsyms::{lookup_name, lookup_symbol, strip_symbol_hash},
+    trace_buf::trace_buf_alloc,
+    trace_event::trace_kprobe,
+};
+
+/// 允许放置探测点的模块。
+/// 断点处理、日志、内存分配等代码本身不能被探测，否则命中探测点时会递归或者死锁
+const KPROBE_WHITELIST: &[&str] = &[
+    "dragonos_kernel::driver::base::block::",
+    "dragonos_kernel::driver::disk::",
+    "dragonos_kernel::driver::usb::",
+    "dragonos_kernel::driver::virtio::",
+    "dragonos_kernel::filesystem::fat::",
+    "dragonos_kernel::filesystem::vfs::",
+];
+
+/// 一个探测点
+#[derive(Debug)]
+struct Kprobe {
+    /// 函数名(不含哈希值)
+    name: String,
+    /// 被断点替换掉的指令
+    saved_insn: u8,
+    /// 命中次数
+    hits: AtomicU64,
+}
+
+/// 所有的探测点，以函数入口地址为键
+static KPROBES: SpinLock<BTreeMap<usize, Kprobe>> = SpinLock::new(BTreeMap::new());
+
+/// 判断函数是否允许被探测
+fn kprobe_allowed(name: &str) -> bool {
+    // trait的实现的名字形如`<a::B as c::D>::f`
+    let name = name.trim_start_matches('<');
+    return KPROBE_WHITELIST
+        .iter()
+        .any(|prefix| name.starts_with(prefix));
+}
+
+/// 在函数入口处放置探测点
+pub fn register_kprobe(symbol: &str) -> Result<(), SystemError> {
+    if !kprobe_allowed(symbol) {
+        return Err(SystemError::EPERM);
+    }
+    let addr = lookup_name(symbol).ok_or(SystemError::ENOENT)?;
+    let name = lookup_symbol(addr)
+        .map(|(n, _)| strip_symbol_hash(n))
+        .unwrap_or(symbol);
+
+    trace_buf_alloc();
+
+    let mut kprobes = KPROBES.lock_irqsave();
+    if kprobes.contains_key(&addr) {
+        return Err(SystemError::EEXIST);
+    }
+    let insn = unsafe { core::ptr::read_volatile(addr as *const u8) };
+    // 只支持以push rbp开头的函数，其他指令无法由跳板代码执行
+    if insn != FUNC_ENTRY_INSN {
+        return Err(SystemError::EINVAL);
+    }
+
+    kprobes.insert(
+        addr,
+        Kprobe {
+            name: String::from(name),
+            saved_insn: insn,
+            hits: AtomicU64::new(0),
+        },
+    );
+    unsafe { text_poke(addr, BREAKPOINT_INSN) };
+    return Ok(());
+}
+
+/// 移除函数入口处的探测点
+pub fn unregister_kprobe(symbol: &str) -> Result<(), SystemError> {
+    let mut kprobes = KPROBES.lock_irqsave();
+    let addr = kprobes
+        .iter()
+        .find(|(_, kp)| kp.name == symbol)
+        .map(|(addr, _)| *addr)
+        .or_else(|| lookup_name(symbol).filter(|addr| kprobes.contains_key(addr)))
+        .ok_or(SystemError::ENOENT)?;
+    let kp = kprobes.remove(&addr).unwrap();
+    unsafe { text_poke(addr, kp.saved_insn) };
+    return Ok(());
+}
+
+/// 处理断点异常
+///
+/// ## 参数
+///
+/// - `addr`：断点指令的地址
+///
+/// ## 返回值
+///
+/// 断点是否由kprobe处理
+pub fn kprobe_handler(frame: &mut TrapFrame, addr: usize) -> bool {
+    let kprobes = KPROBES.lock_irqsave();
+    match kprobes.get(&addr) {
+        Some(kp) => {
+            kp.hits.fetch_add(1, Ordering::Relaxed);
+            drop(kprobes);
+            trace_kprobe(addr, kprobe_args(frame));
+            kprobe_resume(frame, addr);
+            return true;
+        }
+        None => {
+            drop(kprobes);
+            // 探测点可能恰好在断点异常发生之后被移除，此时原来的指令已经恢复，重新执行即可
+            if unsafe { core::ptr::read_volatile(addr as *const u8) } != BREAKPOINT_INSN {
+                frame.rip = addr as u64;
+                return true;
+            }
+            return false;
+        }
+    }
+}
+
+/// 生成kprobe_events文件的内容：每行一个探测点，以及它的命中次数
+pub fn kprobe_events_show() -> String {
+    let mut s = String::new();
+    for (addr, kp) in KPROBES.lock_irqsave().iter() {
+        writeln!(
+            s,
+            "p {} @ {:#018x} hits={}",
+            kp.name,
+            addr,
+            kp.hits.load(Ordering::Relaxed)
+        )
+        .ok();
+    }
+    return s;
+}
+
+/// 写入kprobe_events文件，每行一条命令：`p <函数名>`放置探测点，`- <函数名>`移除探测点
+pub fn kprobe_events_write(buf: &[u8]) -> Result<usize, SystemError> {
+    let cmds = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+    for cmd in cmds.lines() {
+        let cmd = cmd.trim();
+        if cmd.is_empty() || cmd.starts_with('#') {
+            continue;
+        }
+        match cmd.split_once(char::is_whitespace) {
+            Some(("p", symbol)) => register_kprobe(symbol.trim())?,
+            Some(("-", symbol)) => unregister_kprobe(symbol.trim())?,
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
+    return Ok(buf.len());
+}
@@ -0,0 +1,9 @@
+//! 内核调试相关的功能
+
+pub mod kallsyms;
+pub mod kprobe;
+pub mod panic;
+pub mod trace_buf;
+pub mod trace_event;
+pub mod traceback;
+pub mod tracefs;
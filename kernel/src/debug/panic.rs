@@ -0,0 +1,207 @@
+//! 内核panic的处理
+//!
+//! panic时输出panic信息与调用栈，然后根据启动参数决定接下来的行为：
+//!
+//! - `panic=halt`或`panic=0`：停机
+//! - `panic=N`(N>0)：N秒之后重启；N<0时立即重启
+//! - 没有指定时：结束当前进程
+//!
+//! 启动参数`panic_output=serial`使panic信息直接写到串口，不经过控制台。
+//! 适用于控制台本身出错，或者panic时控制台的锁正被持有的情况。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/panic.c
+
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, AtomicI64, AtomicU8, Ordering},
+};
+
+use crate::{
+    arch::{
+        cpu::{cpu_halt, cpu_reset},
+        CurrentIrqArch,
+    },
+    driver::tty::serial::serial8250::send_to_default_serial8250_port,
+    exception::InterruptArch,
+    init::cmdline::ParamValue,
+    kernel_param,
+    libs::{
+        log_buf::{log_store, LogLevel},
+        printk::PrintkWriter,
+    },
+    process::ProcessManager,
+    time::hrtimer::ktime_get,
+};
+
+use super::traceback::dump_stack;
+
+/// panic之后的行为
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum PanicAction {
+    /// 结束当前进程
+    Exit = 0,
+    Halt = 1,
+    /// 等待[`PANIC_TIMEOUT`]秒之后重启
+    Reboot = 2,
+}
+
+static PANIC_ACTION: AtomicU8 = AtomicU8::new(PanicAction::Exit as u8);
+/// 重启之前等待的秒数
+static PANIC_TIMEOUT: AtomicI64 = AtomicI64::new(0);
+/// panic信息是否直接写到串口
+static PANIC_TO_SERIAL: AtomicBool = AtomicBool::new(false);
+/// 是否正在处理panic，用于检测处理panic时再次发生panic
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// 启动参数`panic=`的值
+#[derive(Debug, Clone, Copy)]
+enum PanicParam {
+    Halt,
+    /// 等待指定的秒数之后重启
+    Reboot(i64),
+}
+
+impl ParamValue for PanicParam {
+    fn parse_param(value: &str) -> Option<Self> {
+        match value {
+            "halt" | "0" => Some(Self::Halt),
+            "reboot" => Some(Self::Reboot(0)),
+            v => v.parse().ok().map(Self::Reboot),
+        }
+    }
+}
+
+/// 启动参数`panic_output=`的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PanicOutput {
+    Serial,
+    Console,
+}
+
+impl ParamValue for PanicOutput {
+    fn parse_param(value: &str) -> Option<Self> {
+        match value {
+            "serial" => Some(Self::Serial),
+            "console" => Some(Self::Console),
+            _ => None,
+        }
+    }
+}
+
+kernel_param!(PANIC_PARAM: PanicParam = "panic");
+kernel_param!(PANIC_OUTPUT_PARAM: PanicOutput = "panic_output");
+
+/// 解析启动参数中的`panic=`和`panic_output=`
+///
+/// panic时内存分配器可能已经不可用，因此需要在启动时提前解析
+#[no_mangle]
+pub extern "C" fn rs_panic_init() {
+    match PANIC_PARAM.get() {
+        Some(PanicParam::Halt) => PANIC_ACTION.store(PanicAction::Halt as u8, Ordering::SeqCst),
+        Some(PanicParam::Reboot(secs)) => {
+            PANIC_TIMEOUT.store(secs, Ordering::SeqCst);
+            PANIC_ACTION.store(PanicAction::Reboot as u8, Ordering::SeqCst);
+        }
+        None => {}
+    }
+
+    if let Some(output) = PANIC_OUTPUT_PARAM.get() {
+        PANIC_TO_SERIAL.store(output == PanicOutput::Serial, Ordering::SeqCst);
+    }
+}
+
+/// 输出panic信息。内容总会被记录到日志缓冲区中
+struct PanicWriter {
+    to_serial: bool,
+}
+
+impl fmt::Write for PanicWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if !self.to_serial {
+            return PrintkWriter.write_str(s);
+        }
+
+        log_store(LogLevel::EMERG, false, s);
+        for line in s.split_inclusive('\n') {
+            match line.strip_suffix('\n') {
+                Some(l) => {
+                    send_to_default_serial8250_port(l.as_bytes());
+                    send_to_default_serial8250_port(b"\r\n");
+                }
+                None => send_to_default_serial8250_port(line.as_bytes()),
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// 忙等待指定的秒数。此时中断已经关闭，只能通过读取时间戳计数器计时
+fn panic_delay(secs: u64) {
+    let start = ktime_get();
+    // 时间戳计数器还没有校准时无法计时，直接返回
+    if start == 0 {
+        return;
+    }
+    while ktime_get() - start < secs * 1_000_000_000 {
+        core::hint::spin_loop();
+    }
+}
+
+/// 处理内核panic
+pub fn kernel_panic(info: &PanicInfo) -> ! {
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+
+    // 处理panic的过程中再次panic，说明输出信息的代码本身出了问题，只在串口上留下记录
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        send_to_default_serial8250_port(b"\r\nKernel Panic while panicking, halt.\r\n");
+        cpu_halt();
+    }
+
+    let mut w = PanicWriter {
+        to_serial: PANIC_TO_SERIAL.load(Ordering::SeqCst),
+    };
+    writeln!(w, "Kernel Panic Occurred.").ok();
+
+    match info.location() {
+        Some(loc) => writeln!(
+            w,
+            "Location:\n\tFile: {}\n\tLine: {}, Column: {}",
+            loc.file(),
+            loc.line(),
+            loc.column()
+        ),
+        None => writeln!(w, "No location info"),
+    }
+    .ok();
+
+    match info.message() {
+        Some(msg) => writeln!(w, "Message:\n\t{}", msg),
+        None => writeln!(w, "No panic message."),
+    }
+    .ok();
+
+    dump_stack(&mut w);
+
+    let action = PANIC_ACTION.load(Ordering::SeqCst);
+    if action == PanicAction::Halt as u8 {
+        writeln!(w, "System halted.").ok();
+        cpu_halt();
+    }
+    if action == PanicAction::Reboot as u8 {
+        let timeout = PANIC_TIMEOUT.load(Ordering::SeqCst);
+        if timeout > 0 {
+            writeln!(w, "Rebooting in {} seconds..", timeout).ok();
+            panic_delay(timeout as u64);
+        }
+        writeln!(w, "Rebooting.").ok();
+        cpu_reset();
+    }
+
+    writeln!(w, "Current PCB:\n\t{:?}", *(ProcessManager::current_pcb())).ok();
+    // 只结束当前进程时系统还会继续运行，之后的panic需要被正常处理
+    PANICKING.store(false, Ordering::SeqCst);
+    drop(irq_guard);
+    ProcessManager::exit(usize::MAX);
+}
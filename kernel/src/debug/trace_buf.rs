@@ -0,0 +1,252 @@
+//! 跟踪缓冲区
+//!
+//! 跟踪事件与kprobe产生的记录保存在这里，与内核日志分开，不会输出到控制台。
+//!
+//! 每个cpu有一个独立的环形缓冲区，记录的大小是固定的，参数在读取时才被格式化为文本。
+//! 写入记录时不需要分配内存，因此可以在中断处理函数、调度器等上下文中调用。
+//! 缓冲区满时覆盖最早的记录。
+//!
+//! 缓冲区在第一次启用跟踪事件(或者放置kprobe)时才分配，只为已经启动的cpu分配。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/trace/ring_buffer.c
+
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    libs::spinlock::SpinLock,
+    mm::percpu::PerCpu,
+    process::ProcessManager,
+    smp::core::{smp_cpu_count, smp_get_processor_id},
+    time::hrtimer::ktime_get,
+};
+
+use super::trace_event::TraceEvent;
+
+/// 每个cpu的缓冲区最多保存的记录数量
+const TRACE_BUF_ENTRIES: usize = 2048;
+/// 记录中附带的字符串的最大长度，超出的部分被截断
+pub const TRACE_TEXT_LEN: usize = 24;
+
+/// 一条跟踪记录
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEntry {
+    /// 自启动以来的纳秒数
+    pub ts_ns: u64,
+    /// 产生记录时正在运行的进程
+    pub pid: usize,
+    pub event: &'static TraceEvent,
+    /// 产生记录的指令地址，没有意义时为0
+    pub ip: usize,
+    /// 事件的参数，含义由事件自己定义
+    pub args: [u64; 6],
+    text: [u8; TRACE_TEXT_LEN],
+    text_len: u8,
+}
+
+impl TraceEntry {
+    /// 记录中附带的字符串，例如设备名
+    pub fn text(&self) -> &str {
+        return core::str::from_utf8(&self.text[..self.text_len as usize]).unwrap_or("");
+    }
+}
+
+/// 一个cpu的缓冲区
+#[derive(Debug)]
+struct TraceRing {
+    entries: VecDeque<TraceEntry>,
+    /// 被覆盖的记录数量
+    overrun: u64,
+}
+
+impl TraceRing {
+    const fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            overrun: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn allocated(&self) -> bool {
+        return self.entries.capacity() != 0;
+    }
+}
+
+const TRACE_RING_INIT: SpinLock<TraceRing> = SpinLock::new(TraceRing::new());
+static TRACE_BUFS: [SpinLock<TraceRing>; PerCpu::MAX_CPU_NUM] =
+    [TRACE_RING_INIT; PerCpu::MAX_CPU_NUM];
+
+/// 全局的跟踪开关，关闭时所有的记录都被丢弃，已有的记录保持不变
+static TRACING_ON: AtomicBool = AtomicBool::new(true);
+
+#[inline(always)]
+pub fn tracing_on() -> bool {
+    return TRACING_ON.load(Ordering::Relaxed);
+}
+
+pub fn set_tracing_on(on: bool) {
+    TRACING_ON.store(on, Ordering::SeqCst);
+}
+
+/// 为已经启动的cpu分配缓冲区。已经分配过的不会重复分配
+///
+/// 会分配内存，不能在中断上下文中调用
+pub fn trace_buf_alloc() {
+    for cpu in 0..smp_cpu_count().min(PerCpu::MAX_CPU_NUM) {
+        if TRACE_BUFS[cpu].lock_irqsave().allocated() {
+            continue;
+        }
+        // 分配内存时不能持有关中断的锁
+        let entries = VecDeque::with_capacity(TRACE_BUF_ENTRIES);
+        let mut ring = TRACE_BUFS[cpu].lock_irqsave();
+        if !ring.allocated() {
+            ring.entries = entries;
+        }
+    }
+}
+
+/// 向当前cpu的缓冲区写入一条记录。可能在中断或者异常上下文中调用
+///
+/// ## 参数
+///
+/// - `event`：产生记录的事件
+/// - `ip`：产生记录的指令地址
+/// - `args`：事件的参数
+/// - `text`：附带的字符串，超出[`TRACE_TEXT_LEN`]的部分被截断
+pub fn trace_buf_record(event: &'static TraceEvent, ip: usize, args: [u64; 6], text: &str) {
+    if !tracing_on() {
+        return;
+    }
+
+    let mut len = text.len().min(TRACE_TEXT_LEN);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    let mut entry = TraceEntry {
+        ts_ns: ktime_get(),
+        pid: ProcessManager::current_pcb().pid().data(),
+        event,
+        ip,
+        args,
+        text: [0; TRACE_TEXT_LEN],
+        text_len: len as u8,
+    };
+    entry.text[..len].copy_from_slice(&text.as_bytes()[..len]);
+
+    let cpu = smp_get_processor_id() as usize % PerCpu::MAX_CPU_NUM;
+    let mut ring = TRACE_BUFS[cpu].lock_irqsave();
+    if !ring.allocated() {
+        return;
+    }
+    if ring.entries.len() >= TRACE_BUF_ENTRIES {
+        ring.entries.pop_front();
+        ring.overrun += 1;
+    }
+    ring.entries.push_back(entry);
+}
+
+/// 把一条记录格式化为一行文本
+fn trace_format(w: &mut dyn Write, cpu: usize, e: &TraceEntry) -> fmt::Result {
+    let us = e.ts_ns / 1000;
+    write!(
+        w,
+        "{:>8} [{:03}] {:5}.{:06}: {}: ",
+        e.pid,
+        cpu,
+        us / 1000000,
+        us % 1000000,
+        e.event.name()
+    )?;
+    e.event.print(e, w)?;
+    return writeln!(w);
+}
+
+/// 生成trace文件的内容：所有cpu上的记录，按时间顺序排列
+pub fn trace_show() -> String {
+    let mut all: Vec<(usize, TraceEntry)> = Vec::new();
+    let mut overrun = 0;
+    for cpu in 0..PerCpu::MAX_CPU_NUM {
+        let ring = TRACE_BUFS[cpu].lock_irqsave();
+        if !ring.allocated() {
+            continue;
+        }
+        overrun += ring.overrun;
+        all.extend(ring.entries.iter().map(|e| (cpu, *e)));
+    }
+    all.sort_by_key(|(_, e)| e.ts_ns);
+
+    let mut s = String::new();
+    writeln!(
+        s,
+        "# entries-in-buffer: {}, overrun: {}",
+        all.len(),
+        overrun
+    )
+    .ok();
+    writeln!(s, "#").ok();
+    writeln!(s, "#      PID   CPU    TIMESTAMP  EVENT").ok();
+    for (cpu, e) in all.iter() {
+        trace_format(&mut s, *cpu, e).ok();
+    }
+    return s;
+}
+
+/// 取出所有cpu上最早的一条记录
+fn trace_consume() -> Option<(usize, TraceEntry)> {
+    let cpu = (0..PerCpu::MAX_CPU_NUM)
+        .filter_map(|cpu| {
+            let ring = TRACE_BUFS[cpu].lock_irqsave();
+            ring.entries.front().map(|e| (cpu, e.ts_ns))
+        })
+        .min_by_key(|(_, ts)| *ts)?
+        .0;
+    let e = TRACE_BUFS[cpu].lock_irqsave().entries.pop_front()?;
+    return Some((cpu, e));
+}
+
+/// 读取trace_pipe：按时间顺序取出记录，被读取的记录从缓冲区中删除
+///
+/// 缓冲区为空时返回0，不会等待新的记录
+///
+/// ## 返回值
+///
+/// 写入`buf`的字节数。只返回完整的行，缓冲区放不下一行时截断这一行
+pub fn trace_pipe_read(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    let mut line = String::new();
+    while let Some((cpu, e)) = trace_consume() {
+        line.clear();
+        trace_format(&mut line, cpu, &e).ok();
+        if len + line.len() > buf.len() {
+            if len == 0 {
+                let n = buf.len();
+                buf.copy_from_slice(&line.as_bytes()[..n]);
+                return n;
+            }
+            // 放回缓冲区，留到下一次读取。期间缓冲区又被写满时只能丢弃
+            let mut ring = TRACE_BUFS[cpu].lock_irqsave();
+            if ring.entries.len() < TRACE_BUF_ENTRIES {
+                ring.entries.push_front(e);
+            } else {
+                ring.overrun += 1;
+            }
+            break;
+        }
+        buf[len..len + line.len()].copy_from_slice(line.as_bytes());
+        len += line.len();
+    }
+    return len;
+}
+
+/// 清空所有cpu的缓冲区
+pub fn trace_clear() {
+    for ring in TRACE_BUFS.iter() {
+        let mut ring = ring.lock_irqsave();
+        ring.entries.clear();
+        ring.overrun = 0;
+    }
+}
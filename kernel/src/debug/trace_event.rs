@@ -0,0 +1,285 @@
+//! 静态跟踪事件
+//!
+//! 在内核中固定的位置(调度、中断处理、块设备读写)放置跟踪点。事件默认是关闭的，
+//! 关闭时跟踪点只需要读取一个原子变量，开销可以忽略。启用之后，每次经过跟踪点时
+//! 都会向当前cpu的跟踪缓冲区写入一条记录。
+//!
+//! 事件通过/sys/kernel/tracing下的文件控制，见[`super::tracefs`]。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/tracepoint.h
+
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    exception::irqdesc::IrqNumber,
+    process::{ProcessControlBlock, ProcessState},
+    syscall::SystemError,
+};
+
+use super::{
+    kallsyms::{lookup_symbol, strip_symbol_hash},
+    trace_buf::{trace_buf_alloc, trace_buf_record, TraceEntry},
+};
+
+/// 一个跟踪事件
+pub struct TraceEvent {
+    /// 事件所属的子系统，例如`sched`
+    system: &'static str,
+    name: &'static str,
+    enabled: AtomicBool,
+    /// 把记录中的参数格式化为文本
+    print: fn(&TraceEntry, &mut dyn Write) -> fmt::Result,
+}
+
+impl core::fmt::Debug for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.system, self.name)
+    }
+}
+
+impl TraceEvent {
+    const fn new(
+        system: &'static str,
+        name: &'static str,
+        enabled: bool,
+        print: fn(&TraceEntry, &mut dyn Write) -> fmt::Result,
+    ) -> Self {
+        Self {
+            system,
+            name,
+            enabled: AtomicBool::new(enabled),
+            print,
+        }
+    }
+
+    pub fn system(&self) -> &'static str {
+        self.system
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline(always)]
+    pub fn enabled(&self) -> bool {
+        return self.enabled.load(Ordering::Relaxed);
+    }
+
+    /// 启用或者关闭事件。启用时会分配跟踪缓冲区，不能在中断上下文中调用
+    pub fn set_enabled(&self, enabled: bool) {
+        if enabled {
+            trace_buf_alloc();
+        }
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn print(&self, entry: &TraceEntry, w: &mut dyn Write) -> fmt::Result {
+        return (self.print)(entry, w);
+    }
+
+    #[inline(always)]
+    fn record(&'static self, ip: usize, args: [u64; 6], text: &str) {
+        trace_buf_record(self, ip, args, text);
+    }
+}
+
+pub static SCHED_SWITCH: TraceEvent = TraceEvent::new("sched", "sched_switch", false, |e, w| {
+    write!(
+        w,
+        "prev_pid={} prev_state={} ==> next_pid={}",
+        e.args[0], e.args[1] as u8 as char, e.args[2]
+    )
+});
+
+pub static IRQ_HANDLER_ENTRY: TraceEvent =
+    TraceEvent::new("irq", "irq_handler_entry", false, |e, w| {
+        write!(w, "irq={} name={}", e.args[0], e.text())
+    });
+
+pub static IRQ_HANDLER_EXIT: TraceEvent =
+    TraceEvent::new("irq", "irq_handler_exit", false, |e, w| {
+        let ret = if e.args[1] != 0 {
+            "handled"
+        } else {
+            "unhandled"
+        };
+        write!(w, "irq={} ret={}", e.args[0], ret)
+    });
+
+pub static BLOCK_RQ_ISSUE: TraceEvent =
+    TraceEvent::new("block", "block_rq_issue", false, |e, w| {
+        write!(
+            w,
+            "dev={} rwbs={} sector={} nr_sector={}",
+            e.text(),
+            if e.args[2] != 0 { "W" } else { "R" },
+            e.args[0],
+            e.args[1]
+        )
+    });
+
+pub static BLOCK_RQ_COMPLETE: TraceEvent =
+    TraceEvent::new("block", "block_rq_complete", false, |e, w| {
+        write!(
+            w,
+            "dev={} rwbs={} sector={} nr_sector={} error={}",
+            e.text(),
+            if e.args[2] != 0 { "W" } else { "R" },
+            e.args[0],
+            e.args[1],
+            e.args[3] as i64
+        )
+    });
+
+/// kprobe命中时的记录。探测点需要单独放置，因此这个事件默认是启用的
+pub static KPROBE: TraceEvent = TraceEvent::new("kprobes", "kprobe", true, |e, w| {
+    match lookup_symbol(e.ip) {
+        Some((name, _)) => write!(w, "{}", strip_symbol_hash(name))?,
+        None => write!(w, "{:#x}", e.ip)?,
+    }
+    write!(
+        w,
+        ": ({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x})",
+        e.args[0], e.args[1], e.args[2], e.args[3], e.args[4], e.args[5]
+    )
+});
+
+/// 所有的跟踪事件
+pub static TRACE_EVENTS: [&TraceEvent; 6] = [
+    &SCHED_SWITCH,
+    &IRQ_HANDLER_ENTRY,
+    &IRQ_HANDLER_EXIT,
+    &BLOCK_RQ_ISSUE,
+    &BLOCK_RQ_COMPLETE,
+    &KPROBE,
+];
+
+/// 根据名字查找事件
+///
+/// ## 参数
+///
+/// - `spec`：`<子系统>:<事件>`，两者都可以是`*`；也可以只写事件名
+pub fn find_trace_events(spec: &str) -> Vec<&'static TraceEvent> {
+    let (system, name) = match spec.split_once(':') {
+        Some((system, name)) => (system, name),
+        None => ("*", spec),
+    };
+    return TRACE_EVENTS
+        .iter()
+        .filter(|e| (system == "*" || e.system == system) && (name == "*" || e.name == name))
+        .copied()
+        .collect();
+}
+
+/// 进程切换时调用，`prev`是即将让出cpu的进程
+#[inline(always)]
+pub fn trace_sched_switch(prev: &Arc<ProcessControlBlock>, next: &Arc<ProcessControlBlock>) {
+    if !SCHED_SWITCH.enabled() {
+        return;
+    }
+    let state = match prev.sched_info().state() {
+        ProcessState::Runnable => b'R',
+        ProcessState::Blocked(true) => b'S',
+        ProcessState::Blocked(false) => b'D',
+        ProcessState::Stopped => b'T',
+        ProcessState::Exited(_) => b'X',
+    };
+    SCHED_SWITCH.record(
+        0,
+        [
+            prev.pid().data() as u64,
+            state as u64,
+            next.pid().data() as u64,
+            0,
+            0,
+            0,
+        ],
+        "",
+    );
+}
+
+/// 调用中断处理函数之前调用
+///
+/// ## 参数
+///
+/// - `name`：中断处理函数注册时使用的名字
+#[inline(always)]
+pub fn trace_irq_handler_entry(irq: IrqNumber, name: &str) {
+    if IRQ_HANDLER_ENTRY.enabled() {
+        IRQ_HANDLER_ENTRY.record(0, [irq as u64, 0, 0, 0, 0, 0], name);
+    }
+}
+
+/// 中断处理函数返回之后调用
+#[inline(always)]
+pub fn trace_irq_handler_exit(irq: IrqNumber, handled: bool) {
+    if IRQ_HANDLER_EXIT.enabled() {
+        IRQ_HANDLER_EXIT.record(0, [irq as u64, handled as u64, 0, 0, 0, 0], "");
+    }
+}
+
+/// 向块设备发出读写命令时调用
+///
+/// ## 参数
+///
+/// - `dev`：设备名
+/// - `lba`：起始扇区号
+/// - `count`：扇区数量
+/// - `write`：是否为写命令
+#[inline(always)]
+pub fn trace_block_rq_issue(dev: &str, lba: usize, count: usize, write: bool) {
+    if BLOCK_RQ_ISSUE.enabled() {
+        BLOCK_RQ_ISSUE.record(0, [lba as u64, count as u64, write as u64, 0, 0, 0], dev);
+    }
+}
+
+/// 块设备的读写命令完成时调用，参数与[`trace_block_rq_issue`]相同
+///
+/// ## 参数
+///
+/// - `ret`：命令的执行结果
+#[inline(always)]
+pub fn trace_block_rq_complete(
+    dev: &str,
+    lba: usize,
+    count: usize,
+    write: bool,
+    ret: &Result<(), SystemError>,
+) {
+    if BLOCK_RQ_COMPLETE.enabled() {
+        let error = match ret {
+            Ok(_) => 0,
+            Err(e) => e.to_posix_errno(),
+        };
+        BLOCK_RQ_COMPLETE.record(
+            0,
+            [
+                lba as u64,
+                count as u64,
+                write as u64,
+                error as i64 as u64,
+                0,
+                0,
+            ],
+            dev,
+        );
+    }
+}
+
+/// kprobe命中时调用
+///
+/// ## 参数
+///
+/// - `addr`：探测点所在的函数的入口地址
+/// - `args`：函数的参数
+#[inline(always)]
+pub fn trace_kprobe(addr: usize, args: [u64; 6]) {
+    if KPROBE.enabled() {
+        KPROBE.record(addr, args, "");
+    }
+}
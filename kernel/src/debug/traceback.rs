@@ -0,0 +1,77 @@
+//! 内核调用栈回溯
+//!
+//! 内核编译时保留了帧指针，每个栈帧的开头依次保存着上一个栈帧的rbp和返回地址，因此沿着rbp链就能得到调用栈。
+//! 返回地址通过内核符号表转换为函数名。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/dumpstack.c
+
+use core::fmt;
+
+use crate::{
+    arch::{cpu::current_frame_pointer, MMArch},
+    mm::MemoryManagementArch,
+    process::KernelStack,
+};
+
+use super::kallsyms::lookup_symbol;
+
+/// 最多回溯的栈帧数量
+const BACKTRACE_MAX_DEPTH: usize = 32;
+
+/// 输出一个栈帧
+fn print_frame(w: &mut dyn fmt::Write, addr: usize) {
+    match lookup_symbol(addr) {
+        Some((name, offset)) => writeln!(w, " [<{:#018x}>] {}+{:#x}", addr, name, offset),
+        None => writeln!(w, " [<{:#018x}>] ?", addr),
+    }
+    .ok();
+}
+
+/// 沿着rbp链回溯调用栈
+///
+/// ## 参数
+///
+/// - `w`：输出的位置
+/// - `rip`：出错时的指令地址，作为第一个栈帧输出。从当前函数开始回溯时为None
+/// - `rbp`：开始回溯的栈帧
+pub fn print_backtrace(w: &mut dyn fmt::Write, rip: Option<usize>, mut rbp: usize) {
+    writeln!(w, "Call Trace:").ok();
+    if let Some(rip) = rip {
+        print_frame(w, rip);
+    }
+
+    for _ in 0..BACKTRACE_MAX_DEPTH {
+        // 栈帧必须在内核空间中并且对齐。rbp链被破坏时，宁可少输出几层，也不要在回溯时再次出错
+        if rbp < MMArch::PHYS_OFFSET || rbp % core::mem::size_of::<usize>() != 0 {
+            break;
+        }
+        let frame = rbp as *const usize;
+        let (next_rbp, ret_addr) = unsafe { (*frame, *frame.add(1)) };
+        if ret_addr == 0 {
+            break;
+        }
+        print_frame(w, ret_addr);
+
+        // 栈向低地址增长，上一层的栈帧一定在更高的地址，并且在同一个内核栈中
+        if next_rbp <= rbp || next_rbp - rbp >= KernelStack::SIZE {
+            break;
+        }
+        rbp = next_rbp;
+    }
+}
+
+/// 从调用者开始回溯当前的调用栈
+#[inline(always)]
+pub fn dump_stack(w: &mut dyn fmt::Write) {
+    print_backtrace(w, None, current_frame_pointer());
+}
+
+/// 供C代码在异常处理函数中回溯出错时的调用栈
+#[no_mangle]
+pub extern "C" fn rs_traceback(rip: u64, rbp: u64) {
+    print_backtrace(
+        &mut crate::libs::printk::PrintkWriter,
+        Some(rip as usize),
+        rbp as usize,
+    );
+}
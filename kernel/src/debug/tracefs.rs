@@ -0,0 +1,244 @@
+//! 跟踪功能的控制接口，位于/sys/kernel/tracing
+//!
+//! - `tracing_on`：全局的跟踪开关，写入0或1
+//! - `trace`：按时间顺序列出所有cpu上的记录；写入任意内容则清空缓冲区
+//! - `trace_pipe`：与`trace`相同，但是被读取的记录会从缓冲区中删除。没有记录时读到文件末尾，不会等待
+//! - `available_events`：所有的事件，每行一个`<子系统>:<事件>`
+//! - `set_event`：读取时列出已启用的事件；写入`<子系统>:<事件>`启用事件，前面加`!`则关闭事件
+//! - `events/<子系统>/<事件>/enable`：写入0或1关闭或者启用单个事件
+//! - `kprobe_events`：放置或者移除kprobe，见[`super::kprobe`]
+//!
+//! ```text
+//! cd /sys/kernel/tracing
+//! echo sched:sched_switch > set_event
+//! echo 1 > events/block/block_rq_issue/enable
+//! cat trace
+//! ```
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/trace/trace.c
+
+use core::fmt::Write;
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
+
+use crate::{
+    filesystem::{
+        kernfs::{
+            callback::{KernCallbackData, KernFSCallback, KernInodePrivateData},
+            KernFSInode,
+        },
+        sysfs::sysfs_instance,
+        vfs::{syscall::ModeType, PollStatus},
+    },
+    kinfo,
+    syscall::SystemError,
+};
+
+use super::{
+    kprobe::{kprobe_events_show, kprobe_events_write},
+    trace_buf::{set_tracing_on, trace_clear, trace_pipe_read, trace_show, tracing_on},
+    trace_event::{find_trace_events, TraceEvent, TRACE_EVENTS},
+};
+
+/// /sys/kernel/tracing下的文件
+#[derive(Debug)]
+pub enum TraceFsFile {
+    TracingOn,
+    Trace,
+    TracePipe,
+    AvailableEvents,
+    SetEvent,
+    EventEnable(&'static TraceEvent),
+    KprobeEvents,
+}
+
+/// 从`content`的`offset`处开始复制到`buf`中
+fn read_from_string(content: &str, buf: &mut [u8], offset: usize) -> usize {
+    let content = content.as_bytes();
+    if offset >= content.len() {
+        return 0;
+    }
+    let len = buf.len().min(content.len() - offset);
+    buf[..len].copy_from_slice(&content[offset..offset + len]);
+    return len;
+}
+
+/// 解析写入开关文件的内容
+fn parse_bool(buf: &[u8]) -> Result<bool, SystemError> {
+    match core::str::from_utf8(buf)
+        .map_err(|_| SystemError::EINVAL)?
+        .trim()
+    {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+impl TraceFsFile {
+    pub fn callback_read(&self, buf: &mut [u8], offset: usize) -> Result<usize, SystemError> {
+        let content = match self {
+            TraceFsFile::TracingOn => format!("{}\n", tracing_on() as u8),
+            TraceFsFile::Trace => trace_show(),
+            TraceFsFile::TracePipe => return Ok(trace_pipe_read(buf)),
+            TraceFsFile::AvailableEvents => {
+                let mut s = String::new();
+                for e in TRACE_EVENTS.iter() {
+                    writeln!(s, "{}:{}", e.system(), e.name()).ok();
+                }
+                s
+            }
+            TraceFsFile::SetEvent => {
+                let mut s = String::new();
+                for e in TRACE_EVENTS.iter().filter(|e| e.enabled()) {
+                    writeln!(s, "{}:{}", e.system(), e.name()).ok();
+                }
+                s
+            }
+            TraceFsFile::EventEnable(event) => format!("{}\n", event.enabled() as u8),
+            TraceFsFile::KprobeEvents => kprobe_events_show(),
+        };
+        return Ok(read_from_string(&content, buf, offset));
+    }
+
+    pub fn callback_write(&self, buf: &[u8], _offset: usize) -> Result<usize, SystemError> {
+        match self {
+            TraceFsFile::TracingOn => set_tracing_on(parse_bool(buf)?),
+            TraceFsFile::Trace => trace_clear(),
+            TraceFsFile::TracePipe | TraceFsFile::AvailableEvents => {
+                return Err(SystemError::EPERM)
+            }
+            TraceFsFile::SetEvent => {
+                let specs = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+                for spec in specs.split_whitespace() {
+                    let (enable, spec) = match spec.strip_prefix('!') {
+                        Some(spec) => (false, spec),
+                        None => (true, spec),
+                    };
+                    let events = find_trace_events(spec);
+                    if events.is_empty() {
+                        return Err(SystemError::EINVAL);
+                    }
+                    events.iter().for_each(|e| e.set_enabled(enable));
+                }
+            }
+            TraceFsFile::EventEnable(event) => event.set_enabled(parse_bool(buf)?),
+            TraceFsFile::KprobeEvents => return kprobe_events_write(buf),
+        }
+        return Ok(buf.len());
+    }
+}
+
+#[derive(Debug)]
+struct TraceFsCallback;
+
+impl KernFSCallback for TraceFsCallback {
+    fn open(&self, _data: KernCallbackData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read(
+        &self,
+        data: KernCallbackData,
+        buf: &mut [u8],
+        offset: usize,
+    ) -> Result<usize, SystemError> {
+        return data.callback_read(buf, offset);
+    }
+
+    fn write(
+        &self,
+        data: KernCallbackData,
+        buf: &[u8],
+        offset: usize,
+    ) -> Result<usize, SystemError> {
+        return data.callback_write(buf, offset);
+    }
+
+    #[inline]
+    fn poll(&self, _data: KernCallbackData) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+}
+
+static TRACEFS_CALLBACK: TraceFsCallback = TraceFsCallback;
+
+/// 在`parent`下创建一个文件
+///
+/// ## 参数
+///
+/// - `size`：文件大小。内容长度不固定的文件需要指定一个足够大的值，否则读到文件末尾之后无法继续读取
+fn tracefs_add_file(
+    parent: &Arc<KernFSInode>,
+    name: &str,
+    mode: u32,
+    size: Option<usize>,
+    file: TraceFsFile,
+) -> Result<Arc<KernFSInode>, SystemError> {
+    return parent.add_file(
+        name.to_string(),
+        ModeType::from_bits_truncate(mode),
+        size,
+        Some(KernInodePrivateData::TraceFS(file)),
+        Some(&TRACEFS_CALLBACK),
+    );
+}
+
+/// 创建/sys/kernel/tracing
+///
+/// 作为sysfs的一部分而不是单独挂载，迁移根文件系统时会随sysfs一起迁移
+pub fn tracefs_init() -> Result<(), SystemError> {
+    let dir_mode = ModeType::from_bits_truncate(0o755);
+    let root = sysfs_instance().root_inode();
+    let kernel = root.add_dir("kernel".to_string(), dir_mode, None, None)?;
+    let tracing = kernel.add_dir("tracing".to_string(), dir_mode, None, None)?;
+
+    let unbounded = Some(i64::MAX as usize);
+    tracefs_add_file(&tracing, "tracing_on", 0o644, None, TraceFsFile::TracingOn)?;
+    tracefs_add_file(&tracing, "trace", 0o644, unbounded, TraceFsFile::Trace)?;
+    tracefs_add_file(
+        &tracing,
+        "trace_pipe",
+        0o444,
+        unbounded,
+        TraceFsFile::TracePipe,
+    )?;
+    tracefs_add_file(
+        &tracing,
+        "available_events",
+        0o444,
+        None,
+        TraceFsFile::AvailableEvents,
+    )?;
+    tracefs_add_file(&tracing, "set_event", 0o644, None, TraceFsFile::SetEvent)?;
+    tracefs_add_file(
+        &tracing,
+        "kprobe_events",
+        0o644,
+        None,
+        TraceFsFile::KprobeEvents,
+    )?;
+
+    let events = tracing.add_dir("events".to_string(), dir_mode, None, None)?;
+    let mut systems: BTreeMap<&str, Arc<KernFSInode>> = BTreeMap::new();
+    for event in TRACE_EVENTS.iter().copied() {
+        let system = match systems.get(event.system()) {
+            Some(system) => system.clone(),
+            None => {
+                let system = events.add_dir(event.system().to_string(), dir_mode, None, None)?;
+                systems.insert(event.system(), system.clone());
+                system
+            }
+        };
+        let dir = system.add_dir(event.name().to_string(), dir_mode, None, None)?;
+        tracefs_add_file(&dir, "enable", 0o644, None, TraceFsFile::EventEnable(event))?;
+    }
+
+    kinfo!("Tracing interface created at /sys/kernel/tracing");
+    return Ok(());
+}
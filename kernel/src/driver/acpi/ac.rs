@@ -0,0 +1,75 @@
+//! ACPI交流适配器(ACPI0003)
+//!
+//! 适配器是否接入来自`_PSR`，只有`_PSR`能够被求值时才有数据，参见[`super::aml`]。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/ac.c
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    driver::power_supply::{
+        power_supply_register, PowerSupply, PowerSupplyProperty, PowerSupplyType, PowerSupplyValue,
+    },
+    kinfo,
+    syscall::SystemError,
+};
+
+use super::{acpi_manager, aml::AmlDevice};
+
+/// 交流适配器的硬件ID
+const ACPI_AC_HID: &str = "ACPI0003";
+
+static AC_PROPS: [PowerSupplyProperty; 1] = [PowerSupplyProperty::Online];
+
+/// ACPI交流适配器
+#[derive(Debug)]
+struct AcpiAc {
+    device: AmlDevice,
+}
+
+impl PowerSupply for AcpiAc {
+    fn name(&self) -> String {
+        self.device.name()
+    }
+
+    fn supply_type(&self) -> PowerSupplyType {
+        PowerSupplyType::Mains
+    }
+
+    fn properties(&self) -> &'static [PowerSupplyProperty] {
+        &AC_PROPS
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/ac.c?fi=get_ac_property#get_ac_property
+    fn get_property(&self, prop: PowerSupplyProperty) -> Result<PowerSupplyValue, SystemError> {
+        match prop {
+            PowerSupplyProperty::Online => {
+                let psr = acpi_manager().aml_evaluate(&self.device, b"_PSR")?;
+                let online = psr.as_integer().ok_or(SystemError::EINVAL)?;
+                return Ok(PowerSupplyValue::Int((online != 0) as i64));
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// 查找DSDT中的交流适配器，为它们注册电源
+///
+/// ## 错误
+///
+/// - `ENODEV`：没有找到交流适配器
+pub fn acpi_ac_init() -> Result<(), SystemError> {
+    let devices = acpi_manager().aml_find_devices(ACPI_AC_HID)?;
+    if devices.is_empty() {
+        return Err(SystemError::ENODEV);
+    }
+
+    let mut registered: Vec<String> = Vec::new();
+    for device in devices {
+        let ac = AcpiAc { device };
+        registered.push(ac.name());
+        power_supply_register(Arc::new(ac), None)?;
+    }
+    kinfo!("ACPI: AC adapter {:?} registered", registered);
+    return Ok(());
+}
@@ -6,6 +6,7 @@
 #include <mm/mmio.h>
 
 extern void rs_acpi_init(uint64_t rsdp_paddr);
+extern const uint8_t *rs_boot_info_acpi_rsdp();
 
 #define acpi_get_RSDT_entry_vaddr(phys_addr) (acpi_description_header_base + (phys_addr)-acpi_RSDT_entry_phys_base) // 获取RSDT entry的虚拟地址
 // #define acpi_get_XSDT_entry_vaddr(phys_addr) (ACPI_DESCRIPTION_HEDERS_BASE + (phys_addr)-acpi_XSDT_entry_phys_base) // 获取XSDT entry的虚拟地址
@@ -16,7 +17,6 @@ static struct acpi_RSDT_Structure_t *rsdt;
 static struct acpi_XSDT_Structure_t *xsdt;
 
 static struct multiboot_tag_old_acpi_t old_acpi;
-static struct multiboot_tag_new_acpi_t new_acpi;
 
 static ul acpi_RSDT_offset = 0;
 static ul acpi_XSDT_offset = 0;
@@ -106,42 +106,23 @@ bool acpi_get_HPET(const struct acpi_system_description_table_header_t *_iter_da
     return true;
 }
 
-/**
- * @brief 获取MCFG MCFG_description_table
- *
- * @param _iter_data 要被迭代的信息的结构体
- * @param _data 返回的MCFG表的虚拟地址
- * @return true
- * @return false
- */
-bool acpi_get_MCFG(const struct acpi_system_description_table_header_t *_iter_data, void *_data)
-{
-    if (!(_iter_data->Signature[0] == 'M' && _iter_data->Signature[1] == 'C' && _iter_data->Signature[2] == 'F' && _iter_data->Signature[3] == 'G'))
-        return false;
-    *(ul *)_data = (ul)_iter_data;
-    return true;
-}
-
 /**
  * @brief 初始化acpi模块
  *
  */
-// todo: 修复bug：当物理机上提供了rsdpv2之后，rsdpv1是不提供的（物理地址为0），因此需要手动判断rsdp的版本信息，然后做对应的解析。
 void acpi_init()
 {
     kinfo("Initializing ACPI...");
 
-    // 获取物理地址
-    int reserved;
-
-    multiboot2_iter(multiboot2_get_acpi_old_RSDP, &old_acpi, &reserved);
+    // 从启动信息中获取RSDP。UEFI下引导程序通常只提供ACPI 2.0的RSDP，它的开头与ACPI 1.0的RSDP相同
+    const uint8_t *rsdp = rs_boot_info_acpi_rsdp();
+    if (rsdp != NULL)
+        memcpy(&old_acpi.rsdp, rsdp, sizeof(struct acpi_RSDP_t));
     rsdpv1 = &(old_acpi.rsdp);
 
-    // 这里有bug：当multiboot2不存在rsdpv2的时候，会导致错误
-    // multiboot2_iter(multiboot2_get_acpi_new_RSDP, &new_acpi, &reserved);
-    // rsdpv2 = &(new_acpi.rsdp);
+    // C代码暂时只使用RSDT，XSDT由Rust代码根据ACPI 2.0的RSDP解析
     rsdpv2 = NULL;
-    rs_acpi_init((uint64_t)rsdpv1);
+    rs_acpi_init((uint64_t)rsdp);
 
     uint64_t paddr = 0;
     // An ACPI-compatible OS must use the XSDT if present
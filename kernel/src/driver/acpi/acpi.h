@@ -193,14 +193,5 @@ bool acpi_get_MADT(const struct acpi_system_description_table_header_t *_iter_da
  */
 bool acpi_get_HPET(const struct acpi_system_description_table_header_t *_iter_data, void *_data);
 
-/**
- * @brief 获取MCFG MCFG_description_table
- *
- * @param _iter_data 要被迭代的信息的结构体
- * @param _data 返回的MCFG表的虚拟地址
- * @return true
- * @return false
- */
-bool acpi_get_MCFG(const struct acpi_system_description_table_header_t *_iter_data, void *_data);
 // 初始化acpi模块
 void acpi_init();
\ No newline at end of file
@@ -0,0 +1,382 @@
+//! 在DSDT的AML字节码中查找设备与对象
+//!
+//! 内核目前没有AML解释器，这里只是按照AML的编码规则扫描字节码：
+//! - 通过`Device(...)`的PkgLength确定设备的范围，再根据设备的`_HID`查找设备
+//! - 只能读取常量对象：`Name(XXXX, <常量>)`，或者方法体只有`Return(<常量>)`的方法
+//! - 常量可以是整数、字符串，或者由它们组成的Package
+//!
+//! 需要访问硬件(例如嵌入式控制器)的方法无法求值，此时返回`EOPNOTSUPP_OR_ENOTSUP`。
+//! 通过`Scope(...)`在设备之外为它定义的对象也找不到。
+//!
+//! 参考 https://uefi.org/specs/ACPI/6.5/20_AML_Specification.html
+
+use core::ops::Range;
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{libs::spinlock::SpinLock, syscall::SystemError};
+
+use super::{AcpiHandlerImpl, AcpiManager};
+
+use acpi::AcpiHandler;
+
+/// AML操作码
+pub(super) const AML_ZERO_OP: u8 = 0x00;
+pub(super) const AML_ONE_OP: u8 = 0x01;
+pub(super) const AML_NAME_OP: u8 = 0x08;
+pub(super) const AML_BYTE_PREFIX: u8 = 0x0a;
+pub(super) const AML_WORD_PREFIX: u8 = 0x0b;
+pub(super) const AML_DWORD_PREFIX: u8 = 0x0c;
+pub(super) const AML_STRING_PREFIX: u8 = 0x0d;
+pub(super) const AML_QWORD_PREFIX: u8 = 0x0e;
+pub(super) const AML_PACKAGE_OP: u8 = 0x12;
+pub(super) const AML_METHOD_OP: u8 = 0x14;
+pub(super) const AML_DUAL_NAME_PREFIX: u8 = 0x2e;
+pub(super) const AML_MULTI_NAME_PREFIX: u8 = 0x2f;
+pub(super) const AML_EXT_OP_PREFIX: u8 = 0x5b;
+pub(super) const AML_ROOT_CHAR: u8 = b'\\';
+pub(super) const AML_PARENT_PREFIX_CHAR: u8 = b'^';
+pub(super) const AML_RETURN_OP: u8 = 0xa4;
+pub(super) const AML_ONES_OP: u8 = 0xff;
+/// 跟在`AML_EXT_OP_PREFIX`之后
+pub(super) const AML_DEVICE_OP: u8 = 0x82;
+
+/// 映射之后的DSDT，第一次使用时映射，之后不再取消映射
+static DSDT_AML: SpinLock<Option<&'static [u8]>> = SpinLock::new(None);
+
+/// AML中的常量
+#[derive(Debug, Clone)]
+pub enum AmlValue {
+    Integer(u64),
+    String(String),
+    Package(Vec<AmlValue>),
+}
+
+impl AmlValue {
+    pub fn as_integer(&self) -> Option<u64> {
+        match self {
+            AmlValue::Integer(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AmlValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_package(&self) -> Option<&[AmlValue]> {
+        match self {
+            AmlValue::Package(p) => Some(p),
+            _ => None,
+        }
+    }
+}
+
+/// DSDT中的一个设备
+#[derive(Debug, Clone)]
+pub struct AmlDevice {
+    /// 设备名的最后一段，例如`\_SB.PCI0.BAT0`的`BAT0`
+    name: [u8; 4],
+    /// 设备的TermList在DSDT中的范围
+    body: Range<usize>,
+}
+
+impl AmlDevice {
+    /// 设备名，去掉了末尾用于补齐的`_`
+    pub fn name(&self) -> String {
+        let name = String::from_utf8_lossy(&self.name);
+        return String::from(name.trim_end_matches('_'));
+    }
+}
+
+impl AcpiManager {
+    /// 获取DSDT的字节码
+    pub(super) fn dsdt_aml(&self) -> Result<&'static [u8], SystemError> {
+        let mut guard = DSDT_AML.lock();
+        if let Some(aml) = *guard {
+            return Ok(aml);
+        }
+
+        let tables = self.tables().ok_or(SystemError::ENODEV)?;
+        let dsdt = tables.dsdt().map_err(|_| SystemError::ENODEV)?;
+        let mapping = unsafe {
+            AcpiHandlerImpl.map_physical_region::<u8>(dsdt.address, dsdt.length as usize)
+        };
+        let aml = unsafe {
+            core::slice::from_raw_parts(mapping.virtual_start().as_ptr(), dsdt.length as usize)
+        };
+        // DSDT在内核运行期间一直有效，不取消映射
+        core::mem::forget(mapping);
+
+        *guard = Some(aml);
+        return Ok(aml);
+    }
+
+    /// 查找DSDT中`_HID`为指定值的设备
+    ///
+    /// ## 参数
+    ///
+    /// - `hid`: 设备的硬件ID，例如`PNP0C0A`
+    pub fn aml_find_devices(&self, hid: &str) -> Result<Vec<AmlDevice>, SystemError> {
+        let aml = self.dsdt_aml()?;
+        let mut devices = Vec::new();
+        let mut i = 0;
+        while i + 1 < aml.len() {
+            if aml[i] == AML_EXT_OP_PREFIX && aml[i + 1] == AML_DEVICE_OP {
+                // 设备中还可能有子设备，所以找到之后继续扫描设备内部
+                if let Some(dev) = parse_device(aml, i, aml.len()) {
+                    let matched = find_object(aml, &dev.body, b"_HID")
+                        .and_then(|obj| evaluate_object(aml, obj).ok())
+                        .map_or(false, |v| hid_matches(&v, hid));
+                    if matched {
+                        devices.push(dev);
+                    }
+                }
+                i += 2;
+                continue;
+            }
+            i += 1;
+        }
+        return Ok(devices);
+    }
+
+    /// 对设备的对象求值
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`: 设备
+    /// - `name`: 对象名，例如`_BST`
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOENT`：设备中没有这个对象
+    /// - `EOPNOTSUPP_OR_ENOTSUP`：对象不是常量，需要AML解释器才能求值
+    pub fn aml_evaluate(&self, dev: &AmlDevice, name: &[u8; 4]) -> Result<AmlValue, SystemError> {
+        let aml = self.dsdt_aml()?;
+        let obj = find_object(aml, &dev.body, name).ok_or(SystemError::ENOENT)?;
+        return evaluate_object(aml, obj);
+    }
+}
+
+/// 设备中定义的对象
+#[derive(Debug, Clone, Copy)]
+enum AmlObject {
+    /// `Name`定义的对象，值从这个位置开始
+    Name(usize),
+    /// 方法，方法体从这个位置开始
+    Method(usize),
+}
+
+/// 解析PkgLength
+///
+/// ## 返回值
+///
+/// (包的长度(包括PkgLength本身), PkgLength占用的字节数)
+fn parse_pkg_length(aml: &[u8], off: usize) -> Option<(usize, usize)> {
+    let lead = *aml.get(off)?;
+    // 首字节的高2位表示后面还有几个字节
+    let count = (lead >> 6) as usize;
+    if count == 0 {
+        return Some(((lead & 0x3f) as usize, 1));
+    }
+    let mut len = (lead & 0x0f) as usize;
+    for i in 0..count {
+        len |= (*aml.get(off + 1 + i)? as usize) << (4 + 8 * i);
+    }
+    return Some((len, count + 1));
+}
+
+fn is_name_seg(seg: &[u8]) -> bool {
+    let lead = seg[0] == b'_' || seg[0].is_ascii_uppercase();
+    return lead
+        && seg[1..]
+            .iter()
+            .all(|c| *c == b'_' || c.is_ascii_uppercase() || c.is_ascii_digit());
+}
+
+/// 解析NameString
+///
+/// ## 返回值
+///
+/// (名字的最后一段, NameString占用的字节数)
+fn parse_name_string(aml: &[u8], off: usize) -> Option<([u8; 4], usize)> {
+    let mut i = off;
+    while matches!(
+        aml.get(i),
+        Some(&AML_ROOT_CHAR) | Some(&AML_PARENT_PREFIX_CHAR)
+    ) {
+        i += 1;
+    }
+    let segs = match *aml.get(i)? {
+        AML_DUAL_NAME_PREFIX => {
+            i += 1;
+            2
+        }
+        AML_MULTI_NAME_PREFIX => {
+            let n = *aml.get(i + 1)? as usize;
+            i += 2;
+            n
+        }
+        _ => 1,
+    };
+    if segs == 0 {
+        return None;
+    }
+
+    let mut last = [0u8; 4];
+    for _ in 0..segs {
+        let seg = aml.get(i..i + 4)?;
+        if !is_name_seg(seg) {
+            return None;
+        }
+        last.copy_from_slice(seg);
+        i += 4;
+    }
+    return Some((last, i - off));
+}
+
+/// 解析从`off`开始的`Device(...)`，设备必须在`limit`之前结束
+fn parse_device(aml: &[u8], off: usize, limit: usize) -> Option<AmlDevice> {
+    let pkg_off = off + 2;
+    let (len, len_bytes) = parse_pkg_length(aml, pkg_off)?;
+    let end = pkg_off + len;
+    let (name, name_bytes) = parse_name_string(aml, pkg_off + len_bytes)?;
+    let start = pkg_off + len_bytes + name_bytes;
+    if end > limit || start > end {
+        return None;
+    }
+    return Some(AmlDevice {
+        name,
+        body: start..end,
+    });
+}
+
+/// 在设备中查找对象，跳过子设备与其他方法中的内容
+fn find_object(aml: &[u8], scope: &Range<usize>, name: &[u8; 4]) -> Option<AmlObject> {
+    let mut i = scope.start;
+    while i < scope.end {
+        match aml[i] {
+            AML_EXT_OP_PREFIX if aml.get(i + 1) == Some(&AML_DEVICE_OP) => {
+                if let Some(dev) = parse_device(aml, i, scope.end) {
+                    i = dev.body.end;
+                    continue;
+                }
+            }
+            AML_NAME_OP if aml.get(i + 1..i + 5) == Some(&name[..]) => {
+                return Some(AmlObject::Name(i + 5));
+            }
+            AML_METHOD_OP => {
+                let parsed = parse_pkg_length(aml, i + 1).and_then(|(len, len_bytes)| {
+                    let (seg, name_bytes) = parse_name_string(aml, i + 1 + len_bytes)?;
+                    Some((i + 1 + len, i + 1 + len_bytes + name_bytes, seg))
+                });
+                if let Some((end, name_end, seg)) = parsed {
+                    if end <= scope.end && name_end < end {
+                        if seg == *name {
+                            // 跳过MethodFlags
+                            return Some(AmlObject::Method(name_end + 1));
+                        }
+                        i = end;
+                        continue;
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    return None;
+}
+
+/// 对对象求值，只支持常量
+fn evaluate_object(aml: &[u8], obj: AmlObject) -> Result<AmlValue, SystemError> {
+    let off = match obj {
+        AmlObject::Name(off) => off,
+        AmlObject::Method(body) => {
+            if aml.get(body) != Some(&AML_RETURN_OP) {
+                return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+            }
+            body + 1
+        }
+    };
+    return parse_data_object(aml, off)
+        .map(|(v, _)| v)
+        .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+}
+
+/// 解析常量
+///
+/// ## 返回值
+///
+/// (常量, 常量占用的字节数)
+fn parse_data_object(aml: &[u8], off: usize) -> Option<(AmlValue, usize)> {
+    let read_le = |len: usize| -> Option<u64> {
+        let bytes = aml.get(off + 1..off + 1 + len)?;
+        return Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0u64, |acc, b| (acc << 8) | *b as u64),
+        );
+    };
+
+    let r = match *aml.get(off)? {
+        AML_ZERO_OP => (AmlValue::Integer(0), 1),
+        AML_ONE_OP => (AmlValue::Integer(1), 1),
+        AML_ONES_OP => (AmlValue::Integer(u64::MAX), 1),
+        AML_BYTE_PREFIX => (AmlValue::Integer(read_le(1)?), 2),
+        AML_WORD_PREFIX => (AmlValue::Integer(read_le(2)?), 3),
+        AML_DWORD_PREFIX => (AmlValue::Integer(read_le(4)?), 5),
+        AML_QWORD_PREFIX => (AmlValue::Integer(read_le(8)?), 9),
+        AML_STRING_PREFIX => {
+            let bytes = aml.get(off + 1..)?;
+            let len = bytes.iter().position(|c| *c == 0)?;
+            let s = String::from_utf8_lossy(&bytes[..len]);
+            (AmlValue::String(String::from(s)), len + 2)
+        }
+        AML_PACKAGE_OP => {
+            let (len, len_bytes) = parse_pkg_length(aml, off + 1)?;
+            let end = off + 1 + len;
+            let count = *aml.get(off + 1 + len_bytes)? as usize;
+            let mut pos = off + 2 + len_bytes;
+            let mut elements = Vec::with_capacity(count);
+            // 没有写出的元素是未初始化的，这里不为它们生成值
+            while elements.len() < count && pos < end {
+                let (v, used) = parse_data_object(aml, pos)?;
+                elements.push(v);
+                pos += used;
+            }
+            (AmlValue::Package(elements), len + 1)
+        }
+        _ => return None,
+    };
+    return Some(r);
+}
+
+/// 把压缩的EISA ID(例如`EisaId("PNP0C0A")`)转换为字符串
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/acpica/exutils.c?fi=acpi_ex_eisa_id_to_string#acpi_ex_eisa_id_to_string
+fn eisa_id_to_string(id: u32) -> String {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let id = id.swap_bytes();
+    let mut s = String::with_capacity(7);
+    for shift in [26, 21, 16] {
+        s.push((b'@' + ((id >> shift) & 0x1f) as u8) as char);
+    }
+    for shift in [12, 8, 4, 0] {
+        s.push(HEX[((id >> shift) & 0xf) as usize] as char);
+    }
+    return s;
+}
+
+/// `_HID`的值可以是压缩的EISA ID，也可以是字符串
+fn hid_matches(value: &AmlValue, hid: &str) -> bool {
+    match value {
+        AmlValue::Integer(id) => eisa_id_to_string(*id as u32) == hid,
+        AmlValue::String(s) => s == hid,
+        AmlValue::Package(_) => false,
+    }
+}
@@ -0,0 +1,367 @@
+//! ACPI电池(PNP0C0A)
+//!
+//! 电池的静态信息来自`_BIX`(ACPI 4.0)或者`_BIF`，当前状态来自`_BST`，是否接入来自`_STA`。
+//! 每次读取属性文件时都会重新求值，因此这些对象只有在能够被求值时才有数据，参见[`super::aml`]。
+//!
+//! ACPI总线还不能为设备匹配驱动，因此这里直接在DSDT中查找电池。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/battery.c
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    driver::power_supply::{
+        power_supply_register, PowerSupply, PowerSupplyCapacityLevel, PowerSupplyProperty,
+        PowerSupplyStatus, PowerSupplyTechnology, PowerSupplyType, PowerSupplyValue,
+    },
+    kinfo,
+    syscall::SystemError,
+};
+
+use super::{
+    acpi_manager,
+    aml::{AmlDevice, AmlValue},
+};
+
+/// 电池的硬件ID
+const ACPI_BATTERY_HID: &str = "PNP0C0A";
+
+/// `_BIF`、`_BIX`与`_BST`中表示未知的值
+const ACPI_BATTERY_VALUE_UNKNOWN: u64 = 0xffff_ffff;
+/// 电量的单位为mAh(否则为mWh)
+const ACPI_BATTERY_POWER_UNIT_MA: u64 = 1;
+
+/// `_BST`中的电池状态
+const ACPI_BATTERY_STATE_DISCHARGING: u64 = 1 << 0;
+const ACPI_BATTERY_STATE_CHARGING: u64 = 1 << 1;
+const ACPI_BATTERY_STATE_CRITICAL: u64 = 1 << 2;
+
+/// `_STA`中表示电池已经接入的位
+const ACPI_STA_BATTERY_PRESENT: u64 = 1 << 4;
+
+/// 电量单位为mAh的电池的属性
+static CHARGE_BATTERY_PROPS: [PowerSupplyProperty; 15] = [
+    PowerSupplyProperty::Status,
+    PowerSupplyProperty::Present,
+    PowerSupplyProperty::Technology,
+    PowerSupplyProperty::CycleCount,
+    PowerSupplyProperty::VoltageMinDesign,
+    PowerSupplyProperty::VoltageNow,
+    PowerSupplyProperty::CurrentNow,
+    PowerSupplyProperty::ChargeFullDesign,
+    PowerSupplyProperty::ChargeFull,
+    PowerSupplyProperty::ChargeNow,
+    PowerSupplyProperty::Capacity,
+    PowerSupplyProperty::CapacityLevel,
+    PowerSupplyProperty::ModelName,
+    PowerSupplyProperty::Manufacturer,
+    PowerSupplyProperty::SerialNumber,
+];
+
+/// 电量单位为mWh的电池的属性
+static ENERGY_BATTERY_PROPS: [PowerSupplyProperty; 15] = [
+    PowerSupplyProperty::Status,
+    PowerSupplyProperty::Present,
+    PowerSupplyProperty::Technology,
+    PowerSupplyProperty::CycleCount,
+    PowerSupplyProperty::VoltageMinDesign,
+    PowerSupplyProperty::VoltageNow,
+    PowerSupplyProperty::PowerNow,
+    PowerSupplyProperty::EnergyFullDesign,
+    PowerSupplyProperty::EnergyFull,
+    PowerSupplyProperty::EnergyNow,
+    PowerSupplyProperty::Capacity,
+    PowerSupplyProperty::CapacityLevel,
+    PowerSupplyProperty::ModelName,
+    PowerSupplyProperty::Manufacturer,
+    PowerSupplyProperty::SerialNumber,
+];
+
+/// 电池的静态信息，来自`_BIX`或者`_BIF`
+#[derive(Debug, Clone)]
+struct AcpiBatteryInfo {
+    power_unit: u64,
+    design_capacity: u64,
+    full_charge_capacity: u64,
+    design_voltage: u64,
+    design_capacity_warning: u64,
+    /// 只有`_BIX`提供循环次数
+    cycle_count: Option<u64>,
+    model_number: String,
+    serial_number: String,
+    battery_type: String,
+    oem_info: String,
+}
+
+impl AcpiBatteryInfo {
+    /// 解析`_BIX`，跳过开头的版本号之后与`_BIF`的前几项相同
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/battery.c?fi=extended_info_offsets#extended_info_offsets
+    fn from_bix(p: &[AmlValue]) -> Self {
+        return Self {
+            power_unit: package_int(p, 1),
+            design_capacity: package_int(p, 2),
+            full_charge_capacity: package_int(p, 3),
+            design_voltage: package_int(p, 5),
+            design_capacity_warning: package_int(p, 6),
+            cycle_count: Some(package_int(p, 8)),
+            model_number: package_str(p, 16),
+            serial_number: package_str(p, 17),
+            battery_type: package_str(p, 18),
+            oem_info: package_str(p, 19),
+        };
+    }
+
+    /// 解析`_BIF`
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/battery.c?fi=info_offsets#info_offsets
+    fn from_bif(p: &[AmlValue]) -> Self {
+        return Self {
+            power_unit: package_int(p, 0),
+            design_capacity: package_int(p, 1),
+            full_charge_capacity: package_int(p, 2),
+            design_voltage: package_int(p, 4),
+            design_capacity_warning: package_int(p, 5),
+            cycle_count: None,
+            model_number: package_str(p, 9),
+            serial_number: package_str(p, 10),
+            battery_type: package_str(p, 11),
+            oem_info: package_str(p, 12),
+        };
+    }
+
+    /// 满电时的容量，固件没有提供时使用设计容量
+    fn full_capacity(&self) -> u64 {
+        if is_known(self.full_charge_capacity) && self.full_charge_capacity != 0 {
+            return self.full_charge_capacity;
+        }
+        return self.design_capacity;
+    }
+
+    /// 根据`_BIF`中的电池类型字符串判断电池的化学类型
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/battery.c?fi=get_battery_technology#get_battery_technology
+    fn technology(&self) -> PowerSupplyTechnology {
+        let t = self.battery_type.to_ascii_uppercase();
+        return match t.as_str() {
+            "NICD" => PowerSupplyTechnology::NiCd,
+            "NIMH" => PowerSupplyTechnology::NiMH,
+            "LION" | "LI-ION" | "LIP" => PowerSupplyTechnology::LiIon,
+            "LIPO" => PowerSupplyTechnology::LiPoly,
+            _ => PowerSupplyTechnology::Unknown,
+        };
+    }
+}
+
+/// 电池的当前状态，来自`_BST`
+#[derive(Debug, Clone)]
+struct AcpiBatteryState {
+    state: u64,
+    /// 充放电的电流(mA)或者功率(mW)
+    rate_now: u64,
+    capacity_now: u64,
+    voltage_now: u64,
+}
+
+/// 值是否不是“未知”。`Ones`在64位的AML中是全1，也看作未知
+fn is_known(v: u64) -> bool {
+    return v < ACPI_BATTERY_VALUE_UNKNOWN;
+}
+
+fn package_int(p: &[AmlValue], index: usize) -> u64 {
+    return p
+        .get(index)
+        .and_then(|v| v.as_integer())
+        .unwrap_or(ACPI_BATTERY_VALUE_UNKNOWN);
+}
+
+fn package_str(p: &[AmlValue], index: usize) -> String {
+    return p
+        .get(index)
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .unwrap_or_default();
+}
+
+/// ACPI电池
+#[derive(Debug)]
+struct AcpiBattery {
+    device: AmlDevice,
+    /// 电量的单位为mAh时使用charge_*属性，否则使用energy_*属性。单位在注册时确定
+    charge_unit: bool,
+}
+
+impl AcpiBattery {
+    fn present(&self) -> bool {
+        // `_STA`不存在或者无法求值时，认为电池已经接入
+        return acpi_manager()
+            .aml_evaluate(&self.device, b"_STA")
+            .ok()
+            .and_then(|v| v.as_integer())
+            .map_or(true, |sta| sta & ACPI_STA_BATTERY_PRESENT != 0);
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/battery.c?fi=acpi_battery_get_info#acpi_battery_get_info
+    fn info(&self) -> Result<AcpiBatteryInfo, SystemError> {
+        let manager = acpi_manager();
+        if let Ok(bix) = manager.aml_evaluate(&self.device, b"_BIX") {
+            if let Some(p) = bix.as_package() {
+                return Ok(AcpiBatteryInfo::from_bix(p));
+            }
+        }
+        let bif = manager.aml_evaluate(&self.device, b"_BIF")?;
+        let p = bif.as_package().ok_or(SystemError::EINVAL)?;
+        return Ok(AcpiBatteryInfo::from_bif(p));
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/battery.c?fi=acpi_battery_get_state#acpi_battery_get_state
+    fn state(&self) -> Result<AcpiBatteryState, SystemError> {
+        let bst = acpi_manager().aml_evaluate(&self.device, b"_BST")?;
+        let p = bst.as_package().ok_or(SystemError::EINVAL)?;
+        return Ok(AcpiBatteryState {
+            state: package_int(p, 0),
+            rate_now: package_int(p, 1),
+            capacity_now: package_int(p, 2),
+            voltage_now: package_int(p, 3),
+        });
+    }
+}
+
+/// 把ACPI中的mV、mA、mW、mAh、mWh转换为power_supply使用的µV、µA、µW、µAh、µWh
+fn micro(v: u64) -> Result<PowerSupplyValue, SystemError> {
+    if !is_known(v) {
+        return Err(SystemError::ENODEV);
+    }
+    return Ok(PowerSupplyValue::Int(v as i64 * 1000));
+}
+
+impl PowerSupply for AcpiBattery {
+    fn name(&self) -> String {
+        self.device.name()
+    }
+
+    fn supply_type(&self) -> PowerSupplyType {
+        PowerSupplyType::Battery
+    }
+
+    fn properties(&self) -> &'static [PowerSupplyProperty] {
+        if self.charge_unit {
+            &CHARGE_BATTERY_PROPS
+        } else {
+            &ENERGY_BATTERY_PROPS
+        }
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/battery.c?fi=acpi_battery_get_property#acpi_battery_get_property
+    fn get_property(&self, prop: PowerSupplyProperty) -> Result<PowerSupplyValue, SystemError> {
+        let present = self.present();
+        if prop == PowerSupplyProperty::Present {
+            return Ok(PowerSupplyValue::Int(present as i64));
+        }
+        if !present {
+            return Err(SystemError::ENODEV);
+        }
+
+        let info = self.info()?;
+        let v: PowerSupplyValue = match prop {
+            PowerSupplyProperty::Technology => info.technology().into(),
+            PowerSupplyProperty::CycleCount => {
+                let count = info.cycle_count.ok_or(SystemError::ENODEV)?;
+                if !is_known(count) {
+                    return Err(SystemError::ENODEV);
+                }
+                PowerSupplyValue::Int(count as i64)
+            }
+            PowerSupplyProperty::VoltageMinDesign => micro(info.design_voltage)?,
+            PowerSupplyProperty::ChargeFullDesign | PowerSupplyProperty::EnergyFullDesign => {
+                micro(info.design_capacity)?
+            }
+            PowerSupplyProperty::ChargeFull | PowerSupplyProperty::EnergyFull => {
+                micro(info.full_charge_capacity)?
+            }
+            PowerSupplyProperty::ModelName => PowerSupplyValue::Str(info.model_number),
+            PowerSupplyProperty::Manufacturer => PowerSupplyValue::Str(info.oem_info),
+            PowerSupplyProperty::SerialNumber => PowerSupplyValue::Str(info.serial_number),
+            _ => {
+                let state = self.state()?;
+                let full = info.full_capacity();
+                let charged =
+                    is_known(state.capacity_now) && is_known(full) && state.capacity_now >= full;
+                match prop {
+                    PowerSupplyProperty::Status => {
+                        let status = if state.state & ACPI_BATTERY_STATE_DISCHARGING != 0 {
+                            PowerSupplyStatus::Discharging
+                        } else if state.state & ACPI_BATTERY_STATE_CHARGING != 0 {
+                            PowerSupplyStatus::Charging
+                        } else if charged {
+                            PowerSupplyStatus::Full
+                        } else {
+                            PowerSupplyStatus::NotCharging
+                        };
+                        status.into()
+                    }
+                    PowerSupplyProperty::VoltageNow => micro(state.voltage_now)?,
+                    PowerSupplyProperty::CurrentNow | PowerSupplyProperty::PowerNow => {
+                        micro(state.rate_now)?
+                    }
+                    PowerSupplyProperty::ChargeNow | PowerSupplyProperty::EnergyNow => {
+                        micro(state.capacity_now)?
+                    }
+                    PowerSupplyProperty::Capacity => {
+                        if !is_known(state.capacity_now) || !is_known(full) || full == 0 {
+                            return Err(SystemError::ENODEV);
+                        }
+                        let capacity = (state.capacity_now * 100 / full).min(100);
+                        PowerSupplyValue::Int(capacity as i64)
+                    }
+                    PowerSupplyProperty::CapacityLevel => {
+                        let level = if state.state & ACPI_BATTERY_STATE_CRITICAL != 0 {
+                            PowerSupplyCapacityLevel::Critical
+                        } else if is_known(state.capacity_now)
+                            && is_known(info.design_capacity_warning)
+                            && state.capacity_now <= info.design_capacity_warning
+                        {
+                            PowerSupplyCapacityLevel::Low
+                        } else if charged {
+                            PowerSupplyCapacityLevel::Full
+                        } else {
+                            PowerSupplyCapacityLevel::Normal
+                        };
+                        level.into()
+                    }
+                    _ => return Err(SystemError::EINVAL),
+                }
+            }
+        };
+        return Ok(v);
+    }
+}
+
+/// 查找DSDT中的电池，为它们注册电源
+///
+/// ## 错误
+///
+/// - `ENODEV`：没有找到电池
+pub fn acpi_battery_init() -> Result<(), SystemError> {
+    let devices = acpi_manager().aml_find_devices(ACPI_BATTERY_HID)?;
+    if devices.is_empty() {
+        return Err(SystemError::ENODEV);
+    }
+
+    let mut registered: Vec<String> = Vec::new();
+    for device in devices {
+        let mut battery = AcpiBattery {
+            device,
+            charge_unit: false,
+        };
+        // 信息无法求值时，与Linux相同，默认使用mWh
+        battery.charge_unit = battery
+            .info()
+            .map_or(false, |info| info.power_unit == ACPI_BATTERY_POWER_UNIT_MA);
+        let name = battery.name();
+        power_supply_register(Arc::new(battery), None)?;
+        registered.push(name);
+    }
+    kinfo!("ACPI: battery {:?} registered", registered);
+    return Ok(());
+}
@@ -77,14 +77,6 @@ impl Bus for AcpiBus {
         todo!("acpi_bus: remove")
     }
 
-    fn shutdown(&self, _device: &Arc<dyn Device>) {
-        return;
-    }
-
-    fn resume(&self, _device: &Arc<dyn Device>) -> Result<(), SystemError> {
-        return Ok(());
-    }
-
     /// 通过acpi来匹配驱动
     ///
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/bus.c#1005
@@ -1,5 +1,6 @@
 use crate::{
     arch::MMArch,
+    kwarn,
     libs::align::AlignedBox,
     mm::{MemoryManagementArch, VirtAddr},
 };
@@ -8,8 +9,23 @@ use super::acpi_manager;
 
 static mut RSDP_TMP_BOX: Option<AlignedBox<[u8; 4096], 4096>> = None;
 
+/// RSDP的签名
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
 #[no_mangle]
 unsafe extern "C" fn rs_acpi_init(rsdp_vaddr: u64) {
+    // 引导程序没有提供有效的RSDP时，在BIOS区域中搜索
+    if rsdp_vaddr == 0
+        || core::slice::from_raw_parts(rsdp_vaddr as usize as *const u8, RSDP_SIGNATURE.len())
+            != RSDP_SIGNATURE
+    {
+        kwarn!("rs_acpi_init(): invalid rsdp from bootloader, searching in bios area");
+        acpi_manager()
+            .init(None)
+            .expect("rs_acpi_init(): failed to init acpi");
+        return;
+    }
+
     RSDP_TMP_BOX = Some(AlignedBox::new_zeroed().expect("rs_acpi_init(): failed to alloc"));
     let size = core::mem::size_of::<acpi::rsdp::Rsdp>();
     let tmp_data = core::slice::from_raw_parts(rsdp_vaddr as usize as *const u8, size);
@@ -21,6 +37,6 @@ unsafe extern "C" fn rs_acpi_init(rsdp_vaddr: u64) {
     .unwrap();
 
     acpi_manager()
-        .init(rsdp_paddr)
+        .init(Some(rsdp_paddr))
         .expect("rs_acpi_init(): failed to init acpi");
 }
@@ -0,0 +1,193 @@
+//! FADT(Fixed ACPI Description Table)的解析
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/acpica/tbfadt.c
+
+use acpi::{
+    sdt::{SdtHeader, Signature},
+    AcpiTable,
+};
+
+use crate::syscall::SystemError;
+
+use super::AcpiManager;
+
+/// FADT的表头
+#[repr(C)]
+struct Fadt {
+    header: SdtHeader,
+}
+
+unsafe impl AcpiTable for Fadt {
+    const SIGNATURE: Signature = Signature::FADT;
+    fn header(&self) -> &SdtHeader {
+        return &self.header;
+    }
+}
+
+/// FADT的标志位：PM timer是32位的
+pub const FADT_TMR_VAL_EXT: u32 = 1 << 8;
+/// FADT的标志位：支持通过reset_reg复位系统
+pub const FADT_RESET_REG_SUP: u32 = 1 << 10;
+
+/// Generic Address Structure所在的地址空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    PciConfig,
+    Other(u8),
+}
+
+impl From<u8> for AddressSpace {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => AddressSpace::SystemMemory,
+            1 => AddressSpace::SystemIo,
+            2 => AddressSpace::PciConfig,
+            x => AddressSpace::Other(x),
+        }
+    }
+}
+
+/// ACPI的Generic Address Structure
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    /// 从12字节的原始数据中解析
+    fn parse(data: &[u8]) -> Self {
+        Self {
+            space: AddressSpace::from(data[0]),
+            bit_width: data[1],
+            bit_offset: data[2],
+            access_size: data[3],
+            address: u64::from_le_bytes(data[4..12].try_into().unwrap()),
+        }
+    }
+
+    /// 由旧式的32位I/O端口号构造
+    fn io(port: u32, bit_width: u8) -> Self {
+        Self {
+            space: AddressSpace::SystemIo,
+            bit_width,
+            bit_offset: 0,
+            access_size: 0,
+            address: port as u64,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.address != 0
+    }
+}
+
+/// 从FADT中解析出来的电源管理信息
+#[derive(Debug, Clone, Copy)]
+pub struct FadtInfo {
+    /// SCI中断号
+    pub sci_int: u16,
+    /// SMI命令端口
+    pub smi_cmd: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_evt: GenericAddress,
+    pub pm1b_evt: GenericAddress,
+    pub pm1a_cnt: GenericAddress,
+    pub pm1b_cnt: GenericAddress,
+    pub pm_tmr: GenericAddress,
+    /// IA-PC启动架构标志
+    pub iapc_boot_arch: u16,
+    pub flags: u32,
+    /// 复位寄存器（仅当`flags`中含有`FADT_RESET_REG_SUP`时有效）
+    pub reset_reg: Option<GenericAddress>,
+    pub reset_value: u8,
+    /// FACS的物理地址，为0表示固件没有提供FACS
+    pub firmware_ctrl: u64,
+}
+
+impl AcpiManager {
+    /// 解析FADT
+    ///
+    /// 如果表中提供了64位的X_*字段，则优先使用它们
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：固件没有提供FADT表
+    pub fn fadt_info(&self) -> Result<FadtInfo, SystemError> {
+        let tables = self.tables().ok_or(SystemError::ENODEV)?;
+        let fadt = tables
+            .find_entire_table::<Fadt>()
+            .map_err(|_| SystemError::ENODEV)?;
+
+        let data = unsafe {
+            core::slice::from_raw_parts(
+                fadt.virtual_start().as_ptr() as *const u8,
+                fadt.region_length(),
+            )
+        };
+        let len = core::cmp::min(fadt.header.length as usize, data.len());
+        // ACPI 1.0的FADT长度为116字节
+        if len < 116 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let read_u16 = |off: usize| u16::from_le_bytes(data[off..off + 2].try_into().unwrap());
+        let read_u32 = |off: usize| u32::from_le_bytes(data[off..off + 4].try_into().unwrap());
+        // 优先使用X_*字段，没有的话回退到32位的端口号
+        let pick = |x_off: usize, legacy_off: usize, width: u8| {
+            if x_off + 12 <= len {
+                let gas = GenericAddress::parse(&data[x_off..x_off + 12]);
+                if gas.is_valid() {
+                    return gas;
+                }
+            }
+            GenericAddress::io(read_u32(legacy_off), width)
+        };
+
+        let pm1_evt_len = data[88] * 8;
+        let pm1_cnt_len = data[89] * 8;
+        let pm_tmr_len = data[91] * 8;
+        let flags = read_u32(112);
+
+        let reset_reg = if flags & FADT_RESET_REG_SUP != 0 && len >= 129 {
+            Some(GenericAddress::parse(&data[116..128]))
+        } else {
+            None
+        };
+
+        // 优先使用64位的X_FIRMWARE_CTRL
+        let x_firmware_ctrl = if len >= 140 {
+            u64::from_le_bytes(data[132..140].try_into().unwrap())
+        } else {
+            0
+        };
+        let firmware_ctrl = if x_firmware_ctrl != 0 {
+            x_firmware_ctrl
+        } else {
+            read_u32(36) as u64
+        };
+
+        return Ok(FadtInfo {
+            sci_int: read_u16(46),
+            smi_cmd: read_u32(48),
+            acpi_enable: data[52],
+            acpi_disable: data[53],
+            pm1a_evt: pick(148, 56, pm1_evt_len),
+            pm1b_evt: pick(160, 60, pm1_evt_len),
+            pm1a_cnt: pick(172, 64, pm1_cnt_len),
+            pm1b_cnt: pick(184, 68, pm1_cnt_len),
+            pm_tmr: pick(208, 76, pm_tmr_len),
+            iapc_boot_arch: read_u16(109),
+            flags,
+            reset_value: if reset_reg.is_some() { data[128] } else { 0 },
+            reset_reg,
+            firmware_ctrl,
+        });
+    }
+}
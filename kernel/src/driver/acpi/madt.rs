@@ -0,0 +1,182 @@
+//! MADT(Multiple APIC Description Table)的解析
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/kernel/acpi/boot.c
+
+use acpi::{
+    sdt::{SdtHeader, Signature},
+    AcpiTable,
+};
+use alloc::vec::Vec;
+
+use crate::{kwarn, mm::PhysAddr, syscall::SystemError};
+
+use super::AcpiManager;
+
+/// MADT的表头
+#[repr(C)]
+struct Madt {
+    header: SdtHeader,
+    _local_apic_address: u32,
+    _flags: u32,
+}
+
+unsafe impl AcpiTable for Madt {
+    const SIGNATURE: Signature = Signature::MADT;
+    fn header(&self) -> &SdtHeader {
+        return &self.header;
+    }
+}
+
+/// MADT中的表项类型
+const MADT_TYPE_LOCAL_APIC: u8 = 0;
+const MADT_TYPE_IO_APIC: u8 = 1;
+const MADT_TYPE_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_TYPE_LOCAL_APIC_OVERRIDE: u8 = 5;
+const MADT_TYPE_LOCAL_X2APIC: u8 = 9;
+
+/// Local APIC表项中的“已启用”标志位
+const MADT_LAPIC_ENABLED: u32 = 1 << 0;
+/// Local APIC表项中的“可以被启用”标志位
+const MADT_LAPIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// MADT表头中的标志位：系统中存在兼容8259的双PIC
+pub const MADT_PCAT_COMPAT: u32 = 1 << 0;
+
+/// MADT中描述的一个处理器
+#[derive(Debug, Clone, Copy)]
+pub struct MadtCpu {
+    /// ACPI Processor UID
+    pub processor_uid: u32,
+    /// Local APIC ID
+    pub apic_id: u32,
+    /// 处理器当前是否已经启用
+    pub enabled: bool,
+}
+
+/// MADT中描述的一个I/O APIC
+#[derive(Debug, Clone, Copy)]
+pub struct MadtIoApic {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// 第一个中断输入对应的全局中断号
+    pub gsi_base: u32,
+}
+
+/// ISA中断到全局中断号的重定向
+#[derive(Debug, Clone, Copy)]
+pub struct MadtInterruptOverride {
+    pub bus: u8,
+    /// ISA中断号
+    pub source_irq: u8,
+    /// 全局中断号
+    pub gsi: u32,
+    /// 极性与触发方式
+    pub flags: u16,
+}
+
+/// 从MADT中解析出来的中断控制器与处理器信息
+#[derive(Debug)]
+pub struct MadtInfo {
+    /// Local APIC寄存器的物理地址
+    pub local_apic_address: PhysAddr,
+    /// MADT表头中的标志位
+    pub flags: u32,
+    /// 所有可以使用的处理器（已经启用或者可以被启用）
+    pub cpus: Vec<MadtCpu>,
+    pub io_apics: Vec<MadtIoApic>,
+    pub overrides: Vec<MadtInterruptOverride>,
+}
+
+impl AcpiManager {
+    /// 解析MADT
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：固件没有提供MADT表
+    pub fn madt_info(&self) -> Result<MadtInfo, SystemError> {
+        let tables = self.tables().ok_or(SystemError::ENODEV)?;
+        let madt = tables
+            .find_entire_table::<Madt>()
+            .map_err(|_| SystemError::ENODEV)?;
+
+        let data = unsafe {
+            core::slice::from_raw_parts(
+                madt.virtual_start().as_ptr() as *const u8,
+                madt.region_length(),
+            )
+        };
+        let len = core::cmp::min(madt.header.length as usize, data.len());
+
+        let read_u16 = |off: usize| u16::from_le_bytes(data[off..off + 2].try_into().unwrap());
+        let read_u32 = |off: usize| u32::from_le_bytes(data[off..off + 4].try_into().unwrap());
+        let read_u64 = |off: usize| u64::from_le_bytes(data[off..off + 8].try_into().unwrap());
+
+        let header_len = core::mem::size_of::<SdtHeader>();
+        let mut info = MadtInfo {
+            local_apic_address: PhysAddr::new(read_u32(header_len) as usize),
+            flags: read_u32(header_len + 4),
+            cpus: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+        };
+        let usable = |flags: u32| flags & (MADT_LAPIC_ENABLED | MADT_LAPIC_ONLINE_CAPABLE) != 0;
+
+        let mut offset = core::mem::size_of::<Madt>();
+        while offset + 2 <= len {
+            let entry_type = data[offset];
+            let entry_len = data[offset + 1] as usize;
+            if entry_len < 2 || offset + entry_len > len {
+                kwarn!("MADT: bad entry at offset {}, len {}", offset, entry_len);
+                break;
+            }
+
+            match entry_type {
+                MADT_TYPE_LOCAL_APIC if entry_len >= 8 => {
+                    let flags = read_u32(offset + 4);
+                    if usable(flags) {
+                        info.cpus.push(MadtCpu {
+                            processor_uid: data[offset + 2] as u32,
+                            apic_id: data[offset + 3] as u32,
+                            enabled: flags & MADT_LAPIC_ENABLED != 0,
+                        });
+                    }
+                }
+                MADT_TYPE_IO_APIC if entry_len >= 12 => {
+                    info.io_apics.push(MadtIoApic {
+                        id: data[offset + 2],
+                        address: PhysAddr::new(read_u32(offset + 4) as usize),
+                        gsi_base: read_u32(offset + 8),
+                    });
+                }
+                MADT_TYPE_INTERRUPT_OVERRIDE if entry_len >= 10 => {
+                    info.overrides.push(MadtInterruptOverride {
+                        bus: data[offset + 2],
+                        source_irq: data[offset + 3],
+                        gsi: read_u32(offset + 4),
+                        flags: read_u16(offset + 8),
+                    });
+                }
+                MADT_TYPE_LOCAL_APIC_OVERRIDE if entry_len >= 12 => {
+                    info.local_apic_address = PhysAddr::new(read_u64(offset + 4) as usize);
+                }
+                MADT_TYPE_LOCAL_X2APIC if entry_len >= 16 => {
+                    let flags = read_u32(offset + 8);
+                    let apic_id = read_u32(offset + 4);
+                    // 已经以Local APIC表项描述过的处理器，不再重复添加
+                    if usable(flags) && !info.cpus.iter().any(|c| c.apic_id == apic_id) {
+                        info.cpus.push(MadtCpu {
+                            processor_uid: read_u32(offset + 12),
+                            apic_id,
+                            enabled: flags & MADT_LAPIC_ENABLED != 0,
+                        });
+                    }
+                }
+                _ => {}
+            }
+
+            offset += entry_len;
+        }
+
+        return Ok(info);
+    }
+}
@@ -0,0 +1,80 @@
+//! MCFG(PCI Express memory mapped configuration space base address Description Table)的解析
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/pci_mcfg.c
+
+use acpi::{
+    sdt::{SdtHeader, Signature},
+    AcpiTable,
+};
+use alloc::vec::Vec;
+
+use crate::{mm::PhysAddr, syscall::SystemError};
+
+use super::AcpiManager;
+
+/// MCFG的表头
+#[repr(C)]
+struct Mcfg {
+    header: SdtHeader,
+    _reserved: u64,
+}
+
+unsafe impl AcpiTable for Mcfg {
+    const SIGNATURE: Signature = Signature::MCFG;
+    fn header(&self) -> &SdtHeader {
+        return &self.header;
+    }
+}
+
+/// 每个表项的长度
+const MCFG_ENTRY_LEN: usize = 16;
+
+/// 一个PCI段的ECAM区域
+#[derive(Debug, Clone, Copy)]
+pub struct McfgEntry {
+    /// ECAM区域的物理基地址（对应bus 0）
+    pub base_address: PhysAddr,
+    /// PCI段号
+    pub segment: u16,
+    pub bus_begin: u8,
+    pub bus_end: u8,
+}
+
+impl AcpiManager {
+    /// 解析MCFG，获取所有PCI段的ECAM区域
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：固件没有提供MCFG表（例如不支持PCIe的机器）
+    pub fn mcfg_info(&self) -> Result<Vec<McfgEntry>, SystemError> {
+        let tables = self.tables().ok_or(SystemError::ENODEV)?;
+        let mcfg = tables
+            .find_entire_table::<Mcfg>()
+            .map_err(|_| SystemError::ENODEV)?;
+
+        let data = unsafe {
+            core::slice::from_raw_parts(
+                mcfg.virtual_start().as_ptr() as *const u8,
+                mcfg.region_length(),
+            )
+        };
+        let len = core::cmp::min(mcfg.header.length as usize, data.len());
+
+        let mut entries = Vec::new();
+        let mut offset = core::mem::size_of::<Mcfg>();
+        while offset + MCFG_ENTRY_LEN <= len {
+            let e = &data[offset..offset + MCFG_ENTRY_LEN];
+            entries.push(McfgEntry {
+                base_address: PhysAddr::new(
+                    u64::from_le_bytes(e[0..8].try_into().unwrap()) as usize
+                ),
+                segment: u16::from_le_bytes(e[8..10].try_into().unwrap()),
+                bus_begin: e[10],
+                bus_end: e[11],
+            });
+            offset += MCFG_ENTRY_LEN;
+        }
+
+        return Ok(entries);
+    }
+}
@@ -18,11 +18,18 @@ use super::base::kset::KSet;
 
 extern crate acpi;
 
+pub mod ac;
+pub mod aml;
+pub mod battery;
 pub mod bus;
 mod c_adapter;
+pub mod fadt;
 pub mod glue;
-pub mod old;
+pub mod madt;
+pub mod mcfg;
 pub mod pmtmr;
+pub mod sleep;
+pub mod srat;
 mod sysfs;
 
 static mut __ACPI_TABLE: Option<acpi::AcpiTables<AcpiHandlerImpl>> = None;
@@ -47,13 +54,13 @@ impl AcpiManager {
     ///
     /// ## 参数
     ///
-    /// - `rsdp_paddr`: RSDP的物理地址
+    /// - `rsdp_paddr`: RSDP的物理地址。为`None`时，在BIOS区域中搜索RSDP
     ///
     ///
     /// ## 参考资料
     ///
     /// https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/bus.c#1390
-    pub fn init(&self, rsdp_paddr: PhysAddr) -> Result<(), SystemError> {
+    pub fn init(&self, rsdp_paddr: Option<PhysAddr>) -> Result<(), SystemError> {
         kinfo!("Initializing Acpi Manager...");
 
         // 初始化`/sys/firmware/acpi`的kset
@@ -68,14 +75,17 @@ impl AcpiManager {
         return Ok(());
     }
 
-    fn map_tables(&self, rsdp_paddr: PhysAddr) -> Result<(), SystemError> {
-        let acpi_table: acpi::AcpiTables<AcpiHandlerImpl> =
-            unsafe { acpi::AcpiTables::from_rsdp(AcpiHandlerImpl, rsdp_paddr.data()) }.map_err(
-                |e| {
-                    kerror!("acpi_init(): failed to parse acpi tables, error: {:?}", e);
-                    SystemError::ENOMEM
-                },
-            )?;
+    fn map_tables(&self, rsdp_paddr: Option<PhysAddr>) -> Result<(), SystemError> {
+        let acpi_table: acpi::AcpiTables<AcpiHandlerImpl> = unsafe {
+            match rsdp_paddr {
+                Some(paddr) => acpi::AcpiTables::from_rsdp(AcpiHandlerImpl, paddr.data()),
+                None => acpi::AcpiTables::search_for_rsdp_bios(AcpiHandlerImpl),
+            }
+        }
+        .map_err(|e| {
+            kerror!("acpi_init(): failed to parse acpi tables, error: {:?}", e);
+            SystemError::ENOMEM
+        })?;
 
         unsafe {
             __ACPI_TABLE = Some(acpi_table);
@@ -0,0 +1,268 @@
+//! ACPI睡眠状态（支持S3挂起到内存与S5软关机）
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/acpica/hwsleep.c
+
+use core::hint::spin_loop;
+
+use crate::{
+    arch::{io::PortIOArch, CurrentPortIOArch},
+    kerror, kinfo,
+    syscall::SystemError,
+};
+
+use super::{
+    aml::{
+        AML_BYTE_PREFIX, AML_DWORD_PREFIX, AML_NAME_OP, AML_ONE_OP, AML_PACKAGE_OP, AML_ROOT_CHAR,
+        AML_WORD_PREFIX, AML_ZERO_OP,
+    },
+    fadt::{AddressSpace, FadtInfo, GenericAddress},
+    AcpiHandlerImpl, AcpiManager,
+};
+
+use acpi::AcpiHandler;
+
+/// PM1控制寄存器中的SCI_EN位
+const ACPI_PM1_CNT_SCI_EN: u16 = 1 << 0;
+/// PM1控制寄存器中SLP_TYP字段的偏移
+const ACPI_PM1_CNT_SLP_TYP_SHIFT: u16 = 10;
+/// PM1控制寄存器中的SLP_EN位
+const ACPI_PM1_CNT_SLP_EN: u16 = 1 << 13;
+/// PM1状态寄存器中的WAK_STS位，写1清除
+const ACPI_PM1_STS_WAK: u16 = 1 << 15;
+
+/// FACS的长度
+const FACS_LENGTH: usize = 64;
+/// FACS中32位唤醒向量的偏移
+const FACS_WAKING_VECTOR_OFFSET: usize = 12;
+/// FACS中64位唤醒向量的偏移(ACPI 2.0及之后的版本)
+const FACS_X_WAKING_VECTOR_OFFSET: usize = 24;
+
+/// 等待固件切换到ACPI模式的最大轮询次数
+const ACPI_ENABLE_TIMEOUT: usize = 3000000;
+
+impl AcpiManager {
+    /// 进入S5状态（软关机）
+    ///
+    /// 成功的话不会返回
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：没有FADT或者DSDT中没有`\_S5_`对象
+    /// - `EOPNOTSUPP_OR_ENOTSUP`：PM1控制寄存器不在I/O空间中
+    /// - `ETIMEDOUT`：固件没有切换到ACPI模式
+    pub fn enter_s5(&self) -> Result<(), SystemError> {
+        let fadt = self.fadt_info()?;
+        let (slp_typa, slp_typb) = self.find_sleep_type(b"_S5_")?;
+
+        if !is_io_reg(&fadt.pm1a_cnt) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+
+        self.acpi_enable(&fadt.pm1a_cnt, fadt.smi_cmd, fadt.acpi_enable)?;
+
+        kinfo!("Entering ACPI S5 state...");
+        write_sleep_type(&fadt, slp_typa, slp_typb);
+
+        // 硬件需要一些时间才能断电
+        for _ in 0..ACPI_ENABLE_TIMEOUT {
+            spin_loop();
+        }
+        kerror!("ACPI S5 failed: machine is still running");
+        return Err(SystemError::EIO);
+    }
+
+    /// 固件是否支持S3状态：DSDT中有`\_S3_`对象，并且提供了用于设置唤醒向量的FACS
+    pub fn s3_supported(&self) -> bool {
+        return self.find_sleep_type(b"_S3_").is_ok()
+            && self
+                .fadt_info()
+                .map_or(false, |fadt| fadt.firmware_ctrl != 0);
+    }
+
+    /// 进入S3状态（挂起到内存）
+    ///
+    /// 调用之前，调用者必须已经关闭中断、保存了cpu的状态、通过[`AcpiManager::set_waking_vector`]设置了唤醒向量，
+    /// 并且把cpu的缓存写回了内存。
+    ///
+    /// 成功的话不会返回：机器被唤醒之后，固件在实模式下跳转到唤醒向量
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：没有FADT或者DSDT中没有`\_S3_`对象
+    /// - `EOPNOTSUPP_OR_ENOTSUP`：PM1寄存器不在I/O空间中
+    /// - `ETIMEDOUT`：固件没有切换到ACPI模式
+    /// - `EIO`：写入睡眠控制寄存器之后，机器仍在运行
+    pub fn enter_s3(&self) -> Result<(), SystemError> {
+        let fadt = self.fadt_info()?;
+        let (slp_typa, slp_typb) = self.find_sleep_type(b"_S3_")?;
+
+        if !is_io_reg(&fadt.pm1a_cnt) || !is_io_reg(&fadt.pm1a_evt) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+
+        self.acpi_enable(&fadt.pm1a_cnt, fadt.smi_cmd, fadt.acpi_enable)?;
+
+        // 清除上一次唤醒留下的WAK_STS。状态寄存器位于PM1事件寄存器块的前半部分
+        for evt in [&fadt.pm1a_evt, &fadt.pm1b_evt] {
+            if is_io_reg(evt) {
+                unsafe { CurrentPortIOArch::out16(evt.address as u16, ACPI_PM1_STS_WAK) };
+            }
+        }
+
+        write_sleep_type(&fadt, slp_typa, slp_typb);
+
+        // 有的硬件在写入SLP_EN之后要过一段时间才会睡眠
+        for _ in 0..ACPI_ENABLE_TIMEOUT {
+            spin_loop();
+        }
+        kerror!("ACPI S3 failed: machine is still running");
+        return Err(SystemError::EIO);
+    }
+
+    /// 设置FACS中的唤醒向量：从S3中唤醒之后，固件在实模式下跳转到这里
+    ///
+    /// ## 参数
+    ///
+    /// - `paddr`：唤醒代码的物理地址，必须在1MB以下。固件以`(paddr >> 4):(paddr & 0xf)`作为CS:IP
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：固件没有提供FACS，或者FACS的签名不正确
+    pub fn set_waking_vector(&self, paddr: u32) -> Result<(), SystemError> {
+        let fadt = self.fadt_info()?;
+        if fadt.firmware_ctrl == 0 {
+            return Err(SystemError::ENODEV);
+        }
+
+        let mapping = unsafe {
+            AcpiHandlerImpl.map_physical_region::<u8>(fadt.firmware_ctrl as usize, FACS_LENGTH)
+        };
+        let facs = mapping.virtual_start().as_ptr();
+        unsafe {
+            if core::slice::from_raw_parts(facs, 4) != b"FACS" {
+                return Err(SystemError::ENODEV);
+            }
+            (facs.add(FACS_WAKING_VECTOR_OFFSET) as *mut u32).write_volatile(paddr);
+            // 64位的唤醒向量不为0时，固件会优先使用它
+            let len = (facs.add(4) as *const u32).read_volatile() as usize;
+            if len >= FACS_X_WAKING_VECTOR_OFFSET + 8 {
+                (facs.add(FACS_X_WAKING_VECTOR_OFFSET) as *mut u64).write_volatile(0);
+            }
+        }
+        return Ok(());
+    }
+
+    /// 如果固件仍处于传统模式，则通过SMI命令端口切换到ACPI模式
+    fn acpi_enable(
+        &self,
+        pm1a_cnt: &GenericAddress,
+        smi_cmd: u32,
+        enable_value: u8,
+    ) -> Result<(), SystemError> {
+        let port = pm1a_cnt.address as u16;
+        let sci_enabled = || unsafe { CurrentPortIOArch::in16(port) & ACPI_PM1_CNT_SCI_EN != 0 };
+        if sci_enabled() {
+            return Ok(());
+        }
+        // 不支持传统模式的硬件，固件不会提供SMI命令端口
+        if smi_cmd == 0 || enable_value == 0 {
+            return Ok(());
+        }
+
+        unsafe { CurrentPortIOArch::out8(smi_cmd as u16, enable_value) };
+        for _ in 0..ACPI_ENABLE_TIMEOUT {
+            if sci_enabled() {
+                return Ok(());
+            }
+            spin_loop();
+        }
+        return Err(SystemError::ETIMEDOUT);
+    }
+
+    /// 在DSDT中查找睡眠状态对象(例如`\_S5_`)，获取SLP_TYPa和SLP_TYPb
+    ///
+    /// 这里没有完整地解释AML，只是在字节码中搜索`Name(_Sx_, Package(){...})`
+    fn find_sleep_type(&self, name: &[u8; 4]) -> Result<(u8, u8), SystemError> {
+        let aml = self.dsdt_aml()?;
+
+        let pos = aml
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == name)
+            .map(|(i, _)| i)
+            .find(|&i| {
+                // 前面必须是NameOp（可能带有根路径前缀），后面必须是PackageOp
+                let name_op = (i >= 1 && aml[i - 1] == AML_NAME_OP)
+                    || (i >= 2 && aml[i - 1] == AML_ROOT_CHAR && aml[i - 2] == AML_NAME_OP);
+                name_op && aml.get(i + 4) == Some(&AML_PACKAGE_OP)
+            })
+            .ok_or(SystemError::ENODEV)?;
+
+        // 跳过名字和PackageOp
+        let mut off = pos + 5;
+        // 跳过PkgLength，首字节的高2位表示后面还有几个字节
+        let pkg_len_bytes = (*aml.get(off).ok_or(SystemError::ENODEV)? >> 6) as usize;
+        off += 1 + pkg_len_bytes;
+        // 跳过NumElements
+        off += 1;
+
+        let mut read_element = || -> Result<u8, SystemError> {
+            let op = *aml.get(off).ok_or(SystemError::ENODEV)?;
+            off += 1;
+            let val = match op {
+                AML_ZERO_OP => 0,
+                AML_ONE_OP => 1,
+                AML_BYTE_PREFIX => *aml.get(off).ok_or(SystemError::ENODEV)?,
+                AML_WORD_PREFIX | AML_DWORD_PREFIX => {
+                    // SLP_TYP只有3位，取最低字节即可
+                    *aml.get(off).ok_or(SystemError::ENODEV)?
+                }
+                _ => return Err(SystemError::ENODEV),
+            };
+            off += match op {
+                AML_BYTE_PREFIX => 1,
+                AML_WORD_PREFIX => 2,
+                AML_DWORD_PREFIX => 4,
+                _ => 0,
+            };
+            Ok(val)
+        };
+
+        let slp_typa = read_element()?;
+        let slp_typb = read_element().unwrap_or(0);
+        return Ok((slp_typa & 0x7, slp_typb & 0x7));
+    }
+}
+
+/// 寄存器是否有效并且位于I/O空间中
+fn is_io_reg(reg: &GenericAddress) -> bool {
+    return reg.space == AddressSpace::SystemIo && reg.is_valid();
+}
+
+/// 写入PM1控制寄存器，让机器进入睡眠状态
+///
+/// 与Linux相同，先在两个寄存器中写入SLP_TYP，再单独写入SLP_EN
+fn write_sleep_type(fadt: &FadtInfo, slp_typa: u8, slp_typb: u8) {
+    let write_slp_typ = |reg: &GenericAddress, slp_typ: u8| unsafe {
+        let port = reg.address as u16;
+        let mut val = CurrentPortIOArch::in16(port);
+        val &= !((0x7 << ACPI_PM1_CNT_SLP_TYP_SHIFT) | ACPI_PM1_CNT_SLP_EN);
+        val |= (slp_typ as u16) << ACPI_PM1_CNT_SLP_TYP_SHIFT;
+        CurrentPortIOArch::out16(port, val);
+        val
+    };
+
+    let vala = write_slp_typ(&fadt.pm1a_cnt, slp_typa);
+    let valb = if is_io_reg(&fadt.pm1b_cnt) {
+        Some(write_slp_typ(&fadt.pm1b_cnt, slp_typb))
+    } else {
+        None
+    };
+
+    unsafe {
+        CurrentPortIOArch::out16(fadt.pm1a_cnt.address as u16, vala | ACPI_PM1_CNT_SLP_EN);
+        if let Some(valb) = valb {
+            CurrentPortIOArch::out16(fadt.pm1b_cnt.address as u16, valb | ACPI_PM1_CNT_SLP_EN);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! 在内存管理初始化之前解析SRAT(System Resource Affinity Table)与SLIT(System Locality Information Table)
+//!
+//! 伙伴分配器按照NUMA节点划分内存区，因此SRAT必须在伙伴分配器建立之前解析。
+//! 这时既没有堆，也不能通过mmio映射ACPI表，所以这里不使用`acpi`库，而是按照ACPI规范直接读取物理内存中的表，
+//! 读取物理内存的方法由架构相关的代码通过[`EarlyPhysReader`]提供。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/numa/srat.c
+
+use crate::{
+    kinfo, kwarn,
+    mm::{
+        numa::{
+            numa_add_memblk, numa_register_nodes, numa_reset, numa_set_cpu_node, numa_set_distance,
+            NodeId, MAX_NUMNODES,
+        },
+        PhysAddr,
+    },
+    syscall::SystemError,
+};
+
+/// 读取物理内存的方法
+pub trait EarlyPhysReader {
+    /// 把从`paddr`开始的物理内存读取到`buf`中
+    fn read(&mut self, paddr: PhysAddr, buf: &mut [u8]) -> Result<(), SystemError>;
+}
+
+/// RSDP中，ACPI 2.0之后才有的字段所需要的长度
+const RSDP_V2_LEN: usize = 36;
+/// RSDP中，ACPI 1.0的字段的长度
+const RSDP_V1_LEN: usize = 20;
+/// 系统描述表的表头长度
+const SDT_HEADER_LEN: usize = 36;
+/// SRAT的表头长度：系统描述表的表头，以及12字节的保留字段
+const SRAT_HEADER_LEN: usize = SDT_HEADER_LEN + 12;
+/// SLIT的表头长度：系统描述表的表头，以及8字节的locality数量
+const SLIT_HEADER_LEN: usize = SDT_HEADER_LEN + 8;
+
+/// SRAT中的表项类型
+const SRAT_TYPE_CPU_AFFINITY: u8 = 0;
+const SRAT_TYPE_MEMORY_AFFINITY: u8 = 1;
+const SRAT_TYPE_X2APIC_CPU_AFFINITY: u8 = 2;
+
+/// 表项中的“已启用”标志位
+const SRAT_ENABLED: u32 = 1 << 0;
+/// 内存亲和性表项中的“支持热插拔”标志位
+const SRAT_MEM_HOT_PLUGGABLE: u32 = 1 << 1;
+
+#[inline(always)]
+fn le_u32(data: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(data[off..off + 4].try_into().unwrap())
+}
+
+#[inline(always)]
+fn le_u64(data: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(data[off..off + 8].try_into().unwrap())
+}
+
+/// 把SRAT中的proximity domain映射为连续的节点号
+#[derive(Debug)]
+struct PxmMap {
+    pxms: [u32; MAX_NUMNODES],
+    len: usize,
+}
+
+impl PxmMap {
+    const fn new() -> Self {
+        Self {
+            pxms: [0; MAX_NUMNODES],
+            len: 0,
+        }
+    }
+
+    /// 获取已经出现过的pxm对应的节点
+    fn node(&self, pxm: u32) -> Option<NodeId> {
+        self.pxms[0..self.len]
+            .iter()
+            .position(|x| *x == pxm)
+            .map(NodeId::new)
+    }
+
+    /// 获取pxm对应的节点，第一次出现的pxm分配一个新的节点号
+    fn pxm_to_node(&mut self, pxm: u32) -> Option<NodeId> {
+        if let Some(node) = self.node(pxm) {
+            return Some(node);
+        }
+        if self.len >= MAX_NUMNODES {
+            kwarn!("SRAT: too many proximity domains, ignore pxm {}", pxm);
+            return None;
+        }
+        self.pxms[self.len] = pxm;
+        self.len += 1;
+        return Some(NodeId::new(self.len - 1));
+    }
+}
+
+/// 读取物理内存中的表，并检查校验和
+fn table_checksum<R: EarlyPhysReader>(
+    reader: &mut R,
+    paddr: PhysAddr,
+    len: usize,
+) -> Result<bool, SystemError> {
+    let mut buf = [0u8; 256];
+    let mut sum = 0u8;
+    let mut off = 0;
+    while off < len {
+        let n = core::cmp::min(buf.len(), len - off);
+        reader.read(paddr + off, &mut buf[0..n])?;
+        sum = buf[0..n].iter().fold(sum, |s, x| s.wrapping_add(*x));
+        off += n;
+    }
+    return Ok(sum == 0);
+}
+
+/// 在[start, start + len)中搜索RSDP
+fn scan_rsdp<R: EarlyPhysReader>(
+    reader: &mut R,
+    start: usize,
+    len: usize,
+) -> Option<[u8; RSDP_V2_LEN]> {
+    let mut buf = [0u8; 1024];
+    let mut off = 0;
+    while off < len {
+        let n = core::cmp::min(buf.len(), len - off);
+        reader
+            .read(PhysAddr::new(start + off), &mut buf[0..n])
+            .ok()?;
+        // RSDP按照16字节对齐
+        for i in (0..n).step_by(16) {
+            if &buf[i..core::cmp::min(i + 8, n)] != b"RSD PTR " {
+                continue;
+            }
+            let mut rsdp = [0u8; RSDP_V2_LEN];
+            if reader
+                .read(PhysAddr::new(start + off + i), &mut rsdp)
+                .is_err()
+            {
+                continue;
+            }
+            if rsdp[0..RSDP_V1_LEN]
+                .iter()
+                .fold(0u8, |s, x| s.wrapping_add(*x))
+                == 0
+            {
+                return Some(rsdp);
+            }
+        }
+        off += n;
+    }
+    return None;
+}
+
+/// 获取RSDP。引导程序没有提供时，在EBDA的第一个1KB以及BIOS只读区域中搜索
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/acpica/tbxfroot.c#acpi_find_root_pointer
+fn find_rsdp<R: EarlyPhysReader>(
+    reader: &mut R,
+    boot_rsdp: Option<&[u8]>,
+) -> Option<[u8; RSDP_V2_LEN]> {
+    if let Some(boot_rsdp) = boot_rsdp {
+        let mut rsdp = [0u8; RSDP_V2_LEN];
+        let len = core::cmp::min(boot_rsdp.len(), RSDP_V2_LEN);
+        rsdp[0..len].copy_from_slice(&boot_rsdp[0..len]);
+        return Some(rsdp);
+    }
+
+    let mut ebda = [0u8; 2];
+    if reader.read(PhysAddr::new(0x40e), &mut ebda).is_ok() {
+        let ebda = (u16::from_le_bytes(ebda) as usize) << 4;
+        if ebda > 0x400 {
+            if let Some(rsdp) = scan_rsdp(reader, ebda, 1024) {
+                return Some(rsdp);
+            }
+        }
+    }
+    return scan_rsdp(reader, 0xe0000, 0x20000);
+}
+
+/// 在RSDT/XSDT中查找签名为`signature`的表，返回表的物理地址以及长度
+fn find_table<R: EarlyPhysReader>(
+    reader: &mut R,
+    rsdp: &[u8; RSDP_V2_LEN],
+    signature: &[u8; 4],
+) -> Option<(PhysAddr, usize)> {
+    let revision = rsdp[15];
+    let xsdt = le_u64(rsdp, 24) as usize;
+    let (root, entry_size) = if revision >= 2 && xsdt != 0 {
+        (PhysAddr::new(xsdt), 8)
+    } else {
+        (PhysAddr::new(le_u32(rsdp, 16) as usize), 4)
+    };
+
+    let mut header = [0u8; SDT_HEADER_LEN];
+    reader.read(root, &mut header).ok()?;
+    let root_len = le_u32(&header, 4) as usize;
+    if root_len < SDT_HEADER_LEN {
+        return None;
+    }
+
+    for i in 0..(root_len - SDT_HEADER_LEN) / entry_size {
+        let mut entry = [0u8; 8];
+        reader
+            .read(
+                root + SDT_HEADER_LEN + i * entry_size,
+                &mut entry[0..entry_size],
+            )
+            .ok()?;
+        let table = PhysAddr::new(u64::from_le_bytes(entry) as usize);
+        if table.is_null() {
+            continue;
+        }
+        if reader.read(table, &mut header).is_err() {
+            continue;
+        }
+        if &header[0..4] == signature {
+            return Some((table, le_u32(&header, 4) as usize));
+        }
+    }
+    return None;
+}
+
+/// 解析SRAT中的表项，登记每个节点的内存与CPU
+///
+/// ## 返回值
+///
+/// 登记的内存块数量
+fn parse_srat<R: EarlyPhysReader>(
+    reader: &mut R,
+    srat: PhysAddr,
+    len: usize,
+    pxm_map: &mut PxmMap,
+) -> Result<usize, SystemError> {
+    let mut nr_memblks = 0;
+    let mut offset = SRAT_HEADER_LEN;
+    while offset + 2 <= len {
+        let mut entry = [0u8; 64];
+        reader.read(srat + offset, &mut entry[0..2])?;
+        let entry_type = entry[0];
+        let entry_len = entry[1] as usize;
+        if entry_len < 2 || offset + entry_len > len {
+            kwarn!("SRAT: bad entry at offset {}, len {}", offset, entry_len);
+            break;
+        }
+        let n = core::cmp::min(entry_len, entry.len());
+        reader.read(srat + offset, &mut entry[0..n])?;
+
+        match entry_type {
+            SRAT_TYPE_CPU_AFFINITY if entry_len >= 16 => {
+                if le_u32(&entry, 4) & SRAT_ENABLED != 0 {
+                    let pxm = entry[2] as u32
+                        | (entry[9] as u32) << 8
+                        | (entry[10] as u32) << 16
+                        | (entry[11] as u32) << 24;
+                    // 目前Local APIC ID被直接用作cpu id
+                    let apic_id = entry[3] as usize;
+                    if let Some(node) = pxm_map.pxm_to_node(pxm) {
+                        numa_set_cpu_node(apic_id, node);
+                    }
+                }
+            }
+            SRAT_TYPE_MEMORY_AFFINITY if entry_len >= 40 => {
+                let flags = le_u32(&entry, 28);
+                let base = le_u64(&entry, 8) as usize;
+                let size = le_u64(&entry, 16) as usize;
+                if flags & SRAT_ENABLED != 0 && size != 0 {
+                    if let Some(node) = pxm_map.pxm_to_node(le_u32(&entry, 2)) {
+                        match numa_add_memblk(
+                            PhysAddr::new(base),
+                            size,
+                            node,
+                            flags & SRAT_MEM_HOT_PLUGGABLE != 0,
+                        ) {
+                            Ok(_) => nr_memblks += 1,
+                            Err(e) => kwarn!(
+                                "SRAT: ignore memory [{:#x}, {:#x}): {:?}",
+                                base,
+                                base + size,
+                                e
+                            ),
+                        }
+                    }
+                }
+            }
+            SRAT_TYPE_X2APIC_CPU_AFFINITY if entry_len >= 24 => {
+                if le_u32(&entry, 12) & SRAT_ENABLED != 0 {
+                    let apic_id = le_u32(&entry, 8) as usize;
+                    if let Some(node) = pxm_map.pxm_to_node(le_u32(&entry, 4)) {
+                        numa_set_cpu_node(apic_id, node);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        offset += entry_len;
+    }
+    return Ok(nr_memblks);
+}
+
+/// 解析SLIT，登记节点之间的距离
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/acpi/numa/srat.c#acpi_numa_slit_init
+fn parse_slit<R: EarlyPhysReader>(
+    reader: &mut R,
+    slit: PhysAddr,
+    len: usize,
+    pxm_map: &PxmMap,
+) -> Result<(), SystemError> {
+    if len < SLIT_HEADER_LEN {
+        return Err(SystemError::EINVAL);
+    }
+    let mut count = [0u8; 8];
+    reader.read(slit + SDT_HEADER_LEN, &mut count)?;
+    let count = u64::from_le_bytes(count) as usize;
+    if count > 256 || SLIT_HEADER_LEN + count * count > len {
+        return Err(SystemError::EINVAL);
+    }
+
+    for from in 0..count {
+        let from_node = match pxm_map.node(from as u32) {
+            Some(node) => node,
+            None => continue,
+        };
+        for to in 0..count {
+            let to_node = match pxm_map.node(to as u32) {
+                Some(node) => node,
+                None => continue,
+            };
+            let mut distance = [0u8];
+            reader.read(slit + SLIT_HEADER_LEN + from * count + to, &mut distance)?;
+            numa_set_distance(from_node, to_node, distance[0]);
+        }
+    }
+    return Ok(());
+}
+
+/// 解析SRAT与SLIT，登记NUMA拓扑
+///
+/// 无论是否成功，返回之后NUMA拓扑都是可用的：固件没有提供可用的SRAT时，所有内存与CPU都属于0号节点
+///
+/// ## 参数
+///
+/// - `reader`：读取物理内存的方法
+/// - `boot_rsdp`：引导程序提供的RSDP。为`None`时，在BIOS区域中搜索RSDP
+///
+/// ## 错误
+///
+/// - `ENODEV`：找不到RSDP或者SRAT
+/// - `EINVAL`：SRAT的校验和错误
+pub fn acpi_numa_init<R: EarlyPhysReader>(
+    reader: &mut R,
+    boot_rsdp: Option<&[u8]>,
+) -> Result<(), SystemError> {
+    let r = do_acpi_numa_init(reader, boot_rsdp);
+    if r.is_err() {
+        // 不使用解析了一半的拓扑
+        numa_reset();
+    }
+    numa_register_nodes();
+    return r;
+}
+
+fn do_acpi_numa_init<R: EarlyPhysReader>(
+    reader: &mut R,
+    boot_rsdp: Option<&[u8]>,
+) -> Result<(), SystemError> {
+    let rsdp = find_rsdp(reader, boot_rsdp).ok_or(SystemError::ENODEV)?;
+    let (srat, srat_len) = find_table(reader, &rsdp, b"SRAT").ok_or(SystemError::ENODEV)?;
+    if srat_len < SRAT_HEADER_LEN || !table_checksum(reader, srat, srat_len)? {
+        kwarn!("SRAT: bad table at {:?}", srat);
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut pxm_map = PxmMap::new();
+    let nr_memblks = parse_srat(reader, srat, srat_len, &mut pxm_map)?;
+    kinfo!(
+        "SRAT: {} proximity domain(s), {} memory range(s)",
+        pxm_map.len,
+        nr_memblks
+    );
+
+    if let Some((slit, slit_len)) = find_table(reader, &rsdp, b"SLIT") {
+        if let Err(e) = parse_slit(reader, slit, slit_len, &pxm_map) {
+            kwarn!("SLIT: ignore bad table at {:?}: {:?}", slit, e);
+        }
+    }
+    return Ok(());
+}
@@ -0,0 +1,363 @@
+//! 块设备的性能测试，用法与fio类似
+//!
+//! 开启`blk_bench`特性后，如果启动参数指定了要测试的磁盘，磁盘初始化完成之后就会运行测试，
+//! 结果(IOPS、带宽、延迟的百分位数)输出到内核日志。例如：
+//!
+//! `blk_bench.dev=ahci_disk_0 blk_bench.rw=randread blk_bench.bs=4096 blk_bench.iodepth=4 blk_bench.count=4096`
+//!
+//! 测试只访问`blk_bench.offset`开始的`blk_bench.size`字节。**写测试会覆盖这个区域中的数据！**
+//!
+//! 块设备的读写接口是同步的，队列深度为n时，由n个内核线程同时发起请求。
+//!
+//! 开启方式：`make KERNEL_FEATURES=blk_bench`
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    driver::disk::ahci::get_disks_by_name,
+    init::cmdline::ParamValue,
+    kernel_param, kerror, kinfo,
+    libs::{random::get_random_u64, spinlock::SpinLock, wait_queue::WaitQueue},
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    syscall::SystemError,
+    time::hrtimer::ktime_get,
+};
+
+use super::block_device::{BlockDevice, LBA_SIZE};
+
+/// 测试的读写方式，名字与fio的rw参数相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchPattern {
+    SeqRead,
+    SeqWrite,
+    RandRead,
+    RandWrite,
+}
+
+impl BenchPattern {
+    fn is_write(&self) -> bool {
+        matches!(self, Self::SeqWrite | Self::RandWrite)
+    }
+
+    fn is_random(&self) -> bool {
+        matches!(self, Self::RandRead | Self::RandWrite)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::SeqRead => "read",
+            Self::SeqWrite => "write",
+            Self::RandRead => "randread",
+            Self::RandWrite => "randwrite",
+        }
+    }
+}
+
+impl ParamValue for BenchPattern {
+    fn parse_param(value: &str) -> Option<Self> {
+        match value {
+            "read" => Some(Self::SeqRead),
+            "write" => Some(Self::SeqWrite),
+            "randread" => Some(Self::RandRead),
+            "randwrite" => Some(Self::RandWrite),
+            _ => None,
+        }
+    }
+}
+
+// 启动参数，含义见BenchConfig的各个字段
+kernel_param!(BENCH_DEV_PARAM: String = "blk_bench.dev");
+kernel_param!(BENCH_RW_PARAM: BenchPattern = "blk_bench.rw");
+kernel_param!(BENCH_BS_PARAM: usize = "blk_bench.bs");
+kernel_param!(BENCH_IODEPTH_PARAM: usize = "blk_bench.iodepth");
+kernel_param!(BENCH_COUNT_PARAM: usize = "blk_bench.count");
+kernel_param!(BENCH_OFFSET_PARAM: usize = "blk_bench.offset");
+kernel_param!(BENCH_SIZE_PARAM: usize = "blk_bench.size");
+
+/// @brief 一次测试的参数
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub pattern: BenchPattern,
+    /// 每个请求的字节数，必须是LBA_SIZE的整数倍
+    pub block_size: usize,
+    /// 同时发起请求的线程数
+    pub iodepth: usize,
+    /// 请求的总数
+    pub count: usize,
+    /// 测试区域的起始位置(单位：字节)
+    pub offset: usize,
+    /// 测试区域的大小(单位：字节)
+    pub size: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            pattern: BenchPattern::RandRead,
+            block_size: 4096,
+            iodepth: 1,
+            count: 1024,
+            // 跳过第一个MiB，分区表位于这里
+            offset: 1 << 20,
+            size: 64 << 20,
+        }
+    }
+}
+
+impl BenchConfig {
+    /// @brief 从启动参数读取测试的参数，没有指定的参数使用默认值
+    pub fn from_cmdline() -> Self {
+        let d = Self::default();
+        return Self {
+            pattern: BENCH_RW_PARAM.get().unwrap_or(d.pattern),
+            block_size: BENCH_BS_PARAM.get().unwrap_or(d.block_size),
+            iodepth: BENCH_IODEPTH_PARAM.get().unwrap_or(d.iodepth),
+            count: BENCH_COUNT_PARAM.get().unwrap_or(d.count),
+            offset: BENCH_OFFSET_PARAM.get().unwrap_or(d.offset),
+            size: BENCH_SIZE_PARAM.get().unwrap_or(d.size),
+        };
+    }
+
+    fn check(&self) -> Result<(), SystemError> {
+        if self.block_size == 0
+            || self.block_size % LBA_SIZE != 0
+            || self.offset % LBA_SIZE != 0
+            || self.size < self.block_size
+            || self.iodepth == 0
+            || self.count == 0
+        {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(());
+    }
+
+    /// 第`index`个请求的位置(单位：字节)
+    fn position(&self, index: usize) -> usize {
+        let blocks = self.size / self.block_size;
+        let block = if self.pattern.is_random() {
+            (get_random_u64() % blocks as u64) as usize
+        } else {
+            index % blocks
+        };
+        return self.offset + block * self.block_size;
+    }
+}
+
+/// @brief 测试的结果
+#[derive(Debug)]
+pub struct BenchResult {
+    /// 成功完成的请求的延迟(单位：纳秒)，从小到大排序
+    latencies: Vec<u64>,
+    /// 失败的请求的数量
+    errors: usize,
+    /// 测试的总时长(单位：纳秒)
+    elapsed_ns: u64,
+    block_size: usize,
+}
+
+impl BenchResult {
+    /// @brief 每秒完成的请求数
+    pub fn iops(&self) -> u64 {
+        return self.latencies.len() as u64 * 1_000_000_000 / core::cmp::max(self.elapsed_ns, 1);
+    }
+
+    /// @brief 带宽(单位：KiB/s)
+    pub fn bandwidth_kib(&self) -> u64 {
+        return self.iops() * self.block_size as u64 / 1024;
+    }
+
+    /// @brief 延迟的百分位数(单位：纳秒)
+    ///
+    /// @param permille 千分位，例如999表示p99.9
+    pub fn percentile(&self, permille: usize) -> u64 {
+        if self.latencies.is_empty() {
+            return 0;
+        }
+        let index = (self.latencies.len() * permille / 1000).min(self.latencies.len() - 1);
+        return self.latencies[index];
+    }
+
+    fn average(&self) -> u64 {
+        if self.latencies.is_empty() {
+            return 0;
+        }
+        return self.latencies.iter().sum::<u64>() / self.latencies.len() as u64;
+    }
+}
+
+/// 一次测试中各个线程共享的状态
+struct BenchJob {
+    dev: Arc<dyn BlockDevice>,
+    config: BenchConfig,
+    /// 下一个要发起的请求的序号
+    next: AtomicUsize,
+    state: SpinLock<BenchState>,
+    /// 等待所有线程结束
+    wait_queue: WaitQueue,
+}
+
+struct BenchState {
+    latencies: Vec<u64>,
+    errors: usize,
+    /// 已经结束的线程的数量
+    finished: usize,
+}
+
+impl BenchJob {
+    /// 发起请求的线程：不断领取下一个请求，直到所有请求都已经发起
+    fn worker(&self) -> i32 {
+        let cfg = &self.config;
+        let lba_count = cfg.block_size / LBA_SIZE;
+        let mut buf = vec![0u8; cfg.block_size];
+        if cfg.pattern.is_write() {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = i as u8;
+            }
+        }
+
+        let mut latencies = Vec::new();
+        let mut errors = 0;
+        loop {
+            let index = self.next.fetch_add(1, Ordering::SeqCst);
+            if index >= cfg.count {
+                break;
+            }
+            let lba = cfg.position(index) / LBA_SIZE;
+            let start = ktime_get();
+            let r = if cfg.pattern.is_write() {
+                self.dev.write_at(lba, lba_count, &buf)
+            } else {
+                self.dev.read_at(lba, lba_count, &mut buf)
+            };
+            match r {
+                Ok(_) => latencies.push(ktime_get() - start),
+                Err(_) => errors += 1,
+            }
+        }
+
+        let mut state = self.state.lock_irqsave();
+        state.latencies.append(&mut latencies);
+        state.errors += errors;
+        state.finished += 1;
+        drop(state);
+        self.wait_queue.wakeup_all(None);
+        return 0;
+    }
+}
+
+/// @brief 对块设备运行一次测试
+///
+/// @param dev 要测试的块设备
+/// @param config 测试的参数
+///
+/// @return 测试的结果。参数不合法时返回EINVAL
+pub fn blk_bench(
+    dev: Arc<dyn BlockDevice>,
+    config: &BenchConfig,
+) -> Result<BenchResult, SystemError> {
+    config.check()?;
+    let job = Arc::new(BenchJob {
+        dev,
+        config: config.clone(),
+        next: AtomicUsize::new(0),
+        state: SpinLock::new(BenchState {
+            latencies: Vec::with_capacity(config.count),
+            errors: 0,
+            finished: 0,
+        }),
+        wait_queue: WaitQueue::INIT,
+    });
+
+    let start = ktime_get();
+    let mut started = 0;
+    for i in 0..config.iodepth {
+        let j = job.clone();
+        let r = KernelThreadMechanism::create_and_run(
+            KernelThreadClosure::EmptyClosure((Box::new(move || j.worker()), ())),
+            format!("blk_bench/{}", i),
+        );
+        if r.is_some() {
+            started += 1;
+        }
+    }
+    if started == 0 {
+        return Err(SystemError::ENOMEM);
+    }
+
+    loop {
+        let state = job.state.lock_irqsave();
+        if state.finished == started {
+            break;
+        }
+        job.wait_queue.sleep_unlock_spinlock(state);
+    }
+    let elapsed_ns = ktime_get() - start;
+
+    let mut state = job.state.lock_irqsave();
+    let mut latencies = core::mem::take(&mut state.latencies);
+    latencies.sort_unstable();
+    return Ok(BenchResult {
+        latencies,
+        errors: state.errors,
+        elapsed_ns,
+        block_size: config.block_size,
+    });
+}
+
+/// @brief 如果启动参数指定了要测试的磁盘，则运行测试，并把结果输出到日志
+pub fn blk_bench_init() {
+    let name = match BENCH_DEV_PARAM.get() {
+        Some(name) => name,
+        None => return,
+    };
+    let dev = match get_disks_by_name(name.clone()) {
+        Ok(disk) => disk as Arc<dyn BlockDevice>,
+        Err(e) => {
+            kerror!("blk_bench: disk {} not found: {:?}", name, e);
+            return;
+        }
+    };
+
+    let config = BenchConfig::from_cmdline();
+    kinfo!(
+        "blk_bench: {} rw={} bs={} iodepth={} count={} offset={} size={}",
+        name,
+        config.pattern.name(),
+        config.block_size,
+        config.iodepth,
+        config.count,
+        config.offset,
+        config.size
+    );
+    let result = match blk_bench(dev, &config) {
+        Ok(result) => result,
+        Err(e) => {
+            kerror!("blk_bench: failed: {:?}", e);
+            return;
+        }
+    };
+
+    let us = |ns: u64| ns / 1000;
+    kinfo!(
+        "blk_bench: {}: ios={} errors={} time={}ms iops={} bw={}KiB/s",
+        name,
+        result.latencies.len(),
+        result.errors,
+        result.elapsed_ns / 1_000_000,
+        result.iops(),
+        result.bandwidth_kib()
+    );
+    kinfo!(
+        "blk_bench: {}: lat(us) min={} avg={} max={} p50={} p90={} p99={} p99.9={}",
+        name,
+        us(result.percentile(0)),
+        us(result.average()),
+        us(result.percentile(1000)),
+        us(result.percentile(500)),
+        us(result.percentile(900)),
+        us(result.percentile(990)),
+        us(result.percentile(999))
+    );
+}
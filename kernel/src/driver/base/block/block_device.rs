@@ -13,7 +13,7 @@ use crate::{
 use alloc::{sync::Arc, vec::Vec};
 use core::any::Any;
 
-use super::disk_info::Partition;
+use super::{disk_info::Partition, writeback::BackingDevInfo};
 
 /// 该文件定义了 Device 和 BlockDevice 的接口
 /// Notice 设备错误码使用 Posix 规定的 int32_t 的错误码表示，而不是自己定义错误enum
@@ -235,6 +235,13 @@ pub trait BlockDevice: Device {
     /// @brief 返回当前磁盘上的所有分区的Arc指针数组
     fn partitions(&self) -> Vec<Arc<Partition>>;
 
+    /// @brief 返回设备的回写状态，缓存通过它记录脏数据并对写者限流
+    ///
+    /// @return 不支持回写的设备返回None
+    fn bdi(&self) -> Option<Arc<BackingDevInfo>> {
+        None
+    }
+
     fn write_at_bytes(&self, offset: usize, len: usize, buf: &[u8]) -> Result<usize, SystemError> {
         // assert!(len <= buf.len());
         if len > buf.len() {
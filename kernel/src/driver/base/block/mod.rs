@@ -1,5 +1,9 @@
+#[cfg(feature = "blk_bench")]
+pub mod bench;
 pub mod block_device;
 pub mod disk_info;
+pub mod request;
+pub mod writeback;
 
 #[derive(Debug)]
 #[allow(dead_code)]
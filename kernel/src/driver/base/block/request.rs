@@ -0,0 +1,81 @@
+//! 块设备请求的超时与取消
+//!
+//! 块设备驱动在下发请求时创建一个[`BlockRequestDeadline`]，等待请求完成的过程中检查它是否已经超时。
+//! 超时的请求由驱动负责复位硬件并返回ETIMEDOUT，避免调用者永远等待一个卡住的设备。
+//!
+//! 还没有下发给设备的请求，如果发起它的进程已经被杀死，就不再下发，直接返回EINTR。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/block/blk-timeout.c
+
+use crate::{kernel_param, process::ProcessManager, syscall::SystemError, time::timer::clock};
+
+/// 请求的默认超时时间(单位：毫秒)，与Linux中SCSI磁盘的默认值相同
+pub const BLOCK_DEFAULT_TIMEOUT_MS: u64 = 30 * 1000;
+
+// 启动参数，请求的超时时间(单位：毫秒)
+kernel_param!(BLOCK_TIMEOUT_PARAM: u64 = "block.timeout");
+
+/// 获取请求的超时时间(单位：毫秒)
+pub fn block_request_timeout_ms() -> u64 {
+    return match BLOCK_TIMEOUT_PARAM.get() {
+        Some(ms) if ms > 0 => ms,
+        _ => BLOCK_DEFAULT_TIMEOUT_MS,
+    };
+}
+
+/// 一个块设备请求的截止时间
+#[derive(Debug, Clone, Copy)]
+pub struct BlockRequestDeadline {
+    /// 超时的时刻(单位：jiffies)
+    expire_jiffies: u64,
+}
+
+impl BlockRequestDeadline {
+    /// 创建一个从现在开始、使用默认超时时间的截止时间
+    pub fn new() -> Self {
+        return Self::after_ms(block_request_timeout_ms());
+    }
+
+    /// 创建一个在`ms`毫秒之后到期的截止时间
+    pub fn after_ms(ms: u64) -> Self {
+        return Self {
+            expire_jiffies: clock() + ms * 1000,
+        };
+    }
+
+    /// 是否已经超时
+    #[inline]
+    pub fn expired(&self) -> bool {
+        return clock() >= self.expire_jiffies;
+    }
+
+    /// 已经超时的话，返回ETIMEDOUT
+    #[inline]
+    pub fn check(&self) -> Result<(), SystemError> {
+        if self.expired() {
+            return Err(SystemError::ETIMEDOUT);
+        }
+        return Ok(());
+    }
+}
+
+impl Default for BlockRequestDeadline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 在把请求下发给设备之前调用，如果当前进程已经被杀死，则取消这个请求
+///
+/// ## 返回值
+///
+/// - `Ok(())`：可以继续下发请求
+/// - `Err(SystemError::EINTR)`：当前进程已经收到了SIGKILL，请求被取消
+pub fn block_request_check_cancel() -> Result<(), SystemError> {
+    // 进程管理初始化之前，没有可以被杀死的进程
+    if ProcessManager::initialized() && ProcessManager::current_pcb().sig_info().has_fatal_signal()
+    {
+        return Err(SystemError::EINTR);
+    }
+    return Ok(());
+}
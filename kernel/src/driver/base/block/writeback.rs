@@ -0,0 +1,277 @@
+//! 块设备的脏数据回写与写者限流
+//!
+//! 每个块设备有一个[`BackingDevInfo`]，记录这个设备上还没有写回的脏数据。缓存(页缓存、块缓存等)
+//! 通过[`BackingDevInfo::add_target`]挂到设备上，在把数据标记为脏时调用[`BackingDevInfo::account_dirtied`]，
+//! 把数据写回设备之后调用[`BackingDevInfo::account_cleaned`]。
+//!
+//! 写者在弄脏数据之后调用[`balance_dirty_pages`]：
+//! - 脏数据超过后台回写阈值时，唤醒回写线程
+//! - 脏数据超过上限时，写者睡眠，直到回写线程把脏数据写回到上限以下
+//!
+//! 回写线程除了被写者唤醒之外，每隔[`DIRTY_WRITEBACK_INTERVAL_MS`]也会把所有设备上的脏数据写回一次。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/mm/page-writeback.c
+
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    arch::{mm::LockedFrameAllocator, sched::sched, CurrentIrqArch},
+    exception::InterruptArch,
+    kwarn,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        ProcessManager,
+    },
+    syscall::SystemError,
+    time::timer::{next_n_ms_timer_jiffies, Timer, WakeUpHelper},
+};
+
+use super::request::block_request_check_cancel;
+
+/// 脏数据占内存总量的百分比超过这个值时，开始在后台回写
+pub const DIRTY_BACKGROUND_RATIO: usize = 10;
+/// 脏数据占内存总量的百分比超过这个值时，写者需要等待回写
+pub const DIRTY_RATIO: usize = 20;
+/// 回写线程定期回写的周期(单位：毫秒)
+pub const DIRTY_WRITEBACK_INTERVAL_MS: u64 = 5000;
+/// 被限流的写者每次最多睡眠的时间(单位：毫秒)
+const DIRTY_MAX_PAUSE_MS: u64 = 200;
+
+/// 所有设备上的脏数据的字节数
+static NR_DIRTY: AtomicUsize = AtomicUsize::new(0);
+/// 注册过的设备，设备被释放之后，回写线程会把它从列表中移除
+static BDI_LIST: SpinLock<Vec<Weak<BackingDevInfo>>> = SpinLock::new(Vec::new());
+/// 回写线程在这个队列上等待
+static WRITEBACK_WAIT: WaitQueue = WaitQueue::INIT;
+
+/// @brief 持有某个设备的脏数据的缓存
+pub trait WritebackTarget: Send + Sync + Debug {
+    /// @brief 把最多nr_bytes字节的脏数据写回设备
+    ///
+    /// 写回的数据需要通过[`BackingDevInfo::account_cleaned`]扣除
+    ///
+    /// @return 成功写回的字节数
+    fn writeback(&self, nr_bytes: usize) -> Result<usize, SystemError>;
+}
+
+/// @brief 一个块设备的回写状态
+#[derive(Debug)]
+pub struct BackingDevInfo {
+    name: String,
+    /// 这个设备上的脏数据的字节数
+    dirty: AtomicUsize,
+    /// 设备自己的后台回写阈值(单位：字节)，为0时使用全局的阈值
+    background_thresh: AtomicUsize,
+    /// 设备自己的脏数据上限(单位：字节)，为0时使用全局的上限
+    dirty_thresh: AtomicUsize,
+    /// 挂在这个设备上的缓存
+    targets: SpinLock<Vec<Weak<dyn WritebackTarget>>>,
+    /// 被限流的写者在这个队列上等待
+    throttle_wait: WaitQueue,
+}
+
+impl BackingDevInfo {
+    /// @brief 创建一个设备的回写状态，并把它注册给回写线程
+    pub fn new(name: String) -> Arc<Self> {
+        let bdi = Arc::new(Self {
+            name,
+            dirty: AtomicUsize::new(0),
+            background_thresh: AtomicUsize::new(0),
+            dirty_thresh: AtomicUsize::new(0),
+            targets: SpinLock::new(Vec::new()),
+            throttle_wait: WaitQueue::INIT,
+        });
+        BDI_LIST.lock_irqsave().push(Arc::downgrade(&bdi));
+        return bdi;
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// @brief 把一个缓存挂到这个设备上，缓存被释放之后会被自动移除
+    pub fn add_target(&self, target: Weak<dyn WritebackTarget>) {
+        self.targets.lock_irqsave().push(target);
+    }
+
+    /// @brief 记录新产生的脏数据
+    pub fn account_dirtied(&self, nr_bytes: usize) {
+        self.dirty.fetch_add(nr_bytes, Ordering::SeqCst);
+        NR_DIRTY.fetch_add(nr_bytes, Ordering::SeqCst);
+    }
+
+    /// @brief 记录已经写回的脏数据
+    pub fn account_cleaned(&self, nr_bytes: usize) {
+        let sub = |v: usize| Some(v.saturating_sub(nr_bytes));
+        self.dirty
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, sub)
+            .ok();
+        NR_DIRTY
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, sub)
+            .ok();
+    }
+
+    /// @brief 这个设备上的脏数据的字节数
+    pub fn dirty_bytes(&self) -> usize {
+        self.dirty.load(Ordering::SeqCst)
+    }
+
+    /// @brief 设置设备自己的回写阈值
+    ///
+    /// @param background 后台回写阈值(单位：字节)
+    /// @param limit 脏数据上限(单位：字节)
+    ///
+    /// 两者都为0时恢复使用全局的阈值
+    ///
+    /// @return 后台回写阈值不小于上限时，返回EINVAL
+    pub fn set_thresholds(&self, background: usize, limit: usize) -> Result<(), SystemError> {
+        if (background, limit) != (0, 0) && background >= limit {
+            return Err(SystemError::EINVAL);
+        }
+        self.background_thresh.store(background, Ordering::SeqCst);
+        self.dirty_thresh.store(limit, Ordering::SeqCst);
+        return Ok(());
+    }
+
+    /// @brief 获取这个设备的回写阈值
+    ///
+    /// @return (后台回写阈值, 脏数据上限)，单位为字节
+    pub fn thresholds(&self) -> (usize, usize) {
+        let limit = self.dirty_thresh.load(Ordering::SeqCst);
+        if limit == 0 {
+            return global_dirty_limits();
+        }
+        return (self.background_thresh.load(Ordering::SeqCst), limit);
+    }
+
+    /// @brief 让挂在这个设备上的缓存写回最多nr_bytes字节的脏数据
+    ///
+    /// @return 写回的字节数
+    fn writeback(&self, nr_bytes: usize) -> usize {
+        let targets: Vec<Arc<dyn WritebackTarget>> = {
+            let mut guard = self.targets.lock_irqsave();
+            guard.retain(|t| t.strong_count() > 0);
+            guard.iter().filter_map(|t| t.upgrade()).collect()
+        };
+
+        let mut written = 0;
+        for target in targets {
+            if written >= nr_bytes {
+                break;
+            }
+            match target.writeback(nr_bytes - written) {
+                Ok(n) => written += n,
+                Err(e) => kwarn!("{}: writeback failed: {:?}", self.name, e),
+            }
+        }
+        return written;
+    }
+}
+
+/// @brief 所有设备上的脏数据的字节数
+pub fn global_dirty_bytes() -> usize {
+    NR_DIRTY.load(Ordering::SeqCst)
+}
+
+/// @brief 根据内存总量计算全局的回写阈值
+///
+/// @return (后台回写阈值, 脏数据上限)，单位为字节
+pub fn global_dirty_limits() -> (usize, usize) {
+    let total = LockedFrameAllocator.get_usage().total().bytes();
+    return (
+        total / 100 * DIRTY_BACKGROUND_RATIO,
+        total / 100 * DIRTY_RATIO,
+    );
+}
+
+/// @brief 唤醒回写线程
+pub fn wakeup_flusher_thread() {
+    WRITEBACK_WAIT.wakeup(None);
+}
+
+/// @brief 写者在弄脏数据之后调用，脏数据过多时唤醒回写线程，并让写者等待
+///
+/// 已经被杀死的进程不再等待，以便尽快退出
+///
+/// @param bdi 写入的设备
+pub fn balance_dirty_pages(bdi: &Arc<BackingDevInfo>) {
+    let (global_background, global_limit) = global_dirty_limits();
+    loop {
+        let (background, limit) = bdi.thresholds();
+        let dirty = bdi.dirty_bytes();
+        let global_dirty = global_dirty_bytes();
+
+        if dirty <= background && global_dirty <= global_background {
+            return;
+        }
+        wakeup_flusher_thread();
+
+        if dirty <= limit && global_dirty <= global_limit {
+            return;
+        }
+        if block_request_check_cancel().is_err() {
+            return;
+        }
+        // 回写线程每完成一轮就会唤醒写者，这里的超时只是为了防止唤醒丢失
+        sleep_timeout(&bdi.throttle_wait, DIRTY_MAX_PAUSE_MS);
+    }
+}
+
+/// 在等待队列上睡眠，直到被唤醒或者超时
+fn sleep_timeout(wait_queue: &WaitQueue, timeout_ms: u64) {
+    let pcb = ProcessManager::current_pcb();
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    unsafe { wait_queue.sleep_without_schedule() };
+    let timer = Timer::new(
+        WakeUpHelper::new(pcb.clone()),
+        next_n_ms_timer_jiffies(timeout_ms),
+    );
+    timer.activate();
+    drop(irq_guard);
+    sched();
+
+    // 被定时器唤醒时，进程还在等待队列中
+    wait_queue.remove(&pcb);
+}
+
+/// @brief 启动回写线程
+pub fn writeback_init() -> Result<(), SystemError> {
+    KernelThreadMechanism::create_and_run(
+        KernelThreadClosure::EmptyClosure((Box::new(writeback_thread), ())),
+        String::from("writeback"),
+    )
+    .ok_or(SystemError::ENOMEM)?;
+    return Ok(());
+}
+
+/// 回写线程：被唤醒或者定期地把所有设备上的脏数据写回，之后唤醒被限流的写者
+fn writeback_thread() -> i32 {
+    loop {
+        let bdis: Vec<Arc<BackingDevInfo>> = {
+            let mut guard = BDI_LIST.lock_irqsave();
+            guard.retain(|bdi| bdi.strong_count() > 0);
+            guard.iter().filter_map(|bdi| bdi.upgrade()).collect()
+        };
+
+        for bdi in bdis {
+            let dirty = bdi.dirty_bytes();
+            if dirty > 0 {
+                bdi.writeback(dirty);
+            }
+            bdi.throttle_wait.wakeup_all(None);
+        }
+
+        sleep_timeout(&WRITEBACK_WAIT, DIRTY_WRITEBACK_INTERVAL_MS);
+    }
+}
@@ -1,19 +1,441 @@
-use alloc::{string::ToString, sync::Arc};
+//! 设备类(class)
+//!
+//! class把同一类的设备(如所有的块设备、所有的tty)组织在`/sys/class/<class>`下，而不管它们挂在哪条总线上。
+//! 设备被添加到系统中时，驱动核心为它创建`/sys/class/<class>/<设备名>`链接，
+//! 并且根据class的devnode回调决定设备文件在`/dev`下的路径。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/class.c
 
-use crate::syscall::SystemError;
+use core::{any::Any, fmt::Debug};
 
-use super::kset::KSet;
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    filesystem::{
+        devfs::{devfs_register, devfs_unregister_node, DeviceINode},
+        kernfs::KernFSInode,
+        sysfs::{sysfs_instance, AttributeGroup},
+        vfs::FileType,
+    },
+    libs::{
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
+    },
+    syscall::SystemError,
+};
+
+use super::{
+    device::{
+        bus::Bus, device_manager, driver::Driver, sys_dev_block_kset, sys_devices_virtual_kset,
+        Device, DeviceKObjType, DeviceNumber, DeviceType, IdTable,
+    },
+    kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+    kset::KSet,
+    subsys::SubSysPrivate,
+};
 
 /// `/sys/class`的kset
 static mut CLASS_KSET_INSTANCE: Option<Arc<KSet>> = None;
 
+static mut BLOCK_CLASS_INSTANCE: Option<Arc<GenericClass>> = None;
+static mut NET_CLASS_INSTANCE: Option<Arc<GenericClass>> = None;
+static mut INPUT_CLASS_INSTANCE: Option<Arc<GenericClass>> = None;
+static mut TTY_CLASS_INSTANCE: Option<Arc<GenericClass>> = None;
+
+/// 通过`devnode_register`创建了设备文件的设备，以及设备文件的路径与类型。设备被删除时据此删除设备文件
+static DEVNODES: SpinLock<Vec<(Weak<dyn Device>, String, FileType)>> = SpinLock::new(Vec::new());
+
 #[inline(always)]
-#[allow(dead_code)]
 pub fn sys_class_kset() -> Arc<KSet> {
     unsafe { CLASS_KSET_INSTANCE.clone().unwrap() }
 }
 
-/// 初始化`/sys/class`的kset
+/// 块设备的class，`/sys/class/block`
+#[inline(always)]
+pub fn block_class() -> Arc<dyn Class> {
+    unsafe { BLOCK_CLASS_INSTANCE.clone().unwrap() }
+}
+
+/// 网络设备的class，`/sys/class/net`
+#[inline(always)]
+#[allow(dead_code)]
+pub fn net_class() -> Arc<dyn Class> {
+    unsafe { NET_CLASS_INSTANCE.clone().unwrap() }
+}
+
+/// 输入设备的class，`/sys/class/input`。设备文件位于`/dev/input`下
+#[inline(always)]
+pub fn input_class() -> Arc<dyn Class> {
+    unsafe { INPUT_CLASS_INSTANCE.clone().unwrap() }
+}
+
+/// tty设备的class，`/sys/class/tty`
+#[inline(always)]
+pub fn tty_class() -> Arc<dyn Class> {
+    unsafe { TTY_CLASS_INSTANCE.clone().unwrap() }
+}
+
+#[inline(always)]
+pub fn class_manager() -> &'static ClassManager {
+    &ClassManager
+}
+
+/// 设备类应该实现的操作
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/device/class.h?fi=class#class
+pub trait Class: Debug + Send + Sync {
+    /// class的名字，也是`/sys/class`下的目录名
+    fn name(&self) -> &'static str;
+
+    /// 属于这个class的设备的类型
+    fn dev_type(&self) -> DeviceType;
+
+    /// 属于这个class的设备在`/sys/dev`下的目录。为None时使用`/sys/dev/char`
+    fn dev_kobj(&self) -> Option<Arc<dyn KObject>> {
+        None
+    }
+
+    /// 设备文件相对于`/dev`的路径。为None时使用设备名
+    fn devnode(&self, _dev: &Arc<dyn Device>) -> Option<String> {
+        None
+    }
+
+    /// class的默认属性组，创建在`/sys/class/<class>`下
+    fn class_groups(&self) -> &'static [&'static dyn AttributeGroup] {
+        &[]
+    }
+
+    /// 属于这个class的设备的默认属性组
+    fn dev_groups(&self) -> &'static [&'static dyn AttributeGroup] {
+        &[]
+    }
+
+    fn subsystem(&self) -> &SubSysPrivate;
+}
+
+/// class管理器
+#[derive(Debug)]
+pub struct ClassManager;
+
+impl ClassManager {
+    /// 注册一个class，创建`/sys/class/<class>`以及`/sys/devices/virtual/<class>`目录
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/class.c?fi=__class_register#__class_register
+    pub fn class_register(&self, class: &Arc<dyn Class>) -> Result<(), SystemError> {
+        let subsystem = class.subsystem();
+        subsystem.set_class(Arc::downgrade(class));
+
+        let subsys_kset = subsystem.subsys();
+        subsys_kset.set_name(class.name().to_string());
+        subsys_kset.register(Some(sys_class_kset()))?;
+
+        let devices_kset = KSet::new_and_add(
+            class.name().to_string(),
+            Some(sys_devices_virtual_kset() as Arc<dyn KObject>),
+            None,
+        )?;
+        subsystem.set_devices_kset(devices_kset);
+
+        let class_kobj = subsys_kset as Arc<dyn KObject>;
+        sysfs_instance().create_groups(&class_kobj, class.class_groups())?;
+        return Ok(());
+    }
+
+    /// 创建一个属于指定class的设备，并把它添加到系统中
+    ///
+    /// 这个函数只创建设备在sysfs中的目录，不创建设备文件
+    ///
+    /// ## 参数
+    ///
+    /// - `class`: 设备所属的class
+    /// - `parent`: 父设备，为None时设备位于`/sys/devices/virtual/<class>`下
+    /// - `devt`: 设备号
+    /// - `name`: 设备名
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_create#device_create
+    pub fn device_create(
+        &self,
+        class: Arc<dyn Class>,
+        parent: Option<&Arc<dyn Device>>,
+        devt: DeviceNumber,
+        name: String,
+    ) -> Result<Arc<ClassDevice>, SystemError> {
+        let dev = ClassDevice::new(class, devt, name);
+        if let Some(parent) = parent {
+            let parent = parent.clone() as Arc<dyn KObject>;
+            dev.set_parent(Some(Arc::downgrade(&parent)));
+        }
+        device_manager().register(dev.clone())?;
+        return Ok(dev);
+    }
+
+    /// 创建一个属于指定class的设备，并在devfs中为它创建设备文件
+    ///
+    /// 设备号取自设备文件的元数据，设备文件的路径由class的devnode回调决定
+    ///
+    /// ## 参数
+    ///
+    /// - `class`: 设备所属的class
+    /// - `parent`: 父设备，为None时设备位于`/sys/devices/virtual/<class>`下
+    /// - `name`: 设备名
+    /// - `inode`: 设备文件
+    pub fn device_create_with_inode<T: DeviceINode>(
+        &self,
+        class: Arc<dyn Class>,
+        parent: Option<&Arc<dyn Device>>,
+        name: String,
+        inode: Arc<T>,
+    ) -> Result<Arc<ClassDevice>, SystemError> {
+        let devt = DeviceNumber::new(inode.metadata()?.raw_dev);
+        let dev = self.device_create(class, parent, devt, name)?;
+        self.devnode_register(&(dev.clone() as Arc<dyn Device>), inode)?;
+        return Ok(dev);
+    }
+
+    /// 从系统中删除`device_create`创建的设备，同时删除它的设备文件
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_destroy#device_destroy
+    #[allow(dead_code)]
+    pub fn device_destroy(&self, dev: Arc<ClassDevice>) {
+        device_manager().unregister(dev);
+    }
+
+    /// 在devfs中为属于某个class的设备创建设备文件，路径由class的devnode回调决定
+    pub fn devnode_register<T: DeviceINode>(
+        &self,
+        dev: &Arc<dyn Device>,
+        inode: Arc<T>,
+    ) -> Result<(), SystemError> {
+        let devnode = device_manager().device_get_devnode(dev);
+        let file_type = inode.metadata()?.file_type;
+        devfs_register(&devnode, inode)?;
+        DEVNODES
+            .lock()
+            .push((Arc::downgrade(dev), devnode, file_type));
+        return Ok(());
+    }
+
+    /// 删除`devnode_register`为设备创建的设备文件
+    pub fn devnode_unregister(&self, dev: &Arc<dyn Device>) {
+        let ptr = Arc::as_ptr(dev) as *const ();
+        let mut devnodes = DEVNODES.lock();
+        let index = devnodes
+            .iter()
+            .position(|(d, _, _)| d.as_ptr() as *const () == ptr);
+        if let Some(index) = index {
+            let (_, devnode, file_type) = devnodes.remove(index);
+            drop(devnodes);
+            if let Err(e) = devfs_unregister_node(&devnode, file_type) {
+                kwarn!("failed to remove devnode '{}': {:?}", devnode, e);
+            }
+        }
+    }
+}
+
+/// devnode回调
+pub type ClassDevnodeFn = fn(&Arc<dyn Device>) -> Option<String>;
+
+/// 只需要名字、设备类型与devnode回调的class
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/class.c?fi=__class_create#__class_create
+#[derive(Debug)]
+pub struct GenericClass {
+    name: &'static str,
+    dev_type: DeviceType,
+    dev_kobj: Option<Arc<dyn KObject>>,
+    devnode: Option<ClassDevnodeFn>,
+    subsystem: SubSysPrivate,
+}
+
+impl GenericClass {
+    pub fn new(
+        name: &'static str,
+        dev_type: DeviceType,
+        dev_kobj: Option<Arc<dyn KObject>>,
+        devnode: Option<ClassDevnodeFn>,
+    ) -> Arc<Self> {
+        return Arc::new(Self {
+            name,
+            dev_type,
+            dev_kobj,
+            devnode,
+            subsystem: SubSysPrivate::new_class(name.to_string()),
+        });
+    }
+}
+
+impl Class for GenericClass {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn dev_type(&self) -> DeviceType {
+        self.dev_type
+    }
+
+    fn dev_kobj(&self) -> Option<Arc<dyn KObject>> {
+        self.dev_kobj.clone()
+    }
+
+    fn devnode(&self, dev: &Arc<dyn Device>) -> Option<String> {
+        self.devnode.and_then(|f| f(dev))
+    }
+
+    fn subsystem(&self) -> &SubSysPrivate {
+        &self.subsystem
+    }
+}
+
+/// 输入设备的设备文件位于`/dev/input`下
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/input.c?fi=input_devnode#input_devnode
+fn input_devnode(dev: &Arc<dyn Device>) -> Option<String> {
+    Some(format!("input/{}", dev.name()))
+}
+
+/// 由[`ClassManager::device_create`]创建的设备，只用于在sysfs中表示一个设备文件
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_create_groups_vargs#device_create_groups_vargs
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct ClassDevice {
+    devt: DeviceNumber,
+    class: Arc<dyn Class>,
+    inner: RwLock<InnerClassDevice>,
+    kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+struct InnerClassDevice {
+    name: String,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    inode: Option<Arc<KernFSInode>>,
+    driver: Option<Weak<dyn Driver>>,
+    can_match: bool,
+}
+
+impl ClassDevice {
+    fn new(class: Arc<dyn Class>, devt: DeviceNumber, name: String) -> Arc<Self> {
+        return Arc::new(Self {
+            devt,
+            class,
+            inner: RwLock::new(InnerClassDevice {
+                name,
+                kset: None,
+                parent_kobj: None,
+                inode: None,
+                driver: None,
+                can_match: false,
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        });
+    }
+}
+
+impl Device for ClassDevice {
+    fn dev_type(&self) -> DeviceType {
+        self.class.dev_type()
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(self.name(), self.devt)
+    }
+
+    fn release(&self) {}
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        Some(self.class.clone())
+    }
+
+    fn set_bus(&self, _bus: Option<Arc<dyn Bus>>) {}
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        self.inner.read().driver.clone()?.upgrade()
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner.write().driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner.read().can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner.write().can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+}
+
+impl KObject for ClassDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        Some(&DeviceKObjType)
+    }
+
+    fn set_kobj_type(&self, _ktype: Option<&'static dyn KObjType>) {}
+
+    fn name(&self) -> String {
+        self.inner.read().name.clone()
+    }
+
+    fn set_name(&self, name: String) {
+        self.inner.write().name = name;
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 初始化`/sys/class`的kset，并注册内核内置的class
 pub(super) fn classes_init() -> Result<(), SystemError> {
     let class_kset = KSet::new("class".to_string());
     class_kset
@@ -22,5 +444,24 @@ pub(super) fn classes_init() -> Result<(), SystemError> {
     unsafe {
         CLASS_KSET_INSTANCE = Some(class_kset);
     }
+
+    let block = GenericClass::new(
+        "block",
+        DeviceType::Block,
+        Some(sys_dev_block_kset() as Arc<dyn KObject>),
+        None,
+    );
+    let net = GenericClass::new("net", DeviceType::Net, None, None);
+    let input = GenericClass::new("input", DeviceType::Input, None, Some(input_devnode));
+    let tty = GenericClass::new("tty", DeviceType::Serial, None, None);
+    for class in [&block, &net, &input, &tty] {
+        class_manager().class_register(&(class.clone() as Arc<dyn Class>))?;
+    }
+    unsafe {
+        BLOCK_CLASS_INSTANCE = Some(block);
+        NET_CLASS_INSTANCE = Some(net);
+        INPUT_CLASS_INSTANCE = Some(input);
+        TTY_CLASS_INSTANCE = Some(tty);
+    }
     return Ok(());
 }
@@ -82,14 +82,6 @@ impl Bus for CpuSubSystem {
         todo!()
     }
 
-    fn shutdown(&self, _device: &Arc<dyn Device>) {
-        todo!()
-    }
-
-    fn resume(&self, _device: &Arc<dyn Device>) -> Result<(), SystemError> {
-        todo!()
-    }
-
     fn match_device(
         &self,
         device: &Arc<dyn Device>,
@@ -140,12 +140,41 @@ pub trait Bus: Debug + Send + Sync {
     }
     fn remove(&self, _device: &Arc<dyn Device>) -> Result<(), SystemError>;
     fn sync_state(&self, _device: &Arc<dyn Device>) {}
-    fn shutdown(&self, _device: &Arc<dyn Device>);
-    fn suspend(&self, _device: &Arc<dyn Device>) {
-        // todo: implement suspend
+
+    /// 关机或者重启之前，让总线上的设备停止工作
+    ///
+    /// ## 默认实现
+    ///
+    /// 调用设备绑定的驱动的[`Driver::shutdown`]
+    fn shutdown(&self, device: &Arc<dyn Device>) {
+        if let Some(driver) = device.driver() {
+            driver.shutdown(device);
+        }
+    }
+
+    /// 系统进入睡眠状态之前，让总线上的设备停止工作
+    ///
+    /// ## 默认实现
+    ///
+    /// 调用设备绑定的驱动的[`Driver::suspend`]
+    fn suspend(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        match device.driver() {
+            Some(driver) => driver.suspend(device),
+            None => Ok(()),
+        }
     }
 
-    fn resume(&self, device: &Arc<dyn Device>) -> Result<(), SystemError>;
+    /// 系统从睡眠状态中恢复之后，让总线上的设备重新开始工作
+    ///
+    /// ## 默认实现
+    ///
+    /// 调用设备绑定的驱动的[`Driver::resume`]
+    fn resume(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        match device.driver() {
+            Some(driver) => driver.resume(device),
+            None => Ok(()),
+        }
+    }
 
     /// match platform device to platform driver.
     ///
@@ -291,6 +320,26 @@ impl BusManager {
         return Ok(());
     }
 
+    /// 把设备从它的总线上移除，并与驱动解除绑定
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/bus.c?fi=bus_remove_device#bus_remove_device
+    pub fn remove_device(&self, dev: &Arc<dyn Device>) {
+        let bus = match dev.bus() {
+            Some(bus) => bus,
+            None => return,
+        };
+
+        let dev_kobj = dev.clone() as Arc<dyn KObject>;
+        sysfs_instance().remove_link(&dev_kobj, "subsystem".to_string());
+        if let Some(bus_devices_kset) = bus.subsystem().devices_kset() {
+            sysfs_instance().remove_link(&bus_devices_kset.as_kobject(), dev.name());
+        }
+        device_manager().remove_groups(dev, bus.dev_groups());
+        bus.subsystem().remove_device_from_vec(dev);
+
+        device_manager().device_release_driver(dev);
+    }
+
     /// 在总线上添加一个驱动
     ///
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/bus.c?fi=bus_add_driver#590
@@ -376,8 +425,23 @@ impl BusManager {
         return Ok(());
     }
 
-    pub fn unregister(&self, _bus: Arc<dyn Bus>) -> Result<(), SystemError> {
-        todo!("bus_unregister")
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/bus.c?fi=bus_unregister#bus_unregister
+    pub fn unregister(&self, bus: Arc<dyn Bus>) -> Result<(), SystemError> {
+        let subsys_kset = bus.subsystem().subsys();
+        self.kset_bus_map.write().remove(&subsys_kset);
+
+        let bus_kobj = subsys_kset.clone() as Arc<dyn KObject>;
+        sysfs_instance().remove_groups(&bus_kobj, bus.bus_groups());
+        self.remove_probe_files(&bus);
+
+        if let Some(drivers_kset) = bus.subsystem().drivers_kset() {
+            drivers_kset.unregister();
+        }
+        if let Some(devices_kset) = bus.subsystem().devices_kset() {
+            devices_kset.unregister();
+        }
+        subsys_kset.unregister();
+        return Ok(());
     }
 
     fn add_probe_files(&self, bus: &Arc<dyn Bus>) -> Result<(), SystemError> {
@@ -390,7 +454,6 @@ impl BusManager {
         return r;
     }
 
-    #[allow(dead_code)]
     fn remove_probe_files(&self, bus: &Arc<dyn Bus>) {
         self.remove_file(bus, &BusAttrDriversAutoprobe);
         self.remove_file(bus, &BusAttrDriversProbe);
@@ -570,6 +633,11 @@ pub fn bus_add_device(dev: &Arc<dyn Device>) -> Result<(), SystemError> {
     return bus_manager().add_device(dev);
 }
 
+/// 把设备从它的总线上移除，是`bus_add_device`的逆操作
+pub fn bus_remove_device(dev: &Arc<dyn Device>) {
+    bus_manager().remove_device(dev);
+}
+
 /// 自动为设备在总线上寻找可用的驱动程序
 ///
 /// Automatically probe for a driver if the bus allows it.
@@ -1,15 +1,20 @@
-use core::intrinsics::unlikely;
-
-use alloc::{string::ToString, sync::Arc};
+use alloc::{
+    boxed::Box,
+    collections::LinkedList,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use intertrait::cast::CastArc;
 
 use crate::{
-    driver::base::kobject::KObject,
+    driver::base::{kobject::KObject, uevent::KObjectAction},
     filesystem::{
         sysfs::{file::sysfs_emit_str, sysfs_instance, Attribute, SysFSOpsSupport},
         vfs::syscall::ModeType,
     },
-    libs::wait_queue::WaitQueue,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
     syscall::SystemError,
 };
 
@@ -20,7 +25,14 @@ use super::{
     Device, DeviceManager,
 };
 
+/// 探测完成时唤醒等待者
 static PROBE_WAIT_QUEUE: WaitQueue = WaitQueue::INIT;
+/// 唤醒探测线程
+static PROBE_WORK_WAIT_QUEUE: WaitQueue = WaitQueue::INIT;
+static PROBE_WORKER: SpinLock<ProbeWorker> = SpinLock::new(ProbeWorker::new());
+static DEFERRED_PROBE: SpinLock<DeferredProbe> = SpinLock::new(DeferredProbe::new());
+/// 探测失败的设备。设备成功绑定驱动或者被删除之后，记录会被移除
+static PROBE_FAILURES: SpinLock<Vec<ProbeFailure>> = SpinLock::new(Vec::new());
 
 impl DeviceManager {
     /// 尝试把一个设备与一个驱动匹配
@@ -55,17 +67,10 @@ impl DeviceManager {
         dev: &Arc<dyn Device>,
         allow_async: bool,
     ) -> Result<bool, SystemError> {
-        if unlikely(allow_async) {
-            // todo!("do_device_attach: allow_async")
-            kwarn!("do_device_attach: allow_async is true, but currently not supported");
-        }
         if dev.is_dead() {
             return Ok(false);
         }
 
-        let mut do_async = false;
-        let mut r = Ok(false);
-
         if dev.driver().is_some() {
             if self.device_is_bound(dev) {
                 return Ok(true);
@@ -77,52 +82,104 @@ impl DeviceManager {
                 dev.set_driver(None);
                 return Ok(false);
             }
-        } else {
-            let bus = dev.bus().ok_or(SystemError::EINVAL)?;
-            let mut data = DeviceAttachData::new(dev.clone(), allow_async, false);
-            let mut flag = true;
-            for driver in bus.subsystem().drivers().iter() {
-                if let Some(driver) = driver.upgrade() {
-                    let r = self.do_device_attach_driver(&driver, &mut data);
-                    if unlikely(r.is_err()) {
-                        flag = false;
-                        break;
-                    }
-                }
-            }
+        }
 
-            if flag {
-                r = Ok(true);
-            }
+        let mut data = DeviceAttachData::new(dev.clone(), allow_async, false);
+        let bound = match self.device_attach_drivers(&mut data) {
+            Ok(bound) => bound,
+            // 设备已经加入推迟探测的列表，稍后会被重新探测
+            Err(SystemError::EPROBE_DEFER) => return Ok(false),
+            Err(e) => return Err(e),
+        };
 
-            if !flag && allow_async && data.have_async {
-                // If we could not find appropriate driver
-                // synchronously and we are allowed to do
-                // async probes and there are drivers that
-                // want to probe asynchronously, we'll
-                // try them.
+        if !bound && allow_async && data.have_async {
+            // If we could not find appropriate driver
+            // synchronously and we are allowed to do
+            // async probes and there are drivers that
+            // want to probe asynchronously, we'll
+            // try them.
+            kdebug!(
+                "do_device_attach: try scheduling asynchronous probe for device: {}",
+                dev.name()
+            );
+            probe_worker_queue(ProbeWork::DeviceAttach(dev.clone()));
+        }
+        return Ok(bound);
+    }
 
-                do_async = true;
-                kdebug!(
-                    "do_device_attach: try scheduling asynchronous probe for device: {}",
-                    dev.name()
-                );
-            }
+    /// 在异步探测线程中，使用要求异步探测的驱动为设备进行第二轮匹配
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=__device_attach_async_helper#__device_attach_async_helper
+    fn device_attach_async_helper(&self, dev: &Arc<dyn Device>) {
+        if dev.is_dead() || dev.driver().is_some() {
+            return;
         }
+        let mut data = DeviceAttachData::new(dev.clone(), true, true);
+        self.device_attach_drivers(&mut data).ok();
+    }
 
-        if do_async {
-            todo!("do_device_attach: do_async")
+    /// 按顺序尝试总线上的每一个驱动，直到设备被绑定，或者有驱动要求推迟探测
+    ///
+    /// ## 返回
+    ///
+    /// - Ok(true): 设备已经绑定到驱动上
+    /// - Ok(false): 没有驱动能够绑定这个设备
+    /// - Err(SystemError::EPROBE_DEFER): 探测被推迟，设备已经加入推迟探测的列表
+    fn device_attach_drivers(&self, data: &mut DeviceAttachData) -> Result<bool, SystemError> {
+        let bus = data.dev.bus().ok_or(SystemError::EINVAL)?;
+        // 探测时可能会向总线注册新的驱动，因此不能在遍历时持有锁
+        let drivers = bus.subsystem().drivers().clone();
+        for driver in drivers.iter().filter_map(|driver| driver.upgrade()) {
+            if self.do_device_attach_driver(&driver, data)? {
+                return Ok(true);
+            }
         }
-        return r;
+        return Ok(false);
     }
 
+    /// 尝试把设备绑定到一个驱动上
+    ///
+    /// ## 返回
+    ///
+    /// - Ok(true): 绑定成功，不需要再尝试其他驱动
+    /// - Ok(false): 驱动不匹配，或者探测失败，继续尝试下一个驱动
+    /// - Err(SystemError::EPROBE_DEFER): 探测被推迟，不再尝试其他驱动
+    ///
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c#899
     fn do_device_attach_driver(
         &self,
-        _driver: &Arc<dyn Driver>,
-        _data: &mut DeviceAttachData,
-    ) -> Result<(), SystemError> {
-        todo!("do_device_attach_driver")
+        driver: &Arc<dyn Driver>,
+        data: &mut DeviceAttachData,
+    ) -> Result<bool, SystemError> {
+        match driver_manager().match_device(driver, &data.dev) {
+            Ok(true) => {}
+            Ok(false) => return Ok(false),
+            Err(SystemError::EPROBE_DEFER) => {
+                kdebug!("device '{}' match requests probe deferral", data.dev.name());
+                data.dev.set_can_match(true);
+                driver_deferred_probe_add(&data.dev);
+                return Err(SystemError::EPROBE_DEFER);
+            }
+            Err(e) => {
+                kdebug!("bus: '{}' failed to match device: {:?}", data.dev.name(), e);
+                return Ok(false);
+            }
+        }
+
+        let async_allowed = driver.allows_async_probing();
+        if async_allowed {
+            data.set_have_async();
+        }
+        if data.check_async && async_allowed != data.want_async {
+            return Ok(false);
+        }
+
+        match driver_manager().probe_device(driver, &data.dev) {
+            Ok(()) => return Ok(true),
+            Err(SystemError::EPROBE_DEFER) => return Err(SystemError::EPROBE_DEFER),
+            // 忽略探测失败的错误，让下一个驱动尝试
+            Err(_) => return Ok(false),
+        }
     }
 
     /// 检查设备是否绑定到驱动程序
@@ -173,6 +230,53 @@ impl DeviceManager {
         return r;
     }
 
+    /// 解除设备与驱动的绑定。依赖这个设备的消费者会先与它们的驱动解除绑定
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=__device_release_driver#__device_release_driver
+    pub fn device_release_driver(&self, dev: &Arc<dyn Device>) {
+        let driver = match dev.driver() {
+            Some(driver) => driver,
+            None => return,
+        };
+
+        self.device_links_unbind_consumers(dev);
+
+        if let Some(bus) = dev.bus() {
+            bus.subsystem().bus_notifier().call_chain(
+                BusNotifyEvent::UnbindDriver,
+                Some(dev),
+                None,
+            );
+            if let Err(e) = bus.remove(dev) {
+                kwarn!(
+                    "device_release_driver: failed to remove device '{}': {:?}",
+                    dev.name(),
+                    e
+                );
+            }
+        }
+
+        driver_manager().remove_from_sysfs(dev);
+        driver.delete_device(dev);
+        self.unbind_cleanup(dev);
+        self.device_links_driver_cleanup(dev);
+
+        if let Some(bus) = dev.bus() {
+            bus.subsystem().bus_notifier().call_chain(
+                BusNotifyEvent::UnboundDriver,
+                Some(dev),
+                None,
+            );
+        }
+        if let Err(e) = self.device_uevent(dev, KObjectAction::Unbind) {
+            kwarn!(
+                "device_release_driver: failed to send uevent for device '{}': {:?}",
+                dev.name(),
+                e
+            );
+        }
+    }
+
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_attach#528
     fn unbind_cleanup(&self, dev: &Arc<dyn Device>) {
         dev.set_driver(None);
@@ -182,7 +286,6 @@ impl DeviceManager {
 
 /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c#866
 #[derive(Debug)]
-#[allow(dead_code)]
 struct DeviceAttachData {
     dev: Arc<dyn Device>,
 
@@ -220,7 +323,6 @@ impl DeviceAttachData {
         }
     }
 
-    #[allow(dead_code)]
     #[inline(always)]
     fn set_have_async(&mut self) {
         self.have_async = true;
@@ -230,16 +332,15 @@ impl DeviceAttachData {
 impl DriverManager {
     /// 尝试把驱动绑定到现有的设备上
     ///
-    /// 这个函数会遍历驱动现有的全部设备，然后尝试把他们匹配。
-    /// 一旦有一个设备匹配成功，就会返回，并且设备的driver字段会被设置。
+    /// 这个函数会遍历总线上现有的全部设备，然后尝试把他们与驱动匹配。
+    /// 匹配成功的设备的driver字段会被设置。一个驱动可以绑定多个设备。
     pub fn driver_attach(&self, driver: &Arc<dyn Driver>) -> Result<(), SystemError> {
         let bus = driver.bus().ok_or(SystemError::EINVAL)?;
-        for dev in bus.subsystem().devices().iter() {
+        // 探测设备时可能会向总线添加新的设备，因此不能在遍历时持有锁
+        let devices = bus.subsystem().devices().clone();
+        for dev in devices.iter() {
             if let Some(dev) = dev.upgrade() {
-                if self.do_driver_attach(&dev, &driver) {
-                    // 匹配成功
-                    return Ok(());
-                }
+                self.do_driver_attach(&dev, &driver);
             }
         }
 
@@ -255,10 +356,13 @@ impl DriverManager {
         }
 
         if driver.allows_async_probing() {
-            unimplemented!(
-                "do_driver_attach: probe driver '{}' asynchronously",
-                driver.name()
+            kdebug!(
+                "do_driver_attach: probing driver '{}' with device '{}' asynchronously",
+                driver.name(),
+                device.name()
             );
+            probe_worker_queue(ProbeWork::DriverAttach(driver.clone(), device.clone()));
+            return true;
         }
 
         if self.probe_device(driver, device).is_err() {
@@ -285,6 +389,7 @@ impl DriverManager {
     /// - Ok(): 绑定成功
     /// - Err(ENODEV): 设备未注册
     /// - Err(EBUSY): 设备已经绑定到驱动上
+    /// - Err(EPROBE_DEFER): 探测被推迟，设备已经加入推迟探测的列表
     ///
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_attach#802
     fn probe_device(
@@ -292,11 +397,35 @@ impl DriverManager {
         driver: &Arc<dyn Driver>,
         device: &Arc<dyn Device>,
     ) -> Result<(), SystemError> {
+        let trigger_count = DEFERRED_PROBE.lock_irqsave().trigger_count;
         let r = self.do_probe_device(driver, device);
+        if matches!(r, Err(SystemError::EPROBE_DEFER) | Err(SystemError::EAGAIN)) {
+            driver_deferred_probe_add(device);
+            // 探测期间有其他驱动绑定成功，它可能正是这个设备所等待的，需要再次触发
+            if DEFERRED_PROBE.lock_irqsave().trigger_count != trigger_count {
+                driver_deferred_probe_trigger();
+            }
+        }
         PROBE_WAIT_QUEUE.wakeup_all(None);
         return r;
     }
 
+    /// 等待所有已经提交的异步探测与推迟探测完成
+    ///
+    /// 挂载根文件系统之前调用，保证磁盘控制器的驱动已经完成探测。仍然处于推迟状态的设备不会被等待
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=wait_for_device_probe#wait_for_device_probe
+    pub fn wait_for_device_probe(&self) {
+        loop {
+            let worker = PROBE_WORKER.lock_irqsave();
+            // 探测线程还没有启动时，没有人会执行已经提交的工作
+            if worker.pending == 0 || !worker.started {
+                return;
+            }
+            PROBE_WAIT_QUEUE.sleep_unlock_spinlock(worker);
+        }
+    }
+
     fn do_probe_device(
         &self,
         driver: &Arc<dyn Driver>,
@@ -344,6 +473,9 @@ impl DriverManager {
             device_manager().remove(device);
         };
 
+        // 供应者都绑定了驱动之后，才能探测消费者
+        device_manager().device_links_check_suppliers(device)?;
+
         device.set_driver(Some(Arc::downgrade(driver)));
 
         self.add_to_sysfs(device).map_err(|e| {
@@ -446,8 +578,14 @@ impl DriverManager {
     }
 
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_attach#469
-    fn remove_from_sysfs(&self, _device: &Arc<dyn Device>) {
-        todo!("remove_from_sysfs")
+    fn remove_from_sysfs(&self, device: &Arc<dyn Device>) {
+        if let Some(driver) = device.driver() {
+            let driver_kobj = driver as Arc<dyn KObject>;
+            let device_kobj = device.clone() as Arc<dyn KObject>;
+            sysfs_instance().remove_file(&device_kobj, &DeviceAttrCoredump);
+            sysfs_instance().remove_link(&driver_kobj, device.name());
+            sysfs_instance().remove_link(&device_kobj, "driver".to_string());
+        }
     }
 
     fn call_driver_probe(
@@ -472,6 +610,14 @@ impl DriverManager {
 
         let err = r.unwrap_err();
         match err {
+            SystemError::EPROBE_DEFER => {
+                kdebug!(
+                    "driver '{}' requests probe deferral of {}",
+                    driver.name(),
+                    device.name()
+                );
+            }
+
             SystemError::ENODEV | SystemError::ENXIO => {
                 kdebug!(
                     "driver'{}': probe of {} rejects match {:?}",
@@ -488,6 +634,7 @@ impl DriverManager {
                     device.name(),
                     err
                 );
+                driver_probe_failure_add(device, driver.name(), err.clone());
             }
         }
 
@@ -505,6 +652,13 @@ impl DriverManager {
         let driver = device.driver().unwrap();
         driver.add_device(device.clone());
 
+        device_manager().device_links_driver_bound(device);
+
+        // 新绑定的设备可能正是被推迟的设备所等待的，因此重新探测它们
+        driver_deferred_probe_del(device);
+        driver_probe_failure_del(device);
+        driver_deferred_probe_trigger();
+
         if let Some(bus) = device.bus() {
             bus.subsystem().bus_notifier().call_chain(
                 BusNotifyEvent::BoundDriver,
@@ -513,7 +667,13 @@ impl DriverManager {
             );
         }
 
-        // todo: 发送kobj bind的uevent
+        if let Err(e) = device_manager().device_uevent(device, KObjectAction::Bind) {
+            kwarn!(
+                "driver_bound: failed to send uevent for device '{}': {:?}",
+                device.name(),
+                e
+            );
+        }
     }
 
     fn driver_is_bound(&self, device: &Arc<dyn Device>) -> bool {
@@ -527,6 +687,246 @@ impl DriverManager {
     }
 }
 
+/// 交给探测线程执行的工作
+#[derive(Debug)]
+enum ProbeWork {
+    /// 异步地把设备绑定到驱动上
+    DriverAttach(Arc<dyn Driver>, Arc<dyn Device>),
+    /// 异步地为设备寻找要求异步探测的驱动
+    DeviceAttach(Arc<dyn Device>),
+    /// 重新探测被推迟的设备
+    RetryDeferred,
+}
+
+/// 探测线程：执行异步探测，以及重新探测被推迟的设备
+#[derive(Debug)]
+struct ProbeWorker {
+    works: LinkedList<ProbeWork>,
+    /// 已经提交但还没有执行完毕的工作的数量
+    pending: usize,
+    started: bool,
+}
+
+impl ProbeWorker {
+    const fn new() -> Self {
+        Self {
+            works: LinkedList::new(),
+            pending: 0,
+            started: false,
+        }
+    }
+}
+
+/// 提交一个工作给探测线程。探测线程还没有启动时，工作会在它启动后执行
+fn probe_worker_queue(work: ProbeWork) {
+    let mut worker = PROBE_WORKER.lock_irqsave();
+    worker.works.push_back(work);
+    worker.pending += 1;
+    drop(worker);
+    PROBE_WORK_WAIT_QUEUE.wakeup(None);
+}
+
+/// 启动探测线程。在此之前提交的异步探测，以及被推迟的探测，都会在它启动后执行
+pub fn device_probe_worker_init() -> Result<(), SystemError> {
+    if PROBE_WORKER.lock_irqsave().started {
+        return Ok(());
+    }
+    KernelThreadMechanism::create_and_run(
+        KernelThreadClosure::EmptyClosure((Box::new(probe_worker_thread), ())),
+        String::from("probe_worker"),
+    )
+    .ok_or(SystemError::ENOMEM)?;
+    PROBE_WORKER.lock_irqsave().started = true;
+
+    driver_deferred_probe_trigger();
+    return Ok(());
+}
+
+fn probe_worker_thread() -> i32 {
+    loop {
+        let mut worker = PROBE_WORKER.lock_irqsave();
+        let work = match worker.works.pop_front() {
+            Some(work) => work,
+            None => {
+                PROBE_WORK_WAIT_QUEUE.sleep_unlock_spinlock(worker);
+                continue;
+            }
+        };
+        drop(worker);
+
+        match work {
+            ProbeWork::DriverAttach(driver, device) => {
+                // 等待期间设备可能已经被其他驱动绑定
+                if device.driver().is_none() {
+                    driver_manager().probe_device(&driver, &device).ok();
+                }
+            }
+            ProbeWork::DeviceAttach(device) => {
+                device_manager().device_attach_async_helper(&device);
+            }
+            ProbeWork::RetryDeferred => deferred_probe_work(),
+        }
+
+        PROBE_WORKER.lock_irqsave().pending -= 1;
+        PROBE_WAIT_QUEUE.wakeup_all(None);
+    }
+}
+
+/// 被推迟探测的设备
+///
+/// 驱动的probe返回EPROBE_DEFER时(通常是因为它依赖的设备或者子系统还没有就绪)，设备被加入这个列表；
+/// 每当有驱动成功绑定设备，或者子系统就绪时，列表中的设备都会被重新探测一次
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=deferred_probe_pending_list#deferred_probe_pending_list
+#[derive(Debug)]
+struct DeferredProbe {
+    pending: Vec<Arc<dyn Device>>,
+    /// 触发重新探测的次数
+    trigger_count: usize,
+}
+
+impl DeferredProbe {
+    const fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            trigger_count: 0,
+        }
+    }
+}
+
+/// 把设备加入推迟探测的列表
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_deferred_probe_add#driver_deferred_probe_add
+pub(super) fn driver_deferred_probe_add(dev: &Arc<dyn Device>) {
+    let mut deferred = DEFERRED_PROBE.lock_irqsave();
+    if !deferred.pending.iter().any(|d| Arc::ptr_eq(d, dev)) {
+        kdebug!("device '{}' added to deferred list", dev.name());
+        deferred.pending.push(dev.clone());
+    }
+}
+
+/// 把设备从推迟探测的列表中删除
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_deferred_probe_del#driver_deferred_probe_del
+pub(super) fn driver_deferred_probe_del(dev: &Arc<dyn Device>) {
+    DEFERRED_PROBE
+        .lock_irqsave()
+        .pending
+        .retain(|d| !Arc::ptr_eq(d, dev));
+}
+
+/// 一次失败的探测
+#[derive(Debug)]
+struct ProbeFailure {
+    dev: Arc<dyn Device>,
+    /// 探测失败的驱动的名字
+    driver: String,
+    err: SystemError,
+}
+
+/// 记录设备探测失败的原因，并在设备的sysfs目录下创建`probe_error`文件。同一个设备只保留最近一次的记录
+fn driver_probe_failure_add(dev: &Arc<dyn Device>, driver: String, err: SystemError) {
+    let mut failures = PROBE_FAILURES.lock_irqsave();
+    let failure = ProbeFailure {
+        dev: dev.clone(),
+        driver,
+        err,
+    };
+    if let Some(f) = failures.iter_mut().find(|f| Arc::ptr_eq(&f.dev, dev)) {
+        *f = failure;
+        return;
+    }
+    failures.push(failure);
+    drop(failures);
+
+    if let Err(e) = device_manager().create_file(dev, &DeviceAttrProbeError) {
+        kwarn!(
+            "failed to create probe_error file for device '{}': {:?}",
+            dev.name(),
+            e
+        );
+    }
+}
+
+/// 移除设备探测失败的记录，以及设备的`probe_error`文件
+pub(super) fn driver_probe_failure_del(dev: &Arc<dyn Device>) {
+    let mut failures = PROBE_FAILURES.lock_irqsave();
+    let len = failures.len();
+    failures.retain(|f| !Arc::ptr_eq(&f.dev, dev));
+    if failures.len() == len {
+        return;
+    }
+    drop(failures);
+
+    let kobj = dev.clone() as Arc<dyn KObject>;
+    sysfs_instance().remove_file(&kobj, &DeviceAttrProbeError);
+}
+
+impl DeviceManager {
+    /// 获取设备最近一次探测失败的原因
+    ///
+    /// ## 返回值
+    ///
+    /// (探测失败的驱动的名字, 错误码)。设备没有探测失败过，或者之后已经成功绑定了驱动时返回None
+    pub fn probe_failure(&self, dev: &Arc<dyn Device>) -> Option<(String, SystemError)> {
+        return PROBE_FAILURES
+            .lock_irqsave()
+            .iter()
+            .find(|f| Arc::ptr_eq(&f.dev, dev))
+            .map(|f| (f.driver.clone(), f.err.clone()));
+    }
+
+    /// 记录一次没有经过驱动模型的探测的失败，例如直接扫描PCI设备列表进行初始化的驱动
+    ///
+    /// 与驱动模型中的探测失败相同，原因会展示在设备的`probe_error`文件中
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`：探测失败的设备
+    /// - `driver`：探测失败的驱动的名字
+    /// - `err`：失败的原因
+    pub fn probe_failure_add(&self, dev: &Arc<dyn Device>, driver: &str, err: SystemError) {
+        driver_probe_failure_add(dev, String::from(driver), err);
+    }
+}
+
+/// 触发一次对被推迟的设备的重新探测
+///
+/// 驱动成功绑定设备时会自动调用。子系统（例如devfs）就绪时，也应当调用它，让等待这个子系统的驱动重新探测
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_deferred_probe_trigger#driver_deferred_probe_trigger
+pub fn driver_deferred_probe_trigger() {
+    let mut deferred = DEFERRED_PROBE.lock_irqsave();
+    deferred.trigger_count += 1;
+    if deferred.pending.is_empty() {
+        return;
+    }
+    drop(deferred);
+
+    // 同一时间只需要排队一次重新探测
+    let worker = PROBE_WORKER.lock_irqsave();
+    if worker
+        .works
+        .iter()
+        .any(|w| matches!(w, ProbeWork::RetryDeferred))
+    {
+        return;
+    }
+    drop(worker);
+    probe_worker_queue(ProbeWork::RetryDeferred);
+}
+
+/// 重新探测推迟列表中的每一个设备。探测仍然被推迟的设备会重新加入列表
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=deferred_probe_work_func#deferred_probe_work_func
+fn deferred_probe_work() {
+    let devices = core::mem::take(&mut DEFERRED_PROBE.lock_irqsave().pending);
+    for dev in devices {
+        kdebug!("deferred probe: retrying device '{}'", dev.name());
+        device_manager().device_initial_probe(&dev).ok();
+    }
+}
+
 /// 设备文件夹下的`dev`文件的属性
 #[derive(Debug, Clone, Copy)]
 pub struct DeviceAttrStateSynced;
@@ -591,3 +991,36 @@ impl Attribute for DeviceAttrCoredump {
         return Ok(buf.len());
     }
 }
+
+/// 设备最近一次探测失败的原因，格式为`<驱动名>: <错误>`。只在设备探测失败之后存在
+#[derive(Debug)]
+struct DeviceAttrProbeError;
+
+impl Attribute for DeviceAttrProbeError {
+    fn name(&self) -> &str {
+        "probe_error"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::S_IRUGO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let dev = kobj.cast::<dyn Device>().map_err(|kobj| {
+            kerror!(
+                "Intertrait casting not implemented for kobj: {}",
+                kobj.name()
+            );
+            SystemError::EOPNOTSUPP_OR_ENOTSUP
+        })?;
+
+        let (driver, err) = device_manager()
+            .probe_failure(&dev)
+            .ok_or(SystemError::ENODATA)?;
+        return sysfs_emit_str(buf, &format!("{}: {:?}\n", driver, err));
+    }
+}
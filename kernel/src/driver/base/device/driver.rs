@@ -3,13 +3,20 @@ use super::{
     Device, DeviceMatchName, DeviceMatcher, IdTable,
 };
 use crate::{
-    driver::base::kobject::KObject,
+    driver::base::{
+        kobject::KObject,
+        uevent::{kobject_uevent, KObjectAction},
+    },
     filesystem::sysfs::{sysfs_instance, Attribute, AttributeGroup},
+    kernel_param,
     syscall::SystemError,
 };
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
 use core::fmt::Debug;
 
+// 启动参数`driver_async_probe=ahci,e1000e`使指定的驱动异步探测设备，`*`表示所有驱动
+kernel_param!(DRIVER_ASYNC_PROBE_PARAM: String = "driver_async_probe");
+
 /// @brief: Driver error
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -104,6 +111,21 @@ pub trait Driver: Sync + Send + Debug + KObject {
     fn probe_type(&self) -> DriverProbeType {
         DriverProbeType::DefaultStrategy
     }
+
+    /// 系统进入睡眠状态之前，让绑定到当前驱动的设备停止工作
+    ///
+    /// 由总线的[`Bus::suspend`]调用，总线可以把它转换为总线特有的回调
+    fn suspend(&self, _device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 系统从睡眠状态中恢复之后，让绑定到当前驱动的设备重新开始工作
+    fn resume(&self, _device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 关机或者重启之前，让绑定到当前驱动的设备停止工作
+    fn shutdown(&self, _device: &Arc<dyn Device>) {}
 }
 
 impl dyn Driver {
@@ -112,10 +134,11 @@ impl dyn Driver {
             DriverProbeType::PreferAsync => true,
             DriverProbeType::ForceSync => false,
             DriverProbeType::DefaultStrategy => {
-                // todo: 判断是否请求异步探测，如果是的话，就返回true
-
-                // 由于目前还没有支持异步探测，因此这里暂时返回false
-                false
+                // 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=cmdline_requested_async_probing#cmdline_requested_async_probing
+                let name = self.name();
+                DRIVER_ASYNC_PROBE_PARAM.get().map_or(false, |drivers| {
+                    drivers.split(',').any(|d| d == "*" || d == name)
+                })
             }
         }
     }
@@ -196,7 +219,7 @@ impl DriverManager {
             e
         })?;
 
-        // todo: 发送uevent
+        kobject_uevent(&(driver.clone() as Arc<dyn KObject>), KObjectAction::Add)?;
 
         return Ok(());
     }
@@ -204,6 +227,7 @@ impl DriverManager {
     /// 从系统中删除一个驱动程序
     #[allow(dead_code)]
     pub fn unregister(&self, driver: &Arc<dyn Driver>) {
+        kobject_uevent(&(driver.clone() as Arc<dyn KObject>), KObjectAction::Remove).ok();
         self.remove_groups(driver, driver.groups());
         bus_manager().remove_driver(driver);
     }
@@ -3,7 +3,9 @@ use alloc::{string::ToString, sync::Arc};
 use crate::{
     driver::base::{
         device::{
-            sys_dev_kset, DeviceManager, DEVICES_KSET_INSTANCE, DEVICE_MANAGER, DEV_KSET_INSTANCE,
+            sys_dev_kset, sys_devices_kset, DeviceManager, DEVICES_KSET_INSTANCE,
+            DEVICES_VIRTUAL_KSET_INSTANCE, DEVICE_MANAGER, DEV_BLOCK_KSET_INSTANCE,
+            DEV_CHAR_KSET_INSTANCE, DEV_KSET_INSTANCE,
         },
         kobject::KObject,
         kset::KSet,
@@ -27,6 +29,20 @@ pub fn devices_init() -> Result<(), SystemError> {
         }
     }
 
+    // 创建 `/sys/devices/virtual` 目录
+    {
+        let devices_kset = sys_devices_kset();
+        let virtual_kset = KSet::new_and_add(
+            "virtual".to_string(),
+            Some(devices_kset.clone() as Arc<dyn KObject>),
+            None,
+        )
+        .expect("register devices virtual kset failed");
+        unsafe {
+            DEVICES_VIRTUAL_KSET_INSTANCE = Some(virtual_kset);
+        }
+    }
+
     // 创建 `/sys/dev` 目录
     {
         let dev_kset = KSet::new("dev".to_string());
@@ -47,6 +63,9 @@ pub fn devices_init() -> Result<(), SystemError> {
         dev_block_kset
             .register(Some(dev_kset))
             .expect("register dev block kset failed");
+        unsafe {
+            DEV_BLOCK_KSET_INSTANCE = Some(dev_block_kset);
+        }
     }
 
     // 创建 `/sys/dev/char` 目录
@@ -60,6 +79,9 @@ pub fn devices_init() -> Result<(), SystemError> {
         dev_char_kset
             .register(Some(dev_kset))
             .expect("register dev char kset failed");
+        unsafe {
+            DEV_CHAR_KSET_INSTANCE = Some(dev_char_kset);
+        }
     }
 
     kinfo!("devices init success");
@@ -0,0 +1,322 @@
+//! 设备链接(device link)
+//!
+//! 设备链接描述了两个设备之间不属于父子关系的依赖：消费者(consumer)需要供应者(supplier)先工作，
+//! 例如磁盘依赖它所在的控制器、外设依赖DMA引擎。驱动核心据此保证：
+//!
+//! - 供应者绑定驱动之前，消费者的探测被推迟
+//! - 供应者与驱动解除绑定之前，消费者先与驱动解除绑定
+//! - 睡眠与关机时，消费者先于供应者停止工作；恢复时，供应者先于消费者恢复
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_link_add#device_link_add
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use intertrait::cast::CastArc;
+
+use crate::{
+    driver::base::{kobject::KObject, power::device_pm_move_last},
+    filesystem::sysfs::sysfs_instance,
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+};
+
+use super::{dd::driver_deferred_probe_add, Device, DeviceManager};
+
+/// 所有的设备链接
+static DEVICE_LINKS: SpinLock<Vec<Arc<DeviceLink>>> = SpinLock::new(Vec::new());
+
+bitflags! {
+    /// 设备链接的标志
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/device.h?fi=DL_FLAG_STATELESS#DL_FLAG_STATELESS
+    pub struct DeviceLinkFlags: u32 {
+        /// 驱动核心不根据这个链接管理探测与解绑的顺序，只保证睡眠与关机的顺序
+        const STATELESS = 1 << 0;
+        /// 消费者与驱动解除绑定时，删除这个链接
+        const AUTOREMOVE_CONSUMER = 1 << 1;
+        /// 供应者与驱动解除绑定时，删除这个链接
+        const AUTOREMOVE_SUPPLIER = 1 << 4;
+        /// 供应者绑定驱动之后，自动探测消费者
+        const AUTOPROBE_CONSUMER = 1 << 5;
+    }
+}
+
+/// 一个设备链接
+#[derive(Debug)]
+pub struct DeviceLink {
+    supplier: Weak<dyn Device>,
+    consumer: Weak<dyn Device>,
+    flags: DeviceLinkFlags,
+}
+
+impl DeviceLink {
+    pub fn supplier(&self) -> Option<Arc<dyn Device>> {
+        self.supplier.upgrade()
+    }
+
+    pub fn consumer(&self) -> Option<Arc<dyn Device>> {
+        self.consumer.upgrade()
+    }
+
+    pub fn flags(&self) -> DeviceLinkFlags {
+        self.flags
+    }
+
+    /// 驱动核心是否根据这个链接管理探测与解绑的顺序
+    #[inline]
+    pub fn is_managed(&self) -> bool {
+        !self.flags.contains(DeviceLinkFlags::STATELESS)
+    }
+}
+
+/// 判断两个设备是否是同一个设备
+#[inline]
+fn same_device(a: &Arc<dyn Device>, b: &Arc<dyn Device>) -> bool {
+    Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
+}
+
+impl DeviceManager {
+    /// 添加一个设备链接，表示`consumer`依赖`supplier`
+    ///
+    /// 两个设备之间已经有链接时，返回已有的链接
+    ///
+    /// ## 参数
+    ///
+    /// - `consumer`: 消费者
+    /// - `supplier`: 供应者
+    /// - `flags`: 链接的标志
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：两个设备是同一个设备，或者供应者已经依赖消费者(链接会形成环)，或者设备还没有被添加到系统中
+    pub fn device_link_add(
+        &self,
+        consumer: &Arc<dyn Device>,
+        supplier: &Arc<dyn Device>,
+        flags: DeviceLinkFlags,
+    ) -> Result<Arc<DeviceLink>, SystemError> {
+        if same_device(consumer, supplier)
+            || !consumer.is_registered()
+            || !supplier.is_registered()
+            || self.device_is_dependent(supplier, consumer)
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut links = DEVICE_LINKS.lock();
+        if let Some(link) = links.iter().find(|link| {
+            link.supplier().map_or(false, |s| same_device(&s, supplier))
+                && link.consumer().map_or(false, |c| same_device(&c, consumer))
+        }) {
+            return Ok(link.clone());
+        }
+
+        let link = Arc::new(DeviceLink {
+            supplier: Arc::downgrade(supplier),
+            consumer: Arc::downgrade(consumer),
+            flags,
+        });
+        links.push(link.clone());
+        drop(links);
+
+        let consumer_kobj = consumer.clone() as Arc<dyn KObject>;
+        let supplier_kobj = supplier.clone() as Arc<dyn KObject>;
+        sysfs_instance()
+            .create_link(
+                Some(&consumer_kobj),
+                &supplier_kobj,
+                format!("supplier:{}", supplier.name()),
+            )
+            .ok();
+        sysfs_instance()
+            .create_link(
+                Some(&supplier_kobj),
+                &consumer_kobj,
+                format!("consumer:{}", consumer.name()),
+            )
+            .ok();
+
+        // 让消费者以及依赖它的设备在睡眠与关机时先于供应者停止工作
+        self.device_reorder_to_tail(consumer);
+        return Ok(link);
+    }
+
+    /// 删除一个设备链接
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_link_del#device_link_del
+    pub fn device_link_del(&self, link: &Arc<DeviceLink>) {
+        DEVICE_LINKS.lock().retain(|l| !Arc::ptr_eq(l, link));
+
+        if let (Some(supplier), Some(consumer)) = (link.supplier(), link.consumer()) {
+            let consumer_kobj = consumer.clone() as Arc<dyn KObject>;
+            let supplier_kobj = supplier.clone() as Arc<dyn KObject>;
+            sysfs_instance().remove_link(&consumer_kobj, format!("supplier:{}", supplier.name()));
+            sysfs_instance().remove_link(&supplier_kobj, format!("consumer:{}", consumer.name()));
+        }
+    }
+
+    /// 获取设备作为消费者的所有链接
+    pub fn device_supplier_links(&self, dev: &Arc<dyn Device>) -> Vec<Arc<DeviceLink>> {
+        let mut links = DEVICE_LINKS.lock();
+        links.retain(|link| link.supplier.strong_count() > 0 && link.consumer.strong_count() > 0);
+        return links
+            .iter()
+            .filter(|link| link.consumer().map_or(false, |c| same_device(&c, dev)))
+            .cloned()
+            .collect();
+    }
+
+    /// 获取设备作为供应者的所有链接
+    pub fn device_consumer_links(&self, dev: &Arc<dyn Device>) -> Vec<Arc<DeviceLink>> {
+        let mut links = DEVICE_LINKS.lock();
+        links.retain(|link| link.supplier.strong_count() > 0 && link.consumer.strong_count() > 0);
+        return links
+            .iter()
+            .filter(|link| link.supplier().map_or(false, |s| same_device(&s, dev)))
+            .cloned()
+            .collect();
+    }
+
+    /// `dev`是否直接或者间接地依赖`target`(作为`target`的子设备或者消费者)
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_is_dependent#device_is_dependent
+    fn device_is_dependent(&self, dev: &Arc<dyn Device>, target: &Arc<dyn Device>) -> bool {
+        if same_device(dev, target) {
+            return true;
+        }
+
+        let parent = dev
+            .parent()
+            .and_then(|p| p.upgrade())
+            .and_then(|p| p.cast::<dyn Device>().ok());
+        if let Some(parent) = parent {
+            if self.device_is_dependent(&parent, target) {
+                return true;
+            }
+        }
+
+        return self
+            .device_supplier_links(dev)
+            .iter()
+            .filter_map(|link| link.supplier())
+            .any(|supplier| self.device_is_dependent(&supplier, target));
+    }
+
+    /// 把设备、它的子设备以及它的消费者移动到电源管理列表的末尾
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_reorder_to_tail#device_reorder_to_tail
+    fn device_reorder_to_tail(&self, dev: &Arc<dyn Device>) {
+        let children = device_pm_move_last(dev);
+        for child in children.iter() {
+            self.device_reorder_to_tail(child);
+        }
+        for consumer in self
+            .device_consumer_links(dev)
+            .iter()
+            .filter_map(|link| link.consumer())
+        {
+            self.device_reorder_to_tail(&consumer);
+        }
+    }
+
+    /// 检查设备的供应者是否都已经绑定了驱动
+    ///
+    /// ## 错误
+    ///
+    /// - `EPROBE_DEFER`：有供应者还没有绑定驱动，应当推迟设备的探测
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_links_check_suppliers#device_links_check_suppliers
+    pub fn device_links_check_suppliers(&self, dev: &Arc<dyn Device>) -> Result<(), SystemError> {
+        for link in self.device_supplier_links(dev).iter() {
+            if !link.is_managed() {
+                continue;
+            }
+            if let Some(supplier) = link.supplier() {
+                if supplier.driver().is_none() {
+                    kdebug!(
+                        "device '{}' waits for supplier '{}'",
+                        dev.name(),
+                        supplier.name()
+                    );
+                    return Err(SystemError::EPROBE_DEFER);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// 设备绑定驱动之后调用：要求自动探测的消费者被加入推迟探测的列表，随后被重新探测
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_links_driver_bound#device_links_driver_bound
+    pub fn device_links_driver_bound(&self, dev: &Arc<dyn Device>) {
+        for link in self.device_consumer_links(dev).iter() {
+            if !link.flags().contains(DeviceLinkFlags::AUTOPROBE_CONSUMER) {
+                continue;
+            }
+            if let Some(consumer) = link.consumer() {
+                if consumer.driver().is_none() {
+                    driver_deferred_probe_add(&consumer);
+                }
+            }
+        }
+    }
+
+    /// 设备与驱动解除绑定之前调用：先让所有受管理的消费者与它们的驱动解除绑定
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_links_unbind_consumers#device_links_unbind_consumers
+    pub fn device_links_unbind_consumers(&self, dev: &Arc<dyn Device>) {
+        for link in self.device_consumer_links(dev).iter() {
+            if !link.is_managed() {
+                continue;
+            }
+            if let Some(consumer) = link.consumer() {
+                self.device_release_driver(&consumer);
+            }
+        }
+    }
+
+    /// 设备与驱动解除绑定之后调用：删除要求在解绑时自动删除的链接
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_links_driver_cleanup#device_links_driver_cleanup
+    pub fn device_links_driver_cleanup(&self, dev: &Arc<dyn Device>) {
+        for link in self.device_supplier_links(dev).iter() {
+            if link.flags().contains(DeviceLinkFlags::AUTOREMOVE_CONSUMER) {
+                self.device_link_del(link);
+            }
+        }
+        for link in self.device_consumer_links(dev).iter() {
+            if link.flags().contains(DeviceLinkFlags::AUTOREMOVE_SUPPLIER) {
+                self.device_link_del(link);
+            }
+        }
+    }
+
+    /// 设备被从系统中删除时调用：删除设备作为供应者或者消费者的所有链接
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_links_purge#device_links_purge
+    pub fn device_links_purge(&self, dev: &Arc<dyn Device>) {
+        for link in self.device_supplier_links(dev).iter() {
+            self.device_link_del(link);
+        }
+        for link in self.device_consumer_links(dev).iter() {
+            self.device_link_del(link);
+        }
+    }
+
+    /// 强制绑定驱动时调用：删除供应者还没有绑定驱动的受管理的链接
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_links_force_bind#device_links_force_bind
+    pub fn device_links_force_bind(&self, dev: &Arc<dyn Device>) {
+        for link in self.device_supplier_links(dev).iter() {
+            if !link.is_managed() {
+                continue;
+            }
+            if link.supplier().map_or(true, |s| s.driver().is_none()) {
+                self.device_link_del(link);
+            }
+        }
+    }
+}
@@ -22,20 +22,25 @@ use core::fmt::Debug;
 use core::intrinsics::unlikely;
 
 use self::{
-    bus::{bus_add_device, bus_probe_device, Bus},
+    bus::{bus_add_device, bus_probe_device, bus_remove_device, Bus, BusNotifyEvent},
+    dd::{driver_deferred_probe_del, driver_probe_failure_del},
     driver::Driver,
 };
 
 use super::{
+    class::{class_manager, Class},
     kobject::{KObjType, KObject, KObjectManager, KObjectState},
     kset::KSet,
+    power::{device_pm_add, device_pm_remove},
     swnode::software_node_notify,
+    uevent::{kobject_uevent_env, KObjectAction},
 };
 
 pub mod bus;
 pub mod dd;
 pub mod driver;
 pub mod init;
+pub mod link;
 
 static mut DEVICE_MANAGER: Option<DeviceManager> = None;
 
@@ -58,6 +63,8 @@ lazy_static! {
 
 /// `/sys/devices` 的 kset 实例
 static mut DEVICES_KSET_INSTANCE: Option<Arc<KSet>> = None;
+/// `/sys/devices/virtual` 的 kset 实例
+static mut DEVICES_VIRTUAL_KSET_INSTANCE: Option<Arc<KSet>> = None;
 /// `/sys/dev` 的 kset 实例
 static mut DEV_KSET_INSTANCE: Option<Arc<KSet>> = None;
 /// `/sys/dev/block` 的 kset 实例
@@ -70,13 +77,17 @@ pub(super) fn sys_devices_kset() -> Arc<KSet> {
     unsafe { DEVICES_KSET_INSTANCE.as_ref().unwrap().clone() }
 }
 
+#[inline(always)]
+pub(super) fn sys_devices_virtual_kset() -> Arc<KSet> {
+    unsafe { DEVICES_VIRTUAL_KSET_INSTANCE.as_ref().unwrap().clone() }
+}
+
 #[inline(always)]
 pub(super) fn sys_dev_kset() -> Arc<KSet> {
     unsafe { DEV_KSET_INSTANCE.as_ref().unwrap().clone() }
 }
 
 #[inline(always)]
-#[allow(dead_code)]
 pub(super) fn sys_dev_block_kset() -> Arc<KSet> {
     unsafe { DEV_BLOCK_KSET_INSTANCE.as_ref().unwrap().clone() }
 }
@@ -121,6 +132,11 @@ pub trait Device: KObject {
         return None;
     }
 
+    /// 获取当前设备所属的class
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        return None;
+    }
+
     /// 设置当前设备所属的总线
     ///
     /// （一定要传入Arc，因为bus的subsysprivate里面存储的是Device的Weak指针）
@@ -147,6 +163,26 @@ pub trait Device: KObject {
     /// the software state of this device by calling the driver/bus
     /// sync_state() callback.
     fn state_synced(&self) -> bool;
+
+    /// 系统进入睡眠状态之前调用，在驱动的回调之后执行，用于处理设备自身(而不是驱动)的状态
+    fn suspend(&self) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 系统从睡眠状态中恢复之后调用，在驱动的回调之前执行
+    fn resume(&self) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 关机或者重启之前调用，在驱动的回调之后执行
+    fn shutdown(&self) {}
+
+    /// 设备自己的属性组，在设备被添加到系统中时，创建在设备的sysfs目录下
+    ///
+    /// 属性的show/store回调可以通过[`kobj_to_device`]取得具体的设备
+    fn attribute_groups(&self) -> Option<&'static [&'static dyn AttributeGroup]> {
+        None
+    }
 }
 
 impl dyn Device {
@@ -236,12 +272,12 @@ impl DeviceNumber {
 ///             minor: 次设备号
 /// @return: 设备号实例
 pub fn mkdev(major: usize, minor: usize) -> DeviceNumber {
-    DeviceNumber(((major & 0xfff) << 20) | (minor & 0xfffff))
+    DeviceNumber(DeviceNumber::from_major_minor(major, minor))
 }
 
 /// @brief: 设备类型
 #[allow(dead_code)]
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum DeviceType {
     Bus,
     Net,
@@ -252,6 +288,9 @@ pub enum DeviceType {
     Serial,
     Intc,
     PlatformDev,
+    Pci,
+    Usb,
+    PowerSupply,
 }
 
 /// @brief: 设备标识符类型
@@ -435,7 +474,16 @@ impl DeviceManager {
     #[inline]
     #[allow(dead_code)]
     pub fn add_device(&self, device: Arc<dyn Device>) -> Result<(), SystemError> {
-        // todo: 引入class后，在这里处理与parent相关的逻辑
+        // 没有父设备的class设备，放在`/sys/devices/virtual/<class>`下
+        if device.parent().is_none() {
+            if let Some(devices_kset) = device
+                .class()
+                .and_then(|class| class.subsystem().devices_kset())
+            {
+                let parent = devices_kset as Arc<dyn KObject>;
+                device.set_parent(Some(Arc::downgrade(&parent)));
+            }
+        }
 
         KObjectManager::add_kobj(device.clone() as Arc<dyn KObject>, None).map_err(|e| {
             kerror!("add device '{:?}' failed: {:?}", device.name(), e);
@@ -450,6 +498,8 @@ impl DeviceManager {
 
         bus_add_device(&device)?;
 
+        device_pm_add(&device);
+
         if device.id_table().device_number().major() != 0 {
             self.create_file(&device, &DeviceAttrDev)?;
 
@@ -460,7 +510,7 @@ impl DeviceManager {
         //  after dpm_sysfs_add() and before kobject_uevent().
         // 参考：https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c#3491
 
-        // todo: 发送uevent
+        self.device_uevent(&device, KObjectAction::Add)?;
 
         // probe drivers for a new device
         bus_probe_device(&device);
@@ -468,6 +518,56 @@ impl DeviceManager {
         return Ok(());
     }
 
+    /// 从系统中删除设备，并释放调用者持有的引用。设备在最后一个引用被释放之后，调用它的release回调
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_unregister#device_unregister
+    pub fn unregister(&self, device: Arc<dyn Device>) {
+        self.device_del(&device);
+        KObjectManager::kobj_put(device as Arc<dyn KObject>);
+    }
+
+    /// 从系统中删除设备，是`add_device`的逆操作
+    ///
+    /// 设备在sysfs、devfs、总线、class以及电源管理列表中留下的内容都会被移除，设备与驱动解除绑定
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_del#device_del
+    pub fn device_del(&self, device: &Arc<dyn Device>) {
+        if !device.is_registered() {
+            return;
+        }
+
+        if let Some(bus) = device.bus() {
+            bus.subsystem().bus_notifier().call_chain(
+                BusNotifyEvent::DelDevice,
+                Some(device),
+                None,
+            );
+        }
+
+        if device.id_table().device_number().major() != 0 {
+            self.remove_sys_dev_entry(device);
+        }
+        class_manager().devnode_unregister(device);
+        self.remove_class_symlinks(device);
+
+        bus_remove_device(device);
+        device_pm_remove(device);
+        driver_deferred_probe_del(device);
+        driver_probe_failure_del(device);
+
+        if let Some(bus) = device.bus() {
+            bus.subsystem().bus_notifier().call_chain(
+                BusNotifyEvent::RemovedDevice,
+                Some(device),
+                None,
+            );
+        }
+
+        self.device_links_purge(device);
+        self.device_uevent(device, KObjectAction::Remove).ok();
+        KObjectManager::remove_kobj(&(device.clone() as Arc<dyn KObject>));
+    }
+
     /// @brief: 卸载设备
     /// @parameter id_table: 总线标识符，用于唯一标识该设备
     /// @return: None
@@ -491,38 +591,147 @@ impl DeviceManager {
         todo!("find_device_by_idtable")
     }
 
+    /// 为设备发送uevent，除了kobject的基本信息之外，还包含设备所属的子系统、设备号以及绑定的驱动
+    ///
+    /// 不属于任何总线或者class的设备不发送uevent
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`: 设备
+    /// - `action`: 事件的动作
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=dev_uevent#dev_uevent
+    pub fn device_uevent(
+        &self,
+        dev: &Arc<dyn Device>,
+        action: KObjectAction,
+    ) -> Result<(), SystemError> {
+        let subsystem = match (dev.bus(), dev.class()) {
+            (Some(bus), _) => bus.name(),
+            (None, Some(class)) => class.name().to_string(),
+            (None, None) => return Ok(()),
+        };
+
+        let mut envp = vec![format!("SUBSYSTEM={}", subsystem)];
+        let devt = dev.id_table().device_number();
+        if devt.major() != 0 {
+            envp.push(format!("MAJOR={}", devt.major()));
+            envp.push(format!("MINOR={}", devt.minor()));
+            envp.push(format!("DEVNAME={}", self.device_get_devnode(dev)));
+        }
+        if let Some(driver) = dev.driver() {
+            envp.push(format!("DRIVER={}", driver.name()));
+        }
+
+        return kobject_uevent_env(&(dev.clone() as Arc<dyn KObject>), action, &envp);
+    }
+
     fn device_platform_notify(&self, dev: &Arc<dyn Device>) {
         acpi_device_notify(dev);
         software_node_notify(dev);
     }
 
-    fn add_class_symlinks(&self, _dev: &Arc<dyn Device>) -> Result<(), SystemError> {
-        // todo: 引入class后，在这里处理与class相关的逻辑
-        // https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c#3224
+    /// 设备文件相对于`/dev`的路径：设备所属的class提供了devnode回调时由它决定，否则为设备名
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_get_devnode#device_get_devnode
+    pub fn device_get_devnode(&self, dev: &Arc<dyn Device>) -> String {
+        return dev
+            .class()
+            .and_then(|class| class.devnode(dev))
+            .unwrap_or_else(|| dev.name());
+    }
+
+    /// 为属于某个class的设备创建符号链接：
+    ///
+    /// - 设备目录下的`subsystem`，指向`/sys/class/<class>`
+    /// - 设备目录下的`device`，指向父设备
+    /// - `/sys/class/<class>/<设备名>`，指向设备目录
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_add_class_symlinks#device_add_class_symlinks
+    fn add_class_symlinks(&self, dev: &Arc<dyn Device>) -> Result<(), SystemError> {
+        let class = match dev.class() {
+            Some(class) => class,
+            None => return Ok(()),
+        };
+
+        let dev_kobj = dev.clone() as Arc<dyn KObject>;
+        let class_kobj = class.subsystem().subsys() as Arc<dyn KObject>;
+        sysfs_instance().create_link(Some(&dev_kobj), &class_kobj, "subsystem".to_string())?;
+
+        let parent_dev = dev
+            .parent()
+            .and_then(|p| p.upgrade())
+            .and_then(|p| p.cast::<dyn Device>().ok());
+        if let Some(parent_dev) = parent_dev {
+            let parent_kobj = parent_dev as Arc<dyn KObject>;
+            if let Err(e) =
+                sysfs_instance().create_link(Some(&dev_kobj), &parent_kobj, "device".to_string())
+            {
+                sysfs_instance().remove_link(&dev_kobj, "subsystem".to_string());
+                return Err(e);
+            }
+        }
+
+        if let Err(e) = sysfs_instance().create_link(Some(&class_kobj), &dev_kobj, dev.name()) {
+            sysfs_instance().remove_link(&dev_kobj, "device".to_string());
+            sysfs_instance().remove_link(&dev_kobj, "subsystem".to_string());
+            return Err(e);
+        }
 
+        class.subsystem().add_device_to_vec(dev)?;
         return Ok(());
     }
 
+    /// 删除`add_class_symlinks`创建的符号链接，并把设备从class的设备列表中删除
+    fn remove_class_symlinks(&self, dev: &Arc<dyn Device>) {
+        let class = match dev.class() {
+            Some(class) => class,
+            None => return,
+        };
+
+        let dev_kobj = dev.clone() as Arc<dyn KObject>;
+        let class_kobj = class.subsystem().subsys() as Arc<dyn KObject>;
+        sysfs_instance().remove_link(&class_kobj, dev.name());
+        sysfs_instance().remove_link(&dev_kobj, "device".to_string());
+        sysfs_instance().remove_link(&dev_kobj, "subsystem".to_string());
+
+        class.subsystem().remove_device_from_vec(dev);
+    }
+
     /// 在sysfs中，为指定的设备创建属性文件
     ///
+    /// 依次创建设备所属的class、设备的kobj_type以及设备自己的属性组
+    ///
     /// ## 参数
     ///
     /// - `dev`: 设备
     fn add_attrs(&self, dev: &Arc<dyn Device>) -> Result<(), SystemError> {
-        let kobj_type = dev.kobj_type();
-        if kobj_type.is_none() {
-            return Ok(());
+        let class_groups = dev.class().map(|class| class.dev_groups());
+        if let Some(class_groups) = class_groups {
+            self.add_groups(dev, class_groups)?;
         }
 
-        let kobj_type = kobj_type.unwrap();
-
-        let attr_groups = kobj_type.attribute_groups();
-
-        if attr_groups.is_none() {
-            return Ok(());
+        let attr_groups = dev.kobj_type().and_then(|t| t.attribute_groups());
+        if let Some(attr_groups) = attr_groups {
+            self.add_groups(dev, attr_groups).map_err(|e| {
+                if let Some(class_groups) = class_groups {
+                    self.remove_groups(dev, class_groups);
+                }
+                e
+            })?;
         }
 
-        self.add_groups(dev, attr_groups.unwrap())?;
+        if let Some(dev_groups) = dev.attribute_groups() {
+            self.add_groups(dev, dev_groups).map_err(|e| {
+                if let Some(attr_groups) = attr_groups {
+                    self.remove_groups(dev, attr_groups);
+                }
+                if let Some(class_groups) = class_groups {
+                    self.remove_groups(dev, class_groups);
+                }
+                e
+            })?;
+        }
 
         return Ok(());
     }
@@ -542,6 +751,21 @@ impl DeviceManager {
         return sysfs_instance().create_groups(&kobj, attr_groups);
     }
 
+    /// 在sysfs中，移除设备的属性组，以及属性组中的属性文件
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`: 设备
+    /// - `attr_groups`: 属性组
+    pub fn remove_groups(
+        &self,
+        dev: &Arc<dyn Device>,
+        attr_groups: &'static [&dyn AttributeGroup],
+    ) {
+        let kobj = dev.clone() as Arc<dyn KObject>;
+        sysfs_instance().remove_groups(&kobj, attr_groups);
+    }
+
     /// 为设备在sysfs中创建属性文件
     ///
     /// ## 参数
@@ -577,38 +801,38 @@ impl DeviceManager {
         return sysfs_instance().create_file(&kobj, attr);
     }
 
-    /// 在/sys/dev下，或者设备所属的class下，为指定的设备创建链接
+    /// 在`/sys/dev/char`或者`/sys/dev/block`下，为指定的设备创建名为`<major>:<minor>`的链接
     fn create_sys_dev_entry(&self, dev: &Arc<dyn Device>) -> Result<(), SystemError> {
-        let target_kobj = self.device_to_dev_kobj(dev);
-        let name = dev.id_table().name();
+        let dev_kobj = self.device_to_dev_kobj(dev);
+        let name = Self::sys_dev_entry_name(dev);
         let current_kobj = dev.clone() as Arc<dyn KObject>;
-        return sysfs_instance().create_link(Some(&current_kobj), &target_kobj, name);
+        return sysfs_instance().create_link(Some(&dev_kobj), &current_kobj, name);
     }
 
-    /// Delete symlink for device in `/sys/dev` or `/sys/class/<class_name>`
-    #[allow(dead_code)]
+    /// Delete symlink for device in `/sys/dev`
     fn remove_sys_dev_entry(&self, dev: &Arc<dyn Device>) {
         let kobj = self.device_to_dev_kobj(dev);
-        let name = dev.id_table().name();
+        let name = Self::sys_dev_entry_name(dev);
         sysfs_instance().remove_link(&kobj, name);
     }
 
+    fn sys_dev_entry_name(dev: &Arc<dyn Device>) -> String {
+        let devt = dev.id_table().device_number();
+        return format!("{}:{}", devt.major(), devt.minor());
+    }
+
     /// device_to_dev_kobj - select a /sys/dev/ directory for the device
     ///
-    /// By default we select char/ for new entries.
+    /// 设备所属的class指定了目录时使用它，否则使用`/sys/dev/char`
     ///
     /// ## 参数
     ///
     /// - `dev`: 设备
-    fn device_to_dev_kobj(&self, _dev: &Arc<dyn Device>) -> Arc<dyn KObject> {
-        // todo: 处理class的逻辑
-        let kobj = sys_dev_char_kset().as_kobject();
-        return kobj;
-    }
-
-    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_links_force_bind#1226
-    pub fn device_links_force_bind(&self, _dev: &Arc<dyn Device>) {
-        todo!("device_links_force_bind")
+    fn device_to_dev_kobj(&self, dev: &Arc<dyn Device>) -> Arc<dyn KObject> {
+        return dev
+            .class()
+            .and_then(|class| class.dev_kobj())
+            .unwrap_or_else(|| sys_dev_char_kset().as_kobject());
     }
 
     /// 把device对象的一些结构进行默认初始化
@@ -634,6 +858,18 @@ impl DeviceManager {
     }
 }
 
+/// 把属性文件对应的kobject转换为具体类型的设备，供属性的show/store回调使用
+///
+/// ## 错误
+///
+/// - `EINVAL`：kobject不是`T`类型的设备
+pub fn kobj_to_device<T: Device>(kobj: Arc<dyn KObject>) -> Result<Arc<T>, SystemError> {
+    return kobj
+        .arc_any()
+        .downcast::<T>()
+        .map_err(|_| SystemError::EINVAL);
+}
+
 /// @brief: 设备注册
 /// @parameter: name: 设备名
 /// @return: 操作成功，返回()，操作失败，返回错误码
@@ -644,16 +880,8 @@ pub fn device_register<T: Device>(device: Arc<T>) -> Result<(), SystemError> {
 /// @brief: 设备卸载
 /// @parameter: name: 设备名
 /// @return: 操作成功，返回()，操作失败，返回错误码
-pub fn device_unregister<T: Device>(_device: Arc<T>) {
-    // DEVICE_MANAGER.add_device(device.id_table(), device.clone());
-    // match sys_device_unregister(&device.id_table().name()) {
-    //     Ok(_) => {
-    //         device.set_inode(None);
-    //         return Ok(());
-    //     }
-    //     Err(_) => Err(DeviceError::RegisterError),
-    // }
-    todo!("device_unregister")
+pub fn device_unregister<T: Device>(device: Arc<T>) {
+    device_manager().unregister(device);
 }
 
 /// 设备文件夹下的`dev`文件的属性
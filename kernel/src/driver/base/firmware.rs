@@ -1,12 +1,43 @@
-use alloc::{string::ToString, sync::Arc};
+//! 固件
+//!
+//! - `/sys/firmware`的kset
+//! - 固件加载接口：驱动通过[`request_firmware`]按照名字从根文件系统的`/lib/firmware`下读取固件。
+//!   使用initramfs作为根文件系统时，固件也应当被放在initramfs的`/lib/firmware`下。
+//!
+//! 读取成功的固件会被缓存，同一个固件被再次请求时不需要重新读取文件，
+//! 直到所有使用者都调用[`release_firmware`]释放了它。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/firmware_loader/main.c
 
-use crate::syscall::SystemError;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
 
-use super::kset::KSet;
+use crate::{
+    filesystem::vfs::{FilePrivateData, FileType, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES},
+    libs::spinlock::SpinLock,
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    syscall::SystemError,
+};
+
+use super::{device::Device, kset::KSet};
 
 /// `/sys/firmware`的kset
 static mut FIRMWARE_KSET_INSTANCE: Option<Arc<KSet>> = None;
 
+/// 依次查找固件的目录
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/firmware_loader/main.c?fi=fw_path#fw_path
+const FIRMWARE_PATHS: [&str; 2] = ["/lib/firmware/updates", "/lib/firmware"];
+
+/// 已经被读取的固件
+static FIRMWARE_CACHE: SpinLock<Vec<Arc<Firmware>>> = SpinLock::new(Vec::new());
+
 #[inline(always)]
 #[allow(dead_code)]
 pub fn sys_firmware_kset() -> Arc<KSet> {
@@ -24,3 +55,172 @@ pub(super) fn firmware_init() -> Result<(), SystemError> {
     }
     return Ok(());
 }
+
+/// 一个被加载到内存中的固件
+#[derive(Debug)]
+pub struct Firmware {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl Firmware {
+    /// 固件的名字，即它相对于`/lib/firmware`的路径
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 固件的内容
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// [`request_firmware_nowait`]的回调函数，参数为设备以及加载的结果
+pub type FirmwareCallback =
+    Box<dyn FnOnce(&Arc<dyn Device>, Result<Arc<Firmware>, SystemError>) + Send>;
+
+/// 加载固件
+///
+/// 固件已经被缓存时直接返回缓存，否则依次在[`FIRMWARE_PATHS`]中查找并读取
+///
+/// ## 参数
+///
+/// - `name`: 固件的名字，即它相对于`/lib/firmware`的路径，如`iwlwifi-8000C-36.ucode`
+/// - `dev`: 请求固件的设备
+///
+/// ## 错误
+///
+/// - `EINVAL`：固件的名字为空，或者包含`..`
+/// - `ENOENT`：所有目录下都找不到这个固件(根文件系统还没有被挂载时也会返回这个错误)
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/firmware_loader/main.c?fi=request_firmware#request_firmware
+#[allow(dead_code)]
+pub fn request_firmware(name: &str, dev: &Arc<dyn Device>) -> Result<Arc<Firmware>, SystemError> {
+    if name.is_empty() || name.split('/').any(|part| part == "..") {
+        return Err(SystemError::EINVAL);
+    }
+
+    if let Some(fw) = FIRMWARE_CACHE.lock().iter().find(|fw| fw.name == name) {
+        return Ok(fw.clone());
+    }
+
+    for dir in FIRMWARE_PATHS.iter() {
+        let path = format!("{}/{}", dir, name.trim_start_matches('/'));
+        match firmware_read_file(&path) {
+            Ok(data) => {
+                kdebug!(
+                    "device '{}': loaded firmware '{}' ({} bytes)",
+                    dev.name(),
+                    path,
+                    data.len()
+                );
+                let fw = Arc::new(Firmware {
+                    name: name.to_string(),
+                    data,
+                });
+                let mut cache = FIRMWARE_CACHE.lock();
+                // 其他线程可能已经读取了同一个固件
+                if let Some(cached) = cache.iter().find(|cached| cached.name == name) {
+                    return Ok(cached.clone());
+                }
+                cache.push(fw.clone());
+                return Ok(fw);
+            }
+            Err(SystemError::ENOENT) => continue,
+            Err(e) => {
+                kwarn!(
+                    "device '{}': failed to read firmware '{}': {:?}",
+                    dev.name(),
+                    path,
+                    e
+                );
+                return Err(e);
+            }
+        }
+    }
+
+    kwarn!("device '{}': firmware '{}' not found", dev.name(), name);
+    return Err(SystemError::ENOENT);
+}
+
+/// 在内核线程中异步地加载固件，加载完成后调用回调函数
+///
+/// 适用于在探测期间根文件系统可能还没有被挂载，或者固件较大、不希望阻塞探测的驱动
+///
+/// ## 参数
+///
+/// - `name`: 固件的名字
+/// - `dev`: 请求固件的设备
+/// - `callback`: 回调函数，无论加载成功与否都会被调用一次
+///
+/// ## 错误
+///
+/// - `ENOMEM`：无法创建内核线程，此时回调函数不会被调用
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/firmware_loader/main.c?fi=request_firmware_nowait#request_firmware_nowait
+#[allow(dead_code)]
+pub fn request_firmware_nowait(
+    name: &str,
+    dev: &Arc<dyn Device>,
+    callback: FirmwareCallback,
+) -> Result<(), SystemError> {
+    let work = SpinLock::new(Some((name.to_string(), dev.clone(), callback)));
+    KernelThreadMechanism::create_and_run(
+        KernelThreadClosure::EmptyClosure((
+            Box::new(move || {
+                if let Some((name, dev, callback)) = work.lock().take() {
+                    let r = request_firmware(&name, &dev);
+                    callback(&dev, r);
+                }
+                0
+            }),
+            (),
+        )),
+        format!("firmware/{}", name),
+    )
+    .ok_or(SystemError::ENOMEM)?;
+    return Ok(());
+}
+
+/// 释放固件。所有使用者都释放了固件之后，它会被从缓存中删除
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/firmware_loader/main.c?fi=release_firmware#release_firmware
+#[allow(dead_code)]
+pub fn release_firmware(fw: Arc<Firmware>) {
+    let mut cache = FIRMWARE_CACHE.lock();
+    // 缓存以及调用者各持有一个引用
+    if Arc::strong_count(&fw) <= 2 {
+        cache.retain(|cached| !Arc::ptr_eq(cached, &fw));
+    }
+}
+
+/// 读取文件的全部内容
+fn firmware_read_file(path: &str) -> Result<Vec<u8>, SystemError> {
+    let inode = ROOT_INODE().lookup_follow_symlink(path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    let metadata = inode.metadata()?;
+    if metadata.file_type != FileType::File {
+        return Err(SystemError::EINVAL);
+    }
+
+    let size = metadata.size as usize;
+    let mut data = vec![0u8; size];
+    let mut offset = 0;
+    while offset < size {
+        let len = inode.read_at(
+            offset,
+            size - offset,
+            &mut data[offset..],
+            &mut FilePrivateData::Unused,
+        )?;
+        if len == 0 {
+            break;
+        }
+        offset += len;
+    }
+    data.truncate(offset);
+    return Ok(data);
+}
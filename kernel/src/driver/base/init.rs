@@ -1,4 +1,10 @@
-use crate::{driver::tty::tty_device::tty_init, syscall::SystemError};
+use crate::{
+    driver::{
+        input::input_init, open_firmware::open_firmware_init, power_supply::power_supply_init,
+        tty::tty_device::tty_init,
+    },
+    syscall::SystemError,
+};
 
 use super::{
     class::classes_init,
@@ -7,6 +13,7 @@ use super::{
     firmware::firmware_init,
     hypervisor::hypervisor_init,
     platform::platform_bus_init,
+    power::power_kset_init,
 };
 
 pub(super) fn driver_init() -> Result<(), SystemError> {
@@ -15,8 +22,10 @@ pub(super) fn driver_init() -> Result<(), SystemError> {
     classes_init()?;
     firmware_init()?;
     hypervisor_init()?;
+    power_kset_init()?;
     platform_bus_init()?;
     cpu_device_manager().init()?;
+    open_firmware_init()?;
 
     // 至此，已完成设备驱动模型的初始化
     // 接下来，初始化设备
@@ -25,7 +34,9 @@ pub(super) fn driver_init() -> Result<(), SystemError> {
 }
 
 fn actual_device_init() -> Result<(), SystemError> {
+    input_init();
     tty_init()?;
+    power_supply_init()?;
 
     return Ok(());
 }
@@ -1,8 +1,10 @@
 use core::{any::Any, fmt::Debug, hash::Hash, ops::Deref};
 
 use alloc::{
+    collections::BTreeMap,
     string::String,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use intertrait::CastFromSync;
 
@@ -15,11 +17,21 @@ use crate::{
     libs::{
         casting::DowncastArc,
         rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
     },
     syscall::SystemError,
 };
 
-use super::kset::KSet;
+use super::{
+    kset::KSet,
+    uevent::{kobject_uevent, KObjectAction},
+};
+
+/// 已经被添加到sysfs中的kobject对父kobject持有的引用，保证父kobject不会先于子kobject被释放
+///
+/// key为子kobject的地址
+static KOBJ_PARENT_REFS: SpinLock<BTreeMap<usize, Arc<dyn KObject>>> =
+    SpinLock::new(BTreeMap::new());
 
 pub trait KObject: Any + Send + Sync + Debug + CastFromSync {
     fn as_any_ref(&self) -> &dyn core::any::Any;
@@ -203,10 +215,91 @@ impl KObjectManager {
             return Err(e);
         }
 
+        // 在子kobject被移除之前，持有父kobject的引用
+        if let Some(parent) = kobj.parent().and_then(|p| p.upgrade()) {
+            KOBJ_PARENT_REFS
+                .lock()
+                .insert(Self::kobj_key(&kobj), parent);
+        }
+
         kobj.update_kobj_state(Some(KObjectState::IN_SYSFS), None);
         return Ok(());
     }
 
+    /// 把kobject从sysfs以及它所属的kset中移除，并释放它对父kobject的引用
+    ///
+    /// kobject本身仍然可以被使用，直到它的最后一个引用被释放
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kobject.c?fi=kobject_del#kobject_del
+    pub fn remove_kobj(kobj: &Arc<dyn KObject>) {
+        if !kobj.kobj_state().contains(KObjectState::IN_SYSFS) {
+            return;
+        }
+
+        sysfs_instance().remove_dir(kobj);
+        kobj.update_kobj_state(None, Some(KObjectState::IN_SYSFS));
+
+        if let Some(kset) = kobj.kset() {
+            kset.leave(kobj);
+        }
+
+        let parent = KOBJ_PARENT_REFS.lock().remove(&Self::kobj_key(kobj));
+        if let Some(parent) = parent {
+            Self::kobj_put(parent);
+        }
+    }
+
+    /// 释放调用者持有的kobject引用
+    ///
+    /// sysfs与kset只持有kobject的弱引用。如果这是kobject的最后一个引用，
+    /// 就补发remove事件、清理它在sysfs中留下的内容，然后调用它的ktype的release回调
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kobject.c?fi=kobject_put#kobject_put
+    pub fn kobj_put(kobj: Arc<dyn KObject>) {
+        if Arc::strong_count(&kobj) > 1 {
+            return;
+        }
+
+        let state = *kobj.kobj_state();
+        if state.contains(KObjectState::ADD_UEVENT_SENT)
+            && !state.contains(KObjectState::REMOVE_UEVENT_SENT)
+        {
+            kobject_uevent(&kobj, KObjectAction::Remove).ok();
+        }
+
+        if state.contains(KObjectState::IN_SYSFS) {
+            Self::remove_kobj(&kobj);
+        }
+
+        if let Some(ktype) = kobj.kobj_type() {
+            ktype.release(kobj);
+        }
+    }
+
+    #[inline]
+    fn kobj_key(kobj: &Arc<dyn KObject>) -> usize {
+        Arc::as_ptr(kobj) as *const () as usize
+    }
+
+    /// 获取kobject在sysfs中的路径(不包含`/sys`前缀)，例如`/devices/platform/serial8250`
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kobject.c?fi=kobject_get_path#kobject_get_path
+    pub fn kobj_get_path(kobj: &Arc<dyn KObject>) -> String {
+        let mut names = Vec::new();
+        let mut cur = Some(kobj.clone());
+        while let Some(k) = cur {
+            names.push(k.name());
+            cur = k.parent().and_then(|p| p.upgrade());
+        }
+
+        let mut path = String::new();
+        for name in names.iter().rev() {
+            path.push('/');
+            path.push_str(name);
+        }
+        return path;
+    }
+
     fn create_dir(kobj: Arc<dyn KObject>) -> Result<(), SystemError> {
         // create dir in sysfs
         sysfs_instance().create_dir(kobj.clone())?;
@@ -15,6 +15,7 @@ use crate::{
 use super::kobject::{
     DynamicKObjKType, KObjType, KObject, KObjectManager, KObjectState, LockedKObjectState,
 };
+use super::uevent::{kobject_uevent, KObjectAction};
 
 #[derive(Debug)]
 pub struct KSet {
@@ -85,8 +86,21 @@ impl KSet {
     }
 
     pub fn register(&self, join_kset: Option<Arc<KSet>>) -> Result<(), SystemError> {
-        return KObjectManager::add_kobj(self.self_ref.upgrade().unwrap(), join_kset);
-        // todo: 引入uevent之后，发送uevent
+        let kobj = self.self_ref.upgrade().unwrap() as Arc<dyn KObject>;
+        KObjectManager::add_kobj(kobj.clone(), join_kset)?;
+        // 不属于任何kset的顶层kset(如`/sys/devices`)不发送uevent
+        kobject_uevent(&kobj, KObjectAction::Add).ok();
+        return Ok(());
+    }
+
+    /// 把kset从sysfs中移除。kset在最后一个引用被释放之后销毁
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kobject.c?fi=kset_unregister#kset_unregister
+    #[allow(dead_code)]
+    pub fn unregister(&self) {
+        let kobj = self.as_kobject();
+        kobject_uevent(&kobj, KObjectAction::Remove).ok();
+        KObjectManager::remove_kobj(&kobj);
     }
 
     /// 把一个kobject加入到当前kset中。
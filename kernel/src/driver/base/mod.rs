@@ -11,5 +11,7 @@ pub mod kobject;
 pub mod kset;
 pub mod map;
 pub mod platform;
+pub mod power;
 pub mod subsys;
 pub mod swnode;
+pub mod uevent;
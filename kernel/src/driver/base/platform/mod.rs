@@ -13,6 +13,7 @@ use core::fmt::Debug;
 
 pub mod platform_device;
 pub mod platform_driver;
+pub mod resource;
 pub mod subsys;
 
 static mut PLATFORM_BUS_DEVICE: Option<Arc<PlatformBusDevice>> = None;
@@ -55,6 +56,13 @@ impl CompatibleTable {
         self.0.intersection(&other.0).next().is_some()
     }
 
+    /// @brief: 判断匹配表中是否有指定的条目
+    /// @parameter compatible: 匹配条目
+    /// @return: 如果有，返回true，否则，返回false
+    pub fn contains(&self, compatible: &str) -> bool {
+        self.0.contains(compatible)
+    }
+
     /// @brief: 添加一组匹配条目
     /// @param:
     #[allow(dead_code)]
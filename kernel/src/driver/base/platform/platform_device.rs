@@ -1,6 +1,12 @@
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+};
+
 use alloc::{
     string::{String, ToString},
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use ida::IdAllocator;
 
@@ -10,20 +16,27 @@ use crate::{
             bus::{Bus, BusState},
             device_manager,
             driver::Driver,
-            Device, DeviceNumber, DevicePrivateData, DeviceType, IdTable,
+            Device, DeviceKObjType, DeviceNumber, DevicePrivateData, DeviceType, IdTable,
         },
         kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
         kset::KSet,
     },
+    driver::open_firmware::fdt::DeviceNode,
+    exception::irqdesc::IrqNumber,
     filesystem::kernfs::KernFSInode,
     libs::{
-        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
         spinlock::SpinLock,
     },
     syscall::SystemError,
 };
 
-use super::{super::device::DeviceState, platform_bus, platform_bus_device, CompatibleTable};
+use super::{
+    super::device::DeviceState,
+    platform_bus, platform_bus_device,
+    resource::{Resource, ResourceFlags},
+    CompatibleTable,
+};
 
 /// 平台设备id分配器
 static PLATFORM_DEVID_IDA: IdAllocator = IdAllocator::new(i32::MAX as usize);
@@ -61,6 +74,17 @@ pub trait PlatformDevice: Device {
     fn set_pdev_id_auto(&self, id_auto: bool);
 
     fn compatible_table(&self) -> CompatibleTable;
+
+    /// 描述这个设备的设备树节点，不是由设备树创建的设备返回None
+    fn of_node(&self) -> Option<Arc<DeviceNode>> {
+        None
+    }
+
+    /// 设备占用的资源(MMIO区域、I/O端口、中断号)
+    fn resources(&self) -> &[Resource] {
+        &[]
+    }
+
     /// @brief: 判断设备是否初始化
     /// @parameter: None
     /// @return: 如果已经初始化，返回true，否则，返回false
@@ -72,6 +96,32 @@ pub trait PlatformDevice: Device {
     fn set_state(&self, set_state: DeviceState);
 }
 
+impl dyn PlatformDevice {
+    /// 获取设备的第`index`个类型为`flags`的资源
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/platform.c?fi=platform_get_resource#platform_get_resource
+    pub fn get_resource(&self, flags: ResourceFlags, index: usize) -> Option<Resource> {
+        self.resources()
+            .iter()
+            .filter(|res| res.flags().intersects(flags))
+            .nth(index)
+            .copied()
+    }
+
+    /// 获取设备的第`index`个中断号
+    ///
+    /// ## 错误
+    ///
+    /// - `ENXIO`：设备没有这个中断
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/platform.c?fi=platform_get_irq#platform_get_irq
+    pub fn get_irq(&self, index: usize) -> Result<IrqNumber, SystemError> {
+        self.get_resource(ResourceFlags::IRQ, index)
+            .map(|res| res.start() as IrqNumber)
+            .ok_or(SystemError::ENXIO)
+    }
+}
+
 #[derive(Debug)]
 pub struct PlatformDeviceManager;
 
@@ -118,6 +168,219 @@ impl PlatformDeviceManager {
             return r;
         }
     }
+
+    /// 创建并注册一个只有名字、id以及资源的平台设备
+    ///
+    /// 调用者需要持有返回的设备，直到不再需要它
+    ///
+    /// ## 参数
+    ///
+    /// - `name`: 设备名，与同名的平台驱动匹配
+    /// - `id`: 平台设备id，可以是[`PLATFORM_DEVID_NONE`]或者[`PLATFORM_DEVID_AUTO`]
+    /// - `resources`: 设备占用的资源
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/platform_device.h?fi=platform_device_register_simple#platform_device_register_simple
+    pub fn register_simple(
+        &self,
+        name: &str,
+        id: i32,
+        resources: Vec<Resource>,
+    ) -> Result<Arc<GenericPlatformDevice>, SystemError> {
+        let pdev = GenericPlatformDevice::new(name, id, resources);
+        self.device_add(pdev.clone() as Arc<dyn PlatformDevice>)?;
+        return Ok(pdev);
+    }
+}
+
+/// 只有名字、id以及资源的平台设备，用于注册地址固定的传统设备
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/platform.c?fi=platform_object#platform_object
+#[derive(Debug)]
+#[cast_to([sync] Device, PlatformDevice)]
+pub struct GenericPlatformDevice {
+    /// 设备id是否自动分配
+    id_auto: AtomicBool,
+    /// 平台设备id
+    id: AtomicI32,
+    pdev_name: String,
+    resources: Vec<Resource>,
+    inner: RwLock<InnerGenericPlatformDevice>,
+    kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+struct InnerGenericPlatformDevice {
+    name: String,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    bus: Option<Arc<dyn Bus>>,
+    inode: Option<Arc<KernFSInode>>,
+    driver: Option<Weak<dyn Driver>>,
+    device_state: DeviceState,
+    can_match: bool,
+}
+
+impl GenericPlatformDevice {
+    pub fn new(name: &str, id: i32, resources: Vec<Resource>) -> Arc<Self> {
+        let r = Arc::new(Self {
+            id_auto: AtomicBool::new(false),
+            id: AtomicI32::new(id),
+            pdev_name: name.to_string(),
+            resources,
+            inner: RwLock::new(InnerGenericPlatformDevice {
+                name: name.to_string(),
+                kset: None,
+                parent_kobj: None,
+                bus: None,
+                inode: None,
+                driver: None,
+                device_state: DeviceState::NotInitialized,
+                can_match: true,
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        });
+
+        device_manager().device_default_initialize(&(r.clone() as Arc<dyn Device>));
+
+        return r;
+    }
+}
+
+impl PlatformDevice for GenericPlatformDevice {
+    fn pdev_name(&self) -> &str {
+        &self.pdev_name
+    }
+
+    fn pdev_id(&self) -> (i32, bool) {
+        return (
+            self.id.load(Ordering::SeqCst),
+            self.id_auto.load(Ordering::SeqCst),
+        );
+    }
+
+    fn set_pdev_id(&self, id: i32) {
+        self.id.store(id, Ordering::SeqCst);
+    }
+
+    fn set_pdev_id_auto(&self, id_auto: bool) {
+        self.id_auto.store(id_auto, Ordering::SeqCst);
+    }
+
+    fn compatible_table(&self) -> CompatibleTable {
+        CompatibleTable::new(Vec::new())
+    }
+
+    fn resources(&self) -> &[Resource] {
+        &self.resources
+    }
+
+    fn is_initialized(&self) -> bool {
+        return self.inner.read().device_state == DeviceState::Initialized;
+    }
+
+    fn set_state(&self, set_state: DeviceState) {
+        self.inner.write().device_state = set_state;
+    }
+}
+
+impl Device for GenericPlatformDevice {
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::PlatformDev
+    }
+
+    fn id_table(&self) -> IdTable {
+        return IdTable::new(self.pdev_name.clone(), DeviceNumber::new(0));
+    }
+
+    fn release(&self) {}
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        self.inner.read().driver.clone()?.upgrade()
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner.write().driver = driver;
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner.read().can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner.write().can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+}
+
+impl KObject for GenericPlatformDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        Some(&DeviceKObjType)
+    }
+
+    fn set_kobj_type(&self, _ktype: Option<&'static dyn KObjType>) {}
+
+    fn name(&self) -> String {
+        self.inner.read().name.clone()
+    }
+
+    fn set_name(&self, name: String) {
+        self.inner.write().name = name;
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
 }
 
 #[derive(Debug)]
@@ -8,7 +8,7 @@ use crate::{
     syscall::SystemError,
 };
 
-use super::{platform_bus, platform_device::PlatformDevice};
+use super::{platform_bus, platform_device::PlatformDevice, CompatibleTable};
 
 /// @brief: 实现该trait的设备驱动实例应挂载在platform总线上，
 ///         同时应该实现Driver trait
@@ -27,6 +27,11 @@ pub trait PlatformDriver: Driver {
     fn shutdown(&self, device: &Arc<dyn PlatformDevice>) -> Result<(), SystemError>;
     fn suspend(&self, device: &Arc<dyn PlatformDevice>) -> Result<(), SystemError>;
     fn resume(&self, device: &Arc<dyn PlatformDevice>) -> Result<(), SystemError>;
+
+    /// 驱动支持的设备树节点的`compatible`，不支持设备树的驱动返回None
+    fn of_match_table(&self) -> Option<CompatibleTable> {
+        None
+    }
 }
 
 #[inline(always)]
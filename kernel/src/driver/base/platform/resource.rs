@@ -0,0 +1,70 @@
+//! 平台设备占用的资源：MMIO区域、I/O端口以及中断号
+//!
+//! 地址固定的传统设备(如HPET、RTC、i8042)在注册时声明自己的资源，驱动在probe时通过
+//! `get_resource`/`get_irq`取得它们，而不是在代码中写死地址。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/ioport.h
+
+bitflags! {
+    /// 资源的类型
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/ioport.h?fi=IORESOURCE_IO#IORESOURCE_IO
+    pub struct ResourceFlags: u32 {
+        /// I/O端口
+        const IO = 0x00000100;
+        /// MMIO区域
+        const MEM = 0x00000200;
+        /// 中断号
+        const IRQ = 0x00000400;
+    }
+}
+
+/// 设备占用的一段连续的资源，范围为`[start, end]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resource {
+    start: usize,
+    end: usize,
+    flags: ResourceFlags,
+}
+
+impl Resource {
+    /// 创建一段资源，`size`必须大于0
+    pub const fn new(start: usize, size: usize, flags: ResourceFlags) -> Self {
+        Self {
+            start,
+            end: start + size - 1,
+            flags,
+        }
+    }
+
+    /// 起始物理地址为`start`、长度为`size`字节的MMIO区域
+    pub const fn mem(start: usize, size: usize) -> Self {
+        Self::new(start, size, ResourceFlags::MEM)
+    }
+
+    /// 从`start`开始的`size`个I/O端口
+    pub const fn io(start: usize, size: usize) -> Self {
+        Self::new(start, size, ResourceFlags::IO)
+    }
+
+    /// 中断号(对于ISA设备，是ISA中断号)
+    pub const fn irq(irq: usize) -> Self {
+        Self::new(irq, 1, ResourceFlags::IRQ)
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn size(&self) -> usize {
+        self.end - self.start + 1
+    }
+
+    pub fn flags(&self) -> ResourceFlags {
+        self.flags
+    }
+}
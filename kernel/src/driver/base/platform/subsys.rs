@@ -12,6 +12,7 @@ use crate::{
             kobject::KObject,
             subsys::SubSysPrivate,
         },
+        open_firmware::of_driver_match_device,
     },
     filesystem::{
         sysfs::{Attribute, AttributeGroup},
@@ -37,6 +38,15 @@ impl PlatformBus {
 
         return bus;
     }
+
+    /// 获取平台设备以及它绑定的平台驱动，设备没有绑定驱动时返回None
+    fn to_platform_pair(
+        device: &Arc<dyn Device>,
+    ) -> Option<(Arc<dyn PlatformDriver>, Arc<dyn PlatformDevice>)> {
+        let pdrv = device.driver()?.cast::<dyn PlatformDriver>().ok()?;
+        let pdev = device.clone().cast::<dyn PlatformDevice>().ok()?;
+        return Some((pdrv, pdev));
+    }
 }
 
 impl Bus for PlatformBus {
@@ -74,20 +84,45 @@ impl Bus for PlatformBus {
         return pdrv.probe(&pdev);
     }
 
-    fn remove(&self, _device: &Arc<dyn Device>) -> Result<(), SystemError> {
-        todo!()
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/platform.c?fi=platform_remove#platform_remove
+    fn remove(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        match Self::to_platform_pair(device) {
+            Some((pdrv, pdev)) => pdrv.remove(&pdev),
+            None => Ok(()),
+        }
     }
 
     fn sync_state(&self, _device: &Arc<dyn Device>) {
         todo!()
     }
 
-    fn shutdown(&self, _device: &Arc<dyn Device>) {
-        todo!()
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/platform.c?fi=platform_shutdown#platform_shutdown
+    fn shutdown(&self, device: &Arc<dyn Device>) {
+        if let Some((pdrv, pdev)) = Self::to_platform_pair(device) {
+            if let Err(e) = PlatformDriver::shutdown(pdrv.as_ref(), &pdev) {
+                kwarn!(
+                    "platform device '{}' shutdown failed: {:?}",
+                    device.name(),
+                    e
+                );
+            }
+        }
     }
 
-    fn resume(&self, _device: &Arc<dyn Device>) -> Result<(), SystemError> {
-        todo!()
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/platform.c?fi=platform_pm_suspend#platform_pm_suspend
+    fn suspend(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        match Self::to_platform_pair(device) {
+            Some((pdrv, pdev)) => PlatformDriver::suspend(pdrv.as_ref(), &pdev),
+            None => Ok(()),
+        }
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/platform.c?fi=platform_pm_resume#platform_pm_resume
+    fn resume(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        match Self::to_platform_pair(device) {
+            Some((pdrv, pdev)) => PlatformDriver::resume(pdrv.as_ref(), &pdev),
+            None => Ok(()),
+        }
     }
 
     ///
@@ -120,6 +155,18 @@ impl Bus for PlatformBus {
         device: &Arc<dyn Device>,
         driver: &Arc<dyn Driver>,
     ) -> Result<bool, SystemError> {
+        // 尝试根据设备树节点的compatible属性匹配
+        if let (Ok(pdev), Ok(pdrv)) = (
+            device.clone().cast::<dyn PlatformDevice>(),
+            driver.clone().cast::<dyn PlatformDriver>(),
+        ) {
+            if let (Some(node), Some(table)) = (pdev.of_node(), pdrv.of_match_table()) {
+                if of_driver_match_device(&node, &table) {
+                    return Ok(true);
+                }
+            }
+        }
+
         // 尝试从 ACPI 中匹配
         if let Ok(x) = acpi_manager().driver_match_device(driver, device) {
             if x {
@@ -0,0 +1,160 @@
+//! 设备的电源管理
+//!
+//! 驱动核心按照设备被添加的顺序维护一个设备列表。父设备总是先于子设备被添加，
+//! 因此按照逆序让设备睡眠或者关闭时，子设备先于父设备停止工作；按照正序恢复设备时，父设备先于子设备恢复工作。
+//!
+//! 每个设备先执行总线(没有总线时为驱动)的回调，再执行设备自身的回调；恢复时顺序相反。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/power/main.c
+
+use alloc::{
+    string::ToString,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{libs::spinlock::SpinLock, syscall::SystemError};
+
+use super::{device::Device, kset::KSet};
+
+/// `/sys/power`的kset
+static mut POWER_KSET_INSTANCE: Option<Arc<KSet>> = None;
+
+#[inline(always)]
+pub fn sys_power_kset() -> Arc<KSet> {
+    unsafe { POWER_KSET_INSTANCE.clone().unwrap() }
+}
+
+/// 初始化`/sys/power`的kset
+pub(super) fn power_kset_init() -> Result<(), SystemError> {
+    let power_kset = KSet::new("power".to_string());
+    power_kset
+        .register(None)
+        .expect("register power kset failed");
+    unsafe {
+        POWER_KSET_INSTANCE = Some(power_kset);
+    }
+    return Ok(());
+}
+
+/// 按照添加顺序排列的所有设备
+static DPM_LIST: SpinLock<Vec<Weak<dyn Device>>> = SpinLock::new(Vec::new());
+
+/// 把设备加入电源管理的设备列表，在设备被添加到系统中时调用
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/power/main.c?fi=device_pm_add#device_pm_add
+pub fn device_pm_add(dev: &Arc<dyn Device>) {
+    let mut list = DPM_LIST.lock();
+    list.retain(|d| d.strong_count() > 0);
+    list.push(Arc::downgrade(dev));
+}
+
+/// 把设备从电源管理的设备列表中删除，在设备被从系统中删除时调用
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/power/main.c?fi=device_pm_remove#device_pm_remove
+pub fn device_pm_remove(dev: &Arc<dyn Device>) {
+    let ptr = Arc::as_ptr(dev) as *const ();
+    DPM_LIST
+        .lock()
+        .retain(|d| d.strong_count() > 0 && d.as_ptr() as *const () != ptr);
+}
+
+/// 把设备移动到电源管理列表的末尾，让它在睡眠与关机时先于列表中的其他设备停止工作
+///
+/// ## 返回值
+///
+/// 设备的子设备。调用者需要把它们也移动到末尾，以保持子设备在父设备之后
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/power/main.c?fi=device_pm_move_last#device_pm_move_last
+pub fn device_pm_move_last(dev: &Arc<dyn Device>) -> Vec<Arc<dyn Device>> {
+    let ptr = Arc::as_ptr(dev) as *const ();
+    let mut list = DPM_LIST.lock();
+    if let Some(pos) = list.iter().position(|d| d.as_ptr() as *const () == ptr) {
+        let d = list.remove(pos);
+        list.push(d);
+    }
+    drop(list);
+
+    return dpm_list_snapshot()
+        .into_iter()
+        .filter(|d| {
+            d.parent()
+                .and_then(|p| p.upgrade())
+                .map_or(false, |p| Arc::as_ptr(&p) as *const () == ptr)
+        })
+        .collect();
+}
+
+/// 获取设备列表中仍然存在的设备，按照添加顺序排列
+fn dpm_list_snapshot() -> Vec<Arc<dyn Device>> {
+    return DPM_LIST.lock().iter().filter_map(|d| d.upgrade()).collect();
+}
+
+fn device_suspend(dev: &Arc<dyn Device>) -> Result<(), SystemError> {
+    if let Some(bus) = dev.bus() {
+        bus.suspend(dev)?;
+    } else if let Some(driver) = dev.driver() {
+        driver.suspend(dev)?;
+    }
+    return dev.suspend();
+}
+
+fn device_resume(dev: &Arc<dyn Device>) -> Result<(), SystemError> {
+    dev.resume()?;
+    if let Some(bus) = dev.bus() {
+        return bus.resume(dev);
+    } else if let Some(driver) = dev.driver() {
+        return driver.resume(dev);
+    }
+    return Ok(());
+}
+
+/// 让所有设备进入睡眠状态，子设备先于父设备
+///
+/// ## 错误
+///
+/// 某个设备睡眠失败时，已经睡眠的设备会被恢复，然后返回这个设备的错误
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/power/main.c?fi=dpm_suspend#dpm_suspend
+pub fn dpm_suspend() -> Result<(), SystemError> {
+    let devices = dpm_list_snapshot();
+    for (i, dev) in devices.iter().enumerate().rev() {
+        if let Err(e) = device_suspend(dev) {
+            kerror!("PM: device '{}' failed to suspend: {:?}", dev.name(), e);
+            for dev in devices[i + 1..].iter() {
+                if let Err(e) = device_resume(dev) {
+                    kerror!("PM: device '{}' failed to resume: {:?}", dev.name(), e);
+                }
+            }
+            return Err(e);
+        }
+    }
+    return Ok(());
+}
+
+/// 让所有设备从睡眠状态中恢复，父设备先于子设备。某个设备恢复失败时，继续恢复其他设备
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/power/main.c?fi=dpm_resume#dpm_resume
+pub fn dpm_resume() {
+    for dev in dpm_list_snapshot().iter() {
+        if let Err(e) = device_resume(dev) {
+            kerror!("PM: device '{}' failed to resume: {:?}", dev.name(), e);
+        }
+    }
+}
+
+/// 关机或者重启之前，让所有设备停止工作，子设备先于父设备
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/base/core.c?fi=device_shutdown#device_shutdown
+pub fn device_shutdown() {
+    for dev in dpm_list_snapshot().iter().rev() {
+        if let Some(bus) = dev.bus() {
+            kdebug!("shutdown device '{}'", dev.name());
+            bus.shutdown(dev);
+        } else if let Some(driver) = dev.driver() {
+            kdebug!("shutdown device '{}'", dev.name());
+            driver.shutdown(dev);
+        }
+        dev.shutdown();
+    }
+}
@@ -19,6 +19,7 @@ use crate::{
 };
 
 use super::{
+    class::Class,
     device::{
         bus::{Bus, BusNotifyEvent},
         driver::Driver,
@@ -34,7 +35,9 @@ pub struct SubSysPrivate {
     subsys: Arc<KSet>,
     ksets: RwLock<SubSysKSets>,
     /// 指向拥有当前结构体的`dyn bus`对象的弱引用
-    bus: SpinLock<Weak<dyn Bus>>,
+    bus: SpinLock<Option<Weak<dyn Bus>>>,
+    /// 指向拥有当前结构体的`dyn class`对象的弱引用
+    class: SpinLock<Option<Weak<dyn Class>>>,
     drivers_autoprobe: AtomicBool,
     /// 当前总线上的所有设备
     devices: RwLock<Vec<Weak<dyn Device>>>,
@@ -47,6 +50,8 @@ pub struct SubSysPrivate {
 #[derive(Debug)]
 struct SubSysKSets {
     /// 子系统的`devices`目录
+    ///
+    /// 对于class，是`/sys/devices/virtual/<class>`目录，没有父设备的class设备被放在这里
     devices_kset: Option<Arc<KSet>>,
     /// 子系统的`drivers`目录
     drivers_kset: Option<Arc<KSet>>,
@@ -72,7 +77,8 @@ impl SubSysPrivate {
             subsys,
             ksets: RwLock::new(SubSysKSets::new()),
             drivers_autoprobe: AtomicBool::new(false),
-            bus: SpinLock::new(bus),
+            bus: SpinLock::new(Some(bus)),
+            class: SpinLock::new(None),
             devices: RwLock::new(Vec::new()),
             drivers: RwLock::new(Vec::new()),
             interfaces,
@@ -80,18 +86,48 @@ impl SubSysPrivate {
         };
     }
 
+    /// 创建一个class的子系统信息
+    ///
+    /// ## 参数
+    ///
+    /// - `name`: class的名字
+    pub fn new_class(name: String) -> Self {
+        let subsys = KSet::new(name);
+        return Self {
+            subsys,
+            ksets: RwLock::new(SubSysKSets::new()),
+            drivers_autoprobe: AtomicBool::new(false),
+            bus: SpinLock::new(None),
+            class: SpinLock::new(None),
+            devices: RwLock::new(Vec::new()),
+            drivers: RwLock::new(Vec::new()),
+            interfaces: &[],
+            bus_notifier: AtomicNotifierChain::new(),
+        };
+    }
+
     pub fn subsys(&self) -> Arc<KSet> {
         return self.subsys.clone();
     }
 
     #[inline]
     #[allow(dead_code)]
-    pub fn bus(&self) -> Weak<dyn Bus> {
+    pub fn bus(&self) -> Option<Weak<dyn Bus>> {
         return self.bus.lock().clone();
     }
 
     pub fn set_bus(&self, bus: Weak<dyn Bus>) {
-        *self.bus.lock() = bus;
+        *self.bus.lock() = Some(bus);
+    }
+
+    #[inline]
+    #[allow(dead_code)]
+    pub fn class(&self) -> Option<Weak<dyn Class>> {
+        return self.class.lock().clone();
+    }
+
+    pub fn set_class(&self, class: Weak<dyn Class>) {
+        *self.class.lock() = Some(class);
     }
 
     pub fn devices(&self) -> RwLockReadGuard<Vec<Weak<dyn Device>>> {
@@ -170,7 +206,6 @@ impl SubSysPrivate {
         return Ok(());
     }
 
-    #[allow(dead_code)]
     pub fn remove_device_from_vec(&self, device: &Arc<dyn Device>) {
         let mut devices = self.devices.write();
         let device_weak = Arc::downgrade(device);
@@ -0,0 +1,173 @@
+//! 内核对象事件(uevent)
+//!
+//! kobject被添加、移除或者状态发生变化时，内核生成一条uevent，通过NETLINK_KOBJECT_UEVENT协议的netlink socket
+//! 广播给用户态的设备管理程序(如mdev、udev)，由它们创建或者删除设备节点、加载固件。
+//!
+//! 消息的格式与Linux一致：首先是`<ACTION>@<DEVPATH>`，然后是若干个`KEY=VALUE`形式的环境变量，每一项都以'\0'结尾。
+//! 其中一定包含`ACTION`、`DEVPATH`、`SUBSYSTEM`和`SEQNUM`，设备号不为0的设备还包含`MAJOR`、`MINOR`和`DEVNAME`。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kobject_uevent.c
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    net::socket::{netlink_broadcast, NETLINK_KOBJECT_UEVENT},
+    syscall::SystemError,
+};
+
+use super::kobject::{KObject, KObjectManager, KObjectState};
+
+/// 一条uevent中环境变量的最大数量
+pub const UEVENT_NUM_ENVP: usize = 64;
+/// 一条uevent中环境变量的最大总长度
+pub const UEVENT_BUFFER_SIZE: usize = 2048;
+
+/// uevent被广播到的netlink多播组
+const UEVENT_NETLINK_GROUP: u32 = 1;
+
+/// uevent的序列号，每发出一条uevent加1
+static UEVENT_SEQNUM: AtomicU64 = AtomicU64::new(0);
+
+/// uevent的动作
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/kobject.h?fi=kobject_action#kobject_action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum KObjectAction {
+    Add,
+    Remove,
+    Change,
+    Move,
+    Online,
+    Offline,
+    Bind,
+    Unbind,
+}
+
+impl KObjectAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KObjectAction::Add => "add",
+            KObjectAction::Remove => "remove",
+            KObjectAction::Change => "change",
+            KObjectAction::Move => "move",
+            KObjectAction::Online => "online",
+            KObjectAction::Offline => "offline",
+            KObjectAction::Bind => "bind",
+            KObjectAction::Unbind => "unbind",
+        }
+    }
+}
+
+/// 一条uevent的环境变量
+#[derive(Debug, Default)]
+pub struct KObjUeventEnv {
+    envp: Vec<String>,
+    buflen: usize,
+}
+
+impl KObjUeventEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加一个`KEY=VALUE`形式的环境变量
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOMEM`：环境变量的数量或者总长度超过了限制
+    pub fn add_var(&mut self, var: String) -> Result<(), SystemError> {
+        if self.envp.len() >= UEVENT_NUM_ENVP || self.buflen + var.len() + 1 > UEVENT_BUFFER_SIZE {
+            return Err(SystemError::ENOMEM);
+        }
+        self.buflen += var.len() + 1;
+        self.envp.push(var);
+        return Ok(());
+    }
+
+    pub fn envp(&self) -> &[String] {
+        &self.envp
+    }
+}
+
+/// 发送一条uevent
+///
+/// ## 参数
+///
+/// - `kobj`: 发生事件的kobject
+/// - `action`: 事件的动作
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kobject_uevent.c?fi=kobject_uevent#kobject_uevent
+pub fn kobject_uevent(kobj: &Arc<dyn KObject>, action: KObjectAction) -> Result<(), SystemError> {
+    return kobject_uevent_env(kobj, action, &[]);
+}
+
+/// 发送一条带有额外环境变量的uevent
+///
+/// ## 参数
+///
+/// - `kobj`: 发生事件的kobject
+/// - `action`: 事件的动作
+/// - `envp_ext`: 额外的`KEY=VALUE`形式的环境变量。其中包含`SUBSYSTEM`时，它会代替kobject所属的kset的名字
+///
+/// ## 错误
+///
+/// - `EINVAL`：kobject以及它的祖先都不属于任何kset
+/// - `ENOMEM`：环境变量的数量或者总长度超过了限制
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kobject_uevent.c?fi=kobject_uevent_env#kobject_uevent_env
+pub fn kobject_uevent_env(
+    kobj: &Arc<dyn KObject>,
+    action: KObjectAction,
+    envp_ext: &[String],
+) -> Result<(), SystemError> {
+    // 找到kobject所属的kset，没有kset的kobject不发送uevent
+    let mut top_kobj = kobj.clone();
+    while top_kobj.kset().is_none() {
+        top_kobj = top_kobj
+            .parent()
+            .and_then(|p| p.upgrade())
+            .ok_or(SystemError::EINVAL)?;
+    }
+    let kset = top_kobj.kset().unwrap();
+
+    let devpath = KObjectManager::kobj_get_path(kobj);
+
+    let mut env = KObjUeventEnv::new();
+    env.add_var(format!("ACTION={}", action.as_str()))?;
+    env.add_var(format!("DEVPATH={}", devpath))?;
+    let ext_has_subsystem = envp_ext.iter().any(|var| var.starts_with("SUBSYSTEM="));
+    if !ext_has_subsystem {
+        env.add_var(format!("SUBSYSTEM={}", kset.name()))?;
+    }
+    for var in envp_ext {
+        env.add_var(var.clone())?;
+    }
+
+    // 即使没有socket在接收，也要标记已经发送过，kobject被删除时据此决定是否要补发remove事件
+    match action {
+        KObjectAction::Add => kobj.update_kobj_state(Some(KObjectState::ADD_UEVENT_SENT), None),
+        KObjectAction::Remove => {
+            kobj.update_kobj_state(Some(KObjectState::REMOVE_UEVENT_SENT), None)
+        }
+        _ => {}
+    }
+
+    let seqnum = UEVENT_SEQNUM.fetch_add(1, Ordering::SeqCst) + 1;
+    env.add_var(format!("SEQNUM={}", seqnum))?;
+
+    let mut msg = Vec::with_capacity(devpath.len() + env.buflen + 16);
+    msg.extend_from_slice(action.as_str().as_bytes());
+    msg.push(b'@');
+    msg.extend_from_slice(devpath.as_bytes());
+    msg.push(0);
+    for var in env.envp() {
+        msg.extend_from_slice(var.as_bytes());
+        msg.push(0);
+    }
+    netlink_broadcast(NETLINK_KOBJECT_UEVENT, UEVENT_NETLINK_GROUP, &msg);
+
+    return Ok(());
+}
@@ -0,0 +1,165 @@
+//! CPU调频(cpufreq)
+//!
+//! 由调频驱动提供P-state列表，ondemand governor在时钟中断中定期统计每个cpu的负载，
+//! 负载高时切换到最高频率，负载低时按比例降低频率。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/cpufreq/cpufreq_ondemand.c
+
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use alloc::sync::Arc;
+
+use crate::{
+    arch::CurrentTimeArch, driver::cpuidle::cpuidle_manager, kinfo, kwarn, libs::rwlock::RwLock,
+    mm::percpu::PerCpu, smp::core::smp_get_processor_id, syscall::SystemError, time::TimeArch,
+};
+
+/// 采样周期(ns)
+const CPUFREQ_SAMPLING_RATE: u64 = 50 * 1000 * 1000;
+/// 负载超过该百分比时，直接切换到最高频率
+const CPUFREQ_UP_THRESHOLD: u64 = 80;
+
+/// 一个性能状态(P-state)
+#[derive(Debug, Clone)]
+pub struct CpuFreqPState {
+    /// 频率(kHz)
+    pub freq_khz: u64,
+    /// 驱动私有的控制值
+    pub control: u64,
+}
+
+/// 调频驱动
+pub trait CpuFreqDriver: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// 驱动支持的P-state，按照频率从高到低的顺序排列
+    fn pstates(&self) -> &[CpuFreqPState];
+
+    /// 把当前cpu切换到第`index`个P-state
+    fn target(&self, index: usize) -> Result<(), SystemError>;
+}
+
+/// 每个cpu的采样信息
+#[derive(Debug)]
+struct CpuFreqCpuData {
+    /// 上一次采样的时间(ns)
+    last_sample: AtomicU64,
+    /// 上一次采样时累计的空闲时间(ns)
+    last_idle: AtomicU64,
+    /// 当前所处的P-state，`usize::MAX`表示未知
+    cur_index: AtomicUsize,
+}
+
+const CPUFREQ_CPU_DATA_INIT: CpuFreqCpuData = CpuFreqCpuData {
+    last_sample: AtomicU64::new(0),
+    last_idle: AtomicU64::new(0),
+    cur_index: AtomicUsize::new(usize::MAX),
+};
+static CPUFREQ_CPU_DATA: [CpuFreqCpuData; PerCpu::MAX_CPU_NUM] =
+    [CPUFREQ_CPU_DATA_INIT; PerCpu::MAX_CPU_NUM];
+
+static CPUFREQ_MANAGER: CpuFreqManager = CpuFreqManager {
+    driver: RwLock::new(None),
+};
+
+#[inline(always)]
+pub fn cpufreq_manager() -> &'static CpuFreqManager {
+    &CPUFREQ_MANAGER
+}
+
+#[derive(Debug)]
+pub struct CpuFreqManager {
+    driver: RwLock<Option<Arc<dyn CpuFreqDriver>>>,
+}
+
+impl CpuFreqManager {
+    /// 注册调频驱动
+    ///
+    /// ## 错误
+    ///
+    /// - `EEXIST`：已经注册过调频驱动
+    /// - `EINVAL`：驱动没有提供P-state
+    pub fn register_driver(&self, driver: Arc<dyn CpuFreqDriver>) -> Result<(), SystemError> {
+        let pstates = driver.pstates();
+        if pstates.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut guard = self.driver.write_irqsave();
+        if guard.is_some() {
+            return Err(SystemError::EEXIST);
+        }
+        kinfo!(
+            "cpufreq: using driver '{}', {} pstates, {}MHz - {}MHz",
+            driver.name(),
+            pstates.len(),
+            pstates[pstates.len() - 1].freq_khz / 1000,
+            pstates[0].freq_khz / 1000
+        );
+        guard.replace(driver);
+        return Ok(());
+    }
+
+    /// 获取cpu当前的频率(kHz)，频率未知时返回None
+    #[allow(dead_code)]
+    pub fn cur_freq(&self, cpu: usize) -> Option<u64> {
+        let index = CPUFREQ_CPU_DATA.get(cpu)?.cur_index.load(Ordering::Relaxed);
+        let driver = self.driver.read().clone()?;
+        return driver.pstates().get(index).map(|p| p.freq_khz);
+    }
+
+    /// 时钟中断时调用，按照采样周期更新当前cpu的频率
+    pub fn tick(&self) {
+        let driver = match self.driver.read().clone() {
+            Some(driver) => driver,
+            None => return,
+        };
+        let cpu = smp_get_processor_id() as usize;
+        let data = match CPUFREQ_CPU_DATA.get(cpu) {
+            Some(data) => data,
+            None => return,
+        };
+
+        let now = CurrentTimeArch::cycles2ns(CurrentTimeArch::get_cycles()) as u64;
+        let elapsed = now.saturating_sub(data.last_sample.load(Ordering::Relaxed));
+        if elapsed < CPUFREQ_SAMPLING_RATE {
+            return;
+        }
+        let idle = cpuidle_manager().idle_time(cpu);
+        let idle_delta = idle.saturating_sub(data.last_idle.load(Ordering::Relaxed));
+        data.last_sample.store(now, Ordering::Relaxed);
+        data.last_idle.store(idle, Ordering::Relaxed);
+
+        let load = 100 - core::cmp::min(idle_delta * 100 / elapsed, 100);
+        let index = Self::ondemand_select(driver.pstates(), load);
+        if data.cur_index.load(Ordering::Relaxed) == index {
+            return;
+        }
+
+        match driver.target(index) {
+            Ok(_) => data.cur_index.store(index, Ordering::Relaxed),
+            Err(e) => kwarn!("cpufreq: cpu {} failed to switch pstate: {:?}", cpu, e),
+        }
+    }
+
+    /// ondemand策略：负载较高时使用最高频率，否则选择能满足负载的最低频率
+    fn ondemand_select(pstates: &[CpuFreqPState], load: u64) -> usize {
+        if load > CPUFREQ_UP_THRESHOLD {
+            return 0;
+        }
+        let target = pstates[0].freq_khz * load / CPUFREQ_UP_THRESHOLD;
+        return pstates
+            .iter()
+            .rposition(|p| p.freq_khz >= target)
+            .unwrap_or(0);
+    }
+}
+
+/// 时钟中断处理函数中调用
+#[no_mangle]
+pub extern "C" fn rs_cpufreq_tick() {
+    cpufreq_manager().tick();
+}
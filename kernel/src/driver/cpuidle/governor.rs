@@ -0,0 +1,79 @@
+//! menu governor的简化实现
+//!
+//! 以最近几次空闲时间的指数加权平均值作为下一次空闲时间的预测值，
+//! 选择驻留时间不超过预测值的最深的空闲状态。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/cpuidle/governors/menu.c
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::mm::percpu::PerCpu;
+
+use super::CpuIdleState;
+
+/// 新的测量值在预测值中所占的权重为 1/2^MENU_EWMA_SHIFT
+const MENU_EWMA_SHIFT: u32 = 3;
+
+#[derive(Debug)]
+pub struct MenuGovernor {
+    /// 每个cpu预测的空闲时间(us)
+    predicted_us: [AtomicU64; PerCpu::MAX_CPU_NUM],
+    /// 允许的最大退出延迟(us)
+    latency_limit_us: AtomicU64,
+}
+
+impl MenuGovernor {
+    pub const fn new() -> Self {
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        Self {
+            predicted_us: [ZERO; PerCpu::MAX_CPU_NUM],
+            latency_limit_us: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// 为cpu选择一个空闲状态
+    ///
+    /// ## 参数
+    ///
+    /// - `cpu`: cpu号
+    /// - `states`: 驱动支持的空闲状态
+    /// - `next_event_us`: 距离下一个定时器到期的时间(us)，预测的空闲时间不会超过它
+    ///
+    /// ## 返回值
+    ///
+    /// 空闲状态在`states`中的下标
+    pub fn select(&self, cpu: usize, states: &[CpuIdleState], next_event_us: Option<u64>) -> usize {
+        let mut predicted = self
+            .predicted_us
+            .get(cpu)
+            .map_or(0, |p| p.load(Ordering::Relaxed));
+        if let Some(next_event_us) = next_event_us {
+            predicted = core::cmp::min(predicted, next_event_us);
+        }
+        let latency_limit = self.latency_limit_us.load(Ordering::Relaxed);
+
+        let mut index = 0;
+        for (i, state) in states.iter().enumerate().skip(1) {
+            if state.target_residency > predicted || state.exit_latency > latency_limit {
+                break;
+            }
+            index = i;
+        }
+        return index;
+    }
+
+    /// 根据实际的空闲时间更新预测值
+    pub fn reflect(&self, cpu: usize, measured_us: u64) {
+        if let Some(p) = self.predicted_us.get(cpu) {
+            let old = p.load(Ordering::Relaxed);
+            let new = old - (old >> MENU_EWMA_SHIFT) + (measured_us >> MENU_EWMA_SHIFT);
+            p.store(new, Ordering::Relaxed);
+        }
+    }
+
+    /// 设置允许的最大退出延迟(us)
+    #[allow(dead_code)]
+    pub fn set_latency_limit(&self, limit_us: u64) {
+        self.latency_limit_us.store(limit_us, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,229 @@
+//! CPU空闲状态管理(cpuidle)
+//!
+//! 空闲进程通过`cpu_idle()`进入空闲状态。注册了空闲驱动之后，由governor根据预测的空闲时间，
+//! 选择一个合适的C-state；否则使用架构默认的空闲方式（x86_64上为HLT）。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/cpuidle/cpuidle.c
+
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::sync::Arc;
+
+use crate::{
+    arch::{cpu::arch_cpu_idle, CurrentIrqArch, CurrentTimeArch},
+    exception::InterruptArch,
+    kinfo,
+    libs::rwlock::RwLock,
+    mm::percpu::PerCpu,
+    process::{ProcessFlags, ProcessManager},
+    smp::{
+        core::smp_get_processor_id,
+        hotplug::{cpu_online, cpu_park},
+    },
+    syscall::SystemError,
+    time::{
+        hrtimer::{hrtimer_next_event_delta, tick_nohz_idle_enter, tick_nohz_restart},
+        TimeArch,
+    },
+};
+
+use self::governor::MenuGovernor;
+
+pub mod governor;
+
+/// 一个驱动最多支持的空闲状态数量
+pub const CPUIDLE_STATE_MAX: usize = 8;
+
+/// 一个空闲状态(C-state)
+#[derive(Debug, Clone)]
+pub struct CpuIdleState {
+    pub name: &'static str,
+    /// 退出该状态所需的时间(us)
+    pub exit_latency: u64,
+    /// 最小驻留时间(us)：预计空闲时间短于它时，进入该状态得不偿失
+    pub target_residency: u64,
+}
+
+/// 空闲驱动
+pub trait CpuIdleDriver: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// 驱动支持的空闲状态，按照从浅到深的顺序排列
+    fn states(&self) -> &[CpuIdleState];
+
+    /// 让当前cpu进入第`index`个空闲状态
+    ///
+    /// 调用时中断已经关闭，返回时中断必须已经打开
+    fn enter(&self, index: usize);
+}
+
+/// 每个cpu的空闲统计信息
+#[derive(Debug)]
+struct CpuIdleCpuData {
+    /// 累计的空闲时间(ns)
+    idle_time: AtomicU64,
+    /// 每个空闲状态的进入次数
+    usage: [AtomicU64; CPUIDLE_STATE_MAX],
+    /// 每个空闲状态的累计驻留时间(ns)
+    residency: [AtomicU64; CPUIDLE_STATE_MAX],
+}
+
+impl CpuIdleCpuData {
+    const fn new() -> Self {
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        Self {
+            idle_time: AtomicU64::new(0),
+            usage: [ZERO; CPUIDLE_STATE_MAX],
+            residency: [ZERO; CPUIDLE_STATE_MAX],
+        }
+    }
+}
+
+const CPUIDLE_CPU_DATA_INIT: CpuIdleCpuData = CpuIdleCpuData::new();
+static CPUIDLE_CPU_DATA: [CpuIdleCpuData; PerCpu::MAX_CPU_NUM] =
+    [CPUIDLE_CPU_DATA_INIT; PerCpu::MAX_CPU_NUM];
+
+static CPUIDLE_MANAGER: CpuIdleManager = CpuIdleManager {
+    driver: RwLock::new(None),
+    governor: MenuGovernor::new(),
+};
+
+#[inline(always)]
+pub fn cpuidle_manager() -> &'static CpuIdleManager {
+    &CPUIDLE_MANAGER
+}
+
+#[derive(Debug)]
+pub struct CpuIdleManager {
+    driver: RwLock<Option<Arc<dyn CpuIdleDriver>>>,
+    governor: MenuGovernor,
+}
+
+impl CpuIdleManager {
+    /// 注册空闲驱动
+    ///
+    /// ## 错误
+    ///
+    /// - `EEXIST`：已经注册过空闲驱动
+    /// - `EINVAL`：驱动没有提供空闲状态，或者状态数量过多
+    pub fn register_driver(&self, driver: Arc<dyn CpuIdleDriver>) -> Result<(), SystemError> {
+        let nr_states = driver.states().len();
+        if nr_states == 0 || nr_states > CPUIDLE_STATE_MAX {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut guard = self.driver.write_irqsave();
+        if guard.is_some() {
+            return Err(SystemError::EEXIST);
+        }
+
+        kinfo!("cpuidle: using driver '{}'", driver.name());
+        for (i, state) in driver.states().iter().enumerate() {
+            kinfo!(
+                "cpuidle: state {}: {}, exit latency: {}us, target residency: {}us",
+                i,
+                state.name,
+                state.exit_latency,
+                state.target_residency
+            );
+        }
+        guard.replace(driver);
+        return Ok(());
+    }
+
+    /// 当前使用的空闲驱动
+    pub fn driver(&self) -> Option<Arc<dyn CpuIdleDriver>> {
+        self.driver.read().clone()
+    }
+
+    /// 获取cpu累计的空闲时间(ns)
+    pub fn idle_time(&self, cpu: usize) -> u64 {
+        CPUIDLE_CPU_DATA
+            .get(cpu)
+            .map_or(0, |d| d.idle_time.load(Ordering::Relaxed))
+    }
+
+    /// 获取cpu进入第`index`个空闲状态的次数，以及累计的驻留时间(ns)
+    #[allow(dead_code)]
+    pub fn state_usage(&self, cpu: usize, index: usize) -> Option<(u64, u64)> {
+        let data = CPUIDLE_CPU_DATA.get(cpu)?;
+        let usage = data.usage.get(index)?.load(Ordering::Relaxed);
+        let residency = data.residency.get(index)?.load(Ordering::Relaxed);
+        return Some((usage, residency));
+    }
+
+    /// 更新空闲统计信息
+    fn account(&self, cpu: usize, index: Option<usize>, ns: u64) {
+        let data = match CPUIDLE_CPU_DATA.get(cpu) {
+            Some(data) => data,
+            None => return,
+        };
+        data.idle_time.fetch_add(ns, Ordering::Relaxed);
+        if let Some(index) = index {
+            data.usage[index].fetch_add(1, Ordering::Relaxed);
+            data.residency[index].fetch_add(ns, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 让当前cpu进入空闲状态，直到被中断唤醒
+///
+/// 只能由空闲进程调用，调用时中断必须是打开的
+pub fn cpu_idle() {
+    let cpu = smp_get_processor_id() as usize;
+    let mgr = cpuidle_manager();
+
+    unsafe { CurrentIrqArch::interrupt_disable() };
+    if !cpu_online(cpu) {
+        cpu_park();
+        return;
+    }
+    if ProcessManager::current_pcb()
+        .flags()
+        .contains(ProcessFlags::NEED_SCHEDULE)
+    {
+        unsafe { CurrentIrqArch::interrupt_enable() };
+        return;
+    }
+
+    // 空闲期间停止调度时钟，由下一个定时器或者外部中断唤醒
+    tick_nohz_idle_enter();
+
+    let start = CurrentTimeArch::get_cycles();
+    let index = match mgr.driver() {
+        Some(driver) => {
+            let next_event_us = hrtimer_next_event_delta().map(|ns| ns / 1000);
+            let index = mgr.governor.select(cpu, driver.states(), next_event_us);
+            driver.enter(index);
+            Some(index)
+        }
+        None => {
+            arch_cpu_idle();
+            None
+        }
+    };
+    let ns = CurrentTimeArch::cycles2ns(CurrentTimeArch::get_cycles().wrapping_sub(start)) as u64;
+    tick_nohz_restart();
+
+    mgr.account(cpu, index, ns);
+    mgr.governor.reflect(cpu, ns / 1000);
+}
+
+/// 让当前cpu进入最深的空闲状态，直到被中断唤醒。用于已经下线的cpu
+///
+/// 调用时中断必须已经关闭，返回时中断是打开的
+pub fn cpu_idle_deepest() {
+    match cpuidle_manager().driver() {
+        Some(driver) => driver.enter(driver.states().len() - 1),
+        None => arch_cpu_idle(),
+    }
+}
+
+/// 让当前cpu进入一次空闲状态（供C代码的空闲循环使用）
+#[no_mangle]
+pub extern "C" fn rs_cpu_idle() {
+    cpu_idle();
+}
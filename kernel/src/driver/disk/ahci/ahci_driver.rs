@@ -0,0 +1,310 @@
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::{
+        base::{
+            device::{bus::Bus, driver::Driver, kobj_to_device, Device, IdTable},
+            kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        pci::{
+            device::PciDevice,
+            driver::{PciDeviceId, PciDriver},
+        },
+    },
+    filesystem::{
+        kernfs::KernFSInode,
+        sysfs::{
+            file::{sysfs_buf_to_str, sysfs_emit_str, sysfs_emit_value, sysfs_parse},
+            Attribute, AttributeGroup, SysFSOpsSupport,
+        },
+        vfs::syscall::ModeType,
+    },
+    libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    syscall::SystemError,
+};
+
+use super::{
+    ahci_host_queue_depth, ahci_port_poll_status, ahci_port_status, ahci_probe, ahci_remove,
+    ahci_resume, ahci_set_port_completion, ahci_set_queue_depth, ahci_suspend, AhciCompletion,
+};
+
+/// 大容量存储控制器 - SATA控制器
+static AHCI_PCI_IDS: [PciDeviceId; 1] = [PciDeviceId::class(0x010600, 0xffff00)];
+
+#[derive(Debug)]
+struct InnerAhciDriver {
+    bus: Option<Arc<dyn Bus>>,
+    kobj_type: Option<&'static dyn KObjType>,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    kern_inode: Option<Arc<KernFSInode>>,
+    devices: Vec<Arc<dyn Device>>,
+}
+
+/// AHCI控制器的PCI驱动
+#[derive(Debug)]
+#[cast_to([sync] Driver, PciDriver)]
+pub struct AhciDriver {
+    inner: RwLock<InnerAhciDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl AhciDriver {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: RwLock::new(InnerAhciDriver {
+                bus: None,
+                kobj_type: None,
+                kset: None,
+                parent_kobj: None,
+                kern_inode: None,
+                devices: Vec::new(),
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        })
+    }
+}
+
+impl PciDriver for AhciDriver {
+    fn pci_id_table(&self) -> &'static [PciDeviceId] {
+        &AHCI_PCI_IDS
+    }
+
+    fn probe(&self, dev: &Arc<PciDevice>, _id: &PciDeviceId) -> Result<(), SystemError> {
+        return ahci_probe(dev);
+    }
+
+    fn remove(&self, dev: &Arc<PciDevice>) {
+        ahci_remove(dev);
+    }
+
+    fn shutdown(&self, dev: &Arc<PciDevice>) {
+        if let Err(e) = ahci_suspend(dev) {
+            kwarn!(
+                "ahci {}: shutdown failed: {:?}",
+                dev.bus_device_function(),
+                e
+            );
+        }
+    }
+
+    fn suspend(&self, dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+        return ahci_suspend(dev);
+    }
+
+    fn resume(&self, dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+        return ahci_resume(dev);
+    }
+}
+
+impl Driver for AhciDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        None
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner.read().devices.clone()
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        self.inner.write().devices.push(device);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let mut inner = self.inner.write();
+
+        inner.devices.drain_filter(|d| Arc::ptr_eq(d, device));
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+
+    fn dev_groups(&self) -> &'static [&'static dyn AttributeGroup] {
+        &[&AhciAttrGroup]
+    }
+}
+
+impl KObject for AhciDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner.read().kobj_type.clone()
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner.write().kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        "ahci".to_string()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 与驱动绑定的AHCI控制器的属性组，属性文件位于控制器的PCI设备目录下
+#[derive(Debug)]
+struct AhciAttrGroup;
+
+impl AttributeGroup for AhciAttrGroup {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        return &[&AhciAttrPortStatus, &AhciAttrQueueDepth, &AhciAttrPortPoll];
+    }
+
+    fn is_visible(&self, _kobj: Arc<dyn KObject>, attr: &dyn Attribute) -> Option<ModeType> {
+        return Some(attr.mode());
+    }
+}
+
+/// 每个已实现的端口上接入的设备类型
+#[derive(Debug)]
+struct AhciAttrPortStatus;
+
+impl Attribute for AhciAttrPortStatus {
+    fn name(&self) -> &str {
+        "port_status"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::S_IRUGO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let dev = kobj_to_device::<PciDevice>(kobj)?;
+        let status = ahci_port_status(&dev.bus_device_function())?;
+        return sysfs_emit_str(buf, &status);
+    }
+}
+
+/// 驱动使用的命令槽的数量，取值范围为1到控制器支持的命令槽的数量
+#[derive(Debug)]
+struct AhciAttrQueueDepth;
+
+impl Attribute for AhciAttrQueueDepth {
+    fn name(&self) -> &str {
+        "queue_depth"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::from_bits_truncate(0o644)
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW | SysFSOpsSupport::STORE
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let dev = kobj_to_device::<PciDevice>(kobj)?;
+        let depth = ahci_host_queue_depth(&dev.bus_device_function())?;
+        return sysfs_emit_value(buf, depth);
+    }
+
+    fn store(&self, kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        let dev = kobj_to_device::<PciDevice>(kobj)?;
+        let depth: u32 = sysfs_parse(buf)?;
+        ahci_set_queue_depth(&dev.bus_device_function(), depth)?;
+        return Ok(buf.len());
+    }
+}
+
+/// 每个已实现的端口等待命令完成的方式：`irq`表示睡眠并由中断唤醒，`poll`表示轮询并在轮询的间隔中睡眠。
+/// 写入`<端口号> irq`或者`<端口号> poll`来切换一个端口，例如`echo "0 poll" > port_poll`
+#[derive(Debug)]
+struct AhciAttrPortPoll;
+
+impl Attribute for AhciAttrPortPoll {
+    fn name(&self) -> &str {
+        "port_poll"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::from_bits_truncate(0o644)
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW | SysFSOpsSupport::STORE
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let dev = kobj_to_device::<PciDevice>(kobj)?;
+        let status = ahci_port_poll_status(&dev.bus_device_function())?;
+        return sysfs_emit_str(buf, &status);
+    }
+
+    fn store(&self, kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        let dev = kobj_to_device::<PciDevice>(kobj)?;
+        let mut args = sysfs_buf_to_str(buf)?.split_whitespace();
+        let port: usize = args
+            .next()
+            .and_then(|port| port.parse().ok())
+            .ok_or(SystemError::EINVAL)?;
+        let mode = match args.next() {
+            Some("irq") => AhciCompletion::Irq,
+            Some("poll") => AhciCompletion::Poll,
+            _ => return Err(SystemError::EINVAL),
+        };
+        if args.next().is_some() {
+            return Err(SystemError::EINVAL);
+        }
+        ahci_set_port_completion(&dev.bus_device_function(), port, mode)?;
+        return Ok(buf.len());
+    }
+}
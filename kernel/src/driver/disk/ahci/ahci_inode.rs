@@ -4,7 +4,7 @@ use crate::filesystem::vfs::file::FileMode;
 use crate::filesystem::vfs::syscall::ModeType;
 use crate::filesystem::vfs::{
     core::generate_inode_id, make_rawdev, FilePrivateData, FileSystem, FileType, IndexNode,
-    Metadata, PollStatus,
+    Metadata, PollStatus, PollTable,
 };
 use crate::syscall::SystemError;
 use crate::{libs::spinlock::SpinLock, time::TimeSpec};
@@ -108,7 +108,7 @@ impl IndexNode for LockedAhciInode {
         return Ok(());
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         return Ok(PollStatus::READ | PollStatus::WRITE);
     }
 
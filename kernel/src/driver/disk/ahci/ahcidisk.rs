@@ -1,7 +1,15 @@
-use super::{_port, hba::HbaCmdTable, virt_2_phys};
+use super::{
+    _port, ahci_poll_account, ahci_poll_mean_ns, ahci_port_completion, ahci_port_wait_queues,
+    ahci_queue_depth,
+    hba::{HbaCmdTable, HbaPort},
+    AhciCompletion,
+};
+use crate::arch::{sched::sched, CurrentIrqArch};
+use crate::debug::trace_event::{trace_block_rq_complete, trace_block_rq_issue};
 use crate::driver::base::block::block_device::{BlockDevice, BlockId};
 use crate::driver::base::block::disk_info::Partition;
-use crate::driver::base::block::SeekFrom;
+use crate::driver::base::block::request::{block_request_check_cancel, BlockRequestDeadline};
+use crate::driver::base::block::writeback::BackingDevInfo;
 use crate::driver::base::device::bus::Bus;
 
 use crate::driver::base::device::driver::Driver;
@@ -9,6 +17,9 @@ use crate::driver::base::device::{Device, DeviceType, IdTable};
 use crate::driver::base::kobject::{KObjType, KObject, KObjectState};
 use crate::driver::base::kset::KSet;
 use crate::driver::disk::ahci::HBA_PxIS_TFES;
+use crate::driver::iommu::dma::{dma_map_sg, dma_unmap_sg, DmaDirection, ScatterList};
+use crate::driver::pci::pci::BusDeviceFunction;
+use crate::exception::InterruptArch;
 
 use crate::filesystem::kernfs::KernFSInode;
 use crate::filesystem::mbr::MbrDiskPartionTable;
@@ -16,13 +27,26 @@ use crate::include::bindings::bindings::verify_area;
 
 use crate::kdebug;
 use crate::libs::rwlock::{RwLockReadGuard, RwLockWriteGuard};
-use crate::libs::{spinlock::SpinLock, vec_cursor::VecCursor};
-use crate::mm::phys_2_virt;
+use crate::libs::{
+    mutex::{Mutex, MutexGuard},
+    on_disk::OnDisk,
+    spinlock::{SpinLock, SpinLockGuard},
+};
+use crate::mm::kasan::{kasan_check_read, kasan_check_write};
+use crate::mm::{phys_2_virt, VirtAddr};
+use crate::process::ProcessManager;
 use crate::syscall::SystemError;
+use crate::time::{
+    hrtimer::ktime_get,
+    sleep::nanosleep,
+    timer::{next_n_ms_timer_jiffies, Timer, WakeUpHelper},
+    TimeSpec,
+};
 use crate::{
     driver::disk::ahci::hba::{
         FisRegH2D, FisType, HbaCmdHeader, ATA_CMD_READ_DMA_EXT, ATA_CMD_WRITE_DMA_EXT,
-        ATA_DEV_BUSY, ATA_DEV_DRQ,
+        ATA_DEV_BUSY, ATA_DEV_DRQ, HBA_CMD_HEADER_A, HBA_CMD_HEADER_CFL, HBA_CMD_HEADER_P,
+        HBA_CMD_HEADER_W, HBA_PRDT_DBC, HBA_PRDT_I,
     },
     kerror,
 };
@@ -34,6 +58,14 @@ use core::fmt::Debug;
 use core::sync::atomic::{compiler_fence, Ordering};
 use core::{mem::size_of, ptr::write_bytes};
 
+/// 轮询模式下，第一次睡眠的时间不少于这么多纳秒，更短的话直接忙等待
+const AHCI_POLL_MIN_SLEEP_NS: u64 = 2000;
+/// 轮询模式下，第一次睡眠之后检查的间隔(单位：微秒)的初始值与最大值，每检查一次间隔翻倍
+const AHCI_POLL_BACKOFF_MIN_US: u64 = 2;
+const AHCI_POLL_BACKOFF_MAX_US: u64 = 1000;
+/// 中断模式下每次睡眠的最长时间(单位：毫秒)，防止中断在其他CPU上到来而错过唤醒
+const AHCI_IRQ_WAIT_TIMEOUT_MS: u64 = 1;
+
 /// @brief: 只支持MBR分区格式的磁盘结构体
 pub struct AhciDisk {
     pub name: String,
@@ -42,13 +74,26 @@ pub struct AhciDisk {
     // port: &'static mut HbaPort,      // 控制硬盘的端口
     pub ctrl_num: u8,
     pub port_num: u8,
+    /// AHCI控制器的PCI地址，用于建立DMA映射
+    dev: BusDeviceFunction,
     /// 指向LockAhciDisk的弱引用
     self_ref: Weak<LockedAhciDisk>,
 }
 
 /// @brief: 带锁的AhciDisk
+///
+/// 只能通过[`LockedAhciDisk::lock`]得到的守卫访问磁盘，守卫的生命周期就是持有锁的范围
 #[derive(Debug)]
-pub struct LockedAhciDisk(pub SpinLock<AhciDisk>);
+pub struct LockedAhciDisk {
+    inner: SpinLock<AhciDisk>,
+    /// 串行化磁盘上的读写。读写期间不持有`inner`，因此等待命令完成时可以睡眠
+    io_lock: Mutex<()>,
+    /// 回写状态，不需要锁住磁盘就可以访问
+    bdi: Arc<BackingDevInfo>,
+}
+
+/// @brief: 锁住的AhciDisk，守卫被释放时解锁
+pub type AhciDiskGuard<'a> = SpinLockGuard<'a, AhciDisk>;
 /// 函数实现
 impl Debug for AhciDisk {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -62,6 +107,33 @@ impl Debug for AhciDisk {
 }
 
 impl AhciDisk {
+    /// 读写磁盘所需的信息
+    fn io(&self) -> AhciDiskIo {
+        return AhciDiskIo {
+            name: self.name.clone(),
+            ctrl_num: self.ctrl_num,
+            port_num: self.port_num,
+            dev: self.dev,
+        };
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        // 由于目前没有block cache, 因此sync返回成功即可
+        return Ok(());
+    }
+}
+
+/// 在一个磁盘上执行读写所需的信息，从[`AhciDisk`]中复制出来，使得读写期间不需要锁住磁盘
+#[derive(Debug, Clone)]
+struct AhciDiskIo {
+    name: String,
+    ctrl_num: u8,
+    port_num: u8,
+    /// AHCI控制器的PCI地址，用于建立DMA映射
+    dev: BusDeviceFunction,
+}
+
+impl AhciDiskIo {
     fn read_at(
         &self,
         lba_id_start: BlockId, // 起始lba编号
@@ -80,28 +152,23 @@ impl AhciDisk {
         }
 
         let port = _port(self.ctrl_num, self.port_num);
-        volatile_write!(port.is, u32::MAX); // Clear pending interrupt bits
-
-        let slot = port.find_cmdslot().unwrap_or(u32::MAX);
+        port.is.write(u32::MAX); // Clear pending interrupt bits
 
-        if slot == u32::MAX {
-            return Err(SystemError::EIO);
-        }
+        let deadline = BlockRequestDeadline::new();
+        let slot = Self::alloc_cmdslot(port, self.ctrl_num, &deadline)?;
 
-        #[allow(unused_unsafe)]
         let cmdheader: &mut HbaCmdHeader = unsafe {
             (phys_2_virt(
-                volatile_read!(port.clb) as usize
-                    + slot as usize * size_of::<HbaCmdHeader>() as usize,
+                port.clb.read() as usize + slot as usize * size_of::<HbaCmdHeader>() as usize,
             ) as *mut HbaCmdHeader)
                 .as_mut()
                 .unwrap()
         };
 
-        cmdheader.cfl = (size_of::<FisRegH2D>() / size_of::<u32>()) as u8;
-
-        volatile_set_bit!(cmdheader.cfl, 1 << 6, false); //  Read/Write bit : Read from device
-        volatile_write!(cmdheader.prdtl, check_length as u16); // PRDT entries count
+        // Command FIS size, Read/Write bit : Read from device
+        let cfl = (size_of::<FisRegH2D>() / size_of::<u32>()) as u8;
+        cmdheader.cfl.write(cfl);
+        cmdheader.prdtl.write(check_length as u16); // PRDT entries count
 
         // 设置数据存放地址
         let mut buf_ptr = buf as *mut [u8] as *mut usize as usize;
@@ -124,86 +191,57 @@ impl AhciDisk {
         if kbuf.is_some() {
             buf_ptr = kbuf.as_mut().unwrap().as_mut_ptr() as usize;
         }
+        // 设备将通过DMA写入这段内存，检查它是否仍然有效
+        kasan_check_write(buf_ptr, count * 512);
 
-        #[allow(unused_unsafe)]
         let cmdtbl = unsafe {
-            (phys_2_virt(volatile_read!(cmdheader.ctba) as usize) as *mut HbaCmdTable)
+            (phys_2_virt(cmdheader.ctba.read() as usize) as *mut HbaCmdTable)
                 .as_mut()
                 .unwrap() // 必须使用 as_mut ，得到的才是原来的变量
         };
-        let mut tmp_count = count;
 
         unsafe {
             // 清空整个table的旧数据
             write_bytes(cmdtbl, 0, 1);
         }
-        // kdebug!("cmdheader.prdtl={}", volatile_read!(cmdheader.prdtl));
+        // kdebug!("cmdheader.prdtl={}", cmdheader.prdtl.read());
+
+        // 把缓冲区映射给设备，设备只能写入这段内存
+        let mut sg = Self::build_sg(buf_ptr, count);
+        dma_map_sg(&self.dev, &mut sg, DmaDirection::FromDevice)?;
 
         // 8K bytes (16 sectors) per PRDT
-        for i in 0..((volatile_read!(cmdheader.prdtl) - 1) as usize) {
-            volatile_write!(cmdtbl.prdt_entry[i].dba, virt_2_phys(buf_ptr) as u64);
-            cmdtbl.prdt_entry[i].dbc = 8 * 1024 - 1;
-            volatile_set_bit!(cmdtbl.prdt_entry[i].dbc, 1 << 31, true); // 允许中断 prdt_entry.i
-            buf_ptr += 8 * 1024;
-            tmp_count -= 16;
+        for (i, entry) in sg.iter().enumerate() {
+            let prdt = &mut cmdtbl.prdt_entry[i];
+            prdt.dba.write(entry.dma_address.data() as u64);
+            prdt.dbc.write_bits(HBA_PRDT_DBC, (entry.length - 1) as u32); // 数据长度
+            prdt.dbc.set_bits(HBA_PRDT_I); // 允许中断 prdt_entry.i
         }
 
-        // Last entry
-        let las = (volatile_read!(cmdheader.prdtl) - 1) as usize;
-        volatile_write!(cmdtbl.prdt_entry[las].dba, virt_2_phys(buf_ptr) as u64);
-        cmdtbl.prdt_entry[las].dbc = ((tmp_count << 9) - 1) as u32; // 数据长度
-
-        volatile_set_bit!(cmdtbl.prdt_entry[las].dbc, 1 << 31, true); // 允许中断
-
         // 设置命令
-        let cmdfis = unsafe {
-            ((&mut cmdtbl.cfis) as *mut [u8] as *mut usize as *mut FisRegH2D)
-                .as_mut()
-                .unwrap()
-        };
-        volatile_write!(cmdfis.fis_type, FisType::RegH2D as u8);
-        volatile_set_bit!(cmdfis.pm, 1 << 7, true); // command_bit set
-        volatile_write!(cmdfis.command, ATA_CMD_READ_DMA_EXT);
-
-        volatile_write!(cmdfis.lba0, (lba_id_start & 0xFF) as u8);
-        volatile_write!(cmdfis.lba1, ((lba_id_start >> 8) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba2, ((lba_id_start >> 16) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba3, ((lba_id_start >> 24) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba4, ((lba_id_start >> 32) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba5, ((lba_id_start >> 40) & 0xFF) as u8);
+        let cmdfis = cmdtbl.cmd_fis();
+        cmdfis.fis_type.write(FisType::RegH2D as u8);
+        cmdfis.pm.set_bits(1 << 7); // command_bit set
+        cmdfis.command.write(ATA_CMD_READ_DMA_EXT);
 
-        volatile_write!(cmdfis.countl, (count & 0xFF) as u8);
-        volatile_write!(cmdfis.counth, ((count >> 8) & 0xFF) as u8);
+        cmdfis.set_lba(lba_id_start as u64);
+        cmdfis.set_count(count as u16);
 
-        volatile_write!(cmdfis.device, 1 << 6); // LBA Mode
+        cmdfis.device.write(1 << 6); // LBA Mode
 
         // 等待之前的操作完成
-        let mut spin_count = 0;
-        const SPIN_LIMIT: u32 = 10000;
-
-        while (volatile_read!(port.tfd) as u8 & (ATA_DEV_BUSY | ATA_DEV_DRQ)) > 0
-            && spin_count < SPIN_LIMIT
-        {
-            spin_count += 1;
-        }
-
-        if spin_count == SPIN_LIMIT {
-            kerror!("Port is hung");
-            return Err(SystemError::EIO);
-        }
-
-        volatile_set_bit!(port.ci, 1 << slot, true); // Issue command
-                                                     // kdebug!("To wait ahci read complete.");
-                                                     // 等待操作完成
-        loop {
-            if (volatile_read!(port.ci) & (1 << slot)) == 0 {
-                break;
-            }
-            if (volatile_read!(port.is) & HBA_PxIS_TFES) > 0 {
-                kerror!("Read disk error");
-                return Err(SystemError::EIO);
-            }
-        }
+        let r = Self::wait_idle(port, &deadline).and_then(|_| {
+            // 下发之前最后检查一次，被杀死的进程不再需要这个请求
+            block_request_check_cancel()?;
+            trace_block_rq_issue(&self.name, lba_id_start, count, false);
+            port.ci.set_bits(1 << slot); // Issue command
+            let r = self.wait_complete(port, slot, &deadline, "Read disk error");
+            trace_block_rq_complete(&self.name, lba_id_start, count, false, &r);
+            r
+        });
+        // 无论成功与否，设备都不会再访问这段内存了
+        dma_unmap_sg(&self.dev, &sg, DmaDirection::FromDevice);
+        r?;
 
         if kbuf.is_some() {
             buf.copy_from_slice(kbuf.as_ref().unwrap());
@@ -232,34 +270,30 @@ impl AhciDisk {
 
         let port = _port(self.ctrl_num, self.port_num);
 
-        volatile_write!(port.is, u32::MAX); // Clear pending interrupt bits
-
-        let slot = port.find_cmdslot().unwrap_or(u32::MAX);
+        port.is.write(u32::MAX); // Clear pending interrupt bits
 
-        if slot == u32::MAX {
-            return Err(SystemError::EIO);
-        }
+        let deadline = BlockRequestDeadline::new();
+        let slot = Self::alloc_cmdslot(port, self.ctrl_num, &deadline)?;
 
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
-        #[allow(unused_unsafe)]
         let cmdheader: &mut HbaCmdHeader = unsafe {
             (phys_2_virt(
-                volatile_read!(port.clb) as usize
-                    + slot as usize * size_of::<HbaCmdHeader>() as usize,
+                port.clb.read() as usize + slot as usize * size_of::<HbaCmdHeader>() as usize,
             ) as *mut HbaCmdHeader)
                 .as_mut()
                 .unwrap()
         };
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
 
-        volatile_write_bit!(
-            cmdheader.cfl,
-            (1 << 5) - 1 as u8,
-            (size_of::<FisRegH2D>() / size_of::<u32>()) as u8
+        cmdheader.cfl.write_bits(
+            HBA_CMD_HEADER_CFL,
+            (size_of::<FisRegH2D>() / size_of::<u32>()) as u8,
         ); // Command FIS size
 
-        volatile_set_bit!(cmdheader.cfl, 7 << 5, true); // (p,c,w)都设置为1, Read/Write bit :  Write from device
-        volatile_write!(cmdheader.prdtl, check_length as u16); // PRDT entries count
+        cmdheader
+            .cfl
+            .set_bits(HBA_CMD_HEADER_P | HBA_CMD_HEADER_A | HBA_CMD_HEADER_W); // Read/Write bit :  Write from device
+        cmdheader.prdtl.write(check_length as u16); // PRDT entries count
 
         // 设置数据存放地址
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
@@ -284,14 +318,14 @@ impl AhciDisk {
         if kbuf.is_some() {
             buf_ptr = kbuf.as_mut().unwrap().as_mut_ptr() as usize;
         }
+        // 设备将通过DMA读取这段内存，检查它是否仍然有效
+        kasan_check_read(buf_ptr, count * 512);
 
-        #[allow(unused_unsafe)]
         let cmdtbl = unsafe {
-            (phys_2_virt(volatile_read!(cmdheader.ctba) as usize) as *mut HbaCmdTable)
+            (phys_2_virt(cmdheader.ctba.read() as usize) as *mut HbaCmdTable)
                 .as_mut()
                 .unwrap()
         };
-        let mut tmp_count = count;
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
 
         unsafe {
@@ -299,69 +333,197 @@ impl AhciDisk {
             write_bytes(cmdtbl, 0, 1);
         }
 
+        // 把缓冲区映射给设备，设备只能读取这段内存
+        let mut sg = Self::build_sg(buf_ptr, count);
+        dma_map_sg(&self.dev, &mut sg, DmaDirection::ToDevice)?;
+
         // 8K bytes (16 sectors) per PRDT
-        for i in 0..((volatile_read!(cmdheader.prdtl) - 1) as usize) {
-            volatile_write!(cmdtbl.prdt_entry[i].dba, virt_2_phys(buf_ptr) as u64);
-            volatile_write_bit!(cmdtbl.prdt_entry[i].dbc, (1 << 22) - 1, 8 * 1024 - 1); // 数据长度
-            volatile_set_bit!(cmdtbl.prdt_entry[i].dbc, 1 << 31, true); // 允许中断
-            buf_ptr += 8 * 1024;
-            tmp_count -= 16;
+        for (i, entry) in sg.iter().enumerate() {
+            let prdt = &mut cmdtbl.prdt_entry[i];
+            prdt.dba.write(entry.dma_address.data() as u64);
+            prdt.dbc.write_bits(HBA_PRDT_DBC, (entry.length - 1) as u32); // 数据长度
+            prdt.dbc.set_bits(HBA_PRDT_I); // 允许中断
         }
 
-        // Last entry
-        let las = (volatile_read!(cmdheader.prdtl) - 1) as usize;
-        volatile_write!(cmdtbl.prdt_entry[las].dba, virt_2_phys(buf_ptr) as u64);
-        volatile_set_bit!(cmdtbl.prdt_entry[las].dbc, 1 << 31, true); // 允许中断
-        volatile_write_bit!(
-            cmdtbl.prdt_entry[las].dbc,
-            (1 << 22) - 1,
-            ((tmp_count << 9) - 1) as u32
-        ); // 数据长度
-
         // 设置命令
-        let cmdfis = unsafe {
-            ((&mut cmdtbl.cfis) as *mut [u8] as *mut usize as *mut FisRegH2D)
-                .as_mut()
-                .unwrap()
-        };
-        volatile_write!(cmdfis.fis_type, FisType::RegH2D as u8);
-        volatile_set_bit!(cmdfis.pm, 1 << 7, true); // command_bit set
-        volatile_write!(cmdfis.command, ATA_CMD_WRITE_DMA_EXT);
+        let cmdfis = cmdtbl.cmd_fis();
+        cmdfis.fis_type.write(FisType::RegH2D as u8);
+        cmdfis.pm.set_bits(1 << 7); // command_bit set
+        cmdfis.command.write(ATA_CMD_WRITE_DMA_EXT);
 
-        volatile_write!(cmdfis.lba0, (lba_id_start & 0xFF) as u8);
-        volatile_write!(cmdfis.lba1, ((lba_id_start >> 8) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba2, ((lba_id_start >> 16) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba3, ((lba_id_start >> 24) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba4, ((lba_id_start >> 32) & 0xFF) as u8);
-        volatile_write!(cmdfis.lba5, ((lba_id_start >> 40) & 0xFF) as u8);
+        cmdfis.set_lba(lba_id_start as u64);
+        cmdfis.set_count(count as u16);
 
-        volatile_write!(cmdfis.countl, (count & 0xFF) as u8);
-        volatile_write!(cmdfis.counth, ((count >> 8) & 0xFF) as u8);
+        cmdfis.device.write(1 << 6); // LBA Mode
 
-        volatile_write!(cmdfis.device, 1 << 6); // LBA Mode
+        // 下发之前最后检查一次，被杀死的进程不再需要这个请求
+        let r = block_request_check_cancel().and_then(|_| {
+            trace_block_rq_issue(&self.name, lba_id_start, count, true);
+            port.ci.set_bits(1 << slot); // Issue command
 
-        volatile_set_bit!(port.ci, 1 << slot, true); // Issue command
+            // 等待操作完成
+            let r = self.wait_complete(port, slot, &deadline, "Write disk error");
+            trace_block_rq_complete(&self.name, lba_id_start, count, true, &r);
+            r
+        });
+        dma_unmap_sg(&self.dev, &sg, DmaDirection::ToDevice);
+        r?;
 
-        // 等待操作完成
+        compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        // successfully read
+        return Ok(count * 512);
+    }
+
+    /// 把缓冲区按照每个PRDT表项8K字节(16个扇区)进行切分
+    fn build_sg(buf_ptr: usize, count: usize) -> Vec<ScatterList> {
+        const PRDT_BYTES: usize = 8 * 1024;
+        let total = count * 512;
+        return (0..total)
+            .step_by(PRDT_BYTES)
+            .map(|off| {
+                ScatterList::new(
+                    VirtAddr::new(buf_ptr + off),
+                    core::cmp::min(PRDT_BYTES, total - off),
+                )
+            })
+            .collect();
+    }
+
+    /// 找到一个空闲的命令槽，在找到之前请求处于排队状态
+    ///
+    /// 排队期间进程被杀死的话，请求被取消，返回EINTR；超时则返回ETIMEDOUT
+    fn alloc_cmdslot(
+        port: &HbaPort,
+        ctrl_num: u8,
+        deadline: &BlockRequestDeadline,
+    ) -> Result<u32, SystemError> {
         loop {
-            if (volatile_read!(port.ci) & (1 << slot)) == 0 {
-                break;
+            block_request_check_cancel()?;
+            if let Some(slot) = port.find_cmdslot(ahci_queue_depth(ctrl_num)) {
+                return Ok(slot);
             }
-            if (volatile_read!(port.is) & HBA_PxIS_TFES) > 0 {
-                kerror!("Write disk error");
-                return Err(SystemError::EIO);
+            if deadline.expired() {
+                kerror!("ahci: no free command slot");
+                return Err(SystemError::ETIMEDOUT);
             }
+            core::hint::spin_loop();
         }
-
-        compiler_fence(core::sync::atomic::Ordering::SeqCst);
-        // successfully read
-        return Ok(count * 512);
     }
 
-    fn sync(&self) -> Result<(), SystemError> {
-        // 由于目前没有block cache, 因此sync返回成功即可
+    /// 等待设备不再忙碌，超时的话复位端口
+    fn wait_idle(port: &mut HbaPort, deadline: &BlockRequestDeadline) -> Result<(), SystemError> {
+        while (port.tfd.read() as u8 & (ATA_DEV_BUSY | ATA_DEV_DRQ)) > 0 {
+            if deadline.expired() {
+                kerror!("Port is hung, resetting");
+                port.reset();
+                return Err(SystemError::ETIMEDOUT);
+            }
+            core::hint::spin_loop();
+        }
         return Ok(());
     }
+
+    /// 等待命令执行完成
+    ///
+    /// 端口使用中断时，进程在端口的等待队列上睡眠，由中断处理函数唤醒。端口使用轮询时，先睡眠最近的命令平均完成时间的一半，
+    /// 之后按照指数增长的间隔睡眠并检查，用CPU时间换取更低的延迟。不能睡眠的上下文中(例如调用者持有自旋锁)总是忙等待
+    ///
+    /// 超时的话复位端口，复位会清除这个命令，之后设备不会再访问命令所指向的内存
+    fn wait_complete(
+        &self,
+        port: &mut HbaPort,
+        slot: u32,
+        deadline: &BlockRequestDeadline,
+        err_msg: &str,
+    ) -> Result<(), SystemError> {
+        let mode = ahci_port_completion(self.ctrl_num, self.port_num);
+        let can_sleep = ahci_can_sleep();
+        let start = ktime_get();
+        if mode == AhciCompletion::Poll && can_sleep {
+            let sleep_ns = ahci_poll_mean_ns(self.ctrl_num, self.port_num) / 2;
+            if sleep_ns >= AHCI_POLL_MIN_SLEEP_NS {
+                Self::sleep_ns(sleep_ns);
+            }
+        }
+
+        let mut backoff_us = AHCI_POLL_BACKOFF_MIN_US;
+        loop {
+            if (port.ci.read() & (1 << slot)) == 0 {
+                if mode == AhciCompletion::Poll {
+                    ahci_poll_account(
+                        self.ctrl_num,
+                        self.port_num,
+                        ktime_get().saturating_sub(start),
+                    );
+                }
+                return Ok(());
+            }
+            if (port.is.read() & HBA_PxIS_TFES) > 0 {
+                kerror!("{}", err_msg);
+                // 出错的命令仍然占用着槽位，复位端口清除它，否则之后的命令会一直等到超时
+                port.reset();
+                return Err(SystemError::EIO);
+            }
+            if deadline.expired() {
+                kerror!("{}: command timed out, resetting port", err_msg);
+                port.reset();
+                return Err(SystemError::ETIMEDOUT);
+            }
+            if !can_sleep {
+                core::hint::spin_loop();
+                continue;
+            }
+            match mode {
+                AhciCompletion::Irq => self.sleep_for_irq(port, slot),
+                AhciCompletion::Poll => {
+                    Self::sleep_ns(backoff_us * 1000);
+                    backoff_us = core::cmp::min(backoff_us * 2, AHCI_POLL_BACKOFF_MAX_US);
+                }
+            }
+        }
+    }
+
+    /// 在端口的等待队列上睡眠，直到端口的中断到来，或者等待了AHCI_IRQ_WAIT_TIMEOUT_MS
+    fn sleep_for_irq(&self, port: &HbaPort, slot: u32) {
+        let wait_queues = match ahci_port_wait_queues(self.ctrl_num) {
+            Some(wait_queues) => wait_queues,
+            None => return,
+        };
+        let wait_queue = &wait_queues[self.port_num as usize];
+        let pcb = ProcessManager::current_pcb();
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        // 关中断之后再检查一次，命令可能已经在检查之后完成了
+        if (port.ci.read() & (1 << slot)) == 0 {
+            return;
+        }
+        // 命令执行期间设备在访问缓冲区，因此不能被信号打断
+        unsafe { wait_queue.sleep_without_schedule_uninterruptible() };
+        let timer = Timer::new(
+            WakeUpHelper::new(pcb.clone()),
+            next_n_ms_timer_jiffies(AHCI_IRQ_WAIT_TIMEOUT_MS),
+        );
+        timer.activate();
+        drop(irq_guard);
+        sched();
+
+        // 被中断唤醒时定时器还没有到期，取消它，否则它之后会在任意时刻唤醒进程
+        timer.cancel();
+        // 被定时器唤醒时，进程还在等待队列中
+        wait_queue.remove(&pcb);
+    }
+
+    fn sleep_ns(ns: u64) {
+        nanosleep(TimeSpec {
+            tv_sec: (ns / 1000000000) as i64,
+            tv_nsec: (ns % 1000000000) as i64,
+        })
+        .ok();
+    }
+}
+
+/// 当前上下文是否可以睡眠：中断是打开的，并且没有持有自旋锁
+fn ahci_can_sleep() -> bool {
+    return CurrentIrqArch::is_irq_enabled() && ProcessManager::current_pcb().preempt_count() == 0;
 }
 
 impl LockedAhciDisk {
@@ -370,16 +532,23 @@ impl LockedAhciDisk {
         flags: u16,
         ctrl_num: u8,
         port_num: u8,
+        dev: BusDeviceFunction,
     ) -> Result<Arc<LockedAhciDisk>, SystemError> {
         // 构建磁盘结构体
-        let result: Arc<LockedAhciDisk> = Arc::new(LockedAhciDisk(SpinLock::new(AhciDisk {
-            name,
-            flags,
-            partitions: Default::default(),
-            ctrl_num,
-            port_num,
-            self_ref: Weak::default(),
-        })));
+        let bdi = BackingDevInfo::new(name.clone());
+        let result: Arc<LockedAhciDisk> = Arc::new(LockedAhciDisk {
+            inner: SpinLock::new(AhciDisk {
+                name,
+                flags,
+                partitions: Default::default(),
+                ctrl_num,
+                port_num,
+                dev,
+                self_ref: Weak::default(),
+            }),
+            io_lock: Mutex::new(()),
+            bdi,
+        });
 
         let table: MbrDiskPartionTable = result.read_mbr_table()?;
 
@@ -388,7 +557,7 @@ impl LockedAhciDisk {
             compiler_fence(Ordering::SeqCst);
             if table.dpte[i].part_type != 0 {
                 let w = Arc::downgrade(&result);
-                result.0.lock().partitions.push(Partition::new(
+                result.lock().partitions.push(Partition::new(
                     table.dpte[i].starting_sector() as u64,
                     table.dpte[i].starting_lba as u64,
                     table.dpte[i].total_sectors as u64,
@@ -398,42 +567,43 @@ impl LockedAhciDisk {
             }
         }
 
-        result.0.lock().self_ref = Arc::downgrade(&result);
+        result.lock().self_ref = Arc::downgrade(&result);
 
         return Ok(result);
     }
 
-    /// @brief: 从磁盘中读取 MBR 分区表结构体 TODO: Cursor
-    pub fn read_mbr_table(&self) -> Result<MbrDiskPartionTable, SystemError> {
-        let mut table: MbrDiskPartionTable = Default::default();
+    /// @brief: 锁住磁盘
+    ///
+    /// @return 守卫，在它被释放之前，其他使用者无法访问这个磁盘
+    #[inline]
+    pub fn lock(&self) -> AhciDiskGuard {
+        return self.inner.lock();
+    }
 
-        // 数据缓冲区
-        let mut buf: Vec<u8> = Vec::new();
-        buf.resize(size_of::<MbrDiskPartionTable>(), 0);
+    /// 获得在磁盘上读写的权利。不能睡眠的上下文中通过忙等待获得
+    fn lock_io(&self) -> MutexGuard<()> {
+        if ahci_can_sleep() {
+            return self.io_lock.lock();
+        }
+        loop {
+            if let Ok(guard) = self.io_lock.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
 
+    /// @brief: 从磁盘中读取 MBR 分区表结构体
+    pub fn read_mbr_table(&self) -> Result<MbrDiskPartionTable, SystemError> {
+        // 数据缓冲区
+        let mut buf: Vec<u8> = vec![0; MbrDiskPartionTable::SIZE];
         self.read_at(0, 1, &mut buf)?;
-        // 创建 Cursor 用于按字节读取
-        let mut cursor = VecCursor::new(buf);
-        cursor.seek(SeekFrom::SeekCurrent(446))?;
 
-        for i in 0..4 {
+        let table = MbrDiskPartionTable::from_bytes(&buf)?;
+        for (i, dpte) in table.dpte.iter().enumerate() {
             kdebug!("infomation of partition {}:\n", i);
-
-            table.dpte[i].flags = cursor.read_u8()?;
-            table.dpte[i].starting_head = cursor.read_u8()?;
-            table.dpte[i].starting_sector_cylinder = cursor.read_u16()?;
-            table.dpte[i].part_type = cursor.read_u8()?;
-            table.dpte[i].ending_head = cursor.read_u8()?;
-            table.dpte[i].ending_sector_cylingder = cursor.read_u16()?;
-            table.dpte[i].starting_lba = cursor.read_u32()?;
-            table.dpte[i].total_sectors = cursor.read_u32()?;
-
-            kdebug!("dpte[i] = {:?}", table.dpte[i]);
+            kdebug!("dpte[i] = {:?}", dpte);
         }
-        table.bs_trailsig = cursor.read_u16()?;
-        // kdebug!("bs_trailsig = {}", unsafe {
-        //     read_unaligned(addr_of!(table.bs_trailsig))
-        // });
 
         return Ok(table);
     }
@@ -551,12 +721,12 @@ impl BlockDevice for LockedAhciDisk {
     }
 
     fn sync(&self) -> Result<(), SystemError> {
-        return self.0.lock().sync();
+        return self.lock().sync();
     }
 
     #[inline]
     fn device(&self) -> Arc<dyn Device> {
-        return self.0.lock().self_ref.upgrade().unwrap();
+        return self.lock().self_ref.upgrade().unwrap();
     }
 
     fn block_size(&self) -> usize {
@@ -564,7 +734,11 @@ impl BlockDevice for LockedAhciDisk {
     }
 
     fn partitions(&self) -> Vec<Arc<Partition>> {
-        return self.0.lock().partitions.clone();
+        return self.lock().partitions.clone();
+    }
+
+    fn bdi(&self) -> Option<Arc<BackingDevInfo>> {
+        return Some(self.bdi.clone());
     }
 
     #[inline]
@@ -574,7 +748,9 @@ impl BlockDevice for LockedAhciDisk {
         count: usize,          // 读取lba的数量
         buf: &mut [u8],
     ) -> Result<usize, SystemError> {
-        self.0.lock().read_at(lba_id_start, count, buf)
+        let _io_guard = self.lock_io();
+        let io = self.lock().io();
+        io.read_at(lba_id_start, count, buf)
     }
 
     #[inline]
@@ -584,6 +760,8 @@ impl BlockDevice for LockedAhciDisk {
         count: usize,
         buf: &[u8],
     ) -> Result<usize, SystemError> {
-        self.0.lock().write_at(lba_id_start, count, buf)
+        let _io_guard = self.lock_io();
+        let io = self.lock().io();
+        io.write_at(lba_id_start, count, buf)
     }
 }
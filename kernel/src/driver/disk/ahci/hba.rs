@@ -1,8 +1,9 @@
 use alloc::vec::Vec;
-use core::{intrinsics::size_of, ptr};
+use core::ptr;
 
 use core::sync::atomic::compiler_fence;
 
+use crate::libs::volatile::{Mmio, VolatileCell};
 use crate::mm::phys_2_virt;
 
 /// 文件说明: 实现了 AHCI 中的控制器 HBA 的相关行为
@@ -24,6 +25,10 @@ pub const HBA_PORT_CMD_FR: u32 = 1 << 14;
 pub const HBA_PORT_CMD_FRE: u32 = 1 << 4;
 pub const HBA_PORT_CMD_ST: u32 = 1;
 #[allow(dead_code)]
+/// SControl 寄存器的 Device Detection Initialization 字段
+pub const HBA_PORT_SCTL_DET: u32 = 0xf;
+/// DET 字段的值：向设备发送 COMRESET
+pub const HBA_PORT_SCTL_DET_COMRESET: u32 = 1;
 pub const HBA_PORT_IS_ERR: u32 = 1 << 30 | 1 << 29 | 1 << 28 | 1 << 27;
 pub const HBA_SSTS_PRESENT: u32 = 0x3;
 pub const HBA_SIG_ATA: u32 = 0x00000101;
@@ -43,58 +48,70 @@ pub enum HbaPortType {
 }
 
 /// 声明了 HBA 的所有属性
-#[repr(packed)]
+#[repr(C)]
 pub struct HbaPort {
-    pub clb: u64,         // 0x00, command list base address, 1K-byte aligned
-    pub fb: u64,          // 0x08, FIS base address, 256-byte aligned
-    pub is: u32,          // 0x10, interrupt status
-    pub ie: u32,          // 0x14, interrupt enable
-    pub cmd: u32,         // 0x18, command and status
+    pub clb: Mmio<u64>,   // 0x00, command list base address, 1K-byte aligned
+    pub fb: Mmio<u64>,    // 0x08, FIS base address, 256-byte aligned
+    pub is: Mmio<u32>,    // 0x10, interrupt status
+    pub ie: Mmio<u32>,    // 0x14, interrupt enable
+    pub cmd: Mmio<u32>,   // 0x18, command and status
     pub _rsv0: u32,       // 0x1C, Reserved
-    pub tfd: u32,         // 0x20, task file data
-    pub sig: u32,         // 0x24, signature
-    pub ssts: u32,        // 0x28, SATA status (SCR0:SStatus)
-    pub sctl: u32,        // 0x2C, SATA control (SCR2:SControl)
-    pub serr: u32,        // 0x30, SATA error (SCR1:SError)
-    pub sact: u32,        // 0x34, SATA active (SCR3:SActive)
-    pub ci: u32,          // 0x38, command issue
-    pub sntf: u32,        // 0x3C, SATA notification (SCR4:SNotification)
-    pub fbs: u32,         // 0x40, FIS-based switch control
+    pub tfd: Mmio<u32>,   // 0x20, task file data
+    pub sig: Mmio<u32>,   // 0x24, signature
+    pub ssts: Mmio<u32>,  // 0x28, SATA status (SCR0:SStatus)
+    pub sctl: Mmio<u32>,  // 0x2C, SATA control (SCR2:SControl)
+    pub serr: Mmio<u32>,  // 0x30, SATA error (SCR1:SError)
+    pub sact: Mmio<u32>,  // 0x34, SATA active (SCR3:SActive)
+    pub ci: Mmio<u32>,    // 0x38, command issue
+    pub sntf: Mmio<u32>,  // 0x3C, SATA notification (SCR4:SNotification)
+    pub fbs: Mmio<u32>,   // 0x40, FIS-based switch control
     pub _rsv1: [u32; 11], // 0x44 ~ 0x6F, Reserved
     pub vendor: [u32; 4], // 0x70 ~ 0x7F, vendor specific
 }
 
 /// 全称 HBA Memory Register，是HBA的寄存器在内存中的映射
-#[repr(packed)]
+#[repr(C)]
 pub struct HbaMem {
-    pub cap: u32,             // 0x00, Host capability
-    pub ghc: u32,             // 0x04, Global host control
-    pub is: u32,              // 0x08, Interrupt status
-    pub pi: u32,              // 0x0C, Port implemented
-    pub vs: u32,              // 0x10, Version
-    pub ccc_ctl: u32,         // 0x14, Command completion coalescing control
-    pub ccc_pts: u32,         // 0x18, Command completion coalescing ports
-    pub em_loc: u32,          // 0x1C, Enclosure management location
-    pub em_ctl: u32,          // 0x20, Enclosure management control
-    pub cap2: u32,            // 0x24, Host capabilities extended
-    pub bohc: u32,            // 0x28, BIOS/OS handoff control and status
+    pub cap: Mmio<u32>,       // 0x00, Host capability
+    pub ghc: Mmio<u32>,       // 0x04, Global host control
+    pub is: Mmio<u32>,        // 0x08, Interrupt status
+    pub pi: Mmio<u32>,        // 0x0C, Port implemented
+    pub vs: Mmio<u32>,        // 0x10, Version
+    pub ccc_ctl: Mmio<u32>,   // 0x14, Command completion coalescing control
+    pub ccc_pts: Mmio<u32>,   // 0x18, Command completion coalescing ports
+    pub em_loc: Mmio<u32>,    // 0x1C, Enclosure management location
+    pub em_ctl: Mmio<u32>,    // 0x20, Enclosure management control
+    pub cap2: Mmio<u32>,      // 0x24, Host capabilities extended
+    pub bohc: Mmio<u32>,      // 0x28, BIOS/OS handoff control and status
     pub _rsv: [u8; 116],      // 0x2C - 0x9F, Reserved
     pub vendor: [u8; 96],     // 0xA0 - 0xFF, Vendor specific registers
     pub ports: [HbaPort; 32], // 0x100 - 0x10FF, Port control registers
 }
 
+/// GHC 寄存器的 Interrupt Enable 位
+pub const HBA_GHC_IE: u32 = 1 << 1;
+/// 端口的中断使能位: DHRS | PSS | DSS | UFS，这些中断都表示命令已经完成
+///
+/// 任务文件错误(TFES)不会触发中断，仍由发出命令的一方轮询PxIS来发现
+pub const HBA_PORT_IE_COMPLETION: u32 = 0b10111;
+
+/// PRDT 项中 dbc 字段的 Byte count 部分（实际字节数 - 1）
+pub const HBA_PRDT_DBC: u32 = (1 << 22) - 1;
+/// PRDT 项中 dbc 字段的 Interrupt on completion 位
+pub const HBA_PRDT_I: u32 = 1 << 31;
+
 /// HBA Command Table 里面的 PRDT 项
 /// 作用: 记录了内存中读/写数据的位置，以及长度。你可以把他类比成一个指针？
-#[repr(packed)]
+#[repr(C)]
 pub struct HbaPrdtEntry {
-    pub dba: u64, // Data base address
-    _rsv0: u32,   // Reserved
-    pub dbc: u32, // Byte count, 4M max, interrupt = 1
+    pub dba: VolatileCell<u64>, // Data base address
+    _rsv0: u32,                 // Reserved
+    pub dbc: VolatileCell<u32>, // Byte count, 4M max, interrupt = 1
 }
 
 /// HAB Command Table
 /// 每个 Port 一个 Table，主机和设备的交互都靠这个数据结构
-#[repr(packed)]
+#[repr(C)]
 pub struct HbaCmdTable {
     // 0x00
     pub cfis: [u8; 64], // Command FIS
@@ -106,30 +123,54 @@ pub struct HbaCmdTable {
     pub prdt_entry: [HbaPrdtEntry; 8], // Physical region descriptor table entries, 0 ~ 65535, 需要注意不要越界 这里设置8的原因是，目前CmdTable只预留了8个PRDT项的空间
 }
 
+impl HbaCmdTable {
+    /// 把 cfis 当作 Register FIS - host to device 进行访问
+    pub fn cmd_fis(&mut self) -> &mut FisRegH2D {
+        unsafe { &mut *(self.cfis.as_mut_ptr() as *mut FisRegH2D) }
+    }
+}
+
+/// Command Header 中 cfl 字段的 Command FIS length 部分(以DWORD为单位)
+pub const HBA_CMD_HEADER_CFL: u8 = (1 << 5) - 1;
+/// Command Header 中 cfl 字段的 ATAPI 位
+pub const HBA_CMD_HEADER_A: u8 = 1 << 5;
+/// Command Header 中 cfl 字段的 Write 位 (1: host to device)
+pub const HBA_CMD_HEADER_W: u8 = 1 << 6;
+/// Command Header 中 cfl 字段的 Prefetchable 位
+pub const HBA_CMD_HEADER_P: u8 = 1 << 7;
+
 /// HBA Command Header
 /// 作用: 你可以把他类比成 Command Table 的指针。
 /// 猜测: 这里多了一层 Header，而不是直接在 HbaMem 结构体指向 CmdTable，可能是为了兼容和可移植性？
-#[repr(packed)]
+#[repr(C)]
 pub struct HbaCmdHeader {
     // DW0
-    pub cfl: u8,
+    pub cfl: VolatileCell<u8>,
     // Command FIS length in DWORDS: 5(len in [2, 16]), atapi: 1, write - host to device: 1, prefetchable: 1
-    pub _pm: u8,    // Reset - 0x80, bist: 0x40, clear busy on ok: 0x20, port multiplier
-    pub prdtl: u16, // Physical region descriptor table length in entries
+    pub _pm: VolatileCell<u8>, // Reset - 0x80, bist: 0x40, clear busy on ok: 0x20, port multiplier
+    pub prdtl: VolatileCell<u16>, // Physical region descriptor table length in entries
     // DW1
-    pub _prdbc: u32, // Physical region descriptor byte count transferred
+    pub _prdbc: VolatileCell<u32>, // Physical region descriptor byte count transferred
     // DW2, 3
-    pub ctba: u64, // Command table descriptor base address
+    pub ctba: VolatileCell<u64>, // Command table descriptor base address
     // DW4 - 7
     pub _rsv1: [u32; 4], // Reserved
 }
 
+// 以上结构体的布局由 AHCI 规范规定，在编译期检查它们的大小
+const _: () = assert!(core::mem::size_of::<HbaPort>() == 0x80);
+const _: () = assert!(core::mem::size_of::<HbaMem>() == 0x1100);
+const _: () = assert!(core::mem::size_of::<HbaPrdtEntry>() == 16);
+const _: () = assert!(core::mem::size_of::<HbaCmdTable>() == 0x100);
+const _: () = assert!(core::mem::size_of::<HbaCmdHeader>() == 32);
+const _: () = assert!(core::mem::size_of::<FisRegH2D>() == 20);
+
 /// Port 的函数实现
 impl HbaPort {
     /// 获取设备类型
     pub fn check_type(&mut self) -> HbaPortType {
-        if volatile_read!(self.ssts) & HBA_SSTS_PRESENT > 0 {
-            let sig = volatile_read!(self.sig);
+        if self.ssts.read() & HBA_SSTS_PRESENT > 0 {
+            let sig = self.sig.read();
             match sig {
                 HBA_SIG_ATA => HbaPortType::SATA,
                 HBA_SIG_ATAPI => HbaPortType::SATAPI,
@@ -144,42 +185,60 @@ impl HbaPort {
 
     /// 启动该端口的命令引擎
     pub fn start(&mut self) {
-        while volatile_read!(self.cmd) & HBA_PORT_CMD_CR > 0 {
+        while self.cmd.read() & HBA_PORT_CMD_CR > 0 {
             core::hint::spin_loop();
         }
-        let val: u32 = volatile_read!(self.cmd) | HBA_PORT_CMD_FRE | HBA_PORT_CMD_ST;
-        volatile_write!(self.cmd, val);
+        self.cmd.set_bits(HBA_PORT_CMD_FRE | HBA_PORT_CMD_ST);
     }
 
     /// 关闭该端口的命令引擎
     pub fn stop(&mut self) {
-        #[allow(unused_unsafe)]
-        {
-            volatile_write!(
-                self.cmd,
-                (u32::MAX ^ HBA_PORT_CMD_ST) & volatile_read!(self.cmd)
-            );
+        self.cmd.clear_bits(HBA_PORT_CMD_ST);
+
+        while self.cmd.test_bits(HBA_PORT_CMD_FR | HBA_PORT_CMD_CR) {
+            core::hint::spin_loop();
         }
 
-        while volatile_read!(self.cmd) & (HBA_PORT_CMD_FR | HBA_PORT_CMD_CR)
-            == (HBA_PORT_CMD_FR | HBA_PORT_CMD_CR)
-        {
+        self.cmd.clear_bits(HBA_PORT_CMD_FRE);
+    }
+
+    /// 复位该端口，用于从超时的命令中恢复
+    ///
+    /// 停止命令引擎会清除PxCI中所有还没有完成的命令。如果命令引擎在限定时间内无法停止，
+    /// 则发送COMRESET复位链路，之后清除错误状态并重新启动命令引擎
+    ///
+    /// 参考 AHCI 1.3.1 规范 10.4.2 Port Reset
+    pub fn reset(&mut self) {
+        const SPIN_LIMIT: u32 = 1000000;
+
+        self.cmd.clear_bits(HBA_PORT_CMD_ST);
+        let mut spin_count = 0;
+        while self.cmd.read() & HBA_PORT_CMD_CR > 0 && spin_count < SPIN_LIMIT {
             core::hint::spin_loop();
+            spin_count += 1;
         }
 
-        #[allow(unused_unsafe)]
-        {
-            volatile_write!(
-                self.cmd,
-                (u32::MAX ^ HBA_PORT_CMD_FRE) & volatile_read!(self.cmd)
-            );
+        if spin_count == SPIN_LIMIT {
+            // 命令引擎停不下来，复位链路。规范要求DET=1至少保持1ms
+            self.sctl
+                .write_bits(HBA_PORT_SCTL_DET, HBA_PORT_SCTL_DET_COMRESET);
+            for _ in 0..SPIN_LIMIT {
+                core::hint::spin_loop();
+            }
+            self.sctl.clear_bits(HBA_PORT_SCTL_DET);
         }
+
+        // SError 和 PxIS 都是写1清零
+        self.serr.write(u32::MAX);
+        self.is.write(u32::MAX);
+        self.start();
     }
 
+    /// @param depth: 只使用前depth个命令槽
     /// @return: 返回一个空闲 cmd table 的 id; 如果没有，则返回 Option::None
-    pub fn find_cmdslot(&self) -> Option<u32> {
-        let slots = volatile_read!(self.sact) | volatile_read!(self.ci);
-        for i in 0..32 {
+    pub fn find_cmdslot(&self, depth: u32) -> Option<u32> {
+        let slots = self.sact.read() | self.ci.read();
+        for i in 0..core::cmp::min(depth, 32) {
             if slots & 1 << i == 0 {
                 return Some(i);
             }
@@ -197,7 +256,7 @@ impl HbaPort {
         // Command list entry size = 32
         // Command list entry maxim count = 32
         // Command list maxim size = 32*32 = 1K per port
-        volatile_write!(self.clb, clb);
+        self.clb.write(clb);
 
         unsafe {
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
@@ -207,7 +266,7 @@ impl HbaPort {
         // 赋值 fis base address
         // FIS offset: 32K+256*portno
         // FIS entry size = 256 bytes per port
-        volatile_write!(self.fb, fb);
+        self.fb.write(fb);
         unsafe {
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
             ptr::write_bytes(phys_2_virt(fb as usize) as *mut u64, 0, 256);
@@ -216,32 +275,32 @@ impl HbaPort {
         // 赋值 command table base address
         // Command table offset: 40K + 8K*portno
         // Command table size = 256*32 = 8K per port
-        let mut cmdheaders = phys_2_virt(clb as usize) as *mut u64 as *mut HbaCmdHeader;
-        for i in 0..32 as usize {
-            volatile_write!((*cmdheaders).prdtl, 0); // 一开始没有询问，prdtl = 0（预留了8个PRDT项的空间）
-            volatile_write!((*cmdheaders).ctba, ctbas[i]);
+        let cmdheaders = unsafe {
+            core::slice::from_raw_parts_mut(phys_2_virt(clb as usize) as *mut HbaCmdHeader, 32)
+        };
+        for (i, cmdheader) in cmdheaders.iter_mut().enumerate() {
+            cmdheader.prdtl.write(0); // 一开始没有询问，prdtl = 0（预留了8个PRDT项的空间）
+            cmdheader.ctba.write(ctbas[i]);
             // 这里限制了 prdtl <= 8, 所以一共用了256bytes，如果需要修改，可以修改这里
             compiler_fence(core::sync::atomic::Ordering::SeqCst);
             unsafe {
                 ptr::write_bytes(phys_2_virt(ctbas[i] as usize) as *mut u64, 0, 256);
             }
-            cmdheaders = (cmdheaders as usize + size_of::<HbaCmdHeader>()) as *mut HbaCmdHeader;
         }
 
-        #[allow(unused_unsafe)]
-        {
-            // 启动中断
-            volatile_write!(self.ie, 0 /*TODO: Enable interrupts: 0b10111*/);
+        // 启动中断
+        self.ie.write(0 /*TODO: Enable interrupts: 0b10111*/);
 
-            // 错误码
-            volatile_write!(self.serr, volatile_read!(self.serr));
+        // 错误码
+        let serr = self.serr.read();
+        self.serr.write(serr);
 
-            // Disable power management
-            volatile_write!(self.sctl, volatile_read!(self.sctl) | 7 << 8);
+        // Disable power management
+        self.sctl.set_bits(7 << 8);
+
+        // Power on and spin up device
+        self.cmd.set_bits(1 << 2 | 1 << 1);
 
-            // Power on and spin up device
-            volatile_write!(self.cmd, volatile_read!(self.cmd) | 1 << 2 | 1 << 1);
-        }
         self.start(); // 重新开启端口
     }
 }
@@ -267,40 +326,58 @@ pub enum FisType {
     DevBits = 0xA1,
 }
 
-#[repr(packed)]
+#[repr(C)]
 pub struct FisRegH2D {
     // DWORD 0
-    pub fis_type: u8, // FIS_TYPE_REG_H2D
+    pub fis_type: VolatileCell<u8>, // FIS_TYPE_REG_H2D
 
-    pub pm: u8, // Port multiplier, 1: Command, 0: Control
+    pub pm: VolatileCell<u8>, // Port multiplier, 1: Command, 0: Control
     // uint8_t pmport : 4; // Port multiplier  低4位
     // uint8_t rsv0 : 3;   // Reserved
     // uint8_t c : 1;      // 1: Command, 0: Control
-    pub command: u8,  // Command register
-    pub featurel: u8, // Feature register, 7:0
+    pub command: VolatileCell<u8>,  // Command register
+    pub featurel: VolatileCell<u8>, // Feature register, 7:0
 
     // DWORD 1
-    pub lba0: u8,   // LBA low register, 7:0
-    pub lba1: u8,   // LBA mid register, 15:8
-    pub lba2: u8,   // LBA high register, 23:16
-    pub device: u8, // Device register
+    pub lba0: VolatileCell<u8>,   // LBA low register, 7:0
+    pub lba1: VolatileCell<u8>,   // LBA mid register, 15:8
+    pub lba2: VolatileCell<u8>,   // LBA high register, 23:16
+    pub device: VolatileCell<u8>, // Device register
 
     // DWORD 2
-    pub lba3: u8,     // LBA register, 31:24
-    pub lba4: u8,     // LBA register, 39:32
-    pub lba5: u8,     // LBA register, 47:40
-    pub featureh: u8, // Feature register, 15:8
+    pub lba3: VolatileCell<u8>,     // LBA register, 31:24
+    pub lba4: VolatileCell<u8>,     // LBA register, 39:32
+    pub lba5: VolatileCell<u8>,     // LBA register, 47:40
+    pub featureh: VolatileCell<u8>, // Feature register, 15:8
 
     // DWORD 3
-    pub countl: u8,  // Count register, 7:0
-    pub counth: u8,  // Count register, 15:8
-    pub icc: u8,     // Isochronous command completion
-    pub control: u8, // Control register
+    pub countl: VolatileCell<u8>,  // Count register, 7:0
+    pub counth: VolatileCell<u8>,  // Count register, 15:8
+    pub icc: VolatileCell<u8>,     // Isochronous command completion
+    pub control: VolatileCell<u8>, // Control register
 
     // DWORD 4
     pub rsv1: [u8; 4], // Reserved
 }
 
+impl FisRegH2D {
+    /// 设置48位的起始LBA
+    pub fn set_lba(&mut self, lba: u64) {
+        self.lba0.write(lba as u8);
+        self.lba1.write((lba >> 8) as u8);
+        self.lba2.write((lba >> 16) as u8);
+        self.lba3.write((lba >> 24) as u8);
+        self.lba4.write((lba >> 32) as u8);
+        self.lba5.write((lba >> 40) as u8);
+    }
+
+    /// 设置要传输的扇区数
+    pub fn set_count(&mut self, count: u16) {
+        self.countl.write(count as u8);
+        self.counth.write((count >> 8) as u8);
+    }
+}
+
 #[repr(packed)]
 #[allow(dead_code)]
 pub struct FisRegD2H {
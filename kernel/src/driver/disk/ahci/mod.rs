@@ -1,32 +1,38 @@
 // 导出 ahci 相关的 module
+pub mod ahci_driver;
 pub mod ahci_inode;
 pub mod ahcidisk;
 pub mod hba;
 
-use crate::driver::base::block::block_device::BlockDevice;
+use crate::arch::interrupt::TrapFrame;
+use crate::driver::base::block::block_device::{BlockDevice, LBA_SIZE};
 use crate::driver::base::block::disk_info::BLK_GF_AHCI;
 // 依赖的rust工具包
-use crate::driver::pci::pci::{
-    get_pci_device_structure_mut, PciDeviceStructure, PCI_DEVICE_LINKEDLIST,
-};
-use crate::filesystem::devfs::devfs_register;
-use crate::kerror;
-use crate::libs::rwlock::RwLockWriteGuard;
+use crate::driver::base::device::driver::{driver_manager, Driver};
+use crate::driver::iommu::dma::{dma_map_identity, DmaDirection};
+use crate::driver::pci::device::PciDevice;
+use crate::driver::pci::driver::{pci_driver_manager, PciDriver};
+use crate::driver::pci::pci::BusDeviceFunction;
+use crate::driver::pci::pci_irq::IRQ;
+use crate::exception::irqdesc::{IrqHandleFlags, IrqHandler, IrqNumber, IrqReturn};
+use crate::filesystem::devfs::{devfs_ready, devfs_register};
 use crate::libs::spinlock::{SpinLock, SpinLockGuard};
-use crate::mm::virt_2_phys;
+use crate::libs::wait_queue::WaitQueue;
+use crate::mm::{virt_2_phys, VirtAddr};
 use crate::syscall::SystemError;
 use crate::{
     driver::disk::ahci::{
         ahcidisk::LockedAhciDisk,
         hba::HbaMem,
-        hba::{HbaPort, HbaPortType},
+        hba::{HbaPort, HbaPortType, HBA_GHC_IE, HBA_PORT_IE_COMPLETION},
     },
     kdebug,
 };
+use crate::{kernel_param, kernel_test, kerror, kinfo, ktest_assert, kwarn};
+use ahci_driver::AhciDriver;
 use ahci_inode::LockedAhciInode;
 use alloc::{
     boxed::Box,
-    collections::LinkedList,
     format,
     string::{String, ToString},
     sync::Arc,
@@ -37,124 +43,499 @@ use core::sync::atomic::compiler_fence;
 // 仅module内可见 全局数据区  hbr_port, disks
 static LOCKED_HBA_MEM_LIST: SpinLock<Vec<&mut HbaMem>> = SpinLock::new(Vec::new());
 static LOCKED_DISKS_LIST: SpinLock<Vec<Arc<LockedAhciDisk>>> = SpinLock::new(Vec::new());
+/// 每个控制器的信息，下标与LOCKED_HBA_MEM_LIST相同
+static LOCKED_HOST_LIST: SpinLock<Vec<AhciHost>> = SpinLock::new(Vec::new());
+
+/// 一个AHCI控制器的信息
+#[derive(Debug)]
+struct AhciHost {
+    /// 控制器的PCI地址
+    bdf: BusDeviceFunction,
+    /// 控制器支持的命令槽的数量(CAP.NCS + 1)
+    nr_slots: u32,
+    /// 驱动使用的命令槽的数量，可以通过sysfs中的`queue_depth`修改
+    queue_depth: u32,
+    /// 睡眠之前控制器的中断是否是打开的
+    irq_enabled_before_suspend: bool,
+    /// 初始化失败而被跳过的端口，以及失败的原因
+    port_errors: Vec<(usize, SystemError)>,
+    /// 控制器的MSI中断是否已经设置好
+    irq_ready: bool,
+    /// 使用轮询等待命令完成的端口，可以通过sysfs中的`port_poll`修改
+    poll_ports: u32,
+    /// 轮询模式下每个端口最近的命令的平均完成时间(单位：纳秒)
+    poll_mean_ns: [u64; 32],
+    /// 每个端口的等待队列，中断模式下等待命令完成的进程在上面睡眠
+    port_wait: Arc<[WaitQueue; 32]>,
+}
 
-const AHCI_CLASS: u8 = 0x1;
-const AHCI_SUBCLASS: u8 = 0x6;
+/// 等待端口上的命令完成的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AhciCompletion {
+    /// 睡眠，由端口的中断唤醒
+    Irq,
+    /// 轮询命令是否完成，轮询的间隔中睡眠
+    Poll,
+}
 
 /* TFES - Task File Error Status */
 #[allow(non_upper_case_globals)]
 pub const HBA_PxIS_TFES: u32 = 1 << 30;
 
-/// @brief 寻找所有的ahci设备
-/// @param list 链表的写锁
-/// @return Result<Vec<&'a mut Box<dyn PciDeviceStructure>>, SystemError>   成功则返回包含所有ahci设备结构体的可变引用的链表，失败则返回err
-fn ahci_device_search<'a>(
-    list: &'a mut RwLockWriteGuard<'_, LinkedList<Box<dyn PciDeviceStructure>>>,
-) -> Result<Vec<&'a mut Box<dyn PciDeviceStructure>>, SystemError> {
-    let result = get_pci_device_structure_mut(list, AHCI_CLASS, AHCI_SUBCLASS);
-
-    if result.is_empty() {
+/// @brief: 初始化 ahci
+///
+/// 向PCI总线注册AHCI驱动，由总线探测所有的AHCI控制器
+pub fn ahci_init() -> Result<(), SystemError> {
+    let driver = AhciDriver::new();
+    pci_driver_manager().register(driver.clone() as Arc<dyn PciDriver>)?;
+    driver_manager().wait_for_device_probe();
+    if driver.devices().is_empty() {
         return Err(SystemError::ENODEV);
     }
-
-    return Ok(result);
+    return Ok(());
 }
 
-/// @brief: 初始化 ahci
-pub fn ahci_init() -> Result<(), SystemError> {
-    let mut list = PCI_DEVICE_LINKEDLIST.write();
-    let ahci_device = ahci_device_search(&mut list)?;
+// 启动参数`ahci.ignore_port=1,3`使所有控制器都跳过指定的端口，用于绕过有问题的设备
+kernel_param!(AHCI_IGNORE_PORT_PARAM: Vec<u8> = "ahci.ignore_port");
+
+/// @brief: 初始化一个ahci控制器，并为其上的每一个磁盘创建设备
+fn ahci_probe(dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+    // 磁盘需要注册到devfs中
+    if !devfs_ready() {
+        return Err(SystemError::EPROBE_DEFER);
+    }
+    dev.enable_device();
+    dev.set_master();
+    let bdf = dev.bus_device_function();
+    let virtaddr = dev
+        .with_structure_mut(|device| -> Result<VirtAddr, SystemError> {
+            let standard_device = device.as_standard_device_mut().ok_or(SystemError::ENODEV)?;
+            standard_device.bar_ioremap();
+            return Ok(standard_device
+                .bar()
+                .ok_or(SystemError::EACCES)?
+                .get_bar(5)
+                .or(Err(SystemError::EACCES))?
+                .virtual_address()
+                .ok_or(SystemError::EACCES)?);
+        })
+        .ok_or(SystemError::ENODEV)??;
+    if virtaddr.is_null() {
+        return Err(SystemError::EACCES);
+    }
     // 全局数据 - 列表
     let mut disks_list = LOCKED_DISKS_LIST.lock();
 
-    for device in ahci_device {
-        let standard_device = device.as_standard_device_mut().unwrap();
-        standard_device.bar_ioremap();
-        // 对于每一个ahci控制器分配一块空间
-        let ahci_port_base_vaddr =
-            Box::leak(Box::new([0u8; (1 << 20) as usize])) as *mut u8 as usize;
-        let virtaddr = standard_device
-            .bar()
-            .ok_or(SystemError::EACCES)?
-            .get_bar(5)
-            .or(Err(SystemError::EACCES))?
-            .virtual_address()
-            .unwrap();
-        // 最后把这个引用列表放入到全局列表
-        let mut hba_mem_list = LOCKED_HBA_MEM_LIST.lock();
-        //这里两次unsafe转引用规避rust只能有一个可变引用的检查，提高运行速度
-        let hba_mem = unsafe { (virtaddr.data() as *mut HbaMem).as_mut().unwrap() };
-        hba_mem_list.push(unsafe { (virtaddr.data() as *mut HbaMem).as_mut().unwrap() });
-        let pi = volatile_read!(hba_mem.pi);
-        let hba_mem_index = hba_mem_list.len() - 1;
-        drop(hba_mem_list);
-        // 初始化所有的port
-        let mut id = 0;
-        for j in 0..32 {
-            if (pi >> j) & 1 > 0 {
-                let hba_mem_list = LOCKED_HBA_MEM_LIST.lock();
-                let hba_mem_port = &mut hba_mem.ports[j];
-                let tp = hba_mem_port.check_type();
-                match tp {
-                    HbaPortType::None => {
-                        kdebug!("<ahci_rust_init> Find a None type Disk.");
-                    }
-                    HbaPortType::Unknown(err) => {
-                        kdebug!("<ahci_rust_init> Find a Unknown({:?}) type Disk.", err);
+    // 对于每一个ahci控制器分配一块空间
+    let ahci_port_base_vaddr = Box::leak(Box::new([0u8; (1 << 20) as usize])) as *mut u8 as usize;
+    // 命令列表、FIS接收区和命令表会被控制器长期访问，并且驱动需要根据其中保存的地址找回这些结构体，
+    // 因此以1:1的方式映射给控制器
+    dma_map_identity(
+        &bdf,
+        VirtAddr::new(ahci_port_base_vaddr),
+        1 << 20,
+        DmaDirection::Bidirectional,
+    )?;
+    // 最后把这个引用列表放入到全局列表
+    let mut hba_mem_list = LOCKED_HBA_MEM_LIST.lock();
+    //这里两次unsafe转引用规避rust只能有一个可变引用的检查，提高运行速度
+    let hba_mem = unsafe { &mut *(virtaddr.data() as *mut HbaMem) };
+    hba_mem_list.push(unsafe { &mut *(virtaddr.data() as *mut HbaMem) });
+    let pi = hba_mem.pi.read();
+    let hba_mem_index = hba_mem_list.len() - 1;
+    let nr_slots = ((hba_mem.cap.read() >> 8) & 0x1f) + 1;
+    LOCKED_HOST_LIST.lock().push(AhciHost {
+        bdf,
+        nr_slots,
+        queue_depth: nr_slots,
+        irq_enabled_before_suspend: false,
+        port_errors: Vec::new(),
+        irq_ready: false,
+        // 中断设置好之前只能轮询
+        poll_ports: pi,
+        poll_mean_ns: [0; 32],
+        port_wait: Arc::new([WaitQueue::INIT; 32]),
+    });
+    drop(hba_mem_list);
+    let ignore_ports = AHCI_IGNORE_PORT_PARAM.get().unwrap_or_default();
+    // 一个端口初始化失败时，记录原因并跳过它，不影响控制器上的其他端口
+    let mut port_errors = Vec::new();
+    // 初始化所有的port。端口初始化的日志默认不输出，调试时可以通过
+    // `echo "module ahci +p" > /proc/dynamic_debug/control`或者启动参数`dyndbg=module,ahci,+p`打开
+    for j in 0..32 {
+        if (pi >> j) & 1 > 0 {
+            if ignore_ports.contains(&(j as u8)) {
+                kinfo!("ahci {}: port {}: ignored", bdf, j);
+                continue;
+            }
+            let hba_mem_list = LOCKED_HBA_MEM_LIST.lock();
+            let hba_mem_port = &mut hba_mem.ports[j];
+            let tp = hba_mem_port.check_type();
+            match tp {
+                HbaPortType::None => {
+                    kdebug!("ahci {}: port {}: no device", bdf, j);
+                }
+                HbaPortType::Unknown(err) => {
+                    kdebug!("ahci {}: port {}: unknown device type {:?}", bdf, j, err);
+                }
+                _ => {
+                    kdebug!("ahci {}: port {}: found a {:?} device", bdf, j, tp);
+
+                    // 计算地址
+                    let fb = virt_2_phys(ahci_port_base_vaddr + (32 << 10) + (j << 8));
+                    let clb = virt_2_phys(ahci_port_base_vaddr + (j << 10));
+                    let ctbas = (0..32)
+                        .map(|x| {
+                            virt_2_phys(ahci_port_base_vaddr + (40 << 10) + (j << 13) + (x << 8))
+                                as u64
+                        })
+                        .collect::<Vec<_>>();
+
+                    // 初始化 port
+                    hba_mem_port.init(clb as u64, fb as u64, &ctbas);
+                    drop(hba_mem_list);
+                    compiler_fence(core::sync::atomic::Ordering::SeqCst);
+                    // 创建 disk，ID 从0开始，在所有控制器之间唯一
+                    let id = disks_list.len();
+                    let disk = LockedAhciDisk::new(
+                        format!("ahci_disk_{}", id),
+                        BLK_GF_AHCI,
+                        hba_mem_index as u8,
+                        j as u8,
+                        bdf,
+                    );
+                    match disk {
+                        Ok(disk) => disks_list.push(disk),
+                        Err(e) => {
+                            kerror!("ahci {}: port {}: init failed, skipped: {:?}", bdf, j, e);
+                            port_errors.push((j, e));
+                            continue;
+                        }
                     }
-                    _ => {
-                        kdebug!("<ahci_rust_init> Find a {:?} type Disk.", tp);
-
-                        // 计算地址
-                        let fb = virt_2_phys(ahci_port_base_vaddr + (32 << 10) + (j << 8));
-                        let clb = virt_2_phys(ahci_port_base_vaddr + (j << 10));
-                        let ctbas = (0..32)
-                            .map(|x| {
-                                virt_2_phys(
-                                    ahci_port_base_vaddr + (40 << 10) + (j << 13) + (x << 8),
-                                ) as u64
-                            })
-                            .collect::<Vec<_>>();
-
-                        // 初始化 port
-                        hba_mem_port.init(clb as u64, fb as u64, &ctbas);
-                        drop(hba_mem_list);
-                        compiler_fence(core::sync::atomic::Ordering::SeqCst);
-                        // 创建 disk
-                        disks_list.push(LockedAhciDisk::new(
-                            format!("ahci_disk_{}", id),
-                            BLK_GF_AHCI,
-                            hba_mem_index as u8,
-                            j as u8,
-                        )?);
-                        id += 1; // ID 从0开始
 
-                        kdebug!("start register ahci device");
+                    kdebug!("ahci {}: port {}: registering ahci_{}", bdf, j, id + 1);
 
-                        // 挂载到devfs上面去
-                        let ret = devfs_register(
-                            format!("ahci_{}", id).as_str(),
-                            LockedAhciInode::new(disks_list.last().unwrap().clone()),
+                    // 挂载到devfs上面去
+                    let ret = devfs_register(
+                        format!("ahci_{}", id + 1).as_str(),
+                        LockedAhciInode::new(disks_list.last().unwrap().clone()),
+                    );
+                    if let Err(err) = ret {
+                        kerror!(
+                            "Ahci_{} ctrl = {}, port = {} failed to register, error code = {:?}",
+                            id + 1,
+                            hba_mem_index as u8,
+                            j,
+                            err
                         );
-                        if let Err(err) = ret {
-                            kerror!(
-                                "Ahci_{} ctrl = {}, port = {} failed to register, error code = {:?}",
-                                id,
-                                hba_mem_index as u8,
-                                j,
-                                err
-                            );
-                        }
                     }
                 }
             }
         }
     }
 
+    drop(disks_list);
+    LOCKED_HOST_LIST.lock()[hba_mem_index].port_errors = port_errors;
+    let port_wait = LOCKED_HOST_LIST.lock()[hba_mem_index].port_wait.clone();
+    match ahci_irq_init(dev, hba_mem, pi, port_wait) {
+        Ok(_) => {
+            let mut host_list = LOCKED_HOST_LIST.lock();
+            host_list[hba_mem_index].irq_ready = true;
+            host_list[hba_mem_index].poll_ports = 0;
+        }
+        Err(e) => kwarn!("ahci {}: failed to set up MSI, using polling: {:?}", bdf, e),
+    }
+
     compiler_fence(core::sync::atomic::Ordering::SeqCst);
     return Ok(());
 }
 
+/// AHCI控制器的MSI中断处理函数
+#[derive(Debug)]
+struct AhciIrqHandler {
+    /// 控制器寄存器的虚拟地址
+    hba_mem: VirtAddr,
+    /// 由这个中断向量负责的端口
+    ports: u32,
+    /// 控制器的每个端口的等待队列
+    port_wait: Arc<[WaitQueue; 32]>,
+}
+
+impl IrqHandler for AhciIrqHandler {
+    fn handle(&self, _irq: IrqNumber, _trap_frame: &mut TrapFrame) -> IrqReturn {
+        let hba_mem = unsafe { (self.hba_mem.data() as *mut HbaMem).as_mut().unwrap() };
+        let pending = hba_mem.is.read() & self.ports;
+        if pending == 0 {
+            return IrqReturn::NotHandled;
+        }
+        for j in 0..32 {
+            if pending & (1 << j) != 0 {
+                // 只清除表示命令完成的中断位，错误位留给等待命令完成的一方处理
+                let port = &mut hba_mem.ports[j];
+                port.is.write(port.is.read() & HBA_PORT_IE_COMPLETION);
+                self.port_wait[j].wakeup_all(None);
+            }
+        }
+        hba_mem.is.write(pending);
+        return IrqReturn::Handled;
+    }
+}
+
+/// 为控制器申请MSI中断。能够申请到足够的向量时，每个端口使用一个独立的向量，否则所有端口共用一个
+fn ahci_irq_init(
+    dev: &Arc<PciDevice>,
+    hba_mem: &mut HbaMem,
+    pi: u32,
+    port_wait: Arc<[WaitQueue; 32]>,
+) -> Result<(), SystemError> {
+    // 端口i的中断使用第i个向量，因此需要的向量数量取决于编号最大的端口
+    let want = (32 - pi.leading_zeros() as u16).next_power_of_two();
+    let flags = IRQ::PCI_IRQ_MSI | IRQ::PCI_IRQ_MSIX;
+    let per_port = want > 1 && dev.alloc_irq_vectors(want, want, flags).is_ok();
+    if !per_port {
+        dev.alloc_irq_vectors(1, 1, flags)?;
+    }
+
+    let hba_mem_vaddr = VirtAddr::new(hba_mem as *mut HbaMem as usize);
+    let nvec = if per_port { want } else { 1 };
+    for i in 0..nvec {
+        let ports = if per_port { pi & (1 << i) } else { pi };
+        let handler = Arc::new(AhciIrqHandler {
+            hba_mem: hba_mem_vaddr,
+            ports,
+            port_wait: port_wait.clone(),
+        });
+        if let Err(e) = dev.request_irq(
+            i,
+            &format!("ahci{}", i),
+            handler,
+            IrqHandleFlags::empty(),
+            0,
+        ) {
+            dev.free_irq_vectors();
+            return Err(e);
+        }
+    }
+
+    for j in 0..32 {
+        if pi & (1 << j) != 0 {
+            hba_mem.ports[j].ie.write(HBA_PORT_IE_COMPLETION);
+        }
+    }
+    hba_mem.ghc.set_bits(HBA_GHC_IE);
+    kdebug!(
+        "ahci {}: {} MSI vector(s) enabled",
+        dev.bus_device_function(),
+        nvec
+    );
+    return Ok(());
+}
+
+/// 驱动与控制器解除绑定时，释放控制器的中断
+pub fn ahci_remove(dev: &Arc<PciDevice>) {
+    dev.free_irq_vectors();
+}
+
+/// 让控制器停止工作：把控制器上的磁盘的数据写回，然后关闭控制器的中断
+///
+/// 关闭中断之后，等待命令完成时改为轮询，因此仍然可以读写磁盘
+pub fn ahci_suspend(dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+    let index = ahci_host_index(&dev.bus_device_function()).ok_or(SystemError::ENODEV)?;
+    for disk in disks() {
+        if disk.lock().ctrl_num as usize == index {
+            disk.sync()?;
+        }
+    }
+
+    let mut hba_mem_list = LOCKED_HBA_MEM_LIST.lock();
+    let hba_mem = &mut hba_mem_list[index];
+    LOCKED_HOST_LIST.lock()[index].irq_enabled_before_suspend =
+        hba_mem.ghc.read() & HBA_GHC_IE != 0;
+    hba_mem.ghc.clear_bits(HBA_GHC_IE);
+    return Ok(());
+}
+
+/// 让控制器恢复工作：重新打开睡眠之前打开了的中断
+pub fn ahci_resume(dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+    let index = ahci_host_index(&dev.bus_device_function()).ok_or(SystemError::ENODEV)?;
+    if LOCKED_HOST_LIST.lock()[index].irq_enabled_before_suspend {
+        LOCKED_HBA_MEM_LIST.lock()[index].ghc.set_bits(HBA_GHC_IE);
+    }
+    return Ok(());
+}
+
+/// 控制器的驱动使用的命令槽的数量
+fn ahci_queue_depth(ctrl_num: u8) -> u32 {
+    return LOCKED_HOST_LIST
+        .lock()
+        .get(ctrl_num as usize)
+        .map(|host| host.queue_depth)
+        .unwrap_or(1);
+}
+
+/// 端口等待命令完成的方式。控制器的中断被关闭时(例如睡眠期间)，使用中断的端口也改为轮询
+fn ahci_port_completion(ctrl_num: u8, port_num: u8) -> AhciCompletion {
+    let irq_enabled = LOCKED_HBA_MEM_LIST
+        .lock()
+        .get(ctrl_num as usize)
+        .map_or(false, |hba_mem| hba_mem.ghc.read() & HBA_GHC_IE != 0);
+    let poll = LOCKED_HOST_LIST
+        .lock()
+        .get(ctrl_num as usize)
+        .map_or(true, |host| host.poll_ports & (1 << port_num) != 0);
+    if poll || !irq_enabled {
+        return AhciCompletion::Poll;
+    }
+    return AhciCompletion::Irq;
+}
+
+/// 控制器的每个端口的等待队列
+fn ahci_port_wait_queues(ctrl_num: u8) -> Option<Arc<[WaitQueue; 32]>> {
+    return LOCKED_HOST_LIST
+        .lock()
+        .get(ctrl_num as usize)
+        .map(|host| host.port_wait.clone());
+}
+
+/// 轮询模式下端口最近的命令的平均完成时间(单位：纳秒)，还没有完成过命令时为0
+fn ahci_poll_mean_ns(ctrl_num: u8, port_num: u8) -> u64 {
+    return LOCKED_HOST_LIST
+        .lock()
+        .get(ctrl_num as usize)
+        .map_or(0, |host| host.poll_mean_ns[port_num as usize]);
+}
+
+/// 记录轮询模式下一个命令的完成时间，平均值中最近一次的权重为1/8
+fn ahci_poll_account(ctrl_num: u8, port_num: u8, ns: u64) {
+    if let Some(host) = LOCKED_HOST_LIST.lock().get_mut(ctrl_num as usize) {
+        let mean = &mut host.poll_mean_ns[port_num as usize];
+        *mean = if *mean == 0 {
+            ns
+        } else {
+            *mean - *mean / 8 + ns / 8
+        };
+    }
+}
+
+/// 获取控制器的每个已实现的端口等待命令完成的方式，每个端口一行，例如`0: irq`
+fn ahci_port_poll_status(bdf: &BusDeviceFunction) -> Result<String, SystemError> {
+    let index = ahci_host_index(bdf).ok_or(SystemError::ENODEV)?;
+    let pi = LOCKED_HBA_MEM_LIST.lock()[index].pi.read();
+    let poll_ports = LOCKED_HOST_LIST.lock()[index].poll_ports;
+    let mut s = String::new();
+    for j in 0..32 {
+        if pi & (1 << j) == 0 {
+            continue;
+        }
+        let mode = if poll_ports & (1 << j) != 0 {
+            "poll"
+        } else {
+            "irq"
+        };
+        s.push_str(&format!("{}: {}\n", j, mode));
+    }
+    return Ok(s);
+}
+
+/// 设置端口等待命令完成的方式。轮询的端口不再产生命令完成的中断
+///
+/// ## 错误
+///
+/// - `ENODEV`：控制器不存在
+/// - `EINVAL`：端口没有实现，或者控制器的中断没有设置好而不能使用中断
+fn ahci_set_port_completion(
+    bdf: &BusDeviceFunction,
+    port_num: usize,
+    mode: AhciCompletion,
+) -> Result<(), SystemError> {
+    let index = ahci_host_index(bdf).ok_or(SystemError::ENODEV)?;
+    let mut hba_mem_list = LOCKED_HBA_MEM_LIST.lock();
+    let hba_mem = &mut hba_mem_list[index];
+    if port_num >= 32 || hba_mem.pi.read() & (1 << port_num) == 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let mut host_list = LOCKED_HOST_LIST.lock();
+    let host = &mut host_list[index];
+    match mode {
+        AhciCompletion::Irq => {
+            if !host.irq_ready {
+                return Err(SystemError::EINVAL);
+            }
+            host.poll_ports &= !(1 << port_num);
+            hba_mem.ports[port_num].ie.write(HBA_PORT_IE_COMPLETION);
+        }
+        AhciCompletion::Poll => {
+            host.poll_ports |= 1 << port_num;
+            hba_mem.ports[port_num].ie.write(0);
+            // 唤醒已经在等待中断的进程，让它们改为轮询
+            host.port_wait[port_num].wakeup_all(None);
+        }
+    }
+    return Ok(());
+}
+
+/// 获取PCI地址对应的控制器在LOCKED_HBA_MEM_LIST中的下标
+fn ahci_host_index(bdf: &BusDeviceFunction) -> Option<usize> {
+    return LOCKED_HOST_LIST
+        .lock()
+        .iter()
+        .position(|host| host.bdf == *bdf);
+}
+
+/// 获取控制器的每个已实现的端口上接入的设备类型，每个端口一行，例如`0: sata`。
+/// 初始化失败的端口会附带失败的原因，例如`1: satapi (error: EIO)`
+fn ahci_port_status(bdf: &BusDeviceFunction) -> Result<String, SystemError> {
+    let index = ahci_host_index(bdf).ok_or(SystemError::ENODEV)?;
+    let port_errors = LOCKED_HOST_LIST.lock()[index].port_errors.clone();
+    let mut hba_mem_list = LOCKED_HBA_MEM_LIST.lock();
+    let hba_mem = &mut hba_mem_list[index];
+    let pi = hba_mem.pi.read();
+    let mut s = String::new();
+    for j in 0..32 {
+        if pi & (1 << j) == 0 {
+            continue;
+        }
+        let status = match hba_mem.ports[j].check_type() {
+            HbaPortType::None => "none".to_string(),
+            HbaPortType::SATA => "sata".to_string(),
+            HbaPortType::SATAPI => "satapi".to_string(),
+            HbaPortType::PM => "pm".to_string(),
+            HbaPortType::SEMB => "semb".to_string(),
+            HbaPortType::Unknown(sig) => format!("unknown({:#x})", sig),
+        };
+        match port_errors.iter().find(|(port, _)| *port == j) {
+            Some((_, e)) => s.push_str(&format!("{}: {} (error: {:?})\n", j, status, e)),
+            None => s.push_str(&format!("{}: {}\n", j, status)),
+        }
+    }
+    return Ok(s);
+}
+
+/// 设置控制器的驱动使用的命令槽的数量
+///
+/// ## 错误
+///
+/// - `ENODEV`：控制器不存在
+/// - `EINVAL`：数量为0，或者超过了控制器支持的命令槽的数量
+fn ahci_set_queue_depth(bdf: &BusDeviceFunction, depth: u32) -> Result<(), SystemError> {
+    let mut host_list = LOCKED_HOST_LIST.lock();
+    let host = host_list
+        .iter_mut()
+        .find(|host| host.bdf == *bdf)
+        .ok_or(SystemError::ENODEV)?;
+    if depth == 0 || depth > host.nr_slots {
+        return Err(SystemError::EINVAL);
+    }
+    host.queue_depth = depth;
+    return Ok(());
+}
+
+/// 获取控制器的驱动使用的命令槽的数量
+fn ahci_host_queue_depth(bdf: &BusDeviceFunction) -> Result<u32, SystemError> {
+    let index = ahci_host_index(bdf).ok_or(SystemError::ENODEV)?;
+    return Ok(ahci_queue_depth(index as u8));
+}
+
 /// @brief: 获取所有的 disk
 #[allow(dead_code)]
 pub fn disks() -> Vec<Arc<LockedAhciDisk>> {
@@ -167,7 +548,7 @@ pub fn get_disks_by_name(name: String) -> Result<Arc<LockedAhciDisk>, SystemErro
     let disks_list: SpinLockGuard<Vec<Arc<LockedAhciDisk>>> = LOCKED_DISKS_LIST.lock();
     let result = disks_list
         .iter()
-        .find(|x| x.0.lock().name == name)
+        .find(|x| x.lock().name == name)
         .ok_or(SystemError::ENXIO)?
         .clone();
     return Ok(result);
@@ -181,29 +562,17 @@ fn _port(ctrl_num: u8, port_num: u8) -> &'static mut HbaPort {
     return unsafe { (port as *const HbaPort as *mut HbaPort).as_mut().unwrap() };
 }
 
-/// @brief: 测试函数
-pub fn __test_ahci() {
-    let _res = ahci_init();
-    let disk: Arc<LockedAhciDisk> = get_disks_by_name("ahci_disk_0".to_string()).unwrap();
-    #[deny(overflowing_literals)]
-    let mut buf = [0u8; 3000usize];
-
-    for i in 0..2000 {
-        buf[i] = i as u8;
-    }
-
-    let _dd = disk;
-
-    // 测试1, 写两个块,读4个块
-    // _dd.write_at(123, 2, &buf).unwrap();
-    let mut read_buf = [0u8; 3000usize];
-    _dd.read_at(122, 4, &mut read_buf).unwrap();
+/// 读取第一个磁盘开头的几个扇区：一次读取多个扇区的结果应当与逐个扇区读取的结果相同
+fn ahci_read() -> Result<(), SystemError> {
+    let disk = get_disks_by_name("ahci_disk_0".to_string())?;
+    let mut buf = vec![0u8; 4 * LBA_SIZE];
+    ktest_assert!(disk.read_at(0, 4, &mut buf)? == 4 * LBA_SIZE);
 
-    // 测试2, 只读写一个字节
-    for i in 0..512 {
-        buf[i] = 233;
+    let mut sector = [0u8; LBA_SIZE];
+    for i in 0..4 {
+        ktest_assert!(disk.read_at(i, 1, &mut sector)? == LBA_SIZE);
+        ktest_assert!(sector[..] == buf[i * LBA_SIZE..(i + 1) * LBA_SIZE]);
     }
-    // _dd.write_at(123, 2, &buf).unwrap();
-    let mut read_buf2 = [0u8; 3000usize];
-    _dd.read_at(122, 4, &mut read_buf2).unwrap();
+    return Ok(());
 }
+kernel_test!(ahci_read, requires = ["ahci"]);
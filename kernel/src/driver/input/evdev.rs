@@ -0,0 +1,330 @@
+//! 通用的输入事件接口(evdev)
+//!
+//! 每个输入设备对应一个`/dev/input/eventN`，读取得到与Linux的`struct input_event`格式一致的事件。
+//! 每个设备有一个事件队列，队列满时清空队列，并放入SYN_DROPPED事件通知应用程序丢失了事件。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/evdev.c
+
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::VecDeque,
+    format,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::class::{class_manager, input_class},
+    filesystem::{
+        devfs::{DevFS, DeviceINode},
+        vfs::{
+            core::generate_inode_id,
+            file::{FileMode, FilePrivateData},
+            ioctl::IoctlArg,
+            syscall::ModeType,
+            FileType, IndexNode, Metadata, PollStatus, PollTable,
+        },
+    },
+    kerror,
+    libs::{rwlock::RwLock, spinlock::SpinLock, wait_queue::WaitQueue},
+    process::ProcessManager,
+    syscall::SystemError,
+    time::{
+        hrtimer::{ktime_get, Ktime},
+        timekeeping::getnstimeofday,
+    },
+};
+
+use super::{
+    input_file_nonblock, input_register_handler, InputDevice, InputFilePrivateData, InputHandle,
+    InputHandler, EV_SYN, SYN_DROPPED,
+};
+
+/// 输入设备的主设备号
+const INPUT_MAJOR: usize = 13;
+/// evdev的第一个次设备号
+const EVDEV_MINOR_BASE: usize = 64;
+/// 每个设备的事件队列能容纳的事件数量
+const EVDEV_BUF_SIZE: usize = 64;
+/// evdev协议的版本
+const EV_VERSION: i32 = 0x010001;
+
+// ioctl命令的编码
+// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/input.h
+const EVIOC_TYPE: u32 = 'E' as u32;
+const EVIOCGVERSION: u32 = 0x01;
+const EVIOCGID: u32 = 0x02;
+const EVIOCGNAME: u32 = 0x06;
+const EVIOCGKEY: u32 = 0x18;
+/// EVIOCGBIT(ev, len)的命令号为EVIOCGBIT_BASE + ev
+const EVIOCGBIT_BASE: u32 = 0x20;
+/// EVIOCGABS(abs)的命令号为EVIOCGABS_BASE + abs
+const EVIOCGABS_BASE: u32 = 0x40;
+
+/// @brief 读取evdev设备得到的事件，与Linux的struct input_event(64位)的内存布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputEvent {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+    pub type_: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+/// 队列中的事件，记录的是单调时间，读取时再转换为墙上时间
+#[derive(Debug, Clone, Copy)]
+struct EvdevEvent {
+    ev_type: u16,
+    code: u16,
+    value: i32,
+    time: Ktime,
+}
+
+/// @brief 一个输入设备对应的evdev设备文件
+#[derive(Debug)]
+pub struct EvdevInode {
+    dev: Arc<InputDevice>,
+    /// 尚未被读取的事件
+    buffer: SpinLock<VecDeque<EvdevEvent>>,
+    /// 等待事件的读者
+    wait_queue: WaitQueue,
+    /// 指向inode所在的文件系统对象的指针
+    fs: RwLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl EvdevInode {
+    fn new(dev: Arc<InputDevice>, minor: usize) -> Arc<Self> {
+        let mut metadata = Metadata::default();
+        metadata.inode_id = generate_inode_id();
+        metadata.file_type = FileType::CharDevice;
+        metadata.mode = ModeType::from_bits_truncate(0o660);
+        metadata.nlinks = 1;
+        metadata.raw_dev = (INPUT_MAJOR << 8) | (EVDEV_MINOR_BASE + minor);
+        return Arc::new(Self {
+            dev,
+            buffer: SpinLock::new(VecDeque::with_capacity(EVDEV_BUF_SIZE)),
+            wait_queue: WaitQueue::INIT,
+            fs: RwLock::new(Weak::default()),
+            metadata,
+        });
+    }
+}
+
+impl InputHandle for EvdevInode {
+    fn event(&self, ev_type: u16, code: u16, value: i32, time: Ktime) {
+        let mut buffer = self.buffer.lock_irqsave();
+        if buffer.len() >= EVDEV_BUF_SIZE {
+            // 与Linux一致，队列满时丢弃所有未读取的事件，通知读者重新同步设备的状态
+            buffer.clear();
+            buffer.push_back(EvdevEvent {
+                ev_type: EV_SYN,
+                code: SYN_DROPPED,
+                value: 0,
+                time,
+            });
+        }
+        buffer.push_back(EvdevEvent {
+            ev_type,
+            code,
+            value,
+            time,
+        });
+        drop(buffer);
+        // 一组事件上报完毕之后才唤醒读者
+        if ev_type == EV_SYN {
+            self.wait_queue.wakeup_all(None);
+        }
+    }
+}
+
+impl DeviceINode for EvdevInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.write() = fs;
+    }
+}
+
+impl IndexNode for EvdevInode {
+    fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        *data = FilePrivateData::Input(InputFilePrivateData { mode: *mode });
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    /// @brief 读取事件，每次读取整数个事件。没有事件时，阻塞直到设备上报了一组事件
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        const EVENT_SIZE: usize = size_of::<InputEvent>();
+        let len = core::cmp::min(len, buf.len());
+        if len < EVENT_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+
+        let events: Vec<EvdevEvent> = loop {
+            let mut buffer = self.buffer.lock_irqsave();
+            if !buffer.is_empty() {
+                let n = core::cmp::min(buffer.len(), len / EVENT_SIZE);
+                break buffer.drain(..n).collect();
+            }
+            if input_file_nonblock(data) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if ProcessManager::current_pcb()
+                .sig_info()
+                .has_pending_signal()
+            {
+                return Err(SystemError::EINTR);
+            }
+            self.wait_queue.sleep_unlock_spinlock(buffer);
+        };
+
+        // 事件的时间戳为墙上时间
+        let now = getnstimeofday();
+        let offset = now.tv_sec * 1000000000 + now.tv_nsec - ktime_get() as i64;
+        for (i, ev) in events.iter().enumerate() {
+            let ns = ev.time as i64 + offset;
+            let event = InputEvent {
+                tv_sec: ns / 1000000000,
+                tv_usec: ns % 1000000000 / 1000,
+                type_: ev.ev_type,
+                code: ev.code,
+                value: ev.value,
+            };
+            let bytes = unsafe {
+                core::slice::from_raw_parts(&event as *const InputEvent as *const u8, EVENT_SIZE)
+            };
+            buf[i * EVENT_SIZE..(i + 1) * EVENT_SIZE].copy_from_slice(bytes);
+        }
+        return Ok(events.len() * EVENT_SIZE);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    fn poll(&self, table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        table.wait(&self.wait_queue);
+        if self.buffer.lock_irqsave().is_empty() {
+            return Ok(PollStatus::empty());
+        }
+        return Ok(PollStatus::READ);
+    }
+
+    /// @brief 获取设备的信息：名字、标识、支持的事件、按键的状态、绝对坐标轴的范围
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/evdev.c#evdev_do_ioctl
+    fn ioctl(&self, cmd: u32, data: usize) -> Result<usize, SystemError> {
+        let arg = IoctlArg::new(cmd, data);
+        if arg.ty() != EVIOC_TYPE {
+            return Err(SystemError::ENOTTY);
+        }
+
+        match arg.nr() {
+            EVIOCGVERSION => {
+                arg.copy_out(&EV_VERSION)?;
+                return Ok(0);
+            }
+            EVIOCGID => {
+                arg.copy_out(&self.dev.id())?;
+                return Ok(0);
+            }
+            EVIOCGNAME => {
+                // 名字以'\0'结尾
+                let mut name = Vec::from(self.dev.name().as_bytes());
+                name.push(0);
+                return arg.copy_out_bytes(&name);
+            }
+            EVIOCGKEY => {
+                return arg.copy_out_bytes(&self.dev.key_state().to_bytes());
+            }
+            nr if (EVIOCGBIT_BASE..EVIOCGBIT_BASE + 0x20).contains(&nr) => {
+                let bits = self
+                    .dev
+                    .capability_bits((nr - EVIOCGBIT_BASE) as u16)
+                    .map(|bits| bits.to_bytes())
+                    .unwrap_or_default();
+                // 不支持的事件类型返回空的位图
+                return arg.copy_out_bytes(&bits);
+            }
+            nr if (EVIOCGABS_BASE..EVIOCGABS_BASE + 0x40).contains(&nr) => {
+                let info = self
+                    .dev
+                    .abs_info((nr - EVIOCGABS_BASE) as u16)
+                    .ok_or(SystemError::EINVAL)?;
+                arg.copy_out(&info)?;
+                return Ok(0);
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn crate::filesystem::vfs::FileSystem> {
+        return self.fs.read().upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<alloc::string::String>, SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+}
+
+/// @brief evdev处理者，为每个输入设备创建一个/dev/input/eventN
+#[derive(Debug)]
+struct EvdevHandler {
+    /// 下一个设备的编号
+    next_minor: AtomicUsize,
+}
+
+impl InputHandler for EvdevHandler {
+    fn connect(&self, dev: &Arc<InputDevice>) -> Option<Arc<dyn InputHandle>> {
+        let minor = self.next_minor.fetch_add(1, Ordering::SeqCst);
+        let inode = EvdevInode::new(dev.clone(), minor);
+        if let Err(e) = class_manager().device_create_with_inode(
+            input_class(),
+            None,
+            format!("event{}", minor),
+            inode.clone(),
+        ) {
+            kerror!(
+                "evdev: failed to register device for {}: {:?}",
+                dev.name(),
+                e
+            );
+            return None;
+        }
+        return Some(inode);
+    }
+}
+
+/// @brief 注册evdev处理者
+pub fn evdev_init() {
+    input_register_handler(Arc::new(EvdevHandler {
+        next_minor: AtomicUsize::new(0),
+    }));
+}
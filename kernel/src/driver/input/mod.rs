@@ -0,0 +1,401 @@
+//! 输入子系统
+//!
+//! 键盘、鼠标等输入设备的驱动通过[`InputDevice`]上报输入事件(按键、相对位移、绝对坐标)，
+//! 输入子系统把事件分发给所有连接到这个设备的处理者([`InputHandler`])。例如：
+//! - evdev为每个设备创建`/dev/input/eventN`，应用程序以Linux的`struct input_event`格式读取事件
+//! - mousedev把所有鼠标的事件合并到`/dev/input/mice`，以PS/2鼠标数据包的格式读取
+//! - 虚拟终端把键盘的扫描码转换为字符，送到前台的tty
+//!
+//! 驱动只需要上报事件，不需要关心事件最终被谁使用。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/input.c
+
+pub mod evdev;
+pub mod mousedev;
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::fmt::Debug;
+
+use crate::{
+    filesystem::vfs::file::{FileMode, FilePrivateData},
+    kinfo,
+    libs::{rwlock::RwLock, spinlock::SpinLock},
+    time::hrtimer::{ktime_get, Ktime},
+};
+
+// 事件的类型与编码，与Linux保持一致
+// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/input-event-codes.h
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+pub const EV_MSC: u16 = 0x04;
+pub const EV_MAX: u16 = 0x1f;
+
+pub const SYN_REPORT: u16 = 0;
+pub const SYN_DROPPED: u16 = 3;
+
+/// 设备产生的原始数据，如PS/2键盘的每个扫描码字节
+pub const MSC_RAW: u16 = 0x03;
+/// 按键对应的扫描码
+pub const MSC_SCAN: u16 = 0x04;
+pub const MSC_MAX: u16 = 0x07;
+
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_HWHEEL: u16 = 0x06;
+pub const REL_WHEEL: u16 = 0x08;
+pub const REL_MAX: u16 = 0x0f;
+
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+pub const ABS_MAX: u16 = 0x3f;
+
+pub const KEY_A: u16 = 30;
+
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+pub const BTN_SIDE: u16 = 0x113;
+pub const BTN_EXTRA: u16 = 0x114;
+pub const KEY_MAX: u16 = 0x2ff;
+
+/// 设备所在的总线类型
+pub const BUS_USB: u16 = 0x03;
+pub const BUS_I8042: u16 = 0x11;
+
+/// @brief 输入设备的标识，与Linux的struct input_id的内存布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+/// @brief 绝对坐标轴的取值范围和当前值，与Linux的struct input_absinfo的内存布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputAbsInfo {
+    pub value: i32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+/// @brief 位图，用于记录设备支持的事件以及按键的状态
+#[derive(Debug, Clone)]
+pub struct InputBitmap {
+    bits: Vec<u64>,
+    /// 位的数量
+    len: usize,
+}
+
+impl InputBitmap {
+    pub fn new(len: usize) -> Self {
+        Self {
+            bits: vec![0; (len + 63) / 64],
+            len,
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, bit: u16) {
+        if (bit as usize) < self.len {
+            self.bits[bit as usize / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    #[inline]
+    pub fn clear(&mut self, bit: u16) {
+        if (bit as usize) < self.len {
+            self.bits[bit as usize / 64] &= !(1 << (bit % 64));
+        }
+    }
+
+    #[inline]
+    pub fn test(&self, bit: u16) -> bool {
+        if (bit as usize) >= self.len {
+            return false;
+        }
+        return self.bits[bit as usize / 64] & (1 << (bit % 64)) != 0;
+    }
+
+    /// @brief 以字节数组的形式导出位图，与Linux的EVIOCGBIT等ioctl的格式一致(小端序)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.bits.iter().flat_map(|w| w.to_le_bytes()).collect();
+        bytes.truncate((self.len + 7) / 8);
+        return bytes;
+    }
+}
+
+/// @brief 输入设备文件的私有信息
+#[derive(Debug, Clone)]
+pub struct InputFilePrivateData {
+    /// 打开文件的模式，用于判断是否为非阻塞读取
+    pub mode: FileMode,
+}
+
+/// @brief 判断输入设备文件是否以非阻塞的方式打开
+pub fn input_file_nonblock(data: &FilePrivateData) -> bool {
+    if let FilePrivateData::Input(p) = data {
+        return p.mode.contains(FileMode::O_NONBLOCK);
+    }
+    return false;
+}
+
+/// @brief 处理者与一个输入设备之间的连接，设备上报的事件通过它送到处理者
+pub trait InputHandle: Send + Sync + Debug {
+    /// @brief 处理设备上报的一个事件。可能在中断上下文中被调用，不能睡眠
+    ///
+    /// @param ev_type 事件的类型
+    /// @param code 事件的编码
+    /// @param value 事件的值
+    /// @param time 事件产生的时间(单调时间, ns)
+    fn event(&self, ev_type: u16, code: u16, value: i32, time: Ktime);
+}
+
+/// @brief 输入事件的处理者，如evdev、虚拟终端
+pub trait InputHandler: Send + Sync + Debug {
+    /// @brief 尝试连接一个输入设备
+    ///
+    /// @return 处理者不关心这个设备时返回None
+    fn connect(&self, dev: &Arc<InputDevice>) -> Option<Arc<dyn InputHandle>>;
+}
+
+/// @brief 输入设备
+///
+/// 驱动先通过[`InputDevice::new`]创建设备，用[`InputDevice::set_capability`]声明设备能产生的事件，
+/// 然后调用[`input_register_device`]注册，之后通过[`InputDevice::event`]等方法上报事件。
+#[derive(Debug)]
+pub struct InputDevice {
+    name: String,
+    id: InputId,
+    /// 设备支持的事件类型
+    evbit: InputBitmap,
+    keybit: InputBitmap,
+    relbit: InputBitmap,
+    absbit: InputBitmap,
+    mscbit: InputBitmap,
+    /// 绝对坐标轴的信息
+    absinfo: SpinLock<Vec<InputAbsInfo>>,
+    /// 当前被按下的按键
+    key: SpinLock<InputBitmap>,
+    /// 连接到这个设备的处理者
+    handles: RwLock<Vec<Arc<dyn InputHandle>>>,
+}
+
+impl InputDevice {
+    pub fn new(name: &str, id: InputId) -> Self {
+        let mut evbit = InputBitmap::new(EV_MAX as usize + 1);
+        // 所有设备都会产生同步事件
+        evbit.set(EV_SYN);
+        Self {
+            name: String::from(name),
+            id,
+            evbit,
+            keybit: InputBitmap::new(KEY_MAX as usize + 1),
+            relbit: InputBitmap::new(REL_MAX as usize + 1),
+            absbit: InputBitmap::new(ABS_MAX as usize + 1),
+            mscbit: InputBitmap::new(MSC_MAX as usize + 1),
+            absinfo: SpinLock::new(vec![InputAbsInfo::default(); ABS_MAX as usize + 1]),
+            key: SpinLock::new(InputBitmap::new(KEY_MAX as usize + 1)),
+            handles: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// @brief 声明设备能够产生某个事件，需要在注册设备之前调用
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/input.c#input_set_capability
+    pub fn set_capability(&mut self, ev_type: u16, code: u16) {
+        match ev_type {
+            EV_KEY => self.keybit.set(code),
+            EV_REL => self.relbit.set(code),
+            EV_ABS => self.absbit.set(code),
+            EV_MSC => self.mscbit.set(code),
+            _ => {}
+        }
+        self.evbit.set(ev_type);
+    }
+
+    /// @brief 声明设备的绝对坐标轴，以及它的取值范围
+    pub fn set_abs_params(&mut self, axis: u16, minimum: i32, maximum: i32, fuzz: i32, flat: i32) {
+        if axis > ABS_MAX {
+            return;
+        }
+        self.set_capability(EV_ABS, axis);
+        let mut absinfo = self.absinfo.lock();
+        let info = &mut absinfo[axis as usize];
+        info.minimum = minimum;
+        info.maximum = maximum;
+        info.fuzz = fuzz;
+        info.flat = flat;
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn id(&self) -> InputId {
+        self.id
+    }
+
+    /// @brief 获取设备支持的某一类事件的位图。ev_type为0时获取支持的事件类型
+    pub fn capability_bits(&self, ev_type: u16) -> Option<&InputBitmap> {
+        match ev_type {
+            0 => Some(&self.evbit),
+            EV_KEY => Some(&self.keybit),
+            EV_REL => Some(&self.relbit),
+            EV_ABS => Some(&self.absbit),
+            EV_MSC => Some(&self.mscbit),
+            _ => None,
+        }
+    }
+
+    /// @brief 获取当前被按下的按键
+    pub fn key_state(&self) -> InputBitmap {
+        self.key.lock_irqsave().clone()
+    }
+
+    /// @brief 获取绝对坐标轴的信息
+    pub fn abs_info(&self, axis: u16) -> Option<InputAbsInfo> {
+        if !self.absbit.test(axis) {
+            return None;
+        }
+        return Some(self.absinfo.lock_irqsave()[axis as usize]);
+    }
+
+    /// @brief 上报一个事件，可以在中断上下文中调用
+    ///
+    /// 与Linux一致，设备没有声明的事件、没有变化的按键状态和坐标会被丢弃；
+    /// 已经按下的按键再次被按下时，作为自动重复(值为2)上报
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/input.c#input_handle_event
+    pub fn event(&self, ev_type: u16, code: u16, value: i32) {
+        if !self.evbit.test(ev_type) {
+            return;
+        }
+        let mut value = value;
+        match ev_type {
+            EV_SYN => {}
+            EV_KEY => {
+                if !self.keybit.test(code) {
+                    return;
+                }
+                let mut key = self.key.lock_irqsave();
+                if value != 0 {
+                    if key.test(code) {
+                        value = 2;
+                    } else {
+                        key.set(code);
+                        value = 1;
+                    }
+                } else {
+                    if !key.test(code) {
+                        return;
+                    }
+                    key.clear(code);
+                }
+            }
+            EV_REL => {
+                if !self.relbit.test(code) || value == 0 {
+                    return;
+                }
+            }
+            EV_ABS => {
+                if !self.absbit.test(code) {
+                    return;
+                }
+                let mut absinfo = self.absinfo.lock_irqsave();
+                if absinfo[code as usize].value == value {
+                    return;
+                }
+                absinfo[code as usize].value = value;
+            }
+            EV_MSC => {
+                if !self.mscbit.test(code) {
+                    return;
+                }
+            }
+            _ => return,
+        }
+
+        let time = ktime_get();
+        for handle in self.handles.read().iter() {
+            handle.event(ev_type, code, value, time);
+        }
+    }
+
+    /// @brief 上报按键被按下或者松开
+    #[inline]
+    pub fn report_key(&self, code: u16, pressed: bool) {
+        self.event(EV_KEY, code, pressed as i32);
+    }
+
+    /// @brief 上报相对位移
+    #[inline]
+    pub fn report_rel(&self, code: u16, value: i32) {
+        self.event(EV_REL, code, value);
+    }
+
+    /// @brief 上报绝对坐标
+    #[inline]
+    pub fn report_abs(&self, code: u16, value: i32) {
+        self.event(EV_ABS, code, value);
+    }
+
+    /// @brief 一组事件上报完毕，应用程序以同步事件为界读取一组完整的事件
+    #[inline]
+    pub fn sync(&self) {
+        self.event(EV_SYN, SYN_REPORT, 0);
+    }
+}
+
+lazy_static! {
+    /// 已经注册的输入设备
+    static ref INPUT_DEVICES: RwLock<Vec<Arc<InputDevice>>> = RwLock::new(Vec::new());
+    /// 已经注册的处理者
+    static ref INPUT_HANDLERS: RwLock<Vec<Arc<dyn InputHandler>>> = RwLock::new(Vec::new());
+}
+
+/// 尝试把处理者连接到设备上
+fn input_attach_handler(handler: &Arc<dyn InputHandler>, dev: &Arc<InputDevice>) {
+    if let Some(handle) = handler.connect(dev) {
+        // 中断上下文中会读取handles，因此需要关中断
+        dev.handles.write_irqsave().push(handle);
+    }
+}
+
+/// @brief 注册输入设备，并连接到所有已经注册的处理者
+///
+/// @return 注册之后的设备，驱动通过它上报事件
+pub fn input_register_device(dev: InputDevice) -> Arc<InputDevice> {
+    let dev = Arc::new(dev);
+    // 先获取设备列表的锁，再获取处理者列表的锁，与input_register_handler的顺序一致
+    let mut devices = INPUT_DEVICES.write();
+    for handler in INPUT_HANDLERS.read().iter() {
+        input_attach_handler(handler, &dev);
+    }
+    devices.push(dev.clone());
+    kinfo!("input: {} registered", dev.name());
+    return dev;
+}
+
+/// @brief 注册输入事件的处理者，并连接到所有已经注册的设备
+pub fn input_register_handler(handler: Arc<dyn InputHandler>) {
+    let devices = INPUT_DEVICES.write();
+    let mut handlers = INPUT_HANDLERS.write();
+    for dev in devices.iter() {
+        input_attach_handler(&handler, dev);
+    }
+    handlers.push(handler);
+}
+
+/// @brief 初始化输入子系统，注册内置的处理者
+pub fn input_init() {
+    evdev::evdev_init();
+    mousedev::mousedev_init();
+}
@@ -0,0 +1,417 @@
+//! 鼠标设备接口(mousedev)
+//!
+//! 把所有鼠标上报的事件合并到`/dev/input/mice`，应用程序以PS/2鼠标数据包的格式读取。
+//! 与Linux一致，默认使用3字节的PS/2协议；应用程序可以像对待真实的鼠标一样，
+//! 写入设置采样率的"魔法序列"切换到带滚轮的ImPS/2协议或者ExplorerPS/2协议。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/mousedev.c
+
+use alloc::{
+    collections::VecDeque,
+    string::ToString,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::class::{class_manager, input_class},
+    filesystem::{
+        devfs::{DevFS, DeviceINode},
+        vfs::{
+            core::generate_inode_id,
+            file::{FileMode, FilePrivateData},
+            syscall::ModeType,
+            FileType, IndexNode, Metadata, PollStatus, PollTable,
+        },
+    },
+    kerror,
+    libs::{rwlock::RwLock, spinlock::SpinLock, wait_queue::WaitQueue},
+    process::ProcessManager,
+    syscall::SystemError,
+    time::hrtimer::Ktime,
+};
+
+use super::{
+    input_file_nonblock, input_register_handler, InputDevice, InputFilePrivateData, InputHandle,
+    InputHandler, BTN_EXTRA, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BTN_SIDE, EV_KEY, EV_REL, EV_SYN,
+    REL_WHEEL, REL_X, REL_Y, SYN_REPORT,
+};
+
+/// 输入设备的主设备号
+const INPUT_MAJOR: usize = 13;
+/// /dev/input/mice的次设备号
+const MOUSEDEV_MIXDEV_MINOR: usize = 63;
+
+/// 切换到ImPS/2协议的序列：把采样率依次设置为200、100、80
+const MOUSEDEV_IMPS_SEQ: [u8; 6] = [0xf3, 200, 0xf3, 100, 0xf3, 80];
+/// 切换到ExplorerPS/2协议的序列：把采样率依次设置为200、200、80
+const MOUSEDEV_IMEX_SEQ: [u8; 6] = [0xf3, 200, 0xf3, 200, 0xf3, 80];
+
+/// 模拟的鼠标协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MousedevEmul {
+    /// 3字节，3个按键
+    Ps2,
+    /// 4字节，第4字节为滚轮
+    Imps,
+    /// 4字节，第4字节为滚轮以及第4、5键
+    Exps,
+}
+
+/// 一段时间内累计的位移与按键状态
+#[derive(Debug, Clone, Copy, Default)]
+struct MousedevMotion {
+    dx: i32,
+    dy: i32,
+    dz: i32,
+    /// 按键状态，第0~4位依次为左键、右键、中键、第4键、第5键
+    buttons: u8,
+}
+
+#[derive(Debug)]
+struct MousedevState {
+    /// 尚未以同步事件结束的一组事件
+    frame: MousedevMotion,
+    /// 已经同步、尚未被读取的位移，以及当前的按键状态
+    packet: MousedevMotion,
+    /// 上一次被读取的数据包中的按键状态
+    last_buttons: u8,
+    mode: MousedevEmul,
+    /// 对应用程序写入的命令的应答，先于数据包被读取
+    response: VecDeque<u8>,
+    /// 魔法序列中已经匹配的长度
+    imps_seq: usize,
+    imex_seq: usize,
+    /// 上一个写入的命令，用于区分命令的参数
+    last_cmd: u8,
+}
+
+impl MousedevState {
+    /// 是否有数据包可以被读取
+    fn packet_ready(&self) -> bool {
+        let p = &self.packet;
+        return p.dx != 0 || p.dy != 0 || p.dz != 0 || p.buttons != self.last_buttons;
+    }
+
+    fn readable(&self) -> bool {
+        return !self.response.is_empty() || self.packet_ready();
+    }
+
+    /// @brief 按照当前的协议生成一个数据包，超出一个数据包表示范围的位移留给下一个数据包
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/mousedev.c#mousedev_packet
+    fn make_packet(&mut self) -> Vec<u8> {
+        let p = &mut self.packet;
+        let dx = p.dx.clamp(-127, 127);
+        let dy = p.dy.clamp(-127, 127);
+        p.dx -= dx;
+        p.dy -= dy;
+
+        let mut data = Vec::with_capacity(4);
+        data.push(0x08 | ((dx < 0) as u8) << 4 | ((dy < 0) as u8) << 5 | (p.buttons & 0x07));
+        data.push(dx as u8);
+        data.push(dy as u8);
+        match self.mode {
+            MousedevEmul::Ps2 => {
+                // PS/2协议不能表示滚轮，丢弃滚轮的位移
+                p.dz = 0;
+            }
+            MousedevEmul::Imps => {
+                let dz = p.dz.clamp(-127, 127);
+                p.dz -= dz;
+                data.push(dz as u8);
+            }
+            MousedevEmul::Exps => {
+                let dz = p.dz.clamp(-7, 7);
+                p.dz -= dz;
+                data.push((dz as u8 & 0x0f) | ((p.buttons & 0x18) << 1));
+            }
+        }
+        self.last_buttons = p.buttons;
+        return data;
+    }
+
+    /// @brief 处理应用程序写入的一个字节，模拟真实鼠标的应答
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/mousedev.c#mousedev_write
+    fn write_byte(&mut self, c: u8) {
+        self.imps_seq = Self::match_seq(&MOUSEDEV_IMPS_SEQ, self.imps_seq, c);
+        if self.imps_seq == MOUSEDEV_IMPS_SEQ.len() {
+            self.imps_seq = 0;
+            self.mode = MousedevEmul::Imps;
+        }
+        self.imex_seq = Self::match_seq(&MOUSEDEV_IMEX_SEQ, self.imex_seq, c);
+        if self.imex_seq == MOUSEDEV_IMEX_SEQ.len() {
+            self.imex_seq = 0;
+            self.mode = MousedevEmul::Exps;
+        }
+
+        // 设置采样率的参数不是命令
+        let is_param = self.last_cmd == 0xf3;
+        self.last_cmd = if is_param { 0 } else { c };
+
+        self.response.clear();
+        self.response.push_back(0xfa);
+        if is_param {
+            return;
+        }
+        match c {
+            // 读取数据包
+            0xeb => {
+                let packet = self.make_packet();
+                self.response.extend(packet);
+            }
+            // 读取ID
+            0xf2 => {
+                let id = match self.mode {
+                    MousedevEmul::Ps2 => 0,
+                    MousedevEmul::Imps => 3,
+                    MousedevEmul::Exps => 4,
+                };
+                self.response.push_back(id);
+            }
+            // 读取状态：流模式、分辨率4点/mm、采样率100
+            0xe9 => self.response.extend([0x00, 0x02, 100]),
+            // 复位
+            0xff => {
+                self.mode = MousedevEmul::Ps2;
+                self.imps_seq = 0;
+                self.imex_seq = 0;
+                self.response.extend([0xaa, 0x00]);
+            }
+            _ => {}
+        }
+    }
+
+    /// 匹配魔法序列的下一个字节，返回新的匹配长度
+    fn match_seq(seq: &[u8], matched: usize, c: u8) -> usize {
+        if seq[matched] == c {
+            return matched + 1;
+        }
+        if seq[0] == c {
+            return 1;
+        }
+        return 0;
+    }
+}
+
+/// @brief /dev/input/mice，合并了所有鼠标的事件
+#[derive(Debug)]
+pub struct MousedevInode {
+    state: SpinLock<MousedevState>,
+    /// 等待数据包的读者
+    wait_queue: WaitQueue,
+    /// 指向inode所在的文件系统对象的指针
+    fs: RwLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl MousedevInode {
+    fn new() -> Arc<Self> {
+        let mut metadata = Metadata::default();
+        metadata.inode_id = generate_inode_id();
+        metadata.file_type = FileType::CharDevice;
+        metadata.mode = ModeType::from_bits_truncate(0o660);
+        metadata.nlinks = 1;
+        metadata.raw_dev = (INPUT_MAJOR << 8) | MOUSEDEV_MIXDEV_MINOR;
+        return Arc::new(Self {
+            state: SpinLock::new(MousedevState {
+                frame: MousedevMotion::default(),
+                packet: MousedevMotion::default(),
+                last_buttons: 0,
+                mode: MousedevEmul::Ps2,
+                response: VecDeque::new(),
+                imps_seq: 0,
+                imex_seq: 0,
+                last_cmd: 0,
+            }),
+            wait_queue: WaitQueue::INIT,
+            fs: RwLock::new(Weak::default()),
+            metadata,
+        });
+    }
+
+    /// 按键的编码在按键状态中对应的位
+    fn button_bit(code: u16) -> Option<u8> {
+        match code {
+            BTN_LEFT => Some(0x01),
+            BTN_RIGHT => Some(0x02),
+            BTN_MIDDLE => Some(0x04),
+            BTN_SIDE => Some(0x08),
+            BTN_EXTRA => Some(0x10),
+            _ => None,
+        }
+    }
+}
+
+impl InputHandle for MousedevInode {
+    fn event(&self, ev_type: u16, code: u16, value: i32, _time: Ktime) {
+        let mut state = self.state.lock_irqsave();
+        let frame = &mut state.frame;
+        match (ev_type, code) {
+            (EV_REL, REL_X) => frame.dx += value,
+            // PS/2协议中y轴向上为正
+            (EV_REL, REL_Y) => frame.dy -= value,
+            (EV_REL, REL_WHEEL) => frame.dz -= value,
+            (EV_KEY, code) => {
+                if let Some(bit) = Self::button_bit(code) {
+                    if value != 0 {
+                        frame.buttons |= bit;
+                    } else {
+                        frame.buttons &= !bit;
+                    }
+                }
+            }
+            (EV_SYN, SYN_REPORT) => {
+                let frame = core::mem::take(&mut state.frame);
+                // 按键状态需要在下一组事件中保留
+                state.frame.buttons = frame.buttons;
+                let packet = &mut state.packet;
+                packet.dx += frame.dx;
+                packet.dy += frame.dy;
+                packet.dz += frame.dz;
+                packet.buttons = frame.buttons;
+                let ready = state.packet_ready();
+                drop(state);
+                if ready {
+                    self.wait_queue.wakeup_all(None);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl DeviceINode for MousedevInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.write() = fs;
+    }
+}
+
+impl IndexNode for MousedevInode {
+    fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        *data = FilePrivateData::Input(InputFilePrivateData { mode: *mode });
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    /// @brief 读取命令的应答或者鼠标数据包。没有数据时，阻塞直到鼠标移动或者按键状态变化
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let mut state = loop {
+            let state = self.state.lock_irqsave();
+            if state.readable() {
+                break state;
+            }
+            if input_file_nonblock(data) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if ProcessManager::current_pcb()
+                .sig_info()
+                .has_pending_signal()
+            {
+                return Err(SystemError::EINTR);
+            }
+            self.wait_queue.sleep_unlock_spinlock(state);
+        };
+
+        if state.response.is_empty() {
+            let packet = state.make_packet();
+            state.response.extend(packet);
+        }
+        // 一次读取不完的部分留给下一次读取
+        let n = core::cmp::min(len, state.response.len());
+        for (dst, src) in buf.iter_mut().zip(state.response.drain(..n)) {
+            *dst = src;
+        }
+        return Ok(n);
+    }
+
+    /// @brief 写入PS/2鼠标的命令，用于切换协议
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+        let mut state = self.state.lock_irqsave();
+        for c in &buf[..len] {
+            state.write_byte(*c);
+        }
+        drop(state);
+        self.wait_queue.wakeup_all(None);
+        return Ok(len);
+    }
+
+    fn poll(&self, table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        table.wait(&self.wait_queue);
+        if self.state.lock_irqsave().readable() {
+            return Ok(PollStatus::READ | PollStatus::WRITE);
+        }
+        return Ok(PollStatus::WRITE);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn crate::filesystem::vfs::FileSystem> {
+        return self.fs.read().upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<alloc::string::String>, SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+}
+
+/// @brief mousedev处理者，把所有鼠标连接到/dev/input/mice
+#[derive(Debug)]
+struct MousedevHandler {
+    mixdev: Arc<MousedevInode>,
+}
+
+impl InputHandler for MousedevHandler {
+    fn connect(&self, dev: &Arc<InputDevice>) -> Option<Arc<dyn InputHandle>> {
+        // 只连接能产生相对位移和左键的设备
+        let rel = dev.capability_bits(EV_REL)?;
+        let key = dev.capability_bits(EV_KEY)?;
+        if !rel.test(REL_X) || !rel.test(REL_Y) || !key.test(BTN_LEFT) {
+            return None;
+        }
+        return Some(self.mixdev.clone());
+    }
+}
+
+/// @brief 创建/dev/input/mice，并注册mousedev处理者
+pub fn mousedev_init() {
+    let mixdev = MousedevInode::new();
+    if let Err(e) = class_manager().device_create_with_inode(
+        input_class(),
+        None,
+        "mice".to_string(),
+        mixdev.clone(),
+    ) {
+        kerror!("mousedev: failed to register /dev/input/mice: {:?}", e);
+        return;
+    }
+    input_register_handler(Arc::new(MousedevHandler { mixdev }));
+}
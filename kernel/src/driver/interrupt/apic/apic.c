@@ -13,7 +13,7 @@
 #pragma GCC push_options
 #pragma GCC optimize("O0")
 // 导出定义在irq.c中的中段门表
-extern void (*interrupt_table[26])(void);
+extern void (*interrupt_table[IRQ_NUM])(void);
 extern uint32_t rs_current_pcb_preempt_count();
 extern uint32_t rs_current_pcb_pid();
 extern uint32_t rs_current_pcb_flags();
@@ -362,7 +362,7 @@ int apic_init()
     cli();
     kinfo("Initializing APIC...");
     // 初始化中断门， 中断使用rsp0防止在软中断时发生嵌套，然后处理器重新加载导致数据被抹掉
-    for (int i = 32; i <= 57; ++i)
+    for (int i = 32; i < 32 + IRQ_NUM; ++i)
         set_intr_gate(i, 0, interrupt_table[i - 32]);
 
     // 设置local apic中断门
@@ -404,6 +404,59 @@ int apic_init()
     // sti();
     return 0;
 }
+/**
+ * @brief IO APIC的RTE表项在睡眠前的值
+ *
+ */
+static ul ioapic_saved_rte[24];
+
+/**
+ * @brief 睡眠之前保存IO APIC的RTE表项
+ *
+ */
+void apic_suspend()
+{
+    for (int i = 0x10; i < 0x40; i += 2)
+        ioapic_saved_rte[(i - 0x10) >> 1] = apic_ioapic_read_rte(i);
+}
+
+/**
+ * @brief 从睡眠中恢复之后，重新初始化中断控制器
+ *
+ * 睡眠时中断控制器断电，寄存器回到了复位时的状态。寄存器的映射位于页表中，不需要重新建立。
+ * 重新使能local apic之后，LVT全部被屏蔽，由各个使用者重新设置
+ */
+void apic_resume()
+{
+    //  屏蔽类8259A芯片
+    io_out8(0x21, 0xff);
+    io_out8(0xa1, 0xff);
+
+    // enable IMCR
+    io_out8(0x22, 0x70);
+    io_out8(0x23, 0x01);
+
+    // 复位之后处于xAPIC模式，可以直接切换到x2APIC模式
+    uint64_t ia32_apic_base = rdmsr(0x1b);
+    ia32_apic_base |= (1 << 11);
+    if (flag_support_x2apic)
+        ia32_apic_base |= (1 << 10);
+    wrmsr(0x1b, ia32_apic_base);
+
+    if (flag_support_x2apic)
+        __local_apic_x2apic_init();
+    else
+        __local_apic_xapic_init();
+
+    // 设置IO APIC ID，然后恢复RTE表项
+    *apic_ioapic_map.virtual_index_addr = 0x00;
+    io_mfence();
+    *apic_ioapic_map.virtual_data_addr = 0x0f000000;
+    io_mfence();
+    for (int i = 0x10; i < 0x40; i += 2)
+        apic_ioapic_write_rte(i, ioapic_saved_rte[(i - 0x10) >> 1]);
+}
+
 /**
  * @brief 中断服务程序
  *
@@ -412,6 +465,8 @@ int apic_init()
  */
 void do_IRQ(struct pt_regs *rsp, ul number)
 {
+    rs_irq_account(number);
+    rs_tick_irq_enter();
     if (number < 0x80 && number >= 32) // 以0x80为界限，低于0x80的是外部中断控制器，高于0x80的是Local APIC
     {
         // ==========外部中断控制器========
@@ -563,6 +618,30 @@ void apic_ioapic_uninstall(ul irq_num)
     apic_ioapic_write_rte(0x10 + ((irq_num - 32) << 1), 0x10000UL);
 }
 
+/**
+ * @brief 为ISA设备的中断设置I/O APIC的RTE并解除屏蔽（被rust调用）
+ * 边沿触发、高电平有效，投递到BSP处理器
+ *
+ * @param irq_num 中断向量号，ISA中断号n对应的向量号为32+n
+ */
+void ioapic_install_isa_irq(uint64_t irq_num)
+{
+    struct apic_IO_APIC_RTE_entry entry;
+    apic_make_rte_entry(&entry, irq_num, IO_APIC_FIXED, DEST_PHYSICAL, IDLE, POLARITY_HIGH, IRR_RESET, EDGE_TRIGGER,
+                        UNMASKED, 0);
+    apic_ioapic_install(irq_num, &entry);
+}
+
+/**
+ * @brief 屏蔽ISA设备的中断（被rust调用）
+ *
+ * @param irq_num 中断向量号
+ */
+void ioapic_uninstall_isa_irq(uint64_t irq_num)
+{
+    apic_ioapic_uninstall(irq_num);
+}
+
 void apic_ioapic_level_ack(ul irq_num) // 电平触发
 {
     __send_eoi();
@@ -729,6 +808,24 @@ void apic_write_icr(uint64_t value)
     }
 }
 
+/**
+ * 写入性能监视计数器的LVT寄存器
+ *
+ * @param value 写入的值
+ */
+void apic_write_lvt_pmc(uint32_t value)
+{
+    if (flag_support_x2apic)
+    {
+        wrmsr(0x834, value);
+    }
+    else
+    {
+        *(volatile uint32_t *)(APIC_LOCAL_APIC_VIRT_BASE_ADDR + LOCAL_APIC_OFFSET_Local_APIC_LVT_PERFORMANCE_MONITOR) = value;
+        io_mfence();
+    }
+}
+
 // 查询是否启用了x2APIC
 bool apic_x2apic_enabled()
 {
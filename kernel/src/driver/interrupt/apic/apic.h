@@ -291,6 +291,18 @@ void apic_init_ap_core_local_apic();
  */
 int apic_init();
 
+/**
+ * @brief 睡眠之前保存IO APIC的RTE表项
+ *
+ */
+void apic_suspend();
+
+/**
+ * @brief 从睡眠中恢复之后，重新初始化中断控制器
+ *
+ */
+void apic_resume();
+
 /**
  * @brief 读取指定类型的 Interrupt Control Structure
  *
@@ -331,5 +343,6 @@ void apic_make_rte_entry(struct apic_IO_APIC_RTE_entry *entry, uint8_t vector, u
 
 uint32_t apic_get_local_apic_id();
 void apic_write_icr(uint64_t value);
+void apic_write_lvt_pmc(uint32_t value);
 bool apic_x2apic_enabled();
 #pragma GCC pop_options
\ No newline at end of file
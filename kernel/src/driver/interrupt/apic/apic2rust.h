@@ -1,2 +1,4 @@
 #include <common/stddef.h>
-extern uint64_t ioapic_get_base_paddr();
\ No newline at end of file
+extern uint64_t ioapic_get_base_paddr();
+extern void ioapic_install_isa_irq(uint64_t irq_num);
+extern void ioapic_uninstall_isa_irq(uint64_t irq_num);
@@ -13,6 +13,10 @@ static bool bsp_initialized = false;
 
 extern uint64_t rs_get_cycles();
 extern uint64_t rs_tsc_get_cpu_khz();
+extern void rs_cpufreq_tick();
+extern void rs_hrtimer_cpu_init();
+extern bool rs_hrtimer_interrupt(bool user);
+extern void rs_account_process_tick(bool user);
 
 /**
  * @brief 初始化AP核的apic时钟
@@ -101,10 +105,67 @@ hardware_intr_controller apic_timer_intr_controller = {
 void apic_timer_handler(uint64_t number, uint64_t param, struct pt_regs *regs)
 {
     io_mfence();
-    sched_update_jiffies();
+    // 切换到高精度模式之后，调度时钟由hrtimer模拟
+    if (!rs_hrtimer_interrupt(user_mode(regs)))
+    {
+        rs_account_process_tick(user_mode(regs));
+        sched_update_jiffies();
+        rs_cpufreq_tick();
+    }
+    io_mfence();
+}
+
+/**
+ * @brief 设置apic定时器的计时模式（供hrtimer使用）
+ *
+ * @param mode 计时模式
+ * @param masked 是否屏蔽中断
+ */
+void apic_timer_set_mode(uint32_t mode, bool masked)
+{
+    apic_timer_stop();
+    io_mfence();
+    apic_timer_set_div(APIC_TIMER_DIVISOR);
+    io_mfence();
+    apic_timer_set_init_cnt(0);
+    io_mfence();
+    apic_timer_set_LVT(APIC_TIMER_IRQ_NUM, masked ? 1 : 0, mode);
     io_mfence();
 }
 
+/**
+ * @brief 单次触发模式下，设置apic定时器的初始计数值（为0时停止定时器）
+ *
+ * @param cnt 初始计数值
+ */
+void apic_timer_program_count(uint32_t cnt)
+{
+    apic_timer_set_init_cnt(cnt);
+    io_mfence();
+}
+
+/**
+ * @brief TSC-deadline模式下，设置apic定时器到期时的TSC值（为0时停止定时器）
+ *
+ * @param tsc 到期时的TSC值
+ */
+void apic_timer_program_tsc_deadline(uint64_t tsc)
+{
+    io_mfence();
+    wrmsr(0x6e0, tsc);
+    io_mfence();
+}
+
+/**
+ * @brief 获取apic定时器当前的计数值
+ *
+ * @return uint32_t 当前计数值
+ */
+uint32_t apic_timer_current_count()
+{
+    return apic_timer_get_current();
+}
+
 /**
  * @brief 初始化local APIC定时器
  *
@@ -125,4 +186,6 @@ void apic_timer_init()
     }
     kdebug("apic timer init done for cpu %d", rs_current_pcb_cpuid());
     spin_unlock_irqrestore(&apic_timer_init_lock, flags);
+    // 如果注册了时钟事件设备，则切换到高精度模式
+    rs_hrtimer_cpu_init();
 }
\ No newline at end of file
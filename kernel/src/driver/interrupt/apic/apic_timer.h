@@ -107,4 +107,9 @@ void apic_timer_init();
 
 void apic_timer_ap_core_init();
 
+void apic_timer_set_mode(uint32_t mode, bool masked);
+void apic_timer_program_count(uint32_t cnt);
+void apic_timer_program_tsc_deadline(uint64_t tsc);
+uint32_t apic_timer_current_count();
+
 #pragma GCC pop_options
\ No newline at end of file
@@ -0,0 +1,193 @@
+//! DMA映射接口
+//!
+//! 驱动程序在把内存交给设备进行DMA之前，需要调用[`dma_map_single`]或[`dma_map_sg`]，
+//! 获取设备可见的地址(DMA地址)，并在DMA完成之后调用对应的unmap函数。
+//!
+//! - 启用了IOMMU时，DMA地址是IOMMU为设备分配的IOVA，设备只能访问被映射的页，且只有对应方向的访问权限；
+//! - 未启用IOMMU时，DMA地址就是物理地址。
+
+use alloc::sync::Arc;
+
+use crate::{
+    arch::MMArch,
+    driver::pci::pci::BusDeviceFunction,
+    int_like,
+    libs::align::{page_align_down, page_align_up},
+    mm::{MemoryManagementArch, VirtAddr},
+    syscall::SystemError,
+};
+
+use super::{iommu_device_domain, IommuDomain, IommuProt};
+
+int_like!(DmaAddr, usize);
+
+/// DMA的数据传输方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// 设备从内存中读取数据
+    ToDevice,
+    /// 设备向内存中写入数据
+    FromDevice,
+    /// 设备会读写这段内存
+    Bidirectional,
+}
+
+impl DmaDirection {
+    fn prot(&self) -> IommuProt {
+        match self {
+            DmaDirection::ToDevice => IommuProt::READ,
+            DmaDirection::FromDevice => IommuProt::WRITE,
+            DmaDirection::Bidirectional => IommuProt::READ | IommuProt::WRITE,
+        }
+    }
+}
+
+/// 分散/聚集列表的表项
+#[derive(Debug, Clone, Copy)]
+pub struct ScatterList {
+    /// 缓冲区的虚拟地址（必须位于内核的线性映射区）
+    pub vaddr: VirtAddr,
+    /// 缓冲区的长度
+    pub length: usize,
+    /// 映射之后，设备可见的地址
+    pub dma_address: DmaAddr,
+}
+
+impl ScatterList {
+    pub fn new(vaddr: VirtAddr, length: usize) -> Self {
+        Self {
+            vaddr,
+            length,
+            dma_address: DmaAddr::new(0),
+        }
+    }
+}
+
+/// 把一段内核内存映射给设备
+///
+/// ## 参数
+///
+/// - `dev`：进行DMA的设备
+/// - `vaddr`：缓冲区的起始虚拟地址（必须位于内核的线性映射区）
+/// - `size`：缓冲区的大小
+/// - `dir`：数据传输方向
+///
+/// ## 返回值
+///
+/// 设备可见的DMA地址
+///
+/// ## 错误
+///
+/// - `EINVAL`：size为0
+/// - `EFAULT`：缓冲区不在线性映射区
+/// - `ENOMEM`：设备地址空间不足
+pub fn dma_map_single(
+    dev: &BusDeviceFunction,
+    vaddr: VirtAddr,
+    size: usize,
+    dir: DmaDirection,
+) -> Result<DmaAddr, SystemError> {
+    if size == 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let paddr = unsafe { MMArch::virt_2_phys(vaddr) }.ok_or(SystemError::EFAULT)?;
+    let domain = match iommu_device_domain(dev)? {
+        Some(domain) => domain,
+        None => return Ok(DmaAddr::new(paddr.data())),
+    };
+
+    let start = page_align_down(vaddr.data());
+    let len = page_align_up(vaddr.data() + size) - start;
+    let iova = domain.alloc_iova(len)?;
+    if let Err(e) = map_pages(&domain, iova, VirtAddr::new(start), len, dir.prot()) {
+        domain.free_iova(iova, len);
+        return Err(e);
+    }
+    return Ok(DmaAddr::new(iova + (vaddr.data() - start)));
+}
+
+/// 取消[`dma_map_single`]建立的映射
+///
+/// 参数需要与映射时传入的参数一致
+pub fn dma_unmap_single(dev: &BusDeviceFunction, addr: DmaAddr, size: usize, _dir: DmaDirection) {
+    if let Ok(Some(domain)) = iommu_device_domain(dev) {
+        let start = page_align_down(addr.data());
+        let len = page_align_up(addr.data() + size) - start;
+        domain.unmap(start, len).ok();
+        domain.free_iova(start, len);
+    }
+}
+
+/// 把分散/聚集列表中的每一段内存映射给设备，映射结果写入每个表项的`dma_address`
+///
+/// 任意一段映射失败时，已经建立的映射都会被取消
+pub fn dma_map_sg(
+    dev: &BusDeviceFunction,
+    sg: &mut [ScatterList],
+    dir: DmaDirection,
+) -> Result<(), SystemError> {
+    for i in 0..sg.len() {
+        match dma_map_single(dev, sg[i].vaddr, sg[i].length, dir) {
+            Ok(addr) => sg[i].dma_address = addr,
+            Err(e) => {
+                dma_unmap_sg(dev, &sg[0..i], dir);
+                return Err(e);
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// 取消[`dma_map_sg`]建立的映射
+pub fn dma_unmap_sg(dev: &BusDeviceFunction, sg: &[ScatterList], dir: DmaDirection) {
+    for entry in sg {
+        dma_unmap_single(dev, entry.dma_address, entry.length, dir);
+    }
+}
+
+/// 以1:1的方式把一段内核内存映射给设备，即：DMA地址等于物理地址
+///
+/// 用于设备长期使用、且驱动需要根据DMA地址找回内存的数据结构（例如AHCI的命令列表）。
+/// 这样的映射在设备的整个生命周期内都有效，不需要取消。
+pub fn dma_map_identity(
+    dev: &BusDeviceFunction,
+    vaddr: VirtAddr,
+    size: usize,
+    dir: DmaDirection,
+) -> Result<DmaAddr, SystemError> {
+    if size == 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let paddr = unsafe { MMArch::virt_2_phys(vaddr) }.ok_or(SystemError::EFAULT)?;
+    if let Some(domain) = iommu_device_domain(dev)? {
+        let start = page_align_down(vaddr.data());
+        let len = page_align_up(vaddr.data() + size) - start;
+        let iova = unsafe { MMArch::virt_2_phys(VirtAddr::new(start)) }.unwrap();
+        map_pages(&domain, iova.data(), VirtAddr::new(start), len, dir.prot())?;
+    }
+    return Ok(DmaAddr::new(paddr.data()));
+}
+
+/// 逐页建立映射。失败时取消已经建立的映射
+fn map_pages(
+    domain: &Arc<dyn IommuDomain>,
+    iova: usize,
+    vaddr: VirtAddr,
+    len: usize,
+    prot: IommuProt,
+) -> Result<(), SystemError> {
+    let mut offset = 0;
+    while offset < len {
+        let r = unsafe { MMArch::virt_2_phys(vaddr + offset) }
+            .ok_or(SystemError::EFAULT)
+            .and_then(|paddr| domain.map(iova + offset, paddr, MMArch::PAGE_SIZE, prot));
+        if let Err(e) = r {
+            if offset != 0 {
+                domain.unmap(iova, offset).ok();
+            }
+            return Err(e);
+        }
+        offset += MMArch::PAGE_SIZE;
+    }
+    return Ok(());
+}
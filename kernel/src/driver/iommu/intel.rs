@@ -0,0 +1,582 @@
+//! Intel VT-d IOMMU驱动
+//!
+//! 从ACPI DMAR表中获取重映射硬件单元(DRHD)，为每个单元建立根表(root table)与上下文表(context table)。
+//!
+//! - 初始化时，所有已经枚举到的PCI设备都被设置为直通(pass-through)模式，保持原有的行为；
+//! - 设备第一次使用DMA映射接口时，会被切换到一个独立的地址空间，
+//!   此后设备只能访问通过DMA映射接口映射的内存。
+//!
+//! 参考：Intel Virtualization Technology for Directed I/O Architecture Specification
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicU16, Ordering},
+};
+
+use acpi::{
+    sdt::{SdtHeader, Signature},
+    AcpiTable,
+};
+
+use crate::{
+    arch::MMArch,
+    driver::{
+        acpi::acpi_manager,
+        pci::pci::{BusDeviceFunction, PCI_DEVICE_LINKEDLIST},
+    },
+    kdebug, kinfo, kwarn,
+    libs::spinlock::SpinLock,
+    mm::{
+        allocator::page_frame::{allocate_page_frames, PageFrameCount},
+        mmio_buddy::{mmio_pool, MMIOSpaceGuard},
+        MemoryManagementArch, PhysAddr, VirtAddr,
+    },
+    syscall::SystemError,
+};
+
+use super::{Iommu, IommuDomain, IommuProt, IovaAllocator};
+
+/// DMAR的表头
+#[repr(C)]
+struct Dmar {
+    header: SdtHeader,
+    _host_address_width: u8,
+    _flags: u8,
+    _reserved: [u8; 10],
+}
+
+unsafe impl AcpiTable for Dmar {
+    const SIGNATURE: Signature = Signature::DMAR;
+    fn header(&self) -> &SdtHeader {
+        return &self.header;
+    }
+}
+
+/// DMAR中的重映射结构类型：硬件单元定义(DRHD)
+const DMAR_TYPE_DRHD: u16 = 0;
+/// DRHD的标志位：该单元管理本segment中所有未被其他单元声明的设备
+const DRHD_FLAG_INCLUDE_PCI_ALL: u8 = 1 << 0;
+/// 设备范围(device scope)的类型：PCI端点设备
+const DEVICE_SCOPE_PCI_ENDPOINT: u8 = 1;
+
+// 重映射硬件单元的寄存器
+const DMAR_CAP_REG: usize = 0x8;
+const DMAR_ECAP_REG: usize = 0x10;
+const DMAR_GCMD_REG: usize = 0x18;
+const DMAR_GSTS_REG: usize = 0x1c;
+const DMAR_RTADDR_REG: usize = 0x20;
+const DMAR_CCMD_REG: usize = 0x28;
+const DMAR_FSTS_REG: usize = 0x34;
+
+/// 全局命令/状态寄存器：启用地址转换
+const DMA_GCMD_TE: u32 = 1 << 31;
+/// 全局命令/状态寄存器：设置根表指针
+const DMA_GCMD_SRTP: u32 = 1 << 30;
+/// 写全局命令寄存器时，需要保留的状态位（不包括one-shot的命令位）
+const DMA_GSTS_PRESERVE_MASK: u32 = 0x96ff_ffff;
+
+/// 上下文缓存失效：发起失效，全局失效
+const DMA_CCMD_ICC: u64 = 1 << 63;
+const DMA_CCMD_GLOBAL_INVL: u64 = 1 << 61;
+/// IOTLB失效：发起失效，全局失效
+const DMA_TLB_IVT: u64 = 1 << 63;
+const DMA_TLB_GLOBAL_FLUSH: u64 = 1 << 60;
+
+/// 扩展能力：页表的访问与处理器缓存一致
+const ECAP_C: u64 = 1 << 0;
+/// 扩展能力：支持直通(pass-through)
+const ECAP_PT: u64 = 1 << 6;
+/// 能力：caching mode，硬件可能缓存不存在的表项
+const CAP_CM: u64 = 1 << 7;
+
+/// 上下文表项中的转换类型：只转换未翻译的请求
+const CONTEXT_TT_MULTI_LEVEL: u64 = 0;
+/// 上下文表项中的转换类型：直通
+const CONTEXT_TT_PASS_THROUGH: u64 = 2;
+
+/// 二级页表项：可读、可写
+const DMA_PTE_READ: u64 = 1 << 0;
+const DMA_PTE_WRITE: u64 = 1 << 1;
+const DMA_PTE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// 直通模式使用的domain id(0号在caching mode下被保留)
+const PASS_THROUGH_DID: u16 = 1;
+
+/// 页表每一级的表项数量的位数
+const LEVEL_STRIDE: usize = 9;
+
+/// 一个DMA重映射硬件单元
+#[derive(Debug)]
+struct DmarUnit {
+    /// 寄存器所在的虚拟地址
+    regs: VirtAddr,
+    /// 该单元管理本segment中其他单元没有声明的所有设备
+    include_all: bool,
+    /// 该单元管理的设备((bus << 8) | devfn)
+    scopes: Vec<u16>,
+    /// 根表的物理地址
+    root_table: PhysAddr,
+    /// IOTLB寄存器的偏移量
+    iotlb_offset: usize,
+    /// 页表的级数(3级：39位地址，4级：48位地址)
+    levels: usize,
+    /// 页表的访问是否与处理器缓存一致
+    coherent: bool,
+    caching_mode: bool,
+    /// 能支持的domain的数量
+    max_domains: usize,
+    next_did: AtomicU16,
+    /// 设备((bus << 8) | devfn) -> 设备的地址空间
+    domains: SpinLock<BTreeMap<u16, Arc<IntelIommuDomain>>>,
+    /// 修改根表、上下文表和执行失效操作时需要持有的锁
+    lock: SpinLock<()>,
+}
+
+impl DmarUnit {
+    unsafe fn read32(&self, reg: usize) -> u32 {
+        read_volatile((self.regs.data() + reg) as *const u32)
+    }
+
+    unsafe fn write32(&self, reg: usize, value: u32) {
+        write_volatile((self.regs.data() + reg) as *mut u32, value)
+    }
+
+    unsafe fn read64(&self, reg: usize) -> u64 {
+        read_volatile((self.regs.data() + reg) as *const u64)
+    }
+
+    unsafe fn write64(&self, reg: usize, value: u64) {
+        write_volatile((self.regs.data() + reg) as *mut u64, value)
+    }
+
+    /// 向全局命令寄存器写入命令，并等待状态寄存器中对应的位被置位
+    fn global_command(&self, cmd: u32) {
+        unsafe {
+            let sts = self.read32(DMAR_GSTS_REG) & DMA_GSTS_PRESERVE_MASK;
+            self.write32(DMAR_GCMD_REG, sts | cmd);
+            while self.read32(DMAR_GSTS_REG) & cmd == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// 使所有的上下文缓存失效
+    fn flush_context(&self) {
+        unsafe {
+            self.write64(DMAR_CCMD_REG, DMA_CCMD_ICC | DMA_CCMD_GLOBAL_INVL);
+            while self.read64(DMAR_CCMD_REG) & DMA_CCMD_ICC != 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// 使所有的IOTLB失效
+    fn flush_iotlb(&self) {
+        unsafe {
+            self.write64(self.iotlb_offset, DMA_TLB_IVT | DMA_TLB_GLOBAL_FLUSH);
+            while self.read64(self.iotlb_offset) & DMA_TLB_IVT != 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// 判断设备是否由该单元管理（不考虑INCLUDE_PCI_ALL）
+    fn in_scope(&self, dev: &BusDeviceFunction) -> bool {
+        self.scopes.contains(&source_id(dev))
+    }
+
+    /// 获取设备的上下文表项的虚拟地址。上下文表不存在时，为其分配一个新的上下文表
+    fn context_entry(&self, dev: &BusDeviceFunction) -> Result<*mut u64, SystemError> {
+        let root = phys_to_ptr(self.root_table);
+        let root_entry = unsafe { root.add(dev.bus as usize * 2) };
+        let mut context_table = unsafe { read_volatile(root_entry) };
+        if context_table & 1 == 0 {
+            let table = alloc_table_page(self.coherent)?;
+            context_table = table.data() as u64 | 1;
+            unsafe { write_volatile(root_entry, context_table) };
+            flush_cache(root_entry as usize, 16, self.coherent);
+        }
+        let table = phys_to_ptr(PhysAddr::new((context_table & DMA_PTE_ADDR_MASK) as usize));
+        let devfn = (dev.device as usize) << 3 | dev.function as usize;
+        return Ok(unsafe { table.add(devfn * 2) });
+    }
+
+    /// 设置设备的上下文表项
+    ///
+    /// ## 参数
+    ///
+    /// - `translation`：转换类型
+    /// - `pgd`：页表的物理地址（直通模式下被忽略）
+    /// - `did`：domain id
+    fn set_context(
+        &self,
+        dev: &BusDeviceFunction,
+        translation: u64,
+        pgd: PhysAddr,
+        did: u16,
+    ) -> Result<(), SystemError> {
+        let _guard = self.lock.lock_irqsave();
+        let entry = self.context_entry(dev)?;
+        // 地址宽度：1表示39位(3级页表)，2表示48位(4级页表)
+        let aw = (self.levels - 2) as u64;
+        let lo = (pgd.data() as u64 & DMA_PTE_ADDR_MASK) | translation << 2 | 1;
+        let hi = aw | (did as u64) << 8;
+        unsafe {
+            // 先清除present位，再写入新的表项
+            write_volatile(entry, 0);
+            write_volatile(entry.add(1), hi);
+            write_volatile(entry, lo);
+        }
+        flush_cache(entry as usize, 16, self.coherent);
+        self.flush_context();
+        self.flush_iotlb();
+        return Ok(());
+    }
+
+    /// 启用地址转换
+    fn enable(&self) {
+        unsafe { self.write64(DMAR_RTADDR_REG, self.root_table.data() as u64) };
+        self.global_command(DMA_GCMD_SRTP);
+        self.flush_context();
+        self.flush_iotlb();
+        self.global_command(DMA_GCMD_TE);
+    }
+
+    fn alloc_did(&self) -> Result<u16, SystemError> {
+        let did = self.next_did.fetch_add(1, Ordering::SeqCst);
+        if did as usize >= self.max_domains {
+            return Err(SystemError::ENOSPC);
+        }
+        return Ok(did);
+    }
+}
+
+/// 一个设备地址空间
+#[derive(Debug)]
+pub struct IntelIommuDomain {
+    unit: Arc<DmarUnit>,
+    did: u16,
+    /// 顶级页表的物理地址
+    pgd: PhysAddr,
+    inner: SpinLock<IovaAllocator>,
+}
+
+impl IntelIommuDomain {
+    fn new(unit: Arc<DmarUnit>) -> Result<Self, SystemError> {
+        let did = unit.alloc_did()?;
+        let pgd = alloc_table_page(unit.coherent)?;
+        // 地址空间的上半部分用于动态分配的IOVA，下半部分留给1:1映射
+        let width = 12 + LEVEL_STRIDE * unit.levels;
+        let iova = IovaAllocator::new(1 << (width - 1), 1 << width);
+        return Ok(Self {
+            unit,
+            did,
+            pgd,
+            inner: SpinLock::new(iova),
+        });
+    }
+
+    /// 获取iova对应的最后一级页表项。`create`为true时，为缺失的中间页表分配内存
+    fn pte(&self, iova: usize, create: bool) -> Result<Option<*mut u64>, SystemError> {
+        let mut table = self.pgd;
+        for level in (1..=self.unit.levels).rev() {
+            let index = (iova >> (12 + LEVEL_STRIDE * (level - 1))) & ((1 << LEVEL_STRIDE) - 1);
+            let entry = unsafe { phys_to_ptr(table).add(index) };
+            if level == 1 {
+                return Ok(Some(entry));
+            }
+            let mut value = unsafe { read_volatile(entry) };
+            if value & (DMA_PTE_READ | DMA_PTE_WRITE) == 0 {
+                if !create {
+                    return Ok(None);
+                }
+                let next = alloc_table_page(self.unit.coherent)?;
+                value = next.data() as u64 | DMA_PTE_READ | DMA_PTE_WRITE;
+                unsafe { write_volatile(entry, value) };
+                flush_cache(entry as usize, 8, self.unit.coherent);
+            }
+            table = PhysAddr::new((value & DMA_PTE_ADDR_MASK) as usize);
+        }
+        unreachable!()
+    }
+}
+
+impl IommuDomain for IntelIommuDomain {
+    fn map(
+        &self,
+        iova: usize,
+        paddr: PhysAddr,
+        size: usize,
+        prot: IommuProt,
+    ) -> Result<(), SystemError> {
+        let mut flags = 0;
+        if prot.contains(IommuProt::READ) {
+            flags |= DMA_PTE_READ;
+        }
+        if prot.contains(IommuProt::WRITE) {
+            flags |= DMA_PTE_WRITE;
+        }
+
+        let _guard = self.inner.lock_irqsave();
+        let mut offset = 0;
+        while offset < size {
+            let entry = self.pte(iova + offset, true)?.unwrap();
+            unsafe {
+                write_volatile(
+                    entry,
+                    ((paddr.data() + offset) as u64 & DMA_PTE_ADDR_MASK) | flags,
+                )
+            };
+            flush_cache(entry as usize, 8, self.unit.coherent);
+            offset += MMArch::PAGE_SIZE;
+        }
+        drop(_guard);
+
+        // caching mode下，硬件可能缓存了不存在的表项，因此建立映射之后也需要刷新
+        if self.unit.caching_mode {
+            let _guard = self.unit.lock.lock_irqsave();
+            self.unit.flush_iotlb();
+        }
+        return Ok(());
+    }
+
+    fn unmap(&self, iova: usize, size: usize) -> Result<(), SystemError> {
+        let guard = self.inner.lock_irqsave();
+        let mut offset = 0;
+        while offset < size {
+            if let Some(entry) = self.pte(iova + offset, false)? {
+                unsafe { write_volatile(entry, 0) };
+                flush_cache(entry as usize, 8, self.unit.coherent);
+            }
+            offset += MMArch::PAGE_SIZE;
+        }
+        drop(guard);
+
+        let _guard = self.unit.lock.lock_irqsave();
+        self.unit.flush_iotlb();
+        return Ok(());
+    }
+
+    fn alloc_iova(&self, size: usize) -> Result<usize, SystemError> {
+        self.inner
+            .lock_irqsave()
+            .alloc(size)
+            .ok_or(SystemError::ENOMEM)
+    }
+
+    fn free_iova(&self, iova: usize, size: usize) {
+        self.inner.lock_irqsave().free(iova, size);
+    }
+}
+
+/// Intel VT-d IOMMU
+#[derive(Debug)]
+pub struct IntelIommu {
+    units: Vec<Arc<DmarUnit>>,
+}
+
+impl IntelIommu {
+    /// 获取管理该设备的重映射硬件单元
+    fn unit_of(&self, dev: &BusDeviceFunction) -> Option<&Arc<DmarUnit>> {
+        self.units
+            .iter()
+            .find(|u| u.in_scope(dev))
+            .or_else(|| self.units.iter().find(|u| u.include_all))
+    }
+}
+
+impl Iommu for IntelIommu {
+    fn device_domain(
+        &self,
+        dev: &BusDeviceFunction,
+    ) -> Result<Option<Arc<dyn IommuDomain>>, SystemError> {
+        let unit = match self.unit_of(dev) {
+            Some(unit) => unit,
+            None => return Ok(None),
+        };
+
+        let mut domains = unit.domains.lock_irqsave();
+        if let Some(domain) = domains.get(&source_id(dev)) {
+            return Ok(Some(domain.clone()));
+        }
+
+        // 为设备创建独立的地址空间，并把设备从直通模式切换过去
+        let domain = Arc::new(IntelIommuDomain::new(unit.clone())?);
+        unit.set_context(dev, CONTEXT_TT_MULTI_LEVEL, domain.pgd, domain.did)?;
+        domains.insert(source_id(dev), domain.clone());
+        kdebug!("iommu: device {} attached to domain {}", dev, domain.did);
+        return Ok(Some(domain));
+    }
+}
+
+/// 初始化Intel VT-d
+pub fn intel_iommu_init() -> Result<Arc<IntelIommu>, SystemError> {
+    let tables = acpi_manager().tables().ok_or(SystemError::ENODEV)?;
+    let dmar = tables
+        .find_entire_table::<Dmar>()
+        .map_err(|_| SystemError::ENODEV)?;
+    let data = unsafe {
+        core::slice::from_raw_parts(
+            dmar.virtual_start().as_ptr() as *const u8,
+            dmar.region_length(),
+        )
+    };
+    let len = core::cmp::min(dmar.header.length as usize, data.len());
+    let read_u16 = |off: usize| u16::from_le_bytes(data[off..off + 2].try_into().unwrap());
+    let read_u64 = |off: usize| u64::from_le_bytes(data[off..off + 8].try_into().unwrap());
+
+    let mut units = Vec::new();
+    let mut offset = core::mem::size_of::<Dmar>();
+    while offset + 4 <= len {
+        let entry_type = read_u16(offset);
+        let entry_len = read_u16(offset + 2) as usize;
+        if entry_len < 4 || offset + entry_len > len {
+            kwarn!("DMAR: bad entry at offset {}, len {}", offset, entry_len);
+            break;
+        }
+
+        // 目前只支持segment 0
+        if entry_type == DMAR_TYPE_DRHD && entry_len >= 16 && read_u16(offset + 6) == 0 {
+            let flags = data[offset + 4];
+            let base = PhysAddr::new(read_u64(offset + 8) as usize);
+            let scopes = parse_device_scopes(&data[offset + 16..offset + entry_len]);
+            match DmarUnit::new(base, flags & DRHD_FLAG_INCLUDE_PCI_ALL != 0, scopes) {
+                Ok(unit) => units.push(Arc::new(unit)),
+                Err(e) => kwarn!("DMAR: failed to init unit at {:?}: {:?}", base, e),
+            }
+        }
+        offset += entry_len;
+    }
+
+    if units.is_empty() {
+        return Err(SystemError::ENODEV);
+    }
+    let iommu = Arc::new(IntelIommu { units });
+
+    // 在启用地址转换之前，把所有已知的设备设置为直通模式，否则这些设备的DMA都会被拦截
+    for device in PCI_DEVICE_LINKEDLIST.read().iter() {
+        let dev = device.common_header().bus_device_function;
+        if let Some(unit) = iommu.unit_of(&dev) {
+            unit.set_context(
+                &dev,
+                CONTEXT_TT_PASS_THROUGH,
+                PhysAddr::new(0),
+                PASS_THROUGH_DID,
+            )?;
+        }
+    }
+
+    for unit in iommu.units.iter() {
+        unit.enable();
+        kinfo!(
+            "DMAR: unit at {:#x} enabled, {}-level page table, fault status: {:#x}",
+            unsafe { MMArch::virt_2_phys(unit.regs) }.map_or(0, |x| x.data()),
+            unit.levels,
+            unsafe { unit.read32(DMAR_FSTS_REG) }
+        );
+    }
+
+    return Ok(iommu);
+}
+
+impl DmarUnit {
+    fn new(base: PhysAddr, include_all: bool, scopes: Vec<u16>) -> Result<Self, SystemError> {
+        let mmio = mmio_pool().create_mmio(MMArch::PAGE_SIZE)?;
+        unsafe { mmio.map_phys(base, MMArch::PAGE_SIZE)? };
+        let regs = mmio.vaddr();
+        // 寄存器在IOMMU的整个生命周期内都需要访问
+        unsafe { MMIOSpaceGuard::leak(mmio) };
+
+        let cap = unsafe { read_volatile((regs.data() + DMAR_CAP_REG) as *const u64) };
+        let ecap = unsafe { read_volatile((regs.data() + DMAR_ECAP_REG) as *const u64) };
+
+        if ecap & ECAP_PT == 0 {
+            // 不支持直通时，其他驱动程序的DMA会被拦截
+            kwarn!("DMAR: unit at {:?} does not support pass-through", base);
+            return Err(SystemError::ENOSYS);
+        }
+
+        // SAGAW: 第1位表示支持39位(3级页表)，第2位表示支持48位(4级页表)
+        let sagaw = (cap >> 8) & 0x1f;
+        let levels = if sagaw & (1 << 2) != 0 {
+            4
+        } else if sagaw & (1 << 1) != 0 {
+            3
+        } else {
+            return Err(SystemError::ENOSYS);
+        };
+
+        let coherent = ecap & ECAP_C != 0;
+        let root_table = alloc_table_page(coherent)?;
+        return Ok(Self {
+            regs,
+            include_all,
+            scopes,
+            root_table,
+            iotlb_offset: (((ecap >> 8) & 0x3ff) as usize) * 16 + 8,
+            levels,
+            coherent,
+            caching_mode: cap & CAP_CM != 0,
+            max_domains: 1 << (4 + 2 * (cap & 0x7) as usize),
+            next_did: AtomicU16::new(PASS_THROUGH_DID + 1),
+            domains: SpinLock::new(BTreeMap::new()),
+            lock: SpinLock::new(()),
+        });
+    }
+}
+
+/// 解析DRHD中的设备范围，只处理直接挂在总线上的PCI端点设备
+fn parse_device_scopes(data: &[u8]) -> Vec<u16> {
+    let mut scopes = Vec::new();
+    let mut offset = 0;
+    while offset + 6 <= data.len() {
+        let scope_type = data[offset];
+        let scope_len = data[offset + 1] as usize;
+        if scope_len < 6 || offset + scope_len > data.len() {
+            break;
+        }
+        // 路径只有一级时，设备直接挂在start_bus上
+        if scope_type == DEVICE_SCOPE_PCI_ENDPOINT && scope_len == 8 {
+            let bus = data[offset + 5] as u16;
+            let devfn = (data[offset + 6] as u16) << 3 | data[offset + 7] as u16;
+            scopes.push(bus << 8 | devfn);
+        }
+        offset += scope_len;
+    }
+    return scopes;
+}
+
+/// 获取设备的source id: (bus << 8) | (device << 3) | function
+#[inline]
+fn source_id(dev: &BusDeviceFunction) -> u16 {
+    (dev.bus as u16) << 8 | (dev.device as u16) << 3 | dev.function as u16
+}
+
+#[inline]
+fn phys_to_ptr(paddr: PhysAddr) -> *mut u64 {
+    unsafe { MMArch::phys_2_virt(paddr) }.unwrap().data() as *mut u64
+}
+
+/// 分配一个清零的页，用作根表、上下文表或页表
+fn alloc_table_page(coherent: bool) -> Result<PhysAddr, SystemError> {
+    let (paddr, _) =
+        unsafe { allocate_page_frames(PageFrameCount::new(1)) }.ok_or(SystemError::ENOMEM)?;
+    let vaddr = unsafe { MMArch::phys_2_virt(paddr) }.unwrap();
+    unsafe { core::ptr::write_bytes(vaddr.data() as *mut u8, 0, MMArch::PAGE_SIZE) };
+    flush_cache(vaddr.data(), MMArch::PAGE_SIZE, coherent);
+    return Ok(paddr);
+}
+
+/// 硬件访问页表时不会窥探处理器的缓存，需要把修改写回内存
+fn flush_cache(vaddr: usize, size: usize, coherent: bool) {
+    if coherent {
+        return;
+    }
+    const CACHE_LINE_SIZE: usize = 64;
+    let mut addr = vaddr & !(CACHE_LINE_SIZE - 1);
+    while addr < vaddr + size {
+        unsafe { core::arch::x86_64::_mm_clflush(addr as *const u8) };
+        addr += CACHE_LINE_SIZE;
+    }
+}
@@ -0,0 +1,166 @@
+//! IOMMU(输入输出内存管理单元)支持
+//!
+//! IOMMU为每个设备提供独立的设备地址空间(IOVA)，设备只能访问被显式映射到其地址空间中的物理页。
+//! 驱动程序不直接使用本模块，而是通过[`dma`]模块提供的DMA映射接口来获取设备可见的地址。
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::fmt::Debug;
+
+use crate::{
+    driver::pci::pci::BusDeviceFunction, kinfo, libs::rwlock::RwLock, mm::PhysAddr,
+    syscall::SystemError,
+};
+
+pub mod dma;
+#[cfg(target_arch = "x86_64")]
+pub mod intel;
+
+bitflags! {
+    /// IOMMU页表项的访问权限（从设备的角度来看）
+    pub struct IommuProt: u32 {
+        /// 设备可以读取该页
+        const READ = 1 << 0;
+        /// 设备可以写入该页
+        const WRITE = 1 << 1;
+    }
+}
+
+/// 一个设备地址空间(domain)
+pub trait IommuDomain: Debug + Send + Sync {
+    /// 把[iova, iova+size)映射到[paddr, paddr+size)
+    ///
+    /// iova、paddr和size都必须按页对齐
+    fn map(
+        &self,
+        iova: usize,
+        paddr: PhysAddr,
+        size: usize,
+        prot: IommuProt,
+    ) -> Result<(), SystemError>;
+
+    /// 取消[iova, iova+size)的映射，并刷新IOTLB
+    fn unmap(&self, iova: usize, size: usize) -> Result<(), SystemError>;
+
+    /// 在地址空间中分配一段大小为size的设备地址
+    fn alloc_iova(&self, size: usize) -> Result<usize, SystemError>;
+
+    /// 释放由[`IommuDomain::alloc_iova`]分配的设备地址
+    fn free_iova(&self, iova: usize, size: usize);
+}
+
+/// IOMMU硬件驱动需要实现的trait
+pub trait Iommu: Debug + Send + Sync {
+    /// 获取设备的地址空间
+    ///
+    /// 设备第一次调用时，会为其创建一个独立的地址空间，此后该设备只能访问被映射的内存。
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(None)`：设备不受该IOMMU的管理，设备使用物理地址进行DMA
+    fn device_domain(
+        &self,
+        dev: &BusDeviceFunction,
+    ) -> Result<Option<Arc<dyn IommuDomain>>, SystemError>;
+}
+
+static IOMMU: RwLock<Option<Arc<dyn Iommu>>> = RwLock::new(None);
+
+/// 初始化IOMMU
+///
+/// 没有找到IOMMU硬件时，返回`ENODEV`，此时设备直接使用物理地址进行DMA
+pub fn iommu_init() -> Result<(), SystemError> {
+    let iommu = iommu_probe()?;
+    *IOMMU.write() = Some(iommu);
+    kinfo!("IOMMU enabled");
+    return Ok(());
+}
+
+/// 查找IOMMU硬件。目前只支持x86_64上的Intel VT-d
+#[cfg(target_arch = "x86_64")]
+fn iommu_probe() -> Result<Arc<dyn Iommu>, SystemError> {
+    let iommu: Arc<dyn Iommu> = intel::intel_iommu_init()?;
+    return Ok(iommu);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn iommu_probe() -> Result<Arc<dyn Iommu>, SystemError> {
+    return Err(SystemError::ENODEV);
+}
+
+/// 获取设备的地址空间。没有启用IOMMU，或者设备不受IOMMU管理时，返回`Ok(None)`
+pub fn iommu_device_domain(
+    dev: &BusDeviceFunction,
+) -> Result<Option<Arc<dyn IommuDomain>>, SystemError> {
+    match IOMMU.read().as_ref() {
+        Some(iommu) => iommu.device_domain(dev),
+        None => Ok(None),
+    }
+}
+
+/// 设备地址(IOVA)分配器
+///
+/// 优先从已释放的地址中分配（首次适应），没有合适的空闲地址时，从未使用过的地址中分配
+#[derive(Debug)]
+pub struct IovaAllocator {
+    /// 从未使用过的地址的起始位置
+    next: usize,
+    /// 地址空间的结束位置
+    end: usize,
+    /// 已释放的地址: 起始地址 -> 大小
+    free: BTreeMap<usize, usize>,
+}
+
+impl IovaAllocator {
+    /// 创建一个管理[start, end)的分配器。start和end都必须按页对齐
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            next: start,
+            end,
+            free: BTreeMap::new(),
+        }
+    }
+
+    /// 分配一段大小为size的设备地址
+    pub fn alloc(&mut self, size: usize) -> Option<usize> {
+        let found = self
+            .free
+            .iter()
+            .find(|(_, len)| **len >= size)
+            .map(|(start, len)| (*start, *len));
+        if let Some((start, len)) = found {
+            self.free.remove(&start);
+            if len > size {
+                self.free.insert(start + size, len - size);
+            }
+            return Some(start);
+        }
+
+        if self.end - self.next < size {
+            return None;
+        }
+        let start = self.next;
+        self.next += size;
+        return Some(start);
+    }
+
+    /// 释放一段设备地址，并与相邻的空闲地址合并
+    pub fn free(&mut self, mut start: usize, mut size: usize) {
+        if let Some((prev, prev_len)) = self.free.range(..start).next_back() {
+            let (prev, prev_len) = (*prev, *prev_len);
+            if prev + prev_len == start {
+                self.free.remove(&prev);
+                start = prev;
+                size += prev_len;
+            }
+        }
+        if let Some(next_len) = self.free.remove(&(start + size)) {
+            size += next_len;
+        }
+
+        if start + size == self.next {
+            self.next = start;
+        } else {
+            self.free.insert(start, size);
+        }
+    }
+}
@@ -3,16 +3,18 @@ use core::sync::atomic::AtomicI32;
 use alloc::sync::{Arc, Weak};
 
 use crate::{
-    driver::tty::tty_device::TTY_DEVICES,
+    driver::input::{
+        input_register_device, InputDevice, InputId, BUS_I8042, EV_KEY, EV_MSC, MSC_RAW, MSC_SCAN,
+    },
     filesystem::{
         devfs::{devfs_register, DevFS, DeviceINode},
         vfs::{
             core::generate_inode_id, file::FileMode, syscall::ModeType, FileType, IndexNode,
-            Metadata, PollStatus,
+            Metadata, PollStatus, PollTable,
         },
     },
     include::bindings::bindings::{vfs_file_operations_t, vfs_file_t, vfs_index_node_t},
-    libs::{keyboard_parser::TypeOneFSM, rwlock::RwLock, spinlock::SpinLock},
+    libs::{rwlock::RwLock, spinlock::SpinLock},
     syscall::SystemError,
     time::TimeSpec,
 };
@@ -21,14 +23,120 @@ use crate::{
 pub struct LockedPS2KeyBoardInode(RwLock<PS2KeyBoardInode>, AtomicI32); // self.1 用来记录有多少个文件打开了这个inode
 
 lazy_static! {
-    static ref PS2_KEYBOARD_FSM: SpinLock<TypeOneFSM> = {
-        let tty0 = TTY_DEVICES
-            .read()
-            .get("tty0")
-            .expect("Initializing PS2_KEYBOARD_FSM: Cannot found TTY0!")
-            .clone();
-        SpinLock::new(TypeOneFSM::new(tty0))
-    };
+    /// 键盘对应的输入设备，扫描码通过它上报给输入子系统
+    static ref PS2_KEYBOARD_INPUT: RwLock<Option<Arc<InputDevice>>> = RwLock::new(None);
+}
+
+static PS2_KEYBOARD_DECODER: SpinLock<Ps2ScancodeDecoder> = SpinLock::new(Ps2ScancodeDecoder {
+    e0: false,
+    e1_remaining: 0,
+    e1_break: false,
+});
+
+/// Pause键的键码
+const KEY_PAUSE: u16 = 119;
+/// 第一套扫描码中，没有前缀的按键的最大扫描码(F12)。这些按键的键码与扫描码相同
+const PS2_MAX_PLAIN_SCANCODE: u8 = 0x58;
+/// 以0xE0为前缀的扫描码与键码的对应关系
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/input-event-codes.h
+const PS2_E0_KEYCODES: [(u8, u16); 18] = [
+    (0x1c, 96),  // KEY_KPENTER
+    (0x1d, 97),  // KEY_RIGHTCTRL
+    (0x35, 98),  // KEY_KPSLASH
+    (0x37, 99),  // KEY_SYSRQ
+    (0x38, 100), // KEY_RIGHTALT
+    (0x47, 102), // KEY_HOME
+    (0x48, 103), // KEY_UP
+    (0x49, 104), // KEY_PAGEUP
+    (0x4b, 105), // KEY_LEFT
+    (0x4d, 106), // KEY_RIGHT
+    (0x4f, 107), // KEY_END
+    (0x50, 108), // KEY_DOWN
+    (0x51, 109), // KEY_PAGEDOWN
+    (0x52, 110), // KEY_INSERT
+    (0x53, 111), // KEY_DELETE
+    (0x5b, 125), // KEY_LEFTMETA
+    (0x5c, 126), // KEY_RIGHTMETA
+    (0x5d, 127), // KEY_COMPOSE
+];
+
+/// @brief 把第一套扫描码转换为输入子系统的键码
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/keyboard/atkbd.c#atkbd_interrupt
+#[derive(Debug)]
+struct Ps2ScancodeDecoder {
+    /// 上一个字节是0xE0前缀
+    e0: bool,
+    /// Pause键的扫描码(E1 1D 45 / E1 9D C5)中，0xE1前缀之后还需要接收的字节数
+    e1_remaining: u8,
+    /// 正在接收的Pause键扫描码是松开的扫描码
+    e1_break: bool,
+}
+
+impl Ps2ScancodeDecoder {
+    /// @brief 解析一个扫描码字节
+    ///
+    /// @return (扫描码, 键码, 是否按下)；前缀等不构成完整按键的字节返回None
+    fn decode(&mut self, byte: u8) -> Option<(u32, u16, bool)> {
+        if self.e1_remaining > 0 {
+            self.e1_remaining -= 1;
+            if self.e1_remaining == 1 {
+                self.e1_break = byte & 0x80 != 0;
+            }
+            if self.e1_remaining == 0 {
+                return Some((0xe11d, KEY_PAUSE, !self.e1_break));
+            }
+            return None;
+        }
+        match byte {
+            0xe0 => {
+                self.e0 = true;
+                return None;
+            }
+            0xe1 => {
+                self.e1_remaining = 2;
+                return None;
+            }
+            _ => {}
+        }
+
+        let e0 = core::mem::replace(&mut self.e0, false);
+        let pressed = byte & 0x80 == 0;
+        let code = byte & 0x7f;
+        if e0 {
+            // 键盘模拟的shift(E0 2A、E0 AA等)没有对应的键码，被忽略
+            let (_, keycode) = PS2_E0_KEYCODES.iter().find(|(c, _)| *c == code)?;
+            return Some((0xe000 | code as u32, *keycode, pressed));
+        }
+        if code == 0 || code > PS2_MAX_PLAIN_SCANCODE {
+            return None;
+        }
+        return Some((code as u32, code as u16, pressed));
+    }
+}
+
+/// 创建键盘对应的输入设备，并注册到输入子系统
+fn ps2_keyboard_input_register() {
+    let mut dev = InputDevice::new(
+        "AT Translated Set 2 keyboard",
+        InputId {
+            bustype: BUS_I8042,
+            vendor: 0x0001,
+            product: 0x0001,
+            version: 0xab41,
+        },
+    );
+    for code in 1..=PS2_MAX_PLAIN_SCANCODE {
+        dev.set_capability(EV_KEY, code as u16);
+    }
+    for (_, keycode) in PS2_E0_KEYCODES.iter() {
+        dev.set_capability(EV_KEY, *keycode);
+    }
+    dev.set_capability(EV_KEY, KEY_PAUSE);
+    dev.set_capability(EV_MSC, MSC_RAW);
+    dev.set_capability(EV_MSC, MSC_SCAN);
+    *PS2_KEYBOARD_INPUT.write_irqsave() = Some(input_register_device(dev));
 }
 
 #[derive(Debug)]
@@ -90,6 +198,7 @@ impl DeviceINode for LockedPS2KeyBoardInode {
 pub extern "C" fn ps2_keyboard_register(f_ops: &vfs_file_operations_t) {
     devfs_register("ps2_keyboard", LockedPS2KeyBoardInode::new(f_ops))
         .expect("Failed to register ps/2 keyboard");
+    ps2_keyboard_input_register();
 }
 
 impl IndexNode for LockedPS2KeyBoardInode {
@@ -152,7 +261,7 @@ impl IndexNode for LockedPS2KeyBoardInode {
         return Ok(());
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         return Ok(PollStatus::READ);
     }
 
@@ -185,9 +294,20 @@ impl IndexNode for LockedPS2KeyBoardInode {
     }
 }
 
-#[allow(dead_code)]
+/// @brief 键盘中断收到一个扫描码字节时调用，把扫描码上报给输入子系统
+///
+/// 原始的字节以MSC_RAW事件上报(虚拟终端据此解析出字符)，完整的按键以EV_KEY事件上报
 #[no_mangle]
-/// for test
 pub extern "C" fn ps2_keyboard_parse_keycode(input: u8) {
-    PS2_KEYBOARD_FSM.lock().parse(input);
+    let dev = match PS2_KEYBOARD_INPUT.read().clone() {
+        Some(dev) => dev,
+        None => return,
+    };
+    dev.event(EV_MSC, MSC_RAW, input as i32);
+    let key = PS2_KEYBOARD_DECODER.lock_irqsave().decode(input);
+    if let Some((scancode, keycode, pressed)) = key {
+        dev.event(EV_MSC, MSC_SCAN, scancode as i32);
+        dev.report_key(keycode, pressed);
+    }
+    dev.sync();
 }
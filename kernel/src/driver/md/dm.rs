@@ -0,0 +1,851 @@
+//! 映射设备(device mapper)
+//!
+//! 映射设备是叠加在其他块设备之上的虚拟块设备，对它的读写由目标([`DmTarget`])转换为对下层设备的读写，
+//! 例如crypt目标在读写时透明地解密、加密数据，verity目标在读取时校验数据的完整性。映射设备本身也是块设备，可以继续作为其他映射设备的下层设备。
+//!
+//! 用户态通过`/dev/mapper/control`的ioctl创建、删除映射设备。创建时指定设备名、目标类型以及目标的参数，
+//! 参数的格式由目标决定。映射设备的设备文件为`/dev/block/dm-N`，扇区大小固定为512字节。
+//!
+//! 与Linux不同，每个映射设备只有一个覆盖整个设备的目标，并且创建设备与加载映射表在同一个ioctl中完成。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/md/dm-ioctl.c
+
+use core::{
+    any::Any,
+    fmt::Debug,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::{
+        block::{
+            block_device::{BlockDevice, BlockDeviceOps, BlockId, LBA_SIZE},
+            disk_info::Partition,
+        },
+        class::{block_class, class_manager, Class},
+        device::{
+            bus::Bus, device_manager, driver::Driver, Device, DeviceKObjType, DeviceNumber,
+            DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::{
+        devfs::{devfs_register, DevFS, DeviceINode},
+        kernfs::KernFSInode,
+        vfs::{
+            core::{generate_inode_id, partition_by_name},
+            file::FileMode,
+            make_rawdev,
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus, PollTable,
+        },
+    },
+    kinfo, kwarn,
+    libs::{
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
+    },
+    syscall::{
+        user_access::{check_and_clone_cstr, UserPtr},
+        SystemError,
+    },
+    time::TimeSpec,
+};
+
+use super::{dm_crypt::CryptTarget, dm_verity::VerityTarget};
+
+/// 映射设备的主设备号，与Linux中device-mapper通常被分配到的主设备号相同
+const DM_MAJOR: usize = 253;
+/// 最多能创建的映射设备数量
+const DM_MAX_DEVICES: usize = 256;
+/// `/dev/mapper/control`的设备号，与Linux相同
+const MISC_MAJOR: usize = 10;
+const MAPPER_CTRL_MINOR: usize = 236;
+
+/// 映射设备名的最大长度(包括结尾的'\0')
+pub const DM_NAME_LEN: usize = 128;
+/// 目标类型名的最大长度(包括结尾的'\0')
+pub const DM_MAX_TYPE_NAME: usize = 16;
+/// 目标参数的最大长度
+const DM_PARAMS_MAX: usize = 4096;
+
+/// ioctl：创建映射设备并加载目标。低8位与Linux的DM_DEV_CREATE_CMD相同
+pub const DM_DEV_CREATE: u32 = 0xfd03;
+/// ioctl：删除映射设备。低8位与Linux的DM_DEV_REMOVE_CMD相同
+pub const DM_DEV_REMOVE: u32 = 0xfd04;
+/// ioctl：查询映射设备的设备号与大小。低8位与Linux的DM_DEV_STATUS_CMD相同
+pub const DM_DEV_STATUS: u32 = 0xfd07;
+
+/// `/dev/mapper/control`的ioctl参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DmIoctl {
+    /// 映射设备名，以'\0'结尾
+    pub name: [u8; DM_NAME_LEN],
+    /// 目标类型，以'\0'结尾，例如"crypt"。只在创建时使用
+    pub target_type: [u8; DM_MAX_TYPE_NAME],
+    /// 指向目标参数的用户空间指针，参数是以'\0'结尾、以空白分隔的字符串。只在创建时使用
+    pub params: u64,
+    /// 输出：映射设备的设备号
+    pub dev: u64,
+    /// 输出：映射设备的扇区数
+    pub sectors: u64,
+}
+
+/// 映射设备的目标，把对映射设备的读写转换为对下层设备的读写
+///
+/// 读写的单位是512字节的扇区，映射设备保证读写范围不超过[`DmTarget::sectors`]
+pub trait DmTarget: Debug + Send + Sync {
+    /// 目标的扇区数
+    fn sectors(&self) -> u64;
+
+    /// 读取从`sector`开始的扇区，扇区数由`buf`的长度决定
+    fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), SystemError>;
+
+    /// 写入从`sector`开始的扇区，扇区数由`buf`的长度决定
+    fn write(&self, sector: u64, buf: &[u8]) -> Result<(), SystemError>;
+
+    /// 把写入的数据同步到下层设备
+    fn sync(&self) -> Result<(), SystemError>;
+}
+
+/// 目标类型：根据参数创建目标
+#[derive(Debug)]
+pub struct DmTargetType {
+    pub name: &'static str,
+    pub ctr: fn(&[&str]) -> Result<Box<dyn DmTarget>, SystemError>,
+}
+
+/// 内核支持的目标类型
+static DM_TARGET_TYPES: &[DmTargetType] = &[
+    DmTargetType {
+        name: "crypt",
+        ctr: CryptTarget::ctr,
+    },
+    DmTargetType {
+        name: "verity",
+        ctr: VerityTarget::ctr,
+    },
+];
+
+/// 所有的映射设备
+static MAPPED_DEVICES: RwLock<Vec<Arc<MappedDevice>>> = RwLock::new(Vec::new());
+
+/// 目标的下层设备：磁盘分区或者另一个映射设备
+///
+/// 下层设备是映射设备时，在本结构体被释放之前，下层设备不能被删除
+#[derive(Debug)]
+pub struct DmDev {
+    disk: Arc<dyn BlockDevice>,
+    /// 在磁盘上的起始扇区
+    lba_start: u64,
+    sectors: u64,
+    /// 下层设备是映射设备时，指向这个映射设备
+    mapped: Option<Arc<MappedDevice>>,
+}
+
+impl DmDev {
+    /// 打开下层设备
+    ///
+    /// ## 参数
+    ///
+    /// - `name`：映射设备名、映射设备的`dm-N`名称，或者磁盘分区的设备名(见[`partition_by_name`])
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：没有这个设备
+    pub fn open(name: &str) -> Result<Self, SystemError> {
+        let name = name.strip_prefix("/dev/").unwrap_or(name);
+        let name = name.strip_prefix("block/").unwrap_or(name);
+        let name = name.strip_prefix("mapper/").unwrap_or(name);
+        if let Some(md) = find_mapped_device(name) {
+            md.open_count.fetch_add(1, Ordering::SeqCst);
+            return Ok(Self {
+                disk: md.clone(),
+                lba_start: 0,
+                sectors: md.sectors,
+                mapped: Some(md),
+            });
+        }
+
+        let part = partition_by_name(name).ok_or(SystemError::ENODEV)?;
+        return Ok(Self {
+            disk: part.disk(),
+            lba_start: part.lba_start,
+            sectors: part.sectors_num,
+            mapped: None,
+        });
+    }
+
+    /// 下层设备的扇区数
+    pub fn sectors(&self) -> u64 {
+        return self.sectors;
+    }
+
+    /// 检查读写范围，返回在磁盘上的字节偏移量
+    fn disk_offset(&self, sector: u64, len: usize) -> Result<usize, SystemError> {
+        if len % LBA_SIZE != 0 || sector + (len / LBA_SIZE) as u64 > self.sectors {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok((self.lba_start + sector) as usize * LBA_SIZE);
+    }
+
+    /// 读取从`sector`开始的扇区，扇区数由`buf`的长度决定
+    pub fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        let offset = self.disk_offset(sector, buf.len())?;
+        self.disk.read_at_bytes(offset, buf.len(), buf)?;
+        return Ok(());
+    }
+
+    /// 写入从`sector`开始的扇区，扇区数由`buf`的长度决定
+    pub fn write(&self, sector: u64, buf: &[u8]) -> Result<(), SystemError> {
+        let offset = self.disk_offset(sector, buf.len())?;
+        self.disk.write_at_bytes(offset, buf.len(), buf)?;
+        return Ok(());
+    }
+
+    pub fn sync(&self) -> Result<(), SystemError> {
+        return self.disk.sync();
+    }
+}
+
+impl Drop for DmDev {
+    fn drop(&mut self) {
+        if let Some(md) = self.mapped.as_ref() {
+            md.open_count.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// 映射设备
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct MappedDevice {
+    /// 用户指定的设备名
+    name: String,
+    /// 设备在/dev/block下的名字，即dm-N
+    dm_name: String,
+    devnum: DeviceNumber,
+    target: Box<dyn DmTarget>,
+    sectors: u64,
+    /// 以本设备作为下层设备的目标的数量
+    open_count: AtomicUsize,
+    partitions: RwLock<Vec<Arc<Partition>>>,
+    inner: RwLock<InnerMappedDevice>,
+    kobj_state: LockedKObjectState,
+    self_ref: Weak<MappedDevice>,
+}
+
+#[derive(Debug)]
+struct InnerMappedDevice {
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    inode: Option<Arc<KernFSInode>>,
+}
+
+impl MappedDevice {
+    fn new(
+        name: String,
+        index: usize,
+        devnum: DeviceNumber,
+        target: Box<dyn DmTarget>,
+    ) -> Arc<Self> {
+        let sectors = target.sectors();
+        let md = Arc::new_cyclic(|self_ref| Self {
+            name,
+            dm_name: format!("dm-{}", index),
+            devnum,
+            target,
+            sectors,
+            open_count: AtomicUsize::new(0),
+            partitions: RwLock::new(Vec::new()),
+            inner: RwLock::new(InnerMappedDevice {
+                kset: None,
+                parent_kobj: None,
+                inode: None,
+            }),
+            kobj_state: LockedKObjectState::new(None),
+            self_ref: self_ref.clone(),
+        });
+
+        // 映射设备上没有分区表，用一个覆盖整个设备的分区表示它，以便在上面创建文件系统
+        md.partitions.write().push(Partition::new(
+            0,
+            0,
+            sectors,
+            Arc::downgrade(&(md.clone() as Arc<dyn BlockDevice>)),
+            0,
+        ));
+        return md;
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 检查读写范围
+    fn check_range(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf_len: usize,
+    ) -> Result<usize, SystemError> {
+        let len = count * LBA_SIZE;
+        if buf_len < len || (lba_id_start + count) as u64 > self.sectors {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(len);
+    }
+}
+
+impl BlockDevice for MappedDevice {
+    fn read_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let len = self.check_range(lba_id_start, count, buf.len())?;
+        self.target.read(lba_id_start as u64, &mut buf[..len])?;
+        return Ok(len);
+    }
+
+    fn write_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        let len = self.check_range(lba_id_start, count, buf.len())?;
+        self.target.write(lba_id_start as u64, &buf[..len])?;
+        return Ok(len);
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        return self.target.sync();
+    }
+
+    #[inline]
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    #[inline]
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn device(&self) -> Arc<dyn Device> {
+        return self.self_ref.upgrade().unwrap();
+    }
+
+    #[inline]
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        return self.partitions.read().clone();
+    }
+}
+
+impl Device for MappedDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(self.dm_name.clone(), self.devnum)
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        None
+    }
+
+    fn set_bus(&self, _bus: Option<Arc<dyn Bus>>) {}
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        Some(block_class())
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        None
+    }
+
+    fn set_driver(&self, _driver: Option<Weak<dyn Driver>>) {}
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        false
+    }
+
+    fn set_can_match(&self, _can_match: bool) {}
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn suspend(&self) -> Result<(), SystemError> {
+        return BlockDevice::sync(self);
+    }
+
+    fn shutdown(&self) {
+        if let Err(e) = BlockDevice::sync(self) {
+            kwarn!("dm {}: failed to sync: {:?}", self.name, e);
+        }
+    }
+}
+
+impl KObject for MappedDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        Some(&DeviceKObjType)
+    }
+
+    fn set_kobj_type(&self, _ktype: Option<&'static dyn KObjType>) {}
+
+    fn name(&self) -> String {
+        self.dm_name.clone()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 按用户指定的设备名或者dm-N名称查找映射设备
+fn find_mapped_device(name: &str) -> Option<Arc<MappedDevice>> {
+    return MAPPED_DEVICES
+        .read()
+        .iter()
+        .find(|md| md.name == name || md.dm_name == name)
+        .cloned();
+}
+
+/// 按名字查找映射设备，返回覆盖整个映射设备的分区，用于在映射设备上挂载文件系统
+///
+/// ## 参数
+///
+/// - `name`：映射设备名或者`dm-N`名称，可以带有`/dev/`、`/dev/mapper/`或者`/dev/block/`前缀
+pub fn dm_partition_by_name(name: &str) -> Option<Arc<Partition>> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    let name = name.strip_prefix("block/").unwrap_or(name);
+    let name = name.strip_prefix("mapper/").unwrap_or(name);
+    return find_mapped_device(name)?.partitions.read().first().cloned();
+}
+
+/// 获取所有的映射设备
+pub fn mapped_devices() -> Vec<Arc<MappedDevice>> {
+    return MAPPED_DEVICES.read().clone();
+}
+
+/// devfs中映射设备的块设备文件
+#[derive(Debug)]
+pub struct DmInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: SpinLock<Metadata>,
+    md: Arc<MappedDevice>,
+}
+
+impl DmInode {
+    fn new(md: Arc<MappedDevice>) -> Arc<Self> {
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata: SpinLock::new(Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: (md.sectors as usize * LBA_SIZE) as i64,
+                blk_size: LBA_SIZE,
+                blocks: md.sectors as usize,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::BlockDevice,
+                mode: ModeType::from_bits_truncate(0o660),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: make_rawdev(md.devnum.major(), md.devnum.minor()),
+            }),
+            md,
+        })
+    }
+
+    /// 把读写长度限制在设备的末尾之前
+    fn clamp_len(&self, offset: usize, len: usize) -> usize {
+        let size = self.md.sectors as usize * LBA_SIZE;
+        return len.min(size.saturating_sub(offset));
+    }
+}
+
+impl DeviceINode for DmInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for DmInode {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.lock().clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.fs.lock().upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.metadata.lock();
+        inode.atime = metadata.atime;
+        inode.mtime = metadata.mtime;
+        inode.ctime = metadata.ctime;
+        inode.mode = metadata.mode;
+        inode.uid = metadata.uid;
+        inode.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let len = self.clamp_len(offset, len);
+        if len == 0 {
+            return Ok(0);
+        }
+        return self.md.read_at_bytes(offset, len, buf);
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let len = self.clamp_len(offset, len);
+        if len == 0 {
+            return Err(SystemError::ENOSPC);
+        }
+        return self.md.write_at_bytes(offset, len, buf);
+    }
+}
+
+/// 从定长数组中取出以'\0'结尾的字符串
+fn cstr_field(field: &[u8]) -> Result<&str, SystemError> {
+    let len = field
+        .iter()
+        .position(|&c| c == 0)
+        .ok_or(SystemError::EINVAL)?;
+    return core::str::from_utf8(&field[..len]).map_err(|_| SystemError::EINVAL);
+}
+
+/// 解码目标参数中十六进制表示的密钥、摘要等
+///
+/// ## 错误
+///
+/// - `EINVAL`：不是合法的十六进制字符串
+pub fn decode_hex(s: &str) -> Result<Vec<u8>, SystemError> {
+    if s.len() % 2 != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let digit = |c: u8| -> Result<u8, SystemError> {
+        match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(SystemError::EINVAL),
+        }
+    };
+    return s
+        .as_bytes()
+        .chunks_exact(2)
+        .map(|pair| Ok((digit(pair[0])? << 4) | digit(pair[1])?))
+        .collect();
+}
+
+/// 创建映射设备
+///
+/// ## 参数
+///
+/// - `name`：设备名
+/// - `target_type`：目标类型
+/// - `params`：目标的参数，以空白分隔
+///
+/// ## 错误
+///
+/// - `EINVAL`：设备名为空或者包含'/'，或者目标的参数不合法
+/// - `EBUSY`：已经有同名的映射设备
+/// - `ENOSPC`：映射设备的数量达到上限
+/// - `ENOENT`：不支持这种目标类型
+pub fn dm_create(
+    name: &str,
+    target_type: &str,
+    params: &str,
+) -> Result<Arc<MappedDevice>, SystemError> {
+    if name.is_empty() || name.contains('/') {
+        return Err(SystemError::EINVAL);
+    }
+    let tt = DM_TARGET_TYPES
+        .iter()
+        .find(|tt| tt.name == target_type)
+        .ok_or(SystemError::ENOENT)?;
+
+    let params: Vec<&str> = params.split_whitespace().collect();
+    let target = (tt.ctr)(&params)?;
+
+    let mut devices = MAPPED_DEVICES.write();
+    if devices.iter().any(|md| md.name == name) {
+        return Err(SystemError::EBUSY);
+    }
+    // 使用最小的未被占用的编号
+    let index = (0..DM_MAX_DEVICES)
+        .find(|i| devices.iter().all(|md| md.devnum.minor() != *i))
+        .ok_or(SystemError::ENOSPC)?;
+    let devnum = DeviceNumber::new(DeviceNumber::from_major_minor(DM_MAJOR, index));
+    let md = MappedDevice::new(name.to_string(), index, devnum, target);
+
+    let dev = md.clone() as Arc<dyn Device>;
+    device_manager().register(dev.clone())?;
+    if let Err(e) = class_manager().devnode_register(&dev, DmInode::new(md.clone())) {
+        device_manager().unregister(dev);
+        return Err(e);
+    }
+    devices.push(md.clone());
+    kinfo!(
+        "dm: created {} ({}), target {}, {} sectors",
+        md.name,
+        md.dm_name,
+        target_type,
+        md.sectors
+    );
+    return Ok(md);
+}
+
+/// 删除映射设备
+///
+/// ## 错误
+///
+/// - `ENXIO`：没有这个映射设备
+/// - `EBUSY`：映射设备是其他映射设备的下层设备
+pub fn dm_remove(name: &str) -> Result<(), SystemError> {
+    let mut devices = MAPPED_DEVICES.write();
+    let index = devices
+        .iter()
+        .position(|md| md.name == name)
+        .ok_or(SystemError::ENXIO)?;
+    if devices[index].open_count.load(Ordering::SeqCst) != 0 {
+        return Err(SystemError::EBUSY);
+    }
+
+    let md = devices.remove(index);
+    drop(devices);
+    if let Err(e) = BlockDevice::sync(md.as_ref()) {
+        kwarn!("dm {}: failed to sync: {:?}", md.name, e);
+    }
+    device_manager().unregister(md as Arc<dyn Device>);
+    return Ok(());
+}
+
+/// 处理`/dev/mapper/control`的ioctl
+fn dm_ctl_ioctl(cmd: u32, data: usize) -> Result<usize, SystemError> {
+    let ptr = UserPtr::<DmIoctl>::new(data);
+    let mut param = ptr.read()?;
+    let name = cstr_field(&param.name)?;
+
+    let md = match cmd {
+        DM_DEV_CREATE => {
+            let target_type = cstr_field(&param.target_type)?;
+            let params = check_and_clone_cstr(param.params as *const u8, Some(DM_PARAMS_MAX))?;
+            dm_create(name, target_type, &params)?
+        }
+        DM_DEV_REMOVE => {
+            dm_remove(name)?;
+            return Ok(0);
+        }
+        DM_DEV_STATUS => find_mapped_device(name).ok_or(SystemError::ENXIO)?,
+        _ => return Err(SystemError::ENOTTY),
+    };
+
+    param.dev = Into::<usize>::into(md.devnum) as u64;
+    param.sectors = md.sectors;
+    ptr.write(&param)?;
+    return Ok(0);
+}
+
+/// `/dev/mapper/control`
+#[derive(Debug)]
+pub struct DmControlInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl DmControlInode {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o600),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: make_rawdev(MISC_MAJOR, MAPPER_CTRL_MINOR),
+            },
+        })
+    }
+}
+
+impl DeviceINode for DmControlInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for DmControlInode {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.fs.lock().upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn ioctl(&self, cmd: u32, data: usize) -> Result<usize, SystemError> {
+        return dm_ctl_ioctl(cmd, data);
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+}
+
+/// 注册映射设备的设备号，并创建`/dev/mapper/control`
+pub fn dm_init() -> Result<(), SystemError> {
+    BlockDeviceOps::register_blockdev_region(
+        DeviceNumber::new(DeviceNumber::from_major_minor(DM_MAJOR, 0)),
+        DM_MAX_DEVICES,
+        "device-mapper",
+    )?;
+    devfs_register("mapper/control", DmControlInode::new())?;
+    return Ok(());
+}
@@ -0,0 +1,154 @@
+//! crypt目标：透明加密的映射设备
+//!
+//! 下层设备上保存的是密文，读取时解密，写入时加密。每个512字节的扇区单独加密，
+//! 初始向量由扇区号生成，因此相同的明文位于不同扇区时密文也不同。
+//!
+//! 参数的格式与Linux相同(不支持可选参数)：
+//!
+//! `<cipher> <key> <iv_offset> <device path> <offset>`
+//!
+//! - `cipher`：`分组密码-工作模式-初始向量模式`，目前只支持`aes-xts-plain64`与`aes-xts-plain`
+//! - `key`：十六进制表示的密钥，XTS模式下的长度为AES密钥的两倍，即32或者64字节
+//! - `iv_offset`：生成初始向量时加到扇区号上的值
+//! - `device path`：下层设备，见[`DmDev::open`]
+//! - `offset`：加密数据在下层设备上的起始扇区
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/md/dm-crypt.c
+
+use alloc::boxed::Box;
+
+use crate::{
+    crypto::{
+        crypto_alloc_xts,
+        xts::{Xts, XTS_BLOCK_SIZE},
+    },
+    driver::base::block::block_device::LBA_SIZE,
+    syscall::SystemError,
+};
+
+use super::dm::{decode_hex, DmDev, DmTarget};
+
+/// 初始向量的生成方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IvMode {
+    /// 扇区号的低32位，小端字节序
+    Plain,
+    /// 64位扇区号，小端字节序
+    Plain64,
+}
+
+impl IvMode {
+    fn generate(&self, sector: u64) -> [u8; XTS_BLOCK_SIZE] {
+        let mut iv = [0u8; XTS_BLOCK_SIZE];
+        match self {
+            IvMode::Plain => iv[..4].copy_from_slice(&(sector as u32).to_le_bytes()),
+            IvMode::Plain64 => iv[..8].copy_from_slice(&sector.to_le_bytes()),
+        }
+        return iv;
+    }
+}
+
+#[derive(Debug)]
+pub struct CryptTarget {
+    dev: DmDev,
+    xts: Xts,
+    iv_mode: IvMode,
+    iv_offset: u64,
+    /// 加密数据在下层设备上的起始扇区
+    start: u64,
+    sectors: u64,
+}
+
+impl CryptTarget {
+    /// 根据参数创建crypt目标
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：参数的数量或者格式不正确，或者偏移量超过了下层设备的大小
+    /// - `ENOENT`：不支持这种加密方式
+    /// - `ENODEV`：下层设备不存在
+    pub fn ctr(args: &[&str]) -> Result<Box<dyn DmTarget>, SystemError> {
+        if args.len() != 5 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let (cipher, chain_mode, iv_mode) = parse_cipher(args[0])?;
+        let iv_mode = match iv_mode {
+            "plain" => IvMode::Plain,
+            "plain64" => IvMode::Plain64,
+            _ => return Err(SystemError::ENOENT),
+        };
+        if chain_mode != "xts" {
+            return Err(SystemError::ENOENT);
+        }
+        let mut xts = crypto_alloc_xts(&format!("xts({})", cipher))?;
+
+        let mut key = decode_hex(args[1])?;
+        let r = xts.set_key(&key);
+        key.fill(0);
+        r?;
+
+        let iv_offset = args[2].parse::<u64>().map_err(|_| SystemError::EINVAL)?;
+        let dev = DmDev::open(args[3])?;
+        let start = args[4].parse::<u64>().map_err(|_| SystemError::EINVAL)?;
+        if start >= dev.sectors() {
+            return Err(SystemError::EINVAL);
+        }
+        let sectors = dev.sectors() - start;
+
+        return Ok(Box::new(Self {
+            dev,
+            xts,
+            iv_mode,
+            iv_offset,
+            start,
+            sectors,
+        }));
+    }
+
+    /// 原地加密或者解密从`sector`开始的扇区
+    fn crypt(&self, sector: u64, buf: &mut [u8], encrypt: bool) -> Result<(), SystemError> {
+        for (i, data) in buf.chunks_exact_mut(LBA_SIZE).enumerate() {
+            let iv = self.iv_mode.generate(sector + i as u64 + self.iv_offset);
+            if encrypt {
+                self.xts.encrypt(&iv, data)?;
+            } else {
+                self.xts.decrypt(&iv, data)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl DmTarget for CryptTarget {
+    fn sectors(&self) -> u64 {
+        return self.sectors;
+    }
+
+    fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        self.dev.read(self.start + sector, buf)?;
+        return self.crypt(sector, buf, false);
+    }
+
+    fn write(&self, sector: u64, buf: &[u8]) -> Result<(), SystemError> {
+        // 不能修改调用者的缓冲区，在副本上加密
+        let mut data = buf.to_vec();
+        self.crypt(sector, &mut data, true)?;
+        return self.dev.write(self.start + sector, &data);
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        return self.dev.sync();
+    }
+}
+
+/// 把`aes-xts-plain64`形式的加密方式拆分为分组密码、工作模式与初始向量模式
+fn parse_cipher(spec: &str) -> Result<(&str, &str, &str), SystemError> {
+    let mut parts = spec.splitn(3, '-');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(cipher), Some(chain_mode), Some(iv_mode)) => {
+            return Ok((cipher, chain_mode, iv_mode))
+        }
+        _ => return Err(SystemError::EINVAL),
+    }
+}
@@ -0,0 +1,278 @@
+//! verity目标：只读的、校验数据完整性的映射设备
+//!
+//! 数据设备上的每个数据块都有一个摘要，这些摘要组成哈希块，哈希块的摘要又组成上一层的哈希块，
+//! 直到最上层只剩一个哈希块，它的摘要就是根摘要。哈希树事先生成并保存在哈希设备上，
+//! 根摘要则由创建映射设备的一方提供。读取数据块时，从根摘要开始逐层校验哈希块，最后校验数据块本身，
+//! 任何一层不匹配都会使读取失败。因此只要根摘要可信，读到的数据就与生成哈希树时完全相同。
+//!
+//! 哈希树的格式与Linux的dm-verity相同，可以使用veritysetup生成。参数的格式也与Linux相同(不支持可选参数)：
+//!
+//! `<version> <data_dev> <hash_dev> <data_block_size> <hash_block_size> <num_data_blocks> <hash_start_block> <algorithm> <root_digest> <salt>`
+//!
+//! - `version`：0表示摘要为H(数据 ‖ 盐)，1表示摘要为H(盐 ‖ 数据)
+//! - `data_dev`、`hash_dev`：数据设备与哈希设备，见[`DmDev::open`]，两者可以是同一个设备
+//! - `data_block_size`、`hash_block_size`：数据块与哈希块的字节数，必须是512到4096之间的2的幂
+//! - `num_data_blocks`：数据块的数量
+//! - `hash_start_block`：哈希树在哈希设备上的起始位置，以哈希块为单位
+//! - `algorithm`：哈希算法，见[`crypto_alloc_hash`]
+//! - `root_digest`、`salt`：十六进制表示的根摘要与盐，没有盐时为`-`
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/md/dm-verity-target.c
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+
+use crate::{
+    crypto::{crypto_alloc_hash, Hash},
+    driver::base::block::block_device::LBA_SIZE,
+    kerror,
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+};
+
+use super::dm::{decode_hex, DmDev, DmTarget};
+
+/// 哈希树的最大层数
+const DM_VERITY_MAX_LEVELS: usize = 63;
+/// 缓存的已校验哈希块的数量
+const DM_VERITY_HASH_CACHE_BLOCKS: usize = 128;
+
+#[derive(Debug)]
+pub struct VerityTarget {
+    data_dev: DmDev,
+    hash_dev: DmDev,
+    version: u32,
+    data_block_bits: u32,
+    hash_block_bits: u32,
+    data_blocks: u64,
+    hash: SpinLock<Box<dyn Hash>>,
+    digest_size: usize,
+    root_digest: Vec<u8>,
+    salt: Vec<u8>,
+    /// 每个哈希块中摘要数量的log2
+    hash_per_block_bits: u32,
+    levels: usize,
+    /// 每一层哈希块在哈希设备上的起始块号，第0层是最下层
+    hash_level_block: Vec<u64>,
+    /// 已经校验过的哈希块，键为哈希块号
+    verified: SpinLock<BTreeMap<u64, Vec<u8>>>,
+}
+
+impl VerityTarget {
+    /// 根据参数创建verity目标
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：参数的数量或者格式不正确，或者设备的大小不足以容纳数据或者哈希树
+    /// - `ENOENT`：不支持这种哈希算法
+    /// - `ENODEV`：数据设备或者哈希设备不存在
+    pub fn ctr(args: &[&str]) -> Result<Box<dyn DmTarget>, SystemError> {
+        if args.len() != 10 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let version = parse_num(args[0])? as u32;
+        if version > 1 {
+            return Err(SystemError::EINVAL);
+        }
+        let data_block_bits = parse_block_size(args[3])?;
+        let hash_block_bits = parse_block_size(args[4])?;
+        let data_blocks = parse_num(args[5])?;
+        let hash_start = parse_num(args[6])?;
+        if data_blocks == 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let hash = crypto_alloc_hash(args[7])?;
+        let digest_size = hash.digest_size();
+        let root_digest = decode_hex(args[8])?;
+        if root_digest.len() != digest_size {
+            return Err(SystemError::EINVAL);
+        }
+        let salt = match args[9] {
+            "-" => Vec::new(),
+            s => decode_hex(s)?,
+        };
+
+        // 一个哈希块中的摘要数量向下取整到2的幂，每个摘要占用的空间也随之对齐到2的幂
+        let digests_per_block = (1usize << hash_block_bits) / digest_size;
+        if digests_per_block < 2 {
+            return Err(SystemError::EINVAL);
+        }
+        let hash_per_block_bits = digests_per_block.ilog2();
+
+        let mut levels = 0;
+        while (hash_per_block_bits as usize) * levels < 64
+            && (data_blocks - 1) >> (hash_per_block_bits as usize * levels) != 0
+        {
+            levels += 1;
+        }
+        if levels > DM_VERITY_MAX_LEVELS {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 最上层位于哈希树的开头，之后依次是下面的各层
+        let mut hash_level_block = vec![0u64; levels];
+        let mut hash_position = hash_start;
+        for i in (0..levels).rev() {
+            hash_level_block[i] = hash_position;
+            let shift = (i + 1) * hash_per_block_bits as usize;
+            let count = if shift >= 64 {
+                1
+            } else {
+                ((data_blocks - 1) >> shift) + 1
+            };
+            hash_position = hash_position
+                .checked_add(count)
+                .ok_or(SystemError::EINVAL)?;
+        }
+
+        let data_dev = DmDev::open(args[1])?;
+        let hash_dev = DmDev::open(args[2])?;
+        if data_dev.sectors() >> (data_block_bits - 9) < data_blocks
+            || hash_dev.sectors() >> (hash_block_bits - 9) < hash_position
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        return Ok(Box::new(Self {
+            data_dev,
+            hash_dev,
+            version,
+            data_block_bits,
+            hash_block_bits,
+            data_blocks,
+            hash: SpinLock::new(hash),
+            digest_size,
+            root_digest,
+            salt,
+            hash_per_block_bits,
+            levels,
+            hash_level_block,
+            verified: SpinLock::new(BTreeMap::new()),
+        }));
+    }
+
+    /// 计算数据块或者哈希块的摘要
+    fn hash_of(&self, data: &[u8]) -> Result<Vec<u8>, SystemError> {
+        let mut digest = vec![0u8; self.digest_size];
+        let mut hash = self.hash.lock();
+        hash.reset();
+        if self.version >= 1 {
+            hash.update(&self.salt);
+        }
+        hash.update(data);
+        if self.version == 0 {
+            hash.update(&self.salt);
+        }
+        hash.finalize(&mut digest)?;
+        return Ok(digest);
+    }
+
+    /// 计算数据块`block`的摘要在第`level`层中的位置
+    ///
+    /// ## 返回值
+    ///
+    /// (哈希块号, 摘要在哈希块中的字节偏移量)
+    fn hash_at_level(&self, block: u64, level: usize) -> (u64, usize) {
+        let position = block >> (level * self.hash_per_block_bits as usize);
+        let hash_block = self.hash_level_block[level] + (position >> self.hash_per_block_bits);
+        let index = (position & ((1 << self.hash_per_block_bits) - 1)) as usize;
+        let offset = index << (self.hash_block_bits - self.hash_per_block_bits);
+        return (hash_block, offset);
+    }
+
+    /// 读取哈希块，并校验它的摘要是否等于`want`
+    ///
+    /// ## 错误
+    ///
+    /// - `EIO`：哈希块已被篡改
+    fn read_hash_block(&self, hash_block: u64, want: &[u8]) -> Result<Vec<u8>, SystemError> {
+        if let Some(data) = self.verified.lock().get(&hash_block) {
+            return Ok(data.clone());
+        }
+
+        let mut data = vec![0u8; 1 << self.hash_block_bits];
+        self.hash_dev
+            .read(hash_block << (self.hash_block_bits - 9), &mut data)?;
+        if self.hash_of(&data)? != want {
+            kerror!("dm-verity: hash block {} is corrupted", hash_block);
+            return Err(SystemError::EIO);
+        }
+
+        let mut verified = self.verified.lock();
+        if verified.len() >= DM_VERITY_HASH_CACHE_BLOCKS {
+            verified.pop_last();
+        }
+        verified.insert(hash_block, data.clone());
+        return Ok(data);
+    }
+
+    /// 读取并校验一个数据块
+    ///
+    /// ## 错误
+    ///
+    /// - `EIO`：数据块或者哈希树已被篡改
+    fn read_data_block(&self, block: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        // 从最上层开始，每一层的哈希块都由上一层的摘要校验
+        let mut want = self.root_digest.clone();
+        for level in (0..self.levels).rev() {
+            let (hash_block, offset) = self.hash_at_level(block, level);
+            let data = self.read_hash_block(hash_block, &want)?;
+            want = data[offset..offset + self.digest_size].to_vec();
+        }
+
+        self.data_dev
+            .read(block << (self.data_block_bits - 9), buf)?;
+        if self.hash_of(buf)? != want {
+            kerror!("dm-verity: data block {} is corrupted", block);
+            return Err(SystemError::EIO);
+        }
+        return Ok(());
+    }
+}
+
+impl DmTarget for VerityTarget {
+    fn sectors(&self) -> u64 {
+        return self.data_blocks << (self.data_block_bits - 9);
+    }
+
+    fn read(&self, sector: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        // 数据块可能比扇区大，总是读取并校验完整的数据块，再复制需要的部分
+        let block_size = 1usize << self.data_block_bits;
+        let start = sector as usize * LBA_SIZE;
+        let end = start + buf.len();
+        let mut data = vec![0u8; block_size];
+        let mut pos = start;
+        while pos < end {
+            let block = (pos >> self.data_block_bits) as u64;
+            let block_start = block as usize * block_size;
+            let n = core::cmp::min(block_start + block_size, end) - pos;
+            self.read_data_block(block, &mut data)?;
+            buf[pos - start..pos - start + n]
+                .copy_from_slice(&data[pos - block_start..pos - block_start + n]);
+            pos += n;
+        }
+        return Ok(());
+    }
+
+    fn write(&self, _sector: u64, _buf: &[u8]) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        return Ok(());
+    }
+}
+
+fn parse_num(s: &str) -> Result<u64, SystemError> {
+    return s.parse::<u64>().map_err(|_| SystemError::EINVAL);
+}
+
+/// 解析块大小，返回它的log2
+fn parse_block_size(s: &str) -> Result<u32, SystemError> {
+    let size = parse_num(s)?;
+    if !size.is_power_of_two() || !(LBA_SIZE as u64..=4096).contains(&size) {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(size.ilog2());
+}
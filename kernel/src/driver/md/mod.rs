@@ -0,0 +1,8 @@
+//! 多设备(multiple devices)驱动
+//!
+//! - [`dm`]：映射设备(device mapper)，把对虚拟块设备的读写转换为对其他块设备的读写
+//! - [`dm_crypt`]：透明加密的映射设备目标
+//! - [`dm_verity`]：只读的、使用哈希树校验数据完整性的映射设备目标
+pub mod dm;
+pub mod dm_crypt;
+pub mod dm_verity;
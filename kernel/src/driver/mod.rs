@@ -1,10 +1,19 @@
 pub mod acpi;
 pub mod base;
+pub mod cpufreq;
+pub mod cpuidle;
 pub mod disk;
+pub mod input;
+pub mod iommu;
 pub mod keyboard;
+pub mod md;
+pub mod mouse;
 pub mod net;
+pub mod open_firmware;
 pub mod pci;
+pub mod power_supply;
 pub mod timers;
 pub mod tty;
+pub mod usb;
 pub mod video;
 pub mod virtio;
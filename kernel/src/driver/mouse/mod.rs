@@ -0,0 +1 @@
+pub mod ps2_mouse;
@@ -0,0 +1,334 @@
+//! PS/2鼠标驱动
+//!
+//! 鼠标连接在i8042控制器的辅助端口(aux port)上。初始化时以轮询的方式复位鼠标，并通过设置采样率的
+//! "魔法序列"检测鼠标是否支持滚轮(IntelliMouse, ID=3)以及第4、5键(IntelliMouse Explorer, ID=4)。
+//! 之后鼠标的每个数据包通过中断送到这里解析，以相对位移、按键事件的形式上报给输入子系统。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/mouse/psmouse-base.c
+
+use alloc::sync::Arc;
+
+use crate::{
+    arch::{io::PortIOArch, CurrentIrqArch, CurrentPortIOArch},
+    driver::input::{
+        input_register_device, InputDevice, InputId, BTN_EXTRA, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT,
+        BTN_SIDE, BUS_I8042, EV_KEY, EV_REL, REL_WHEEL, REL_X, REL_Y,
+    },
+    exception::InterruptArch,
+    kinfo, kwarn,
+    libs::{rwlock::RwLock, spinlock::SpinLock},
+    syscall::SystemError,
+};
+
+const PORT_PS2_DATA: u16 = 0x60;
+const PORT_PS2_STATUS: u16 = 0x64;
+const PORT_PS2_COMMAND: u16 = 0x64;
+
+/// 状态寄存器：输出缓冲区已满，可以从数据端口读取
+const PS2_STATUS_OUTBUF_FULL: u8 = 0x01;
+/// 状态寄存器：输入缓冲区已满，控制器尚未取走上一个写入的字节
+const PS2_STATUS_INBUF_FULL: u8 = 0x02;
+/// 状态寄存器：输出缓冲区中的数据来自辅助端口
+const PS2_STATUS_AUX_DATA: u8 = 0x20;
+
+/// 控制器命令：开启辅助端口
+const I8042_CMD_AUX_ENABLE: u8 = 0xa8;
+/// 控制器命令：把下一个写入数据端口的字节发送给辅助端口上的设备
+const I8042_CMD_AUX_SEND: u8 = 0xd4;
+
+// 鼠标的命令
+const PS2_MOUSE_GET_ID: u8 = 0xf2;
+const PS2_MOUSE_SET_RATE: u8 = 0xf3;
+const PS2_MOUSE_ENABLE: u8 = 0xf4;
+const PS2_MOUSE_DISABLE: u8 = 0xf5;
+const PS2_MOUSE_RESET: u8 = 0xff;
+
+/// 鼠标对命令的应答
+const PS2_MOUSE_ACK: u8 = 0xfa;
+/// 鼠标复位之后，自检通过的应答
+const PS2_MOUSE_BAT_OK: u8 = 0xaa;
+
+/// 支持滚轮的鼠标(IntelliMouse)的ID
+const PS2_MOUSE_ID_IMPS: u8 = 3;
+/// 支持滚轮以及第4、5键的鼠标(IntelliMouse Explorer)的ID
+const PS2_MOUSE_ID_EXPS: u8 = 4;
+
+/// 等待控制器的轮询次数，超过之后认为没有连接鼠标
+const PS2_POLL_TIMEOUT: usize = 1000000;
+
+lazy_static! {
+    /// 鼠标对应的输入设备
+    static ref PS2_MOUSE_INPUT: RwLock<Option<Arc<InputDevice>>> = RwLock::new(None);
+}
+
+static PS2_MOUSE_DECODER: SpinLock<Ps2MouseDecoder> = SpinLock::new(Ps2MouseDecoder {
+    packet: [0; 4],
+    count: 0,
+    packet_size: 3,
+    mouse_id: 0,
+    skip_ack: false,
+});
+
+/// @brief 把鼠标发送的字节组装为数据包，并解析为输入事件
+///
+/// 数据包的格式：
+/// - byte0: [y溢出, x溢出, y符号位, x符号位, 1, 中键, 右键, 左键]
+/// - byte1, byte2: x、y方向的位移(与byte0中的符号位组成9位补码)，y轴向上为正
+/// - byte3: 仅ID=3、ID=4的鼠标有。ID=3时为滚轮的位移；ID=4时为[0, 0, 第5键, 第4键, 滚轮的位移(4位补码)]
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/mouse/psmouse-base.c#psmouse_process_byte
+#[derive(Debug)]
+struct Ps2MouseDecoder {
+    packet: [u8; 4],
+    /// 已经收到的字节数
+    count: usize,
+    /// 数据包的长度，ID=0时为3，ID=3或ID=4时为4
+    packet_size: usize,
+    mouse_id: u8,
+    /// 下一个字节是开启数据包发送命令的应答，需要丢弃
+    skip_ack: bool,
+}
+
+impl Ps2MouseDecoder {
+    /// @brief 接收一个字节
+    ///
+    /// @return 收到完整的数据包时返回它
+    fn receive(&mut self, byte: u8) -> Option<[u8; 4]> {
+        if self.skip_ack {
+            self.skip_ack = false;
+            if byte == PS2_MOUSE_ACK {
+                return None;
+            }
+        }
+        // 第一个字节的第3位总是1，不是1说明丢失了字节，丢弃直到重新同步
+        if self.count == 0 && byte & 0x08 == 0 {
+            return None;
+        }
+        self.packet[self.count] = byte;
+        self.count += 1;
+        if self.count < self.packet_size {
+            return None;
+        }
+        self.count = 0;
+        return Some(self.packet);
+    }
+
+    /// @brief 把数据包上报给输入子系统
+    fn report(&self, dev: &InputDevice, packet: &[u8; 4]) {
+        let byte0 = packet[0];
+        dev.report_key(BTN_LEFT, byte0 & 0x01 != 0);
+        dev.report_key(BTN_RIGHT, byte0 & 0x02 != 0);
+        dev.report_key(BTN_MIDDLE, byte0 & 0x04 != 0);
+
+        match self.mouse_id {
+            PS2_MOUSE_ID_IMPS => {
+                dev.report_rel(REL_WHEEL, -(packet[3] as i8 as i32));
+            }
+            PS2_MOUSE_ID_EXPS => {
+                // 滚轮的位移为低4位的补码
+                let wheel = ((packet[3] << 4) as i8 >> 4) as i32;
+                dev.report_rel(REL_WHEEL, -wheel);
+                dev.report_key(BTN_SIDE, packet[3] & 0x10 != 0);
+                dev.report_key(BTN_EXTRA, packet[3] & 0x20 != 0);
+            }
+            _ => {}
+        }
+
+        // 溢出时位移的值不可信，丢弃位移
+        if byte0 & 0xc0 == 0 {
+            let dx = packet[1] as i32 - (((byte0 as i32) << 4) & 0x100);
+            let dy = packet[2] as i32 - (((byte0 as i32) << 3) & 0x100);
+            dev.report_rel(REL_X, dx);
+            // 输入子系统中y轴向下为正
+            dev.report_rel(REL_Y, -dy);
+        }
+        dev.sync();
+    }
+}
+
+/// @brief 以轮询的方式访问i8042控制器上的鼠标，只在初始化、尚未开启鼠标中断时使用
+struct Ps2AuxPort;
+
+impl Ps2AuxPort {
+    /// 等待控制器取走上一个写入的字节
+    fn wait_write() -> Result<(), SystemError> {
+        for _ in 0..PS2_POLL_TIMEOUT {
+            if unsafe { CurrentPortIOArch::in8(PORT_PS2_STATUS) } & PS2_STATUS_INBUF_FULL == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        return Err(SystemError::ETIMEDOUT);
+    }
+
+    /// 读取鼠标发送的一个字节。键盘发送的字节由键盘中断处理，这里不会读取
+    fn read() -> Result<u8, SystemError> {
+        for _ in 0..PS2_POLL_TIMEOUT {
+            let status = unsafe { CurrentPortIOArch::in8(PORT_PS2_STATUS) };
+            if status & (PS2_STATUS_OUTBUF_FULL | PS2_STATUS_AUX_DATA)
+                == PS2_STATUS_OUTBUF_FULL | PS2_STATUS_AUX_DATA
+            {
+                return Ok(unsafe { CurrentPortIOArch::in8(PORT_PS2_DATA) });
+            }
+            core::hint::spin_loop();
+        }
+        return Err(SystemError::ETIMEDOUT);
+    }
+
+    /// 向控制器发送命令
+    fn controller_command(cmd: u8) -> Result<(), SystemError> {
+        Self::wait_write()?;
+        unsafe { CurrentPortIOArch::out8(PORT_PS2_COMMAND, cmd) };
+        return Ok(());
+    }
+
+    /// 向鼠标发送一个字节(不等待应答)
+    fn write(byte: u8) -> Result<(), SystemError> {
+        Self::controller_command(I8042_CMD_AUX_SEND)?;
+        Self::wait_write()?;
+        unsafe { CurrentPortIOArch::out8(PORT_PS2_DATA, byte) };
+        return Ok(());
+    }
+
+    /// 向鼠标发送一个字节，并等待应答
+    fn command(byte: u8) -> Result<(), SystemError> {
+        Self::write(byte)?;
+        if Self::read()? != PS2_MOUSE_ACK {
+            return Err(SystemError::EIO);
+        }
+        return Ok(());
+    }
+
+    fn set_rate(rate: u8) -> Result<(), SystemError> {
+        Self::command(PS2_MOUSE_SET_RATE)?;
+        return Self::command(rate);
+    }
+
+    fn get_id() -> Result<u8, SystemError> {
+        Self::command(PS2_MOUSE_GET_ID)?;
+        return Self::read();
+    }
+
+    /// @brief 依次设置采样率，然后读取鼠标的ID。支持扩展协议的鼠标在收到对应的序列之后会切换协议
+    fn knock(rates: &[u8]) -> Result<u8, SystemError> {
+        for rate in rates {
+            Self::set_rate(*rate)?;
+        }
+        return Self::get_id();
+    }
+}
+
+/// @brief 复位鼠标，并检测鼠标支持的协议
+///
+/// @return 鼠标的ID
+fn ps2_mouse_detect() -> Result<u8, SystemError> {
+    Ps2AuxPort::controller_command(I8042_CMD_AUX_ENABLE)?;
+
+    Ps2AuxPort::command(PS2_MOUSE_RESET)?;
+    if Ps2AuxPort::read()? != PS2_MOUSE_BAT_OK {
+        return Err(SystemError::EIO);
+    }
+    // 自检通过之后，鼠标发送它的ID
+    Ps2AuxPort::read()?;
+    Ps2AuxPort::command(PS2_MOUSE_DISABLE)?;
+
+    // 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/input/mouse/psmouse-base.c#intellimouse_detect
+    let mut id = Ps2AuxPort::knock(&[200, 100, 80])?;
+    if id == PS2_MOUSE_ID_IMPS {
+        // 根据规范，需要先切换到ID=3，才能切换到ID=4
+        let exps_id = Ps2AuxPort::knock(&[200, 200, 80])?;
+        if exps_id == PS2_MOUSE_ID_EXPS {
+            id = exps_id;
+        }
+    } else {
+        id = 0;
+    }
+    Ps2AuxPort::set_rate(100)?;
+    return Ok(id);
+}
+
+/// 创建鼠标对应的输入设备，并注册到输入子系统
+fn ps2_mouse_input_register(mouse_id: u8) {
+    let name = match mouse_id {
+        PS2_MOUSE_ID_IMPS => "ImPS/2 Generic Wheel Mouse",
+        PS2_MOUSE_ID_EXPS => "ImExPS/2 Generic Explorer Mouse",
+        _ => "PS/2 Generic Mouse",
+    };
+    let mut dev = InputDevice::new(
+        name,
+        InputId {
+            bustype: BUS_I8042,
+            vendor: 0x0002,
+            product: mouse_id as u16,
+            version: 0,
+        },
+    );
+    dev.set_capability(EV_KEY, BTN_LEFT);
+    dev.set_capability(EV_KEY, BTN_RIGHT);
+    dev.set_capability(EV_KEY, BTN_MIDDLE);
+    dev.set_capability(EV_REL, REL_X);
+    dev.set_capability(EV_REL, REL_Y);
+    if mouse_id == PS2_MOUSE_ID_IMPS || mouse_id == PS2_MOUSE_ID_EXPS {
+        dev.set_capability(EV_REL, REL_WHEEL);
+    }
+    if mouse_id == PS2_MOUSE_ID_EXPS {
+        dev.set_capability(EV_KEY, BTN_SIDE);
+        dev.set_capability(EV_KEY, BTN_EXTRA);
+    }
+    *PS2_MOUSE_INPUT.write_irqsave() = Some(input_register_device(dev));
+}
+
+/// @brief 检测并初始化PS/2鼠标，在注册鼠标中断之前调用
+///
+/// @return 成功返回0；没有连接鼠标或者鼠标没有正确应答时返回错误码
+#[no_mangle]
+pub extern "C" fn ps2_mouse_probe() -> i32 {
+    // 轮询期间关中断，防止键盘中断处理函数读走鼠标的应答
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let r = ps2_mouse_detect();
+    drop(irq_guard);
+
+    let mouse_id = match r {
+        Ok(id) => id,
+        Err(e) => {
+            kwarn!("ps/2 mouse: probe failed: {:?}", e);
+            return e.to_posix_errno();
+        }
+    };
+
+    let mut decoder = PS2_MOUSE_DECODER.lock_irqsave();
+    decoder.mouse_id = mouse_id;
+    decoder.packet_size = if mouse_id == 0 { 3 } else { 4 };
+    decoder.count = 0;
+    drop(decoder);
+
+    ps2_mouse_input_register(mouse_id);
+    kinfo!("ps/2 mouse: detected, id={}", mouse_id);
+    return 0;
+}
+
+/// @brief 开启鼠标的数据包发送，在注册鼠标中断之后调用
+///
+/// 命令的应答由中断处理函数收到并丢弃
+#[no_mangle]
+pub extern "C" fn ps2_mouse_enable() -> i32 {
+    PS2_MOUSE_DECODER.lock_irqsave().skip_ack = true;
+    if let Err(e) = Ps2AuxPort::write(PS2_MOUSE_ENABLE) {
+        PS2_MOUSE_DECODER.lock_irqsave().skip_ack = false;
+        return e.to_posix_errno();
+    }
+    return 0;
+}
+
+/// @brief 鼠标中断收到一个字节时调用，收到完整的数据包时上报给输入子系统
+#[no_mangle]
+pub extern "C" fn ps2_mouse_parse_byte(byte: u8) {
+    let dev = match PS2_MOUSE_INPUT.read().clone() {
+        Some(dev) => dev,
+        None => return,
+    };
+    let mut decoder = PS2_MOUSE_DECODER.lock_irqsave();
+    if let Some(packet) = decoder.receive(byte) {
+        decoder.report(&dev, &packet);
+    }
+}
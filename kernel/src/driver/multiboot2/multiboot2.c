@@ -24,6 +24,12 @@ bool multiboot2_init(uint64_t mb2_info_paddr, uint32_t mb2_magic)
   return true;
 }
 
+const uint8_t *multiboot2_raw_info(unsigned int *size)
+{
+  *size = multiboot2_boot_info_size;
+  return mbi_raw;
+}
+
 void multiboot2_iter(bool (*_fun)(const struct iter_data_t *, void *, unsigned int *),
                      void *data, unsigned int *count)
 {
@@ -148,4 +154,28 @@ bool multiboot2_get_acpi_new_RSDP(const struct iter_data_t *_iter_data, void *da
     return false;
   *(struct multiboot_tag_new_acpi_t *)data = *(struct multiboot_tag_new_acpi_t *)_iter_data;
   return true;
-}
\ No newline at end of file
+}
+
+/**
+ * @brief 获取内核启动参数
+ *
+ * @param _iter_data 要被迭代的信息的结构体
+ * @param data 保存启动参数的缓冲区，返回的字符串以'\0'结尾
+ * @param count 传入缓冲区的大小，返回启动参数的长度
+ */
+bool multiboot2_get_cmdline(const struct iter_data_t *_iter_data, void *data, unsigned int *count)
+{
+  if (_iter_data->type != MULTIBOOT_TAG_TYPE_CMDLINE)
+    return false;
+  const char *str = ((const struct multiboot_tag_string_t *)_iter_data)->string;
+  char *buf = (char *)data;
+  unsigned int len = 0;
+  while (len + 1 < *count && str[len] != '\0')
+  {
+    buf[len] = str[len];
+    ++len;
+  }
+  buf[len] = '\0';
+  *count = len;
+  return true;
+}
@@ -415,6 +415,14 @@ struct iter_data_t
  */
 bool multiboot2_init(uint64_t mb2_info_paddr, uint32_t mb2_magic);
 
+/**
+ * @brief 获取启动时保存的multiboot2信息的原始数据
+ *
+ * @param size 返回数据的字节数
+ * @return const uint8_t* 数据的起始地址
+ */
+const uint8_t *multiboot2_raw_info(unsigned int *size);
+
 /**
  * @brief 迭代器
  * @param  _fun            迭代操作
@@ -468,4 +476,13 @@ bool multiboot2_get_acpi_old_RSDP(const struct iter_data_t *_iter_data, void *da
  * @param reserved
  * @return uint8_t*  struct multiboot_tag_old_acpi_t
  */
-bool multiboot2_get_acpi_new_RSDP(const struct iter_data_t *_iter_data, void *data, unsigned int *reserved);
\ No newline at end of file
+bool multiboot2_get_acpi_new_RSDP(const struct iter_data_t *_iter_data, void *data, unsigned int *reserved);
+
+/**
+ * @brief 获取内核启动参数
+ *
+ * @param _iter_data 要被迭代的信息的结构体
+ * @param data 保存启动参数的缓冲区，返回的字符串以'\0'结尾
+ * @param count 传入缓冲区的大小，返回启动参数的长度
+ */
+bool multiboot2_get_cmdline(const struct iter_data_t *_iter_data, void *data, unsigned int *count);
@@ -1,7 +1,9 @@
 // 参考手册: PCIe* GbE Controllers Open Source Software Developer’s Manual
 // Refernce: PCIe* GbE Controllers Open Source Software Developer’s Manual
+// 8254x系列(e1000)参考手册: PCI/PCI-X Family of Gigabit Ethernet Controllers Software Developer’s Manual
+// 两者的寄存器布局与描述符格式基本一致，因此使用同一个驱动
 
-use alloc::vec::Vec;
+use alloc::{sync::Arc, vec::Vec};
 use core::intrinsics::unlikely;
 use core::mem::size_of;
 use core::ptr::NonNull;
@@ -9,16 +11,21 @@ use core::slice::{from_raw_parts, from_raw_parts_mut};
 use core::sync::atomic::{compiler_fence, Ordering};
 
 use super::e1000e_driver::e1000e_driver_init;
+use crate::driver::base::device::{device_manager, Device};
 use crate::driver::net::dma::{dma_alloc, dma_dealloc};
+use crate::driver::pci::device::pci_device_manager;
 use crate::driver::pci::pci::{
     get_pci_device_structure_mut, PciDeviceStructure, PciDeviceStructureGeneralDevice, PciError,
     PCI_DEVICE_LINKEDLIST,
 };
-use crate::driver::pci::pci_irq::{IrqCommonMsg, IrqMsg, IrqSpecificMsg, PciInterrupt, IRQ};
+use crate::driver::pci::pci_irq::{
+    IrqCommonMsg, IrqMsg, IrqSpecificMsg, PciInterrupt, PciIrqError, IRQ,
+};
 use crate::include::bindings::bindings::pt_regs;
 use crate::libs::volatile::{ReadOnly, Volatile, VolatileReadable, VolatileWritable, WriteOnly};
 use crate::net::net_core::poll_ifaces_try_lock_onetime;
-use crate::{kdebug, kinfo};
+use crate::syscall::SystemError;
+use crate::{kdebug, kerror, kinfo, kwarn};
 
 const PAGE_SIZE: usize = 4096;
 const NETWORK_CLASS: u8 = 0x2;
@@ -40,6 +47,30 @@ const E1000E_DEVICE_ID: [u16; 14] = [
     0x1503, // 82579V
     0x150c, // 82583V
 ];
+// e1000系列(8254x)网卡的device id列表，来源同上
+const E1000_DEVICE_ID: [u16; 12] = [
+    0x100e, // 82540EM, qemu -device e1000
+    0x1015, // 82540EM LOM
+    0x1016, // 82540EP LOM
+    0x1017, // 82540EP
+    0x100f, // 82545EM copper
+    0x1011, // 82545EM fiber
+    0x1026, // 82545GM copper
+    0x1010, // 82546EB copper
+    0x1079, // 82546GB copper
+    0x1076, // 82541GI
+    0x107c, // 82541PI
+    0x1019, // 82547EI
+];
+
+/// 网卡所属的系列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E1000EKind {
+    /// PCI/PCI-X接口的8254x系列
+    E1000,
+    /// PCIe接口的8257x及之后的系列
+    E1000E,
+}
 
 // e1000e网卡与BAR有关的常量
 // BAR0空间大小(128KB)
@@ -159,6 +190,7 @@ unsafe extern "C" fn e1000e_irq_handler(irq_num: u64, irq_paramer: u64, regs: *m
 }
 
 pub struct E1000EDevice {
+    kind: E1000EKind,
     // 设备寄存器
     // device registers
     general_regs: NonNull<GeneralRegs>,
@@ -194,10 +226,13 @@ pub struct E1000EDevice {
 impl E1000EDevice {
     // 从PCI标准设备进行驱动初始化
     // init the device for PCI standard device struct
-    pub fn new(device: &mut PciDeviceStructureGeneralDevice) -> Result<Self, E1000EPciError> {
+    pub fn new(
+        device: &mut PciDeviceStructureGeneralDevice,
+        kind: E1000EKind,
+    ) -> Result<Self, E1000EPciError> {
         // 从BAR0获取我们需要的寄存器
         // Build registers sturcts from BAR0
-        device.bar_ioremap().unwrap()?;
+        device.bar_ioremap().ok_or(E1000EPciError::BarGetFailed)??;
         device.enable_master();
         let bar = device.bar().ok_or(E1000EPciError::BarGetFailed)?;
         let bar0 = bar.get_bar(0)?;
@@ -217,21 +252,29 @@ impl E1000EDevice {
 
         // 初始化msi中断
         // initialize msi interupt
-        let irq_vector = device.irq_vector_mut().unwrap();
+        let irq_vector = device
+            .irq_vector_mut()
+            .ok_or(PciError::PciIrqError(PciIrqError::PciDeviceNotSupportIrq))?;
         irq_vector.push(E1000E_RECV_VECTOR);
-        device.irq_init(IRQ::PCI_IRQ_MSI).expect("IRQ Init Failed");
-        let msg = IrqMsg {
-            irq_common_message: IrqCommonMsg::init_from(
-                0,
-                "E1000E_RECV_IRQ",
-                0,
-                e1000e_irq_handler,
-                None,
-            ),
-            irq_specific_message: IrqSpecificMsg::msi_default(),
-        };
-        device.irq_install(msg)?;
-        device.irq_enable(true)?;
+        if device.irq_init(IRQ::PCI_IRQ_MSI).is_some() {
+            let msg = IrqMsg {
+                irq_common_message: IrqCommonMsg::init_from(
+                    0,
+                    "E1000E_RECV_IRQ",
+                    0,
+                    e1000e_irq_handler,
+                    None,
+                ),
+                irq_specific_message: IrqSpecificMsg::msi_default(),
+            };
+            device.irq_install(msg)?;
+            device.irq_enable(true)?;
+        } else {
+            // 部分8254x网卡(例如82540EM)不支持msi，而目前还不支持PCI的legacy中断，
+            // 此时由网络轮询线程定期收包
+            // the device does not support msi, packets will be received by the net poll thread
+            kwarn!("{:?}: msi is not supported, fallback to polling mode", kind);
+        }
 
         let general_regs: NonNull<GeneralRegs> =
             get_register_ptr(vaddress, E1000E_GENERAL_REGS_OFFSET);
@@ -265,9 +308,13 @@ impl E1000EDevice {
             // 关闭中断
             // close the interrupt
             volwrite!(interrupt_regs, imc, E1000E_IMC_CLEAR);
-            let mut gcr = volread!(pcie_regs, gcr);
-            gcr = gcr | (1 << 22);
-            volwrite!(pcie_regs, gcr, gcr);
+            // GCR寄存器只存在于PCIe接口的网卡中
+            // GCR only exists in PCIe devices
+            if kind == E1000EKind::E1000E {
+                let mut gcr = volread!(pcie_regs, gcr);
+                gcr = gcr | (1 << 22);
+                volwrite!(pcie_regs, gcr, gcr);
+            }
             compiler_fence(Ordering::AcqRel);
             // PHY Initialization 14.8.1
             // MAC/PHY Link Setup 14.8.2
@@ -280,16 +327,26 @@ impl E1000EDevice {
 
         // 读取设备的mac地址
         // Read mac address
-        let ral = unsafe { volread!(ra_regs, ral0) };
-        let rah = unsafe { volread!(ra_regs, rah0) };
-        let mac: [u8; 6] = [
-            ((ral >> 0) & 0xFF) as u8,
-            ((ral >> 8) & 0xFF) as u8,
-            ((ral >> 16) & 0xFF) as u8,
-            ((ral >> 24) & 0xFF) as u8,
-            ((rah >> 0) & 0xFF) as u8,
-            ((rah >> 8) & 0xFF) as u8,
-        ];
+        let mac = match kind {
+            // 8254x系列从EEPROM中读取mac地址，并写入接收地址寄存器，使网卡能够接收发往该地址的包
+            // 8254x devices read mac address from EEPROM
+            E1000EKind::E1000 => match e1000_read_eeprom_mac(general_regs) {
+                Some(mac) => {
+                    let ral = u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]);
+                    let rah = u16::from_le_bytes([mac[4], mac[5]]) as u32 | E1000E_RAH_AV;
+                    unsafe {
+                        volwrite!(ra_regs, ral0, ral);
+                        volwrite!(ra_regs, rah0, rah);
+                    }
+                    mac
+                }
+                None => {
+                    kwarn!("e1000: failed to read mac address from eeprom");
+                    read_receive_address(ra_regs)
+                }
+            },
+            E1000EKind::E1000E => read_receive_address(ra_regs),
+        };
         // 初始化receive和transimit descriptor环形队列
         // initialize receive and transimit desciptor ring
         let (recv_ring_pa, recv_ring_va) = dma_alloc(E1000E_DMA_PAGES);
@@ -393,11 +450,14 @@ impl E1000EDevice {
             volwrite!(interrupt_regs, icr, icr);
             // 开启收包相关的中断
             // Enable receive interrupts
-            let mut ims = volread!(interrupt_regs, ims);
-            ims = E1000E_IMS_LSC | E1000E_IMS_RXT0 | E1000E_IMS_RXDMT0 | E1000E_IMS_OTHER;
+            let mut ims = E1000E_IMS_LSC | E1000E_IMS_RXT0 | E1000E_IMS_RXDMT0;
+            if kind == E1000EKind::E1000E {
+                ims |= E1000E_IMS_OTHER;
+            }
             volwrite!(interrupt_regs, ims, ims);
         }
         return Ok(E1000EDevice {
+            kind,
             general_regs,
             interrupt_regs,
             rctl_regs,
@@ -468,6 +528,10 @@ impl E1000EDevice {
     pub fn mac_address(&self) -> [u8; 6] {
         return self.mac;
     }
+
+    pub fn kind(&self) -> E1000EKind {
+        return self.kind;
+    }
     // 向ICR寄存器中的某一bit写入1b表示该中断已经被接收，同时会清空该位
     // we need to clear ICR to tell e1000e we have read the interrupt
     pub fn e1000e_intr(&mut self) {
@@ -585,8 +649,8 @@ pub extern "C" fn rs_e1000e_init() {
 
 pub fn e1000e_init() -> () {
     match e1000e_probe() {
-        Ok(code) => kinfo!("Successfully init e1000e device!"),
-        Err(error) => kinfo!("Error occurred!"),
+        Ok(_) => kinfo!("Successfully init e1000e device!"),
+        Err(error) => kerror!("Failed to init e1000e device: {:?}", error),
     }
 }
 
@@ -597,17 +661,39 @@ pub fn e1000e_probe() -> Result<u64, E1000EPciError> {
         return Ok(0);
     }
     for device in result {
-        let standard_device = device.as_standard_device_mut().unwrap();
+        let standard_device = match device.as_standard_device_mut() {
+            Some(standard_device) => standard_device,
+            None => continue,
+        };
         let header = &standard_device.common_header;
+        let bdf = header.bus_device_function;
         if header.vendor_id == 0x8086 {
             // intel
-            if E1000E_DEVICE_ID.contains(&header.device_id) {
-                kdebug!(
-                    "Detected e1000e PCI device with device id {:#x}",
-                    header.device_id
-                );
-                let e1000e = E1000EDevice::new(standard_device)?;
-                e1000e_driver_init(e1000e);
+            let kind = if E1000E_DEVICE_ID.contains(&header.device_id) {
+                E1000EKind::E1000E
+            } else if E1000_DEVICE_ID.contains(&header.device_id) {
+                E1000EKind::E1000
+            } else {
+                continue;
+            };
+            kdebug!(
+                "Detected {:?} PCI device with device id {:#x}",
+                kind,
+                header.device_id
+            );
+            // 一块网卡初始化失败时，记录失败的原因并跳过它，继续初始化其他网卡
+            match E1000EDevice::new(standard_device, kind) {
+                Ok(e1000e) => e1000e_driver_init(e1000e),
+                Err(e) => {
+                    kerror!("{:?} {}: init failed, skipped: {:?}", kind, bdf, e);
+                    if let Some(dev) = pci_device_manager().find(&bdf) {
+                        device_manager().probe_failure_add(
+                            &(dev as Arc<dyn Device>),
+                            "e1000e",
+                            e.into(),
+                        );
+                    }
+                }
             }
         }
     }
@@ -615,6 +701,53 @@ pub fn e1000e_probe() -> Result<u64, E1000EPciError> {
     return Ok(1);
 }
 
+// 从接收地址寄存器中读取mac地址
+// read mac address from receive address registers
+fn read_receive_address(ra_regs: NonNull<ReceiveAddressRegs>) -> [u8; 6] {
+    let ral = unsafe { volread!(ra_regs, ral0) };
+    let rah = unsafe { volread!(ra_regs, rah0) };
+    return [
+        ((ral >> 0) & 0xFF) as u8,
+        ((ral >> 8) & 0xFF) as u8,
+        ((ral >> 16) & 0xFF) as u8,
+        ((ral >> 24) & 0xFF) as u8,
+        ((rah >> 0) & 0xFF) as u8,
+        ((rah >> 8) & 0xFF) as u8,
+    ];
+}
+
+// 通过EERD寄存器读取8254x网卡EEPROM中的一个字，超时返回None
+// read a word from EEPROM of 8254x devices through EERD
+fn e1000_read_eeprom(general_regs: NonNull<GeneralRegs>, addr: u8) -> Option<u16> {
+    unsafe {
+        volwrite!(
+            general_regs,
+            eerd,
+            ((addr as u32) << E1000_EERD_ADDR_SHIFT) | E1000_EERD_START
+        )
+    };
+    for _ in 0..E1000_EERD_TIMEOUT {
+        let eerd = unsafe { volread!(general_regs, eerd) };
+        if eerd & E1000_EERD_DONE != 0 {
+            return Some((eerd >> E1000_EERD_DATA_SHIFT) as u16);
+        }
+        core::hint::spin_loop();
+    }
+    return None;
+}
+
+// EEPROM的前三个字为mac地址 pp.98 Table 5-2
+// the first three words in EEPROM are the mac address
+fn e1000_read_eeprom_mac(general_regs: NonNull<GeneralRegs>) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    for i in 0..3 {
+        let word = e1000_read_eeprom(general_regs, i as u8)?;
+        mac[i * 2] = (word & 0xff) as u8;
+        mac[i * 2 + 1] = (word >> 8) as u8;
+    }
+    return Some(mac);
+}
+
 // 用到的e1000e寄存器结构体
 // pp.275, Table 13-3
 // 设备通用寄存器
@@ -729,6 +862,17 @@ const E1000E_IMS_OTHER: u32 = 1 << 24; // qemu use this bit to set msi-x interru
 // IMC
 const E1000E_IMC_CLEAR: u32 = 0xffffffff;
 
+// RAH寄存器: 地址有效位
+const E1000E_RAH_AV: u32 = 1 << 31;
+
+// 8254x的EERD寄存器 pp.237
+const E1000_EERD_START: u32 = 1 << 0;
+const E1000_EERD_DONE: u32 = 1 << 4;
+const E1000_EERD_ADDR_SHIFT: u32 = 8;
+const E1000_EERD_DATA_SHIFT: u32 = 16;
+// 等待EEPROM读取完成的最大轮询次数
+const E1000_EERD_TIMEOUT: usize = 100000;
+
 // RCTL
 const E1000E_RCTL_EN: u32 = 1 << 1;
 const E1000E_RCTL_BAM: u32 = 1 << 15;
@@ -759,6 +903,7 @@ const E1000E_TXD_CMD_IFCS: u8 = 1 << 1;
 const E1000E_TXD_CMD_RS: u8 = 1 << 3;
 
 // E1000E驱动初始化过程中可能的错误
+#[derive(Debug)]
 pub enum E1000EPciError {
     // 获取到错误类型的BAR（IO BAR）
     // An IO BAR was provided rather than a memory BAR.
@@ -776,13 +921,27 @@ pub enum E1000EPciError {
     Pci(PciError),
 }
 
-/// PCI error到VirtioPciError的转换，层层上报
+/// PCI error到E1000EPciError的转换，层层上报
 impl From<PciError> for E1000EPciError {
     fn from(error: PciError) -> Self {
         Self::Pci(error)
     }
 }
 
+impl From<E1000EPciError> for SystemError {
+    fn from(error: E1000EPciError) -> Self {
+        match error {
+            E1000EPciError::UnexpectedBarType | E1000EPciError::UnexpectedBarSize => {
+                SystemError::ENODEV
+            }
+            E1000EPciError::BarNotAllocated
+            | E1000EPciError::BarGetVaddrFailed
+            | E1000EPciError::BarGetFailed => SystemError::ENOMEM,
+            E1000EPciError::Pci(e) => e.into(),
+        }
+    }
+}
+
 /**
  * @brief 获取基地址的某个偏移量的指针，用于在mmio bar中构造寄存器结构体
  * @brief used for build register struct in mmio bar
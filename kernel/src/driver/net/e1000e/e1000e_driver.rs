@@ -10,11 +10,19 @@ use crate::{
             },
             kobject::{KObjType, KObject, KObjectState},
         },
-        net::NetDriver,
+        net::{loopback::loopback_intercept, NetDeviceStats, NetDriver},
     },
     kdebug, kinfo,
     libs::spinlock::SpinLock,
-    net::{generate_iface_id, NET_DRIVERS},
+    net::{
+        generate_iface_id,
+        ipv4::inet_set_addr,
+        netdevice::{netif_receive, register_netdev, NetDevice, NetRxResult},
+        netfilter::{nf_hook_input, nf_hook_output, NfVerdict},
+        skbuff::SkBuff,
+        socket::packet_tap,
+        NET_DRIVERS,
+    },
     syscall::SystemError,
     time::Instant,
 };
@@ -34,6 +42,10 @@ pub struct E1000ETxToken {
 }
 pub struct E1000EDriver {
     pub inner: Arc<SpinLock<E1000EDevice>>,
+    /// 网卡的id，用于把收发的帧交给packet socket
+    iface_id: usize,
+    /// 网卡的收发统计信息
+    stats: Arc<NetDeviceStats>,
 }
 
 /// @brief 网卡驱动的包裹器，这是为了获取网卡驱动的可变引用而设计的。
@@ -90,26 +102,36 @@ impl phy::TxToken for E1000ETxToken {
     {
         let mut buffer = E1000EBuffer::new(4096);
         let result = f(buffer.as_mut_slice());
+        if nf_hook_output(&buffer.as_slice()[..len], phy::Medium::Ethernet) == NfVerdict::Drop {
+            buffer.free_buffer();
+            return result;
+        }
+        packet_tap(
+            self.driver.iface_id,
+            &buffer.as_slice()[..len],
+            phy::Medium::Ethernet,
+            true,
+        );
+        // 发往回环地址的包交给lo，不发送到网络上
+        if loopback_intercept(&buffer.as_slice()[..len]) {
+            buffer.free_buffer();
+            return result;
+        }
         let mut device = self.driver.inner.lock();
         device.e1000e_transmit(buffer);
+        self.driver.stats.tx(len);
         return result;
     }
 }
 
 impl E1000EDriver {
     pub fn new(device: E1000EDevice) -> Self {
-        let mut iface_config = smoltcp::iface::Config::new();
-
-        // todo: 随机设定这个值。
-        // 参见 https://docs.rs/smoltcp/latest/smoltcp/iface/struct.Config.html#structfield.random_seed
-        iface_config.random_seed = 12345;
-
-        iface_config.hardware_addr = Some(wire::HardwareAddress::Ethernet(
-            smoltcp::wire::EthernetAddress(device.mac_address()),
-        ));
-
         let inner: Arc<SpinLock<E1000EDevice>> = Arc::new(SpinLock::new(device));
-        let result = E1000EDriver { inner };
+        let result = E1000EDriver {
+            inner,
+            iface_id: 0,
+            stats: Arc::new(NetDeviceStats::new()),
+        };
         return result;
     }
 }
@@ -118,6 +140,8 @@ impl Clone for E1000EDriver {
     fn clone(&self) -> Self {
         return E1000EDriver {
             inner: self.inner.clone(),
+            iface_id: self.iface_id,
+            stats: self.stats.clone(),
         };
     }
 }
@@ -130,16 +154,28 @@ impl phy::Device for E1000EDriver {
         &mut self,
         _timestamp: smoltcp::time::Instant,
     ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        match self.inner.lock().e1000e_receive() {
-            Some(buffer) => Some((
+        loop {
+            let buffer = self.inner.lock().e1000e_receive()?;
+            self.stats.rx(buffer.as_slice().len());
+            packet_tap(
+                self.iface_id,
+                buffer.as_slice(),
+                phy::Medium::Ethernet,
+                false,
+            );
+            // 被过滤规则丢弃的包，以及内核已经应答的ARP请求和ICMP回显请求，不交给smoltcp
+            if nf_hook_input(buffer.as_slice(), phy::Medium::Ethernet) == NfVerdict::Drop
+                || netif_receive(self.iface_id, buffer.as_slice()) == NetRxResult::Consumed
+            {
+                buffer.free_buffer();
+                continue;
+            }
+            return Some((
                 E1000ERxToken(buffer),
                 E1000ETxToken {
                     driver: self.clone(),
                 },
-            )),
-            None => {
-                return None;
-            }
+            ));
         }
     }
 
@@ -171,16 +207,17 @@ impl phy::Device for E1000EDriver {
 impl E1000EInterface {
     pub fn new(mut driver: E1000EDriver) -> Arc<Self> {
         let iface_id = generate_iface_id();
-        let mut iface_config = smoltcp::iface::Config::new();
+        let mut iface_config = smoltcp::iface::Config::new(wire::HardwareAddress::Ethernet(
+            smoltcp::wire::EthernetAddress(driver.inner.lock().mac_address()),
+        ));
 
         // todo: 随机设定这个值。
         // 参见 https://docs.rs/smoltcp/latest/smoltcp/iface/struct.Config.html#structfield.random_seed
         iface_config.random_seed = 12345;
 
-        iface_config.hardware_addr = Some(wire::HardwareAddress::Ethernet(
-            smoltcp::wire::EthernetAddress(driver.inner.lock().mac_address()),
-        ));
-        let iface = smoltcp::iface::Interface::new(iface_config, &mut driver);
+        driver.iface_id = iface_id;
+        let iface =
+            smoltcp::iface::Interface::new(iface_config, &mut driver, Instant::now().into());
 
         let driver: E1000EDriverWrapper = E1000EDriverWrapper(UnsafeCell::new(driver));
         let result = Arc::new(E1000EInterface {
@@ -260,6 +297,11 @@ impl NetDriver for E1000EInterface {
                 *dest = ip_addrs[0];
             }
         });
+        let addr = match ip_addrs[0] {
+            wire::IpCidr::Ipv4(cidr) => Some(cidr),
+            _ => None,
+        };
+        inet_set_addr(self.iface_id, addr);
         return Ok(());
     }
 
@@ -280,6 +322,51 @@ impl NetDriver for E1000EInterface {
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
         return &self.iface;
     }
+
+    #[inline]
+    fn stats(&self) -> &NetDeviceStats {
+        return &self.driver.stats;
+    }
+}
+
+impl NetDevice for E1000EInterface {
+    #[inline]
+    fn name(&self) -> String {
+        return self.name.clone();
+    }
+
+    #[inline]
+    fn nic_id(&self) -> usize {
+        return self.iface_id;
+    }
+
+    fn mac(&self) -> wire::EthernetAddress {
+        return NetDriver::mac(self);
+    }
+
+    fn start_xmit(&self, skb: SkBuff) -> Result<(), SystemError> {
+        let frame = skb.data();
+        if nf_hook_output(frame, phy::Medium::Ethernet) == NfVerdict::Drop {
+            return Ok(());
+        }
+        packet_tap(self.iface_id, frame, phy::Medium::Ethernet, true);
+
+        let mut device = self.driver.inner.lock();
+        if !device.e1000e_can_transmit() {
+            self.driver.stats.tx_error();
+            return Err(SystemError::ENOBUFS);
+        }
+        let mut buffer = E1000EBuffer::new(frame.len());
+        buffer.as_mut_slice().copy_from_slice(frame);
+        device.e1000e_transmit(buffer);
+        self.driver.stats.tx(frame.len());
+        return Ok(());
+    }
+
+    #[inline]
+    fn stats(&self) -> &NetDeviceStats {
+        return &self.driver.stats;
+    }
 }
 
 impl KObject for E1000EInterface {
@@ -349,6 +436,7 @@ pub fn e1000e_driver_init(device: E1000EDevice) {
     let driver = E1000EDriver::new(device);
     let iface = E1000EInterface::new(driver);
     // 将网卡的接口信息注册到全局的网卡接口信息表中
-    NET_DRIVERS.write().insert(iface.nic_id(), iface.clone());
+    NET_DRIVERS.write().insert(iface.iface_id, iface.clone());
+    register_netdev(iface.clone());
     kinfo!("e1000e driver init successfully!\tMAC: [{}]", mac);
 }
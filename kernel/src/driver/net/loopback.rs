@@ -0,0 +1,373 @@
+//! 回环网络设备(lo)
+//!
+//! lo发送的包会被直接放回它自己的接收队列中，配置了127.0.0.1/8的地址，
+//! 因此发往127.0.0.0/8的包都会经由它送回本机，不需要任何硬件。
+//!
+//! 由于所有网卡共用同一个SocketSet，发往回环地址的包也可能先被其他网卡发出
+//! (例如网卡配置了默认网关时)。网卡的发送函数需要调用[`loopback_intercept`]，
+//! 把这些包转交给lo，而不是发送到网络上。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/net/loopback.c
+
+use core::any::Any;
+
+use alloc::{
+    collections::VecDeque,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use smoltcp::{
+    phy,
+    wire::{self, EthernetFrame, EthernetProtocol, Ipv4Address, Ipv4Cidr, Ipv4Packet},
+};
+
+use crate::{
+    driver::base::{
+        device::{bus::Bus, driver::Driver, Device, IdTable},
+        kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::kernfs::KernFSInode,
+    kinfo,
+    libs::{
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
+    },
+    net::{
+        generate_iface_id,
+        netfilter::{nf_hook_input, nf_hook_output, NfVerdict},
+        socket::packet_tap,
+        NET_DRIVERS,
+    },
+    syscall::SystemError,
+    time::Instant,
+};
+
+use super::{NetDeviceStats, NetDriver};
+
+/// 回环设备的名字
+pub const LOOPBACK_IFACE_NAME: &str = "lo";
+/// 回环设备的MTU，与Linux保持一致
+const LOOPBACK_MTU: usize = 65536;
+/// 接收队列中最多缓存的包的数量，超出时丢弃新的包
+const LOOPBACK_QUEUE_LEN: usize = 256;
+
+/// 回环设备的接收队列，队列中的每一项都是一个完整的IP包
+static LOOPBACK_QUEUE: SpinLock<VecDeque<Vec<u8>>> = SpinLock::new(VecDeque::new());
+/// 回环设备的收发统计信息
+static LOOPBACK_STATS: NetDeviceStats = NetDeviceStats::new();
+
+fn loopback_enqueue(packet: &[u8]) {
+    let mut queue = LOOPBACK_QUEUE.lock_irqsave();
+    if queue.len() < LOOPBACK_QUEUE_LEN {
+        queue.push_back(packet.to_vec());
+        LOOPBACK_STATS.tx(packet.len());
+    } else {
+        LOOPBACK_STATS.rx_drop();
+    }
+}
+
+/// 检查以太网卡将要发送的帧是否是发往回环地址的IPv4包，如果是，则把它转交给lo
+///
+/// ## 参数
+///
+/// - `frame`: 完整的以太网帧
+///
+/// ## 返回值
+///
+/// - `true`: 这个帧已经被转交给lo，调用者不应该再把它发送到网络上
+/// - `false`: 这个帧不是发往回环地址的
+pub fn loopback_intercept(frame: &[u8]) -> bool {
+    let eth = match EthernetFrame::new_checked(frame) {
+        Ok(eth) => eth,
+        Err(_) => return false,
+    };
+    if eth.ethertype() != EthernetProtocol::Ipv4 {
+        return false;
+    }
+    let payload = eth.payload();
+    let ip = match Ipv4Packet::new_checked(payload) {
+        Ok(ip) => ip,
+        Err(_) => return false,
+    };
+    if !ip.dst_addr().is_loopback() {
+        return false;
+    }
+    loopback_enqueue(&payload[..ip.total_len() as usize]);
+    return true;
+}
+
+/// 回环设备驱动，发送的包直接放入接收队列
+#[derive(Debug, Clone, Copy)]
+pub struct LoopbackDriver {
+    /// 网卡的id，用于把收发的包交给packet socket
+    iface_id: usize,
+}
+
+pub struct LoopbackRxToken(Vec<u8>);
+pub struct LoopbackTxToken {
+    iface_id: usize,
+}
+
+impl phy::RxToken for LoopbackRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        return f(self.0.as_mut_slice());
+    }
+}
+
+impl phy::TxToken for LoopbackTxToken {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buffer = vec![0u8; len];
+        let result = f(buffer.as_mut_slice());
+        if nf_hook_output(&buffer, phy::Medium::Ip) == NfVerdict::Accept {
+            packet_tap(self.iface_id, &buffer, phy::Medium::Ip, true);
+            loopback_enqueue(&buffer);
+        }
+        return result;
+    }
+}
+
+impl phy::Device for LoopbackDriver {
+    type RxToken<'a> = LoopbackRxToken;
+    type TxToken<'a> = LoopbackTxToken;
+
+    fn receive(
+        &mut self,
+        _timestamp: smoltcp::time::Instant,
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        loop {
+            let packet = LOOPBACK_QUEUE.lock_irqsave().pop_front()?;
+            LOOPBACK_STATS.rx(packet.len());
+            packet_tap(self.iface_id, &packet, phy::Medium::Ip, false);
+            // 被过滤规则丢弃的包不交给协议栈
+            if nf_hook_input(&packet, phy::Medium::Ip) == NfVerdict::Drop {
+                continue;
+            }
+            return Some((
+                LoopbackRxToken(packet),
+                LoopbackTxToken {
+                    iface_id: self.iface_id,
+                },
+            ));
+        }
+    }
+
+    fn transmit(&mut self, _timestamp: smoltcp::time::Instant) -> Option<Self::TxToken<'_>> {
+        return Some(LoopbackTxToken {
+            iface_id: self.iface_id,
+        });
+    }
+
+    fn capabilities(&self) -> phy::DeviceCapabilities {
+        let mut caps = phy::DeviceCapabilities::default();
+        // 回环设备上没有以太网头，也不需要ARP
+        caps.medium = phy::Medium::Ip;
+        caps.max_transmission_unit = LOOPBACK_MTU;
+        return caps;
+    }
+}
+
+#[derive(Debug, Default)]
+struct InnerLoopbackInterface {
+    bus: Option<Arc<dyn Bus>>,
+    kobj_type: Option<&'static dyn KObjType>,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    kern_inode: Option<Arc<KernFSInode>>,
+    devices: Vec<Arc<dyn Device>>,
+}
+
+pub struct LoopbackInterface {
+    driver: LoopbackDriver,
+    iface_id: usize,
+    iface: SpinLock<smoltcp::iface::Interface>,
+    inner: RwLock<InnerLoopbackInterface>,
+    kobj_state: LockedKObjectState,
+}
+
+impl core::fmt::Debug for LoopbackInterface {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LoopbackInterface")
+            .field("iface_id", &self.iface_id)
+            .field("iface", &"smoltcp::iface::Interface")
+            .finish()
+    }
+}
+
+impl LoopbackInterface {
+    pub fn new() -> Arc<Self> {
+        let iface_id = generate_iface_id();
+        let mut iface_config = smoltcp::iface::Config::new(wire::HardwareAddress::Ip);
+        iface_config.random_seed = 12345;
+
+        let mut driver = LoopbackDriver { iface_id };
+        let mut iface =
+            smoltcp::iface::Interface::new(iface_config, &mut driver, Instant::now().into());
+        // 127.0.0.0/8整个网段都直接经由lo发送，不需要额外的网关
+        iface.update_ip_addrs(|addrs| {
+            addrs
+                .push(wire::IpCidr::Ipv4(Ipv4Cidr::new(
+                    Ipv4Address::new(127, 0, 0, 1),
+                    8,
+                )))
+                .expect("Push ipCidr failed: full");
+        });
+
+        return Arc::new(LoopbackInterface {
+            driver,
+            iface_id,
+            iface: SpinLock::new(iface),
+            inner: RwLock::new(InnerLoopbackInterface::default()),
+            kobj_state: LockedKObjectState::new(None),
+        });
+    }
+}
+
+impl Driver for LoopbackInterface {
+    fn id_table(&self) -> Option<IdTable> {
+        // lo是纯软件的设备，不与任何总线上的设备匹配
+        None
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        self.inner.write().devices.push(device);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let mut inner = self.inner.write();
+
+        inner.devices.drain_filter(|d| Arc::ptr_eq(d, device));
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner.read().devices.clone()
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+}
+
+impl NetDriver for LoopbackInterface {
+    fn mac(&self) -> smoltcp::wire::EthernetAddress {
+        return smoltcp::wire::EthernetAddress([0; 6]);
+    }
+
+    #[inline]
+    fn nic_id(&self) -> usize {
+        return self.iface_id;
+    }
+
+    #[inline]
+    fn name(&self) -> String {
+        return String::from(LOOPBACK_IFACE_NAME);
+    }
+
+    fn update_ip_addrs(&self, _ip_addrs: &[wire::IpCidr]) -> Result<(), SystemError> {
+        // 回环设备的地址是固定的
+        return Err(SystemError::EPERM);
+    }
+
+    fn poll(&self, sockets: &mut smoltcp::iface::SocketSet) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        let mut guard = self.iface.lock();
+        let mut driver = self.driver;
+        let poll_res = guard.poll(timestamp, &mut driver, sockets);
+        if poll_res {
+            return Ok(());
+        }
+        return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    }
+
+    #[inline(always)]
+    fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
+        return &self.iface;
+    }
+
+    #[inline]
+    fn stats(&self) -> &NetDeviceStats {
+        return &LOOPBACK_STATS;
+    }
+}
+
+impl KObject for LoopbackInterface {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner.read().kobj_type
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner.write().kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        String::from(LOOPBACK_IFACE_NAME)
+    }
+
+    // lo的名字是固定的
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 注册回环设备
+///
+/// 需要在其他网卡之后注册，使得lo在轮询时排在最后：
+/// 以太网卡无法发出的回环包(例如没有默认网关时)会留给lo来发送，
+/// 而发往其他地址的包不会被lo抢先发送。
+pub fn loopback_init() {
+    let iface = LoopbackInterface::new();
+    NET_DRIVERS.write().insert(iface.nic_id(), iface.clone());
+    kinfo!(
+        "Loopback device init successfully!\tNetDevID: [{}]",
+        LOOPBACK_IFACE_NAME
+    );
+}
@@ -1,4 +1,5 @@
 use alloc::string::String;
+use core::sync::atomic::{AtomicU64, Ordering};
 use smoltcp::{
     iface,
     wire::{self, EthernetAddress},
@@ -10,6 +11,7 @@ use super::base::device::driver::Driver;
 
 mod dma;
 pub mod e1000e;
+pub mod loopback;
 pub mod virtio_net;
 
 pub trait NetDriver: Driver {
@@ -28,4 +30,63 @@ pub trait NetDriver: Driver {
     /// @brief 获取smoltcp的网卡接口类型
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface>;
     // fn as_any_ref(&'static self) -> &'static dyn core::any::Any;
+
+    /// @brief 获取网卡的收发统计信息
+    fn stats(&self) -> &NetDeviceStats;
+}
+
+/// @brief 网卡的收发统计信息，展示在/proc/net/dev中
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/if_link.h#rtnl_link_stats64
+#[derive(Debug, Default)]
+pub struct NetDeviceStats {
+    pub rx_packets: AtomicU64,
+    pub rx_bytes: AtomicU64,
+    pub rx_errors: AtomicU64,
+    pub rx_dropped: AtomicU64,
+    pub tx_packets: AtomicU64,
+    pub tx_bytes: AtomicU64,
+    pub tx_errors: AtomicU64,
+    pub tx_dropped: AtomicU64,
+}
+
+impl NetDeviceStats {
+    pub const fn new() -> Self {
+        Self {
+            rx_packets: AtomicU64::new(0),
+            rx_bytes: AtomicU64::new(0),
+            rx_errors: AtomicU64::new(0),
+            rx_dropped: AtomicU64::new(0),
+            tx_packets: AtomicU64::new(0),
+            tx_bytes: AtomicU64::new(0),
+            tx_errors: AtomicU64::new(0),
+            tx_dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// @brief 记录收到了一个长度为len的包
+    #[inline]
+    pub fn rx(&self, len: usize) {
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        self.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// @brief 记录发送了一个长度为len的包
+    #[inline]
+    pub fn tx(&self, len: usize) {
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        self.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// @brief 记录收到的包被丢弃
+    #[inline]
+    pub fn rx_drop(&self) {
+        self.rx_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// @brief 记录发送失败
+    #[inline]
+    pub fn tx_error(&self) {
+        self.tx_errors.fetch_add(1, Ordering::Relaxed);
+    }
 }
@@ -16,24 +16,38 @@ use crate::{
         },
         virtio::virtio_impl::HalImpl,
     },
-    kdebug, kerror, kinfo,
+    kerror, kinfo,
     libs::spinlock::SpinLock,
-    net::{generate_iface_id, NET_DRIVERS},
+    net::{
+        generate_iface_id,
+        ipv4::inet_set_addr,
+        netdevice::{netif_receive, register_netdev, NetDevice, NetRxResult},
+        netfilter::{nf_hook_input, nf_hook_output, NfVerdict},
+        skbuff::SkBuff,
+        socket::packet_tap,
+        NET_DRIVERS,
+    },
     syscall::SystemError,
     time::Instant,
 };
 
-use super::NetDriver;
+use super::{loopback::loopback_intercept, NetDeviceStats, NetDriver};
 
 /// @brief Virtio网络设备驱动(加锁)
 pub struct VirtioNICDriver<T: Transport> {
     pub inner: Arc<SpinLock<VirtIONet<HalImpl, T, 2>>>,
+    /// 网卡的id，用于把收发的帧交给packet socket
+    iface_id: usize,
+    /// 网卡的收发统计信息
+    stats: Arc<NetDeviceStats>,
 }
 
 impl<T: Transport> Clone for VirtioNICDriver<T> {
     fn clone(&self) -> Self {
         return VirtioNICDriver {
             inner: self.inner.clone(),
+            iface_id: self.iface_id,
+            stats: self.stats.clone(),
         };
     }
 }
@@ -90,16 +104,17 @@ impl<T: Transport> Debug for VirtioInterface<T> {
 impl<T: Transport> VirtioInterface<T> {
     pub fn new(mut driver: VirtioNICDriver<T>) -> Arc<Self> {
         let iface_id = generate_iface_id();
-        let mut iface_config = smoltcp::iface::Config::new();
+        let mut iface_config = smoltcp::iface::Config::new(wire::HardwareAddress::Ethernet(
+            smoltcp::wire::EthernetAddress(driver.inner.lock().mac_address()),
+        ));
 
         // todo: 随机设定这个值。
         // 参见 https://docs.rs/smoltcp/latest/smoltcp/iface/struct.Config.html#structfield.random_seed
         iface_config.random_seed = 12345;
 
-        iface_config.hardware_addr = Some(wire::HardwareAddress::Ethernet(
-            smoltcp::wire::EthernetAddress(driver.inner.lock().mac_address()),
-        ));
-        let iface = smoltcp::iface::Interface::new(iface_config, &mut driver);
+        driver.iface_id = iface_id;
+        let iface =
+            smoltcp::iface::Interface::new(iface_config, &mut driver, Instant::now().into());
 
         let driver: VirtioNICDriverWrapper<T> = VirtioNICDriverWrapper(UnsafeCell::new(driver));
         let result = Arc::new(VirtioInterface {
@@ -115,18 +130,12 @@ impl<T: Transport> VirtioInterface<T> {
 
 impl<T: 'static + Transport> VirtioNICDriver<T> {
     pub fn new(driver_net: VirtIONet<HalImpl, T, 2>) -> Self {
-        let mut iface_config = smoltcp::iface::Config::new();
-
-        // todo: 随机设定这个值。
-        // 参见 https://docs.rs/smoltcp/latest/smoltcp/iface/struct.Config.html#structfield.random_seed
-        iface_config.random_seed = 12345;
-
-        iface_config.hardware_addr = Some(wire::HardwareAddress::Ethernet(
-            smoltcp::wire::EthernetAddress(driver_net.mac_address()),
-        ));
-
         let inner: Arc<SpinLock<VirtIONet<HalImpl, T, 2>>> = Arc::new(SpinLock::new(driver_net));
-        let result = VirtioNICDriver { inner };
+        let result = VirtioNICDriver {
+            inner,
+            iface_id: 0,
+            stats: Arc::new(NetDeviceStats::new()),
+        };
         return result;
     }
 }
@@ -153,13 +162,28 @@ impl<T: Transport> phy::Device for VirtioNICDriver<T> {
         &mut self,
         _timestamp: smoltcp::time::Instant,
     ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        match self.inner.lock().receive() {
-            Ok(buf) => Some((
+        loop {
+            let buf = match self.inner.lock().receive() {
+                Ok(buf) => buf,
+                Err(virtio_drivers::Error::NotReady) => return None,
+                Err(err) => panic!("VirtIO receive failed: {}", err),
+            };
+            self.stats.rx(buf.packet().len());
+            packet_tap(self.iface_id, buf.packet(), phy::Medium::Ethernet, false);
+            // 被过滤规则丢弃的包，以及内核已经应答的ARP请求和ICMP回显请求，不交给smoltcp
+            if nf_hook_input(buf.packet(), phy::Medium::Ethernet) == NfVerdict::Drop
+                || netif_receive(self.iface_id, buf.packet()) == NetRxResult::Consumed
+            {
+                self.inner
+                    .lock()
+                    .recycle_rx_buffer(buf)
+                    .expect("virtio_net recv failed");
+                continue;
+            }
+            return Some((
                 VirtioNetToken::new(self.clone(), Some(buf)),
                 VirtioNetToken::new(self.clone(), None),
-            )),
-            Err(virtio_drivers::Error::NotReady) => None,
-            Err(err) => panic!("VirtIO receive failed: {}", err),
+            ));
         }
     }
 
@@ -198,7 +222,23 @@ impl<T: Transport> phy::TxToken for VirtioNetToken<T> {
         let mut driver_net = self.driver.inner.lock();
         let mut tx_buf = driver_net.new_tx_buffer(len);
         let result = f(tx_buf.packet_mut());
-        driver_net.send(tx_buf).expect("virtio_net send failed");
+        if nf_hook_output(tx_buf.packet(), phy::Medium::Ethernet) == NfVerdict::Drop {
+            return result;
+        }
+        packet_tap(
+            self.driver.iface_id,
+            tx_buf.packet(),
+            phy::Medium::Ethernet,
+            true,
+        );
+        // 发往回环地址的包交给lo，不发送到网络上
+        if loopback_intercept(tx_buf.packet()) {
+            return result;
+        }
+        match driver_net.send(tx_buf) {
+            Ok(_) => self.driver.stats.tx(len),
+            Err(_) => self.driver.stats.tx_error(),
+        }
         return result;
     }
 }
@@ -235,7 +275,8 @@ pub fn virtio_net<T: Transport + 'static>(transport: T) {
     let iface = VirtioInterface::new(driver);
     let name = iface.name.clone();
     // 将网卡的接口信息注册到全局的网卡接口信息表中
-    NET_DRIVERS.write().insert(iface.nic_id(), iface.clone());
+    NET_DRIVERS.write().insert(iface.iface_id, iface.clone());
+    register_netdev(iface.clone());
     kinfo!(
         "Virtio-net driver init successfully!\tNetDevID: [{}], MAC: [{}]",
         name,
@@ -299,6 +340,11 @@ impl<T: Transport + 'static> NetDriver for VirtioInterface<T> {
                 *dest = ip_addrs[0];
             }
         });
+        let addr = match ip_addrs[0] {
+            wire::IpCidr::Ipv4(cidr) => Some(cidr),
+            _ => None,
+        };
+        inet_set_addr(self.iface_id, addr);
         return Ok(());
     }
 
@@ -310,7 +356,6 @@ impl<T: Transport + 'static> NetDriver for VirtioInterface<T> {
         let mut guard = self.iface.lock();
         let poll_res = guard.poll(timestamp, self.driver.force_get_mut(), sockets);
         // todo: notify!!!
-        kdebug!("Virtio Interface poll:{poll_res}");
         if poll_res {
             return Ok(());
         }
@@ -321,11 +366,61 @@ impl<T: Transport + 'static> NetDriver for VirtioInterface<T> {
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
         return &self.iface;
     }
+
+    #[inline]
+    fn stats(&self) -> &NetDeviceStats {
+        return &self.driver.stats;
+    }
     // fn as_any_ref(&'static self) -> &'static dyn core::any::Any {
     //     return self;
     // }
 }
 
+impl<T: Transport + 'static> NetDevice for VirtioInterface<T> {
+    #[inline]
+    fn name(&self) -> String {
+        return self.name.clone();
+    }
+
+    #[inline]
+    fn nic_id(&self) -> usize {
+        return self.iface_id;
+    }
+
+    fn mac(&self) -> wire::EthernetAddress {
+        return NetDriver::mac(self);
+    }
+
+    fn start_xmit(&self, skb: SkBuff) -> Result<(), SystemError> {
+        let frame = skb.data();
+        if nf_hook_output(frame, phy::Medium::Ethernet) == NfVerdict::Drop {
+            return Ok(());
+        }
+        packet_tap(self.iface_id, frame, phy::Medium::Ethernet, true);
+
+        let mut driver_net = self.driver.inner.lock();
+        if !driver_net.can_send() {
+            self.driver.stats.tx_error();
+            return Err(SystemError::ENOBUFS);
+        }
+        let mut tx_buf = driver_net.new_tx_buffer(frame.len());
+        tx_buf.packet_mut().copy_from_slice(frame);
+        match driver_net.send(tx_buf) {
+            Ok(_) => self.driver.stats.tx(frame.len()),
+            Err(_) => {
+                self.driver.stats.tx_error();
+                return Err(SystemError::EIO);
+            }
+        }
+        return Ok(());
+    }
+
+    #[inline]
+    fn stats(&self) -> &NetDeviceStats {
+        return &self.driver.stats;
+    }
+}
+
 impl<T: Transport + 'static> KObject for VirtioInterface<T> {
     fn as_any_ref(&self) -> &dyn core::any::Any {
         self
@@ -0,0 +1,455 @@
+//! 扁平设备树(FDT)的解析
+//!
+//! 把引导程序传入的FDT展开成[`DeviceNode`]组成的树，所有数据都会被复制，展开之后不再需要原来的FDT。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/fdt.c
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use crate::syscall::SystemError;
+
+/// FDT头部的魔数
+const FDT_MAGIC: u32 = 0xd00d_feed;
+/// 支持的最低版本
+const FDT_LAST_COMP_VERSION: u32 = 16;
+/// FDT头部的长度
+const FDT_HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// 没有`#address-cells`属性时的默认值
+const OF_ROOT_NODE_ADDR_CELLS_DEFAULT: u32 = 2;
+/// 没有`#size-cells`属性时的默认值
+const OF_ROOT_NODE_SIZE_CELLS_DEFAULT: u32 = 1;
+/// 节点的最大嵌套深度，用于防止损坏的FDT导致栈溢出
+const FDT_MAX_DEPTH: usize = 64;
+
+/// 设备树节点的一个属性
+#[derive(Debug)]
+pub struct Property {
+    name: String,
+    value: Vec<u8>,
+}
+
+/// 设备树中的一个节点
+#[derive(Debug)]
+pub struct DeviceNode {
+    /// 节点名，包括单元地址，例如`uart@10000000`。根节点的名字为空
+    name: String,
+    /// 从根节点开始的完整路径，例如`/soc/uart@10000000`
+    full_name: String,
+    properties: Vec<Property>,
+    children: Vec<Arc<DeviceNode>>,
+    /// 父节点的`#address-cells`，用于解析本节点的`reg`属性
+    parent_addr_cells: u32,
+    /// 父节点的`#size-cells`
+    parent_size_cells: u32,
+}
+
+#[allow(dead_code)]
+impl DeviceNode {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 去掉单元地址之后的节点名，例如`uart`
+    pub fn node_name(&self) -> &str {
+        return self.name.split('@').next().unwrap_or_default();
+    }
+
+    pub fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    pub fn children(&self) -> &[Arc<DeviceNode>] {
+        &self.children
+    }
+
+    /// 获取属性的原始值
+    pub fn property(&self, name: &str) -> Option<&[u8]> {
+        return self
+            .properties
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.value.as_slice());
+    }
+
+    /// 获取由一个大端u32组成的属性
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        let value = self.property(name)?;
+        return Some(u32::from_be_bytes(value.get(0..4)?.try_into().unwrap()));
+    }
+
+    /// 获取字符串属性
+    pub fn property_str(&self, name: &str) -> Option<&str> {
+        return self.property_str_list(name)?.next();
+    }
+
+    /// 获取由多个'\0'结尾的字符串组成的属性
+    pub fn property_str_list(&self, name: &str) -> Option<impl Iterator<Item = &str>> {
+        let value = self.property(name)?;
+        return Some(
+            value
+                .split(|b| *b == 0)
+                .filter(|s| !s.is_empty())
+                .filter_map(|s| core::str::from_utf8(s).ok()),
+        );
+    }
+
+    /// 节点的`compatible`属性，按照从具体到通用的顺序排列
+    pub fn compatible(&self) -> impl Iterator<Item = &str> {
+        return self.property_str_list("compatible").into_iter().flatten();
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        return self.compatible().any(|c| c == compatible);
+    }
+
+    /// 节点是否可用：没有`status`属性，或者它的值为`okay`
+    pub fn is_available(&self) -> bool {
+        match self.property_str("status") {
+            None | Some("okay") | Some("ok") => true,
+            _ => false,
+        }
+    }
+
+    /// 解析`reg`属性
+    ///
+    /// ## 返回值
+    ///
+    /// 每一项为(地址, 大小)。地址是父总线上的地址，没有经过`ranges`转换
+    pub fn reg(&self) -> Vec<(u64, u64)> {
+        let (ac, sc) = (
+            self.parent_addr_cells as usize,
+            self.parent_size_cells as usize,
+        );
+        let value = match self.property("reg") {
+            Some(v) if ac + sc != 0 && ac <= 2 && sc <= 2 => v,
+            _ => return Vec::new(),
+        };
+        let read_cells = |cells: &[u8]| {
+            cells.chunks_exact(4).fold(0u64, |v, c| {
+                (v << 32) | u32::from_be_bytes(c.try_into().unwrap()) as u64
+            })
+        };
+        return value
+            .chunks_exact((ac + sc) * 4)
+            .map(|entry| (read_cells(&entry[..ac * 4]), read_cells(&entry[ac * 4..])))
+            .collect();
+    }
+
+    /// 按照路径查找子孙节点，例如`soc/uart@10000000`
+    pub fn find_by_path(self: &Arc<Self>, path: &str) -> Option<Arc<DeviceNode>> {
+        let mut node = self.clone();
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            let child = node
+                .children
+                .iter()
+                .find(|c| c.name == name || (!name.contains('@') && c.node_name() == name))?
+                .clone();
+            node = child;
+        }
+        return Some(node);
+    }
+
+    /// 以先序遍历的顺序访问这个节点以及它的所有子孙节点
+    pub fn for_each(self: &Arc<Self>, f: &mut dyn FnMut(&Arc<DeviceNode>)) {
+        f(self);
+        for child in self.children.iter() {
+            child.for_each(f);
+        }
+    }
+}
+
+/// FDT的读取器。读取过程不分配内存，因此也可以在内存管理初始化之前使用
+struct FdtReader<'a> {
+    /// 结构块
+    structs: &'a [u8],
+    /// 字符串块
+    strings: &'a [u8],
+    /// 在结构块中的偏移量
+    pos: usize,
+}
+
+impl<'a> FdtReader<'a> {
+    /// 检查FDT的头部，创建读取器
+    fn new(fdt: &'a [u8]) -> Result<Self, SystemError> {
+        if fdt.len() < FDT_HEADER_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+        let header = |i: usize| u32::from_be_bytes(fdt[i * 4..i * 4 + 4].try_into().unwrap());
+        let (magic, totalsize, off_struct, off_strings) =
+            (header(0), header(1), header(2), header(3));
+        let (last_comp_version, size_strings, size_struct) = (header(6), header(8), header(9));
+        if magic != FDT_MAGIC
+            || last_comp_version > FDT_LAST_COMP_VERSION
+            || totalsize as usize > fdt.len()
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let fdt = &fdt[..totalsize as usize];
+        let range = |off: u32, size: u32| {
+            let (off, size) = (off as usize, size as usize);
+            fdt.get(off..off.checked_add(size)?)
+        };
+        return Ok(Self {
+            structs: range(off_struct, size_struct).ok_or(SystemError::EINVAL)?,
+            strings: range(off_strings, size_strings).ok_or(SystemError::EINVAL)?,
+            pos: 0,
+        });
+    }
+
+    fn read_u32(&mut self) -> Result<u32, SystemError> {
+        let bytes = self
+            .structs
+            .get(self.pos..self.pos + 4)
+            .ok_or(SystemError::EINVAL)?;
+        self.pos += 4;
+        return Ok(u32::from_be_bytes(bytes.try_into().unwrap()));
+    }
+
+    /// 读取`len`字节，然后对齐到4字节
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SystemError> {
+        let bytes = self
+            .structs
+            .get(self.pos..self.pos + len)
+            .ok_or(SystemError::EINVAL)?;
+        self.pos = (self.pos + len + 3) & !3;
+        return Ok(bytes);
+    }
+
+    /// 读取'\0'结尾的节点名，然后对齐到4字节
+    fn read_name(&mut self) -> Result<&'a str, SystemError> {
+        let rest = self.structs.get(self.pos..).ok_or(SystemError::EINVAL)?;
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or(SystemError::EINVAL)?;
+        let name = core::str::from_utf8(&rest[..len]).map_err(|_| SystemError::EINVAL)?;
+        self.pos = (self.pos + len + 1 + 3) & !3;
+        return Ok(name);
+    }
+
+    fn string_at(&self, off: usize) -> Result<&'a str, SystemError> {
+        let rest = self.strings.get(off..).ok_or(SystemError::EINVAL)?;
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or(SystemError::EINVAL)?;
+        return core::str::from_utf8(&rest[..len]).map_err(|_| SystemError::EINVAL);
+    }
+
+    /// 下一个不是NOP的token
+    fn next_token(&mut self) -> Result<u32, SystemError> {
+        loop {
+            let token = self.read_u32()?;
+            if token != FDT_NOP {
+                return Ok(token);
+            }
+        }
+    }
+
+    /// 展开一个节点。调用之前已经读取了它的FDT_BEGIN_NODE
+    fn unflatten_node(
+        &mut self,
+        parent_path: &str,
+        parent_cells: (u32, u32),
+        depth: usize,
+    ) -> Result<Arc<DeviceNode>, SystemError> {
+        if depth > FDT_MAX_DEPTH {
+            return Err(SystemError::EINVAL);
+        }
+        let name = self.read_name()?.to_string();
+        let full_name = match (parent_path, depth) {
+            (_, 0) => "/".to_string(),
+            ("/", _) => format!("/{}", name),
+            _ => format!("{}/{}", parent_path, name),
+        };
+
+        let mut properties = Vec::new();
+        let mut children = Vec::new();
+        loop {
+            match self.next_token()? {
+                FDT_PROP => {
+                    if !children.is_empty() {
+                        // 属性必须位于所有子节点之前
+                        return Err(SystemError::EINVAL);
+                    }
+                    let len = self.read_u32()? as usize;
+                    let nameoff = self.read_u32()? as usize;
+                    let value = self.read_bytes(len)?.to_vec();
+                    properties.push(Property {
+                        name: self.string_at(nameoff)?.to_string(),
+                        value,
+                    });
+                }
+                FDT_BEGIN_NODE => {
+                    // 子节点的reg按照本节点的#address-cells和#size-cells解析
+                    let cells = Self::cells_of(&properties);
+                    children.push(self.unflatten_node(&full_name, cells, depth + 1)?);
+                }
+                FDT_END_NODE => break,
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+
+        return Ok(Arc::new(DeviceNode {
+            name,
+            full_name,
+            properties,
+            children,
+            parent_addr_cells: parent_cells.0,
+            parent_size_cells: parent_cells.1,
+        }));
+    }
+
+    /// 根据节点的属性，获取它的`#address-cells`和`#size-cells`
+    fn cells_of(properties: &[Property]) -> (u32, u32) {
+        let get = |name: &str, default: u32| {
+            properties
+                .iter()
+                .find(|p| p.name == name)
+                .and_then(|p| p.value.get(0..4))
+                .map(|v| u32::from_be_bytes(v.try_into().unwrap()))
+                .unwrap_or(default)
+        };
+        return (
+            get("#address-cells", OF_ROOT_NODE_ADDR_CELLS_DEFAULT),
+            get("#size-cells", OF_ROOT_NODE_SIZE_CELLS_DEFAULT),
+        );
+    }
+}
+
+/// 把FDT展开成设备树
+///
+/// ## 参数
+///
+/// - `fdt`：FDT的内容
+///
+/// ## 返回值
+///
+/// 设备树的根节点
+///
+/// ## 错误
+///
+/// - `EINVAL`：FDT的格式不正确，或者版本太旧
+pub fn unflatten_device_tree(fdt: &[u8]) -> Result<Arc<DeviceNode>, SystemError> {
+    let mut reader = FdtReader::new(fdt)?;
+    if reader.next_token()? != FDT_BEGIN_NODE {
+        return Err(SystemError::EINVAL);
+    }
+    let root = reader.unflatten_node(
+        "",
+        (
+            OF_ROOT_NODE_ADDR_CELLS_DEFAULT,
+            OF_ROOT_NODE_SIZE_CELLS_DEFAULT,
+        ),
+        0,
+    )?;
+    if reader.next_token()? != FDT_END {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(root);
+}
+
+/// FDT的总长度(头部中的`totalsize`)，用于在复制FDT之前确定它的大小
+///
+/// ## 错误
+///
+/// - `EINVAL`：魔数不正确
+pub fn fdt_total_size(header: &[u8]) -> Result<usize, SystemError> {
+    let word = |i: usize| {
+        header
+            .get(i * 4..i * 4 + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+    };
+    if word(0) != Some(FDT_MAGIC) {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(word(1).ok_or(SystemError::EINVAL)? as usize);
+}
+
+/// 在展开设备树之前，扫描根节点下`device_type`为`memory`的节点，获取物理内存的范围。
+/// 这个过程不分配内存，供内存管理初始化之前使用
+///
+/// ## 参数
+///
+/// - `fdt`：FDT的内容
+/// - `f`：对每一块内存调用，参数为(基地址, 大小)
+///
+/// ## 错误
+///
+/// - `EINVAL`：FDT的格式不正确
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/fdt.c (early_init_dt_scan_memory)
+pub fn early_init_dt_scan_memory(
+    fdt: &[u8],
+    f: &mut dyn FnMut(u64, u64),
+) -> Result<(), SystemError> {
+    let mut reader = FdtReader::new(fdt)?;
+    if reader.next_token()? != FDT_BEGIN_NODE {
+        return Err(SystemError::EINVAL);
+    }
+    reader.read_name()?;
+
+    let (mut ac, mut sc) = (
+        OF_ROOT_NODE_ADDR_CELLS_DEFAULT as usize,
+        OF_ROOT_NODE_SIZE_CELLS_DEFAULT as usize,
+    );
+    let read_cells = |cells: &[u8]| {
+        cells.chunks_exact(4).fold(0u64, |v, c| {
+            (v << 32) | u32::from_be_bytes(c.try_into().unwrap()) as u64
+        })
+    };
+    // 当前位于根节点之内
+    let mut depth = 1;
+    // 当前的根节点的子节点是否为内存节点，以及它的reg属性
+    let mut is_memory = false;
+    let mut reg: Option<&[u8]> = None;
+    loop {
+        match reader.next_token()? {
+            FDT_PROP => {
+                let len = reader.read_u32()? as usize;
+                let nameoff = reader.read_u32()? as usize;
+                let value = reader.read_bytes(len)?;
+                let name = reader.string_at(nameoff)?;
+                let be32 = || value.get(0..4).map(|v| read_cells(v) as usize);
+                match (depth, name) {
+                    (1, "#address-cells") => ac = be32().unwrap_or(ac),
+                    (1, "#size-cells") => sc = be32().unwrap_or(sc),
+                    (2, "device_type") => is_memory = value.starts_with(b"memory\0"),
+                    (2, "reg") => reg = Some(value),
+                    _ => {}
+                }
+            }
+            FDT_BEGIN_NODE => {
+                reader.read_name()?;
+                depth += 1;
+                if depth == 2 {
+                    is_memory = false;
+                    reg = None;
+                }
+            }
+            FDT_END_NODE => {
+                if depth == 2 && is_memory && ac + sc != 0 && ac <= 2 && sc <= 2 {
+                    for entry in reg.unwrap_or_default().chunks_exact((ac + sc) * 4) {
+                        f(read_cells(&entry[..ac * 4]), read_cells(&entry[ac * 4..]));
+                    }
+                }
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
+}
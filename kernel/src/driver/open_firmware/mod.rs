@@ -0,0 +1,83 @@
+//! 设备树(Open Firmware)支持
+//!
+//! 没有ACPI的平台(RISC-V、ARM，以及QEMU的virt机器)通过设备树描述硬件。引导程序传入的扁平设备树(FDT)
+//! 在驱动模型初始化之后被展开，然后根节点下的设备(以及`simple-bus`等总线下的设备)被创建为平台设备。
+//! 平台驱动通过[`PlatformDriver::of_match_table`]给出自己支持的`compatible`，与设备节点匹配。
+//!
+//! x86_64上没有固件提供的设备树，可以通过引导程序以multiboot2模块的方式传入，模块的命令行为`dtb`。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/
+//!
+//! [`PlatformDriver::of_match_table`]: crate::driver::base::platform::platform_driver::PlatformDriver::of_match_table
+
+use alloc::sync::Arc;
+
+use crate::{init::boot_info::boot_info, kerror, kinfo, syscall::SystemError};
+
+use self::{
+    fdt::{unflatten_device_tree, DeviceNode},
+    platform::of_platform_populate,
+};
+
+use super::base::platform::CompatibleTable;
+
+pub mod fdt;
+pub mod platform;
+
+/// 设备树的根节点，没有设备树时为None
+static mut OF_ROOT: Option<Arc<DeviceNode>> = None;
+
+/// 获取设备树的根节点
+#[inline(always)]
+#[allow(dead_code)]
+pub fn of_root() -> Option<Arc<DeviceNode>> {
+    unsafe { OF_ROOT.clone() }
+}
+
+/// 按照路径查找节点，例如`/soc/uart@10000000`
+#[allow(dead_code)]
+pub fn of_find_node_by_path(path: &str) -> Option<Arc<DeviceNode>> {
+    return of_root()?.find_by_path(path);
+}
+
+/// 查找第一个可用的、带有指定`compatible`的节点
+#[allow(dead_code)]
+pub fn of_find_compatible_node(compatible: &str) -> Option<Arc<DeviceNode>> {
+    let mut result = None;
+    of_root()?.for_each(&mut |node| {
+        if result.is_none() && node.is_available() && node.is_compatible(compatible) {
+            result = Some(node.clone());
+        }
+    });
+    return result;
+}
+
+/// 判断设备树节点能否与驱动的匹配表匹配
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/device.c#25
+pub fn of_driver_match_device(node: &DeviceNode, table: &CompatibleTable) -> bool {
+    return node.compatible().any(|c| table.contains(c));
+}
+
+/// 展开引导程序传入的设备树，并创建平台设备。
+///
+/// 应当在platform总线初始化之后调用。没有设备树时什么也不做
+pub(super) fn open_firmware_init() -> Result<(), SystemError> {
+    let fdt = match boot_info().fdt {
+        Some(fdt) => fdt,
+        None => return Ok(()),
+    };
+    let root = unflatten_device_tree(fdt).map_err(|e| {
+        kerror!("of: invalid flattened device tree: {:?}", e);
+        e
+    })?;
+    unsafe { OF_ROOT = Some(root.clone()) };
+
+    let count = of_platform_populate(&root);
+    kinfo!(
+        "of: device tree '{}' unflattened, {} platform devices created",
+        root.property_str("model").unwrap_or("unknown"),
+        count
+    );
+    return Ok(());
+}
@@ -0,0 +1,301 @@
+//! 根据设备树创建平台设备
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/platform.c
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+};
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::{
+        device::{
+            bus::Bus, device_manager, driver::Driver, Device, DeviceKObjType, DeviceNumber,
+            DeviceState, DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+        kset::KSet,
+        platform::{
+            platform_device::{platform_device_manager, PlatformDevice, PLATFORM_DEVID_NONE},
+            CompatibleTable,
+        },
+    },
+    filesystem::kernfs::KernFSInode,
+    kwarn,
+    libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    syscall::SystemError,
+};
+
+use super::fdt::DeviceNode;
+
+/// 子节点也需要被创建为平台设备的总线。
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/platform.c#26
+const OF_DEFAULT_BUS_MATCH_TABLE: [&str; 4] = ["simple-bus", "simple-mfd", "isa", "arm,amba-bus"];
+
+/// 由设备树节点创建的平台设备
+#[derive(Debug)]
+#[cast_to([sync] Device, PlatformDevice)]
+pub struct OfPlatformDevice {
+    /// 设备id是否自动分配
+    id_auto: AtomicBool,
+    /// 平台设备id
+    id: AtomicI32,
+    /// 设备名，例如`10000000.uart`
+    pdev_name: String,
+    of_node: Arc<DeviceNode>,
+    inner: RwLock<InnerOfPlatformDevice>,
+    kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+struct InnerOfPlatformDevice {
+    name: String,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    bus: Option<Arc<dyn Bus>>,
+    inode: Option<Arc<KernFSInode>>,
+    driver: Option<Weak<dyn Driver>>,
+    device_state: DeviceState,
+    can_match: bool,
+}
+
+impl OfPlatformDevice {
+    pub fn new(of_node: Arc<DeviceNode>) -> Arc<Self> {
+        let pdev_name = of_device_make_bus_id(&of_node);
+        let r = Arc::new(Self {
+            id_auto: AtomicBool::new(false),
+            id: AtomicI32::new(PLATFORM_DEVID_NONE),
+            pdev_name: pdev_name.clone(),
+            of_node,
+            inner: RwLock::new(InnerOfPlatformDevice {
+                name: pdev_name,
+                kset: None,
+                parent_kobj: None,
+                bus: None,
+                inode: None,
+                driver: None,
+                device_state: DeviceState::NotInitialized,
+                can_match: true,
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        });
+
+        device_manager().device_default_initialize(&(r.clone() as Arc<dyn Device>));
+
+        return r;
+    }
+}
+
+impl PlatformDevice for OfPlatformDevice {
+    fn pdev_name(&self) -> &str {
+        &self.pdev_name
+    }
+
+    fn pdev_id(&self) -> (i32, bool) {
+        return (
+            self.id.load(Ordering::SeqCst),
+            self.id_auto.load(Ordering::SeqCst),
+        );
+    }
+
+    fn set_pdev_id(&self, id: i32) {
+        self.id.store(id, Ordering::SeqCst);
+    }
+
+    fn set_pdev_id_auto(&self, id_auto: bool) {
+        self.id_auto.store(id_auto, Ordering::SeqCst);
+    }
+
+    fn compatible_table(&self) -> CompatibleTable {
+        CompatibleTable::new(Vec::new())
+    }
+
+    fn of_node(&self) -> Option<Arc<DeviceNode>> {
+        Some(self.of_node.clone())
+    }
+
+    fn is_initialized(&self) -> bool {
+        return self.inner.read().device_state == DeviceState::Initialized;
+    }
+
+    fn set_state(&self, set_state: DeviceState) {
+        self.inner.write().device_state = set_state;
+    }
+}
+
+impl Device for OfPlatformDevice {
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::PlatformDev
+    }
+
+    fn id_table(&self) -> IdTable {
+        return IdTable::new(self.pdev_name.clone(), DeviceNumber::new(0));
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        self.inner.read().driver.clone()?.upgrade()
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner.write().driver = driver;
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner.read().can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner.write().can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+}
+
+impl KObject for OfPlatformDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        Some(&DeviceKObjType)
+    }
+
+    fn set_kobj_type(&self, _ktype: Option<&'static dyn KObjType>) {}
+
+    fn name(&self) -> String {
+        self.inner.read().name.clone()
+    }
+
+    fn set_name(&self, name: String) {
+        self.inner.write().name = name;
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 生成设备名：有`reg`属性时为`<地址>.<节点名>`，否则为节点的完整路径中的各级节点名用'.'连接
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/platform.c#83
+fn of_device_make_bus_id(node: &DeviceNode) -> String {
+    if let Some((addr, _)) = node.reg().first() {
+        return format!("{:x}.{}", addr, node.node_name());
+    }
+    return node.full_name().trim_start_matches('/').replace('/', ".");
+}
+
+/// 为节点以及它的子孙节点创建平台设备
+///
+/// 只有带有`compatible`属性并且可用的节点才会被创建为设备；
+/// 节点是[`OF_DEFAULT_BUS_MATCH_TABLE`]中的总线时，继续为它的子节点创建设备，它们的父设备是总线对应的设备
+fn of_platform_bus_create(
+    node: &Arc<DeviceNode>,
+    parent: Option<&Arc<OfPlatformDevice>>,
+) -> Result<usize, SystemError> {
+    if node.property("compatible").is_none() || !node.is_available() {
+        return Ok(0);
+    }
+    let pdev = OfPlatformDevice::new(node.clone());
+    if let Some(parent) = parent {
+        pdev.set_parent(Some(Arc::downgrade(&(parent.clone() as Arc<dyn KObject>))));
+    }
+    platform_device_manager().device_add(pdev.clone() as Arc<dyn PlatformDevice>)?;
+    let mut count = 1;
+
+    if OF_DEFAULT_BUS_MATCH_TABLE
+        .iter()
+        .any(|bus| node.is_compatible(bus))
+    {
+        for child in node.children() {
+            count += of_platform_bus_create(child, Some(&pdev)).unwrap_or_else(|e| {
+                kwarn!(
+                    "of: failed to create device for {}: {:?}",
+                    child.full_name(),
+                    e
+                );
+                0
+            });
+        }
+    }
+    return Ok(count);
+}
+
+/// 为根节点的所有子节点创建平台设备
+///
+/// ## 返回值
+///
+/// 创建的设备数量
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/of/platform.c#470
+pub fn of_platform_populate(root: &Arc<DeviceNode>) -> usize {
+    let mut count = 0;
+    for child in root.children() {
+        // 这些节点描述的是CPU、内存与引导参数，不对应平台设备
+        if matches!(child.node_name(), "cpus" | "memory" | "chosen" | "aliases") {
+            continue;
+        }
+        count += of_platform_bus_create(child, None).unwrap_or_else(|e| {
+            kwarn!(
+                "of: failed to create device for {}: {:?}",
+                child.full_name(),
+                e
+            );
+            0
+        });
+    }
+    return count;
+}
@@ -0,0 +1,404 @@
+//! PCI设备在设备驱动模型中的表示
+
+use core::any::Any;
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::{
+        device::{
+            bus::Bus, device_manager, driver::Driver, sys_devices_kset, Device, DeviceKObjType,
+            DeviceNumber, DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::kernfs::KernFSInode,
+    kerror,
+    libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    syscall::SystemError,
+};
+
+use super::{
+    pci::{
+        capabilities_offset, pci_read_config, pci_write_config, BusDeviceFunction,
+        CapabilityIterator, Command, PciDeviceStructure, PciDeviceStructureHeader, PCI_CAP_ID_EXP,
+        PCI_CAP_ID_MSI, PCI_CAP_ID_MSIX, PCI_CAP_ID_PM, PCI_DEVICE_LINKEDLIST,
+        STATUS_COMMAND_OFFSET,
+    },
+    pci_bus,
+    resource::PciResource,
+};
+
+/// 所有已经加入设备驱动模型的PCI设备
+///
+/// 总线只保存设备的弱引用，因此需要在这里持有设备
+static PCI_DEVICES: RwLock<Vec<Arc<PciDevice>>> = RwLock::new(Vec::new());
+
+#[inline(always)]
+pub fn pci_device_manager() -> &'static PciDeviceManager {
+    &PciDeviceManager
+}
+
+/// MSI-X capability的信息
+#[derive(Debug, Clone, Copy)]
+pub struct PciMsixInfo {
+    /// capability在配置空间中的偏移量
+    pub offset: u8,
+    /// 中断向量表的表项数量
+    pub table_size: u16,
+    /// 中断向量表所在的BAR
+    pub table_bar: u8,
+    /// 中断向量表在BAR中的偏移量
+    pub table_offset: u32,
+    /// Pending Bit Array所在的BAR
+    pub pba_bar: u8,
+    /// Pending Bit Array在BAR中的偏移量
+    pub pba_offset: u32,
+}
+
+/// 从capability链表中解析出来的、驱动常用的capability
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PciCapabilities {
+    /// MSI capability的偏移量
+    pub msi: Option<u8>,
+    pub msix: Option<PciMsixInfo>,
+    /// 电源管理capability的偏移量，以及PMC寄存器的值
+    pub pm: Option<(u8, u16)>,
+    /// PCI Express capability的偏移量
+    pub pcie: Option<u8>,
+}
+
+impl PciCapabilities {
+    /// 遍历设备的capability链表
+    pub fn parse(bus_device_function: &BusDeviceFunction) -> Self {
+        let mut caps = Self::default();
+        let iter = CapabilityIterator {
+            bus_device_function: *bus_device_function,
+            next_capability_offset: capabilities_offset(*bus_device_function),
+        };
+        // 防止损坏的链表形成环
+        for cap in iter.take(48) {
+            match cap.id {
+                PCI_CAP_ID_MSI => caps.msi = Some(cap.offset),
+                PCI_CAP_ID_MSIX => {
+                    let table = pci_read_config(bus_device_function, cap.offset as u16 + 4);
+                    let pba = pci_read_config(bus_device_function, cap.offset as u16 + 8);
+                    caps.msix = Some(PciMsixInfo {
+                        offset: cap.offset,
+                        table_size: (cap.private_header & 0x7ff) + 1,
+                        table_bar: (table & 0x7) as u8,
+                        table_offset: table & !0x7,
+                        pba_bar: (pba & 0x7) as u8,
+                        pba_offset: pba & !0x7,
+                    });
+                }
+                PCI_CAP_ID_PM => caps.pm = Some((cap.offset, cap.private_header)),
+                PCI_CAP_ID_EXP => caps.pcie = Some(cap.offset),
+                _ => {}
+            }
+        }
+        return caps;
+    }
+}
+
+/// 设备驱动模型中的PCI设备
+///
+/// 设备的配置空间头部仍然保存在[`PCI_DEVICE_LINKEDLIST`]中，
+/// 驱动可以通过[`PciDevice::with_structure_mut`]访问它。
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct PciDevice {
+    bus_device_function: BusDeviceFunction,
+    vendor_id: u16,
+    device_id: u16,
+    /// 类别代码: class_code << 16 | subclass << 8 | prog_if
+    class: u32,
+    revision_id: u8,
+    resources: Vec<PciResource>,
+    caps: PciCapabilities,
+    name: String,
+    inner: RwLock<InnerPciDevice>,
+    kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+struct InnerPciDevice {
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    bus: Option<Arc<dyn Bus>>,
+    inode: Option<Arc<KernFSInode>>,
+    driver: Option<Weak<dyn Driver>>,
+    can_match: bool,
+}
+
+impl PciDevice {
+    fn new(header: &PciDeviceStructureHeader, resources: Vec<PciResource>) -> Arc<Self> {
+        let bdf = header.bus_device_function;
+        let r = Arc::new(Self {
+            bus_device_function: bdf,
+            vendor_id: header.vendor_id,
+            device_id: header.device_id,
+            class: (header.class_code as u32) << 16
+                | (header.subclass as u32) << 8
+                | header.prog_if as u32,
+            revision_id: header.revision_id,
+            resources,
+            caps: PciCapabilities::parse(&bdf),
+            name: format!("0000:{:02x}:{:02x}.{:x}", bdf.bus, bdf.device, bdf.function),
+            inner: RwLock::new(InnerPciDevice {
+                kset: None,
+                parent_kobj: None,
+                bus: None,
+                inode: None,
+                driver: None,
+                can_match: false,
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        });
+
+        device_manager().device_default_initialize(&(r.clone() as Arc<dyn Device>));
+        return r;
+    }
+
+    #[inline]
+    pub fn bus_device_function(&self) -> BusDeviceFunction {
+        self.bus_device_function
+    }
+
+    #[inline]
+    pub fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    #[inline]
+    pub fn device_id(&self) -> u16 {
+        self.device_id
+    }
+
+    /// 类别代码: class_code << 16 | subclass << 8 | prog_if
+    #[inline]
+    pub fn class(&self) -> u32 {
+        self.class
+    }
+
+    #[inline]
+    pub fn revision_id(&self) -> u8 {
+        self.revision_id
+    }
+
+    /// 设备的BAR资源
+    #[inline]
+    pub fn resources(&self) -> &[PciResource] {
+        &self.resources
+    }
+
+    /// 获取指定BAR的资源
+    pub fn resource(&self, bar: u8) -> Option<&PciResource> {
+        self.resources.iter().find(|r| r.bar == bar)
+    }
+
+    #[inline]
+    pub fn capabilities(&self) -> &PciCapabilities {
+        &self.caps
+    }
+
+    /// 读取配置空间中的一个32位寄存器
+    #[inline]
+    pub fn read_config(&self, offset: u16) -> u32 {
+        pci_read_config(&self.bus_device_function, offset)
+    }
+
+    /// 写入配置空间中的一个32位寄存器
+    #[inline]
+    pub fn write_config(&self, offset: u16, data: u32) {
+        pci_write_config(&self.bus_device_function, offset, data)
+    }
+
+    /// 根据设备的BAR资源，允许设备响应IO和内存空间的访问
+    pub fn enable_device(&self) {
+        let mut command = Command::empty();
+        for res in self.resources.iter().filter(|r| r.is_assigned()) {
+            if res.is_io() {
+                command |= Command::IO_SPACE;
+            } else {
+                command |= Command::MEMORY_SPACE;
+            }
+        }
+        self.set_command_bits(command);
+    }
+
+    /// 允许设备作为总线主设备发起DMA
+    pub fn set_master(&self) {
+        self.set_command_bits(Command::BUS_MASTER);
+    }
+
+    fn set_command_bits(&self, bits: Command) {
+        let old = self.read_config(STATUS_COMMAND_OFFSET) as u16;
+        let new = old | bits.bits();
+        if new != old {
+            self.write_config(STATUS_COMMAND_OFFSET, new as u32);
+        }
+        // 保持PCI_DEVICE_LINKEDLIST中的头部与硬件一致
+        self.with_structure_mut(|s| s.common_header_mut().command = new);
+    }
+
+    /// 访问设备在[`PCI_DEVICE_LINKEDLIST`]中的结构体
+    ///
+    /// 用于调用尚未迁移到[`PciDevice`]上的接口（例如BAR映射、中断分配）
+    pub fn with_structure_mut<R>(
+        &self,
+        f: impl FnOnce(&mut Box<dyn PciDeviceStructure>) -> R,
+    ) -> Option<R> {
+        let mut list = PCI_DEVICE_LINKEDLIST.write();
+        let dev = list
+            .iter_mut()
+            .find(|d| d.common_header().bus_device_function == self.bus_device_function)?;
+        return Some(f(dev));
+    }
+}
+
+impl Device for PciDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Pci
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new("pci".to_string(), DeviceNumber::new(0))
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        self.inner.read().driver.clone()?.upgrade()
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner.write().driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner.read().can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner.write().can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+}
+
+impl KObject for PciDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        Some(&DeviceKObjType)
+    }
+
+    fn set_kobj_type(&self, _ktype: Option<&'static dyn KObjType>) {}
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+#[derive(Debug)]
+pub struct PciDeviceManager;
+
+impl PciDeviceManager {
+    /// 为一个已经枚举到的设备创建[`PciDevice`]，并加入设备驱动模型
+    ///
+    /// 如果总线上已经注册了能驱动该设备的驱动，设备会被立即探测
+    pub fn device_add(
+        &self,
+        header: &PciDeviceStructureHeader,
+        resources: Vec<PciResource>,
+    ) -> Result<Arc<PciDevice>, SystemError> {
+        let dev = PciDevice::new(header, resources);
+        dev.set_parent(Some(Arc::downgrade(
+            &(sys_devices_kset() as Arc<dyn KObject>),
+        )));
+        dev.set_bus(Some(pci_bus() as Arc<dyn Bus>));
+
+        PCI_DEVICES.write().push(dev.clone());
+        device_manager()
+            .add_device(dev.clone() as Arc<dyn Device>)
+            .map_err(|e| {
+                kerror!("PCI: failed to add device {}: {:?}", dev.name, e);
+                PCI_DEVICES.write().retain(|d| !Arc::ptr_eq(d, &dev));
+                e
+            })?;
+        return Ok(dev);
+    }
+
+    /// 根据BusDeviceFunction查找设备
+    pub fn find(&self, bus_device_function: &BusDeviceFunction) -> Option<Arc<PciDevice>> {
+        PCI_DEVICES
+            .read()
+            .iter()
+            .find(|d| d.bus_device_function == *bus_device_function)
+            .cloned()
+    }
+
+    /// 获取所有的PCI设备
+    pub fn devices(&self) -> Vec<Arc<PciDevice>> {
+        PCI_DEVICES.read().clone()
+    }
+}
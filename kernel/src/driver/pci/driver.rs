@@ -0,0 +1,114 @@
+use alloc::sync::Arc;
+
+use crate::{
+    driver::base::device::{
+        bus::Bus,
+        driver::{driver_manager, Driver},
+    },
+    syscall::SystemError,
+};
+
+use super::{device::PciDevice, pci_bus};
+
+/// 匹配任意值
+pub const PCI_ANY_ID: u32 = !0;
+
+/// PCI驱动的设备匹配表项
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/mod_devicetable.h#40
+#[derive(Debug, Clone, Copy)]
+pub struct PciDeviceId {
+    /// 厂商id，为[`PCI_ANY_ID`]时匹配任意厂商
+    pub vendor: u32,
+    /// 设备id，为[`PCI_ANY_ID`]时匹配任意设备
+    pub device: u32,
+    /// 类别代码: class_code << 16 | subclass << 8 | prog_if
+    pub class: u32,
+    /// 比较类别代码时使用的掩码，为0时不比较类别代码
+    pub class_mask: u32,
+}
+
+impl PciDeviceId {
+    /// 根据厂商id和设备id进行匹配
+    pub const fn new(vendor: u32, device: u32) -> Self {
+        Self {
+            vendor,
+            device,
+            class: 0,
+            class_mask: 0,
+        }
+    }
+
+    /// 根据类别代码进行匹配
+    pub const fn class(class: u32, class_mask: u32) -> Self {
+        Self {
+            vendor: PCI_ANY_ID,
+            device: PCI_ANY_ID,
+            class,
+            class_mask,
+        }
+    }
+
+    pub fn matches(&self, dev: &PciDevice) -> bool {
+        (self.vendor == PCI_ANY_ID || self.vendor == dev.vendor_id() as u32)
+            && (self.device == PCI_ANY_ID || self.device == dev.device_id() as u32)
+            && (self.class ^ dev.class()) & self.class_mask == 0
+    }
+}
+
+/// 实现该trait的驱动应挂载在PCI总线上，同时应该实现Driver trait
+///
+/// ## 注意
+///
+/// 应当在所有实现这个trait的结构体上方，添加 `#[cast_to([sync] PciDriver)]`，
+/// 否则运行时将报错“该对象不是PciDriver”
+pub trait PciDriver: Driver {
+    /// 驱动能够驱动的设备
+    fn pci_id_table(&self) -> &'static [PciDeviceId];
+
+    /// 初始化设备
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`：要初始化的设备
+    /// - `id`：匹配表中与设备匹配的表项
+    fn probe(&self, dev: &Arc<PciDevice>, id: &PciDeviceId) -> Result<(), SystemError>;
+
+    fn remove(&self, _dev: &Arc<PciDevice>) {}
+
+    fn shutdown(&self, _dev: &Arc<PciDevice>) {}
+
+    /// 系统进入睡眠状态之前，让设备停止工作
+    fn suspend(&self, _dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 系统从睡眠状态中恢复之后，让设备重新开始工作
+    fn resume(&self, _dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+        Ok(())
+    }
+}
+
+#[inline(always)]
+pub fn pci_driver_manager() -> &'static PciDriverManager {
+    &PciDriverManager
+}
+
+#[derive(Debug)]
+pub struct PciDriverManager;
+
+impl PciDriverManager {
+    /// 注册PCI驱动，并尝试绑定总线上已有的设备
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/pci/pci-driver.c#1432
+    pub fn register(&self, driver: Arc<dyn PciDriver>) -> Result<(), SystemError> {
+        driver.set_bus(Some(pci_bus() as Arc<dyn Bus>));
+        return driver_manager().register(driver as Arc<dyn Driver>);
+    }
+
+    /// 卸载PCI驱动
+    #[allow(dead_code)]
+    pub fn unregister(&self, driver: &Arc<dyn PciDriver>) {
+        driver_manager().unregister(&(driver.clone() as Arc<dyn Driver>));
+    }
+}
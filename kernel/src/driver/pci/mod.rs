@@ -1,2 +1,30 @@
+use alloc::sync::Arc;
+
+use crate::{
+    driver::base::device::bus::{bus_register, Bus},
+    syscall::SystemError,
+};
+
+use self::subsys::PciBus;
+
+pub mod device;
+pub mod driver;
+pub mod msi;
 pub mod pci;
 pub mod pci_irq;
+pub mod resource;
+pub mod subsys;
+
+static mut PCI_BUS: Option<Arc<PciBus>> = None;
+
+#[inline(always)]
+pub fn pci_bus() -> Arc<PciBus> {
+    unsafe { PCI_BUS.clone().unwrap() }
+}
+
+/// 注册PCI总线
+pub fn pci_bus_init() -> Result<(), SystemError> {
+    let bus = PciBus::new();
+    unsafe { PCI_BUS = Some(bus.clone()) };
+    return bus_register(bus as Arc<dyn Bus>);
+}
@@ -0,0 +1,330 @@
+//! PCI设备的MSI/MSI-X中断分配
+//!
+//! 驱动先通过[`PciDevice::alloc_irq_vectors`]为设备申请一组中断向量，
+//! 再通过[`PciDevice::request_irq`]为其中的每一个向量注册处理函数并指定目标CPU。
+//! 设备被移除时，驱动应调用[`PciDevice::free_irq_vectors`]释放这些向量。
+
+use alloc::{collections::BTreeSet, string::ToString, sync::Arc, vec::Vec};
+
+use crate::{
+    exception::irqdesc::{irq_manager, IrqChip, IrqHandleFlags, IrqHandler, IrqNumber},
+    kerror,
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+};
+
+use super::{
+    device::PciDevice,
+    pci::{BusDeviceFunction, PciError, PCI_DEVICE_LINKEDLIST},
+    pci_irq::{pci_irq_dev_id, IrqType, PciInterrupt, PciIrqError, TriggerMode, IRQ},
+};
+
+/// 可以动态分配给MSI/MSI-X的中断向量的范围: [MSI_VECTOR_START, MSI_VECTOR_END)
+const MSI_VECTOR_START: u16 = 58;
+const MSI_VECTOR_END: u16 = 0x80;
+
+/// MSI最多支持32个中断
+const MSI_MAX_VECTORS: u16 = 32;
+
+/// 已经分配出去的中断向量
+static MSI_VECTORS: SpinLock<BTreeSet<u16>> = SpinLock::new(BTreeSet::new());
+
+/// 分配count个中断向量
+///
+/// ## 参数
+///
+/// - `aligned`：是否需要连续、且起始向量号按count对齐的向量（MSI的多个中断只在message data的低位上有区别）
+fn msi_vectors_alloc(count: u16, aligned: bool) -> Option<Vec<u16>> {
+    let mut table = MSI_VECTORS.lock_irqsave();
+    let vectors: Vec<u16> = if aligned {
+        let mut start = (MSI_VECTOR_START + count - 1) & !(count - 1);
+        loop {
+            if start + count > MSI_VECTOR_END {
+                return None;
+            }
+            if (start..start + count).all(|v| !table.contains(&v)) {
+                break;
+            }
+            start += count;
+        }
+        (start..start + count).collect()
+    } else {
+        let free: Vec<u16> = (MSI_VECTOR_START..MSI_VECTOR_END)
+            .filter(|v| !table.contains(v))
+            .take(count as usize)
+            .collect();
+        if free.len() < count as usize {
+            return None;
+        }
+        free
+    };
+
+    table.extend(vectors.iter());
+    return Some(vectors);
+}
+
+fn msi_vectors_free(vectors: &[u16]) {
+    let mut table = MSI_VECTORS.lock_irqsave();
+    for vector in vectors {
+        table.remove(vector);
+    }
+}
+
+/// MSI/MSI-X中断的控制器操作：修改目标CPU时，重新写入Message Address
+#[derive(Debug)]
+pub struct PciMsiChip {
+    bus_device_function: BusDeviceFunction,
+    /// 中断在设备的中断向量组中的序号
+    index: u16,
+}
+
+impl PciMsiChip {
+    pub fn new(bus_device_function: BusDeviceFunction, index: u16) -> Arc<Self> {
+        Arc::new(Self {
+            bus_device_function,
+            index,
+        })
+    }
+}
+
+impl IrqChip for PciMsiChip {
+    fn set_affinity(&self, _irq: IrqNumber, cpu: usize) -> Result<(), SystemError> {
+        let mut list = PCI_DEVICE_LINKEDLIST.write();
+        let dev = list
+            .iter_mut()
+            .find(|d| d.common_header().bus_device_function == self.bus_device_function)
+            .and_then(|d| d.as_standard_device_mut())
+            .ok_or(SystemError::ENODEV)?;
+        dev.irq_set_msg(self.index, cpu as u16, TriggerMode::EdgeTrigger)?;
+        return Ok(());
+    }
+}
+
+impl From<PciError> for SystemError {
+    fn from(e: PciError) -> Self {
+        match e {
+            PciError::PciIrqError(PciIrqError::IrqNumOccupied(_)) => SystemError::EBUSY,
+            PciError::PciIrqError(PciIrqError::InvalidIrqIndex(_))
+            | PciError::PciIrqError(PciIrqError::InvalidIrqNum(_)) => SystemError::EINVAL,
+            PciError::PciIrqError(PciIrqError::IrqTypeNotSupported)
+            | PciError::PciIrqError(PciIrqError::PciDeviceNotSupportIrq) => {
+                SystemError::EOPNOTSUPP_OR_ENOTSUP
+            }
+            _ => SystemError::EIO,
+        }
+    }
+}
+
+impl PciDevice {
+    /// 为设备申请MSI-X或MSI中断向量（MSI-X优先）
+    ///
+    /// ## 参数
+    ///
+    /// - `min_vecs`：最少需要的向量数量
+    /// - `max_vecs`：最多需要的向量数量
+    /// - `flags`：允许使用的中断类型，只有`PCI_IRQ_MSI`和`PCI_IRQ_MSIX`有效
+    ///
+    /// ## 返回值
+    ///
+    /// 实际分配的向量数量，在min_vecs和max_vecs之间。使用MSI时，数量是2的幂
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：参数不合法
+    /// - `EBUSY`：设备已经分配了中断向量
+    /// - `EOPNOTSUPP_OR_ENOTSUP`：设备不支持flags指定的中断类型
+    /// - `ENOSPC`：没有足够的空闲向量
+    pub fn alloc_irq_vectors(
+        &self,
+        min_vecs: u16,
+        max_vecs: u16,
+        flags: IRQ,
+    ) -> Result<u16, SystemError> {
+        if min_vecs == 0 || min_vecs > max_vecs {
+            return Err(SystemError::EINVAL);
+        }
+        self.with_structure_mut(|dev| -> Result<u16, SystemError> {
+            let dev = dev
+                .as_standard_device_mut()
+                .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+            if dev.irq_vector_mut().map_or(true, |v| !v.is_empty()) {
+                return Err(SystemError::EBUSY);
+            }
+
+            let flags = flags & (IRQ::PCI_IRQ_MSI | IRQ::PCI_IRQ_MSIX);
+            let irq_type = dev
+                .irq_init(flags)
+                .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+            let (hw_max, aligned) = match irq_type {
+                IrqType::Msix {
+                    irq_max_num,
+                    msix_table_bar,
+                    ..
+                } => {
+                    // 写入MSI-X表项时需要BAR已经被映射
+                    let mapped = dev
+                        .bar()
+                        .and_then(|bar| bar.get_bar(msix_table_bar).ok())
+                        .and_then(|bar| bar.virtual_address())
+                        .is_some();
+                    if !mapped {
+                        dev.bar_ioremap()
+                            .ok_or(SystemError::ENODEV)?
+                            .map_err(SystemError::from)?;
+                    }
+                    (irq_max_num, false)
+                }
+                IrqType::Msi { irq_max_num, .. } => (irq_max_num.min(MSI_MAX_VECTORS), true),
+                _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+            };
+
+            let mut count = max_vecs.min(hw_max);
+            if aligned {
+                // MSI的中断数量只能是2的幂
+                count = 1 << (15 - count.leading_zeros());
+            }
+            while count >= min_vecs {
+                if let Some(vectors) = msi_vectors_alloc(count, aligned) {
+                    *dev.irq_vector_mut().unwrap() = vectors;
+                    return Ok(count);
+                }
+                count = if aligned { count / 2 } else { count - 1 };
+            }
+            *dev.irq_type_mut().unwrap() = IrqType::Unused;
+            return Err(SystemError::ENOSPC);
+        })
+        .ok_or(SystemError::ENODEV)?
+    }
+
+    /// 为设备的第index个中断向量注册处理函数，并启用设备的MSI/MSI-X中断
+    ///
+    /// 使用MSI时，所有向量共用同一个目标CPU（由第0个向量的`cpu`参数决定），
+    /// 并且必须先注册第0个向量。
+    ///
+    /// ## 参数
+    ///
+    /// - `index`：中断向量在[`PciDevice::alloc_irq_vectors`]分配的向量组中的序号
+    /// - `name`：中断的名字
+    /// - `handler`：中断处理函数
+    /// - `flags`：标志
+    /// - `cpu`：处理该中断的CPU
+    ///
+    /// ## 返回值
+    ///
+    /// 中断向量号
+    pub fn request_irq(
+        &self,
+        index: u16,
+        name: &str,
+        handler: Arc<dyn IrqHandler>,
+        flags: IrqHandleFlags,
+        cpu: usize,
+    ) -> Result<u16, SystemError> {
+        return self.do_request_irq(index, name, handler, flags, cpu, false);
+    }
+
+    /// 与[`PciDevice::request_irq`]相同，但会创建一个中断线程执行[`IrqHandler::handle_thread`]
+    pub fn request_threaded_irq(
+        &self,
+        index: u16,
+        name: &str,
+        handler: Arc<dyn IrqHandler>,
+        flags: IrqHandleFlags,
+        cpu: usize,
+    ) -> Result<u16, SystemError> {
+        return self.do_request_irq(index, name, handler, flags, cpu, true);
+    }
+
+    fn do_request_irq(
+        &self,
+        index: u16,
+        name: &str,
+        handler: Arc<dyn IrqHandler>,
+        flags: IrqHandleFlags,
+        cpu: usize,
+        threaded: bool,
+    ) -> Result<u16, SystemError> {
+        let bdf = self.bus_device_function();
+        let dev_id = pci_irq_dev_id(&bdf);
+        let (vector, first, is_msi) = self
+            .with_structure_mut(|dev| -> Result<(u16, u16, bool), SystemError> {
+                let dev = dev
+                    .as_standard_device_mut()
+                    .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+                let is_msi = matches!(dev.irq_type_mut(), Some(IrqType::Msi { .. }));
+                let vectors = dev.irq_vector_mut().ok_or(SystemError::EINVAL)?;
+                let vector = *vectors.get(index as usize).ok_or(SystemError::EINVAL)?;
+                return Ok((vector, vectors[0], is_msi));
+            })
+            .ok_or(SystemError::ENODEV)??;
+        // MSI的message address和message data在注册第0个向量时写入
+        if is_msi && index != 0 && !irq_manager().has_action(first, dev_id) {
+            return Err(SystemError::EINVAL);
+        }
+
+        if threaded {
+            irq_manager().request_threaded_irq(vector, name.to_string(), handler, flags, dev_id)?;
+        } else {
+            irq_manager().request_irq(vector, name.to_string(), handler, flags, dev_id)?;
+        }
+
+        let r = self
+            .with_structure_mut(|dev| -> Result<(), PciError> {
+                let dev = dev.as_standard_device_mut().unwrap();
+                if !is_msi || index == 0 {
+                    dev.irq_set_msg(index, cpu as u16, TriggerMode::EdgeTrigger)?;
+                }
+                dev.irq_enable(true)?;
+                return Ok(());
+            })
+            .ok_or(SystemError::ENODEV)
+            .and_then(|r| r.map_err(SystemError::from));
+        if let Err(e) = r {
+            kerror!(
+                "PCI: {} failed to install irq vector {}: {:?}",
+                bdf,
+                vector,
+                e
+            );
+            irq_manager().free_irq(vector, dev_id).ok();
+            return Err(e);
+        }
+        irq_manager().set_chip(vector, PciMsiChip::new(bdf, index), cpu)?;
+        return Ok(vector);
+    }
+
+    /// 关闭设备的MSI/MSI-X中断，注销所有的处理函数，并释放中断向量
+    pub fn free_irq_vectors(&self) {
+        // 先在不持有设备链表的锁的情况下注销处理函数，因为需要等待中断线程退出
+        let dev_id = pci_irq_dev_id(&self.bus_device_function());
+        let vectors = self
+            .with_structure_mut(|dev| dev.irq_vector_mut().cloned())
+            .flatten()
+            .unwrap_or_default();
+        for vector in vectors {
+            if irq_manager().has_action(vector, dev_id) {
+                irq_manager().free_irq(vector, dev_id).ok();
+            }
+        }
+
+        self.with_structure_mut(|dev| {
+            let dev = match dev.as_standard_device_mut() {
+                Some(dev) if dev.irq_vector_mut().map_or(false, |v| !v.is_empty()) => dev,
+                _ => return,
+            };
+            dev.irq_uninstall().ok();
+            let vectors = core::mem::take(dev.irq_vector_mut().unwrap());
+            *dev.irq_type_mut().unwrap() = IrqType::Unused;
+            msi_vectors_free(&vectors);
+        });
+    }
+
+    /// 获取第index个中断向量的向量号
+    pub fn irq_vector(&self, index: u16) -> Option<u16> {
+        self.with_structure_mut(|dev| {
+            dev.irq_vector_mut()
+                .and_then(|v| v.get(index as usize).copied())
+        })
+        .flatten()
+    }
+}
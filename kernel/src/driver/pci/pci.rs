@@ -1,7 +1,13 @@
 #![allow(dead_code)]
 // 目前仅支持单主桥单Segment
 
+use super::device::pci_device_manager;
+use super::pci_bus_init;
 use super::pci_irq::{IrqType, PciIrqError};
+use super::resource::{
+    pci_assign_unassigned_resources, pci_probe_bars, PciResourceType, PCI_BRIDGE_NUM_BARS,
+    PCI_STD_NUM_BARS,
+};
 use crate::arch::{PciArch, TraitPciArch};
 use crate::include::bindings::bindings::PAGE_2M_SIZE;
 use crate::libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
@@ -9,6 +15,7 @@ use crate::libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use crate::mm::mmio_buddy::{mmio_pool, MMIOSpaceGuard};
 
 use crate::mm::{PhysAddr, VirtAddr};
+use crate::syscall::SystemError;
 use crate::{kdebug, kerror, kinfo, kwarn};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -142,13 +149,13 @@ pub fn get_pci_device_structure<'a>(
     result
 }
 
-//Bar0寄存器的offset
-const BAR0_OFFSET: u8 = 0x10;
 //Status、Command寄存器的offset
-const STATUS_COMMAND_OFFSET: u8 = 0x04;
+pub const STATUS_COMMAND_OFFSET: u16 = 0x04;
 /// ID for vendor-specific PCI capabilities.(Virtio Capabilities)
 pub const PCI_CAP_ID_VNDR: u8 = 0x09;
+pub const PCI_CAP_ID_PM: u8 = 0x01;
 pub const PCI_CAP_ID_MSI: u8 = 0x05;
+pub const PCI_CAP_ID_EXP: u8 = 0x10;
 pub const PCI_CAP_ID_MSIX: u8 = 0x11;
 pub const PORT_PCI_CONFIG_ADDRESS: u16 = 0xcf8;
 pub const PORT_PCI_CONFIG_DATA: u16 = 0xcfc;
@@ -324,7 +331,7 @@ pub trait PciDeviceStructure: Send + Sync {
         let common_header = self.common_header_mut();
         let command = command.bits();
         common_header.command = command;
-        PciArch::write_config(
+        pci_write_config(
             &common_header.bus_device_function,
             STATUS_COMMAND_OFFSET,
             command as u32,
@@ -350,6 +357,11 @@ pub trait PciDeviceStructure: Send + Sync {
     fn enable_master(&mut self) {
         self.set_command(Command::IO_SPACE | Command::MEMORY_SPACE | Command::BUS_MASTER);
     }
+    /// @brief 关闭设备的总线主控，此后设备不能再发起DMA
+    fn disable_master(&mut self) {
+        let command = Command::from_bits_truncate(self.common_header().command);
+        self.set_command(command - Command::BUS_MASTER);
+    }
     /// @brief 寻找设备的msix空间的offset
     fn msix_capability_offset(&self) -> Option<u8> {
         for capability in self.capabilities()? {
@@ -677,7 +689,7 @@ impl PciRoot {
     /// @param register_offset 寄存器在设备中的offset
     /// @param data 要写入的值
     pub fn write_config(
-        &mut self,
+        &self,
         bus_device_function: BusDeviceFunction,
         register_offset: u16,
         data: u32,
@@ -692,6 +704,10 @@ impl PciRoot {
             .write_volatile(data)
         }
     }
+    /// @brief 判断设备是否位于该分组的总线范围内
+    fn contains(&self, bus_device_function: &BusDeviceFunction) -> bool {
+        bus_device_function.bus >= self.bus_begin && bus_device_function.bus <= self.bus_end
+    }
     /// @brief 返回迭代器，遍历pcie设备的external_capabilities
     pub fn external_capabilities(
         &self,
@@ -704,15 +720,52 @@ impl PciRoot {
         }
     }
 }
+/// 读取PCI设备配置空间中的一个32位寄存器
+///
+/// 优先通过ECAM访问；没有ECAM，或者设备不在ECAM的总线范围内时，使用端口IO的方式访问，
+/// 此时只能访问配置空间的前256字节，超出范围的读取会返回全1。
+///
+/// ## 参数
+///
+/// - `bus_device_function`：PCI设备的唯一标识
+/// - `offset`：寄存器在配置空间中的偏移量，必须按4字节对齐
+pub fn pci_read_config(bus_device_function: &BusDeviceFunction, offset: u16) -> u32 {
+    if let Some(root) = PCI_ROOT_0.as_ref() {
+        if root.contains(bus_device_function) {
+            return root.read_config(*bus_device_function, offset);
+        }
+    }
+    if offset >= 0x100 {
+        return u32::MAX;
+    }
+    return PciArch::read_config(bus_device_function, offset as u8);
+}
+
+/// 写入PCI设备配置空间中的一个32位寄存器
+///
+/// 访问方式与[`pci_read_config`]相同，端口IO方式下超出前256字节的写入会被忽略。
+pub fn pci_write_config(bus_device_function: &BusDeviceFunction, offset: u16, data: u32) {
+    if let Some(root) = PCI_ROOT_0.as_ref() {
+        if root.contains(bus_device_function) {
+            root.write_config(*bus_device_function, offset, data);
+            return;
+        }
+    }
+    if offset >= 0x100 {
+        return;
+    }
+    PciArch::write_config(bus_device_function, offset as u8, data);
+}
+
 /// Gets the capabilities 'pointer' for the device function, if any.
 /// @brief 获取第一个capability 的offset
 /// @param bus_device_function PCI设备的唯一标识
 /// @return Option<u8> offset
 pub fn capabilities_offset(bus_device_function: BusDeviceFunction) -> Option<u8> {
-    let result = PciArch::read_config(&bus_device_function, STATUS_COMMAND_OFFSET);
+    let result = pci_read_config(&bus_device_function, STATUS_COMMAND_OFFSET);
     let status: Status = Status::from_bits_truncate((result >> 16) as u16);
     if status.contains(Status::CAPABILITIES_LIST) {
-        let cap_pointer = PciArch::read_config(&bus_device_function, 0x34) as u8 & 0xFC;
+        let cap_pointer = pci_read_config(&bus_device_function, 0x34) as u8 & 0xFC;
         Some(cap_pointer)
     } else {
         None
@@ -728,21 +781,21 @@ fn pci_read_header(
     add_to_list: bool,
 ) -> Result<Box<dyn PciDeviceStructure>, PciError> {
     // 先读取公共header
-    let result = PciArch::read_config(&bus_device_function, 0x00);
+    let result = pci_read_config(&bus_device_function, 0x00);
     let vendor_id = result as u16;
     let device_id = (result >> 16) as u16;
 
-    let result = PciArch::read_config(&bus_device_function, 0x04);
+    let result = pci_read_config(&bus_device_function, 0x04);
     let command = result as u16;
     let status = (result >> 16) as u16;
 
-    let result = PciArch::read_config(&bus_device_function, 0x08);
+    let result = pci_read_config(&bus_device_function, 0x08);
     let revision_id = result as u8;
     let prog_if = (result >> 8) as u8;
     let subclass = (result >> 16) as u8;
     let class_code = (result >> 24) as u8;
 
-    let result = PciArch::read_config(&bus_device_function, 0x0c);
+    let result = pci_read_config(&bus_device_function, 0x0c);
     let cache_line_size = result as u8;
     let latency_timer = (result >> 8) as u8;
     let header_type = (result >> 16) as u8;
@@ -808,22 +861,22 @@ fn pci_read_general_device_header(
     bus_device_function: &BusDeviceFunction,
 ) -> PciDeviceStructureGeneralDevice {
     let standard_device_bar = PciStandardDeviceBar::default();
-    let cardbus_cis_pointer = PciArch::read_config(bus_device_function, 0x28);
+    let cardbus_cis_pointer = pci_read_config(bus_device_function, 0x28);
 
-    let result = PciArch::read_config(bus_device_function, 0x2c);
+    let result = pci_read_config(bus_device_function, 0x2c);
     let subsystem_vendor_id = result as u16;
     let subsystem_id = (result >> 16) as u16;
 
-    let expansion_rom_base_address = PciArch::read_config(bus_device_function, 0x30);
+    let expansion_rom_base_address = pci_read_config(bus_device_function, 0x30);
 
-    let result = PciArch::read_config(bus_device_function, 0x34);
+    let result = pci_read_config(bus_device_function, 0x34);
     let capabilities_pointer = result as u8;
     let reserved0 = (result >> 8) as u8;
     let reserved1 = (result >> 16) as u16;
 
-    let reserved2 = PciArch::read_config(bus_device_function, 0x38);
+    let reserved2 = pci_read_config(bus_device_function, 0x38);
 
-    let result = PciArch::read_config(bus_device_function, 0x3c);
+    let result = pci_read_config(bus_device_function, 0x3c);
     let interrupt_line = result as u8;
     let interrupt_pin = (result >> 8) as u8;
     let min_grant = (result >> 16) as u8;
@@ -857,44 +910,44 @@ fn pci_read_pci_to_pci_bridge_header(
     common_header: PciDeviceStructureHeader,
     bus_device_function: &BusDeviceFunction,
 ) -> PciDeviceStructurePciToPciBridge {
-    let bar0 = PciArch::read_config(bus_device_function, 0x10);
-    let bar1 = PciArch::read_config(bus_device_function, 0x14);
+    let bar0 = pci_read_config(bus_device_function, 0x10);
+    let bar1 = pci_read_config(bus_device_function, 0x14);
 
-    let result = PciArch::read_config(bus_device_function, 0x18);
+    let result = pci_read_config(bus_device_function, 0x18);
 
     let primary_bus_number = result as u8;
     let secondary_bus_number = (result >> 8) as u8;
     let subordinate_bus_number = (result >> 16) as u8;
     let secondary_latency_timer = (result >> 24) as u8;
 
-    let result = PciArch::read_config(bus_device_function, 0x1c);
+    let result = pci_read_config(bus_device_function, 0x1c);
     let io_base = result as u8;
     let io_limit = (result >> 8) as u8;
     let secondary_status = (result >> 16) as u16;
 
-    let result = PciArch::read_config(bus_device_function, 0x20);
+    let result = pci_read_config(bus_device_function, 0x20);
     let memory_base = result as u16;
     let memory_limit = (result >> 16) as u16;
 
-    let result = PciArch::read_config(bus_device_function, 0x24);
+    let result = pci_read_config(bus_device_function, 0x24);
     let prefetchable_memory_base = result as u16;
     let prefetchable_memory_limit = (result >> 16) as u16;
 
-    let prefetchable_base_upper_32_bits = PciArch::read_config(bus_device_function, 0x28);
-    let prefetchable_limit_upper_32_bits = PciArch::read_config(bus_device_function, 0x2c);
+    let prefetchable_base_upper_32_bits = pci_read_config(bus_device_function, 0x28);
+    let prefetchable_limit_upper_32_bits = pci_read_config(bus_device_function, 0x2c);
 
-    let result = PciArch::read_config(bus_device_function, 0x30);
+    let result = pci_read_config(bus_device_function, 0x30);
     let io_base_upper_16_bits = result as u16;
     let io_limit_upper_16_bits = (result >> 16) as u16;
 
-    let result = PciArch::read_config(bus_device_function, 0x34);
+    let result = pci_read_config(bus_device_function, 0x34);
     let capability_pointer = result as u8;
     let reserved0 = (result >> 8) as u8;
     let reserved1 = (result >> 16) as u16;
 
-    let expansion_rom_base_address = PciArch::read_config(bus_device_function, 0x38);
+    let expansion_rom_base_address = pci_read_config(bus_device_function, 0x38);
 
-    let result = PciArch::read_config(bus_device_function, 0x3c);
+    let result = pci_read_config(bus_device_function, 0x3c);
     let interrupt_line = result as u8;
     let interrupt_pin = (result >> 8) as u8;
     let bridge_control = (result >> 16) as u16;
@@ -938,38 +991,38 @@ fn pci_read_pci_to_cardbus_bridge_header(
     common_header: PciDeviceStructureHeader,
     busdevicefunction: &BusDeviceFunction,
 ) -> PciDeviceStructurePciToCardbusBridge {
-    let cardbus_socket_ex_ca_base_address = PciArch::read_config(busdevicefunction, 0x10);
+    let cardbus_socket_ex_ca_base_address = pci_read_config(busdevicefunction, 0x10);
 
-    let result = PciArch::read_config(busdevicefunction, 0x14);
+    let result = pci_read_config(busdevicefunction, 0x14);
     let offset_of_capabilities_list = result as u8;
     let reserved = (result >> 8) as u8;
     let secondary_status = (result >> 16) as u16;
 
-    let result = PciArch::read_config(busdevicefunction, 0x18);
+    let result = pci_read_config(busdevicefunction, 0x18);
     let pci_bus_number = result as u8;
     let card_bus_bus_number = (result >> 8) as u8;
     let subordinate_bus_number = (result >> 16) as u8;
     let card_bus_latency_timer = (result >> 24) as u8;
 
-    let memory_base_address0 = PciArch::read_config(busdevicefunction, 0x1c);
-    let memory_limit0 = PciArch::read_config(busdevicefunction, 0x20);
-    let memory_base_address1 = PciArch::read_config(busdevicefunction, 0x24);
-    let memory_limit1 = PciArch::read_config(busdevicefunction, 0x28);
+    let memory_base_address0 = pci_read_config(busdevicefunction, 0x1c);
+    let memory_limit0 = pci_read_config(busdevicefunction, 0x20);
+    let memory_base_address1 = pci_read_config(busdevicefunction, 0x24);
+    let memory_limit1 = pci_read_config(busdevicefunction, 0x28);
 
-    let io_base_address0 = PciArch::read_config(busdevicefunction, 0x2c);
-    let io_limit0 = PciArch::read_config(busdevicefunction, 0x30);
-    let io_base_address1 = PciArch::read_config(busdevicefunction, 0x34);
-    let io_limit1 = PciArch::read_config(busdevicefunction, 0x38);
-    let result = PciArch::read_config(busdevicefunction, 0x3c);
+    let io_base_address0 = pci_read_config(busdevicefunction, 0x2c);
+    let io_limit0 = pci_read_config(busdevicefunction, 0x30);
+    let io_base_address1 = pci_read_config(busdevicefunction, 0x34);
+    let io_limit1 = pci_read_config(busdevicefunction, 0x38);
+    let result = pci_read_config(busdevicefunction, 0x3c);
     let interrupt_line = result as u8;
     let interrupt_pin = (result >> 8) as u8;
     let bridge_control = (result >> 16) as u16;
 
-    let result = PciArch::read_config(busdevicefunction, 0x40);
+    let result = pci_read_config(busdevicefunction, 0x40);
     let subsystem_device_id = result as u16;
     let subsystem_vendor_id = (result >> 16) as u16;
 
-    let pc_card_legacy_mode_base_address_16_bit = PciArch::read_config(busdevicefunction, 0x44);
+    let pc_card_legacy_mode_base_address_16_bit = pci_read_config(busdevicefunction, 0x44);
     PciDeviceStructurePciToCardbusBridge {
         common_header,
         cardbus_socket_ex_ca_base_address,
@@ -1156,9 +1209,48 @@ pub fn pci_init() {
             HeaderType::Unrecognised(_) => {}
         }
     }
+    drop(list);
+
+    if let Err(e) = pci_register_devices() {
+        kerror!("Failed to add pci devices to the device model: {:?}", e);
+    }
     kinfo!("PCI bus initialized.");
 }
 
+/// 探测所有设备的BAR、为没有地址的BAR分配地址，然后把设备加入设备驱动模型
+fn pci_register_devices() -> Result<(), SystemError> {
+    pci_bus_init()?;
+
+    let mut headers = Vec::new();
+    let mut resources = Vec::new();
+    for dev in PCI_DEVICE_LINKEDLIST.read().iter() {
+        let header = dev.common_header().clone();
+        let num_bars = match dev.header_type() {
+            HeaderType::Standard => PCI_STD_NUM_BARS,
+            HeaderType::PciPciBridge => PCI_BRIDGE_NUM_BARS,
+            _ => 0,
+        };
+        let bars = pci_probe_bars(&header.bus_device_function, num_bars).unwrap_or_else(|e| {
+            kwarn!(
+                "PCI: failed to probe BARs of {}: {}",
+                header.bus_device_function,
+                e
+            );
+            Vec::new()
+        });
+        resources.push((header.bus_device_function, bars));
+        headers.push(header);
+    }
+
+    pci_assign_unassigned_resources(&mut resources);
+
+    // 添加设备时会探测驱动，而驱动可能会访问PCI_DEVICE_LINKEDLIST，因此这里不能持有它的锁
+    for (header, (_, bars)) in headers.iter().zip(resources) {
+        pci_device_manager().device_add(header, bars).ok();
+    }
+    return Ok(());
+}
+
 /// An identifier for a PCI bus, device and function.
 /// PCI设备的唯一标识
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -1361,69 +1453,52 @@ pub fn pci_bar_init(
     bus_device_function: BusDeviceFunction,
 ) -> Result<PciStandardDeviceBar, PciError> {
     let mut device_bar: PciStandardDeviceBar = PciStandardDeviceBar::default();
-    let mut bar_index_ignore: u8 = 255;
-    for bar_index in 0..6 {
-        if bar_index == bar_index_ignore {
-            continue;
-        }
+    for resource in pci_probe_bars(&bus_device_function, PCI_STD_NUM_BARS)? {
+        let bar_index = resource.bar;
         let bar_info;
-        let bar_orig = PciArch::read_config(&bus_device_function, BAR0_OFFSET + 4 * bar_index);
-        PciArch::write_config(
-            &bus_device_function,
-            BAR0_OFFSET + 4 * bar_index,
-            0xffffffff,
-        );
-        let size_mask = PciArch::read_config(&bus_device_function, BAR0_OFFSET + 4 * bar_index);
-        // A wrapping add is necessary to correctly handle the case of unused BARs, which read back
-        // as 0, and should be treated as size 0.
-        let size = (!(size_mask & 0xfffffff0)).wrapping_add(1);
-        //kdebug!("bar_orig:{:#x},size: {:#x}", bar_orig,size);
-        // Restore the original value.
-        PciArch::write_config(&bus_device_function, BAR0_OFFSET + 4 * bar_index, bar_orig);
-        if size == 0 {
+        if resource.size > u32::MAX as u64 {
+            kwarn!(
+                "pci_bar_init: {} bar{} is too large ({:#x}), ignored",
+                bus_device_function,
+                bar_index,
+                resource.size
+            );
             continue;
         }
-        if bar_orig & 0x00000001 == 0x00000001 {
-            // I/O space
-            let address = bar_orig & 0xfffffffc;
-            bar_info = BarInfo::IO { address, size };
-        } else {
-            // Memory space
-            let mut address = u64::from(bar_orig & 0xfffffff0);
-            let prefetchable = bar_orig & 0x00000008 != 0;
-            let address_type = MemoryBarType::try_from(((bar_orig & 0x00000006) >> 1) as u8)?;
-            if address_type == MemoryBarType::Width64 {
-                if bar_index >= 5 {
-                    return Err(PciError::InvalidBarType);
-                }
-                let address_top =
-                    PciArch::read_config(&bus_device_function, BAR0_OFFSET + 4 * (bar_index + 1));
-                address |= u64::from(address_top) << 32;
-                bar_index_ignore = bar_index + 1; //下个bar跳过，因为64位的memory bar覆盖了两个bar
+        let size = resource.size as u32;
+        match resource.res_type {
+            PciResourceType::Io => {
+                bar_info = BarInfo::IO {
+                    address: resource.start as u32,
+                    size,
+                };
             }
-            let pci_address = PciAddr::new(address as usize);
-            let paddr = PciArch::address_pci_to_physical(pci_address); //PCI总线域物理地址转换为存储器域物理地址
-
-            let space_guard: Arc<MMIOSpaceGuard>;
-            unsafe {
-                let size_want = size as usize;
-                let tmp = mmio_pool()
-                    .create_mmio(size_want)
-                    .map_err(|_| PciError::CreateMmioError)?;
-                space_guard = Arc::new(tmp);
-                //kdebug!("Pci bar init: mmio space: {space_guard:?}, paddr={paddr:?}, size_want={size_want}");
-                assert!(
-                    space_guard.map_phys(paddr, size_want).is_ok(),
-                    "pci_bar_init: map_phys failed"
-                );
+            PciResourceType::Memory(address_type) => {
+                let address = resource.start;
+                let pci_address = PciAddr::new(address as usize);
+                let paddr = PciArch::address_pci_to_physical(pci_address); //PCI总线域物理地址转换为存储器域物理地址
+
+                let space_guard: Arc<MMIOSpaceGuard>;
+                unsafe {
+                    let size_want = size as usize;
+                    let tmp = mmio_pool()
+                        .create_mmio(size_want)
+                        .map_err(|_| PciError::CreateMmioError)?;
+                    space_guard = Arc::new(tmp);
+                    //kdebug!("Pci bar init: mmio space: {space_guard:?}, paddr={paddr:?}, size_want={size_want}");
+                    assert!(
+                        space_guard.map_phys(paddr, size_want).is_ok(),
+                        "pci_bar_init: map_phys failed"
+                    );
+                }
+                bar_info = BarInfo::Memory {
+                    address_type,
+                    prefetchable: resource.prefetchable,
+                    address,
+                    size,
+                    mmio_guard: space_guard,
+                };
             }
-            bar_info = BarInfo::Memory {
-                address_type,
-                prefetchable,
-                address,
-                size,
-                mmio_guard: space_guard,
-            };
         }
         match bar_index {
             0 => {
@@ -1477,7 +1552,7 @@ impl Iterator for CapabilityIterator {
         let offset = self.next_capability_offset?;
 
         // Read the first 4 bytes of the capability.
-        let capability_header = PciArch::read_config(&self.bus_device_function, offset);
+        let capability_header = pci_read_config(&self.bus_device_function, offset as u16);
         let id = capability_header as u8;
         let next_offset = (capability_header >> 8) as u8;
         let private_header = (capability_header >> 16) as u16;
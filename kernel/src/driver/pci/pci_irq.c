@@ -92,4 +92,21 @@ void c_irq_uninstall(ul irq_num)
     }
     p->parameter = 0;
     p->handler = NULL;
-}
\ No newline at end of file
+}
+
+/// @brief 获取中断向量在注册时使用的名字
+/// @param irq_num 中断向量号
+/// @return 中断的名字，中断未被注册时返回NULL
+const char *c_irq_name(ul irq_num)
+{
+    irq_desc_t *p = NULL;
+    if (irq_num >= 32 && irq_num < 0x80)
+        p = &interrupt_desc[irq_num - 32];
+    else if (irq_num >= 150 && irq_num < 200)
+        p = &local_apic_interrupt_desc[irq_num - 150];
+    else if (irq_num >= 200 && irq_num < 200 + SMP_IRQ_NUM)
+        p = &SMP_IPI_desc[irq_num - 200];
+    else
+        return NULL;
+    return p->irq_name;
+}
@@ -6,12 +6,15 @@ use core::ptr::NonNull;
 use alloc::ffi::CString;
 use alloc::vec::Vec;
 
-use super::pci::{PciDeviceStructure, PciDeviceStructureGeneralDevice, PciError};
+use super::msi::PciMsiChip;
+use super::pci::{
+    BusDeviceFunction, PciDeviceStructure, PciDeviceStructureGeneralDevice, PciError,
+};
 use crate::arch::msi::{ia64_pci_get_arch_msi_message_address, ia64_pci_get_arch_msi_message_data};
 use crate::arch::{PciArch, TraitPciArch};
-use crate::include::bindings::bindings::{
-    c_irq_install, c_irq_uninstall, pt_regs, ul, EAGAIN, EINVAL,
-};
+use crate::exception::irqdesc::irq_manager;
+use crate::include::bindings::bindings::{pt_regs, ul};
+use crate::syscall::SystemError;
 
 use crate::libs::volatile::{volread, volwrite, Volatile};
 
@@ -129,6 +132,13 @@ pub enum TriggerMode {
     AssertLow,
 }
 
+/// 通过PCI设备注册中断处理函数时使用的注册者标识
+pub fn pci_irq_dev_id(bus_device_function: &BusDeviceFunction) -> usize {
+    ((bus_device_function.bus as usize) << 8)
+        | ((bus_device_function.device as usize) << 3)
+        | bus_device_function.function as usize
+}
+
 bitflags! {
     /// 设备中断类型，使用bitflag使得中断类型的选择更多元化
     pub struct IRQ: u8{
@@ -303,7 +313,7 @@ pub trait PciInterrupt: PciDeviceStructure {
     /// @return 一切正常返回Ok(0),有错误返回对应错误原因
     fn irq_install(&mut self, msg: IrqMsg) -> Result<u8, PciError> {
         if let Some(irq_vector) = self.irq_vector_mut() {
-            if msg.irq_common_message.irq_index as usize > irq_vector.len() {
+            if msg.irq_common_message.irq_index as usize >= irq_vector.len() {
                 return Err(PciError::PciIrqError(PciIrqError::InvalidIrqIndex(
                     msg.irq_common_message.irq_index,
                 )));
@@ -335,135 +345,244 @@ pub trait PciInterrupt: PciDeviceStructure {
     fn msi_install(&mut self, msg: IrqMsg) -> Result<u8, PciError> {
         if let Some(irq_type) = self.irq_type_mut() {
             match *irq_type {
-                IrqType::Msi {
-                    address_64,
-                    irq_max_num,
-                    cap_offset,
-                    ..
-                } => {
+                IrqType::Msi { irq_max_num, .. } => {
                     // 注意：MSI中断分配的中断号必须连续且大小为2的倍数
                     if self.irq_vector_mut().unwrap().len() > irq_max_num as usize {
                         return Err(PciError::PciIrqError(PciIrqError::DeviceIrqOverflow));
                     }
                     let irq_num =
                         self.irq_vector_mut().unwrap()[msg.irq_common_message.irq_index as usize];
+                    self.request_msg_irq(irq_num, &msg)?;
                     let common_msg = &msg.irq_common_message;
-                    let result = unsafe {
-                        c_irq_install(
-                            irq_num as u64,
-                            Some(common_msg.irq_hander),
-                            common_msg.irq_parameter as u64,
-                            common_msg.irq_name.as_ptr(),
-                            common_msg.irq_ack,
-                        )
-                    };
-                    match result as u32 {
-                        EINVAL => {
-                            return Err(PciError::PciIrqError(PciIrqError::InvalidIrqNum(irq_num)));
-                        }
-                        EAGAIN => {
-                            return Err(PciError::PciIrqError(PciIrqError::IrqNumOccupied(
-                                irq_num,
-                            )));
-                        }
-                        _ => {}
-                    }
                     //MSI中断只需配置一次PCI寄存器
                     if common_msg.irq_index == 0 {
-                        let msg_address = ia64_pci_get_arch_msi_message_address(0);
-                        let trigger = match msg.irq_specific_message {
+                        let (processor, trigger) = match msg.irq_specific_message {
                             IrqSpecificMsg::Legacy => {
                                 return Err(PciError::PciIrqError(PciIrqError::IrqTypeUnmatch));
                             }
-                            IrqSpecificMsg::Msi { trigger_mode, .. } => trigger_mode,
+                            IrqSpecificMsg::Msi {
+                                processor,
+                                trigger_mode,
+                            } => (processor, trigger_mode),
                         };
-                        let msg_data = ia64_pci_get_arch_msi_message_data(irq_num, 0, trigger);
-                        //写入Message Data和Message Address
-                        if address_64 {
+                        self.msi_set_msg(processor, trigger)?;
+                    }
+                    return Ok(0);
+                }
+                IrqType::Unused => {
+                    return Err(PciError::PciIrqError(PciIrqError::IrqNotInited));
+                }
+                _ => {
+                    return Err(PciError::PciIrqError(PciIrqError::IrqTypeUnmatch));
+                }
+            }
+        }
+        return Err(PciError::PciIrqError(PciIrqError::PciDeviceNotSupportIrq));
+    }
+    /// @brief 进行PCI设备中断的安装(MSIX)
+    /// @param self PCI设备的可变引用
+    /// @param msg PCI设备install中断时需要传递的共同参数
+    /// @return 一切正常返回Ok(0),有错误返回对应错误原因
+    fn msix_install(&mut self, msg: IrqMsg) -> Result<u8, PciError> {
+        if let Some(irq_type) = self.irq_type_mut() {
+            match *irq_type {
+                IrqType::Msix { irq_max_num, .. } => {
+                    if self.irq_vector_mut().unwrap().len() > irq_max_num as usize {
+                        return Err(PciError::PciIrqError(PciIrqError::DeviceIrqOverflow));
+                    }
+                    let irq_num =
+                        self.irq_vector_mut().unwrap()[msg.irq_common_message.irq_index as usize];
+                    self.request_msg_irq(irq_num, &msg)?;
+                    let common_msg = &msg.irq_common_message;
+
+                    let (processor, trigger) = match msg.irq_specific_message {
+                        IrqSpecificMsg::Legacy => {
+                            return Err(PciError::PciIrqError(PciIrqError::IrqTypeUnmatch));
+                        }
+                        IrqSpecificMsg::Msi {
+                            processor,
+                            trigger_mode,
+                        } => (processor, trigger_mode),
+                    };
+                    self.msix_set_msg(common_msg.irq_index, processor, trigger)?;
+                    return Ok(0);
+                }
+                IrqType::Unused => {
+                    return Err(PciError::PciIrqError(PciIrqError::IrqNotInited));
+                }
+                _ => {
+                    return Err(PciError::PciIrqError(PciIrqError::IrqTypeUnmatch));
+                }
+            }
+        }
+        return Err(PciError::PciIrqError(PciIrqError::PciDeviceNotSupportIrq));
+    }
+    /// @brief 通过通用中断处理层注册中断处理函数
+    /// @param self PCI设备的可变引用
+    /// @param irq_num 中断向量号
+    /// @param msg PCI设备install中断时需要传递的参数
+    fn request_msg_irq(&mut self, irq_num: u16, msg: &IrqMsg) -> Result<u8, PciError> {
+        let bus_device_function = self.common_header().bus_device_function;
+        let dev_id = pci_irq_dev_id(&bus_device_function);
+        let common_msg = &msg.irq_common_message;
+        irq_manager()
+            .request_c_irq(
+                irq_num,
+                common_msg.irq_name.to_string_lossy().into_owned(),
+                common_msg.irq_hander,
+                common_msg.irq_parameter as ul,
+                common_msg.irq_ack,
+                dev_id,
+            )
+            .map_err(|e| match e {
+                SystemError::EBUSY => PciError::PciIrqError(PciIrqError::IrqNumOccupied(irq_num)),
+                _ => PciError::PciIrqError(PciIrqError::InvalidIrqNum(irq_num)),
+            })?;
+        // 使得可以通过/proc/irq/<irq>/smp_affinity修改处理中断的CPU
+        let processor = match msg.irq_specific_message {
+            IrqSpecificMsg::Msi { processor, .. } => processor,
+            IrqSpecificMsg::Legacy => 0,
+        };
+        irq_manager()
+            .set_chip(
+                irq_num,
+                PciMsiChip::new(bus_device_function, common_msg.irq_index),
+                processor as usize,
+            )
+            .ok();
+        return Ok(0);
+    }
+    /// @brief 重新写入中断的Message Address和Message Data，用于修改处理中断的CPU
+    /// @param self PCI设备的可变引用
+    /// @param index 中断在irq_vector中的index，MSI的所有中断共用同一个Message Address，此时index被忽略
+    /// @param processor 目标CPU ID号
+    /// @param trigger 申请中断的触发模式
+    fn irq_set_msg(
+        &mut self,
+        index: u16,
+        processor: u16,
+        trigger: TriggerMode,
+    ) -> Result<u8, PciError> {
+        if let Some(irq_type) = self.irq_type_mut() {
+            match *irq_type {
+                IrqType::Msix { .. } => {
+                    return self.msix_set_msg(index, processor, trigger);
+                }
+                IrqType::Msi { .. } => {
+                    return self.msi_set_msg(processor, trigger);
+                }
+                IrqType::Unused => {
+                    return Err(PciError::PciIrqError(PciIrqError::IrqNotInited));
+                }
+                _ => {
+                    return Err(PciError::PciIrqError(PciIrqError::IrqTypeNotSupported));
+                }
+            }
+        }
+        return Err(PciError::PciIrqError(PciIrqError::PciDeviceNotSupportIrq));
+    }
+    /// @brief 写入MSI的Message Address和Message Data
+    /// @param self PCI设备的可变引用
+    /// @param processor 目标CPU ID号
+    /// @param trigger 申请中断的触发模式
+    fn msi_set_msg(&mut self, processor: u16, trigger: TriggerMode) -> Result<u8, PciError> {
+        if let Some(irq_type) = self.irq_type_mut() {
+            match *irq_type {
+                IrqType::Msi {
+                    address_64,
+                    cap_offset,
+                    ..
+                } => {
+                    let irq_num = *self
+                        .irq_vector_mut()
+                        .unwrap()
+                        .first()
+                        .ok_or(PciError::PciIrqError(PciIrqError::InvalidIrqIndex(0)))?;
+                    let msg_address = ia64_pci_get_arch_msi_message_address(processor);
+                    let msg_data = ia64_pci_get_arch_msi_message_data(irq_num, processor, trigger);
+                    //写入Message Data和Message Address
+                    if address_64 {
+                        PciArch::write_config(
+                            &self.common_header().bus_device_function,
+                            cap_offset + 4,
+                            msg_address,
+                        );
+                        PciArch::write_config(
+                            &self.common_header().bus_device_function,
+                            cap_offset + 8,
+                            0,
+                        );
+                        PciArch::write_config(
+                            &self.common_header().bus_device_function,
+                            cap_offset + 12,
+                            msg_data,
+                        );
+                    } else {
+                        PciArch::write_config(
+                            &self.common_header().bus_device_function,
+                            cap_offset + 4,
+                            msg_address,
+                        );
+                        PciArch::write_config(
+                            &self.common_header().bus_device_function,
+                            cap_offset + 8,
+                            msg_data,
+                        );
+                    }
+                    let data =
+                        PciArch::read_config(&self.common_header().bus_device_function, cap_offset);
+                    let message_control = (data >> 16) as u16;
+                    match self.irq_vector_mut().unwrap().len() {
+                        1 => {
+                            let temp = message_control & (!0x0070);
                             PciArch::write_config(
                                 &self.common_header().bus_device_function,
-                                cap_offset + 4,
-                                msg_address,
+                                cap_offset,
+                                (temp as u32) << 16,
                             );
+                        }
+                        2 => {
+                            let temp = message_control & (!0x0070);
                             PciArch::write_config(
                                 &self.common_header().bus_device_function,
-                                cap_offset + 8,
-                                0,
+                                cap_offset,
+                                ((temp | (0x0001 << 4)) as u32) << 16,
                             );
+                        }
+                        4 => {
+                            let temp = message_control & (!0x0070);
                             PciArch::write_config(
                                 &self.common_header().bus_device_function,
-                                cap_offset + 12,
-                                msg_data,
+                                cap_offset,
+                                ((temp | (0x0002 << 4)) as u32) << 16,
                             );
-                        } else {
+                        }
+                        8 => {
+                            let temp = message_control & (!0x0070);
                             PciArch::write_config(
                                 &self.common_header().bus_device_function,
-                                cap_offset + 4,
-                                msg_address,
+                                cap_offset,
+                                ((temp | (0x0003 << 4)) as u32) << 16,
                             );
+                        }
+                        16 => {
+                            let temp = message_control & (!0x0070);
                             PciArch::write_config(
                                 &self.common_header().bus_device_function,
-                                cap_offset + 8,
-                                msg_data,
+                                cap_offset,
+                                ((temp | (0x0004 << 4)) as u32) << 16,
                             );
                         }
-                        let data = PciArch::read_config(
-                            &self.common_header().bus_device_function,
-                            cap_offset,
-                        );
-                        let message_control = (data >> 16) as u16;
-                        match self.irq_vector_mut().unwrap().len() {
-                            1 => {
-                                let temp = message_control & (!0x0070);
-                                PciArch::write_config(
-                                    &self.common_header().bus_device_function,
-                                    cap_offset,
-                                    (temp as u32) << 16,
-                                );
-                            }
-                            2 => {
-                                let temp = message_control & (!0x0070);
-                                PciArch::write_config(
-                                    &self.common_header().bus_device_function,
-                                    cap_offset,
-                                    ((temp | (0x0001 << 4)) as u32) << 16,
-                                );
-                            }
-                            4 => {
-                                let temp = message_control & (!0x0070);
-                                PciArch::write_config(
-                                    &self.common_header().bus_device_function,
-                                    cap_offset,
-                                    ((temp | (0x0002 << 4)) as u32) << 16,
-                                );
-                            }
-                            8 => {
-                                let temp = message_control & (!0x0070);
-                                PciArch::write_config(
-                                    &self.common_header().bus_device_function,
-                                    cap_offset,
-                                    ((temp | (0x0003 << 4)) as u32) << 16,
-                                );
-                            }
-                            16 => {
-                                let temp = message_control & (!0x0070);
-                                PciArch::write_config(
-                                    &self.common_header().bus_device_function,
-                                    cap_offset,
-                                    ((temp | (0x0004 << 4)) as u32) << 16,
-                                );
-                            }
-                            32 => {
-                                let temp = message_control & (!0x0070);
-                                PciArch::write_config(
-                                    &self.common_header().bus_device_function,
-                                    cap_offset,
-                                    ((temp | (0x0005 << 4)) as u32) << 16,
-                                );
-                            }
-                            _ => {
-                                return Err(PciError::PciIrqError(PciIrqError::MxiIrqNumWrong));
-                            }
+                        32 => {
+                            let temp = message_control & (!0x0070);
+                            PciArch::write_config(
+                                &self.common_header().bus_device_function,
+                                cap_offset,
+                                ((temp | (0x0005 << 4)) as u32) << 16,
+                            );
+                        }
+                        _ => {
+                            return Err(PciError::PciIrqError(PciIrqError::MxiIrqNumWrong));
                         }
                     }
                     return Ok(0);
@@ -478,54 +597,31 @@ pub trait PciInterrupt: PciDeviceStructure {
         }
         return Err(PciError::PciIrqError(PciIrqError::PciDeviceNotSupportIrq));
     }
-    /// @brief 进行PCI设备中断的安装(MSIX)
+    /// @brief 写入MSIX表项的Message Address和Message Data
     /// @param self PCI设备的可变引用
-    /// @param msg PCI设备install中断时需要传递的共同参数
-    /// @return 一切正常返回Ok(0),有错误返回对应错误原因
-    fn msix_install(&mut self, msg: IrqMsg) -> Result<u8, PciError> {
+    /// @param index 中断在irq_vector中的index
+    /// @param processor 目标CPU ID号
+    /// @param trigger 申请中断的触发模式
+    fn msix_set_msg(
+        &mut self,
+        index: u16,
+        processor: u16,
+        trigger: TriggerMode,
+    ) -> Result<u8, PciError> {
         if let Some(irq_type) = self.irq_type_mut() {
             match *irq_type {
                 IrqType::Msix {
-                    irq_max_num,
                     msix_table_bar,
                     msix_table_offset,
                     ..
                 } => {
-                    if self.irq_vector_mut().unwrap().len() > irq_max_num as usize {
-                        return Err(PciError::PciIrqError(PciIrqError::DeviceIrqOverflow));
-                    }
-                    let irq_num =
-                        self.irq_vector_mut().unwrap()[msg.irq_common_message.irq_index as usize];
-                    let common_msg = &msg.irq_common_message;
-                    let result = unsafe {
-                        c_irq_install(
-                            irq_num as u64,
-                            Some(common_msg.irq_hander),
-                            common_msg.irq_parameter as u64,
-                            common_msg.irq_name.as_ptr(),
-                            common_msg.irq_ack,
-                        )
-                    };
-                    match result as u32 {
-                        EINVAL => {
-                            return Err(PciError::PciIrqError(PciIrqError::InvalidIrqNum(irq_num)));
-                        }
-                        EAGAIN => {
-                            return Err(PciError::PciIrqError(PciIrqError::IrqNumOccupied(
-                                irq_num,
-                            )));
-                        }
-                        _ => {}
-                    }
-
-                    let msg_address = ia64_pci_get_arch_msi_message_address(0);
-                    let trigger = match msg.irq_specific_message {
-                        IrqSpecificMsg::Legacy => {
-                            return Err(PciError::PciIrqError(PciIrqError::IrqTypeUnmatch));
-                        }
-                        IrqSpecificMsg::Msi { trigger_mode, .. } => trigger_mode,
-                    };
-                    let msg_data = ia64_pci_get_arch_msi_message_data(irq_num, 0, trigger);
+                    let irq_num = *self
+                        .irq_vector_mut()
+                        .unwrap()
+                        .get(index as usize)
+                        .ok_or(PciError::PciIrqError(PciIrqError::InvalidIrqIndex(index)))?;
+                    let msg_address = ia64_pci_get_arch_msi_message_address(processor);
+                    let msg_data = ia64_pci_get_arch_msi_message_data(irq_num, processor, trigger);
                     //写入Message Data和Message Address
                     let pcistandardbar = self
                         .bar()
@@ -535,7 +631,7 @@ pub trait PciInterrupt: PciDeviceStructure {
                         .virtual_address()
                         .ok_or(PciError::PciIrqError(PciIrqError::BarGetVaddrFailed))?
                         + msix_table_offset as usize
-                        + msg.irq_common_message.irq_index as usize * size_of::<MsixEntry>();
+                        + index as usize * size_of::<MsixEntry>();
                     let msix_entry = NonNull::new(vaddr.data() as *mut MsixEntry).unwrap();
                     // 这里的操作并不适用于所有架构，需要再优化，msg_upper_data并不一定为0
                     unsafe {
@@ -588,9 +684,10 @@ pub trait PciInterrupt: PciDeviceStructure {
                     cap_offset,
                     ..
                 } => {
+                    let dev_id = pci_irq_dev_id(&self.common_header().bus_device_function);
                     for vector in self.irq_vector_mut().unwrap() {
-                        unsafe {
-                            c_irq_uninstall(vector.clone() as u64);
+                        if irq_manager().has_action(*vector, dev_id) {
+                            irq_manager().free_irq(*vector, dev_id).ok();
                         }
                     }
                     PciArch::write_config(&self.common_header().bus_device_function, cap_offset, 0);
@@ -635,9 +732,10 @@ pub trait PciInterrupt: PciDeviceStructure {
                     msix_table_offset,
                     ..
                 } => {
+                    let dev_id = pci_irq_dev_id(&self.common_header().bus_device_function);
                     for vector in self.irq_vector_mut().unwrap() {
-                        unsafe {
-                            c_irq_uninstall(vector.clone() as u64);
+                        if irq_manager().has_action(*vector, dev_id) {
+                            irq_manager().free_irq(*vector, dev_id).ok();
                         }
                     }
                     PciArch::write_config(&self.common_header().bus_device_function, cap_offset, 0);
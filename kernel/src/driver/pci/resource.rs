@@ -0,0 +1,286 @@
+//! PCI设备BAR的探测与资源分配
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/pci/setup-res.c
+
+use alloc::vec::Vec;
+
+use crate::{kdebug, kwarn};
+
+use super::pci::{
+    pci_read_config, pci_write_config, BusDeviceFunction, Command, MemoryBarType, PciError,
+    STATUS_COMMAND_OFFSET,
+};
+
+/// Bar0寄存器的offset
+pub const BAR0_OFFSET: u16 = 0x10;
+
+/// 普通设备的BAR数量
+pub const PCI_STD_NUM_BARS: u8 = 6;
+/// PCI-to-PCI桥的BAR数量
+pub const PCI_BRIDGE_NUM_BARS: u8 = 2;
+
+/// 为没有被固件分配地址的32位memory BAR分配地址时，地址的上限(IOAPIC等设备的寄存器从这里开始)
+const PCI_MEM32_WINDOW_END: u64 = 0xfec0_0000;
+/// 为IO BAR分配地址时的下限(更低的端口被传统ISA设备占用)
+const PCI_IO_WINDOW_START: u64 = 0x1000;
+/// IO端口空间的上限
+const PCI_IO_WINDOW_END: u64 = 0x1_0000;
+
+/// BAR所描述的地址空间类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PciResourceType {
+    /// IO端口空间
+    Io,
+    /// 内存空间
+    Memory(MemoryBarType),
+}
+
+/// 一个BAR所描述的资源
+#[derive(Debug, Clone, Copy)]
+pub struct PciResource {
+    /// BAR的序号。64位的memory BAR占用两个BAR寄存器，序号为较低的那个
+    pub bar: u8,
+    pub res_type: PciResourceType,
+    /// 读取这段内存是否没有副作用
+    pub prefetchable: bool,
+    /// PCI总线域中的起始地址，为0表示固件没有为其分配地址
+    pub start: u64,
+    /// 大小（字节）
+    pub size: u64,
+}
+
+impl PciResource {
+    #[inline]
+    pub fn end(&self) -> u64 {
+        self.start + self.size
+    }
+
+    #[inline]
+    pub fn is_assigned(&self) -> bool {
+        self.start != 0
+    }
+
+    #[inline]
+    pub fn is_io(&self) -> bool {
+        self.res_type == PciResourceType::Io
+    }
+
+    /// 这段资源占用的BAR寄存器的数量
+    #[inline]
+    pub fn bar_count(&self) -> u8 {
+        match self.res_type {
+            PciResourceType::Memory(MemoryBarType::Width64) => 2,
+            _ => 1,
+        }
+    }
+
+    /// 把新的地址写入BAR寄存器
+    fn write_bar(&mut self, bus_device_function: &BusDeviceFunction, start: u64) {
+        let offset = BAR0_OFFSET + 4 * self.bar as u16;
+        let orig = pci_read_config(bus_device_function, offset);
+        let flags = if self.is_io() { orig & 0x3 } else { orig & 0xf };
+        pci_write_config(bus_device_function, offset, start as u32 | flags);
+        if self.bar_count() == 2 {
+            pci_write_config(bus_device_function, offset + 4, (start >> 32) as u32);
+        }
+        self.start = start;
+    }
+}
+
+/// 探测设备的BAR，获取每个BAR的类型、地址和大小
+///
+/// 探测期间会暂时关闭设备对IO和内存空间的响应，避免向BAR写入全1时设备解码到错误的地址上。
+///
+/// ## 参数
+///
+/// - `bus_device_function`：PCI设备的唯一标识
+/// - `num_bars`：BAR的数量。普通设备为[`PCI_STD_NUM_BARS`]，桥为[`PCI_BRIDGE_NUM_BARS`]
+///
+/// ## 返回值
+///
+/// 所有大小不为0的BAR
+pub fn pci_probe_bars(
+    bus_device_function: &BusDeviceFunction,
+    num_bars: u8,
+) -> Result<Vec<PciResource>, PciError> {
+    let status_command = pci_read_config(bus_device_function, STATUS_COMMAND_OFFSET);
+    let decode = (Command::IO_SPACE | Command::MEMORY_SPACE).bits() as u32;
+    if status_command & decode != 0 {
+        // 高16位是Status寄存器，写入0不会清除其中的状态位
+        pci_write_config(
+            bus_device_function,
+            STATUS_COMMAND_OFFSET,
+            status_command & 0xffff & !decode,
+        );
+    }
+
+    let r = do_probe_bars(bus_device_function, num_bars);
+
+    if status_command & decode != 0 {
+        pci_write_config(
+            bus_device_function,
+            STATUS_COMMAND_OFFSET,
+            status_command & 0xffff,
+        );
+    }
+    return r;
+}
+
+fn do_probe_bars(
+    bus_device_function: &BusDeviceFunction,
+    num_bars: u8,
+) -> Result<Vec<PciResource>, PciError> {
+    let mut resources = Vec::new();
+    let mut bar = 0;
+    while bar < num_bars {
+        let offset = BAR0_OFFSET + 4 * bar as u16;
+        let bar_orig = pci_read_config(bus_device_function, offset);
+        pci_write_config(bus_device_function, offset, 0xffffffff);
+        let size_mask = pci_read_config(bus_device_function, offset);
+        pci_write_config(bus_device_function, offset, bar_orig);
+
+        if bar_orig & 0x1 == 0x1 {
+            // I/O space，高16位可能没有实现
+            let size = (!(size_mask & 0xfffffffc) & 0xffff).wrapping_add(1) as u64;
+            if size_mask & 0xfffffffc != 0 {
+                resources.push(PciResource {
+                    bar,
+                    res_type: PciResourceType::Io,
+                    prefetchable: false,
+                    start: (bar_orig & 0xfffffffc) as u64,
+                    size,
+                });
+            }
+            bar += 1;
+            continue;
+        }
+
+        // Memory space
+        let address_type = MemoryBarType::try_from(((bar_orig & 0x6) >> 1) as u8)?;
+        let prefetchable = bar_orig & 0x8 != 0;
+        let mut start = (bar_orig & 0xfffffff0) as u64;
+        let mut mask = (size_mask & 0xfffffff0) as u64;
+        let implemented;
+        if address_type == MemoryBarType::Width64 {
+            if bar + 1 >= num_bars {
+                return Err(PciError::InvalidBarType);
+            }
+            let upper_orig = pci_read_config(bus_device_function, offset + 4);
+            pci_write_config(bus_device_function, offset + 4, 0xffffffff);
+            let upper_mask = pci_read_config(bus_device_function, offset + 4);
+            pci_write_config(bus_device_function, offset + 4, upper_orig);
+
+            start |= (upper_orig as u64) << 32;
+            mask |= (upper_mask as u64) << 32;
+            implemented = mask != 0;
+        } else {
+            implemented = mask != 0;
+            mask |= 0xffffffff_00000000;
+        }
+
+        // 未实现的BAR读回0
+        if implemented {
+            let size = (!mask).wrapping_add(1);
+            resources.push(PciResource {
+                bar,
+                res_type: PciResourceType::Memory(address_type),
+                prefetchable,
+                start,
+                size,
+            });
+        }
+
+        bar += if address_type == MemoryBarType::Width64 {
+            2
+        } else {
+            1
+        };
+    }
+
+    return Ok(resources);
+}
+
+/// 为固件没有分配地址的BAR分配地址
+///
+/// 目前只为根总线(bus 0)上的设备分配：其他总线上的设备受到上游桥的地址窗口的限制，
+/// 而我们还没有对桥的窗口进行重新配置。
+///
+/// 新的地址从已分配的地址之后开始，按大小降序、自然对齐地分配，
+/// 内存地址不超过[`PCI_MEM32_WINDOW_END`]，IO端口在[`PCI_IO_WINDOW_START`]到[`PCI_IO_WINDOW_END`]之间。
+///
+/// ## 参数
+///
+/// - `devices`：所有设备及其BAR资源，分配成功后会更新其中的地址
+pub fn pci_assign_unassigned_resources(devices: &mut [(BusDeviceFunction, Vec<PciResource>)]) {
+    let mut mem_next: u64 = 0;
+    let mut io_next: u64 = PCI_IO_WINDOW_START;
+    for (_, resources) in devices.iter() {
+        for res in resources.iter().filter(|r| r.is_assigned()) {
+            if res.is_io() {
+                io_next = io_next.max(res.end());
+            } else if res.end() <= PCI_MEM32_WINDOW_END {
+                mem_next = mem_next.max(res.end());
+            }
+        }
+    }
+
+    // 收集所有未分配的资源：(设备下标, 资源下标)
+    let mut pending: Vec<(usize, usize)> = Vec::new();
+    for (i, (bdf, resources)) in devices.iter().enumerate() {
+        for (j, res) in resources.iter().enumerate() {
+            if res.is_assigned() {
+                continue;
+            }
+            if bdf.bus != 0 {
+                kwarn!(
+                    "PCI: {} BAR{} is not assigned, and it is behind a bridge",
+                    bdf,
+                    res.bar
+                );
+                continue;
+            }
+            pending.push((i, j));
+        }
+    }
+    pending.sort_by(|a, b| devices[b.0].1[b.1].size.cmp(&devices[a.0].1[a.1].size));
+
+    for (i, j) in pending {
+        let (bdf, resources) = &mut devices[i];
+        let res = &mut resources[j];
+        let (next, limit) = if res.is_io() {
+            (&mut io_next, PCI_IO_WINDOW_END)
+        } else {
+            // 没有任何已分配的内存BAR作为参照时，我们无法确定哪些地址是空闲的
+            if mem_next == 0 {
+                kwarn!(
+                    "PCI: no memory window to assign {} BAR{} (size {:#x})",
+                    bdf,
+                    res.bar,
+                    res.size
+                );
+                continue;
+            }
+            (&mut mem_next, PCI_MEM32_WINDOW_END)
+        };
+
+        let start = (*next + res.size - 1) & !(res.size - 1);
+        if start + res.size > limit {
+            kwarn!(
+                "PCI: failed to assign {} BAR{} (size {:#x}): window exhausted",
+                bdf,
+                res.bar,
+                res.size
+            );
+            continue;
+        }
+        res.write_bar(bdf, start);
+        *next = start + res.size;
+        kdebug!(
+            "PCI: assigned {} BAR{} to [{:#x}, {:#x})",
+            bdf,
+            res.bar,
+            res.start,
+            res.end()
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+};
+use intertrait::cast::CastArc;
+
+use crate::{
+    driver::base::{
+        device::{bus::Bus, driver::Driver, Device},
+        kobject::KObject,
+        subsys::SubSysPrivate,
+    },
+    filesystem::{
+        sysfs::{file::sysfs_emit_str, Attribute, AttributeGroup, SysFSOpsSupport},
+        vfs::syscall::ModeType,
+    },
+    syscall::SystemError,
+};
+
+use super::{device::PciDevice, driver::PciDriver};
+
+#[derive(Debug)]
+pub struct PciBus {
+    private: SubSysPrivate,
+}
+
+impl PciBus {
+    pub fn new() -> Arc<Self> {
+        let w: Weak<Self> = Weak::new();
+        let private = SubSysPrivate::new("pci".to_string(), w, &[]);
+        let bus = Arc::new(Self { private });
+        bus.subsystem()
+            .set_bus(Arc::downgrade(&(bus.clone() as Arc<dyn Bus>)));
+
+        return bus;
+    }
+
+    fn to_pci_device(device: &Arc<dyn Device>) -> Result<Arc<PciDevice>, SystemError> {
+        return device
+            .clone()
+            .arc_any()
+            .downcast::<PciDevice>()
+            .map_err(|_| {
+                kerror!(
+                    "PciBus: device is not a PciDevice. Device: '{:?}'",
+                    device.name()
+                );
+                SystemError::EINVAL
+            });
+    }
+
+    fn to_pci_driver(driver: Arc<dyn Driver>) -> Result<Arc<dyn PciDriver>, SystemError> {
+        return driver.cast::<dyn PciDriver>().map_err(|drv| {
+            kerror!(
+                "PciBus: driver is not a PciDriver. Driver: '{:?}'",
+                drv.name()
+            );
+            SystemError::EINVAL
+        });
+    }
+}
+
+impl Bus for PciBus {
+    fn name(&self) -> String {
+        return "pci".to_string();
+    }
+
+    fn dev_name(&self) -> String {
+        return self.name();
+    }
+
+    fn dev_groups(&self) -> &'static [&'static dyn AttributeGroup] {
+        return &[&PciDeviceAttrGroup];
+    }
+
+    fn subsystem(&self) -> &SubSysPrivate {
+        return &self.private;
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/pci/pci-driver.c#438
+    fn probe(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        let drv = Self::to_pci_driver(device.driver().ok_or(SystemError::EINVAL)?)?;
+        let pdev = Self::to_pci_device(device)?;
+        let id = drv
+            .pci_id_table()
+            .iter()
+            .find(|id| id.matches(&pdev))
+            .ok_or(SystemError::ENODEV)?;
+        return drv.probe(&pdev, id);
+    }
+
+    fn remove(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        if let Some(drv) = device.driver() {
+            Self::to_pci_driver(drv)?.remove(&Self::to_pci_device(device)?);
+        }
+        return Ok(());
+    }
+
+    fn shutdown(&self, device: &Arc<dyn Device>) {
+        if let Some(drv) = device.driver() {
+            if let (Ok(drv), Ok(pdev)) = (Self::to_pci_driver(drv), Self::to_pci_device(device)) {
+                PciDriver::shutdown(drv.as_ref(), &pdev);
+            }
+        }
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/pci/pci-driver.c?fi=pci_pm_suspend#pci_pm_suspend
+    fn suspend(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        if let Some(drv) = device.driver() {
+            PciDriver::suspend(
+                Self::to_pci_driver(drv)?.as_ref(),
+                &Self::to_pci_device(device)?,
+            )?;
+        }
+        return Ok(());
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/pci/pci-driver.c?fi=pci_pm_resume#pci_pm_resume
+    fn resume(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        if let Some(drv) = device.driver() {
+            PciDriver::resume(
+                Self::to_pci_driver(drv)?.as_ref(),
+                &Self::to_pci_device(device)?,
+            )?;
+        }
+        return Ok(());
+    }
+
+    /// 根据驱动的PCI匹配表，判断驱动能否驱动设备
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/pci/pci-driver.c#1509
+    fn match_device(
+        &self,
+        device: &Arc<dyn Device>,
+        driver: &Arc<dyn Driver>,
+    ) -> Result<bool, SystemError> {
+        let drv = Self::to_pci_driver(driver.clone())?;
+        let pdev = Self::to_pci_device(device)?;
+        return Ok(drv.pci_id_table().iter().any(|id| id.matches(&pdev)));
+    }
+}
+
+#[derive(Debug)]
+pub struct PciDeviceAttrGroup;
+
+impl AttributeGroup for PciDeviceAttrGroup {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        return &[
+            &PciDeviceAttr::Vendor,
+            &PciDeviceAttr::Device,
+            &PciDeviceAttr::Class,
+        ];
+    }
+
+    fn is_visible(&self, _kobj: Arc<dyn KObject>, attr: &dyn Attribute) -> Option<ModeType> {
+        return Some(attr.mode());
+    }
+}
+
+/// PCI设备文件夹下的只读属性文件
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/pci/pci-sysfs.c#44
+#[derive(Debug)]
+enum PciDeviceAttr {
+    Vendor,
+    Device,
+    Class,
+}
+
+impl Attribute for PciDeviceAttr {
+    fn name(&self) -> &str {
+        match self {
+            PciDeviceAttr::Vendor => "vendor",
+            PciDeviceAttr::Device => "device",
+            PciDeviceAttr::Class => "class",
+        }
+    }
+
+    fn mode(&self) -> ModeType {
+        // 0o444
+        return ModeType::S_IRUGO;
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let dev = kobj
+            .arc_any()
+            .downcast::<PciDevice>()
+            .map_err(|_| SystemError::EINVAL)?;
+        let s = match self {
+            PciDeviceAttr::Vendor => format!("0x{:04x}\n", dev.vendor_id()),
+            PciDeviceAttr::Device => format!("0x{:04x}\n", dev.device_id()),
+            PciDeviceAttr::Class => format!("0x{:06x}\n", dev.class()),
+        };
+        return sysfs_emit_str(buf, &s);
+    }
+}
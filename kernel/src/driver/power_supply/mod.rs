@@ -0,0 +1,436 @@
+//! 电源(power_supply)子系统
+//!
+//! 电池、交流适配器等电源的驱动实现[`PowerSupply`]，通过[`power_supply_register`]注册之后，
+//! 电源出现在`/sys/class/power_supply/<名字>`下，每个属性是一个文件，文件名与取值的格式与Linux相同，例如：
+//!
+//! ```text
+//! /sys/class/power_supply/BAT0/status     Discharging
+//! /sys/class/power_supply/BAT0/capacity   87
+//! /sys/class/power_supply/AC/online       0
+//! ```
+//!
+//! 只有驱动在[`PowerSupply::properties`]中声明的属性才会创建文件。读取文件时才向驱动查询属性的值。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/power/supply/power_supply_core.c
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/power/supply/power_supply_sysfs.c
+
+use core::fmt::{Debug, Display};
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::{
+        class::{class_manager, Class, ClassDevice},
+        device::{Device, DeviceNumber, DeviceType},
+        kobject::KObject,
+        subsys::SubSysPrivate,
+    },
+    filesystem::{
+        sysfs::{file::sysfs_emit_str, Attribute, AttributeGroup, SysFSOpsSupport},
+        vfs::syscall::ModeType,
+    },
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+};
+
+static mut POWER_SUPPLY_CLASS_INSTANCE: Option<Arc<PowerSupplyClass>> = None;
+
+/// 已经注册的电源
+static POWER_SUPPLIES: SpinLock<Vec<Arc<dyn PowerSupply>>> = SpinLock::new(Vec::new());
+
+/// 电源的class，`/sys/class/power_supply`
+#[inline(always)]
+pub fn power_supply_class() -> Arc<dyn Class> {
+    unsafe { POWER_SUPPLY_CLASS_INSTANCE.clone().unwrap() }
+}
+
+/// 电源的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSupplyType {
+    Unknown,
+    Battery,
+    /// 交流适配器
+    Mains,
+}
+
+impl PowerSupplyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unknown => "Unknown",
+            Self::Battery => "Battery",
+            Self::Mains => "Mains",
+        }
+    }
+}
+
+/// 电池的充电状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSupplyStatus {
+    Unknown,
+    Charging,
+    Discharging,
+    NotCharging,
+    Full,
+}
+
+impl Display for PowerSupplyStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let s = match self {
+            Self::Unknown => "Unknown",
+            Self::Charging => "Charging",
+            Self::Discharging => "Discharging",
+            Self::NotCharging => "Not charging",
+            Self::Full => "Full",
+        };
+        return f.write_str(s);
+    }
+}
+
+/// 电池的化学类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSupplyTechnology {
+    Unknown,
+    NiMH,
+    LiIon,
+    LiPoly,
+    LiFe,
+    NiCd,
+    LiMn,
+}
+
+impl Display for PowerSupplyTechnology {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let s = match self {
+            Self::Unknown => "Unknown",
+            Self::NiMH => "NiMH",
+            Self::LiIon => "Li-ion",
+            Self::LiPoly => "Li-poly",
+            Self::LiFe => "LiFe",
+            Self::NiCd => "NiCd",
+            Self::LiMn => "LiMn",
+        };
+        return f.write_str(s);
+    }
+}
+
+/// 电池的电量等级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSupplyCapacityLevel {
+    Unknown,
+    Critical,
+    Low,
+    Normal,
+    High,
+    Full,
+}
+
+impl Display for PowerSupplyCapacityLevel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let s = match self {
+            Self::Unknown => "Unknown",
+            Self::Critical => "Critical",
+            Self::Low => "Low",
+            Self::Normal => "Normal",
+            Self::High => "High",
+            Self::Full => "Full",
+        };
+        return f.write_str(s);
+    }
+}
+
+/// 电源的属性。电压、电流、功率、电量、能量的单位分别是µV、µA、µW、µAh、µWh
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSupplyProperty {
+    Status,
+    Present,
+    /// 交流适配器是否接入
+    Online,
+    Technology,
+    CycleCount,
+    VoltageMinDesign,
+    VoltageNow,
+    CurrentNow,
+    PowerNow,
+    ChargeFullDesign,
+    ChargeFull,
+    ChargeNow,
+    EnergyFullDesign,
+    EnergyFull,
+    EnergyNow,
+    /// 剩余电量的百分比
+    Capacity,
+    CapacityLevel,
+    ModelName,
+    Manufacturer,
+    SerialNumber,
+}
+
+impl PowerSupplyProperty {
+    /// 属性文件的名字
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Present => "present",
+            Self::Online => "online",
+            Self::Technology => "technology",
+            Self::CycleCount => "cycle_count",
+            Self::VoltageMinDesign => "voltage_min_design",
+            Self::VoltageNow => "voltage_now",
+            Self::CurrentNow => "current_now",
+            Self::PowerNow => "power_now",
+            Self::ChargeFullDesign => "charge_full_design",
+            Self::ChargeFull => "charge_full",
+            Self::ChargeNow => "charge_now",
+            Self::EnergyFullDesign => "energy_full_design",
+            Self::EnergyFull => "energy_full",
+            Self::EnergyNow => "energy_now",
+            Self::Capacity => "capacity",
+            Self::CapacityLevel => "capacity_level",
+            Self::ModelName => "model_name",
+            Self::Manufacturer => "manufacturer",
+            Self::SerialNumber => "serial_number",
+        }
+    }
+}
+
+/// 属性的值
+#[derive(Debug, Clone)]
+pub enum PowerSupplyValue {
+    Int(i64),
+    Str(String),
+}
+
+impl Display for PowerSupplyValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Int(v) => write!(f, "{}", v),
+            Self::Str(s) => f.write_str(s),
+        }
+    }
+}
+
+impl From<PowerSupplyStatus> for PowerSupplyValue {
+    fn from(status: PowerSupplyStatus) -> Self {
+        Self::Str(status.to_string())
+    }
+}
+
+impl From<PowerSupplyTechnology> for PowerSupplyValue {
+    fn from(technology: PowerSupplyTechnology) -> Self {
+        Self::Str(technology.to_string())
+    }
+}
+
+impl From<PowerSupplyCapacityLevel> for PowerSupplyValue {
+    fn from(level: PowerSupplyCapacityLevel) -> Self {
+        Self::Str(level.to_string())
+    }
+}
+
+/// 电源驱动应该实现的操作
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/power_supply.h?fi=power_supply_desc#power_supply_desc
+pub trait PowerSupply: Debug + Send + Sync {
+    /// 电源的名字，也是`/sys/class/power_supply`下的目录名，必须唯一
+    fn name(&self) -> String;
+
+    fn supply_type(&self) -> PowerSupplyType;
+
+    /// 电源支持的属性
+    fn properties(&self) -> &'static [PowerSupplyProperty];
+
+    /// 获取属性的当前值
+    ///
+    /// ## 错误
+    ///
+    /// 暂时无法获取属性时返回错误，读取属性文件的进程会得到这个错误
+    fn get_property(&self, prop: PowerSupplyProperty) -> Result<PowerSupplyValue, SystemError>;
+}
+
+/// 注册一个电源，创建`/sys/class/power_supply/<名字>`
+///
+/// ## 参数
+///
+/// - `psy`: 电源
+/// - `parent`: 父设备，为None时设备位于`/sys/devices/virtual/power_supply`下
+///
+/// ## 错误
+///
+/// - `EEXIST`：已经有同名的电源
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/power/supply/power_supply_core.c?fi=power_supply_register#power_supply_register
+pub fn power_supply_register(
+    psy: Arc<dyn PowerSupply>,
+    parent: Option<&Arc<dyn Device>>,
+) -> Result<Arc<ClassDevice>, SystemError> {
+    let name = psy.name();
+    {
+        let mut supplies = POWER_SUPPLIES.lock();
+        if supplies.iter().any(|p| p.name() == name) {
+            return Err(SystemError::EEXIST);
+        }
+        // 创建属性文件时需要根据名字找到电源，所以要在创建设备之前加入列表
+        supplies.push(psy.clone());
+    }
+
+    let r = class_manager().device_create(
+        power_supply_class(),
+        parent,
+        DeviceNumber::default(),
+        name.clone(),
+    );
+    if r.is_err() {
+        POWER_SUPPLIES.lock().retain(|p| !Arc::ptr_eq(p, &psy));
+    }
+    return r;
+}
+
+/// 根据设备找到对应的电源
+fn power_supply_of(kobj: &Arc<dyn KObject>) -> Result<Arc<dyn PowerSupply>, SystemError> {
+    let name = kobj.name();
+    return POWER_SUPPLIES
+        .lock()
+        .iter()
+        .find(|p| p.name() == name)
+        .cloned()
+        .ok_or(SystemError::ENODEV);
+}
+
+/// 电源的class
+#[derive(Debug)]
+struct PowerSupplyClass {
+    subsystem: SubSysPrivate,
+}
+
+impl Class for PowerSupplyClass {
+    fn name(&self) -> &'static str {
+        "power_supply"
+    }
+
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::PowerSupply
+    }
+
+    fn dev_groups(&self) -> &'static [&'static dyn AttributeGroup] {
+        &[&PowerSupplyAttrGroup]
+    }
+
+    fn subsystem(&self) -> &SubSysPrivate {
+        &self.subsystem
+    }
+}
+
+/// 电源设备的属性，只为驱动声明的属性创建文件
+#[derive(Debug)]
+struct PowerSupplyAttrGroup;
+
+impl AttributeGroup for PowerSupplyAttrGroup {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        return &[
+            &PowerSupplyAttrType,
+            &PowerSupplyAttr(PowerSupplyProperty::Status),
+            &PowerSupplyAttr(PowerSupplyProperty::Present),
+            &PowerSupplyAttr(PowerSupplyProperty::Online),
+            &PowerSupplyAttr(PowerSupplyProperty::Technology),
+            &PowerSupplyAttr(PowerSupplyProperty::CycleCount),
+            &PowerSupplyAttr(PowerSupplyProperty::VoltageMinDesign),
+            &PowerSupplyAttr(PowerSupplyProperty::VoltageNow),
+            &PowerSupplyAttr(PowerSupplyProperty::CurrentNow),
+            &PowerSupplyAttr(PowerSupplyProperty::PowerNow),
+            &PowerSupplyAttr(PowerSupplyProperty::ChargeFullDesign),
+            &PowerSupplyAttr(PowerSupplyProperty::ChargeFull),
+            &PowerSupplyAttr(PowerSupplyProperty::ChargeNow),
+            &PowerSupplyAttr(PowerSupplyProperty::EnergyFullDesign),
+            &PowerSupplyAttr(PowerSupplyProperty::EnergyFull),
+            &PowerSupplyAttr(PowerSupplyProperty::EnergyNow),
+            &PowerSupplyAttr(PowerSupplyProperty::Capacity),
+            &PowerSupplyAttr(PowerSupplyProperty::CapacityLevel),
+            &PowerSupplyAttr(PowerSupplyProperty::ModelName),
+            &PowerSupplyAttr(PowerSupplyProperty::Manufacturer),
+            &PowerSupplyAttr(PowerSupplyProperty::SerialNumber),
+        ];
+    }
+
+    fn is_visible(&self, kobj: Arc<dyn KObject>, attr: &'static dyn Attribute) -> Option<ModeType> {
+        let psy = match power_supply_of(&kobj) {
+            Ok(psy) => psy,
+            Err(_) => return Some(ModeType::empty()),
+        };
+        if attr.name() == PowerSupplyAttrType.name()
+            || psy.properties().iter().any(|p| p.name() == attr.name())
+        {
+            return Some(attr.mode());
+        }
+        return Some(ModeType::empty());
+    }
+}
+
+/// `type`：电源的类型
+#[derive(Debug)]
+struct PowerSupplyAttrType;
+
+impl Attribute for PowerSupplyAttrType {
+    fn name(&self) -> &str {
+        "type"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::from_bits_truncate(0o444)
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let psy = power_supply_of(&kobj)?;
+        return sysfs_emit_str(buf, &format!("{}\n", psy.supply_type().as_str()));
+    }
+}
+
+/// 由驱动提供值的属性
+#[derive(Debug)]
+struct PowerSupplyAttr(PowerSupplyProperty);
+
+impl Attribute for PowerSupplyAttr {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::from_bits_truncate(0o444)
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let psy = power_supply_of(&kobj)?;
+        let value = psy.get_property(self.0)?;
+        return sysfs_emit_str(buf, &format!("{}\n", value));
+    }
+}
+
+/// 注册电源的class
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/power/supply/power_supply_core.c?fi=power_supply_class_init#power_supply_class_init
+pub fn power_supply_init() -> Result<(), SystemError> {
+    let class = Arc::new(PowerSupplyClass {
+        subsystem: SubSysPrivate::new_class("power_supply".to_string()),
+    });
+    class_manager().class_register(&(class.clone() as Arc<dyn Class>))?;
+    unsafe {
+        POWER_SUPPLY_CLASS_INSTANCE = Some(class);
+    }
+    return Ok(());
+}
@@ -1,7 +1,6 @@
 use crate::{
-    arch::CurrentIrqArch,
+    arch::{io::PortIOArch, CurrentIrqArch, CurrentPortIOArch},
     exception::InterruptArch,
-    include::bindings::bindings::{io_in8, io_out8},
     syscall::SystemError,
 };
 
@@ -63,7 +62,7 @@ impl RtcTime {
         }
 
         unsafe {
-            io_out8(0x70, 0x00);
+            CurrentPortIOArch::out8(0x70, 0x00);
         }
 
         if !is_binary
@@ -78,22 +77,96 @@ impl RtcTime {
         }
         self.year += 2000;
 
-        if (!is_24h) && (self.hour & 0x80) != 0 {
-            self.hour = ((self.hour & 0x7f) + 12) % 24;
+        if !is_24h {
+            let pm = (self.hour & 0x80) != 0;
+            self.hour = (self.hour & 0x7f) % 12;
+            if pm {
+                self.hour += 12;
+            }
         } // 将十二小时制转为24小时
 
         drop(irq_guard);
 
         return Ok(0);
     }
+
+    /// 把时间写入主板cmos
+    ///
+    /// 按照状态寄存器B中设置的格式(BCD/二进制、12/24小时制)写入
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：时间不合法，或者年份不在2000~2099之间(cmos只保存了年份的后两位)
+    pub fn set(&self) -> Result<(), SystemError> {
+        if !(2000..2100).contains(&self.year)
+            || !(1..=12).contains(&self.month)
+            || !(1..=31).contains(&self.day)
+            || !(0..24).contains(&self.hour)
+            || !(0..60).contains(&self.minute)
+            || !(0..60).contains(&self.second)
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        let status_register_b: u8 = read_cmos(0x0B);
+        let is_24h = (status_register_b & 0x02) != 0;
+        let is_binary = (status_register_b & 0x04) != 0;
+
+        let encode = |val: i32| -> u8 {
+            if is_binary {
+                val as u8
+            } else {
+                (((val / 10) << 4) | (val % 10)) as u8
+            }
+        };
+
+        let hour = if is_24h {
+            encode(self.hour)
+        } else {
+            // 十二小时制下，0点为12AM，最高位表示PM
+            let h = match self.hour % 12 {
+                0 => 12,
+                h => h,
+            };
+            encode(h) | if self.hour >= 12 { 0x80 } else { 0 }
+        };
+
+        // 置位SET位，写入期间停止RTC的更新
+        write_cmos(0x0B, status_register_b | 0x80);
+        write_cmos(CMOSTimeSelector::Year as u8, encode(self.year - 2000));
+        write_cmos(CMOSTimeSelector::Month as u8, encode(self.month));
+        write_cmos(CMOSTimeSelector::Day as u8, encode(self.day));
+        write_cmos(CMOSTimeSelector::Hour as u8, hour);
+        write_cmos(CMOSTimeSelector::Minute as u8, encode(self.minute));
+        write_cmos(CMOSTimeSelector::Second as u8, encode(self.second));
+        write_cmos(0x0B, status_register_b & !0x80);
+
+        unsafe {
+            CurrentPortIOArch::out8(0x70, 0x00);
+        }
+
+        drop(irq_guard);
+
+        return Ok(());
+    }
 }
 
 ///置位0x70的第7位，禁止不可屏蔽中断
 #[inline]
 fn read_cmos(addr: u8) -> u8 {
     unsafe {
-        io_out8(0x70, 0x80 | addr);
-        return io_in8(0x71);
+        CurrentPortIOArch::out8(0x70, 0x80 | addr);
+        return CurrentPortIOArch::in8(0x71);
+    }
+}
+
+///置位0x70的第7位，禁止不可屏蔽中断
+#[inline]
+fn write_cmos(addr: u8, val: u8) {
+    unsafe {
+        CurrentPortIOArch::out8(0x70, 0x80 | addr);
+        CurrentPortIOArch::out8(0x71, val);
     }
 }
 
@@ -1,25 +1,40 @@
-use core::intrinsics::unlikely;
-
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 
 use thingbuf::mpsc::{
     self,
     errors::{TryRecvError, TrySendError},
 };
 
-use crate::libs::rwlock::RwLock;
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    filesystem::vfs::{file::FileMode, PollStatus, PollTable},
+    ipc::signal_types::{SigInfo, SigType},
+    libs::{
+        rwlock::RwLock,
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    process::{Pid, ProcessManager},
+    syscall::SystemError,
+};
+
+use self::{
+    n_tty::{n_tty_process_output, NTtyData},
+    termios::{LocalMode, Termios},
+};
 
 pub mod init;
+pub mod n_tty;
 pub mod serial;
+pub mod termios;
 pub mod tty_device;
 pub mod tty_driver;
+pub mod vt;
 
 bitflags! {
     pub struct TtyCoreState: u32{
         /// 在读取stdin缓冲区时，由于队列为空，有读者被阻塞
         const BLOCK_AT_STDIN_READ = (1 << 0);
-        /// 开启输入回显。
-        const ECHO_ON = (1 << 1);
     }
 
     #[derive(Default)]
@@ -34,9 +49,33 @@ bitflags! {
 }
 
 /// @brief tty文件的私有信息
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct TtyFilePrivateData {
     flags: TtyFileFlag,
+    /// 打开文件的模式，用于判断是否为非阻塞读取
+    mode: FileMode,
+}
+
+impl TtyFilePrivateData {
+    pub fn new(mode: FileMode) -> Self {
+        return Self {
+            flags: TtyFileFlag::empty(),
+            mode,
+        };
+    }
+
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+}
+
+/// @brief tty的行规程，以及行规程所使用的终端属性
+///
+/// 输入端口在中断上下文中被调用，因此两者放在同一把自旋锁中，并且加锁时需要关中断
+#[derive(Debug)]
+struct TtyLdisc {
+    data: NTtyData,
+    termios: Termios,
 }
 
 /// @brief tty设备的核心功能结构体。在此结构体的基础上，衍生出TTY/PTY/PTS等
@@ -45,21 +84,22 @@ pub struct TtyFilePrivateData {
 /// - stdin：连接到一个活动进程的stdin文件描述符
 /// - stdout：连接到多个进程的stdout文件描述符
 /// - stderr：连接到多个进程的stdout文件描述符
-/// - 输入端口：向tty设备输入数据的接口。输入到该接口的数据，将被导向stdin接口。
+/// - 输入端口：向tty设备输入数据的接口。输入到该接口的数据，经过行规程(N_TTY)的处理之后，被导向stdin接口。
 ///     如果开启了回显，那么，数据也将同时被导向输出端
 /// - 输出端口：tty设备对外输出数据的端口。从stdout、stderr输入的数据，将会被导向此端口。
 ///             此端口可以连接到屏幕、文件、或者是另一个tty core的输入端口。如果开启了
 ///             输入数据回显，那么，输入端口的数据，将会被同时导向此端口，以及stdin端口
 #[derive(Debug)]
 struct TtyCore {
-    /// stdin的mpsc队列输入输出端
-    stdin_rx: mpsc::Receiver<u8>,
-    stdin_tx: mpsc::Sender<u8>,
+    /// 行规程以及终端属性
+    ldisc: SpinLock<TtyLdisc>,
+    /// 等待stdin数据的读者
+    read_wait: WaitQueue,
     /// 输出的mpsc队列输入输出端
     output_rx: mpsc::Receiver<u8>,
     output_tx: mpsc::Sender<u8>,
-    // 前台进程,以后改成前台进程组
-    // front_job: Option<Pid>,
+    /// 前台进程组，控制字符产生的信号发送给这个进程组
+    pgrp: SpinLock<Option<Pid>>,
     /// tty核心的状态
     state: RwLock<TtyCoreState>,
 }
@@ -82,39 +122,141 @@ pub enum TtyError {
 
 impl TtyCore {
     // 各个缓冲区的大小
-    pub const STDIN_BUF_SIZE: usize = 4096;
+    pub const STDIN_BUF_SIZE: usize = NTtyData::BUF_SIZE;
     pub const OUTPUT_BUF_SIZE: usize = 4096;
 
     /// @brief 创建一个TTY核心组件
     pub fn new() -> TtyCore {
-        let (stdin_tx, stdin_rx) = mpsc::channel::<u8>(Self::STDIN_BUF_SIZE);
         let (output_tx, output_rx) = mpsc::channel::<u8>(Self::OUTPUT_BUF_SIZE);
         let state: RwLock<TtyCoreState> = RwLock::new(TtyCoreState { bits: 0 });
 
         return TtyCore {
-            stdin_rx,
-            stdin_tx,
+            ldisc: SpinLock::new(TtyLdisc {
+                data: NTtyData::new(),
+                termios: Termios::std(),
+            }),
+            read_wait: WaitQueue::INIT,
             output_rx,
             output_tx,
+            pgrp: SpinLock::new(None),
             state,
         };
     }
 
-    /// @brief 向tty的输入端口输入数据
+    /// @brief 向tty的输入端口输入数据，数据经过行规程处理之后进入stdin
     ///
     /// @param buf 输入数据
     ///
-    /// @param block 是否允许阻塞
+    /// @param block 回显时，输出缓冲区满的情况下是否允许阻塞
     ///
     /// @return Ok(成功传送的字节数)
     /// @return Err(TtyError) 内部错误信息
     pub fn input(&self, buf: &[u8], block: bool) -> Result<usize, TtyError> {
-        let val = self.write_stdin(buf, block)?;
-        // 如果开启了输入回显，那么就写一份到输出缓冲区
-        if self.echo_enabled() {
-            self.write_output(&buf[0..val], true)?;
+        for &c in buf {
+            let mut ldisc = self.ldisc.lock_irqsave();
+            let termios = ldisc.termios;
+            let r = ldisc.data.receive_char(c, &termios);
+            drop(ldisc);
+
+            if !r.echo.is_empty() {
+                // 回显的数据在读者被唤醒之后才会被输出到屏幕，缓冲区满时丢弃
+                match self.write_output(&n_tty_process_output(&r.echo, &termios), block) {
+                    Ok(_) | Err(TtyError::BufferFull(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if r.readable || !r.echo.is_empty() {
+                self.read_wait.wakeup_all(None);
+            }
+            if let Some(sig) = r.signal {
+                self.signal_foreground(sig);
+            }
+        }
+        return Ok(buf.len());
+    }
+
+    /// @brief 向前台进程组发送控制字符产生的信号
+    ///
+    /// 前台进程组由TIOCSPGRP设置，进程组id等于进程组中首进程的pid
+    fn signal_foreground(&self, sig: Signal) {
+        let pgrp = match *self.pgrp.lock_irqsave() {
+            Some(pgrp) => pgrp,
+            // 没有设置前台进程组时，不发送信号
+            None => return,
+        };
+        for pid in ProcessManager::get_all_pids() {
+            let pcb = match ProcessManager::find(pid) {
+                Some(pcb) => pcb,
+                None => continue,
+            };
+            if pid != pgrp && pcb.basic().pgid() != pgrp {
+                continue;
+            }
+            let mut info = SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(pid));
+            // 目标进程可能已经退出
+            sig.send_signal_info(Some(&mut info), pid).ok();
+        }
+    }
+
+    /// @brief 获取前台进程组
+    pub fn foreground_pgrp(&self) -> Option<Pid> {
+        return *self.pgrp.lock_irqsave();
+    }
+
+    /// @brief 设置前台进程组
+    ///
+    /// @return 进程组中没有任何进程时，返回ESRCH
+    pub fn set_foreground_pgrp(&self, pgrp: Pid) -> Result<(), SystemError> {
+        let exists = ProcessManager::get_all_pids().into_iter().any(|pid| {
+            pid == pgrp || ProcessManager::find(pid).map_or(false, |pcb| pcb.basic().pgid() == pgrp)
+        });
+        if !exists {
+            return Err(SystemError::ESRCH);
+        }
+        *self.pgrp.lock_irqsave() = Some(pgrp);
+        return Ok(());
+    }
+
+    /// @brief 获取终端属性
+    pub fn termios(&self) -> Termios {
+        return self.ldisc.lock_irqsave().termios;
+    }
+
+    /// @brief 设置终端属性
+    ///
+    /// @param termios 新的终端属性
+    /// @param flush 是否丢弃尚未被读取的输入(TCSETSF)
+    pub fn set_termios(&self, termios: Termios, flush: bool) {
+        let mut ldisc = self.ldisc.lock_irqsave();
+        if flush {
+            ldisc.data.flush();
+        }
+        let canonical = termios.lflag().contains(LocalMode::ICANON);
+        if ldisc.termios.lflag().contains(LocalMode::ICANON) != canonical {
+            ldisc.data.set_canonical(canonical);
+        }
+        ldisc.termios = termios;
+        drop(ldisc);
+        // 属性变化之后，读者可能可以返回了
+        self.read_wait.wakeup_all(None);
+    }
+
+    /// @brief stdin中可以被读取的字节数
+    pub fn stdin_available(&self) -> usize {
+        let ldisc = self.ldisc.lock_irqsave();
+        return ldisc.data.available(&ldisc.termios);
+    }
+
+    /// @brief 获取tty的状态，并把当前进程注册到stdin的等待队列上
+    ///
+    /// @param table 本次poll使用的等待表
+    pub fn poll(&self, table: &mut PollTable) -> PollStatus {
+        table.wait(&self.read_wait);
+        // 输出是同步完成的，因此tty总是可写
+        if self.stdin_available() > 0 {
+            return PollStatus::READ | PollStatus::WRITE;
         }
-        return Ok(val);
+        return PollStatus::WRITE;
     }
 
     /// @brief 从tty的输出端口读出数据
@@ -136,7 +278,7 @@ impl TtyCore {
     /// @return Err(TtyError) 内部错误信息
     #[inline]
     pub fn stdout(&self, buf: &[u8], block: bool) -> Result<usize, TtyError> {
-        return self.write_output(buf, block);
+        return self.write_processed(buf, block);
     }
 
     /// @brief tty的stderr接口
@@ -147,83 +289,56 @@ impl TtyCore {
     /// @return Err(TtyError) 内部错误信息
     #[inline]
     pub fn stderr(&self, buf: &[u8], block: bool) -> Result<usize, TtyError> {
-        return self.write_output(buf, block);
+        return self.write_processed(buf, block);
     }
 
-    /// @brief 读取TTY的stdin缓冲区
+    /// @brief 按照终端属性的输出模式处理数据，然后写入output缓冲区
     ///
-    /// @param buf 读取到的位置
-    /// @param block 是否阻塞读
-    ///
-    /// @return Ok(成功读取的字节数)
-    /// @return Err(TtyError) 内部错误信息
-    pub fn read_stdin(&self, buf: &mut [u8], block: bool) -> Result<usize, TtyError> {
-        // TODO: 增加对EOF的处理
-        let mut cnt = 0;
-        while cnt < buf.len() {
-            let val: Result<mpsc::RecvRef<u8>, TryRecvError> = self.stdin_rx.try_recv_ref();
-            if let Err(err) = val {
-                match err {
-                    TryRecvError::Closed => return Err(TtyError::Closed),
-                    TryRecvError::Empty => {
-                        if block {
-                            continue;
-                        } else {
-                            return Ok(cnt);
-                        }
-                    }
-                    _ => return Err(TtyError::Unknown(format!("{err:?}"))),
-                }
-            } else {
-                let x = *val.unwrap();
-                buf[cnt] = x;
-                cnt += 1;
-
-                if unlikely(self.stdin_should_return(x)) {
-                    return Ok(cnt);
-                }
-            }
-        }
-        return Ok(cnt);
-    }
-
-    fn stdin_should_return(&self, c: u8) -> bool {
-        // 如果是换行符或者是ctrl+d，那么就应该返回
-        return c == b'\n' || c == 4;
+    /// @return Ok(buf中成功传送的字节数)
+    fn write_processed(&self, buf: &[u8], block: bool) -> Result<usize, TtyError> {
+        let termios = self.termios();
+        let processed: Vec<u8> = n_tty_process_output(buf, &termios);
+        self.write_output(&processed, block)?;
+        return Ok(buf.len());
     }
 
-    /// @brief 向stdin缓冲区内写入数据
+    /// @brief 读取TTY的stdin缓冲区
     ///
-    /// @param buf 输入缓冲区
+    /// 规范模式下，每次最多读取一行；非规范模式下，至少等到VMIN个字符
     ///
-    /// @param block 当缓冲区满的时候，是否阻塞
+    /// @param buf 读取到的位置
+    /// @param block 是否阻塞读
+    /// @param before_sleep 每次睡眠之前调用，用于把回显的数据输出到屏幕
     ///
-    /// @return Ok(成功传送的字节数)
-    /// @return Err(BufferFull(成功传送的字节数)) 缓冲区满，成功传送的字节数
-    /// @return Err(TtyError) 内部错误信息
-    fn write_stdin(&self, buf: &[u8], block: bool) -> Result<usize, TtyError> {
-        let mut cnt = 0;
-        while cnt < buf.len() {
-            let r: Result<mpsc::SendRef<u8>, TrySendError> = self.stdin_tx.try_send_ref();
-            if let Err(e) = r {
-                match e {
-                    TrySendError::Closed(_) => return Err(TtyError::Closed),
-                    TrySendError::Full(_) => {
-                        if block {
-                            continue;
-                        } else {
-                            return Err(TtyError::BufferFull(cnt));
-                        }
-                    }
-                    _ => return Err(TtyError::Unknown(format!("{e:?}"))),
-                }
-            } else {
-                *r.unwrap() = buf[cnt];
-                cnt += 1;
+    /// @return Ok(成功读取的字节数)，在行首读到EOF字符时返回0
+    /// @return Err(EAGAIN_OR_EWOULDBLOCK) 非阻塞读时没有数据
+    /// @return Err(EINTR) 等待的过程中收到了信号
+    pub fn read_stdin(
+        &self,
+        buf: &mut [u8],
+        block: bool,
+        before_sleep: impl Fn(),
+    ) -> Result<usize, SystemError> {
+        loop {
+            before_sleep();
+            let mut ldisc: SpinLockGuard<TtyLdisc> = self.ldisc.lock_irqsave();
+            let termios = ldisc.termios;
+            if let Some(n) = ldisc.data.read(buf, &termios) {
+                return Ok(n);
             }
+            if !block {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if ProcessManager::current_pcb()
+                .sig_info()
+                .has_pending_signal()
+            {
+                return Err(SystemError::EINTR);
+            }
+            self.state.write().insert(TtyCoreState::BLOCK_AT_STDIN_READ);
+            self.read_wait.sleep_unlock_spinlock(ldisc);
+            self.state.write().remove(TtyCoreState::BLOCK_AT_STDIN_READ);
         }
-
-        return Ok(cnt);
     }
 
     /// @brief 读取TTY的output缓冲区
@@ -306,15 +421,19 @@ impl TtyCore {
 
     /// @brief 开启tty输入回显（也就是将输入数据传送一份到输出缓冲区）
     #[inline]
+    #[allow(dead_code)]
     pub fn enable_echo(&self) {
-        self.state.write().set(TtyCoreState::ECHO_ON, true);
+        let mut termios = self.termios();
+        termios.c_lflag |= LocalMode::ECHO.bits();
+        self.set_termios(termios, false);
     }
 
     /// @brief 关闭输入回显
     #[inline]
-    #[allow(dead_code)]
     pub fn disable_echo(&self) {
-        self.state.write().set(TtyCoreState::ECHO_ON, false);
+        let mut termios = self.termios();
+        termios.c_lflag &= !LocalMode::ECHO.bits();
+        self.set_termios(termios, false);
     }
 
     /// @brief 判断当前tty核心，是否开启了输入回显
@@ -325,7 +444,7 @@ impl TtyCore {
     #[inline]
     #[allow(dead_code)]
     pub fn echo_enabled(&self) -> bool {
-        return self.state.read().contains(TtyCoreState::ECHO_ON);
+        return self.termios().lflag().contains(LocalMode::ECHO);
     }
 }
 
@@ -0,0 +1,350 @@
+//! N_TTY行规程
+//!
+//! 行规程位于tty的输入端口与stdin之间，根据termios对输入的字符进行处理：
+//! - 规范模式(ICANON)下，输入按行缓冲，支持ERASE、WERASE、KILL等行编辑字符，读者每次最多读到一行
+//! - 非规范模式下，输入的字符直接交给读者，读者至少等到VMIN个字符
+//! - 开启ISIG时，INTR、QUIT、SUSP字符产生对应的信号
+//! - 开启ECHO时，输入的字符被回显到输出端口
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/n_tty.c
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::arch::ipc::signal::Signal;
+
+use super::termios::{
+    InputMode, LocalMode, OutputMode, Termios, VEOF, VEOL, VEOL2, VERASE, VINTR, VKILL, VLNEXT,
+    VMIN, VQUIT, VSUSP, VWERASE,
+};
+
+/// 行规程处理一个输入字符的结果
+#[derive(Debug, Default)]
+pub struct NTtyReceive {
+    /// 需要回显到输出端口的数据
+    pub echo: Vec<u8>,
+    /// 需要向前台进程组发送的信号
+    pub signal: Option<Signal>,
+    /// 是否有新的数据可以被读者读取
+    pub readable: bool,
+}
+
+/// @brief N_TTY行规程的状态
+#[derive(Debug)]
+pub struct NTtyData {
+    /// 可以被读者读取的数据
+    read_buf: VecDeque<u8>,
+    /// 规范模式下，read_buf中每一行的长度。长度为0的行表示行首的EOF
+    lines: VecDeque<usize>,
+    /// 规范模式下，正在编辑的行
+    line: Vec<u8>,
+    /// 上一个字符是LNEXT，下一个字符按照普通字符处理
+    lnext: bool,
+}
+
+impl NTtyData {
+    /// 输入缓冲区的大小，超出之后新输入的字符被丢弃
+    pub const BUF_SIZE: usize = 4096;
+
+    pub const fn new() -> Self {
+        Self {
+            read_buf: VecDeque::new(),
+            lines: VecDeque::new(),
+            line: Vec::new(),
+            lnext: false,
+        }
+    }
+
+    /// @brief 清空所有尚未被读取的输入
+    pub fn flush(&mut self) {
+        self.read_buf.clear();
+        self.lines.clear();
+        self.line.clear();
+        self.lnext = false;
+    }
+
+    /// @brief 切换规范模式时调用。离开规范模式时，正在编辑的行直接交给读者
+    pub fn set_canonical(&mut self, canonical: bool) {
+        if !canonical {
+            self.read_buf.extend(self.line.drain(..));
+            self.lines.clear();
+        } else {
+            // 进入规范模式时，已有的数据作为一行
+            if !self.read_buf.is_empty() {
+                self.lines.clear();
+                self.lines.push_back(self.read_buf.len());
+            }
+        }
+        self.lnext = false;
+    }
+
+    /// @brief 可以被读取的字节数
+    pub fn available(&self, termios: &Termios) -> usize {
+        if termios.lflag().contains(LocalMode::ICANON) {
+            return self.lines.iter().sum();
+        }
+        return self.read_buf.len();
+    }
+
+    /// @brief 判断读者是否可以返回，而不需要继续等待输入
+    pub fn readable(&self, termios: &Termios) -> bool {
+        if termios.lflag().contains(LocalMode::ICANON) {
+            return !self.lines.is_empty();
+        }
+        let vmin = termios.c_cc[VMIN] as usize;
+        return vmin == 0 || self.read_buf.len() >= vmin;
+    }
+
+    /// @brief 读取输入的数据
+    ///
+    /// @param buf 读取到的位置
+    /// @param termios 终端的属性
+    ///
+    /// @return 数据未就绪、需要等待时返回None；读到行首的EOF时返回Some(0)
+    pub fn read(&mut self, buf: &mut [u8], termios: &Termios) -> Option<usize> {
+        if !self.readable(termios) {
+            return None;
+        }
+
+        if termios.lflag().contains(LocalMode::ICANON) {
+            let line_len = self.lines.front_mut().unwrap();
+            let n = core::cmp::min(*line_len, buf.len());
+            for (dst, src) in buf.iter_mut().zip(self.read_buf.drain(..n)) {
+                *dst = src;
+            }
+            // 一行没有读完时，剩余的部分留给下一次读取
+            *line_len -= n;
+            if *line_len == 0 {
+                self.lines.pop_front();
+            }
+            return Some(n);
+        }
+
+        let n = core::cmp::min(self.read_buf.len(), buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.read_buf.drain(..n)) {
+            *dst = src;
+        }
+        return Some(n);
+    }
+
+    /// @brief 处理一个输入的字符
+    ///
+    /// @param c 输入的字符
+    /// @param termios 终端的属性
+    ///
+    /// @return 处理的结果，包括需要回显的数据、需要发送的信号
+    pub fn receive_char(&mut self, c: u8, termios: &Termios) -> NTtyReceive {
+        let mut result = NTtyReceive::default();
+        let iflag = termios.iflag();
+        let lflag = termios.lflag();
+
+        let mut c = c;
+        if iflag.contains(InputMode::ISTRIP) {
+            c &= 0x7f;
+        }
+
+        if self.lnext {
+            self.lnext = false;
+            self.put_char(c, termios, &mut result);
+            return result;
+        }
+
+        if c == b'\r' {
+            if iflag.contains(InputMode::IGNCR) {
+                return result;
+            }
+            if iflag.contains(InputMode::ICRNL) {
+                c = b'\n';
+            }
+        } else if c == b'\n' && iflag.contains(InputMode::INLCR) {
+            c = b'\r';
+        }
+
+        if lflag.contains(LocalMode::ISIG) {
+            let signal = if termios.is_cc(VINTR, c) {
+                Some(Signal::SIGINT)
+            } else if termios.is_cc(VQUIT, c) {
+                Some(Signal::SIGQUIT)
+            } else if termios.is_cc(VSUSP, c) {
+                Some(Signal::SIGTSTP)
+            } else {
+                None
+            };
+            if signal.is_some() {
+                if !lflag.contains(LocalMode::NOFLSH) {
+                    self.flush();
+                }
+                self.echo_char(c, termios, &mut result.echo);
+                result.signal = signal;
+                return result;
+            }
+        }
+
+        if lflag.contains(LocalMode::ICANON) {
+            if termios.is_cc(VERASE, c) {
+                self.erase(false, termios, &mut result.echo);
+                return result;
+            }
+            if termios.is_cc(VKILL, c) {
+                self.kill(termios, &mut result.echo);
+                return result;
+            }
+            if lflag.contains(LocalMode::IEXTEN) {
+                if termios.is_cc(VWERASE, c) {
+                    self.erase(true, termios, &mut result.echo);
+                    return result;
+                }
+                if termios.is_cc(VLNEXT, c) {
+                    self.lnext = true;
+                    if lflag.contains(LocalMode::ECHO) && lflag.contains(LocalMode::ECHOCTL) {
+                        result.echo.extend_from_slice(b"^\x08");
+                    }
+                    return result;
+                }
+            }
+            if termios.is_cc(VEOF, c) {
+                // EOF字符结束当前行，但不会被读者读到
+                self.end_line(&mut result);
+                return result;
+            }
+            if c == b'\n' || termios.is_cc(VEOL, c) || termios.is_cc(VEOL2, c) {
+                if self.line.len() + self.read_buf.len() < Self::BUF_SIZE {
+                    self.line.push(c);
+                }
+                if lflag.contains(LocalMode::ECHO)
+                    || (c == b'\n' && lflag.contains(LocalMode::ECHONL))
+                {
+                    self.echo_char(c, termios, &mut result.echo);
+                }
+                self.end_line(&mut result);
+                return result;
+            }
+        }
+
+        self.put_char(c, termios, &mut result);
+        return result;
+    }
+
+    /// 把普通字符放入缓冲区并回显
+    fn put_char(&mut self, c: u8, termios: &Termios, result: &mut NTtyReceive) {
+        if self.line.len() + self.read_buf.len() >= Self::BUF_SIZE {
+            return;
+        }
+        if termios.lflag().contains(LocalMode::ECHO) {
+            self.echo_char(c, termios, &mut result.echo);
+        }
+        if termios.lflag().contains(LocalMode::ICANON) {
+            self.line.push(c);
+        } else {
+            self.read_buf.push_back(c);
+            result.readable = self.readable(termios);
+        }
+    }
+
+    /// 规范模式下，把正在编辑的行交给读者
+    fn end_line(&mut self, result: &mut NTtyReceive) {
+        self.lines.push_back(self.line.len());
+        self.read_buf.extend(self.line.drain(..));
+        result.readable = true;
+    }
+
+    /// 回显一个字符，开启ECHOCTL时，控制字符回显为^X的形式
+    fn echo_char(&self, c: u8, termios: &Termios, echo: &mut Vec<u8>) {
+        if termios.lflag().contains(LocalMode::ECHOCTL)
+            && Self::is_ctrl(c)
+            && c != b'\t'
+            && c != b'\n'
+        {
+            echo.push(b'^');
+            echo.push(c ^ 0x40);
+        } else {
+            echo.push(c);
+        }
+    }
+
+    #[inline]
+    fn is_ctrl(c: u8) -> bool {
+        return c < 0x20 || c == 0x7f;
+    }
+
+    /// 擦除正在编辑的行的最后一个字符(或者最后一个单词)
+    fn erase(&mut self, word: bool, termios: &Termios, echo: &mut Vec<u8>) {
+        let mut seen_alnum = false;
+        while let Some(&c) = self.line.last() {
+            if word {
+                let alnum = c.is_ascii_alphanumeric() || c == b'_';
+                if alnum {
+                    seen_alnum = true;
+                } else if seen_alnum {
+                    break;
+                }
+            }
+            self.line.pop();
+            self.echo_erase(c, termios, echo);
+            if !word {
+                break;
+            }
+        }
+    }
+
+    /// 擦除正在编辑的整行
+    fn kill(&mut self, termios: &Termios, echo: &mut Vec<u8>) {
+        let lflag = termios.lflag();
+        if !lflag.contains(LocalMode::ECHO) {
+            self.line.clear();
+            return;
+        }
+        if lflag.contains(LocalMode::ECHOKE) && lflag.contains(LocalMode::ECHOE) {
+            while let Some(c) = self.line.pop() {
+                self.echo_erase(c, termios, echo);
+            }
+            return;
+        }
+        self.line.clear();
+        self.echo_char(termios.c_cc[VKILL], termios, echo);
+        if lflag.contains(LocalMode::ECHOK) {
+            echo.push(b'\n');
+        }
+    }
+
+    /// 回显擦除一个字符。以^X的形式回显的控制字符占两列
+    fn echo_erase(&self, c: u8, termios: &Termios, echo: &mut Vec<u8>) {
+        let lflag = termios.lflag();
+        if !lflag.contains(LocalMode::ECHO) {
+            return;
+        }
+        if !lflag.contains(LocalMode::ECHOE) {
+            self.echo_char(termios.c_cc[VERASE], termios, echo);
+            return;
+        }
+        let width = if lflag.contains(LocalMode::ECHOCTL) && Self::is_ctrl(c) && c != b'\t' {
+            2
+        } else {
+            1
+        };
+        for _ in 0..width {
+            echo.extend_from_slice(b"\x08 \x08");
+        }
+    }
+}
+
+/// @brief 按照termios的输出模式处理输出的数据
+///
+/// @param buf 待输出的数据
+/// @param termios 终端的属性
+///
+/// @return 处理之后的数据
+pub fn n_tty_process_output(buf: &[u8], termios: &Termios) -> Vec<u8> {
+    let oflag = termios.oflag();
+    if !oflag.contains(OutputMode::OPOST) {
+        return buf.to_vec();
+    }
+    let mut result = Vec::with_capacity(buf.len());
+    for &c in buf {
+        match c {
+            b'\n' if oflag.contains(OutputMode::ONLCR) => result.extend_from_slice(b"\r\n"),
+            b'\r' if oflag.contains(OutputMode::OCRNL) => result.push(b'\n'),
+            _ => result.push(c),
+        }
+    }
+    return result;
+}
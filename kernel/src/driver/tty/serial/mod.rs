@@ -1,12 +1,16 @@
 use core::{fmt::Debug, sync::atomic::AtomicU32};
 
-use alloc::sync::Arc;
+use alloc::{format, sync::Arc};
 
-use crate::{driver::base::device::DeviceNumber, mm::VirtAddr, syscall::SystemError};
+use crate::{driver::base::device::DeviceNumber, kwarn, mm::VirtAddr, syscall::SystemError};
 
 use self::serial8250::serial8250_manager;
 
-use super::tty_driver::TtyDriver;
+use super::{
+    termios::{ControlMode, Termios},
+    tty_device::{tty_register_device, TtyDevice},
+    tty_driver::{TtyDriver, TtyDriverOperations},
+};
 
 pub mod serial8250;
 
@@ -22,7 +26,7 @@ pub trait UartDriver: Debug + Send + Sync + TtyDriver {
 /// 串口端口应当实现的trait
 ///
 /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/serial_core.h#428
-pub trait UartPort {
+pub trait UartPort: Debug + Send + Sync {
     fn iobase(&self) -> Option<usize> {
         None
     }
@@ -37,6 +41,13 @@ pub trait UartPort {
     fn startup(&self) -> Result<(), SystemError>;
     fn shutdown(&self);
     fn handle_irq(&self) -> Result<(), SystemError>;
+    /// 发送数据，直到所有数据都被写入发送FIFO才返回
+    fn send_bytes(&self, s: &[u8]);
+    /// 按照终端属性设置波特率、字符长度、停止位和奇偶校验
+    fn set_termios(&self, termios: &Termios) -> Result<(), SystemError>;
+    /// 端口连接的tty设备，接收到的数据会被送到这个设备
+    fn tty(&self) -> Option<Arc<TtyDevice>>;
+    fn set_tty(&self, tty: Option<&Arc<TtyDevice>>);
 }
 
 int_like!(BaudRate, AtomicBaudRate, u32, AtomicU32);
@@ -52,6 +63,43 @@ pub(super) fn uart_manager() -> &'static UartManager {
 pub(super) struct UartManager;
 
 impl UartManager {
+    /// 串口tty默认的控制模式：115200波特、8位数据、无校验、1位停止位
+    const DEFAULT_CFLAG: ControlMode = ControlMode::from_bits_truncate(
+        ControlMode::B115200.bits()
+            | ControlMode::CS8.bits()
+            | ControlMode::CREAD.bits()
+            | ControlMode::HUPCL.bits()
+            | ControlMode::CLOCAL.bits(),
+    );
+
+    /// 为串口端口创建tty设备ttyS{line}并启动端口
+    ///
+    /// ## 参数
+    ///
+    /// - `port`：串口端口
+    /// - `line`：端口的编号
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/serial/serial_core.c#uart_add_one_port
+    pub fn add_one_port(
+        &self,
+        port: &'static dyn UartPort,
+        line: usize,
+    ) -> Result<Arc<TtyDevice>, SystemError> {
+        let tty = TtyDevice::new_with_ops(&format!("ttyS{}", line), Arc::new(UartTtyOps { port }));
+        tty_register_device(tty.clone())?;
+        port.set_tty(Some(&tty));
+
+        let mut termios = tty.termios();
+        termios.c_cflag = Self::DEFAULT_CFLAG.bits();
+        tty.set_termios(termios, false);
+
+        port.startup().map_err(|e| {
+            port.set_tty(None);
+            e
+        })?;
+        return Ok(tty);
+    }
+
     /// todo: 把uart设备注册到tty层
     ///
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/serial/serial_core.c?fi=uart_register_driver#2720
@@ -60,6 +108,26 @@ impl UartManager {
     }
 }
 
+/// 串口tty对硬件的操作
+#[derive(Debug)]
+struct UartTtyOps {
+    port: &'static dyn UartPort,
+}
+
+impl TtyDriverOperations for UartTtyOps {
+    fn write(&self, _tty: &TtyDevice, buf: &[u8]) -> Result<usize, SystemError> {
+        self.port.send_bytes(buf);
+        return Ok(buf.len());
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/serial/serial_core.c#uart_set_termios
+    fn set_termios(&self, tty: &TtyDevice, _old: &Termios) {
+        if let Err(e) = self.port.set_termios(&tty.termios()) {
+            kwarn!("{}: failed to apply termios: {:?}", tty.name(), e);
+        }
+    }
+}
+
 pub fn serial_early_init() -> Result<(), SystemError> {
     serial8250_manager().early_init()?;
     return Ok(());
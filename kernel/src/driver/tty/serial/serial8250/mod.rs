@@ -21,6 +21,7 @@ use crate::{
             platform::{
                 platform_device::{platform_device_manager, PlatformDevice},
                 platform_driver::{platform_driver_manager, PlatformDriver},
+                resource::Resource,
             },
         },
         tty::{
@@ -33,7 +34,9 @@ use crate::{
     syscall::SystemError,
 };
 
-use self::serial8250_pio::{send_to_serial8250_pio_com1, serial8250_pio_port_early_init};
+use self::serial8250_pio::{
+    send_to_serial8250_pio_com1, serial8250_pio_port_early_init, SERIAL8250_PIO_RESOURCES,
+};
 
 use super::{uart_manager, UartDriver, UartPort};
 
@@ -65,6 +68,8 @@ pub(super) struct Serial8250Manager;
 impl Serial8250Manager {
     /// 初始化串口设备（在内存管理初始化之前）
     pub fn early_init(&self) -> Result<(), SystemError> {
+        // 只有x86_64上存在端口I/O的串口
+        #[cfg(target_arch = "x86_64")]
         serial8250_pio_port_early_init()?;
         return Ok(());
     }
@@ -119,16 +124,22 @@ impl Serial8250Manager {
         self.bind_pio_ports(uart_driver, devs);
     }
 
-    /// 把uart端口与uart driver绑定
+    /// 把uart端口与uart driver绑定，并为端口创建tty设备
+    ///
+    /// ## 参数
+    ///
+    /// - `port`：端口
+    /// - `line`：端口的编号，对应的tty设备为ttyS{line}
     ///
     /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/serial/serial_core.c?fi=uart_add_one_port#3048
     pub(self) fn uart_add_one_port(
         &self,
         _uart_driver: &Arc<Serial8250ISADriver>,
-        _port: &dyn UartPort,
+        port: &'static dyn UartPort,
+        line: usize,
     ) -> Result<(), SystemError> {
+        uart_manager().add_one_port(port, line)?;
         return Ok(());
-        // todo!("Serial8250Manager::uart_add_one_port")
     }
 }
 
@@ -193,6 +204,10 @@ impl PlatformDevice for Serial8250ISADevices {
         return self.name;
     }
 
+    fn resources(&self) -> &[Resource] {
+        &SERIAL8250_PIO_RESOURCES
+    }
+
     fn is_initialized(&self) -> bool {
         return self.inner.read().device_state == DeviceState::Initialized;
     }
@@ -440,16 +455,18 @@ impl PlatformDriver for Serial8250ISADriver {
         todo!()
     }
 
+    /// 串口同时也是控制台，关机的过程中还要输出信息，因此不关闭串口
     fn shutdown(&self, _device: &Arc<dyn PlatformDevice>) -> Result<(), SystemError> {
-        todo!()
+        return Ok(());
     }
 
+    /// 串口是控制台，保持它的工作状态，以便输出睡眠过程中的信息
     fn suspend(&self, _device: &Arc<dyn PlatformDevice>) -> Result<(), SystemError> {
-        todo!()
+        return Ok(());
     }
 
     fn resume(&self, _device: &Arc<dyn PlatformDevice>) -> Result<(), SystemError> {
-        todo!()
+        return Ok(());
     }
 }
 
@@ -539,5 +556,10 @@ impl KObject for Serial8250ISADriver {
 
 /// 临时函数，用于向默认的串口发送数据
 pub fn send_to_default_serial8250_port(s: &[u8]) {
+    #[cfg(target_arch = "x86_64")]
     send_to_serial8250_pio_com1(s);
+
+    // RISC-V上通过SBI固件输出
+    #[cfg(target_arch = "riscv64")]
+    crate::arch::sbi::console_putstr(s);
 }
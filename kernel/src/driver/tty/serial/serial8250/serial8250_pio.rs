@@ -5,11 +5,24 @@ use core::{
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use alloc::sync::{Arc, Weak};
+use alloc::{
+    format,
+    sync::{Arc, Weak},
+};
 
 use crate::{
-    arch::{io::PortIOArch, CurrentPortIOArch},
-    driver::tty::serial::{AtomicBaudRate, BaudRate, DivisorFraction, UartPort},
+    arch::{interrupt::TrapFrame, io::PortIOArch, CurrentPortIOArch},
+    driver::{
+        base::platform::resource::Resource,
+        tty::{
+            serial::{AtomicBaudRate, BaudRate, DivisorFraction, UartPort},
+            termios::{ControlMode, Termios},
+            tty_device::TtyDevice,
+        },
+    },
+    exception::irqdesc::{irq_manager, IrqHandleFlags, IrqHandler, IrqNumber, IrqReturn},
+    include::bindings::bindings::{ioapic_install_isa_irq, ioapic_uninstall_isa_irq},
+    kwarn,
     libs::rwlock::RwLock,
     syscall::SystemError,
 };
@@ -28,12 +41,71 @@ impl Serial8250Manager {
         for i in 0..8 {
             if let Some(port) = unsafe { PIO_PORTS[i].as_ref() } {
                 port.set_device(Some(devs));
-                self.uart_add_one_port(uart_driver, port).ok();
+                if let Err(e) = self.uart_add_one_port(uart_driver, port, i) {
+                    kwarn!("Failed to add serial port {:?}: {:?}", port.iobase, e);
+                }
             }
         }
     }
 }
 
+/// 根据端口的基地址找到端口
+fn pio_port(iobase: Serial8250PortBase) -> Option<&'static Serial8250PIOPort> {
+    unsafe { PIO_PORTS.iter() }
+        .flatten()
+        .find(|port| port.iobase as u16 == iobase as u16)
+}
+
+// 8250的寄存器相对于基地址的偏移量
+// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/serial_reg.h
+const UART_RX: u32 = 0;
+const UART_IER: u32 = 1;
+const UART_IIR: u32 = 2;
+const UART_FCR: u32 = 2;
+const UART_LCR: u32 = 3;
+const UART_MCR: u32 = 4;
+const UART_LSR: u32 = 5;
+const UART_MSR: u32 = 6;
+
+/// IER: 接收到数据时产生中断
+const UART_IER_RDI: u32 = 0x01;
+/// IIR: 没有待处理的中断
+const UART_IIR_NO_INT: u32 = 0x01;
+/// FCR: 启用FIFO并清空，接收FIFO中有14个字节时产生中断
+const UART_FCR_ENABLE_CLEAR: u32 = 0xC7;
+/// LCR: 2个停止位、启用奇偶校验、偶校验
+const UART_LCR_STOP: u32 = 0x04;
+const UART_LCR_PARITY: u32 = 0x08;
+const UART_LCR_EPAR: u32 = 0x10;
+/// MCR: DTR、RTS以及OUT2。PC上只有OUT2置位时，串口的中断才会被送到中断控制器
+const UART_MCR_DTR: u32 = 0x01;
+const UART_MCR_RTS: u32 = 0x02;
+const UART_MCR_OUT2: u32 = 0x08;
+/// LSR: 接收缓冲区中有数据
+const UART_LSR_DR: u32 = 0x01;
+/// LSR: 发送保持寄存器为空
+const UART_LSR_THRE: u32 = 0x20;
+
+/// 串口的中断处理函数。共享同一个中断的端口各自注册一个处理函数
+#[derive(Debug)]
+struct Serial8250PIOIrqHandler {
+    iobase: Serial8250PortBase,
+}
+
+impl IrqHandler for Serial8250PIOIrqHandler {
+    fn handle(&self, _irq: IrqNumber, _trap_frame: &mut TrapFrame) -> IrqReturn {
+        let port = match pio_port(self.iobase) {
+            Some(port) => port,
+            None => return IrqReturn::NotHandled,
+        };
+        if port.serial_in(UART_IIR) & UART_IIR_NO_INT != 0 {
+            return IrqReturn::NotHandled;
+        }
+        port.handle_irq().ok();
+        return IrqReturn::Handled;
+    }
+}
+
 macro_rules! init_port {
     ($port_num:expr, $baudrate:expr) => {
         unsafe {
@@ -73,16 +145,21 @@ pub struct Serial8250PIOPort {
     iobase: Serial8250PortBase,
     baudrate: AtomicBaudRate,
     initialized: AtomicBool,
+    /// 是否已经注册了中断处理函数
+    irq_requested: AtomicBool,
     inner: RwLock<Serial8250PIOPortInner>,
 }
 
 impl Serial8250PIOPort {
     const SERIAL8250PIO_MAX_BAUD_RATE: BaudRate = BaudRate::new(115200);
+    /// 在一次中断中最多读取的字节数
+    const RX_BATCH_SIZE: usize = 64;
     pub fn new(iobase: Serial8250PortBase, baudrate: BaudRate) -> Result<Self, SystemError> {
         let r = Self {
             iobase,
             baudrate: AtomicBaudRate::new(baudrate),
             initialized: AtomicBool::new(false),
+            irq_requested: AtomicBool::new(false),
             inner: RwLock::new(Serial8250PIOPortInner::new()),
         };
 
@@ -147,9 +224,8 @@ impl Serial8250PIOPort {
         return Ok(());
     }
 
-    #[allow(dead_code)]
     fn serial_received(&self) -> bool {
-        if self.serial_in(5) & 1 != 0 {
+        if self.serial_in(UART_LSR) & UART_LSR_DR != 0 {
             true
         } else {
             false
@@ -157,25 +233,40 @@ impl Serial8250PIOPort {
     }
 
     fn is_transmit_empty(&self) -> bool {
-        if self.serial_in(5) & 0x20 != 0 {
+        if self.serial_in(UART_LSR) & UART_LSR_THRE != 0 {
             true
         } else {
             false
         }
     }
 
-    /// 发送字节
-    ///
-    /// ## 参数
-    ///
-    /// - `s`：待发送的字节
-    fn send_bytes(&self, s: &[u8]) {
-        while self.is_transmit_empty() == false {
-            spin_loop();
+    /// 在中断向量上注册中断处理函数，并在I/O APIC中打开对应的中断
+    fn request_irq(&self, irq: IrqNumber) -> Result<(), SystemError> {
+        if self.irq_requested.load(Ordering::SeqCst) {
+            return Ok(());
         }
+        irq_manager().request_irq(
+            irq,
+            format!("serial8250 {:#x}", self.iobase as u16),
+            Arc::new(Serial8250PIOIrqHandler {
+                iobase: self.iobase,
+            }),
+            IrqHandleFlags::IRQF_SHARED,
+            self.iobase as usize,
+        )?;
+        unsafe { ioapic_install_isa_irq(irq as u64) };
+        self.irq_requested.store(true, Ordering::SeqCst);
+        return Ok(());
+    }
 
-        for c in s {
-            self.serial_out(0, (*c).into());
+    fn free_irq(&self, irq: IrqNumber) {
+        if !self.irq_requested.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        irq_manager().free_irq(irq, self.iobase as usize).ok();
+        // 共享同一个中断的其他端口都已经注销时，才屏蔽中断
+        if !irq_manager().active_irqs().contains(&irq) {
+            unsafe { ioapic_uninstall_isa_irq(irq as u64) };
         }
     }
 
@@ -233,20 +324,121 @@ impl UartPort for Serial8250PIOPort {
         return Ok(());
     }
 
+    /// 启动端口：清空FIFO，注册中断并打开接收中断
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/serial/8250/8250_port.c#serial8250_do_startup
     fn startup(&self) -> Result<(), SystemError> {
-        todo!("serial8250_pio::startup")
+        self.serial_out(UART_IER, 0);
+        self.serial_out(UART_FCR, UART_FCR_ENABLE_CLEAR);
+        // 清除所有待处理的中断
+        self.serial_in(UART_LSR);
+        self.serial_in(UART_RX);
+        self.serial_in(UART_IIR);
+        self.serial_in(UART_MSR);
+
+        let irq = match self.iobase.irq() {
+            Some(irq) => irq,
+            None => {
+                // 没有固定中断号的端口只能发送数据
+                kwarn!(
+                    "serial8250: port {:#x} has no irq, receiving is disabled",
+                    self.iobase as u16
+                );
+                return Ok(());
+            }
+        };
+        self.request_irq(irq)?;
+
+        self.serial_out(UART_MCR, UART_MCR_DTR | UART_MCR_RTS | UART_MCR_OUT2);
+        self.serial_out(UART_IER, UART_IER_RDI);
+        return Ok(());
     }
 
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/serial/8250/8250_port.c#serial8250_do_shutdown
     fn shutdown(&self) {
-        todo!("serial8250_pio::shutdown")
+        self.serial_out(UART_IER, 0);
+        self.serial_out(UART_MCR, UART_MCR_OUT2);
+        if let Some(irq) = self.iobase.irq() {
+            self.free_irq(irq);
+        }
+        // 丢弃接收FIFO中剩余的数据
+        self.serial_out(UART_FCR, UART_FCR_ENABLE_CLEAR);
+        self.serial_in(UART_RX);
     }
 
     fn baud_rate(&self) -> Option<BaudRate> {
         Some(self.baudrate.load(Ordering::SeqCst))
     }
 
+    /// 读空接收FIFO，把数据送到端口连接的tty设备
     fn handle_irq(&self) -> Result<(), SystemError> {
-        todo!("serial8250_pio::handle_irq")
+        let mut buf = [0u8; Self::RX_BATCH_SIZE];
+        let mut len = 0;
+        while len < buf.len() && self.serial_received() {
+            buf[len] = self.serial_in(UART_RX) as u8;
+            len += 1;
+        }
+        if len == 0 {
+            return Ok(());
+        }
+
+        let tty = self.tty().ok_or(SystemError::ENODEV)?;
+        tty.input(&buf[..len])?;
+        return Ok(());
+    }
+
+    /// 发送字节
+    ///
+    /// ## 参数
+    ///
+    /// - `s`：待发送的字节
+    fn send_bytes(&self, s: &[u8]) {
+        for c in s {
+            while self.is_transmit_empty() == false {
+                spin_loop();
+            }
+            self.serial_out(0, (*c).into());
+        }
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/serial/8250/8250_port.c#serial8250_do_set_termios
+    fn set_termios(&self, termios: &Termios) -> Result<(), SystemError> {
+        let baud = termios.baud_rate();
+        // 波特率为0表示挂断，串口没有调制解调器控制线需要处理，保持原来的波特率
+        if baud == 0 {
+            return Ok(());
+        }
+        let baud = BaudRate::new(baud);
+        self.check_baudrate(&baud)?;
+
+        let cflag = termios.cflag();
+        let mut lcr = match cflag & ControlMode::CSIZE {
+            x if x == ControlMode::CS6 => 0x01,
+            x if x == ControlMode::CS7 => 0x02,
+            x if x == ControlMode::CS8 => 0x03,
+            _ => 0x00,
+        };
+        if cflag.contains(ControlMode::CSTOPB) {
+            lcr |= UART_LCR_STOP;
+        }
+        if cflag.contains(ControlMode::PARENB) {
+            lcr |= UART_LCR_PARITY;
+            if !cflag.contains(ControlMode::PARODD) {
+                lcr |= UART_LCR_EPAR;
+            }
+        }
+
+        self.set_divisor(baud)?;
+        self.serial_out(UART_LCR, lcr);
+        return Ok(());
+    }
+
+    fn tty(&self) -> Option<Arc<TtyDevice>> {
+        self.inner.read().tty()
+    }
+
+    fn set_tty(&self, tty: Option<&Arc<TtyDevice>>) {
+        self.inner.write().set_tty(tty);
     }
 }
 
@@ -256,11 +448,24 @@ struct Serial8250PIOPortInner {
     ///
     /// ps: 存储weak以避免循环引用
     device: Option<Weak<Serial8250ISADevices>>,
+    /// 端口连接的tty设备
+    tty: Option<Weak<TtyDevice>>,
 }
 
 impl Serial8250PIOPortInner {
     pub const fn new() -> Self {
-        Self { device: None }
+        Self {
+            device: None,
+            tty: None,
+        }
+    }
+
+    pub fn tty(&self) -> Option<Arc<TtyDevice>> {
+        self.tty.as_ref()?.upgrade()
+    }
+
+    fn set_tty(&mut self, tty: Option<&Arc<TtyDevice>>) {
+        self.tty = tty.map(Arc::downgrade);
     }
 
     pub fn device(&self) -> Option<Arc<Serial8250ISADevices>> {
@@ -289,6 +494,29 @@ pub enum Serial8250PortBase {
     COM8 = 0x4e8,
 }
 
+impl Serial8250PortBase {
+    /// 端口的中断向量号。ISA中断号n经过I/O APIC被映射到中断向量32+n
+    ///
+    /// COM1和COM3使用ISA中断4，COM2和COM4使用ISA中断3，其余的端口没有固定的中断号
+    pub fn irq(&self) -> Option<IrqNumber> {
+        match self {
+            Serial8250PortBase::COM1 | Serial8250PortBase::COM3 => Some(32 + 4),
+            Serial8250PortBase::COM2 | Serial8250PortBase::COM4 => Some(32 + 3),
+            _ => None,
+        }
+    }
+}
+
+/// 有固定中断号的传统串口(COM1~COM4)占用的I/O端口与ISA中断
+pub(super) const SERIAL8250_PIO_RESOURCES: [Resource; 6] = [
+    Resource::io(Serial8250PortBase::COM1 as usize, 8),
+    Resource::io(Serial8250PortBase::COM2 as usize, 8),
+    Resource::io(Serial8250PortBase::COM3 as usize, 8),
+    Resource::io(Serial8250PortBase::COM4 as usize, 8),
+    Resource::irq(4),
+    Resource::irq(3),
+];
+
 /// 临时函数，用于向COM1发送数据
 pub fn send_to_serial8250_pio_com1(s: &[u8]) {
     if let Some(port) = unsafe { PIO_PORTS[0].as_ref() } {
@@ -0,0 +1,224 @@
+//! 终端的属性(termios)
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/asm-generic/termbits.h
+
+/// c_cc数组的长度
+pub const NCCS: usize = 19;
+
+// c_cc数组中各个控制字符的下标
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSWTC: usize = 7;
+pub const VSTART: usize = 8;
+pub const VSTOP: usize = 9;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VREPRINT: usize = 12;
+pub const VDISCARD: usize = 13;
+pub const VWERASE: usize = 14;
+pub const VLNEXT: usize = 15;
+pub const VEOL2: usize = 16;
+
+/// 控制字符的值为此值时，表示禁用该控制字符
+pub const POSIX_VDISABLE: u8 = 0;
+
+// tty的ioctl命令
+// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/asm-generic/ioctls.h
+pub const TCGETS: u32 = 0x5401;
+pub const TCSETS: u32 = 0x5402;
+pub const TCSETSW: u32 = 0x5403;
+pub const TCSETSF: u32 = 0x5404;
+pub const TIOCGPGRP: u32 = 0x540F;
+pub const TIOCSPGRP: u32 = 0x5410;
+pub const FIONREAD: u32 = 0x541B;
+
+bitflags! {
+    /// 输入模式(c_iflag)
+    pub struct InputMode: u32 {
+        const IGNBRK = 0o000001;
+        const BRKINT = 0o000002;
+        const IGNPAR = 0o000004;
+        const PARMRK = 0o000010;
+        const INPCK = 0o000020;
+        /// 去掉输入字符的第8位
+        const ISTRIP = 0o000040;
+        /// 把输入的NL转换为CR
+        const INLCR = 0o000100;
+        /// 忽略输入的CR
+        const IGNCR = 0o000200;
+        /// 把输入的CR转换为NL
+        const ICRNL = 0o000400;
+        const IUCLC = 0o001000;
+        const IXON = 0o002000;
+        const IXANY = 0o004000;
+        const IXOFF = 0o010000;
+        const IMAXBEL = 0o020000;
+        const IUTF8 = 0o040000;
+    }
+
+    /// 输出模式(c_oflag)
+    pub struct OutputMode: u32 {
+        /// 对输出进行处理
+        const OPOST = 0o000001;
+        const OLCUC = 0o000002;
+        /// 把输出的NL转换为CR-NL
+        const ONLCR = 0o000004;
+        const OCRNL = 0o000010;
+        const ONOCR = 0o000020;
+        const ONLRET = 0o000040;
+    }
+
+    /// 控制模式(c_cflag)
+    pub struct ControlMode: u32 {
+        /// 波特率字段的掩码
+        const CBAUD = 0o010017;
+        const B9600 = 0o000015;
+        const B19200 = 0o000016;
+        const B38400 = 0o000017;
+        const B57600 = 0o010001;
+        const B115200 = 0o010002;
+        /// 字符长度字段的掩码，字段为0时表示5位
+        const CSIZE = 0o000060;
+        const CS6 = 0o000020;
+        const CS7 = 0o000040;
+        const CS8 = 0o000060;
+        /// 使用2个停止位
+        const CSTOPB = 0o000100;
+        const CREAD = 0o000200;
+        /// 启用奇偶校验
+        const PARENB = 0o000400;
+        /// 使用奇校验，否则为偶校验
+        const PARODD = 0o001000;
+        const HUPCL = 0o002000;
+        const CLOCAL = 0o004000;
+    }
+
+    /// 本地模式(c_lflag)
+    pub struct LocalMode: u32 {
+        /// 收到INTR、QUIT、SUSP字符时，产生对应的信号
+        const ISIG = 0o000001;
+        /// 规范模式，输入按行缓冲，并且支持行编辑
+        const ICANON = 0o000002;
+        const XCASE = 0o000004;
+        /// 回显输入的字符
+        const ECHO = 0o000010;
+        /// 规范模式下，ERASE字符擦除前一个字符
+        const ECHOE = 0o000020;
+        /// 规范模式下，KILL字符之后回显换行
+        const ECHOK = 0o000040;
+        /// 规范模式下，即使没有设置ECHO，也回显NL
+        const ECHONL = 0o000100;
+        /// 产生信号时不清空输入缓冲区
+        const NOFLSH = 0o000200;
+        const TOSTOP = 0o000400;
+        /// 把控制字符回显为^X的形式
+        const ECHOCTL = 0o001000;
+        const ECHOPRT = 0o002000;
+        /// 规范模式下，KILL字符逐个擦除整行
+        const ECHOKE = 0o004000;
+        const FLUSHO = 0o010000;
+        const PENDIN = 0o040000;
+        /// 启用WERASE、LNEXT等扩展的控制字符
+        const IEXTEN = 0o100000;
+    }
+}
+
+/// c_cflag中的扩展波特率标志，置位时波特率字段的低4位表示高于38400的波特率
+const CBAUDEX: u32 = 0o010000;
+
+/// 波特率字段的值对应的波特率
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/tty_baudrate.c#baud_table
+const BAUD_TABLE: [u32; 31] = [
+    0, 50, 75, 110, 134, 150, 200, 300, 600, 1200, 1800, 2400, 4800, 9600, 19200, 38400, 57600,
+    115200, 230400, 460800, 500000, 576000, 921600, 1000000, 1152000, 1500000, 2000000, 2500000,
+    3000000, 3500000, 4000000,
+];
+
+/// @brief 终端的属性，与Linux的struct termios(TCGETS使用的内核版本)的内存布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// 默认的控制字符: ^C ^\ DEL ^U ^D 0 1 0 ^Q ^S ^Z 0 ^R ^O ^W ^V 0
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/tty.h#INIT_C_CC
+    const INIT_C_CC: [u8; NCCS] = [
+        3, 28, 127, 21, 4, 0, 1, 0, 17, 19, 26, 0, 18, 15, 23, 22, 0, 0, 0,
+    ];
+
+    /// @brief 标准的终端属性，与Linux的tty_std_termios一致
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/tty_io.c#tty_std_termios
+    pub const fn std() -> Self {
+        Self {
+            c_iflag: InputMode::ICRNL.bits() | InputMode::IXON.bits(),
+            c_oflag: OutputMode::OPOST.bits() | OutputMode::ONLCR.bits(),
+            c_cflag: ControlMode::B38400.bits()
+                | ControlMode::CS8.bits()
+                | ControlMode::CREAD.bits()
+                | ControlMode::HUPCL.bits(),
+            c_lflag: LocalMode::ISIG.bits()
+                | LocalMode::ICANON.bits()
+                | LocalMode::ECHO.bits()
+                | LocalMode::ECHOE.bits()
+                | LocalMode::ECHOK.bits()
+                | LocalMode::ECHOCTL.bits()
+                | LocalMode::ECHOKE.bits()
+                | LocalMode::IEXTEN.bits(),
+            c_line: 0,
+            c_cc: Self::INIT_C_CC,
+        }
+    }
+
+    #[inline]
+    pub fn iflag(&self) -> InputMode {
+        InputMode::from_bits_truncate(self.c_iflag)
+    }
+
+    #[inline]
+    pub fn oflag(&self) -> OutputMode {
+        OutputMode::from_bits_truncate(self.c_oflag)
+    }
+
+    #[inline]
+    pub fn lflag(&self) -> LocalMode {
+        LocalMode::from_bits_truncate(self.c_lflag)
+    }
+
+    #[inline]
+    pub fn cflag(&self) -> ControlMode {
+        ControlMode::from_bits_truncate(self.c_cflag)
+    }
+
+    /// @brief 获取c_cflag中设置的波特率，为0时表示挂断
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/tty_baudrate.c#tty_termios_baud_rate
+    pub fn baud_rate(&self) -> u32 {
+        let mut index = self.c_cflag & ControlMode::CBAUD.bits();
+        if index & CBAUDEX != 0 {
+            index = (index & !CBAUDEX) + 15;
+        }
+        return BAUD_TABLE.get(index as usize).copied().unwrap_or(0);
+    }
+
+    /// @brief 判断字符是否为指定的控制字符。被禁用的控制字符不匹配任何字符
+    #[inline]
+    pub fn is_cc(&self, index: usize, c: u8) -> bool {
+        let cc = self.c_cc[index];
+        return cc != POSIX_VDISABLE && cc == c;
+    }
+}
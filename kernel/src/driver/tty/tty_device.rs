@@ -5,22 +5,31 @@ use alloc::{
 };
 
 use crate::{
+    driver::base::class::{class_manager, tty_class},
     filesystem::{
-        devfs::{devfs_register, DevFS, DeviceINode},
+        devfs::{DevFS, DeviceINode},
         vfs::{
-            file::FileMode, syscall::ModeType, FilePrivateData, FileType, IndexNode, Metadata,
-            ROOT_INODE,
+            file::FileMode, ioctl::IoctlArg, syscall::ModeType, FilePrivateData, FileType,
+            IndexNode, Metadata, PollStatus, PollTable, ROOT_INODE,
         },
     },
     kerror,
     libs::{
-        lib_ui::textui::{textui_putchar, FontColor},
+        lib_ui::textui::{textui_putchar, textui_putchar_to, FontColor, TextuiWindow},
         rwlock::RwLock,
+        spinlock::SpinLock,
     },
+    process::Pid,
     syscall::SystemError,
 };
 
-use super::{serial::serial_init, TtyCore, TtyError, TtyFileFlag, TtyFilePrivateData};
+use super::{
+    serial::serial_init,
+    termios::{Termios, FIONREAD, TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGPGRP, TIOCSPGRP},
+    tty_driver::TtyDriverOperations,
+    vt::vt_init,
+    TtyCore, TtyError, TtyFileFlag, TtyFilePrivateData,
+};
 
 lazy_static! {
     /// 所有TTY设备的B树。用于根据名字，找到Arc<TtyDevice>
@@ -37,6 +46,10 @@ pub struct TtyDevice {
     fs: RwLock<Weak<DevFS>>,
     /// TTY设备私有信息
     private_data: RwLock<TtyDevicePrivateData>,
+    /// 虚拟终端对应的textui窗口。为None时，输出到当前显示在屏幕上的窗口
+    window: Option<Arc<SpinLock<TextuiWindow>>>,
+    /// 对硬件的操作。不为None时，输出写入硬件而不是屏幕
+    ops: Option<Arc<dyn TtyDriverOperations>>,
 }
 
 #[derive(Debug)]
@@ -50,16 +63,45 @@ struct TtyDevicePrivateData {
 
 impl TtyDevice {
     pub fn new(name: &str) -> Arc<TtyDevice> {
+        return Self::new_vt(name, None);
+    }
+
+    /// @brief 创建一个虚拟终端
+    ///
+    /// @param name 设备名
+    /// @param window 虚拟终端独占的textui窗口，输出只会写入这个窗口
+    pub fn new_vt(name: &str, window: Option<Arc<SpinLock<TextuiWindow>>>) -> Arc<TtyDevice> {
         let result = Arc::new(TtyDevice {
             core: TtyCore::new(),
             fs: RwLock::new(Weak::default()),
             private_data: TtyDevicePrivateData::new(name),
+            window,
+            ops: None,
         });
-        // 默认开启输入回显
-        result.core.enable_echo();
+        // 默认使用标准的终端属性，开启规范模式、输入回显以及控制字符产生的信号
         return result;
     }
 
+    /// @brief 创建一个连接到硬件的TTY设备（如串口）
+    ///
+    /// @param name 设备名
+    /// @param ops 对硬件的操作，TTY的输出通过它写入硬件
+    pub fn new_with_ops(name: &str, ops: Arc<dyn TtyDriverOperations>) -> Arc<TtyDevice> {
+        return Arc::new(TtyDevice {
+            core: TtyCore::new(),
+            fs: RwLock::new(Weak::default()),
+            private_data: TtyDevicePrivateData::new(name),
+            window: None,
+            ops: Some(ops),
+        });
+    }
+
+    /// @brief 获取虚拟终端的窗口
+    #[inline]
+    pub fn window(&self) -> Option<&Arc<SpinLock<TextuiWindow>>> {
+        return self.window.as_ref();
+    }
+
     /// @brief 判断文件私有信息是否为TTY文件的私有信息
     #[inline]
     fn verify_file_private_data<'a>(
@@ -87,6 +129,23 @@ impl TtyDevice {
         return Ok(());
     }
 
+    /// @brief 获取终端属性
+    #[inline]
+    pub fn termios(&self) -> Termios {
+        return self.core.termios();
+    }
+
+    /// @brief 设置终端属性，并通知硬件按照新的属性工作
+    ///
+    /// @param flush 是否清空输入缓冲区
+    pub fn set_termios(&self, termios: Termios, flush: bool) {
+        let old = self.core.termios();
+        self.core.set_termios(termios, flush);
+        if let Some(ops) = self.ops.as_ref() {
+            ops.set_termios(self, &old);
+        }
+    }
+
     /// @brief 向TTY的输入端口导入数据
     pub fn input(&self, buf: &[u8]) -> Result<usize, SystemError> {
         let r: Result<usize, TtyError> = self.core.input(buf, false);
@@ -123,7 +182,7 @@ impl IndexNode for TtyDevice {
     /// - mode的值为O_WRONLY时，表示这个文件是stdout
     /// - mode的值为O_WRONLY | O_SYNC时，表示这个文件是stderr
     fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
-        let mut p = TtyFilePrivateData::default();
+        let mut p = TtyFilePrivateData::new(*mode);
 
         // 检查打开模式
         let accmode = mode.accmode();
@@ -151,7 +210,7 @@ impl IndexNode for TtyDevice {
         buf: &mut [u8],
         data: &mut crate::filesystem::vfs::FilePrivateData,
     ) -> Result<usize, SystemError> {
-        let _data: &mut TtyFilePrivateData = match self.verify_file_private_data(data) {
+        let data: &mut TtyFilePrivateData = match self.verify_file_private_data(data) {
             Ok(t) => t,
             Err(e) => {
                 kerror!("Try to read tty device, but file private data type mismatch!");
@@ -160,22 +219,11 @@ impl IndexNode for TtyDevice {
         };
         self.check_rw_param(len, buf)?;
 
-        // 读取stdin队列
-        let r: Result<usize, TtyError> = self.core.read_stdin(&mut buf[0..len], true);
-        if r.is_ok() {
-            return Ok(r.unwrap());
-        }
-
-        match r.unwrap_err() {
-            TtyError::EOF(n) => {
-                return Ok(n);
-            }
-
-            x => {
-                kerror!("Error occurred when reading tty, msg={x:?}");
-                return Err(SystemError::ECONNABORTED);
-            }
-        }
+        // 读取经过行规程处理的stdin数据。等待的过程中，把回显的数据输出到屏幕
+        let block = !data.mode.contains(FileMode::O_NONBLOCK);
+        return self.core.read_stdin(&mut buf[0..len], block, || {
+            self.sync().ok();
+        });
     }
 
     fn write_at(
@@ -214,8 +262,51 @@ impl IndexNode for TtyDevice {
         return Err(SystemError::EIO);
     }
 
-    fn poll(&self) -> Result<crate::filesystem::vfs::PollStatus, SystemError> {
-        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    fn poll(&self, table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(self.core.poll(table));
+    }
+
+    /// @brief tty设备的ioctl，支持获取/设置终端属性，以及获取/设置前台进程组
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/tty_io.c#tty_ioctl
+    fn ioctl(&self, cmd: u32, data: usize) -> Result<usize, SystemError> {
+        let arg = IoctlArg::new(cmd, data);
+        match cmd {
+            TCGETS => {
+                arg.copy_out(&self.core.termios())?;
+                return Ok(0);
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let mut termios = self.core.termios();
+                arg.copy_in(&mut termios)?;
+                // 输出是同步完成的，因此TCSETSW不需要等待输出缓冲区清空
+                self.set_termios(termios, cmd == TCSETSF);
+                return Ok(0);
+            }
+            TIOCGPGRP => {
+                let pgrp = self
+                    .core
+                    .foreground_pgrp()
+                    .map_or(0, |pid| pid.data() as i32);
+                arg.copy_out(&pgrp)?;
+                return Ok(0);
+            }
+            TIOCSPGRP => {
+                let mut pgrp: i32 = 0;
+                arg.copy_in(&mut pgrp)?;
+                if pgrp <= 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                self.core.set_foreground_pgrp(Pid::new(pgrp as usize))?;
+                return Ok(0);
+            }
+            FIONREAD => {
+                let n = self.core.stdin_available() as i32;
+                arg.copy_out(&n)?;
+                return Ok(0);
+            }
+            _ => return Err(SystemError::ENOTTY),
+        }
     }
 
     fn fs(&self) -> Arc<dyn crate::filesystem::vfs::FileSystem> {
@@ -239,9 +330,7 @@ impl IndexNode for TtyDevice {
     }
 
     fn sync(&self) -> Result<(), SystemError> {
-        // TODO: 引入IO重定向后，需要将输出重定向到对应的设备。
-        // 目前只是简单的输出到屏幕（为了实现的简便）
-
+        // 连接到硬件的TTY设备输出到硬件，其余的输出到屏幕
         loop {
             let mut buf = [0u8; 512];
             let r: Result<usize, TtyError> = self.core.output(&mut buf[0..511], false);
@@ -259,10 +348,25 @@ impl IndexNode for TtyDevice {
             if len == 0 {
                 break;
             }
-            // 输出到屏幕
-
+            if let Some(ops) = self.ops.as_ref() {
+                let mut written = 0;
+                while written < len {
+                    written += ops.write(self, &buf[written..len])?;
+                }
+                continue;
+            }
+            // 输出到屏幕。虚拟终端输出到自己的窗口，窗口不在前台时只更新窗口的内容
             for x in 0..len {
-                textui_putchar(buf[x] as char, FontColor::WHITE, FontColor::BLACK).ok();
+                match &self.window {
+                    Some(window) => textui_putchar_to(
+                        window,
+                        buf[x] as char,
+                        FontColor::WHITE,
+                        FontColor::BLACK,
+                    ),
+                    None => textui_putchar(buf[x] as char, FontColor::WHITE, FontColor::BLACK),
+                }
+                .ok();
             }
         }
         return Ok(());
@@ -284,33 +388,58 @@ impl TtyDevicePrivateData {
 }
 
 /// @brief 初始化TTY设备
+///
+/// 创建虚拟终端tty1..ttyN。tty0与tty1是同一个设备，init进程的stdio连接在tty0上
 pub fn tty_init() -> Result<(), SystemError> {
-    let tty: Arc<TtyDevice> = TtyDevice::new("tty0");
     let devfs_root_inode = ROOT_INODE().lookup("/dev");
     if devfs_root_inode.is_err() {
         return Err(devfs_root_inode.unwrap_err());
     }
-    // 当前关闭键盘输入回显
-    // TODO: 完善Termios之后, 改为默认开启键盘输入回显.
-    tty.core.disable_echo();
-    let guard = TTY_DEVICES.upgradeable_read();
 
-    // 如果已经存在了这个设备
-    if guard.contains_key("tty0") {
+    let consoles = vt_init()?;
+    for tty in consoles.iter() {
+        // 用户态的shell自行回显键盘输入，因此默认关闭输入回显，需要回显的程序可以通过tcsetattr开启
+        tty.core.disable_echo();
+    }
+
+    let mut guard = TTY_DEVICES.write();
+    // 如果已经存在了这些设备
+    if consoles.iter().any(|tty| guard.contains_key(&tty.name())) || guard.contains_key("tty0") {
         return Err(SystemError::EEXIST);
     }
+    guard.insert("tty0".to_string(), consoles[0].clone());
+    for tty in consoles.iter() {
+        guard.insert(tty.name(), tty.clone());
+    }
+    drop(guard);
 
-    let mut guard = guard.upgrade();
+    class_manager().device_create_with_inode(
+        tty_class(),
+        None,
+        "tty0".to_string(),
+        consoles[0].clone(),
+    )?;
+    for tty in consoles.iter() {
+        class_manager().device_create_with_inode(tty_class(), None, tty.name(), tty.clone())?;
+    }
 
-    guard.insert("tty0".to_string(), tty.clone());
+    serial_init()?;
+    return Ok(());
+}
 
-    drop(guard);
+/// @brief 注册一个不属于虚拟终端的TTY设备（如串口），设备文件为/dev/<name>，sysfs中位于/sys/class/tty/<name>
+pub fn tty_register_device(tty: Arc<TtyDevice>) -> Result<(), SystemError> {
+    // 与虚拟终端相同，默认关闭输入回显
+    tty.core.disable_echo();
 
-    let r = devfs_register(&tty.name(), tty);
-    if r.is_err() {
-        return Err(devfs_root_inode.unwrap_err());
+    let name = tty.name();
+    let mut guard = TTY_DEVICES.write();
+    if guard.contains_key(&name) {
+        return Err(SystemError::EEXIST);
     }
+    guard.insert(name.clone(), tty.clone());
+    drop(guard);
 
-    serial_init()?;
+    class_manager().device_create_with_inode(tty_class(), None, name, tty)?;
     return Ok(());
 }
@@ -2,9 +2,9 @@ use core::fmt::Debug;
 
 use alloc::sync::Arc;
 
-use crate::driver::base::device::driver::Driver;
+use crate::{driver::base::device::driver::Driver, syscall::SystemError};
 
-use super::tty_device::TtyDevice;
+use super::{termios::Termios, tty_device::TtyDevice};
 
 /// TTY 驱动
 ///
@@ -57,5 +57,21 @@ bitflags! {
     }
 }
 
+/// TTY设备对硬件的操作。没有这些操作的TTY设备（如虚拟终端）输出到屏幕
+///
 /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/tty_driver.h#350
-pub trait TtyDriverOperations {}
+pub trait TtyDriverOperations: Debug + Send + Sync {
+    /// 把TTY输出的数据写入硬件
+    ///
+    /// ## 返回值
+    ///
+    /// 成功写入的字节数
+    fn write(&self, tty: &TtyDevice, buf: &[u8]) -> Result<usize, SystemError>;
+
+    /// 终端属性被修改后，按照新的属性设置硬件
+    ///
+    /// ## 参数
+    ///
+    /// - `old`：修改之前的终端属性
+    fn set_termios(&self, _tty: &TtyDevice, _old: &Termios) {}
+}
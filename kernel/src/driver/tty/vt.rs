@@ -0,0 +1,209 @@
+//! 虚拟终端(VT)
+//!
+//! 每个虚拟终端是一个独立的tty设备(/dev/tty1../dev/ttyN)，拥有自己的textui窗口作为屏幕缓冲区。
+//! 同一时刻只有一个虚拟终端在前台：它的窗口显示在屏幕上，并且接收键盘输入。按下Alt+Fn切换到第n个虚拟终端。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/vt/vt.c
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{format, sync::Arc, vec::Vec};
+
+use crate::{
+    driver::input::{
+        input_register_handler, InputDevice, InputHandle, InputHandler, BUS_I8042, EV_KEY, EV_MSC,
+        KEY_A, MSC_RAW,
+    },
+    kinfo,
+    libs::{
+        keyboard_parser::TypeOneFSM,
+        lib_ui::textui::{textui_framework, TEXTUI_IS_INIT},
+        rwlock::RwLock,
+        spinlock::SpinLock,
+    },
+    syscall::SystemError,
+    time::hrtimer::Ktime,
+};
+
+use super::tty_device::TtyDevice;
+
+/// 虚拟终端的数量
+pub const MAX_NR_CONSOLES: usize = 6;
+
+lazy_static! {
+    /// 所有的虚拟终端，下标为虚拟终端的编号减1
+    static ref VIRTUAL_CONSOLES: RwLock<Vec<Arc<TtyDevice>>> = RwLock::new(Vec::new());
+}
+
+/// 前台虚拟终端的下标
+static FG_CONSOLE: AtomicUsize = AtomicUsize::new(0);
+
+/// @brief 创建所有的虚拟终端
+///
+/// 第一个虚拟终端使用textui的初始窗口，其余的虚拟终端各自创建一个不可见的窗口
+///
+/// @return 创建的虚拟终端，由调用者注册到设备文件系统中
+pub fn vt_init() -> Result<Vec<Arc<TtyDevice>>, SystemError> {
+    let mut consoles = VIRTUAL_CONSOLES.write();
+    if !consoles.is_empty() {
+        return Err(SystemError::EEXIST);
+    }
+    for i in 0..MAX_NR_CONSOLES {
+        // textui初始化失败时，所有虚拟终端都输出到同一个屏幕上
+        let window = if unsafe { TEXTUI_IS_INIT } {
+            let framework = textui_framework();
+            if i == 0 {
+                Some(framework.current_window())
+            } else {
+                Some(framework.new_window())
+            }
+        } else {
+            None
+        };
+        consoles.push(TtyDevice::new_vt(&format!("tty{}", i + 1), window));
+    }
+    input_register_handler(Arc::new(VtKeyboardHandler));
+    kinfo!("{} virtual consoles initialized", MAX_NR_CONSOLES);
+    return Ok(consoles.clone());
+}
+
+/// @brief 获取前台的虚拟终端，键盘输入被送到这个终端
+pub fn vt_foreground() -> Option<Arc<TtyDevice>> {
+    return VIRTUAL_CONSOLES
+        .read()
+        .get(FG_CONSOLE.load(Ordering::SeqCst))
+        .cloned();
+}
+
+/// @brief 切换前台的虚拟终端
+///
+/// @param index 虚拟终端的下标(从0开始)
+///
+/// @return 下标超出范围时返回ENXIO
+pub fn vt_switch(index: usize) -> Result<(), SystemError> {
+    let consoles = VIRTUAL_CONSOLES.read();
+    let tty = consoles.get(index).ok_or(SystemError::ENXIO)?;
+    if FG_CONSOLE.swap(index, Ordering::SeqCst) == index {
+        return Ok(());
+    }
+    if let Some(window) = tty.window() {
+        textui_framework().switch_window(window)?;
+    }
+    return Ok(());
+}
+
+/// @brief 在前台虚拟终端的回滚缓冲区中翻看之前的输出，每次翻看半个屏幕(Shift+PageUp/PageDown)
+///
+/// @param up 为true时向上翻看更早的输出，为false时向下翻看
+pub fn vt_scrollback(up: bool) {
+    let tty = match vt_foreground() {
+        Some(tty) => tty,
+        None => return,
+    };
+    if let Some(window) = tty.window() {
+        let mut window = window.lock_irqsave();
+        let lines = (window.rows() / 2).max(1);
+        window.scroll_view(if up { lines } else { -lines }).ok();
+    }
+}
+
+/// @brief 虚拟终端的键盘处理者，把键盘的输入解析为字符送到前台的虚拟终端
+///
+/// PS/2键盘上报的第一套扫描码直接交给状态机解析；其他键盘(如USB键盘)只上报键码，
+/// 先转换为第一套扫描码再解析
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/vt/keyboard.c
+#[derive(Debug)]
+struct VtKeyboardHandler;
+
+impl InputHandler for VtKeyboardHandler {
+    fn connect(&self, dev: &Arc<InputDevice>) -> Option<Arc<dyn InputHandle>> {
+        let raw = dev.id().bustype == BUS_I8042
+            && dev
+                .capability_bits(EV_MSC)
+                .map_or(false, |bits| bits.test(MSC_RAW));
+        // 能输入字母的设备才是键盘，排除鼠标等只有按键的设备
+        let keyboard = dev
+            .capability_bits(EV_KEY)
+            .map_or(false, |bits| bits.test(KEY_A));
+        if !raw && !keyboard {
+            return None;
+        }
+        return Some(Arc::new(VtKeyboardHandle {
+            fsm: SpinLock::new(TypeOneFSM::new()),
+            raw,
+        }));
+    }
+}
+
+#[derive(Debug)]
+struct VtKeyboardHandle {
+    fsm: SpinLock<TypeOneFSM>,
+    /// 设备是否上报第一套扫描码
+    raw: bool,
+}
+
+impl InputHandle for VtKeyboardHandle {
+    fn event(&self, ev_type: u16, code: u16, value: i32, _time: Ktime) {
+        if self.raw {
+            if ev_type == EV_MSC && code == MSC_RAW {
+                self.fsm.lock_irqsave().parse(value as u8);
+            }
+            return;
+        }
+
+        if ev_type != EV_KEY {
+            return;
+        }
+        if let Some((extended, scancode)) = keycode_to_scancode(code) {
+            let mut fsm = self.fsm.lock_irqsave();
+            if extended {
+                fsm.parse(0xe0);
+            }
+            // 值为0表示松开，1和2表示按下和自动重复
+            fsm.parse(if value == 0 {
+                scancode | 0x80
+            } else {
+                scancode
+            });
+        }
+    }
+}
+
+/// @brief 把键码转换为第一套扫描码
+///
+/// 键码1~88与第一套扫描码相同，其余的常用按键的扫描码带有0xe0前缀
+///
+/// @return (是否带有0xe0前缀, 扫描码)，没有对应的扫描码时返回None
+fn keycode_to_scancode(code: u16) -> Option<(bool, u8)> {
+    let scancode = match code {
+        1..=88 => return Some((false, code as u8)),
+        // KEY_KPENTER
+        96 => 0x1c,
+        // KEY_RIGHTCTRL
+        97 => 0x1d,
+        // KEY_KPSLASH
+        98 => 0x35,
+        // KEY_RIGHTALT
+        100 => 0x38,
+        // KEY_HOME, KEY_UP, KEY_PAGEUP
+        102 => 0x47,
+        103 => 0x48,
+        104 => 0x49,
+        // KEY_LEFT, KEY_RIGHT
+        105 => 0x4b,
+        106 => 0x4d,
+        // KEY_END, KEY_DOWN, KEY_PAGEDOWN, KEY_INSERT, KEY_DELETE
+        107 => 0x4f,
+        108 => 0x50,
+        109 => 0x51,
+        110 => 0x52,
+        111 => 0x53,
+        // KEY_LEFTMETA, KEY_RIGHTMETA, KEY_COMPOSE
+        125 => 0x5b,
+        126 => 0x5c,
+        127 => 0x5d,
+        _ => return None,
+    };
+    return Some((true, scancode));
+}
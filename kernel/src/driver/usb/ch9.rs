@@ -0,0 +1,311 @@
+//! USB 2.0/3.x规范第9章定义的标准请求和描述符
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/usb/ch9.h
+
+use alloc::vec::Vec;
+
+use crate::syscall::SystemError;
+
+/// 请求类型(bmRequestType)的第7位：数据阶段的方向
+pub const USB_DIR_OUT: u8 = 0;
+pub const USB_DIR_IN: u8 = 0x80;
+
+/// 请求类型(bmRequestType)的第5~6位：请求的类别
+pub const USB_TYPE_STANDARD: u8 = 0x00;
+pub const USB_TYPE_CLASS: u8 = 0x20;
+
+/// 请求类型(bmRequestType)的第0~4位：请求的接收者
+pub const USB_RECIP_DEVICE: u8 = 0x00;
+pub const USB_RECIP_INTERFACE: u8 = 0x01;
+pub const USB_RECIP_ENDPOINT: u8 = 0x02;
+
+/// 标准请求
+pub const USB_REQ_CLEAR_FEATURE: u8 = 0x01;
+pub const USB_REQ_GET_DESCRIPTOR: u8 = 0x06;
+pub const USB_REQ_SET_CONFIGURATION: u8 = 0x09;
+
+/// CLEAR_FEATURE请求使用的特性：端点的STALL状态
+pub const USB_ENDPOINT_HALT: u16 = 0;
+
+/// 描述符类型
+pub const USB_DT_DEVICE: u8 = 0x01;
+pub const USB_DT_CONFIG: u8 = 0x02;
+pub const USB_DT_INTERFACE: u8 = 0x04;
+pub const USB_DT_ENDPOINT: u8 = 0x05;
+
+pub const USB_DT_DEVICE_SIZE: usize = 18;
+pub const USB_DT_CONFIG_SIZE: usize = 9;
+pub const USB_DT_INTERFACE_SIZE: usize = 9;
+pub const USB_DT_ENDPOINT_SIZE: usize = 7;
+
+/// 设备和接口的类别代码
+pub const USB_CLASS_HID: u8 = 0x03;
+pub const USB_CLASS_MASS_STORAGE: u8 = 0x08;
+pub const USB_CLASS_HUB: u8 = 0x09;
+
+/// 端点地址的第0~3位是端点号，第7位是方向
+pub const USB_ENDPOINT_NUMBER_MASK: u8 = 0x0f;
+pub const USB_ENDPOINT_DIR_MASK: u8 = 0x80;
+
+/// 端点的传输类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsbTransferType {
+    Control,
+    Isochronous,
+    Bulk,
+    Interrupt,
+}
+
+/// 控制传输的SETUP数据包
+#[derive(Debug, Clone, Copy)]
+pub struct UsbCtrlRequest {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl UsbCtrlRequest {
+    pub const fn new(request_type: u8, request: u8, value: u16, index: u16, length: u16) -> Self {
+        Self {
+            request_type,
+            request,
+            value,
+            index,
+            length,
+        }
+    }
+
+    /// GET_DESCRIPTOR请求
+    pub const fn get_descriptor(desc_type: u8, desc_index: u8, length: u16) -> Self {
+        Self::new(
+            USB_DIR_IN | USB_TYPE_STANDARD | USB_RECIP_DEVICE,
+            USB_REQ_GET_DESCRIPTOR,
+            (desc_type as u16) << 8 | desc_index as u16,
+            0,
+            length,
+        )
+    }
+
+    /// 数据阶段的方向是否为设备到主机
+    #[inline]
+    pub fn is_in(&self) -> bool {
+        self.request_type & USB_DIR_IN != 0
+    }
+
+    /// 按照总线上的字节序(小端)，把SETUP数据包转换为8字节的整数
+    pub fn to_u64(&self) -> u64 {
+        (self.request_type as u64)
+            | (self.request as u64) << 8
+            | (self.value as u64) << 16
+            | (self.index as u64) << 32
+            | (self.length as u64) << 48
+    }
+}
+
+#[inline]
+fn le16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// 设备描述符
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsbDeviceDescriptor {
+    /// USB规范的版本，BCD编码
+    pub usb_version: u16,
+    pub device_class: u8,
+    pub device_subclass: u8,
+    pub device_protocol: u8,
+    /// 端点0的最大包长。对于SuperSpeed设备，这个值是以2为底的指数
+    pub max_packet_size0: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// 设备的版本，BCD编码
+    pub device_version: u16,
+    pub num_configurations: u8,
+}
+
+impl UsbDeviceDescriptor {
+    /// 解析设备描述符，`buf`中至少需要有前8个字节
+    ///
+    /// 只读取了前8个字节时，除了`max_packet_size0`之外的字段可能为0
+    pub fn parse(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < 8 || buf[1] != USB_DT_DEVICE {
+            return Err(SystemError::EINVAL);
+        }
+        let mut desc = Self {
+            usb_version: le16(buf, 2),
+            device_class: buf[4],
+            device_subclass: buf[5],
+            device_protocol: buf[6],
+            max_packet_size0: buf[7],
+            ..Default::default()
+        };
+        if buf.len() >= USB_DT_DEVICE_SIZE {
+            desc.vendor_id = le16(buf, 8);
+            desc.product_id = le16(buf, 10);
+            desc.device_version = le16(buf, 12);
+            desc.num_configurations = buf[17];
+        }
+        return Ok(desc);
+    }
+}
+
+/// 配置描述符
+#[derive(Debug, Clone, Copy)]
+pub struct UsbConfigDescriptor {
+    /// 配置描述符及其之后的所有接口、端点描述符的总长度
+    pub total_length: u16,
+    pub num_interfaces: u8,
+    /// SET_CONFIGURATION请求使用的配置值
+    pub configuration_value: u8,
+    pub attributes: u8,
+    /// 最大功耗，单位为2mA(SuperSpeed设备为8mA)
+    pub max_power: u8,
+}
+
+impl UsbConfigDescriptor {
+    pub fn parse(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < USB_DT_CONFIG_SIZE || buf[1] != USB_DT_CONFIG {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Self {
+            total_length: le16(buf, 2),
+            num_interfaces: buf[4],
+            configuration_value: buf[5],
+            attributes: buf[7],
+            max_power: buf[8],
+        });
+    }
+}
+
+/// 接口描述符
+#[derive(Debug, Clone, Copy)]
+pub struct UsbInterfaceDescriptor {
+    pub interface_number: u8,
+    pub alternate_setting: u8,
+    pub num_endpoints: u8,
+    pub interface_class: u8,
+    pub interface_subclass: u8,
+    pub interface_protocol: u8,
+}
+
+impl UsbInterfaceDescriptor {
+    fn parse(buf: &[u8]) -> Self {
+        Self {
+            interface_number: buf[2],
+            alternate_setting: buf[3],
+            num_endpoints: buf[4],
+            interface_class: buf[5],
+            interface_subclass: buf[6],
+            interface_protocol: buf[7],
+        }
+    }
+}
+
+/// 端点描述符
+#[derive(Debug, Clone, Copy)]
+pub struct UsbEndpointDescriptor {
+    pub endpoint_address: u8,
+    pub attributes: u8,
+    pub max_packet_size: u16,
+    pub interval: u8,
+}
+
+impl UsbEndpointDescriptor {
+    fn parse(buf: &[u8]) -> Self {
+        Self {
+            endpoint_address: buf[2],
+            attributes: buf[3],
+            max_packet_size: le16(buf, 4),
+            interval: buf[6],
+        }
+    }
+
+    /// 端点号
+    #[inline]
+    pub fn number(&self) -> u8 {
+        self.endpoint_address & USB_ENDPOINT_NUMBER_MASK
+    }
+
+    /// 端点的方向是否为设备到主机
+    #[inline]
+    pub fn is_in(&self) -> bool {
+        self.endpoint_address & USB_ENDPOINT_DIR_MASK != 0
+    }
+
+    pub fn transfer_type(&self) -> UsbTransferType {
+        match self.attributes & 0x3 {
+            0 => UsbTransferType::Control,
+            1 => UsbTransferType::Isochronous,
+            2 => UsbTransferType::Bulk,
+            _ => UsbTransferType::Interrupt,
+        }
+    }
+
+    /// 最大包长。高速端点的第11~12位表示每个微帧的额外事务数，不计入包长
+    #[inline]
+    pub fn max_packet(&self) -> u16 {
+        self.max_packet_size & 0x7ff
+    }
+}
+
+/// 配置中的一个接口(只保留了默认的备用设置)，及其所有端点
+#[derive(Debug, Clone)]
+pub struct UsbInterfaceInfo {
+    pub desc: UsbInterfaceDescriptor,
+    pub endpoints: Vec<UsbEndpointDescriptor>,
+}
+
+/// 完整的配置: 配置描述符以及它之后的所有接口、端点描述符
+#[derive(Debug, Clone)]
+pub struct UsbConfiguration {
+    pub desc: UsbConfigDescriptor,
+    pub interfaces: Vec<UsbInterfaceInfo>,
+}
+
+impl UsbConfiguration {
+    /// 解析GET_DESCRIPTOR(CONFIG)请求返回的全部数据
+    ///
+    /// 类别特定的描述符(如HID描述符)被跳过
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/config.c#usb_parse_configuration
+    pub fn parse(buf: &[u8]) -> Result<Self, SystemError> {
+        let desc = UsbConfigDescriptor::parse(buf)?;
+        let total = (desc.total_length as usize).min(buf.len());
+        let mut interfaces: Vec<UsbInterfaceInfo> = Vec::new();
+        // 当前的描述符是否属于一个备用设置不为0的接口
+        let mut in_alt_setting = false;
+
+        let mut offset = buf[0] as usize;
+        while offset + 2 <= total {
+            let len = buf[offset] as usize;
+            if len < 2 || offset + len > total {
+                break;
+            }
+            let d = &buf[offset..offset + len];
+            match d[1] {
+                USB_DT_INTERFACE if len >= USB_DT_INTERFACE_SIZE => {
+                    let intf = UsbInterfaceDescriptor::parse(d);
+                    in_alt_setting = intf.alternate_setting != 0;
+                    if !in_alt_setting {
+                        interfaces.push(UsbInterfaceInfo {
+                            desc: intf,
+                            endpoints: Vec::new(),
+                        });
+                    }
+                }
+                USB_DT_ENDPOINT if len >= USB_DT_ENDPOINT_SIZE && !in_alt_setting => {
+                    if let Some(intf) = interfaces.last_mut() {
+                        intf.endpoints.push(UsbEndpointDescriptor::parse(d));
+                    }
+                }
+                _ => {}
+            }
+            offset += len;
+        }
+
+        return Ok(Self { desc, interfaces });
+    }
+}
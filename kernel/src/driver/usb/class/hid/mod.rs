@@ -0,0 +1,88 @@
+//! USB人机接口设备(HID)
+//!
+//! - [`parser`]：报告描述符解析器
+//! - [`usbkbd`]：启动协议键盘驱动
+//! - [`usbmouse`]：鼠标驱动，能解析报告描述符时使用报告协议，否则使用启动协议
+//!
+//! 参考 Device Class Definition for Human Interface Devices (HID) Version 1.11
+
+use alloc::vec::Vec;
+
+use crate::{
+    driver::usb::{
+        ch9::{USB_DIR_IN, USB_RECIP_INTERFACE, USB_REQ_GET_DESCRIPTOR, USB_TYPE_CLASS},
+        device::UsbInterface,
+        hcd::UsbData,
+    },
+    syscall::SystemError,
+};
+
+pub mod parser;
+pub mod usbkbd;
+pub mod usbmouse;
+
+/// HID接口的子类别：支持启动协议
+pub const USB_INTERFACE_SUBCLASS_BOOT: u8 = 1;
+/// HID接口的协议
+pub const USB_INTERFACE_PROTOCOL_KEYBOARD: u8 = 1;
+pub const USB_INTERFACE_PROTOCOL_MOUSE: u8 = 2;
+
+/// HID类别描述符的类型
+pub const HID_DT_REPORT: u8 = 0x22;
+
+/// HID类别请求
+pub const HID_REQ_SET_IDLE: u8 = 0x0a;
+pub const HID_REQ_SET_PROTOCOL: u8 = 0x0b;
+/// SET_PROTOCOL请求的参数
+pub const HID_BOOT_PROTOCOL: u16 = 0;
+pub const HID_REPORT_PROTOCOL: u16 = 1;
+
+/// 报告描述符的最大长度
+const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+
+/// 设置接口使用的协议
+///
+/// ## 参数
+///
+/// - `protocol`：[`HID_BOOT_PROTOCOL`]或者[`HID_REPORT_PROTOCOL`]
+pub fn hid_set_protocol(intf: &UsbInterface, protocol: u16) -> Result<(), SystemError> {
+    intf.class_msg(
+        USB_TYPE_CLASS | USB_RECIP_INTERFACE,
+        HID_REQ_SET_PROTOCOL,
+        protocol,
+        UsbData::None,
+    )?;
+    return Ok(());
+}
+
+/// 设置报告的空闲速率
+///
+/// ## 参数
+///
+/// - `duration`：以4ms为单位，为0时设备只在数据变化时发送报告
+pub fn hid_set_idle(intf: &UsbInterface, duration: u8) -> Result<(), SystemError> {
+    intf.class_msg(
+        USB_TYPE_CLASS | USB_RECIP_INTERFACE,
+        HID_REQ_SET_IDLE,
+        (duration as u16) << 8,
+        UsbData::None,
+    )?;
+    return Ok(());
+}
+
+/// 读取接口的报告描述符
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/usbhid/hid-core.c#hid_get_class_descriptor
+pub fn hid_get_report_descriptor(intf: &UsbInterface) -> Result<Vec<u8>, SystemError> {
+    let mut buf = vec![0u8; HID_MAX_DESCRIPTOR_SIZE];
+    // 报告描述符是标准请求，接收者为接口
+    let len = intf.usb_device().control_msg(
+        USB_DIR_IN | USB_RECIP_INTERFACE,
+        USB_REQ_GET_DESCRIPTOR,
+        (HID_DT_REPORT as u16) << 8,
+        intf.descriptor().interface_number as u16,
+        UsbData::In(&mut buf),
+    )?;
+    buf.truncate(len);
+    return Ok(buf);
+}
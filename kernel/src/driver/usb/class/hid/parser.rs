@@ -0,0 +1,383 @@
+//! HID报告描述符解析器
+//!
+//! 报告描述符由一系列条目(item)组成，描述了设备发送的每个报告中各个字段的位置、长度和含义。
+//! 这里只解析输入报告中的字段。输出报告和特性报告有各自的偏移量，不影响输入报告，因此直接忽略
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/hid-core.c
+
+use alloc::vec::Vec;
+
+use crate::syscall::SystemError;
+
+/// 条目的类型
+const HID_ITEM_TYPE_MAIN: u8 = 0;
+const HID_ITEM_TYPE_GLOBAL: u8 = 1;
+const HID_ITEM_TYPE_LOCAL: u8 = 2;
+/// 长条目的前缀
+const HID_ITEM_TAG_LONG: u8 = 0xfe;
+
+/// 主条目
+const HID_MAIN_ITEM_TAG_INPUT: u8 = 0x8;
+const HID_MAIN_ITEM_TAG_OUTPUT: u8 = 0x9;
+const HID_MAIN_ITEM_TAG_BEGIN_COLLECTION: u8 = 0xa;
+const HID_MAIN_ITEM_TAG_FEATURE: u8 = 0xb;
+const HID_MAIN_ITEM_TAG_END_COLLECTION: u8 = 0xc;
+
+/// 全局条目
+const HID_GLOBAL_ITEM_TAG_USAGE_PAGE: u8 = 0x0;
+const HID_GLOBAL_ITEM_TAG_LOGICAL_MINIMUM: u8 = 0x1;
+const HID_GLOBAL_ITEM_TAG_LOGICAL_MAXIMUM: u8 = 0x2;
+const HID_GLOBAL_ITEM_TAG_REPORT_SIZE: u8 = 0x7;
+const HID_GLOBAL_ITEM_TAG_REPORT_ID: u8 = 0x8;
+const HID_GLOBAL_ITEM_TAG_REPORT_COUNT: u8 = 0x9;
+const HID_GLOBAL_ITEM_TAG_PUSH: u8 = 0xa;
+const HID_GLOBAL_ITEM_TAG_POP: u8 = 0xb;
+
+/// 局部条目
+const HID_LOCAL_ITEM_TAG_USAGE: u8 = 0x0;
+const HID_LOCAL_ITEM_TAG_USAGE_MINIMUM: u8 = 0x1;
+const HID_LOCAL_ITEM_TAG_USAGE_MAXIMUM: u8 = 0x2;
+
+/// 输入、输出、特性条目的标志位
+pub const HID_MAIN_ITEM_CONSTANT: u32 = 1 << 0;
+pub const HID_MAIN_ITEM_VARIABLE: u32 = 1 << 1;
+pub const HID_MAIN_ITEM_RELATIVE: u32 = 1 << 2;
+
+/// 用法页
+pub const HID_UP_GENDESK: u32 = 0x0001_0000;
+pub const HID_UP_KEYBOARD: u32 = 0x0007_0000;
+pub const HID_UP_BUTTON: u32 = 0x0009_0000;
+
+/// 通用桌面用法页中的用法
+pub const HID_GD_MOUSE: u32 = HID_UP_GENDESK | 0x02;
+pub const HID_GD_X: u32 = HID_UP_GENDESK | 0x30;
+pub const HID_GD_Y: u32 = HID_UP_GENDESK | 0x31;
+pub const HID_GD_WHEEL: u32 = HID_UP_GENDESK | 0x38;
+
+/// 一个报告中的字段最多的用法数量，防止错误的描述符导致分配过多的内存
+const HID_MAX_USAGES: u32 = 1024;
+/// 全局状态栈的深度
+const HID_GLOBAL_STACK_SIZE: usize = 4;
+/// 字段最大的位数
+const HID_MAX_FIELD_SIZE: u32 = 32;
+
+/// 输入报告中的一个字段，由若干个大小相同的元素组成
+#[derive(Debug, Clone)]
+pub struct HidField {
+    /// 字段所在报告的id，设备不使用报告id时为0
+    pub report_id: u8,
+    /// 字段在报告中的位偏移量，不包括报告id所在的第一个字节
+    pub bit_offset: usize,
+    /// 每个元素的位数
+    pub bit_size: u8,
+    /// 元素的数量
+    pub count: u16,
+    /// 输入条目的标志位
+    pub flags: u32,
+    pub logical_minimum: i32,
+    pub logical_maximum: i32,
+    /// 元素的用法(高16位为用法页)。
+    /// 对于变量字段，第i个元素的用法为第i个用法(不足时取最后一个)；
+    /// 对于数组字段，元素的值减去逻辑最小值作为下标，在这个列表中查找用法
+    pub usages: Vec<u32>,
+}
+
+impl HidField {
+    #[inline]
+    pub fn is_variable(&self) -> bool {
+        self.flags & HID_MAIN_ITEM_VARIABLE != 0
+    }
+
+    #[inline]
+    pub fn is_relative(&self) -> bool {
+        self.flags & HID_MAIN_ITEM_RELATIVE != 0
+    }
+
+    /// 变量字段中第`index`个元素的用法
+    pub fn usage(&self, index: usize) -> Option<u32> {
+        self.usages
+            .get(index)
+            .or_else(|| self.usages.last())
+            .copied()
+    }
+
+    /// 在变量字段中查找用法，返回元素的下标
+    pub fn find_usage(&self, usage: u32) -> Option<usize> {
+        if !self.is_variable() {
+            return None;
+        }
+        (0..self.count as usize).find(|i| self.usage(*i) == Some(usage))
+    }
+
+    /// 从报告中读取第`index`个元素的值，逻辑最小值为负数时进行符号扩展
+    ///
+    /// ## 参数
+    ///
+    /// - `report`：去掉报告id之后的报告
+    pub fn value(&self, report: &[u8], index: usize) -> Option<i32> {
+        let raw = hid_extract(
+            report,
+            self.bit_offset + index * self.bit_size as usize,
+            self.bit_size as usize,
+        )?;
+        if self.logical_minimum < 0 && self.bit_size < 32 {
+            let shift = 32 - self.bit_size as u32;
+            return Some(((raw << shift) as i32) >> shift);
+        }
+        return Some(raw as i32);
+    }
+}
+
+/// 从报告中读取从第`offset`位开始的`n`位，位按照小端序排列
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/hid-core.c#__extract
+pub fn hid_extract(report: &[u8], offset: usize, n: usize) -> Option<u32> {
+    if n == 0 || n > 32 || offset + n > report.len() * 8 {
+        return None;
+    }
+    let mut value: u64 = 0;
+    let first = offset / 8;
+    let last = (offset + n - 1) / 8;
+    for (i, byte) in report[first..=last].iter().enumerate() {
+        value |= (*byte as u64) << (i * 8);
+    }
+    value >>= offset % 8;
+    return Some((value & ((1u64 << n) - 1)) as u32);
+}
+
+/// 全局条目设置的状态，可以通过PUSH/POP保存和恢复
+#[derive(Debug, Clone, Copy, Default)]
+struct HidGlobal {
+    usage_page: u32,
+    logical_minimum: i32,
+    logical_maximum: i32,
+    report_id: u8,
+    report_size: u32,
+    report_count: u32,
+}
+
+/// 局部条目设置的状态，每个主条目之后清空
+#[derive(Debug, Default)]
+struct HidLocal {
+    usages: Vec<u32>,
+    usage_minimum: Option<u32>,
+}
+
+impl HidLocal {
+    /// 16位以下的用法使用当前的用法页
+    fn full_usage(global: &HidGlobal, size: usize, data: u32) -> u32 {
+        if size <= 2 {
+            global.usage_page | data
+        } else {
+            data
+        }
+    }
+}
+
+/// 解析后的报告描述符
+#[derive(Debug, Default)]
+pub struct HidReportDescriptor {
+    /// 输入报告中所有非常量的字段
+    pub fields: Vec<HidField>,
+    /// 设备是否使用报告id
+    pub uses_report_id: bool,
+    /// 每个报告id对应的输入报告的位数
+    input_bits: Vec<(u8, usize)>,
+}
+
+impl HidReportDescriptor {
+    /// 解析报告描述符
+    ///
+    /// ## 错误
+    ///
+    /// 描述符格式错误时返回EINVAL
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/hid-core.c#hid_open_report
+    pub fn parse(data: &[u8]) -> Result<Self, SystemError> {
+        let mut desc = HidReportDescriptor::default();
+        let mut global = HidGlobal::default();
+        let mut stack: Vec<HidGlobal> = Vec::new();
+        let mut local = HidLocal::default();
+        let mut collection_depth = 0usize;
+
+        let mut pos = 0;
+        while pos < data.len() {
+            let prefix = data[pos];
+            pos += 1;
+
+            if prefix == HID_ITEM_TAG_LONG {
+                // 长条目：数据长度、标签、数据。没有定义任何长条目，直接跳过
+                let len = *data.get(pos).ok_or(SystemError::EINVAL)? as usize;
+                pos += 2 + len;
+                continue;
+            }
+
+            let size = match prefix & 0x3 {
+                3 => 4,
+                n => n as usize,
+            };
+            let item_type = (prefix >> 2) & 0x3;
+            let tag = prefix >> 4;
+            let bytes = data.get(pos..pos + size).ok_or(SystemError::EINVAL)?;
+            pos += size;
+
+            let udata = bytes
+                .iter()
+                .enumerate()
+                .fold(0u32, |acc, (i, b)| acc | (*b as u32) << (i * 8));
+            let sdata = match size {
+                1 => udata as u8 as i8 as i32,
+                2 => udata as u16 as i16 as i32,
+                _ => udata as i32,
+            };
+
+            match item_type {
+                HID_ITEM_TYPE_MAIN => {
+                    match tag {
+                        HID_MAIN_ITEM_TAG_INPUT => {
+                            desc.add_input(&global, &local, udata)?;
+                        }
+                        HID_MAIN_ITEM_TAG_OUTPUT | HID_MAIN_ITEM_TAG_FEATURE => {}
+                        HID_MAIN_ITEM_TAG_BEGIN_COLLECTION => collection_depth += 1,
+                        HID_MAIN_ITEM_TAG_END_COLLECTION => {
+                            collection_depth =
+                                collection_depth.checked_sub(1).ok_or(SystemError::EINVAL)?;
+                        }
+                        _ => return Err(SystemError::EINVAL),
+                    }
+                    local = HidLocal::default();
+                }
+                HID_ITEM_TYPE_GLOBAL => match tag {
+                    HID_GLOBAL_ITEM_TAG_USAGE_PAGE => global.usage_page = udata << 16,
+                    HID_GLOBAL_ITEM_TAG_LOGICAL_MINIMUM => global.logical_minimum = sdata,
+                    HID_GLOBAL_ITEM_TAG_LOGICAL_MAXIMUM => {
+                        // 逻辑最小值非负时，逻辑最大值按照无符号数解释
+                        global.logical_maximum = if global.logical_minimum < 0 {
+                            sdata
+                        } else {
+                            udata as i32
+                        };
+                    }
+                    HID_GLOBAL_ITEM_TAG_REPORT_SIZE => {
+                        if udata > HID_MAX_FIELD_SIZE {
+                            return Err(SystemError::EINVAL);
+                        }
+                        global.report_size = udata;
+                    }
+                    HID_GLOBAL_ITEM_TAG_REPORT_ID => {
+                        if udata == 0 || udata > 0xff {
+                            return Err(SystemError::EINVAL);
+                        }
+                        global.report_id = udata as u8;
+                        desc.uses_report_id = true;
+                    }
+                    HID_GLOBAL_ITEM_TAG_REPORT_COUNT => {
+                        if udata > HID_MAX_USAGES {
+                            return Err(SystemError::EINVAL);
+                        }
+                        global.report_count = udata;
+                    }
+                    HID_GLOBAL_ITEM_TAG_PUSH => {
+                        if stack.len() >= HID_GLOBAL_STACK_SIZE {
+                            return Err(SystemError::EINVAL);
+                        }
+                        stack.push(global);
+                    }
+                    HID_GLOBAL_ITEM_TAG_POP => {
+                        global = stack.pop().ok_or(SystemError::EINVAL)?;
+                    }
+                    // 物理范围、单位等不影响字段的解析
+                    _ => {}
+                },
+                HID_ITEM_TYPE_LOCAL => match tag {
+                    HID_LOCAL_ITEM_TAG_USAGE => {
+                        if local.usages.len() as u32 >= HID_MAX_USAGES {
+                            return Err(SystemError::EINVAL);
+                        }
+                        local
+                            .usages
+                            .push(HidLocal::full_usage(&global, size, udata));
+                    }
+                    HID_LOCAL_ITEM_TAG_USAGE_MINIMUM => {
+                        local.usage_minimum = Some(HidLocal::full_usage(&global, size, udata));
+                    }
+                    HID_LOCAL_ITEM_TAG_USAGE_MAXIMUM => {
+                        let min = local.usage_minimum.ok_or(SystemError::EINVAL)?;
+                        let max = HidLocal::full_usage(&global, size, udata);
+                        if max < min || max - min >= HID_MAX_USAGES {
+                            return Err(SystemError::EINVAL);
+                        }
+                        local.usages.extend(min..=max);
+                    }
+                    _ => {}
+                },
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+
+        if collection_depth != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(desc);
+    }
+
+    /// 处理输入条目，把字段添加到报告中
+    fn add_input(
+        &mut self,
+        global: &HidGlobal,
+        local: &HidLocal,
+        flags: u32,
+    ) -> Result<(), SystemError> {
+        let bits = (global.report_size * global.report_count) as usize;
+        let offset = Self::bits_entry(&mut self.input_bits, global.report_id);
+        let bit_offset = *offset;
+        *offset += bits;
+
+        // 常量字段只用于填充
+        if flags & HID_MAIN_ITEM_CONSTANT != 0 || bits == 0 {
+            return Ok(());
+        }
+        self.fields.push(HidField {
+            report_id: global.report_id,
+            bit_offset,
+            bit_size: global.report_size as u8,
+            count: global.report_count as u16,
+            flags,
+            logical_minimum: global.logical_minimum,
+            logical_maximum: global.logical_maximum,
+            usages: local.usages.clone(),
+        });
+        return Ok(());
+    }
+
+    /// 获取报告已经使用的位数
+    fn bits_entry(list: &mut Vec<(u8, usize)>, report_id: u8) -> &mut usize {
+        let index = match list.iter().position(|(id, _)| *id == report_id) {
+            Some(index) => index,
+            None => {
+                list.push((report_id, 0));
+                list.len() - 1
+            }
+        };
+        return &mut list[index].1;
+    }
+
+    /// 输入报告的字节数，不包括报告id
+    pub fn input_report_len(&self, report_id: u8) -> usize {
+        self.input_bits
+            .iter()
+            .find(|(id, _)| *id == report_id)
+            .map_or(0, |(_, bits)| (bits + 7) / 8)
+    }
+
+    /// 查找包含指定用法的变量字段
+    ///
+    /// ## 返回值
+    ///
+    /// 字段以及用法对应的元素在字段中的下标
+    pub fn find_variable(&self, usage: u32) -> Option<(&HidField, usize)> {
+        self.fields
+            .iter()
+            .find_map(|field| field.find_usage(usage).map(|index| (field, index)))
+    }
+}
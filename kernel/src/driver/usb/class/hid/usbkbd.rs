@@ -0,0 +1,289 @@
+//! USB启动协议键盘驱动
+//!
+//! 键盘工作在启动协议下，每次按键状态变化时通过中断IN端点发送8字节的报告：
+//! 第0字节为修饰键，第2~7字节为当前按下的按键的用法码(usage id)。
+//! 驱动比较前后两次报告，把差异转换为Linux的键码上报到输入子系统
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/usbhid/usbkbd.c
+
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::{
+        base::{
+            device::{bus::Bus, driver::Driver, Device, IdTable},
+            kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        input::{input_register_device, InputDevice, InputId, BUS_USB, EV_KEY},
+        usb::{
+            ch9::{UsbTransferType, USB_CLASS_HID},
+            device::UsbInterface,
+            driver::{UsbDeviceId, UsbDriver},
+            hcd::UsbInterruptHandler,
+        },
+    },
+    filesystem::kernfs::KernFSInode,
+    kwarn,
+    libs::{
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
+    },
+    syscall::SystemError,
+};
+
+use super::{
+    hid_set_idle, hid_set_protocol, HID_BOOT_PROTOCOL, USB_INTERFACE_PROTOCOL_KEYBOARD,
+    USB_INTERFACE_SUBCLASS_BOOT,
+};
+
+/// 启动协议下键盘报告的长度
+const USB_KBD_REPORT_LEN: usize = 8;
+
+static USB_KBD_IDS: [UsbDeviceId; 1] = [UsbDeviceId::interface(
+    USB_CLASS_HID,
+    USB_INTERFACE_SUBCLASS_BOOT,
+    USB_INTERFACE_PROTOCOL_KEYBOARD,
+)];
+
+/// HID键盘用法码到Linux键码的映射，第224~231项为修饰键，对应报告第0字节的8个位
+#[rustfmt::skip]
+static USB_KBD_KEYCODE: [u8; 256] = [
+      0,  0,  0,  0, 30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38,
+     50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45, 21, 44,  2,  3,
+      4,  5,  6,  7,  8,  9, 10, 11, 28,  1, 14, 15, 57, 12, 13, 26,
+     27, 43, 43, 39, 40, 41, 51, 52, 53, 58, 59, 60, 61, 62, 63, 64,
+     65, 66, 67, 68, 87, 88, 99, 70,119,110,102,104,111,107,109,106,
+    105,108,103, 69, 98, 55, 74, 78, 96, 79, 80, 81, 75, 76, 77, 71,
+     72, 73, 82, 83, 86,127,116,117,183,184,185,186,187,188,189,190,
+    191,192,193,194,134,138,130,132,128,129,131,137,133,135,136,113,
+    115,114,  0,  0,  0,121,  0, 89, 93,124, 92, 94, 95,  0,  0,  0,
+    122,123, 90, 91, 85,  0,  0,  0,  0,  0,  0,  0,111,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,
+     29, 42, 56,125, 97, 54,100,126,164,166,165,163,161,115,114,113,
+    150,158,159,128,136,177,178,176,142,152,173,140,  0,  0,  0,  0,
+];
+
+#[derive(Debug)]
+struct InnerUsbKbdDriver {
+    bus: Option<Arc<dyn Bus>>,
+    kobj_type: Option<&'static dyn KObjType>,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    kern_inode: Option<Arc<KernFSInode>>,
+    devices: Vec<Arc<dyn Device>>,
+}
+
+/// USB启动协议键盘的驱动
+#[derive(Debug)]
+#[cast_to([sync] Driver, UsbDriver)]
+pub struct UsbKbdDriver {
+    inner: RwLock<InnerUsbKbdDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl UsbKbdDriver {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: RwLock::new(InnerUsbKbdDriver {
+                bus: None,
+                kobj_type: None,
+                kset: None,
+                parent_kobj: None,
+                kern_inode: None,
+                devices: Vec::new(),
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        })
+    }
+}
+
+impl UsbDriver for UsbKbdDriver {
+    fn usb_id_table(&self) -> &'static [UsbDeviceId] {
+        &USB_KBD_IDS
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/usbhid/usbkbd.c#usb_kbd_probe
+    fn probe(&self, intf: &Arc<UsbInterface>, _id: &UsbDeviceId) -> Result<(), SystemError> {
+        let endpoint = intf
+            .find_endpoint(UsbTransferType::Interrupt, true)
+            .ok_or(SystemError::ENODEV)?;
+
+        // 复位之后设备默认使用报告协议，切换到格式固定的启动协议。
+        // 有些键盘不支持这两个请求，失败时仍然继续
+        hid_set_protocol(intf, HID_BOOT_PROTOCOL).ok();
+        // 只在按键状态变化时发送报告
+        hid_set_idle(intf, 0).ok();
+
+        let usb_dev = intf.usb_device();
+        let desc = usb_dev.descriptor();
+        let mut input = InputDevice::new(
+            &format!(
+                "USB Keyboard {:04x}:{:04x}",
+                desc.vendor_id, desc.product_id
+            ),
+            InputId {
+                bustype: BUS_USB,
+                vendor: desc.vendor_id,
+                product: desc.product_id,
+                version: desc.device_version,
+            },
+        );
+        for code in USB_KBD_KEYCODE.iter().filter(|code| **code != 0) {
+            input.set_capability(EV_KEY, *code as u16);
+        }
+
+        let kbd = Arc::new(UsbKbd {
+            input: input_register_device(input),
+            old: SpinLock::new([0; USB_KBD_REPORT_LEN]),
+        });
+        let len = (endpoint.max_packet() as usize).max(USB_KBD_REPORT_LEN);
+        return usb_dev.interrupt_in_start(endpoint.endpoint_address, len, kbd);
+    }
+}
+
+impl Driver for UsbKbdDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        None
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner.read().devices.clone()
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        self.inner.write().devices.push(device);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let mut inner = self.inner.write();
+
+        inner.devices.drain_filter(|d| Arc::ptr_eq(d, device));
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+}
+
+impl KObject for UsbKbdDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner.read().kobj_type.clone()
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner.write().kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        "usbkbd".to_string()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 一个USB键盘
+#[derive(Debug)]
+struct UsbKbd {
+    input: Arc<InputDevice>,
+    /// 上一次收到的报告
+    old: SpinLock<[u8; USB_KBD_REPORT_LEN]>,
+}
+
+impl UsbInterruptHandler for UsbKbd {
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/usbhid/usbkbd.c#usb_kbd_irq
+    fn complete(&self, data: &[u8]) {
+        if data.len() < USB_KBD_REPORT_LEN {
+            return;
+        }
+        let new = &data[..USB_KBD_REPORT_LEN];
+        let mut old = self.old.lock_irqsave();
+
+        for i in 0..8 {
+            self.input
+                .report_key(USB_KBD_KEYCODE[i + 224] as u16, (new[0] >> i) & 1 != 0);
+        }
+
+        // 用法码0~3表示没有按键或者出错(例如同时按下的键太多)，不是真正的按键
+        for i in 2..USB_KBD_REPORT_LEN {
+            if old[i] > 3 && !new[2..].contains(&old[i]) {
+                self.report_usage(old[i], false);
+            }
+            if new[i] > 3 && !old[2..].contains(&new[i]) {
+                self.report_usage(new[i], true);
+            }
+        }
+        self.input.sync();
+
+        old.copy_from_slice(new);
+    }
+}
+
+impl UsbKbd {
+    fn report_usage(&self, usage: u8, pressed: bool) {
+        let code = USB_KBD_KEYCODE[usage as usize];
+        if code != 0 {
+            self.input.report_key(code as u16, pressed);
+        } else {
+            kwarn!(
+                "{}: Unknown key (scancode {:#x}) {}.",
+                self.input.name(),
+                usage,
+                if pressed { "pressed" } else { "released" }
+            );
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! USB鼠标驱动
+//!
+//! 优先读取并解析报告描述符，按照描述符中按键、X/Y位移和滚轮字段的位置解析报告协议下的报告；
+//! 描述符无法解析时，支持启动协议的鼠标切换到启动协议，报告的格式固定为：
+//! 第0字节的低5位为按键，之后依次为X位移、Y位移和滚轮(可选)，都是有符号数
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/usbhid/usbmouse.c
+
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::{
+        base::{
+            device::{bus::Bus, driver::Driver, Device, IdTable},
+            kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        input::{
+            input_register_device, InputDevice, InputId, BTN_EXTRA, BTN_LEFT, BTN_MIDDLE,
+            BTN_RIGHT, BTN_SIDE, BUS_USB, EV_KEY, EV_REL, REL_WHEEL, REL_X, REL_Y,
+        },
+        usb::{
+            ch9::{UsbTransferType, USB_CLASS_HID},
+            device::UsbInterface,
+            driver::{UsbDeviceId, UsbDriver},
+            hcd::UsbInterruptHandler,
+        },
+    },
+    filesystem::kernfs::KernFSInode,
+    libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    syscall::SystemError,
+};
+
+use super::{
+    hid_get_report_descriptor, hid_set_idle, hid_set_protocol,
+    parser::{HidField, HidReportDescriptor, HID_GD_WHEEL, HID_GD_X, HID_GD_Y, HID_UP_BUTTON},
+    HID_BOOT_PROTOCOL, USB_INTERFACE_PROTOCOL_MOUSE, USB_INTERFACE_SUBCLASS_BOOT,
+};
+
+/// 支持启动协议的鼠标，以及其他能从报告描述符中找到相对位移的HID设备
+static USB_MOUSE_IDS: [UsbDeviceId; 2] = [
+    UsbDeviceId::interface(
+        USB_CLASS_HID,
+        USB_INTERFACE_SUBCLASS_BOOT,
+        USB_INTERFACE_PROTOCOL_MOUSE,
+    ),
+    UsbDeviceId::interface(USB_CLASS_HID, 0, 0),
+];
+
+/// 启动协议下鼠标报告的格式，按照报告描述符的格式描述，
+/// 在HID规范附录B.2的基础上增加了两个按键和滚轮
+#[rustfmt::skip]
+static USB_MOUSE_BOOT_DESCRIPTOR: [u8; 52] = [
+    0x05, 0x01,         // Usage Page (Generic Desktop)
+    0x09, 0x02,         // Usage (Mouse)
+    0xa1, 0x01,         // Collection (Application)
+    0x09, 0x01,         //   Usage (Pointer)
+    0xa1, 0x00,         //   Collection (Physical)
+    0x05, 0x09,         //     Usage Page (Button)
+    0x19, 0x01,         //     Usage Minimum (1)
+    0x29, 0x05,         //     Usage Maximum (5)
+    0x15, 0x00,         //     Logical Minimum (0)
+    0x25, 0x01,         //     Logical Maximum (1)
+    0x95, 0x05,         //     Report Count (5)
+    0x75, 0x01,         //     Report Size (1)
+    0x81, 0x02,         //     Input (Data, Variable, Absolute)
+    0x95, 0x01,         //     Report Count (1)
+    0x75, 0x03,         //     Report Size (3)
+    0x81, 0x01,         //     Input (Constant)
+    0x05, 0x01,         //     Usage Page (Generic Desktop)
+    0x09, 0x30,         //     Usage (X)
+    0x09, 0x31,         //     Usage (Y)
+    0x09, 0x38,         //     Usage (Wheel)
+    0x15, 0x81,         //     Logical Minimum (-127)
+    0x25, 0x7f,         //     Logical Maximum (127)
+    0x75, 0x08,         //     Report Size (8)
+    0x95, 0x03,         //     Report Count (3)
+    0x81, 0x06,         //     Input (Data, Variable, Relative)
+    0xc0,               //   End Collection
+    0xc0,               // End Collection
+];
+
+/// 按键用法页中第1~5个按键对应的键码
+const USB_MOUSE_BUTTONS: [u16; 5] = [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE, BTN_SIDE, BTN_EXTRA];
+
+#[derive(Debug)]
+struct InnerUsbMouseDriver {
+    bus: Option<Arc<dyn Bus>>,
+    kobj_type: Option<&'static dyn KObjType>,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    kern_inode: Option<Arc<KernFSInode>>,
+    devices: Vec<Arc<dyn Device>>,
+}
+
+/// USB鼠标的驱动
+#[derive(Debug)]
+#[cast_to([sync] Driver, UsbDriver)]
+pub struct UsbMouseDriver {
+    inner: RwLock<InnerUsbMouseDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl UsbMouseDriver {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: RwLock::new(InnerUsbMouseDriver {
+                bus: None,
+                kobj_type: None,
+                kset: None,
+                parent_kobj: None,
+                kern_inode: None,
+                devices: Vec::new(),
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        })
+    }
+}
+
+impl UsbDriver for UsbMouseDriver {
+    fn usb_id_table(&self) -> &'static [UsbDeviceId] {
+        &USB_MOUSE_IDS
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/usbhid/usbmouse.c#usb_mouse_probe
+    fn probe(&self, intf: &Arc<UsbInterface>, _id: &UsbDeviceId) -> Result<(), SystemError> {
+        let endpoint = intf
+            .find_endpoint(UsbTransferType::Interrupt, true)
+            .ok_or(SystemError::ENODEV)?;
+
+        // 设备复位之后使用报告协议，先尝试按照报告描述符解析报告
+        let layout = hid_get_report_descriptor(intf)
+            .and_then(|data| HidReportDescriptor::parse(&data))
+            .ok()
+            .and_then(|desc| UsbMouseLayout::from_descriptor(&desc));
+        let layout = match layout {
+            Some(layout) => layout,
+            None => {
+                if intf.descriptor().interface_subclass != USB_INTERFACE_SUBCLASS_BOOT {
+                    return Err(SystemError::ENODEV);
+                }
+                // 不支持这个请求的鼠标，报告协议下的格式通常与启动协议相同
+                hid_set_protocol(intf, HID_BOOT_PROTOCOL).ok();
+                let desc = HidReportDescriptor::parse(&USB_MOUSE_BOOT_DESCRIPTOR)?;
+                UsbMouseLayout::from_descriptor(&desc).ok_or(SystemError::EINVAL)?
+            }
+        };
+        // 只在数据变化时发送报告
+        hid_set_idle(intf, 0).ok();
+
+        let usb_dev = intf.usb_device();
+        let desc = usb_dev.descriptor();
+        let mut input = InputDevice::new(
+            &format!("USB Mouse {:04x}:{:04x}", desc.vendor_id, desc.product_id),
+            InputId {
+                bustype: BUS_USB,
+                vendor: desc.vendor_id,
+                product: desc.product_id,
+                version: desc.device_version,
+            },
+        );
+        for (code, item) in USB_MOUSE_BUTTONS.iter().zip(layout.buttons.iter()) {
+            if item.is_some() {
+                input.set_capability(EV_KEY, *code);
+            }
+        }
+        input.set_capability(EV_REL, REL_X);
+        input.set_capability(EV_REL, REL_Y);
+        if layout.wheel.is_some() {
+            input.set_capability(EV_REL, REL_WHEEL);
+        }
+
+        let mouse = Arc::new(UsbMouse {
+            input: input_register_device(input),
+            layout,
+        });
+        return usb_dev.interrupt_in_start(
+            endpoint.endpoint_address,
+            endpoint.max_packet() as usize,
+            mouse,
+        );
+    }
+}
+
+impl Driver for UsbMouseDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        None
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner.read().devices.clone()
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        self.inner.write().devices.push(device);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let mut inner = self.inner.write();
+
+        inner.devices.drain_filter(|d| Arc::ptr_eq(d, device));
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+}
+
+impl KObject for UsbMouseDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner.read().kobj_type.clone()
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner.write().kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        "usbmouse".to_string()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 报告中的一个数据项：字段以及数据项在字段中的下标
+#[derive(Debug, Clone)]
+struct UsbMouseItem {
+    field: HidField,
+    index: usize,
+}
+
+impl UsbMouseItem {
+    #[inline]
+    fn value(&self, report: &[u8]) -> Option<i32> {
+        self.field.value(report, self.index)
+    }
+}
+
+/// 鼠标报告的格式
+#[derive(Debug)]
+struct UsbMouseLayout {
+    /// 报告id，设备不使用报告id时为None
+    report_id: Option<u8>,
+    buttons: [Option<UsbMouseItem>; 5],
+    x: UsbMouseItem,
+    y: UsbMouseItem,
+    wheel: Option<UsbMouseItem>,
+}
+
+impl UsbMouseLayout {
+    /// 从报告描述符中查找鼠标的各个数据项，没有相对的X/Y位移时返回None
+    fn from_descriptor(desc: &HidReportDescriptor) -> Option<Self> {
+        let (x_field, x_index) = desc.find_variable(HID_GD_X)?;
+        if !x_field.is_relative() {
+            return None;
+        }
+        let report_id = x_field.report_id;
+        // 其余的数据项必须与X位移位于同一个报告中
+        let find = |usage: u32| -> Option<UsbMouseItem> {
+            desc.fields
+                .iter()
+                .filter(|field| field.report_id == report_id)
+                .find_map(|field| {
+                    field.find_usage(usage).map(|index| UsbMouseItem {
+                        field: field.clone(),
+                        index,
+                    })
+                })
+        };
+
+        let y = find(HID_GD_Y)?;
+        if !y.field.is_relative() {
+            return None;
+        }
+        let mut buttons: [Option<UsbMouseItem>; 5] = Default::default();
+        for (i, button) in buttons.iter_mut().enumerate() {
+            *button = find(HID_UP_BUTTON | (i as u32 + 1));
+        }
+        return Some(Self {
+            report_id: desc.uses_report_id.then_some(report_id),
+            buttons,
+            x: UsbMouseItem {
+                field: x_field.clone(),
+                index: x_index,
+            },
+            y,
+            wheel: find(HID_GD_WHEEL).filter(|item| item.field.is_relative()),
+        });
+    }
+}
+
+/// 一个USB鼠标
+#[derive(Debug)]
+struct UsbMouse {
+    input: Arc<InputDevice>,
+    layout: UsbMouseLayout,
+}
+
+impl UsbInterruptHandler for UsbMouse {
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/hid/usbhid/usbmouse.c#usb_mouse_irq
+    fn complete(&self, data: &[u8]) {
+        let report = match self.layout.report_id {
+            Some(id) => match data.split_first() {
+                Some((first, report)) if *first == id => report,
+                // 其他报告中没有鼠标的数据
+                _ => return,
+            },
+            None => data,
+        };
+
+        for (code, item) in USB_MOUSE_BUTTONS.iter().zip(self.layout.buttons.iter()) {
+            if let Some(value) = item.as_ref().and_then(|item| item.value(report)) {
+                self.input.report_key(*code, value != 0);
+            }
+        }
+        if let Some(dx) = self.layout.x.value(report) {
+            self.input.report_rel(REL_X, dx);
+        }
+        if let Some(dy) = self.layout.y.value(report) {
+            self.input.report_rel(REL_Y, dy);
+        }
+        // 只有3个字节的启动协议报告没有滚轮
+        if let Some(wheel) = self
+            .layout
+            .wheel
+            .as_ref()
+            .and_then(|item| item.value(report))
+        {
+            self.input.report_rel(REL_WHEEL, wheel);
+        }
+        self.input.sync();
+    }
+}
@@ -0,0 +1,23 @@
+//! USB接口驱动
+
+use alloc::sync::Arc;
+
+use crate::syscall::SystemError;
+
+use self::{
+    hid::{usbkbd::UsbKbdDriver, usbmouse::UsbMouseDriver},
+    storage::UsbStorageDriver,
+};
+
+use super::driver::{usb_driver_manager, UsbDriver};
+
+pub mod hid;
+pub mod storage;
+
+/// 注册内置的USB接口驱动
+pub fn usb_class_init() -> Result<(), SystemError> {
+    usb_driver_manager().register(UsbKbdDriver::new() as Arc<dyn UsbDriver>)?;
+    usb_driver_manager().register(UsbMouseDriver::new() as Arc<dyn UsbDriver>)?;
+    usb_driver_manager().register(UsbStorageDriver::new() as Arc<dyn UsbDriver>)?;
+    return Ok(());
+}
@@ -0,0 +1,944 @@
+//! USB大容量存储设备驱动
+//!
+//! 只支持使用Bulk-Only传输协议(BOT)和SCSI透明命令集的设备，这也是U盘最常见的组合。
+//! 每条SCSI命令由三个阶段组成：通过批量OUT端点发送命令块封装(CBW)，
+//! 在批量端点上传输数据，最后通过批量IN端点读取命令状态封装(CSW)。
+//!
+//! 与Linux的sd驱动一致，磁盘使用SCSI磁盘的主设备号，在devfs中注册为sda、sdb等，
+//! 磁盘上的MBR分区注册为sda1、sda2等
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/storage/transport.c
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    debug::trace_event::{trace_block_rq_complete, trace_block_rq_issue},
+    driver::{
+        base::{
+            block::{
+                block_device::{BlockDevice, BlockDeviceOps, BlockId},
+                disk_info::Partition,
+            },
+            class::{block_class, class_manager, Class},
+            device::{
+                bus::Bus, device_manager, driver::Driver, Device, DeviceKObjType, DeviceNumber,
+                DeviceType, IdTable,
+            },
+            kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+            kset::KSet,
+        },
+        usb::{
+            ch9::{UsbTransferType, USB_CLASS_MASS_STORAGE, USB_RECIP_INTERFACE, USB_TYPE_CLASS},
+            device::UsbInterface,
+            driver::{UsbDeviceId, UsbDriver},
+            hcd::UsbData,
+        },
+    },
+    filesystem::{
+        devfs::{DevFS, DeviceINode},
+        kernfs::KernFSInode,
+        vfs::{
+            core::generate_inode_id, file::FileMode, make_rawdev, syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus, PollTable,
+        },
+    },
+    kinfo, kwarn,
+    libs::{
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+        spinlock::SpinLock,
+    },
+    syscall::SystemError,
+    time::{hrtimer::ktime_get, TimeSpec, NSEC_PER_MSEC},
+};
+
+/// 接口子类别：SCSI透明命令集
+const USB_SC_SCSI: u8 = 0x06;
+/// 接口协议：Bulk-Only传输
+const USB_PR_BULK: u8 = 0x50;
+
+static USB_STORAGE_IDS: [UsbDeviceId; 1] = [UsbDeviceId::interface(
+    USB_CLASS_MASS_STORAGE,
+    USB_SC_SCSI,
+    USB_PR_BULK,
+)];
+
+/// 命令块封装(CBW)
+const US_BULK_CB_SIGN: u32 = 0x43425355;
+const US_BULK_CB_WRAP_LEN: usize = 31;
+const US_BULK_FLAG_IN: u8 = 0x80;
+/// 命令状态封装(CSW)
+const US_BULK_CS_SIGN: u32 = 0x53425355;
+const US_BULK_CS_WRAP_LEN: usize = 13;
+const US_BULK_STAT_OK: u8 = 0;
+const US_BULK_STAT_FAIL: u8 = 1;
+/// Bulk-Only Mass Storage Reset 类别请求
+const US_BULK_RESET_REQUEST: u8 = 0xff;
+
+/// SCSI命令
+const TEST_UNIT_READY: u8 = 0x00;
+const REQUEST_SENSE: u8 = 0x03;
+const INQUIRY: u8 = 0x12;
+const READ_CAPACITY: u8 = 0x25;
+const READ_10: u8 = 0x28;
+const WRITE_10: u8 = 0x2a;
+const SYNCHRONIZE_CACHE: u8 = 0x35;
+
+/// 目前只支持512字节的扇区
+const USB_STORAGE_BLOCK_SIZE_LOG2: u8 = 9;
+const USB_STORAGE_BLOCK_SIZE: usize = 1 << USB_STORAGE_BLOCK_SIZE_LOG2;
+/// 一条READ(10)/WRITE(10)命令最多传输的块数，主机控制器单次传输最多64K字节
+const USB_STORAGE_MAX_BLOCKS: usize = 128;
+/// 等待介质就绪的次数，每次间隔100ms
+const USB_STORAGE_READY_RETRIES: usize = 50;
+
+/// SCSI磁盘的主设备号，每个磁盘占用16个次设备号，第0个为整个磁盘，其余为分区
+const SCSI_DISK0_MAJOR: usize = 8;
+const SD_MINORS: usize = 16;
+/// 主设备号8最多容纳16个磁盘(sda~sdp)
+const SD_MAX_DISKS: usize = 16;
+
+/// 已经分配的磁盘编号的数量，用于生成磁盘的名称和设备号
+static USB_STORAGE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 所有已经注册的USB磁盘
+static USB_STORAGE_DISKS: RwLock<Vec<Arc<UsbStorageDisk>>> = RwLock::new(Vec::new());
+
+/// 获取所有已经注册的USB磁盘
+pub fn usb_storage_disks() -> Vec<Arc<UsbStorageDisk>> {
+    USB_STORAGE_DISKS.read().clone()
+}
+
+#[derive(Debug)]
+struct InnerUsbStorageDriver {
+    bus: Option<Arc<dyn Bus>>,
+    kobj_type: Option<&'static dyn KObjType>,
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    kern_inode: Option<Arc<KernFSInode>>,
+    devices: Vec<Arc<dyn Device>>,
+}
+
+/// USB大容量存储设备的驱动
+#[derive(Debug)]
+#[cast_to([sync] Driver, UsbDriver)]
+pub struct UsbStorageDriver {
+    inner: RwLock<InnerUsbStorageDriver>,
+    kobj_state: LockedKObjectState,
+}
+
+impl UsbStorageDriver {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: RwLock::new(InnerUsbStorageDriver {
+                bus: None,
+                kobj_type: None,
+                kset: None,
+                parent_kobj: None,
+                kern_inode: None,
+                devices: Vec::new(),
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        })
+    }
+}
+
+impl UsbDriver for UsbStorageDriver {
+    fn usb_id_table(&self) -> &'static [UsbDeviceId] {
+        &USB_STORAGE_IDS
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/storage/usb.c#usb_stor_probe1
+    fn probe(&self, intf: &Arc<UsbInterface>, _id: &UsbDeviceId) -> Result<(), SystemError> {
+        let bulk_in = intf
+            .find_endpoint(UsbTransferType::Bulk, true)
+            .ok_or(SystemError::ENODEV)?;
+        let bulk_out = intf
+            .find_endpoint(UsbTransferType::Bulk, false)
+            .ok_or(SystemError::ENODEV)?;
+
+        let mut transport = UsbStorageTransport {
+            intf: intf.clone(),
+            bulk_in: bulk_in.endpoint_address,
+            bulk_out: bulk_out.endpoint_address,
+            tag: 0,
+        };
+        transport.inquiry()?;
+        transport.wait_ready()?;
+        let (block_count, block_size) = transport.read_capacity()?;
+        if block_size != USB_STORAGE_BLOCK_SIZE {
+            kwarn!(
+                "usb-storage {}: unsupported block size {}",
+                intf.name(),
+                block_size
+            );
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+
+        let id = USB_STORAGE_COUNT.fetch_add(1, Ordering::SeqCst);
+        if id >= SD_MAX_DISKS {
+            return Err(SystemError::ENOSPC);
+        }
+        let devnum = BlockDeviceOps::register_blockdev_region(
+            DeviceNumber::new(DeviceNumber::from_major_minor(
+                SCSI_DISK0_MAJOR,
+                id * SD_MINORS,
+            )),
+            SD_MINORS,
+            "sd",
+        )?;
+        let name = format!("sd{}", (b'a' + id as u8) as char);
+        let disk = UsbStorageDisk::new(name, devnum, transport, block_count)?;
+        kinfo!(
+            "usb-storage {}: {}: {} blocks ({} MiB), {} partitions",
+            intf.name(),
+            disk.name,
+            block_count,
+            (block_count << USB_STORAGE_BLOCK_SIZE_LOG2) >> 20,
+            disk.partitions().len()
+        );
+
+        // 磁盘位于USB接口之下，分区位于磁盘之下，它们都属于block class
+        let intf_kobj = intf.clone() as Arc<dyn KObject>;
+        disk.set_parent(Some(Arc::downgrade(&intf_kobj)));
+        let disk_dev = disk.clone() as Arc<dyn Device>;
+        device_manager().register(disk_dev.clone())?;
+        class_manager().devnode_register(&disk_dev, UsbStorageInode::new(disk.clone(), None))?;
+        for part in disk.partitions() {
+            class_manager().device_create_with_inode(
+                block_class(),
+                Some(&disk_dev),
+                format!("{}{}", disk.name, part.partno + 1),
+                UsbStorageInode::new(disk.clone(), Some(part)),
+            )?;
+        }
+        USB_STORAGE_DISKS.write().push(disk);
+        return Ok(());
+    }
+}
+
+impl Driver for UsbStorageDriver {
+    fn id_table(&self) -> Option<IdTable> {
+        None
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        self.inner.read().devices.clone()
+    }
+
+    fn add_device(&self, device: Arc<dyn Device>) {
+        self.inner.write().devices.push(device);
+    }
+
+    fn delete_device(&self, device: &Arc<dyn Device>) {
+        let mut inner = self.inner.write();
+
+        inner.devices.drain_filter(|d| Arc::ptr_eq(d, device));
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+}
+
+impl KObject for UsbStorageDriver {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner.read().kobj_type.clone()
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner.write().kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        "usb-storage".to_string()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// 忙等待一段时间
+fn delay_ms(ms: u64) {
+    let deadline = ktime_get() + ms * NSEC_PER_MSEC as u64;
+    while ktime_get() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+/// 与设备进行Bulk-Only传输
+#[derive(Debug)]
+struct UsbStorageTransport {
+    intf: Arc<UsbInterface>,
+    bulk_in: u8,
+    bulk_out: u8,
+    /// 上一个CBW的标签，设备在CSW中原样返回
+    tag: u32,
+}
+
+impl UsbStorageTransport {
+    /// 在批量端点上传输数据，端点被STALL时清除STALL状态并返回EPIPE
+    fn bulk_msg(&self, endpoint: u8, data: UsbData) -> Result<usize, SystemError> {
+        let usb_dev = self.intf.usb_device();
+        let r = usb_dev.bulk_msg(endpoint, data);
+        if let Err(SystemError::EPIPE) = r {
+            usb_dev.clear_halt(endpoint)?;
+        }
+        return r;
+    }
+
+    /// 执行一条SCSI命令
+    ///
+    /// ## 参数
+    ///
+    /// - `cdb`：命令描述块
+    /// - `data`：数据阶段
+    ///
+    /// ## 返回值
+    ///
+    /// 数据阶段实际传输的字节数
+    ///
+    /// ## 错误
+    ///
+    /// 设备报告命令失败时返回EIO，此时可以通过REQUEST SENSE读取失败原因
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/storage/transport.c#usb_stor_Bulk_transport
+    fn transport(&mut self, cdb: &[u8], data: UsbData) -> Result<usize, SystemError> {
+        self.tag = self.tag.wrapping_add(1);
+        let flags = match data {
+            UsbData::In(_) => US_BULK_FLAG_IN,
+            _ => 0,
+        };
+
+        let mut cbw = [0u8; US_BULK_CB_WRAP_LEN];
+        cbw[0..4].copy_from_slice(&US_BULK_CB_SIGN.to_le_bytes());
+        cbw[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        cbw[8..12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        cbw[12] = flags;
+        // 只使用逻辑单元0
+        cbw[13] = 0;
+        cbw[14] = cdb.len() as u8;
+        cbw[15..15 + cdb.len()].copy_from_slice(cdb);
+        if let Err(err) = self.bulk_msg(self.bulk_out, UsbData::Out(&cbw)) {
+            self.reset_recovery();
+            return Err(err);
+        }
+
+        // 数据阶段的端点被STALL时，仍然需要读取CSW
+        let transferred = match data {
+            UsbData::None => 0,
+            UsbData::In(buf) => match self.bulk_msg(self.bulk_in, UsbData::In(buf)) {
+                Err(SystemError::EPIPE) => 0,
+                r => r?,
+            },
+            UsbData::Out(buf) => match self.bulk_msg(self.bulk_out, UsbData::Out(buf)) {
+                Err(SystemError::EPIPE) => 0,
+                r => r?,
+            },
+        };
+
+        // 读取CSW时被STALL，清除之后重试一次
+        let mut csw = [0u8; US_BULK_CS_WRAP_LEN];
+        let len = match self.bulk_msg(self.bulk_in, UsbData::In(&mut csw)) {
+            Err(SystemError::EPIPE) => self.bulk_msg(self.bulk_in, UsbData::In(&mut csw)),
+            r => r,
+        };
+        let sign = u32::from_le_bytes(csw[0..4].try_into().unwrap());
+        let tag = u32::from_le_bytes(csw[4..8].try_into().unwrap());
+        if len != Ok(US_BULK_CS_WRAP_LEN) || sign != US_BULK_CS_SIGN || tag != self.tag {
+            self.reset_recovery();
+            return Err(SystemError::EIO);
+        }
+
+        return match csw[12] {
+            US_BULK_STAT_OK => Ok(transferred),
+            US_BULK_STAT_FAIL => Err(SystemError::EIO),
+            // 阶段错误
+            _ => {
+                self.reset_recovery();
+                Err(SystemError::EIO)
+            }
+        };
+    }
+
+    /// 传输出错之后复位设备，并清除两个批量端点的STALL状态
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/storage/transport.c#usb_stor_Bulk_reset
+    fn reset_recovery(&self) {
+        self.intf
+            .class_msg(
+                USB_TYPE_CLASS | USB_RECIP_INTERFACE,
+                US_BULK_RESET_REQUEST,
+                0,
+                UsbData::None,
+            )
+            .ok();
+        let usb_dev = self.intf.usb_device();
+        usb_dev.clear_halt(self.bulk_in).ok();
+        usb_dev.clear_halt(self.bulk_out).ok();
+    }
+
+    /// 读取并打印设备的厂商和产品信息
+    fn inquiry(&mut self) -> Result<(), SystemError> {
+        let mut buf = [0u8; 36];
+        self.transport(
+            &[INQUIRY, 0, 0, 0, buf.len() as u8, 0],
+            UsbData::In(&mut buf),
+        )?;
+        kinfo!(
+            "usb-storage {}: {} {} {}",
+            self.intf.name(),
+            core::str::from_utf8(&buf[8..16]).unwrap_or("").trim(),
+            core::str::from_utf8(&buf[16..32]).unwrap_or("").trim(),
+            core::str::from_utf8(&buf[32..36]).unwrap_or("").trim()
+        );
+        return Ok(());
+    }
+
+    /// 等待介质就绪。设备刚上电时通常会报告UNIT ATTENTION，需要读取感知数据之后重试
+    fn wait_ready(&mut self) -> Result<(), SystemError> {
+        for _ in 0..USB_STORAGE_READY_RETRIES {
+            if self
+                .transport(&[TEST_UNIT_READY, 0, 0, 0, 0, 0], UsbData::None)
+                .is_ok()
+            {
+                return Ok(());
+            }
+            let mut sense = [0u8; 18];
+            self.transport(
+                &[REQUEST_SENSE, 0, 0, 0, sense.len() as u8, 0],
+                UsbData::In(&mut sense),
+            )
+            .ok();
+            delay_ms(100);
+        }
+        return Err(SystemError::ENOMEDIUM);
+    }
+
+    /// 读取磁盘的块数和块大小
+    fn read_capacity(&mut self) -> Result<(u64, usize), SystemError> {
+        let mut buf = [0u8; 8];
+        self.transport(
+            &[READ_CAPACITY, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            UsbData::In(&mut buf),
+        )?;
+        let last_lba = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+        let block_size = u32::from_be_bytes(buf[4..8].try_into().unwrap());
+        return Ok((last_lba as u64 + 1, block_size as usize));
+    }
+
+    /// 构造READ(10)/WRITE(10)命令
+    fn rw10_cdb(opcode: u8, lba: u32, count: u16) -> [u8; 10] {
+        let lba = lba.to_be_bytes();
+        let count = count.to_be_bytes();
+        [
+            opcode, 0, lba[0], lba[1], lba[2], lba[3], 0, count[0], count[1], 0,
+        ]
+    }
+
+    fn read_blocks(&mut self, lba: u32, count: usize, buf: &mut [u8]) -> Result<(), SystemError> {
+        let len = count << USB_STORAGE_BLOCK_SIZE_LOG2;
+        let cdb = Self::rw10_cdb(READ_10, lba, count as u16);
+        if self.transport(&cdb, UsbData::In(&mut buf[..len]))? != len {
+            return Err(SystemError::EIO);
+        }
+        return Ok(());
+    }
+
+    fn write_blocks(&mut self, lba: u32, count: usize, buf: &[u8]) -> Result<(), SystemError> {
+        let len = count << USB_STORAGE_BLOCK_SIZE_LOG2;
+        let cdb = Self::rw10_cdb(WRITE_10, lba, count as u16);
+        if self.transport(&cdb, UsbData::Out(&buf[..len]))? != len {
+            return Err(SystemError::EIO);
+        }
+        return Ok(());
+    }
+}
+
+/// USB大容量存储设备上的磁盘，只支持MBR分区格式
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct UsbStorageDisk {
+    name: String,
+    devnum: DeviceNumber,
+    transport: SpinLock<UsbStorageTransport>,
+    block_count: u64,
+    partitions: RwLock<Vec<Arc<Partition>>>,
+    inner: RwLock<InnerUsbStorageDisk>,
+    kobj_state: LockedKObjectState,
+    self_ref: Weak<UsbStorageDisk>,
+}
+
+#[derive(Debug)]
+struct InnerUsbStorageDisk {
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    inode: Option<Arc<KernFSInode>>,
+}
+
+impl UsbStorageDisk {
+    fn new(
+        name: String,
+        devnum: DeviceNumber,
+        transport: UsbStorageTransport,
+        block_count: u64,
+    ) -> Result<Arc<Self>, SystemError> {
+        let disk = Arc::new_cyclic(|self_ref| Self {
+            name,
+            devnum,
+            transport: SpinLock::new(transport),
+            block_count,
+            partitions: RwLock::new(Vec::new()),
+            inner: RwLock::new(InnerUsbStorageDisk {
+                kset: None,
+                parent_kobj: None,
+                inode: None,
+            }),
+            kobj_state: LockedKObjectState::new(None),
+            self_ref: self_ref.clone(),
+        });
+        disk.read_partitions()?;
+        return Ok(disk);
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 读取MBR分区表
+    fn read_partitions(self: &Arc<Self>) -> Result<(), SystemError> {
+        let mut mbr = [0u8; USB_STORAGE_BLOCK_SIZE];
+        self.read_at(0, 1, &mut mbr)?;
+        // 没有分区表的磁盘
+        if mbr[510] != 0x55 || mbr[511] != 0xaa {
+            return Ok(());
+        }
+
+        let mut partitions = self.partitions.write();
+        for i in 0..4 {
+            let entry = &mbr[446 + i * 16..446 + (i + 1) * 16];
+            // 分区类型为0表示分区表项未使用
+            if entry[4] == 0 {
+                continue;
+            }
+            let starting_lba = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+            let total_sectors = u32::from_le_bytes(entry[12..16].try_into().unwrap());
+            partitions.push(Partition::new(
+                starting_lba as u64,
+                starting_lba as u64,
+                total_sectors as u64,
+                Arc::downgrade(&(self.clone() as Arc<dyn BlockDevice>)),
+                i as u16,
+            ));
+        }
+        return Ok(());
+    }
+
+    /// 检查读写范围，按照单条命令的长度上限逐段执行
+    fn do_rw(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf_len: usize,
+        write: bool,
+        mut f: impl FnMut(&mut UsbStorageTransport, u32, usize, usize) -> Result<(), SystemError>,
+    ) -> Result<usize, SystemError> {
+        let len = count << USB_STORAGE_BLOCK_SIZE_LOG2;
+        if buf_len < len {
+            return Err(SystemError::EINVAL);
+        }
+        if (lba_id_start + count) as u64 > self.block_count {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut transport = self.transport.lock();
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(USB_STORAGE_MAX_BLOCKS);
+            let lba = lba_id_start + done;
+            trace_block_rq_issue(&self.name, lba, n, write);
+            let r = f(
+                &mut transport,
+                lba as u32,
+                n,
+                done << USB_STORAGE_BLOCK_SIZE_LOG2,
+            );
+            trace_block_rq_complete(&self.name, lba, n, write, &r);
+            r?;
+            done += n;
+        }
+        return Ok(len);
+    }
+}
+
+impl BlockDevice for UsbStorageDisk {
+    fn read_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        return self.do_rw(
+            lba_id_start,
+            count,
+            buf.len(),
+            false,
+            |t, lba, n, offset| t.read_blocks(lba, n, &mut buf[offset..]),
+        );
+    }
+
+    fn write_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        return self.do_rw(lba_id_start, count, buf.len(), true, |t, lba, n, offset| {
+            t.write_blocks(lba, n, &buf[offset..])
+        });
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        // 不支持这条命令的设备没有写缓存，忽略错误
+        self.transport
+            .lock()
+            .transport(
+                &[SYNCHRONIZE_CACHE, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                UsbData::None,
+            )
+            .ok();
+        return Ok(());
+    }
+
+    #[inline]
+    fn blk_size_log2(&self) -> u8 {
+        USB_STORAGE_BLOCK_SIZE_LOG2
+    }
+
+    #[inline]
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn device(&self) -> Arc<dyn Device> {
+        return self.self_ref.upgrade().unwrap();
+    }
+
+    #[inline]
+    fn block_size(&self) -> usize {
+        USB_STORAGE_BLOCK_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        return self.partitions.read().clone();
+    }
+}
+
+impl Device for UsbStorageDisk {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(self.name.clone(), self.devnum)
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        None
+    }
+
+    fn set_bus(&self, _bus: Option<Arc<dyn Bus>>) {}
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        Some(block_class())
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        None
+    }
+
+    fn set_driver(&self, _driver: Option<Weak<dyn Driver>>) {}
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        false
+    }
+
+    fn set_can_match(&self, _can_match: bool) {}
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn suspend(&self) -> Result<(), SystemError> {
+        return BlockDevice::sync(self);
+    }
+
+    fn shutdown(&self) {
+        if let Err(e) = BlockDevice::sync(self) {
+            kwarn!("usb-storage {}: failed to sync: {:?}", self.name, e);
+        }
+    }
+}
+
+impl KObject for UsbStorageDisk {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        Some(&DeviceKObjType)
+    }
+
+    fn set_kobj_type(&self, _ktype: Option<&'static dyn KObjType>) {}
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+/// devfs中USB磁盘或者磁盘分区的块设备文件
+#[derive(Debug)]
+pub struct UsbStorageInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: SpinLock<Metadata>,
+    disk: Arc<UsbStorageDisk>,
+    /// 文件在磁盘上的起始字节偏移量，整个磁盘为0
+    start: usize,
+    /// 文件的字节数
+    size: usize,
+}
+
+impl UsbStorageInode {
+    /// 创建块设备文件
+    ///
+    /// ## 参数
+    ///
+    /// - `disk`：文件所在的磁盘
+    /// - `part`：为None时表示整个磁盘，否则表示磁盘上的一个分区
+    fn new(disk: Arc<UsbStorageDisk>, part: Option<Arc<Partition>>) -> Arc<Self> {
+        let (lba_start, blocks, minor) = match part {
+            Some(part) => (
+                part.lba_start,
+                part.sectors_num,
+                disk.devnum.minor() + part.partno as usize + 1,
+            ),
+            None => (0, disk.block_count, disk.devnum.minor()),
+        };
+        let size = (blocks as usize) << USB_STORAGE_BLOCK_SIZE_LOG2;
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata: SpinLock::new(Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: size as i64,
+                blk_size: USB_STORAGE_BLOCK_SIZE,
+                blocks: blocks as usize,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::BlockDevice,
+                mode: ModeType::from_bits_truncate(0o666),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: make_rawdev(SCSI_DISK0_MAJOR, minor),
+            }),
+            disk,
+            start: (lba_start as usize) << USB_STORAGE_BLOCK_SIZE_LOG2,
+            size,
+        })
+    }
+
+    /// 把文件内的读写范围转换为磁盘上的字节偏移量，读写不能越过文件的末尾
+    fn disk_range(&self, offset: usize, len: usize) -> (usize, usize) {
+        let len = len.min(self.size.saturating_sub(offset));
+        return (self.start + offset, len);
+    }
+}
+
+impl DeviceINode for UsbStorageInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for UsbStorageInode {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.lock().clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.fs.lock().upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.metadata.lock();
+        inode.atime = metadata.atime;
+        inode.mtime = metadata.mtime;
+        inode.ctime = metadata.ctime;
+        inode.mode = metadata.mode;
+        inode.uid = metadata.uid;
+        inode.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        if let FilePrivateData::Unused = data {
+            let (offset, len) = self.disk_range(offset, len);
+            if len == 0 {
+                return Ok(0);
+            }
+            return self.disk.read_at_bytes(offset, len, buf);
+        }
+
+        return Err(SystemError::EINVAL);
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+
+        if let FilePrivateData::Unused = data {
+            let (offset, len) = self.disk_range(offset, len);
+            if len == 0 {
+                return Err(SystemError::ENOSPC);
+            }
+            return self.disk.write_at_bytes(offset, len, buf);
+        }
+
+        return Err(SystemError::EINVAL);
+    }
+}
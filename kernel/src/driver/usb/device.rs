@@ -0,0 +1,388 @@
+//! USB设备与接口在设备驱动模型中的表示
+//!
+//! 与Linux一致，USB设备本身不绑定驱动：设备的每个接口作为一个[`UsbInterface`]注册到USB总线上，
+//! 由接口驱动(类驱动)进行匹配。
+
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::{
+        device::{
+            bus::Bus, device_manager, driver::Driver, sys_devices_kset, Device, DeviceKObjType,
+            DeviceNumber, DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::kernfs::KernFSInode,
+    kerror, kinfo,
+    libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    syscall::SystemError,
+};
+
+use super::{
+    ch9::{
+        UsbConfiguration, UsbCtrlRequest, UsbDeviceDescriptor, UsbEndpointDescriptor,
+        UsbInterfaceDescriptor, UsbTransferType, USB_DIR_IN, USB_DIR_OUT, USB_ENDPOINT_HALT,
+        USB_RECIP_ENDPOINT, USB_REQ_CLEAR_FEATURE, USB_TYPE_STANDARD,
+    },
+    hcd::{UsbData, UsbHostController, UsbInterruptHandler, UsbSpeed},
+    usb_bus,
+};
+
+/// 所有已经加入设备驱动模型的USB接口
+///
+/// 总线只保存设备的弱引用，因此需要在这里持有接口
+static USB_INTERFACES: RwLock<Vec<Arc<UsbInterface>>> = RwLock::new(Vec::new());
+
+#[inline(always)]
+pub fn usb_device_manager() -> &'static UsbDeviceManager {
+    &UsbDeviceManager
+}
+
+/// 一个已经完成枚举、并设置了配置的USB设备
+#[derive(Debug)]
+pub struct UsbDevice {
+    hcd: Arc<dyn UsbHostController>,
+    /// 主机控制器为设备分配的槽位号
+    slot: u8,
+    /// 设备的名称，格式为"总线号-端口号"，与Linux一致
+    name: String,
+    speed: UsbSpeed,
+    descriptor: UsbDeviceDescriptor,
+    config: UsbConfiguration,
+}
+
+impl UsbDevice {
+    pub fn new(
+        hcd: Arc<dyn UsbHostController>,
+        slot: u8,
+        name: String,
+        speed: UsbSpeed,
+        descriptor: UsbDeviceDescriptor,
+        config: UsbConfiguration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            hcd,
+            slot,
+            name,
+            speed,
+            descriptor,
+            config,
+        })
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn speed(&self) -> UsbSpeed {
+        self.speed
+    }
+
+    #[inline]
+    pub fn descriptor(&self) -> &UsbDeviceDescriptor {
+        &self.descriptor
+    }
+
+    #[inline]
+    pub fn config(&self) -> &UsbConfiguration {
+        &self.config
+    }
+
+    /// 在端点0上发送一个控制请求
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/message.c#usb_control_msg
+    pub fn control_msg(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: UsbData,
+    ) -> Result<usize, SystemError> {
+        let req = UsbCtrlRequest::new(request_type, request, value, index, data.len() as u16);
+        return self.hcd.control_transfer(self.slot, &req, data);
+    }
+
+    /// 在批量端点上进行一次传输
+    pub fn bulk_msg(&self, endpoint: u8, data: UsbData) -> Result<usize, SystemError> {
+        return self.hcd.bulk_transfer(self.slot, endpoint, data);
+    }
+
+    /// 开始轮询中断IN端点，参见[`UsbHostController::interrupt_in_start`]
+    pub fn interrupt_in_start(
+        &self,
+        endpoint: u8,
+        len: usize,
+        handler: Arc<dyn UsbInterruptHandler>,
+    ) -> Result<(), SystemError> {
+        return self
+            .hcd
+            .interrupt_in_start(self.slot, endpoint, len, handler);
+    }
+
+    /// 清除端点的STALL状态
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/message.c#usb_clear_halt
+    pub fn clear_halt(&self, endpoint: u8) -> Result<(), SystemError> {
+        self.control_msg(
+            USB_DIR_OUT | USB_TYPE_STANDARD | USB_RECIP_ENDPOINT,
+            USB_REQ_CLEAR_FEATURE,
+            USB_ENDPOINT_HALT,
+            endpoint as u16,
+            UsbData::None,
+        )?;
+        return self.hcd.reset_endpoint(self.slot, endpoint);
+    }
+}
+
+/// USB设备的一个接口，是USB总线上进行驱动匹配的单位
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct UsbInterface {
+    usb_dev: Arc<UsbDevice>,
+    desc: UsbInterfaceDescriptor,
+    endpoints: Vec<UsbEndpointDescriptor>,
+    /// 接口的名称，格式为"总线号-端口号:配置值.接口号"，与Linux一致
+    name: String,
+    inner: RwLock<InnerUsbInterface>,
+    kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+struct InnerUsbInterface {
+    kset: Option<Arc<KSet>>,
+    parent_kobj: Option<Weak<dyn KObject>>,
+    bus: Option<Arc<dyn Bus>>,
+    inode: Option<Arc<KernFSInode>>,
+    driver: Option<Weak<dyn Driver>>,
+    can_match: bool,
+}
+
+impl UsbInterface {
+    fn new(
+        usb_dev: &Arc<UsbDevice>,
+        desc: UsbInterfaceDescriptor,
+        endpoints: Vec<UsbEndpointDescriptor>,
+    ) -> Arc<Self> {
+        let name = format!(
+            "{}:{}.{}",
+            usb_dev.name, usb_dev.config.desc.configuration_value, desc.interface_number
+        );
+        let r = Arc::new(Self {
+            usb_dev: usb_dev.clone(),
+            desc,
+            endpoints,
+            name,
+            inner: RwLock::new(InnerUsbInterface {
+                kset: None,
+                parent_kobj: None,
+                bus: None,
+                inode: None,
+                driver: None,
+                can_match: false,
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        });
+
+        device_manager().device_default_initialize(&(r.clone() as Arc<dyn Device>));
+        return r;
+    }
+
+    /// 接口所属的USB设备
+    #[inline]
+    pub fn usb_device(&self) -> &Arc<UsbDevice> {
+        &self.usb_dev
+    }
+
+    #[inline]
+    pub fn descriptor(&self) -> &UsbInterfaceDescriptor {
+        &self.desc
+    }
+
+    #[inline]
+    pub fn endpoints(&self) -> &[UsbEndpointDescriptor] {
+        &self.endpoints
+    }
+
+    /// 查找接口上第一个指定类型和方向的端点
+    pub fn find_endpoint(
+        &self,
+        transfer_type: UsbTransferType,
+        is_in: bool,
+    ) -> Option<UsbEndpointDescriptor> {
+        self.endpoints
+            .iter()
+            .find(|ep| ep.transfer_type() == transfer_type && ep.is_in() == is_in)
+            .copied()
+    }
+
+    /// 向接口发送一个类别请求
+    pub fn class_msg(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        data: UsbData,
+    ) -> Result<usize, SystemError> {
+        let dir = match data {
+            UsbData::In(_) => USB_DIR_IN,
+            _ => USB_DIR_OUT,
+        };
+        return self.usb_dev.control_msg(
+            dir | request_type,
+            request,
+            value,
+            self.desc.interface_number as u16,
+            data,
+        );
+    }
+}
+
+impl Device for UsbInterface {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Usb
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new("usb".to_string(), DeviceNumber::new(0))
+    }
+
+    fn bus(&self) -> Option<Arc<dyn Bus>> {
+        self.inner.read().bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Arc<dyn Bus>>) {
+        self.inner.write().bus = bus;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        self.inner.read().driver.clone()?.upgrade()
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner.write().driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner.read().can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner.write().can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+}
+
+impl KObject for UsbInterface {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().parent_kobj.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().parent_kobj = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        Some(&DeviceKObjType)
+    }
+
+    fn set_kobj_type(&self, _ktype: Option<&'static dyn KObjType>) {}
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn set_name(&self, _name: String) {}
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state.write() = state;
+    }
+}
+
+#[derive(Debug)]
+pub struct UsbDeviceManager;
+
+impl UsbDeviceManager {
+    /// 把一个已经设置了配置的USB设备的所有接口加入设备驱动模型
+    ///
+    /// 总线上已经注册的类驱动会立即探测匹配的接口
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/message.c#usb_set_configuration
+    pub fn device_add(&self, usb_dev: &Arc<UsbDevice>) -> Result<(), SystemError> {
+        let desc = usb_dev.descriptor();
+        kinfo!(
+            "usb {}: new {}Mbps device {:04x}:{:04x}, {} interface(s)",
+            usb_dev.name(),
+            usb_dev.speed().mbps(),
+            desc.vendor_id,
+            desc.product_id,
+            usb_dev.config().interfaces.len()
+        );
+
+        for info in usb_dev.config().interfaces.iter() {
+            let intf = UsbInterface::new(usb_dev, info.desc, info.endpoints.clone());
+            intf.set_parent(Some(Arc::downgrade(
+                &(sys_devices_kset() as Arc<dyn KObject>),
+            )));
+            intf.set_bus(Some(usb_bus() as Arc<dyn Bus>));
+
+            USB_INTERFACES.write().push(intf.clone());
+            if let Err(e) = device_manager().add_device(intf.clone() as Arc<dyn Device>) {
+                kerror!("usb: failed to add interface {}: {:?}", intf.name, e);
+                USB_INTERFACES.write().retain(|d| !Arc::ptr_eq(d, &intf));
+                return Err(e);
+            }
+        }
+        return Ok(());
+    }
+
+    /// 获取所有的USB接口
+    pub fn interfaces(&self) -> Vec<Arc<UsbInterface>> {
+        USB_INTERFACES.read().clone()
+    }
+}
@@ -0,0 +1,107 @@
+use alloc::sync::Arc;
+
+use crate::{
+    driver::base::device::{
+        bus::Bus,
+        driver::{driver_manager, Driver},
+    },
+    syscall::SystemError,
+};
+
+use super::{device::UsbInterface, usb_bus};
+
+/// 匹配任意值
+pub const USB_ANY_ID: u32 = !0;
+
+/// USB接口驱动的匹配表项
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/mod_devicetable.h#103
+#[derive(Debug, Clone, Copy)]
+pub struct UsbDeviceId {
+    /// 厂商id，为[`USB_ANY_ID`]时匹配任意厂商
+    pub vendor: u32,
+    /// 产品id，为[`USB_ANY_ID`]时匹配任意产品
+    pub product: u32,
+    /// 接口的类别代码: class << 16 | subclass << 8 | protocol
+    pub class: u32,
+    /// 比较类别代码时使用的掩码，为0时不比较类别代码
+    pub class_mask: u32,
+}
+
+impl UsbDeviceId {
+    /// 根据厂商id和产品id进行匹配
+    pub const fn new(vendor: u16, product: u16) -> Self {
+        Self {
+            vendor: vendor as u32,
+            product: product as u32,
+            class: 0,
+            class_mask: 0,
+        }
+    }
+
+    /// 根据接口的类别、子类别和协议进行匹配
+    pub const fn interface(class: u8, subclass: u8, protocol: u8) -> Self {
+        Self {
+            vendor: USB_ANY_ID,
+            product: USB_ANY_ID,
+            class: (class as u32) << 16 | (subclass as u32) << 8 | protocol as u32,
+            class_mask: 0xffffff,
+        }
+    }
+
+    pub fn matches(&self, intf: &UsbInterface) -> bool {
+        let dev = intf.usb_device().descriptor();
+        let desc = intf.descriptor();
+        let class = (desc.interface_class as u32) << 16
+            | (desc.interface_subclass as u32) << 8
+            | desc.interface_protocol as u32;
+        (self.vendor == USB_ANY_ID || self.vendor == dev.vendor_id as u32)
+            && (self.product == USB_ANY_ID || self.product == dev.product_id as u32)
+            && (self.class ^ class) & self.class_mask == 0
+    }
+}
+
+/// 实现该trait的驱动应挂载在USB总线上，同时应该实现Driver trait
+///
+/// ## 注意
+///
+/// 应当在所有实现这个trait的结构体上方，添加 `#[cast_to([sync] UsbDriver)]`，
+/// 否则运行时将报错“该对象不是UsbDriver”
+pub trait UsbDriver: Driver {
+    /// 驱动能够驱动的接口
+    fn usb_id_table(&self) -> &'static [UsbDeviceId];
+
+    /// 初始化接口
+    ///
+    /// ## 参数
+    ///
+    /// - `intf`：要初始化的接口
+    /// - `id`：匹配表中与接口匹配的表项
+    fn probe(&self, intf: &Arc<UsbInterface>, id: &UsbDeviceId) -> Result<(), SystemError>;
+
+    fn disconnect(&self, _intf: &Arc<UsbInterface>) {}
+}
+
+#[inline(always)]
+pub fn usb_driver_manager() -> &'static UsbDriverManager {
+    &UsbDriverManager
+}
+
+#[derive(Debug)]
+pub struct UsbDriverManager;
+
+impl UsbDriverManager {
+    /// 注册USB接口驱动，并尝试绑定总线上已有的接口
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/driver.c#usb_register_driver
+    pub fn register(&self, driver: Arc<dyn UsbDriver>) -> Result<(), SystemError> {
+        driver.set_bus(Some(usb_bus() as Arc<dyn Bus>));
+        return driver_manager().register(driver as Arc<dyn Driver>);
+    }
+
+    /// 卸载USB接口驱动
+    #[allow(dead_code)]
+    pub fn unregister(&self, driver: &Arc<dyn UsbDriver>) {
+        driver_manager().unregister(&(driver.clone() as Arc<dyn Driver>));
+    }
+}
@@ -0,0 +1,126 @@
+//! USB主机控制器驱动(HCD)的抽象
+//!
+//! USB核心和类驱动只通过[`UsbHostController`]发起传输，不关心控制器的具体类型
+
+use alloc::sync::Arc;
+use core::fmt::Debug;
+
+use crate::syscall::SystemError;
+
+use super::ch9::UsbCtrlRequest;
+
+/// USB设备的速度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsbSpeed {
+    /// 1.5Mbps
+    Low,
+    /// 12Mbps
+    Full,
+    /// 480Mbps
+    High,
+    /// 5Gbps及以上
+    Super,
+}
+
+impl UsbSpeed {
+    /// 与Linux的/sys/bus/usb/devices/*/speed一致，单位为Mbps
+    pub fn mbps(&self) -> &'static str {
+        match self {
+            UsbSpeed::Low => "1.5",
+            UsbSpeed::Full => "12",
+            UsbSpeed::High => "480",
+            UsbSpeed::Super => "5000",
+        }
+    }
+}
+
+/// 一次传输的数据阶段
+#[derive(Debug)]
+pub enum UsbData<'a> {
+    /// 没有数据阶段
+    None,
+    /// 从设备读取数据
+    In(&'a mut [u8]),
+    /// 向设备写入数据
+    Out(&'a [u8]),
+}
+
+impl<'a> UsbData<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            UsbData::None => 0,
+            UsbData::In(buf) => buf.len(),
+            UsbData::Out(buf) => buf.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 中断IN端点收到数据时的回调
+pub trait UsbInterruptHandler: Send + Sync + Debug {
+    /// 端点完成了一次传输
+    ///
+    /// 这个函数可能在中断上下文中、持有主机控制器的锁时被调用，因此不能睡眠，也不能向同一个控制器发起传输
+    ///
+    /// ## 参数
+    ///
+    /// - `data`：设备发送的数据
+    fn complete(&self, data: &[u8]);
+}
+
+/// 主机控制器驱动需要实现的接口
+///
+/// 设备由控制器在枚举时分配的槽位号(slot)标识，端点由端点地址(包含方向位)标识。
+/// 除了[`UsbHostController::interrupt_in_start`]以外，所有的传输都是同步的：函数在传输完成或超时之后才返回
+pub trait UsbHostController: Send + Sync + Debug {
+    /// 在端点0上进行一次控制传输
+    ///
+    /// ## 返回值
+    ///
+    /// 数据阶段实际传输的字节数
+    ///
+    /// ## 错误
+    ///
+    /// - `EPIPE`：设备以STALL应答了请求
+    /// - `ETIMEDOUT`：传输超时
+    /// - `EIO`：其他传输错误
+    fn control_transfer(
+        &self,
+        slot: u8,
+        req: &UsbCtrlRequest,
+        data: UsbData,
+    ) -> Result<usize, SystemError>;
+
+    /// 在批量端点上进行一次传输
+    ///
+    /// ## 返回值
+    ///
+    /// 实际传输的字节数。设备发送短包时，IN传输的字节数可能小于缓冲区的长度
+    ///
+    /// ## 错误
+    ///
+    /// 与[`UsbHostController::control_transfer`]相同。端点STALL之后，需要调用
+    /// [`UsbHostController::reset_endpoint`]才能继续使用
+    fn bulk_transfer(&self, slot: u8, endpoint: u8, data: UsbData) -> Result<usize, SystemError>;
+
+    /// 开始周期性地轮询一个中断IN端点，每次收到数据时调用`handler`
+    ///
+    /// ## 参数
+    ///
+    /// - `len`：每次传输的最大长度，通常是端点的最大包长
+    fn interrupt_in_start(
+        &self,
+        slot: u8,
+        endpoint: u8,
+        len: usize,
+        handler: Arc<dyn UsbInterruptHandler>,
+    ) -> Result<(), SystemError>;
+
+    /// 清除端点在控制器一侧的STALL状态，并丢弃端点上未完成的传输
+    ///
+    /// 设备一侧的STALL状态需要由调用者发送CLEAR_FEATURE(ENDPOINT_HALT)请求来清除
+    fn reset_endpoint(&self, slot: u8, endpoint: u8) -> Result<(), SystemError>;
+}
@@ -0,0 +1,49 @@
+//! USB子系统
+//!
+//! - [`xhci`]：xHCI主机控制器驱动，负责枚举根集线器端口上的设备
+//! - [`device`]：设备和接口。设备的每个接口作为一个[`device::UsbInterface`]注册到USB总线上
+//! - [`driver`]：接口驱动的匹配与注册
+//! - [`class`]：各类设备的接口驱动
+//!
+//! 目前不支持外接集线器和热插拔，设备只在控制器初始化时被枚举
+
+use alloc::sync::Arc;
+
+use crate::{
+    driver::base::device::bus::{bus_register, Bus},
+    syscall::SystemError,
+};
+
+use self::subsys::UsbBus;
+
+pub mod ch9;
+pub mod class;
+pub mod device;
+pub mod driver;
+pub mod hcd;
+pub mod subsys;
+pub mod xhci;
+
+static mut USB_BUS: Option<Arc<UsbBus>> = None;
+
+#[inline(always)]
+pub fn usb_bus() -> Arc<UsbBus> {
+    unsafe { USB_BUS.clone().unwrap() }
+}
+
+/// 注册USB总线
+fn usb_bus_init() -> Result<(), SystemError> {
+    let bus = UsbBus::new();
+    unsafe { USB_BUS = Some(bus.clone()) };
+    return bus_register(bus as Arc<dyn Bus>);
+}
+
+/// 初始化USB子系统
+///
+/// 先注册总线和接口驱动，再注册xHCI驱动。xHCI控制器被PCI总线探测时枚举设备，
+/// 设备的接口在加入总线时就能绑定到接口驱动
+pub fn usb_init() -> Result<(), SystemError> {
+    usb_bus_init()?;
+    class::usb_class_init()?;
+    return xhci::xhci_init();
+}
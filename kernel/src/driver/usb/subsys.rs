@@ -0,0 +1,190 @@
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+};
+use intertrait::cast::CastArc;
+
+use crate::{
+    driver::base::{
+        device::{bus::Bus, driver::Driver, Device},
+        kobject::KObject,
+        subsys::SubSysPrivate,
+    },
+    filesystem::{
+        sysfs::{file::sysfs_emit_str, Attribute, AttributeGroup, SysFSOpsSupport},
+        vfs::syscall::ModeType,
+    },
+    syscall::SystemError,
+};
+
+use super::{device::UsbInterface, driver::UsbDriver};
+
+#[derive(Debug)]
+pub struct UsbBus {
+    private: SubSysPrivate,
+}
+
+impl UsbBus {
+    pub fn new() -> Arc<Self> {
+        let w: Weak<Self> = Weak::new();
+        let private = SubSysPrivate::new("usb".to_string(), w, &[]);
+        let bus = Arc::new(Self { private });
+        bus.subsystem()
+            .set_bus(Arc::downgrade(&(bus.clone() as Arc<dyn Bus>)));
+
+        return bus;
+    }
+
+    fn to_usb_interface(device: &Arc<dyn Device>) -> Result<Arc<UsbInterface>, SystemError> {
+        return device
+            .clone()
+            .arc_any()
+            .downcast::<UsbInterface>()
+            .map_err(|_| {
+                kerror!(
+                    "UsbBus: device is not a UsbInterface. Device: '{:?}'",
+                    device.name()
+                );
+                SystemError::EINVAL
+            });
+    }
+
+    fn to_usb_driver(driver: Arc<dyn Driver>) -> Result<Arc<dyn UsbDriver>, SystemError> {
+        return driver.cast::<dyn UsbDriver>().map_err(|drv| {
+            kerror!(
+                "UsbBus: driver is not a UsbDriver. Driver: '{:?}'",
+                drv.name()
+            );
+            SystemError::EINVAL
+        });
+    }
+}
+
+impl Bus for UsbBus {
+    fn name(&self) -> String {
+        return "usb".to_string();
+    }
+
+    fn dev_name(&self) -> String {
+        return self.name();
+    }
+
+    fn dev_groups(&self) -> &'static [&'static dyn AttributeGroup] {
+        return &[&UsbInterfaceAttrGroup];
+    }
+
+    fn subsystem(&self) -> &SubSysPrivate {
+        return &self.private;
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/driver.c#usb_probe_interface
+    fn probe(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        let drv = Self::to_usb_driver(device.driver().ok_or(SystemError::EINVAL)?)?;
+        let intf = Self::to_usb_interface(device)?;
+        let id = drv
+            .usb_id_table()
+            .iter()
+            .find(|id| id.matches(&intf))
+            .ok_or(SystemError::ENODEV)?;
+        return drv.probe(&intf, id);
+    }
+
+    fn remove(&self, device: &Arc<dyn Device>) -> Result<(), SystemError> {
+        if let Some(drv) = device.driver() {
+            Self::to_usb_driver(drv)?.disconnect(&Self::to_usb_interface(device)?);
+        }
+        return Ok(());
+    }
+
+    /// 根据驱动的匹配表，判断驱动能否驱动接口
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/driver.c#usb_device_match
+    fn match_device(
+        &self,
+        device: &Arc<dyn Device>,
+        driver: &Arc<dyn Driver>,
+    ) -> Result<bool, SystemError> {
+        let drv = Self::to_usb_driver(driver.clone())?;
+        let intf = Self::to_usb_interface(device)?;
+        return Ok(drv.usb_id_table().iter().any(|id| id.matches(&intf)));
+    }
+}
+
+#[derive(Debug)]
+pub struct UsbInterfaceAttrGroup;
+
+impl AttributeGroup for UsbInterfaceAttrGroup {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        return &[
+            &UsbInterfaceAttr::IdVendor,
+            &UsbInterfaceAttr::IdProduct,
+            &UsbInterfaceAttr::Speed,
+            &UsbInterfaceAttr::InterfaceClass,
+            &UsbInterfaceAttr::InterfaceSubClass,
+            &UsbInterfaceAttr::InterfaceProtocol,
+        ];
+    }
+
+    fn is_visible(&self, _kobj: Arc<dyn KObject>, attr: &dyn Attribute) -> Option<ModeType> {
+        return Some(attr.mode());
+    }
+}
+
+/// USB接口文件夹下的只读属性文件
+///
+/// Linux中厂商id、产品id和速度位于USB设备的文件夹下，这里为了方便，放在了接口的文件夹下
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/sysfs.c
+#[derive(Debug)]
+enum UsbInterfaceAttr {
+    IdVendor,
+    IdProduct,
+    Speed,
+    InterfaceClass,
+    InterfaceSubClass,
+    InterfaceProtocol,
+}
+
+impl Attribute for UsbInterfaceAttr {
+    fn name(&self) -> &str {
+        match self {
+            UsbInterfaceAttr::IdVendor => "idVendor",
+            UsbInterfaceAttr::IdProduct => "idProduct",
+            UsbInterfaceAttr::Speed => "speed",
+            UsbInterfaceAttr::InterfaceClass => "bInterfaceClass",
+            UsbInterfaceAttr::InterfaceSubClass => "bInterfaceSubClass",
+            UsbInterfaceAttr::InterfaceProtocol => "bInterfaceProtocol",
+        }
+    }
+
+    fn mode(&self) -> ModeType {
+        // 0o444
+        return ModeType::S_IRUGO;
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let intf = kobj
+            .arc_any()
+            .downcast::<UsbInterface>()
+            .map_err(|_| SystemError::EINVAL)?;
+        let dev = intf.usb_device();
+        let desc = intf.descriptor();
+        let s = match self {
+            UsbInterfaceAttr::IdVendor => format!("{:04x}\n", dev.descriptor().vendor_id),
+            UsbInterfaceAttr::IdProduct => format!("{:04x}\n", dev.descriptor().product_id),
+            UsbInterfaceAttr::Speed => format!("{}\n", dev.speed().mbps()),
+            UsbInterfaceAttr::InterfaceClass => format!("{:02x}\n", desc.interface_class),
+            UsbInterfaceAttr::InterfaceSubClass => format!("{:02x}\n", desc.interface_subclass),
+            UsbInterfaceAttr::InterfaceProtocol => format!("{:02x}\n", desc.interface_protocol),
+        };
+        return sysfs_emit_str(buf, &s);
+    }
+}
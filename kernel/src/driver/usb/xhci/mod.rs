@@ -0,0 +1,1182 @@
+//! xHCI主机控制器驱动
+//!
+//! 驱动在控制器初始化时枚举根集线器端口上已经连接的设备：复位端口、分配槽位、设置地址、
+//! 读取描述符、配置端点，然后把设备交给USB核心，由USB总线为设备的接口匹配驱动。
+//!
+//! 所有的命令和控制/批量传输都是同步的：提交TRB之后轮询事件环，直到对应的事件出现。
+//! 中断处理函数(或者无法使用MSI时的轮询线程)同样会处理事件环，并负责中断IN端点的回调。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci.c
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    arch::interrupt::TrapFrame,
+    driver::{
+        base::device::driver::Driver,
+        pci::{
+            device::PciDevice,
+            driver::{pci_driver_manager, PciDriver},
+            pci::BusDeviceFunction,
+            pci_irq::IRQ,
+        },
+    },
+    exception::irqdesc::{IrqHandleFlags, IrqHandler, IrqNumber, IrqReturn},
+    kdebug, kerror, kinfo, kwarn,
+    libs::{spinlock::SpinLock, volatile::Mmio},
+    mm::VirtAddr,
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    syscall::SystemError,
+    time::{hrtimer::ktime_get, sleep::nanosleep, TimeSpec, NSEC_PER_MSEC},
+};
+
+use self::{
+    regs::*,
+    ring::{
+        DmaBuffer, Trb, XhciEventRing, XhciRing, COMP_SHORT_PACKET, COMP_STALL_ERROR, COMP_SUCCESS,
+        TRB_ADDRESS_DEVICE, TRB_CONFIGURE_EP, TRB_DATA, TRB_DIR_IN, TRB_DISABLE_SLOT,
+        TRB_ENABLE_SLOT, TRB_EP_ID_SHIFT, TRB_EVALUATE_CONTEXT, TRB_IDT, TRB_IOC, TRB_ISP,
+        TRB_NORMAL, TRB_PORT_STATUS_EVENT, TRB_RESET_EP, TRB_SETUP, TRB_SET_TR_DEQ,
+        TRB_SLOT_ID_SHIFT, TRB_STATUS, TRB_TRANSFER_EVENT, TRB_TRT_IN, TRB_TRT_OUT, TRB_TYPE_SHIFT,
+    },
+    xhci_driver::XhciDriver,
+};
+
+use super::{
+    ch9::{
+        UsbConfigDescriptor, UsbConfiguration, UsbCtrlRequest, UsbDeviceDescriptor,
+        UsbEndpointDescriptor, UsbTransferType, USB_CLASS_HUB, USB_DIR_OUT, USB_DT_CONFIG,
+        USB_DT_CONFIG_SIZE, USB_DT_DEVICE, USB_DT_DEVICE_SIZE, USB_RECIP_DEVICE,
+        USB_REQ_SET_CONFIGURATION, USB_TYPE_STANDARD,
+    },
+    device::{usb_device_manager, UsbDevice},
+    hcd::{UsbData, UsbHostController, UsbInterruptHandler, UsbSpeed},
+};
+
+pub mod regs;
+pub mod ring;
+pub mod xhci_driver;
+
+/// 已经初始化的控制器
+static XHCI_CONTROLLERS: SpinLock<Vec<Arc<Xhci>>> = SpinLock::new(Vec::new());
+/// 是否已经启动了轮询线程
+static XHCI_POLLING: AtomicBool = AtomicBool::new(false);
+
+/// 等待命令完成的超时时间(ms)
+const XHCI_CMD_TIMEOUT_MS: u64 = 1000;
+/// 等待传输完成的超时时间(ms)
+const XHCI_TRANSFER_TIMEOUT_MS: u64 = 5000;
+/// 无法使用MSI时，轮询线程的轮询周期(ms)
+const XHCI_POLL_INTERVAL_MS: i64 = 10;
+/// 一次控制/批量传输的最大长度。数据缓冲区不超过64K时不会跨越64K边界，只需要一个TRB
+pub const XHCI_MAX_TRANSFER: usize = 64 * 1024;
+/// 中断调节间隔，单位为250ns，与Linux默认的40us一致
+const XHCI_IMOD_INTERVAL: u32 = 160;
+
+/// 端点上下文中的端点类型
+const EP_TYPE_BULK_OUT: u32 = 2;
+const EP_TYPE_INT_OUT: u32 = 3;
+const EP_TYPE_CONTROL: u32 = 4;
+const EP_TYPE_BULK_IN: u32 = 6;
+const EP_TYPE_INT_IN: u32 = 7;
+/// 端点上下文中的错误重试次数
+const EP_CERR: u32 = 3;
+
+/// 注册xHCI驱动，由PCI总线探测所有的xHCI控制器
+pub fn xhci_init() -> Result<(), SystemError> {
+    let driver = XhciDriver::new();
+    pci_driver_manager().register(driver.clone() as Arc<dyn PciDriver>)?;
+    if driver.devices().is_empty() {
+        return Err(SystemError::ENODEV);
+    }
+    return Ok(());
+}
+
+/// 初始化一个xHCI控制器，并枚举其上已经连接的设备
+fn xhci_probe(dev: &Arc<PciDevice>) -> Result<(), SystemError> {
+    let busnum = XHCI_CONTROLLERS.lock().len() + 1;
+    let xhci = Xhci::new(dev, busnum)?;
+    XHCI_CONTROLLERS.lock().push(xhci.clone());
+
+    if let Err(e) = xhci_irq_init(dev, &xhci) {
+        kwarn!(
+            "xhci {}: failed to set up MSI, using polling: {:?}",
+            xhci.bdf,
+            e
+        );
+        xhci_start_polling();
+    }
+
+    xhci.enumerate();
+    return Ok(());
+}
+
+/// 驱动与控制器解除绑定时，停止控制器并释放中断
+fn xhci_remove(dev: &Arc<PciDevice>) {
+    let bdf = dev.bus_device_function();
+    let mut controllers = XHCI_CONTROLLERS.lock();
+    if let Some(xhci) = controllers.iter().find(|x| x.bdf == bdf) {
+        xhci.op().usbcmd.clear_bits(CMD_RUN | CMD_INTE);
+    }
+    controllers.retain(|x| x.bdf != bdf);
+    drop(controllers);
+    dev.free_irq_vectors();
+}
+
+/// xHCI控制器的MSI中断处理函数
+#[derive(Debug)]
+struct XhciIrqHandler {
+    xhci: Arc<Xhci>,
+}
+
+impl IrqHandler for XhciIrqHandler {
+    fn handle(&self, _irq: IrqNumber, _trap_frame: &mut TrapFrame) -> IrqReturn {
+        let op = self.xhci.op();
+        let status = op.usbsts.read();
+        if status & STS_EINT == 0 {
+            return IrqReturn::NotHandled;
+        }
+        if status & STS_FATAL != 0 {
+            kerror!("xhci {}: host system error", self.xhci.bdf);
+        }
+        // USBSTS和IMAN中的中断标志都是写1清零的
+        op.usbsts.write(STS_EINT);
+        self.xhci.intr().iman.write(IMAN_IE | IMAN_IP);
+        self.xhci.poll();
+        return IrqReturn::Handled;
+    }
+}
+
+/// 为控制器申请一个MSI/MSI-X中断向量
+fn xhci_irq_init(dev: &Arc<PciDevice>, xhci: &Arc<Xhci>) -> Result<(), SystemError> {
+    dev.alloc_irq_vectors(1, 1, IRQ::PCI_IRQ_MSI | IRQ::PCI_IRQ_MSIX)?;
+    let handler = Arc::new(XhciIrqHandler { xhci: xhci.clone() });
+    if let Err(e) = dev.request_irq(
+        0,
+        &format!("xhci{}", xhci.busnum),
+        handler,
+        IrqHandleFlags::empty(),
+        0,
+    ) {
+        dev.free_irq_vectors();
+        return Err(e);
+    }
+    return Ok(());
+}
+
+/// 启动轮询线程，定期处理所有控制器的事件环
+fn xhci_start_polling() {
+    if XHCI_POLLING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if KernelThreadMechanism::create_and_run(
+        KernelThreadClosure::EmptyClosure((Box::new(xhci_poll_thread), ())),
+        String::from("xhci_poll"),
+    )
+    .is_none()
+    {
+        kerror!("xhci: failed to create the polling thread");
+        XHCI_POLLING.store(false, Ordering::SeqCst);
+    }
+}
+
+fn xhci_poll_thread() -> i32 {
+    let interval = TimeSpec::new(0, XHCI_POLL_INTERVAL_MS * NSEC_PER_MSEC as i64);
+    loop {
+        let controllers = XHCI_CONTROLLERS.lock().clone();
+        for xhci in controllers.iter() {
+            xhci.poll();
+        }
+        nanosleep(interval).ok();
+    }
+}
+
+/// 忙等待，直到`cond`返回true
+fn wait_until(timeout_ms: u64, mut cond: impl FnMut() -> bool) -> Result<(), SystemError> {
+    let deadline = ktime_get() + timeout_ms * NSEC_PER_MSEC as u64;
+    while !cond() {
+        if ktime_get() > deadline {
+            return Err(SystemError::ETIMEDOUT);
+        }
+        spin_loop();
+    }
+    return Ok(());
+}
+
+/// 忙等待一段时间
+fn delay_ms(ms: u64) {
+    wait_until(ms, || false).ok();
+}
+
+/// 根据端点地址计算端点上下文的下标(DCI)
+///
+/// 端点0为1，其余端点为 端点号*2 + 方向(IN为1)
+#[inline]
+fn endpoint_dci(endpoint: u8) -> u8 {
+    let num = endpoint & 0xf;
+    if num == 0 {
+        return 1;
+    }
+    return num * 2 + (endpoint >> 7);
+}
+
+/// 把事件的完成码转换为错误码
+fn completion_result(event: &Trb) -> Result<(), SystemError> {
+    match event.completion_code() {
+        COMP_SUCCESS | COMP_SHORT_PACKET => Ok(()),
+        COMP_STALL_ERROR => Err(SystemError::EPIPE),
+        code => {
+            kdebug!("xhci: transfer failed, completion code {}", code);
+            Err(SystemError::EIO)
+        }
+    }
+}
+
+/// 正在轮询的中断IN端点
+#[derive(Debug)]
+struct XhciInterruptPipe {
+    buf: DmaBuffer,
+    len: usize,
+    handler: Arc<dyn UsbInterruptHandler>,
+}
+
+/// 一个已经分配的设备槽位
+#[derive(Debug)]
+struct XhciSlot {
+    port: u8,
+    speed: UsbSpeed,
+    /// 设备上下文，由控制器维护
+    dev_ctx: DmaBuffer,
+    /// 输入上下文，用于向控制器传递命令的参数
+    input_ctx: DmaBuffer,
+    /// 每个端点的传输环，key为DCI
+    rings: BTreeMap<u8, XhciRing>,
+    /// 正在轮询的中断IN端点，key为DCI
+    interrupt_pipes: BTreeMap<u8, XhciInterruptPipe>,
+}
+
+#[derive(Debug)]
+struct InnerXhci {
+    /// 设备上下文基地址数组
+    dcbaa: DmaBuffer,
+    /// 暂存区缓冲区数组，以及各个缓冲区。只需要在控制器运行期间保持有效
+    _scratchpad: Vec<DmaBuffer>,
+    cmd_ring: XhciRing,
+    event_ring: XhciEventRing,
+    slots: BTreeMap<u8, XhciSlot>,
+    /// 已经完成、但还没有被等待者取走的TRB，key为TRB的地址，value为对应的事件
+    completed: BTreeMap<u64, Trb>,
+}
+
+/// 一个xHCI控制器
+#[derive(Debug)]
+pub struct Xhci {
+    bdf: BusDeviceFunction,
+    /// 总线号，从1开始，用于给设备命名
+    busnum: usize,
+    /// 能力寄存器的地址
+    cap: VirtAddr,
+    /// 操作寄存器的地址
+    op: VirtAddr,
+    /// 第0个中断器的寄存器的地址
+    intr: VirtAddr,
+    /// 门铃寄存器数组的地址
+    db: VirtAddr,
+    max_slots: u8,
+    max_ports: u8,
+    /// 上下文结构体的大小(32或64字节)
+    ctx_size: usize,
+    inner: SpinLock<InnerXhci>,
+    self_ref: Weak<Xhci>,
+}
+
+impl Xhci {
+    /// 映射控制器的寄存器，从BIOS接管控制器，复位并启动控制器
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci.c#xhci_init
+    fn new(dev: &Arc<PciDevice>, busnum: usize) -> Result<Arc<Self>, SystemError> {
+        dev.enable_device();
+        dev.set_master();
+        let bdf = dev.bus_device_function();
+        let base = dev
+            .with_structure_mut(|device| -> Result<VirtAddr, SystemError> {
+                let standard_device = device.as_standard_device_mut().ok_or(SystemError::ENODEV)?;
+                standard_device.bar_ioremap();
+                return standard_device
+                    .bar()
+                    .ok_or(SystemError::EACCES)?
+                    .get_bar(0)
+                    .or(Err(SystemError::EACCES))?
+                    .virtual_address()
+                    .ok_or(SystemError::EACCES);
+            })
+            .ok_or(SystemError::ENODEV)??;
+
+        let cap = unsafe { &*(base.data() as *const XhciCapRegs) };
+        let caplength = (cap.caplength_hciversion.read() & 0xff) as usize;
+        let hcsparams1 = cap.hcsparams1.read();
+        let hcsparams2 = cap.hcsparams2.read();
+        let max_scratchpad = ((hcsparams2 >> HCS_MAX_SCRATCHPAD_HI_SHIFT) & 0x1f) << 5
+            | (hcsparams2 >> HCS_MAX_SCRATCHPAD_LO_SHIFT) & 0x1f;
+        let ctx_size = if cap.hccparams1.read() & HCC_64BYTE_CONTEXT != 0 {
+            64
+        } else {
+            32
+        };
+        let rtsoff = (cap.rtsoff.read() & !0x1f) as usize;
+        let dboff = (cap.dboff.read() & !0x3) as usize;
+
+        let dcbaa = DmaBuffer::new(&bdf, 256 * core::mem::size_of::<u64>())?;
+        let mut scratchpad = Vec::new();
+        if max_scratchpad > 0 {
+            let array = DmaBuffer::new(&bdf, max_scratchpad as usize * 8)?;
+            for i in 0..max_scratchpad as usize {
+                let buf = DmaBuffer::new(&bdf, 4096)?;
+                array.write64(i * 8, buf.dma());
+                scratchpad.push(buf);
+            }
+            dcbaa.write64(0, array.dma());
+            scratchpad.push(array);
+        }
+
+        let cmd_ring = XhciRing::new(&bdf)?;
+        let event_ring = XhciEventRing::new(&bdf)?;
+        let xhci = Arc::new_cyclic(|self_ref| Self {
+            bdf,
+            busnum,
+            cap: base,
+            op: base + caplength,
+            intr: base + rtsoff + XHCI_INTR0_OFFSET,
+            db: base + dboff,
+            max_slots: (hcsparams1 & HCS_MAX_SLOTS_MASK) as u8,
+            max_ports: (hcsparams1 >> HCS_MAX_PORTS_SHIFT) as u8,
+            ctx_size,
+            inner: SpinLock::new(InnerXhci {
+                dcbaa,
+                _scratchpad: scratchpad,
+                cmd_ring,
+                event_ring,
+                slots: BTreeMap::new(),
+                completed: BTreeMap::new(),
+            }),
+            self_ref: self_ref.clone(),
+        });
+
+        xhci.bios_handoff();
+        xhci.reset()?;
+        xhci.start()?;
+        kinfo!(
+            "xhci {}: usb bus {}, version {:x}, {} slots, {} ports",
+            bdf,
+            busnum,
+            cap.caplength_hciversion.read() >> 16,
+            xhci.max_slots,
+            xhci.max_ports
+        );
+        return Ok(xhci);
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    fn op(&self) -> &mut XhciOpRegs {
+        unsafe { &mut *(self.op.data() as *mut XhciOpRegs) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    fn intr(&self) -> &mut XhciIntrRegs {
+        unsafe { &mut *(self.intr.data() as *mut XhciIntrRegs) }
+    }
+
+    /// 端口的寄存器，端口号从1开始
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    fn port(&self, port: u8) -> &mut XhciPortRegs {
+        let addr = self.op + XHCI_PORT_REGS_OFFSET + (port as usize - 1) * 0x10;
+        unsafe { &mut *(addr.data() as *mut XhciPortRegs) }
+    }
+
+    /// 敲响门铃：slot为0时通知控制器处理命令环，否则通知控制器处理设备的第target个端点
+    #[inline]
+    fn ring_doorbell(&self, slot: u8, target: u8) {
+        let addr = self.db + slot as usize * 4;
+        unsafe { (*(addr.data() as *mut Mmio<u32>)).write(target as u32) };
+    }
+
+    /// 如果BIOS正在使用控制器(例如模拟PS/2键盘)，请求BIOS交出控制器的所有权
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/pci-quirks.c#quirk_usb_handoff_xhci
+    fn bios_handoff(&self) {
+        let cap = unsafe { &*(self.cap.data() as *const XhciCapRegs) };
+        let mut offset = ((cap.hccparams1.read() >> HCC_EXT_CAPS_SHIFT) as usize) << 2;
+        while offset != 0 {
+            let legsup = unsafe { &mut *((self.cap + offset).data() as *mut Mmio<u32>) };
+            let val = legsup.read();
+            if val & 0xff == XHCI_EXT_CAPS_LEGACY {
+                if val & XHCI_HC_BIOS_OWNED != 0 {
+                    legsup.write(val | XHCI_HC_OS_OWNED);
+                    if wait_until(1000, || legsup.read() & XHCI_HC_BIOS_OWNED == 0).is_err() {
+                        kwarn!("xhci {}: BIOS handoff failed, taking over", self.bdf);
+                        legsup.clear_bits(XHCI_HC_BIOS_OWNED);
+                    }
+                }
+                let ctlsts = unsafe { &mut *((self.cap + offset + 4).data() as *mut Mmio<u32>) };
+                ctlsts.modify(|v| (v & XHCI_LEGACY_DISABLE_SMI) | XHCI_LEGACY_SMI_EVENTS);
+                return;
+            }
+            let next = ((val >> 8) & 0xff) as usize;
+            if next == 0 {
+                return;
+            }
+            offset += next << 2;
+        }
+    }
+
+    /// 停止并复位控制器
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci.c#xhci_reset
+    fn reset(&self) -> Result<(), SystemError> {
+        let op = self.op();
+        wait_until(1000, || op.usbsts.read() & STS_CNR == 0)?;
+        op.usbcmd.clear_bits(CMD_RUN);
+        wait_until(1000, || op.usbsts.read() & STS_HALT != 0)?;
+        op.usbcmd.set_bits(CMD_RESET);
+        return wait_until(1000, || {
+            op.usbcmd.read() & CMD_RESET == 0 && op.usbsts.read() & STS_CNR == 0
+        });
+    }
+
+    /// 设置数据结构的地址，启动控制器，并为所有端口供电
+    fn start(&self) -> Result<(), SystemError> {
+        let inner = self.inner.lock_irqsave();
+        let op = self.op();
+        op.config
+            .modify(|v| (v & !HCS_MAX_SLOTS_MASK) | self.max_slots as u32);
+        let dcbaap = inner.dcbaa.dma();
+        op.dcbaap_lo.write(dcbaap as u32);
+        op.dcbaap_hi.write((dcbaap >> 32) as u32);
+        let crcr = inner.cmd_ring.dma() | CMD_RING_RCS as u64;
+        op.crcr_lo.write(crcr as u32);
+        op.crcr_hi.write((crcr >> 32) as u32);
+
+        let intr = self.intr();
+        intr.erstsz.write(1);
+        let erdp = inner.event_ring.dequeue_dma();
+        intr.erdp_lo.write(erdp as u32);
+        intr.erdp_hi.write((erdp >> 32) as u32);
+        // 写入ERSTBA时控制器开始使用事件环，因此需要在ERSTSZ和ERDP之后写入
+        let erstba = inner.event_ring.erst_dma();
+        intr.erstba_lo.write(erstba as u32);
+        intr.erstba_hi.write((erstba >> 32) as u32);
+        intr.imod.write(XHCI_IMOD_INTERVAL);
+        intr.iman.write(IMAN_IE | IMAN_IP);
+        drop(inner);
+
+        op.usbcmd.set_bits(CMD_RUN | CMD_INTE);
+        wait_until(1000, || op.usbsts.read() & STS_HALT == 0)?;
+
+        let mut powered = false;
+        for port in 1..=self.max_ports {
+            let regs = self.port(port);
+            let portsc = regs.portsc.read();
+            if portsc & PORT_POWER == 0 {
+                regs.portsc
+                    .write(port_state_to_neutral(portsc) | PORT_POWER);
+                powered = true;
+            }
+        }
+        // 等待端口上电，以及设备连接的消抖
+        delay_ms(if powered { 120 } else { 100 });
+        return Ok(());
+    }
+
+    /// 处理事件环上的所有事件
+    ///
+    /// 命令和传输的完成事件被保存到`completed`中，由等待者取走；中断IN端点的事件直接调用回调函数
+    fn process_events(&self, inner: &mut InnerXhci) {
+        let mut handled = false;
+        while let Some(event) = inner.event_ring.pop() {
+            handled = true;
+            match event.trb_type() {
+                TRB_TRANSFER_EVENT => {
+                    if !self.handle_interrupt_event(inner, &event) {
+                        inner.completed.insert(event.param, event);
+                    }
+                }
+                TRB_PORT_STATUS_EVENT => {
+                    let port = (event.param >> 24) as u8;
+                    if (1..=self.max_ports).contains(&port) {
+                        let regs = self.port(port);
+                        let portsc = regs.portsc.read();
+                        regs.portsc
+                            .write(port_state_to_neutral(portsc) | (portsc & PORT_CHANGE_MASK));
+                        kdebug!(
+                            "xhci {}: port {} status changed, portsc={:#x}",
+                            self.bdf,
+                            port,
+                            portsc
+                        );
+                    }
+                }
+                _ => {
+                    inner.completed.insert(event.param, event);
+                }
+            }
+        }
+
+        if handled {
+            let erdp = inner.event_ring.dequeue_dma() | ERDP_EHB as u64;
+            let intr = self.intr();
+            intr.erdp_lo.write(erdp as u32);
+            intr.erdp_hi.write((erdp >> 32) as u32);
+        }
+    }
+
+    /// 处理中断IN端点的传输事件：调用回调函数，并重新提交传输
+    ///
+    /// ## 返回值
+    ///
+    /// 事件是否属于一个正在轮询的中断IN端点
+    fn handle_interrupt_event(&self, inner: &mut InnerXhci, event: &Trb) -> bool {
+        let slot_id = event.slot_id();
+        let dci = event.endpoint_id();
+        let slot = match inner.slots.get_mut(&slot_id) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let pipe = match slot.interrupt_pipes.get(&dci) {
+            Some(pipe) => pipe,
+            None => return false,
+        };
+
+        if let Err(e) = completion_result(event) {
+            kwarn!(
+                "xhci {}: interrupt endpoint {} of slot {} stopped: {:?}",
+                self.bdf,
+                dci,
+                slot_id,
+                e
+            );
+            slot.interrupt_pipes.remove(&dci);
+            return true;
+        }
+        let len = pipe.len.saturating_sub(event.transfer_residue());
+        pipe.handler.complete(pipe.buf.as_slice(len));
+
+        let trb = Trb::new(
+            pipe.buf.dma(),
+            pipe.len as u32,
+            TRB_NORMAL << TRB_TYPE_SHIFT | TRB_ISP | TRB_IOC,
+        );
+        if let Some(ring) = slot.rings.get_mut(&dci) {
+            ring.push(trb);
+            self.ring_doorbell(slot_id, dci);
+        }
+        return true;
+    }
+
+    /// 处理事件环，由中断处理函数和轮询线程调用
+    fn poll(&self) {
+        let mut inner = self.inner.lock_irqsave();
+        self.process_events(&mut inner);
+    }
+
+    /// 等待一个传输描述符(或者一个命令)完成
+    ///
+    /// 传输描述符中途出错时，之后的TRB不会产生事件，因此任意一个TRB的事件表示出错时就返回
+    ///
+    /// ## 参数
+    ///
+    /// - `trbs`：传输描述符中可能产生事件的TRB的地址，最后一个TRB必须设置了IOC
+    ///
+    /// ## 返回值
+    ///
+    /// 每个TRB对应的事件，没有产生事件的TRB为None
+    fn wait_td(&self, trbs: &[u64], timeout_ms: u64) -> Result<Vec<Option<Trb>>, SystemError> {
+        let deadline = ktime_get() + timeout_ms * NSEC_PER_MSEC as u64;
+        loop {
+            {
+                let mut inner = self.inner.lock_irqsave();
+                self.process_events(&mut inner);
+                let done = trbs.iter().enumerate().any(|(i, addr)| {
+                    inner.completed.get(addr).map_or(false, |ev| {
+                        i == trbs.len() - 1 || completion_result(ev).is_err()
+                    })
+                });
+                if done {
+                    return Ok(trbs
+                        .iter()
+                        .map(|addr| inner.completed.remove(addr))
+                        .collect());
+                }
+            }
+            if ktime_get() > deadline {
+                return Err(SystemError::ETIMEDOUT);
+            }
+            spin_loop();
+        }
+    }
+
+    /// 检查传输描述符中所有事件的完成码
+    fn td_result(events: &[Option<Trb>]) -> Result<(), SystemError> {
+        for event in events.iter().flatten() {
+            completion_result(event)?;
+        }
+        return Ok(());
+    }
+
+    /// 执行一个命令，返回命令完成事件
+    fn command(&self, trb: Trb) -> Result<Trb, SystemError> {
+        let addr = {
+            let mut inner = self.inner.lock_irqsave();
+            let addr = inner.cmd_ring.push(trb);
+            self.ring_doorbell(0, 0);
+            addr
+        };
+        let event = self.wait_td(&[addr], XHCI_CMD_TIMEOUT_MS)?[0].unwrap();
+        if event.completion_code() != COMP_SUCCESS {
+            kwarn!(
+                "xhci {}: command {} failed, completion code {}",
+                self.bdf,
+                trb.trb_type(),
+                event.completion_code()
+            );
+            return Err(SystemError::EIO);
+        }
+        return Ok(event);
+    }
+
+    /// 在输入上下文中写入一个双字
+    ///
+    /// ## 参数
+    ///
+    /// - `ctx`：上下文结构体的下标。0为输入控制上下文，1为槽位上下文，DCI+1为端点上下文
+    /// - `dw`：双字在上下文结构体中的下标
+    #[inline]
+    fn input_ctx_write(&self, slot: &XhciSlot, ctx: usize, dw: usize, value: u32) {
+        slot.input_ctx.write32(ctx * self.ctx_size + dw * 4, value);
+    }
+
+    /// 在输入上下文中填写槽位上下文
+    fn input_ctx_slot(&self, slot: &XhciSlot, context_entries: u8) {
+        let speed = match slot.speed {
+            UsbSpeed::Full => XHCI_SPEED_FULL,
+            UsbSpeed::Low => XHCI_SPEED_LOW,
+            UsbSpeed::High => XHCI_SPEED_HIGH,
+            UsbSpeed::Super => XHCI_SPEED_SUPER,
+        };
+        self.input_ctx_write(slot, 1, 0, speed << 20 | (context_entries as u32) << 27);
+        self.input_ctx_write(slot, 1, 1, (slot.port as u32) << 16);
+    }
+
+    /// 在输入上下文中填写端点上下文
+    #[allow(clippy::too_many_arguments)]
+    fn input_ctx_endpoint(
+        &self,
+        slot: &XhciSlot,
+        dci: u8,
+        ep_type: u32,
+        max_packet: u32,
+        interval: u32,
+        avg_trb_len: u32,
+        max_esit_payload: u32,
+    ) {
+        let ctx = dci as usize + 1;
+        let (dequeue, cycle) = slot.rings[&dci].enqueue_pointer();
+        self.input_ctx_write(slot, ctx, 0, interval << 16);
+        self.input_ctx_write(slot, ctx, 1, EP_CERR << 1 | ep_type << 3 | max_packet << 16);
+        self.input_ctx_write(slot, ctx, 2, dequeue as u32 | cycle as u32);
+        self.input_ctx_write(slot, ctx, 3, (dequeue >> 32) as u32);
+        self.input_ctx_write(slot, ctx, 4, avg_trb_len | max_esit_payload << 16);
+    }
+
+    /// 枚举所有已经连接了设备的端口
+    fn enumerate(&self) {
+        for port in 1..=self.max_ports {
+            if self.port(port).portsc.read() & PORT_CONNECT == 0 {
+                continue;
+            }
+            if let Err(e) = self.enumerate_port(port) {
+                kwarn!(
+                    "xhci {}: failed to enumerate device on port {}: {:?}",
+                    self.bdf,
+                    port,
+                    e
+                );
+            }
+        }
+    }
+
+    /// 复位端口，返回连接在端口上的设备的速度
+    ///
+    /// USB3端口在链路训练完成之后自动启用，不需要复位
+    fn reset_port(&self, port: u8) -> Result<UsbSpeed, SystemError> {
+        let regs = self.port(port);
+        let portsc = regs.portsc.read();
+        if portsc & PORT_PE == 0 {
+            regs.portsc
+                .write(port_state_to_neutral(portsc) | PORT_RESET);
+            wait_until(500, || regs.portsc.read() & PORT_RC != 0)?;
+            // 复位恢复时间
+            delay_ms(10);
+        }
+
+        let portsc = regs.portsc.read();
+        regs.portsc
+            .write(port_state_to_neutral(portsc) | (portsc & PORT_CHANGE_MASK));
+        if portsc & PORT_PE == 0 {
+            return Err(SystemError::ENODEV);
+        }
+        return match (portsc & PORT_SPEED_MASK) >> PORT_SPEED_SHIFT {
+            XHCI_SPEED_FULL => Ok(UsbSpeed::Full),
+            XHCI_SPEED_LOW => Ok(UsbSpeed::Low),
+            XHCI_SPEED_HIGH => Ok(UsbSpeed::High),
+            XHCI_SPEED_SUPER | XHCI_SPEED_SUPER_PLUS => Ok(UsbSpeed::Super),
+            _ => Err(SystemError::EINVAL),
+        };
+    }
+
+    /// 枚举端口上的设备，并把设备交给USB核心
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/core/hub.c#hub_port_init
+    fn enumerate_port(&self, port: u8) -> Result<(), SystemError> {
+        let speed = self.reset_port(port)?;
+        let slot = self.enable_slot(port, speed)?;
+        let result = self.setup_device(slot, port, speed);
+        if result.is_err() {
+            self.disable_slot(slot);
+        }
+        return result;
+    }
+
+    /// 分配一个设备槽位，以及设备上下文和端点0的传输环
+    fn enable_slot(&self, port: u8, speed: UsbSpeed) -> Result<u8, SystemError> {
+        let event = self.command(Trb::new(0, 0, TRB_ENABLE_SLOT << TRB_TYPE_SHIFT))?;
+        let slot_id = event.slot_id();
+        if slot_id == 0 || slot_id > self.max_slots {
+            return Err(SystemError::EIO);
+        }
+
+        let mut rings = BTreeMap::new();
+        rings.insert(1, XhciRing::new(&self.bdf)?);
+        let slot = XhciSlot {
+            port,
+            speed,
+            dev_ctx: DmaBuffer::new(&self.bdf, 32 * self.ctx_size)?,
+            input_ctx: DmaBuffer::new(&self.bdf, 33 * self.ctx_size)?,
+            rings,
+            interrupt_pipes: BTreeMap::new(),
+        };
+        let mut inner = self.inner.lock_irqsave();
+        inner
+            .dcbaa
+            .write64(slot_id as usize * 8, slot.dev_ctx.dma());
+        inner.slots.insert(slot_id, slot);
+        return Ok(slot_id);
+    }
+
+    /// 释放设备槽位
+    fn disable_slot(&self, slot_id: u8) {
+        self.command(Trb::new(
+            0,
+            0,
+            TRB_DISABLE_SLOT << TRB_TYPE_SHIFT | (slot_id as u32) << TRB_SLOT_ID_SHIFT,
+        ))
+        .ok();
+        let mut inner = self.inner.lock_irqsave();
+        inner.dcbaa.write64(slot_id as usize * 8, 0);
+        inner.slots.remove(&slot_id);
+    }
+
+    /// 设置设备的地址，配置端点0
+    fn address_device(&self, slot_id: u8, max_packet0: u32) -> Result<(), SystemError> {
+        let input_ctx = {
+            let inner = self.inner.lock_irqsave();
+            let slot = inner.slots.get(&slot_id).ok_or(SystemError::ENODEV)?;
+            slot.input_ctx.clear();
+            // 添加槽位上下文和端点0的上下文
+            self.input_ctx_write(slot, 0, 1, 0b11);
+            self.input_ctx_slot(slot, 1);
+            self.input_ctx_endpoint(slot, 1, EP_TYPE_CONTROL, max_packet0, 0, 8, 0);
+            slot.input_ctx.dma()
+        };
+        self.command(Trb::new(
+            input_ctx,
+            0,
+            TRB_ADDRESS_DEVICE << TRB_TYPE_SHIFT | (slot_id as u32) << TRB_SLOT_ID_SHIFT,
+        ))?;
+        return Ok(());
+    }
+
+    /// 读取到设备描述符之后，更新全速设备的端点0的最大包长
+    fn update_max_packet0(&self, slot_id: u8, max_packet0: u32) -> Result<(), SystemError> {
+        let input_ctx = {
+            let inner = self.inner.lock_irqsave();
+            let slot = inner.slots.get(&slot_id).ok_or(SystemError::ENODEV)?;
+            slot.input_ctx.clear();
+            self.input_ctx_write(slot, 0, 1, 0b10);
+            self.input_ctx_write(
+                slot,
+                2,
+                1,
+                EP_CERR << 1 | EP_TYPE_CONTROL << 3 | max_packet0 << 16,
+            );
+            slot.input_ctx.dma()
+        };
+        self.command(Trb::new(
+            input_ctx,
+            0,
+            TRB_EVALUATE_CONTEXT << TRB_TYPE_SHIFT | (slot_id as u32) << TRB_SLOT_ID_SHIFT,
+        ))?;
+        return Ok(());
+    }
+
+    /// 计算中断端点的轮询间隔，以2^n * 125us表示
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci-mem.c#xhci_get_endpoint_interval
+    fn endpoint_interval(speed: UsbSpeed, ep: &UsbEndpointDescriptor) -> u32 {
+        if ep.transfer_type() != UsbTransferType::Interrupt {
+            return 0;
+        }
+        let interval = ep.interval.max(1) as u32;
+        match speed {
+            UsbSpeed::High | UsbSpeed::Super => interval.min(16) - 1,
+            // 全速和低速设备的bInterval以1ms为单位
+            UsbSpeed::Full | UsbSpeed::Low => (31 - (interval * 8).leading_zeros()).clamp(3, 10),
+        }
+    }
+
+    /// 为配置中所有的批量和中断端点创建传输环，并通知控制器
+    ///
+    /// 等时端点目前不被支持，会被跳过
+    fn configure_endpoints(
+        &self,
+        slot_id: u8,
+        config: &UsbConfiguration,
+    ) -> Result<(), SystemError> {
+        let input_ctx = {
+            let mut inner = self.inner.lock_irqsave();
+            let slot = inner.slots.get_mut(&slot_id).ok_or(SystemError::ENODEV)?;
+            slot.input_ctx.clear();
+            let mut add_flags = 1;
+            let mut max_dci = 1;
+            for ep in config.interfaces.iter().flat_map(|i| i.endpoints.iter()) {
+                let ep_type = match (ep.transfer_type(), ep.is_in()) {
+                    (UsbTransferType::Bulk, false) => EP_TYPE_BULK_OUT,
+                    (UsbTransferType::Bulk, true) => EP_TYPE_BULK_IN,
+                    (UsbTransferType::Interrupt, false) => EP_TYPE_INT_OUT,
+                    (UsbTransferType::Interrupt, true) => EP_TYPE_INT_IN,
+                    _ => continue,
+                };
+                let dci = endpoint_dci(ep.endpoint_address);
+                slot.rings.insert(dci, XhciRing::new(&self.bdf)?);
+
+                let max_packet = ep.max_packet() as u32;
+                let (avg_trb_len, max_esit_payload) = match ep.transfer_type() {
+                    UsbTransferType::Interrupt => (max_packet, max_packet),
+                    _ => (3072, 0),
+                };
+                self.input_ctx_endpoint(
+                    slot,
+                    dci,
+                    ep_type,
+                    max_packet,
+                    Self::endpoint_interval(slot.speed, ep),
+                    avg_trb_len,
+                    max_esit_payload,
+                );
+                add_flags |= 1 << dci;
+                max_dci = max_dci.max(dci);
+            }
+            self.input_ctx_write(slot, 0, 1, add_flags);
+            self.input_ctx_slot(slot, max_dci);
+            slot.input_ctx.dma()
+        };
+        self.command(Trb::new(
+            input_ctx,
+            0,
+            TRB_CONFIGURE_EP << TRB_TYPE_SHIFT | (slot_id as u32) << TRB_SLOT_ID_SHIFT,
+        ))?;
+        return Ok(());
+    }
+
+    /// 为设备设置地址、读取描述符、设置配置，然后把设备交给USB核心
+    fn setup_device(&self, slot_id: u8, port: u8, speed: UsbSpeed) -> Result<(), SystemError> {
+        let max_packet0 = match speed {
+            UsbSpeed::Low | UsbSpeed::Full => 8,
+            UsbSpeed::High => 64,
+            UsbSpeed::Super => 512,
+        };
+        self.address_device(slot_id, max_packet0)?;
+
+        // 先读取设备描述符的前8个字节，得到端点0实际的最大包长
+        let mut buf = [0u8; USB_DT_DEVICE_SIZE];
+        self.control_transfer(
+            slot_id,
+            &UsbCtrlRequest::get_descriptor(USB_DT_DEVICE, 0, 8),
+            UsbData::In(&mut buf[..8]),
+        )?;
+        let desc = UsbDeviceDescriptor::parse(&buf[..8])?;
+        if speed == UsbSpeed::Full && desc.max_packet_size0 as u32 != max_packet0 {
+            self.update_max_packet0(slot_id, desc.max_packet_size0 as u32)?;
+        }
+
+        let len = self.control_transfer(
+            slot_id,
+            &UsbCtrlRequest::get_descriptor(USB_DT_DEVICE, 0, USB_DT_DEVICE_SIZE as u16),
+            UsbData::In(&mut buf),
+        )?;
+        if len < USB_DT_DEVICE_SIZE {
+            return Err(SystemError::EIO);
+        }
+        let desc = UsbDeviceDescriptor::parse(&buf)?;
+        if desc.device_class == USB_CLASS_HUB {
+            kwarn!(
+                "usb {}-{}: external hubs are not supported",
+                self.busnum,
+                port
+            );
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+
+        let mut buf = [0u8; USB_DT_CONFIG_SIZE];
+        self.control_transfer(
+            slot_id,
+            &UsbCtrlRequest::get_descriptor(USB_DT_CONFIG, 0, USB_DT_CONFIG_SIZE as u16),
+            UsbData::In(&mut buf),
+        )?;
+        let total_length = UsbConfigDescriptor::parse(&buf)?.total_length as usize;
+        let mut buf = vec![0u8; total_length];
+        let len = self.control_transfer(
+            slot_id,
+            &UsbCtrlRequest::get_descriptor(USB_DT_CONFIG, 0, total_length as u16),
+            UsbData::In(&mut buf),
+        )?;
+        let config = UsbConfiguration::parse(&buf[..len])?;
+
+        self.configure_endpoints(slot_id, &config)?;
+        self.control_transfer(
+            slot_id,
+            &UsbCtrlRequest::new(
+                USB_DIR_OUT | USB_TYPE_STANDARD | USB_RECIP_DEVICE,
+                USB_REQ_SET_CONFIGURATION,
+                config.desc.configuration_value as u16,
+                0,
+                0,
+            ),
+            UsbData::None,
+        )?;
+
+        let hcd = self.self_ref.upgrade().unwrap() as Arc<dyn UsbHostController>;
+        let dev = UsbDevice::new(
+            hcd,
+            slot_id,
+            format!("{}-{}", self.busnum, port),
+            speed,
+            desc,
+            config,
+        );
+        return usb_device_manager().device_add(&dev);
+    }
+
+    /// 重置一个停止的端点，并丢弃环上未完成的TRB
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci-ring.c#xhci_handle_cmd_reset_ep
+    fn reset_endpoint_dci(&self, slot_id: u8, dci: u8) -> Result<(), SystemError> {
+        let target = (dci as u32) << TRB_EP_ID_SHIFT | (slot_id as u32) << TRB_SLOT_ID_SHIFT;
+        self.command(Trb::new(0, 0, TRB_RESET_EP << TRB_TYPE_SHIFT | target))?;
+        let (dequeue, cycle) = {
+            let inner = self.inner.lock_irqsave();
+            inner
+                .slots
+                .get(&slot_id)
+                .and_then(|slot| slot.rings.get(&dci))
+                .ok_or(SystemError::EINVAL)?
+                .enqueue_pointer()
+        };
+        self.command(Trb::new(
+            dequeue | cycle as u64,
+            0,
+            TRB_SET_TR_DEQ << TRB_TYPE_SHIFT | target,
+        ))?;
+        return Ok(());
+    }
+
+    /// 为一次传输分配数据缓冲区，OUT传输的数据被复制到缓冲区中
+    fn alloc_transfer_buffer(&self, data: &UsbData) -> Result<Option<DmaBuffer>, SystemError> {
+        if data.is_empty() {
+            return Ok(None);
+        }
+        if data.len() > XHCI_MAX_TRANSFER {
+            return Err(SystemError::EINVAL);
+        }
+        let buf = DmaBuffer::new(&self.bdf, data.len())?;
+        if let UsbData::Out(out) = data {
+            buf.copy_from(out);
+        }
+        return Ok(Some(buf));
+    }
+
+    /// 等待传输完成。超时的传输仍然可能被控制器执行，因此不能释放它的缓冲区
+    fn wait_transfer(
+        &self,
+        trbs: &[u64],
+        buf: &mut Option<DmaBuffer>,
+    ) -> Result<Vec<Option<Trb>>, SystemError> {
+        let result = self.wait_td(trbs, XHCI_TRANSFER_TIMEOUT_MS);
+        if result.is_err() {
+            kwarn!("xhci {}: transfer timed out", self.bdf);
+            core::mem::forget(buf.take());
+        }
+        return result;
+    }
+}
+
+impl UsbHostController for Xhci {
+    fn control_transfer(
+        &self,
+        slot: u8,
+        req: &UsbCtrlRequest,
+        data: UsbData,
+    ) -> Result<usize, SystemError> {
+        let len = data.len();
+        let mut buf = self.alloc_transfer_buffer(&data)?;
+        let (data_trb, status_trb) = {
+            let mut inner = self.inner.lock_irqsave();
+            let ring = inner
+                .slots
+                .get_mut(&slot)
+                .and_then(|s| s.rings.get_mut(&1))
+                .ok_or(SystemError::ENODEV)?;
+            let (trt, dir) = match (&buf, req.is_in()) {
+                (None, _) => (0, 0),
+                (Some(_), true) => (TRB_TRT_IN, TRB_DIR_IN),
+                (Some(_), false) => (TRB_TRT_OUT, 0),
+            };
+            ring.push(Trb::new(
+                req.to_u64(),
+                8,
+                TRB_SETUP << TRB_TYPE_SHIFT | TRB_IDT | trt,
+            ));
+            let data_trb = buf.as_ref().map(|b| {
+                ring.push(Trb::new(
+                    b.dma(),
+                    len as u32,
+                    TRB_DATA << TRB_TYPE_SHIFT | TRB_ISP | dir,
+                ))
+            });
+            // 状态阶段的方向与数据阶段相反，没有数据阶段时为IN
+            let status_dir = if dir == 0 { TRB_DIR_IN } else { 0 };
+            let status_trb = ring.push(Trb::new(
+                0,
+                0,
+                TRB_STATUS << TRB_TYPE_SHIFT | status_dir | TRB_IOC,
+            ));
+            self.ring_doorbell(slot, 1);
+            (data_trb, status_trb)
+        };
+
+        let mut trbs = Vec::with_capacity(2);
+        trbs.extend(data_trb);
+        trbs.push(status_trb);
+        let events = self.wait_transfer(&trbs, &mut buf)?;
+        if let Err(e) = Self::td_result(&events) {
+            // 端点0被STALL之后，需要重置才能发送下一个请求
+            if e == SystemError::EPIPE {
+                self.reset_endpoint_dci(slot, 1).ok();
+            }
+            return Err(e);
+        }
+
+        // 只有发生短包时，数据阶段才会产生事件
+        let actual = match (data_trb.is_some(), events[0]) {
+            (true, Some(event)) => len.saturating_sub(event.transfer_residue()),
+            _ => len,
+        };
+        if let (UsbData::In(inbuf), Some(buf)) = (data, buf) {
+            buf.copy_to(&mut inbuf[..actual]);
+        }
+        return Ok(actual);
+    }
+
+    fn bulk_transfer(&self, slot: u8, endpoint: u8, data: UsbData) -> Result<usize, SystemError> {
+        let len = data.len();
+        let mut buf = self.alloc_transfer_buffer(&data)?;
+        let dma = match &buf {
+            Some(buf) => buf.dma(),
+            None => return Ok(0),
+        };
+        let dci = endpoint_dci(endpoint);
+        let trb = {
+            let mut inner = self.inner.lock_irqsave();
+            let ring = inner
+                .slots
+                .get_mut(&slot)
+                .ok_or(SystemError::ENODEV)?
+                .rings
+                .get_mut(&dci)
+                .ok_or(SystemError::EINVAL)?;
+            let trb = ring.push(Trb::new(
+                dma,
+                len as u32,
+                TRB_NORMAL << TRB_TYPE_SHIFT | TRB_ISP | TRB_IOC,
+            ));
+            self.ring_doorbell(slot, dci);
+            trb
+        };
+
+        let events = self.wait_transfer(&[trb], &mut buf)?;
+        Self::td_result(&events)?;
+        let actual = len.saturating_sub(events[0].unwrap().transfer_residue());
+        if let (UsbData::In(inbuf), Some(buf)) = (data, buf) {
+            buf.copy_to(&mut inbuf[..actual]);
+        }
+        return Ok(actual);
+    }
+
+    fn interrupt_in_start(
+        &self,
+        slot: u8,
+        endpoint: u8,
+        len: usize,
+        handler: Arc<dyn UsbInterruptHandler>,
+    ) -> Result<(), SystemError> {
+        if len == 0 || len > XHCI_MAX_TRANSFER || endpoint & 0x80 == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let dci = endpoint_dci(endpoint);
+        let buf = DmaBuffer::new(&self.bdf, len)?;
+
+        let mut inner = self.inner.lock_irqsave();
+        let slot_ref = inner.slots.get_mut(&slot).ok_or(SystemError::ENODEV)?;
+        if slot_ref.interrupt_pipes.contains_key(&dci) {
+            return Err(SystemError::EBUSY);
+        }
+        let ring = slot_ref.rings.get_mut(&dci).ok_or(SystemError::EINVAL)?;
+        ring.push(Trb::new(
+            buf.dma(),
+            len as u32,
+            TRB_NORMAL << TRB_TYPE_SHIFT | TRB_ISP | TRB_IOC,
+        ));
+        slot_ref
+            .interrupt_pipes
+            .insert(dci, XhciInterruptPipe { buf, len, handler });
+        self.ring_doorbell(slot, dci);
+        return Ok(());
+    }
+
+    fn reset_endpoint(&self, slot: u8, endpoint: u8) -> Result<(), SystemError> {
+        return self.reset_endpoint_dci(slot, endpoint_dci(endpoint));
+    }
+}
@@ -0,0 +1,152 @@
+//! xHCI控制器的寄存器
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci.h
+//! 以及 eXtensible Host Controller Interface for Universal Serial Bus (xHCI) Revision 1.2 第5章
+
+use crate::libs::volatile::Mmio;
+
+/// 能力寄存器
+#[repr(C)]
+pub struct XhciCapRegs {
+    /// 第0~7位为能力寄存器的长度，第16~31位为控制器支持的xHCI版本
+    pub caplength_hciversion: Mmio<u32>,
+    pub hcsparams1: Mmio<u32>,
+    pub hcsparams2: Mmio<u32>,
+    pub hcsparams3: Mmio<u32>,
+    pub hccparams1: Mmio<u32>,
+    /// 门铃寄存器相对于能力寄存器的偏移量
+    pub dboff: Mmio<u32>,
+    /// 运行时寄存器相对于能力寄存器的偏移量
+    pub rtsoff: Mmio<u32>,
+    pub hccparams2: Mmio<u32>,
+}
+
+/// HCSPARAMS1: 设备槽位的最大数量
+pub const HCS_MAX_SLOTS_MASK: u32 = 0xff;
+/// HCSPARAMS1: 根集线器端口的数量
+pub const HCS_MAX_PORTS_SHIFT: u32 = 24;
+/// HCSPARAMS2: 暂存区缓冲区数量的高5位和低5位
+pub const HCS_MAX_SCRATCHPAD_HI_SHIFT: u32 = 21;
+pub const HCS_MAX_SCRATCHPAD_LO_SHIFT: u32 = 27;
+/// HCCPARAMS1: 上下文结构体的大小为64字节
+pub const HCC_64BYTE_CONTEXT: u32 = 1 << 2;
+/// HCCPARAMS1: 第一个扩展能力相对于能力寄存器的偏移量(以4字节为单位)
+pub const HCC_EXT_CAPS_SHIFT: u32 = 16;
+
+/// 操作寄存器
+#[repr(C)]
+pub struct XhciOpRegs {
+    pub usbcmd: Mmio<u32>,
+    pub usbsts: Mmio<u32>,
+    pub pagesize: Mmio<u32>,
+    _rsvd1: [u32; 2],
+    pub dnctrl: Mmio<u32>,
+    /// 命令环控制寄存器，64位寄存器按照低32位、高32位的顺序访问
+    pub crcr_lo: Mmio<u32>,
+    pub crcr_hi: Mmio<u32>,
+    _rsvd2: [u32; 4],
+    /// 设备上下文基地址数组的地址
+    pub dcbaap_lo: Mmio<u32>,
+    pub dcbaap_hi: Mmio<u32>,
+    pub config: Mmio<u32>,
+}
+
+/// 端口寄存器组相对于操作寄存器的偏移量
+pub const XHCI_PORT_REGS_OFFSET: usize = 0x400;
+
+/// USBCMD
+pub const CMD_RUN: u32 = 1 << 0;
+pub const CMD_RESET: u32 = 1 << 1;
+pub const CMD_INTE: u32 = 1 << 2;
+
+/// USBSTS
+pub const STS_HALT: u32 = 1 << 0;
+pub const STS_FATAL: u32 = 1 << 2;
+pub const STS_EINT: u32 = 1 << 3;
+pub const STS_PORT: u32 = 1 << 4;
+/// 控制器尚未就绪，不能写入操作寄存器
+pub const STS_CNR: u32 = 1 << 11;
+
+/// CRCR: 命令环的消费者周期状态
+pub const CMD_RING_RCS: u32 = 1 << 0;
+
+/// 一个根集线器端口的寄存器
+#[repr(C)]
+pub struct XhciPortRegs {
+    pub portsc: Mmio<u32>,
+    pub portpmsc: Mmio<u32>,
+    pub portli: Mmio<u32>,
+    pub porthlpmc: Mmio<u32>,
+}
+
+/// PORTSC
+pub const PORT_CONNECT: u32 = 1 << 0;
+pub const PORT_PE: u32 = 1 << 1;
+pub const PORT_RESET: u32 = 1 << 4;
+pub const PORT_POWER: u32 = 1 << 9;
+pub const PORT_SPEED_SHIFT: u32 = 10;
+pub const PORT_SPEED_MASK: u32 = 0xf << PORT_SPEED_SHIFT;
+pub const PORT_CSC: u32 = 1 << 17;
+pub const PORT_PEC: u32 = 1 << 18;
+pub const PORT_WRC: u32 = 1 << 19;
+pub const PORT_OCC: u32 = 1 << 20;
+pub const PORT_RC: u32 = 1 << 21;
+pub const PORT_PLC: u32 = 1 << 22;
+pub const PORT_CEC: u32 = 1 << 23;
+/// 所有写1清零的状态变化位
+pub const PORT_CHANGE_MASK: u32 =
+    PORT_CSC | PORT_PEC | PORT_WRC | PORT_OCC | PORT_RC | PORT_PLC | PORT_CEC;
+/// 只读的位，写入时的值没有影响
+const PORT_RO: u32 = (1 << 0) | (1 << 3) | (0xf << 10) | (1 << 30);
+/// 读写并需要保持的位
+const PORT_RWS: u32 = (0xf << 5) | (1 << 9) | (0x3 << 14) | (0x7 << 25);
+
+/// PORTSC中的端口速度
+pub const XHCI_SPEED_FULL: u32 = 1;
+pub const XHCI_SPEED_LOW: u32 = 2;
+pub const XHCI_SPEED_HIGH: u32 = 3;
+pub const XHCI_SPEED_SUPER: u32 = 4;
+pub const XHCI_SPEED_SUPER_PLUS: u32 = 5;
+
+/// 把读取到的PORTSC转换为写回时不会产生副作用的值：不清除状态变化位，也不会禁用端口
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci-hub.c#xhci_port_state_to_neutral
+#[inline]
+pub fn port_state_to_neutral(state: u32) -> u32 {
+    state & (PORT_RO | PORT_RWS)
+}
+
+/// 中断器寄存器组
+#[repr(C)]
+pub struct XhciIntrRegs {
+    pub iman: Mmio<u32>,
+    pub imod: Mmio<u32>,
+    /// 事件环段表的大小
+    pub erstsz: Mmio<u32>,
+    _rsvd: u32,
+    /// 事件环段表的地址
+    pub erstba_lo: Mmio<u32>,
+    pub erstba_hi: Mmio<u32>,
+    /// 事件环的出队指针
+    pub erdp_lo: Mmio<u32>,
+    pub erdp_hi: Mmio<u32>,
+}
+
+/// 第0个中断器相对于运行时寄存器的偏移量
+pub const XHCI_INTR0_OFFSET: usize = 0x20;
+
+/// IMAN
+pub const IMAN_IP: u32 = 1 << 0;
+pub const IMAN_IE: u32 = 1 << 1;
+/// ERDP: 事件处理程序忙，写1清零
+pub const ERDP_EHB: u32 = 1 << 3;
+
+/// 扩展能力：USB传统支持，用于从BIOS接管控制器
+pub const XHCI_EXT_CAPS_LEGACY: u32 = 1;
+/// USBLEGSUP
+pub const XHCI_HC_BIOS_OWNED: u32 = 1 << 16;
+pub const XHCI_HC_OS_OWNED: u32 = 1 << 24;
+/// USBLEGCTLSTS: 需要保留的位，其余的SMI使能位需要清零
+pub const XHCI_LEGACY_DISABLE_SMI: u32 = (0x7 << 1) | (0xff << 5) | (0x7 << 17);
+/// USBLEGCTLSTS: 写1清零的SMI事件位
+pub const XHCI_LEGACY_SMI_EVENTS: u32 = 0x7 << 29;
@@ -0,0 +1,377 @@
+//! xHCI控制器与驱动之间共享的数据结构：TRB、命令环/传输环、事件环
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/usb/host/xhci-ring.c
+
+use core::sync::atomic::{fence, Ordering};
+
+use crate::{
+    arch::MMArch,
+    driver::{
+        iommu::dma::{dma_map_single, dma_unmap_single, DmaAddr, DmaDirection},
+        pci::pci::BusDeviceFunction,
+    },
+    libs::align::page_align_up,
+    mm::{
+        allocator::page_frame::{
+            allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
+        },
+        MemoryManagementArch, PhysAddr, VirtAddr,
+    },
+    syscall::SystemError,
+};
+
+/// TRB的类型，位于control字段的第10~15位
+pub const TRB_NORMAL: u32 = 1;
+pub const TRB_SETUP: u32 = 2;
+pub const TRB_DATA: u32 = 3;
+pub const TRB_STATUS: u32 = 4;
+pub const TRB_LINK: u32 = 6;
+pub const TRB_ENABLE_SLOT: u32 = 9;
+pub const TRB_DISABLE_SLOT: u32 = 10;
+pub const TRB_ADDRESS_DEVICE: u32 = 11;
+pub const TRB_CONFIGURE_EP: u32 = 12;
+pub const TRB_EVALUATE_CONTEXT: u32 = 13;
+pub const TRB_RESET_EP: u32 = 14;
+pub const TRB_SET_TR_DEQ: u32 = 16;
+pub const TRB_TRANSFER_EVENT: u32 = 32;
+pub const TRB_COMPLETION_EVENT: u32 = 33;
+pub const TRB_PORT_STATUS_EVENT: u32 = 34;
+pub const TRB_TYPE_SHIFT: u32 = 10;
+
+/// TRB的control字段
+pub const TRB_CYCLE: u32 = 1 << 0;
+/// Link TRB: 经过这个TRB时翻转生产者的周期状态
+pub const LINK_TOGGLE: u32 = 1 << 1;
+/// 短包时也产生事件
+pub const TRB_ISP: u32 = 1 << 2;
+/// 这个TRB与下一个TRB属于同一个传输描述符
+pub const TRB_CHAIN: u32 = 1 << 4;
+/// 完成时产生事件
+pub const TRB_IOC: u32 = 1 << 5;
+/// 数据直接保存在TRB的参数字段中
+pub const TRB_IDT: u32 = 1 << 6;
+/// 数据阶段、状态阶段TRB的方向
+pub const TRB_DIR_IN: u32 = 1 << 16;
+/// Setup Stage TRB的传输类型
+pub const TRB_TRT_OUT: u32 = 2 << 16;
+pub const TRB_TRT_IN: u32 = 3 << 16;
+pub const TRB_SLOT_ID_SHIFT: u32 = 24;
+pub const TRB_EP_ID_SHIFT: u32 = 16;
+
+/// 事件TRB的status字段中的完成码
+pub const COMP_SUCCESS: u32 = 1;
+pub const COMP_STALL_ERROR: u32 = 6;
+pub const COMP_SHORT_PACKET: u32 = 13;
+
+/// 一个环段中TRB的数量(包含末尾的Link TRB)，一个环段恰好占用一页
+pub const TRBS_PER_SEGMENT: usize = 256;
+
+/// 传输请求块
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Trb {
+    pub param: u64,
+    pub status: u32,
+    pub control: u32,
+}
+
+impl Trb {
+    pub const fn new(param: u64, status: u32, control: u32) -> Self {
+        Self {
+            param,
+            status,
+            control,
+        }
+    }
+
+    #[inline]
+    pub fn trb_type(&self) -> u32 {
+        (self.control >> TRB_TYPE_SHIFT) & 0x3f
+    }
+
+    /// 事件TRB的完成码
+    #[inline]
+    pub fn completion_code(&self) -> u32 {
+        self.status >> 24
+    }
+
+    /// 传输事件中，未传输的字节数
+    #[inline]
+    pub fn transfer_residue(&self) -> usize {
+        (self.status & 0xffffff) as usize
+    }
+
+    #[inline]
+    pub fn slot_id(&self) -> u8 {
+        (self.control >> TRB_SLOT_ID_SHIFT) as u8
+    }
+
+    /// 传输事件对应的端点上下文下标
+    #[inline]
+    pub fn endpoint_id(&self) -> u8 {
+        ((self.control >> TRB_EP_ID_SHIFT) & 0x1f) as u8
+    }
+}
+
+/// 与控制器共享的一段物理连续的内存
+///
+/// 内存按页分配并清零，在整个生命周期内都映射给控制器
+#[derive(Debug)]
+pub struct DmaBuffer {
+    bdf: BusDeviceFunction,
+    paddr: PhysAddr,
+    vaddr: VirtAddr,
+    dma: DmaAddr,
+    count: PageFrameCount,
+}
+
+impl DmaBuffer {
+    /// 分配一段至少`size`字节的内存
+    ///
+    /// 内存的大小是2的幂次个页，并且按照其大小对齐，因此不超过64K的缓冲区不会跨越64K边界
+    pub fn new(bdf: &BusDeviceFunction, size: usize) -> Result<Self, SystemError> {
+        let pages = (page_align_up(size.max(1)) >> MMArch::PAGE_SHIFT).next_power_of_two();
+        let (paddr, count) = unsafe { allocate_page_frames(PageFrameCount::new(pages)) }
+            .ok_or(SystemError::ENOMEM)?;
+        let vaddr = unsafe { MMArch::phys_2_virt(paddr) }.unwrap();
+        unsafe { core::ptr::write_bytes(vaddr.data() as *mut u8, 0, count.bytes()) };
+
+        let dma = match dma_map_single(bdf, vaddr, count.bytes(), DmaDirection::Bidirectional) {
+            Ok(dma) => dma,
+            Err(e) => {
+                unsafe { deallocate_page_frames(PhysPageFrame::new(paddr), count) };
+                return Err(e);
+            }
+        };
+        return Ok(Self {
+            bdf: *bdf,
+            paddr,
+            vaddr,
+            dma,
+            count,
+        });
+    }
+
+    /// 控制器访问这段内存时使用的地址
+    #[inline]
+    pub fn dma(&self) -> u64 {
+        self.dma.data() as u64
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.count.bytes()
+    }
+
+    /// 获取偏移量`offset`处的指针
+    #[inline]
+    pub fn ptr<T>(&self, offset: usize) -> *mut T {
+        assert!(offset + core::mem::size_of::<T>() <= self.size());
+        (self.vaddr.data() + offset) as *mut T
+    }
+
+    #[inline]
+    pub fn read32(&self, offset: usize) -> u32 {
+        unsafe { self.ptr::<u32>(offset).read_volatile() }
+    }
+
+    #[inline]
+    pub fn write32(&self, offset: usize, value: u32) {
+        unsafe { self.ptr::<u32>(offset).write_volatile(value) }
+    }
+
+    #[inline]
+    pub fn write64(&self, offset: usize, value: u64) {
+        unsafe { self.ptr::<u64>(offset).write_volatile(value) }
+    }
+
+    /// 把整个缓冲区清零
+    pub fn clear(&self) {
+        unsafe { core::ptr::write_bytes(self.vaddr.data() as *mut u8, 0, self.size()) };
+    }
+
+    /// 把缓冲区开头的数据复制到`buf`
+    pub fn copy_to(&self, buf: &mut [u8]) {
+        let len = buf.len().min(self.size());
+        buf[..len].copy_from_slice(unsafe {
+            core::slice::from_raw_parts(self.vaddr.data() as *const u8, len)
+        });
+    }
+
+    /// 把`buf`复制到缓冲区的开头
+    pub fn copy_from(&self, buf: &[u8]) {
+        let len = buf.len().min(self.size());
+        unsafe { core::slice::from_raw_parts_mut(self.vaddr.data() as *mut u8, len) }
+            .copy_from_slice(&buf[..len]);
+    }
+
+    /// 以字节切片的形式访问缓冲区开头的`len`个字节
+    pub fn as_slice(&self, len: usize) -> &[u8] {
+        let len = len.min(self.size());
+        unsafe { core::slice::from_raw_parts(self.vaddr.data() as *const u8, len) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        dma_unmap_single(
+            &self.bdf,
+            self.dma,
+            self.count.bytes(),
+            DmaDirection::Bidirectional,
+        );
+        unsafe { deallocate_page_frames(PhysPageFrame::new(self.paddr), self.count) };
+    }
+}
+
+/// 命令环或者传输环，由一个环段组成，驱动是生产者
+#[derive(Debug)]
+pub struct XhciRing {
+    buf: DmaBuffer,
+    /// 下一个TRB的下标
+    enqueue: usize,
+    /// 生产者的周期状态
+    cycle: bool,
+}
+
+impl XhciRing {
+    pub fn new(bdf: &BusDeviceFunction) -> Result<Self, SystemError> {
+        let buf = DmaBuffer::new(bdf, TRBS_PER_SEGMENT * core::mem::size_of::<Trb>())?;
+        let ring = Self {
+            buf,
+            enqueue: 0,
+            cycle: true,
+        };
+        // 环段末尾的Link TRB指向环段的开头
+        ring.write_trb(
+            TRBS_PER_SEGMENT - 1,
+            Trb::new(ring.buf.dma(), 0, TRB_LINK << TRB_TYPE_SHIFT | LINK_TOGGLE),
+        );
+        return Ok(ring);
+    }
+
+    /// 环段的起始地址
+    #[inline]
+    pub fn dma(&self) -> u64 {
+        self.buf.dma()
+    }
+
+    /// 下一个TRB的地址，以及生产者的周期状态，用于设置端点的出队指针
+    #[inline]
+    pub fn enqueue_pointer(&self) -> (u64, bool) {
+        (self.trb_dma(self.enqueue), self.cycle)
+    }
+
+    #[inline]
+    fn trb_dma(&self, index: usize) -> u64 {
+        self.buf.dma() + (index * core::mem::size_of::<Trb>()) as u64
+    }
+
+    fn write_trb(&self, index: usize, trb: Trb) {
+        let ptr = self.buf.ptr::<Trb>(index * core::mem::size_of::<Trb>());
+        unsafe {
+            core::ptr::addr_of_mut!((*ptr).param).write_volatile(trb.param);
+            core::ptr::addr_of_mut!((*ptr).status).write_volatile(trb.status);
+            // 控制器根据周期位判断TRB是否有效，必须最后写入control字段
+            fence(Ordering::SeqCst);
+            core::ptr::addr_of_mut!((*ptr).control).write_volatile(trb.control);
+        }
+    }
+
+    /// 把一个TRB放入环中，TRB的周期位由环设置
+    ///
+    /// ## 返回值
+    ///
+    /// TRB的地址，控制器产生的事件通过这个地址指明对应的TRB
+    pub fn push(&mut self, trb: Trb) -> u64 {
+        let addr = self.trb_dma(self.enqueue);
+        let cycle = if self.cycle { TRB_CYCLE } else { 0 };
+        self.write_trb(
+            self.enqueue,
+            Trb::new(trb.param, trb.status, (trb.control & !TRB_CYCLE) | cycle),
+        );
+        self.enqueue += 1;
+
+        if self.enqueue == TRBS_PER_SEGMENT - 1 {
+            // 把Link TRB交给控制器。传输描述符跨越环段末尾时，Link TRB也需要设置链接位
+            let control =
+                TRB_LINK << TRB_TYPE_SHIFT | LINK_TOGGLE | (trb.control & TRB_CHAIN) | cycle;
+            self.write_trb(TRBS_PER_SEGMENT - 1, Trb::new(self.buf.dma(), 0, control));
+            self.enqueue = 0;
+            self.cycle = !self.cycle;
+        }
+        return addr;
+    }
+}
+
+/// 事件环段表的表项
+#[repr(C)]
+struct XhciErstEntry {
+    base: u64,
+    size: u32,
+    _rsvd: u32,
+}
+
+/// 事件环，由一个环段组成，控制器是生产者
+#[derive(Debug)]
+pub struct XhciEventRing {
+    buf: DmaBuffer,
+    /// 事件环段表
+    erst: DmaBuffer,
+    /// 下一个待处理的TRB的下标
+    dequeue: usize,
+    /// 消费者的周期状态
+    cycle: bool,
+}
+
+impl XhciEventRing {
+    pub fn new(bdf: &BusDeviceFunction) -> Result<Self, SystemError> {
+        let buf = DmaBuffer::new(bdf, TRBS_PER_SEGMENT * core::mem::size_of::<Trb>())?;
+        let erst = DmaBuffer::new(bdf, core::mem::size_of::<XhciErstEntry>())?;
+        unsafe {
+            erst.ptr::<XhciErstEntry>(0).write_volatile(XhciErstEntry {
+                base: buf.dma(),
+                size: TRBS_PER_SEGMENT as u32,
+                _rsvd: 0,
+            })
+        };
+        return Ok(Self {
+            buf,
+            erst,
+            dequeue: 0,
+            cycle: true,
+        });
+    }
+
+    /// 事件环段表的地址
+    #[inline]
+    pub fn erst_dma(&self) -> u64 {
+        self.erst.dma()
+    }
+
+    /// 出队指针，需要写入ERDP寄存器
+    #[inline]
+    pub fn dequeue_dma(&self) -> u64 {
+        self.buf.dma() + (self.dequeue * core::mem::size_of::<Trb>()) as u64
+    }
+
+    /// 取出下一个事件，没有新的事件时返回None
+    pub fn pop(&mut self) -> Option<Trb> {
+        let ptr = self
+            .buf
+            .ptr::<Trb>(self.dequeue * core::mem::size_of::<Trb>());
+        let control = unsafe { core::ptr::addr_of!((*ptr).control).read_volatile() };
+        if (control & TRB_CYCLE != 0) != self.cycle {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let trb = unsafe { ptr.read_volatile() };
+
+        self.dequeue += 1;
+        if self.dequeue == TRBS_PER_SEGMENT {
+            self.dequeue = 0;
+            self.cycle = !self.cycle;
+        }
+        return Some(trb);
+    }
+}
@@ -1,8 +1,4 @@
-use core::{
-    ffi::{c_uint, c_void},
-    mem::MaybeUninit,
-    sync::atomic::{AtomicBool, Ordering},
-};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use alloc::{boxed::Box, sync::Arc};
 
@@ -10,9 +6,9 @@ use crate::{
     arch::MMArch,
     driver::tty::serial::serial8250::send_to_default_serial8250_port,
     include::bindings::bindings::{
-        multiboot2_get_Framebuffer_info, multiboot2_iter, multiboot_tag_framebuffer_info_t,
         FRAME_BUFFER_MAPPING_OFFSET, SPECIAL_MEMOEY_MAPPING_VIRT_ADDR_BASE,
     },
+    init::boot_info::{boot_info, BootFramebuffer, BootFramebufferType},
     kinfo,
     libs::{
         align::page_align_up,
@@ -22,7 +18,7 @@ use crate::{
     },
     mm::{
         allocator::page_frame::PageFrameCount, kernel_mapper::KernelMapper,
-        no_init::pseudo_map_phys, page::PageFlags, MemoryManagementArch, PhysAddr, VirtAddr,
+        no_init::pseudo_map_phys, page::PageFlags, MemoryManagementArch, VirtAddr,
     },
     syscall::SystemError,
     time::timer::{Timer, TimerFunction},
@@ -41,7 +37,7 @@ pub fn video_refresh_manager() -> &'static VideoRefreshManager {
 ///管理显示刷新变量的结构体
 pub struct VideoRefreshManager {
     device_buffer: RwLock<ScmBufferInfo>,
-    fb_info: multiboot_tag_framebuffer_info_t,
+    fb_info: BootFramebuffer,
     refresh_target: RwLock<Option<Arc<SpinLock<Box<[u32]>>>>>,
     running: AtomicBool,
 }
@@ -100,11 +96,11 @@ impl VideoRefreshManager {
         }
 
         // 地址映射
-        let mut paddr = PhysAddr::new(self.fb_info.framebuffer_addr as usize);
+        let mut paddr = self.fb_info.addr;
         let count = PageFrameCount::new(
             page_align_up(frame_buffer_info_graud.buf_size()) / MMArch::PAGE_SIZE,
         );
-        let page_flags: PageFlags<MMArch> = PageFlags::new().set_execute(true).set_write(true);
+        let page_flags: PageFlags<MMArch> = PageFlags::new().set_write(true);
 
         let mut kernel_mapper = KernelMapper::lock();
         let mut kernel_mapper = kernel_mapper.as_mut();
@@ -185,30 +181,21 @@ impl VideoRefreshManager {
             panic!("Try to init video twice!");
         }
 
-        let mut _reserved: u32 = 0;
-
-        let mut fb_info: MaybeUninit<multiboot_tag_framebuffer_info_t> = MaybeUninit::uninit();
-        //从multiboot2中读取帧缓冲区信息至fb_info
-        multiboot2_iter(
-            Some(multiboot2_get_Framebuffer_info),
-            fb_info.as_mut_ptr() as usize as *mut c_void,
-            &mut _reserved as *mut c_uint,
-        );
-        fb_info.assume_init();
-        let fb_info: multiboot_tag_framebuffer_info_t = core::mem::transmute(fb_info);
+        //从启动信息中读取帧缓冲区信息
+        let fb_info = boot_info().framebuffer.ok_or(SystemError::ENODEV)?;
 
-        let width = fb_info.framebuffer_width;
-        let height = fb_info.framebuffer_height;
+        let width = fb_info.width;
+        let height = fb_info.height;
 
         //初始化帧缓冲区信息结构体
-        let (bit_depth, flags) = if fb_info.framebuffer_type == 2 {
+        let (bit_depth, flags) = if fb_info.fb_type == BootFramebufferType::Text {
             //当type=2时,width与height用字符数表示,故depth=8
 
             (8u32, ScmBufferFlag::SCM_BF_TEXT | ScmBufferFlag::SCM_BF_FB)
         } else {
             //否则为图像模式,depth应参照帧缓冲区信息里面的每个像素的位数
             (
-                fb_info.framebuffer_bpp as u32,
+                fb_info.bpp as u32,
                 ScmBufferFlag::SCM_BF_PIXEL | ScmBufferFlag::SCM_BF_FB,
             )
         };
@@ -228,7 +215,7 @@ impl VideoRefreshManager {
         send_to_default_serial8250_port(init_text.as_bytes());
 
         //地址映射
-        let paddr = PhysAddr::new(fb_info.framebuffer_addr as usize);
+        let paddr = fb_info.addr;
         let count = PageFrameCount::new(
             page_align_up(device_buffer.buf_size() as usize) / MMArch::PAGE_SIZE,
         );
@@ -1,3 +1,5 @@
+pub mod queue;
 pub mod transport_pci;
 pub mod virtio;
+pub mod virtio_9p;
 pub mod virtio_impl;
@@ -0,0 +1,240 @@
+//! 同步使用的split virtqueue
+//!
+//! virtio-drivers没有导出它内部的virtqueue，需要自己管理队列的设备(目前是virtio-9p)使用这里的实现。
+//! 队列中同一时间只有一个请求：提交请求之后忙等待设备处理完成，因此总是从第0个描述符开始构造描述符链。
+//!
+//! 参考 virtio v1.1 2.6 Split Virtqueues
+
+use core::{
+    hint::spin_loop,
+    mem::size_of,
+    ptr::{read_volatile, write_volatile, NonNull},
+    sync::atomic::{fence, Ordering},
+};
+
+use virtio_drivers::{transport::Transport, BufferDirection, Hal, PAGE_SIZE};
+
+use crate::{
+    kerror,
+    syscall::SystemError,
+    time::{hrtimer::ktime_get, NSEC_PER_MSEC},
+};
+
+use super::virtio_impl::HalImpl;
+
+/// 描述符链中还有下一个描述符
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+/// 描述符指向的缓冲区由设备写入
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// 队列的描述符数量上限，每个请求只需要两个描述符
+const SYNC_QUEUE_MAX_SIZE: u16 = 16;
+
+/// 描述符表中的表项
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// 已使用环中的表项
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// 设备可以直接访问的内存
+#[derive(Debug)]
+pub struct DmaBuffer {
+    paddr: usize,
+    vaddr: NonNull<u8>,
+    pages: usize,
+    len: usize,
+}
+
+unsafe impl Send for DmaBuffer {}
+unsafe impl Sync for DmaBuffer {}
+
+impl DmaBuffer {
+    /// 分配至少`len`字节的DMA内存，内容被清零
+    pub fn new(len: usize) -> Self {
+        let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        let (paddr, vaddr) = HalImpl::dma_alloc(pages, BufferDirection::Both);
+        return Self {
+            paddr,
+            vaddr,
+            pages,
+            len,
+        };
+    }
+
+    pub fn paddr(&self) -> usize {
+        return self.paddr;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        return unsafe { core::slice::from_raw_parts(self.vaddr.as_ptr(), self.len) };
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        return unsafe { core::slice::from_raw_parts_mut(self.vaddr.as_ptr(), self.len) };
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        unsafe { HalImpl::dma_dealloc(self.paddr, self.vaddr, self.pages) };
+    }
+}
+
+/// 同一时间只有一个请求的split virtqueue
+#[derive(Debug)]
+pub struct SyncQueue {
+    /// 队列号
+    index: u16,
+    /// 描述符的数量
+    size: u16,
+    /// 描述符表、可用环与已使用环，依次存放在同一块DMA内存中
+    mem: DmaBuffer,
+    avail_offset: usize,
+    used_offset: usize,
+    /// 下一个要写入可用环的位置
+    avail_idx: u16,
+    /// 下一个要读取的已使用环的位置
+    last_used_idx: u16,
+    /// 请求超时之后，设备仍然可能访问缓冲区，队列不能再使用
+    broken: bool,
+}
+
+impl SyncQueue {
+    /// 创建队列，并告诉设备队列的地址
+    ///
+    /// ## 参数
+    ///
+    /// - `transport`：设备，必须处于初始化阶段(已经协商完特性，还没有设置DRIVER_OK)
+    /// - `index`：队列号
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：设备没有这个队列
+    pub fn new<T: Transport>(transport: &mut T, index: u16) -> Result<Self, SystemError> {
+        let size = core::cmp::min(transport.max_queue_size(), SYNC_QUEUE_MAX_SIZE as u32) as u16;
+        if size < 2 {
+            return Err(SystemError::ENODEV);
+        }
+
+        let n = size as usize;
+        let avail_offset = size_of::<Descriptor>() * n;
+        // 已使用环需要4字节对齐
+        let used_offset = (avail_offset + 6 + 2 * n + 3) & !3;
+        let total = used_offset + 6 + size_of::<UsedElem>() * n;
+        let mem = DmaBuffer::new(total);
+
+        transport.queue_set(
+            index,
+            size as u32,
+            mem.paddr(),
+            mem.paddr() + avail_offset,
+            mem.paddr() + used_offset,
+        );
+
+        return Ok(Self {
+            index,
+            size,
+            mem,
+            avail_offset,
+            used_offset,
+            avail_idx: 0,
+            last_used_idx: 0,
+            broken: false,
+        });
+    }
+
+    /// 提交一个请求，并等待设备处理完成
+    ///
+    /// ## 参数
+    ///
+    /// - `req`：请求所在的缓冲区，以及请求的长度
+    /// - `resp`：接收响应的缓冲区
+    /// - `timeout_ms`：等待的最长时间
+    ///
+    /// ## 返回值
+    ///
+    /// 设备写入`resp`的字节数
+    ///
+    /// ## 错误
+    ///
+    /// - `ETIMEDOUT`：设备没有在规定的时间内处理完请求，之后队列不能再使用
+    /// - `EIO`：队列因为之前的超时而不能使用
+    pub fn request<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        req: (&DmaBuffer, usize),
+        resp: &mut DmaBuffer,
+        timeout_ms: u64,
+    ) -> Result<usize, SystemError> {
+        if self.broken {
+            return Err(SystemError::EIO);
+        }
+
+        let base = self.mem.vaddr.as_ptr();
+        unsafe {
+            let desc = base as *mut Descriptor;
+            write_volatile(
+                desc,
+                Descriptor {
+                    addr: req.0.paddr() as u64,
+                    len: req.1 as u32,
+                    flags: VIRTQ_DESC_F_NEXT,
+                    next: 1,
+                },
+            );
+            write_volatile(
+                desc.add(1),
+                Descriptor {
+                    addr: resp.paddr() as u64,
+                    len: resp.len() as u32,
+                    flags: VIRTQ_DESC_F_WRITE,
+                    next: 0,
+                },
+            );
+
+            // 把描述符链的头放入可用环，然后才能更新可用环的idx
+            let avail = base.add(self.avail_offset) as *mut u16;
+            write_volatile(avail.add(2 + (self.avail_idx % self.size) as usize), 0);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            write_volatile(avail.add(1), self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+        transport.notify(self.index);
+
+        let used = unsafe { base.add(self.used_offset) as *const u16 };
+        let deadline = ktime_get() + timeout_ms * NSEC_PER_MSEC as u64;
+        while unsafe { read_volatile(used.add(1)) } == self.last_used_idx {
+            if ktime_get() > deadline {
+                kerror!("virtqueue {}: request timed out", self.index);
+                self.broken = true;
+                return Err(SystemError::ETIMEDOUT);
+            }
+            spin_loop();
+        }
+        fence(Ordering::SeqCst);
+
+        let elem = unsafe {
+            let ring = used.add(2) as *const UsedElem;
+            read_volatile(ring.add((self.last_used_idx % self.size) as usize))
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        return Ok(core::cmp::min(elem.len as usize, resp.len()));
+    }
+}
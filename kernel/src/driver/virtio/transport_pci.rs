@@ -5,13 +5,17 @@ use crate::driver::pci::pci::{
     PciStandardDeviceBar, PCI_CAP_ID_VNDR,
 };
 
-use crate::driver::pci::pci_irq::{IrqCommonMsg, IrqMsg, IrqSpecificMsg, PciInterrupt, IRQ};
+use crate::driver::pci::pci_irq::{
+    IrqCommonMsg, IrqMsg, IrqSpecificMsg, PciInterrupt, PciIrqError, IRQ,
+};
 use crate::include::bindings::bindings::pt_regs;
+use crate::kwarn;
 use crate::libs::volatile::{
     volread, volwrite, ReadOnly, Volatile, VolatileReadable, VolatileWritable, WriteOnly,
 };
 use crate::mm::VirtAddr;
 use crate::net::net_core::poll_ifaces_try_lock_onetime;
+use crate::syscall::SystemError;
 use core::{
     fmt::{self, Display, Formatter},
     mem::{align_of, size_of},
@@ -60,6 +64,8 @@ const VIRTIO_PCI_CAP_DEVICE_CFG: u8 = 4;
 const VIRTIO_RECV_VECTOR: u16 = 56;
 /// Virtio设备接收中断的设备号的表项号
 const VIRTIO_RECV_VECTOR_INDEX: u16 = 0;
+/// 队列不使用MSI-X中断
+const VIRTIO_MSI_NO_VECTOR: u16 = 0xffff;
 // 接收的queue号
 const QUEUE_RECEIVE: u16 = 0;
 ///@brief device id 转换为设备类型
@@ -86,7 +92,7 @@ fn device_type(pci_device_id: u16) -> DeviceType {
 pub struct PciTransport {
     device_type: DeviceType,
     /// The bus, device and function identifier for the VirtIO device.
-    _bus_device_function: BusDeviceFunction,
+    bus_device_function: BusDeviceFunction,
     /// The common configuration structure within some BAR.
     common_cfg: NonNull<CommonCfg>,
     /// The start of the queue notification region within some BAR.
@@ -123,30 +129,44 @@ impl PciTransport {
         let mut notify_off_multiplier = 0;
         let mut isr_cfg = None;
         let mut device_cfg = None;
-        device.bar_ioremap().unwrap()?;
+        device
+            .bar_ioremap()
+            .ok_or(VirtioPciError::BarGetVaddrFailed)??;
         device.enable_master();
-        let standard_device = device.as_standard_device_mut().unwrap();
-        // 目前缺少对PCI设备中断号的统一管理，所以这里需要指定一个中断号。不能与其他中断重复
-        let irq_vector = standard_device.irq_vector_mut().unwrap();
-        irq_vector.push(VIRTIO_RECV_VECTOR);
-        standard_device
-            .irq_init(IRQ::PCI_IRQ_MSIX)
-            .expect("IRQ init failed");
-        // 中断相关信息
-        let msg = IrqMsg {
-            irq_common_message: IrqCommonMsg::init_from(
-                0,
-                "Virtio_Recv_IRQ",
-                0,
-                virtio_irq_hander,
-                None,
-            ),
-            irq_specific_message: IrqSpecificMsg::msi_default(),
-        };
-        standard_device.irq_install(msg)?;
-        standard_device.irq_enable(true)?;
+        // 只有virtio-net使用中断，其他设备(例如virtio-9p)轮询队列
+        if device_type == DeviceType::Network {
+            let standard_device = device
+                .as_standard_device_mut()
+                .ok_or(PciError::PciDeviceStructureTransformError)?;
+            // 目前缺少对PCI设备中断号的统一管理，所以这里需要指定一个中断号。不能与其他中断重复
+            let irq_vector = standard_device
+                .irq_vector_mut()
+                .ok_or(PciError::PciIrqError(PciIrqError::PciDeviceNotSupportIrq))?;
+            irq_vector.push(VIRTIO_RECV_VECTOR);
+            standard_device
+                .irq_init(IRQ::PCI_IRQ_MSIX)
+                .ok_or(VirtioPciError::Pci(PciError::PciIrqError(
+                    PciIrqError::IrqTypeNotSupported,
+                )))?;
+            // 中断相关信息
+            let msg = IrqMsg {
+                irq_common_message: IrqCommonMsg::init_from(
+                    0,
+                    "Virtio_Recv_IRQ",
+                    0,
+                    virtio_irq_hander,
+                    None,
+                ),
+                irq_specific_message: IrqSpecificMsg::msi_default(),
+            };
+            standard_device.irq_install(msg)?;
+            standard_device.irq_enable(true)?;
+        }
         //device_capability为迭代器，遍历其相当于遍历所有的cap空间
-        for capability in device.capabilities().unwrap() {
+        let capabilities = device
+            .capabilities()
+            .ok_or(VirtioPciError::MissingCommonConfig)?;
+        for capability in capabilities {
             if capability.id != PCI_CAP_ID_VNDR {
                 continue;
             }
@@ -216,7 +236,7 @@ impl PciTransport {
         };
         Ok(Self {
             device_type,
-            _bus_device_function: bus_device_function,
+            bus_device_function,
             common_cfg,
             notify_region,
             notify_off_multiplier,
@@ -309,11 +329,18 @@ impl Transport for PciTransport {
             volwrite!(self.common_cfg, queue_driver, driver_area as u64);
             volwrite!(self.common_cfg, queue_device, device_area as u64);
             // 这里设置队列中断对应的中断项
-            if queue == QUEUE_RECEIVE {
+            if self.device_type == DeviceType::Network && queue == QUEUE_RECEIVE {
                 volwrite!(self.common_cfg, queue_msix_vector, VIRTIO_RECV_VECTOR_INDEX);
                 let vector = volread!(self.common_cfg, queue_msix_vector);
+                // Transport::queue_set不能返回错误。设备不接受这个中断向量时，接收队列不使用中断，
+                // 由网络轮询线程定期收包
                 if vector != VIRTIO_RECV_VECTOR_INDEX {
-                    panic!("Vector set failed");
+                    kwarn!(
+                        "virtio {}: failed to set msix vector of queue {}, fallback to polling mode",
+                        self.bus_device_function,
+                        queue
+                    );
+                    volwrite!(self.common_cfg, queue_msix_vector, VIRTIO_MSI_NO_VECTOR);
                 }
             }
             volwrite!(self.common_cfg, queue_enable, 1);
@@ -491,6 +518,24 @@ impl From<PciError> for VirtioPciError {
     }
 }
 
+impl From<VirtioPciError> for SystemError {
+    fn from(error: VirtioPciError) -> Self {
+        match error {
+            VirtioPciError::InvalidVendorId(_)
+            | VirtioPciError::MissingCommonConfig
+            | VirtioPciError::MissingNotifyConfig
+            | VirtioPciError::InvalidNotifyOffMultiplier(_)
+            | VirtioPciError::MissingIsrConfig
+            | VirtioPciError::UnexpectedBarType => SystemError::ENODEV,
+            VirtioPciError::BarNotAllocated(_)
+            | VirtioPciError::BarOffsetOutOfRange
+            | VirtioPciError::Misaligned { .. }
+            | VirtioPciError::BarGetVaddrFailed => SystemError::ENOMEM,
+            VirtioPciError::Pci(e) => e.into(),
+        }
+    }
+}
+
 /// @brief 获取虚拟地址并将其转化为对应类型的指针
 /// @param device_bar 存储bar信息的结构体 struct_info 存储cfg空间的位置信息
 /// @return Result<NonNull<T>, VirtioPciError> 成功则返回对应类型的指针，失败则返回Error
@@ -1,28 +1,34 @@
 use super::transport_pci::PciTransport;
+use super::virtio_9p::virtio_9p;
 use super::virtio_impl::HalImpl;
+use crate::driver::base::device::{device_manager, Device};
 use crate::driver::net::virtio_net::virtio_net;
+use crate::driver::pci::device::pci_device_manager;
 use crate::driver::pci::pci::{
-    get_pci_device_structure_mut, PciDeviceStructure, PciDeviceStructureGeneralDevice,
-    PCI_DEVICE_LINKEDLIST,
+    PciDeviceStructure, PciDeviceStructureGeneralDevice, PCI_DEVICE_LINKEDLIST,
 };
 use crate::libs::rwlock::RwLockWriteGuard;
 use crate::{kdebug, kerror, kwarn};
-use alloc::{boxed::Box, collections::LinkedList};
+use alloc::{boxed::Box, collections::LinkedList, sync::Arc};
 use virtio_drivers::transport::{DeviceType, Transport};
 const NETWORK_CLASS: u8 = 0x2;
 const ETHERNET_SUBCLASS: u8 = 0x0;
+const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+/// virtio-9p设备的PCI device id：过渡设备与非过渡设备
+const VIRTIO_9P_TRANSITIONAL_ID: u16 = 0x1009;
+const VIRTIO_9P_MODERN_ID: u16 = 0x1049;
 
 //Virtio设备寻找过程中出现的问题
 enum VirtioError {
-    VirtioNetNotFound,
-    NetDeviceNotFound,
+    VirtioDeviceNotFound,
 }
 
-///@brief 寻找并加载所有virtio设备的驱动（目前只有virtio-net，但其他virtio设备也可添加）
+///@brief 寻找并加载所有virtio设备的驱动（目前支持virtio-net与virtio-9p，其他virtio设备也可添加）
 pub fn virtio_probe() {
     let mut list = PCI_DEVICE_LINKEDLIST.write();
     if let Ok(virtio_list) = virtio_device_search(&mut list) {
         for virtio_device in virtio_list {
+            let bdf = virtio_device.common_header.bus_device_function;
             match PciTransport::new::<HalImpl>(virtio_device) {
                 Ok(mut transport) => {
                     kdebug!(
@@ -33,7 +39,15 @@ pub fn virtio_probe() {
                     virtio_device_init(transport);
                 }
                 Err(err) => {
+                    // 记录失败的原因并跳过这个设备，继续初始化其他virtio设备
                     kerror!("Pci transport create failed because of error: {}", err);
+                    if let Some(dev) = pci_device_manager().find(&bdf) {
+                        device_manager().probe_failure_add(
+                            &(dev as Arc<dyn Device>),
+                            "virtio",
+                            err.into(),
+                        );
+                    }
                 }
             }
         }
@@ -55,6 +69,7 @@ fn virtio_device_init(transport: impl Transport + 'static) {
             kwarn!("Not support virtio_input device for now");
         }
         DeviceType::Network => virtio_net(transport),
+        DeviceType::_9P => virtio_9p(transport),
         t => {
             kwarn!("Unrecognized virtio device: {:?}", t);
         }
@@ -64,36 +79,45 @@ fn virtio_device_init(transport: impl Transport + 'static) {
 /// @brief 寻找所有的virtio设备
 /// @param list 链表的写锁
 /// @return Result<LinkedList<&'a mut Pci_Device_Structure_General_Device>, VirtioError>  成功则返回包含所有virtio设备结构体的可变引用的链表，失败则返回err
-/// 该函数主要是为其他virtio设备预留支持
+/// 目前返回第一个virtio-net设备以及所有的virtio-9p设备
 fn virtio_device_search<'a>(
     list: &'a mut RwLockWriteGuard<'_, LinkedList<Box<dyn PciDeviceStructure>>>,
 ) -> Result<LinkedList<&'a mut PciDeviceStructureGeneralDevice>, VirtioError> {
     let mut virtio_list: LinkedList<&mut PciDeviceStructureGeneralDevice> = LinkedList::new();
-    let virtio_net_device = get_virtio_net_device(list)?;
-    virtio_list.push_back(virtio_net_device);
+    let mut net_found = false;
+    for device in list.iter_mut() {
+        let standard_device = match device.as_standard_device_mut() {
+            Some(standard_device) => standard_device,
+            None => continue,
+        };
+        if !net_found && is_virtio_net_device(standard_device) {
+            net_found = true;
+            virtio_list.push_back(standard_device);
+        } else if is_virtio_9p_device(standard_device) {
+            virtio_list.push_back(standard_device);
+        }
+    }
+    if virtio_list.is_empty() {
+        return Err(VirtioError::VirtioDeviceNotFound);
+    }
     Ok(virtio_list)
 }
 
-/// @brief 寻找virtio-net设备
-/// @param list 链表的写锁
-/// @return Result<&'a mut Pci_Device_Structure_General_Device, VirtioError> 成功则返回virtio设备结构体的可变引用，失败则返回err
-fn get_virtio_net_device<'a>(
-    list: &'a mut RwLockWriteGuard<'_, LinkedList<Box<dyn PciDeviceStructure>>>,
-) -> Result<&'a mut PciDeviceStructureGeneralDevice, VirtioError> {
-    let result = get_pci_device_structure_mut(list, NETWORK_CLASS, ETHERNET_SUBCLASS);
-    if result.is_empty() {
-        return Err(VirtioError::NetDeviceNotFound);
-    }
-    for device in result {
-        let standard_device = device.as_standard_device_mut().unwrap();
-        let header = &standard_device.common_header;
-        if header.vendor_id == 0x1AF4
-            && header.device_id >= 0x1000
-            && header.device_id <= 0x103F
-            && standard_device.subsystem_id == 1
-        {
-            return Ok(standard_device);
-        }
-    }
-    Err(VirtioError::VirtioNetNotFound)
+/// @brief 判断设备是否为virtio-net设备
+fn is_virtio_net_device(device: &PciDeviceStructureGeneralDevice) -> bool {
+    let header = &device.common_header;
+    return header.class_code == NETWORK_CLASS
+        && header.subclass == ETHERNET_SUBCLASS
+        && header.vendor_id == VIRTIO_VENDOR_ID
+        && header.device_id >= 0x1000
+        && header.device_id <= 0x103F
+        && device.subsystem_id == 1;
+}
+
+/// @brief 判断设备是否为virtio-9p设备
+fn is_virtio_9p_device(device: &PciDeviceStructureGeneralDevice) -> bool {
+    let header = &device.common_header;
+    return header.vendor_id == VIRTIO_VENDOR_ID
+        && (header.device_id == VIRTIO_9P_TRANSITIONAL_ID
+            || header.device_id == VIRTIO_9P_MODERN_ID);
 }
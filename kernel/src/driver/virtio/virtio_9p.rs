@@ -0,0 +1,169 @@
+//! virtio-9p设备驱动
+//!
+//! 设备只有一个请求队列，每个请求由一条9P消息与接收回复的缓冲区组成。设备在配置空间中提供挂载标签，
+//! 挂载时用标签选择设备，例如`mount("hostshare", "/mnt", "9p")`。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/9p/trans_virtio.c
+
+use core::ptr::read_volatile;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use virtio_drivers::transport::{DeviceStatus, Transport};
+
+use crate::{
+    filesystem::p9::client::P9Transport,
+    kerror, kinfo,
+    libs::{mutex::Mutex, rwlock::RwLock},
+    syscall::SystemError,
+};
+
+use super::queue::{DmaBuffer, SyncQueue};
+
+/// 设备在配置空间中提供挂载标签
+const VIRTIO_9P_MOUNT_TAG: u64 = 1 << 0;
+/// 设备遵循virtio 1.0及之后的规范
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+/// 挂载标签的最大长度
+const VIRTIO_9P_MAX_TAG_LEN: usize = 64;
+/// 单条9P消息的最大长度
+const VIRTIO_9P_MSIZE: usize = 128 * 1024;
+/// 等待主机回复的最长时间
+const VIRTIO_9P_TIMEOUT_MS: u64 = 30 * 1000;
+
+/// 所有的virtio-9p设备
+static VIRTIO_9P_DEVICES: RwLock<Vec<Arc<dyn P9Transport>>> = RwLock::new(Vec::new());
+
+struct Virtio9pInner<T: Transport> {
+    transport: T,
+    queue: SyncQueue,
+    req: DmaBuffer,
+    resp: DmaBuffer,
+}
+
+pub struct Virtio9pDevice<T: Transport> {
+    tag: String,
+    inner: Mutex<Virtio9pInner<T>>,
+}
+
+unsafe impl<T: Transport> Send for Virtio9pDevice<T> {}
+unsafe impl<T: Transport> Sync for Virtio9pDevice<T> {}
+
+impl<T: Transport> core::fmt::Debug for Virtio9pDevice<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Virtio9pDevice")
+            .field("tag", &self.tag)
+            .finish()
+    }
+}
+
+impl<T: Transport> Virtio9pDevice<T> {
+    /// 初始化设备：协商特性、读取挂载标签并创建请求队列
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：设备不支持挂载标签，或者没有请求队列
+    fn new(mut transport: T) -> Result<Self, SystemError> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features();
+        if features & VIRTIO_9P_MOUNT_TAG == 0 {
+            transport.set_status(DeviceStatus::FAILED);
+            return Err(SystemError::ENODEV);
+        }
+        transport.write_driver_features(features & (VIRTIO_9P_MOUNT_TAG | VIRTIO_F_VERSION_1));
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+
+        let tag = Self::read_tag(&transport)?;
+        let queue = match SyncQueue::new(&mut transport, 0) {
+            Ok(queue) => queue,
+            Err(e) => {
+                transport.set_status(DeviceStatus::FAILED);
+                return Err(e);
+            }
+        };
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE
+                | DeviceStatus::DRIVER
+                | DeviceStatus::FEATURES_OK
+                | DeviceStatus::DRIVER_OK,
+        );
+
+        return Ok(Self {
+            tag,
+            inner: Mutex::new(Virtio9pInner {
+                transport,
+                queue,
+                req: DmaBuffer::new(VIRTIO_9P_MSIZE),
+                resp: DmaBuffer::new(VIRTIO_9P_MSIZE),
+            }),
+        });
+    }
+
+    /// 从配置空间读取挂载标签：2字节的长度，之后是标签本身
+    fn read_tag(transport: &T) -> Result<String, SystemError> {
+        let config = transport
+            .config_space::<u16>()
+            .map_err(|_| SystemError::ENODEV)?;
+        let len = unsafe { read_volatile(config.as_ptr()) } as usize;
+        if len == 0 || len > VIRTIO_9P_MAX_TAG_LEN {
+            return Err(SystemError::ENODEV);
+        }
+
+        let bytes = config.as_ptr() as *const u8;
+        let tag: Vec<u8> = (0..len)
+            .map(|i| unsafe { read_volatile(bytes.add(2 + i)) })
+            .collect();
+        return Ok(String::from_utf8_lossy(&tag).into_owned());
+    }
+}
+
+impl<T: Transport> P9Transport for Virtio9pDevice<T> {
+    fn tag(&self) -> &str {
+        return &self.tag;
+    }
+
+    fn msize(&self) -> usize {
+        return VIRTIO_9P_MSIZE;
+    }
+
+    fn rpc(&self, req: &[u8], resp: &mut Vec<u8>) -> Result<(), SystemError> {
+        if req.len() > VIRTIO_9P_MSIZE {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut guard = self.inner.lock();
+        let inner = &mut *guard;
+        inner.req.as_mut_slice()[..req.len()].copy_from_slice(req);
+        let len = inner.queue.request(
+            &mut inner.transport,
+            (&inner.req, req.len()),
+            &mut inner.resp,
+            VIRTIO_9P_TIMEOUT_MS,
+        )?;
+        resp.clear();
+        resp.extend_from_slice(&inner.resp.as_slice()[..len]);
+        return Ok(());
+    }
+}
+
+/// 初始化virtio-9p设备，之后可以通过挂载标签挂载
+pub fn virtio_9p<T: Transport + 'static>(transport: T) {
+    match Virtio9pDevice::new(transport) {
+        Ok(dev) => {
+            kinfo!("virtio-9p: found device with mount tag '{}'", dev.tag);
+            VIRTIO_9P_DEVICES.write().push(Arc::new(dev));
+        }
+        Err(e) => kerror!("virtio-9p: failed to initialize device: {:?}", e),
+    }
+}
+
+/// 根据挂载标签查找virtio-9p设备
+pub fn virtio_9p_find(tag: &str) -> Option<Arc<dyn P9Transport>> {
+    return VIRTIO_9P_DEVICES
+        .read()
+        .iter()
+        .find(|dev| dev.tag() == tag)
+        .cloned();
+}
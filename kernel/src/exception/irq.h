@@ -16,13 +16,16 @@
 #pragma GCC push_options
 #pragma GCC optimize ("O0")
 
-#define IRQ_NUM 26
+#define IRQ_NUM 96
 #define SMP_IRQ_NUM 10
 #define LOCAL_APIC_IRQ_NUM 50
 
-extern void (*interrupt_table[26])(void);
+extern void (*interrupt_table[IRQ_NUM])(void);
 extern void do_IRQ(struct pt_regs *regs, ul number);
 
+// 统计中断发生的次数（由Rust实现）
+extern void rs_irq_account(ul irq_num);
+extern void rs_tick_irq_enter();
 
 extern void (*SMP_interrupt_table[SMP_IRQ_NUM])(void);
 
@@ -84,6 +87,7 @@ extern void (*local_apic_interrupt_table[LOCAL_APIC_IRQ_NUM])(void);
 	55	PIRQH
 	56  VIRTIO_RECV
 	57  E1000E_RECV
+58  ~   127	MSI/MSI-X (动态分配)
 	
 	
 0x80		system call
@@ -0,0 +1,578 @@
+//! 通用的中断处理层
+//!
+//! 驱动通过[`IrqManager::request_irq`]或[`IrqManager::request_threaded_irq`]在中断向量上注册处理函数。
+//! 多个设备可以共享同一个中断向量，此时所有的处理函数都必须带有[`IrqHandleFlags::IRQF_SHARED`]标志。
+//! 设备被移除时，驱动应当调用[`IrqManager::free_irq`]注销自己的处理函数。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/irq/manage.c
+
+use core::{
+    ffi::CStr,
+    fmt::Debug,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use alloc::{boxed::Box, ffi::CString, format, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    arch::{interrupt::TrapFrame, sched::sched, CurrentIrqArch},
+    debug::trace_event::{trace_irq_handler_entry, trace_irq_handler_exit},
+    filesystem::procfs::{procfs_register_irq, procfs_unregister_irq},
+    include::bindings::bindings::{
+        c_irq_install, c_irq_name, c_irq_uninstall, pt_regs, ul, EAGAIN, EINVAL,
+    },
+    kwarn,
+    libs::spinlock::SpinLock,
+    mm::percpu::PerCpu,
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        ProcessControlBlock, ProcessManager,
+    },
+    smp::{
+        core::{smp_cpu_count, smp_get_processor_id},
+        hotplug::cpu_online,
+    },
+    syscall::SystemError,
+};
+
+use super::InterruptArch;
+
+pub type IrqNumber = u16;
+
+/// 中断向量的数量
+const NR_VECTORS: usize = 256;
+
+/// 由本模块管理的外部中断向量的范围: [IRQ_DESC_START, IRQ_DESC_END)
+const IRQ_DESC_START: IrqNumber = 32;
+const IRQ_DESC_END: IrqNumber = 0x80;
+
+/// 中断处理函数的返回值
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/irqreturn.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// 中断不是由本设备产生的
+    NotHandled,
+    /// 中断已经被处理
+    Handled,
+    /// 需要唤醒中断线程，在进程上下文中继续处理
+    WakeThread,
+}
+
+bitflags! {
+    /// 注册中断处理函数时使用的标志
+    pub struct IrqHandleFlags: u32 {
+        /// 允许多个设备共享同一个中断向量
+        const IRQF_SHARED = 1 << 0;
+    }
+}
+
+/// 中断处理函数
+pub trait IrqHandler: Debug + Send + Sync {
+    /// 在中断上下文中执行，不能睡眠
+    ///
+    /// ## 参数
+    ///
+    /// - `irq`：中断向量号
+    /// - `trap_frame`：被中断时的现场
+    fn handle(&self, irq: IrqNumber, trap_frame: &mut TrapFrame) -> IrqReturn;
+
+    /// 在中断线程中执行，可以睡眠
+    ///
+    /// 只有通过[`IrqManager::request_threaded_irq`]注册，并且[`IrqHandler::handle`]返回
+    /// [`IrqReturn::WakeThread`]时才会被调用
+    fn handle_thread(&self, _irq: IrqNumber) -> IrqReturn {
+        IrqReturn::NotHandled
+    }
+}
+
+/// 中断控制器相关的操作，由产生中断的设备（或中断控制器）实现
+pub trait IrqChip: Debug + Send + Sync {
+    /// 把中断投递到指定的CPU上
+    fn set_affinity(&self, irq: IrqNumber, cpu: usize) -> Result<(), SystemError>;
+}
+
+/// 注册在中断向量上的一个处理函数
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/interrupt.h#118
+#[derive(Debug)]
+pub struct IrqAction {
+    name: String,
+    handler: Arc<dyn IrqHandler>,
+    flags: IrqHandleFlags,
+    /// 注册者的标识，注销时用于找到对应的处理函数
+    dev_id: usize,
+    /// 中断线程
+    thread: SpinLock<Option<Arc<ProcessControlBlock>>>,
+    /// 中断线程是否有待处理的工作
+    thread_pending: AtomicBool,
+}
+
+impl IrqAction {
+    fn new(
+        name: String,
+        handler: Arc<dyn IrqHandler>,
+        flags: IrqHandleFlags,
+        dev_id: usize,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            handler,
+            flags,
+            dev_id,
+            thread: SpinLock::new(None),
+            thread_pending: AtomicBool::new(false),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 唤醒中断线程
+    fn wake_thread(&self) {
+        self.thread_pending.store(true, Ordering::SeqCst);
+        if let Some(pcb) = self.thread.lock_irqsave().as_ref() {
+            ProcessManager::wakeup(pcb).ok();
+        }
+    }
+}
+
+/// 中断线程的主循环
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/irq/manage.c#1234
+fn irq_thread(irq: IrqNumber, action: Arc<IrqAction>) -> i32 {
+    let pcb = ProcessManager::current_pcb();
+    loop {
+        let guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        if KernelThreadMechanism::should_stop(&pcb) {
+            break;
+        }
+        if !action.thread_pending.swap(false, Ordering::SeqCst) {
+            ProcessManager::mark_sleep(true).ok();
+            drop(guard);
+            sched();
+            continue;
+        }
+        drop(guard);
+
+        action.handler.handle_thread(irq);
+    }
+    return 0;
+}
+
+#[derive(Debug)]
+struct InnerIrqDesc {
+    actions: Vec<Arc<IrqAction>>,
+    chip: Option<Arc<dyn IrqChip>>,
+    /// 处理该中断的CPU
+    affinity: usize,
+    /// 向中断控制器应答的函数，为None时发送EOI
+    ack: Option<unsafe extern "C" fn(irq_num: ul)>,
+}
+
+/// 中断描述符
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/irqdesc.h#55
+#[derive(Debug)]
+pub struct IrqDesc {
+    irq: IrqNumber,
+    /// 中断处理过程中也会访问，加锁时必须关中断
+    inner: SpinLock<InnerIrqDesc>,
+}
+
+impl IrqDesc {
+    fn new(irq: IrqNumber) -> Self {
+        Self {
+            irq,
+            inner: SpinLock::new(InnerIrqDesc {
+                actions: Vec::new(),
+                chip: None,
+                affinity: 0,
+                ack: None,
+            }),
+        }
+    }
+
+    pub fn irq(&self) -> IrqNumber {
+        self.irq
+    }
+
+    /// 所有处理函数的名字
+    pub fn action_names(&self) -> Vec<String> {
+        self.inner
+            .lock_irqsave()
+            .actions
+            .iter()
+            .map(|a| a.name.clone())
+            .collect()
+    }
+
+    fn handle(&self, trap_frame: &mut TrapFrame) {
+        // 调用处理函数之前先释放锁，处理函数可能会访问中断描述符
+        let actions = self.inner.lock_irqsave().actions.clone();
+        let mut handled = false;
+        for action in actions.iter() {
+            trace_irq_handler_entry(self.irq, &action.name);
+            let ret = action.handler.handle(self.irq, trap_frame);
+            trace_irq_handler_exit(self.irq, ret != IrqReturn::NotHandled);
+            match ret {
+                IrqReturn::NotHandled => {}
+                IrqReturn::Handled => handled = true,
+                IrqReturn::WakeThread => {
+                    action.wake_thread();
+                    handled = true;
+                }
+            }
+        }
+        if !handled {
+            kwarn!("irq {}: nobody cared", self.irq);
+        }
+    }
+}
+
+lazy_static! {
+    static ref IRQ_DESCS: Vec<IrqDesc> = (IRQ_DESC_START..IRQ_DESC_END).map(IrqDesc::new).collect();
+}
+
+const IRQ_COUNT_INIT: AtomicU64 = AtomicU64::new(0);
+const IRQ_COUNTS_INIT: [AtomicU64; NR_VECTORS] = [IRQ_COUNT_INIT; NR_VECTORS];
+/// 每个CPU上各个中断向量发生的次数
+static IRQ_COUNTS: [[AtomicU64; NR_VECTORS]; PerCpu::MAX_CPU_NUM] =
+    [IRQ_COUNTS_INIT; PerCpu::MAX_CPU_NUM];
+
+/// 统计中断次数，由do_IRQ在分发中断前调用
+#[no_mangle]
+pub extern "C" fn rs_irq_account(irq_num: ul) {
+    let cpu = smp_get_processor_id() as usize % PerCpu::MAX_CPU_NUM;
+    if let Some(cnt) = IRQ_COUNTS[cpu].get(irq_num as usize) {
+        cnt.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// 所有通过本模块注册的中断的入口
+unsafe extern "C" fn irq_desc_handler(irq_num: ul, _parameter: ul, regs: *mut pt_regs) {
+    // TrapFrame与pt_regs的内存布局相同
+    let trap_frame = &mut *(regs as *mut TrapFrame);
+    if let Some(desc) = irq_manager().desc(irq_num as IrqNumber) {
+        desc.handle(trap_frame);
+    }
+}
+
+/// 把使用C接口的中断处理函数包装为[`IrqHandler`]
+#[derive(Debug)]
+struct CIrqHandler {
+    func: unsafe extern "C" fn(irq_num: ul, parameter: ul, regs: *mut pt_regs),
+    parameter: ul,
+}
+
+impl IrqHandler for CIrqHandler {
+    fn handle(&self, irq: IrqNumber, trap_frame: &mut TrapFrame) -> IrqReturn {
+        unsafe {
+            (self.func)(
+                irq as ul,
+                self.parameter,
+                trap_frame as *mut TrapFrame as *mut pt_regs,
+            )
+        };
+        return IrqReturn::Handled;
+    }
+}
+
+#[inline(always)]
+pub fn irq_manager() -> &'static IrqManager {
+    &IrqManager
+}
+
+#[derive(Debug)]
+pub struct IrqManager;
+
+impl IrqManager {
+    fn desc(&self, irq: IrqNumber) -> Option<&'static IrqDesc> {
+        if !(IRQ_DESC_START..IRQ_DESC_END).contains(&irq) {
+            return None;
+        }
+        return IRQ_DESCS.get((irq - IRQ_DESC_START) as usize);
+    }
+
+    /// 在中断向量上注册处理函数
+    ///
+    /// ## 参数
+    ///
+    /// - `irq`：中断向量号
+    /// - `name`：中断的名字，会显示在/proc/interrupts中
+    /// - `handler`：中断处理函数
+    /// - `flags`：标志
+    /// - `dev_id`：注册者的标识，同一个向量上的标识不能重复
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：中断向量号不合法
+    /// - `EBUSY`：中断向量已被占用，并且不能共享
+    pub fn request_irq(
+        &self,
+        irq: IrqNumber,
+        name: String,
+        handler: Arc<dyn IrqHandler>,
+        flags: IrqHandleFlags,
+        dev_id: usize,
+    ) -> Result<(), SystemError> {
+        let action = IrqAction::new(name, handler, flags, dev_id);
+        return self.setup_irq(irq, action, false, None);
+    }
+
+    /// 在中断向量上注册处理函数，并创建一个中断线程执行[`IrqHandler::handle_thread`]
+    ///
+    /// 参数与[`IrqManager::request_irq`]相同
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/irq/manage.c#2143
+    pub fn request_threaded_irq(
+        &self,
+        irq: IrqNumber,
+        name: String,
+        handler: Arc<dyn IrqHandler>,
+        flags: IrqHandleFlags,
+        dev_id: usize,
+    ) -> Result<(), SystemError> {
+        let action = IrqAction::new(name, handler, flags, dev_id);
+        return self.setup_irq(irq, action, true, None);
+    }
+
+    /// 为使用C接口的中断处理函数注册中断（不可共享）
+    ///
+    /// ## 参数
+    ///
+    /// - `ack`：向中断控制器应答的函数，为None时发送EOI
+    ///
+    /// 其余参数与[`IrqManager::request_irq`]相同
+    pub fn request_c_irq(
+        &self,
+        irq: IrqNumber,
+        name: String,
+        func: unsafe extern "C" fn(irq_num: ul, parameter: ul, regs: *mut pt_regs),
+        parameter: ul,
+        ack: Option<unsafe extern "C" fn(irq_num: ul)>,
+        dev_id: usize,
+    ) -> Result<(), SystemError> {
+        let handler = Arc::new(CIrqHandler { func, parameter });
+        let action = IrqAction::new(name, handler, IrqHandleFlags::empty(), dev_id);
+        return self.setup_irq(irq, action, false, ack);
+    }
+
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/irq/manage.c#1468
+    fn setup_irq(
+        &self,
+        irq: IrqNumber,
+        action: Arc<IrqAction>,
+        threaded: bool,
+        ack: Option<unsafe extern "C" fn(irq_num: ul)>,
+    ) -> Result<(), SystemError> {
+        let desc = self.desc(irq).ok_or(SystemError::EINVAL)?;
+        let (flags, dev_id) = (action.flags, action.dev_id);
+
+        let mut inner = desc.inner.lock_irqsave();
+        let first = inner.actions.is_empty();
+        if !first {
+            let shareable = flags.contains(IrqHandleFlags::IRQF_SHARED)
+                && inner
+                    .actions
+                    .iter()
+                    .all(|a| a.flags.contains(IrqHandleFlags::IRQF_SHARED) && a.dev_id != dev_id);
+            if !shareable || ack != inner.ack {
+                return Err(SystemError::EBUSY);
+            }
+        } else {
+            let cname = CString::new(action.name.as_str()).map_err(|_| SystemError::EINVAL)?;
+            let r =
+                unsafe { c_irq_install(irq as ul, Some(irq_desc_handler), 0, cname.as_ptr(), ack) };
+            match r as u32 {
+                EINVAL => return Err(SystemError::EINVAL),
+                EAGAIN => return Err(SystemError::EBUSY),
+                _ => {}
+            }
+            inner.ack = ack;
+        }
+        inner.actions.push(action.clone());
+        drop(inner);
+
+        if threaded {
+            let a = action.clone();
+            let pcb = KernelThreadMechanism::create_and_run(
+                KernelThreadClosure::EmptyClosure((
+                    Box::new(move || irq_thread(irq, a.clone())),
+                    (),
+                )),
+                format!("irq/{}-{}", irq, action.name),
+            );
+            match pcb {
+                Some(pcb) => *action.thread.lock_irqsave() = Some(pcb),
+                None => {
+                    self.free_irq(irq, dev_id).ok();
+                    return Err(SystemError::ENOMEM);
+                }
+            }
+        }
+
+        if first {
+            procfs_register_irq(irq).ok();
+        }
+        return Ok(());
+    }
+
+    /// 注销中断处理函数。注销最后一个处理函数时，中断向量会被释放
+    ///
+    /// ## 参数
+    ///
+    /// - `irq`：中断向量号
+    /// - `dev_id`：注册时使用的标识
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/irq/manage.c#1906
+    pub fn free_irq(&self, irq: IrqNumber, dev_id: usize) -> Result<(), SystemError> {
+        let desc = self.desc(irq).ok_or(SystemError::EINVAL)?;
+        let mut inner = desc.inner.lock_irqsave();
+        let pos = inner
+            .actions
+            .iter()
+            .position(|a| a.dev_id == dev_id)
+            .ok_or_else(|| {
+                kwarn!("Trying to free already-free IRQ {}", irq);
+                SystemError::ENOENT
+            })?;
+        let action = inner.actions.remove(pos);
+        let last = inner.actions.is_empty();
+        if last {
+            unsafe { c_irq_uninstall(irq as ul) };
+            inner.chip = None;
+            inner.ack = None;
+            inner.affinity = 0;
+        }
+        drop(inner);
+
+        let thread = action.thread.lock_irqsave().take();
+        if let Some(pcb) = thread {
+            KernelThreadMechanism::stop(&pcb).ok();
+        }
+        if last {
+            procfs_unregister_irq(irq).ok();
+        }
+        return Ok(());
+    }
+
+    /// 设置中断的控制器操作
+    ///
+    /// ## 参数
+    ///
+    /// - `irq`：中断向量号
+    /// - `chip`：控制器操作
+    /// - `affinity`：中断当前被投递到的CPU
+    pub fn set_chip(
+        &self,
+        irq: IrqNumber,
+        chip: Arc<dyn IrqChip>,
+        affinity: usize,
+    ) -> Result<(), SystemError> {
+        let desc = self.desc(irq).ok_or(SystemError::EINVAL)?;
+        let mut inner = desc.inner.lock_irqsave();
+        inner.chip = Some(chip);
+        inner.affinity = affinity;
+        return Ok(());
+    }
+
+    /// 注册者是否已经在中断向量上注册了处理函数
+    pub fn has_action(&self, irq: IrqNumber, dev_id: usize) -> bool {
+        self.desc(irq).map_or(false, |desc| {
+            desc.inner
+                .lock_irqsave()
+                .actions
+                .iter()
+                .any(|a| a.dev_id == dev_id)
+        })
+    }
+
+    /// 把中断投递到指定的CPU上
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：中断向量号不合法，或者CPU不存在、已经下线
+    /// - `EIO`：中断没有控制器操作，无法修改
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/irq/manage.c#260
+    pub fn set_affinity(&self, irq: IrqNumber, cpu: usize) -> Result<(), SystemError> {
+        if !cpu_online(cpu) {
+            return Err(SystemError::EINVAL);
+        }
+        let desc = self.desc(irq).ok_or(SystemError::EINVAL)?;
+        let chip = desc.inner.lock_irqsave().chip.clone();
+        chip.ok_or(SystemError::EIO)?.set_affinity(irq, cpu)?;
+        desc.inner.lock_irqsave().affinity = cpu;
+        return Ok(());
+    }
+
+    /// 获取处理中断的CPU
+    pub fn affinity(&self, irq: IrqNumber) -> Option<usize> {
+        self.desc(irq)
+            .map(|desc| desc.inner.lock_irqsave().affinity)
+    }
+
+    /// 已经注册了处理函数的中断向量
+    pub fn active_irqs(&self) -> Vec<IrqNumber> {
+        IRQ_DESCS
+            .iter()
+            .filter(|desc| !desc.inner.lock_irqsave().actions.is_empty())
+            .map(|desc| desc.irq)
+            .collect()
+    }
+
+    /// 中断向量在指定CPU上发生的次数
+    pub fn irq_count(&self, irq: IrqNumber, cpu: usize) -> u64 {
+        IRQ_COUNTS
+            .get(cpu)
+            .and_then(|counts| counts.get(irq as usize))
+            .map_or(0, |cnt| cnt.load(Ordering::Relaxed))
+    }
+
+    /// 获取中断的名字。对于通过C接口直接注册的中断，使用注册时的名字
+    pub fn irq_name(&self, irq: IrqNumber) -> Option<String> {
+        if let Some(desc) = self.desc(irq) {
+            let names = desc.action_names();
+            if !names.is_empty() {
+                return Some(names.join(", "));
+            }
+        }
+        let name = unsafe { c_irq_name(irq as ul) };
+        if name.is_null() {
+            return None;
+        }
+        return Some(
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned(),
+        );
+    }
+
+    /// 生成/proc/interrupts的内容
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/irq/proc.c#462
+    pub fn show_interrupts(&self) -> String {
+        let cpus = smp_cpu_count();
+        let mut s = String::from("    ");
+        for cpu in 0..cpus {
+            s.push_str(&format!(" {:>10}", format!("CPU{}", cpu)));
+        }
+        s.push('\n');
+
+        for irq in 0..NR_VECTORS as IrqNumber {
+            let name = self.irq_name(irq);
+            let counts: Vec<u64> = (0..cpus).map(|cpu| self.irq_count(irq, cpu)).collect();
+            if name.is_none() && counts.iter().all(|c| *c == 0) {
+                continue;
+            }
+            s.push_str(&format!("{:>3}:", irq));
+            for cnt in counts {
+                s.push_str(&format!(" {:>10}", cnt));
+            }
+            s.push_str(&format!("  {}\n", name.unwrap_or_default()));
+        }
+        return s;
+    }
+}
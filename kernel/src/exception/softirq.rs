@@ -69,6 +69,8 @@ pub enum SoftirqNumber {
     /// 时钟软中断信号
     TIMER = 0,
     VideoRefresh = 1, //帧缓冲区刷新软中断
+    /// POSIX定时器发送信号
+    PosixTimer = 2,
 }
 
 impl From<u64> for SoftirqNumber {
@@ -82,6 +84,7 @@ bitflags! {
     pub struct VecStatus: u64 {
         const TIMER = 1 << 0;
         const VIDEO_REFRESH = 1 << 1;
+        const POSIX_TIMER = 1 << 2;
     }
 }
 
@@ -7,6 +7,11 @@
 #include <sched/sched.h>
 
 extern void ignore_int();
+extern bool rs_fixup_exception(struct pt_regs *regs);
+extern bool rs_handle_page_fault(struct pt_regs *regs, unsigned long error_code, unsigned long address);
+extern bool rs_kprobe_handler(struct pt_regs *regs);
+extern bool rs_kexec_nmi_handler(struct pt_regs *regs);
+extern bool rs_perf_nmi_handler(struct pt_regs *regs);
 
 // 0 #DE 除法错误
 void do_divide_error(struct pt_regs *regs, unsigned long error_code)
@@ -34,6 +39,13 @@ void do_debug(struct pt_regs *regs, unsigned long error_code)
 // 2 不可屏蔽中断
 void do_nmi(struct pt_regs *regs, unsigned long error_code)
 {
+    // kexec要求当前cpu停止运行
+    if (rs_kexec_nmi_handler(regs))
+        return;
+
+    // 性能计数器溢出产生的中断
+    if (rs_perf_nmi_handler(regs))
+        return;
 
     printk("[ ");
     printk_color(BLUE, BLACK, "INT");
@@ -47,6 +59,9 @@ void do_nmi(struct pt_regs *regs, unsigned long error_code)
 // 3 #BP 断点异常
 void do_int3(struct pt_regs *regs, unsigned long error_code)
 {
+    // kprobe放置的断点
+    if (rs_kprobe_handler(regs))
+        return;
 
     printk("[ ");
     printk_color(YELLOW, BLACK, "TRAP");
@@ -207,6 +222,14 @@ void do_page_fault(struct pt_regs *regs, unsigned long error_code)
 
     __asm__ __volatile__("movq	%%cr2,	%0" : "=r"(cr2)::"memory");
 
+    // 写入只读的匿名页(零页或者fork之后共享的页)，进行写时复制
+    if (rs_handle_page_fault(regs, error_code, cr2))
+        return;
+
+    // 内核在访问用户空间内存时出错，尝试通过异常表进行修复
+    if (!(error_code & 0x04) && rs_fixup_exception(regs))
+        return;
+
     kerror("do_page_fault(14),Error code :%#018lx,RSP:%#018lx, RBP=%#018lx, RIP:%#018lx CPU:%d, pid=%d\n", error_code,
            regs->rsp, regs->rbp, regs->rip, rs_current_pcb_cpuid(), rs_current_pcb_pid());
     kerror("regs->rax = %#018lx\n", regs->rax);
@@ -0,0 +1,130 @@
+//! 匿名inode文件系统
+//!
+//! io_uring、perf event等没有路径的文件描述符，它们的inode都属于这个文件系统。
+//! 该文件系统不会被挂载到目录树中，只是让这些inode的[`IndexNode::fs`]能够返回一个真实的文件系统，
+//! 使得fstat等通过`inode.fs()`访问文件系统的路径能够正常工作。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/anon_inodes.c
+
+use core::any::Any;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    filesystem::vfs::{
+        core::generate_inode_id, syscall::ModeType, FilePrivateData, FileSystem, FileType, FsInfo,
+        IndexNode, Metadata, PollStatus, PollTable,
+    },
+    syscall::SystemError,
+    time::TimeSpec,
+};
+
+/// 匿名inode文件系统中文件名的最大长度。该文件系统中没有目录项，与Linux的NAME_MAX保持一致
+const ANON_INODE_MAX_NAMELEN: usize = 255;
+
+lazy_static! {
+    /// 所有匿名inode共享的文件系统
+    static ref ANON_INODE_FS: Arc<AnonInodeFS> = AnonInodeFS::new();
+}
+
+/// @brief 获取匿名inode文件系统
+pub fn anon_inode_fs() -> Arc<dyn FileSystem> {
+    return ANON_INODE_FS.clone();
+}
+
+/// @brief 匿名inode文件系统
+#[derive(Debug)]
+pub struct AnonInodeFS {
+    /// 文件系统的根inode，即Linux中所有匿名文件共享的那个inode
+    root_inode: Arc<AnonInode>,
+}
+
+impl AnonInodeFS {
+    fn new() -> Arc<Self> {
+        return Arc::new(Self {
+            root_inode: Arc::new(AnonInode {
+                metadata: Metadata {
+                    dev_id: 0,
+                    inode_id: generate_inode_id(),
+                    size: 0,
+                    blk_size: 0,
+                    blocks: 0,
+                    atime: TimeSpec::default(),
+                    mtime: TimeSpec::default(),
+                    ctime: TimeSpec::default(),
+                    file_type: FileType::File,
+                    mode: ModeType::from_bits_truncate(0o600),
+                    nlinks: 1,
+                    uid: 0,
+                    gid: 0,
+                    raw_dev: 0,
+                },
+            }),
+        });
+    }
+}
+
+impl FileSystem for AnonInodeFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: ANON_INODE_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// @brief 匿名inode文件系统的根inode。它不对应任何文件，不能被读写
+#[derive(Debug)]
+pub struct AnonInode {
+    metadata: Metadata,
+}
+
+impl IndexNode for AnonInode {
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::empty());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return anon_inode_fs();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        return Err(SystemError::ENOTDIR);
+    }
+}
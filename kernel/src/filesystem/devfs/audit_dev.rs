@@ -0,0 +1,188 @@
+//! /dev/audit：按记录读取审计日志，通过ioctl查询和设置审计子系统的状态
+//!
+//! Linux通过netlink套接字与审计守护进程通信，这里用字符设备代替，ioctl的命令号与netlink消息类型相同
+
+use crate::audit::{audit_ioctl, AUDIT_BUF, AUDIT_WAIT};
+use crate::filesystem::vfs::file::FileMode;
+use crate::filesystem::vfs::make_rawdev;
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{
+    core::generate_inode_id, FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+    PollStatus, PollTable,
+};
+use crate::process::ProcessManager;
+use crate::{libs::spinlock::SpinLock, syscall::SystemError, time::TimeSpec};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use super::{DevFS, DeviceINode};
+
+/// 每个打开的/dev/audit文件的读取位置
+#[derive(Debug, Clone)]
+pub struct AuditFilePrivateData {
+    mode: FileMode,
+    /// 下一次读取的记录的序号
+    seq: u64,
+}
+
+impl AuditFilePrivateData {
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditInode {
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedAuditInode>,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevFS>,
+    /// INode 元数据
+    metadata: Metadata,
+}
+
+#[derive(Debug)]
+pub struct LockedAuditInode(SpinLock<AuditInode>);
+
+impl LockedAuditInode {
+    pub fn new() -> Arc<Self> {
+        let inode = AuditInode {
+            self_ref: Weak::default(),
+            fs: Weak::default(),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o600),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: make_rawdev(1, 12),
+            },
+        };
+
+        let result = Arc::new(LockedAuditInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        return result;
+    }
+}
+
+impl DeviceINode for LockedAuditInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.0.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LockedAuditInode {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        *data = FilePrivateData::Audit(AuditFilePrivateData {
+            mode: *mode,
+            seq: AUDIT_BUF.lock_irqsave().first_seq(),
+        });
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ);
+    }
+
+    fn ioctl(&self, cmd: u32, data: usize) -> Result<usize, SystemError> {
+        return audit_ioctl(cmd, data);
+    }
+
+    /// 每次读取一条记录。缓冲区放不下一条记录时返回EINVAL；
+    /// 要读取的记录已经被丢弃时返回一次EPIPE，之后从最早的记录继续读取
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let private = match data {
+            FilePrivateData::Audit(p) => p,
+            _ => return Err(SystemError::EINVAL),
+        };
+
+        let record = loop {
+            let guard = AUDIT_BUF.lock_irqsave();
+            if private.seq < guard.first_seq() {
+                private.seq = guard.first_seq();
+                return Err(SystemError::EPIPE);
+            }
+            if let Some(r) = guard.record(private.seq) {
+                break r.clone();
+            }
+            if private.mode.contains(FileMode::O_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if ProcessManager::current_pcb()
+                .sig_info()
+                .has_pending_signal()
+            {
+                return Err(SystemError::EINTR);
+            }
+            AUDIT_WAIT.sleep_unlock_spinlock(guard);
+        };
+
+        let len = core::cmp::min(len, buf.len());
+        if record.len() > len {
+            return Err(SystemError::EINVAL);
+        }
+        buf[..record.len()].copy_from_slice(record.as_bytes());
+        private.seq += 1;
+        return Ok(record.len());
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EPERM);
+    }
+}
@@ -0,0 +1,206 @@
+//! /dev/kmsg：按记录读取内核日志，写入的内容作为一条新的日志记录
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/printk/printk.c#devkmsg_read
+
+use crate::filesystem::vfs::file::FileMode;
+use crate::filesystem::vfs::make_rawdev;
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{
+    core::generate_inode_id, FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+    PollStatus, PollTable,
+};
+use crate::libs::log_buf::{LogLevel, LOG_BUF, LOG_WAIT};
+use crate::libs::printk::PrintkWriter;
+use crate::process::ProcessManager;
+use crate::{libs::spinlock::SpinLock, syscall::SystemError, time::TimeSpec};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use super::{DevFS, DeviceINode};
+
+/// 每个打开的/dev/kmsg文件的读取位置
+#[derive(Debug, Clone)]
+pub struct KmsgFilePrivateData {
+    mode: FileMode,
+    /// 下一次读取的记录的序号
+    seq: u64,
+}
+
+impl KmsgFilePrivateData {
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+}
+
+#[derive(Debug)]
+pub struct KmsgInode {
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedKmsgInode>,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevFS>,
+    /// INode 元数据
+    metadata: Metadata,
+}
+
+#[derive(Debug)]
+pub struct LockedKmsgInode(SpinLock<KmsgInode>);
+
+impl LockedKmsgInode {
+    pub fn new() -> Arc<Self> {
+        let inode = KmsgInode {
+            self_ref: Weak::default(),
+            fs: Weak::default(),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o644),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: make_rawdev(1, 11),
+            },
+        };
+
+        let result = Arc::new(LockedKmsgInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        return result;
+    }
+}
+
+impl DeviceINode for LockedKmsgInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.0.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LockedKmsgInode {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        *data = FilePrivateData::Kmsg(KmsgFilePrivateData {
+            mode: *mode,
+            seq: LOG_BUF.lock_irqsave().first_seq(),
+        });
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+
+    /// 每次读取一条记录。缓冲区放不下一条记录时返回EINVAL；
+    /// 要读取的记录已经被覆盖时返回一次EPIPE，之后从最早的记录继续读取
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let private = match data {
+            FilePrivateData::Kmsg(p) => p,
+            _ => return Err(SystemError::EINVAL),
+        };
+
+        let line = loop {
+            let guard = LOG_BUF.lock_irqsave();
+            if private.seq < guard.first_seq() {
+                private.seq = guard.first_seq();
+                return Err(SystemError::EPIPE);
+            }
+            if let Some(r) = guard.record(private.seq) {
+                break r.format_kmsg();
+            }
+            if private.mode.contains(FileMode::O_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if ProcessManager::current_pcb()
+                .sig_info()
+                .has_pending_signal()
+            {
+                return Err(SystemError::EINTR);
+            }
+            LOG_WAIT.sleep_unlock_spinlock(guard);
+        };
+
+        let len = core::cmp::min(len, buf.len());
+        if line.len() > len {
+            return Err(SystemError::EINVAL);
+        }
+        buf[..line.len()].copy_from_slice(line.as_bytes());
+        private.seq += 1;
+        return Ok(line.len());
+    }
+
+    /// 写入的内容作为一条日志记录，开头可以用`<N>`指定日志级别
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let text = core::str::from_utf8(&buf[..len]).map_err(|_| SystemError::EINVAL)?;
+
+        let mut level = LogLevel::DEFAULT;
+        let mut msg = text;
+        if let Some(rest) = text.strip_prefix('<') {
+            if let Some((n, rest)) = rest.split_once('>') {
+                if let Some(l) = n.parse::<u8>().ok().and_then(|n| LogLevel::from_u8(n & 7)) {
+                    level = l;
+                    msg = rest;
+                }
+            }
+        }
+
+        let msg = msg.trim_end_matches('\n');
+        if !msg.is_empty() {
+            PrintkWriter.__log(level, format_args!("{}\n", msg));
+        }
+        return Ok(len);
+    }
+}
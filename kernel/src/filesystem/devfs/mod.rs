@@ -1,14 +1,18 @@
 /// 导出devfs的模块
+pub mod audit_dev;
+pub mod kmsg_dev;
 pub mod null_dev;
 pub mod zero_dev;
 
 use super::vfs::{
     core::{generate_inode_id, ROOT_INODE},
     file::FileMode,
+    list_dir_entries,
     syscall::ModeType,
-    FileSystem, FileType, FsInfo, IndexNode, Metadata, PollStatus,
+    DirEntry, FileSystem, FileType, FsInfo, IndexNode, Metadata, PollStatus, PollTable,
 };
 use crate::{
+    driver::base::device::dd::driver_deferred_probe_trigger,
     kerror, kinfo,
     libs::{
         once::Once,
@@ -17,6 +21,8 @@ use crate::{
     syscall::SystemError,
     time::TimeSpec,
 };
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use alloc::{
     collections::BTreeMap,
     string::{String, ToString},
@@ -76,6 +82,14 @@ impl DevFS {
 
         root.add_dir("block")
             .expect("DevFS: Failed to create /dev/block");
+
+        // POSIX共享内存对象所在的目录，vfs初始化时会在这里挂载一个ramfs
+        root.add_dir("shm")
+            .expect("DevFS: Failed to create /dev/shm");
+
+        // POSIX消息队列所在的目录，vfs初始化时会在这里挂载mqueue文件系统
+        root.add_dir("mqueue")
+            .expect("DevFS: Failed to create /dev/mqueue");
         devfs.register_bultinin_device();
 
         // kdebug!("ls /dev: {:?}", root.list());
@@ -84,6 +98,9 @@ impl DevFS {
 
     /// @brief 注册系统内部自带的设备
     fn register_bultinin_device(&self) {
+        use crate::filesystem::fuse::dev::LockedFuseDevInode;
+        use audit_dev::LockedAuditInode;
+        use kmsg_dev::LockedKmsgInode;
         use null_dev::LockedNullInode;
         use zero_dev::LockedZeroInode;
         let dev_root: Arc<LockedDevFSInode> = self.root_inode.clone();
@@ -93,6 +110,15 @@ impl DevFS {
         dev_root
             .add_dev("zero", LockedZeroInode::new())
             .expect("DevFS: Failed to register /dev/zero");
+        dev_root
+            .add_dev("kmsg", LockedKmsgInode::new())
+            .expect("DevFS: Failed to register /dev/kmsg");
+        dev_root
+            .add_dev("audit", LockedAuditInode::new())
+            .expect("DevFS: Failed to register /dev/audit");
+        dev_root
+            .add_dev("fuse", LockedFuseDevInode::new())
+            .expect("DevFS: Failed to register /dev/fuse");
     }
 
     /// @brief 在devfs内注册设备
@@ -122,6 +148,26 @@ impl DevFS {
                     .as_any_ref()
                     .downcast_ref::<LockedDevFSInode>()
                     .unwrap();
+                // 名字中带有目录的设备(如input/event0)，同时挂载在 /dev 下对应的目录中
+                if let Some((dir, base)) = name.split_once('/') {
+                    if let Err(_) = dev_root_inode.find(dir) {
+                        dev_root_inode.create(
+                            dir,
+                            FileType::Dir,
+                            ModeType::from_bits_truncate(0o755),
+                        )?;
+                    }
+                    let any_dir_inode = dev_root_inode.find(dir)?;
+                    let dev_dir_inode: &LockedDevFSInode = any_dir_inode
+                        .as_any_ref()
+                        .downcast_ref::<LockedDevFSInode>()
+                        .unwrap();
+                    dev_char_inode.add_dev(base, device.clone())?;
+                    dev_dir_inode.add_dev(base, device.clone())?;
+                    device.set_fs(dev_char_inode.0.lock().fs.clone());
+                    return Ok(());
+                }
+
                 // 在 /dev/char 下创建设备节点
                 dev_char_inode.add_dev(name, device.clone())?;
 
@@ -168,27 +214,41 @@ impl DevFS {
         name: &str,
         device: Arc<T>,
     ) -> Result<(), SystemError> {
+        return self.unregister_node(name, device.metadata()?.file_type);
+    }
+
+    /// @brief 删除register_device为设备创建的所有节点
+    ///
+    /// @param name 设备名称
+    /// @param file_type 设备文件的类型
+    pub fn unregister_node(&self, name: &str, file_type: FileType) -> Result<(), SystemError> {
         let dev_root_inode: Arc<LockedDevFSInode> = self.root_inode.clone();
-        match device.metadata().unwrap().file_type {
+        match file_type {
             // 字节设备挂载在 /dev/char
             FileType::CharDevice => {
-                if let Err(_) = dev_root_inode.find("char") {
-                    return Err(SystemError::ENOENT);
-                }
-
                 let any_char_inode = dev_root_inode.find("char")?;
                 let dev_char_inode = any_char_inode
                     .as_any_ref()
                     .downcast_ref::<LockedDevFSInode>()
                     .unwrap();
                 // TODO： 调用设备的卸载接口（当引入卸载接口之后）
+                if let Some((dir, base)) = name.split_once('/') {
+                    let any_dir_inode = dev_root_inode.find(dir)?;
+                    let dev_dir_inode = any_dir_inode
+                        .as_any_ref()
+                        .downcast_ref::<LockedDevFSInode>()
+                        .unwrap();
+                    dev_char_inode.remove(base)?;
+                    dev_dir_inode.remove(base)?;
+                    return Ok(());
+                }
+
                 dev_char_inode.remove(name)?;
+                if name.starts_with("tty") && name.len() > 3 {
+                    dev_root_inode.remove(name)?;
+                }
             }
             FileType::BlockDevice => {
-                if let Err(_) = dev_root_inode.find("block") {
-                    return Err(SystemError::ENOENT);
-                }
-
                 let any_block_inode = dev_root_inode.find("block")?;
                 let dev_block_inode = any_block_inode
                     .as_any_ref()
@@ -473,6 +533,24 @@ impl IndexNode for LockedDevFSInode {
         return Ok(keys);
     }
 
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let (ino, parent) = {
+            let inode = self.0.lock();
+            if inode.metadata.file_type != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (inode.metadata.inode_id, inode.parent.upgrade())
+        };
+        let parent_ino = parent.map_or(ino, |parent| parent.0.lock().metadata.inode_id);
+
+        let inode = self.0.lock();
+        return list_dir_entries(ino, parent_ino, inode.children.iter(), offset, filler);
+    }
+
     fn metadata(&self) -> Result<Metadata, SystemError> {
         return Ok(self.0.lock().metadata.clone());
     }
@@ -489,7 +567,7 @@ impl IndexNode for LockedDevFSInode {
         return Ok(());
     }
 
-    fn poll(&self) -> Result<super::vfs::PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<super::vfs::PollStatus, SystemError> {
         // 加锁
         let inode: SpinLockGuard<DevFSInode> = self.0.lock();
 
@@ -552,6 +630,15 @@ macro_rules! devfs_exact_ref {
     .downcast_ref::<DevFS>()
     .unwrap()};
 }
+/// devfs是否已经挂载
+static DEVFS_READY: AtomicBool = AtomicBool::new(false);
+
+/// devfs是否已经挂载。驱动在devfs挂载之前探测设备时，应当返回`EPROBE_DEFER`，等待devfs挂载后重新探测
+#[inline]
+pub fn devfs_ready() -> bool {
+    DEVFS_READY.load(Ordering::SeqCst)
+}
+
 /// @brief devfs的设备注册函数
 pub fn devfs_register<T: DeviceINode>(name: &str, device: Arc<T>) -> Result<(), SystemError> {
     return devfs_exact_ref!().register_device(name, device);
@@ -563,6 +650,11 @@ pub fn devfs_unregister<T: DeviceINode>(name: &str, device: Arc<T>) -> Result<()
     return devfs_exact_ref!().unregister_device(name, device);
 }
 
+/// @brief 按照设备名称与设备文件的类型，删除devfs中的设备节点
+pub fn devfs_unregister_node(name: &str, file_type: FileType) -> Result<(), SystemError> {
+    return devfs_exact_ref!().unregister_node(name, file_type);
+}
+
 pub fn devfs_init() -> Result<(), SystemError> {
     static INIT: Once = Once::new();
     let mut result = None;
@@ -577,6 +669,9 @@ pub fn devfs_init() -> Result<(), SystemError> {
             .mount(devfs)
             .expect("Failed to mount devfs");
         kinfo!("DevFS mounted.");
+        DEVFS_READY.store(true, Ordering::SeqCst);
+        // 因为devfs还没有挂载而推迟探测的设备，现在可以重新探测了
+        driver_deferred_probe_trigger();
         result = Some(Ok(()));
     });
 
@@ -2,7 +2,8 @@ use crate::filesystem::vfs::file::FileMode;
 use crate::filesystem::vfs::make_rawdev;
 use crate::filesystem::vfs::syscall::ModeType;
 use crate::filesystem::vfs::{
-    core::generate_inode_id, FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus,
+    core::generate_inode_id, FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+    PollStatus, PollTable,
 };
 use crate::{libs::spinlock::SpinLock, syscall::SystemError, time::TimeSpec};
 use alloc::{
@@ -102,7 +103,7 @@ impl IndexNode for LockedZeroInode {
         return Ok(());
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         return Ok(PollStatus::READ | PollStatus::WRITE);
     }
 
@@ -2,9 +2,9 @@
 use alloc::{sync::Arc, vec::Vec};
 
 use crate::{
-    driver::base::block::{block_device::LBA_SIZE, disk_info::Partition, SeekFrom},
+    driver::base::block::{block_device::LBA_SIZE, disk_info::Partition},
     kerror,
-    libs::vec_cursor::VecCursor,
+    libs::byte_cursor::ByteReader,
     syscall::SystemError,
 };
 
@@ -229,7 +229,7 @@ impl BiosParameterBlock {
             .read_at(partition.lba_start as usize, 1, &mut v)?;
 
         // 获取指针对象
-        let mut cursor = VecCursor::new(v);
+        let mut cursor = ByteReader::new(&v);
 
         let mut bpb = BiosParameterBlock::default();
 
@@ -265,7 +265,7 @@ impl BiosParameterBlock {
         cursor.read_exact(&mut bpb32.filesystem_type)?;
 
         // 跳过启动代码
-        cursor.seek(SeekFrom::SeekCurrent(420))?;
+        cursor.skip(420)?;
         // 读取尾部的启动扇区标志
         bpb.trail_sig = cursor.read_u16()?;
 
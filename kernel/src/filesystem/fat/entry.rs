@@ -4,8 +4,9 @@ use core::{cmp::min, intrinsics::unlikely};
 use crate::{
     driver::base::block::{block_device::LBA_SIZE, SeekFrom},
     kwarn,
-    libs::vec_cursor::VecCursor,
+    libs::byte_cursor::{ByteReader, ByteWriter},
     syscall::SystemError,
+    time::{timekeeping::getnstimeofday, TimeSpec},
 };
 use alloc::{
     string::{String, ToString},
@@ -15,7 +16,7 @@ use alloc::{
 
 use super::{
     fs::{Cluster, FATFileSystem, MAX_FILE_SIZE},
-    utils::decode_u8_ascii,
+    utils::{decode_u8_ascii, fat_time_to_unix, unix_to_fat_time},
 };
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -144,7 +145,6 @@ impl FATFile {
                 break;
             }
         }
-        // todo: 更新时间信息
         return Ok(read_ok);
     }
 
@@ -163,6 +163,10 @@ impl FATFile {
     ) -> Result<usize, SystemError> {
         self.ensure_len(fs, offset, buf.len() as u64)?;
 
+        // 更新修改时间，并把短目录项(包括ensure_len更新的文件大小、第一个簇)写入磁盘
+        self.short_dir_entry.set_wrt_time(&getnstimeofday());
+        self.flush_short_entry(fs)?;
+
         // 要写入的第一个簇的簇号
         let start_cluster_num = offset / fs.bytes_per_cluster();
         // 获取要写入的第一个簇
@@ -198,7 +202,7 @@ impl FATFile {
             // 计算本次写入位置在磁盘上的偏移量
             let offset = fs.cluster_bytes_offset(current_cluster) + in_cluster_bytes_offset;
             // 写入磁盘
-            let w: usize = fs.partition.disk().write_at(
+            let w: usize = fs.partition.disk().write_at_bytes(
                 offset as usize,
                 end_len,
                 &buf[start..start + end_len],
@@ -213,12 +217,12 @@ impl FATFile {
                 break;
             }
         }
-        // todo: 更新时间信息
         return Ok(write_ok);
     }
 
     /// @brief 确保文件从指定偏移量开始，仍有长度为len的空间。
     /// 如果文件大小不够，就尝试分配更多的空间给这个文件。
+    /// 本函数只更新内存中的短目录项，由调用者把它写入磁盘
     ///
     /// @param fs 当前文件所属的文件系统
     /// @param offset 起始位置在文件内的字节偏移量
@@ -303,14 +307,16 @@ impl FATFile {
         // 计算文件的新大小
         let new_size = self.size() + extra_bytes;
         self.set_size(new_size as u32);
-        // 计算短目录项所在的位置，更新短目录项
-        let short_entry_offset = fs.cluster_bytes_offset(self.loc.1 .0) + self.loc.1 .1;
-        // todo: 更新时间信息
-        // 把短目录项写入磁盘
-        self.short_dir_entry.flush(fs, short_entry_offset)?;
         return Ok(());
     }
 
+    /// @brief 把文件的短目录项写入磁盘
+    fn flush_short_entry(&self, fs: &Arc<FATFileSystem>) -> Result<(), SystemError> {
+        // 计算短目录项在磁盘内的字节偏移量
+        let short_entry_offset = fs.cluster_bytes_offset(self.loc.1 .0) + self.loc.1 .1;
+        return self.short_dir_entry.flush(fs, short_entry_offset);
+    }
+
     /// @brief 把磁盘上[range_start, range_end)范围的数据清零
     ///
     /// @param range_start 磁盘上起始位置（单位：字节）
@@ -326,9 +332,11 @@ impl FATFile {
         }
 
         let zeroes: Vec<u8> = vec![0u8; (range_end - range_start) as usize];
-        fs.partition
-            .disk()
-            .write_at(range_start as usize, zeroes.len(), zeroes.as_slice())?;
+        fs.partition.disk().write_at_bytes(
+            range_start as usize,
+            zeroes.len(),
+            zeroes.as_slice(),
+        )?;
         return Ok(());
     }
 
@@ -357,9 +365,8 @@ impl FATFile {
         }
 
         self.set_size(new_size as u32);
-        // 计算短目录项在磁盘内的字节偏移量
-        let short_entry_offset = fs.cluster_bytes_offset((self.loc.1).0) + (self.loc.1).1;
-        self.short_dir_entry.flush(fs, short_entry_offset)?;
+        self.short_dir_entry.set_wrt_time(&getnstimeofday());
+        self.flush_short_entry(fs)?;
 
         return Ok(());
     }
@@ -568,12 +575,14 @@ impl FATDir {
             FATDirEntryOrShortName::ShortName(short_name) => {
                 // 确认名称是一个可行的长文件名
                 LongDirEntry::validate_long_name(name)?;
+                let mut short_entry = ShortDirEntry::default();
+                short_entry.set_crt_time(&getnstimeofday());
                 // 创建目录项
                 let x: Result<FATFile, SystemError> = self
                     .create_dir_entries(
                         name.trim(),
                         &short_name,
-                        None,
+                        Some(short_entry),
                         FileAttributes {
                             value: FileAttributes::ARCHIVE,
                         },
@@ -609,7 +618,9 @@ impl FATDir {
             FATDirEntryOrShortName::ShortName(short_name) => {
                 LongDirEntry::validate_long_name(name)?;
                 // 目标目录项
+                let now = getnstimeofday();
                 let mut short_entry = ShortDirEntry::default();
+                short_entry.set_crt_time(&now);
 
                 let first_cluster: Cluster = fs.allocate_cluster(None)?;
                 short_entry.set_first_cluster(first_cluster);
@@ -621,8 +632,8 @@ impl FATDir {
                 dot_entry.name = ShortNameGenerator::new(".").generate().unwrap();
                 dot_entry.attributes.value = FileAttributes::DIRECTORY;
                 dot_entry.set_first_cluster(first_cluster);
+                dot_entry.set_crt_time(&now);
 
-                // todo: 设置创建、访问时间
                 dot_entry.flush(&fs, fs.cluster_bytes_offset(first_cluster) + offset)?;
 
                 drop(dot_entry);
@@ -634,7 +645,7 @@ impl FATDir {
                 dot_dot_entry.name = ShortNameGenerator::new("..").generate().unwrap();
                 dot_dot_entry.attributes.value = FileAttributes::DIRECTORY;
                 dot_dot_entry.set_first_cluster(self.first_cluster);
-                // todo: 设置创建、访问时间
+                dot_dot_entry.set_crt_time(&now);
 
                 dot_dot_entry.flush(&fs, fs.cluster_bytes_offset(first_cluster) + offset)?;
 
@@ -725,8 +736,6 @@ impl FATDir {
         short_dentry.name = short_name.clone();
         short_dentry.attributes = attrs;
 
-        // todo: 设置创建时间、修改时间
-
         let mut long_name_gen: LongNameEntryGenerator =
             LongNameEntryGenerator::new(long_name, short_dentry.checksum());
         let num_entries = long_name_gen.num_entries() as u64;
@@ -1091,7 +1100,7 @@ impl LongDirEntry {
             .disk()
             .read_at(lba, 1 * fs.lba_per_sector(), &mut v)?;
 
-        let mut cursor: VecCursor = VecCursor::new(v);
+        let mut cursor = ByteWriter::new(&mut v);
         // 切换游标到对应位置
         cursor.seek(SeekFrom::SeekSet(blk_offset as i64))?;
 
@@ -1305,7 +1314,7 @@ impl ShortDirEntry {
             .disk()
             .read_at(lba, 1 * fs.lba_per_sector(), &mut v)?;
 
-        let mut cursor: VecCursor = VecCursor::new(v);
+        let mut cursor = ByteWriter::new(&mut v);
         // 切换游标到对应位置
         cursor.seek(SeekFrom::SeekSet(blk_offset as i64))?;
         cursor.write_exact(&self.name)?;
@@ -1335,6 +1344,40 @@ impl ShortDirEntry {
         self.fst_clus_lo = (cluster.cluster_num & 0x0000ffff) as u16;
         self.fst_clus_hi = ((cluster.cluster_num & 0xffff0000) >> 16) as u16;
     }
+
+    /// @brief 设置创建时间，同时把最后写入时间和最后访问日期设置为相同的值
+    pub fn set_crt_time(&mut self, ts: &TimeSpec) {
+        let (date, time, time_tenth) = unix_to_fat_time(ts);
+        self.crt_date = date;
+        self.crt_time = time;
+        self.crt_time_tenth = time_tenth;
+        self.wrt_date = date;
+        self.wrt_time = time;
+        self.lst_acc_date = date;
+    }
+
+    /// @brief 设置最后写入时间。写入也是一次访问，因此同时更新最后访问日期
+    pub fn set_wrt_time(&mut self, ts: &TimeSpec) {
+        let (date, time, _) = unix_to_fat_time(ts);
+        self.wrt_date = date;
+        self.wrt_time = time;
+        self.lst_acc_date = date;
+    }
+
+    /// @brief 获取创建时间
+    pub fn crt_time(&self) -> TimeSpec {
+        return fat_time_to_unix(self.crt_date, self.crt_time, self.crt_time_tenth);
+    }
+
+    /// @brief 获取最后写入时间
+    pub fn wrt_time(&self) -> TimeSpec {
+        return fat_time_to_unix(self.wrt_date, self.wrt_time, 0);
+    }
+
+    /// @brief 获取最后访问时间（FAT只记录日期）
+    pub fn acc_time(&self) -> TimeSpec {
+        return fat_time_to_unix(self.lst_acc_date, 0, 0);
+    }
 }
 
 /// @brief FAT文件系统标准定义的目录项
@@ -2368,7 +2411,7 @@ pub fn get_raw_dir_entry(
 
     fs.partition.disk().read_at(lba, 1, &mut v)?;
 
-    let mut cursor: VecCursor = VecCursor::new(v);
+    let mut cursor = ByteReader::new(&v);
     // 切换游标到对应位置
     cursor.seek(SeekFrom::SeekSet(blk_offset as i64))?;
 
@@ -2382,7 +2425,7 @@ pub fn get_raw_dir_entry(
             return Ok(FATRawDirEntry::Free);
         }
         _ => {
-            cursor.seek(SeekFrom::SeekCurrent(10))?;
+            cursor.skip(10)?;
             let file_attr: FileAttributes = FileAttributes::new(cursor.read_u8()?);
 
             // 指针回到目录项的开始处
@@ -16,13 +16,14 @@ use crate::{
     filesystem::vfs::{
         core::generate_inode_id,
         file::{FileMode, FilePrivateData},
+        fsck::FsckReport,
         syscall::ModeType,
-        FileSystem, FileType, IndexNode, InodeId, Metadata, PollStatus,
+        DirEntry, FileSystem, FileType, IndexNode, InodeId, Metadata, PollStatus, PollTable,
     },
     kerror,
     libs::{
+        byte_cursor::{ByteReader, ByteWriter},
         spinlock::{SpinLock, SpinLockGuard},
-        vec_cursor::VecCursor,
     },
     syscall::SystemError,
     time::TimeSpec,
@@ -114,19 +115,27 @@ pub struct FATInode {
 impl FATInode {
     /// @brief 更新当前inode的元数据
     pub fn update_metadata(&mut self) {
-        // todo: 更新文件的访问时间等信息
-        match &self.inode_type {
+        let short_dir_entry = match &self.inode_type {
             FATDirEntry::File(f) | FATDirEntry::VolId(f) => {
                 self.metadata.size = f.size() as i64;
+                Some(f.short_dir_entry)
             }
             FATDirEntry::Dir(d) => {
                 self.metadata.size = d.size(&self.fs.upgrade().unwrap().clone()) as i64;
+                d.short_dir_entry
             }
             FATDirEntry::UnInit => {
                 kerror!("update_metadata: Uninitialized FATDirEntry: {:?}", self);
                 return;
             }
         };
+
+        // 根目录没有短目录项，也就没有时间信息
+        if let Some(e) = short_dir_entry {
+            self.metadata.atime = e.acc_time();
+            self.metadata.mtime = e.wrt_time();
+            self.metadata.ctime = e.crt_time();
+        }
     }
 
     fn find(&mut self, name: &str) -> Result<Arc<LockedFATInode>, SystemError> {
@@ -163,6 +172,18 @@ impl FATInode {
             }
         }
     }
+
+    /// @brief 获取目录项对应的子inode，不在缓存区中时创建一个并加入缓存区
+    fn cached_child(&mut self, ent: FATDirEntry) -> Arc<LockedFATInode> {
+        // 由于FAT文件系统的大小写不敏感问题，因此存入缓存区的key应当是全大写的
+        let key = ent.name().to_uppercase();
+        if let Some(child) = self.children.get(&key) {
+            return child.clone();
+        }
+        let child = LockedFATInode::new(self.fs.upgrade().unwrap(), self.self_ref.clone(), ent);
+        self.children.insert(key, child.clone());
+        return child;
+    }
 }
 
 impl LockedFATInode {
@@ -243,6 +264,69 @@ impl FileSystem for FATFileSystem {
         todo!()
     }
 
+    /// @brief 检查引导扇区、FAT表头部以及卷的脏标志
+    fn fsck(&self) -> Result<FsckReport, SystemError> {
+        let mut report = FsckReport::new();
+
+        if self.bpb.trail_sig != 0xAA55 {
+            report.error(format!(
+                "FAT: invalid boot sector signature {:#06x}",
+                self.bpb.trail_sig
+            ));
+        }
+
+        if !self.is_shut_bit_ok()? {
+            report.warn(String::from(
+                "FAT: volume was not properly unmounted, some data may be corrupt",
+            ));
+        }
+        if !self.is_hard_error_bit_ok()? {
+            report.error(String::from(
+                "FAT: disk I/O errors were recorded on the last mount",
+            ));
+        }
+
+        // FAT[0]的低8位应当与BPB中的媒体描述符一致
+        let media = self.get_fat_entry_raw(Cluster::new(0))? & 0xff;
+        if media != self.bpb.media as u64 {
+            report.error(format!(
+                "FAT: media descriptor in FAT ({:#04x}) does not match BPB ({:#04x})",
+                media, self.bpb.media
+            ));
+        }
+
+        if let FATType::FAT32(bpb32) = self.bpb.fat_type {
+            // 引导扇区的备份应当与主引导扇区中的BPB一致
+            if bpb32.backup_boot_sec != 0 {
+                let primary = self.read_sector(0)?;
+                let backup = self.read_sector(bpb32.backup_boot_sec as u64)?;
+                if primary[..Self::BPB_SIZE] != backup[..Self::BPB_SIZE] {
+                    report.warn(String::from(
+                        "FAT: backup boot sector differs from the primary boot sector",
+                    ));
+                }
+            }
+        }
+
+        // 启用镜像时，各个FAT表应当一致。前两个表项保存的是媒体描述符和脏标志，不参与比较
+        if self.mirroring_enabled() && self.bpb.num_fats > 1 {
+            let reserved_bytes = match self.bpb.fat_type {
+                FATType::FAT12(_) => 3,
+                FATType::FAT16(_) => 4,
+                FATType::FAT32(_) => 8,
+            };
+            let first = self.read_sector(self.bpb.rsvd_sec_cnt as u64)?;
+            for i in 1..self.bpb.num_fats as u64 {
+                let copy = self.read_sector(self.bpb.rsvd_sec_cnt as u64 + i * self.fat_size())?;
+                if first[reserved_bytes..] != copy[reserved_bytes..] {
+                    report.error(format!("FAT: FAT copy {} differs from the first FAT", i));
+                }
+            }
+        }
+
+        return Ok(report);
+    }
+
     /// @brief 本函数用于实现动态转换。
     /// 具体的文件系统在实现本函数时，最简单的方式就是：直接返回self
     fn as_any_ref(&self) -> &dyn Any {
@@ -257,6 +341,8 @@ impl FATFileSystem {
     pub const FAT16_MAX_CLUSTER: u32 = 0xFFF5;
     /// FAT32允许的最大簇号
     pub const FAT32_MAX_CLUSTER: u32 = 0x0FFFFFF7;
+    /// 引导扇区中BPB(包括FAT32的扩展部分)的大小
+    const BPB_SIZE: usize = 90;
 
     pub fn new(partition: Arc<Partition>) -> Result<Arc<FATFileSystem>, SystemError> {
         let bpb = BiosParameterBlock::new(partition.clone())?;
@@ -334,6 +420,18 @@ impl FATFileSystem {
             root_inode: root_inode,
         });
 
+        // FsInfo中的空闲簇数目不合理时，将其标记为未知，避免在此基础上继续累加出错误的值
+        {
+            let mut fs_info = result.fs_info.0.lock();
+            if fs_info.offset.is_some()
+                && fs_info
+                    .count_free_cluster(result.max_cluster_number())
+                    .is_none()
+            {
+                fs_info.update_free_count_abs(0xffffffff);
+            }
+        }
+
         // 对root inode加锁，并继续完成初始化工作
         let mut root_guard: SpinLockGuard<FATInode> = result.root_inode.0.lock();
         root_guard.inode_type = FATDirEntry::Dir(result.root_dir());
@@ -381,13 +479,18 @@ impl FATFileSystem {
         // FAT表项在逻辑块内的字节偏移量
         let blk_offset = self.get_in_block_offset(fat_bytes_offset);
 
+        // FAT12的表项可能跨越两个逻辑块，因此多读取一个逻辑块
+        let num_lba = match fat_type {
+            FATType::FAT12(_) => self.lba_per_sector() + 1,
+            _ => self.lba_per_sector(),
+        };
         let mut v = Vec::<u8>::new();
-        v.resize(self.bpb.bytes_per_sector as usize, 0);
+        v.resize(num_lba * LBA_SIZE, 0);
         self.partition
             .disk()
-            .read_at(fat_ent_lba as usize, 1 * self.lba_per_sector(), &mut v)?;
+            .read_at(fat_ent_lba as usize, num_lba, &mut v)?;
 
-        let mut cursor = VecCursor::new(v);
+        let mut cursor = ByteReader::new(&v);
         cursor.seek(SeekFrom::SeekSet(blk_offset as i64))?;
 
         let res: FATEntry = match self.bpb.fat_type {
@@ -481,7 +584,7 @@ impl FATFileSystem {
             .disk()
             .read_at(fat_ent_lba, 1 * self.lba_per_sector(), &mut v)?;
 
-        let mut cursor = VecCursor::new(v);
+        let mut cursor = ByteReader::new(&v);
         cursor.seek(SeekFrom::SeekSet(blk_offset as i64))?;
 
         let res = match self.bpb.fat_type {
@@ -696,14 +799,14 @@ impl FATFileSystem {
         }
     }
 
-    /// @brief 判断当前文件系统是否启用了FAT表镜像
+    /// @brief 判断当前文件系统是否启用了FAT表镜像。FAT12/FAT16总是镜像所有的FAT表
     pub fn mirroring_enabled(&self) -> bool {
         match self.bpb.fat_type {
             FATType::FAT32(bpb32) => {
                 return (bpb32.ext_flags & 0x80) == 0;
             }
             _ => {
-                return false;
+                return true;
             }
         }
     }
@@ -716,6 +819,22 @@ impl FATFileSystem {
             as usize;
     }
 
+    /// @brief 读取分区内的一个扇区
+    ///
+    /// @param in_partition_sec_offset 扇区在分区内的偏移量
+    ///
+    /// @return Ok(Vec<u8>) 扇区的内容
+    /// @return Err(SystemError) 读取失败
+    fn read_sector(&self, in_partition_sec_offset: u64) -> Result<Vec<u8>, SystemError> {
+        let mut buf = vec![0u8; self.bpb.bytes_per_sector as usize];
+        self.partition.disk().read_at(
+            self.get_lba_from_offset(in_partition_sec_offset),
+            self.lba_per_sector(),
+            &mut buf,
+        )?;
+        return Ok(buf);
+    }
+
     /// @brief 获取每个扇区占用多少个LBA
     #[inline]
     pub fn lba_per_sector(&self) -> usize {
@@ -784,7 +903,7 @@ impl FATFileSystem {
     /// @return Ok(true) 正常
     /// @return Ok(false) 不正常
     /// @return Err(SystemError) 在判断时发生错误
-    pub fn is_shut_bit_ok(&mut self) -> Result<bool, SystemError> {
+    pub fn is_shut_bit_ok(&self) -> Result<bool, SystemError> {
         match self.bpb.fat_type {
             FATType::FAT32(_) => {
                 // 对于FAT32, error bit位于第一个扇区的第8字节。
@@ -807,7 +926,7 @@ impl FATFileSystem {
     /// @return Ok(true) 正常
     /// @return Ok(false) 不正常
     /// @return Err(SystemError) 在判断时发生错误
-    pub fn is_hard_error_bit_ok(&mut self) -> Result<bool, SystemError> {
+    pub fn is_hard_error_bit_ok(&self) -> Result<bool, SystemError> {
         match self.bpb.fat_type {
             FATType::FAT32(_) => {
                 let bit = self.get_fat_entry_raw(Cluster::new(1))? & 0x0400_0000;
@@ -866,6 +985,11 @@ impl FATFileSystem {
         }
     }
 
+    /// @brief 把被修改过的fs info刷入磁盘。FAT12/FAT16没有fs info，不做操作
+    pub fn flush_fs_info(&self) -> Result<(), SystemError> {
+        return self.fs_info.0.lock().flush_if_dirty(&self.partition);
+    }
+
     /// @brief 执行文件系统卸载前的一些准备工作：设置好对应的标志位，并把缓存中的数据刷入磁盘
     pub fn umount(&mut self) -> Result<(), SystemError> {
         self.fs_info.0.lock().flush(&self.partition)?;
@@ -947,7 +1071,7 @@ impl FATFileSystem {
                 v.resize(num_lba * LBA_SIZE, 0);
                 self.partition.disk().read_at(lba, num_lba, &mut v)?;
 
-                let mut cursor: VecCursor = VecCursor::new(v);
+                let mut cursor = ByteReader::new(&v);
                 cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
 
                 let mut packed_val: u16 = cursor.read_u16()?;
@@ -995,13 +1119,13 @@ impl FATFileSystem {
                         .disk()
                         .read_at(lba, self.lba_per_sector(), &mut v)?;
 
-                    let mut cursor: VecCursor = VecCursor::new(v);
+                    let mut cursor = ByteReader::new(&v);
                     cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
 
                     let val = cursor.read_u16()?;
                     // 找到空闲簇
                     if val == 0 {
-                        return Ok(Cluster::new(val as u64));
+                        return Ok(Cluster::new(cluster));
                     }
                     cluster += 1;
                 }
@@ -1027,7 +1151,7 @@ impl FATFileSystem {
                         .disk()
                         .read_at(lba, self.lba_per_sector(), &mut v)?;
 
-                    let mut cursor: VecCursor = VecCursor::new(v);
+                    let mut cursor = ByteReader::new(&v);
                     cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
 
                     let val = cursor.read_u32()? & 0x0fffffff;
@@ -1044,7 +1168,7 @@ impl FATFileSystem {
         }
     }
 
-    /// @brief 在FAT表中，设置指定的簇的信息。
+    /// @brief 在FAT表中，设置指定的簇的信息。启用了FAT表镜像时，所有的FAT表都会被更新
     ///
     /// @param cluster 目标簇
     /// @param fat_entry 这个簇在FAT表中，存储的信息（下一个簇的簇号）
@@ -1056,101 +1180,79 @@ impl FATFileSystem {
             self.bpb.bytes_per_sector as u64,
         );
 
-        match self.bpb.fat_type {
-            FATType::FAT12(_) => {
-                // 计算要写入的值
-                let raw_val: u16 = match fat_entry {
-                    FATEntry::Unused => 0,
-                    FATEntry::Bad => 0xff7,
-                    FATEntry::EndOfChain => 0xfff,
-                    FATEntry::Next(c) => c.cluster_num as u16,
-                };
-
-                let in_block_offset = self.get_in_block_offset(fat_part_bytes_offset);
-
-                let lba = self.get_lba_from_offset(self.bytes_to_sector(fat_part_bytes_offset));
-
-                let mut v: Vec<u8> = Vec::new();
-                v.resize(LBA_SIZE, 0);
-                self.partition.disk().read_at(lba, 1, &mut v)?;
-
-                let mut cursor: VecCursor = VecCursor::new(v);
-                cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
-
-                let old_val: u16 = cursor.read_u16()?;
-                let new_val: u16 = if (cluster.cluster_num & 0x1) > 0 {
-                    (old_val & 0x000f) | (raw_val << 4)
-                } else {
-                    (old_val & 0xf000) | raw_val
-                };
-
-                // 写回数据到磁盘上
-                cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
-                cursor.write_u16(new_val)?;
-                self.partition.disk().write_at(lba, 1, cursor.as_slice())?;
-                return Ok(());
+        if let FATType::FAT32(_) = self.bpb.fat_type {
+            if fat_entry == FATEntry::Unused
+                && cluster.cluster_num >= 0x0ffffff7
+                && cluster.cluster_num <= 0x0fffffff
+            {
+                kerror!(
+                    "FAT32: Reserved Cluster {:?} cannot be marked as free",
+                    cluster
+                );
+                return Err(SystemError::EPERM);
             }
-            FATType::FAT16(_) => {
-                // 计算要写入的值
-                let raw_val: u16 = match fat_entry {
-                    FATEntry::Unused => 0,
-                    FATEntry::Bad => 0xfff7,
-                    FATEntry::EndOfChain => 0xfdff,
-                    FATEntry::Next(c) => c.cluster_num as u16,
-                };
+        }
 
-                let in_block_offset = self.get_in_block_offset(fat_part_bytes_offset);
+        // 需要更新的FAT表的数量
+        let bound: u64 = if self.mirroring_enabled() {
+            self.bpb.num_fats as u64
+        } else {
+            1
+        };
+        let fat_bytes: u64 = self.fat_size() * self.bpb.bytes_per_sector as u64;
+        // FAT12的表项可能跨越两个逻辑块，因此读取两个逻辑块
+        let num_lba: usize = match self.bpb.fat_type {
+            FATType::FAT12(_) => 2,
+            _ => 1,
+        };
 
-                let lba = self.get_lba_from_offset(self.bytes_to_sector(fat_part_bytes_offset));
+        for i in 0..bound {
+            // 当前操作的FAT表项在分区内的字节偏移量
+            let f_offset: u64 = fat_part_bytes_offset + i * fat_bytes;
+            let in_block_offset: u64 = self.get_in_block_offset(f_offset);
+            let lba = (self.partition.lba_start + f_offset / LBA_SIZE as u64) as usize;
 
-                let mut v: Vec<u8> = Vec::new();
-                v.resize(LBA_SIZE, 0);
-                self.partition.disk().read_at(lba, 1, &mut v)?;
+            let mut v: Vec<u8> = vec![0u8; num_lba * LBA_SIZE];
+            self.partition.disk().read_at(lba, num_lba, &mut v)?;
 
-                let mut cursor: VecCursor = VecCursor::new(v);
-                cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
+            let mut cursor = ByteWriter::new(&mut v);
+            cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
 
-                cursor.write_u16(raw_val)?;
-                self.partition.disk().write_at(lba, 1, cursor.as_slice())?;
+            match self.bpb.fat_type {
+                FATType::FAT12(_) => {
+                    // 计算要写入的值
+                    let raw_val: u16 = match fat_entry {
+                        FATEntry::Unused => 0,
+                        FATEntry::Bad => 0xff7,
+                        FATEntry::EndOfChain => 0xfff,
+                        FATEntry::Next(c) => c.cluster_num as u16,
+                    };
 
-                return Ok(());
-            }
-            FATType::FAT32(_) => {
-                let fat_size: u64 = self.fat_size();
-                let bound: u64 = if self.mirroring_enabled() {
-                    1
-                } else {
-                    self.bpb.num_fats as u64
-                };
-                // kdebug!("set entry, bound={bound}, fat_size={fat_size}");
-                for i in 0..bound {
-                    // 当前操作的FAT表在磁盘上的字节偏移量
-                    let f_offset: u64 = fat_part_bytes_offset + i * fat_size;
-                    let in_block_offset: u64 = self.get_in_block_offset(f_offset);
-                    let lba = self.get_lba_from_offset(self.bytes_to_sector(f_offset));
-
-                    // kdebug!("set entry, lba={lba}, in_block_offset={in_block_offset}");
-                    let mut v: Vec<u8> = Vec::new();
-                    v.resize(LBA_SIZE, 0);
-                    self.partition.disk().read_at(lba, 1, &mut v)?;
+                    let old_val: u16 = cursor.read_u16()?;
+                    let new_val: u16 = if (cluster.cluster_num & 0x1) > 0 {
+                        (old_val & 0x000f) | (raw_val << 4)
+                    } else {
+                        (old_val & 0xf000) | raw_val
+                    };
 
-                    let mut cursor: VecCursor = VecCursor::new(v);
                     cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
+                    cursor.write_u16(new_val)?;
+                }
+                FATType::FAT16(_) => {
+                    // 计算要写入的值
+                    let raw_val: u16 = match fat_entry {
+                        FATEntry::Unused => 0,
+                        FATEntry::Bad => 0xfff7,
+                        FATEntry::EndOfChain => 0xffff,
+                        FATEntry::Next(c) => c.cluster_num as u16,
+                    };
 
+                    cursor.write_u16(raw_val)?;
+                }
+                FATType::FAT32(_) => {
                     // FAT32的高4位保留
                     let old_bits = cursor.read_u32()? & 0xf0000000;
 
-                    if fat_entry == FATEntry::Unused
-                        && cluster.cluster_num >= 0x0ffffff7
-                        && cluster.cluster_num <= 0x0fffffff
-                    {
-                        kerror!(
-                            "FAT32: Reserved Cluster {:?} cannot be marked as free",
-                            cluster
-                        );
-                        return Err(SystemError::EPERM);
-                    }
-
                     // 计算要写入的值
                     let mut raw_val: u32 = match fat_entry {
                         FATEntry::Unused => 0,
@@ -1162,17 +1264,18 @@ impl FATFileSystem {
                     // 恢复保留位
                     raw_val |= old_bits;
 
-                    // kdebug!("sent entry, raw_val={raw_val}");
-
                     cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
                     cursor.write_u32(raw_val)?;
-
-                    self.partition.disk().write_at(lba, 1, cursor.as_slice())?;
                 }
-
-                return Ok(());
             }
+
+            // 写回数据到磁盘上
+            self.partition
+                .disk()
+                .write_at(lba, num_lba, cursor.as_slice())?;
         }
+
+        return Ok(());
     }
 
     /// @brief 清空指定的簇
@@ -1224,17 +1327,17 @@ impl FATFsInfo {
         partition
             .disk()
             .read_at(in_disk_fs_info_offset as usize / LBA_SIZE, 1, &mut v)?;
-        let mut cursor = VecCursor::new(v);
+        let mut cursor = ByteReader::new(&v);
 
         let mut fsinfo = FATFsInfo::default();
 
         fsinfo.lead_sig = cursor.read_u32()?;
-        cursor.seek(SeekFrom::SeekCurrent(480))?;
+        cursor.skip(480)?;
         fsinfo.struc_sig = cursor.read_u32()?;
         fsinfo.free_count = cursor.read_u32()?;
         fsinfo.next_free = cursor.read_u32()?;
 
-        cursor.seek(SeekFrom::SeekCurrent(12))?;
+        cursor.skip(12)?;
 
         fsinfo.trail_sig = cursor.read_u32()?;
         fsinfo.dirty = false;
@@ -1278,13 +1381,18 @@ impl FATFsInfo {
     /// 请注意，除非手动调用`flush()`，否则本函数不会将数据刷入磁盘
     pub fn update_free_count_abs(&mut self, new_count: u32) {
         self.free_count = new_count;
+        self.dirty = true;
     }
 
-    /// @brief 更新FsInfo中的“空闲簇统计信息“，把它加上delta.
+    /// @brief 更新FsInfo中的“空闲簇统计信息“，把它加上delta. 如果空闲簇数目未知，则保持未知
     ///
     /// 请注意，除非手动调用`flush()`，否则本函数不会将数据刷入磁盘
     pub fn update_free_count_delta(&mut self, delta: i32) {
+        if self.free_count == 0xffffffff {
+            return;
+        }
         self.free_count = (self.free_count as i32 + delta) as u32;
+        self.dirty = true;
     }
 
     /// @brief 更新FsInfo中的“第一个空闲簇统计信息“为next_free.
@@ -1293,6 +1401,7 @@ impl FATFsInfo {
     pub fn update_next_free(&mut self, next_free: u32) {
         // 这个值是参考量，不一定要准确，仅供加速查找
         self.next_free = next_free;
+        self.dirty = true;
     }
 
     /// @brief 获取fs info 记载的第一个空闲簇。（不一定准确，仅供参考）
@@ -1307,7 +1416,7 @@ impl FATFsInfo {
     /// @brief 把fs info刷入磁盘
     ///
     /// @param partition fs info所在的分区
-    pub fn flush(&self, partition: &Arc<Partition>) -> Result<(), SystemError> {
+    pub fn flush(&mut self, partition: &Arc<Partition>) -> Result<(), SystemError> {
         if let Some(off) = self.offset {
             let in_block_offset = off % LBA_SIZE as u64;
 
@@ -1317,22 +1426,33 @@ impl FATFsInfo {
             v.resize(LBA_SIZE, 0);
             partition.disk().read_at(lba, 1, &mut v)?;
 
-            let mut cursor: VecCursor = VecCursor::new(v);
+            let mut cursor = ByteWriter::new(&mut v);
             cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
 
             cursor.write_u32(self.lead_sig)?;
-            cursor.seek(SeekFrom::SeekCurrent(480))?;
+            cursor.skip(480)?;
             cursor.write_u32(self.struc_sig)?;
             cursor.write_u32(self.free_count)?;
             cursor.write_u32(self.next_free)?;
-            cursor.seek(SeekFrom::SeekCurrent(12))?;
+            cursor.skip(12)?;
             cursor.write_u32(self.trail_sig)?;
 
             partition.disk().write_at(lba, 1, cursor.as_slice())?;
         }
+        self.dirty = false;
         return Ok(());
     }
 
+    /// @brief 如果fs info被修改过，则把它刷入磁盘
+    ///
+    /// @param partition fs info所在的分区
+    pub fn flush_if_dirty(&mut self, partition: &Arc<Partition>) -> Result<(), SystemError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        return self.flush(partition);
+    }
+
     /// @brief 读取磁盘上的Fs Info扇区，将里面的内容更新到结构体中
     ///
     /// @param partition fs info所在的分区
@@ -1345,15 +1465,15 @@ impl FATFsInfo {
             let mut v: Vec<u8> = Vec::new();
             v.resize(LBA_SIZE, 0);
             partition.disk().read_at(lba, 1, &mut v)?;
-            let mut cursor: VecCursor = VecCursor::new(v);
+            let mut cursor = ByteReader::new(&v);
             cursor.seek(SeekFrom::SeekSet(in_block_offset as i64))?;
             self.lead_sig = cursor.read_u32()?;
 
-            cursor.seek(SeekFrom::SeekCurrent(480))?;
+            cursor.skip(480)?;
             self.struc_sig = cursor.read_u32()?;
             self.free_count = cursor.read_u32()?;
             self.next_free = cursor.read_u32()?;
-            cursor.seek(SeekFrom::SeekCurrent(12))?;
+            cursor.skip(12)?;
             self.trail_sig = cursor.read_u32()?;
         }
         return Ok(());
@@ -1403,6 +1523,7 @@ impl IndexNode for LockedFATInode {
             FATDirEntry::File(f) | FATDirEntry::VolId(f) => {
                 let r = f.write(fs, &buf[0..len], offset as u64);
                 guard.update_metadata();
+                fs.flush_fs_info()?;
                 return r;
             }
             FATDirEntry::Dir(_) => {
@@ -1415,7 +1536,7 @@ impl IndexNode for LockedFATInode {
         }
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         // 加锁
         let inode: SpinLockGuard<FATInode> = self.0.lock();
 
@@ -1448,6 +1569,7 @@ impl IndexNode for LockedFATInode {
                 }
                 FileType::Dir => {
                     d.create_dir(name, fs)?;
+                    fs.flush_fs_info()?;
                     return Ok(guard.find(name)?);
                 }
 
@@ -1472,6 +1594,13 @@ impl IndexNode for LockedFATInode {
     fn metadata(&self) -> Result<Metadata, SystemError> {
         return Ok(self.0.lock().metadata.clone());
     }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        let fs = self.0.lock().fs.upgrade().unwrap();
+        fs.flush_fs_info()?;
+        return fs.partition.disk().sync();
+    }
+
     fn resize(&self, len: usize) -> Result<(), SystemError> {
         let mut guard: SpinLockGuard<FATInode> = self.0.lock();
         let fs: &Arc<FATFileSystem> = &guard.fs.upgrade().unwrap();
@@ -1501,6 +1630,7 @@ impl IndexNode for LockedFATInode {
                     file.truncate(fs, len as u64)?;
                 }
                 guard.update_metadata();
+                fs.flush_fs_info()?;
                 return Ok(());
             }
             FATDirEntry::Dir(_) => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
@@ -1523,27 +1653,12 @@ impl IndexNode for LockedFATInode {
                 let mut ret: Vec<String> = Vec::new();
                 let dir_iter: FATDirIter = dir.to_iter(guard.fs.upgrade().unwrap());
                 for ent in dir_iter {
-                    ret.push(ent.name());
-
-                    // ====== 生成inode缓存，存入B树
                     let name: String = ent.name();
-                    // kdebug!("name={name}");
-
-                    if guard.children.contains_key(&name.to_uppercase()) == false
-                        && name != "."
-                        && name != ".."
-                    {
-                        // 创建新的inode
-                        let entry_inode: Arc<LockedFATInode> = LockedFATInode::new(
-                            guard.fs.upgrade().unwrap(),
-                            guard.self_ref.clone(),
-                            ent,
-                        );
-                        // 加入缓存区, 由于FAT文件系统的大小写不敏感问题，因此存入缓存区的key应当是全大写的
-                        guard
-                            .children
-                            .insert(name.to_uppercase(), entry_inode.clone());
+                    // ====== 生成inode缓存，存入B树
+                    if name != "." && name != ".." {
+                        guard.cached_child(ent);
                     }
+                    ret.push(name);
                 }
                 return Ok(ret);
             }
@@ -1554,6 +1669,66 @@ impl IndexNode for LockedFATInode {
         }
     }
 
+    /// @brief 从目录中的第offset个目录项开始列出目录项，目录项的序号就是它在磁盘上的目录中的位置
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let (ino, parent) = {
+            let guard: SpinLockGuard<FATInode> = self.0.lock();
+            match &guard.inode_type {
+                FATDirEntry::Dir(_) => {}
+                FATDirEntry::File(_) | FATDirEntry::VolId(_) => {
+                    return Err(SystemError::ENOTDIR);
+                }
+                FATDirEntry::UnInit => {
+                    kerror!("FATFS: param: Inode_type uninitialized.");
+                    return Err(SystemError::EROFS);
+                }
+            }
+            (guard.metadata.inode_id, guard.parent.upgrade())
+        };
+        // 父目录需要在放开当前inode的锁之后再锁住，避免与从父到子的加锁顺序相反
+        let parent_ino = parent.map_or(ino, |parent| parent.0.lock().metadata.inode_id);
+
+        let mut guard: SpinLockGuard<FATInode> = self.0.lock();
+        let dir_iter: FATDirIter = match &guard.inode_type {
+            FATDirEntry::Dir(dir) => dir.to_iter(guard.fs.upgrade().unwrap()),
+            _ => return Err(SystemError::ENOTDIR),
+        };
+        let mut pos = offset;
+        for ent in dir_iter.skip(offset) {
+            let name: String = ent.name();
+            let entry = match name.as_str() {
+                "." => DirEntry {
+                    name,
+                    ino,
+                    file_type: FileType::Dir,
+                },
+                ".." => DirEntry {
+                    name,
+                    ino: parent_ino,
+                    file_type: FileType::Dir,
+                },
+                _ => {
+                    let child = guard.cached_child(ent);
+                    let metadata = child.0.lock().metadata.clone();
+                    DirEntry {
+                        name,
+                        ino: metadata.inode_id,
+                        file_type: metadata.file_type,
+                    }
+                }
+            };
+            if !filler(entry) {
+                break;
+            }
+            pos += 1;
+        }
+        return Ok(pos);
+    }
+
     fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
         let mut guard: SpinLockGuard<FATInode> = self.0.lock();
         let target = guard.find(name)?;
@@ -1598,8 +1773,10 @@ impl IndexNode for LockedFATInode {
         dir.check_existence(name, Some(false), guard.fs.upgrade().unwrap())?;
 
         // 再从磁盘删除
-        let r = dir.remove(guard.fs.upgrade().unwrap().clone(), name, true);
+        let fs = guard.fs.upgrade().unwrap();
+        let r = dir.remove(fs.clone(), name, true);
         drop(target_guard);
+        fs.flush_fs_info()?;
         return r;
     }
 
@@ -1625,9 +1802,10 @@ impl IndexNode for LockedFATInode {
         dir.check_existence(name, Some(true), guard.fs.upgrade().unwrap())?;
 
         // 再从磁盘删除
-        let r: Result<(), SystemError> =
-            dir.remove(guard.fs.upgrade().unwrap().clone(), name, true);
+        let fs = guard.fs.upgrade().unwrap();
+        let r: Result<(), SystemError> = dir.remove(fs.clone(), name, true);
         if r.is_ok() {
+            fs.flush_fs_info()?;
             return r;
         } else {
             let r = r.unwrap_err();
@@ -1,8 +1,15 @@
 use core::char::REPLACEMENT_CHARACTER;
 
+use crate::time::TimeSpec;
+
 /// FAT文件系统保留开头的2个簇
 pub const RESERVED_CLUSTERS: u32 = 2;
 
+/// FAT时间戳能表示的最早时间，即1980-01-01 00:00:00 UTC
+const FAT_MIN_TIME: i64 = 315532800;
+/// FAT时间戳能表示的最晚时间，即2107-12-31 23:59:58 UTC
+const FAT_MAX_TIME: i64 = 4354819198;
+
 /// @brief 将u8转为ascii字符。
 /// 当转码成功时，返回对应的ascii字符，否则返回Unicode占位符
 pub fn decode_u8_ascii(value: u8) -> char {
@@ -13,3 +20,82 @@ pub fn decode_u8_ascii(value: u8) -> char {
         return REPLACEMENT_CHARACTER;
     }
 }
+
+/// @brief 将Unix时间转换为FAT目录项中的时间戳，时区为UTC。超出FAT能表示的范围的时间会被截断
+///
+/// @return (日期, 时间, 以10毫秒为单位的时间)。
+/// 日期的格式为：年(从1980年起)7位，月4位，日5位；时间的格式为：时5位，分6位，秒/2 5位
+pub fn unix_to_fat_time(ts: &TimeSpec) -> (u16, u16, u8) {
+    let secs = ts.tv_sec.clamp(FAT_MIN_TIME, FAT_MAX_TIME);
+    let mut days = secs / 86400;
+    let rem = secs % 86400;
+
+    let mut year = 1970;
+    while days >= days_in_year(year) {
+        days -= days_in_year(year);
+        year += 1;
+    }
+    let mut month = 1;
+    while days >= days_in_month(year, month) {
+        days -= days_in_month(year, month);
+        month += 1;
+    }
+
+    let date = (((year - 1980) as u16) << 9) | ((month as u16) << 5) | (days + 1) as u16;
+    let time =
+        (((rem / 3600) as u16) << 11) | ((((rem / 60) % 60) as u16) << 5) | ((rem % 60) / 2) as u16;
+    let time_tenth = if secs == ts.tv_sec {
+        ((secs % 2) * 100 + ts.tv_nsec / 10_000_000) as u8
+    } else {
+        0
+    };
+    return (date, time, time_tenth);
+}
+
+/// @brief 将FAT目录项中的时间戳转换为Unix时间，时区为UTC
+///
+/// @param date 日期，格式见`unix_to_fat_time`
+/// @param time 时间，只记录了日期的时间戳(例如最后访问日期)为0
+/// @param time_tenth 以10毫秒为单位的时间，没有时为0
+pub fn fat_time_to_unix(date: u16, time: u16, time_tenth: u8) -> TimeSpec {
+    // 日期为0表示没有记录时间
+    if date == 0 {
+        return TimeSpec::default();
+    }
+    let year = 1980 + (date >> 9) as i64;
+    let month = (((date >> 5) & 0xf) as i64).clamp(1, 12);
+    let day = ((date & 0x1f) as i64).max(1);
+
+    // 1970-01-01到year-month-day的天数
+    let days: i64 = (1970..year).map(days_in_year).sum::<i64>()
+        + (1..month).map(|m| days_in_month(year, m)).sum::<i64>()
+        + day
+        - 1;
+
+    let secs = days * 86400
+        + ((time >> 11) as i64) * 3600
+        + (((time >> 5) & 0x3f) as i64) * 60
+        + ((time & 0x1f) as i64) * 2
+        + (time_tenth / 100) as i64;
+    return TimeSpec {
+        tv_sec: secs,
+        tv_nsec: (time_tenth % 100) as i64 * 10_000_000,
+    };
+}
+
+fn is_leap_year(year: i64) -> bool {
+    return (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+}
+
+fn days_in_year(year: i64) -> i64 {
+    return if is_leap_year(year) { 366 } else { 365 };
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => return 29,
+        2 => return 28,
+        4 | 6 | 9 | 11 => return 30,
+        _ => return 31,
+    }
+}
@@ -0,0 +1,511 @@
+//! /dev/fuse：内核与用户态文件系统守护进程之间的通道
+//!
+//! 每次打开/dev/fuse都会创建一个新的连接。守护进程把文件描述符通过`fd=`选项传给mount(2)，
+//! 之后文件系统的操作被转换成请求放入连接的队列，守护进程通过read(2)取出请求，
+//! 处理完成后通过write(2)写回带有相同unique的回复。
+//!
+//! 守护进程关闭了连接的所有文件描述符之后，连接被断开，所有等待中的请求与之后的请求都返回ENOTCONN。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/fuse/dev.c
+
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    filesystem::{
+        devfs::{DevFS, DeviceINode},
+        vfs::{
+            core::generate_inode_id, file::FileMode, make_rawdev, syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus, PollTable,
+        },
+    },
+    kerror,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    process::ProcessManager,
+    syscall::SystemError,
+    time::TimeSpec,
+};
+
+use super::protocol::{
+    FuseAbi, FuseArgs, FuseInHeader, FuseInitIn, FuseInitOut, FuseOutHeader, FUSE_INIT,
+    FUSE_KERNEL_MINOR_VERSION, FUSE_KERNEL_VERSION, FUSE_MIN_READ_BUFFER,
+};
+
+/// 守护进程没有声明max_write时，WRITE请求中数据的最大长度
+const FUSE_DEFAULT_MAX_WRITE: usize = 4096;
+
+/// 每个打开的/dev/fuse文件的私有信息
+#[derive(Debug, Clone)]
+pub struct FuseDevPrivateData {
+    conn: Arc<FuseConn>,
+    mode: FileMode,
+}
+
+impl FuseDevPrivateData {
+    pub fn conn(&self) -> &Arc<FuseConn> {
+        return &self.conn;
+    }
+
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+}
+
+/// 一个发往守护进程的请求
+#[derive(Debug)]
+struct FuseRequest {
+    unique: u64,
+    opcode: u32,
+    /// 包括FuseInHeader在内的整条消息
+    msg: Vec<u8>,
+    /// 为false时，守护进程不会回复这个请求(FORGET)
+    need_reply: bool,
+    /// 守护进程的回复(去掉了FuseOutHeader)或者错误码
+    reply: SpinLock<Option<Result<Vec<u8>, SystemError>>>,
+    wait: WaitQueue,
+}
+
+impl FuseRequest {
+    fn complete(&self, result: Result<Vec<u8>, SystemError>) {
+        *self.reply.lock() = Some(result);
+        self.wait.wakeup_all(None);
+    }
+
+    /// @brief 等待守护进程的回复
+    ///
+    /// 与Linux一样，请求被发出之后不能被信号打断，否则守护进程的状态会与内核不一致
+    fn wait_reply(&self) -> Result<Vec<u8>, SystemError> {
+        loop {
+            let mut guard = self.reply.lock();
+            if let Some(result) = guard.take() {
+                return result;
+            }
+            self.wait.sleep_uninterruptible_unlock_spinlock(guard);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FuseConnInner {
+    /// 守护进程关闭了连接之后为false
+    connected: bool,
+    /// 收到了INIT的回复
+    initialized: bool,
+    /// 已经被一个文件系统使用
+    mounted: bool,
+    next_unique: u64,
+    /// 等待守护进程读取的请求
+    pending: VecDeque<Arc<FuseRequest>>,
+    /// 已经被守护进程读取、等待回复的请求
+    processing: BTreeMap<u64, Arc<FuseRequest>>,
+    max_write: usize,
+}
+
+/// 内核与一个守护进程之间的连接
+#[derive(Debug)]
+pub struct FuseConn {
+    inner: SpinLock<FuseConnInner>,
+    /// 守护进程在这里等待新的请求
+    read_wait: WaitQueue,
+    /// 文件系统的操作在这里等待INIT完成
+    init_wait: WaitQueue,
+    /// 指向这个连接的/dev/fuse文件的数量
+    dev_count: AtomicUsize,
+}
+
+impl FuseConn {
+    fn new() -> Arc<Self> {
+        return Arc::new(Self {
+            inner: SpinLock::new(FuseConnInner {
+                connected: true,
+                initialized: false,
+                mounted: false,
+                next_unique: 1,
+                pending: VecDeque::new(),
+                processing: BTreeMap::new(),
+                max_write: FUSE_DEFAULT_MAX_WRITE,
+            }),
+            read_wait: WaitQueue::INIT,
+            init_wait: WaitQueue::INIT,
+            dev_count: AtomicUsize::new(1),
+        });
+    }
+
+    /// @brief WRITE请求中数据的最大长度，由守护进程在INIT的回复中指定
+    pub fn max_write(&self) -> usize {
+        return self.inner.lock().max_write;
+    }
+
+    /// @brief 把连接标记为已挂载，并向守护进程发送INIT请求
+    ///
+    /// INIT的回复是异步处理的：守护进程通常在调用mount(2)之后才开始读取请求，
+    /// 在这里等待回复会导致单线程的守护进程死锁。在INIT完成之前，其它请求会等待。
+    ///
+    /// @return Err(SystemError::EINVAL) 连接已经被挂载过，或者已经断开
+    pub fn mount(&self) -> Result<(), SystemError> {
+        let mut guard = self.inner.lock();
+        if guard.mounted || !guard.connected {
+            return Err(SystemError::EINVAL);
+        }
+        guard.mounted = true;
+        drop(guard);
+
+        let init = FuseInitIn {
+            major: FUSE_KERNEL_VERSION,
+            minor: FUSE_KERNEL_MINOR_VERSION,
+            max_readahead: 0,
+            flags: 0,
+        };
+        self.queue(FUSE_INIT, 0, FuseArgs::new().push(&init).finish(), true)?;
+        return Ok(());
+    }
+
+    /// @brief 发送请求并等待守护进程的回复
+    ///
+    /// @param opcode 操作码
+    /// @param nodeid 操作的对象的节点号
+    /// @param args 操作码对应的参数
+    ///
+    /// @return Ok(Vec<u8>) 去掉了FuseOutHeader的回复
+    /// @return Err(SystemError::ENOTCONN) 连接已经断开
+    /// @return Err(SystemError) 守护进程返回的错误码
+    pub fn request(&self, opcode: u32, nodeid: u64, args: Vec<u8>) -> Result<Vec<u8>, SystemError> {
+        return self.queue(opcode, nodeid, args, true)?.wait_reply();
+    }
+
+    /// @brief 发送请求但不等待回复，用于RELEASE、FORGET这类调用者不关心结果的请求
+    ///
+    /// @param need_reply 守护进程是否会回复这个请求
+    pub fn request_background(&self, opcode: u32, nodeid: u64, args: Vec<u8>, need_reply: bool) {
+        // 连接已经断开时，守护进程也不再需要这些请求
+        self.queue(opcode, nodeid, args, need_reply).ok();
+    }
+
+    fn queue(
+        &self,
+        opcode: u32,
+        nodeid: u64,
+        args: Vec<u8>,
+        need_reply: bool,
+    ) -> Result<Arc<FuseRequest>, SystemError> {
+        let mut guard = loop {
+            let guard = self.inner.lock();
+            if !guard.connected {
+                return Err(SystemError::ENOTCONN);
+            }
+            if guard.initialized || opcode == FUSE_INIT {
+                break guard;
+            }
+            self.init_wait.sleep_uninterruptible_unlock_spinlock(guard);
+        };
+
+        let unique = guard.next_unique;
+        guard.next_unique += 1;
+        let pcb = ProcessManager::current_pcb();
+        let header = FuseInHeader {
+            len: (size_of::<FuseInHeader>() + args.len()) as u32,
+            opcode,
+            unique,
+            nodeid,
+            uid: pcb.basic().uid(),
+            gid: 0,
+            pid: pcb.pid().data() as u32,
+            padding: 0,
+        };
+        let mut msg = Vec::with_capacity(header.len as usize);
+        msg.extend_from_slice(header.as_bytes());
+        msg.extend_from_slice(&args);
+
+        let req = Arc::new(FuseRequest {
+            unique,
+            opcode,
+            msg,
+            need_reply,
+            reply: SpinLock::new(None),
+            wait: WaitQueue::INIT,
+        });
+        guard.pending.push_back(req.clone());
+        drop(guard);
+        self.read_wait.wakeup(None);
+        return Ok(req);
+    }
+
+    /// @brief 守护进程读取一个请求
+    ///
+    /// @return Err(SystemError::EINVAL) 缓冲区小于FUSE_MIN_READ_BUFFER
+    /// @return Err(SystemError::ENODEV) 连接已经断开
+    /// @return Err(SystemError::EIO) 缓冲区放不下这个请求，请求会以EIO失败
+    fn read(&self, buf: &mut [u8], nonblock: bool) -> Result<usize, SystemError> {
+        if buf.len() < FUSE_MIN_READ_BUFFER {
+            return Err(SystemError::EINVAL);
+        }
+
+        let req = loop {
+            let mut guard = self.inner.lock();
+            if !guard.connected {
+                return Err(SystemError::ENODEV);
+            }
+            if let Some(req) = guard.pending.pop_front() {
+                if req.msg.len() > buf.len() {
+                    drop(guard);
+                    req.complete(Err(SystemError::EIO));
+                    return Err(SystemError::EIO);
+                }
+                if req.need_reply {
+                    guard.processing.insert(req.unique, req.clone());
+                }
+                break req;
+            }
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if ProcessManager::current_pcb()
+                .sig_info()
+                .has_pending_signal()
+            {
+                return Err(SystemError::EINTR);
+            }
+            self.read_wait.sleep_unlock_spinlock(guard);
+        };
+
+        buf[..req.msg.len()].copy_from_slice(&req.msg);
+        return Ok(req.msg.len());
+    }
+
+    /// @brief 守护进程写入一个回复
+    ///
+    /// @return Err(SystemError::EINVAL) 回复的格式不正确
+    /// @return Err(SystemError::ENOENT) 没有等待这个回复的请求
+    fn write(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        let header = FuseOutHeader::read_from(buf).map_err(|_| SystemError::EINVAL)?;
+        if header.len as usize != buf.len() {
+            return Err(SystemError::EINVAL);
+        }
+        // unique为0的是守护进程主动发出的通知，目前不支持任何通知
+        if header.unique == 0 {
+            return Ok(buf.len());
+        }
+        if header.error > 0 || header.error <= -512 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let req = {
+            let mut guard = self.inner.lock();
+            if !guard.connected {
+                return Err(SystemError::ENOENT);
+            }
+            guard
+                .processing
+                .remove(&header.unique)
+                .ok_or(SystemError::ENOENT)?
+        };
+
+        // 守护进程运行在DragonOS上，错误码来自DragonOS的系统调用，不需要转换
+        let result = match header.error {
+            0 => Ok(buf[size_of::<FuseOutHeader>()..].to_vec()),
+            err => Err(SystemError::from_posix_errno(err).unwrap_or(SystemError::EIO)),
+        };
+        if req.opcode == FUSE_INIT {
+            self.process_init(result);
+        } else {
+            req.complete(result);
+        }
+        return Ok(buf.len());
+    }
+
+    /// @brief 处理INIT的回复。守护进程不支持本协议的主版本时断开连接
+    fn process_init(&self, result: Result<Vec<u8>, SystemError>) {
+        let out = match result {
+            // 旧版本的守护进程的回复更短，缺少的字段为0
+            Ok(data) if data.len() >= 8 => {
+                let mut out = FuseInitOut::default();
+                let len = data.len().min(size_of::<FuseInitOut>());
+                out.as_bytes_mut()[..len].copy_from_slice(&data[..len]);
+                out
+            }
+            _ => {
+                kerror!("fuse: INIT failed");
+                self.abort();
+                return;
+            }
+        };
+        if out.major != FUSE_KERNEL_VERSION {
+            kerror!(
+                "fuse: unsupported protocol version {}.{}",
+                out.major,
+                out.minor
+            );
+            self.abort();
+            return;
+        }
+
+        let mut guard = self.inner.lock();
+        guard.max_write = (out.max_write as usize).max(FUSE_DEFAULT_MAX_WRITE);
+        guard.initialized = true;
+        drop(guard);
+        self.init_wait.wakeup_all(None);
+    }
+
+    /// @brief 断开连接，所有未完成的请求都以ENOTCONN失败
+    pub fn abort(&self) {
+        let mut guard = self.inner.lock();
+        guard.connected = false;
+        let pending: Vec<_> = guard.pending.drain(..).collect();
+        let processing = core::mem::take(&mut guard.processing);
+        drop(guard);
+
+        for req in pending.iter().chain(processing.values()) {
+            req.complete(Err(SystemError::ENOTCONN));
+        }
+        self.read_wait.wakeup_all(None);
+        self.init_wait.wakeup_all(None);
+    }
+}
+
+#[derive(Debug)]
+pub struct FuseDevInode {
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedFuseDevInode>,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<DevFS>,
+    /// INode 元数据
+    metadata: Metadata,
+}
+
+#[derive(Debug)]
+pub struct LockedFuseDevInode(SpinLock<FuseDevInode>);
+
+impl LockedFuseDevInode {
+    pub fn new() -> Arc<Self> {
+        let inode = FuseDevInode {
+            self_ref: Weak::default(),
+            fs: Weak::default(),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o666),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: make_rawdev(10, 229),
+            },
+        };
+
+        let result = Arc::new(LockedFuseDevInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        return result;
+    }
+}
+
+impl DeviceINode for LockedFuseDevInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        self.0.lock().fs = fs;
+    }
+}
+
+impl IndexNode for LockedFuseDevInode {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    /// 每次open(2)创建一个新的连接；dup(2)得到的文件与原来的文件共享连接
+    fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        if let FilePrivateData::FuseDev(p) = data {
+            p.conn.dev_count.fetch_add(1, Ordering::SeqCst);
+            return Ok(());
+        }
+        *data = FilePrivateData::FuseDev(FuseDevPrivateData {
+            conn: FuseConn::new(),
+            mode: *mode,
+        });
+        return Ok(());
+    }
+
+    fn close(&self, data: &mut FilePrivateData) -> Result<(), SystemError> {
+        if let FilePrivateData::FuseDev(p) = data {
+            if p.conn.dev_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+                p.conn.abort();
+            }
+        }
+        return Ok(());
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    /// poll拿不到文件的私有信息，无法知道连接中是否有请求，因此总是认为可读写，
+    /// 守护进程应当使用阻塞的read(2)等待请求
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let private = match data {
+            FilePrivateData::FuseDev(p) => p,
+            _ => return Err(SystemError::EINVAL),
+        };
+        let len = core::cmp::min(len, buf.len());
+        return private
+            .conn
+            .read(&mut buf[..len], private.mode.contains(FileMode::O_NONBLOCK));
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let private = match data {
+            FilePrivateData::FuseDev(p) => p,
+            _ => return Err(SystemError::EINVAL),
+        };
+        let len = core::cmp::min(len, buf.len());
+        return private.conn.write(&buf[..len]);
+    }
+}
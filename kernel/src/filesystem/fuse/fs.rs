@@ -0,0 +1,896 @@
+use core::{any::Any, mem::size_of};
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    filesystem::vfs::{
+        file::{FileMode, FilePrivateData},
+        syscall::ModeType,
+        DirEntry, FileSystem, FileType, FsInfo, IndexNode, InodeId, Metadata, PollStatus,
+        PollTable, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG, DT_SOCK,
+    },
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    process::ProcessManager,
+    syscall::SystemError,
+    time::{
+        hrtimer::{ktime_get, Ktime},
+        TimeSpec, NSEC_PER_SEC,
+    },
+};
+
+use super::{
+    dev::FuseConn,
+    protocol::{
+        FuseAbi, FuseArgs, FuseAttr, FuseAttrOut, FuseCreateIn, FuseDirent, FuseEntryOut,
+        FuseForgetIn, FuseGetattrIn, FuseLinkIn, FuseMkdirIn, FuseMknodIn, FuseOpenIn, FuseOpenOut,
+        FuseReadIn, FuseReleaseIn, FuseRenameIn, FuseSetattrIn, FuseWriteIn, FuseWriteOut,
+        FATTR_ATIME, FATTR_GID, FATTR_MODE, FATTR_MTIME, FATTR_SIZE, FATTR_UID, FUSE_CREATE,
+        FUSE_FORGET, FUSE_GETATTR, FUSE_LINK, FUSE_LOOKUP, FUSE_MKDIR, FUSE_MKNOD, FUSE_OPEN,
+        FUSE_OPENDIR, FUSE_READ, FUSE_READDIR, FUSE_READLINK, FUSE_RELEASE, FUSE_RELEASEDIR,
+        FUSE_RENAME, FUSE_RMDIR, FUSE_ROOT_ID, FUSE_SETATTR, FUSE_UNLINK, FUSE_WRITE,
+    },
+};
+
+/// 文件名的最大长度
+const FUSE_NAME_MAX: usize = 255;
+/// 默认的READ请求的最大长度
+const FUSE_DEFAULT_MAX_READ: usize = 32 * 4096;
+/// READ请求的最小长度
+const FUSE_MIN_MAX_READ: usize = 4096;
+/// 每个READDIR请求的回复的最大长度
+const FUSE_READDIR_SIZE: u32 = 4096;
+
+/// 每个打开的FUSE文件的私有信息
+#[derive(Debug, Clone)]
+pub struct FuseFilePrivateData {
+    /// 守护进程在OPEN的回复中分配的文件句柄
+    fh: u64,
+    /// 打开文件时的flags，RELEASE时传回守护进程
+    flags: u32,
+}
+
+/// FUSE的挂载选项
+///
+/// 与Linux的同名选项含义相同：
+///
+/// - `fd=`：打开/dev/fuse得到的文件描述符，必须指定
+/// - `rootmode=`：根目录的mode(八进制)，默认为040000
+/// - `user_id=`、`group_id=`：挂载文件系统的用户
+/// - `max_read=`：每个READ请求的最大长度
+///
+/// `allow_other`、`default_permissions`被接受但不起作用
+#[derive(Debug, Clone)]
+pub struct FuseMountOptions {
+    pub fd: i32,
+    pub rootmode: u32,
+    pub user_id: u32,
+    pub group_id: u32,
+    pub max_read: usize,
+}
+
+impl FuseMountOptions {
+    /// @brief 解析挂载选项
+    ///
+    /// @return Err(SystemError::EINVAL) 没有指定fd，有不支持的选项，或者选项的值不合法
+    pub fn parse(s: &str) -> Result<Self, SystemError> {
+        let mut fd = None;
+        let mut opts = Self {
+            fd: -1,
+            rootmode: ModeType::S_IFDIR.bits(),
+            user_id: 0,
+            group_id: 0,
+            max_read: FUSE_DEFAULT_MAX_READ,
+        };
+
+        for opt in s.split(',').filter(|opt| !opt.is_empty()) {
+            let (name, value) = opt.split_once('=').unwrap_or((opt, ""));
+            let num = || value.parse::<u32>().map_err(|_| SystemError::EINVAL);
+            match name {
+                "fd" => fd = Some(value.parse::<i32>().map_err(|_| SystemError::EINVAL)?),
+                "rootmode" => {
+                    opts.rootmode =
+                        u32::from_str_radix(value, 8).map_err(|_| SystemError::EINVAL)?
+                }
+                "user_id" => opts.user_id = num()?,
+                "group_id" => opts.group_id = num()?,
+                "max_read" => opts.max_read = (num()? as usize).max(FUSE_MIN_MAX_READ),
+                "allow_other" | "default_permissions" => {}
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        opts.fd = fd.ok_or(SystemError::EINVAL)?;
+        if opts.rootmode & ModeType::S_IFMT.bits() != ModeType::S_IFDIR.bits() {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(opts);
+    }
+}
+
+/// @brief 由用户态守护进程实现的文件系统
+///
+/// 每个操作都被转换成请求发给守护进程，守护进程的回复中带有属性与目录项的有效时间，
+/// 在有效时间内不会重复发送GETATTR请求
+#[derive(Debug)]
+pub struct FuseFileSystem {
+    conn: Arc<FuseConn>,
+    options: FuseMountOptions,
+    /// 文件系统的根inode
+    root_inode: Arc<LockedFuseInode>,
+}
+
+/// FUSE文件系统的Inode
+#[derive(Debug)]
+pub struct LockedFuseInode(SpinLock<FuseInode>);
+
+#[derive(Debug)]
+pub struct FuseInode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedFuseInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedFuseInode>,
+    /// 守护进程分配的节点号
+    nodeid: u64,
+    /// 缓存的属性
+    attr: FuseAttr,
+    /// 属性缓存过期的时间
+    attr_expire: Ktime,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<FuseFileSystem>,
+}
+
+impl FileSystem for FuseFileSystem {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: FUSE_NAME_MAX,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl FuseFileSystem {
+    /// @brief 挂载由守护进程实现的文件系统
+    ///
+    /// 挂载时只向守护进程发送INIT请求，不等待回复。文件系统的操作会等待INIT完成
+    ///
+    /// @param data 挂载选项，见[`FuseMountOptions`]
+    ///
+    /// @return Err(SystemError::EBADF) fd不是打开的文件描述符
+    /// @return Err(SystemError::EINVAL) fd不是/dev/fuse，连接已经被挂载过，或者挂载选项不合法
+    pub fn mount(data: &str) -> Result<Arc<FuseFileSystem>, SystemError> {
+        let options = FuseMountOptions::parse(data)?;
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(options.fd)
+            .ok_or(SystemError::EBADF)?;
+        let conn = match &file.lock().private_data {
+            FilePrivateData::FuseDev(p) => p.conn().clone(),
+            _ => return Err(SystemError::EINVAL),
+        };
+        conn.mount()?;
+
+        // 根目录的属性在INIT完成之前无法获取，先使用挂载选项中的值，第一次访问时再向守护进程获取
+        let root_attr = FuseAttr {
+            ino: FUSE_ROOT_ID,
+            mode: options.rootmode,
+            nlink: 2,
+            uid: options.user_id,
+            gid: options.group_id,
+            ..Default::default()
+        };
+        let result: Arc<FuseFileSystem> = Arc::new(FuseFileSystem {
+            conn,
+            options,
+            root_inode: Arc::new(LockedFuseInode(SpinLock::new(FuseInode {
+                parent: Weak::default(),
+                self_ref: Weak::default(),
+                nodeid: FUSE_ROOT_ID,
+                attr: root_attr,
+                attr_expire: 0,
+                fs: Weak::default(),
+            }))),
+        });
+
+        // 对root inode加锁，并继续完成初始化工作
+        let mut root_guard: SpinLockGuard<FuseInode> = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        root_guard.fs = Arc::downgrade(&result);
+        drop(root_guard);
+
+        return Ok(result);
+    }
+}
+
+/// @brief 把守护进程返回的有效时间转换为过期的时刻
+fn expire_time(sec: u64, nsec: u32) -> Ktime {
+    return ktime_get()
+        .saturating_add(sec.saturating_mul(NSEC_PER_SEC as u64))
+        .saturating_add(nsec as u64);
+}
+
+impl LockedFuseInode {
+    fn new(
+        fs: &Arc<FuseFileSystem>,
+        parent: Weak<LockedFuseInode>,
+        entry: &FuseEntryOut,
+    ) -> Arc<Self> {
+        let inode = Arc::new(LockedFuseInode(SpinLock::new(FuseInode {
+            parent,
+            self_ref: Weak::default(),
+            nodeid: entry.nodeid,
+            attr: entry.attr,
+            attr_expire: expire_time(entry.attr_valid, entry.attr_valid_nsec),
+            fs: Arc::downgrade(fs),
+        })));
+        inode.0.lock().self_ref = Arc::downgrade(&inode);
+        return inode;
+    }
+
+    /// @brief 获取文件系统对象与当前inode的节点号。向守护进程发送请求时不持有锁
+    fn fs_and_nodeid(&self) -> (Arc<FuseFileSystem>, u64) {
+        let guard: SpinLockGuard<FuseInode> = self.0.lock();
+        return (guard.fs.upgrade().unwrap(), guard.nodeid);
+    }
+
+    fn file_type(&self) -> FileType {
+        return mode_file_type(self.0.lock().attr.mode);
+    }
+
+    /// @brief 获取文件的属性，缓存过期时向守护进程重新获取
+    fn attr(&self, fs: &FuseFileSystem, nodeid: u64) -> Result<FuseAttr, SystemError> {
+        {
+            let guard: SpinLockGuard<FuseInode> = self.0.lock();
+            if ktime_get() < guard.attr_expire {
+                return Ok(guard.attr);
+            }
+        }
+        let args = FuseArgs::new().push(&FuseGetattrIn::default()).finish();
+        let out = FuseAttrOut::read_from(&fs.conn.request(FUSE_GETATTR, nodeid, args)?)?;
+        self.update_attr(&out);
+        return Ok(out.attr);
+    }
+
+    fn update_attr(&self, out: &FuseAttrOut) {
+        let mut guard: SpinLockGuard<FuseInode> = self.0.lock();
+        guard.attr = out.attr;
+        guard.attr_expire = expire_time(out.attr_valid, out.attr_valid_nsec);
+    }
+
+    /// @brief 使属性缓存失效，下一次访问属性时向守护进程重新获取
+    fn invalidate_attr(&self) {
+        self.0.lock().attr_expire = 0;
+    }
+
+    /// @brief 为守护进程返回的目录项创建inode
+    ///
+    /// @return Err(SystemError::ENOENT) 节点号为0，表示文件不存在
+    fn new_child(
+        &self,
+        fs: &Arc<FuseFileSystem>,
+        reply: &[u8],
+    ) -> Result<Arc<LockedFuseInode>, SystemError> {
+        let entry = FuseEntryOut::read_from(reply)?;
+        if entry.nodeid == 0 {
+            return Err(SystemError::ENOENT);
+        }
+        let self_ref = self.0.lock().self_ref.clone();
+        return Ok(LockedFuseInode::new(fs, self_ref, &entry));
+    }
+
+    /// @brief 向守护进程打开文件，守护进程没有实现OPEN时使用0作为文件句柄
+    fn open_fh(
+        fs: &FuseFileSystem,
+        nodeid: u64,
+        opcode: u32,
+        flags: u32,
+    ) -> Result<u64, SystemError> {
+        let args = FuseArgs::new()
+            .push(&FuseOpenIn {
+                flags,
+                open_flags: 0,
+            })
+            .finish();
+        match fs.conn.request(opcode, nodeid, args) {
+            Ok(reply) => return Ok(FuseOpenOut::read_from(&reply)?.fh),
+            Err(SystemError::ENOSYS) => return Ok(0),
+            Err(e) => return Err(e),
+        }
+    }
+
+    fn release_fh(fs: &FuseFileSystem, nodeid: u64, opcode: u32, fh: u64, flags: u32) {
+        let args = FuseArgs::new()
+            .push(&FuseReleaseIn {
+                fh,
+                flags,
+                ..Default::default()
+            })
+            .finish();
+        fs.conn.request_background(opcode, nodeid, args, true);
+    }
+
+    /// @brief 读取目录中的所有目录项，不包括"."与".."
+    fn read_entries(&self) -> Result<Vec<FuseDirent>, SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let flags = (FileMode::O_RDONLY | FileMode::O_DIRECTORY).bits();
+        let fh = Self::open_fh(&fs, nodeid, FUSE_OPENDIR, flags)?;
+
+        let mut entries: Vec<FuseDirent> = Vec::new();
+        let mut offset = 0;
+        let result = loop {
+            let args = FuseArgs::new()
+                .push(&FuseReadIn {
+                    fh,
+                    offset,
+                    size: FUSE_READDIR_SIZE,
+                    ..Default::default()
+                })
+                .finish();
+            let batch = match fs
+                .conn
+                .request(FUSE_READDIR, nodeid, args)
+                .and_then(|reply| FuseDirent::parse_all(&reply))
+            {
+                Ok(batch) => batch,
+                Err(e) => break Err(e),
+            };
+            offset = match batch.last() {
+                Some(last) => last.off,
+                None => break Ok(()),
+            };
+            entries.extend(
+                batch
+                    .into_iter()
+                    .filter(|entry| entry.name != "." && entry.name != ".."),
+            );
+        };
+        Self::release_fh(&fs, nodeid, FUSE_RELEASEDIR, fh, flags);
+        result?;
+        return Ok(entries);
+    }
+
+    /// @brief 检查另一个inode是否属于同一个FUSE文件系统
+    fn same_fs<'a>(
+        &self,
+        other: &'a Arc<dyn IndexNode>,
+    ) -> Result<&'a LockedFuseInode, SystemError> {
+        let other: &LockedFuseInode = other
+            .downcast_ref::<LockedFuseInode>()
+            .ok_or(SystemError::EXDEV)?;
+        // 两个inode可能是同一个，因此不能同时持有它们的锁
+        let fs = self.0.lock().fs.clone();
+        if !Weak::ptr_eq(&fs, &other.0.lock().fs) {
+            return Err(SystemError::EXDEV);
+        }
+        return Ok(other);
+    }
+
+    /// @brief 发送SETATTR请求，并用回复中的属性更新缓存
+    fn setattr(&self, attr: &FuseSetattrIn) -> Result<(), SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let args = FuseArgs::new().push(attr).finish();
+        let out = FuseAttrOut::read_from(&fs.conn.request(FUSE_SETATTR, nodeid, args)?)?;
+        self.update_attr(&out);
+        return Ok(());
+    }
+}
+
+impl Drop for LockedFuseInode {
+    fn drop(&mut self) {
+        // 每个通过LOOKUP、CREATE、MKDIR等得到的inode都使守护进程中的查找计数加1，释放时需要减去
+        let guard: SpinLockGuard<FuseInode> = self.0.lock();
+        if guard.nodeid == FUSE_ROOT_ID {
+            return;
+        }
+        if let Some(fs) = guard.fs.upgrade() {
+            let args = FuseArgs::new().push(&FuseForgetIn { nlookup: 1 }).finish();
+            fs.conn
+                .request_background(FUSE_FORGET, guard.nodeid, args, false);
+        }
+    }
+}
+
+/// @brief 把mode中的类型位转换为文件类型
+fn mode_file_type(mode: u32) -> FileType {
+    match ModeType::from_bits_truncate(mode) & ModeType::S_IFMT {
+        ModeType::S_IFDIR => return FileType::Dir,
+        ModeType::S_IFLNK => return FileType::SymLink,
+        ModeType::S_IFCHR => return FileType::CharDevice,
+        ModeType::S_IFBLK => return FileType::BlockDevice,
+        ModeType::S_IFIFO => return FileType::Pipe,
+        ModeType::S_IFSOCK => return FileType::Socket,
+        _ => return FileType::File,
+    }
+}
+
+/// @brief 把READDIR中目录项的类型转换为文件类型，类型未知时返回None
+fn dirent_file_type(ty: u32) -> Option<FileType> {
+    match ty as u16 {
+        DT_DIR => return Some(FileType::Dir),
+        DT_REG => return Some(FileType::File),
+        DT_LNK => return Some(FileType::SymLink),
+        DT_CHR => return Some(FileType::CharDevice),
+        DT_BLK => return Some(FileType::BlockDevice),
+        DT_FIFO => return Some(FileType::Pipe),
+        DT_SOCK => return Some(FileType::Socket),
+        _ => return None,
+    }
+}
+
+/// @brief 把fuse_attr中的文件属性转换为元数据
+fn attr_to_metadata(attr: &FuseAttr) -> Metadata {
+    return Metadata {
+        dev_id: 0,
+        inode_id: InodeId::new(attr.ino as usize),
+        size: attr.size as i64,
+        blk_size: attr.blksize as usize,
+        blocks: attr.blocks as usize,
+        atime: TimeSpec::new(attr.atime as i64, attr.atimensec as i64),
+        mtime: TimeSpec::new(attr.mtime as i64, attr.mtimensec as i64),
+        ctime: TimeSpec::new(attr.ctime as i64, attr.ctimensec as i64),
+        file_type: mode_file_type(attr.mode),
+        mode: ModeType::from_bits_truncate(attr.mode),
+        nlinks: attr.nlink as usize,
+        uid: attr.uid as usize,
+        gid: attr.gid as usize,
+        raw_dev: attr.rdev as usize,
+    };
+}
+
+impl IndexNode for LockedFuseInode {
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        match self.file_type() {
+            FileType::Dir => return Err(SystemError::EISDIR),
+            FileType::SymLink => {
+                // 符号链接的内容为它的目标
+                let target = fs.conn.request(FUSE_READLINK, nodeid, Vec::new())?;
+                if offset >= target.len() {
+                    return Ok(0);
+                }
+                let n = core::cmp::min(len, target.len() - offset);
+                buf[..n].copy_from_slice(&target[offset..offset + n]);
+                return Ok(n);
+            }
+            _ => {}
+        }
+
+        // 内核自己读取文件时没有打开文件，临时向守护进程打开一次
+        let flags = FileMode::O_RDONLY.bits();
+        let (fh, temporary) = match data {
+            FilePrivateData::FuseFile(p) => (p.fh, false),
+            _ => (Self::open_fh(&fs, nodeid, FUSE_OPEN, flags)?, true),
+        };
+
+        let len = core::cmp::min(len, buf.len());
+        let mut done = 0;
+        let result = loop {
+            if done == len {
+                break Ok(done);
+            }
+            let size = core::cmp::min(len - done, fs.options.max_read);
+            let args = FuseArgs::new()
+                .push(&FuseReadIn {
+                    fh,
+                    offset: (offset + done) as u64,
+                    size: size as u32,
+                    ..Default::default()
+                })
+                .finish();
+            let reply = match fs.conn.request(FUSE_READ, nodeid, args) {
+                Ok(reply) => reply,
+                Err(e) => break Err(e),
+            };
+            let n = core::cmp::min(reply.len(), size);
+            buf[done..done + n].copy_from_slice(&reply[..n]);
+            done += n;
+            // 读到的数据比请求的少，说明到达了文件末尾
+            if n < size {
+                break Ok(done);
+            }
+        };
+        if temporary {
+            Self::release_fh(&fs, nodeid, FUSE_RELEASE, fh, flags);
+        }
+        return result;
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let flags = FileMode::O_WRONLY.bits();
+        let (fh, temporary) = match data {
+            FilePrivateData::FuseFile(p) => (p.fh, false),
+            _ => (Self::open_fh(&fs, nodeid, FUSE_OPEN, flags)?, true),
+        };
+
+        let len = core::cmp::min(len, buf.len());
+        let max_write = fs.conn.max_write();
+        let mut done = 0;
+        let result = loop {
+            if done == len {
+                break Ok(done);
+            }
+            let size = core::cmp::min(len - done, max_write);
+            let args = FuseArgs::new()
+                .push(&FuseWriteIn {
+                    fh,
+                    offset: (offset + done) as u64,
+                    size: size as u32,
+                    ..Default::default()
+                })
+                .push_bytes(&buf[done..done + size])
+                .finish();
+            let out = match fs
+                .conn
+                .request(FUSE_WRITE, nodeid, args)
+                .and_then(|reply| FuseWriteOut::read_from(&reply))
+            {
+                Ok(out) => out,
+                Err(e) => break Err(e),
+            };
+            let n = core::cmp::min(out.size as usize, size);
+            done += n;
+            if n < size {
+                break Ok(done);
+            }
+        };
+        if temporary {
+            Self::release_fh(&fs, nodeid, FUSE_RELEASE, fh, flags);
+        }
+        // 文件的大小与修改时间发生了变化
+        self.invalidate_attr();
+
+        let n = result?;
+        if n == 0 && len > 0 {
+            return Err(SystemError::EIO);
+        }
+        return Ok(n);
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        return self;
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        return Ok(attr_to_metadata(&self.attr(&fs, nodeid)?));
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let current = self.attr(&fs, nodeid)?;
+
+        // 只修改发生变化的属性
+        let mode = (metadata.mode & !ModeType::S_IFMT).bits();
+        let mut attr = FuseSetattrIn::default();
+        if mode != current.mode & !ModeType::S_IFMT.bits() {
+            attr.valid |= FATTR_MODE;
+            attr.mode = mode;
+        }
+        if metadata.uid as u32 != current.uid {
+            attr.valid |= FATTR_UID;
+            attr.uid = metadata.uid as u32;
+        }
+        if metadata.gid as u32 != current.gid {
+            attr.valid |= FATTR_GID;
+            attr.gid = metadata.gid as u32;
+        }
+        if metadata.atime != TimeSpec::new(current.atime as i64, current.atimensec as i64) {
+            attr.valid |= FATTR_ATIME;
+            attr.atime = metadata.atime.tv_sec as u64;
+            attr.atimensec = metadata.atime.tv_nsec as u32;
+        }
+        if metadata.mtime != TimeSpec::new(current.mtime as i64, current.mtimensec as i64) {
+            attr.valid |= FATTR_MTIME;
+            attr.mtime = metadata.mtime.tv_sec as u64;
+            attr.mtimensec = metadata.mtime.tv_nsec as u32;
+        }
+        if attr.valid == 0 {
+            return Ok(());
+        }
+        return self.setattr(&attr);
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        return self.setattr(&FuseSetattrIn {
+            valid: FATTR_SIZE,
+            size: len as u64,
+            ..Default::default()
+        });
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        if self.attr(&fs, nodeid)?.size <= len as u64 {
+            return Ok(());
+        }
+        return self.resize(len);
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let perm = (mode & !ModeType::S_IFMT).bits();
+        let reply = match file_type {
+            FileType::File => {
+                let flags = (FileMode::O_RDWR | FileMode::O_CREAT | FileMode::O_EXCL).bits();
+                let args = FuseArgs::new()
+                    .push(&FuseCreateIn {
+                        flags,
+                        mode: ModeType::S_IFREG.bits() | perm,
+                        umask: 0,
+                        open_flags: 0,
+                    })
+                    .push_name(name)
+                    .finish();
+                match fs.conn.request(FUSE_CREATE, nodeid, args) {
+                    Ok(reply) => {
+                        // 文件由之后的open(2)打开，CREATE顺便打开的文件句柄不再需要
+                        let entry = FuseEntryOut::read_from(&reply)?;
+                        let open = FuseOpenOut::read_from(&reply[size_of::<FuseEntryOut>()..])?;
+                        Self::release_fh(&fs, entry.nodeid, FUSE_RELEASE, open.fh, flags);
+                        reply
+                    }
+                    // 守护进程没有实现CREATE时使用MKNOD
+                    Err(SystemError::ENOSYS) => {
+                        let args = FuseArgs::new()
+                            .push(&FuseMknodIn {
+                                mode: ModeType::S_IFREG.bits() | perm,
+                                ..Default::default()
+                            })
+                            .push_name(name)
+                            .finish();
+                        fs.conn.request(FUSE_MKNOD, nodeid, args)?
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            FileType::Dir => {
+                let args = FuseArgs::new()
+                    .push(&FuseMkdirIn {
+                        mode: perm,
+                        umask: 0,
+                    })
+                    .push_name(name)
+                    .finish();
+                fs.conn.request(FUSE_MKDIR, nodeid, args)?
+            }
+            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        };
+        // 目录的mtime发生了变化
+        self.invalidate_attr();
+        let inode = self.new_child(&fs, &reply).map_err(|_| SystemError::EIO)?;
+        return Ok(inode);
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        let other = self.same_fs(other)?;
+        if other.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let oldnodeid = other.0.lock().nodeid;
+        let args = FuseArgs::new()
+            .push(&FuseLinkIn { oldnodeid })
+            .push_name(name)
+            .finish();
+        let reply = fs.conn.request(FUSE_LINK, nodeid, args)?;
+        // 守护进程为新的目录项增加了查找计数，释放这个inode时会发送FORGET
+        self.new_child(&fs, &reply)?;
+        self.invalidate_attr();
+        other.invalidate_attr();
+        return Ok(());
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let args = FuseArgs::new().push_name(name).finish();
+        fs.conn.request(FUSE_UNLINK, nodeid, args)?;
+        self.invalidate_attr();
+        return Ok(());
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let args = FuseArgs::new().push_name(name).finish();
+        fs.conn.request(FUSE_RMDIR, nodeid, args)?;
+        self.invalidate_attr();
+        return Ok(());
+    }
+
+    fn move_(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let target = self.same_fs(target)?;
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let newdir = target.0.lock().nodeid;
+        let args = FuseArgs::new()
+            .push(&FuseRenameIn { newdir })
+            .push_name(old_name)
+            .push_name(new_name)
+            .finish();
+        fs.conn.request(FUSE_RENAME, nodeid, args)?;
+        self.invalidate_attr();
+        target.invalidate_attr();
+        return Ok(());
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        if self.file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(self.read_entries()?.into_iter().map(|entry| entry.name));
+        return Ok(keys);
+    }
+
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let (ino, parent) = {
+            let guard: SpinLockGuard<FuseInode> = self.0.lock();
+            if mode_file_type(guard.attr.mode) != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (guard.attr.ino, guard.parent.upgrade().unwrap())
+        };
+        let parent_ino = parent.0.lock().attr.ino;
+
+        let mut entries: Vec<DirEntry> = Vec::new();
+        for entry in self.read_entries()? {
+            // 守护进程不知道文件类型时需要查找文件
+            let file_type = match dirent_file_type(entry.ty) {
+                Some(file_type) => file_type,
+                None => self.find(&entry.name)?.metadata()?.file_type,
+            };
+            entries.push(DirEntry {
+                ino: InodeId::new(entry.ino as usize),
+                file_type,
+                name: entry.name,
+            });
+        }
+        entries.push(DirEntry {
+            name: String::from("."),
+            ino: InodeId::new(ino as usize),
+            file_type: FileType::Dir,
+        });
+        entries.push(DirEntry {
+            name: String::from(".."),
+            ino: InodeId::new(parent_ino as usize),
+            file_type: FileType::Dir,
+        });
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut pos = offset;
+        for entry in entries.into_iter().skip(offset) {
+            if !filler(entry) {
+                break;
+            }
+            pos += 1;
+        }
+        return Ok(pos);
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let (self_ref, parent) = {
+            let guard: SpinLockGuard<FuseInode> = self.0.lock();
+            if mode_file_type(guard.attr.mode) != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (guard.self_ref.clone(), guard.parent.clone())
+        };
+        let target = match name {
+            "" | "." => self_ref.upgrade(),
+            ".." => parent.upgrade(),
+            _ => None,
+        };
+        if let Some(target) = target {
+            return Ok(target);
+        }
+        if name.len() > FUSE_NAME_MAX {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+
+        let (fs, nodeid) = self.fs_and_nodeid();
+        let args = FuseArgs::new().push_name(name).finish();
+        let reply = fs.conn.request(FUSE_LOOKUP, nodeid, args)?;
+        let inode = self.new_child(&fs, &reply)?;
+        return Ok(inode);
+    }
+
+    /// 普通文件在打开时向守护进程发送OPEN，得到的文件句柄保存在文件的私有信息中。
+    /// dup(2)得到的文件也会重新打开一次，使每个文件在关闭时各自发送RELEASE
+    fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        match self.file_type() {
+            FileType::Dir | FileType::SymLink => return Ok(()),
+            _ => {}
+        }
+        let (fs, nodeid) = self.fs_and_nodeid();
+        // 创建与截断文件由vfs完成
+        let flags = (*mode
+            & !(FileMode::O_CREAT | FileMode::O_EXCL | FileMode::O_NOCTTY | FileMode::O_TRUNC))
+            .bits();
+        let fh = Self::open_fh(&fs, nodeid, FUSE_OPEN, flags)?;
+        *data = FilePrivateData::FuseFile(FuseFilePrivateData { fh, flags });
+        return Ok(());
+    }
+
+    fn close(&self, data: &mut FilePrivateData) -> Result<(), SystemError> {
+        if let FilePrivateData::FuseFile(p) = data {
+            let (fs, nodeid) = self.fs_and_nodeid();
+            Self::release_fh(&fs, nodeid, FUSE_RELEASE, p.fh, p.flags);
+        }
+        return Ok(());
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        // 每次写入都直接发给了守护进程，内核中没有需要写回的数据
+        return Ok(());
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        if self.file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        return self
+            .read_entries()?
+            .into_iter()
+            .find(|entry| InodeId::new(entry.ino as usize) == ino)
+            .map(|entry| entry.name)
+            .ok_or(SystemError::ENOENT);
+    }
+}
@@ -0,0 +1,21 @@
+//! 用户态文件系统(FUSE)
+//!
+//! 文件系统由用户态的守护进程实现，内核把文件系统的操作转换成请求，通过/dev/fuse交给守护进程处理。
+//! 消息格式与Linux的FUSE协议7.31相同，因此libfuse等现有的库只需要很少的修改就可以使用。
+//! 守护进程的使用方法：
+//!
+//! 1. 打开/dev/fuse，得到文件描述符fd
+//! 2. 调用`mount("fuse", "/mnt", "fuse", 0, "fd=<fd>,rootmode=40000")`
+//! 3. 循环read(2)读取请求、处理之后write(2)写回回复，第一个请求总是INIT
+//!
+//! 守护进程关闭fd之后，文件系统的所有操作都返回ENOTCONN。
+//!
+//! - [`protocol`]：请求与回复的格式
+//! - [`dev`]：/dev/fuse设备与请求队列
+//! - [`fs`]：文件系统与inode
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/fuse/
+
+pub mod dev;
+pub mod fs;
+pub mod protocol;
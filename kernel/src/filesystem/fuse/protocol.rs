@@ -0,0 +1,402 @@
+//! FUSE的消息格式，与Linux的uapi/linux/fuse.h中的7.31版本相同
+//!
+//! 每个请求由[`FuseInHeader`]、操作码对应的参数结构体以及可选的文件名组成，
+//! 回复由[`FuseOutHeader`]与结果结构体(或者数据)组成。所有整数都使用本机字节序。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/fuse.h
+
+use core::mem::size_of;
+
+use alloc::{string::String, vec::Vec};
+
+use crate::syscall::SystemError;
+
+/// 协议的版本
+pub const FUSE_KERNEL_VERSION: u32 = 7;
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+
+/// 根目录的节点号
+pub const FUSE_ROOT_ID: u64 = 1;
+
+/// 守护进程读取请求时使用的缓冲区的最小长度
+pub const FUSE_MIN_READ_BUFFER: usize = 8192;
+
+/// 操作码
+pub const FUSE_LOOKUP: u32 = 1;
+pub const FUSE_FORGET: u32 = 2;
+pub const FUSE_GETATTR: u32 = 3;
+pub const FUSE_SETATTR: u32 = 4;
+pub const FUSE_READLINK: u32 = 5;
+pub const FUSE_MKNOD: u32 = 8;
+pub const FUSE_MKDIR: u32 = 9;
+pub const FUSE_UNLINK: u32 = 10;
+pub const FUSE_RMDIR: u32 = 11;
+pub const FUSE_RENAME: u32 = 12;
+pub const FUSE_LINK: u32 = 13;
+pub const FUSE_OPEN: u32 = 14;
+pub const FUSE_READ: u32 = 15;
+pub const FUSE_WRITE: u32 = 16;
+pub const FUSE_RELEASE: u32 = 18;
+pub const FUSE_INIT: u32 = 26;
+pub const FUSE_OPENDIR: u32 = 27;
+pub const FUSE_READDIR: u32 = 28;
+pub const FUSE_RELEASEDIR: u32 = 29;
+pub const FUSE_CREATE: u32 = 35;
+
+/// fuse_setattr_in.valid中的标志
+pub const FATTR_MODE: u32 = 1 << 0;
+pub const FATTR_UID: u32 = 1 << 1;
+pub const FATTR_GID: u32 = 1 << 2;
+pub const FATTR_SIZE: u32 = 1 << 3;
+pub const FATTR_ATIME: u32 = 1 << 4;
+pub const FATTR_MTIME: u32 = 1 << 5;
+
+/// @brief 可以直接按照内存布局与字节相互转换的消息结构体
+///
+/// # Safety
+///
+/// 实现者必须是`#[repr(C)]`的、只包含整数字段并且没有填充字节的结构体
+pub unsafe trait FuseAbi: Copy + Default {
+    fn as_bytes(&self) -> &[u8] {
+        return unsafe {
+            core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>())
+        };
+    }
+
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        return unsafe {
+            core::slice::from_raw_parts_mut(self as *mut Self as *mut u8, size_of::<Self>())
+        };
+    }
+
+    /// @brief 从消息的开头解析结构体
+    ///
+    /// @return Err(SystemError::EIO) 消息的长度不够
+    fn read_from(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < size_of::<Self>() {
+            return Err(SystemError::EIO);
+        }
+        let mut result = Self::default();
+        result
+            .as_bytes_mut()
+            .copy_from_slice(&buf[..size_of::<Self>()]);
+        return Ok(result);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseInHeader {
+    pub len: u32,
+    pub opcode: u32,
+    pub unique: u64,
+    pub nodeid: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseOutHeader {
+    pub len: u32,
+    /// 0或者负数的错误码
+    pub error: i32,
+    pub unique: u64,
+}
+
+/// 文件的属性
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseAttr {
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    /// 包括文件类型在内的mode
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u32,
+    pub blksize: u32,
+    pub flags: u32,
+}
+
+/// LOOKUP、MKDIR、CREATE等操作返回的目录项
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseEntryOut {
+    /// 为0时表示文件不存在
+    pub nodeid: u64,
+    pub generation: u64,
+    /// 目录项与属性的有效时间
+    pub entry_valid: u64,
+    pub attr_valid: u64,
+    pub entry_valid_nsec: u32,
+    pub attr_valid_nsec: u32,
+    pub attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseForgetIn {
+    pub nlookup: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseGetattrIn {
+    pub getattr_flags: u32,
+    pub dummy: u32,
+    pub fh: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseAttrOut {
+    pub attr_valid: u64,
+    pub attr_valid_nsec: u32,
+    pub dummy: u32,
+    pub attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseMknodIn {
+    pub mode: u32,
+    pub rdev: u32,
+    pub umask: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseMkdirIn {
+    pub mode: u32,
+    pub umask: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseRenameIn {
+    pub newdir: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseLinkIn {
+    pub oldnodeid: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseSetattrIn {
+    /// 要修改的属性，FATTR_*
+    pub valid: u32,
+    pub padding: u32,
+    pub fh: u64,
+    pub size: u64,
+    pub lock_owner: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    pub mode: u32,
+    pub unused4: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub unused5: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseOpenIn {
+    pub flags: u32,
+    pub open_flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseCreateIn {
+    pub flags: u32,
+    pub mode: u32,
+    pub umask: u32,
+    pub open_flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseOpenOut {
+    /// 守护进程分配的文件句柄
+    pub fh: u64,
+    pub open_flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseReleaseIn {
+    pub fh: u64,
+    pub flags: u32,
+    pub release_flags: u32,
+    pub lock_owner: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseReadIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub read_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseWriteIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub write_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseWriteOut {
+    pub size: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseInitIn {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuseInitOut {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+    pub max_background: u16,
+    pub congestion_threshold: u16,
+    /// WRITE请求中数据的最大长度
+    pub max_write: u32,
+    pub time_gran: u32,
+    pub max_pages: u16,
+    pub map_alignment: u16,
+    pub flags2: u32,
+    pub unused: [u32; 7],
+}
+
+unsafe impl FuseAbi for FuseInHeader {}
+unsafe impl FuseAbi for FuseOutHeader {}
+unsafe impl FuseAbi for FuseAttr {}
+unsafe impl FuseAbi for FuseEntryOut {}
+unsafe impl FuseAbi for FuseForgetIn {}
+unsafe impl FuseAbi for FuseGetattrIn {}
+unsafe impl FuseAbi for FuseAttrOut {}
+unsafe impl FuseAbi for FuseMknodIn {}
+unsafe impl FuseAbi for FuseMkdirIn {}
+unsafe impl FuseAbi for FuseRenameIn {}
+unsafe impl FuseAbi for FuseLinkIn {}
+unsafe impl FuseAbi for FuseSetattrIn {}
+unsafe impl FuseAbi for FuseOpenIn {}
+unsafe impl FuseAbi for FuseCreateIn {}
+unsafe impl FuseAbi for FuseOpenOut {}
+unsafe impl FuseAbi for FuseReleaseIn {}
+unsafe impl FuseAbi for FuseReadIn {}
+unsafe impl FuseAbi for FuseWriteIn {}
+unsafe impl FuseAbi for FuseWriteOut {}
+unsafe impl FuseAbi for FuseInitIn {}
+unsafe impl FuseAbi for FuseInitOut {}
+
+/// @brief 构造请求的参数：依次追加结构体与以'\0'结尾的文件名
+#[derive(Debug, Default)]
+pub struct FuseArgs {
+    buf: Vec<u8>,
+}
+
+impl FuseArgs {
+    pub fn new() -> Self {
+        return Self { buf: Vec::new() };
+    }
+
+    pub fn push<T: FuseAbi>(mut self, v: &T) -> Self {
+        self.buf.extend_from_slice(v.as_bytes());
+        return self;
+    }
+
+    pub fn push_bytes(mut self, data: &[u8]) -> Self {
+        self.buf.extend_from_slice(data);
+        return self;
+    }
+
+    pub fn push_name(mut self, name: &str) -> Self {
+        self.buf.extend_from_slice(name.as_bytes());
+        self.buf.push(0);
+        return self;
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        return self.buf;
+    }
+}
+
+/// READDIR返回的目录项
+#[derive(Debug, Clone)]
+pub struct FuseDirent {
+    pub ino: u64,
+    /// 读取下一个目录项时使用的偏移量
+    pub off: u64,
+    /// DT_*
+    pub ty: u32,
+    pub name: String,
+}
+
+/// fuse_dirent中名字之前的固定部分的长度
+const FUSE_NAME_OFFSET: usize = 24;
+
+impl FuseDirent {
+    /// @brief 解析READDIR的回复中的所有目录项，每个目录项对齐到8字节
+    pub fn parse_all(mut buf: &[u8]) -> Result<Vec<FuseDirent>, SystemError> {
+        let mut entries = Vec::new();
+        while buf.len() >= FUSE_NAME_OFFSET {
+            let u64_at = |i: usize| u64::from_ne_bytes(buf[i..i + 8].try_into().unwrap());
+            let u32_at = |i: usize| u32::from_ne_bytes(buf[i..i + 4].try_into().unwrap());
+            let namelen = u32_at(16) as usize;
+            let end = FUSE_NAME_OFFSET + namelen;
+            if end > buf.len() {
+                return Err(SystemError::EIO);
+            }
+            entries.push(FuseDirent {
+                ino: u64_at(0),
+                off: u64_at(8),
+                ty: u32_at(20),
+                name: String::from_utf8_lossy(&buf[FUSE_NAME_OFFSET..end]).into_owned(),
+            });
+            buf = &buf[((end + 7) & !7).min(buf.len())..];
+        }
+        return Ok(entries);
+    }
+}
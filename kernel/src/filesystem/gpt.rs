@@ -0,0 +1,88 @@
+#![allow(dead_code)]
+//! GUID分区表(GPT)的磁盘结构
+//!
+//! GPT头位于磁盘的LBA 1，备份位于最后一个LBA。分区表项数组的位置由GPT头给出，所有字段都是小端字节序。
+//!
+//! 参考 https://uefi.org/specs/UEFI/2.10/05_GUID_Partition_Table_Format.html
+
+use alloc::string::String;
+
+use crate::on_disk_struct;
+
+on_disk_struct! {
+    endian: Little;
+    /// @brief GPT头
+    #[derive(Debug, Clone, Copy)]
+    pub struct GptHeader {
+        /// "EFI PART"
+        pub signature: [u8; 8],
+        pub revision: u32,
+        /// GPT头的长度，不小于92
+        pub header_size: u32,
+        /// 计算时本字段为0
+        pub header_crc32: u32,
+        pub reserved: u32,
+        /// 当前这个GPT头所在的LBA
+        pub my_lba: u64,
+        /// 另一个GPT头所在的LBA
+        pub alternate_lba: u64,
+        pub first_usable_lba: u64,
+        pub last_usable_lba: u64,
+        pub disk_guid: [u8; 16],
+        /// 分区表项数组的起始LBA
+        pub partition_entry_lba: u64,
+        pub num_partition_entries: u32,
+        /// 每个分区表项的长度，为128 * 2^n
+        pub size_of_partition_entry: u32,
+        pub partition_entry_array_crc32: u32,
+    }
+}
+
+impl GptHeader {
+    pub const SIGNATURE: [u8; 8] = *b"EFI PART";
+
+    /// @brief 判断签名与各项长度是否合理
+    pub fn is_valid(&self) -> bool {
+        return self.signature == Self::SIGNATURE
+            && self.header_size >= 92
+            && self.size_of_partition_entry >= 128
+            && self.size_of_partition_entry.is_power_of_two();
+    }
+}
+
+on_disk_struct! {
+    endian: Little;
+    /// @brief GPT分区表项
+    #[derive(Debug, Clone, Copy)]
+    pub struct GptPartitionEntry {
+        /// 分区类型，全为0表示这个表项没有被使用
+        pub partition_type_guid: [u8; 16],
+        pub unique_partition_guid: [u8; 16],
+        pub starting_lba: u64,
+        /// 分区的最后一个LBA(包含)
+        pub ending_lba: u64,
+        pub attributes: u64,
+        /// UTF-16LE编码的分区名
+        pub partition_name: [u16; 36],
+    }
+}
+
+impl GptPartitionEntry {
+    /// @brief 这个表项是否没有被使用
+    pub fn is_unused(&self) -> bool {
+        return self.partition_type_guid.iter().all(|b| *b == 0);
+    }
+
+    /// @brief 分区占用的LBA数量
+    pub fn total_sectors(&self) -> u64 {
+        return self.ending_lba + 1 - self.starting_lba;
+    }
+
+    /// @brief 分区名，到第一个0为止
+    pub fn name(&self) -> String {
+        let units = self.partition_name.iter().copied().take_while(|c| *c != 0);
+        return char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+    }
+}
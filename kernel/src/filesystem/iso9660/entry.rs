@@ -0,0 +1,217 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{kerror, syscall::SystemError, time::TimeSpec};
+
+/// 目录记录中，文件名之前的固定部分的长度
+pub const DIR_RECORD_HEADER_LEN: usize = 33;
+
+bitflags! {
+    /// 目录记录的文件标志
+    pub struct DirRecordFlags: u8 {
+        /// 隐藏文件
+        const HIDDEN = 1 << 0;
+        /// 目录
+        const DIRECTORY = 1 << 1;
+        /// 关联文件(例如Macintosh的资源分支)
+        const ASSOCIATED = 1 << 2;
+        /// 文件的格式由扩展属性记录描述
+        const RECORD = 1 << 3;
+        /// 权限由扩展属性记录描述
+        const PROTECTION = 1 << 4;
+        /// 文件还有后续的区段，下一个同名的目录记录描述后续的区段
+        const MULTI_EXTENT = 1 << 7;
+    }
+}
+
+/// 磁盘上的目录记录
+///
+/// 参考 ECMA-119 9.1
+#[derive(Debug, Clone)]
+pub struct DirRecord {
+    /// 扩展属性记录占用的逻辑块数，文件的数据紧跟在扩展属性记录之后
+    pub ext_attr_len: u8,
+    /// 区段的起始逻辑块号
+    pub extent: u32,
+    /// 区段的字节数
+    pub data_len: u32,
+    /// 记录时间
+    pub time: TimeSpec,
+    pub flags: DirRecordFlags,
+    /// 原始的文件标识符
+    pub name: Vec<u8>,
+    /// 系统使用区，Rock Ridge扩展保存在这里
+    pub system_use: Vec<u8>,
+}
+
+impl DirRecord {
+    /// @brief 从目录记录的字节中解析目录记录
+    ///
+    /// @param buf 目录记录，长度为记录的第0个字节指定的长度
+    ///
+    /// @return Err(SystemError::EIO) 目录记录已损坏
+    pub fn parse(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < DIR_RECORD_HEADER_LEN || buf.len() < DIR_RECORD_HEADER_LEN + buf[32] as usize
+        {
+            kerror!("iso9660: corrupted directory record, len={}", buf.len());
+            return Err(SystemError::EIO);
+        }
+
+        let name_len = buf[32] as usize;
+        // 文件名的长度为偶数时，后面有一个填充字节，使系统使用区从偶数偏移量开始
+        let system_use_start = DIR_RECORD_HEADER_LEN + name_len + (1 - name_len % 2);
+        let system_use = if system_use_start < buf.len() {
+            buf[system_use_start..].to_vec()
+        } else {
+            Vec::new()
+        };
+
+        return Ok(Self {
+            ext_attr_len: buf[1],
+            extent: read_u32_le(&buf[2..6]),
+            data_len: read_u32_le(&buf[10..14]),
+            time: record_time_to_timespec(&buf[18..25]),
+            flags: DirRecordFlags::from_bits_truncate(buf[25]),
+            name: buf[DIR_RECORD_HEADER_LEN..DIR_RECORD_HEADER_LEN + name_len].to_vec(),
+            system_use,
+        });
+    }
+
+    /// @brief 是否为目录中表示自身的"."记录或者表示父目录的".."记录
+    pub fn is_dot_or_dotdot(&self) -> bool {
+        return self.name.len() == 1 && (self.name[0] == 0 || self.name[0] == 1);
+    }
+
+    /// @brief 文件数据的起始逻辑块号
+    pub fn data_block(&self) -> u32 {
+        return self.extent + self.ext_attr_len as u32;
+    }
+
+    /// @brief 把文件标识符转换为文件名
+    ///
+    /// 去掉文件名末尾的版本号(";1")与空的扩展名("FILE."中的".")，
+    /// 没有Joliet扩展时与Linux一样把文件名转换为小写
+    ///
+    /// @param joliet 文件标识符是否为Joliet扩展中的UCS-2(大端)字符
+    pub fn decode_name(&self, joliet: bool) -> String {
+        let mut name: String = if joliet {
+            let units = self
+                .name
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]));
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        } else {
+            self.name
+                .iter()
+                .map(|c| c.to_ascii_lowercase() as char)
+                .collect()
+        };
+
+        if let Some(pos) = name.rfind(';') {
+            name.truncate(pos);
+        }
+        if name.len() > 1 && name.ends_with('.') {
+            name.pop();
+        }
+        // Joliet文件名可以包含"/"，而"/"在DragonOS中是路径分隔符
+        return name.replace('/', "_");
+    }
+}
+
+#[inline]
+pub fn read_u16_le(buf: &[u8]) -> u16 {
+    return u16::from_le_bytes([buf[0], buf[1]]);
+}
+
+#[inline]
+pub fn read_u32_le(buf: &[u8]) -> u32 {
+    return u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+}
+
+/// @brief 转换目录记录中的7字节时间
+///
+/// 依次为：从1900年开始的年数、月、日、时、分、秒、以15分钟为单位的时区偏移量(有符号)
+///
+/// @return 全部为0或者不合法时返回0
+pub fn record_time_to_timespec(buf: &[u8]) -> TimeSpec {
+    return date_to_timespec(
+        1900 + buf[0] as i64,
+        buf[1] as i64,
+        buf[2] as i64,
+        buf[3] as i64,
+        buf[4] as i64,
+        buf[5] as i64,
+        buf[6] as i8,
+    );
+}
+
+/// @brief 转换卷描述符与Rock Ridge中使用的17字节时间
+///
+/// 前16个字节为"YYYYMMDDHHMMSScc"形式的数字字符，cc为百分之一秒，最后一个字节为时区偏移量
+///
+/// @return 全部为0或者不合法时返回0
+pub fn long_time_to_timespec(buf: &[u8]) -> TimeSpec {
+    let digits = |range: core::ops::Range<usize>| -> Option<i64> {
+        let mut v = 0;
+        for c in &buf[range] {
+            if !c.is_ascii_digit() {
+                return None;
+            }
+            v = v * 10 + (c - b'0') as i64;
+        }
+        return Some(v);
+    };
+
+    let fields = (
+        digits(0..4),
+        digits(4..6),
+        digits(6..8),
+        digits(8..10),
+        digits(10..12),
+        digits(12..14),
+        digits(14..16),
+    );
+    match fields {
+        (Some(year), Some(month), Some(day), Some(hour), Some(min), Some(sec), Some(centi)) => {
+            let mut time = date_to_timespec(year, month, day, hour, min, sec, buf[16] as i8);
+            if time.tv_sec != 0 {
+                time.tv_nsec = centi * 10_000_000;
+            }
+            return time;
+        }
+        _ => return TimeSpec::default(),
+    }
+}
+
+/// @brief 把本地时间转换为UNIX时间
+///
+/// @param gmt_offset 以15分钟为单位的时区偏移量
+fn date_to_timespec(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    gmt_offset: i8,
+) -> TimeSpec {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return TimeSpec::default();
+    }
+
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + min * 60 + sec
+        - gmt_offset as i64 * 15 * 60;
+    return TimeSpec::new(secs, 0);
+}
+
+/// @brief 计算公历日期距离1970年1月1日的天数
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // 把3月作为一年的第一个月，这样闰日位于一年的末尾
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
@@ -0,0 +1,712 @@
+use core::any::Any;
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    driver::base::block::{
+        block_device::{BlockDevice, LBA_SIZE},
+        disk_info::Partition,
+    },
+    filesystem::vfs::{
+        core::generate_inode_id,
+        file::{FileMode, FilePrivateData},
+        syscall::ModeType,
+        FileSystem, FileType, FsInfo, IndexNode, InodeId, Metadata, PollStatus, PollTable,
+    },
+    kerror, kinfo,
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    syscall::SystemError,
+};
+
+use super::{
+    entry::{read_u16_le, DirRecord, DirRecordFlags, DIR_RECORD_HEADER_LEN},
+    rock_ridge::RockRidge,
+};
+
+/// 卷描述符的大小，卷描述符从第16个描述符开始存放
+const ISO_VD_SIZE: usize = 2048;
+const ISO_VD_START: usize = 16;
+/// 最多查找的卷描述符数量
+const ISO_VD_MAX: usize = 64;
+/// 卷描述符的类型
+const ISO_VD_PRIMARY: u8 = 1;
+const ISO_VD_SUPPLEMENTARY: u8 = 2;
+const ISO_VD_TERMINATOR: u8 = 255;
+/// 卷描述符中的标准标识符
+const ISO_STANDARD_ID: &[u8; 5] = b"CD001";
+
+/// 文件名的最大长度(Rock Ridge扩展中的长文件名)
+const ISO9660_MAX_NAMELEN: usize = 255;
+/// 读取文件时，每次从磁盘读取的最大字节数
+const ISO9660_READ_CHUNK: usize = 64 * 1024;
+
+/// @brief ISO9660文件系统
+///
+/// 文件系统是只读的。挂载时优先使用主卷描述符上的Rock Ridge扩展，没有Rock Ridge时使用Joliet扩展的目录树，
+/// 两者都没有时使用ISO9660的8.3文件名
+#[derive(Debug)]
+pub struct Iso9660FileSystem {
+    /// 当前文件系统所在的分区
+    partition: Arc<Partition>,
+    /// 分区所在的磁盘。持有强引用，使映射设备被删除之后，已挂载的文件系统仍然可以访问设备
+    disk: Arc<dyn BlockDevice>,
+    /// 逻辑块的字节数
+    block_size: usize,
+    /// 文件标识符是否为Joliet扩展中的UCS-2字符
+    joliet: bool,
+    /// 使用Rock Ridge扩展时，每个系统使用区开头需要跳过的字节数
+    rock_ridge_skip: Option<usize>,
+    /// 卷标
+    volume_id: String,
+    /// 文件系统的根inode
+    root_inode: Arc<LockedIso9660Inode>,
+}
+
+/// ISO9660文件系统的Inode
+#[derive(Debug)]
+pub struct LockedIso9660Inode(SpinLock<Iso9660Inode>);
+
+#[derive(Debug)]
+pub struct Iso9660Inode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedIso9660Inode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedIso9660Inode>,
+    /// 子Inode的B树，key为文件名。第一次查找或者列出目录时读取整个目录，由于文件系统只读，之后不再改变
+    children: Option<BTreeMap<String, Arc<LockedIso9660Inode>>>,
+    /// 文件数据所在的区段：(起始逻辑块号, 字节数)。超过4GB的文件由多个区段组成
+    extents: Vec<(u32, u32)>,
+    /// 符号链接的目标
+    symlink: Option<String>,
+    /// 当前inode的元数据
+    metadata: Metadata,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<Iso9660FileSystem>,
+}
+
+/// 从目录记录以及Rock Ridge扩展中得到的文件信息
+#[derive(Debug)]
+struct Iso9660DirEntry {
+    name: String,
+    extents: Vec<(u32, u32)>,
+    symlink: Option<String>,
+    metadata: Metadata,
+}
+
+impl FileSystem for Iso9660FileSystem {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: ISO9660_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Iso9660FileSystem {
+    /// @brief 在分区上挂载ISO9660文件系统
+    ///
+    /// @param partition 文件系统所在的分区
+    ///
+    /// @return Err(SystemError::EINVAL) 分区上不是ISO9660文件系统
+    /// @return Err(SystemError::EIO) 读取磁盘失败，或者文件系统已损坏
+    pub fn new(partition: Arc<Partition>) -> Result<Arc<Iso9660FileSystem>, SystemError> {
+        let mut fs = Iso9660FileSystem {
+            disk: partition.disk(),
+            partition,
+            block_size: ISO_VD_SIZE,
+            joliet: false,
+            rock_ridge_skip: None,
+            volume_id: String::new(),
+            root_inode: Arc::new(LockedIso9660Inode(SpinLock::new(Iso9660Inode {
+                parent: Weak::default(),
+                self_ref: Weak::default(),
+                children: None,
+                extents: Vec::new(),
+                symlink: None,
+                metadata: Metadata::default(),
+                fs: Weak::default(),
+            }))),
+        };
+
+        // 查找主卷描述符与Joliet扩展的辅助卷描述符
+        let mut primary: Option<Vec<u8>> = None;
+        let mut joliet: Option<Vec<u8>> = None;
+        for i in 0..ISO_VD_MAX {
+            let mut vd = vec![0u8; ISO_VD_SIZE];
+            fs.read_bytes(((ISO_VD_START + i) * ISO_VD_SIZE) as u64, &mut vd)?;
+            if &vd[1..6] != ISO_STANDARD_ID {
+                return Err(SystemError::EINVAL);
+            }
+            match vd[0] {
+                ISO_VD_PRIMARY if primary.is_none() => primary = Some(vd),
+                ISO_VD_SUPPLEMENTARY if joliet.is_none() && Self::is_joliet(&vd) => {
+                    joliet = Some(vd)
+                }
+                ISO_VD_TERMINATOR => break,
+                _ => {}
+            }
+        }
+        let primary = primary.ok_or(SystemError::EINVAL)?;
+
+        fs.block_size = read_u16_le(&primary[128..130]) as usize;
+        if !matches!(fs.block_size, 512 | 1024 | 2048) {
+            kerror!("iso9660: unsupported logical block size {}", fs.block_size);
+            return Err(SystemError::EINVAL);
+        }
+        fs.volume_id = String::from_utf8_lossy(&primary[40..72]).trim_end().into();
+
+        // 根目录的"."记录的系统使用区以SP条目开头时，文件系统使用了Rock Ridge扩展
+        let mut root = DirRecord::parse(&primary[156..190])?;
+        let mut root_dot = fs.read_dot_record(root.data_block())?;
+        fs.rock_ridge_skip = RockRidge::detect(&root_dot.system_use);
+        if fs.rock_ridge_skip.is_none() {
+            if let Some(vd) = joliet {
+                root = DirRecord::parse(&vd[156..190])?;
+                root_dot = fs.read_dot_record(root.data_block())?;
+                fs.joliet = true;
+            }
+        }
+
+        // 根目录的属性保存在它的"."记录中
+        let mut root_entry = fs
+            .decode_record(&root_dot, false)?
+            .ok_or(SystemError::EIO)?;
+        if root_entry.metadata.file_type != FileType::Dir {
+            kerror!("iso9660: root directory record is not a directory");
+            return Err(SystemError::EIO);
+        }
+        root_entry.extents = vec![(root.data_block(), root.data_len)];
+
+        kinfo!(
+            "iso9660: volume '{}', rock ridge: {}, joliet: {}",
+            fs.volume_id,
+            fs.rock_ridge_skip.is_some(),
+            fs.joliet
+        );
+
+        let result: Arc<Iso9660FileSystem> = Arc::new(fs);
+
+        // 对root inode加锁，并继续完成初始化工作
+        let mut root_guard: SpinLockGuard<Iso9660Inode> = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        root_guard.fs = Arc::downgrade(&result);
+        root_guard.extents = root_entry.extents;
+        root_guard.metadata = root_entry.metadata;
+        drop(root_guard);
+
+        return Ok(result);
+    }
+
+    /// @brief 卷标
+    pub fn volume_id(&self) -> &str {
+        return &self.volume_id;
+    }
+
+    /// @brief 判断辅助卷描述符是否属于Joliet扩展
+    ///
+    /// Joliet扩展通过转义序列"%/@"、"%/C"或者"%/E"(分别对应UCS-2的3个等级)标识
+    fn is_joliet(vd: &[u8]) -> bool {
+        let esc = &vd[88..91];
+        return esc[0] == b'%' && esc[1] == b'/' && matches!(esc[2], b'@' | b'C' | b'E');
+    }
+
+    /// @brief 从分区中读取数据
+    ///
+    /// @param offset 在分区中的字节偏移量，必须对齐到扇区
+    /// @param buf 缓冲区，长度必须是扇区大小的整数倍
+    ///
+    /// @return Err(SystemError::EIO) 读取的范围超出了分区
+    fn read_bytes(&self, offset: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        if offset + buf.len() as u64 > self.partition.sectors_num * LBA_SIZE as u64 {
+            return Err(SystemError::EIO);
+        }
+        let disk_offset = self.partition.lba_start * LBA_SIZE as u64 + offset;
+        self.disk
+            .read_at_bytes(disk_offset as usize, buf.len(), buf)?;
+        return Ok(());
+    }
+
+    /// @brief 读取从`block`开始的逻辑块，块数由`buf`的长度决定
+    fn read_blocks(&self, block: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        return self.read_bytes(block * self.block_size as u64, buf);
+    }
+
+    /// @brief 读取Rock Ridge扩展的延续区
+    ///
+    /// @return Err(SystemError::EIO) 延续区跨越了逻辑块的边界
+    pub fn read_continuation(
+        &self,
+        block: u32,
+        offset: u32,
+        len: u32,
+    ) -> Result<Vec<u8>, SystemError> {
+        let (offset, len) = (offset as usize, len as usize);
+        if offset + len > self.block_size {
+            kerror!("iso9660: invalid continuation area at block {}", block);
+            return Err(SystemError::EIO);
+        }
+        let mut buf = vec![0u8; self.block_size];
+        self.read_blocks(block as u64, &mut buf)?;
+        return Ok(buf[offset..offset + len].to_vec());
+    }
+
+    /// @brief 读取目录的第一个目录记录，即表示目录自身的"."记录
+    fn read_dot_record(&self, block: u32) -> Result<DirRecord, SystemError> {
+        let mut buf = vec![0u8; self.block_size];
+        self.read_blocks(block as u64, &mut buf)?;
+        let len = buf[0] as usize;
+        if len < DIR_RECORD_HEADER_LEN {
+            kerror!("iso9660: no '.' record in directory at block {}", block);
+            return Err(SystemError::EIO);
+        }
+        let record = DirRecord::parse(&buf[..len])?;
+        if !record.is_dot_or_dotdot() {
+            kerror!("iso9660: no '.' record in directory at block {}", block);
+            return Err(SystemError::EIO);
+        }
+        return Ok(record);
+    }
+
+    /// @brief 读取目录中的所有文件
+    ///
+    /// 忽略"."、".."、关联文件以及被Rock Ridge重定位的目录，多个区段组成的文件合并为一项
+    ///
+    /// @param block 目录的起始逻辑块号
+    /// @param len 目录的字节数
+    fn read_dir(&self, block: u32, len: u32) -> Result<Vec<Iso9660DirEntry>, SystemError> {
+        let bs = self.block_size;
+        let mut data = vec![0u8; (len as usize + bs - 1) / bs * bs];
+        self.read_blocks(block as u64, &mut data)?;
+
+        let mut entries: Vec<Iso9660DirEntry> = Vec::new();
+        // 上一个目录记录的文件标识符，以及它是否还有后续的区段
+        let mut last_name: Vec<u8> = Vec::new();
+        let mut last_multi_extent = false;
+        let mut pos = 0;
+        while pos < len as usize {
+            // 目录记录不会跨越逻辑块，长度为0表示当前逻辑块中没有更多的目录记录
+            let rec_len = data[pos] as usize;
+            if rec_len == 0 {
+                pos = (pos / bs + 1) * bs;
+                continue;
+            }
+            if pos + rec_len > data.len() {
+                kerror!("iso9660: directory record crosses the end of directory");
+                return Err(SystemError::EIO);
+            }
+            let record = DirRecord::parse(&data[pos..pos + rec_len])?;
+            pos += rec_len;
+
+            if record.is_dot_or_dotdot() || record.flags.contains(DirRecordFlags::ASSOCIATED) {
+                continue;
+            }
+
+            // 文件的后续区段
+            if last_multi_extent && record.name == last_name {
+                if let Some(entry) = entries.last_mut() {
+                    entry.extents.push((record.data_block(), record.data_len));
+                    entry.metadata.size += record.data_len as i64;
+                    entry.metadata.blocks += (record.data_len as usize + bs - 1) / bs;
+                }
+                last_multi_extent = record.flags.contains(DirRecordFlags::MULTI_EXTENT);
+                continue;
+            }
+            last_multi_extent = record.flags.contains(DirRecordFlags::MULTI_EXTENT);
+            last_name = record.name.clone();
+
+            if let Some(entry) = self.decode_record(&record, true)? {
+                if !entry.name.is_empty() {
+                    entries.push(entry);
+                }
+            }
+        }
+        return Ok(entries);
+    }
+
+    /// @brief 根据目录记录以及其中的Rock Ridge扩展生成文件信息
+    ///
+    /// @param follow_child_link 是否跟随CL条目，读取被重定位的目录
+    ///
+    /// @return Ok(None) 目录记录是被重定位的目录，应该忽略
+    fn decode_record(
+        &self,
+        record: &DirRecord,
+        follow_child_link: bool,
+    ) -> Result<Option<Iso9660DirEntry>, SystemError> {
+        let bs = self.block_size;
+        let is_dir = record.flags.contains(DirRecordFlags::DIRECTORY);
+        let mut entry = Iso9660DirEntry {
+            name: record.decode_name(self.joliet),
+            extents: vec![(record.data_block(), record.data_len)],
+            symlink: None,
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                size: record.data_len as i64,
+                blk_size: bs,
+                blocks: (record.data_len as usize + bs - 1) / bs,
+                atime: record.time,
+                mtime: record.time,
+                ctime: record.time,
+                file_type: if is_dir {
+                    FileType::Dir
+                } else {
+                    FileType::File
+                },
+                mode: ModeType::from_bits_truncate(0o555),
+                nlinks: if is_dir { 2 } else { 1 },
+                uid: 0,
+                gid: 0,
+                raw_dev: 0,
+            },
+        };
+
+        let skip = match self.rock_ridge_skip {
+            Some(skip) => skip,
+            None => return Ok(Some(entry)),
+        };
+        let rr = RockRidge::parse(self, &record.system_use, skip)?;
+        if rr.relocated {
+            return Ok(None);
+        }
+        if let Some(name) = &rr.name {
+            entry.name = String::from_utf8_lossy(name).replace('/', "_");
+        }
+
+        // 被重定位的目录：这个目录记录只是占位的文件，目录本身的属性保存在它的"."记录中
+        if let Some(block) = rr.child_link {
+            if !follow_child_link {
+                return Err(SystemError::EIO);
+            }
+            let dot = self.read_dot_record(block)?;
+            let mut dir = self.decode_record(&dot, false)?.ok_or(SystemError::EIO)?;
+            if dir.metadata.file_type != FileType::Dir {
+                return Err(SystemError::EIO);
+            }
+            dir.name = entry.name;
+            return Ok(Some(dir));
+        }
+
+        if let Some(mode) = rr.mode {
+            let fmt = ModeType::from_bits_truncate(mode) & ModeType::S_IFMT;
+            if !is_dir {
+                entry.metadata.file_type = if fmt == ModeType::S_IFLNK {
+                    FileType::SymLink
+                } else if fmt == ModeType::S_IFCHR {
+                    FileType::CharDevice
+                } else if fmt == ModeType::S_IFBLK {
+                    FileType::BlockDevice
+                } else if fmt == ModeType::S_IFIFO {
+                    FileType::Pipe
+                } else if fmt == ModeType::S_IFSOCK {
+                    FileType::Socket
+                } else {
+                    FileType::File
+                };
+            }
+            entry.metadata.mode = ModeType::from_bits_truncate(mode & 0o7777);
+        }
+        if let Some(nlinks) = rr.nlinks {
+            entry.metadata.nlinks = nlinks as usize;
+        }
+        if let Some(uid) = rr.uid {
+            entry.metadata.uid = uid as usize;
+        }
+        if let Some(gid) = rr.gid {
+            entry.metadata.gid = gid as usize;
+        }
+        entry.metadata.atime = rr.atime.unwrap_or(entry.metadata.atime);
+        entry.metadata.mtime = rr.mtime.unwrap_or(entry.metadata.mtime);
+        entry.metadata.ctime = rr.ctime.unwrap_or(entry.metadata.ctime);
+
+        if entry.metadata.file_type == FileType::SymLink {
+            let target = rr.symlink.unwrap_or_default();
+            entry.metadata.size = target.len() as i64;
+            entry.symlink = Some(target);
+        }
+        return Ok(Some(entry));
+    }
+
+    /// @brief 读取文件的数据
+    ///
+    /// @param extents 文件数据所在的区段
+    /// @param size 文件的字节数
+    /// @param offset 起始位置在文件中的偏移量
+    /// @param buf 缓冲区
+    ///
+    /// @return 读取的字节数
+    fn read_file(
+        &self,
+        extents: &[(u32, u32)],
+        size: usize,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if offset >= size {
+            return Ok(0);
+        }
+        let end = core::cmp::min(offset + buf.len(), size);
+        let bs = self.block_size;
+
+        let mut extent_start = 0;
+        for &(block, len) in extents {
+            let extent_end = extent_start + len as usize;
+            let mut pos = core::cmp::max(offset, extent_start);
+            let stop = core::cmp::min(end, extent_end);
+            while pos < stop {
+                // 在区段内的偏移量
+                let rel = pos - extent_start;
+                let skip = rel % bs;
+                let n = core::cmp::min(stop - pos, ISO9660_READ_CHUNK - skip);
+                let mut data = vec![0u8; (skip + n + bs - 1) / bs * bs];
+                self.read_blocks(block as u64 + (rel / bs) as u64, &mut data)?;
+                buf[pos - offset..pos - offset + n].copy_from_slice(&data[skip..skip + n]);
+                pos += n;
+            }
+            extent_start = extent_end;
+        }
+        return Ok(end - offset);
+    }
+}
+
+impl Iso9660Inode {
+    /// @brief 读取目录中的所有文件，生成子inode的缓存
+    fn load_children(&mut self) -> Result<(), SystemError> {
+        if self.children.is_some() {
+            return Ok(());
+        }
+        if self.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let fs = self.fs.upgrade().unwrap();
+        let (block, len) = self.extents[0];
+        let mut children = BTreeMap::new();
+        for entry in fs.read_dir(block, len)? {
+            // 有重名的文件时，只保留第一个
+            if children.contains_key(&entry.name) {
+                continue;
+            }
+            let name = entry.name.clone();
+            let inode = LockedIso9660Inode::new(&fs, self.self_ref.clone(), entry);
+            children.insert(name, inode);
+        }
+        self.children = Some(children);
+        return Ok(());
+    }
+
+    fn find(&mut self, name: &str) -> Result<Arc<LockedIso9660Inode>, SystemError> {
+        if self.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        match name {
+            "" | "." => return self.self_ref.upgrade().ok_or(SystemError::ENOENT),
+            ".." => return self.parent.upgrade().ok_or(SystemError::ENOENT),
+            _ => {}
+        }
+
+        self.load_children()?;
+        return self
+            .children
+            .as_ref()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or(SystemError::ENOENT);
+    }
+}
+
+impl LockedIso9660Inode {
+    fn new(
+        fs: &Arc<Iso9660FileSystem>,
+        parent: Weak<LockedIso9660Inode>,
+        entry: Iso9660DirEntry,
+    ) -> Arc<LockedIso9660Inode> {
+        let inode = Arc::new(LockedIso9660Inode(SpinLock::new(Iso9660Inode {
+            parent,
+            self_ref: Weak::default(),
+            children: None,
+            extents: entry.extents,
+            symlink: entry.symlink,
+            metadata: entry.metadata,
+            fs: Arc::downgrade(fs),
+        })));
+        inode.0.lock().self_ref = Arc::downgrade(&inode);
+        return inode;
+    }
+}
+
+impl IndexNode for LockedIso9660Inode {
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let guard: SpinLockGuard<Iso9660Inode> = self.0.lock();
+        match guard.metadata.file_type {
+            FileType::Dir => return Err(SystemError::EISDIR),
+            FileType::SymLink => {
+                // 符号链接的内容为它的目标
+                let target = guard.symlink.as_deref().unwrap_or("").as_bytes();
+                if offset >= target.len() {
+                    return Ok(0);
+                }
+                let n = core::cmp::min(len, target.len() - offset);
+                buf[..n].copy_from_slice(&target[offset..offset + n]);
+                return Ok(n);
+            }
+            _ => {
+                let fs = guard.fs.upgrade().unwrap();
+                return fs.read_file(
+                    &guard.extents,
+                    guard.metadata.size as usize,
+                    offset,
+                    &mut buf[0..len],
+                );
+            }
+        }
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        if self.0.lock().metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        return Ok(PollStatus::READ);
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        return self;
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.0.lock().metadata.clone());
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn truncate(&self, _len: usize) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn create_with_data(
+        &self,
+        _name: &str,
+        _file_type: FileType,
+        _mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn link(&self, _name: &str, _other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn unlink(&self, _name: &str) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn rmdir(&self, _name: &str) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn move_(
+        &self,
+        _old_name: &str,
+        _target: &Arc<dyn IndexNode>,
+        _new_name: &str,
+    ) -> Result<(), SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn mknod(
+        &self,
+        _filename: &str,
+        _mode: ModeType,
+        _dev_t: crate::driver::base::device::DeviceNumber,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        return Err(SystemError::EROFS);
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let mut guard: SpinLockGuard<Iso9660Inode> = self.0.lock();
+        guard.load_children()?;
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(guard.children.as_ref().unwrap().keys().cloned());
+        return Ok(keys);
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let target = self.0.lock().find(name)?;
+        return Ok(target);
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        let mut guard: SpinLockGuard<Iso9660Inode> = self.0.lock();
+        if guard.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        match ino.into() {
+            0 => return Ok(String::from(".")),
+            1 => return Ok(String::from("..")),
+            _ => {}
+        }
+
+        guard.load_children()?;
+        return guard
+            .children
+            .as_ref()
+            .unwrap()
+            .iter()
+            .find(|(_, inode)| inode.0.lock().metadata.inode_id == ino)
+            .map(|(name, _)| name.clone())
+            .ok_or(SystemError::ENOENT);
+    }
+}
@@ -0,0 +1,14 @@
+//! ISO9660文件系统(只读)
+//!
+//! 光盘镜像使用的文件系统，支持以下扩展：
+//!
+//! - Rock Ridge：POSIX文件属性、长文件名与符号链接，见[`rock_ridge`]
+//! - Joliet：保存在辅助卷描述符中的另一棵目录树，使用UCS-2编码的长文件名
+//!
+//! 可以通过mount系统调用把磁盘分区或者映射设备上的镜像挂载到任意目录，文件系统类型为`iso9660`。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/isofs/
+
+pub mod entry;
+pub mod fs;
+pub mod rock_ridge;
@@ -0,0 +1,233 @@
+//! Rock Ridge扩展
+//!
+//! Rock Ridge扩展把POSIX文件属性(权限、所有者、时间戳、长文件名、符号链接等)保存在目录记录的系统使用区中，
+//! 系统使用区按照SUSP(System Use Sharing Protocol)的格式划分为多个条目，每个条目以两个字符的签名开头。
+//! 系统使用区放不下的条目保存在由CE条目指向的延续区中。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/isofs/rock.c
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{syscall::SystemError, time::TimeSpec};
+
+use super::{
+    entry::{long_time_to_timespec, read_u32_le, record_time_to_timespec},
+    fs::Iso9660FileSystem,
+};
+
+/// SUSP条目的头部长度：签名(2字节)、条目长度、版本
+const SUSP_HEADER_LEN: usize = 4;
+/// 最多跟随的延续区数量，防止损坏的镜像中的CE条目形成环
+const SUSP_MAX_CONTINUATIONS: usize = 16;
+
+/// NM条目的标志：名字在下一个NM条目中继续
+const NM_CONTINUE: u8 = 1 << 0;
+/// NM条目的标志：名字为"."
+const NM_CURRENT: u8 = 1 << 1;
+/// NM条目的标志：名字为".."
+const NM_PARENT: u8 = 1 << 2;
+
+/// SL条目中组成部分的标志：这个组成部分在下一个组成部分中继续
+const SL_CONTINUE: u8 = 1 << 0;
+/// SL条目中组成部分的标志：组成部分为"."
+const SL_CURRENT: u8 = 1 << 1;
+/// SL条目中组成部分的标志：组成部分为".."
+const SL_PARENT: u8 = 1 << 2;
+/// SL条目中组成部分的标志：组成部分为根目录
+const SL_ROOT: u8 = 1 << 3;
+
+/// TF条目的标志
+const TF_CREATE: u8 = 1 << 0;
+const TF_MODIFY: u8 = 1 << 1;
+const TF_ACCESS: u8 = 1 << 2;
+const TF_ATTRIBUTES: u8 = 1 << 3;
+/// TF条目中的时间戳使用17字节的长格式
+const TF_LONG_FORM: u8 = 1 << 7;
+
+/// 从一个目录记录的系统使用区中解析出的Rock Ridge属性
+#[derive(Debug, Default)]
+pub struct RockRidge {
+    /// NM条目中的文件名
+    pub name: Option<Vec<u8>>,
+    /// PX条目中的文件类型与权限
+    pub mode: Option<u32>,
+    pub nlinks: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// SL条目中的符号链接目标
+    pub symlink: Option<String>,
+    pub atime: Option<TimeSpec>,
+    pub mtime: Option<TimeSpec>,
+    pub ctime: Option<TimeSpec>,
+    /// 存在RE条目，表示这个目录被重定位到了别处，不应该出现在目录列表中
+    pub relocated: bool,
+    /// CL条目指向的被重定位的目录的起始逻辑块号
+    pub child_link: Option<u32>,
+    /// 上一个NM条目表示名字还没有结束
+    name_continue: bool,
+    /// 符号链接目标中，上一个组成部分还没有结束
+    symlink_continue: bool,
+}
+
+impl RockRidge {
+    /// @brief 解析目录记录的系统使用区
+    ///
+    /// @param fs 文件系统，用于读取CE条目指向的延续区
+    /// @param system_use 目录记录的系统使用区
+    /// @param skip 每个系统使用区开头需要跳过的字节数，由根目录的SP条目指定
+    ///
+    /// @return Err(SystemError::EIO) 读取延续区失败
+    pub fn parse(
+        fs: &Iso9660FileSystem,
+        system_use: &[u8],
+        skip: usize,
+    ) -> Result<Self, SystemError> {
+        let mut rr = Self::default();
+        let mut area: Vec<u8> = system_use.get(skip..).unwrap_or(&[]).to_vec();
+
+        for _ in 0..=SUSP_MAX_CONTINUATIONS {
+            let next = rr.parse_area(&area);
+            match next {
+                Some((block, offset, len)) => {
+                    area = fs.read_continuation(block, offset, len)?;
+                }
+                None => return Ok(rr),
+            }
+        }
+        return Ok(rr);
+    }
+
+    /// @brief 检查系统使用区是否以SP条目开头，即文件系统是否使用了SUSP
+    ///
+    /// @return 使用了SUSP时，返回每个系统使用区开头需要跳过的字节数
+    pub fn detect(system_use: &[u8]) -> Option<usize> {
+        if system_use.len() >= 7
+            && &system_use[0..2] == b"SP"
+            && system_use[4] == 0xbe
+            && system_use[5] == 0xef
+        {
+            return Some(system_use[6] as usize);
+        }
+        return None;
+    }
+
+    /// @brief 解析一段系统使用区或者延续区
+    ///
+    /// @return 其中有CE条目时，返回延续区的(逻辑块号, 块内偏移量, 长度)
+    fn parse_area(&mut self, area: &[u8]) -> Option<(u32, u32, u32)> {
+        let mut continuation = None;
+        let mut pos = 0;
+        while pos + SUSP_HEADER_LEN <= area.len() {
+            let len = area[pos + 2] as usize;
+            if len < SUSP_HEADER_LEN || pos + len > area.len() {
+                break;
+            }
+            let entry = &area[pos..pos + len];
+            pos += len;
+
+            match &entry[0..2] {
+                b"CE" if len >= 28 => {
+                    continuation = Some((
+                        read_u32_le(&entry[4..8]),
+                        read_u32_le(&entry[12..16]),
+                        read_u32_le(&entry[20..24]),
+                    ));
+                }
+                b"PX" if len >= 36 => {
+                    self.mode = Some(read_u32_le(&entry[4..8]));
+                    self.nlinks = Some(read_u32_le(&entry[12..16]));
+                    self.uid = Some(read_u32_le(&entry[20..24]));
+                    self.gid = Some(read_u32_le(&entry[28..32]));
+                }
+                b"NM" if len >= 5 => self.parse_nm(entry),
+                b"SL" if len >= 5 => self.parse_sl(entry),
+                b"TF" if len >= 5 => self.parse_tf(entry),
+                b"RE" => self.relocated = true,
+                b"CL" if len >= 12 => self.child_link = Some(read_u32_le(&entry[4..8])),
+                // 系统使用区的结束
+                b"ST" => break,
+                _ => {}
+            }
+        }
+        return continuation;
+    }
+
+    fn parse_nm(&mut self, entry: &[u8]) {
+        let flags = entry[4];
+        // "."与".."由目录记录本身表示，忽略
+        if flags & (NM_CURRENT | NM_PARENT) != 0 {
+            return;
+        }
+
+        let name = self.name.get_or_insert_with(Vec::new);
+        if !self.name_continue {
+            name.clear();
+        }
+        name.extend_from_slice(&entry[5..]);
+        self.name_continue = flags & NM_CONTINUE != 0;
+    }
+
+    fn parse_sl(&mut self, entry: &[u8]) {
+        let link = self.symlink.get_or_insert_with(String::new);
+        let mut pos = 5;
+        while pos + 2 <= entry.len() {
+            let flags = entry[pos];
+            let len = entry[pos + 1] as usize;
+            if pos + 2 + len > entry.len() {
+                break;
+            }
+            let content = &entry[pos + 2..pos + 2 + len];
+            pos += 2 + len;
+
+            if flags & SL_ROOT != 0 {
+                link.clear();
+                link.push('/');
+                self.symlink_continue = true;
+                continue;
+            }
+            if !self.symlink_continue && !link.is_empty() {
+                link.push('/');
+            }
+            if flags & SL_CURRENT != 0 {
+                link.push('.');
+            } else if flags & SL_PARENT != 0 {
+                link.push_str("..");
+            } else {
+                link.push_str(&String::from_utf8_lossy(content));
+            }
+            self.symlink_continue = flags & SL_CONTINUE != 0;
+        }
+    }
+
+    fn parse_tf(&mut self, entry: &[u8]) {
+        let flags = entry[4];
+        let size = if flags & TF_LONG_FORM != 0 { 17 } else { 7 };
+        let mut pos = 5;
+        let mut next = |bit: u8| -> Option<TimeSpec> {
+            if flags & bit == 0 || pos + size > entry.len() {
+                return None;
+            }
+            let buf = &entry[pos..pos + size];
+            pos += size;
+            if size == 17 {
+                return Some(long_time_to_timespec(buf));
+            }
+            return Some(record_time_to_timespec(buf));
+        };
+
+        // 时间戳按照标志位从低到高的顺序排列，与Linux一样，属性修改时间优先作为ctime
+        let create = next(TF_CREATE);
+        let modify = next(TF_MODIFY);
+        let access = next(TF_ACCESS);
+        let attributes = next(TF_ATTRIBUTES);
+        if modify.is_some() {
+            self.mtime = modify;
+        }
+        if access.is_some() {
+            self.atime = access;
+        }
+        if let Some(t) = attributes.or(create) {
+            self.ctime = Some(t);
+        }
+    }
+}
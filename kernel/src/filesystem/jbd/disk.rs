@@ -0,0 +1,355 @@
+//! 日志在磁盘上的格式
+//!
+//! 与Linux的jbd2相同，使用大端字节序。不支持校验和与64位块号，因此一个文件系统块号最多为32位。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/jbd2.h
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    driver::base::block::{block_device::BlockDevice, disk_info::Partition},
+    syscall::SystemError,
+};
+
+pub const JBD2_MAGIC_NUMBER: u32 = 0xc03b3998;
+
+/// 日志块的类型
+pub const JBD2_DESCRIPTOR_BLOCK: u32 = 1;
+pub const JBD2_COMMIT_BLOCK: u32 = 2;
+pub const JBD2_SUPERBLOCK_V1: u32 = 3;
+pub const JBD2_SUPERBLOCK_V2: u32 = 4;
+pub const JBD2_REVOKE_BLOCK: u32 = 5;
+
+/// 描述符中的标签的标志
+/// 数据块的开头与魔数相同，写入日志时被清零
+pub const JBD2_FLAG_ESCAPE: u16 = 1;
+/// 与上一个标签的UUID相同，标签之后没有UUID
+pub const JBD2_FLAG_SAME_UUID: u16 = 2;
+/// 描述符中的最后一个标签
+pub const JBD2_FLAG_LAST_TAG: u16 = 8;
+
+/// 日志中有撤销块
+pub const JBD2_FEATURE_INCOMPAT_REVOKE: u32 = 0x1;
+
+/// journal_header_t的长度
+pub const JOURNAL_HEADER_SIZE: usize = 12;
+/// 不使用校验和与64位块号时，journal_block_tag_t的长度
+pub const JOURNAL_TAG_SIZE: usize = 8;
+pub const JOURNAL_UUID_SIZE: usize = 16;
+/// jbd2_journal_revoke_header_t的长度
+pub const JOURNAL_REVOKE_HEADER_SIZE: usize = 16;
+/// 日志区域的最小长度(块)
+pub const JBD2_MIN_JOURNAL_BLOCKS: u32 = 1024;
+
+fn get_be32(buf: &[u8], offset: usize) -> u32 {
+    return u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap());
+}
+
+fn put_be32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+fn get_be16(buf: &[u8], offset: usize) -> u16 {
+    return u16::from_be_bytes(buf[offset..offset + 2].try_into().unwrap());
+}
+
+fn put_be16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+/// 每个日志块开头的头部
+#[derive(Debug, Clone, Copy)]
+pub struct JournalHeader {
+    pub blocktype: u32,
+    /// 日志块所属的事务的序号
+    pub sequence: u32,
+}
+
+impl JournalHeader {
+    /// @brief 解析日志块的头部
+    ///
+    /// @return None 魔数不正确，不是日志块
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if get_be32(buf, 0) != JBD2_MAGIC_NUMBER {
+            return None;
+        }
+        return Some(Self {
+            blocktype: get_be32(buf, 4),
+            sequence: get_be32(buf, 8),
+        });
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) {
+        put_be32(buf, 0, JBD2_MAGIC_NUMBER);
+        put_be32(buf, 4, self.blocktype);
+        put_be32(buf, 8, self.sequence);
+    }
+}
+
+/// 日志的超级块，位于日志区域的第0块
+#[derive(Debug, Clone)]
+pub struct JournalSuperblock {
+    pub blocksize: u32,
+    /// 日志区域的长度(块)
+    pub maxlen: u32,
+    /// 第一个可以写入日志的块
+    pub first: u32,
+    /// 日志中第一个事务的序号
+    pub sequence: u32,
+    /// 日志中第一个事务的位置，为0表示日志是空的
+    pub start: u32,
+    /// 日志被中止时的错误码
+    pub errno: i32,
+    pub feature_compat: u32,
+    pub feature_incompat: u32,
+    pub feature_ro_compat: u32,
+    pub uuid: [u8; JOURNAL_UUID_SIZE],
+}
+
+impl JournalSuperblock {
+    /// @brief 解析日志的超级块
+    ///
+    /// @return Err(SystemError::EINVAL) 不是日志的超级块
+    pub fn decode(buf: &[u8]) -> Result<Self, SystemError> {
+        match JournalHeader::decode(buf) {
+            Some(header)
+                if header.blocktype == JBD2_SUPERBLOCK_V1
+                    || header.blocktype == JBD2_SUPERBLOCK_V2 => {}
+            _ => return Err(SystemError::EINVAL),
+        }
+        let v2 = get_be32(buf, 4) == JBD2_SUPERBLOCK_V2;
+        let mut uuid = [0u8; JOURNAL_UUID_SIZE];
+        if v2 {
+            uuid.copy_from_slice(&buf[0x30..0x30 + JOURNAL_UUID_SIZE]);
+        }
+        return Ok(Self {
+            blocksize: get_be32(buf, 0x0c),
+            maxlen: get_be32(buf, 0x10),
+            first: get_be32(buf, 0x14),
+            sequence: get_be32(buf, 0x18),
+            start: get_be32(buf, 0x1c),
+            errno: get_be32(buf, 0x20) as i32,
+            // 第1版的超级块没有特性字段
+            feature_compat: if v2 { get_be32(buf, 0x24) } else { 0 },
+            feature_incompat: if v2 { get_be32(buf, 0x28) } else { 0 },
+            feature_ro_compat: if v2 { get_be32(buf, 0x2c) } else { 0 },
+            uuid,
+        });
+    }
+
+    /// @brief 以第2版的格式写入超级块，buf中其它字段保持不变
+    pub fn encode(&self, buf: &mut [u8]) {
+        JournalHeader {
+            blocktype: JBD2_SUPERBLOCK_V2,
+            sequence: 0,
+        }
+        .encode(buf);
+        put_be32(buf, 0x0c, self.blocksize);
+        put_be32(buf, 0x10, self.maxlen);
+        put_be32(buf, 0x14, self.first);
+        put_be32(buf, 0x18, self.sequence);
+        put_be32(buf, 0x1c, self.start);
+        put_be32(buf, 0x20, self.errno as u32);
+        put_be32(buf, 0x24, self.feature_compat);
+        put_be32(buf, 0x28, self.feature_incompat);
+        put_be32(buf, 0x2c, self.feature_ro_compat);
+        buf[0x30..0x30 + JOURNAL_UUID_SIZE].copy_from_slice(&self.uuid);
+        // s_nr_users：内部日志只有一个使用者
+        put_be32(buf, 0x40, 1);
+    }
+}
+
+/// 描述符中的一个标签，对应描述符之后的一个数据块
+#[derive(Debug, Clone, Copy)]
+pub struct JournalBlockTag {
+    /// 数据块在文件系统中的位置
+    pub blocknr: u32,
+    pub flags: u16,
+}
+
+/// @brief 每个描述符块中最多可以放下的标签数量。第一个标签之后有UUID
+pub fn tags_per_descriptor(block_size: usize) -> usize {
+    return (block_size - JOURNAL_HEADER_SIZE - JOURNAL_UUID_SIZE) / JOURNAL_TAG_SIZE;
+}
+
+/// @brief 每个撤销块中最多可以放下的块号数量
+pub fn records_per_revoke(block_size: usize) -> usize {
+    return (block_size - JOURNAL_REVOKE_HEADER_SIZE) / 4;
+}
+
+/// @brief 构造描述符块
+///
+/// @param tags 描述符之后的数据块，不能超过tags_per_descriptor()个
+pub fn encode_descriptor(
+    buf: &mut [u8],
+    sequence: u32,
+    tags: &[JournalBlockTag],
+    uuid: &[u8; JOURNAL_UUID_SIZE],
+) {
+    buf.fill(0);
+    JournalHeader {
+        blocktype: JBD2_DESCRIPTOR_BLOCK,
+        sequence,
+    }
+    .encode(buf);
+    let mut pos = JOURNAL_HEADER_SIZE;
+    for (i, tag) in tags.iter().enumerate() {
+        let mut flags = tag.flags;
+        if i > 0 {
+            flags |= JBD2_FLAG_SAME_UUID;
+        }
+        if i + 1 == tags.len() {
+            flags |= JBD2_FLAG_LAST_TAG;
+        }
+        put_be32(buf, pos, tag.blocknr);
+        put_be16(buf, pos + 6, flags);
+        pos += JOURNAL_TAG_SIZE;
+        if i == 0 {
+            buf[pos..pos + JOURNAL_UUID_SIZE].copy_from_slice(uuid);
+            pos += JOURNAL_UUID_SIZE;
+        }
+    }
+}
+
+/// @brief 解析描述符块中的所有标签
+pub fn decode_descriptor(buf: &[u8]) -> Vec<JournalBlockTag> {
+    let mut tags = Vec::new();
+    let mut pos = JOURNAL_HEADER_SIZE;
+    while pos + JOURNAL_TAG_SIZE <= buf.len() {
+        let tag = JournalBlockTag {
+            blocknr: get_be32(buf, pos),
+            flags: get_be16(buf, pos + 6),
+        };
+        pos += JOURNAL_TAG_SIZE;
+        if tag.flags & JBD2_FLAG_SAME_UUID == 0 {
+            pos += JOURNAL_UUID_SIZE;
+        }
+        tags.push(tag);
+        if tag.flags & JBD2_FLAG_LAST_TAG != 0 {
+            break;
+        }
+    }
+    return tags;
+}
+
+/// @brief 构造撤销块
+///
+/// @param blocks 被撤销的块，不能超过records_per_revoke()个
+pub fn encode_revoke(buf: &mut [u8], sequence: u32, blocks: &[u32]) {
+    buf.fill(0);
+    JournalHeader {
+        blocktype: JBD2_REVOKE_BLOCK,
+        sequence,
+    }
+    .encode(buf);
+    // r_count是撤销块中使用的字节数，包括头部
+    put_be32(
+        buf,
+        JOURNAL_HEADER_SIZE,
+        (JOURNAL_REVOKE_HEADER_SIZE + blocks.len() * 4) as u32,
+    );
+    for (i, block) in blocks.iter().enumerate() {
+        put_be32(buf, JOURNAL_REVOKE_HEADER_SIZE + i * 4, *block);
+    }
+}
+
+/// @brief 解析撤销块中的块号
+///
+/// @return Err(SystemError::EIO) r_count不合法
+pub fn decode_revoke(buf: &[u8]) -> Result<Vec<u32>, SystemError> {
+    let count = get_be32(buf, JOURNAL_HEADER_SIZE) as usize;
+    if count < JOURNAL_REVOKE_HEADER_SIZE || count > buf.len() {
+        return Err(SystemError::EIO);
+    }
+    return Ok((JOURNAL_REVOKE_HEADER_SIZE..count)
+        .step_by(4)
+        .filter(|pos| pos + 4 <= count)
+        .map(|pos| get_be32(buf, pos))
+        .collect());
+}
+
+/// @brief 构造提交块
+pub fn encode_commit(buf: &mut [u8], sequence: u32) {
+    buf.fill(0);
+    JournalHeader {
+        blocktype: JBD2_COMMIT_BLOCK,
+        sequence,
+    }
+    .encode(buf);
+}
+
+/// @brief 日志所在的设备，以文件系统的块为单位读写
+///
+/// 日志中的块号与事务中的块号都是相对于文件系统起始位置的块号
+#[derive(Debug)]
+pub struct JournalDevice {
+    disk: Arc<dyn BlockDevice>,
+    /// 文件系统在磁盘上的起始LBA
+    start_lba: usize,
+    /// 文件系统的块大小
+    block_size: usize,
+}
+
+impl JournalDevice {
+    /// @brief 创建日志设备
+    ///
+    /// @param start_lba 文件系统在磁盘上的起始LBA
+    /// @param block_size 文件系统的块大小，必须是磁盘块大小的整数倍
+    ///
+    /// @return Err(SystemError::EINVAL) 块大小不合法
+    pub fn new(
+        disk: Arc<dyn BlockDevice>,
+        start_lba: usize,
+        block_size: usize,
+    ) -> Result<Self, SystemError> {
+        let disk_block_size = 1usize << disk.blk_size_log2();
+        if !block_size.is_power_of_two()
+            || block_size < disk_block_size
+            || block_size < JOURNAL_HEADER_SIZE + JOURNAL_UUID_SIZE + JOURNAL_TAG_SIZE
+        {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Self {
+            disk,
+            start_lba,
+            block_size,
+        });
+    }
+
+    /// @brief 为分区上的文件系统创建日志设备
+    pub fn from_partition(
+        partition: &Arc<Partition>,
+        block_size: usize,
+    ) -> Result<Self, SystemError> {
+        return Self::new(partition.disk(), partition.lba_start as usize, block_size);
+    }
+
+    pub fn block_size(&self) -> usize {
+        return self.block_size;
+    }
+
+    fn block_to_lba(&self, block: u64) -> (usize, usize) {
+        let log2 = self.disk.blk_size_log2();
+        let count = self.block_size >> log2;
+        return (self.start_lba + block as usize * count, count);
+    }
+
+    /// @brief 读取一个文件系统块
+    pub fn read_block(&self, block: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        let (lba, count) = self.block_to_lba(block);
+        self.disk.read_at(lba, count, &mut buf[..self.block_size])?;
+        return Ok(());
+    }
+
+    /// @brief 写入一个文件系统块
+    pub fn write_block(&self, block: u64, buf: &[u8]) -> Result<(), SystemError> {
+        let (lba, count) = self.block_to_lba(block);
+        self.disk.write_at(lba, count, &buf[..self.block_size])?;
+        return Ok(());
+    }
+
+    /// @brief 等待之前的写入到达稳定存储
+    pub fn flush(&self) -> Result<(), SystemError> {
+        return self.disk.sync();
+    }
+}
@@ -0,0 +1,456 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+
+use crate::{
+    kerror, kinfo,
+    libs::mutex::{Mutex, MutexGuard},
+    syscall::SystemError,
+};
+
+use super::{
+    disk::{
+        encode_commit, encode_descriptor, encode_revoke, records_per_revoke, tags_per_descriptor,
+        JournalBlockTag, JournalDevice, JournalSuperblock, JBD2_FEATURE_INCOMPAT_REVOKE,
+        JBD2_FLAG_ESCAPE, JBD2_MAGIC_NUMBER, JBD2_MIN_JOURNAL_BLOCKS, JOURNAL_UUID_SIZE,
+    },
+    recovery::recover,
+};
+
+#[derive(Debug)]
+struct JournalState {
+    sb: JournalSuperblock,
+    /// 超级块所在的整个块，写回时保留不认识的字段
+    sb_buf: Vec<u8>,
+    /// 下一个事务写入日志的位置
+    head: u32,
+    /// 下一个事务的序号
+    next_sequence: u32,
+    /// 已经提交、但还没有写回原位置的元数据块
+    checkpoint: BTreeMap<u64, Vec<u8>>,
+    /// 写入日志失败之后，日志被中止，不能再开始新的事务
+    aborted: bool,
+}
+
+/// @brief 预写日志
+///
+/// 日志占用文件系统中一段连续的块。文件系统对元数据的修改放在一个事务中，
+/// 提交时先写入日志，日志中的提交块落盘之后，事务中的修改就不会丢失；之后再把元数据写回原位置(检查点)。
+/// 写回之前断电的话，下一次挂载时通过[`Journal::load`]重放日志。
+///
+/// 文件数据使用ordered模式：数据块不写入日志，但是在事务的提交块之前写入原位置，
+/// 因此重放之后的元数据不会指向还没有写入的数据。
+///
+/// 已经提交但还没有写回的元数据只在内存中，文件系统必须通过[`Journal::read_block`]或者
+/// [`Transaction::read_block`]读取元数据，并且在sync与卸载时调用[`Journal::checkpoint`]。
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/jbd2/
+#[derive(Debug)]
+pub struct Journal {
+    dev: JournalDevice,
+    /// 日志区域在文件系统中的起始块号
+    offset: u64,
+    state: Mutex<JournalState>,
+}
+
+impl Journal {
+    /// @brief 在文件系统的一段块中创建空的日志，日志区域中原有的内容被清零
+    ///
+    /// @param dev 文件系统所在的设备
+    /// @param offset 日志区域的起始块号
+    /// @param len 日志区域的长度(块)，至少为JBD2_MIN_JOURNAL_BLOCKS
+    /// @param uuid 文件系统的UUID
+    ///
+    /// @return Err(SystemError::EINVAL) 日志区域太小
+    pub fn format(
+        dev: &JournalDevice,
+        offset: u64,
+        len: u32,
+        uuid: [u8; JOURNAL_UUID_SIZE],
+    ) -> Result<(), SystemError> {
+        if len < JBD2_MIN_JOURNAL_BLOCKS {
+            return Err(SystemError::EINVAL);
+        }
+        let mut buf = vec![0u8; dev.block_size()];
+        // 清零日志区域，防止以前的日志块在重放时被误认为是新的事务
+        for block in 1..len as u64 {
+            dev.write_block(offset + block, &buf)?;
+        }
+        JournalSuperblock {
+            blocksize: dev.block_size() as u32,
+            maxlen: len,
+            first: 1,
+            sequence: 1,
+            start: 0,
+            errno: 0,
+            feature_compat: 0,
+            feature_incompat: 0,
+            feature_ro_compat: 0,
+            uuid,
+        }
+        .encode(&mut buf);
+        dev.write_block(offset, &buf)?;
+        dev.flush()?;
+        return Ok(());
+    }
+
+    /// @brief 加载日志，日志不是空的时先重放日志
+    ///
+    /// @param dev 文件系统所在的设备
+    /// @param offset 日志区域的起始块号
+    /// @param len 日志区域的长度(块)
+    ///
+    /// @return Err(SystemError::EINVAL) 日志的超级块不合法，或者使用了不支持的特性
+    /// @return Err(SystemError::EIO) 日志的内容损坏，或者读写失败
+    pub fn load(dev: JournalDevice, offset: u64, len: u32) -> Result<Journal, SystemError> {
+        let mut sb_buf = vec![0u8; dev.block_size()];
+        dev.read_block(offset, &mut sb_buf)?;
+        let mut sb = JournalSuperblock::decode(&sb_buf)?;
+        if sb.blocksize as usize != dev.block_size()
+            || sb.maxlen > len
+            || sb.first == 0
+            || sb.first >= sb.maxlen
+        {
+            return Err(SystemError::EINVAL);
+        }
+        if sb.feature_incompat & !JBD2_FEATURE_INCOMPAT_REVOKE != 0 {
+            kerror!(
+                "jbd: unsupported incompatible features: {:#x}",
+                sb.feature_incompat
+            );
+            return Err(SystemError::EINVAL);
+        }
+
+        if sb.start != 0 {
+            sb.sequence = recover(&dev, offset, &sb)?;
+            sb.start = 0;
+            sb.encode(&mut sb_buf);
+            dev.write_block(offset, &sb_buf)?;
+            dev.flush()?;
+        }
+        kinfo!(
+            "jbd: journal loaded, {} blocks, sequence {}",
+            sb.maxlen,
+            sb.sequence
+        );
+
+        let state = JournalState {
+            head: sb.first,
+            next_sequence: sb.sequence,
+            sb,
+            sb_buf,
+            checkpoint: BTreeMap::new(),
+            aborted: false,
+        };
+        return Ok(Journal {
+            dev,
+            offset,
+            state: Mutex::new(state),
+        });
+    }
+
+    pub fn device(&self) -> &JournalDevice {
+        return &self.dev;
+    }
+
+    /// @brief 一个事务中最多可以修改的元数据块的数量
+    pub fn max_transaction_blocks(&self) -> usize {
+        let guard = self.state.lock();
+        let bs = self.dev.block_size();
+        let space = (guard.sb.maxlen - guard.sb.first) as usize;
+        // 留出一个撤销块与提交块的空间，其余的块中每tags_per_descriptor+1个块可以放tags_per_descriptor个元数据块
+        let per_desc = tags_per_descriptor(bs);
+        return (space.saturating_sub(2)) * per_desc / (per_desc + 1);
+    }
+
+    /// @brief 开始一个新的事务
+    ///
+    /// 同时只能有一个事务，其它的事务会等待这个事务结束。事务结束之前，
+    /// 当前进程不能调用Journal的其它方法，否则会死锁
+    ///
+    /// @return Err(SystemError::EROFS) 日志已经被中止
+    pub fn begin(&self) -> Result<Transaction<'_>, SystemError> {
+        let state = self.state.lock();
+        if state.aborted {
+            return Err(SystemError::EROFS);
+        }
+        return Ok(Transaction {
+            journal: self,
+            state,
+            data: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+            revoked: BTreeSet::new(),
+        });
+    }
+
+    /// @brief 读取文件系统中的一个块，已经提交但还没有写回的元数据从内存中读取
+    pub fn read_block(&self, block: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        let guard = self.state.lock();
+        return self.read_block_locked(&guard, block, buf);
+    }
+
+    fn read_block_locked(
+        &self,
+        state: &JournalState,
+        block: u64,
+        buf: &mut [u8],
+    ) -> Result<(), SystemError> {
+        if let Some(data) = state.checkpoint.get(&block) {
+            buf[..data.len()].copy_from_slice(data);
+            return Ok(());
+        }
+        return self.dev.read_block(block, buf);
+    }
+
+    /// @brief 把所有已经提交的元数据写回原位置，之后日志是空的
+    pub fn checkpoint(&self) -> Result<(), SystemError> {
+        let mut guard = self.state.lock();
+        return self.checkpoint_locked(&mut guard);
+    }
+
+    fn checkpoint_locked(&self, state: &mut JournalState) -> Result<(), SystemError> {
+        if state.aborted {
+            return Err(SystemError::EROFS);
+        }
+        if state.sb.start == 0 {
+            return Ok(());
+        }
+        let result = self.do_checkpoint(state);
+        if result.is_err() {
+            self.abort(state);
+        }
+        return result;
+    }
+
+    fn do_checkpoint(&self, state: &mut JournalState) -> Result<(), SystemError> {
+        for (block, data) in state.checkpoint.iter() {
+            self.dev.write_block(*block, data)?;
+        }
+        self.dev.flush()?;
+        state.checkpoint.clear();
+
+        // 所有事务都已经写回，日志变为空的，下一个事务从头开始写入
+        state.sb.start = 0;
+        state.sb.sequence = state.next_sequence;
+        state.head = state.sb.first;
+        self.write_superblock(state)?;
+        return Ok(());
+    }
+
+    fn write_superblock(&self, state: &mut JournalState) -> Result<(), SystemError> {
+        let JournalState { sb, sb_buf, .. } = state;
+        sb.encode(sb_buf);
+        self.dev.write_block(self.offset, sb_buf)?;
+        self.dev.flush()?;
+        return Ok(());
+    }
+
+    /// @brief 写入失败之后中止日志。已经提交的事务仍然可以在下一次挂载时重放
+    fn abort(&self, state: &mut JournalState) {
+        kerror!("jbd: journal aborted");
+        state.aborted = true;
+    }
+}
+
+/// @brief 一个事务，通过[`Journal::begin`]创建
+///
+/// 事务中的修改在[`Transaction::commit`]之前只保存在内存中，没有提交就被丢弃的事务不会修改文件系统
+pub struct Transaction<'a> {
+    journal: &'a Journal,
+    state: MutexGuard<'a, JournalState>,
+    /// ordered模式的数据块
+    data: BTreeMap<u64, Vec<u8>>,
+    /// 写入日志的元数据块
+    metadata: BTreeMap<u64, Vec<u8>>,
+    /// 被撤销的块：被释放的元数据块之后可能用来存放数据，重放时不能再写入它以前的内容
+    revoked: BTreeSet<u64>,
+}
+
+impl<'a> Transaction<'a> {
+    /// @brief 检查块号，并复制一个块的内容
+    fn check_block(&self, block: u64, buf: &[u8]) -> Result<Vec<u8>, SystemError> {
+        let bs = self.journal.dev.block_size();
+        let journal_end = self.journal.offset + self.state.sb.maxlen as u64;
+        // 标签中的块号只有32位
+        if buf.len() < bs
+            || block > u32::MAX as u64
+            || (block >= self.journal.offset && block < journal_end)
+        {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(buf[..bs].to_vec());
+    }
+
+    /// @brief 写入一个数据块，数据块在提交块之前写入原位置，但不写入日志
+    ///
+    /// @return Err(SystemError::EINVAL) 块号不合法，或者buf比块小
+    pub fn write_data(&mut self, block: u64, buf: &[u8]) -> Result<(), SystemError> {
+        let data = self.check_block(block, buf)?;
+        self.metadata.remove(&block);
+        // 这个块以前是还没有写回的元数据，写回与重放时都不能覆盖新的数据
+        if self.state.checkpoint.contains_key(&block) {
+            self.revoked.insert(block);
+        }
+        self.data.insert(block, data);
+        return Ok(());
+    }
+
+    /// @brief 写入一个元数据块，元数据块先写入日志，之后再写回原位置
+    ///
+    /// @return Err(SystemError::EINVAL) 块号不合法，或者buf比块小
+    pub fn write_metadata(&mut self, block: u64, buf: &[u8]) -> Result<(), SystemError> {
+        let data = self.check_block(block, buf)?;
+        self.data.remove(&block);
+        self.revoked.remove(&block);
+        self.metadata.insert(block, data);
+        return Ok(());
+    }
+
+    /// @brief 撤销一个元数据块，用于释放元数据块的时候
+    pub fn revoke(&mut self, block: u64) -> Result<(), SystemError> {
+        if block > u32::MAX as u64 {
+            return Err(SystemError::EINVAL);
+        }
+        self.metadata.remove(&block);
+        self.revoked.insert(block);
+        return Ok(());
+    }
+
+    /// @brief 读取一个块，包括这个事务中还没有提交的修改
+    pub fn read_block(&self, block: u64, buf: &mut [u8]) -> Result<(), SystemError> {
+        if let Some(data) = self.metadata.get(&block).or(self.data.get(&block)) {
+            buf[..data.len()].copy_from_slice(data);
+            return Ok(());
+        }
+        return self.journal.read_block_locked(&self.state, block, buf);
+    }
+
+    /// @brief 提交事务。返回之后，即使断电，事务中的修改也会在下一次挂载时恢复
+    ///
+    /// @return Err(SystemError::ENOSPC) 事务太大，日志放不下
+    /// @return Err(SystemError::EROFS) 日志已经被中止
+    /// @return Err(SystemError) 读写错误，日志被中止
+    pub fn commit(mut self) -> Result<(), SystemError> {
+        if self.metadata.is_empty() && self.revoked.is_empty() {
+            // 没有元数据的修改时不需要写日志
+            for (block, data) in self.data.iter() {
+                self.journal.dev.write_block(*block, data)?;
+            }
+            return Ok(());
+        }
+
+        let bs = self.journal.dev.block_size();
+        let per_revoke = records_per_revoke(bs);
+        let per_desc = tags_per_descriptor(bs);
+        let revoke_blocks = (self.revoked.len() + per_revoke - 1) / per_revoke;
+        let desc_blocks = (self.metadata.len() + per_desc - 1) / per_desc;
+        let needed = (revoke_blocks + desc_blocks + self.metadata.len() + 1) as u32;
+        let state = &mut *self.state;
+        if needed > state.sb.maxlen - state.sb.first {
+            return Err(SystemError::ENOSPC);
+        }
+        if needed > state.sb.maxlen - state.head {
+            self.journal.checkpoint_locked(state)?;
+        }
+
+        let result = Self::write_log(
+            self.journal,
+            state,
+            &self.data,
+            &self.metadata,
+            &self.revoked,
+        );
+        if result.is_err() {
+            self.journal.abort(state);
+            return result;
+        }
+
+        state.head += needed;
+        state.next_sequence = state.next_sequence.wrapping_add(1);
+        for block in self.revoked.iter() {
+            state.checkpoint.remove(block);
+        }
+        for block in self.data.keys() {
+            state.checkpoint.remove(block);
+        }
+        state.checkpoint.append(&mut self.metadata);
+        return Ok(());
+    }
+
+    fn write_log(
+        journal: &Journal,
+        state: &mut JournalState,
+        data: &BTreeMap<u64, Vec<u8>>,
+        metadata: &BTreeMap<u64, Vec<u8>>,
+        revoked: &BTreeSet<u64>,
+    ) -> Result<(), SystemError> {
+        let dev = &journal.dev;
+        let bs = dev.block_size();
+        let sequence = state.next_sequence;
+
+        // ordered模式：数据先于提交块落盘
+        for (block, buf) in data.iter() {
+            dev.write_block(*block, buf)?;
+        }
+        dev.flush()?;
+
+        // 日志是空的时，在超级块中记录第一个事务的位置
+        if state.sb.start == 0 {
+            state.sb.start = state.head;
+            state.sb.sequence = sequence;
+            if !revoked.is_empty() {
+                state.sb.feature_incompat |= JBD2_FEATURE_INCOMPAT_REVOKE;
+            }
+            journal.write_superblock(state)?;
+        } else if !revoked.is_empty()
+            && state.sb.feature_incompat & JBD2_FEATURE_INCOMPAT_REVOKE == 0
+        {
+            state.sb.feature_incompat |= JBD2_FEATURE_INCOMPAT_REVOKE;
+            journal.write_superblock(state)?;
+        }
+
+        let mut pos = journal.offset + state.head as u64;
+        let mut buf = vec![0u8; bs];
+        let revoked: Vec<u32> = revoked.iter().map(|block| *block as u32).collect();
+        for chunk in revoked.chunks(records_per_revoke(bs)) {
+            encode_revoke(&mut buf, sequence, chunk);
+            dev.write_block(pos, &buf)?;
+            pos += 1;
+        }
+
+        let metadata: Vec<(&u64, &Vec<u8>)> = metadata.iter().collect();
+        for chunk in metadata.chunks(tags_per_descriptor(bs)) {
+            let tags: Vec<JournalBlockTag> = chunk
+                .iter()
+                .map(|(block, data)| JournalBlockTag {
+                    blocknr: **block as u32,
+                    flags: if escaped(data) { JBD2_FLAG_ESCAPE } else { 0 },
+                })
+                .collect();
+            encode_descriptor(&mut buf, sequence, &tags, &state.sb.uuid);
+            dev.write_block(pos, &buf)?;
+            pos += 1;
+            for (_, data) in chunk.iter() {
+                buf.copy_from_slice(data);
+                // 与魔数相同的开头会被当作日志块，写入日志时清零，重放时恢复
+                if escaped(data) {
+                    buf[0..4].fill(0);
+                }
+                dev.write_block(pos, &buf)?;
+                pos += 1;
+            }
+        }
+        dev.flush()?;
+
+        // 提交块落盘之后事务才算完成
+        encode_commit(&mut buf, sequence);
+        dev.write_block(pos, &buf)?;
+        dev.flush()?;
+        return Ok(());
+    }
+}
+
+fn escaped(data: &[u8]) -> bool {
+    return data[0..4] == JBD2_MAGIC_NUMBER.to_be_bytes();
+}
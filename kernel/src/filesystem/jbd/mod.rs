@@ -0,0 +1,23 @@
+//! 通用的预写日志(JBD)
+//!
+//! 为块设备上的文件系统提供崩溃一致性：元数据的修改以事务为单位先写入日志，
+//! 挂载时重放已经提交但还没有写回的事务。日志的格式与Linux的jbd2相同，
+//! 使ext2之类的文件系统可以逐步加上ext3的日志语义。
+//!
+//! - [`disk`]：日志在磁盘上的格式
+//! - [`journal`]：日志与事务，提交与检查点
+//! - [`recovery`]：挂载时重放日志
+//!
+//! 使用方法：
+//!
+//! 1. 创建文件系统时调用[`Journal::format`](journal::Journal::format)在文件系统中划出日志区域
+//! 2. 挂载时调用[`Journal::load`](journal::Journal::load)，必要时重放日志
+//! 3. 每个修改元数据的操作通过[`Journal::begin`](journal::Journal::begin)开始事务，
+//!    把修改的块写入事务之后提交
+//! 4. sync与卸载时调用[`Journal::checkpoint`](journal::Journal::checkpoint)
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/jbd2/
+
+pub mod disk;
+pub mod journal;
+pub mod recovery;
@@ -0,0 +1,141 @@
+//! 挂载时重放日志
+//!
+//! 与jbd2一样分为三遍：第一遍找出日志中所有完整提交的事务，第二遍收集撤销记录，
+//! 第三遍把没有被撤销的块写回它们在文件系统中的位置。没有提交块的事务被丢弃。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/jbd2/recovery.c
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{kinfo, syscall::SystemError};
+
+use super::disk::{
+    decode_descriptor, decode_revoke, JournalDevice, JournalHeader, JournalSuperblock,
+    JBD2_COMMIT_BLOCK, JBD2_DESCRIPTOR_BLOCK, JBD2_FLAG_ESCAPE, JBD2_MAGIC_NUMBER,
+    JBD2_REVOKE_BLOCK,
+};
+
+/// 日志中一个完整提交的事务
+struct RecoveredTransaction {
+    sequence: u32,
+    /// (文件系统中的块号, 日志中的块号, 标志)
+    blocks: Vec<(u32, u32, u16)>,
+    revoked: Vec<u32>,
+}
+
+/// @brief 序号a是否不早于序号b，序号会回绕
+pub(super) fn tid_geq(a: u32, b: u32) -> bool {
+    return a.wrapping_sub(b) as i32 >= 0;
+}
+
+/// @brief 重放日志
+///
+/// @param dev 日志所在的设备
+/// @param offset 日志区域在文件系统中的起始块号
+/// @param sb 日志的超级块，start不为0
+///
+/// @return Ok(u32) 下一个事务应当使用的序号
+/// @return Err(SystemError::EIO) 日志的内容损坏，或者读写失败
+pub(super) fn recover(
+    dev: &JournalDevice,
+    offset: u64,
+    sb: &JournalSuperblock,
+) -> Result<u32, SystemError> {
+    let transactions = scan(dev, offset, sb)?;
+    let next_sequence = match transactions.last() {
+        Some(last) => last.sequence.wrapping_add(1),
+        None => sb.sequence,
+    };
+
+    // 每个块最后一次被撤销的事务的序号，这个事务以及之前的事务中的这个块都不需要重放
+    let mut revoked: BTreeMap<u32, u32> = BTreeMap::new();
+    for transaction in transactions.iter() {
+        for block in transaction.revoked.iter() {
+            revoked.insert(*block, transaction.sequence);
+        }
+    }
+
+    let mut buf = vec![0u8; dev.block_size()];
+    let mut replayed = 0;
+    for transaction in transactions.iter() {
+        for (blocknr, log_block, flags) in transaction.blocks.iter() {
+            if let Some(revoke_seq) = revoked.get(blocknr) {
+                if tid_geq(*revoke_seq, transaction.sequence) {
+                    continue;
+                }
+            }
+            dev.read_block(offset + *log_block as u64, &mut buf)?;
+            if flags & JBD2_FLAG_ESCAPE != 0 {
+                buf[0..4].copy_from_slice(&JBD2_MAGIC_NUMBER.to_be_bytes());
+            }
+            dev.write_block(*blocknr as u64, &buf)?;
+            replayed += 1;
+        }
+    }
+    dev.flush()?;
+
+    kinfo!(
+        "jbd: recovered {} transactions, {} blocks replayed",
+        transactions.len(),
+        replayed
+    );
+    return Ok(next_sequence);
+}
+
+/// @brief 从sb.start开始找出所有完整提交的事务
+fn scan(
+    dev: &JournalDevice,
+    offset: u64,
+    sb: &JournalSuperblock,
+) -> Result<Vec<RecoveredTransaction>, SystemError> {
+    if sb.start < sb.first || sb.start >= sb.maxlen {
+        return Err(SystemError::EIO);
+    }
+    // 日志区域是环形的，超过末尾之后回到first
+    let next = |block: u32| -> u32 {
+        if block + 1 >= sb.maxlen {
+            return sb.first;
+        }
+        return block + 1;
+    };
+
+    let mut transactions = Vec::new();
+    let mut current = RecoveredTransaction {
+        sequence: sb.sequence,
+        blocks: Vec::new(),
+        revoked: Vec::new(),
+    };
+    let mut block = sb.start;
+    let mut buf = vec![0u8; dev.block_size()];
+    // 最多扫描整个日志一次，防止损坏的日志导致死循环
+    for _ in sb.first..sb.maxlen {
+        dev.read_block(offset + block as u64, &mut buf)?;
+        let header = match JournalHeader::decode(&buf) {
+            Some(header) if header.sequence == current.sequence => header,
+            _ => break,
+        };
+        match header.blocktype {
+            JBD2_DESCRIPTOR_BLOCK => {
+                for tag in decode_descriptor(&buf) {
+                    block = next(block);
+                    current.blocks.push((tag.blocknr, block, tag.flags));
+                }
+            }
+            JBD2_REVOKE_BLOCK => current.revoked.extend(decode_revoke(&buf)?),
+            JBD2_COMMIT_BLOCK => {
+                let sequence = current.sequence.wrapping_add(1);
+                transactions.push(core::mem::replace(
+                    &mut current,
+                    RecoveredTransaction {
+                        sequence,
+                        blocks: Vec::new(),
+                        revoked: Vec::new(),
+                    },
+                ));
+            }
+            _ => break,
+        }
+        block = next(block);
+    }
+    return Ok(transactions);
+}
@@ -1,4 +1,5 @@
 use crate::{
+    debug::tracefs::TraceFsFile,
     filesystem::{sysfs::SysFSKernPrivateData, vfs::PollStatus},
     libs::spinlock::SpinLockGuard,
     syscall::SystemError,
@@ -86,6 +87,7 @@ impl<'a> KernCallbackData<'a> {
 #[derive(Debug)]
 pub enum KernInodePrivateData {
     SysFS(SysFSKernPrivateData),
+    TraceFS(TraceFsFile),
 }
 
 impl KernInodePrivateData {
@@ -95,6 +97,9 @@ impl KernInodePrivateData {
             KernInodePrivateData::SysFS(private_data) => {
                 return private_data.callback_read(buf, offset);
             }
+            KernInodePrivateData::TraceFS(file) => {
+                return file.callback_read(buf, offset);
+            }
         }
     }
 
@@ -104,6 +109,9 @@ impl KernInodePrivateData {
             KernInodePrivateData::SysFS(private_data) => {
                 return private_data.callback_write(buf, offset);
             }
+            KernInodePrivateData::TraceFS(file) => {
+                return file.callback_write(buf, offset);
+            }
         }
     }
 }
@@ -21,7 +21,7 @@ use self::callback::{KernCallbackData, KernFSCallback, KernInodePrivateData};
 
 use super::vfs::{
     core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData, FileSystem,
-    FileType, FsInfo, IndexNode, InodeId, Metadata, PollStatus,
+    FileType, FsInfo, IndexNode, InodeId, Metadata, PollStatus, PollTable,
 };
 
 pub mod callback;
@@ -293,7 +293,7 @@ impl IndexNode for KernFSInode {
         return Ok(keys);
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         // todo: 根据inode的具体attribute，返回PollStatus
         return Ok(PollStatus::READ | PollStatus::WRITE);
     }
@@ -615,7 +615,6 @@ impl KernFSInode {
     }
 
     /// 删除当前的inode（包括其自身、子目录和子文件）
-    #[allow(dead_code)]
     pub fn remove_inode_include_self(&self) {
         let parent = self.parent();
         if let Some(parent) = parent {
@@ -1,18 +1,22 @@
 #![allow(dead_code)]
 use core::default::Default;
 
-/// @brief MBR硬盘分区表项的结构
-#[repr(packed)]
-#[derive(Debug, Clone, Copy)]
-pub struct MbrDiskPartitionTableEntry {
-    pub flags: u8,                     // 引导标志符，标记此分区为活动分区
-    pub starting_head: u8,             // 起始磁头号
-    pub starting_sector_cylinder: u16, // sector : 低6, cylinder : 高10;   起始扇区号 + 起始柱面号
-    pub part_type: u8,                 // 分区类型ID
-    pub ending_head: u8,               // 结束磁头号
-    pub ending_sector_cylingder: u16, // ending_sector : 低6, ending_cylinder : 高10;  结束扇区号 + 结束柱面号
-    pub starting_lba: u32,            // 起始逻辑扇区
-    pub total_sectors: u32,           // 分区占用的磁盘扇区数
+use crate::on_disk_struct;
+
+on_disk_struct! {
+    endian: Little;
+    /// @brief MBR硬盘分区表项的结构
+    #[derive(Debug, Clone, Copy)]
+    pub struct MbrDiskPartitionTableEntry {
+        pub flags: u8,                     // 引导标志符，标记此分区为活动分区
+        pub starting_head: u8,             // 起始磁头号
+        pub starting_sector_cylinder: u16, // sector : 低6, cylinder : 高10;   起始扇区号 + 起始柱面号
+        pub part_type: u8,                 // 分区类型ID
+        pub ending_head: u8,               // 结束磁头号
+        pub ending_sector_cylingder: u16, // ending_sector : 低6, ending_cylinder : 高10;  结束扇区号 + 结束柱面号
+        pub starting_lba: u32,            // 起始逻辑扇区
+        pub total_sectors: u32,           // 分区占用的磁盘扇区数
+    }
 }
 
 impl MbrDiskPartitionTableEntry {
@@ -30,13 +34,15 @@ impl MbrDiskPartitionTableEntry {
     }
 }
 
-/// @brief MBR磁盘分区表结构体
-#[repr(packed)]
-#[derive(Debug, Clone, Copy)]
-pub struct MbrDiskPartionTable {
-    pub reserved: [u8; 446],
-    pub dpte: [MbrDiskPartitionTableEntry; 4], // 磁盘分区表项
-    pub bs_trailsig: u16,
+on_disk_struct! {
+    endian: Little;
+    /// @brief MBR磁盘分区表结构体
+    #[derive(Debug, Clone, Copy)]
+    pub struct MbrDiskPartionTable {
+        pub reserved: [u8; 446],
+        pub dpte: [MbrDiskPartitionTableEntry; 4], // 磁盘分区表项
+        pub bs_trailsig: u16,
+    }
 }
 
 impl Default for MbrDiskPartitionTableEntry {
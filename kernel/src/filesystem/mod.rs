@@ -1,7 +1,14 @@
+pub mod anon_inode;
 pub mod devfs;
 pub mod fat;
+pub mod fuse;
+pub mod gpt;
+pub mod iso9660;
+pub mod jbd;
 pub mod kernfs;
 pub mod mbr;
+pub mod nfs;
+pub mod p9;
 pub mod procfs;
 pub mod ramfs;
 pub mod sysfs;
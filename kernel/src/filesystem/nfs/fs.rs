@@ -0,0 +1,684 @@
+use core::{any::Any, str::FromStr};
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+
+use crate::{
+    filesystem::vfs::{
+        file::{FileMode, FilePrivateData},
+        make_rawdev,
+        syscall::ModeType,
+        DirEntry, FileSystem, FileType, FsInfo, IndexNode, InodeId, Metadata, PollStatus,
+        PollTable,
+    },
+    kernel_param, kinfo,
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    syscall::SystemError,
+    time::{
+        hrtimer::{ktime_get, Ktime},
+        NSEC_PER_SEC,
+    },
+};
+
+use super::{
+    mount::mount_export,
+    nfs3::{
+        Fattr3, Nfs3Client, Nfs3DirEntry, NfsFh, Sattr3, NFS3_MAXNAMLEN, NFS3_VERIFIER_SIZE,
+        NFS_PROGRAM, NFS_VERSION,
+    },
+    options::NfsMountOptions,
+    rpc::{pmap_getport, RpcClient},
+};
+
+/// READDIRPLUS回复的长度范围
+const NFS_MIN_DTSIZE: u32 = 1024;
+const NFS_MAX_DTSIZE: u32 = 32 * 1024;
+
+/// @brief NFS第3版文件系统
+///
+/// 文件的内容保存在服务器上，每次读写都向服务器发送请求。文件的属性会在本地缓存一段时间，
+/// 缓存的有效时间从acregmin(目录为acdirmin)开始，每次重新获取的属性没有变化时加倍，直到acregmax，
+/// 属性发生变化时恢复为最小值。因此很少修改的文件只需要很少的GETATTR请求，
+/// 而其他客户端对文件的修改最多在acregmax秒之后可以看到
+///
+/// 客户端不能绑定1024以下的端口，服务器的导出选项中需要加上`insecure`
+#[derive(Debug)]
+pub struct NfsFileSystem {
+    client: Nfs3Client,
+    options: NfsMountOptions,
+    /// READDIRPLUS回复的最大长度
+    dtsize: u32,
+    /// 文件系统的根inode
+    root_inode: Arc<LockedNfsInode>,
+}
+
+/// NFS文件系统的Inode
+#[derive(Debug)]
+pub struct LockedNfsInode(SpinLock<NfsInode>);
+
+#[derive(Debug)]
+pub struct NfsInode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedNfsInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedNfsInode>,
+    /// 文件在服务器上的句柄
+    fh: NfsFh,
+    /// 缓存的属性，fileid用作inode号
+    attr: Fattr3,
+    /// 属性缓存过期的时间
+    attr_expire: Ktime,
+    /// 属性缓存当前的有效时间(秒)
+    attr_timeo: u64,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<NfsFileSystem>,
+}
+
+impl FileSystem for NfsFileSystem {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: NFS3_MAXNAMLEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl NfsFileSystem {
+    /// @brief 挂载服务器导出的目录
+    ///
+    /// @param source 导出目录，格式为`<server-ip>:<path>`，目前只支持IPv4地址
+    /// @param data 挂载选项，见[`NfsMountOptions`]
+    ///
+    /// @return Err(SystemError::EINVAL) 导出目录的格式或者挂载选项不合法
+    /// @return Err(SystemError::EACCES) 服务器拒绝了本机的挂载请求
+    /// @return Err(SystemError::ETIMEDOUT) 使用soft选项挂载时，服务器没有响应
+    pub fn mount(source: &str, data: &str) -> Result<Arc<NfsFileSystem>, SystemError> {
+        let (server, path) = parse_source(source)?;
+        let mut options = NfsMountOptions::parse(data)?;
+        let root_fh = mount_export(server, path, &options)?;
+
+        let config = options.rpc_config();
+        let port = match options.port {
+            Some(port) => port,
+            None => pmap_getport(server, NFS_PROGRAM, NFS_VERSION, &config)?,
+        };
+        let endpoint = IpEndpoint::new(IpAddress::Ipv4(server), port);
+        let client = Nfs3Client::new(RpcClient::new(endpoint, NFS_PROGRAM, NFS_VERSION, &config)?);
+
+        // 读写请求的长度不能超过服务器的限制
+        let info = client.fsinfo(&root_fh)?;
+        if info.rtmax > 0 {
+            options.rsize = options.rsize.min(info.rtmax as usize);
+        }
+        if info.wtmax > 0 {
+            options.wsize = options.wsize.min(info.wtmax as usize);
+        }
+        // 目录项的回复与READ的回复一样受到rsize的限制
+        let dtsize = info
+            .dtpref
+            .clamp(NFS_MIN_DTSIZE, NFS_MAX_DTSIZE)
+            .min(options.rsize as u32);
+
+        let root_attr = client.getattr(&root_fh)?;
+        if root_attr.file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let result: Arc<NfsFileSystem> = Arc::new(NfsFileSystem {
+            client,
+            root_inode: Arc::new(LockedNfsInode(SpinLock::new(NfsInode {
+                parent: Weak::default(),
+                self_ref: Weak::default(),
+                fh: root_fh,
+                attr: root_attr,
+                attr_expire: ktime_get() + options.acdirmin * NSEC_PER_SEC as u64,
+                attr_timeo: options.acdirmin,
+                fs: Weak::default(),
+            }))),
+            options,
+            dtsize,
+        });
+
+        // 对root inode加锁，并继续完成初始化工作
+        let mut root_guard: SpinLockGuard<NfsInode> = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        root_guard.fs = Arc::downgrade(&result);
+        drop(root_guard);
+
+        kinfo!(
+            "nfs: mounted '{}', rsize: {}, wsize: {}",
+            source,
+            result.options.rsize,
+            result.options.wsize
+        );
+        return Ok(result);
+    }
+
+    /// @brief 只读挂载时拒绝修改文件系统
+    fn check_writable(&self) -> Result<(), SystemError> {
+        if self.options.ro {
+            return Err(SystemError::EROFS);
+        }
+        return Ok(());
+    }
+
+    /// @brief 属性缓存的最短与最长有效时间(秒)
+    fn attr_timeo_range(&self, file_type: FileType) -> (u64, u64) {
+        if file_type == FileType::Dir {
+            return (self.options.acdirmin, self.options.acdirmax);
+        }
+        return (self.options.acregmin, self.options.acregmax);
+    }
+}
+
+/// @brief 解析`<server-ip>:<path>`格式的导出目录
+fn parse_source(source: &str) -> Result<(Ipv4Address, &str), SystemError> {
+    let (server, path) = source.split_once(':').ok_or(SystemError::EINVAL)?;
+    let server = Ipv4Address::from_str(server).map_err(|_| SystemError::EINVAL)?;
+    if !path.starts_with('/') {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok((server, path));
+}
+
+kernel_param!(NFSROOT_PARAM: String = "nfsroot");
+
+/// @brief 挂载启动参数`nfsroot=<server-ip>:<root-dir>[,<nfs-options>]`指定的根文件系统
+///
+/// 启动参数中需要同时指定`root=/dev/nfs`，网络地址由`ip=`配置
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/nfs/nfsroot.c
+pub fn nfs_root_fs() -> Result<Arc<NfsFileSystem>, SystemError> {
+    let param = NFSROOT_PARAM.get().ok_or(SystemError::EINVAL)?;
+    let (source, options) = param.split_once(',').unwrap_or((param.as_str(), ""));
+    return NfsFileSystem::mount(source, options);
+}
+
+impl LockedNfsInode {
+    fn new(
+        fs: &Arc<NfsFileSystem>,
+        parent: Weak<LockedNfsInode>,
+        fh: NfsFh,
+        attr: Fattr3,
+    ) -> Arc<LockedNfsInode> {
+        let (timeo, _) = fs.attr_timeo_range(attr.file_type());
+        let inode = Arc::new(LockedNfsInode(SpinLock::new(NfsInode {
+            parent,
+            self_ref: Weak::default(),
+            fh,
+            attr,
+            attr_expire: ktime_get() + timeo * NSEC_PER_SEC as u64,
+            attr_timeo: timeo,
+            fs: Arc::downgrade(fs),
+        })));
+        inode.0.lock().self_ref = Arc::downgrade(&inode);
+        return inode;
+    }
+
+    /// @brief 获取文件系统对象与当前inode的文件句柄。向服务器发送请求时不持有锁
+    fn fs_and_fh(&self) -> (Arc<NfsFileSystem>, NfsFh) {
+        let guard: SpinLockGuard<NfsInode> = self.0.lock();
+        return (guard.fs.upgrade().unwrap(), guard.fh.clone());
+    }
+
+    fn file_type(&self) -> FileType {
+        return self.0.lock().attr.file_type();
+    }
+
+    /// @brief 获取文件的属性，缓存过期时向服务器重新获取
+    fn attr(&self, fs: &NfsFileSystem, fh: &NfsFh) -> Result<Fattr3, SystemError> {
+        {
+            let guard: SpinLockGuard<NfsInode> = self.0.lock();
+            if ktime_get() < guard.attr_expire {
+                return Ok(guard.attr.clone());
+            }
+        }
+        let attr = fs.client.getattr(fh)?;
+        self.update_attr(fs, attr.clone());
+        return Ok(attr);
+    }
+
+    /// @brief 用服务器返回的属性更新缓存，并调整缓存的有效时间
+    fn update_attr(&self, fs: &NfsFileSystem, attr: Fattr3) {
+        let (min, max) = fs.attr_timeo_range(attr.file_type());
+        let mut guard: SpinLockGuard<NfsInode> = self.0.lock();
+        let unchanged = guard.attr.mtime == attr.mtime && guard.attr.ctime == attr.ctime;
+        guard.attr_timeo = if unchanged {
+            (guard.attr_timeo * 2).max(1).clamp(min, max)
+        } else {
+            min
+        };
+        guard.attr_expire = ktime_get() + guard.attr_timeo * NSEC_PER_SEC as u64;
+        guard.attr = attr;
+    }
+
+    /// @brief 使属性缓存失效，下一次访问属性时向服务器重新获取
+    fn invalidate_attr(&self) {
+        self.0.lock().attr_expire = 0;
+    }
+
+    /// @brief 修改文件之后，用服务器返回的属性更新缓存。服务器没有返回属性时使缓存失效
+    fn post_op_attr(&self, fs: &NfsFileSystem, attr: Option<Fattr3>) {
+        match attr {
+            Some(attr) => self.update_attr(fs, attr),
+            None => self.invalidate_attr(),
+        }
+    }
+
+    /// @brief 为目录中的文件创建inode，服务器没有返回文件的属性时通过GETATTR获取
+    fn new_child(
+        &self,
+        fs: &Arc<NfsFileSystem>,
+        fh: NfsFh,
+        attr: Option<Fattr3>,
+    ) -> Result<Arc<LockedNfsInode>, SystemError> {
+        let attr = match attr {
+            Some(attr) => attr,
+            None => fs.client.getattr(&fh)?,
+        };
+        let self_ref = self.0.lock().self_ref.clone();
+        return Ok(LockedNfsInode::new(fs, self_ref, fh, attr));
+    }
+
+    /// @brief 读取目录中的所有目录项，不包括"."与".."
+    fn read_entries(&self) -> Result<Vec<Nfs3DirEntry>, SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        let mut entries: Vec<Nfs3DirEntry> = Vec::new();
+        let mut cookie = 0;
+        let mut verf = [0u8; NFS3_VERIFIER_SIZE];
+        loop {
+            let (batch, next_verf, eof) = fs.client.readdirplus(&fh, cookie, verf, fs.dtsize)?;
+            verf = next_verf;
+            let last_cookie = match batch.last() {
+                Some(last) => last.cookie,
+                None => break,
+            };
+            cookie = last_cookie;
+            entries.extend(
+                batch
+                    .into_iter()
+                    .filter(|entry| entry.name != "." && entry.name != ".."),
+            );
+            if eof {
+                break;
+            }
+        }
+        return Ok(entries);
+    }
+
+    /// @brief 检查另一个inode是否属于同一个NFS文件系统
+    fn same_fs<'a>(
+        &self,
+        other: &'a Arc<dyn IndexNode>,
+    ) -> Result<&'a LockedNfsInode, SystemError> {
+        let other: &LockedNfsInode = other
+            .downcast_ref::<LockedNfsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        // 两个inode可能是同一个，因此不能同时持有它们的锁
+        let fs = self.0.lock().fs.clone();
+        if !Weak::ptr_eq(&fs, &other.0.lock().fs) {
+            return Err(SystemError::EXDEV);
+        }
+        return Ok(other);
+    }
+}
+
+/// @brief 把文件类型转换为mode中的类型位
+fn file_type_mode(file_type: FileType) -> ModeType {
+    match file_type {
+        FileType::Dir => return ModeType::S_IFDIR,
+        FileType::SymLink => return ModeType::S_IFLNK,
+        FileType::CharDevice => return ModeType::S_IFCHR,
+        FileType::BlockDevice => return ModeType::S_IFBLK,
+        FileType::Pipe => return ModeType::S_IFIFO,
+        FileType::Socket => return ModeType::S_IFSOCK,
+        _ => return ModeType::S_IFREG,
+    }
+}
+
+/// @brief 把fattr3中的文件属性转换为元数据
+fn attr_to_metadata(attr: &Fattr3) -> Metadata {
+    let file_type = attr.file_type();
+    return Metadata {
+        dev_id: 0,
+        inode_id: InodeId::new(attr.fileid as usize),
+        size: attr.size as i64,
+        blk_size: 512,
+        blocks: ((attr.used + 511) / 512) as usize,
+        atime: attr.atime,
+        mtime: attr.mtime,
+        ctime: attr.ctime,
+        file_type,
+        mode: ModeType::from_bits_truncate(attr.mode & !ModeType::S_IFMT.bits())
+            | file_type_mode(file_type),
+        nlinks: attr.nlink as usize,
+        uid: attr.uid as usize,
+        gid: attr.gid as usize,
+        raw_dev: make_rawdev(attr.rdev.0 as usize, attr.rdev.1 as usize),
+    };
+}
+
+impl IndexNode for LockedNfsInode {
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        match self.file_type() {
+            FileType::Dir => return Err(SystemError::EISDIR),
+            FileType::SymLink => {
+                // 符号链接的内容为它的目标
+                let target = fs.client.readlink(&fh)?;
+                let target = target.as_bytes();
+                if offset >= target.len() {
+                    return Ok(0);
+                }
+                let n = core::cmp::min(len, target.len() - offset);
+                buf[..n].copy_from_slice(&target[offset..offset + n]);
+                return Ok(n);
+            }
+            _ => {}
+        }
+
+        let (n, attr) = fs
+            .client
+            .read(&fh, offset as u64, &mut buf[0..len], fs.options.rsize)?;
+        if let Some(attr) = attr {
+            self.update_attr(&fs, attr);
+        }
+        return Ok(n);
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+
+        let (n, attr) = fs
+            .client
+            .write(&fh, offset as u64, &buf[0..len], fs.options.wsize)?;
+        self.post_op_attr(&fs, attr);
+        if n == 0 && len > 0 {
+            return Err(SystemError::EIO);
+        }
+        return Ok(n);
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        return self;
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        return Ok(attr_to_metadata(&self.attr(&fs, &fh)?));
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+        let current = self.attr(&fs, &fh)?;
+
+        // 只修改发生变化的属性，避免服务器拒绝没有必要的chown
+        let mode = (metadata.mode & !ModeType::S_IFMT).bits();
+        let mut attr = Sattr3::default();
+        if mode != current.mode & !ModeType::S_IFMT.bits() {
+            attr.mode = Some(mode);
+        }
+        if metadata.uid as u32 != current.uid {
+            attr.uid = Some(metadata.uid as u32);
+        }
+        if metadata.gid as u32 != current.gid {
+            attr.gid = Some(metadata.gid as u32);
+        }
+        if metadata.atime != current.atime {
+            attr.atime = Some(metadata.atime);
+        }
+        if metadata.mtime != current.mtime {
+            attr.mtime = Some(metadata.mtime);
+        }
+        if attr == Sattr3::default() {
+            return Ok(());
+        }
+        let attr = fs.client.setattr(&fh, &attr)?;
+        self.post_op_attr(&fs, attr);
+        return Ok(());
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        if self.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+        let attr = Sattr3 {
+            size: Some(len as u64),
+            ..Default::default()
+        };
+        let attr = fs.client.setattr(&fh, &attr)?;
+        self.post_op_attr(&fs, attr);
+        return Ok(());
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        if self.attr(&fs, &fh)?.size <= len as u64 {
+            return Ok(());
+        }
+        return self.resize(len);
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+        let perm = (mode & !ModeType::S_IFMT).bits();
+        let (child_fh, attr) = match file_type {
+            FileType::File => fs.client.create(&fh, name, perm)?,
+            FileType::Dir => fs.client.mkdir(&fh, name, perm)?,
+            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        };
+        // 目录的mtime发生了变化
+        self.invalidate_attr();
+        let inode = self.new_child(&fs, child_fh, attr)?;
+        return Ok(inode);
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        let other = self.same_fs(other)?;
+        if other.file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+        let other_fh = other.0.lock().fh.clone();
+        fs.client.link(&other_fh, &fh, name)?;
+        self.invalidate_attr();
+        other.invalidate_attr();
+        return Ok(());
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+        fs.client.remove(&fh, name)?;
+        self.invalidate_attr();
+        return Ok(());
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+        fs.client.rmdir(&fh, name)?;
+        self.invalidate_attr();
+        return Ok(());
+    }
+
+    fn move_(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let target = self.same_fs(target)?;
+        let (fs, fh) = self.fs_and_fh();
+        fs.check_writable()?;
+        let target_fh = target.0.lock().fh.clone();
+        fs.client.rename(&fh, old_name, &target_fh, new_name)?;
+        self.invalidate_attr();
+        target.invalidate_attr();
+        return Ok(());
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        if self.file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(self.read_entries()?.into_iter().map(|entry| entry.name));
+        return Ok(keys);
+    }
+
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let (fileid, parent) = {
+            let guard: SpinLockGuard<NfsInode> = self.0.lock();
+            if guard.attr.file_type() != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (guard.attr.fileid, guard.parent.upgrade().unwrap())
+        };
+        let parent_fileid = parent.0.lock().attr.fileid;
+
+        // READDIRPLUS已经返回了目录项的属性，不需要像默认实现那样逐个查找
+        let mut entries: Vec<DirEntry> = Vec::new();
+        for entry in self.read_entries()? {
+            let file_type = match &entry.attr {
+                Some(attr) => attr.file_type(),
+                None => self.find(&entry.name)?.metadata()?.file_type,
+            };
+            entries.push(DirEntry {
+                ino: InodeId::new(entry.fileid as usize),
+                file_type,
+                name: entry.name,
+            });
+        }
+        entries.push(DirEntry {
+            name: String::from("."),
+            ino: InodeId::new(fileid as usize),
+            file_type: FileType::Dir,
+        });
+        entries.push(DirEntry {
+            name: String::from(".."),
+            ino: InodeId::new(parent_fileid as usize),
+            file_type: FileType::Dir,
+        });
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut pos = offset;
+        for entry in entries.into_iter().skip(offset) {
+            if !filler(entry) {
+                break;
+            }
+            pos += 1;
+        }
+        return Ok(pos);
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let (self_ref, parent) = {
+            let guard: SpinLockGuard<NfsInode> = self.0.lock();
+            if guard.attr.file_type() != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (guard.self_ref.clone(), guard.parent.clone())
+        };
+        let target = match name {
+            "" | "." => self_ref.upgrade(),
+            ".." => parent.upgrade(),
+            _ => None,
+        };
+        if let Some(target) = target {
+            return Ok(target);
+        }
+
+        let (fs, fh) = self.fs_and_fh();
+        let (child_fh, attr) = fs.client.lookup(&fh, name)?;
+        let inode = self.new_child(&fs, child_fh, attr)?;
+        return Ok(inode);
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        // close-to-open：打开文件时总是重新获取属性，使其他客户端关闭文件之前的修改可以看到
+        let (fs, fh) = self.fs_and_fh();
+        self.invalidate_attr();
+        self.attr(&fs, &fh)?;
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        // 每次写入之后都已经通过COMMIT把数据写入了服务器的稳定存储
+        return Ok(());
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        if self.file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        return self
+            .read_entries()?
+            .into_iter()
+            .find(|entry| InodeId::new(entry.fileid as usize) == ino)
+            .map(|entry| entry.name)
+            .ok_or(SystemError::ENOENT);
+    }
+}
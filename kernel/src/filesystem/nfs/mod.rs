@@ -0,0 +1,20 @@
+//! NFS第3版文件系统客户端
+//!
+//! 通过网络挂载NFS服务器导出的目录，例如`mount("192.168.1.1:/export", "/mnt", "nfs", 0, "proto=tcp,rsize=32768")`，
+//! 挂载选项见[`options::NfsMountOptions`]。也可以使用启动参数`root=/dev/nfs nfsroot=<server-ip>:<root-dir>`
+//! 把导出的目录作为根文件系统，实现无盘启动。
+//!
+//! - [`xdr`]：XDR编码与解码
+//! - [`rpc`]：ONC RPC客户端，基于TCP或者UDP
+//! - [`mount`]：获取导出目录的文件句柄的MOUNT协议
+//! - [`nfs3`]：NFS第3版协议的各个过程
+//! - [`fs`]：文件系统与inode，以及属性缓存
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/nfs/
+
+pub mod fs;
+pub mod mount;
+pub mod nfs3;
+pub mod options;
+pub mod rpc;
+pub mod xdr;
@@ -0,0 +1,55 @@
+//! MOUNT第3版协议，用于获取导出目录的文件句柄
+//!
+//! 参考 https://www.rfc-editor.org/rfc/rfc1813#section-5
+
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+
+use crate::syscall::SystemError;
+
+use super::{
+    nfs3::{nfsstat3_to_system_error, NfsFh},
+    options::NfsMountOptions,
+    rpc::{pmap_getport, RpcClient},
+    xdr::{XdrDecoder, XdrEncoder},
+};
+
+const MOUNT_PROGRAM: u32 = 100005;
+const MOUNT_VERSION: u32 = 3;
+const MOUNTPROC3_MNT: u32 = 1;
+/// 导出目录路径的最大长度
+const MNTPATHLEN: usize = 1024;
+
+/// @brief 向服务器上的MOUNT服务请求导出目录的文件句柄
+///
+/// @param server 服务器的地址
+/// @param path 导出目录在服务器上的路径
+///
+/// @return Err(SystemError::EACCES) 服务器拒绝了本机的挂载请求
+pub fn mount_export(
+    server: Ipv4Address,
+    path: &str,
+    options: &NfsMountOptions,
+) -> Result<NfsFh, SystemError> {
+    if path.len() > MNTPATHLEN {
+        return Err(SystemError::ENAMETOOLONG);
+    }
+    let config = options.rpc_config();
+    let port = match options.mountport {
+        Some(port) => port,
+        None => pmap_getport(server, MOUNT_PROGRAM, MOUNT_VERSION, &config)?,
+    };
+    let endpoint = IpEndpoint::new(IpAddress::Ipv4(server), port);
+    let client = RpcClient::new(endpoint, MOUNT_PROGRAM, MOUNT_VERSION, &config)?;
+
+    let mut args = XdrEncoder::new();
+    args.str(path);
+    let res = client.call(MOUNTPROC3_MNT, args.finish())?;
+    let mut dec = XdrDecoder::new(&res);
+    // mountstat3与nfsstat3使用相同的错误码
+    match dec.u32()? {
+        0 => {}
+        stat => return Err(nfsstat3_to_system_error(stat)),
+    }
+    // 之后是服务器接受的认证方式，客户端总是使用AUTH_UNIX，不需要检查
+    return NfsFh::decode(&mut dec);
+}
@@ -0,0 +1,661 @@
+//! NFS第3版协议
+//!
+//! 参考 https://www.rfc-editor.org/rfc/rfc1813
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{filesystem::vfs::FileType, syscall::SystemError, time::TimeSpec};
+
+use super::{
+    rpc::RpcClient,
+    xdr::{XdrDecoder, XdrEncoder},
+};
+
+pub const NFS_PROGRAM: u32 = 100003;
+pub const NFS_VERSION: u32 = 3;
+
+/// 文件句柄的最大长度
+pub const NFS3_FHSIZE: usize = 64;
+/// 文件名与路径的最大长度
+pub const NFS3_MAXNAMLEN: usize = 255;
+const NFS3_MAXPATHLEN: usize = 4096;
+/// WRITE与READDIRPLUS中verifier的长度
+pub const NFS3_VERIFIER_SIZE: usize = 8;
+
+/// 过程号
+const NFSPROC3_GETATTR: u32 = 1;
+const NFSPROC3_SETATTR: u32 = 2;
+const NFSPROC3_LOOKUP: u32 = 3;
+const NFSPROC3_READLINK: u32 = 5;
+const NFSPROC3_READ: u32 = 6;
+const NFSPROC3_WRITE: u32 = 7;
+const NFSPROC3_CREATE: u32 = 8;
+const NFSPROC3_MKDIR: u32 = 9;
+const NFSPROC3_REMOVE: u32 = 12;
+const NFSPROC3_RMDIR: u32 = 13;
+const NFSPROC3_RENAME: u32 = 14;
+const NFSPROC3_LINK: u32 = 15;
+const NFSPROC3_READDIRPLUS: u32 = 17;
+const NFSPROC3_FSINFO: u32 = 19;
+const NFSPROC3_COMMIT: u32 = 21;
+
+/// 文件类型
+const NF3REG: u32 = 1;
+const NF3DIR: u32 = 2;
+const NF3BLK: u32 = 3;
+const NF3CHR: u32 = 4;
+const NF3LNK: u32 = 5;
+const NF3SOCK: u32 = 6;
+const NF3FIFO: u32 = 7;
+
+/// WRITE请求的数据写入稳定存储的方式
+const NFS3_UNSTABLE: u32 = 0;
+const NFS3_FILE_SYNC: u32 = 2;
+
+/// CREATE的方式：UNCHECKED表示文件已经存在时不报错
+const NFS3_CREATE_UNCHECKED: u32 = 0;
+
+/// sattr3中设置时间的方式
+const NFS3_SET_TO_CLIENT_TIME: u32 = 2;
+
+/// 同时发出的READ/WRITE请求的最大数量
+const NFS_MAX_INFLIGHT: usize = 8;
+
+/// @brief 把NFS的状态码转换为SystemError
+///
+/// nfsstat3中的错误码沿用了BSD的errno，与Linux的errno并不完全相同，因此逐个转换
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/nfs/nfs2xdr.c
+pub fn nfsstat3_to_system_error(stat: u32) -> SystemError {
+    match stat {
+        1 => return SystemError::EPERM,
+        2 => return SystemError::ENOENT,
+        5 => return SystemError::EIO,
+        6 => return SystemError::ENXIO,
+        13 => return SystemError::EACCES,
+        17 => return SystemError::EEXIST,
+        18 => return SystemError::EXDEV,
+        19 => return SystemError::ENODEV,
+        20 => return SystemError::ENOTDIR,
+        21 => return SystemError::EISDIR,
+        22 => return SystemError::EINVAL,
+        27 => return SystemError::EFBIG,
+        28 => return SystemError::ENOSPC,
+        30 => return SystemError::EROFS,
+        31 => return SystemError::EMLINK,
+        63 => return SystemError::ENAMETOOLONG,
+        66 => return SystemError::ENOTEMPTY,
+        69 => return SystemError::EDQUOT,
+        70 => return SystemError::ESTALE,
+        71 => return SystemError::EREMOTE,
+        // BADHANDLE
+        10001 => return SystemError::EBADF,
+        // BAD_COOKIE、TOOSMALL、BADTYPE
+        10003 | 10005 | 10007 => return SystemError::EINVAL,
+        // NOTSUPP
+        10004 => return SystemError::EOPNOTSUPP_OR_ENOTSUP,
+        // JUKEBOX：服务器暂时无法处理请求(例如文件正在从磁带中恢复)
+        10008 => return SystemError::EAGAIN_OR_EWOULDBLOCK,
+        // NOT_SYNC、SERVERFAULT以及未知的错误码
+        _ => return SystemError::EIO,
+    }
+}
+
+/// @brief 读取回复开头的状态码
+fn check_status(dec: &mut XdrDecoder) -> Result<(), SystemError> {
+    match dec.u32()? {
+        0 => return Ok(()),
+        stat => return Err(nfsstat3_to_system_error(stat)),
+    }
+}
+
+/// 文件句柄
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NfsFh(pub Vec<u8>);
+
+impl NfsFh {
+    pub fn decode(dec: &mut XdrDecoder) -> Result<Self, SystemError> {
+        return Ok(Self(dec.opaque(NFS3_FHSIZE)?.to_vec()));
+    }
+
+    fn encode(&self, enc: &mut XdrEncoder) {
+        enc.opaque(&self.0);
+    }
+}
+
+/// 文件的属性
+#[derive(Debug, Clone)]
+pub struct Fattr3 {
+    pub ftype: u32,
+    /// 权限位，不包括文件类型
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    /// 文件占用的磁盘空间(字节)
+    pub used: u64,
+    pub rdev: (u32, u32),
+    pub fsid: u64,
+    pub fileid: u64,
+    pub atime: TimeSpec,
+    pub mtime: TimeSpec,
+    pub ctime: TimeSpec,
+}
+
+impl Fattr3 {
+    fn decode(dec: &mut XdrDecoder) -> Result<Self, SystemError> {
+        return Ok(Self {
+            ftype: dec.u32()?,
+            mode: dec.u32()?,
+            nlink: dec.u32()?,
+            uid: dec.u32()?,
+            gid: dec.u32()?,
+            size: dec.u64()?,
+            used: dec.u64()?,
+            rdev: (dec.u32()?, dec.u32()?),
+            fsid: dec.u64()?,
+            fileid: dec.u64()?,
+            atime: decode_time(dec)?,
+            mtime: decode_time(dec)?,
+            ctime: decode_time(dec)?,
+        });
+    }
+
+    pub fn file_type(&self) -> FileType {
+        match self.ftype {
+            NF3DIR => return FileType::Dir,
+            NF3BLK => return FileType::BlockDevice,
+            NF3CHR => return FileType::CharDevice,
+            NF3LNK => return FileType::SymLink,
+            NF3SOCK => return FileType::Socket,
+            NF3FIFO => return FileType::Pipe,
+            NF3REG => return FileType::File,
+            _ => return FileType::File,
+        }
+    }
+}
+
+fn decode_time(dec: &mut XdrDecoder) -> Result<TimeSpec, SystemError> {
+    let sec = dec.u32()? as i64;
+    let nsec = dec.u32()? as i64;
+    return Ok(TimeSpec::new(sec, nsec));
+}
+
+/// @brief 解析post_op_attr：服务器可以不返回属性
+fn decode_post_op_attr(dec: &mut XdrDecoder) -> Result<Option<Fattr3>, SystemError> {
+    if dec.bool()? {
+        return Ok(Some(Fattr3::decode(dec)?));
+    }
+    return Ok(None);
+}
+
+/// @brief 解析wcc_data，只返回修改之后的属性
+fn decode_wcc_data(dec: &mut XdrDecoder) -> Result<Option<Fattr3>, SystemError> {
+    // pre_op_attr：修改之前的size、mtime与ctime
+    if dec.bool()? {
+        dec.fixed(8 + 8 + 8)?;
+    }
+    return decode_post_op_attr(dec);
+}
+
+/// @brief 解析post_op_fh3
+fn decode_post_op_fh(dec: &mut XdrDecoder) -> Result<Option<NfsFh>, SystemError> {
+    if dec.bool()? {
+        return Ok(Some(NfsFh::decode(dec)?));
+    }
+    return Ok(None);
+}
+
+/// 要修改的属性，None表示不修改
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sattr3 {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub size: Option<u64>,
+    pub atime: Option<TimeSpec>,
+    pub mtime: Option<TimeSpec>,
+}
+
+impl Sattr3 {
+    fn encode(&self, enc: &mut XdrEncoder) {
+        for v in [self.mode, self.uid, self.gid] {
+            enc.bool(v.is_some());
+            if let Some(v) = v {
+                enc.u32(v);
+            }
+        }
+        enc.bool(self.size.is_some());
+        if let Some(size) = self.size {
+            enc.u64(size);
+        }
+        for t in [&self.atime, &self.mtime] {
+            match t {
+                Some(t) => {
+                    enc.u32(NFS3_SET_TO_CLIENT_TIME)
+                        .u32(t.tv_sec as u32)
+                        .u32(t.tv_nsec as u32);
+                }
+                // DONT_CHANGE
+                None => {
+                    enc.u32(0);
+                }
+            }
+        }
+    }
+}
+
+/// READDIRPLUS返回的目录项
+#[derive(Debug, Clone)]
+pub struct Nfs3DirEntry {
+    pub fileid: u64,
+    pub name: String,
+    /// 读取下一个目录项时使用的位置
+    pub cookie: u64,
+    pub attr: Option<Fattr3>,
+    pub fh: Option<NfsFh>,
+}
+
+/// FSINFO返回的服务器的限制
+#[derive(Debug, Clone)]
+pub struct Nfs3FsInfo {
+    /// READ请求的最大长度
+    pub rtmax: u32,
+    /// WRITE请求的最大长度
+    pub wtmax: u32,
+    /// READDIR请求的推荐长度
+    pub dtpref: u32,
+}
+
+/// @brief NFS第3版的客户端，每个方法对应一个过程
+#[derive(Debug)]
+pub struct Nfs3Client {
+    rpc: RpcClient,
+}
+
+impl Nfs3Client {
+    pub fn new(rpc: RpcClient) -> Self {
+        return Self { rpc };
+    }
+
+    /// @brief 编码diropargs3：目录的文件句柄与文件名
+    fn diropargs(enc: &mut XdrEncoder, dir: &NfsFh, name: &str) -> Result<(), SystemError> {
+        if name.len() > NFS3_MAXNAMLEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        dir.encode(enc);
+        enc.str(name);
+        return Ok(());
+    }
+
+    pub fn getattr(&self, fh: &NfsFh) -> Result<Fattr3, SystemError> {
+        let mut args = XdrEncoder::new();
+        fh.encode(&mut args);
+        let res = self.rpc.call(NFSPROC3_GETATTR, args.finish())?;
+        let mut dec = XdrDecoder::new(&res);
+        check_status(&mut dec)?;
+        return Fattr3::decode(&mut dec);
+    }
+
+    /// @brief 修改文件的属性
+    ///
+    /// @return Ok(Option<Fattr3>) 修改之后的属性
+    pub fn setattr(&self, fh: &NfsFh, attr: &Sattr3) -> Result<Option<Fattr3>, SystemError> {
+        let mut args = XdrEncoder::new();
+        fh.encode(&mut args);
+        attr.encode(&mut args);
+        // 不检查文件的ctime
+        args.bool(false);
+        let res = self.rpc.call(NFSPROC3_SETATTR, args.finish())?;
+        let mut dec = XdrDecoder::new(&res);
+        check_status(&mut dec)?;
+        return decode_wcc_data(&mut dec);
+    }
+
+    /// @brief 在目录中查找文件
+    ///
+    /// @return Ok((NfsFh, Option<Fattr3>)) 文件的句柄与属性
+    pub fn lookup(&self, dir: &NfsFh, name: &str) -> Result<(NfsFh, Option<Fattr3>), SystemError> {
+        let mut args = XdrEncoder::new();
+        Self::diropargs(&mut args, dir, name)?;
+        let res = self.rpc.call(NFSPROC3_LOOKUP, args.finish())?;
+        let mut dec = XdrDecoder::new(&res);
+        check_status(&mut dec)?;
+        let fh = NfsFh::decode(&mut dec)?;
+        let attr = decode_post_op_attr(&mut dec)?;
+        return Ok((fh, attr));
+    }
+
+    pub fn readlink(&self, fh: &NfsFh) -> Result<String, SystemError> {
+        let mut args = XdrEncoder::new();
+        fh.encode(&mut args);
+        let res = self.rpc.call(NFSPROC3_READLINK, args.finish())?;
+        let mut dec = XdrDecoder::new(&res);
+        check_status(&mut dec)?;
+        decode_post_op_attr(&mut dec)?;
+        return dec.str(NFS3_MAXPATHLEN);
+    }
+
+    /// @brief 读取文件，把`buf`分成多个不超过`rsize`的READ请求，同时发出
+    ///
+    /// @return Ok((usize, Option<Fattr3>)) 读取的字节数，以及文件最新的属性
+    pub fn read(
+        &self,
+        fh: &NfsFh,
+        offset: u64,
+        buf: &mut [u8],
+        rsize: usize,
+    ) -> Result<(usize, Option<Fattr3>), SystemError> {
+        let mut total = 0;
+        let mut attr = None;
+        for group in buf.chunks_mut(rsize * NFS_MAX_INFLIGHT) {
+            let group_offset = offset + total as u64;
+            let calls = group
+                .chunks(rsize)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let mut args = XdrEncoder::new();
+                    fh.encode(&mut args);
+                    args.u64(group_offset + (i * rsize) as u64)
+                        .u32(chunk.len() as u32);
+                    (NFSPROC3_READ, args.finish())
+                })
+                .collect();
+            let results = self.rpc.call_batch(calls)?;
+
+            for (chunk, res) in group.chunks_mut(rsize).zip(results.iter()) {
+                let mut dec = XdrDecoder::new(res);
+                let r = check_status(&mut dec).and_then(|_| {
+                    let attr = decode_post_op_attr(&mut dec)?;
+                    let count = dec.u32()? as usize;
+                    let eof = dec.bool()?;
+                    let data = dec.opaque(chunk.len())?;
+                    return Ok((attr, count.min(data.len()), eof, data));
+                });
+                let (chunk_attr, count, eof, data) = match r {
+                    Ok(r) => r,
+                    // 已经读取了一部分数据时，返回已经读取的字节数
+                    Err(_) if total > 0 => return Ok((total, attr)),
+                    Err(e) => return Err(e),
+                };
+                chunk[..count].copy_from_slice(&data[..count]);
+                total += count;
+                if chunk_attr.is_some() {
+                    attr = chunk_attr;
+                }
+                // 读到了文件末尾，或者服务器只返回了一部分数据，之后的请求的结果都不再连续
+                if eof || count < chunk.len() {
+                    return Ok((total, attr));
+                }
+            }
+        }
+        return Ok((total, attr));
+    }
+
+    /// @brief 写入文件，把`data`分成多个不超过`wsize`的WRITE请求，同时发出
+    ///
+    /// 数据先以UNSTABLE的方式写入，最后用一个COMMIT请求把数据写入服务器的稳定存储。
+    /// 如果COMMIT返回的verifier与WRITE返回的不同，说明服务器在这期间重启过，缓存中的数据可能已经丢失，
+    /// 因此以FILE_SYNC的方式重新写入
+    ///
+    /// @return Ok((usize, Option<Fattr3>)) 写入的字节数，以及文件最新的属性
+    pub fn write(
+        &self,
+        fh: &NfsFh,
+        offset: u64,
+        data: &[u8],
+        wsize: usize,
+    ) -> Result<(usize, Option<Fattr3>), SystemError> {
+        let (written, verf, attr) = self.write_chunks(fh, offset, data, wsize, NFS3_UNSTABLE)?;
+        let verf = match verf {
+            Some(verf) => verf,
+            // 服务器已经把数据写入了稳定存储
+            None => return Ok((written, attr)),
+        };
+
+        let mut args = XdrEncoder::new();
+        fh.encode(&mut args);
+        args.u64(offset).u32(written as u32);
+        let res = self.rpc.call(NFSPROC3_COMMIT, args.finish())?;
+        let mut dec = XdrDecoder::new(&res);
+        check_status(&mut dec)?;
+        let commit_attr = decode_wcc_data(&mut dec)?;
+        let commit_verf = dec.fixed(NFS3_VERIFIER_SIZE)?;
+        if commit_verf == &verf[..] {
+            return Ok((written, commit_attr.or(attr)));
+        }
+
+        let (written, _, attr) =
+            self.write_chunks(fh, offset, &data[..written], wsize, NFS3_FILE_SYNC)?;
+        return Ok((written, attr));
+    }
+
+    /// @brief 发出WRITE请求
+    ///
+    /// @return Ok((usize, Option<[u8; 8]>, Option<Fattr3>)) 写入的字节数；
+    /// 如果有数据没有写入稳定存储，返回WRITE的verifier；文件最新的属性
+    #[allow(clippy::type_complexity)]
+    fn write_chunks(
+        &self,
+        fh: &NfsFh,
+        offset: u64,
+        data: &[u8],
+        wsize: usize,
+        stable: u32,
+    ) -> Result<(usize, Option<[u8; NFS3_VERIFIER_SIZE]>, Option<Fattr3>), SystemError> {
+        let mut total = 0;
+        let mut verf = None;
+        let mut attr = None;
+        for group in data.chunks(wsize * NFS_MAX_INFLIGHT) {
+            let group_offset = offset + total as u64;
+            let calls = group
+                .chunks(wsize)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let mut args = XdrEncoder::new();
+                    fh.encode(&mut args);
+                    args.u64(group_offset + (i * wsize) as u64)
+                        .u32(chunk.len() as u32)
+                        .u32(stable)
+                        .opaque(chunk);
+                    (NFSPROC3_WRITE, args.finish())
+                })
+                .collect();
+            let results = self.rpc.call_batch(calls)?;
+
+            for (chunk, res) in group.chunks(wsize).zip(results.iter()) {
+                let mut dec = XdrDecoder::new(res);
+                let r = check_status(&mut dec).and_then(|_| {
+                    let attr = decode_wcc_data(&mut dec)?;
+                    let count = dec.u32()? as usize;
+                    let committed = dec.u32()?;
+                    let verf = dec.fixed(NFS3_VERIFIER_SIZE)?;
+                    return Ok((attr, count.min(chunk.len()), committed, verf));
+                });
+                let (chunk_attr, count, committed, chunk_verf) = match r {
+                    Ok(r) => r,
+                    Err(_) if total > 0 => return Ok((total, verf, attr)),
+                    Err(e) => return Err(e),
+                };
+                total += count;
+                if chunk_attr.is_some() {
+                    attr = chunk_attr;
+                }
+                if committed != NFS3_FILE_SYNC {
+                    let mut v = [0u8; NFS3_VERIFIER_SIZE];
+                    v.copy_from_slice(chunk_verf);
+                    verf = Some(v);
+                }
+                // 服务器只写入了一部分数据，之后的请求写入的数据不再连续
+                if count < chunk.len() {
+                    return Ok((total, verf, attr));
+                }
+            }
+        }
+        return Ok((total, verf, attr));
+    }
+
+    /// @brief 在目录中创建普通文件
+    ///
+    /// @return Ok((NfsFh, Option<Fattr3>)) 新文件的句柄与属性
+    pub fn create(
+        &self,
+        dir: &NfsFh,
+        name: &str,
+        mode: u32,
+    ) -> Result<(NfsFh, Option<Fattr3>), SystemError> {
+        let mut args = XdrEncoder::new();
+        Self::diropargs(&mut args, dir, name)?;
+        args.u32(NFS3_CREATE_UNCHECKED);
+        Sattr3 {
+            mode: Some(mode),
+            ..Default::default()
+        }
+        .encode(&mut args);
+        let res = self.rpc.call(NFSPROC3_CREATE, args.finish())?;
+        return self.decode_diropres(&res, dir, name);
+    }
+
+    /// @brief 在目录中创建子目录
+    ///
+    /// @return Ok((NfsFh, Option<Fattr3>)) 新目录的句柄与属性
+    pub fn mkdir(
+        &self,
+        dir: &NfsFh,
+        name: &str,
+        mode: u32,
+    ) -> Result<(NfsFh, Option<Fattr3>), SystemError> {
+        let mut args = XdrEncoder::new();
+        Self::diropargs(&mut args, dir, name)?;
+        Sattr3 {
+            mode: Some(mode),
+            ..Default::default()
+        }
+        .encode(&mut args);
+        let res = self.rpc.call(NFSPROC3_MKDIR, args.finish())?;
+        return self.decode_diropres(&res, dir, name);
+    }
+
+    /// @brief 解析CREATE与MKDIR的结果。服务器可以不返回新文件的句柄，这时通过LOOKUP获取
+    fn decode_diropres(
+        &self,
+        res: &[u8],
+        dir: &NfsFh,
+        name: &str,
+    ) -> Result<(NfsFh, Option<Fattr3>), SystemError> {
+        let mut dec = XdrDecoder::new(res);
+        check_status(&mut dec)?;
+        let fh = decode_post_op_fh(&mut dec)?;
+        let attr = decode_post_op_attr(&mut dec)?;
+        match fh {
+            Some(fh) => return Ok((fh, attr)),
+            None => return self.lookup(dir, name),
+        }
+    }
+
+    /// @brief 删除目录中的文件
+    pub fn remove(&self, dir: &NfsFh, name: &str) -> Result<(), SystemError> {
+        return self.dirop(NFSPROC3_REMOVE, dir, name);
+    }
+
+    /// @brief 删除目录中的子目录
+    pub fn rmdir(&self, dir: &NfsFh, name: &str) -> Result<(), SystemError> {
+        return self.dirop(NFSPROC3_RMDIR, dir, name);
+    }
+
+    fn dirop(&self, procedure: u32, dir: &NfsFh, name: &str) -> Result<(), SystemError> {
+        let mut args = XdrEncoder::new();
+        Self::diropargs(&mut args, dir, name)?;
+        let res = self.rpc.call(procedure, args.finish())?;
+        return check_status(&mut XdrDecoder::new(&res));
+    }
+
+    /// @brief 移动文件，目标文件已经存在时会被替换
+    pub fn rename(
+        &self,
+        from_dir: &NfsFh,
+        from_name: &str,
+        to_dir: &NfsFh,
+        to_name: &str,
+    ) -> Result<(), SystemError> {
+        let mut args = XdrEncoder::new();
+        Self::diropargs(&mut args, from_dir, from_name)?;
+        Self::diropargs(&mut args, to_dir, to_name)?;
+        let res = self.rpc.call(NFSPROC3_RENAME, args.finish())?;
+        return check_status(&mut XdrDecoder::new(&res));
+    }
+
+    /// @brief 为文件创建硬链接
+    pub fn link(&self, fh: &NfsFh, dir: &NfsFh, name: &str) -> Result<(), SystemError> {
+        let mut args = XdrEncoder::new();
+        fh.encode(&mut args);
+        Self::diropargs(&mut args, dir, name)?;
+        let res = self.rpc.call(NFSPROC3_LINK, args.finish())?;
+        return check_status(&mut XdrDecoder::new(&res));
+    }
+
+    /// @brief 读取目录项以及它们的属性与句柄
+    ///
+    /// @param cookie 从哪个目录项之后开始读取，0表示从头开始
+    /// @param cookieverf 上一次READDIRPLUS返回的verifier，从头开始读取时为0
+    /// @param maxcount 回复的最大长度
+    ///
+    /// @return Ok((Vec<Nfs3DirEntry>, [u8; 8], bool)) 目录项、新的verifier以及是否已经读到目录末尾
+    #[allow(clippy::type_complexity)]
+    pub fn readdirplus(
+        &self,
+        dir: &NfsFh,
+        cookie: u64,
+        cookieverf: [u8; NFS3_VERIFIER_SIZE],
+        maxcount: u32,
+    ) -> Result<(Vec<Nfs3DirEntry>, [u8; NFS3_VERIFIER_SIZE], bool), SystemError> {
+        let mut args = XdrEncoder::new();
+        dir.encode(&mut args);
+        args.u64(cookie)
+            .fixed(&cookieverf)
+            .u32(maxcount / 2)
+            .u32(maxcount);
+        let res = self.rpc.call(NFSPROC3_READDIRPLUS, args.finish())?;
+        let mut dec = XdrDecoder::new(&res);
+        check_status(&mut dec)?;
+        decode_post_op_attr(&mut dec)?;
+        let mut verf = [0u8; NFS3_VERIFIER_SIZE];
+        verf.copy_from_slice(dec.fixed(NFS3_VERIFIER_SIZE)?);
+
+        let mut entries = Vec::new();
+        while dec.bool()? {
+            entries.push(Nfs3DirEntry {
+                fileid: dec.u64()?,
+                name: dec.str(NFS3_MAXNAMLEN)?,
+                cookie: dec.u64()?,
+                attr: decode_post_op_attr(&mut dec)?,
+                fh: decode_post_op_fh(&mut dec)?,
+            });
+        }
+        let eof = dec.bool()?;
+        return Ok((entries, verf, eof));
+    }
+
+    /// @brief 查询服务器的限制
+    pub fn fsinfo(&self, root: &NfsFh) -> Result<Nfs3FsInfo, SystemError> {
+        let mut args = XdrEncoder::new();
+        root.encode(&mut args);
+        let res = self.rpc.call(NFSPROC3_FSINFO, args.finish())?;
+        let mut dec = XdrDecoder::new(&res);
+        check_status(&mut dec)?;
+        decode_post_op_attr(&mut dec)?;
+        let rtmax = dec.u32()?;
+        let _rtpref = dec.u32()?;
+        let _rtmult = dec.u32()?;
+        let wtmax = dec.u32()?;
+        let _wtpref = dec.u32()?;
+        let _wtmult = dec.u32()?;
+        let dtpref = dec.u32()?;
+        return Ok(Nfs3FsInfo {
+            rtmax,
+            wtmax,
+            dtpref,
+        });
+    }
+}
@@ -0,0 +1,169 @@
+//! NFS的挂载选项
+//!
+//! 挂载选项是以逗号分隔的`name=value`或者`name`，与Linux的nfs(5)中的同名选项含义相同。支持的选项：
+//!
+//! - `proto=tcp|udp`、`tcp`、`udp`：传输层协议，默认为TCP
+//! - `port=`、`mountport=`：NFS与MOUNT服务的端口，不指定时通过portmap查询
+//! - `rsize=`、`wsize=`：每个READ/WRITE请求最多传输的字节数，会被限制在服务器支持的范围内。
+//!   网络协议栈不支持IP分片，使用UDP时每条消息都必须能放进一个以太网帧，因此最大为1024
+//! - `timeo=`：等待回复的时间(单位为0.1秒)，`retrans=`：重传的次数
+//! - `soft`、`hard`：服务器没有响应时，是返回错误还是一直重试，默认为`hard`
+//! - `acregmin=`、`acregmax=`、`acdirmin=`、`acdirmax=`、`actimeo=`：属性缓存的有效时间(单位为秒)
+//! - `noac`：不缓存属性
+//! - `ro`、`rw`：只读挂载
+//!
+//! `vers=3`、`nfsvers=3`、`nolock`、`sec=sys`等选项被接受但不起作用。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/nfs/fs_context.c
+
+use crate::syscall::SystemError;
+
+use super::rpc::{RpcConfig, RpcProto};
+
+/// 默认的rsize与wsize
+const NFS_DEFAULT_IOSIZE: usize = 32 * 1024;
+/// rsize与wsize的范围
+const NFS_MIN_IOSIZE: usize = 1024;
+const NFS_MAX_IOSIZE: usize = 64 * 1024;
+/// 使用UDP时rsize与wsize的最大值，加上RPC的头部之后不超过以太网的MTU
+const NFS_MAX_IOSIZE_UDP: usize = 1024;
+
+/// NFS挂载选项
+#[derive(Debug, Clone)]
+pub struct NfsMountOptions {
+    pub proto: RpcProto,
+    /// NFS服务的端口，None表示通过portmap查询
+    pub port: Option<u16>,
+    /// MOUNT服务的端口，None表示通过portmap查询
+    pub mountport: Option<u16>,
+    pub rsize: usize,
+    pub wsize: usize,
+    /// 等待回复的时间(单位为0.1秒)
+    pub timeo: u64,
+    pub retrans: u32,
+    pub soft: bool,
+    /// 普通文件的属性缓存的最短与最长有效时间(秒)
+    pub acregmin: u64,
+    pub acregmax: u64,
+    /// 目录的属性缓存的最短与最长有效时间(秒)
+    pub acdirmin: u64,
+    pub acdirmax: u64,
+    /// 只读挂载
+    pub ro: bool,
+}
+
+impl Default for NfsMountOptions {
+    fn default() -> Self {
+        return Self {
+            proto: RpcProto::Tcp,
+            port: None,
+            mountport: None,
+            rsize: NFS_DEFAULT_IOSIZE,
+            wsize: NFS_DEFAULT_IOSIZE,
+            timeo: 600,
+            retrans: 2,
+            soft: false,
+            acregmin: 3,
+            acregmax: 60,
+            acdirmin: 30,
+            acdirmax: 60,
+            ro: false,
+        };
+    }
+}
+
+impl NfsMountOptions {
+    /// @brief 解析挂载选项
+    ///
+    /// @return Err(SystemError::EINVAL) 不支持的选项，或者选项的值不合法
+    /// @return Err(SystemError::EPROTONOSUPPORT) 指定了3之外的NFS版本
+    pub fn parse(s: &str) -> Result<Self, SystemError> {
+        let mut opts = Self::default();
+        let mut rsize = None;
+        let mut wsize = None;
+        let mut timeo = None;
+        let mut retrans = None;
+
+        for opt in s.split(',').filter(|opt| !opt.is_empty()) {
+            let (name, value) = opt.split_once('=').unwrap_or((opt, ""));
+            let num = || value.parse::<u64>().map_err(|_| SystemError::EINVAL);
+            match name {
+                "proto" => {
+                    opts.proto = match value {
+                        "tcp" => RpcProto::Tcp,
+                        "udp" => RpcProto::Udp,
+                        _ => return Err(SystemError::EINVAL),
+                    }
+                }
+                "tcp" => opts.proto = RpcProto::Tcp,
+                "udp" => opts.proto = RpcProto::Udp,
+                "port" => opts.port = Some(parse_port(value)?),
+                "mountport" => opts.mountport = Some(parse_port(value)?),
+                "rsize" => rsize = Some(num()? as usize),
+                "wsize" => wsize = Some(num()? as usize),
+                "timeo" => timeo = Some(num()?),
+                "retrans" => retrans = Some(num()? as u32),
+                "soft" => opts.soft = true,
+                "hard" => opts.soft = false,
+                "acregmin" => opts.acregmin = num()?,
+                "acregmax" => opts.acregmax = num()?,
+                "acdirmin" => opts.acdirmin = num()?,
+                "acdirmax" => opts.acdirmax = num()?,
+                "actimeo" => {
+                    let t = num()?;
+                    opts.acregmin = t;
+                    opts.acregmax = t;
+                    opts.acdirmin = t;
+                    opts.acdirmax = t;
+                }
+                "noac" => {
+                    opts.acregmin = 0;
+                    opts.acregmax = 0;
+                    opts.acdirmin = 0;
+                    opts.acdirmax = 0;
+                }
+                "ro" => opts.ro = true,
+                "rw" => opts.ro = false,
+                "vers" | "nfsvers" => {
+                    if value != "3" {
+                        return Err(SystemError::EPROTONOSUPPORT);
+                    }
+                }
+                "nolock" | "lock" | "intr" | "nointr" | "sec" | "addr" => {}
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+
+        // 与Linux一样，UDP的超时时间更短：UDP没有TCP的重传机制，丢失的请求只能靠RPC层重传
+        let (max_iosize, default_timeo, default_retrans) = match opts.proto {
+            RpcProto::Tcp => (NFS_MAX_IOSIZE, 600, 2),
+            RpcProto::Udp => (NFS_MAX_IOSIZE_UDP, 11, 3),
+        };
+        opts.rsize = clamp_iosize(rsize.unwrap_or(NFS_DEFAULT_IOSIZE), max_iosize);
+        opts.wsize = clamp_iosize(wsize.unwrap_or(NFS_DEFAULT_IOSIZE), max_iosize);
+        opts.timeo = timeo.unwrap_or(default_timeo).max(1);
+        opts.retrans = retrans.unwrap_or(default_retrans);
+        opts.acregmax = opts.acregmax.max(opts.acregmin);
+        opts.acdirmax = opts.acdirmax.max(opts.acdirmin);
+        return Ok(opts);
+    }
+
+    /// @brief RPC的超时与重传设置
+    pub fn rpc_config(&self) -> RpcConfig {
+        return RpcConfig {
+            proto: self.proto,
+            timeout_ms: self.timeo * 100,
+            retrans: self.retrans,
+            soft: self.soft,
+        };
+    }
+}
+
+fn parse_port(value: &str) -> Result<u16, SystemError> {
+    return value.parse::<u16>().map_err(|_| SystemError::EINVAL);
+}
+
+/// @brief 把rsize与wsize限制在合理的范围内，并向下对齐到1024字节
+fn clamp_iosize(size: usize, max: usize) -> usize {
+    return size.clamp(NFS_MIN_IOSIZE, max) & !(NFS_MIN_IOSIZE - 1);
+}
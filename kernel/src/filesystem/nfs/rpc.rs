@@ -0,0 +1,428 @@
+//! ONC RPC客户端
+//!
+//! NFS、MOUNT以及portmap协议都基于ONC RPC。使用TCP时，每条消息前有4字节的记录标记(record marking)；
+//! 使用UDP时，每个数据报就是一条消息，超时后需要重传。
+//!
+//! 同一个客户端可以一次发送多个请求，之后再按照xid把回复与请求对应起来(见[`RpcClient::call_batch`])，
+//! 这样大块的读写不需要逐个等待每个请求的回复。
+//!
+//! 参考 https://www.rfc-editor.org/rfc/rfc5531
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use alloc::{boxed::Box, vec::Vec};
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+
+use crate::{
+    kwarn,
+    libs::mutex::Mutex,
+    net::{
+        socket::{SocketOptions, TcpSocket, UdpSocket, SOCKET_WAITQUEUE, SOL_SOCKET},
+        syscall::PosixSocketOption,
+        Endpoint, ShutdownType, Socket,
+    },
+    syscall::SystemError,
+    time::{
+        hrtimer::{ktime_get, Ktime},
+        sleep::nanosleep,
+        TimeSpec, NSEC_PER_MSEC,
+    },
+};
+
+use super::xdr::{XdrDecoder, XdrEncoder};
+
+/// RPC协议的版本
+const RPC_VERSION: u32 = 2;
+/// 消息类型
+const RPC_CALL: u32 = 0;
+const RPC_REPLY: u32 = 1;
+/// 回复的状态
+const RPC_MSG_ACCEPTED: u32 = 0;
+const RPC_MSG_DENIED: u32 = 1;
+/// 请求被接受之后的处理结果
+const RPC_SUCCESS: u32 = 0;
+const RPC_PROG_UNAVAIL: u32 = 1;
+const RPC_PROG_MISMATCH: u32 = 2;
+const RPC_PROC_UNAVAIL: u32 = 3;
+const RPC_GARBAGE_ARGS: u32 = 4;
+/// 认证方式
+const RPC_AUTH_NONE: u32 = 0;
+const RPC_AUTH_UNIX: u32 = 1;
+/// 认证信息的最大长度
+const RPC_MAX_AUTH_LEN: usize = 400;
+/// AUTH_UNIX中的主机名
+const RPC_AUTH_MACHINE_NAME: &str = "dragonos";
+
+/// TCP记录标记中表示最后一个分片的位
+const RPC_LAST_FRAGMENT: u32 = 1 << 31;
+/// 回复的最大长度。读写的数据量由rsize与wsize限制，远小于这个值
+const RPC_MAX_RECORD: usize = 1024 * 1024;
+/// 每次从socket中接收的最大字节数
+const RPC_RECV_CHUNK: usize = 64 * 1024;
+
+/// portmap协议，用于查询其他RPC程序的端口
+const PMAP_PORT: u16 = 111;
+const PMAP_PROGRAM: u32 = 100000;
+const PMAP_VERSION: u32 = 2;
+const PMAPPROC_GETPORT: u32 = 3;
+/// portmap中的协议号
+const IPPROTO_TCP: u32 = 6;
+const IPPROTO_UDP: u32 = 17;
+
+/// RPC使用的传输层协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcProto {
+    Tcp,
+    Udp,
+}
+
+/// 超时与重传的设置
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    pub proto: RpcProto,
+    /// 等待回复的时间，超时之后重传请求
+    pub timeout_ms: u64,
+    /// 重传的次数，之后认为服务器没有响应
+    pub retrans: u32,
+    /// 服务器没有响应时返回ETIMEDOUT。否则打印警告并一直重试
+    pub soft: bool,
+}
+
+/// 与服务器之间的连接
+#[derive(Debug)]
+struct RpcTransport {
+    socket: Box<dyn Socket>,
+    /// TCP连接上收到的还没有组成完整记录的数据
+    stream: Vec<u8>,
+}
+
+/// @brief 调用某个服务器上的一个RPC程序的客户端
+#[derive(Debug)]
+pub struct RpcClient {
+    server: IpEndpoint,
+    program: u32,
+    version: u32,
+    config: RpcConfig,
+    transport: Mutex<Option<RpcTransport>>,
+    /// 下一个请求的xid
+    xid: AtomicU32,
+}
+
+impl RpcTransport {
+    /// @brief 连接到服务器
+    fn connect(server: IpEndpoint, config: &RpcConfig) -> Result<Self, SystemError> {
+        let mut socket: Box<dyn Socket> = match config.proto {
+            RpcProto::Tcp => Box::new(TcpSocket::new(SocketOptions::empty())),
+            RpcProto::Udp => Box::new(UdpSocket::new(SocketOptions::empty())),
+        };
+        socket.connect(Endpoint::Ip(Some(server)))?;
+
+        // 接收数据时最多等待一个超时时间，之后由调用者决定是否重传
+        let micros = config.timeout_ms * 1000;
+        let mut tv = [0u8; 16];
+        tv[..8].copy_from_slice(&((micros / 1000000) as i64).to_ne_bytes());
+        tv[8..].copy_from_slice(&((micros % 1000000) as i64).to_ne_bytes());
+        socket.setsockopt(
+            SOL_SOCKET as usize,
+            PosixSocketOption::SO_RCVTIMEO_NEW as usize,
+            &tv,
+        )?;
+
+        return Ok(Self {
+            socket,
+            stream: Vec::new(),
+        });
+    }
+
+    /// @brief 发送一条消息，使用TCP时加上记录标记
+    fn send(&mut self, msg: &[u8], proto: RpcProto, deadline: Ktime) -> Result<(), SystemError> {
+        if proto == RpcProto::Udp {
+            self.socket.write(msg, None)?;
+            return Ok(());
+        }
+
+        let mut record = Vec::with_capacity(msg.len() + 4);
+        record.extend_from_slice(&(RPC_LAST_FRAGMENT | msg.len() as u32).to_be_bytes());
+        record.extend_from_slice(msg);
+        let mut sent = 0;
+        while sent < record.len() {
+            match self.socket.write(&record[sent..], None) {
+                Ok(n) => sent += n,
+                // 发送缓冲区已满，等待对端确认之前的数据
+                Err(SystemError::ENOBUFS) => {
+                    if ktime_get() >= deadline {
+                        return Err(SystemError::ETIMEDOUT);
+                    }
+                    SOCKET_WAITQUEUE.sleep();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        return Ok(());
+    }
+
+    /// @brief 接收一条消息
+    ///
+    /// @return Err(SystemError::ETIMEDOUT) 在`deadline`之前没有收到完整的消息
+    fn recv(&mut self, proto: RpcProto, deadline: Ktime) -> Result<Vec<u8>, SystemError> {
+        let mut buf = vec![0u8; RPC_RECV_CHUNK];
+        loop {
+            if proto == RpcProto::Tcp {
+                if let Some(record) = self.take_record()? {
+                    return Ok(record);
+                }
+            }
+
+            match self.socket.read(&mut buf).0 {
+                Ok(n) => {
+                    if proto == RpcProto::Udp {
+                        buf.truncate(n);
+                        return Ok(buf);
+                    }
+                    self.stream.extend_from_slice(&buf[..n]);
+                }
+                Err(SystemError::EAGAIN_OR_EWOULDBLOCK) => {}
+                Err(e) => return Err(e),
+            }
+            if ktime_get() >= deadline {
+                return Err(SystemError::ETIMEDOUT);
+            }
+        }
+    }
+
+    /// @brief 从TCP连接上收到的数据中取出一条完整的记录
+    fn take_record(&mut self) -> Result<Option<Vec<u8>>, SystemError> {
+        let mut record = Vec::new();
+        let mut pos = 0;
+        loop {
+            let header = match self.stream.get(pos..pos + 4) {
+                Some(header) => u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+                None => return Ok(None),
+            };
+            let len = (header & !RPC_LAST_FRAGMENT) as usize;
+            if record.len() + len > RPC_MAX_RECORD {
+                // 无法再找到之后的记录的边界，只能断开连接
+                return Err(SystemError::EPROTO);
+            }
+            let fragment = match self.stream.get(pos + 4..pos + 4 + len) {
+                Some(fragment) => fragment,
+                None => return Ok(None),
+            };
+            record.extend_from_slice(fragment);
+            pos += 4 + len;
+            if header & RPC_LAST_FRAGMENT != 0 {
+                self.stream.drain(..pos);
+                return Ok(Some(record));
+            }
+        }
+    }
+}
+
+impl Drop for RpcTransport {
+    fn drop(&mut self) {
+        self.socket.shutdown(ShutdownType::ShutRdwr).ok();
+    }
+}
+
+impl RpcClient {
+    /// @brief 创建客户端并连接到服务器
+    ///
+    /// @param server 服务器上RPC程序的地址
+    /// @param program RPC程序号
+    /// @param version RPC程序的版本
+    pub fn new(
+        server: IpEndpoint,
+        program: u32,
+        version: u32,
+        config: &RpcConfig,
+    ) -> Result<Self, SystemError> {
+        let transport = RpcTransport::connect(server, config)?;
+        return Ok(Self {
+            server,
+            program,
+            version,
+            config: config.clone(),
+            transport: Mutex::new(Some(transport)),
+            // 使用不同的初始xid，使服务器不会把重新挂载之后的请求当作重复的请求
+            xid: AtomicU32::new(ktime_get() as u32),
+        });
+    }
+
+    /// @brief 调用一个过程
+    ///
+    /// @param procedure 过程号
+    /// @param args XDR编码的参数
+    ///
+    /// @return Ok(Vec<u8>) XDR编码的结果
+    pub fn call(&self, procedure: u32, args: Vec<u8>) -> Result<Vec<u8>, SystemError> {
+        let mut results = self.call_batch(vec![(procedure, args)])?;
+        return Ok(results.pop().unwrap());
+    }
+
+    /// @brief 一次发送多个请求，然后等待所有的回复
+    ///
+    /// 超时之后重传还没有收到回复的请求，使用TCP时还会重新建立连接
+    ///
+    /// @param calls 每个请求的过程号与XDR编码的参数
+    ///
+    /// @return Ok(Vec<Vec<u8>>) 按照请求的顺序排列的结果
+    /// @return Err(SystemError::ETIMEDOUT) 使用soft选项挂载时，服务器没有响应
+    pub fn call_batch(&self, calls: Vec<(u32, Vec<u8>)>) -> Result<Vec<Vec<u8>>, SystemError> {
+        let mut xids = Vec::with_capacity(calls.len());
+        let mut msgs = Vec::with_capacity(calls.len());
+        for (procedure, args) in calls {
+            let xid = self.xid.fetch_add(1, Ordering::SeqCst);
+            xids.push(xid);
+            msgs.push(self.encode_call(xid, procedure, &args));
+        }
+
+        let mut replies: Vec<Option<Vec<u8>>> = vec![None; msgs.len()];
+        let mut guard = self.transport.lock();
+        let mut tries = 0;
+        loop {
+            match self.exchange(&mut guard, &xids, &msgs, &mut replies) {
+                Ok(()) => break,
+                Err(SystemError::ETIMEDOUT) => {}
+                // 连接已经断开，重新连接之后重传
+                Err(e) if self.config.proto == RpcProto::Tcp => {
+                    match guard.take() {
+                        Some(_) => kwarn!("rpc: connection to {} lost: {:?}", self.server, e),
+                        // 重新连接失败，等待一个超时时间之后再尝试
+                        None => {
+                            let ms = self.config.timeout_ms;
+                            let sec = (ms / 1000) as i64;
+                            let nsec = ((ms % 1000) * NSEC_PER_MSEC as u64) as i64;
+                            nanosleep(TimeSpec::new(sec, nsec)).ok();
+                        }
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+
+            tries += 1;
+            if tries > self.config.retrans {
+                if self.config.soft {
+                    return Err(SystemError::ETIMEDOUT);
+                }
+                kwarn!("rpc: server {} not responding, still trying", self.server);
+                tries = 0;
+            }
+        }
+        drop(guard);
+
+        return replies
+            .into_iter()
+            .map(|reply| Self::decode_reply(&reply.unwrap()))
+            .collect();
+    }
+
+    /// @brief 发送还没有收到回复的请求，并接收回复，直到收到所有的回复或者超时
+    fn exchange(
+        &self,
+        transport: &mut Option<RpcTransport>,
+        xids: &[u32],
+        msgs: &[Vec<u8>],
+        replies: &mut [Option<Vec<u8>>],
+    ) -> Result<(), SystemError> {
+        if transport.is_none() {
+            *transport = Some(RpcTransport::connect(self.server, &self.config)?);
+        }
+        let transport = transport.as_mut().unwrap();
+        let proto = self.config.proto;
+        let deadline = ktime_get() + self.config.timeout_ms * NSEC_PER_MSEC as u64;
+
+        for (msg, reply) in msgs.iter().zip(replies.iter()) {
+            if reply.is_none() {
+                transport.send(msg, proto, deadline)?;
+            }
+        }
+
+        while replies.iter().any(|reply| reply.is_none()) {
+            let msg = transport.recv(proto, deadline)?;
+            let xid = match XdrDecoder::new(&msg).u32() {
+                Ok(xid) => xid,
+                Err(_) => continue,
+            };
+            // 忽略之前重传的请求的重复回复
+            if let Some(i) = xids.iter().position(|x| *x == xid) {
+                replies[i] = Some(msg);
+            }
+        }
+        return Ok(());
+    }
+
+    /// @brief 构造一条请求消息，使用AUTH_UNIX认证(root用户)
+    fn encode_call(&self, xid: u32, procedure: u32, args: &[u8]) -> Vec<u8> {
+        let mut cred = XdrEncoder::new();
+        cred.u32(0).str(RPC_AUTH_MACHINE_NAME).u32(0).u32(0).u32(0);
+        let cred = cred.finish();
+
+        let mut msg = XdrEncoder::new();
+        msg.u32(xid)
+            .u32(RPC_CALL)
+            .u32(RPC_VERSION)
+            .u32(self.program)
+            .u32(self.version)
+            .u32(procedure)
+            .u32(RPC_AUTH_UNIX)
+            .opaque(&cred)
+            .u32(RPC_AUTH_NONE)
+            .u32(0);
+        let mut msg = msg.finish();
+        msg.extend_from_slice(args);
+        return msg;
+    }
+
+    /// @brief 检查回复的状态，返回其中的结果
+    fn decode_reply(msg: &[u8]) -> Result<Vec<u8>, SystemError> {
+        let mut dec = XdrDecoder::new(msg);
+        let _xid = dec.u32()?;
+        if dec.u32()? != RPC_REPLY {
+            return Err(SystemError::EPROTO);
+        }
+        match dec.u32()? {
+            RPC_MSG_ACCEPTED => {}
+            // 认证失败或者RPC版本不匹配
+            RPC_MSG_DENIED => return Err(SystemError::EACCES),
+            _ => return Err(SystemError::EPROTO),
+        }
+
+        // 服务器的认证信息
+        let _flavor = dec.u32()?;
+        dec.opaque(RPC_MAX_AUTH_LEN)?;
+        match dec.u32()? {
+            RPC_SUCCESS => return Ok(dec.remaining().to_vec()),
+            RPC_PROG_UNAVAIL | RPC_PROG_MISMATCH => return Err(SystemError::EPROTONOSUPPORT),
+            RPC_PROC_UNAVAIL => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+            RPC_GARBAGE_ARGS => return Err(SystemError::EINVAL),
+            _ => return Err(SystemError::EIO),
+        }
+    }
+}
+
+/// @brief 通过服务器上的portmap查询RPC程序的端口
+///
+/// @return Ok(u16) RPC程序的端口
+/// @return Err(SystemError::EPROTONOSUPPORT) 服务器上没有这个RPC程序
+pub fn pmap_getport(
+    server: Ipv4Address,
+    program: u32,
+    version: u32,
+    config: &RpcConfig,
+) -> Result<u16, SystemError> {
+    let endpoint = IpEndpoint::new(IpAddress::Ipv4(server), PMAP_PORT);
+    let client = RpcClient::new(endpoint, PMAP_PROGRAM, PMAP_VERSION, config)?;
+    let proto = match config.proto {
+        RpcProto::Tcp => IPPROTO_TCP,
+        RpcProto::Udp => IPPROTO_UDP,
+    };
+
+    let mut args = XdrEncoder::new();
+    args.u32(program).u32(version).u32(proto).u32(0);
+    let result = client.call(PMAPPROC_GETPORT, args.finish())?;
+    let port = XdrDecoder::new(&result).u32()?;
+    if port == 0 || port > u16::MAX as u32 {
+        return Err(SystemError::EPROTONOSUPPORT);
+    }
+    return Ok(port as u16);
+}
@@ -0,0 +1,123 @@
+//! XDR(External Data Representation)编码
+//!
+//! 所有数据都以4字节为单位，整数为大端字节序，变长的数据先写入4字节的长度，再写入数据，最后补齐到4字节。
+//!
+//! 参考 https://www.rfc-editor.org/rfc/rfc4506
+
+use alloc::{string::String, vec::Vec};
+
+use crate::syscall::SystemError;
+
+/// 把`len`向上对齐到4字节
+fn xdr_align(len: usize) -> usize {
+    return (len + 3) & !3;
+}
+
+/// 构造XDR编码的数据
+#[derive(Debug, Default)]
+pub struct XdrEncoder {
+    buf: Vec<u8>,
+}
+
+impl XdrEncoder {
+    pub fn new() -> Self {
+        return Self { buf: Vec::new() };
+    }
+
+    pub fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+        return self;
+    }
+
+    pub fn u64(&mut self, v: u64) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+        return self;
+    }
+
+    pub fn bool(&mut self, v: bool) -> &mut Self {
+        return self.u32(v as u32);
+    }
+
+    /// 定长的数据，补齐到4字节
+    pub fn fixed(&mut self, data: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(data);
+        self.buf.resize(xdr_align(self.buf.len()), 0);
+        return self;
+    }
+
+    /// 变长的数据：长度加上数据
+    pub fn opaque(&mut self, data: &[u8]) -> &mut Self {
+        self.u32(data.len() as u32);
+        return self.fixed(data);
+    }
+
+    pub fn str(&mut self, s: &str) -> &mut Self {
+        return self.opaque(s.as_bytes());
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        return self.buf;
+    }
+}
+
+/// 解析XDR编码的数据，数据不完整时返回EIO
+#[derive(Debug)]
+pub struct XdrDecoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> XdrDecoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        return Self { buf, pos: 0 };
+    }
+
+    /// 读取`len`字节，并跳过补齐的字节
+    pub fn fixed(&mut self, len: usize) -> Result<&'a [u8], SystemError> {
+        let end = self.pos.checked_add(len).ok_or(SystemError::EIO)?;
+        if xdr_align(end) > self.buf.len() {
+            return Err(SystemError::EIO);
+        }
+        let data = &self.buf[self.pos..end];
+        self.pos = xdr_align(end);
+        return Ok(data);
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SystemError> {
+        let data = self.fixed(4)?;
+        return Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]));
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SystemError> {
+        let hi = self.u32()? as u64;
+        let lo = self.u32()? as u64;
+        return Ok((hi << 32) | lo);
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SystemError> {
+        return Ok(self.u32()? != 0);
+    }
+
+    /// 变长的数据
+    ///
+    /// ## 参数
+    ///
+    /// - `max`：数据的最大长度，超过时说明数据已损坏
+    pub fn opaque(&mut self, max: usize) -> Result<&'a [u8], SystemError> {
+        let len = self.u32()? as usize;
+        if len > max {
+            return Err(SystemError::EIO);
+        }
+        return self.fixed(len);
+    }
+
+    pub fn str(&mut self, max: usize) -> Result<String, SystemError> {
+        let data = self.opaque(max)?;
+        return Ok(String::from_utf8_lossy(data).into_owned());
+    }
+
+    /// 还没有读取的数据
+    pub fn remaining(&self) -> &'a [u8] {
+        return &self.buf[self.pos..];
+    }
+}
@@ -0,0 +1,390 @@
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use crate::{libs::byte_cursor::VecCursor, syscall::SystemError};
+
+use super::protocol::{
+    read_str, P9Attr, P9DirEntry, P9SetAttr, P9Writer, Qid, P9_GETATTR_BASIC, P9_HEADER_LEN,
+    P9_MAXWELEM, P9_NOFID, P9_NOTAG, P9_PROTO_2000L, P9_RLERROR, P9_TATTACH, P9_TCLUNK, P9_TFSYNC,
+    P9_TGETATTR, P9_TLCREATE, P9_TLINK, P9_TLOPEN, P9_TMKDIR, P9_TREAD, P9_TREADDIR, P9_TREADLINK,
+    P9_TRENAMEAT, P9_TSETATTR, P9_TUNLINKAT, P9_TVERSION, P9_TWALK, P9_TWRITE,
+};
+
+/// 普通请求使用的标签。传输层同一时间只处理一个请求，因此所有请求使用同一个标签
+const P9_TAG: u16 = 1;
+
+/// Tread/Twrite/Treaddir消息头部的长度，决定一次最多能传输的数据量
+const P9_IOHDR_LEN: usize = 24;
+
+/// @brief 把Linux的errno转换为SystemError
+///
+/// Rlerror中的错误码是主机(Linux)上的errno。Linux没有使用41，因此大于41的错误码比SystemError中的大1
+fn linux_errno_to_system_error(ecode: u32) -> SystemError {
+    let errno = if ecode > 41 { ecode - 1 } else { ecode };
+    return SystemError::from_posix_errno(-(errno as i32)).unwrap_or(SystemError::EIO);
+}
+
+/// @brief 传输9P消息的设备(例如virtio-9p)
+pub trait P9Transport: Debug + Send + Sync {
+    /// @brief 设备的挂载标签
+    fn tag(&self) -> &str;
+
+    /// @brief 设备能够传输的单条消息的最大长度
+    fn msize(&self) -> usize;
+
+    /// @brief 发送一条请求，并等待回复
+    ///
+    /// @param req 完整的请求消息
+    /// @param resp 用于存放完整的回复消息
+    ///
+    /// @return Err(SystemError::ETIMEDOUT) 主机没有在规定的时间内回复
+    fn rpc(&self, req: &[u8], resp: &mut Vec<u8>) -> Result<(), SystemError>;
+}
+
+/// @brief 9P2000.L客户端
+///
+/// 对每种消息提供一个方法。fid由客户端分配，不再使用的fid需要调用者通过[`P9Client::clunk`]释放
+#[derive(Debug)]
+pub struct P9Client {
+    transport: Arc<dyn P9Transport>,
+    /// 与服务器协商得到的单条消息的最大长度
+    msize: usize,
+    /// 下一个要分配的fid
+    next_fid: AtomicU32,
+}
+
+impl P9Client {
+    /// @brief 与服务器协商协议版本与消息的最大长度
+    ///
+    /// @return Err(SystemError::EPROTONOSUPPORT) 服务器不支持9P2000.L
+    pub fn new(transport: Arc<dyn P9Transport>) -> Result<Self, SystemError> {
+        let msize = transport.msize();
+        let mut client = Self {
+            transport,
+            msize,
+            next_fid: AtomicU32::new(0),
+        };
+
+        let req = P9Writer::new(P9_TVERSION, P9_NOTAG)
+            .u32(msize as u32)
+            .str(P9_PROTO_2000L)
+            .finish();
+        let (server_msize, version) = client.call_with(req, P9_TVERSION, |cursor| {
+            let msize = cursor.read_u32()? as usize;
+            return Ok((msize, read_str(cursor)?));
+        })?;
+        if version != P9_PROTO_2000L {
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+        client.msize = core::cmp::min(msize, server_msize);
+        if client.msize <= P9_IOHDR_LEN {
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+        return Ok(client);
+    }
+
+    /// @brief 一次Tread/Twrite/Treaddir最多能传输的字节数
+    pub fn iounit(&self) -> usize {
+        return self.msize - P9_IOHDR_LEN;
+    }
+
+    /// @brief 分配一个新的fid
+    fn alloc_fid(&self) -> u32 {
+        return self.next_fid.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// @brief 发送请求，并检查回复的类型
+    ///
+    /// @param req 完整的请求消息
+    /// @param ty 请求的类型，回复的类型应该是`ty + 1`
+    ///
+    /// @return Ok(VecCursor) 回复的内容，已经跳过了消息头部
+    /// @return Err(SystemError) 服务器返回的错误(Rlerror)，或者回复的格式错误(EIO)
+    fn call(&self, req: Vec<u8>, ty: u8) -> Result<VecCursor, SystemError> {
+        let mut resp = Vec::new();
+        self.transport.rpc(&req, &mut resp)?;
+        if resp.len() < P9_HEADER_LEN {
+            return Err(SystemError::EIO);
+        }
+        let size = u32::from_le_bytes([resp[0], resp[1], resp[2], resp[3]]) as usize;
+        if size < P9_HEADER_LEN || size > resp.len() {
+            return Err(SystemError::EIO);
+        }
+        resp.truncate(size);
+
+        let rtype = resp[4];
+        let mut cursor = VecCursor::new(resp);
+        cursor.skip(P9_HEADER_LEN)?;
+        if rtype == P9_RLERROR {
+            let ecode = cursor.read_u32().map_err(|_| SystemError::EIO)?;
+            return Err(linux_errno_to_system_error(ecode));
+        }
+        if rtype != ty + 1 {
+            return Err(SystemError::EIO);
+        }
+        return Ok(cursor);
+    }
+
+    /// @brief 与[`P9Client::call`]相同，但回复的内容不完整时返回EIO
+    fn call_with<R>(
+        &self,
+        req: Vec<u8>,
+        ty: u8,
+        f: impl FnOnce(&mut VecCursor) -> Result<R, SystemError>,
+    ) -> Result<R, SystemError> {
+        let mut cursor = self.call(req, ty)?;
+        return f(&mut cursor).map_err(|_| SystemError::EIO);
+    }
+
+    /// @brief 连接到服务器导出的文件树
+    ///
+    /// @param aname 要挂载的文件树，为空时使用服务器的默认值
+    ///
+    /// @return Ok((fid, qid)) 代表文件树根目录的fid
+    pub fn attach(&self, aname: &str) -> Result<(u32, Qid), SystemError> {
+        let fid = self.alloc_fid();
+        let req = P9Writer::new(P9_TATTACH, P9_TAG)
+            .u32(fid)
+            .u32(P9_NOFID)
+            .str("root")
+            .str(aname)
+            .u32(0)
+            .finish();
+        let qid = self.call_with(req, P9_TATTACH, Qid::decode)?;
+        return Ok((fid, qid));
+    }
+
+    /// @brief 从`fid`出发，逐个走过`names`中的路径分量，得到一个新的fid
+    ///
+    /// @param names 路径分量，为空时复制`fid`
+    ///
+    /// @return Ok((newfid, qid)) 新的fid，以及它指向的文件的qid
+    /// @return Err(SystemError::ENOENT) 路径中的某个分量不存在
+    pub fn walk(&self, fid: u32, names: &[&str]) -> Result<(u32, Qid), SystemError> {
+        if names.len() > P9_MAXWELEM {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+
+        let newfid = self.alloc_fid();
+        let mut req = P9Writer::new(P9_TWALK, P9_TAG)
+            .u32(fid)
+            .u32(newfid)
+            .u16(names.len() as u16);
+        for name in names {
+            req = req.str(name);
+        }
+        let qids = self.call_with(req.finish(), P9_TWALK, |cursor| {
+            let n = cursor.read_u16()? as usize;
+            let mut qids = Vec::with_capacity(n);
+            for _ in 0..n {
+                qids.push(Qid::decode(cursor)?);
+            }
+            return Ok(qids);
+        })?;
+
+        // 只走过了一部分分量时，服务器不会创建newfid
+        if qids.len() != names.len() {
+            return Err(SystemError::ENOENT);
+        }
+        let qid = match qids.last() {
+            Some(qid) => *qid,
+            None => self.getattr(newfid)?.qid,
+        };
+        return Ok((newfid, qid));
+    }
+
+    /// @brief 释放fid。之后服务器可以回收与它关联的资源
+    pub fn clunk(&self, fid: u32) -> Result<(), SystemError> {
+        let req = P9Writer::new(P9_TCLUNK, P9_TAG).u32(fid).finish();
+        self.call(req, P9_TCLUNK)?;
+        return Ok(());
+    }
+
+    /// @brief 打开fid指向的文件，之后可以用它读写
+    ///
+    /// @param flags 打开标志，见[`super::protocol::P9_DOTL_RDONLY`]等
+    pub fn lopen(&self, fid: u32, flags: u32) -> Result<Qid, SystemError> {
+        let req = P9Writer::new(P9_TLOPEN, P9_TAG)
+            .u32(fid)
+            .u32(flags)
+            .finish();
+        return self.call_with(req, P9_TLOPEN, Qid::decode);
+    }
+
+    /// @brief 在`fid`指向的目录下创建并打开普通文件。之后`fid`指向新的文件
+    pub fn lcreate(
+        &self,
+        fid: u32,
+        name: &str,
+        flags: u32,
+        mode: u32,
+        gid: u32,
+    ) -> Result<Qid, SystemError> {
+        let req = P9Writer::new(P9_TLCREATE, P9_TAG)
+            .u32(fid)
+            .str(name)
+            .u32(flags)
+            .u32(mode)
+            .u32(gid)
+            .finish();
+        return self.call_with(req, P9_TLCREATE, Qid::decode);
+    }
+
+    /// @brief 从已打开的fid中读取数据，一次最多读取[`P9Client::iounit`]字节
+    ///
+    /// @return Ok(usize) 读取的字节数，为0表示已经到达文件末尾
+    pub fn read(&self, fid: u32, offset: u64, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let count = core::cmp::min(buf.len(), self.iounit());
+        let req = P9Writer::new(P9_TREAD, P9_TAG)
+            .u32(fid)
+            .u64(offset)
+            .u32(count as u32)
+            .finish();
+        return self.call_with(req, P9_TREAD, |cursor| {
+            let n = cursor.read_u32()? as usize;
+            if n > count {
+                return Err(SystemError::EIO);
+            }
+            cursor.read_exact(&mut buf[..n])?;
+            return Ok(n);
+        });
+    }
+
+    /// @brief 向已打开的fid中写入数据，一次最多写入[`P9Client::iounit`]字节
+    ///
+    /// @return Ok(usize) 写入的字节数
+    pub fn write(&self, fid: u32, offset: u64, buf: &[u8]) -> Result<usize, SystemError> {
+        let count = core::cmp::min(buf.len(), self.iounit());
+        let req = P9Writer::new(P9_TWRITE, P9_TAG)
+            .u32(fid)
+            .u64(offset)
+            .u32(count as u32)
+            .bytes(&buf[..count])
+            .finish();
+        return self.call_with(req, P9_TWRITE, |cursor| {
+            return Ok(cursor.read_u32()? as usize);
+        });
+    }
+
+    /// @brief 获取fid指向的文件的属性
+    pub fn getattr(&self, fid: u32) -> Result<P9Attr, SystemError> {
+        let req = P9Writer::new(P9_TGETATTR, P9_TAG)
+            .u32(fid)
+            .u64(P9_GETATTR_BASIC)
+            .finish();
+        return self.call_with(req, P9_TGETATTR, P9Attr::decode);
+    }
+
+    /// @brief 修改fid指向的文件的属性
+    pub fn setattr(&self, fid: u32, attr: &P9SetAttr) -> Result<(), SystemError> {
+        let req = P9Writer::new(P9_TSETATTR, P9_TAG)
+            .u32(fid)
+            .u32(attr.valid.bits())
+            .u32(attr.mode)
+            .u32(attr.uid)
+            .u32(attr.gid)
+            .u64(attr.size)
+            .u64(attr.atime.tv_sec as u64)
+            .u64(attr.atime.tv_nsec as u64)
+            .u64(attr.mtime.tv_sec as u64)
+            .u64(attr.mtime.tv_nsec as u64)
+            .finish();
+        self.call(req, P9_TSETATTR)?;
+        return Ok(());
+    }
+
+    /// @brief 读取已打开的目录中的目录项
+    ///
+    /// @param offset 从哪个目录项开始读取：第一次为0，之后为上一次读取到的最后一个目录项的`offset`
+    ///
+    /// @return Ok(Vec<P9DirEntry>) 目录项，为空表示已经读取完所有目录项
+    pub fn readdir(&self, fid: u32, offset: u64) -> Result<Vec<P9DirEntry>, SystemError> {
+        let req = P9Writer::new(P9_TREADDIR, P9_TAG)
+            .u32(fid)
+            .u64(offset)
+            .u32(self.iounit() as u32)
+            .finish();
+        return self.call_with(req, P9_TREADDIR, |cursor| {
+            let count = cursor.read_u32()? as usize;
+            if count > cursor.remaining() {
+                return Err(SystemError::EIO);
+            }
+            let end = cursor.pos() + count;
+            let mut entries = Vec::new();
+            while cursor.pos() < end {
+                entries.push(P9DirEntry::decode(cursor)?);
+            }
+            return Ok(entries);
+        });
+    }
+
+    /// @brief 在`dfid`指向的目录下创建目录
+    pub fn mkdir(&self, dfid: u32, name: &str, mode: u32, gid: u32) -> Result<Qid, SystemError> {
+        let req = P9Writer::new(P9_TMKDIR, P9_TAG)
+            .u32(dfid)
+            .str(name)
+            .u32(mode)
+            .u32(gid)
+            .finish();
+        return self.call_with(req, P9_TMKDIR, Qid::decode);
+    }
+
+    /// @brief 在`dfid`指向的目录下创建指向`fid`的硬链接
+    pub fn link(&self, dfid: u32, fid: u32, name: &str) -> Result<(), SystemError> {
+        let req = P9Writer::new(P9_TLINK, P9_TAG)
+            .u32(dfid)
+            .u32(fid)
+            .str(name)
+            .finish();
+        self.call(req, P9_TLINK)?;
+        return Ok(());
+    }
+
+    /// @brief 删除`dfid`指向的目录下的文件或目录
+    ///
+    /// @param flags 删除目录时为[`super::protocol::P9_AT_REMOVEDIR`]，否则为0
+    pub fn unlinkat(&self, dfid: u32, name: &str, flags: u32) -> Result<(), SystemError> {
+        let req = P9Writer::new(P9_TUNLINKAT, P9_TAG)
+            .u32(dfid)
+            .str(name)
+            .u32(flags)
+            .finish();
+        self.call(req, P9_TUNLINKAT)?;
+        return Ok(());
+    }
+
+    /// @brief 把`old_dfid`目录下的`old_name`移动到`new_dfid`目录下，并重命名为`new_name`
+    pub fn renameat(
+        &self,
+        old_dfid: u32,
+        old_name: &str,
+        new_dfid: u32,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let req = P9Writer::new(P9_TRENAMEAT, P9_TAG)
+            .u32(old_dfid)
+            .str(old_name)
+            .u32(new_dfid)
+            .str(new_name)
+            .finish();
+        self.call(req, P9_TRENAMEAT)?;
+        return Ok(());
+    }
+
+    /// @brief 读取符号链接的目标
+    pub fn readlink(&self, fid: u32) -> Result<String, SystemError> {
+        let req = P9Writer::new(P9_TREADLINK, P9_TAG).u32(fid).finish();
+        return self.call_with(req, P9_TREADLINK, read_str);
+    }
+
+    /// @brief 把已打开的fid的数据写回服务器上的存储设备
+    pub fn fsync(&self, fid: u32) -> Result<(), SystemError> {
+        let req = P9Writer::new(P9_TFSYNC, P9_TAG).u32(fid).u32(0).finish();
+        self.call(req, P9_TFSYNC)?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,629 @@
+use core::any::Any;
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    filesystem::vfs::{
+        file::{FileMode, FilePrivateData},
+        syscall::ModeType,
+        DirEntry, FileSystem, FileType, FsInfo, IndexNode, InodeId, Metadata, PollStatus,
+        PollTable,
+    },
+    kinfo,
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    syscall::SystemError,
+};
+
+use super::{
+    client::{P9Client, P9Transport},
+    protocol::{
+        P9Attr, P9DirEntry, P9SetAttr, P9SetattrValid, Qid, P9_AT_REMOVEDIR, P9_DOTL_DIRECTORY,
+        P9_DOTL_RDONLY, P9_DOTL_WRONLY, P9_DT_BLK, P9_DT_CHR, P9_DT_DIR, P9_DT_FIFO, P9_DT_LNK,
+        P9_DT_SOCK,
+    },
+};
+
+/// 文件名的最大长度
+const P9_MAX_NAMELEN: usize = 255;
+
+/// @brief 9P2000.L文件系统，把主机上的目录共享给DragonOS
+///
+/// 文件的内容与属性都保存在主机上，每次访问都向主机发送请求，不在本地缓存，
+/// 因此主机上对共享目录的修改可以立即看到
+#[derive(Debug)]
+pub struct P9FileSystem {
+    client: P9Client,
+    /// 文件系统的根inode
+    root_inode: Arc<LockedP9Inode>,
+}
+
+/// 9P文件系统的Inode
+#[derive(Debug)]
+pub struct LockedP9Inode(SpinLock<P9Inode>);
+
+#[derive(Debug)]
+pub struct P9Inode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedP9Inode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedP9Inode>,
+    /// 指向这个文件的fid，用于walk、getattr等不需要打开文件的操作
+    fid: u32,
+    /// 以只读方式打开的fid，第一次读取时打开
+    read_fid: Option<u32>,
+    /// 以只写方式打开的fid，第一次写入时打开
+    write_fid: Option<u32>,
+    /// 文件在服务器上的标识，qid.path用作inode号
+    qid: Qid,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<P9FileSystem>,
+}
+
+impl FileSystem for P9FileSystem {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: P9_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl P9FileSystem {
+    /// @brief 通过传输设备连接到主机，挂载主机导出的目录
+    ///
+    /// @param transport 传输9P消息的设备
+    ///
+    /// @return Err(SystemError::EPROTONOSUPPORT) 主机不支持9P2000.L
+    /// @return Err(SystemError::ETIMEDOUT) 主机没有回复
+    pub fn new(transport: Arc<dyn P9Transport>) -> Result<Arc<P9FileSystem>, SystemError> {
+        let tag = String::from(transport.tag());
+        let client = P9Client::new(transport)?;
+        let (fid, qid) = client.attach("")?;
+        if qid.ty & Qid::QTDIR == 0 {
+            client.clunk(fid).ok();
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let result: Arc<P9FileSystem> = Arc::new(P9FileSystem {
+            client,
+            root_inode: Arc::new(LockedP9Inode(SpinLock::new(P9Inode {
+                parent: Weak::default(),
+                self_ref: Weak::default(),
+                fid,
+                read_fid: None,
+                write_fid: None,
+                qid,
+                fs: Weak::default(),
+            }))),
+        });
+
+        // 对root inode加锁，并继续完成初始化工作
+        let mut root_guard: SpinLockGuard<P9Inode> = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        root_guard.fs = Arc::downgrade(&result);
+        drop(root_guard);
+
+        kinfo!("9p: mounted '{}', iounit: {}", tag, result.client.iounit());
+        return Ok(result);
+    }
+}
+
+impl P9Inode {
+    fn file_type(&self) -> FileType {
+        if self.qid.ty & Qid::QTDIR != 0 {
+            return FileType::Dir;
+        } else if self.qid.ty & Qid::QTSYMLINK != 0 {
+            return FileType::SymLink;
+        }
+        return FileType::File;
+    }
+}
+
+impl Drop for P9Inode {
+    fn drop(&mut self) {
+        // 文件系统已经被卸载时，服务器会在设备重置时回收所有fid
+        if let Some(fs) = self.fs.upgrade() {
+            for fid in [Some(self.fid), self.read_fid, self.write_fid]
+                .into_iter()
+                .flatten()
+            {
+                fs.client.clunk(fid).ok();
+            }
+        }
+    }
+}
+
+impl LockedP9Inode {
+    fn new(
+        fs: &Arc<P9FileSystem>,
+        parent: Weak<LockedP9Inode>,
+        fid: u32,
+        qid: Qid,
+    ) -> Arc<LockedP9Inode> {
+        let inode = Arc::new(LockedP9Inode(SpinLock::new(P9Inode {
+            parent,
+            self_ref: Weak::default(),
+            fid,
+            read_fid: None,
+            write_fid: None,
+            qid,
+            fs: Arc::downgrade(fs),
+        })));
+        inode.0.lock().self_ref = Arc::downgrade(&inode);
+        return inode;
+    }
+
+    /// @brief 获取文件系统对象与当前inode的fid。向服务器发送请求时不持有锁
+    fn fs_and_fid(&self) -> (Arc<P9FileSystem>, u32) {
+        let guard: SpinLockGuard<P9Inode> = self.0.lock();
+        return (guard.fs.upgrade().unwrap(), guard.fid);
+    }
+
+    /// @brief 获取已打开的fid，还没有打开时打开它
+    ///
+    /// @param write 为true时获取只写的fid，否则获取只读的fid
+    fn opened_fid(&self, fs: &Arc<P9FileSystem>, write: bool) -> Result<u32, SystemError> {
+        let fid = {
+            let guard: SpinLockGuard<P9Inode> = self.0.lock();
+            let opened = if write {
+                guard.write_fid
+            } else {
+                guard.read_fid
+            };
+            if let Some(opened) = opened {
+                return Ok(opened);
+            }
+            guard.fid
+        };
+
+        let (newfid, _) = fs.client.walk(fid, &[])?;
+        let flags = if write {
+            P9_DOTL_WRONLY
+        } else {
+            P9_DOTL_RDONLY
+        };
+        if let Err(e) = fs.client.lopen(newfid, flags) {
+            fs.client.clunk(newfid).ok();
+            return Err(e);
+        }
+
+        let mut guard: SpinLockGuard<P9Inode> = self.0.lock();
+        let slot = if write {
+            &mut guard.write_fid
+        } else {
+            &mut guard.read_fid
+        };
+        let opened = *slot.get_or_insert(newfid);
+        drop(guard);
+        // 其他进程已经打开了，使用它打开的fid
+        if opened != newfid {
+            fs.client.clunk(newfid).ok();
+        }
+        return Ok(opened);
+    }
+
+    /// @brief 读取目录中的所有目录项，不包括"."与".."
+    fn read_entries(&self) -> Result<Vec<P9DirEntry>, SystemError> {
+        let (fs, fid) = self.fs_and_fid();
+        let (dirfid, _) = fs.client.walk(fid, &[])?;
+        let result = Self::read_entries_from(&fs, dirfid);
+        fs.client.clunk(dirfid).ok();
+        return result;
+    }
+
+    fn read_entries_from(
+        fs: &Arc<P9FileSystem>,
+        dirfid: u32,
+    ) -> Result<Vec<P9DirEntry>, SystemError> {
+        fs.client
+            .lopen(dirfid, P9_DOTL_RDONLY | P9_DOTL_DIRECTORY)?;
+
+        let mut entries: Vec<P9DirEntry> = Vec::new();
+        let mut offset = 0;
+        loop {
+            let batch = fs.client.readdir(dirfid, offset)?;
+            match batch.last() {
+                Some(last) => offset = last.offset,
+                None => break,
+            }
+            entries.extend(
+                batch
+                    .into_iter()
+                    .filter(|entry| entry.name != "." && entry.name != ".."),
+            );
+        }
+        return Ok(entries);
+    }
+
+    /// @brief 检查另一个inode是否属于同一个9P文件系统
+    fn same_fs<'a>(&self, other: &'a Arc<dyn IndexNode>) -> Result<&'a LockedP9Inode, SystemError> {
+        let other: &LockedP9Inode = other
+            .downcast_ref::<LockedP9Inode>()
+            .ok_or(SystemError::EXDEV)?;
+        // 两个inode可能是同一个，因此不能同时持有它们的锁
+        let fs = self.0.lock().fs.clone();
+        if !Weak::ptr_eq(&fs, &other.0.lock().fs) {
+            return Err(SystemError::EXDEV);
+        }
+        return Ok(other);
+    }
+}
+
+/// @brief 把Rgetattr中的文件属性转换为元数据
+fn attr_to_metadata(attr: &P9Attr) -> Metadata {
+    let mode = ModeType::from_bits_truncate(attr.mode);
+    let file_type = match mode & ModeType::S_IFMT {
+        ModeType::S_IFDIR => FileType::Dir,
+        ModeType::S_IFLNK => FileType::SymLink,
+        ModeType::S_IFCHR => FileType::CharDevice,
+        ModeType::S_IFBLK => FileType::BlockDevice,
+        ModeType::S_IFIFO => FileType::Pipe,
+        ModeType::S_IFSOCK => FileType::Socket,
+        _ => FileType::File,
+    };
+    return Metadata {
+        dev_id: 0,
+        inode_id: InodeId::new(attr.qid.path as usize),
+        size: attr.size as i64,
+        blk_size: attr.blksize as usize,
+        blocks: attr.blocks as usize,
+        atime: attr.atime,
+        mtime: attr.mtime,
+        ctime: attr.ctime,
+        file_type,
+        mode,
+        nlinks: attr.nlink as usize,
+        uid: attr.uid as usize,
+        gid: attr.gid as usize,
+        raw_dev: attr.rdev as usize,
+    };
+}
+
+/// @brief 把Rreaddir中目录项的类型转换为文件类型
+fn dirent_file_type(ty: u8) -> FileType {
+    match ty {
+        P9_DT_DIR => return FileType::Dir,
+        P9_DT_LNK => return FileType::SymLink,
+        P9_DT_CHR => return FileType::CharDevice,
+        P9_DT_BLK => return FileType::BlockDevice,
+        P9_DT_FIFO => return FileType::Pipe,
+        P9_DT_SOCK => return FileType::Socket,
+        _ => return FileType::File,
+    }
+}
+
+impl IndexNode for LockedP9Inode {
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let file_type = self.0.lock().file_type();
+        let (fs, fid) = self.fs_and_fid();
+        match file_type {
+            FileType::Dir => return Err(SystemError::EISDIR),
+            FileType::SymLink => {
+                // 符号链接的内容为它的目标
+                let target = fs.client.readlink(fid)?;
+                let target = target.as_bytes();
+                if offset >= target.len() {
+                    return Ok(0);
+                }
+                let n = core::cmp::min(len, target.len() - offset);
+                buf[..n].copy_from_slice(&target[offset..offset + n]);
+                return Ok(n);
+            }
+            _ => {}
+        }
+
+        let fid = self.opened_fid(&fs, false)?;
+        let buf = &mut buf[0..len];
+        let mut done = 0;
+        while done < len {
+            let n = fs
+                .client
+                .read(fid, (offset + done) as u64, &mut buf[done..])?;
+            if n == 0 {
+                break;
+            }
+            done += n;
+        }
+        return Ok(done);
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if self.0.lock().file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, _) = self.fs_and_fid();
+        let fid = self.opened_fid(&fs, true)?;
+
+        let buf = &buf[0..len];
+        let mut done = 0;
+        while done < len {
+            let n = fs.client.write(fid, (offset + done) as u64, &buf[done..])?;
+            if n == 0 {
+                return Err(SystemError::EIO);
+            }
+            done += n;
+        }
+        return Ok(done);
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        if self.0.lock().file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        return self;
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let (fs, fid) = self.fs_and_fid();
+        return Ok(attr_to_metadata(&fs.client.getattr(fid)?));
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let (fs, fid) = self.fs_and_fid();
+        let current = fs.client.getattr(fid)?;
+
+        // 只修改发生变化的属性，避免主机拒绝没有必要的chown
+        let mut attr = P9SetAttr {
+            mode: (metadata.mode & !ModeType::S_IFMT).bits(),
+            uid: metadata.uid as u32,
+            gid: metadata.gid as u32,
+            atime: metadata.atime,
+            mtime: metadata.mtime,
+            ..Default::default()
+        };
+        if attr.mode != current.mode & !ModeType::S_IFMT.bits() {
+            attr.valid |= P9SetattrValid::MODE;
+        }
+        if attr.uid != current.uid {
+            attr.valid |= P9SetattrValid::UID;
+        }
+        if attr.gid != current.gid {
+            attr.valid |= P9SetattrValid::GID;
+        }
+        if attr.atime != current.atime {
+            attr.valid |= P9SetattrValid::ATIME | P9SetattrValid::ATIME_SET;
+        }
+        if attr.mtime != current.mtime {
+            attr.valid |= P9SetattrValid::MTIME | P9SetattrValid::MTIME_SET;
+        }
+        if attr.valid.is_empty() {
+            return Ok(());
+        }
+        return fs.client.setattr(fid, &attr);
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        if self.0.lock().file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, fid) = self.fs_and_fid();
+        let attr = P9SetAttr {
+            valid: P9SetattrValid::SIZE,
+            size: len as u64,
+            ..Default::default()
+        };
+        return fs.client.setattr(fid, &attr);
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        let (fs, fid) = self.fs_and_fid();
+        if fs.client.getattr(fid)?.size <= len as u64 {
+            return Ok(());
+        }
+        return self.resize(len);
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let (fs, fid) = self.fs_and_fid();
+        let perm = (mode & !ModeType::S_IFMT).bits();
+        match file_type {
+            FileType::File => {
+                // Tlcreate会让fid指向新的文件，因此在fid的副本上创建，之后把副本用作新文件的只写fid
+                let (newfid, _) = fs.client.walk(fid, &[])?;
+                if let Err(e) = fs.client.lcreate(newfid, name, P9_DOTL_WRONLY, perm, 0) {
+                    fs.client.clunk(newfid).ok();
+                    return Err(e);
+                }
+                let inode = match self.find(name) {
+                    Ok(inode) => inode,
+                    Err(e) => {
+                        fs.client.clunk(newfid).ok();
+                        return Err(e);
+                    }
+                };
+                let p9_inode = inode.downcast_ref::<LockedP9Inode>().unwrap();
+                p9_inode.0.lock().write_fid = Some(newfid);
+                return Ok(inode);
+            }
+            FileType::Dir => {
+                fs.client.mkdir(fid, name, perm, 0)?;
+                return self.find(name);
+            }
+            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        }
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        let other = self.same_fs(other)?;
+        if other.0.lock().file_type() == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let (fs, fid) = self.fs_and_fid();
+        let other_fid = other.0.lock().fid;
+        return fs.client.link(fid, other_fid, name);
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let (fs, fid) = self.fs_and_fid();
+        return fs.client.unlinkat(fid, name, 0);
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let (fs, fid) = self.fs_and_fid();
+        return fs.client.unlinkat(fid, name, P9_AT_REMOVEDIR);
+    }
+
+    fn move_(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let target = self.same_fs(target)?;
+        let (fs, fid) = self.fs_and_fid();
+        let target_fid = target.0.lock().fid;
+        return fs.client.renameat(fid, old_name, target_fid, new_name);
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        if self.0.lock().file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(self.read_entries()?.into_iter().map(|entry| entry.name));
+        return Ok(keys);
+    }
+
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let (qid, parent) = {
+            let guard: SpinLockGuard<P9Inode> = self.0.lock();
+            if guard.file_type() != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (guard.qid, guard.parent.upgrade())
+        };
+        // 父目录已经被释放时，".."指向目录自身
+        let parent_qid = parent.map_or(qid, |parent| parent.0.lock().qid);
+
+        // 目录项中已经有inode号与文件类型，不需要像默认实现那样逐个查找
+        let mut entries: Vec<DirEntry> = self
+            .read_entries()?
+            .into_iter()
+            .map(|entry| DirEntry {
+                ino: InodeId::new(entry.qid.path as usize),
+                file_type: dirent_file_type(entry.ty),
+                name: entry.name,
+            })
+            .collect();
+        entries.push(DirEntry {
+            name: String::from("."),
+            ino: InodeId::new(qid.path as usize),
+            file_type: FileType::Dir,
+        });
+        entries.push(DirEntry {
+            name: String::from(".."),
+            ino: InodeId::new(parent_qid.path as usize),
+            file_type: FileType::Dir,
+        });
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut pos = offset;
+        for entry in entries.into_iter().skip(offset) {
+            if !filler(entry) {
+                break;
+            }
+            pos += 1;
+        }
+        return Ok(pos);
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let (self_ref, parent) = {
+            let guard: SpinLockGuard<P9Inode> = self.0.lock();
+            if guard.file_type() != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (guard.self_ref.clone(), guard.parent.clone())
+        };
+        let target = match name {
+            "" | "." => self_ref.upgrade(),
+            ".." => parent.upgrade(),
+            _ => None,
+        };
+        if let Some(target) = target {
+            return Ok(target);
+        }
+
+        // 每次查找都向服务器发送请求，使主机上的修改可以立即看到
+        let (fs, fid) = self.fs_and_fid();
+        let (newfid, qid) = fs.client.walk(fid, &[name])?;
+        return Ok(LockedP9Inode::new(&fs, self_ref, newfid, qid));
+    }
+
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        let write_fid = self.0.lock().write_fid;
+        match write_fid {
+            Some(fid) => {
+                let (fs, _) = self.fs_and_fid();
+                return fs.client.fsync(fid);
+            }
+            None => return Ok(()),
+        }
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        if self.0.lock().file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        return self
+            .read_entries()?
+            .into_iter()
+            .find(|entry| InodeId::new(entry.qid.path as usize) == ino)
+            .map(|entry| entry.name)
+            .ok_or(SystemError::ENOENT);
+    }
+}
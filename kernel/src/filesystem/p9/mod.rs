@@ -0,0 +1,15 @@
+//! 9P2000.L文件系统客户端
+//!
+//! 在虚拟机中开发时，通过virtio-9p设备把主机上的目录共享给DragonOS，例如QEMU的参数
+//! `-virtfs local,path=<dir>,mount_tag=hostshare,security_model=none`。
+//! 共享目录可以通过mount系统调用挂载到任意目录，source为设备的挂载标签，文件系统类型为`9p`。
+//!
+//! - [`protocol`]：消息的编码与解码
+//! - [`client`]：向服务器发送请求的客户端，以及传输消息的设备需要实现的[`client::P9Transport`]
+//! - [`fs`]：文件系统与inode
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/9p/
+
+pub mod client;
+pub mod fs;
+pub mod protocol;
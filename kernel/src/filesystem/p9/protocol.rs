@@ -0,0 +1,252 @@
+//! 9P2000.L消息的编码与解码
+//!
+//! 每条消息的格式为`size[4] type[1] tag[2] ...`，整数均为小端字节序，字符串为`len[2]`加上UTF-8字节。
+//!
+//! 参考 https://github.com/chaos/diod/blob/master/protocol.md
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{libs::byte_cursor::VecCursor, syscall::SystemError, time::TimeSpec};
+
+/// 消息头部的长度：size[4] type[1] tag[2]
+pub const P9_HEADER_LEN: usize = 7;
+/// Tversion使用的标签
+pub const P9_NOTAG: u16 = !0;
+/// 表示"没有fid"
+pub const P9_NOFID: u32 = !0;
+/// 协议版本
+pub const P9_PROTO_2000L: &str = "9P2000.L";
+
+/// 消息类型。T开头的是请求，对应的回复的类型为请求的类型加1
+pub const P9_RLERROR: u8 = 7;
+pub const P9_TLOPEN: u8 = 12;
+pub const P9_TLCREATE: u8 = 14;
+pub const P9_TREADLINK: u8 = 22;
+pub const P9_TGETATTR: u8 = 24;
+pub const P9_TSETATTR: u8 = 26;
+pub const P9_TREADDIR: u8 = 40;
+pub const P9_TFSYNC: u8 = 50;
+pub const P9_TLINK: u8 = 70;
+pub const P9_TMKDIR: u8 = 72;
+pub const P9_TRENAMEAT: u8 = 74;
+pub const P9_TUNLINKAT: u8 = 76;
+pub const P9_TVERSION: u8 = 100;
+pub const P9_TATTACH: u8 = 104;
+pub const P9_TWALK: u8 = 110;
+pub const P9_TREAD: u8 = 116;
+pub const P9_TWRITE: u8 = 118;
+pub const P9_TCLUNK: u8 = 120;
+
+/// 一次Twalk最多能够包含的路径分量数量
+pub const P9_MAXWELEM: usize = 16;
+
+/// Tgetattr请求的属性：基本属性(mode、nlink、uid、gid、rdev、时间戳、大小、块数)
+pub const P9_GETATTR_BASIC: u64 = 0x7ff;
+
+bitflags! {
+    /// Tsetattr中要修改的属性
+    #[derive(Default)]
+    pub struct P9SetattrValid: u32 {
+        const MODE = 1 << 0;
+        const UID = 1 << 1;
+        const GID = 1 << 2;
+        const SIZE = 1 << 3;
+        const ATIME = 1 << 4;
+        const MTIME = 1 << 5;
+        const CTIME = 1 << 6;
+        const ATIME_SET = 1 << 7;
+        const MTIME_SET = 1 << 8;
+    }
+}
+
+/// Tunlinkat的标志：删除的是目录
+pub const P9_AT_REMOVEDIR: u32 = 0x200;
+
+/// Tlopen与Tlcreate中的打开标志，取值与Linux相同
+pub const P9_DOTL_RDONLY: u32 = 0o0;
+pub const P9_DOTL_WRONLY: u32 = 0o1;
+pub const P9_DOTL_RDWR: u32 = 0o2;
+pub const P9_DOTL_DIRECTORY: u32 = 0o200000;
+
+/// 服务器上文件的唯一标识
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Qid {
+    /// 文件类型，见[`Qid::QTDIR`]等
+    pub ty: u8,
+    pub version: u32,
+    /// 在服务器上唯一标识一个文件，用作inode号
+    pub path: u64,
+}
+
+impl Qid {
+    pub const QTDIR: u8 = 0x80;
+    pub const QTSYMLINK: u8 = 0x02;
+
+    pub fn decode(cursor: &mut VecCursor) -> Result<Self, SystemError> {
+        return Ok(Self {
+            ty: cursor.read_u8()?,
+            version: cursor.read_u32()?,
+            path: cursor.read_u64()?,
+        });
+    }
+}
+
+/// Rgetattr中的文件属性
+#[derive(Debug, Clone, Default)]
+pub struct P9Attr {
+    pub qid: Qid,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+    pub rdev: u64,
+    pub size: u64,
+    pub blksize: u64,
+    pub blocks: u64,
+    pub atime: TimeSpec,
+    pub mtime: TimeSpec,
+    pub ctime: TimeSpec,
+}
+
+impl P9Attr {
+    pub fn decode(cursor: &mut VecCursor) -> Result<Self, SystemError> {
+        let _valid = cursor.read_u64()?;
+        let qid = Qid::decode(cursor)?;
+        let mode = cursor.read_u32()?;
+        let uid = cursor.read_u32()?;
+        let gid = cursor.read_u32()?;
+        let nlink = cursor.read_u64()?;
+        let rdev = cursor.read_u64()?;
+        let size = cursor.read_u64()?;
+        let blksize = cursor.read_u64()?;
+        let blocks = cursor.read_u64()?;
+        let mut time = || -> Result<TimeSpec, SystemError> {
+            let sec = cursor.read_u64()? as i64;
+            let nsec = cursor.read_u64()? as i64;
+            return Ok(TimeSpec::new(sec, nsec));
+        };
+        let atime = time()?;
+        let mtime = time()?;
+        let ctime = time()?;
+        return Ok(Self {
+            qid,
+            mode,
+            uid,
+            gid,
+            nlink,
+            rdev,
+            size,
+            blksize,
+            blocks,
+            atime,
+            mtime,
+            ctime,
+        });
+    }
+}
+
+/// Tsetattr中的文件属性，只有`valid`中指定的属性会被修改
+#[derive(Debug, Clone, Default)]
+pub struct P9SetAttr {
+    pub valid: P9SetattrValid,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub atime: TimeSpec,
+    pub mtime: TimeSpec,
+}
+
+/// Rreaddir中目录项的类型
+pub const P9_DT_FIFO: u8 = 1;
+pub const P9_DT_CHR: u8 = 2;
+pub const P9_DT_DIR: u8 = 4;
+pub const P9_DT_BLK: u8 = 6;
+pub const P9_DT_LNK: u8 = 10;
+pub const P9_DT_SOCK: u8 = 12;
+
+/// Rreaddir中的一个目录项
+#[derive(Debug, Clone)]
+pub struct P9DirEntry {
+    pub qid: Qid,
+    /// 下一个目录项的偏移量，用于下一次Treaddir
+    pub offset: u64,
+    /// 文件类型，取值与Linux中的DT_*相同
+    pub ty: u8,
+    pub name: String,
+}
+
+impl P9DirEntry {
+    pub fn decode(cursor: &mut VecCursor) -> Result<Self, SystemError> {
+        let qid = Qid::decode(cursor)?;
+        let offset = cursor.read_u64()?;
+        let ty = cursor.read_u8()?;
+        let name = read_str(cursor)?;
+        return Ok(Self {
+            qid,
+            offset,
+            ty,
+            name,
+        });
+    }
+}
+
+/// 读取字符串：len[2]加上UTF-8字节
+pub fn read_str(cursor: &mut VecCursor) -> Result<String, SystemError> {
+    let len = cursor.read_u16()? as usize;
+    let data = cursor.read_slice(len)?;
+    return String::from_utf8(data.to_vec()).map_err(|_| SystemError::EIO);
+}
+
+/// 构造一条请求消息
+#[derive(Debug)]
+pub struct P9Writer {
+    buf: Vec<u8>,
+}
+
+impl P9Writer {
+    /// 开始构造一条消息，头部中的size在[`P9Writer::finish`]时填写
+    pub fn new(ty: u8, tag: u16) -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&[0; 4]);
+        buf.push(ty);
+        buf.extend_from_slice(&tag.to_le_bytes());
+        return Self { buf };
+    }
+
+    pub fn u8(mut self, v: u8) -> Self {
+        self.buf.push(v);
+        return self;
+    }
+
+    pub fn u16(mut self, v: u16) -> Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        return self;
+    }
+
+    pub fn u32(mut self, v: u32) -> Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        return self;
+    }
+
+    pub fn u64(mut self, v: u64) -> Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        return self;
+    }
+
+    pub fn str(self, s: &str) -> Self {
+        return self.u16(s.len() as u16).bytes(s.as_bytes());
+    }
+
+    pub fn bytes(mut self, data: &[u8]) -> Self {
+        self.buf.extend_from_slice(data);
+        return self;
+    }
+
+    /// 填写消息的长度，返回完整的消息
+    pub fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_le_bytes());
+        return self.buf;
+    }
+}
@@ -4,31 +4,50 @@ use alloc::{
     borrow::ToOwned,
     collections::BTreeMap,
     format,
-    string::String,
+    string::{String, ToString},
     sync::{Arc, Weak},
     vec::Vec,
 };
 
 use crate::{
     arch::mm::LockedFrameAllocator,
+    driver::base::block::writeback::global_dirty_bytes,
+    exception::irqdesc::{irq_manager, IrqNumber},
     filesystem::vfs::{
         core::{generate_inode_id, ROOT_INODE},
         FileType,
     },
+    ipc::sysv::{msg::msg_show, sem::sem_show, shm::shm_show},
     kerror, kinfo,
     libs::{
+        dynamic_debug::{dynamic_debug_show, dynamic_debug_write},
         once::Once,
         spinlock::{SpinLock, SpinLockGuard},
     },
+    mm::aslr::{randomize_va_space_show, randomize_va_space_write},
+    module::modules_show,
+    net::{
+        arp::arp_show,
+        net_core::dev_show,
+        netfilter::{nf_rules_show, nf_rules_write},
+        ping::{ping_proc_show, ping_proc_write},
+        route::route_show,
+        socket::tcp_show,
+    },
     process::{Pid, ProcessManager},
+    smp::{
+        core::smp_cpu_count,
+        hotplug::{cpu_down, cpu_online, cpu_up},
+    },
     syscall::SystemError,
     time::TimeSpec,
 };
 
 use super::vfs::{
     file::{FileMode, FilePrivateData},
+    list_dir_entries,
     syscall::ModeType,
-    FileSystem, FsInfo, IndexNode, InodeId, Metadata, PollStatus,
+    DirEntry, FileSystem, FsInfo, IndexNode, InodeId, Metadata, PollStatus, PollTable,
 };
 
 /// @brief 进程文件类型
@@ -40,6 +59,36 @@ pub enum ProcFileType {
     ProcStatus = 0,
     /// meminfo
     ProcMeminfo = 1,
+    /// 各个中断在每个CPU上发生的次数
+    ProcInterrupts = 2,
+    /// /proc/irq/<irq>/smp_affinity，处理中断的CPU
+    ProcIrqAffinity = 3,
+    /// /proc/net/nf_rules，数据包过滤规则
+    ProcNetfilterRules = 4,
+    /// /proc/net/dev，网卡的收发统计信息
+    ProcNetDev = 5,
+    /// /proc/net/arp，邻居表
+    ProcNetArp = 6,
+    /// /proc/net/tcp，tcp socket的列表
+    ProcNetTcp = 7,
+    /// /proc/net/ping，内核中的ping工具
+    ProcNetPing = 8,
+    /// /proc/dynamic_debug/control，kdebug!/kinfo!调用点的启用状态
+    ProcDynamicDebug = 9,
+    /// /proc/modules，已经加载的模块
+    ProcModules = 10,
+    /// /proc/cpu/<cpu>/online，cpu是否在线
+    ProcCpuOnline = 11,
+    /// /proc/sysvipc/shm，System V共享内存段的列表
+    ProcSysvipcShm = 12,
+    /// /proc/sysvipc/sem，System V信号量集的列表
+    ProcSysvipcSem = 13,
+    /// /proc/sysvipc/msg，System V消息队列的列表
+    ProcSysvipcMsg = 14,
+    /// /proc/sys/kernel/randomize_va_space，用户地址空间布局随机化的级别
+    ProcRandomizeVaSpace = 15,
+    /// /proc/net/route，IPv4路由表
+    ProcNetRoute = 16,
     //todo: 其他文件类型
     ///默认文件类型
     Default,
@@ -50,6 +99,21 @@ impl From<u8> for ProcFileType {
         match value {
             0 => ProcFileType::ProcStatus,
             1 => ProcFileType::ProcMeminfo,
+            2 => ProcFileType::ProcInterrupts,
+            3 => ProcFileType::ProcIrqAffinity,
+            4 => ProcFileType::ProcNetfilterRules,
+            5 => ProcFileType::ProcNetDev,
+            6 => ProcFileType::ProcNetArp,
+            7 => ProcFileType::ProcNetTcp,
+            8 => ProcFileType::ProcNetPing,
+            9 => ProcFileType::ProcDynamicDebug,
+            10 => ProcFileType::ProcModules,
+            11 => ProcFileType::ProcCpuOnline,
+            12 => ProcFileType::ProcSysvipcShm,
+            13 => ProcFileType::ProcSysvipcSem,
+            14 => ProcFileType::ProcSysvipcMsg,
+            15 => ProcFileType::ProcRandomizeVaSpace,
+            16 => ProcFileType::ProcNetRoute,
             _ => ProcFileType::Default,
         }
     }
@@ -62,6 +126,10 @@ pub struct InodeInfo {
     pid: Pid,
     ///文件类型
     ftype: ProcFileType,
+    ///中断向量号
+    irq: IrqNumber,
+    ///cpu编号
+    cpu: usize,
     //其他需要传入的信息在此定义
 }
 
@@ -196,6 +264,11 @@ impl ProcFSInode {
         );
         pdata.append(&mut format!("\nVmData:\t{} kB", data).as_bytes().to_owned());
         pdata.append(&mut format!("\nVmExe:\t{} kB", text).as_bytes().to_owned());
+        pdata.append(
+            &mut format!("\nSeccomp:\t{}", pcb.seccomp().mode() as u32)
+                .as_bytes()
+                .to_owned(),
+        );
         pdata.append(
             &mut format!("\nflags: {:?}\n", pcb.flags().clone())
                 .as_bytes()
@@ -228,6 +301,88 @@ impl ProcFSInode {
                 .to_owned(),
         );
 
+        data.append(
+            &mut format!("Dirty:\t\t{} kB\n", global_dirty_bytes() >> 10)
+                .as_bytes()
+                .to_owned(),
+        );
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 interrupts 文件
+    fn open_interrupts(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut irq_manager().show_interrupts().as_bytes().to_owned());
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 /proc/irq/<irq>/smp_affinity 文件
+    fn open_irq_affinity(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let cpu = irq_manager()
+            .affinity(self.fdata.irq)
+            .ok_or(SystemError::ENOENT)?;
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut format!("{:x}\n", 1u128 << cpu).as_bytes().to_owned());
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 /proc/net/nf_rules 文件
+    /// 打开 /proc/cpu/<cpu>/online 文件
+    fn open_cpu_online(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(
+            &mut format!("{}\n", cpu_online(self.fdata.cpu) as u8)
+                .as_bytes()
+                .to_owned(),
+        );
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    fn open_nf_rules(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut nf_rules_show().as_bytes().to_owned());
+
+        // 去除多余的\0
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 /proc/net 下展示网络状态的文件，以及其他内容由对应模块生成的文件
+    fn open_net_file(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let content = match self.fdata.ftype {
+            ProcFileType::ProcNetDev => dev_show(),
+            ProcFileType::ProcNetArp => arp_show(),
+            ProcFileType::ProcNetRoute => route_show(),
+            ProcFileType::ProcNetTcp => tcp_show(),
+            ProcFileType::ProcNetPing => ping_proc_show(),
+            ProcFileType::ProcDynamicDebug => dynamic_debug_show(),
+            ProcFileType::ProcModules => modules_show(),
+            ProcFileType::ProcSysvipcShm => shm_show(),
+            ProcFileType::ProcSysvipcSem => sem_show(),
+            ProcFileType::ProcSysvipcMsg => msg_show(),
+            ProcFileType::ProcRandomizeVaSpace => randomize_va_space_show(),
+            _ => return Err(SystemError::EINVAL),
+        };
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut content.as_bytes().to_owned());
+
         // 去除多余的\0
         self.trim_string(data);
 
@@ -303,6 +458,8 @@ impl ProcFS {
                 fdata: InodeInfo {
                     pid: Pid::new(0),
                     ftype: ProcFileType::Default,
+                    irq: 0,
+                    cpu: 0,
                 },
             })));
 
@@ -334,6 +491,199 @@ impl ProcFS {
             panic!("create meminfo error");
         }
 
+        // 创建interrupts文件
+        let binding = inode.create(
+            "interrupts",
+            FileType::File,
+            ModeType::from_bits_truncate(0o444),
+        );
+        if let Ok(interrupts) = binding {
+            let interrupts_file = interrupts
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            interrupts_file.0.lock().fdata.ftype = ProcFileType::ProcInterrupts;
+        } else {
+            panic!("create interrupts error");
+        }
+
+        // 创建irq文件夹，并为已经注册的中断创建对应的文件
+        inode
+            .create("irq", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .expect("create irq error");
+        for irq in irq_manager().active_irqs() {
+            result.register_irq(irq).ok();
+        }
+
+        // 创建net文件夹，以及其中的nf_rules文件
+        let net_dir = inode
+            .create("net", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .expect("create net error");
+        let binding = net_dir
+            .create(
+                "nf_rules",
+                FileType::File,
+                ModeType::from_bits_truncate(0o644),
+            )
+            .expect("create nf_rules error");
+        binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcNetfilterRules;
+
+        // 创建/proc/net下展示网络状态的只读文件
+        for (name, ftype) in [
+            ("dev", ProcFileType::ProcNetDev),
+            ("arp", ProcFileType::ProcNetArp),
+            ("route", ProcFileType::ProcNetRoute),
+            ("tcp", ProcFileType::ProcNetTcp),
+        ] {
+            let binding = net_dir
+                .create(name, FileType::File, ModeType::from_bits_truncate(0o444))
+                .expect("create net file error");
+            binding
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap()
+                .0
+                .lock()
+                .fdata
+                .ftype = ftype;
+        }
+
+        // 创建/proc/net/ping，写入目标地址发起ping，读取得到最近一次的结果
+        let binding = net_dir
+            .create("ping", FileType::File, ModeType::from_bits_truncate(0o644))
+            .expect("create ping error");
+        binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcNetPing;
+
+        // 创建/proc/dynamic_debug/control，用于在运行时启用或禁用kdebug!/kinfo!
+        let binding = inode
+            .create(
+                "dynamic_debug",
+                FileType::Dir,
+                ModeType::from_bits_truncate(0o555),
+            )
+            .expect("create dynamic_debug error")
+            .create(
+                "control",
+                FileType::File,
+                ModeType::from_bits_truncate(0o644),
+            )
+            .expect("create dynamic_debug control error");
+        binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcDynamicDebug;
+
+        // 创建/proc/modules，列出已经加载的模块
+        let binding = inode
+            .create(
+                "modules",
+                FileType::File,
+                ModeType::from_bits_truncate(0o444),
+            )
+            .expect("create modules error");
+        binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcModules;
+
+        // 创建cpu文件夹，以及每个cpu的online文件
+        let cpu_dir = inode
+            .create("cpu", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .expect("create cpu error");
+        for cpu in 0..smp_cpu_count() {
+            let binding = cpu_dir
+                .create(
+                    &cpu.to_string(),
+                    FileType::Dir,
+                    ModeType::from_bits_truncate(0o555),
+                )
+                .and_then(|dir| {
+                    dir.create(
+                        "online",
+                        FileType::File,
+                        ModeType::from_bits_truncate(0o644),
+                    )
+                })
+                .expect("create cpu online error");
+            let online_file: &LockedProcFSInode = binding
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            online_file.0.lock().fdata.cpu = cpu;
+            online_file.0.lock().fdata.ftype = ProcFileType::ProcCpuOnline;
+        }
+
+        // 创建sysvipc文件夹，以及展示System V IPC对象的文件
+        let sysvipc_dir = inode
+            .create(
+                "sysvipc",
+                FileType::Dir,
+                ModeType::from_bits_truncate(0o555),
+            )
+            .expect("create sysvipc error");
+        for (name, ftype) in [
+            ("shm", ProcFileType::ProcSysvipcShm),
+            ("sem", ProcFileType::ProcSysvipcSem),
+            ("msg", ProcFileType::ProcSysvipcMsg),
+        ] {
+            let binding = sysvipc_dir
+                .create(name, FileType::File, ModeType::from_bits_truncate(0o444))
+                .expect("create sysvipc file error");
+            binding
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap()
+                .0
+                .lock()
+                .fdata
+                .ftype = ftype;
+        }
+
+        // 创建/proc/sys/kernel/randomize_va_space，用于调整用户地址空间布局随机化的级别
+        let binding = inode
+            .create("sys", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .and_then(|dir| {
+                dir.create("kernel", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            })
+            .and_then(|dir| {
+                dir.create(
+                    "randomize_va_space",
+                    FileType::File,
+                    ModeType::from_bits_truncate(0o644),
+                )
+            })
+            .expect("create randomize_va_space error");
+        binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcRandomizeVaSpace;
+
         return result;
     }
 
@@ -367,6 +717,40 @@ impl ProcFS {
         return Ok(());
     }
 
+    /// 为中断创建/proc/irq/<irq>文件夹
+    pub fn register_irq(&self, irq: IrqNumber) -> Result<(), SystemError> {
+        let irq_root: Arc<dyn IndexNode> = self.root_inode().find("irq")?;
+        let irq_dir: Arc<dyn IndexNode> = irq_root.create(
+            &irq.to_string(),
+            FileType::Dir,
+            ModeType::from_bits_truncate(0o555),
+        )?;
+        // smp_affinity文件
+        let binding: Arc<dyn IndexNode> = irq_dir.create(
+            "smp_affinity",
+            FileType::File,
+            ModeType::from_bits_truncate(0o644),
+        )?;
+        let affinity_file: &LockedProcFSInode = binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap();
+        affinity_file.0.lock().fdata.irq = irq;
+        affinity_file.0.lock().fdata.ftype = ProcFileType::ProcIrqAffinity;
+
+        return Ok(());
+    }
+
+    /// 删除/proc/irq/<irq>文件夹
+    pub fn unregister_irq(&self, irq: IrqNumber) -> Result<(), SystemError> {
+        let irq_root: Arc<dyn IndexNode> = self.root_inode().find("irq")?;
+        let irq_dir: Arc<dyn IndexNode> = irq_root.find(&irq.to_string())?;
+        irq_dir.unlink("smp_affinity")?;
+        irq_root.unlink(&irq.to_string())?;
+
+        return Ok(());
+    }
+
     /// @brief 解除进程注册
     ///
     pub fn unregister_pid(&self, pid: Pid) -> Result<(), SystemError> {
@@ -401,6 +785,21 @@ impl IndexNode for LockedProcFSInode {
         let file_size = match inode.fdata.ftype {
             ProcFileType::ProcStatus => inode.open_status(&mut private_data)?,
             ProcFileType::ProcMeminfo => inode.open_meminfo(&mut private_data)?,
+            ProcFileType::ProcInterrupts => inode.open_interrupts(&mut private_data)?,
+            ProcFileType::ProcIrqAffinity => inode.open_irq_affinity(&mut private_data)?,
+            ProcFileType::ProcCpuOnline => inode.open_cpu_online(&mut private_data)?,
+            ProcFileType::ProcNetfilterRules => inode.open_nf_rules(&mut private_data)?,
+            ProcFileType::ProcNetDev
+            | ProcFileType::ProcNetArp
+            | ProcFileType::ProcNetRoute
+            | ProcFileType::ProcNetTcp
+            | ProcFileType::ProcNetPing
+            | ProcFileType::ProcDynamicDebug
+            | ProcFileType::ProcModules
+            | ProcFileType::ProcSysvipcShm
+            | ProcFileType::ProcSysvipcSem
+            | ProcFileType::ProcSysvipcMsg
+            | ProcFileType::ProcRandomizeVaSpace => inode.open_net_file(&mut private_data)?,
             _ => {
                 todo!()
             }
@@ -460,6 +859,23 @@ impl IndexNode for LockedProcFSInode {
         match inode.fdata.ftype {
             ProcFileType::ProcStatus => return inode.proc_read(offset, len, buf, private_data),
             ProcFileType::ProcMeminfo => return inode.proc_read(offset, len, buf, private_data),
+            ProcFileType::ProcInterrupts => return inode.proc_read(offset, len, buf, private_data),
+            ProcFileType::ProcIrqAffinity
+            | ProcFileType::ProcCpuOnline
+            | ProcFileType::ProcNetfilterRules
+            | ProcFileType::ProcNetDev
+            | ProcFileType::ProcNetArp
+            | ProcFileType::ProcNetRoute
+            | ProcFileType::ProcNetTcp
+            | ProcFileType::ProcNetPing
+            | ProcFileType::ProcDynamicDebug
+            | ProcFileType::ProcModules
+            | ProcFileType::ProcSysvipcShm
+            | ProcFileType::ProcSysvipcSem
+            | ProcFileType::ProcSysvipcMsg
+            | ProcFileType::ProcRandomizeVaSpace => {
+                return inode.proc_read(offset, len, buf, private_data)
+            }
             ProcFileType::Default => (),
         };
 
@@ -481,14 +897,43 @@ impl IndexNode for LockedProcFSInode {
     fn write_at(
         &self,
         _offset: usize,
-        _len: usize,
-        _buf: &[u8],
+        len: usize,
+        buf: &[u8],
         _data: &mut FilePrivateData,
     ) -> Result<usize, SystemError> {
-        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        let inode: SpinLockGuard<ProcFSInode> = self.0.lock();
+        match inode.fdata.ftype {
+            ProcFileType::ProcIrqAffinity => {
+                let irq = inode.fdata.irq;
+                drop(inode);
+                return write_irq_affinity(irq, &buf[..len.min(buf.len())]);
+            }
+            ProcFileType::ProcCpuOnline => {
+                let cpu = inode.fdata.cpu;
+                drop(inode);
+                return write_cpu_online(cpu, &buf[..len.min(buf.len())]);
+            }
+            ProcFileType::ProcNetfilterRules => {
+                drop(inode);
+                return nf_rules_write(&buf[..len.min(buf.len())]);
+            }
+            ProcFileType::ProcNetPing => {
+                drop(inode);
+                return ping_proc_write(&buf[..len.min(buf.len())]);
+            }
+            ProcFileType::ProcDynamicDebug => {
+                drop(inode);
+                return dynamic_debug_write(&buf[..len.min(buf.len())]);
+            }
+            ProcFileType::ProcRandomizeVaSpace => {
+                drop(inode);
+                return randomize_va_space_write(&buf[..len.min(buf.len())]);
+            }
+            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        }
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         // 加锁
         let inode: SpinLockGuard<ProcFSInode> = self.0.lock();
 
@@ -582,6 +1027,8 @@ impl IndexNode for LockedProcFSInode {
                 fdata: InodeInfo {
                     pid: Pid::new(0),
                     ftype: ProcFileType::Default,
+                    irq: 0,
+                    cpu: 0,
                 },
             })));
 
@@ -732,6 +1179,24 @@ impl IndexNode for LockedProcFSInode {
 
         return Ok(keys);
     }
+
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let (ino, parent) = {
+            let inode = self.0.lock();
+            if inode.metadata.file_type != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (inode.metadata.inode_id, inode.parent.upgrade())
+        };
+        let parent_ino = parent.map_or(ino, |parent| parent.0.lock().metadata.inode_id);
+
+        let inode = self.0.lock();
+        return list_dir_entries(ino, parent_ino, inode.children.iter(), offset, filler);
+    }
 }
 
 /// @brief 向procfs注册进程
@@ -765,6 +1230,57 @@ pub fn procfs_unregister_pid(pid: Pid) -> Result<(), SystemError> {
     return procfs.unregister_pid(pid);
 }
 
+/// 获取已经挂载的procfs实例
+fn procfs_instance() -> Result<Arc<dyn FileSystem>, SystemError> {
+    let procfs_inode = ROOT_INODE().find("proc")?;
+    // procfs还没有挂载
+    let procfs_inode = procfs_inode
+        .downcast_ref::<LockedProcFSInode>()
+        .ok_or(SystemError::ENODEV)?;
+    return Ok(procfs_inode.fs());
+}
+
+/// 向procfs注册中断
+pub fn procfs_register_irq(irq: IrqNumber) -> Result<(), SystemError> {
+    let fs = procfs_instance()?;
+    let procfs: &ProcFS = fs.as_any_ref().downcast_ref::<ProcFS>().unwrap();
+    return procfs.register_irq(irq);
+}
+
+/// 在procfs中解除中断的注册
+pub fn procfs_unregister_irq(irq: IrqNumber) -> Result<(), SystemError> {
+    let fs = procfs_instance()?;
+    let procfs: &ProcFS = fs.as_any_ref().downcast_ref::<ProcFS>().unwrap();
+    return procfs.unregister_irq(irq);
+}
+
+/// 解析写入smp_affinity的CPU掩码（十六进制），把中断投递到掩码中编号最小的CPU上
+fn write_irq_affinity(irq: IrqNumber, buf: &[u8]) -> Result<usize, SystemError> {
+    let s = core::str::from_utf8(buf)
+        .map_err(|_| SystemError::EINVAL)?
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    let s = s.trim_start_matches("0x");
+    let mask = u128::from_str_radix(s, 16).map_err(|_| SystemError::EINVAL)?;
+    if mask == 0 {
+        return Err(SystemError::EINVAL);
+    }
+    irq_manager().set_affinity(irq, mask.trailing_zeros() as usize)?;
+    return Ok(buf.len());
+}
+
+/// 写入1让cpu上线，写入0让cpu下线
+fn write_cpu_online(cpu: usize, buf: &[u8]) -> Result<usize, SystemError> {
+    let s = core::str::from_utf8(buf)
+        .map_err(|_| SystemError::EINVAL)?
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    match s {
+        "0" => cpu_down(cpu)?,
+        "1" => cpu_up(cpu)?,
+        _ => return Err(SystemError::EINVAL),
+    }
+    return Ok(buf.len());
+}
+
 pub fn procfs_init() -> Result<(), SystemError> {
     static INIT: Once = Once::new();
     let mut result = None;
@@ -0,0 +1,150 @@
+//! memfd：没有路径的、数据保存在内存中的文件
+//!
+//! memfd的inode属于一个内核内部的ramfs，但是不在任何目录中，最后一个引用它的文件被关闭之后就会被释放。
+//! 与/dev/shm下的文件不同，创建时指定了`MFD_ALLOW_SEALING`的memfd可以通过fcntl添加封印。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/mm/memfd.c
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use crate::{
+    filesystem::vfs::{
+        core::generate_inode_id,
+        fcntl::{FcntlCommand, FileSeals},
+        file::{File, FileMode},
+        syscall::ModeType,
+        FileType, Metadata,
+    },
+    libs::spinlock::SpinLock,
+    mm::shmem::ShmemPages,
+    syscall::SystemError,
+    time::TimeSpec,
+};
+
+use super::{LockedRamFSInode, RamFS, RamFSInode};
+
+bitflags! {
+    /// memfd_create的flags
+    pub struct MemFdFlags: u32 {
+        /// 为文件描述符设置FD_CLOEXEC
+        const MFD_CLOEXEC = 0x0001;
+        /// 允许添加封印
+        const MFD_ALLOW_SEALING = 0x0002;
+        /// 使用巨页(暂不支持)
+        const MFD_HUGETLB = 0x0004;
+    }
+}
+
+/// memfd名字的最大长度。名字加上"memfd:"前缀不能超过NAME_MAX
+pub const MFD_NAME_MAX_LEN: usize = 255 - "memfd:".len();
+
+lazy_static! {
+    /// memfd的inode所属的文件系统
+    static ref MEMFD_FS: Arc<RamFS> = RamFS::new();
+}
+
+/// @brief 创建一个memfd
+///
+/// @param name 文件的名字，仅用于调试
+/// @param flags memfd_create的flags
+///
+/// @return 成功：Ok(以读写模式打开的文件)
+///         失败：Err(EINVAL) 名字过长或者flags不合法
+///               Err(错误码) 打开文件失败
+pub fn memfd_create(name: &str, flags: MemFdFlags) -> Result<File, SystemError> {
+    if name.len() > MFD_NAME_MAX_LEN {
+        return Err(SystemError::EINVAL);
+    }
+    // 暂不支持巨页
+    if flags.contains(MemFdFlags::MFD_HUGETLB) {
+        return Err(SystemError::EINVAL);
+    }
+
+    let seals = if flags.contains(MemFdFlags::MFD_ALLOW_SEALING) {
+        FileSeals::empty()
+    } else {
+        FileSeals::F_SEAL_SEAL
+    };
+
+    let inode: Arc<LockedRamFSInode> = Arc::new(LockedRamFSInode(SpinLock::new(RamFSInode {
+        parent: Weak::default(),
+        self_ref: Weak::default(),
+        children: BTreeMap::new(),
+        data: ShmemPages::new(),
+        seals,
+        metadata: Metadata {
+            dev_id: 0,
+            inode_id: generate_inode_id(),
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: TimeSpec::default(),
+            mtime: TimeSpec::default(),
+            ctime: TimeSpec::default(),
+            file_type: FileType::File,
+            mode: ModeType::from_bits_truncate(0o777),
+            nlinks: 0,
+            uid: 0,
+            gid: 0,
+            raw_dev: 0,
+        },
+        fs: Arc::downgrade(&*MEMFD_FS),
+        special_node: None,
+    })));
+    inode.0.lock().self_ref = Arc::downgrade(&inode);
+
+    let mut mode = FileMode::O_RDWR;
+    if flags.contains(MemFdFlags::MFD_CLOEXEC) {
+        mode |= FileMode::O_CLOEXEC;
+    }
+    return File::new(inode, mode);
+}
+
+/// @brief 处理fcntl的F_ADD_SEALS和F_GET_SEALS命令
+///
+/// @param file 要操作的文件
+/// @param cmd fcntl的命令
+/// @param arg F_ADD_SEALS时为要添加的封印
+///
+/// @return 成功：F_GET_SEALS时返回文件的封印，F_ADD_SEALS时返回0
+///         失败：Err(EINVAL) 文件不是memfd，或者封印不合法
+///               Err(EPERM) 文件不是以可写的方式打开的，或者文件已经有F_SEAL_SEAL封印
+///               Err(EBUSY) 添加F_SEAL_WRITE时，文件存在可写的映射
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/mm/memfd.c?fi=memfd_fcntl#memfd_fcntl
+pub fn memfd_fcntl(file: &File, cmd: FcntlCommand, arg: u32) -> Result<usize, SystemError> {
+    let inode = file.inode();
+    let inode = inode
+        .as_any_ref()
+        .downcast_ref::<LockedRamFSInode>()
+        .ok_or(SystemError::EINVAL)?;
+
+    match cmd {
+        FcntlCommand::GetSeals => {
+            return Ok(inode.0.lock().seals.bits() as usize);
+        }
+        FcntlCommand::AddSeals => {
+            file.writeable().map_err(|_| SystemError::EPERM)?;
+            let seals = FileSeals::from_bits(arg).ok_or(SystemError::EINVAL)?;
+
+            let mut guard = inode.0.lock();
+            if guard.seals.contains(FileSeals::F_SEAL_SEAL) {
+                return Err(SystemError::EPERM);
+            }
+            if seals.contains(FileSeals::F_SEAL_WRITE)
+                && !guard.seals.contains(FileSeals::F_SEAL_WRITE)
+                && guard.data.writable_mappings() > 0
+            {
+                return Err(SystemError::EBUSY);
+            }
+            guard.seals.insert(seals);
+            return Ok(0);
+        }
+        _ => {
+            return Err(SystemError::EINVAL);
+        }
+    }
+}
@@ -12,15 +12,22 @@ use crate::{
     filesystem::vfs::{core::generate_inode_id, FileType},
     ipc::pipe::LockedPipeInode,
     libs::spinlock::{SpinLock, SpinLockGuard},
+    mm::{
+        allocator::page_frame::{PageFrameCount, PhysPageFrame},
+        shmem::ShmemPages,
+        syscall::ProtFlags,
+    },
     syscall::SystemError,
     time::TimeSpec,
 };
 
 use super::vfs::{
-    file::FilePrivateData, syscall::ModeType, FileSystem, FsInfo, IndexNode, InodeId, Metadata,
-    PollStatus, SpecialNodeData,
+    fcntl::FileSeals, file::FilePrivateData, list_dir_entries, syscall::ModeType, DirEntry,
+    FileSystem, FsInfo, IndexNode, InodeId, Metadata, PollStatus, PollTable, SpecialNodeData,
 };
 
+pub mod memfd;
+
 /// RamFS的inode名称的最大长度
 const RAMFS_MAX_NAMELEN: usize = 64;
 
@@ -49,7 +56,9 @@ pub struct RamFSInode {
     /// 子Inode的B树
     children: BTreeMap<String, Arc<LockedRamFSInode>>,
     /// 当前inode的数据部分
-    data: Vec<u8>,
+    data: ShmemPages,
+    /// 文件的封印，只有memfd允许修改
+    seals: FileSeals,
     /// 当前inode的元数据
     metadata: Metadata,
     /// 指向inode所在的文件系统对象的指针
@@ -84,7 +93,8 @@ impl RamFS {
             parent: Weak::default(),
             self_ref: Weak::default(),
             children: BTreeMap::new(),
-            data: Vec::new(),
+            data: ShmemPages::new(),
+            seals: FileSeals::F_SEAL_SEAL,
             metadata: Metadata {
                 dev_id: 0,
                 inode_id: generate_inode_id(),
@@ -129,8 +139,11 @@ impl IndexNode for LockedRamFSInode {
         }
 
         //当前文件长度大于_len才进行截断，否则不操作
-        if inode.data.len() > len {
-            inode.data.resize(len, 0);
+        if inode.data.size() > len {
+            if inode.seals.contains(FileSeals::F_SEAL_SHRINK) {
+                return Err(SystemError::EPERM);
+            }
+            inode.data.resize(len);
         }
         return Ok(());
     }
@@ -165,18 +178,8 @@ impl IndexNode for LockedRamFSInode {
             return Err(SystemError::EISDIR);
         }
 
-        let start = inode.data.len().min(offset);
-        let end = inode.data.len().min(offset + len);
-
-        // buffer空间不足
-        if buf.len() < (end - start) {
-            return Err(SystemError::ENOBUFS);
-        }
-
         // 拷贝数据
-        let src = &inode.data[start..end];
-        buf[0..src.len()].copy_from_slice(src);
-        return Ok(src.len());
+        return Ok(inode.data.read_at(offset, &mut buf[0..len]));
     }
 
     fn write_at(
@@ -198,19 +201,21 @@ impl IndexNode for LockedRamFSInode {
             return Err(SystemError::EISDIR);
         }
 
-        let data: &mut Vec<u8> = &mut inode.data;
-
-        // 如果文件大小比原来的大，那就resize这个数组
-        if offset + len > data.len() {
-            data.resize(offset + len, 0);
+        if inode
+            .seals
+            .intersects(FileSeals::F_SEAL_WRITE | FileSeals::F_SEAL_FUTURE_WRITE)
+        {
+            return Err(SystemError::EPERM);
+        }
+        // 写入的数据会使文件变大
+        if offset + len > inode.data.size() && inode.seals.contains(FileSeals::F_SEAL_GROW) {
+            return Err(SystemError::EPERM);
         }
 
-        let target = &mut data[offset..offset + len];
-        target.copy_from_slice(&buf[0..len]);
-        return Ok(len);
+        return inode.data.write_at(offset, &buf[0..len]);
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         // 加锁
         let inode: SpinLockGuard<RamFSInode> = self.0.lock();
 
@@ -233,7 +238,7 @@ impl IndexNode for LockedRamFSInode {
     fn metadata(&self) -> Result<Metadata, SystemError> {
         let inode = self.0.lock();
         let mut metadata = inode.metadata.clone();
-        metadata.size = inode.data.len() as i64;
+        metadata.size = inode.data.size() as i64;
 
         return Ok(metadata);
     }
@@ -253,13 +258,43 @@ impl IndexNode for LockedRamFSInode {
     fn resize(&self, len: usize) -> Result<(), SystemError> {
         let mut inode = self.0.lock();
         if inode.metadata.file_type == FileType::File {
-            inode.data.resize(len, 0);
+            let size = inode.data.size();
+            if (len < size && inode.seals.contains(FileSeals::F_SEAL_SHRINK))
+                || (len > size && inode.seals.contains(FileSeals::F_SEAL_GROW))
+            {
+                return Err(SystemError::EPERM);
+            }
+            inode.data.resize(len);
             return Ok(());
         } else {
             return Err(SystemError::EINVAL);
         }
     }
 
+    /// @brief 把文件的数据页映射到进程的地址空间，映射范围可以超出文件末尾
+    fn mmap_frames(
+        &self,
+        offset: usize,
+        count: PageFrameCount,
+        prot_flags: ProtFlags,
+    ) -> Result<(Vec<PhysPageFrame>, Arc<dyn Any + Send + Sync>), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::File {
+            return Err(SystemError::ENODEV);
+        }
+
+        let writable = prot_flags.contains(ProtFlags::PROT_WRITE);
+        // 没有写时复制，私有映射也会写到文件中，因此被封印的文件不允许任何可写的映射
+        if writable
+            && inode
+                .seals
+                .intersects(FileSeals::F_SEAL_WRITE | FileSeals::F_SEAL_FUTURE_WRITE)
+        {
+            return Err(SystemError::EPERM);
+        }
+        return inode.data.mmap_frames(offset, count, writable);
+    }
+
     fn create_with_data(
         &self,
         name: &str,
@@ -283,7 +318,8 @@ impl IndexNode for LockedRamFSInode {
             parent: inode.self_ref.clone(),
             self_ref: Weak::default(),
             children: BTreeMap::new(),
-            data: Vec::new(),
+            data: ShmemPages::new(),
+            seals: FileSeals::F_SEAL_SEAL,
             metadata: Metadata {
                 dev_id: 0,
                 inode_id: generate_inode_id(),
@@ -483,6 +519,24 @@ impl IndexNode for LockedRamFSInode {
         return Ok(keys);
     }
 
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let (ino, parent) = {
+            let inode = self.0.lock();
+            if inode.metadata.file_type != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            (inode.metadata.inode_id, inode.parent.upgrade())
+        };
+        let parent_ino = parent.map_or(ino, |parent| parent.0.lock().metadata.inode_id);
+
+        let inode = self.0.lock();
+        return list_dir_entries(ino, parent_ino, inode.children.iter(), offset, filler);
+    }
+
     fn mknod(
         &self,
         filename: &str,
@@ -504,7 +558,8 @@ impl IndexNode for LockedRamFSInode {
             parent: inode.self_ref.clone(),
             self_ref: Weak::default(),
             children: BTreeMap::new(),
-            data: Vec::new(),
+            data: ShmemPages::new(),
+            seals: FileSeals::F_SEAL_SEAL,
             metadata: Metadata {
                 dev_id: 0,
                 inode_id: generate_inode_id(),
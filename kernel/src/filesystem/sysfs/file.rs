@@ -1,4 +1,4 @@
-use core::{intrinsics::unlikely, ops::BitAnd};
+use core::{fmt::Display, intrinsics::unlikely, ops::BitAnd, str::FromStr};
 
 use alloc::{
     string::ToString,
@@ -422,3 +422,55 @@ pub fn sysfs_emit_str(buf: &mut [u8], s: &str) -> Result<usize, SystemError> {
     buf[len] = b'\0';
     return Ok(len);
 }
+
+/// 把值按照`Display`格式化，并在结尾加上换行符之后写入属性文件的缓冲区
+pub fn sysfs_emit_value<T: Display>(buf: &mut [u8], val: T) -> Result<usize, SystemError> {
+    return sysfs_emit_str(buf, format!("{}\n", val).as_str());
+}
+
+/// 取出写入属性文件的字符串，去掉结尾的'\0'以及首尾的空白字符(例如`echo`添加的换行符)
+///
+/// ## 错误
+///
+/// - `EINVAL`：写入的内容不是合法的UTF-8字符串
+pub fn sysfs_buf_to_str(buf: &[u8]) -> Result<&str, SystemError> {
+    let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    return core::str::from_utf8(&buf[..end])
+        .map(|s| s.trim())
+        .map_err(|_| SystemError::EINVAL);
+}
+
+/// 把写入属性文件的内容解析为指定的类型，例如`let depth: u32 = sysfs_parse(buf)?;`
+///
+/// ## 错误
+///
+/// - `EINVAL`：写入的内容不能被解析为`T`
+pub fn sysfs_parse<T: FromStr>(buf: &[u8]) -> Result<T, SystemError> {
+    return sysfs_buf_to_str(buf)?
+        .parse::<T>()
+        .map_err(|_| SystemError::EINVAL);
+}
+
+/// 把写入属性文件的内容解析为布尔值
+///
+/// 规则与Linux的`kstrtobool`相同：以`1`、`y`、`t`开头或者为`on`时为真，以`0`、`n`、`f`开头或者为`off`时为假(不区分大小写)
+///
+/// ## 错误
+///
+/// - `EINVAL`：写入的内容不能被解析为布尔值
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/kstrtox.c?fi=kstrtobool#kstrtobool
+pub fn sysfs_parse_bool(buf: &[u8]) -> Result<bool, SystemError> {
+    let s = sysfs_buf_to_str(buf)?.to_ascii_lowercase();
+    let mut chars = s.chars();
+    return match chars.next() {
+        Some('1' | 'y' | 't') => Ok(true),
+        Some('0' | 'n' | 'f') => Ok(false),
+        Some('o') => match chars.next() {
+            Some('n') => Ok(true),
+            Some('f') => Ok(false),
+            _ => Err(SystemError::EINVAL),
+        },
+        _ => Err(SystemError::EINVAL),
+    };
+}
@@ -1,25 +1,38 @@
 use core::{hint::spin_loop, sync::atomic::Ordering};
 
-use alloc::{format, string::ToString, sync::Arc};
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 
 use crate::{
+    debug::tracefs::tracefs_init,
     driver::{
-        base::block::disk_info::Partition,
+        base::block::{block_device::BlockDevice, disk_info::Partition},
         disk::ahci::{self},
+        usb::class::storage::usb_storage_disks,
     },
     filesystem::{
         devfs::devfs_init,
         fat::fs::FATFileSystem,
+        nfs::fs::nfs_root_fs,
         procfs::procfs_init,
         ramfs::RamFS,
         sysfs::sysfs_init,
         vfs::{mount::MountFS, syscall::ModeType, AtomicInodeId, FileSystem, FileType},
     },
-    kdebug, kerror, kinfo,
+    ipc::mqueue::mqueue_fs,
+    kdebug, kernel_param, kerror, kinfo,
+    process::ProcessManager,
     syscall::SystemError,
 };
 
-use super::{file::FileMode, utils::rsplit_path, IndexNode, InodeId, MAX_PATHLEN};
+use super::{
+    fcntl::AT_FDCWD, file::FileMode, utils::rsplit_path, IndexNode, InodeId, MAX_PATHLEN,
+    VFS_MAX_FOLLOW_SYMLINK_TIMES,
+};
 
 /// @brief 原子地生成新的Inode号。
 /// 请注意，所有的inode号都需要通过该函数来生成.全局的inode号，除了以下两个特殊的以外，都是唯一的
@@ -33,17 +46,45 @@ pub fn generate_inode_id() -> InodeId {
 
 static mut __ROOT_INODE: Option<Arc<dyn IndexNode>> = None;
 
-/// @brief 获取全局的根节点
+/// @brief 获取当前进程的根目录
+///
+/// 进程通过chroot设置了根目录时返回这个目录，否则返回进程所在的挂载命名空间的根
 #[inline(always)]
 #[allow(non_snake_case)]
 pub fn ROOT_INODE() -> Arc<dyn IndexNode> {
+    if ProcessManager::initialized() {
+        let pcb = ProcessManager::current_pcb();
+        if let Some(fs_root) = pcb.basic().fs_root() {
+            return fs_root.inode();
+        }
+        return pcb.nsproxy().mnt_ns.root_inode();
+    }
+    return init_root_inode();
+}
+
+/// @brief 判断两个inode是否为同一个文件系统中的同一个文件
+pub fn is_same_inode(a: &Arc<dyn IndexNode>, b: &Arc<dyn IndexNode>) -> Result<bool, SystemError> {
+    let same_fs = Arc::as_ptr(&a.fs()) as *const () == Arc::as_ptr(&b.fs()) as *const ();
+    return Ok(same_fs && a.metadata()?.inode_id == b.metadata()?.inode_id);
+}
+
+/// @brief 获取初始的挂载命名空间的根节点
+pub(super) fn init_root_inode() -> Arc<dyn IndexNode> {
     unsafe {
         return __ROOT_INODE.as_ref().unwrap().clone();
     }
 }
 
+/// 初始化VFS，供C代码调用。错误码在这里被转换为负数的posix错误码
 #[no_mangle]
 pub extern "C" fn vfs_init() -> i32 {
+    return do_vfs_init().map(|_| 0).unwrap_or_else(|e| {
+        kerror!("Failed to initialize VFS: {:?}", e);
+        e.to_posix_errno()
+    });
+}
+
+fn do_vfs_init() -> Result<(), SystemError> {
     // 使用Ramfs作为默认的根文件系统
     let ramfs = RamFS::new();
     let mount_fs = MountFS::new(ramfs, None);
@@ -54,28 +95,35 @@ pub extern "C" fn vfs_init() -> i32 {
     }
 
     // 创建文件夹
-    root_inode
-        .create("proc", FileType::Dir, ModeType::from_bits_truncate(0o755))
-        .expect("Failed to create /proc");
-    root_inode
-        .create("dev", FileType::Dir, ModeType::from_bits_truncate(0o755))
-        .expect("Failed to create /dev");
-    root_inode
-        .create("sys", FileType::Dir, ModeType::from_bits_truncate(0o755))
-        .expect("Failed to create /sys");
+    for name in ["proc", "dev", "sys"] {
+        root_inode
+            .create(name, FileType::Dir, ModeType::from_bits_truncate(0o755))
+            .map_err(|e| {
+                kerror!("Failed to create /{}: {:?}", name, e);
+                e
+            })?;
+    }
     kdebug!("dir in root:{:?}", root_inode.list());
 
-    procfs_init().expect("Failed to initialize procfs");
+    procfs_init()?;
 
-    devfs_init().expect("Failed to initialize devfs");
+    devfs_init()?;
 
-    sysfs_init().expect("Failed to initialize sysfs");
+    // 在/dev/shm挂载ramfs，shm_open()创建的共享内存对象保存在这里
+    ROOT_INODE().lookup("/dev/shm")?.mount(RamFS::new())?;
 
-    let root_entries = ROOT_INODE().list().expect("VFS init failed");
+    // 在/dev/mqueue挂载mqueue文件系统，mq_open()创建的消息队列保存在这里
+    ROOT_INODE().lookup("/dev/mqueue")?.mount(mqueue_fs())?;
+
+    sysfs_init()?;
+
+    tracefs_init()?;
+
+    let root_entries = ROOT_INODE().list()?;
     if root_entries.len() > 0 {
         kinfo!("Successfully initialized VFS!");
     }
-    return 0;
+    return Ok(());
 }
 
 /// @brief 真正执行伪文件系统迁移的过程
@@ -119,7 +167,7 @@ fn migrate_virtual_filesystem(new_fs: Arc<dyn FileSystem>) -> Result<(), SystemE
     let binding = ROOT_INODE().find("sys").expect("SysFs not mounted!").fs();
     let sys: &MountFS = binding.as_any_ref().downcast_ref::<MountFS>().unwrap();
 
-    let new_fs = MountFS::new(new_fs, None);
+    let new_fs = MountFS::new_checked(new_fs, None)?;
     // 获取新的根文件系统的根节点的引用
     let new_root_inode = new_fs.root_inode();
 
@@ -142,13 +190,20 @@ fn migrate_virtual_filesystem(new_fs: Arc<dyn FileSystem>) -> Result<(), SystemE
 }
 
 pub fn mount_root_fs() -> Result<(), SystemError> {
+    if root_is_nfs() {
+        return mount_nfs_root();
+    }
+
     kinfo!("Try to mount FAT32 as root fs...");
-    let partiton: Arc<Partition> = ahci::get_disks_by_name("ahci_disk_0".to_string())
-        .unwrap()
-        .0
-        .lock()
-        .partitions[0]
-        .clone();
+    let partiton: Arc<Partition> = match root_partition() {
+        Some(partiton) => partiton,
+        None => {
+            kerror!("Failed to find a partition for root fs");
+            loop {
+                spin_loop();
+            }
+        }
+    };
 
     let fatfs: Result<Arc<FATFileSystem>, SystemError> = FATFileSystem::new(partiton);
     if fatfs.is_err() {
@@ -173,63 +228,191 @@ pub fn mount_root_fs() -> Result<(), SystemError> {
     return Ok(());
 }
 
-/// @brief 创建文件/文件夹
-pub fn do_mkdir(path: &str, _mode: FileMode) -> Result<u64, SystemError> {
-    // 文件名过长
-    if path.len() > MAX_PATHLEN as usize {
-        return Err(SystemError::ENAMETOOLONG);
+/// @brief 挂载启动参数`nfsroot=`指定的NFS导出目录作为根文件系统
+fn mount_nfs_root() -> Result<(), SystemError> {
+    kinfo!("Try to mount NFS as root fs...");
+    let nfs = nfs_root_fs().map_err(|e| {
+        kerror!("Failed to mount NFS root, code={:?}", e);
+        e
+    })?;
+    migrate_virtual_filesystem(nfs).map_err(|e| {
+        kerror!("Failed to migrate virtual filesystem to NFS!");
+        e
+    })?;
+    kinfo!("Successfully migrate rootfs to NFS!");
+    return Ok(());
+}
+
+kernel_param!(ROOT_PARAM: String = "root");
+
+/// @brief 启动参数是否指定了`root=/dev/nfs`。此时根文件系统需要在网络初始化之后挂载
+pub fn root_is_nfs() -> bool {
+    return ROOT_PARAM.get().as_deref() == Some("/dev/nfs");
+}
+
+/// @brief 在磁盘的分区中按照分区号查找分区
+///
+/// @param partno 从1开始的分区号，为空时返回第一个分区
+fn find_partition(partitions: &[Arc<Partition>], partno: &str) -> Option<Arc<Partition>> {
+    if partno.is_empty() {
+        return partitions.first().cloned();
     }
+    let partno = partno.parse::<u16>().ok()?.checked_sub(1)?;
+    return partitions.iter().find(|p| p.partno == partno).cloned();
+}
 
-    let inode: Result<Arc<dyn IndexNode>, SystemError> = ROOT_INODE().lookup(path);
-
-    if inode.is_err() {
-        let errno = inode.unwrap_err();
-        // 文件不存在，且需要创建
-        if errno == SystemError::ENOENT {
-            let (filename, parent_path) = rsplit_path(path);
-            // 查找父目录
-            let parent_inode: Arc<dyn IndexNode> =
-                ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
-            // 创建文件夹
-            let _create_inode: Arc<dyn IndexNode> = parent_inode.create(
-                filename,
-                FileType::Dir,
-                ModeType::from_bits_truncate(0o755),
-            )?;
-        } else {
-            // 不需要创建文件，因此返回错误码
-            return Err(errno);
+/// @brief 根据设备名查找磁盘分区，用于启动参数`root=`以及在分区上创建映射设备
+///
+/// @param root 设备在/dev中的名字，可以带有"/dev/"前缀：
+/// AHCI磁盘为`ahci_N`或者`ahci_NpM`，USB磁盘为`sdX`或者`sdXM`，M为从1开始的分区号，
+/// 不指定分区号时使用磁盘的第一个分区
+pub fn partition_by_name(root: &str) -> Option<Arc<Partition>> {
+    let root = root.strip_prefix("/dev/").unwrap_or(root);
+    if let Some(rest) = root.strip_prefix("ahci_") {
+        let (id, partno) = rest.split_once('p').unwrap_or((rest, ""));
+        let id = id.parse::<usize>().ok()?.checked_sub(1)?;
+        let disk = ahci::get_disks_by_name(format!("ahci_disk_{}", id)).ok()?;
+        let partitions: Vec<Arc<Partition>> = disk.0.lock().partitions.clone();
+        return find_partition(&partitions, partno);
+    }
+    return usb_storage_disks().iter().find_map(|disk| {
+        let partno = root.strip_prefix(disk.name())?;
+        return find_partition(&disk.partitions(), partno);
+    });
+}
+
+/// @brief 查找根文件系统所在的分区
+///
+/// 启动参数指定了`root=`时使用指定的分区。否则优先使用第一块AHCI磁盘的第一个分区，
+/// 没有AHCI磁盘时使用第一块带有分区的USB磁盘，以支持从U盘启动
+fn root_partition() -> Option<Arc<Partition>> {
+    if let Some(root) = ROOT_PARAM.get() {
+        let partition = partition_by_name(&root);
+        if partition.is_none() {
+            kerror!("Root device {} not found", root);
         }
+        return partition;
     }
+    if let Ok(disk) = ahci::get_disks_by_name("ahci_disk_0".to_string()) {
+        if let Some(partiton) = disk.0.lock().partitions.first() {
+            return Some(partiton.clone());
+        }
+    }
+    return usb_storage_disks()
+        .iter()
+        .find_map(|disk| disk.partitions().first().cloned());
+}
 
-    return Ok(0);
+/// @brief 获取*at系列系统调用查找路径时的起始目录
+///
+/// - 绝对路径从根目录开始查找，此时忽略`dirfd`
+/// - `dirfd`为[`AT_FDCWD`]时，相对路径从当前进程的工作目录开始查找
+/// - 否则，相对路径从`dirfd`对应的目录开始查找
+///
+/// @param dirfd 目录的文件描述符，或者AT_FDCWD
+/// @param path 要查找的路径
+///
+/// @return Err(SystemError::EBADF) dirfd不存在
+/// @return Err(SystemError::ENOTDIR) dirfd对应的文件不是目录
+pub fn user_path_at(dirfd: i32, path: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+    if path.starts_with('/') {
+        return Ok(ROOT_INODE());
+    }
+
+    let pcb = ProcessManager::current_pcb();
+    if dirfd == AT_FDCWD {
+        let cwd = pcb.basic().cwd();
+        return ROOT_INODE().lookup_follow_symlink(&cwd, VFS_MAX_FOLLOW_SYMLINK_TIMES);
+    }
+
+    let file = pcb
+        .fd_table()
+        .read()
+        .get_file_by_fd(dirfd)
+        .ok_or(SystemError::EBADF)?;
+    let inode = file.lock().inode();
+    if inode.metadata()?.file_type != FileType::Dir {
+        return Err(SystemError::ENOTDIR);
+    }
+    return Ok(inode);
 }
 
-/// @brief 删除文件夹
-pub fn do_remove_dir(path: &str) -> Result<u64, SystemError> {
-    // 文件名过长
+/// @brief 查找路径的父目录
+///
+/// @param dirfd 目录的文件描述符，或者AT_FDCWD，含义与[`user_path_at`]相同
+/// @param path 要查找的路径
+///
+/// @return (父目录的inode, 最后一级的文件名)。path只有一级时，父目录就是起始目录
+pub fn lookup_parent_at(dirfd: i32, path: &str) -> Result<(Arc<dyn IndexNode>, &str), SystemError> {
     if path.len() > MAX_PATHLEN as usize {
         return Err(SystemError::ENAMETOOLONG);
     }
+    let base = user_path_at(dirfd, path)?;
+    let (filename, parent_path) = rsplit_path(path);
+    let parent = match parent_path {
+        Some(parent_path) => {
+            base.lookup_follow_symlink(parent_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?
+        }
+        None => base,
+    };
+    if parent.metadata()?.file_type != FileType::Dir {
+        return Err(SystemError::ENOTDIR);
+    }
+    return Ok((parent, filename));
+}
 
-    let inode: Result<Arc<dyn IndexNode>, SystemError> = ROOT_INODE().lookup(path);
+/// @brief 根据dirfd以及路径查找inode
+///
+/// @param dirfd 目录的文件描述符，或者AT_FDCWD，含义与[`user_path_at`]相同
+/// @param path 要查找的路径
+/// @param follow_last 最后一级是符号链接时，是否跟随它。中间的符号链接总是会被跟随
+pub fn lookup_at(
+    dirfd: i32,
+    path: &str,
+    follow_last: bool,
+) -> Result<Arc<dyn IndexNode>, SystemError> {
+    if path.len() > MAX_PATHLEN as usize {
+        return Err(SystemError::ENAMETOOLONG);
+    }
+    if follow_last {
+        return user_path_at(dirfd, path)?
+            .lookup_follow_symlink(path, VFS_MAX_FOLLOW_SYMLINK_TIMES);
+    }
 
-    if inode.is_err() {
-        let errno = inode.unwrap_err();
-        // 文件不存在
-        if errno == SystemError::ENOENT {
-            return Err(SystemError::ENOENT);
-        }
+    let (parent, filename) = lookup_parent_at(dirfd, path)?;
+    if filename.is_empty() {
+        return Ok(parent);
     }
+    return parent.find(filename);
+}
 
-    let (filename, parent_path) = rsplit_path(path);
-    // 查找父目录
-    let parent_inode: Arc<dyn IndexNode> = ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
+/// @brief 创建文件夹
+///
+/// @param dirfd 目录的文件描述符，或者AT_FDCWD，含义与[`user_path_at`]相同
+pub fn do_mkdir(dirfd: i32, path: &str, _mode: FileMode) -> Result<u64, SystemError> {
+    let (parent_inode, filename) = lookup_parent_at(dirfd, path)?;
+    if filename.is_empty() {
+        return Err(SystemError::EEXIST);
+    }
 
-    if parent_inode.metadata()?.file_type != FileType::Dir {
-        return Err(SystemError::ENOTDIR);
+    match parent_inode.find(filename) {
+        Ok(_) => return Err(SystemError::EEXIST),
+        // 文件不存在，创建文件夹
+        Err(SystemError::ENOENT) => {
+            parent_inode.create(filename, FileType::Dir, ModeType::from_bits_truncate(0o755))?;
+        }
+        Err(e) => return Err(e),
     }
 
+    return Ok(0);
+}
+
+/// @brief 删除文件夹
+///
+/// @param dirfd 目录的文件描述符，或者AT_FDCWD，含义与[`user_path_at`]相同
+pub fn do_remove_dir(dirfd: i32, path: &str) -> Result<u64, SystemError> {
+    let (parent_inode, filename) = lookup_parent_at(dirfd, path)?;
+
     let target_inode: Arc<dyn IndexNode> = parent_inode.find(filename)?;
     if target_inode.metadata()?.file_type != FileType::Dir {
         return Err(SystemError::ENOTDIR);
@@ -242,34 +425,18 @@ pub fn do_remove_dir(path: &str) -> Result<u64, SystemError> {
 }
 
 /// @brief 删除文件
-pub fn do_unlink_at(path: &str, _mode: FileMode) -> Result<u64, SystemError> {
-    // 文件名过长
-    if path.len() > MAX_PATHLEN as usize {
-        return Err(SystemError::ENAMETOOLONG);
-    }
-
-    let inode: Result<Arc<dyn IndexNode>, SystemError> = ROOT_INODE().lookup(path);
+///
+/// @param dirfd 目录的文件描述符，或者AT_FDCWD，含义与[`user_path_at`]相同
+pub fn do_unlink_at(dirfd: i32, path: &str, _mode: FileMode) -> Result<u64, SystemError> {
+    let (parent_inode, filename) = lookup_parent_at(dirfd, path)?;
 
-    if inode.is_err() {
-        let errno = inode.clone().unwrap_err();
-        // 文件不存在，且需要创建
-        if errno == SystemError::ENOENT {
-            return Err(SystemError::ENOENT);
-        }
-    }
+    // 不跟随符号链接，删除的是链接本身
+    let target_inode: Arc<dyn IndexNode> = parent_inode.find(filename)?;
     // 禁止在目录上unlink
-    if inode.unwrap().metadata()?.file_type == FileType::Dir {
+    if target_inode.metadata()?.file_type == FileType::Dir {
         return Err(SystemError::EPERM);
     }
 
-    let (filename, parent_path) = rsplit_path(path);
-    // 查找父目录
-    let parent_inode: Arc<dyn IndexNode> = ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
-
-    if parent_inode.metadata()?.file_type != FileType::Dir {
-        return Err(SystemError::ENOTDIR);
-    }
-
     // 删除文件
     parent_inode.unlink(filename)?;
 
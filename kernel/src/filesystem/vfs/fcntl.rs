@@ -60,3 +60,33 @@ pub enum FcntlCommand {
 
 /// for F_[GET|SET]FL
 pub const FD_CLOEXEC: u32 = 1;
+
+bitflags! {
+    /// 文件的封印，用于F_ADD_SEALS和F_GET_SEALS
+    pub struct FileSeals: u32 {
+        /// 不允许再添加封印
+        const F_SEAL_SEAL = 0x0001;
+        /// 不允许缩小文件
+        const F_SEAL_SHRINK = 0x0002;
+        /// 不允许增大文件
+        const F_SEAL_GROW = 0x0004;
+        /// 不允许写入文件，也不允许存在可写的映射
+        const F_SEAL_WRITE = 0x0008;
+        /// 不允许新的写入，已经存在的可写映射不受影响
+        const F_SEAL_FUTURE_WRITE = 0x0010;
+    }
+}
+
+/// *at系列系统调用中，表示相对于当前工作目录查找路径
+pub const AT_FDCWD: i32 = -100;
+/// 不跟随路径最后一级的符号链接
+pub const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+/// unlinkat()：删除文件夹
+pub const AT_REMOVEDIR: u32 = 0x200;
+/// 路径为空时，操作dirfd本身
+pub const AT_EMPTY_PATH: u32 = 0x1000;
+
+/// close_range()：关闭之前，先复制一份文件描述符表
+pub const CLOSE_RANGE_UNSHARE: u32 = 1 << 1;
+/// close_range()：不关闭文件描述符，而是为它们设置FD_CLOEXEC
+pub const CLOSE_RANGE_CLOEXEC: u32 = 1 << 2;
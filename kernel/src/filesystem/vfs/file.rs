@@ -1,21 +1,30 @@
 use core::mem::MaybeUninit;
 
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc};
 
 use crate::{
     driver::{
         base::{block::SeekFrom, device::DevicePrivateData},
+        input::InputFilePrivateData,
         tty::TtyFilePrivateData,
     },
-    filesystem::procfs::ProcfsFilePrivateData,
+    filesystem::{
+        devfs::{audit_dev::AuditFilePrivateData, kmsg_dev::KmsgFilePrivateData},
+        fuse::{dev::FuseDevPrivateData, fs::FuseFilePrivateData},
+        procfs::ProcfsFilePrivateData,
+    },
     ipc::pipe::PipeFsPrivateData,
     kerror,
     libs::spinlock::SpinLock,
+    net::socket::SocketFilePrivateData,
     process::ProcessManager,
     syscall::SystemError,
 };
 
-use super::{Dirent, FileType, IndexNode, InodeId, Metadata, SpecialNodeData};
+use super::{
+    fcntl::FD_CLOEXEC, DirEntry, Dirent, FileType, IndexNode, InodeId, LegacyDirent, Metadata,
+    SpecialNodeData,
+};
 
 /// 文件私有信息的枚举类型
 #[derive(Debug, Clone)]
@@ -28,6 +37,18 @@ pub enum FilePrivateData {
     DevFS(DevicePrivateData),
     /// tty设备文件的私有信息
     Tty(TtyFilePrivateData),
+    /// 输入设备文件的私有信息
+    Input(InputFilePrivateData),
+    /// /dev/kmsg文件的私有信息
+    Kmsg(KmsgFilePrivateData),
+    /// /dev/audit文件的私有信息
+    Audit(AuditFilePrivateData),
+    /// socket文件的私有信息
+    Socket(SocketFilePrivateData),
+    /// /dev/fuse文件的私有信息
+    FuseDev(FuseDevPrivateData),
+    /// FUSE文件系统中的文件的私有信息
+    FuseFile(FuseFilePrivateData),
     /// 不需要文件私有信息
     Unused,
 }
@@ -38,6 +59,24 @@ impl Default for FilePrivateData {
     }
 }
 
+impl FilePrivateData {
+    /// @brief 文件的打开模式被fcntl(F_SETFL)修改之后，更新私有信息中保存的打开模式
+    ///
+    /// 读写时需要知道文件是否以O_NONBLOCK打开的inode，会在私有信息中保存打开模式
+    pub fn update_mode(&mut self, mode: FileMode) {
+        match self {
+            FilePrivateData::Pipefs(p) => p.set_mode(mode),
+            FilePrivateData::Tty(p) => p.set_mode(mode),
+            FilePrivateData::Input(p) => p.mode = mode,
+            FilePrivateData::Kmsg(p) => p.set_mode(mode),
+            FilePrivateData::Audit(p) => p.set_mode(mode),
+            FilePrivateData::Socket(p) => p.set_mode(mode),
+            FilePrivateData::FuseDev(p) => p.set_mode(mode),
+            _ => {}
+        }
+    }
+}
+
 bitflags! {
     /// @brief 文件打开模式
     /// 其中，低2bit组合而成的数字的值，用于表示访问权限。其他的bit，才支持通过按位或的方式来表示参数
@@ -104,8 +143,6 @@ pub struct File {
     mode: FileMode,
     /// 文件类型
     file_type: FileType,
-    /// readdir时候用的，暂存的本次循环中，所有子目录项的名字的数组
-    readdir_subdirs_name: Vec<String>,
     pub private_data: FilePrivateData,
 }
 
@@ -131,7 +168,6 @@ impl File {
             offset: 0,
             mode,
             file_type,
-            readdir_subdirs_name: Vec::new(),
             private_data: FilePrivateData::default(),
         };
         // kdebug!("inode:{:?}",f.inode);
@@ -148,6 +184,25 @@ impl File {
     /// @return Ok(usize) 成功读取的字节数
     /// @return Err(SystemError) 错误码
     pub fn read(&mut self, len: usize, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let len = self.pread(self.offset, len, buf)?;
+        self.offset += len;
+        return Ok(len);
+    }
+
+    /// @brief 从文件的指定偏移量处读取数据到buffer中，不改变文件指针
+    ///
+    /// @param offset 开始读取的偏移量
+    /// @param len 要读取的字节数
+    /// @param buf 目标buffer
+    ///
+    /// @return Ok(usize) 成功读取的字节数
+    /// @return Err(SystemError) 错误码
+    pub fn pread(
+        &mut self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
         // 先检查本文件在权限等规则下，是否可读取。
         self.readable()?;
 
@@ -155,16 +210,14 @@ impl File {
             return Err(SystemError::ENOBUFS);
         }
 
-        // 如果文件指针已经超过了文件大小，则返回0
-        if self.offset > self.inode.metadata()?.size as usize {
+        // 如果偏移量已经超过了文件大小，则返回0。管道与字符设备没有大小，不需要检查
+        if !matches!(self.file_type, FileType::Pipe | FileType::CharDevice)
+            && offset > self.inode.metadata()?.size as usize
+        {
             return Ok(0);
         }
 
-        let len = self
-            .inode
-            .read_at(self.offset, len, buf, &mut self.private_data)?;
-        self.offset += len;
-        return Ok(len);
+        return self.inode.read_at(offset, len, buf, &mut self.private_data);
     }
 
     /// @brief 从buffer向文件写入指定的字节数的数据
@@ -175,22 +228,36 @@ impl File {
     /// @return Ok(usize) 成功写入的字节数
     /// @return Err(SystemError) 错误码
     pub fn write(&mut self, len: usize, buf: &[u8]) -> Result<usize, SystemError> {
+        let len = self.pwrite(self.offset, len, buf)?;
+        self.offset += len;
+        return Ok(len);
+    }
+
+    /// @brief 从buffer向文件的指定偏移量处写入数据，不改变文件指针
+    ///
+    /// @param offset 开始写入的偏移量
+    /// @param len 要写入的字节数
+    /// @param buf 源数据buffer
+    ///
+    /// @return Ok(usize) 成功写入的字节数
+    /// @return Err(SystemError) 错误码
+    pub fn pwrite(&mut self, offset: usize, len: usize, buf: &[u8]) -> Result<usize, SystemError> {
         // 先检查本文件在权限等规则下，是否可写入。
         self.writeable()?;
         if buf.len() < len {
             return Err(SystemError::ENOBUFS);
         }
 
-        // 如果文件指针已经超过了文件大小，则需要扩展文件大小
-        let file_size = self.inode.metadata()?.size as usize;
-        if self.offset > file_size {
-            self.inode.resize(self.offset)?;
+        // 如果偏移量已经超过了文件大小，则需要扩展文件大小。管道与字符设备没有大小，不需要扩展
+        if !matches!(self.file_type, FileType::Pipe | FileType::CharDevice) {
+            let file_size = self.inode.metadata()?.size as usize;
+            if offset > file_size {
+                self.inode.resize(offset)?;
+            }
         }
-        let len = self
+        return self
             .inode
-            .write_at(self.offset, len, buf, &mut self.private_data)?;
-        self.offset += len;
-        return Ok(len);
+            .write_at(offset, len, buf, &mut self.private_data);
     }
 
     /// @brief 获取文件的元数据
@@ -262,53 +329,48 @@ impl File {
         return Ok(());
     }
 
-    /// @biref 充填dirent结构体
-    /// @return 返回dirent结构体的大小
-    pub fn readdir(&mut self, dirent: &mut Dirent) -> Result<u64, SystemError> {
-        let inode: &Arc<dyn IndexNode> = &self.inode;
-
-        // 如果偏移量为0
-        if self.offset == 0 {
-            self.readdir_subdirs_name = inode.list()?;
-            self.readdir_subdirs_name.sort();
-        }
-        // kdebug!("sub_entries={sub_entries:?}");
-        if self.readdir_subdirs_name.is_empty() {
-            self.offset = 0;
-            return Ok(0);
+    /// @brief 从文件的当前偏移量(即目录项的序号)开始读取目录项，尽可能多地填充到buf中
+    ///
+    /// @param buf 输出缓冲区
+    /// @param dirent64 为true时按照linux_dirent64的格式填充，否则按照linux_dirent的格式填充
+    ///
+    /// @return 成功：Ok(填充的字节数)，读到目录的末尾时返回0
+    ///         失败：Err(ENOTDIR) 文件不是目录
+    ///               Err(EINVAL) 缓冲区放不下一个目录项
+    pub fn readdir(&mut self, buf: &mut [u8], dirent64: bool) -> Result<usize, SystemError> {
+        if self.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
         }
-        let name: String = self.readdir_subdirs_name.remove(0);
-        let sub_inode: Arc<dyn IndexNode> = match inode.find(&name) {
-            Ok(i) => i,
-            Err(e) => {
-                kerror!(
-                    "Readdir error: Failed to find sub inode:{name:?}, file={self:?}, error={e:?}"
-                );
-                return Err(e);
-            }
+        let write_dirent: fn(&mut [u8], &DirEntry, i64) -> Option<usize> = if dirent64 {
+            Dirent::write_to
+        } else {
+            LegacyDirent::write_to
         };
 
-        let name_bytes: &[u8] = name.as_bytes();
-
-        self.offset += 1;
-        dirent.d_ino = sub_inode.metadata().unwrap().inode_id.into() as u64;
-        dirent.d_type = sub_inode.metadata().unwrap().file_type.get_file_type_num() as u8;
-        // 根据posix的规定，dirent中的d_name是一个不定长的数组，因此需要unsafe来拷贝数据
-        unsafe {
-            let ptr = &mut dirent.d_name as *mut u8;
-            let buf: &mut [u8] =
-                ::core::slice::from_raw_parts_mut::<'static, u8>(ptr, name_bytes.len());
-            buf.copy_from_slice(name_bytes);
-        }
-
-        // 计算dirent结构体的大小
-        let size = (name_bytes.len() + ::core::mem::size_of::<Dirent>()
-            - ::core::mem::size_of_val(&dirent.d_name)) as u64;
-
-        dirent.d_reclen = size as u16;
-        dirent.d_off += dirent.d_reclen as i64;
+        let start = self.offset;
+        let mut written = 0;
+        let mut count = 0;
+        let mut too_small = false;
+        let next = self.inode.list_at(start, &mut |entry| {
+            let next_off = (start + count + 1) as i64;
+            match write_dirent(&mut buf[written..], &entry, next_off) {
+                Some(reclen) => {
+                    written += reclen;
+                    count += 1;
+                    true
+                }
+                None => {
+                    too_small = count == 0;
+                    false
+                }
+            }
+        })?;
 
-        return Ok(size);
+        if too_small {
+            return Err(SystemError::EINVAL);
+        }
+        self.offset = next;
+        return Ok(written);
     }
 
     pub fn inode(&self) -> Arc<dyn IndexNode> {
@@ -324,7 +386,6 @@ impl File {
             offset: self.offset.clone(),
             mode: self.mode.clone(),
             file_type: self.file_type.clone(),
-            readdir_subdirs_name: self.readdir_subdirs_name.clone(),
             private_data: self.private_data.clone(),
         };
         // 调用inode的open方法，让inode知道有新的文件打开了这个inode
@@ -348,6 +409,8 @@ impl File {
     }
 
     /// 获取文件是否在execve时关闭
+    ///
+    /// 文件被放入文件描述符表时，这个标志会被转移到文件描述符的[`FdFlags`]中
     #[inline]
     pub fn close_on_exec(&self) -> bool {
         return self.mode.contains(FileMode::O_CLOEXEC);
@@ -363,12 +426,10 @@ impl File {
         }
     }
 
+    /// @brief 设置文件的打开模式，并同步到文件的私有信息中
     pub fn set_mode(&mut self, mode: FileMode) -> Result<(), SystemError> {
-        // todo: 是否需要调用inode的open方法，以更新private data（假如它与mode有关的话）?
-        // 也许需要加个更好的设计，让inode知晓文件的打开模式发生了变化，让它自己决定是否需要更新private data
-
-        // 直接修改文件的打开模式
         self.mode = mode;
+        self.private_data.update_mode(mode);
         return Ok(());
     }
 
@@ -404,11 +465,24 @@ impl Drop for File {
     }
 }
 
+bitflags! {
+    /// 文件描述符的标志(fcntl的F_GETFD、F_SETFD)
+    ///
+    /// 与文件的打开模式不同，这些标志属于文件描述符本身，dup出来的文件描述符不会继承它们
+    #[derive(Default)]
+    pub struct FdFlags: u32 {
+        /// 执行execve时关闭这个文件描述符
+        const CLOEXEC = FD_CLOEXEC;
+    }
+}
+
 /// @brief pcb里面的文件描述符数组
 #[derive(Debug)]
 pub struct FileDescriptorVec {
     /// 当前进程打开的文件描述符
     fds: [Option<Arc<SpinLock<File>>>; FileDescriptorVec::PROCESS_MAX_FD],
+    /// 每个文件描述符的标志
+    fd_flags: [FdFlags; FileDescriptorVec::PROCESS_MAX_FD],
 }
 
 impl FileDescriptorVec {
@@ -432,7 +506,10 @@ impl FileDescriptorVec {
         };
 
         // 初始化文件描述符数组结构体
-        return FileDescriptorVec { fds: data };
+        return FileDescriptorVec {
+            fds: data,
+            fd_flags: [FdFlags::empty(); FileDescriptorVec::PROCESS_MAX_FD],
+        };
     }
 
     /// @brief 克隆一个文件描述符数组
@@ -444,6 +521,7 @@ impl FileDescriptorVec {
             if let Some(file) = &self.fds[i] {
                 if let Some(file) = file.lock().try_clone() {
                     res.fds[i] = Some(Arc::new(SpinLock::new(file)));
+                    res.fd_flags[i] = self.fd_flags[i];
                 }
             }
         }
@@ -457,7 +535,7 @@ impl FileDescriptorVec {
     /// @return false 不合法
     #[inline]
     pub fn validate_fd(fd: i32) -> bool {
-        if fd < 0 || fd as usize > FileDescriptorVec::PROCESS_MAX_FD {
+        if fd < 0 || fd as usize >= FileDescriptorVec::PROCESS_MAX_FD {
             return false;
         } else {
             return true;
@@ -468,34 +546,57 @@ impl FileDescriptorVec {
     ///
     /// ## 参数
     ///
-    /// - `file` 要存放的文件对象
+    /// - `file` 要存放的文件对象。如果它的打开模式中有O_CLOEXEC，那么新的文件描述符会被设置[`FdFlags::CLOEXEC`]
     /// - `fd` 如果为Some(i32)，表示指定要申请这个文件描述符，如果这个文件描述符已经被使用，那么返回EBADF
     ///
     /// ## 返回值
     ///
     /// - `Ok(i32)` 申请成功，返回申请到的文件描述符
     /// - `Err(SystemError)` 申请失败，返回错误码，并且，file对象将被drop掉
-    pub fn alloc_fd(&mut self, file: File, fd: Option<i32>) -> Result<i32, SystemError> {
-        if fd.is_some() {
+    pub fn alloc_fd(&mut self, mut file: File, fd: Option<i32>) -> Result<i32, SystemError> {
+        let new_fd = match fd {
             // 指定了要申请的文件描述符编号
-            let new_fd = fd.unwrap();
-            let x = &mut self.fds[new_fd as usize];
-            if x.is_none() {
-                *x = Some(Arc::new(SpinLock::new(file)));
-                return Ok(new_fd);
-            } else {
-                return Err(SystemError::EBADF);
-            }
-        } else {
-            // 没有指定要申请的文件描述符编号
-            for i in 0..FileDescriptorVec::PROCESS_MAX_FD {
-                if self.fds[i].is_none() {
-                    self.fds[i] = Some(Arc::new(SpinLock::new(file)));
-                    return Ok(i as i32);
+            Some(fd) => {
+                if !FileDescriptorVec::validate_fd(fd) || self.fds[fd as usize].is_some() {
+                    return Err(SystemError::EBADF);
                 }
+                fd
             }
-            return Err(SystemError::EMFILE);
-        }
+            // 没有指定要申请的文件描述符编号
+            None => self.next_free_fd(0).ok_or(SystemError::EMFILE)?,
+        };
+
+        let mut flags = FdFlags::empty();
+        flags.set(FdFlags::CLOEXEC, file.close_on_exec());
+        file.set_close_on_exec(false);
+
+        self.fds[new_fd as usize] = Some(Arc::new(SpinLock::new(file)));
+        self.fd_flags[new_fd as usize] = flags;
+        return Ok(new_fd);
+    }
+
+    /// 查找不小于`start`的最小的空闲文件描述符
+    pub fn next_free_fd(&self, start: usize) -> Option<i32> {
+        return (start..FileDescriptorVec::PROCESS_MAX_FD)
+            .find(|&i| self.fds[i].is_none())
+            .map(|i| i as i32);
+    }
+
+    /// 获取文件描述符的标志，文件描述符不存在时返回None
+    pub fn fd_flags(&self, fd: i32) -> Option<FdFlags> {
+        self.get_file_by_fd(fd)?;
+        return Some(self.fd_flags[fd as usize]);
+    }
+
+    /// 设置文件描述符的标志
+    ///
+    /// ## 错误
+    ///
+    /// - `EBADF`：文件描述符不存在
+    pub fn set_fd_flags(&mut self, fd: i32, flags: FdFlags) -> Result<(), SystemError> {
+        self.get_file_by_fd(fd).ok_or(SystemError::EBADF)?;
+        self.fd_flags[fd as usize] = flags;
+        return Ok(());
     }
 
     /// 根据文件描述符序号，获取文件结构体的Arc指针
@@ -525,6 +626,7 @@ impl FileDescriptorVec {
 
         // 把文件描述符数组对应位置设置为空
         let file = self.fds[fd as usize].take().unwrap();
+        self.fd_flags[fd as usize] = FdFlags::empty();
 
         assert!(Arc::strong_count(&file) == 1);
         return Ok(());
@@ -534,20 +636,18 @@ impl FileDescriptorVec {
         return FileDescriptorIterator::new(self);
     }
 
+    /// 关闭所有设置了[`FdFlags::CLOEXEC`]的文件描述符
     pub fn close_on_exec(&mut self) {
         for i in 0..FileDescriptorVec::PROCESS_MAX_FD {
-            if let Some(file) = &self.fds[i] {
-                let to_drop = file.lock().close_on_exec();
-                if to_drop {
-                    let r = self.drop_fd(i as i32);
-                    if let Err(r) = r {
-                        kerror!(
-                            "Failed to close file: pid = {:?}, fd = {}, error = {:?}",
-                            ProcessManager::current_pcb().pid(),
-                            i,
-                            r
-                        );
-                    }
+            if self.fds[i].is_some() && self.fd_flags[i].contains(FdFlags::CLOEXEC) {
+                let r = self.drop_fd(i as i32);
+                if let Err(r) = r {
+                    kerror!(
+                        "Failed to close file: pid = {:?}, fd = {}, error = {:?}",
+                        ProcessManager::current_pcb().pid(),
+                        i,
+                        r
+                    );
                 }
             }
         }
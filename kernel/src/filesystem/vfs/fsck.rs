@@ -0,0 +1,138 @@
+//! 挂载时的一致性检查
+//!
+//! 文件系统可以实现[`FileSystem::fsck`]，在挂载时检查超级块的魔数与校验和、未正常卸载的标志等。
+//! 检查发现的问题会被输出到内核日志中。发现错误时，根据启动参数`fsck.errors=`决定如何处理：
+//!
+//! - `continue`: 照常挂载
+//! - `ro`: 以只读方式挂载，防止进一步破坏已经损坏的文件系统(默认)
+//! - `refuse`: 拒绝挂载，返回`EUCLEAN`
+//!
+//! 只有警告(例如文件系统没有被正常卸载)时，总是照常挂载。
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{init::cmdline::ParamValue, kernel_param, kerror, kinfo, kwarn, syscall::SystemError};
+
+use super::FileSystem;
+
+/// 检查发现的问题的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsckSeverity {
+    /// 不影响文件系统的一致性，例如文件系统没有被正常卸载
+    Warning,
+    /// 文件系统的元数据已经损坏，继续写入可能造成进一步的破坏
+    Error,
+}
+
+/// 检查发现的一个问题
+#[derive(Debug, Clone)]
+pub struct FsckProblem {
+    pub severity: FsckSeverity,
+    pub message: String,
+}
+
+/// 一次检查的结果
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    problems: Vec<FsckProblem>,
+}
+
+impl FsckReport {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// 记录一个警告
+    pub fn warn(&mut self, message: String) {
+        self.problems.push(FsckProblem {
+            severity: FsckSeverity::Warning,
+            message,
+        });
+    }
+
+    /// 记录一个错误
+    pub fn error(&mut self, message: String) {
+        self.problems.push(FsckProblem {
+            severity: FsckSeverity::Error,
+            message,
+        });
+    }
+
+    pub fn problems(&self) -> &[FsckProblem] {
+        return &self.problems;
+    }
+
+    pub fn is_clean(&self) -> bool {
+        return self.problems.is_empty();
+    }
+
+    pub fn has_errors(&self) -> bool {
+        return self
+            .problems
+            .iter()
+            .any(|p| p.severity == FsckSeverity::Error);
+    }
+}
+
+/// 启动参数`fsck.errors=`的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FsckErrorPolicy {
+    Continue,
+    ReadOnly,
+    Refuse,
+}
+
+impl ParamValue for FsckErrorPolicy {
+    fn parse_param(value: &str) -> Option<Self> {
+        match value {
+            "continue" => Some(Self::Continue),
+            "ro" => Some(Self::ReadOnly),
+            "refuse" => Some(Self::Refuse),
+            _ => None,
+        }
+    }
+}
+
+kernel_param!(FSCK_ERRORS_PARAM: FsckErrorPolicy = "fsck.errors");
+
+/// 在挂载之前检查文件系统
+///
+/// ## 参数
+///
+/// - `fs`: 将要被挂载的文件系统
+///
+/// ## 返回值
+///
+/// 文件系统是否应当以只读方式挂载
+///
+/// ## 错误
+///
+/// - `EUCLEAN`: 发现错误，并且启动参数要求拒绝挂载
+/// - 其余错误由[`FileSystem::fsck`]返回，例如读取超级块失败
+pub fn fsck_on_mount(fs: &dyn FileSystem) -> Result<bool, SystemError> {
+    let report = fs.fsck()?;
+    for problem in report.problems() {
+        match problem.severity {
+            FsckSeverity::Warning => kwarn!("fsck: {}", problem.message),
+            FsckSeverity::Error => kerror!("fsck: {}", problem.message),
+        }
+    }
+    if !report.has_errors() {
+        return Ok(false);
+    }
+
+    match FSCK_ERRORS_PARAM.get().unwrap_or(FsckErrorPolicy::ReadOnly) {
+        FsckErrorPolicy::Continue => {
+            kwarn!("fsck: errors found, mounting read-write as requested");
+            return Ok(false);
+        }
+        FsckErrorPolicy::ReadOnly => {
+            kinfo!("fsck: errors found, mounting read-only");
+            return Ok(true);
+        }
+        FsckErrorPolicy::Refuse => {
+            kerror!("fsck: errors found, refusing to mount");
+            return Err(SystemError::EUCLEAN);
+        }
+    }
+}
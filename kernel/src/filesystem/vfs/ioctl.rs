@@ -0,0 +1,207 @@
+//! ioctl命令号的编码，以及ioctl参数在用户空间与内核之间的拷贝
+//!
+//! 命令号的编码与Linux一致(x86_64、riscv64)：
+//!
+//! ```text
+//!  31  30 29          16 15       8 7        0
+//! +------+--------------+----------+----------+
+//! | dir  |     size     |   type   |    nr    |
+//! +------+--------------+----------+----------+
+//! ```
+//!
+//! 驱动使用[`ioc_none!`](crate::ioc_none)、[`ioc_read!`](crate::ioc_read)、
+//! [`ioc_write!`](crate::ioc_write)、[`ioc_readwrite!`](crate::ioc_readwrite)定义命令号，
+//! 然后在[`IndexNode::ioctl`](super::IndexNode::ioctl)中通过[`IoctlArg`]读写参数，
+//! 参数的大小与方向会根据命令号的编码进行检查。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/asm-generic/ioctl.h
+
+use core::mem::size_of;
+
+use crate::syscall::{
+    user_access::{UserBufferReader, UserBufferWriter},
+    SystemError,
+};
+
+pub const IOC_NRBITS: u32 = 8;
+pub const IOC_TYPEBITS: u32 = 8;
+pub const IOC_SIZEBITS: u32 = 14;
+pub const IOC_DIRBITS: u32 = 2;
+
+pub const IOC_NRSHIFT: u32 = 0;
+pub const IOC_TYPESHIFT: u32 = IOC_NRSHIFT + IOC_NRBITS;
+pub const IOC_SIZESHIFT: u32 = IOC_TYPESHIFT + IOC_TYPEBITS;
+pub const IOC_DIRSHIFT: u32 = IOC_SIZESHIFT + IOC_SIZEBITS;
+
+/// 没有参数
+pub const IOC_NONE: u32 = 0;
+/// 用户程序向内核写入参数
+pub const IOC_WRITE: u32 = 1;
+/// 用户程序从内核读取参数
+pub const IOC_READ: u32 = 2;
+
+/// 编码一个ioctl命令号
+///
+/// ## 参数
+///
+/// - `dir`: 参数的方向，[`IOC_NONE`]、[`IOC_READ`]、[`IOC_WRITE`]的组合
+/// - `ty`: 命令的类型(一般是驱动专属的一个字符)
+/// - `nr`: 命令在这个类型中的编号
+/// - `size`: 参数的大小
+pub const fn ioc(dir: u32, ty: u32, nr: u32, size: usize) -> u32 {
+    (dir << IOC_DIRSHIFT)
+        | (ty << IOC_TYPESHIFT)
+        | (nr << IOC_NRSHIFT)
+        | ((size as u32) << IOC_SIZESHIFT)
+}
+
+/// 定义一个没有参数的ioctl命令号，即Linux的`_IO(type, nr)`
+#[macro_export]
+macro_rules! ioc_none {
+    ($ty:expr, $nr:expr) => {
+        $crate::filesystem::vfs::ioctl::ioc(
+            $crate::filesystem::vfs::ioctl::IOC_NONE,
+            $ty as u32,
+            $nr as u32,
+            0,
+        )
+    };
+}
+
+/// 定义一个用户程序从内核读取参数的ioctl命令号，即Linux的`_IOR(type, nr, T)`
+#[macro_export]
+macro_rules! ioc_read {
+    ($ty:expr, $nr:expr, $arg:ty) => {
+        $crate::filesystem::vfs::ioctl::ioc(
+            $crate::filesystem::vfs::ioctl::IOC_READ,
+            $ty as u32,
+            $nr as u32,
+            ::core::mem::size_of::<$arg>(),
+        )
+    };
+}
+
+/// 定义一个用户程序向内核写入参数的ioctl命令号，即Linux的`_IOW(type, nr, T)`
+#[macro_export]
+macro_rules! ioc_write {
+    ($ty:expr, $nr:expr, $arg:ty) => {
+        $crate::filesystem::vfs::ioctl::ioc(
+            $crate::filesystem::vfs::ioctl::IOC_WRITE,
+            $ty as u32,
+            $nr as u32,
+            ::core::mem::size_of::<$arg>(),
+        )
+    };
+}
+
+/// 定义一个参数双向传递的ioctl命令号，即Linux的`_IOWR(type, nr, T)`
+#[macro_export]
+macro_rules! ioc_readwrite {
+    ($ty:expr, $nr:expr, $arg:ty) => {
+        $crate::filesystem::vfs::ioctl::ioc(
+            $crate::filesystem::vfs::ioctl::IOC_READ | $crate::filesystem::vfs::ioctl::IOC_WRITE,
+            $ty as u32,
+            $nr as u32,
+            ::core::mem::size_of::<$arg>(),
+        )
+    };
+}
+
+/// 一次ioctl调用的命令号以及参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoctlArg {
+    cmd: u32,
+    data: usize,
+}
+
+impl IoctlArg {
+    pub const fn new(cmd: u32, data: usize) -> Self {
+        Self { cmd, data }
+    }
+
+    pub const fn cmd(&self) -> u32 {
+        self.cmd
+    }
+
+    /// 参数的原始值(一般是用户空间的地址)
+    pub const fn data(&self) -> usize {
+        self.data
+    }
+
+    /// 参数的方向
+    pub const fn dir(&self) -> u32 {
+        (self.cmd >> IOC_DIRSHIFT) & ((1 << IOC_DIRBITS) - 1)
+    }
+
+    /// 命令的类型
+    pub const fn ty(&self) -> u32 {
+        (self.cmd >> IOC_TYPESHIFT) & ((1 << IOC_TYPEBITS) - 1)
+    }
+
+    /// 命令在它的类型中的编号
+    pub const fn nr(&self) -> u32 {
+        (self.cmd >> IOC_NRSHIFT) & ((1 << IOC_NRBITS) - 1)
+    }
+
+    /// 命令号中编码的参数大小
+    pub const fn size(&self) -> usize {
+        ((self.cmd >> IOC_SIZESHIFT) & ((1 << IOC_SIZEBITS) - 1)) as usize
+    }
+
+    /// 检查命令号中编码的方向与大小是否与`T`匹配
+    ///
+    /// 早期的命令号(如`TCGETS`)没有编码方向以及大小，这样的命令号不做检查
+    fn check<T>(&self, dir: u32) -> Result<(), SystemError> {
+        if self.dir() == IOC_NONE {
+            return Ok(());
+        }
+        if self.dir() & dir == 0 || self.size() != size_of::<T>() {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(());
+    }
+
+    /// 从用户空间拷贝参数到`val`
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：命令号的方向不包含[`IOC_WRITE`]，或者编码的大小与`T`不一致
+    /// - `EFAULT`：参数的地址不合法
+    pub fn copy_in<T: Copy>(&self, val: &mut T) -> Result<(), SystemError> {
+        self.check::<T>(IOC_WRITE)?;
+        let reader = UserBufferReader::new(self.data as *const T, size_of::<T>(), true)?;
+        reader.copy_one_from_user(val, 0)?;
+        return Ok(());
+    }
+
+    /// 把参数拷贝回用户空间
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：命令号的方向不包含[`IOC_READ`]，或者编码的大小与`T`不一致
+    /// - `EFAULT`：参数的地址不合法
+    pub fn copy_out<T: Copy>(&self, val: &T) -> Result<(), SystemError> {
+        self.check::<T>(IOC_READ)?;
+        let mut writer = UserBufferWriter::new(self.data as *mut T, size_of::<T>(), true)?;
+        writer.copy_one_to_user(val, 0)?;
+        return Ok(());
+    }
+
+    /// 把变长的字节数组拷贝回用户空间，长度超出命令号中编码的大小时截断
+    ///
+    /// ## 返回值
+    ///
+    /// 拷贝的字节数
+    pub fn copy_out_bytes(&self, bytes: &[u8]) -> Result<usize, SystemError> {
+        if self.dir() & IOC_READ == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let len = core::cmp::min(self.size(), bytes.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        let mut writer = UserBufferWriter::new(self.data as *mut u8, len, true)?;
+        writer.copy_to_user(&bytes[..len], 0)?;
+        return Ok(len);
+    }
+}
@@ -3,7 +3,11 @@
 pub mod core;
 pub mod fcntl;
 pub mod file;
+pub mod fsck;
+pub mod ioctl;
 pub mod mount;
+pub mod namespace;
+pub mod poll;
 pub mod syscall;
 mod utils;
 
@@ -15,12 +19,21 @@ use crate::{
     driver::base::{block::block_device::BlockDevice, char::CharDevice, device::DeviceNumber},
     ipc::pipe::LockedPipeInode,
     libs::casting::DowncastArc,
+    mm::{
+        allocator::page_frame::{PageFrameCount, PhysPageFrame},
+        syscall::ProtFlags,
+    },
     syscall::SystemError,
     time::TimeSpec,
 };
 
-use self::{core::generate_inode_id, file::FileMode, syscall::ModeType};
-pub use self::{core::ROOT_INODE, file::FilePrivateData, mount::MountFS};
+pub use self::{core::ROOT_INODE, file::FilePrivateData, mount::MountFS, poll::PollTable};
+use self::{
+    core::{generate_inode_id, is_same_inode},
+    file::FileMode,
+    fsck::FsckReport,
+    syscall::ModeType,
+};
 
 /// vfs容许的最大的路径名称长度
 pub const MAX_PATHLEN: usize = 1024;
@@ -104,6 +117,8 @@ bitflags! {
         const WRITE = 1u8 << 0;
         const READ = 1u8 << 1;
         const ERROR = 1u8 << 2;
+        /// 对端已经关闭(如管道的写端全部被关闭)
+        const HUP = 1u8 << 3;
     }
 }
 
@@ -162,8 +177,13 @@ pub trait IndexNode: Any + Sync + Send + Debug {
 
     /// @brief 获取当前inode的状态。
     ///
+    /// 文件的状态会发生变化的inode，需要通过table.wait()把当前进程注册到相应的等待队列上，
+    /// 并且在状态变化时唤醒等待队列，这样poll、select才能在文件就绪时被唤醒
+    ///
+    /// @param table 本次poll使用的等待表
+    ///
     /// @return PollStatus结构体
-    fn poll(&self) -> Result<PollStatus, SystemError>;
+    fn poll(&self, table: &mut PollTable) -> Result<PollStatus, SystemError>;
 
     /// @brief 获取inode的元数据
     ///
@@ -323,14 +343,36 @@ pub trait IndexNode: Any + Sync + Send + Debug {
 
     /// @brief io control接口
     ///
+    /// 命令号以及参数的拷贝见[`ioctl`]模块，实现者可以通过[`ioctl::IoctlArg`]按照命令号的编码读写参数
+    ///
     /// @param cmd 命令
     /// @param data 数据
     ///
     /// @return 成功：Ok()
     ///         失败：Err(错误码)
     fn ioctl(&self, _cmd: u32, _data: usize) -> Result<usize, SystemError> {
-        // 若文件系统没有实现此方法，则返回“不支持”
-        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        // 若文件系统没有实现此方法，则与Linux一致，返回ENOTTY
+        return Err(SystemError::ENOTTY);
+    }
+
+    /// @brief 获取用于mmap的物理页帧
+    ///
+    /// 映射期间，调用者会持有返回的所有者对象，页帧在此期间不能被释放
+    ///
+    /// @param offset 映射的起始位置在文件中的偏移量，已经按页对齐
+    /// @param count 要映射的页数
+    /// @param prot_flags 映射的保护标志
+    ///
+    /// @return 成功：Ok((页帧, 页帧的所有者))
+    ///         失败：Err(错误码)
+    fn mmap_frames(
+        &self,
+        _offset: usize,
+        _count: PageFrameCount,
+        _prot_flags: ProtFlags,
+    ) -> Result<(Vec<PhysPageFrame>, Arc<dyn Any + Send + Sync>), SystemError> {
+        // 若文件系统没有实现此方法，则表示不支持映射
+        return Err(SystemError::ENODEV);
     }
 
     /// @brief 获取inode所在的文件系统的指针
@@ -343,6 +385,41 @@ pub trait IndexNode: Any + Sync + Send + Debug {
     /// @brief 列出当前inode下的所有目录项的名字
     fn list(&self) -> Result<Vec<String>, SystemError>;
 
+    /// @brief 从第offset个目录项开始，依次把目录项交给filler，直到filler返回false或者没有更多的目录项
+    ///
+    /// 目录项的序号在两次调用之间需要保持稳定，这样getdents才能从上次结束的位置继续读取。
+    /// 默认实现基于[`IndexNode::list`]，按照名字排序来保证顺序稳定。
+    /// 如果有条件，请在文件系统中使用高效的方式实现本接口。
+    ///
+    /// @param offset 第一个要列出的目录项的序号
+    /// @param filler 接收目录项，返回false表示不再接收，此时这个目录项不会被计入
+    ///
+    /// @return 成功：Ok(下一个要列出的目录项的序号)
+    ///         失败：Err(错误码)
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        let mut names = self.list()?;
+        names.sort();
+
+        let mut pos = offset;
+        for name in names.into_iter().skip(offset) {
+            let metadata = self.find(&name)?.metadata()?;
+            let entry = DirEntry {
+                name,
+                ino: metadata.inode_id,
+                file_type: metadata.file_type,
+            };
+            if !filler(entry) {
+                break;
+            }
+            pos += 1;
+        }
+        return Ok(pos);
+    }
+
     /// @brief 在当前Inode下，挂载一个新的文件系统
     /// 请注意！该函数只能被MountFS实现，其他文件系统不应实现这个函数
     fn mount(&self, _fs: Arc<dyn FileSystem>) -> Result<Arc<MountFS>, SystemError> {
@@ -456,7 +533,12 @@ impl dyn IndexNode {
                 continue;
             }
 
-            let inode = result.find(&name)?;
+            // 不能通过".."越过进程的根目录(chroot)
+            let inode = if name == ".." && is_same_inode(&result, &ROOT_INODE())? {
+                result.clone()
+            } else {
+                result.find(&name)?
+            };
 
             // 处理符号链接的问题
             if inode.metadata()?.file_type == FileType::SymLink && max_follow_times > 0 {
@@ -560,6 +642,16 @@ pub trait FileSystem: Any + Sync + Send + Debug {
     /// @brief 获取当前文件系统的信息
     fn info(&self) -> FsInfo;
 
+    /// @brief 挂载时检查文件系统的一致性，例如超级块的魔数与校验和、未正常卸载的标志
+    ///
+    /// 发现的问题记录在返回的报告中，由[`fsck::fsck_on_mount`]决定如何处理。默认不做任何检查
+    ///
+    /// @return Ok(FsckReport) 检查的结果
+    /// @return Err(SystemError) 无法完成检查，例如读取超级块失败，此时挂载会失败
+    fn fsck(&self) -> Result<FsckReport, SystemError> {
+        return Ok(FsckReport::new());
+    }
+
     /// @brief 本函数用于实现动态转换。
     /// 具体的文件系统在实现本函数时，最简单的方式就是：直接返回self
     fn as_any_ref(&self) -> &dyn Any;
@@ -579,22 +671,166 @@ pub struct FsInfo {
     pub max_name_len: usize,
 }
 
+/// @brief 为子节点保存在内存中的文件系统实现[`IndexNode::list_at`]
+///
+/// 目录项的序号：0为"."，1为".."，之后按照`children`的顺序排列。
+/// 因此`children`的顺序需要在两次调用之间保持稳定，例如按名字排序的BTreeMap
+///
+/// @param ino 当前目录的inode号
+/// @param parent_ino 父目录的inode号，根目录传入它自己的inode号。
+///                   调用者需要在放开当前目录的锁之后再获取，避免与从父到子的加锁顺序相反
+/// @param children 当前目录的所有子节点，由本函数跳过序号小于offset的子节点
+/// @param offset 第一个要列出的目录项的序号
+/// @param filler 接收目录项，返回false表示不再接收
+///
+/// @return 成功：Ok(下一个要列出的目录项的序号)
+///         失败：Err(错误码)
+pub fn list_dir_entries<'a, C, I>(
+    ino: InodeId,
+    parent_ino: InodeId,
+    children: I,
+    offset: usize,
+    filler: &mut dyn FnMut(DirEntry) -> bool,
+) -> Result<usize, SystemError>
+where
+    C: IndexNode + ?Sized + 'a,
+    I: Iterator<Item = (&'a String, &'a Arc<C>)>,
+{
+    let mut pos = offset;
+    for (name, ino) in [(".", ino), ("..", parent_ino)].into_iter().skip(offset) {
+        let entry = DirEntry {
+            name: String::from(name),
+            ino,
+            file_type: FileType::Dir,
+        };
+        if !filler(entry) {
+            return Ok(pos);
+        }
+        pos += 1;
+    }
+
+    for (name, child) in children.skip(pos - 2) {
+        let metadata = child.metadata()?;
+        let entry = DirEntry {
+            name: name.clone(),
+            ino: metadata.inode_id,
+            file_type: metadata.file_type,
+        };
+        if !filler(entry) {
+            break;
+        }
+        pos += 1;
+    }
+    return Ok(pos);
+}
+
 /// @brief 整合主设备号+次设备号
 pub fn make_rawdev(major: usize, minor: usize) -> usize {
     ((major & 0xffffff) << 8) | (minor & 0xff)
 }
 
-/// @brief
+/// @brief 目录项，由[`IndexNode::list_at`]列出
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// 目录项的名字
+    pub name: String,
+    /// 目录项对应的inode号
+    pub ino: InodeId,
+    /// 目录项的类型
+    pub file_type: FileType,
+}
+
+/// @brief getdents64返回给用户程序的目录项，与Linux的struct linux_dirent64的内存布局一致
+///
+/// 结构体只用于描述内存布局，目录项由[`Dirent::write_to`]逐字节写入
 #[repr(C)]
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct Dirent {
     d_ino: u64,    // 文件序列号
-    d_off: i64,    // dir偏移量
-    d_reclen: u16, // 目录下的记录数
+    d_off: i64,    // 下一个目录项的偏移量
+    d_reclen: u16, // 当前目录项的长度
     d_type: u8,    // entry的类型
     d_name: u8,    // 文件entry的名字(是一个零长数组)， 本字段仅用于占位
 }
 
+impl Dirent {
+    /// d_name在结构体中的偏移量
+    const NAME_OFFSET: usize = 19;
+
+    /// @brief 把目录项按照linux_dirent64的格式写入buf
+    ///
+    /// @param buf 输出缓冲区
+    /// @param entry 目录项
+    /// @param next_off 下一个目录项的偏移量
+    ///
+    /// @return 成功：Some(写入的字节数，按8字节对齐)
+    ///         缓冲区空间不足：None
+    pub fn write_to(buf: &mut [u8], entry: &DirEntry, next_off: i64) -> Option<usize> {
+        let name = entry.name.as_bytes();
+        // 文件名以'\0'结尾
+        let reclen = (Self::NAME_OFFSET + name.len() + 1 + 7) & !7;
+        if reclen > buf.len() {
+            return None;
+        }
+
+        let rec = &mut buf[..reclen];
+        rec.fill(0);
+        rec[0..8].copy_from_slice(&(entry.ino.into() as u64).to_ne_bytes());
+        rec[8..16].copy_from_slice(&next_off.to_ne_bytes());
+        rec[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+        rec[18] = entry.file_type.get_file_type_num() as u8;
+        rec[Self::NAME_OFFSET..Self::NAME_OFFSET + name.len()].copy_from_slice(name);
+        return Some(reclen);
+    }
+}
+
+/// @brief getdents返回给用户程序的目录项，与Linux的struct linux_dirent的内存布局一致
+///
+/// d_type不在结构体中，而是位于目录项的最后一个字节。目录项由[`LegacyDirent::write_to`]逐字节写入
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/readdir.c?fi=filldir#filldir
+#[repr(C)]
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct LegacyDirent {
+    d_ino: u64,    // 文件序列号
+    d_off: u64,    // 下一个目录项的偏移量
+    d_reclen: u16, // 当前目录项的长度
+    d_name: u8,    // 文件entry的名字(是一个零长数组)， 本字段仅用于占位
+}
+
+impl LegacyDirent {
+    /// d_name在结构体中的偏移量
+    const NAME_OFFSET: usize = 18;
+
+    /// @brief 把目录项按照linux_dirent的格式写入buf
+    ///
+    /// @param buf 输出缓冲区
+    /// @param entry 目录项
+    /// @param next_off 下一个目录项的偏移量
+    ///
+    /// @return 成功：Some(写入的字节数，按8字节对齐)
+    ///         缓冲区空间不足：None
+    pub fn write_to(buf: &mut [u8], entry: &DirEntry, next_off: i64) -> Option<usize> {
+        let name = entry.name.as_bytes();
+        // 文件名以'\0'结尾，之后还需要一个字节存放d_type
+        let reclen = (Self::NAME_OFFSET + name.len() + 2 + 7) & !7;
+        if reclen > buf.len() {
+            return None;
+        }
+
+        let rec = &mut buf[..reclen];
+        rec.fill(0);
+        rec[0..8].copy_from_slice(&(entry.ino.into() as u64).to_ne_bytes());
+        rec[8..16].copy_from_slice(&(next_off as u64).to_ne_bytes());
+        rec[16..18].copy_from_slice(&(reclen as u16).to_ne_bytes());
+        rec[Self::NAME_OFFSET..Self::NAME_OFFSET + name.len()].copy_from_slice(name);
+        rec[reclen - 1] = entry.file_type.get_file_type_num() as u8;
+        return Some(reclen);
+    }
+}
+
 impl Metadata {
     pub fn new(file_type: FileType, mode: ModeType) -> Self {
         Metadata {
@@ -1,17 +1,27 @@
 use core::{
     any::Any,
-    sync::atomic::{compiler_fence, Ordering},
+    sync::atomic::{compiler_fence, AtomicBool, Ordering},
 };
 
 use alloc::{
     collections::BTreeMap,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 
-use crate::{driver::base::device::DeviceNumber, libs::spinlock::SpinLock, syscall::SystemError};
+use crate::{
+    driver::base::device::DeviceNumber,
+    libs::{casting::DowncastArc, spinlock::SpinLock},
+    mm::{
+        allocator::page_frame::{PageFrameCount, PhysPageFrame},
+        syscall::ProtFlags,
+    },
+    syscall::SystemError,
+};
 
 use super::{
-    file::FileMode, syscall::ModeType, FilePrivateData, FileSystem, FileType, IndexNode, InodeId,
+    file::FileMode, fsck::fsck_on_mount, syscall::ModeType, DirEntry, FilePrivateData, FileSystem,
+    FileType, IndexNode, InodeId,
 };
 
 /// @brief 挂载文件系统
@@ -23,7 +33,9 @@ pub struct MountFS {
     /// 用来存储InodeID->挂载点的MountFS的B树
     mountpoints: SpinLock<BTreeMap<InodeId, Arc<MountFS>>>,
     /// 当前文件系统挂载到的那个挂载点的Inode
-    self_mountpoint: Option<Arc<MountFSInode>>,
+    self_mountpoint: SpinLock<Option<Arc<MountFSInode>>>,
+    /// 是否以只读方式挂载
+    readonly: AtomicBool,
     /// 指向当前MountFS的弱引用
     self_ref: Weak<MountFS>,
 }
@@ -47,12 +59,29 @@ impl MountFS {
         return MountFS {
             inner_filesystem: inner_fs,
             mountpoints: SpinLock::new(BTreeMap::new()),
-            self_mountpoint: self_mountpoint,
+            self_mountpoint: SpinLock::new(self_mountpoint),
+            readonly: AtomicBool::new(false),
             self_ref: Weak::default(),
         }
         .wrap();
     }
 
+    /// @brief 检查将要被挂载的文件系统，然后为它创建MountFS
+    ///
+    /// 检查发现错误时，根据启动参数决定以只读方式挂载还是拒绝挂载，参见[`fsck_on_mount`]
+    ///
+    /// @return Ok(Arc<MountFS>) 新的MountFS
+    /// @return Err(SystemError) 检查失败，或者拒绝挂载
+    pub fn new_checked(
+        inner_fs: Arc<dyn FileSystem>,
+        self_mountpoint: Option<Arc<MountFSInode>>,
+    ) -> Result<Arc<Self>, SystemError> {
+        let readonly = fsck_on_mount(inner_fs.as_ref())?;
+        let mount_fs = Self::new(inner_fs, self_mountpoint);
+        mount_fs.set_readonly(readonly);
+        return Ok(mount_fs);
+    }
+
     /// @brief 用Arc指针包裹MountFS对象。
     /// 本函数的主要功能为，初始化MountFS对象中的自引用Weak指针
     /// 本函数只应在构造器中被调用
@@ -84,6 +113,138 @@ impl MountFS {
     pub fn inner_filesystem(&self) -> Arc<dyn FileSystem> {
         return self.inner_filesystem.clone();
     }
+
+    /// @brief 当前文件系统是否以只读方式挂载
+    pub fn readonly(&self) -> bool {
+        return self.readonly.load(Ordering::SeqCst);
+    }
+
+    pub fn set_readonly(&self, readonly: bool) {
+        self.readonly.store(readonly, Ordering::SeqCst);
+    }
+
+    /// 复制以当前MountFS为根的挂载树
+    ///
+    /// 新的挂载树与原来的挂载树共享底层的文件系统，但是之后在其中一棵树上挂载文件系统，不会影响另一棵树。
+    ///
+    /// ## 参数
+    ///
+    /// - `self_mountpoint`：新的MountFS在新的挂载树中的挂载点，为None表示新的MountFS是挂载树的根
+    pub fn copy_tree(&self, self_mountpoint: Option<Arc<MountFSInode>>) -> Arc<MountFS> {
+        let new_fs = MountFS::new(self.inner_filesystem.clone(), self_mountpoint);
+        new_fs.set_readonly(self.readonly());
+
+        // 递归复制时不持有锁，避免与挂载操作产生死锁
+        let mountpoints = self.mountpoints.lock().clone();
+        let mut new_mountpoints = BTreeMap::new();
+        for (inode_id, sub_fs) in mountpoints {
+            let inner_inode = sub_fs
+                .self_mountpoint
+                .lock()
+                .as_ref()
+                .expect("sub mountfs has no mountpoint")
+                .inner_inode
+                .clone();
+            let mountpoint = MountFSInode {
+                inner_inode,
+                mount_fs: new_fs.clone(),
+                self_ref: Weak::default(),
+            }
+            .wrap();
+            new_mountpoints.insert(inode_id, sub_fs.copy_tree(Some(mountpoint)));
+        }
+        *new_fs.mountpoints.lock() = new_mountpoints;
+
+        return new_fs;
+    }
+}
+
+/// 切换挂载树的根
+///
+/// 把new_root所在的文件系统从它的挂载点上取下来，作为挂载树新的根，原来的根文件系统被挂载到put_old。
+///
+/// ## 参数
+///
+/// - `old_root`：挂载树原来的根目录
+/// - `new_root`：新的根目录，必须是某个文件系统的根目录，并且位于原来的挂载树中
+/// - `put_old`：原来的根文件系统的新挂载点，必须位于new_root之下
+///
+/// ## 返回值
+///
+/// 挂载树新的根目录
+///
+/// ## 错误
+///
+/// - `EINVAL`：参数不满足上述条件
+/// - `EBUSY`：new_root或put_old位于原来的根文件系统上，或者put_old已经是一个挂载点
+/// - `ENOTDIR`：put_old不是目录
+pub fn pivot_root(
+    old_root: Arc<dyn IndexNode>,
+    new_root: Arc<dyn IndexNode>,
+    put_old: Arc<dyn IndexNode>,
+) -> Result<Arc<MountFSInode>, SystemError> {
+    let old_root = old_root
+        .downcast_arc::<MountFSInode>()
+        .ok_or(SystemError::EINVAL)?;
+    let new_root = new_root
+        .downcast_arc::<MountFSInode>()
+        .ok_or(SystemError::EINVAL)?;
+    let put_old = put_old
+        .downcast_arc::<MountFSInode>()
+        .ok_or(SystemError::EINVAL)?;
+
+    let put_old_metadata = put_old.metadata()?;
+    if put_old_metadata.file_type != FileType::Dir {
+        return Err(SystemError::ENOTDIR);
+    }
+
+    let old_fs = old_root.mount_fs.clone();
+    let new_fs = new_root.mount_fs.clone();
+    // 原来的根目录必须是整个挂载树的根
+    if !old_root.is_mountpoint_root()? || old_fs.self_mountpoint.lock().is_some() {
+        return Err(SystemError::EINVAL);
+    }
+    if Arc::ptr_eq(&old_fs, &new_fs) || Arc::ptr_eq(&put_old.mount_fs, &old_fs) {
+        return Err(SystemError::EBUSY);
+    }
+    if !new_root.is_mountpoint_root()?
+        || !new_root.clone().is_descendant_of(&old_root)?
+        || !put_old.clone().is_descendant_of(&new_root)?
+    {
+        return Err(SystemError::EINVAL);
+    }
+    if put_old
+        .mount_fs
+        .mountpoints
+        .lock()
+        .contains_key(&put_old_metadata.inode_id)
+    {
+        return Err(SystemError::EBUSY);
+    }
+
+    // 把new_root所在的文件系统从原来的挂载点上取下来
+    let mountpoint = new_fs
+        .self_mountpoint
+        .lock()
+        .clone()
+        .ok_or(SystemError::EINVAL)?;
+    let mountpoint_id = mountpoint.metadata()?.inode_id;
+    new_fs.self_mountpoint.lock().take();
+    mountpoint
+        .mount_fs
+        .mountpoints
+        .lock()
+        .remove(&mountpoint_id);
+
+    // 把原来的根文件系统挂载到put_old
+    *old_fs.self_mountpoint.lock() = Some(put_old.clone());
+    put_old
+        .mount_fs
+        .mountpoints
+        .lock()
+        .insert(put_old_metadata.inode_id, old_fs);
+
+    return Ok(new_fs.mountpoint_root_inode());
 }
 
 impl MountFSInode {
@@ -114,6 +275,41 @@ impl MountFSInode {
             == self.inner_inode.metadata()?.inode_id);
     }
 
+    /// @brief 判断两个inode是否为挂载树上的同一个节点
+    fn is_same(&self, other: &MountFSInode) -> Result<bool, SystemError> {
+        return Ok(Arc::ptr_eq(&self.mount_fs, &other.mount_fs)
+            && self.metadata()?.inode_id == other.metadata()?.inode_id);
+    }
+
+    /// @brief 判断当前inode是否为ancestor本身，或者位于ancestor之下
+    fn is_descendant_of(self: Arc<Self>, ancestor: &MountFSInode) -> Result<bool, SystemError> {
+        let mut inode = self;
+        loop {
+            if inode.is_same(ancestor)? {
+                return Ok(true);
+            }
+            let parent = inode
+                .find("..")?
+                .downcast_arc::<MountFSInode>()
+                .ok_or(SystemError::EINVAL)?;
+            // 已经到达挂载树的根
+            if parent.is_same(&inode)? {
+                return Ok(false);
+            }
+            inode = parent;
+        }
+    }
+
+    /// @brief 检查当前inode所在的文件系统是否允许写入
+    ///
+    /// @return Err(SystemError::EROFS) 文件系统以只读方式挂载
+    fn check_writable(&self) -> Result<(), SystemError> {
+        if self.mount_fs.readonly() {
+            return Err(SystemError::EROFS);
+        }
+        return Ok(());
+    }
+
     /// @brief 在挂载树上进行inode替换。
     /// 如果当前inode是父MountFS内的一个挂载点，那么，本函数将会返回挂载到这个挂载点下的文件系统的root inode.
     /// 如果当前inode在父MountFS内，但不是挂载点，那么说明在这里不需要进行inode替换，因此直接返回当前inode。
@@ -132,6 +328,12 @@ impl MountFSInode {
 
 impl IndexNode for MountFSInode {
     fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        // 只读的文件系统上的普通文件不能以写方式打开，设备文件等不受影响
+        if mode.accmode() != FileMode::O_RDONLY.bits()
+            && self.inner_inode.metadata()?.file_type == FileType::File
+        {
+            self.check_writable()?;
+        }
         return self.inner_inode.open(data, mode);
     }
 
@@ -146,6 +348,7 @@ impl IndexNode for MountFSInode {
         mode: ModeType,
         data: usize,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_writable()?;
         return Ok(MountFSInode {
             inner_inode: self
                 .inner_inode
@@ -157,6 +360,7 @@ impl IndexNode for MountFSInode {
     }
 
     fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        self.check_writable()?;
         return self.inner_inode.truncate(len);
     }
 
@@ -181,8 +385,8 @@ impl IndexNode for MountFSInode {
     }
 
     #[inline]
-    fn poll(&self) -> Result<super::PollStatus, SystemError> {
-        return self.inner_inode.poll();
+    fn poll(&self, table: &mut super::PollTable) -> Result<super::PollStatus, SystemError> {
+        return self.inner_inode.poll(table);
     }
 
     #[inline]
@@ -202,11 +406,13 @@ impl IndexNode for MountFSInode {
 
     #[inline]
     fn set_metadata(&self, metadata: &super::Metadata) -> Result<(), SystemError> {
+        self.check_writable()?;
         return self.inner_inode.set_metadata(metadata);
     }
 
     #[inline]
     fn resize(&self, len: usize) -> Result<(), SystemError> {
+        self.check_writable()?;
         return self.inner_inode.resize(len);
     }
 
@@ -217,6 +423,7 @@ impl IndexNode for MountFSInode {
         file_type: FileType,
         mode: ModeType,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_writable()?;
         return Ok(MountFSInode {
             inner_inode: self.inner_inode.create(name, file_type, mode)?,
             mount_fs: self.mount_fs.clone(),
@@ -226,12 +433,14 @@ impl IndexNode for MountFSInode {
     }
 
     fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        self.check_writable()?;
         return self.inner_inode.link(name, other);
     }
 
     /// @brief 在挂载文件系统中删除文件/文件夹
     #[inline]
     fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        self.check_writable()?;
         let inode_id = self.inner_inode.find(name)?.metadata()?.inode_id;
 
         // 先检查这个inode是否为一个挂载点，如果当前inode是一个挂载点，那么就不能删除这个inode
@@ -244,6 +453,7 @@ impl IndexNode for MountFSInode {
 
     #[inline]
     fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        self.check_writable()?;
         let inode_id = self.inner_inode.find(name)?.metadata()?.inode_id;
 
         // 先检查这个inode是否为一个挂载点，如果当前inode是一个挂载点，那么就不能删除这个inode
@@ -263,6 +473,7 @@ impl IndexNode for MountFSInode {
         target: &Arc<dyn IndexNode>,
         new_name: &str,
     ) -> Result<(), SystemError> {
+        self.check_writable()?;
         return self.inner_inode.move_(old_name, target, new_name);
     }
 
@@ -274,7 +485,8 @@ impl IndexNode for MountFSInode {
             ".." => {
                 if self.is_mountpoint_root()? {
                     // 当前inode是它所在的文件系统的root inode
-                    match &self.mount_fs.self_mountpoint {
+                    let self_mountpoint = self.mount_fs.self_mountpoint.lock().clone();
+                    match self_mountpoint {
                         Some(inode) => {
                             return inode.find(name);
                         }
@@ -325,13 +537,33 @@ impl IndexNode for MountFSInode {
         return self.inner_inode.ioctl(cmd, data);
     }
 
+    fn mmap_frames(
+        &self,
+        offset: usize,
+        count: PageFrameCount,
+        prot_flags: ProtFlags,
+    ) -> Result<(Vec<PhysPageFrame>, Arc<dyn Any + Send + Sync>), SystemError> {
+        return self.inner_inode.mmap_frames(offset, count, prot_flags);
+    }
+
     #[inline]
     fn list(&self) -> Result<alloc::vec::Vec<alloc::string::String>, SystemError> {
         return self.inner_inode.list();
     }
 
+    #[inline]
+    fn list_at(
+        &self,
+        offset: usize,
+        filler: &mut dyn FnMut(DirEntry) -> bool,
+    ) -> Result<usize, SystemError> {
+        return self.inner_inode.list_at(offset, filler);
+    }
+
     /// @brief 在当前inode下，挂载一个文件系统
     ///
+    /// 挂载之前会检查文件系统的一致性，参见[`MountFS::new_checked`]
+    ///
     /// @return Ok(Arc<MountFS>) 挂载成功，返回指向MountFS的指针
     fn mount(&self, fs: Arc<dyn FileSystem>) -> Result<Arc<MountFS>, SystemError> {
         let metadata = self.inner_inode.metadata()?;
@@ -340,7 +572,8 @@ impl IndexNode for MountFSInode {
         }
 
         // 为新的挂载点创建挂载文件系统
-        let new_mount_fs: Arc<MountFS> = MountFS::new(fs, Some(self.self_ref.upgrade().unwrap()));
+        let new_mount_fs: Arc<MountFS> =
+            MountFS::new_checked(fs, Some(self.self_ref.upgrade().unwrap()))?;
         // 将新的挂载点-挂载文件系统添加到父级的挂载树
         self.mount_fs
             .mountpoints
@@ -356,6 +589,7 @@ impl IndexNode for MountFSInode {
         mode: ModeType,
         dev_t: DeviceNumber,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.check_writable()?;
         return Ok(MountFSInode {
             inner_inode: self.inner_inode.mknod(filename, mode, dev_t)?,
             mount_fs: self.mount_fs.clone(),
@@ -372,7 +606,8 @@ impl IndexNode for MountFSInode {
 
 impl FileSystem for MountFS {
     fn root_inode(&self) -> Arc<dyn IndexNode> {
-        match &self.self_mountpoint {
+        let self_mountpoint = self.self_mountpoint.lock().clone();
+        match self_mountpoint {
             Some(inode) => return inode.mount_fs.root_inode(),
             // 当前文件系统是rootfs
             None => self.mountpoint_root_inode(),
@@ -383,6 +618,10 @@ impl FileSystem for MountFS {
         return self.inner_filesystem.info();
     }
 
+    fn fsck(&self) -> Result<super::fsck::FsckReport, SystemError> {
+        return self.inner_filesystem.fsck();
+    }
+
     /// @brief 本函数用于实现动态转换。
     /// 具体的文件系统在实现本函数时，最简单的方式就是：直接返回self
     fn as_any_ref(&self) -> &dyn Any {
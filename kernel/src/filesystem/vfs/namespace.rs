@@ -0,0 +1,96 @@
+//! 挂载命名空间
+//!
+//! 每个挂载命名空间有自己的一棵挂载树。创建新的挂载命名空间时，复制原来的命名空间的挂载树，
+//! 之后在两个命名空间中挂载文件系统互不影响。进程解析路径时，从它的根目录开始：
+//! 进程没有通过chroot设置根目录时，根目录就是它所在的挂载命名空间的根。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/namespace.c
+
+use alloc::{string::String, sync::Arc};
+
+use crate::{libs::rwlock::RwLock, syscall::SystemError};
+
+use super::{core::init_root_inode, mount::MountFS, IndexNode, VFS_MAX_FOLLOW_SYMLINK_TIMES};
+
+#[derive(Debug)]
+pub struct MntNamespace {
+    /// 命名空间的根。为None时表示初始的挂载命名空间还没有执行过pivot_root，
+    /// 它的根是全局的根文件系统(启动过程中会被替换)
+    root: RwLock<Option<Arc<dyn IndexNode>>>,
+}
+
+impl MntNamespace {
+    /// 创建初始的挂载命名空间
+    pub fn new_init() -> Arc<Self> {
+        return Arc::new(Self {
+            root: RwLock::new(None),
+        });
+    }
+
+    /// 获取命名空间的根节点
+    pub fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root.read().clone().unwrap_or_else(init_root_inode);
+    }
+
+    /// 设置命名空间的根节点，由pivot_root调用
+    pub(super) fn set_root_inode(&self, root: Arc<dyn IndexNode>) {
+        *self.root.write() = Some(root);
+    }
+
+    /// 复制一份挂载命名空间
+    ///
+    /// ## 返回值
+    ///
+    /// 新的挂载命名空间，它拥有一棵与原来的命名空间相同的挂载树
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：命名空间的根不是挂载文件系统
+    pub fn copy(&self) -> Result<Arc<Self>, SystemError> {
+        let root_fs = self.root_inode().fs();
+        let mount_fs = root_fs
+            .as_any_ref()
+            .downcast_ref::<MountFS>()
+            .ok_or(SystemError::EINVAL)?;
+        let new_root: Arc<dyn IndexNode> = mount_fs.copy_tree(None).mountpoint_root_inode();
+
+        return Ok(Arc::new(Self {
+            root: RwLock::new(Some(new_root)),
+        }));
+    }
+}
+
+/// 进程通过chroot设置的根目录
+///
+/// 除了根目录的inode，还记录了它在挂载命名空间中的路径。进程进入新的挂载命名空间时，
+/// 根据这个路径在新的挂载树中找到对应的根目录。
+#[derive(Debug, Clone)]
+pub struct FsRoot {
+    /// 根目录在挂载命名空间中的绝对路径
+    path: String,
+    /// 根目录的inode
+    inode: Arc<dyn IndexNode>,
+}
+
+impl FsRoot {
+    pub fn new(path: String, inode: Arc<dyn IndexNode>) -> Self {
+        return Self { path, inode };
+    }
+
+    pub fn path(&self) -> &str {
+        return &self.path;
+    }
+
+    pub fn inode(&self) -> Arc<dyn IndexNode> {
+        return self.inode.clone();
+    }
+
+    /// 在另一个挂载命名空间中，找到与当前根目录路径相同的目录
+    pub fn in_namespace(&self, mnt_ns: &MntNamespace) -> Result<Self, SystemError> {
+        let inode = mnt_ns.root_inode().lookup_follow_symlink(
+            self.path.trim_start_matches('/'),
+            VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        )?;
+        return Ok(Self::new(self.path.clone(), inode));
+    }
+}
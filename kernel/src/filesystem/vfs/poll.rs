@@ -0,0 +1,311 @@
+//! poll、select系列系统调用的实现
+//!
+//! 进程在所有文件上检查一遍状态时，会通过[`PollTable`]把自己注册到各个inode的等待队列上。
+//! 没有文件就绪时进程睡眠，直到其中一个等待队列被唤醒、超时或者收到信号，然后重新检查所有文件。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/select.c
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    arch::{sched::sched, CurrentIrqArch},
+    exception::InterruptArch,
+    libs::wait_queue::WaitQueue,
+    process::{ProcessControlBlock, ProcessManager},
+    syscall::SystemError,
+    time::timer::{clock, Timer, WakeUpHelper},
+};
+
+use super::{IndexNode, PollStatus};
+
+pub const POLLIN: u16 = 0x0001;
+pub const POLLPRI: u16 = 0x0002;
+pub const POLLOUT: u16 = 0x0004;
+pub const POLLERR: u16 = 0x0008;
+pub const POLLHUP: u16 = 0x0010;
+pub const POLLNVAL: u16 = 0x0020;
+pub const POLLRDNORM: u16 = 0x0040;
+pub const POLLRDBAND: u16 = 0x0080;
+pub const POLLWRNORM: u16 = 0x0100;
+pub const POLLWRBAND: u16 = 0x0200;
+
+/// 总是会被报告的事件，不需要在events中指定
+const POLL_ALWAYS: u16 = POLLERR | POLLHUP | POLLNVAL;
+
+/// select()能够处理的最大文件描述符数量
+pub const FD_SETSIZE: usize = 1024;
+
+/// 用户程序传入poll()的结构体，与Linux的struct pollfd的内存布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+/// 记录一次poll期间，当前进程被注册到的等待队列
+///
+/// 进程被唤醒或者poll结束时，通过[`PollTable::clear`]从所有等待队列中移除
+///
+/// ## 注意
+///
+/// 表中只保存了等待队列的指针。调用者需要保证在PollTable被清空之前，
+/// 被poll的inode(以及它们的等待队列)一直存活
+#[derive(Debug)]
+pub struct PollTable {
+    pcb: Arc<ProcessControlBlock>,
+    queues: Vec<*const WaitQueue>,
+}
+
+impl PollTable {
+    fn new() -> Self {
+        Self {
+            pcb: ProcessManager::current_pcb(),
+            queues: Vec::new(),
+        }
+    }
+
+    /// 把当前进程注册到等待队列上。等待队列被唤醒时，poll会重新检查所有文件
+    ///
+    /// 实现[`IndexNode::poll`]的设备，需要对每个可能使文件状态发生变化的等待队列调用本函数
+    pub fn wait(&mut self, wq: &WaitQueue) {
+        let ptr = wq as *const WaitQueue;
+        if self.queues.contains(&ptr) {
+            return;
+        }
+        wq.add(self.pcb.clone());
+        self.queues.push(ptr);
+    }
+
+    /// 当前进程是否已经被某个等待队列唤醒
+    fn woken(&self) -> bool {
+        self.queues
+            .iter()
+            .any(|wq| !unsafe { &**wq }.contains(&self.pcb))
+    }
+
+    /// 把当前进程从所有等待队列中移除
+    fn clear(&mut self) {
+        for wq in self.queues.drain(..) {
+            unsafe { &*wq }.remove(&self.pcb);
+        }
+    }
+}
+
+impl Drop for PollTable {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// 把inode的状态转换为poll的事件
+fn poll_status_to_events(status: PollStatus) -> u16 {
+    let mut events = 0;
+    if status.contains(PollStatus::READ) {
+        events |= POLLIN | POLLRDNORM;
+    }
+    if status.contains(PollStatus::WRITE) {
+        events |= POLLOUT | POLLWRNORM;
+    }
+    if status.contains(PollStatus::ERROR) {
+        events |= POLLERR;
+    }
+    if status.contains(PollStatus::HUP) {
+        events |= POLLHUP;
+    }
+    return events;
+}
+
+/// 根据文件描述符获取inode，文件描述符不存在时返回None
+fn poll_get_inode(fd: i32) -> Option<Arc<dyn IndexNode>> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)?;
+    let inode = file.lock().inode();
+    return Some(inode);
+}
+
+/// 检查一遍所有文件的状态
+///
+/// @return 有事件发生的文件的数量
+fn poll_once(
+    inodes: &[Option<Arc<dyn IndexNode>>],
+    fds: &mut [PollFd],
+    table: &mut PollTable,
+) -> usize {
+    let mut count = 0;
+    for (pfd, inode) in fds.iter_mut().zip(inodes.iter()) {
+        pfd.revents = 0;
+        if pfd.fd < 0 {
+            continue;
+        }
+        let events = match inode {
+            Some(inode) => inode
+                .poll(table)
+                .map(poll_status_to_events)
+                .unwrap_or(POLLERR),
+            None => POLLNVAL,
+        };
+        pfd.revents = events & (pfd.events | POLL_ALWAYS);
+        if pfd.revents != 0 {
+            count += 1;
+        }
+    }
+    return count;
+}
+
+/// @brief 等待一组文件中的任意一个发生事件
+///
+/// @param fds 要等待的文件，返回时revents被设置为发生的事件
+/// @param timeout_us 超时时间(单位：微秒)，为None时一直等待，为0时不等待
+///
+/// @return Ok(usize) 有事件发生的文件的数量，超时时返回0
+/// @return Err(SystemError::EINTR) 没有事件发生之前，进程收到了信号
+pub fn do_poll(fds: &mut [PollFd], timeout_us: Option<u64>) -> Result<usize, SystemError> {
+    let inodes: Vec<Option<Arc<dyn IndexNode>>> = fds
+        .iter()
+        .map(|pfd| {
+            if pfd.fd < 0 {
+                None
+            } else {
+                poll_get_inode(pfd.fd)
+            }
+        })
+        .collect();
+    let deadline = timeout_us.map(|us| clock().saturating_add(us));
+    let pcb = ProcessManager::current_pcb();
+
+    // 整个等待过程只使用一个定时器，返回时取消它，避免它在之后错误地唤醒进程
+    let timer = deadline.map(|deadline| {
+        let timer = Timer::new(WakeUpHelper::new(pcb.clone()), deadline);
+        timer.activate();
+        timer
+    });
+    let r = poll_wait(&inodes, fds, deadline, &pcb);
+    if let Some(timer) = timer {
+        timer.cancel();
+    }
+    return r;
+}
+
+/// 反复检查所有文件的状态，没有文件就绪时睡眠，直到有文件就绪、超时或者收到信号
+fn poll_wait(
+    inodes: &[Option<Arc<dyn IndexNode>>],
+    fds: &mut [PollFd],
+    deadline: Option<u64>,
+    pcb: &Arc<ProcessControlBlock>,
+) -> Result<usize, SystemError> {
+    loop {
+        let mut table = PollTable::new();
+        let count = poll_once(inodes, fds, &mut table);
+        if count > 0 {
+            return Ok(count);
+        }
+        if deadline.map_or(false, |deadline| clock() >= deadline) {
+            return Ok(0);
+        }
+        if pcb.sig_info().has_pending_signal() {
+            return Err(SystemError::EINTR);
+        }
+
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        // 关中断之后再检查一次是否超时：定时器只会被激活一次，如果它在上面的检查之后、进入睡眠之前到期，进程将不会再被唤醒
+        if deadline.map_or(false, |deadline| clock() >= deadline) {
+            drop(irq_guard);
+            return Ok(0);
+        }
+        // 检查完文件状态之后、进入睡眠之前，等待队列可能已经被唤醒了
+        if table.woken() {
+            drop(irq_guard);
+            continue;
+        }
+        ProcessManager::mark_sleep(true)?;
+        drop(irq_guard);
+        sched();
+        drop(table);
+    }
+}
+
+/// @brief select()的实现。把三个文件描述符集合转换为poll的事件，等待之后再写回集合
+///
+/// @param nfds 最大的文件描述符加一
+/// @param readfds 等待可读的文件描述符集合
+/// @param writefds 等待可写的文件描述符集合
+/// @param exceptfds 等待异常事件的文件描述符集合
+/// @param timeout_us 超时时间(单位：微秒)，为None时一直等待
+///
+/// @return Ok(usize) 三个集合中被设置的位的总数
+pub fn do_select(
+    nfds: usize,
+    mut readfds: Option<&mut [u64]>,
+    mut writefds: Option<&mut [u64]>,
+    mut exceptfds: Option<&mut [u64]>,
+    timeout_us: Option<u64>,
+) -> Result<usize, SystemError> {
+    const IN_EVENTS: u16 = POLLIN | POLLRDNORM | POLLHUP | POLLERR;
+    const OUT_EVENTS: u16 = POLLOUT | POLLWRNORM | POLLERR;
+    const EX_EVENTS: u16 = POLLPRI;
+
+    let test = |set: &Option<&mut [u64]>, fd: usize| -> bool {
+        set.as_ref()
+            .map_or(false, |set| set[fd / 64] & (1 << (fd % 64)) != 0)
+    };
+
+    let mut fds = Vec::new();
+    for fd in 0..nfds {
+        let mut events = 0;
+        if test(&readfds, fd) {
+            events |= IN_EVENTS;
+        }
+        if test(&writefds, fd) {
+            events |= OUT_EVENTS;
+        }
+        if test(&exceptfds, fd) {
+            events |= EX_EVENTS;
+        }
+        if events != 0 {
+            fds.push(PollFd {
+                fd: fd as i32,
+                events,
+                revents: 0,
+            });
+        }
+    }
+
+    // select()遇到不存在的文件描述符时返回错误，而不是像poll()那样设置POLLNVAL
+    if fds.iter().any(|pfd| poll_get_inode(pfd.fd).is_none()) {
+        return Err(SystemError::EBADF);
+    }
+
+    do_poll(&mut fds, timeout_us)?;
+
+    for set in [&mut readfds, &mut writefds, &mut exceptfds]
+        .into_iter()
+        .flatten()
+    {
+        set.fill(0);
+    }
+
+    let mut count = 0;
+    let mut set_bit = |set: &mut Option<&mut [u64]>, fd: usize| {
+        if let Some(set) = set {
+            set[fd / 64] |= 1 << (fd % 64);
+            count += 1;
+        }
+    };
+    for pfd in fds.iter() {
+        let fd = pfd.fd as usize;
+        if pfd.revents & IN_EVENTS != 0 && pfd.events & IN_EVENTS != 0 {
+            set_bit(&mut readfds, fd);
+        }
+        if pfd.revents & OUT_EVENTS != 0 && pfd.events & OUT_EVENTS != 0 {
+            set_bit(&mut writefds, fd);
+        }
+        if pfd.revents & EX_EVENTS != 0 {
+            set_bit(&mut exceptfds, fd);
+        }
+    }
+    return Ok(count);
+}
@@ -1,4 +1,4 @@
-use core::ffi::CStr;
+use core::mem::size_of;
 
 use alloc::{
     string::{String, ToString},
@@ -7,31 +7,72 @@ use alloc::{
 };
 
 use crate::{
-    driver::base::{block::SeekFrom, device::DeviceNumber},
-    filesystem::vfs::file::FileDescriptorVec,
-    include::bindings::bindings::{verify_area, AT_REMOVEDIR, PROC_MAX_FD_NUM},
+    arch::ipc::signal::SigSet,
+    audit::{audit_log, AuditEvents},
+    driver::{
+        base::{block::SeekFrom, device::DeviceNumber},
+        md::dm::dm_partition_by_name,
+        virtio::virtio_9p::virtio_9p_find,
+    },
+    filesystem::{
+        fuse::fs::FuseFileSystem,
+        iso9660::fs::Iso9660FileSystem,
+        nfs::fs::NfsFileSystem,
+        p9::fs::P9FileSystem,
+        ramfs::memfd::{memfd_create, memfd_fcntl, MemFdFlags},
+        vfs::file::FileDescriptorVec,
+    },
+    include::bindings::bindings::{verify_area, PROC_MAX_FD_NUM},
+    ipc::signal::set_current_sig_blocked,
     kerror,
-    libs::rwlock::RwLockWriteGuard,
+    libs::{
+        rwlock::{RwLock, RwLockWriteGuard},
+        spinlock::SpinLock,
+    },
     mm::VirtAddr,
     process::ProcessManager,
-    syscall::{user_access::UserBufferReader, Syscall, SystemError},
-    time::TimeSpec,
+    syscall::{
+        user_access::{
+            check_and_clone_cstr, copy_from_user, copy_to_user, copy_to_user_partial, UserPtr,
+        },
+        Syscall, SystemError,
+    },
+    time::{syscall::PosixTimeval, TimeSpec},
 };
 
 use super::{
-    core::{do_mkdir, do_remove_dir, do_unlink_at},
-    fcntl::{FcntlCommand, FD_CLOEXEC},
-    file::{File, FileMode},
-    utils::rsplit_path,
-    Dirent, FileType, IndexNode, MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    core::{
+        do_mkdir, do_remove_dir, do_unlink_at, is_same_inode, lookup_at, lookup_parent_at,
+        partition_by_name,
+    },
+    fcntl::{
+        FcntlCommand, AT_EMPTY_PATH, AT_FDCWD, AT_REMOVEDIR, AT_SYMLINK_NOFOLLOW,
+        CLOSE_RANGE_CLOEXEC, CLOSE_RANGE_UNSHARE,
+    },
+    file::{FdFlags, File, FileMode},
+    mount,
+    namespace::FsRoot,
+    poll::{do_poll, do_select, PollFd, FD_SETSIZE},
+    utils::absolute_path,
+    FileSystem, FileType, IndexNode, MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
 // use crate::kdebug;
 
+/// read、write与getdents在内核中使用的中转缓冲区的最大长度(字节)
+///
+/// 一次read或者getdents最多返回这么多字节；write会分多次写入
+const USER_BOUNCE_BUFFER_MAX: usize = 1 << 20;
+
 pub const SEEK_SET: u32 = 0;
 pub const SEEK_CUR: u32 = 1;
 pub const SEEK_END: u32 = 2;
 pub const SEEK_MAX: u32 = 3;
 
+/// 单次读写的最大字节数，与Linux的MAX_RW_COUNT一致
+const MAX_RW_COUNT: usize = 0x7fff_f000;
+/// sendfile、copy_file_range在内核中转数据时使用的缓冲区大小
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
 bitflags! {
     /// 文件类型和权限
     #[repr(C)]
@@ -150,15 +191,42 @@ impl Syscall {
     ///
     /// @return 文件描述符编号，或者是错误码
     pub fn open(path: &str, mode: FileMode) -> Result<usize, SystemError> {
-        // kdebug!("open: path: {}, mode: {:?}", path, mode);
+        return Self::openat(AT_FDCWD, path, mode);
+    }
+
+    /// @brief 为当前进程打开一个文件，相对路径从dirfd对应的目录开始查找
+    ///
+    /// @param dirfd 目录的文件描述符，为AT_FDCWD时从当前工作目录开始查找
+    /// @param path 文件路径
+    /// @param mode 打开文件的标志位
+    ///
+    /// @return 文件描述符编号，或者是错误码
+    pub fn openat(dirfd: i32, path: &str, mode: FileMode) -> Result<usize, SystemError> {
+        // kdebug!("openat: dirfd: {}, path: {}, mode: {:?}", dirfd, path, mode);
+        let r = Self::do_openat(dirfd, path, mode);
+
+        let accmode = mode.accmode();
+        if accmode == FileMode::O_WRONLY.bits() || accmode == FileMode::O_RDWR.bits() {
+            audit_log(
+                AuditEvents::OPEN_WRITE,
+                &r,
+                format_args!("dirfd={} path=\"{}\" flags={:#o}", dirfd, path, mode.bits()),
+            );
+        }
+        return r;
+    }
 
+    fn do_openat(dirfd: i32, path: &str, mode: FileMode) -> Result<usize, SystemError> {
         // 文件名过长
         if path.len() > MAX_PATHLEN as usize {
             return Err(SystemError::ENAMETOOLONG);
         }
+        if path.is_empty() {
+            return Err(SystemError::ENOENT);
+        }
 
         let inode: Result<Arc<dyn IndexNode>, SystemError> =
-            ROOT_INODE().lookup_follow_symlink(path, VFS_MAX_FOLLOW_SYMLINK_TIMES);
+            lookup_at(dirfd, path, !mode.contains(FileMode::O_NOFOLLOW));
 
         let inode: Arc<dyn IndexNode> = if inode.is_err() {
             let errno = inode.unwrap_err();
@@ -167,10 +235,8 @@ impl Syscall {
                 && !mode.contains(FileMode::O_DIRECTORY)
                 && errno == SystemError::ENOENT
             {
-                let (filename, parent_path) = rsplit_path(path);
                 // 查找父目录
-                let parent_inode: Arc<dyn IndexNode> =
-                    ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
+                let (parent_inode, filename) = lookup_parent_at(dirfd, path)?;
                 // 创建文件
                 let inode: Arc<dyn IndexNode> = parent_inode.create(
                     filename,
@@ -187,6 +253,10 @@ impl Syscall {
         };
 
         let file_type: FileType = inode.metadata()?.file_type;
+        // 设置了O_NOFOLLOW，并且目标是符号链接
+        if mode.contains(FileMode::O_NOFOLLOW) && file_type == FileType::SymLink {
+            return Err(SystemError::ELOOP);
+        }
         // 如果要打开的是文件夹，而目标不是文件夹
         if mode.contains(FileMode::O_DIRECTORY) && file_type != FileType::Dir {
             return Err(SystemError::ENOTDIR);
@@ -295,6 +365,66 @@ impl Syscall {
         return file.lock_no_preempt().write(buf.len(), buf);
     }
 
+    /// @brief 从文件读取数据到用户缓冲区
+    ///
+    /// 文件系统只读写内核中的中转缓冲区，读取完成之后再通过copy_to_user拷贝到用户空间。
+    /// 因此访问用户内存只发生在拷贝函数中，缺页由异常表处理，用户内存的访问窗口也只在拷贝期间打开
+    ///
+    /// 与Linux一致，拷贝到一半访问到未映射的用户内存时，返回已经拷贝的字节数。
+    /// TODO: 让read_at支持直接写入用户缓冲区，避免管道、socket的数据被拷贝两次
+    ///
+    /// @param fd 文件描述符编号
+    /// @param buf 用户空间的输出缓冲区
+    /// @param len 输出缓冲区的长度，一次最多读取USER_BOUNCE_BUFFER_MAX字节
+    ///
+    /// @return Ok(usize) 成功拷贝到用户空间的字节数
+    /// @return Err(SystemError::EFAULT) 输出缓冲区不可写
+    pub fn read_to_user(fd: i32, buf: VirtAddr, len: usize) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, USER_BOUNCE_BUFFER_MAX);
+        // 在读取之前检查地址范围，避免从管道或socket中取出数据之后才发现缓冲区不合法
+        crate::mm::verify_area(buf, len).map_err(|_| SystemError::EFAULT)?;
+
+        let mut kbuf: Vec<u8> = vec![0; len];
+        let n = Self::read(fd, &mut kbuf)?;
+
+        let copied = unsafe { copy_to_user_partial(buf, &kbuf[..n]) }?;
+        if copied == 0 && n != 0 {
+            return Err(SystemError::EFAULT);
+        }
+        return Ok(copied);
+    }
+
+    /// @brief 把用户缓冲区中的数据写入文件
+    ///
+    /// 每次把最多USER_BOUNCE_BUFFER_MAX字节拷贝到内核中的中转缓冲区，再交给文件系统写入，直到写完或者写入的字节数不足
+    ///
+    /// @param fd 文件描述符编号
+    /// @param buf 用户空间的输入缓冲区
+    /// @param len 输入缓冲区的长度
+    ///
+    /// @return Ok(usize) 成功写入的数据的字节数
+    /// @return Err(SystemError) 一个字节都没有写入时，返回错误码
+    pub fn write_from_user(fd: i32, buf: VirtAddr, len: usize) -> Result<usize, SystemError> {
+        let mut written = 0;
+        while written < len {
+            let chunk = core::cmp::min(len - written, USER_BOUNCE_BUFFER_MAX);
+            let mut kbuf = vec![0u8; chunk];
+            let r = unsafe { copy_from_user(&mut kbuf, buf + written) }
+                .and_then(|_| Self::write(fd, &kbuf));
+            match r {
+                Ok(n) => {
+                    written += n;
+                    if n < chunk {
+                        break;
+                    }
+                }
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        return Ok(written);
+    }
+
     /// @brief 调整文件操作指针的位置
     ///
     /// @param fd 文件描述符编号
@@ -314,6 +444,229 @@ impl Syscall {
         return file.lock_no_preempt().lseek(seek);
     }
 
+    /// @brief 在内核中把数据从in_fd拷贝到out_fd，避免数据在用户态和内核态之间来回拷贝
+    ///
+    /// @param out_fd 目标文件描述符，可以是普通文件或者socket
+    /// @param in_fd 源文件描述符，必须是普通文件
+    /// @param offset 用户态的读取偏移量指针。为空时从in_fd的文件指针处开始读取，并更新文件指针；
+    ///               否则从*offset处开始读取，不改变文件指针，并把*offset更新为下一个要读取的位置
+    /// @param count 最多拷贝的字节数
+    ///
+    /// @return Ok(usize) 成功拷贝的字节数
+    /// @return Err(SystemError) 错误码
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/read_write.c?fi=do_sendfile#do_sendfile
+    pub fn sendfile(
+        out_fd: i32,
+        in_fd: i32,
+        offset: UserPtr<i64>,
+        count: usize,
+    ) -> Result<usize, SystemError> {
+        let (in_file, out_file) = Self::get_copy_files(in_fd, out_fd)?;
+
+        {
+            let in_guard = in_file.lock();
+            in_guard.readable().map_err(|_| SystemError::EBADF)?;
+            // 源文件需要支持按偏移量读取
+            if in_guard.file_type() != FileType::File {
+                return Err(SystemError::EINVAL);
+            }
+        }
+        {
+            let out_guard = out_file.lock();
+            out_guard.writeable().map_err(|_| SystemError::EBADF)?;
+            if out_guard.mode().contains(FileMode::O_APPEND) {
+                return Err(SystemError::EINVAL);
+            }
+        }
+
+        let count = core::cmp::min(count, MAX_RW_COUNT);
+        let mut pos = Self::read_user_offset(offset)?;
+        let r = Self::do_copy_file(&in_file, pos.as_mut(), &out_file, None, count);
+        if let Some(pos) = pos {
+            offset.write(&(pos as i64))?;
+        }
+        return r;
+    }
+
+    /// @brief 在内核中把数据从一个普通文件拷贝到另一个普通文件
+    ///
+    /// @param fd_in 源文件描述符
+    /// @param off_in 用户态的源文件偏移量指针，为空时使用并更新fd_in的文件指针
+    /// @param fd_out 目标文件描述符
+    /// @param off_out 用户态的目标文件偏移量指针，为空时使用并更新fd_out的文件指针
+    /// @param len 最多拷贝的字节数
+    /// @param flags 保留，必须为0
+    ///
+    /// @return Ok(usize) 成功拷贝的字节数
+    /// @return Err(SystemError) 错误码
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/read_write.c?fi=vfs_copy_file_range#vfs_copy_file_range
+    pub fn copy_file_range(
+        fd_in: i32,
+        off_in: UserPtr<i64>,
+        fd_out: i32,
+        off_out: UserPtr<i64>,
+        len: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let (in_file, out_file) = Self::get_copy_files(fd_in, fd_out)?;
+
+        let check_file_type = |file_type: FileType| match file_type {
+            FileType::File => Ok(()),
+            FileType::Dir => Err(SystemError::EISDIR),
+            _ => Err(SystemError::EINVAL),
+        };
+        let in_meta = {
+            let in_guard = in_file.lock();
+            in_guard.readable().map_err(|_| SystemError::EBADF)?;
+            check_file_type(in_guard.file_type())?;
+            in_guard.metadata()?
+        };
+        let out_meta = {
+            let out_guard = out_file.lock();
+            out_guard.writeable().map_err(|_| SystemError::EBADF)?;
+            if out_guard.mode().contains(FileMode::O_APPEND) {
+                return Err(SystemError::EBADF);
+            }
+            check_file_type(out_guard.file_type())?;
+            out_guard.metadata()?
+        };
+
+        let len = core::cmp::min(len, MAX_RW_COUNT);
+        let mut pos_in = Self::read_user_offset(off_in)?;
+        let mut pos_out = Self::read_user_offset(off_out)?;
+
+        // 源文件和目标文件是同一个文件时，拷贝的区间不能重叠
+        if in_meta.dev_id == out_meta.dev_id && in_meta.inode_id == out_meta.inode_id {
+            let start_in = match pos_in {
+                Some(pos) => pos,
+                None => in_file.lock().lseek(SeekFrom::SeekCurrent(0))?,
+            };
+            let start_out = match pos_out {
+                Some(pos) => pos,
+                None => out_file.lock().lseek(SeekFrom::SeekCurrent(0))?,
+            };
+            if start_in < start_out + len && start_out < start_in + len {
+                return Err(SystemError::EINVAL);
+            }
+        }
+
+        let r = Self::do_copy_file(&in_file, pos_in.as_mut(), &out_file, pos_out.as_mut(), len);
+        if let Some(pos) = pos_in {
+            off_in.write(&(pos as i64))?;
+        }
+        if let Some(pos) = pos_out {
+            off_out.write(&(pos as i64))?;
+        }
+        return r;
+    }
+
+    /// @brief 获取sendfile、copy_file_range的源文件和目标文件
+    fn get_copy_files(
+        in_fd: i32,
+        out_fd: i32,
+    ) -> Result<(Arc<SpinLock<File>>, Arc<SpinLock<File>>), SystemError> {
+        let binding = ProcessManager::current_pcb().fd_table();
+        let fd_table_guard = binding.read();
+        let in_file = fd_table_guard
+            .get_file_by_fd(in_fd)
+            .ok_or(SystemError::EBADF)?;
+        let out_file = fd_table_guard
+            .get_file_by_fd(out_fd)
+            .ok_or(SystemError::EBADF)?;
+        return Ok((in_file, out_file));
+    }
+
+    /// @brief 从用户态读取一个文件偏移量。指针为空时返回None
+    fn read_user_offset(ptr: UserPtr<i64>) -> Result<Option<usize>, SystemError> {
+        if ptr.is_null() {
+            return Ok(None);
+        }
+        let offset = ptr.read()?;
+        if offset < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Some(offset as usize));
+    }
+
+    /// @brief 以内核缓冲区作为中转，把数据从一个文件拷贝到另一个文件
+    ///
+    /// @param in_file 源文件
+    /// @param in_pos 源文件的读取偏移量。为None时使用并更新源文件的文件指针，否则更新该偏移量
+    /// @param out_file 目标文件
+    /// @param out_pos 目标文件的写入偏移量。为None时使用并更新目标文件的文件指针，否则更新该偏移量
+    /// @param count 最多拷贝的字节数
+    ///
+    /// @return Ok(usize) 成功拷贝的字节数。若拷贝了部分数据之后出错，则返回已经拷贝的字节数
+    /// @return Err(SystemError) 还没有拷贝任何数据就出错了
+    fn do_copy_file(
+        in_file: &Arc<SpinLock<File>>,
+        mut in_pos: Option<&mut usize>,
+        out_file: &Arc<SpinLock<File>>,
+        mut out_pos: Option<&mut usize>,
+        count: usize,
+    ) -> Result<usize, SystemError> {
+        let mut buf = vec![0u8; core::cmp::min(count, COPY_CHUNK_SIZE)];
+        let mut copied = 0;
+
+        while copied < count {
+            let chunk = core::cmp::min(count - copied, buf.len());
+            // 源文件和目标文件可能是同一个文件，因此不能同时持有两个文件的锁
+            let r = match in_pos.as_deref() {
+                Some(pos) => in_file.lock_no_preempt().pread(*pos, chunk, &mut buf),
+                None => in_file.lock_no_preempt().read(chunk, &mut buf),
+            };
+            let nread = match r {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if copied == 0 => return Err(e),
+                Err(_) => break,
+            };
+
+            let w = match out_pos.as_deref() {
+                Some(pos) => out_file.lock_no_preempt().pwrite(*pos, nread, &buf),
+                None => out_file.lock_no_preempt().write(nread, &buf),
+            };
+            let (nwritten, err) = match w {
+                Ok(n) => (n, None),
+                Err(e) => (0, Some(e)),
+            };
+
+            // 没有写出去的数据需要退回给源文件，使得下次能够从正确的位置继续读取
+            match in_pos.as_deref_mut() {
+                Some(pos) => *pos += nwritten,
+                None if nwritten < nread => {
+                    let back = (nread - nwritten) as i64;
+                    in_file
+                        .lock_no_preempt()
+                        .lseek(SeekFrom::SeekCurrent(-back))?;
+                }
+                None => {}
+            }
+            if let Some(pos) = out_pos.as_deref_mut() {
+                *pos += nwritten;
+            }
+            copied += nwritten;
+
+            if let Some(e) = err {
+                if copied == 0 {
+                    return Err(e);
+                }
+                break;
+            }
+            if nwritten < nread {
+                break;
+            }
+        }
+
+        return Ok(copied);
+    }
+
     /// @brief 切换工作目录
     ///
     /// @param dest_path 目标路径
@@ -342,29 +695,7 @@ impl Syscall {
         let path = dest_path.to_string();
         let mut new_path = String::from("");
         if path.len() > 0 {
-            let cwd = match path.as_bytes()[0] {
-                b'/' => String::from("/"),
-                _ => proc.basic().cwd(),
-            };
-            let mut cwd_vec: Vec<_> = cwd.split("/").filter(|&x| x != "").collect();
-            let path_split = path.split("/").filter(|&x| x != "");
-            for seg in path_split {
-                if seg == ".." {
-                    cwd_vec.pop();
-                } else if seg == "." {
-                    // 当前目录
-                } else {
-                    cwd_vec.push(seg);
-                }
-            }
-            //proc.basic().set_path(String::from(""));
-            for seg in cwd_vec {
-                new_path.push_str("/");
-                new_path.push_str(seg);
-            }
-            if new_path == "" {
-                new_path = String::from("/");
-            }
+            new_path = absolute_path(&proc.basic().cwd(), &path);
         }
         let inode =
             match ROOT_INODE().lookup_follow_symlink(&new_path, VFS_MAX_FOLLOW_SYMLINK_TIMES) {
@@ -405,19 +736,198 @@ impl Syscall {
         return Ok(VirtAddr::new(buf.as_ptr() as usize));
     }
 
-    /// @brief 获取目录中的数据
+    /// @brief 改变当前进程的根目录
+    ///
+    /// 之后解析绝对路径时从新的根目录开始，并且不能通过".."越过新的根目录。
+    /// 由于当前目录是以相对于根目录的路径保存的，当前目录会被设置为新的根目录
+    ///
+    /// @param path 新的根目录的路径
+    ///
+    /// @return 成功返回0
+    pub fn chroot(path: &str) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let path = absolute_path(&pcb.basic().cwd(), path);
+        let inode = ROOT_INODE().lookup_follow_symlink(&path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+        if inode.metadata()?.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        // 新的根目录在挂载命名空间中的路径
+        let root_path = match pcb.basic().fs_root() {
+            Some(root) => absolute_path(root.path(), path.trim_start_matches('/')),
+            None => path,
+        };
+
+        let mut basic = pcb.basic_mut();
+        basic.set_fs_root(Some(FsRoot::new(root_path, inode)));
+        basic.set_cwd(String::from("/"));
+        return Ok(0);
+    }
+
+    /// @brief 把设备上的文件系统挂载到目录
+    ///
+    /// 目前只支持创建新的挂载，mount(2)的flags参数被忽略
+    ///
+    /// @param source 文件系统所在的设备：磁盘分区(见[`partition_by_name`])或者映射设备(见[`dm_partition_by_name`])，
+    /// 对于`9p`为virtio-9p设备的挂载标签(见[`virtio_9p_find`])，对于`nfs`为`<server-ip>:<path>`格式的导出目录，`fuse`不使用
+    /// @param target 挂载点，必须是目录
+    /// @param fstype 文件系统类型，目前支持`iso9660`、`9p`、`nfs`与`fuse`
+    /// @param data 文件系统的挂载选项，目前只有`nfs`(见[`NfsMountOptions`](crate::filesystem::nfs::options::NfsMountOptions))
+    /// 与`fuse`(见[`FuseMountOptions`](crate::filesystem::fuse::fs::FuseMountOptions))使用
+    ///
+    /// @return 成功返回0
+    /// @return Err(SystemError::ENODEV) 不支持这种文件系统
+    /// @return Err(SystemError::ENOENT) source对应的设备不存在
+    pub fn mount(
+        source: &str,
+        target: &str,
+        fstype: &str,
+        data: Option<&str>,
+    ) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let target = ROOT_INODE().lookup_follow_symlink(
+            &absolute_path(&pcb.basic().cwd(), target),
+            VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        )?;
+        if target.metadata()?.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let fs: Arc<dyn FileSystem> = match fstype {
+            "iso9660" => {
+                let partition = partition_by_name(source)
+                    .or_else(|| dm_partition_by_name(source))
+                    .ok_or(SystemError::ENOENT)?;
+                Iso9660FileSystem::new(partition)?
+            }
+            "9p" => {
+                let transport = virtio_9p_find(source).ok_or(SystemError::ENOENT)?;
+                P9FileSystem::new(transport)?
+            }
+            "nfs" => NfsFileSystem::mount(source, data.unwrap_or(""))?,
+            "fuse" => FuseFileSystem::mount(data.unwrap_or(""))?,
+            _ => return Err(SystemError::ENODEV),
+        };
+        target.mount(fs)?;
+        return Ok(0);
+    }
+
+    /// @brief 切换当前进程所在的挂载命名空间的根文件系统
+    ///
+    /// new_root所在的文件系统成为新的根文件系统，原来的根文件系统被挂载到put_old。
+    /// 根目录为原来的根目录的进程(包括当前进程)，之后使用新的根目录。
+    /// 可以用于从initramfs切换到磁盘上的根文件系统
+    ///
+    /// @param new_root 新的根目录，必须是某个文件系统的根目录
+    /// @param put_old 原来的根文件系统的新挂载点，必须位于new_root之下
+    ///
+    /// @return 成功返回0
+    pub fn pivot_root(new_root: &str, put_old: &str) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let cwd = pcb.basic().cwd();
+        let old_root = ROOT_INODE();
+        let new_root = old_root
+            .lookup_follow_symlink(&absolute_path(&cwd, new_root), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+        let put_old = old_root
+            .lookup_follow_symlink(&absolute_path(&cwd, put_old), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+
+        let new_root = mount::pivot_root(old_root.clone(), new_root, put_old)?;
+
+        let mnt_ns = pcb.nsproxy().mnt_ns.clone();
+        mnt_ns.set_root_inode(new_root);
+
+        // 通过chroot把根目录设为原来的根目录的进程，改为使用命名空间新的根目录
+        for pid in ProcessManager::get_all_pids() {
+            let p = match ProcessManager::find(pid) {
+                Some(p) => p,
+                None => continue,
+            };
+            if !Arc::ptr_eq(&p.nsproxy().mnt_ns, &mnt_ns) {
+                continue;
+            }
+            let mut basic = p.basic_mut();
+            if let Some(fs_root) = basic.fs_root() {
+                if is_same_inode(&fs_root.inode(), &old_root)? {
+                    basic.set_fs_root(None);
+                }
+            }
+        }
+
+        return Ok(0);
+    }
+
+    /// @brief 读取目录项，按照struct linux_dirent的格式紧密地排列在buf中
     ///
-    /// TODO: 这个函数的语义与Linux不一致，需要修改！！！
+    /// 每次调用从上次结束的位置继续读取，读到目录的末尾时返回0
     ///
     /// @param fd 文件描述符号
     /// @param buf 输出缓冲区
     ///
-    /// @return 成功返回读取的字节数，失败返回错误码
+    /// @return 成功返回填充的字节数
+    /// @return Err(SystemError::EINVAL) 缓冲区放不下一个目录项
+    /// @return Err(SystemError::ENOTDIR) fd对应的文件不是目录
     pub fn getdents(fd: i32, buf: &mut [u8]) -> Result<usize, SystemError> {
-        let dirent =
-            unsafe { (buf.as_mut_ptr() as *mut Dirent).as_mut() }.ok_or(SystemError::EFAULT)?;
+        return Self::do_getdents(fd, buf, false);
+    }
+
+    /// @brief 读取目录项，按照struct linux_dirent64的格式紧密地排列在buf中
+    ///
+    /// 每次调用从上次结束的位置继续读取，读到目录的末尾时返回0
+    ///
+    /// @param fd 文件描述符号
+    /// @param buf 输出缓冲区
+    ///
+    /// @return 成功返回填充的字节数
+    /// @return Err(SystemError::EINVAL) 缓冲区放不下一个目录项
+    /// @return Err(SystemError::ENOTDIR) fd对应的文件不是目录
+    pub fn getdents64(fd: i32, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return Self::do_getdents(fd, buf, true);
+    }
+
+    /// @brief 读取目录项到用户缓冲区，格式与getdents或getdents64相同
+    ///
+    /// 目录项先写入内核中的中转缓冲区，再把实际写入的部分拷贝到用户空间
+    ///
+    /// @param fd 文件描述符号
+    /// @param buf 用户空间的输出缓冲区
+    /// @param len 输出缓冲区的长度，一次最多读取USER_BOUNCE_BUFFER_MAX字节
+    /// @param dirent64 为true时使用struct linux_dirent64的格式，否则使用struct linux_dirent的格式
+    ///
+    /// @return 成功返回填充的字节数
+    /// @return Err(SystemError::EINVAL) 缓冲区放不下一个目录项
+    /// @return Err(SystemError::EFAULT) 输出缓冲区不可写
+    pub fn getdents_to_user(
+        fd: i32,
+        buf: VirtAddr,
+        len: usize,
+        dirent64: bool,
+    ) -> Result<usize, SystemError> {
+        let mut kbuf = vec![0u8; core::cmp::min(len, USER_BOUNCE_BUFFER_MAX)];
+        let file = Self::getdents_file(fd)?;
+        let mut guard = file.lock_no_preempt();
+        let start = guard.lseek(SeekFrom::SeekCurrent(0))?;
+        let n = guard.readdir(&mut kbuf, dirent64)?;
+        drop(guard);
+
+        if let Err(e) = unsafe { copy_to_user(buf, &kbuf[..n]) } {
+            // 拷贝失败时目录项没有交给用户，退回到读取之前的位置，使得重试时还能读到它们
+            file.lock_no_preempt()
+                .lseek(SeekFrom::SeekSet(start as i64))?;
+            return Err(e);
+        }
+        return Ok(n);
+    }
+
+    fn do_getdents(fd: i32, buf: &mut [u8], dirent64: bool) -> Result<usize, SystemError> {
+        let file = Self::getdents_file(fd)?;
+        let res = file.lock_no_preempt().readdir(buf, dirent64);
+
+        return res;
+    }
 
-        if fd < 0 || fd as u32 > PROC_MAX_FD_NUM {
+    /// 获取getdents要读取的目录对应的文件
+    fn getdents_file(fd: i32) -> Result<Arc<SpinLock<File>>, SystemError> {
+        if fd < 0 || fd as u32 >= PROC_MAX_FD_NUM {
             return Err(SystemError::EBADF);
         }
 
@@ -431,9 +941,7 @@ impl Syscall {
         // drop guard 以避免无法调度的问题
         drop(fd_table_guard);
 
-        let res = file.lock_no_preempt().readdir(dirent).map(|x| x as usize);
-
-        return res;
+        return Ok(file);
     }
 
     /// @brief 创建文件夹
@@ -442,19 +950,29 @@ impl Syscall {
     ///
     /// @return uint64_t 负数错误码 / 0表示成功
     pub fn mkdir(path: &str, mode: usize) -> Result<usize, SystemError> {
-        return do_mkdir(path, FileMode::from_bits_truncate(mode as u32)).map(|x| x as usize);
+        return Self::mkdirat(AT_FDCWD, path, mode);
+    }
+
+    /// @brief 创建文件夹，相对路径从dirfd对应的目录开始查找
+    ///
+    /// @param dirfd 目录的文件描述符，为AT_FDCWD时从当前工作目录开始查找
+    /// @param path 文件夹的路径
+    /// @param mode 模式
+    pub fn mkdirat(dirfd: i32, path: &str, mode: usize) -> Result<usize, SystemError> {
+        return do_mkdir(dirfd, path, FileMode::from_bits_truncate(mode as u32))
+            .map(|x| x as usize);
     }
 
     /// **删除文件夹、取消文件的链接、删除文件的系统调用**
     ///
     /// ## 参数
     ///
-    /// - `dirfd`：文件夹的文件描述符.目前暂未实现
+    /// - `dirfd`：文件夹的文件描述符，为AT_FDCWD时相对路径从当前工作目录开始查找
     /// - `pathname`：文件夹的路径
     /// - `flags`：标志位
     ///
     ///
-    pub fn unlinkat(_dirfd: i32, pathname: &str, flags: u32) -> Result<usize, SystemError> {
+    pub fn unlinkat(dirfd: i32, pathname: &str, flags: u32) -> Result<usize, SystemError> {
         // kdebug!("sys_unlink_at={path:?}");
         if (flags & (!AT_REMOVEDIR)) != 0 {
             return Err(SystemError::EINVAL);
@@ -462,7 +980,7 @@ impl Syscall {
 
         if (flags & AT_REMOVEDIR) > 0 {
             // kdebug!("rmdir");
-            match do_remove_dir(&pathname) {
+            match do_remove_dir(dirfd, &pathname) {
                 Err(err) => {
                     kerror!("Failed to Remove Directory, Error Code = {:?}", err);
                     return Err(err);
@@ -473,7 +991,7 @@ impl Syscall {
             }
         }
 
-        match do_unlink_at(&pathname, FileMode::from_bits_truncate(flags as u32)) {
+        match do_unlink_at(dirfd, &pathname, FileMode::from_bits_truncate(flags as u32)) {
             Err(err) => {
                 kerror!("Failed to Remove Directory, Error Code = {:?}", err);
                 return Err(err);
@@ -489,17 +1007,8 @@ impl Syscall {
         let binding = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = binding.write();
 
-        let old_file = fd_table_guard
-            .get_file_by_fd(oldfd)
-            .ok_or(SystemError::EBADF)?;
-
-        let new_file = old_file
-            .lock_no_preempt()
-            .try_clone()
-            .ok_or(SystemError::EBADF)?;
-        // 申请文件描述符，并把文件对象存入其中
-        let res = fd_table_guard.alloc_fd(new_file, None).map(|x| x as usize);
-        return res;
+        let newfd = fd_table_guard.next_free_fd(0).ok_or(SystemError::EMFILE)?;
+        return Self::do_dup2(oldfd, newfd, FdFlags::empty(), &mut fd_table_guard);
     }
 
     /// 根据提供的文件描述符的fd，和指定新fd，复制对应的文件结构体，
@@ -518,12 +1027,54 @@ impl Syscall {
     pub fn dup2(oldfd: i32, newfd: i32) -> Result<usize, SystemError> {
         let binding = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = binding.write();
-        return Self::do_dup2(oldfd, newfd, &mut fd_table_guard);
+        if oldfd == newfd {
+            // oldfd有效时什么都不做
+            fd_table_guard
+                .get_file_by_fd(oldfd)
+                .ok_or(SystemError::EBADF)?;
+            return Ok(newfd as usize);
+        }
+        return Self::do_dup2(oldfd, newfd, FdFlags::empty(), &mut fd_table_guard);
     }
 
+    /// 与dup2相同，但是可以通过`flags`为新的文件描述符设置O_CLOEXEC
+    ///
+    /// ## 参数
+    ///
+    /// - `oldfd`：旧文件描述符
+    /// - `newfd`：新文件描述符
+    /// - `flags`：只能为0或者O_CLOEXEC
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：`oldfd`与`newfd`相等，或者`flags`中有O_CLOEXEC以外的标志
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/file.c?fi=ksys_dup3#ksys_dup3
+    pub fn dup3(oldfd: i32, newfd: i32, flags: u32) -> Result<usize, SystemError> {
+        let flags = FileMode::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        if !(flags - FileMode::O_CLOEXEC).is_empty() || oldfd == newfd {
+            return Err(SystemError::EINVAL);
+        }
+        let mut fd_flags = FdFlags::empty();
+        fd_flags.set(FdFlags::CLOEXEC, flags.contains(FileMode::O_CLOEXEC));
+
+        let binding = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = binding.write();
+        return Self::do_dup2(oldfd, newfd, fd_flags, &mut fd_table_guard);
+    }
+
+    /// 把`oldfd`复制到`newfd`。如果`newfd`已经打开，则先关闭它
+    ///
+    /// ## 参数
+    ///
+    /// - `oldfd`：旧文件描述符
+    /// - `newfd`：新文件描述符，不能与`oldfd`相等
+    /// - `fd_flags`：新文件描述符的标志
+    /// - `fd_table_guard`：文件描述符表的写锁
     fn do_dup2(
         oldfd: i32,
         newfd: i32,
+        fd_flags: FdFlags,
         fd_table_guard: &mut RwLockWriteGuard<'_, FileDescriptorVec>,
     ) -> Result<usize, SystemError> {
         // 确认oldfd, newid是否有效
@@ -531,19 +1082,7 @@ impl Syscall {
             return Err(SystemError::EBADF);
         }
 
-        if oldfd == newfd {
-            // 若oldfd与newfd相等
-            return Ok(newfd as usize);
-        }
-        let new_exists = fd_table_guard.get_file_by_fd(newfd).is_some();
-        if new_exists {
-            // close newfd
-            if let Err(_) = fd_table_guard.drop_fd(newfd) {
-                // An I/O error occurred while attempting to close fildes2.
-                return Err(SystemError::EIO);
-            }
-        }
-
+        // 先复制旧的文件，oldfd无效时，newfd不会被关闭
         let old_file = fd_table_guard
             .get_file_by_fd(oldfd)
             .ok_or(SystemError::EBADF)?;
@@ -551,11 +1090,65 @@ impl Syscall {
             .lock_no_preempt()
             .try_clone()
             .ok_or(SystemError::EBADF)?;
+
+        if fd_table_guard.get_file_by_fd(newfd).is_some() {
+            // close newfd
+            if let Err(_) = fd_table_guard.drop_fd(newfd) {
+                // An I/O error occurred while attempting to close fildes2.
+                return Err(SystemError::EIO);
+            }
+        }
+
         // 申请文件描述符，并把文件对象存入其中
-        let res = fd_table_guard
-            .alloc_fd(new_file, Some(newfd))
-            .map(|x| x as usize);
-        return res;
+        fd_table_guard.alloc_fd(new_file, Some(newfd))?;
+        fd_table_guard.set_fd_flags(newfd, fd_flags)?;
+        return Ok(newfd as usize);
+    }
+
+    /// 关闭[first, last]范围内的文件描述符
+    ///
+    /// ## 参数
+    ///
+    /// - `first`：第一个要关闭的文件描述符
+    /// - `last`：最后一个要关闭的文件描述符，可以超出文件描述符表的大小
+    /// - `flags`：`CLOSE_RANGE_UNSHARE`、`CLOSE_RANGE_CLOEXEC`的组合
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：`first`大于`last`，或者`flags`不合法
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/file.c?fi=__close_range#__close_range
+    pub fn close_range(first: u32, last: u32, flags: u32) -> Result<usize, SystemError> {
+        if first > last || flags & !(CLOSE_RANGE_UNSHARE | CLOSE_RANGE_CLOEXEC) != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pcb = ProcessManager::current_pcb();
+        if flags & CLOSE_RANGE_UNSHARE != 0 {
+            // 文件描述符表被其他线程共享时，先复制一份，之后的操作不会影响其他线程
+            let fd_table = pcb.fd_table();
+            if Arc::strong_count(&fd_table) > 2 {
+                let new_fd_table = fd_table.read().clone();
+                pcb.basic_mut()
+                    .set_fd_table(Some(Arc::new(RwLock::new(new_fd_table))));
+            }
+        }
+
+        let binding = pcb.fd_table();
+        let mut fd_table_guard = binding.write();
+        let last = core::cmp::min(last as usize, FileDescriptorVec::PROCESS_MAX_FD - 1);
+        for fd in (first as usize)..=last {
+            let fd = fd as i32;
+            if fd_table_guard.get_file_by_fd(fd).is_none() {
+                continue;
+            }
+            if flags & CLOSE_RANGE_CLOEXEC != 0 {
+                fd_table_guard.set_fd_flags(fd, FdFlags::CLOEXEC)?;
+            } else {
+                fd_table_guard.drop_fd(fd)?;
+            }
+        }
+        return Ok(0);
     }
 
     /// # fcntl
@@ -567,51 +1160,35 @@ impl Syscall {
     /// - `arg`：参数
     pub fn fcntl(fd: i32, cmd: FcntlCommand, arg: i32) -> Result<usize, SystemError> {
         match cmd {
-            FcntlCommand::DupFd => {
+            FcntlCommand::DupFd | FcntlCommand::DupFdCloexec => {
+                // 复制到不小于arg的最小的空闲文件描述符
                 if arg < 0 || arg as usize >= FileDescriptorVec::PROCESS_MAX_FD {
-                    return Err(SystemError::EBADF);
-                }
-                let arg = arg as usize;
-                for i in arg..FileDescriptorVec::PROCESS_MAX_FD {
-                    let binding = ProcessManager::current_pcb().fd_table();
-                    let mut fd_table_guard = binding.write();
-                    if fd_table_guard.get_file_by_fd(fd).is_none() {
-                        return Self::do_dup2(fd, i as i32, &mut fd_table_guard);
-                    }
+                    return Err(SystemError::EINVAL);
                 }
-                return Err(SystemError::EMFILE);
+                let mut fd_flags = FdFlags::empty();
+                fd_flags.set(FdFlags::CLOEXEC, cmd == FcntlCommand::DupFdCloexec);
+
+                let binding = ProcessManager::current_pcb().fd_table();
+                let mut fd_table_guard = binding.write();
+                let newfd = fd_table_guard
+                    .next_free_fd(arg as usize)
+                    .ok_or(SystemError::EMFILE)?;
+                return Self::do_dup2(fd, newfd, fd_flags, &mut fd_table_guard);
             }
             FcntlCommand::GetFd => {
                 // Get file descriptor flags.
                 let binding = ProcessManager::current_pcb().fd_table();
                 let fd_table_guard = binding.read();
-                if let Some(file) = fd_table_guard.get_file_by_fd(fd) {
-                    // drop guard 以避免无法调度的问题
-                    drop(fd_table_guard);
-
-                    if file.lock().close_on_exec() {
-                        return Ok(FD_CLOEXEC as usize);
-                    }
-                }
-                return Err(SystemError::EBADF);
+                let fd_flags = fd_table_guard.fd_flags(fd).ok_or(SystemError::EBADF)?;
+                return Ok(fd_flags.bits() as usize);
             }
             FcntlCommand::SetFd => {
                 // Set file descriptor flags.
                 let binding = ProcessManager::current_pcb().fd_table();
-                let fd_table_guard = binding.write();
-
-                if let Some(file) = fd_table_guard.get_file_by_fd(fd) {
-                    // drop guard 以避免无法调度的问题
-                    drop(fd_table_guard);
-                    let arg = arg as u32;
-                    if arg & FD_CLOEXEC != 0 {
-                        file.lock().set_close_on_exec(true);
-                    } else {
-                        file.lock().set_close_on_exec(false);
-                    }
-                    return Ok(0);
-                }
-                return Err(SystemError::EBADF);
+                let mut fd_table_guard = binding.write();
+                let fd_flags = FdFlags::from_bits_truncate(arg as u32);
+                fd_table_guard.set_fd_flags(fd, fd_flags)?;
+                return Ok(0);
             }
 
             FcntlCommand::GetFlags => {
@@ -633,16 +1210,35 @@ impl Syscall {
                 let fd_table_guard = binding.write();
 
                 if let Some(file) = fd_table_guard.get_file_by_fd(fd) {
-                    let arg = arg as u32;
-                    let mode = FileMode::from_bits(arg).ok_or(SystemError::EINVAL)?;
+                    // 只有这些状态标志可以被修改，访问模式等其他标志保持不变
+                    // 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/fs/fcntl.c?fi=setfl#setfl
+                    let settable = FileMode::O_APPEND
+                        | FileMode::O_NONBLOCK
+                        | FileMode::O_DIRECT
+                        | FileMode::O_NOATIME
+                        | FileMode::FASYNC;
+                    let arg = FileMode::from_bits_truncate(arg as u32) & settable;
                     // drop guard 以避免无法调度的问题
                     drop(fd_table_guard);
-                    file.lock_no_preempt().set_mode(mode)?;
+                    let mut file = file.lock_no_preempt();
+                    let mode = (file.mode() - settable) | arg;
+                    file.set_mode(mode)?;
                     return Ok(0);
                 }
 
                 return Err(SystemError::EBADF);
             }
+            FcntlCommand::AddSeals | FcntlCommand::GetSeals => {
+                let binding = ProcessManager::current_pcb().fd_table();
+                let fd_table_guard = binding.read();
+                let file = fd_table_guard
+                    .get_file_by_fd(fd)
+                    .ok_or(SystemError::EBADF)?;
+                // drop guard 以避免无法调度的问题
+                drop(fd_table_guard);
+                let file = file.lock_no_preempt();
+                return memfd_fcntl(&file, cmd, arg as u32);
+            }
             _ => {
                 // TODO: unimplemented
                 // 未实现的命令，返回0，不报错。
@@ -653,6 +1249,23 @@ impl Syscall {
         }
     }
 
+    /// @brief 创建一个memfd，即一个没有路径的、数据保存在内存中的文件
+    ///
+    /// @param name 文件的名字，仅用于调试
+    /// @param flags MFD_CLOEXEC、MFD_ALLOW_SEALING等标志
+    ///
+    /// @return Ok(usize) 新的文件描述符
+    /// @return Err(SystemError) 错误码
+    pub fn memfd_create(name: &str, flags: u32) -> Result<usize, SystemError> {
+        let flags = MemFdFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let file = memfd_create(name, flags)?;
+        return ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(file, None)
+            .map(|fd| fd as usize);
+    }
+
     /// # ftruncate
     ///
     /// ## 描述
@@ -692,9 +1305,15 @@ impl Syscall {
         // drop guard 以避免无法调度的问题
         drop(fd_table_guard);
 
+        let inode = file.lock().inode();
+        return Self::do_stat_inode(&inode);
+    }
+
+    /// @brief 根据inode的元数据，填写stat结构体
+    fn do_stat_inode(inode: &Arc<dyn IndexNode>) -> Result<PosixKstat, SystemError> {
         let mut kstat = PosixKstat::new();
         // 获取文件信息
-        let metadata = file.lock().metadata()?;
+        let metadata = inode.metadata()?;
         kstat.size = metadata.size as i64;
         kstat.dev_id = metadata.dev_id as u64;
         kstat.inode = metadata.inode_id.into() as u64;
@@ -713,7 +1332,7 @@ impl Syscall {
         kstat.gid = metadata.gid as i32;
         kstat.rdev = metadata.raw_dev as i64;
         kstat.mode = metadata.mode;
-        match file.lock().file_type() {
+        match metadata.file_type {
             FileType::File => kstat.mode.insert(ModeType::S_IFREG),
             FileType::Dir => kstat.mode.insert(ModeType::S_IFDIR),
             FileType::BlockDevice => kstat.mode.insert(ModeType::S_IFBLK),
@@ -729,11 +1348,59 @@ impl Syscall {
 
     pub fn fstat(fd: i32, usr_kstat: *mut PosixKstat) -> Result<usize, SystemError> {
         let kstat = Self::do_fstat(fd)?;
+        return Self::copy_kstat_to_user(&kstat, usr_kstat);
+    }
+
+    /// @brief 获取文件的状态，相对路径从dirfd对应的目录开始查找
+    ///
+    /// @param dirfd 目录的文件描述符，为AT_FDCWD时从当前工作目录开始查找
+    /// @param path 文件路径
+    /// @param usr_kstat 用户空间的stat结构体
+    /// @param flags AT_SYMLINK_NOFOLLOW、AT_EMPTY_PATH的组合
+    ///
+    /// @return Err(SystemError::EINVAL) flags中有不支持的标志
+    /// @return Err(SystemError::ENOENT) path为空，且没有指定AT_EMPTY_PATH
+    pub fn fstatat(
+        dirfd: i32,
+        path: &str,
+        usr_kstat: *mut PosixKstat,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags & !(AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let kstat = if path.is_empty() {
+            if flags & AT_EMPTY_PATH == 0 {
+                return Err(SystemError::ENOENT);
+            }
+            if dirfd == AT_FDCWD {
+                Self::do_stat_inode(&lookup_at(AT_FDCWD, ".", true)?)?
+            } else {
+                Self::do_fstat(dirfd)?
+            }
+        } else {
+            let inode = lookup_at(dirfd, path, flags & AT_SYMLINK_NOFOLLOW == 0)?;
+            Self::do_stat_inode(&inode)?
+        };
+        return Self::copy_kstat_to_user(&kstat, usr_kstat);
+    }
+
+    fn copy_kstat_to_user(
+        kstat: &PosixKstat,
+        usr_kstat: *mut PosixKstat,
+    ) -> Result<usize, SystemError> {
         if usr_kstat.is_null() {
             return Err(SystemError::EFAULT);
         }
         unsafe {
-            *usr_kstat = kstat;
+            copy_to_user(
+                VirtAddr::new(usr_kstat as usize),
+                core::slice::from_raw_parts(
+                    kstat as *const PosixKstat as *const u8,
+                    core::mem::size_of::<PosixKstat>(),
+                ),
+            )?;
         }
         return Ok(0);
     }
@@ -744,33 +1411,227 @@ impl Syscall {
         dev_t: DeviceNumber,
     ) -> Result<usize, SystemError> {
         // 安全检验
-        let len = unsafe { CStr::from_ptr(path_ptr).to_bytes().len() };
-        let user_buffer = UserBufferReader::new(path_ptr, len, true)?;
-        let buf = user_buffer.read_from_user::<u8>(0)?;
-        let path = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+        let path = check_and_clone_cstr(path_ptr as *const u8, Some(MAX_PATHLEN + 1))?;
+        let path = path.as_str();
 
         // 文件名过长
         if path.len() > MAX_PATHLEN as usize {
             return Err(SystemError::ENAMETOOLONG);
         }
 
-        let inode: Result<Arc<dyn IndexNode>, SystemError> =
-            ROOT_INODE().lookup_follow_symlink(path, VFS_MAX_FOLLOW_SYMLINK_TIMES);
+        let inode: Result<Arc<dyn IndexNode>, SystemError> = lookup_at(AT_FDCWD, path, false);
 
         if inode.is_ok() {
             return Err(SystemError::EEXIST);
         }
 
-        let (filename, parent_path) = rsplit_path(path);
-
         // 查找父目录
-        let parent_inode: Arc<dyn IndexNode> = ROOT_INODE()
-            .lookup_follow_symlink(parent_path.unwrap_or("/"), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+        let (parent_inode, filename) = lookup_parent_at(AT_FDCWD, path)?;
         // 创建nod
         parent_inode.mknod(filename, mode, dev_t)?;
 
         return Ok(0);
     }
+
+    /// @brief 等待一组文件描述符上的事件
+    ///
+    /// @param fds 用户空间的struct pollfd数组
+    /// @param nfds 数组的长度
+    /// @param timeout_ms 超时时间(单位：毫秒)，为负数时一直等待
+    ///
+    /// @return Ok(usize) 有事件发生的文件描述符的数量，超时时返回0
+    pub fn poll(fds: UserPtr<PollFd>, nfds: usize, timeout_ms: i32) -> Result<usize, SystemError> {
+        let timeout_us = if timeout_ms < 0 {
+            None
+        } else {
+            Some(timeout_ms as u64 * 1000)
+        };
+        return Self::do_sys_poll(fds, nfds, timeout_us);
+    }
+
+    /// @brief 等待一组文件描述符上的事件，等待期间使用给定的信号掩码
+    ///
+    /// @param fds 用户空间的struct pollfd数组
+    /// @param nfds 数组的长度
+    /// @param tsp 超时时间，为空时一直等待
+    /// @param sigmask 等待期间使用的信号掩码，为空时不改变信号掩码
+    /// @param sigsetsize 信号掩码的大小
+    ///
+    /// @return Ok(usize) 有事件发生的文件描述符的数量，超时时返回0
+    pub fn ppoll(
+        fds: UserPtr<PollFd>,
+        nfds: usize,
+        tsp: UserPtr<TimeSpec>,
+        sigmask: UserPtr<SigSet>,
+        sigsetsize: usize,
+    ) -> Result<usize, SystemError> {
+        let timeout_us = Self::poll_timeout_from_timespec(tsp)?;
+        let old_mask = Self::poll_set_sigmask(sigmask, sigsetsize)?;
+        let r = Self::do_sys_poll(fds, nfds, timeout_us);
+        if let Some(mut old_mask) = old_mask {
+            set_current_sig_blocked(&mut old_mask);
+        }
+        return r;
+    }
+
+    /// @brief 等待文件描述符集合中的文件可读、可写或者发生异常
+    ///
+    /// @param nfds 最大的文件描述符加一
+    /// @param readfds 等待可读的文件描述符集合，返回时只保留可读的文件描述符
+    /// @param writefds 等待可写的文件描述符集合，返回时只保留可写的文件描述符
+    /// @param exceptfds 等待异常事件的文件描述符集合，返回时只保留发生异常的文件描述符
+    /// @param timeout 超时时间，为空时一直等待
+    ///
+    /// @return Ok(usize) 三个集合中被设置的文件描述符的总数，超时时返回0
+    pub fn select(
+        nfds: i32,
+        readfds: UserPtr<u64>,
+        writefds: UserPtr<u64>,
+        exceptfds: UserPtr<u64>,
+        timeout: UserPtr<PosixTimeval>,
+    ) -> Result<usize, SystemError> {
+        let timeout_us = if timeout.is_null() {
+            None
+        } else {
+            let tv = timeout.read()?;
+            if tv.tv_sec < 0 || tv.tv_usec < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            Some(
+                (tv.tv_sec as u64)
+                    .saturating_mul(1000000)
+                    .saturating_add(tv.tv_usec as u64),
+            )
+        };
+        return Self::do_sys_select(nfds, readfds, writefds, exceptfds, timeout_us);
+    }
+
+    /// @brief select()的变体，超时时间的精度为纳秒，并且等待期间使用给定的信号掩码
+    ///
+    /// @param sig 指向{信号掩码的地址, 信号掩码的大小}，为空时不改变信号掩码
+    ///
+    /// 其余参数与select()相同
+    pub fn pselect6(
+        nfds: i32,
+        readfds: UserPtr<u64>,
+        writefds: UserPtr<u64>,
+        exceptfds: UserPtr<u64>,
+        tsp: UserPtr<TimeSpec>,
+        sig: UserPtr<[usize; 2]>,
+    ) -> Result<usize, SystemError> {
+        let timeout_us = Self::poll_timeout_from_timespec(tsp)?;
+        let old_mask = if sig.is_null() {
+            None
+        } else {
+            let [sigmask, sigsetsize] = sig.read()?;
+            Self::poll_set_sigmask(UserPtr::new(sigmask), sigsetsize)?
+        };
+        let r = Self::do_sys_select(nfds, readfds, writefds, exceptfds, timeout_us);
+        if let Some(mut old_mask) = old_mask {
+            set_current_sig_blocked(&mut old_mask);
+        }
+        return r;
+    }
+
+    /// 把pollfd数组拷贝到内核，等待之后再把结果拷贝回用户空间
+    fn do_sys_poll(
+        fds: UserPtr<PollFd>,
+        nfds: usize,
+        timeout_us: Option<u64>,
+    ) -> Result<usize, SystemError> {
+        if nfds > PROC_MAX_FD_NUM as usize {
+            return Err(SystemError::EINVAL);
+        }
+        let mut pollfds: Vec<PollFd> = Vec::with_capacity(nfds);
+        for i in 0..nfds {
+            pollfds.push(fds.add(i).read()?);
+        }
+
+        let r = do_poll(&mut pollfds, timeout_us)?;
+
+        for (i, pfd) in pollfds.iter().enumerate() {
+            fds.add(i).write(pfd)?;
+        }
+        return Ok(r);
+    }
+
+    /// 把文件描述符集合拷贝到内核，等待之后再把结果拷贝回用户空间
+    fn do_sys_select(
+        nfds: i32,
+        readfds: UserPtr<u64>,
+        writefds: UserPtr<u64>,
+        exceptfds: UserPtr<u64>,
+        timeout_us: Option<u64>,
+    ) -> Result<usize, SystemError> {
+        if nfds < 0 || nfds as usize > FD_SETSIZE {
+            return Err(SystemError::EINVAL);
+        }
+        let nfds = nfds as usize;
+        let words = (nfds + 63) / 64;
+        let read_set = |set: UserPtr<u64>| -> Result<Option<Vec<u64>>, SystemError> {
+            if set.is_null() {
+                return Ok(None);
+            }
+            let words: Result<Vec<u64>, SystemError> =
+                (0..words).map(|i| set.add(i).read()).collect();
+            return Ok(Some(words?));
+        };
+        let mut rset = read_set(readfds)?;
+        let mut wset = read_set(writefds)?;
+        let mut eset = read_set(exceptfds)?;
+
+        let count = do_select(
+            nfds,
+            rset.as_deref_mut(),
+            wset.as_deref_mut(),
+            eset.as_deref_mut(),
+            timeout_us,
+        )?;
+
+        for (ptr, set) in [(readfds, rset), (writefds, wset), (exceptfds, eset)] {
+            if let Some(set) = set {
+                for (i, word) in set.iter().enumerate() {
+                    ptr.add(i).write(word)?;
+                }
+            }
+        }
+        return Ok(count);
+    }
+
+    /// 把用户空间的timespec转换为poll的超时时间(单位：微秒，向上取整)
+    fn poll_timeout_from_timespec(tsp: UserPtr<TimeSpec>) -> Result<Option<u64>, SystemError> {
+        if tsp.is_null() {
+            return Ok(None);
+        }
+        let ts = tsp.read()?;
+        if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= 1000000000 {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Some(
+            (ts.tv_sec as u64)
+                .saturating_mul(1000000)
+                .saturating_add((ts.tv_nsec as u64 + 999) / 1000),
+        ));
+    }
+
+    /// 把当前进程的信号掩码设置为用户给出的掩码
+    ///
+    /// @return Ok(Some(原来的信号掩码))，sigmask为空时返回Ok(None)
+    fn poll_set_sigmask(
+        sigmask: UserPtr<SigSet>,
+        sigsetsize: usize,
+    ) -> Result<Option<SigSet>, SystemError> {
+        if sigmask.is_null() {
+            return Ok(None);
+        }
+        if sigsetsize != size_of::<SigSet>() {
+            return Err(SystemError::EINVAL);
+        }
+        let mut new_mask = sigmask.read()?;
+        let old_mask = *ProcessManager::current_pcb().sig_info().sig_block();
+        set_current_sig_blocked(&mut new_mask);
+        return Ok(Some(old_mask));
+    }
 }
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -784,9 +1645,10 @@ pub struct IoVec {
 /// 用于存储多个来自用户空间的IoVec
 ///
 /// 由于目前内核中的文件系统还不支持分散读写，所以暂时只支持将用户空间的IoVec聚合成一个缓冲区，然后进行操作。
+/// 内核不会直接解引用IoVec中的用户地址，所有的访问都通过`copy_from_user`/`copy_to_user`完成。
 /// TODO：支持分散读写
 #[derive(Debug)]
-pub struct IoVecs(Vec<&'static mut [u8]>);
+pub struct IoVecs(Vec<IoVec>);
 
 impl IoVecs {
     /// 从用户空间的IoVec中构造IoVecs
@@ -801,21 +1663,12 @@ impl IoVecs {
         iovcnt: usize,
         _readv: bool,
     ) -> Result<Self, SystemError> {
-        // 检查iov指针所在空间是否合法
-        if !verify_area(
-            iov as usize as u64,
-            (iovcnt * core::mem::size_of::<IoVec>()) as u64,
-        ) {
-            return Err(SystemError::EFAULT);
-        }
-
-        // 将用户空间的IoVec转换为引用（注意：这里的引用是静态的，因为用户空间的IoVec不会被释放）
-        let iovs: &[IoVec] = core::slice::from_raw_parts(iov, iovcnt);
+        let iov = UserPtr::<IoVec>::from(iov);
+        let mut iovs: Vec<IoVec> = Vec::with_capacity(iovcnt);
 
-        let mut slices: Vec<&mut [u8]> = vec![];
-        slices.reserve(iovs.len());
-
-        for iov in iovs.iter() {
+        for i in 0..iovcnt {
+            // 把用户空间的IoVec拷贝到内核空间
+            let iov = iov.add(i).read()?;
             if iov.iov_len == 0 {
                 continue;
             }
@@ -824,35 +1677,45 @@ impl IoVecs {
                 return Err(SystemError::EFAULT);
             }
 
-            slices.push(core::slice::from_raw_parts_mut(iov.iov_base, iov.iov_len));
+            iovs.push(iov);
         }
 
-        return Ok(Self(slices));
+        return Ok(Self(iovs));
     }
 
     /// @brief 将IoVecs中的数据聚合到一个缓冲区中
     ///
     /// @return 返回聚合后的缓冲区
-    pub fn gather(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
-        for slice in self.0.iter() {
-            buf.extend_from_slice(slice);
+    pub fn gather(&self) -> Result<Vec<u8>, SystemError> {
+        let mut buf = self.new_buf(true);
+        let mut offset = 0;
+        for iov in self.0.iter() {
+            unsafe {
+                copy_from_user(
+                    &mut buf[offset..offset + iov.iov_len],
+                    VirtAddr::new(iov.iov_base as usize),
+                )?;
+            }
+            offset += iov.iov_len;
         }
-        return buf;
+        return Ok(buf);
     }
 
     /// @brief 将给定的数据分散写入到IoVecs中
-    pub fn scatter(&mut self, data: &[u8]) {
+    pub fn scatter(&mut self, data: &[u8]) -> Result<(), SystemError> {
         let mut data: &[u8] = data;
-        for slice in self.0.iter_mut() {
-            let len = core::cmp::min(slice.len(), data.len());
+        for iov in self.0.iter() {
+            let len = core::cmp::min(iov.iov_len, data.len());
             if len == 0 {
                 continue;
             }
 
-            slice[..len].copy_from_slice(&data[..len]);
+            unsafe {
+                copy_to_user(VirtAddr::new(iov.iov_base as usize), &data[..len])?;
+            }
             data = &data[len..];
         }
+        return Ok(());
     }
 
     /// @brief 创建与IoVecs等长的缓冲区
@@ -863,7 +1726,7 @@ impl IoVecs {
     ///
     /// @return 返回创建的缓冲区
     pub fn new_buf(&self, set_len: bool) -> Vec<u8> {
-        let total_len: usize = self.0.iter().map(|slice| slice.len()).sum();
+        let total_len: usize = self.0.iter().map(|iov| iov.iov_len).sum();
         let mut buf: Vec<u8> = Vec::with_capacity(total_len);
 
         if set_len {
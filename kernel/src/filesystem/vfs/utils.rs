@@ -1,3 +1,5 @@
+use alloc::{string::String, vec::Vec};
+
 /// @brief 切分路径字符串，返回最左侧那一级的目录名和剩余的部分。
 ///
 /// 举例：对于 /123/456/789/   本函数返回的第一个值为123, 第二个值为456/789
@@ -19,3 +21,30 @@ pub fn rsplit_path(path: &str) -> (&str, Option<&str>) {
 
     return (comp, rest_opt);
 }
+
+/// @brief 把路径转换为绝对路径，并且去掉其中的"."、".."以及多余的"/"。
+///
+/// 转换只按照字符串进行，不会解析符号链接。".."不会越过根目录
+///
+/// 举例：当前目录为/123/456时，对于 ../789/./ 本函数返回/123/789
+pub fn absolute_path(cwd: &str, path: &str) -> String {
+    let cwd = if path.starts_with('/') { "/" } else { cwd };
+    let mut comps: Vec<&str> = cwd.split('/').filter(|&x| x != "").collect();
+    for seg in path.split('/').filter(|&x| x != "") {
+        if seg == ".." {
+            comps.pop();
+        } else if seg != "." {
+            comps.push(seg);
+        }
+    }
+
+    let mut result = String::new();
+    for seg in comps {
+        result.push('/');
+        result.push_str(seg);
+    }
+    if result.is_empty() {
+        result.push('/');
+    }
+    return result;
+}
@@ -0,0 +1,197 @@
+//! 引导程序传递的机器信息
+//!
+//! 不同的引导协议(BIOS或者UEFI下的multiboot2，以及设备树)提供的内存布局、帧缓冲区、ACPI表的格式各不相同。
+//! 体系结构相关的代码在启动的最早期把它们解析成统一的[`BootInfo`]，内存管理与驱动的初始化代码只读取[`BootInfo`]，
+//! 不再直接解析引导协议的数据。
+//!
+//! 解析发生在内存分配器初始化之前，因此[`BootInfo`]中只能使用固定大小的数组。
+
+use crate::{
+    arch::boot::arch_boot_info_init,
+    libs::rwlock::{RwLock, RwLockReadGuard},
+    mm::PhysAddr,
+};
+
+/// 内存区域的最大数量。相邻的同类型区域会被合并，因此UEFI提供的零碎的内存区域通常不会超过这个数量
+pub const BOOT_MEMORY_REGIONS_MAX: usize = 512;
+/// 保存的RSDP的最大长度(ACPI 2.0及以上版本的RSDP的长度)
+pub const BOOT_RSDP_MAX_LEN: usize = 36;
+
+/// 内核是通过什么方式启动的
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootProtocol {
+    Unknown,
+    /// BIOS下的multiboot2
+    Multiboot2,
+    /// UEFI下的multiboot2，引导程序已经退出了UEFI的启动服务
+    Multiboot2Efi,
+    /// SBI固件(如OpenSBI)传入的设备树
+    DeviceTree,
+}
+
+/// 内存区域的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootMemoryType {
+    /// 可以使用的内存
+    Usable,
+    Reserved,
+    /// 存放ACPI表的内存，解析完ACPI表之后可以回收
+    AcpiReclaimable,
+    /// 固件在休眠时需要保留的内存
+    AcpiNvs,
+    /// 损坏的内存
+    Unusable,
+}
+
+/// 一块物理内存区域
+#[derive(Debug, Clone, Copy)]
+pub struct BootMemoryRegion {
+    pub base: PhysAddr,
+    pub size: usize,
+    pub mem_type: BootMemoryType,
+}
+
+impl BootMemoryRegion {
+    pub const fn new(base: PhysAddr, size: usize, mem_type: BootMemoryType) -> Self {
+        Self {
+            base,
+            size,
+            mem_type,
+        }
+    }
+
+    pub fn end(&self) -> PhysAddr {
+        return PhysAddr::new(self.base.data() + self.size);
+    }
+}
+
+/// 帧缓冲区的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootFramebufferType {
+    /// 使用调色板的图形模式
+    Indexed,
+    /// 直接使用RGB颜色的图形模式(UEFI的GOP只提供这种模式)
+    Rgb,
+    /// 文本模式，宽度与高度以字符为单位
+    Text,
+}
+
+/// 引导程序设置好的帧缓冲区
+#[derive(Debug, Clone, Copy)]
+pub struct BootFramebuffer {
+    pub addr: PhysAddr,
+    /// 每一行的字节数
+    #[allow(dead_code)]
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    /// 每个像素的位数
+    pub bpp: u8,
+    pub fb_type: BootFramebufferType,
+}
+
+/// 统一的启动信息
+#[derive(Debug)]
+pub struct BootInfo {
+    pub protocol: BootProtocol,
+    memory_regions: [BootMemoryRegion; BOOT_MEMORY_REGIONS_MAX],
+    nr_memory_regions: usize,
+    /// 由于区域数量超过[`BOOT_MEMORY_REGIONS_MAX`]而被丢弃的内存区域的数量
+    pub dropped_memory_regions: usize,
+    pub framebuffer: Option<BootFramebuffer>,
+    /// RSDP的副本。引导程序提供的RSDP所在的内存可能在之后被覆盖，因此需要保存下来
+    rsdp: [u8; BOOT_RSDP_MAX_LEN],
+    rsdp_len: usize,
+    /// UEFI系统表的物理地址
+    #[allow(dead_code)]
+    pub efi_system_table: Option<PhysAddr>,
+    /// 扁平设备树(FDT)，没有时为None
+    pub fdt: Option<&'static [u8]>,
+}
+
+impl BootInfo {
+    const fn new() -> Self {
+        Self {
+            protocol: BootProtocol::Unknown,
+            memory_regions: [BootMemoryRegion::new(PhysAddr::new(0), 0, BootMemoryType::Reserved);
+                BOOT_MEMORY_REGIONS_MAX],
+            nr_memory_regions: 0,
+            dropped_memory_regions: 0,
+            framebuffer: None,
+            rsdp: [0; BOOT_RSDP_MAX_LEN],
+            rsdp_len: 0,
+            efi_system_table: None,
+            fdt: None,
+        }
+    }
+
+    /// 所有的内存区域，按照引导程序提供的顺序排列
+    pub fn memory_regions(&self) -> &[BootMemoryRegion] {
+        return &self.memory_regions[..self.nr_memory_regions];
+    }
+
+    /// 添加一块内存区域。与上一块区域相邻并且类型相同时，与它合并
+    pub fn add_memory_region(&mut self, region: BootMemoryRegion) {
+        if region.size == 0 {
+            return;
+        }
+        if let Some(last) = self.memory_regions[..self.nr_memory_regions].last_mut() {
+            if last.mem_type == region.mem_type && last.end() == region.base {
+                last.size += region.size;
+                return;
+            }
+        }
+        if self.nr_memory_regions == BOOT_MEMORY_REGIONS_MAX {
+            self.dropped_memory_regions += 1;
+            return;
+        }
+        self.memory_regions[self.nr_memory_regions] = region;
+        self.nr_memory_regions += 1;
+    }
+
+    /// 是否已经有了内存区域的信息
+    pub fn has_memory_regions(&self) -> bool {
+        self.nr_memory_regions != 0
+    }
+
+    /// ACPI的RSDP，没有时返回None
+    pub fn acpi_rsdp(&self) -> Option<&[u8]> {
+        if self.rsdp_len == 0 {
+            return None;
+        }
+        return Some(&self.rsdp[..self.rsdp_len]);
+    }
+
+    /// 保存RSDP的副本，超出[`BOOT_RSDP_MAX_LEN`]的部分会被丢弃
+    pub fn set_acpi_rsdp(&mut self, rsdp: &[u8]) {
+        let len = rsdp.len().min(BOOT_RSDP_MAX_LEN);
+        self.rsdp = [0; BOOT_RSDP_MAX_LEN];
+        self.rsdp[..len].copy_from_slice(&rsdp[..len]);
+        self.rsdp_len = len;
+    }
+}
+
+static BOOT_INFO: RwLock<BootInfo> = RwLock::new(BootInfo::new());
+
+/// 获取启动信息
+pub fn boot_info() -> RwLockReadGuard<'static, BootInfo> {
+    return BOOT_INFO.read();
+}
+
+/// 解析引导程序传递的信息，必须在内存管理与显示驱动初始化之前调用
+pub fn boot_info_init() {
+    arch_boot_info_init(&mut BOOT_INFO.write());
+}
+
+/// 获取RSDP副本的地址，供C代码使用。启动信息在初始化之后不会再被修改，因此这个地址一直有效
+///
+/// ## 返回值
+///
+/// 引导程序没有提供RSDP时返回空指针
+#[no_mangle]
+pub extern "C" fn rs_boot_info_acpi_rsdp() -> *const u8 {
+    return boot_info()
+        .acpi_rsdp()
+        .map(|rsdp| rsdp.as_ptr())
+        .unwrap_or(core::ptr::null());
+}
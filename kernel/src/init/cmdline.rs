@@ -0,0 +1,244 @@
+//! 内核启动参数，由bootloader通过multiboot2协议传入，格式为以空格分隔的`name=value`或`name`
+//!
+//! 内核的各个模块通过[`kernel_param!`](crate::kernel_param)登记自己关心的参数及其类型，
+//! 每次登记都会在`__param`段中放入一个[`KernelParamOps`]，链接器把它们收集到一起。
+//! 第一次查询任意参数时，会解析一次启动参数，并把值写入所有登记过的参数中，值不合法的参数会被忽略。
+//!
+//! ```ignore
+//! kernel_param!(LOGLEVEL: u8 = "loglevel");
+//!
+//! if let Some(level) = LOGLEVEL.get() { ... }
+//! ```
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/params.c
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ffi::c_void;
+
+use crate::{
+    include::bindings::bindings::{multiboot2_get_cmdline, multiboot2_iter},
+    kernel_test, ktest_assert, kwarn,
+    libs::{once::Once, spinlock::SpinLock},
+    syscall::SystemError,
+};
+
+/// 启动参数的最大长度
+const CMDLINE_MAX_LEN: usize = 4096;
+
+/// 获取完整的内核启动参数
+pub fn kernel_cmdline() -> String {
+    let mut buf = [0u8; CMDLINE_MAX_LEN];
+    let mut len = CMDLINE_MAX_LEN as u32;
+    unsafe {
+        multiboot2_iter(
+            Some(multiboot2_get_cmdline),
+            buf.as_mut_ptr() as *mut c_void,
+            &mut len,
+        )
+    };
+    // 没有找到启动参数时，len不会被修改
+    if len as usize >= CMDLINE_MAX_LEN {
+        return String::new();
+    }
+    return String::from_utf8_lossy(&buf[..len as usize]).to_string();
+}
+
+/// 获取启动参数中指定参数的值
+///
+/// 适用于只在一处使用、不需要登记的参数，其余情况应当使用[`kernel_param!`](crate::kernel_param)
+///
+/// ## 参数
+///
+/// - `name`: 参数名，例如`ip=dhcp`中的`ip`
+///
+/// ## 返回值
+///
+/// 参数的值，参数没有值(例如`quiet`)时为空字符串；参数不存在时返回None。
+/// 同一个参数出现多次时，以最后一次为准
+#[allow(dead_code)]
+pub fn cmdline_get_param(name: &str) -> Option<String> {
+    let cmdline = kernel_cmdline();
+    let mut result = None;
+    for param in cmdline.split_ascii_whitespace() {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        if key == name {
+            result = Some(value.to_string());
+        }
+    }
+    return result;
+}
+
+/// 可以作为启动参数的值的类型
+pub trait ParamValue: Sized {
+    /// 解析参数的值，参数没有值(例如`quiet`)时`value`为空字符串
+    ///
+    /// ## 返回值
+    ///
+    /// 值不合法时返回None
+    fn parse_param(value: &str) -> Option<Self>;
+}
+
+impl ParamValue for bool {
+    fn parse_param(value: &str) -> Option<Self> {
+        match value {
+            "" | "1" | "y" | "Y" | "on" => Some(true),
+            "0" | "n" | "N" | "off" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl ParamValue for String {
+    fn parse_param(value: &str) -> Option<Self> {
+        return Some(value.to_string());
+    }
+}
+
+macro_rules! impl_param_value_for_int {
+    ($($ty:ty),*) => {
+        $(
+            impl ParamValue for $ty {
+                fn parse_param(value: &str) -> Option<Self> {
+                    match value.strip_prefix("0x") {
+                        Some(hex) => <$ty>::from_str_radix(hex, 16).ok(),
+                        None => value.parse().ok(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_param_value_for_int!(u8, u16, u32, u64, usize, i32, i64);
+
+/// 以逗号分隔的列表，例如`ahci.ignore_port=1,3`
+impl<T: ParamValue> ParamValue for Vec<T> {
+    fn parse_param(value: &str) -> Option<Self> {
+        if value.is_empty() {
+            return Some(Vec::new());
+        }
+        return value.split(',').map(T::parse_param).collect();
+    }
+}
+
+/// 登记在`__param`段中的参数
+pub trait KernelParamOps: Sync {
+    /// 参数名
+    fn name(&self) -> &'static str;
+
+    /// 设置参数的值
+    ///
+    /// ## 返回值
+    ///
+    /// 值不合法时返回false
+    fn set(&self, value: &str) -> bool;
+}
+
+/// 一个有类型的启动参数
+pub struct KernelParam<T> {
+    name: &'static str,
+    value: SpinLock<Option<T>>,
+}
+
+impl<T: ParamValue + Clone + Send> KernelParam<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: SpinLock::new(None),
+        }
+    }
+
+    /// 获取参数的值
+    ///
+    /// ## 返回值
+    ///
+    /// 启动参数中没有这个参数，或者它的值不合法时返回None
+    pub fn get(&self) -> Option<T> {
+        parse_kernel_params();
+        return self.value.lock().clone();
+    }
+}
+
+impl<T: ParamValue + Clone + Send> KernelParamOps for KernelParam<T> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn set(&self, value: &str) -> bool {
+        match T::parse_param(value) {
+            Some(v) => {
+                *self.value.lock() = Some(v);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 登记一个有类型的启动参数
+///
+/// ```ignore
+/// kernel_param!(pub AHCI_IGNORE_PORT: Vec<u8> = "ahci.ignore_port");
+/// ```
+#[macro_export]
+macro_rules! kernel_param {
+    ($vis:vis $ident:ident: $ty:ty = $name:literal) => {
+        $vis static $ident: $crate::init::cmdline::KernelParam<$ty> =
+            $crate::init::cmdline::KernelParam::new($name);
+        const _: () = {
+            #[link_section = "__param"]
+            #[used]
+            static __PARAM_ENTRY: &'static dyn $crate::init::cmdline::KernelParamOps = &$ident;
+        };
+    };
+}
+
+extern "C" {
+    static __start___param: &'static dyn KernelParamOps;
+    static __stop___param: &'static dyn KernelParamOps;
+}
+
+/// 获取链接器收集的所有参数
+fn kernel_params() -> &'static [&'static dyn KernelParamOps] {
+    unsafe {
+        let start = &__start___param as *const &'static dyn KernelParamOps;
+        let end = &__stop___param as *const &'static dyn KernelParamOps;
+        let len = (end as usize - start as usize) / core::mem::size_of::<&dyn KernelParamOps>();
+        return core::slice::from_raw_parts(start, len);
+    }
+}
+
+/// 保证启动参数只被解析一次
+static PARAMS_PARSED: Once = Once::new();
+
+/// 解析启动参数，把值写入所有登记过的参数中。同一个参数出现多次时，以最后一次为准
+fn parse_kernel_params() {
+    PARAMS_PARSED.call_once(|| {
+        let params = kernel_params();
+        let cmdline = kernel_cmdline();
+        for param in cmdline.split_ascii_whitespace() {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            for p in params.iter().filter(|p| p.name() == key) {
+                if !p.set(value) {
+                    kwarn!("Invalid boot parameter {}={}", key, value);
+                }
+            }
+        }
+    });
+}
+
+/// 检查各种类型的参数值的解析
+fn cmdline_parse_param() -> Result<(), SystemError> {
+    ktest_assert!(bool::parse_param("") == Some(true));
+    ktest_assert!(bool::parse_param("off") == Some(false));
+    ktest_assert!(bool::parse_param("maybe").is_none());
+    ktest_assert!(u32::parse_param("0x10") == Some(16));
+    ktest_assert!(u8::parse_param("256").is_none());
+    ktest_assert!(Vec::<u8>::parse_param("1,3") == Some(vec![1, 3]));
+    ktest_assert!(Vec::<u8>::parse_param("1,x").is_none());
+    return Ok(());
+}
+kernel_test!(cmdline_parse_param);
@@ -3,7 +3,11 @@ use crate::{
     libs::lib_ui::screen_manager::scm_init,
 };
 
+use self::boot_info::boot_info_init;
+
+pub mod boot_info;
 pub mod c_adapter;
+pub mod cmdline;
 
 fn init_intertrait() {
     intertrait::init_caster_map();
@@ -11,6 +15,7 @@ fn init_intertrait() {
 
 /// 在内存管理初始化之前，执行的初始化
 fn init_before_mem_init() {
+    boot_info_init();
     tty_early_init().expect("tty early init failed");
     unsafe { VideoRefreshManager::video_init().ok() };
     scm_init();
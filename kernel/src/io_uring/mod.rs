@@ -0,0 +1,748 @@
+//! 基于共享内存环的异步I/O接口，接口与Linux的io_uring兼容
+//!
+//! 用户程序通过io_uring_setup创建一个环，得到它的文件描述符，然后通过mmap把环映射到自己的地址空间：
+//! - 提交队列(SQ)：用户程序把请求写入SQE数组，再把SQE的下标放入SQ，之后更新SQ的tail
+//! - 完成队列(CQ)：内核把请求的结果写入CQ，之后更新CQ的tail；用户程序读取结果之后更新CQ的head
+//!
+//! io_uring_enter消费SQ中的请求。可能阻塞的请求交给内核的工作线程执行(见[`worker`])，
+//! 执行完成之后由工作线程把结果写入CQ。
+//!
+//! 工作线程不能访问用户程序的地址空间。写请求的数据在提交时被拷贝到内核中；
+//! 读请求读取到的数据则要等到用户程序下一次调用io_uring_enter时，才被拷贝到用户的缓冲区，
+//! 之后它的结果才会出现在CQ中。
+//!
+//! 目前支持的操作：NOP、FSYNC、READ、WRITE、SEND、RECV
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/io_uring/io_uring.c
+
+pub mod syscall;
+mod worker;
+
+use core::{
+    any::Any,
+    fmt::Debug,
+    mem::size_of,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    arch::MMArch,
+    filesystem::{
+        anon_inode::anon_inode_fs,
+        vfs::{
+            core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData,
+            FileSystem, FileType, IndexNode, Metadata, PollStatus, PollTable,
+        },
+    },
+    libs::{mutex::Mutex, spinlock::SpinLock, wait_queue::WaitQueue},
+    mm::{
+        allocator::page_frame::{
+            allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
+        },
+        syscall::ProtFlags,
+        ucontext::AddressSpace,
+        MemoryManagementArch, PhysAddr, VirtAddr,
+    },
+    process::ProcessManager,
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        SystemError,
+    },
+    time::TimeSpec,
+};
+
+use self::worker::{io_wq_enqueue, IoWork, IoWorkOp};
+
+/// mmap的偏移量：SQ
+pub const IORING_OFF_SQ_RING: usize = 0;
+/// mmap的偏移量：CQ。SQ与CQ位于同一块内存中，映射哪一个都可以
+pub const IORING_OFF_CQ_RING: usize = 0x8000000;
+/// mmap的偏移量：SQE数组
+pub const IORING_OFF_SQES: usize = 0x10000000;
+
+/// io_uring_setup的标志：由用户程序指定CQ的大小
+pub const IORING_SETUP_CQSIZE: u32 = 1 << 3;
+
+/// SQ与CQ可以通过一次mmap映射
+pub const IORING_FEAT_SINGLE_MMAP: u32 = 1 << 0;
+/// CQ满了的时候，结果不会被丢弃，而是暂存在内核中
+pub const IORING_FEAT_NODROP: u32 = 1 << 1;
+
+/// io_uring_enter的标志：等待min_complete个结果
+pub const IORING_ENTER_GETEVENTS: u32 = 1 << 0;
+
+/// SQ的最大长度
+pub const IORING_MAX_ENTRIES: u32 = 4096;
+/// CQ的最大长度
+pub const IORING_MAX_CQ_ENTRIES: u32 = 2 * IORING_MAX_ENTRIES;
+
+/// 一次读写请求最多传输的字节数，读写的数据需要先放在内核的缓冲区中
+const IO_URING_MAX_RW_LEN: usize = 1 << 20;
+
+/// 请求的操作码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum IoUringOp {
+    Nop = 0,
+    Fsync = 3,
+    Read = 22,
+    Write = 23,
+    Send = 26,
+    Recv = 27,
+}
+
+impl IoUringOp {
+    fn from_u8(opcode: u8) -> Option<Self> {
+        match opcode {
+            0 => Some(Self::Nop),
+            3 => Some(Self::Fsync),
+            22 => Some(Self::Read),
+            23 => Some(Self::Write),
+            26 => Some(Self::Send),
+            27 => Some(Self::Recv),
+            _ => None,
+        }
+    }
+}
+
+/// SQ的各个字段在环中的偏移量，与Linux的`struct io_sqring_offsets`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoSqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub flags: u32,
+    pub dropped: u32,
+    pub array: u32,
+    pub resv1: u32,
+    pub resv2: u64,
+}
+
+/// CQ的各个字段在环中的偏移量，与Linux的`struct io_cqring_offsets`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoCqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub overflow: u32,
+    pub cqes: u32,
+    pub flags: u32,
+    pub resv1: u32,
+    pub resv2: u64,
+}
+
+/// io_uring_setup的参数，与Linux的`struct io_uring_params`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoUringParams {
+    pub sq_entries: u32,
+    pub cq_entries: u32,
+    pub flags: u32,
+    pub sq_thread_cpu: u32,
+    pub sq_thread_idle: u32,
+    pub features: u32,
+    pub wq_fd: u32,
+    pub resv: [u32; 3],
+    pub sq_off: IoSqringOffsets,
+    pub cq_off: IoCqringOffsets,
+}
+
+/// 提交队列中的请求，与Linux的`struct io_uring_sqe`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoUringSqe {
+    pub opcode: u8,
+    pub flags: u8,
+    pub ioprio: u16,
+    pub fd: i32,
+    /// 读写的偏移量，为u64::MAX时使用并更新文件指针
+    pub off: u64,
+    /// 用户缓冲区的地址
+    pub addr: u64,
+    pub len: u32,
+    /// 与操作相关的标志，例如fsync_flags、msg_flags
+    pub op_flags: u32,
+    /// 原样返回给用户程序的数据
+    pub user_data: u64,
+    pub buf_index: u16,
+    pub personality: u16,
+    pub splice_fd_in: i32,
+    pub addr3: u64,
+    pub _pad2: u64,
+}
+
+/// 完成队列中的结果，与Linux的`struct io_uring_cqe`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoUringCqe {
+    pub user_data: u64,
+    /// 成功时为传输的字节数，失败时为负的错误码
+    pub res: i32,
+    pub flags: u32,
+}
+
+const _: () = assert!(size_of::<IoUringParams>() == 120);
+const _: () = assert!(size_of::<IoUringSqe>() == 64);
+const _: () = assert!(size_of::<IoUringCqe>() == 16);
+
+/// 环的头部，位于环形内存的开头，字段的偏移量通过[`IoUringParams`]告诉用户程序
+#[repr(C)]
+struct IoRingHeader {
+    sq_head: AtomicU32,
+    sq_tail: AtomicU32,
+    sq_ring_mask: u32,
+    sq_ring_entries: u32,
+    sq_flags: AtomicU32,
+    sq_dropped: AtomicU32,
+    cq_head: AtomicU32,
+    cq_tail: AtomicU32,
+    cq_ring_mask: u32,
+    cq_ring_entries: u32,
+    cq_overflow: AtomicU32,
+    cq_flags: AtomicU32,
+}
+
+/// CQE数组在环中的偏移量，之后是SQ的下标数组
+const IO_RING_CQES_OFFSET: usize = 64;
+const _: () = assert!(size_of::<IoRingHeader>() <= IO_RING_CQES_OFFSET);
+
+/// 一段物理上连续、被映射给用户程序的内存
+#[derive(Debug)]
+struct RingPages {
+    paddr: PhysAddr,
+    /// 分配的页数，是2的幂
+    count: PageFrameCount,
+}
+
+impl RingPages {
+    fn new(bytes: usize) -> Result<Self, SystemError> {
+        let pages = (bytes + MMArch::PAGE_SIZE - 1) / MMArch::PAGE_SIZE;
+        let count = PageFrameCount::new(pages.next_power_of_two());
+        let (paddr, count) = unsafe { allocate_page_frames(count) }.ok_or(SystemError::ENOMEM)?;
+        let r = Self { paddr, count };
+        unsafe { MMArch::write_bytes(r.vaddr(), 0, count.bytes()) };
+        return Ok(r);
+    }
+
+    fn vaddr(&self) -> VirtAddr {
+        return unsafe { MMArch::phys_2_virt(self.paddr) }.unwrap();
+    }
+
+    fn frames(&self) -> Vec<PhysPageFrame> {
+        return (0..self.count.data())
+            .map(|i| PhysPageFrame::new(PhysAddr::new(self.paddr.data() + i * MMArch::PAGE_SIZE)))
+            .collect();
+    }
+}
+
+impl Drop for RingPages {
+    fn drop(&mut self) {
+        unsafe { deallocate_page_frames(PhysPageFrame::new(self.paddr), self.count) };
+    }
+}
+
+/// 读请求读取到的数据，等待提交请求的进程把它拷贝到用户缓冲区
+struct PendingCopy {
+    cqe: IoUringCqe,
+    addr: VirtAddr,
+    data: Vec<u8>,
+    /// 提交请求的进程的地址空间，只有在这个地址空间中才能完成拷贝
+    address_space: Arc<AddressSpace>,
+}
+
+struct InnerCq {
+    /// CQ满了的时候，结果暂存在这里
+    overflow: VecDeque<IoUringCqe>,
+    /// 等待拷贝到用户缓冲区的读请求
+    pending_copy: VecDeque<PendingCopy>,
+}
+
+/// 一个io_uring实例
+pub struct IoRing {
+    /// 头部、CQE数组与SQ的下标数组
+    rings: RingPages,
+    /// SQE数组
+    sqes: RingPages,
+    sq_entries: u32,
+    cq_entries: u32,
+    /// 同一时间只能有一个进程消费SQ
+    submit_lock: Mutex<()>,
+    cq: SpinLock<InnerCq>,
+    /// 已经交给工作线程、还没有完成的请求的数量
+    inflight: AtomicUsize,
+    /// 等待结果的进程
+    wait_queue: WaitQueue,
+}
+
+impl Debug for IoRing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "IoRing {{ sq_entries: {}, cq_entries: {}, inflight: {} }}",
+            self.sq_entries,
+            self.cq_entries,
+            self.inflight.load(Ordering::SeqCst)
+        )
+    }
+}
+
+impl IoRing {
+    /// 创建一个环，并填写params中的长度与偏移量
+    ///
+    /// ## 参数
+    ///
+    /// - `entries`：SQ的长度，会被向上取整到2的幂
+    /// - `params`：用户程序传入的参数
+    fn new(entries: u32, params: &mut IoUringParams) -> Result<Arc<Self>, SystemError> {
+        if entries == 0 || entries > IORING_MAX_ENTRIES {
+            return Err(SystemError::EINVAL);
+        }
+        let sq_entries = entries.next_power_of_two();
+        let cq_entries = if params.flags & IORING_SETUP_CQSIZE != 0 {
+            if params.cq_entries == 0 || params.cq_entries > IORING_MAX_CQ_ENTRIES {
+                return Err(SystemError::EINVAL);
+            }
+            let cq_entries = params.cq_entries.next_power_of_two();
+            if cq_entries < sq_entries {
+                return Err(SystemError::EINVAL);
+            }
+            cq_entries
+        } else {
+            2 * sq_entries
+        };
+
+        let array_offset = IO_RING_CQES_OFFSET + cq_entries as usize * size_of::<IoUringCqe>();
+        let rings = RingPages::new(array_offset + sq_entries as usize * size_of::<u32>())?;
+        let sqes = RingPages::new(sq_entries as usize * size_of::<IoUringSqe>())?;
+
+        let ring = Self {
+            rings,
+            sqes,
+            sq_entries,
+            cq_entries,
+            submit_lock: Mutex::new(()),
+            cq: SpinLock::new(InnerCq {
+                overflow: VecDeque::new(),
+                pending_copy: VecDeque::new(),
+            }),
+            inflight: AtomicUsize::new(0),
+            wait_queue: WaitQueue::INIT,
+        };
+        let header = unsafe { &mut *ring.header_ptr() };
+        header.sq_ring_mask = sq_entries - 1;
+        header.sq_ring_entries = sq_entries;
+        header.cq_ring_mask = cq_entries - 1;
+        header.cq_ring_entries = cq_entries;
+
+        // 头部的字段都是u32，偏移量为字段的序号乘以4
+        params.sq_entries = sq_entries;
+        params.cq_entries = cq_entries;
+        params.features = IORING_FEAT_SINGLE_MMAP | IORING_FEAT_NODROP;
+        params.sq_off = IoSqringOffsets {
+            head: 0,
+            tail: 4,
+            ring_mask: 8,
+            ring_entries: 12,
+            flags: 16,
+            dropped: 20,
+            array: array_offset as u32,
+            ..Default::default()
+        };
+        params.cq_off = IoCqringOffsets {
+            head: 24,
+            tail: 28,
+            ring_mask: 32,
+            ring_entries: 36,
+            overflow: 40,
+            flags: 44,
+            cqes: IO_RING_CQES_OFFSET as u32,
+            ..Default::default()
+        };
+        return Ok(Arc::new(ring));
+    }
+
+    fn header_ptr(&self) -> *mut IoRingHeader {
+        return self.rings.vaddr().data() as *mut IoRingHeader;
+    }
+
+    fn header(&self) -> &IoRingHeader {
+        return unsafe { &*self.header_ptr() };
+    }
+
+    /// SQ中第`index`项的SQE下标
+    fn sq_array(&self, index: u32) -> u32 {
+        let offset = IO_RING_CQES_OFFSET
+            + self.cq_entries as usize * size_of::<IoUringCqe>()
+            + (index & (self.sq_entries - 1)) as usize * size_of::<u32>();
+        let ptr = (self.rings.vaddr().data() + offset) as *const AtomicU32;
+        return unsafe { &*ptr }.load(Ordering::Relaxed);
+    }
+
+    fn sqe(&self, index: u32) -> IoUringSqe {
+        let ptr = (self.sqes.vaddr().data() + index as usize * size_of::<IoUringSqe>())
+            as *const IoUringSqe;
+        return unsafe { ptr.read_volatile() };
+    }
+
+    /// CQ中已经有多少个结果
+    fn cq_ready(&self) -> u32 {
+        let header = self.header();
+        return header
+            .cq_tail
+            .load(Ordering::Relaxed)
+            .wrapping_sub(header.cq_head.load(Ordering::Acquire));
+    }
+
+    /// 把结果写入CQ，调用者需要持有cq的锁
+    ///
+    /// ## 返回值
+    ///
+    /// CQ已满时返回false
+    fn fill_cqe(&self, cqe: &IoUringCqe) -> bool {
+        if self.cq_ready() >= self.cq_entries {
+            return false;
+        }
+        let header = self.header();
+        let tail = header.cq_tail.load(Ordering::Relaxed);
+        let offset =
+            IO_RING_CQES_OFFSET + (tail & (self.cq_entries - 1)) as usize * size_of::<IoUringCqe>();
+        let ptr = (self.rings.vaddr().data() + offset) as *mut IoUringCqe;
+        unsafe { ptr.write_volatile(*cqe) };
+        // 结果必须在tail更新之前对用户程序可见
+        header
+            .cq_tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+        return true;
+    }
+
+    /// 写入一个结果，CQ已满时暂存在内核中。调用者需要持有cq的锁
+    fn post_cqe_locked(&self, inner: &mut InnerCq, cqe: IoUringCqe) {
+        if !inner.overflow.is_empty() || !self.fill_cqe(&cqe) {
+            if inner.overflow.is_empty() {
+                self.header().cq_overflow.fetch_add(1, Ordering::Relaxed);
+            }
+            inner.overflow.push_back(cqe);
+        }
+    }
+
+    /// 写入一个结果，之后唤醒等待的进程
+    fn post_cqe(&self, cqe: IoUringCqe) {
+        let mut guard = self.cq.lock_irqsave();
+        self.post_cqe_locked(&mut guard, cqe);
+        drop(guard);
+        self.wait_queue.wakeup_all(None);
+    }
+
+    /// 工作线程完成了一个请求
+    ///
+    /// ## 参数
+    ///
+    /// - `cqe`：请求的结果
+    /// - `copy`：读请求读取到的数据，以及要拷贝到的用户地址和地址空间
+    fn complete(&self, cqe: IoUringCqe, copy: Option<(VirtAddr, Vec<u8>, Arc<AddressSpace>)>) {
+        let mut guard = self.cq.lock_irqsave();
+        // 在cq的锁内减少计数，等待的进程检查计数与结果时看到的是一致的状态
+        self.inflight.fetch_sub(1, Ordering::SeqCst);
+        match copy {
+            Some((addr, data, address_space)) => {
+                guard.pending_copy.push_back(PendingCopy {
+                    cqe,
+                    addr,
+                    data,
+                    address_space,
+                });
+            }
+            None => self.post_cqe_locked(&mut guard, cqe),
+        }
+        drop(guard);
+        self.wait_queue.wakeup_all(None);
+    }
+
+    /// 在提交请求的进程的上下文中，把读取到的数据拷贝到用户缓冲区，并且把暂存的结果写入CQ
+    fn flush_completions(&self) {
+        if let Ok(address_space) = AddressSpace::current() {
+            loop {
+                let pending = {
+                    let mut guard = self.cq.lock_irqsave();
+                    let index = guard
+                        .pending_copy
+                        .iter()
+                        .position(|p| Arc::ptr_eq(&p.address_space, &address_space));
+                    match index {
+                        Some(index) => guard.pending_copy.remove(index).unwrap(),
+                        None => break,
+                    }
+                };
+                let mut cqe = pending.cqe;
+                let r =
+                    UserBufferWriter::new(pending.addr.data() as *mut u8, pending.data.len(), true)
+                        .and_then(|mut writer| writer.copy_to_user(&pending.data, 0));
+                if let Err(e) = r {
+                    cqe.res = e.to_posix_errno();
+                }
+                self.post_cqe(cqe);
+            }
+        }
+
+        let mut guard = self.cq.lock_irqsave();
+        while let Some(cqe) = guard.overflow.front() {
+            if !self.fill_cqe(cqe) {
+                break;
+            }
+            guard.overflow.pop_front();
+        }
+    }
+
+    /// 消费SQ中最多`to_submit`个请求
+    ///
+    /// ## 返回值
+    ///
+    /// 消费的请求的数量。准备请求时出现的错误通过CQ返回，不影响这个数量
+    fn submit(self: &Arc<Self>, to_submit: u32) -> Result<usize, SystemError> {
+        let _guard = self.submit_lock.lock();
+        let header = self.header();
+        let mut head = header.sq_head.load(Ordering::Relaxed);
+        let tail = header.sq_tail.load(Ordering::Acquire);
+        let count = core::cmp::min(to_submit, tail.wrapping_sub(head));
+
+        let mut submitted = 0;
+        for _ in 0..count {
+            let index = self.sq_array(head);
+            head = head.wrapping_add(1);
+            if index >= self.sq_entries {
+                header.sq_dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            let sqe = self.sqe(index);
+            if let Err(e) = self.submit_sqe(&sqe) {
+                self.post_cqe(IoUringCqe {
+                    user_data: sqe.user_data,
+                    res: e.to_posix_errno(),
+                    flags: 0,
+                });
+            }
+            submitted += 1;
+        }
+        // SQE已经被读取，用户程序可以重新使用它们
+        header.sq_head.store(head, Ordering::Release);
+        return Ok(submitted);
+    }
+
+    /// 准备一个请求，把它交给工作线程，或者直接完成它
+    fn submit_sqe(self: &Arc<Self>, sqe: &IoUringSqe) -> Result<(), SystemError> {
+        let op = IoUringOp::from_u8(sqe.opcode).ok_or(SystemError::EINVAL)?;
+        if sqe.flags != 0 || sqe.ioprio != 0 || sqe.buf_index != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if op == IoUringOp::Nop {
+            self.post_cqe(IoUringCqe {
+                user_data: sqe.user_data,
+                res: 0,
+                flags: 0,
+            });
+            return Ok(());
+        }
+
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(sqe.fd)
+            .ok_or(SystemError::EBADF)?;
+        let offset = match sqe.off {
+            u64::MAX => None,
+            off => Some(off as usize),
+        };
+        let len = core::cmp::min(sqe.len as usize, IO_URING_MAX_RW_LEN);
+
+        let work_op = match op {
+            IoUringOp::Nop => unreachable!(),
+            IoUringOp::Fsync => IoWorkOp::Fsync(file),
+            IoUringOp::Read | IoUringOp::Recv => {
+                if op == IoUringOp::Recv && (sqe.op_flags != 0 || sqe.off != 0) {
+                    return Err(SystemError::EINVAL);
+                }
+                // 提前检查缓冲区，避免读取了数据之后才发现无法拷贝
+                UserBufferWriter::new(sqe.addr as *mut u8, len, true)?;
+                IoWorkOp::Read {
+                    file,
+                    offset: if op == IoUringOp::Recv { None } else { offset },
+                    len,
+                    addr: VirtAddr::new(sqe.addr as usize),
+                    address_space: AddressSpace::current()?,
+                }
+            }
+            IoUringOp::Write | IoUringOp::Send => {
+                if op == IoUringOp::Send && (sqe.op_flags != 0 || sqe.off != 0) {
+                    return Err(SystemError::EINVAL);
+                }
+                let reader = UserBufferReader::new(sqe.addr as *const u8, len, true)?;
+                let mut data = vec![0u8; len];
+                reader.copy_from_user(&mut data, 0)?;
+                IoWorkOp::Write {
+                    file,
+                    offset: if op == IoUringOp::Send { None } else { offset },
+                    data,
+                }
+            }
+        };
+
+        self.inflight.fetch_add(1, Ordering::SeqCst);
+        io_wq_enqueue(IoWork::new(self.clone(), sqe.user_data, work_op));
+        return Ok(());
+    }
+
+    /// 等待CQ中至少有`min_complete`个结果
+    ///
+    /// 没有正在执行的请求时，结果不会再增加，直接返回
+    fn wait_cqes(&self, min_complete: u32) -> Result<(), SystemError> {
+        let address_space = AddressSpace::current()?;
+        // 暂存的结果只有在CQ满了的时候才会留在内核中，因此最多只能等到CQ满
+        let min_complete = core::cmp::min(min_complete, self.cq_entries);
+        loop {
+            self.flush_completions();
+            let guard = self.cq.lock_irqsave();
+            if self.cq_ready() >= min_complete {
+                return Ok(());
+            }
+            // 拷贝完成之前又有读请求完成了
+            if guard
+                .pending_copy
+                .iter()
+                .any(|p| Arc::ptr_eq(&p.address_space, &address_space))
+            {
+                drop(guard);
+                continue;
+            }
+            if self.inflight.load(Ordering::SeqCst) == 0 {
+                return Ok(());
+            }
+            // 被信号打断时，还没有完成的请求会继续执行，结果在下一次调用时写入CQ
+            if ProcessManager::current_pcb()
+                .sig_info()
+                .has_pending_signal()
+            {
+                return Err(SystemError::EINTR);
+            }
+            self.wait_queue.sleep_unlock_spinlock(guard);
+        }
+    }
+}
+
+/// io_uring_setup返回的文件描述符对应的inode
+#[derive(Debug)]
+pub struct IoUringInode {
+    ring: Arc<IoRing>,
+    metadata: Metadata,
+}
+
+impl IoUringInode {
+    fn new(ring: Arc<IoRing>) -> Arc<Self> {
+        return Arc::new(Self {
+            ring,
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::File,
+                mode: ModeType::from_bits_truncate(0o600),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: 0,
+            },
+        });
+    }
+
+    pub fn ring(&self) -> &Arc<IoRing> {
+        &self.ring
+    }
+}
+
+impl IndexNode for IoUringInode {
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    /// @brief CQ中有结果时可读
+    fn poll(&self, table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        table.wait(&self.ring.wait_queue);
+        self.ring.flush_completions();
+        let mut status = PollStatus::WRITE;
+        if self.ring.cq_ready() > 0 {
+            status.insert(PollStatus::READ);
+        }
+        return Ok(status);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.clone());
+    }
+
+    /// @brief 映射SQ、CQ或者SQE数组
+    fn mmap_frames(
+        &self,
+        offset: usize,
+        count: PageFrameCount,
+        _prot_flags: ProtFlags,
+    ) -> Result<(Vec<PhysPageFrame>, Arc<dyn Any + Send + Sync>), SystemError> {
+        let pages = match offset {
+            IORING_OFF_SQ_RING | IORING_OFF_CQ_RING => &self.ring.rings,
+            IORING_OFF_SQES => &self.ring.sqes,
+            _ => return Err(SystemError::EINVAL),
+        };
+        if count.data() > pages.count.data() {
+            return Err(SystemError::EINVAL);
+        }
+        let mut frames = pages.frames();
+        frames.truncate(count.data());
+        let backing: Arc<dyn Any + Send + Sync> = self.ring.clone();
+        return Ok((frames, backing));
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return anon_inode_fs();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        return Err(SystemError::ENOTDIR);
+    }
+}
@@ -0,0 +1,100 @@
+use core::mem::size_of;
+
+use crate::{
+    filesystem::vfs::file::{File, FileMode},
+    process::ProcessManager,
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall, SystemError,
+    },
+};
+
+use super::{IoRing, IoUringInode, IoUringParams, IORING_ENTER_GETEVENTS, IORING_SETUP_CQSIZE};
+
+impl Syscall {
+    /// ## io_uring_setup系统调用
+    ///
+    /// 创建一个io_uring实例。不支持SQPOLL、IOPOLL等标志，也不支持共享工作线程池
+    ///
+    /// ## 参数
+    ///
+    /// - `entries`：SQ的长度，会被向上取整到2的幂
+    /// - `params`：输入标志，返回SQ与CQ的长度、各个字段在环中的偏移量
+    ///
+    /// ## 返回值
+    ///
+    /// 成功时返回实例的文件描述符，它带有close-on-exec标志
+    pub fn io_uring_setup(entries: u32, params: *mut IoUringParams) -> Result<usize, SystemError> {
+        let reader = UserBufferReader::new(params, size_of::<IoUringParams>(), true)?;
+        let mut p = IoUringParams::default();
+        reader.copy_one_from_user(&mut p, 0)?;
+        if p.flags & !IORING_SETUP_CQSIZE != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if p.resv.iter().any(|r| *r != 0) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let ring = IoRing::new(entries, &mut p)?;
+        let mut writer = UserBufferWriter::new(params, size_of::<IoUringParams>(), true)?;
+        writer.copy_one_to_user(&p, 0)?;
+
+        let mut file = File::new(IoUringInode::new(ring), FileMode::O_RDWR)?;
+        file.set_close_on_exec(true);
+        let r = ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(file, None)
+            .map(|fd| fd as usize);
+        return r;
+    }
+
+    /// ## io_uring_enter系统调用
+    ///
+    /// 提交SQ中的请求，并且可以等待请求完成
+    ///
+    /// ## 参数
+    ///
+    /// - `fd`：io_uring实例的文件描述符
+    /// - `to_submit`：最多提交的请求数量
+    /// - `min_complete`：设置了IORING_ENTER_GETEVENTS时，等待CQ中至少有这么多个结果
+    /// - `flags`：只支持IORING_ENTER_GETEVENTS
+    ///
+    /// ## 返回值
+    ///
+    /// 成功提交的请求数量
+    pub fn io_uring_enter(
+        fd: i32,
+        to_submit: u32,
+        min_complete: u32,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags & !IORING_ENTER_GETEVENTS != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        let inode = file.lock_no_preempt().inode();
+        let ring = inode
+            .as_any_ref()
+            .downcast_ref::<IoUringInode>()
+            .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?
+            .ring()
+            .clone();
+
+        let submitted = ring.submit(to_submit)?;
+        if flags & IORING_ENTER_GETEVENTS != 0 {
+            match ring.wait_cqes(min_complete) {
+                // 已经提交了请求时，返回提交的数量，结果之后再取
+                Err(SystemError::EINTR) if submitted > 0 => {}
+                r => r?,
+            }
+        } else {
+            ring.flush_completions();
+        }
+        return Ok(submitted);
+    }
+}
@@ -0,0 +1,170 @@
+//! io_uring的工作线程池
+//!
+//! 请求被放入全局的队列，由工作线程取出执行。读写socket、管道等操作可能会一直阻塞，
+//! 因此在没有空闲的工作线程时，会按需创建新的工作线程，直到[`IO_WQ_MAX_WORKERS`]个。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/io_uring/io-wq.c
+
+use alloc::{boxed::Box, collections::VecDeque, format, sync::Arc, vec::Vec};
+
+use crate::{
+    filesystem::vfs::file::File,
+    kwarn,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    mm::{ucontext::AddressSpace, VirtAddr},
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    syscall::SystemError,
+};
+
+use super::{IoRing, IoUringCqe};
+
+/// 工作线程的最大数量
+pub const IO_WQ_MAX_WORKERS: usize = 16;
+
+/// 交给工作线程执行的操作
+pub(super) enum IoWorkOp {
+    Fsync(Arc<SpinLock<File>>),
+    Read {
+        file: Arc<SpinLock<File>>,
+        /// 为None时使用并更新文件指针
+        offset: Option<usize>,
+        len: usize,
+        /// 读取到的数据要拷贝到的用户地址
+        addr: VirtAddr,
+        address_space: Arc<AddressSpace>,
+    },
+    Write {
+        file: Arc<SpinLock<File>>,
+        /// 为None时使用并更新文件指针
+        offset: Option<usize>,
+        /// 提交时从用户缓冲区拷贝的数据
+        data: Vec<u8>,
+    },
+}
+
+/// 一个交给工作线程的请求
+pub(super) struct IoWork {
+    ring: Arc<IoRing>,
+    user_data: u64,
+    op: IoWorkOp,
+}
+
+impl IoWork {
+    pub(super) fn new(ring: Arc<IoRing>, user_data: u64, op: IoWorkOp) -> Self {
+        return Self {
+            ring,
+            user_data,
+            op,
+        };
+    }
+
+    /// 执行请求，并把结果交给环
+    fn run(self) {
+        let mut copy = None;
+        let r = match self.op {
+            IoWorkOp::Fsync(file) => file.lock_no_preempt().inode().sync().map(|_| 0),
+            IoWorkOp::Read {
+                file,
+                offset,
+                len,
+                addr,
+                address_space,
+            } => {
+                let mut buf = vec![0u8; len];
+                let r = {
+                    let mut file = file.lock_no_preempt();
+                    match offset {
+                        Some(offset) => file.pread(offset, len, &mut buf),
+                        None => file.read(len, &mut buf),
+                    }
+                };
+                if let Ok(n) = r {
+                    buf.truncate(n);
+                    copy = Some((addr, buf, address_space));
+                }
+                r
+            }
+            IoWorkOp::Write { file, offset, data } => {
+                let mut file = file.lock_no_preempt();
+                match offset {
+                    Some(offset) => file.pwrite(offset, data.len(), &data),
+                    None => file.write(data.len(), &data),
+                }
+            }
+        };
+
+        let res = match r {
+            Ok(n) => n as i32,
+            Err(e) => e.to_posix_errno(),
+        };
+        let cqe = IoUringCqe {
+            user_data: self.user_data,
+            res,
+            flags: 0,
+        };
+        self.ring.complete(cqe, copy);
+    }
+}
+
+struct IoWorkQueue {
+    works: VecDeque<IoWork>,
+    /// 已经创建的工作线程的数量
+    workers: usize,
+    /// 正在等待请求的工作线程的数量
+    idle: usize,
+}
+
+static IO_WQ: SpinLock<IoWorkQueue> = SpinLock::new(IoWorkQueue {
+    works: VecDeque::new(),
+    workers: 0,
+    idle: 0,
+});
+/// 空闲的工作线程在这个队列上等待
+static IO_WQ_WAIT_QUEUE: WaitQueue = WaitQueue::INIT;
+
+/// 把请求交给工作线程，没有空闲的工作线程时创建一个新的
+pub(super) fn io_wq_enqueue(work: IoWork) {
+    let mut wq = IO_WQ.lock_irqsave();
+    wq.works.push_back(work);
+    let spawn = wq.idle < wq.works.len() && wq.workers < IO_WQ_MAX_WORKERS;
+    if spawn {
+        wq.workers += 1;
+    }
+    let id = wq.workers;
+    drop(wq);
+
+    if spawn {
+        if let Err(e) = io_wq_create_worker(id) {
+            kwarn!("io_uring: failed to create worker: {:?}", e);
+            IO_WQ.lock_irqsave().workers -= 1;
+        }
+    }
+    IO_WQ_WAIT_QUEUE.wakeup(None);
+}
+
+fn io_wq_create_worker(id: usize) -> Result<(), SystemError> {
+    KernelThreadMechanism::create_and_run(
+        KernelThreadClosure::EmptyClosure((Box::new(io_wq_worker_thread), ())),
+        format!("iou-wrk-{}", id),
+    )
+    .ok_or(SystemError::ENOMEM)?;
+    return Ok(());
+}
+
+fn io_wq_worker_thread() -> i32 {
+    loop {
+        let mut wq = IO_WQ.lock_irqsave();
+        let work = match wq.works.pop_front() {
+            Some(work) => work,
+            None => {
+                wq.idle += 1;
+                IO_WQ_WAIT_QUEUE.sleep_unlock_spinlock(wq);
+                IO_WQ.lock_irqsave().idle -= 1;
+                continue;
+            }
+        };
+        drop(wq);
+
+        work.run();
+    }
+}
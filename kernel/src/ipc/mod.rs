@@ -1,4 +1,6 @@
+pub mod mqueue;
 pub mod pipe;
 pub mod signal;
 pub mod signal_types;
 pub mod syscall;
+pub mod sysv;
@@ -0,0 +1,880 @@
+//! POSIX消息队列
+//!
+//! 消息队列是mqueue文件系统根目录下的文件，mqueue文件系统挂载在/dev/mqueue。
+//! mq_open()在根目录中查找或者创建队列，返回的文件描述符通过mq_*系统调用收发消息，读取队列文件得到的是队列的状态。
+//!
+//! 消息按照优先级从高到低接收，优先级相同的消息按照发送的顺序接收。
+//! 消息被发送到空队列，并且没有进程在等待接收时，向通过mq_notify()注册的进程发送通知，然后取消注册。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/ipc/mqueue.c
+
+use core::any::Any;
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    filesystem::vfs::{
+        core::generate_inode_id,
+        file::{File, FileMode, FilePrivateData},
+        syscall::ModeType,
+        FileSystem, FileType, FsInfo, IndexNode, Metadata, PollStatus, PollTable,
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    libs::{casting::DowncastArc, spinlock::SpinLock, wait_queue::WaitQueue},
+    net::socket::NetlinkSocket,
+    process::{Pid, ProcessManager},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter, UserPtr},
+        Syscall, SystemError,
+    },
+    time::{
+        posix_timer::{timespec_to_ns, PosixSigevent, SIGEV_NONE, SIGEV_SIGNAL, SIGEV_THREAD},
+        timekeeping::getnstimeofday,
+        timer::{clock, next_n_us_timer_jiffies},
+        TimeSpec,
+    },
+};
+
+use super::sysv::ipc_wait;
+
+/// 消息优先级的上限(不包括)
+pub const MQ_PRIO_MAX: u32 = 32768;
+/// 队列名字的最大长度
+pub const MQUEUE_MAX_NAMELEN: usize = 255;
+/// 创建队列时没有指定属性，队列中消息数量的上限
+const DFLT_MSGMAX: usize = 10;
+/// 队列中消息数量的上限所能设置的最大值
+const HARD_MSGMAX: i64 = 65536;
+/// 创建队列时没有指定属性，消息的最大长度
+const DFLT_MSGSIZEMAX: usize = 8192;
+/// 消息的最大长度所能设置的最大值
+const HARD_MSGSIZEMAX: i64 = 16 * 1024 * 1024;
+
+/// SIGEV_THREAD通知中，通过netlink socket发送给libc的cookie的长度
+const NOTIFY_COOKIE_LEN: usize = 32;
+/// cookie的最后一个字节：队列中有了新的消息
+const NOTIFY_WOKENUP: u8 = 1;
+/// cookie的最后一个字节：通知被取消
+const NOTIFY_REMOVED: u8 = 2;
+
+/// 与Linux的`struct mq_attr`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixMqAttr {
+    /// 只有O_NONBLOCK有意义
+    pub mq_flags: i64,
+    /// 队列中消息数量的上限
+    pub mq_maxmsg: i64,
+    /// 消息的最大长度
+    pub mq_msgsize: i64,
+    /// 队列中消息的数量
+    pub mq_curmsgs: i64,
+    _reserved: [i64; 4],
+}
+
+lazy_static! {
+    static ref MQUEUE_FS: Arc<MqueueFS> = MqueueFS::new();
+}
+
+/// 获取mqueue文件系统，用于挂载到/dev/mqueue
+pub fn mqueue_fs() -> Arc<MqueueFS> {
+    return MQUEUE_FS.clone();
+}
+
+/// mqueue文件系统
+#[derive(Debug)]
+pub struct MqueueFS {
+    root_inode: Arc<LockedMqueueInode>,
+}
+
+impl MqueueFS {
+    fn new() -> Arc<Self> {
+        let root = LockedMqueueInode::new(
+            None,
+            FileType::Dir,
+            ModeType::S_ISVTX | ModeType::from_bits_truncate(0o777),
+        );
+        let result = Arc::new(Self { root_inode: root });
+        result.root_inode.inner.lock().fs = Arc::downgrade(&result);
+        return result;
+    }
+}
+
+impl FileSystem for MqueueFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: MQUEUE_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// mq_notify()注册的通知方式
+#[derive(Debug)]
+enum MqNotifyMethod {
+    /// SIGEV_NONE：只占用注册，不发送通知
+    None,
+    /// SIGEV_SIGNAL：向注册的进程发送信号。目前siginfo中不包含sigev_value
+    Signal(Signal),
+    /// SIGEV_THREAD：通过netlink socket把cookie发送给libc，由libc在新的线程中调用通知函数
+    Thread {
+        socket: NetlinkSocket,
+        cookie: [u8; NOTIFY_COOKIE_LEN],
+    },
+}
+
+/// 队列上注册的通知
+#[derive(Debug)]
+struct MqNotify {
+    /// 注册通知的进程
+    owner: Pid,
+    method: MqNotifyMethod,
+}
+
+impl MqNotify {
+    /// 队列中有了新的消息，发送通知
+    ///
+    /// ## 参数
+    ///
+    /// - `sender`：发送消息的进程
+    fn deliver(self, sender: Pid) {
+        match self.method {
+            MqNotifyMethod::None => {}
+            MqNotifyMethod::Signal(sig) => {
+                let mut info = SigInfo::new(sig, 0, SigCode::Mesgq, SigType::Kill(sender));
+                // 注册的进程可能已经退出
+                sig.send_signal_info(Some(&mut info), self.owner).ok();
+            }
+            MqNotifyMethod::Thread { socket, mut cookie } => {
+                cookie[NOTIFY_COOKIE_LEN - 1] = NOTIFY_WOKENUP;
+                socket.unicast(&cookie).ok();
+            }
+        }
+    }
+
+    /// 通知被取消。对于SIGEV_THREAD，需要告诉libc结束等待通知的线程
+    fn remove(self) {
+        if let MqNotifyMethod::Thread { socket, mut cookie } = self.method {
+            cookie[NOTIFY_COOKIE_LEN - 1] = NOTIFY_REMOVED;
+            socket.unicast(&cookie).ok();
+        }
+    }
+}
+
+/// 一个消息队列
+#[derive(Debug)]
+struct MessageQueue {
+    /// 队列中消息数量的上限
+    maxmsg: usize,
+    /// 消息的最大长度
+    msgsize: usize,
+    /// 优先级 -> 该优先级的消息，按照发送的顺序排列
+    messages: BTreeMap<u32, VecDeque<Vec<u8>>>,
+    /// 队列中消息的数量
+    curmsgs: usize,
+    /// 队列中消息的总字节数
+    qsize: usize,
+    /// 正在等待接收消息的进程的数量。有进程在等待时，新的消息不会触发通知
+    recv_waiters: usize,
+    notify: Option<MqNotify>,
+}
+
+impl MessageQueue {
+    /// 创建一个空的消息队列
+    ///
+    /// ## 参数
+    ///
+    /// - `attr`：队列的属性，为None时使用默认的属性
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：属性不合法
+    fn new(attr: Option<&PosixMqAttr>) -> Result<Self, SystemError> {
+        let (maxmsg, msgsize) = match attr {
+            Some(attr) => {
+                if attr.mq_maxmsg <= 0
+                    || attr.mq_msgsize <= 0
+                    || attr.mq_maxmsg > HARD_MSGMAX
+                    || attr.mq_msgsize > HARD_MSGSIZEMAX
+                {
+                    return Err(SystemError::EINVAL);
+                }
+                (attr.mq_maxmsg as usize, attr.mq_msgsize as usize)
+            }
+            None => (DFLT_MSGMAX, DFLT_MSGSIZEMAX),
+        };
+        return Ok(Self {
+            maxmsg,
+            msgsize,
+            messages: BTreeMap::new(),
+            curmsgs: 0,
+            qsize: 0,
+            recv_waiters: 0,
+            notify: None,
+        });
+    }
+
+    fn push(&mut self, prio: u32, msg: Vec<u8>) {
+        self.qsize += msg.len();
+        self.curmsgs += 1;
+        self.messages.entry(prio).or_default().push_back(msg);
+    }
+
+    /// 取出优先级最高的消息中，最早发送的那一条
+    fn pop(&mut self) -> Option<(u32, Vec<u8>)> {
+        let mut entry = self.messages.last_entry()?;
+        let prio = *entry.key();
+        let msg = entry.get_mut().pop_front().unwrap();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        self.qsize -= msg.len();
+        self.curmsgs -= 1;
+        return Some((prio, msg));
+    }
+
+    fn attr(&self, mode: FileMode) -> PosixMqAttr {
+        return PosixMqAttr {
+            mq_flags: (mode & FileMode::O_NONBLOCK).bits() as i64,
+            mq_maxmsg: self.maxmsg as i64,
+            mq_msgsize: self.msgsize as i64,
+            mq_curmsgs: self.curmsgs as i64,
+            ..Default::default()
+        };
+    }
+
+    /// 读取队列文件时得到的状态
+    fn status(&self) -> String {
+        let (notify, signo, pid) = match &self.notify {
+            None => (0, 0, 0),
+            Some(n) => match &n.method {
+                MqNotifyMethod::None => (SIGEV_NONE, 0, n.owner.data()),
+                MqNotifyMethod::Signal(sig) => (SIGEV_SIGNAL, *sig as i32, n.owner.data()),
+                MqNotifyMethod::Thread { .. } => (SIGEV_THREAD, 0, n.owner.data()),
+            },
+        };
+        return format!(
+            "QSIZE:{:<10} NOTIFY:{:<5} SIGNO:{:<5} NOTIFY_PID:{:<6}\n",
+            self.qsize, notify, signo, pid
+        );
+    }
+}
+
+/// mqueue文件系统的inode，根目录或者一个消息队列
+#[derive(Debug)]
+pub struct LockedMqueueInode {
+    inner: SpinLock<MqueueInode>,
+    /// 等待队列有空位的发送者
+    send_wait_queue: WaitQueue,
+    /// 等待消息的接收者
+    recv_wait_queue: WaitQueue,
+}
+
+#[derive(Debug)]
+struct MqueueInode {
+    self_ref: Weak<LockedMqueueInode>,
+    /// 根目录下的消息队列
+    children: BTreeMap<String, Arc<LockedMqueueInode>>,
+    /// 消息队列，根目录为None
+    queue: Option<MessageQueue>,
+    metadata: Metadata,
+    fs: Weak<MqueueFS>,
+}
+
+impl LockedMqueueInode {
+    fn new(queue: Option<MessageQueue>, file_type: FileType, mode: ModeType) -> Arc<Self> {
+        return Arc::new_cyclic(|self_ref| Self {
+            inner: SpinLock::new(MqueueInode {
+                self_ref: self_ref.clone(),
+                children: BTreeMap::new(),
+                queue,
+                metadata: Metadata {
+                    dev_id: 0,
+                    inode_id: generate_inode_id(),
+                    size: 0,
+                    blk_size: 0,
+                    blocks: 0,
+                    atime: TimeSpec::default(),
+                    mtime: TimeSpec::default(),
+                    ctime: TimeSpec::default(),
+                    file_type,
+                    mode,
+                    nlinks: 1,
+                    uid: 0,
+                    gid: 0,
+                    raw_dev: 0,
+                },
+                fs: Weak::default(),
+            }),
+            send_wait_queue: WaitQueue::INIT,
+            recv_wait_queue: WaitQueue::INIT,
+        });
+    }
+
+    /// 在根目录中创建一个消息队列
+    ///
+    /// ## 参数
+    ///
+    /// - `name`：队列的名字
+    /// - `mode`：队列文件的权限
+    /// - `attr`：队列的属性，为None时使用默认的属性
+    fn create_queue(
+        &self,
+        name: &str,
+        mode: ModeType,
+        attr: Option<&PosixMqAttr>,
+    ) -> Result<Arc<LockedMqueueInode>, SystemError> {
+        let mut inner = self.inner.lock();
+        if inner.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if inner.children.contains_key(name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let queue = LockedMqueueInode::new(
+            Some(MessageQueue::new(attr)?),
+            FileType::File,
+            mode & ModeType::S_IRWXUGO,
+        );
+        queue.inner.lock().fs = inner.fs.clone();
+        inner.children.insert(String::from(name), queue.clone());
+        return Ok(queue);
+    }
+}
+
+impl IndexNode for LockedMqueueInode {
+    /// 关闭队列的文件描述符时，取消当前进程注册的通知
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        let pid = ProcessManager::current_pcb().pid();
+        let mut inner = self.inner.lock();
+        let notify = match inner.queue.as_mut() {
+            Some(queue) if queue.notify.as_ref().map_or(false, |n| n.owner == pid) => {
+                queue.notify.take()
+            }
+            _ => None,
+        };
+        drop(inner);
+
+        if let Some(notify) = notify {
+            notify.remove();
+        }
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let status = match &self.inner.lock().queue {
+            Some(queue) => queue.status(),
+            None => return Err(SystemError::EISDIR),
+        };
+
+        let start = core::cmp::min(offset, status.len());
+        let end = core::cmp::min(offset + len, status.len());
+        buf[..end - start].copy_from_slice(&status.as_bytes()[start..end]);
+        return Ok(end - start);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        // 只能通过mq_timedsend发送消息
+        return Err(SystemError::EINVAL);
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        let inner = self.inner.lock();
+        let queue = inner.queue.as_ref().ok_or(SystemError::EISDIR)?;
+        let mut status = PollStatus::empty();
+        if queue.curmsgs > 0 {
+            status |= PollStatus::READ;
+        }
+        if queue.curmsgs < queue.maxmsg {
+            status |= PollStatus::WRITE;
+        }
+        return Ok(status);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.inner.lock().metadata.clone());
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inner = self.inner.lock();
+        inner.metadata.atime = metadata.atime;
+        inner.metadata.mtime = metadata.mtime;
+        inner.metadata.ctime = metadata.ctime;
+        inner.metadata.mode = metadata.mode;
+        inner.metadata.uid = metadata.uid;
+        inner.metadata.gid = metadata.gid;
+        return Ok(());
+    }
+
+    /// 通过open(O_CREAT)在/dev/mqueue中创建队列，队列使用默认的属性
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if file_type != FileType::File {
+            return Err(SystemError::EPERM);
+        }
+        return Ok(self.create_queue(name, mode, None)?);
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let mut inner = self.inner.lock();
+        if inner.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        // 已经打开的描述符仍然可以使用这个队列，直到它们被关闭
+        inner.children.remove(name).ok_or(SystemError::ENOENT)?;
+        return Ok(());
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let inner = self.inner.lock();
+        if inner.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        match name {
+            "" | "." | ".." => {
+                return Ok(inner.self_ref.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            name => {
+                return Ok(inner.children.get(name).ok_or(SystemError::ENOENT)?.clone());
+            }
+        }
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.inner.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let inner = self.inner.lock();
+        if inner.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(inner.children.keys().cloned());
+        return Ok(keys);
+    }
+}
+
+/// 检查队列的名字。libc会去掉名字开头的'/'，因此名字中不能再包含'/'
+fn check_mq_name(name: &str) -> Result<(), SystemError> {
+    if name.is_empty() {
+        return Err(SystemError::ENOENT);
+    }
+    if name.len() > MQUEUE_MAX_NAMELEN {
+        return Err(SystemError::ENAMETOOLONG);
+    }
+    if name.contains('/') || name == "." || name == ".." {
+        return Err(SystemError::EACCES);
+    }
+    return Ok(());
+}
+
+/// 获取消息队列描述符对应的队列以及打开模式
+///
+/// ## 错误
+///
+/// - `EBADF`：文件描述符不存在，或者不是消息队列
+fn mq_get(mqdes: i32) -> Result<(Arc<LockedMqueueInode>, FileMode), SystemError> {
+    let binding = ProcessManager::current_pcb().fd_table();
+    let file = binding
+        .read()
+        .get_file_by_fd(mqdes)
+        .ok_or(SystemError::EBADF)?;
+    let file = file.lock();
+    let inode = file
+        .inode()
+        .downcast_arc::<LockedMqueueInode>()
+        .ok_or(SystemError::EBADF)?;
+    if inode.inner.lock().queue.is_none() {
+        return Err(SystemError::EBADF);
+    }
+    return Ok((inode, file.mode()));
+}
+
+/// 把用户传入的绝对超时时刻(CLOCK_REALTIME)转换为定时器的jiffies
+///
+/// ## 参数
+///
+/// - `abs_timeout`：用户空间的`struct timespec`，为0时表示一直等待
+fn mq_deadline(abs_timeout: usize) -> Result<Option<u64>, SystemError> {
+    if abs_timeout == 0 {
+        return Ok(None);
+    }
+    let ts = UserPtr::<TimeSpec>::new(abs_timeout).read()?;
+    let abs = timespec_to_ns(&ts)?;
+    let now = timespec_to_ns(&getnstimeofday()).unwrap_or(0);
+    if abs <= now {
+        // 已经超时，只尝试一次
+        return Ok(Some(clock()));
+    }
+    return Ok(Some(next_n_us_timer_jiffies((abs - now) / 1000)));
+}
+
+impl Syscall {
+    /// ## mq_open系统调用
+    ///
+    /// 打开或者创建消息队列
+    ///
+    /// ## 参数
+    ///
+    /// - `name`：队列的名字，不包含开头的'/'
+    /// - `oflag`：打开模式，支持O_CREAT、O_EXCL、O_NONBLOCK、O_CLOEXEC
+    /// - `mode`：创建队列时，队列文件的权限
+    /// - `attr`：用户空间的`struct mq_attr`，创建队列时的属性。为0时使用默认的属性
+    ///
+    /// ## 返回值
+    ///
+    /// 消息队列描述符
+    pub fn mq_open(name: &str, oflag: u32, mode: u32, attr: usize) -> Result<usize, SystemError> {
+        check_mq_name(name)?;
+        let oflag = FileMode::from_bits_truncate(oflag);
+        if oflag.accmode() == FileMode::O_ACCMODE.bits() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let root = MQUEUE_FS.root_inode.clone();
+        let inode = match root.find(name) {
+            Ok(inode) => {
+                if oflag.contains(FileMode::O_CREAT | FileMode::O_EXCL) {
+                    return Err(SystemError::EEXIST);
+                }
+                inode
+            }
+            Err(SystemError::ENOENT) if oflag.contains(FileMode::O_CREAT) => {
+                let attr = if attr != 0 {
+                    Some(UserPtr::<PosixMqAttr>::new(attr).read()?)
+                } else {
+                    None
+                };
+                root.create_queue(name, ModeType::from_bits_truncate(mode), attr.as_ref())?
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mode = oflag & (FileMode::O_ACCMODE | FileMode::O_NONBLOCK | FileMode::O_CLOEXEC);
+        let file = File::new(inode, mode)?;
+        return ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(file, None)
+            .map(|fd| fd as usize);
+    }
+
+    /// ## mq_unlink系统调用
+    ///
+    /// 删除消息队列。已经打开的描述符仍然可以使用这个队列
+    ///
+    /// ## 参数
+    ///
+    /// - `name`：队列的名字，不包含开头的'/'
+    pub fn mq_unlink(name: &str) -> Result<usize, SystemError> {
+        check_mq_name(name)?;
+        MQUEUE_FS.root_inode.unlink(name)?;
+        return Ok(0);
+    }
+
+    /// ## mq_timedsend系统调用
+    ///
+    /// 向消息队列发送一条消息，队列已满时等待
+    ///
+    /// ## 参数
+    ///
+    /// - `mqdes`：消息队列描述符
+    /// - `msg_ptr`：用户空间的消息
+    /// - `msg_len`：消息的长度
+    /// - `msg_prio`：消息的优先级，数值越大优先级越高
+    /// - `abs_timeout`：用户空间的`struct timespec`，等待的绝对超时时刻。为0时一直等待
+    ///
+    /// ## 错误
+    ///
+    /// - `EAGAIN`：队列已满，且描述符是非阻塞的
+    /// - `EBADF`：描述符不是消息队列，或者不可写
+    /// - `EMSGSIZE`：消息超过了队列的消息长度上限
+    /// - `ETIMEDOUT`：等待超时
+    pub fn mq_timedsend(
+        mqdes: i32,
+        msg_ptr: usize,
+        msg_len: usize,
+        msg_prio: u32,
+        abs_timeout: usize,
+    ) -> Result<usize, SystemError> {
+        if msg_prio >= MQ_PRIO_MAX {
+            return Err(SystemError::EINVAL);
+        }
+        let (mq, mode) = mq_get(mqdes)?;
+        if mode.accmode() == FileMode::O_RDONLY.bits() {
+            return Err(SystemError::EBADF);
+        }
+        if msg_len > mq.inner.lock().queue.as_ref().unwrap().msgsize {
+            return Err(SystemError::EMSGSIZE);
+        }
+        let deadline = mq_deadline(abs_timeout)?;
+
+        let mut msg = vec![0u8; msg_len];
+        if msg_len > 0 {
+            let reader = UserBufferReader::new(msg_ptr as *const u8, msg_len, true)?;
+            reader.copy_from_user(&mut msg, 0)?;
+        }
+
+        loop {
+            let mut inner = mq.inner.lock();
+            let queue = inner.queue.as_mut().unwrap();
+            if queue.curmsgs < queue.maxmsg {
+                // 消息被发送到空队列，并且没有进程在等待时，发送通知
+                let notify = if queue.curmsgs == 0 && queue.recv_waiters == 0 {
+                    queue.notify.take()
+                } else {
+                    None
+                };
+                queue.push(msg_prio, msg);
+                drop(inner);
+
+                mq.recv_wait_queue.wakeup_all(None);
+                if let Some(notify) = notify {
+                    notify.deliver(ProcessManager::current_pcb().pid());
+                }
+                return Ok(0);
+            }
+
+            if mode.contains(FileMode::O_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if deadline.map_or(false, |d| clock() >= d) {
+                return Err(SystemError::ETIMEDOUT);
+            }
+            ipc_wait(&mq.send_wait_queue, inner, deadline)?;
+        }
+    }
+
+    /// ## mq_timedreceive系统调用
+    ///
+    /// 从消息队列接收优先级最高的消息中最早发送的那一条，队列为空时等待
+    ///
+    /// ## 参数
+    ///
+    /// - `mqdes`：消息队列描述符
+    /// - `msg_ptr`：用户空间的缓冲区
+    /// - `msg_len`：缓冲区的长度，不能小于队列的消息长度上限
+    /// - `msg_prio`：用户空间的`unsigned int`，用于保存消息的优先级。为0时不保存
+    /// - `abs_timeout`：用户空间的`struct timespec`，等待的绝对超时时刻。为0时一直等待
+    ///
+    /// ## 返回值
+    ///
+    /// 消息的长度
+    ///
+    /// ## 错误
+    ///
+    /// - `EAGAIN`：队列为空，且描述符是非阻塞的
+    /// - `EBADF`：描述符不是消息队列，或者不可读
+    /// - `EMSGSIZE`：缓冲区小于队列的消息长度上限
+    /// - `ETIMEDOUT`：等待超时
+    pub fn mq_timedreceive(
+        mqdes: i32,
+        msg_ptr: usize,
+        msg_len: usize,
+        msg_prio: usize,
+        abs_timeout: usize,
+    ) -> Result<usize, SystemError> {
+        let (mq, mode) = mq_get(mqdes)?;
+        if mode.accmode() == FileMode::O_WRONLY.bits() {
+            return Err(SystemError::EBADF);
+        }
+        if msg_len < mq.inner.lock().queue.as_ref().unwrap().msgsize {
+            return Err(SystemError::EMSGSIZE);
+        }
+        let deadline = mq_deadline(abs_timeout)?;
+
+        let (prio, msg) = loop {
+            let mut inner = mq.inner.lock();
+            let queue = inner.queue.as_mut().unwrap();
+            if let Some(msg) = queue.pop() {
+                drop(inner);
+                mq.send_wait_queue.wakeup_all(None);
+                break msg;
+            }
+
+            if mode.contains(FileMode::O_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if deadline.map_or(false, |d| clock() >= d) {
+                return Err(SystemError::ETIMEDOUT);
+            }
+            queue.recv_waiters += 1;
+            let r = ipc_wait(&mq.recv_wait_queue, inner, deadline);
+            mq.inner.lock().queue.as_mut().unwrap().recv_waiters -= 1;
+            r?;
+        };
+
+        if !msg.is_empty() {
+            let mut writer = UserBufferWriter::new(msg_ptr as *mut u8, msg.len(), true)?;
+            writer.copy_to_user(&msg, 0)?;
+        }
+        if msg_prio != 0 {
+            UserPtr::<u32>::new(msg_prio).write(&prio)?;
+        }
+        return Ok(msg.len());
+    }
+
+    /// ## mq_notify系统调用
+    ///
+    /// 注册或者取消消息到达的通知。每个队列同时只能有一个进程注册通知
+    ///
+    /// ## 参数
+    ///
+    /// - `mqdes`：消息队列描述符
+    /// - `sevp`：用户空间的`struct sigevent`，为0时取消当前进程注册的通知。
+    ///   SIGEV_THREAD时，`sigev_signo`为libc创建的netlink socket，`sigev_value`指向cookie
+    ///
+    /// ## 错误
+    ///
+    /// - `EBUSY`：已经有进程注册了通知
+    /// - `EINVAL`：通知方式或者信号不合法
+    pub fn mq_notify(mqdes: i32, sevp: usize) -> Result<usize, SystemError> {
+        let (mq, _) = mq_get(mqdes)?;
+        let pid = ProcessManager::current_pcb().pid();
+
+        let method = if sevp != 0 {
+            let event = UserPtr::<PosixSigevent>::new(sevp).read()?;
+            let method = match event.sigev_notify {
+                SIGEV_NONE => MqNotifyMethod::None,
+                SIGEV_SIGNAL => {
+                    let sig = Signal::from(event.sigev_signo);
+                    if sig == Signal::INVALID {
+                        return Err(SystemError::EINVAL);
+                    }
+                    MqNotifyMethod::Signal(sig)
+                }
+                SIGEV_THREAD => {
+                    let mut cookie = [0u8; NOTIFY_COOKIE_LEN];
+                    let reader = UserBufferReader::new(
+                        event.sigev_value as *const u8,
+                        NOTIFY_COOKIE_LEN,
+                        true,
+                    )?;
+                    reader.copy_from_user(&mut cookie, 0)?;
+
+                    let socket_inode = ProcessManager::current_pcb()
+                        .get_socket(event.sigev_signo)
+                        .ok_or(SystemError::EBADF)?;
+                    let socket = socket_inode
+                        .inner()
+                        .as_any_ref()
+                        .downcast_ref::<NetlinkSocket>()
+                        .ok_or(SystemError::ECONNREFUSED)?
+                        .clone();
+                    MqNotifyMethod::Thread { socket, cookie }
+                }
+                _ => return Err(SystemError::EINVAL),
+            };
+            Some(method)
+        } else {
+            None
+        };
+
+        let mut inner = mq.inner.lock();
+        let queue = inner.queue.as_mut().unwrap();
+        match method {
+            Some(method) => {
+                if queue.notify.is_some() {
+                    return Err(SystemError::EBUSY);
+                }
+                queue.notify = Some(MqNotify { owner: pid, method });
+            }
+            None => {
+                // 只能取消当前进程注册的通知
+                if queue.notify.as_ref().map_or(false, |n| n.owner == pid) {
+                    let notify = queue.notify.take().unwrap();
+                    drop(inner);
+                    notify.remove();
+                }
+            }
+        }
+        return Ok(0);
+    }
+
+    /// ## mq_getsetattr系统调用
+    ///
+    /// 获取消息队列的属性，并且设置描述符的O_NONBLOCK标志
+    ///
+    /// ## 参数
+    ///
+    /// - `mqdes`：消息队列描述符
+    /// - `new_attr`：用户空间的`struct mq_attr`，只使用其中的mq_flags。为0时不修改
+    /// - `old_attr`：用户空间的`struct mq_attr`，用于保存修改之前的属性。为0时不保存
+    pub fn mq_getsetattr(
+        mqdes: i32,
+        new_attr: usize,
+        old_attr: usize,
+    ) -> Result<usize, SystemError> {
+        let new_attr = if new_attr != 0 {
+            let attr = UserPtr::<PosixMqAttr>::new(new_attr).read()?;
+            if attr.mq_flags & !(FileMode::O_NONBLOCK.bits() as i64) != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            Some(attr)
+        } else {
+            None
+        };
+
+        let (mq, mode) = mq_get(mqdes)?;
+        let old = mq.inner.lock().queue.as_ref().unwrap().attr(mode);
+
+        if let Some(attr) = new_attr {
+            let binding = ProcessManager::current_pcb().fd_table();
+            let file = binding
+                .read()
+                .get_file_by_fd(mqdes)
+                .ok_or(SystemError::EBADF)?;
+            let mut file = file.lock();
+            let mut mode = file.mode();
+            mode.set(FileMode::O_NONBLOCK, attr.mq_flags != 0);
+            file.set_mode(mode)?;
+        }
+        if old_attr != 0 {
+            UserPtr::<PosixMqAttr>::new(old_attr).write(&old)?;
+        }
+        return Ok(0);
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
     exception::InterruptArch,
     filesystem::vfs::{
         core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData, FileSystem,
-        FileType, IndexNode, Metadata, PollStatus,
+        FileType, IndexNode, Metadata, PollStatus, PollTable,
     },
     libs::{spinlock::SpinLock, wait_queue::WaitQueue},
     process::ProcessState,
@@ -25,6 +25,10 @@ impl PipeFsPrivateData {
     pub fn new(mode: FileMode) -> Self {
         return PipeFsPrivateData { mode: mode };
     }
+
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
 }
 
 /// @brief 管道文件i节点(锁)
@@ -309,8 +313,27 @@ impl IndexNode for LockedPipeInode {
         return Ok(len);
     }
 
-    fn poll(&self) -> Result<PollStatus, crate::syscall::SystemError> {
-        return Ok(PollStatus::READ | PollStatus::WRITE);
+    fn poll(&self, table: &mut PollTable) -> Result<PollStatus, crate::syscall::SystemError> {
+        let inode = self.0.lock();
+        table.wait(&inode.read_wait_queue);
+        table.wait(&inode.write_wait_queue);
+
+        let mut status = PollStatus::empty();
+        if inode.valid_cnt > 0 {
+            status.insert(PollStatus::READ);
+        }
+        if (inode.valid_cnt as usize) < PIPE_BUFF_SIZE {
+            status.insert(PollStatus::WRITE);
+        }
+        // 写端全部关闭之后，读端会读到EOF
+        if inode.writer == 0 {
+            status.insert(PollStatus::HUP);
+        }
+        // 读端全部关闭之后，写入会失败
+        if inode.reader == 0 {
+            status.insert(PollStatus::ERROR);
+        }
+        return Ok(status);
     }
 
     fn as_any_ref(&self) -> &dyn core::any::Any {
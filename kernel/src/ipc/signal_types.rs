@@ -299,13 +299,22 @@ impl SigInfo {
 #[derive(Copy, Clone, Debug)]
 pub enum SigType {
     Kill(Pid),
+    /// 由定时器到期产生，`overrun`为信号发送之前定时器额外到期的次数
+    Timer {
+        timerid: i32,
+        overrun: i32,
+    },
     // 后续完善下列中的具体字段
-    // Timer,
     // Rt,
     // SigChild,
     // SigFault,
     // SigPoll,
-    // SigSys,
+    /// 由seccomp过滤器产生，记录被拦截的系统调用
+    SigSys {
+        call_addr: usize,
+        syscall: i32,
+        arch: u32,
+    },
 }
 
 impl SigInfo {
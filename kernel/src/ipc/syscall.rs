@@ -12,7 +12,10 @@ use crate::{
     kerror, kwarn,
     mm::VirtAddr,
     process::{Pid, ProcessManager},
-    syscall::{user_access::UserBufferWriter, Syscall, SystemError},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall, SystemError,
+    },
 };
 
 use super::{
@@ -37,7 +40,6 @@ impl Syscall {
         {
             let mut user_buffer =
                 UserBufferWriter::new(fd, core::mem::size_of::<[c_int; 2]>(), true)?;
-            let fd = user_buffer.buffer::<i32>(0)?;
             let pipe_ptr = LockedPipeInode::new();
             let mut read_file = File::new(pipe_ptr.clone(), FileMode::O_RDONLY)?;
             read_file.private_data =
@@ -56,8 +58,7 @@ impl Syscall {
 
             drop(fd_table_guard);
 
-            fd[0] = read_fd;
-            fd[1] = write_fd;
+            user_buffer.copy_to_user(&[read_fd, write_fd], 0)?;
             Ok(0)
         } else {
             Err(SystemError::EINVAL)
@@ -105,28 +106,31 @@ impl Syscall {
     ) -> Result<usize, SystemError> {
         // 请注意：用户态传进来的user_sigaction结构体类型，请注意，这个结构体与内核实际的不一样
         let act: *mut UserSigaction = new_act as *mut UserSigaction;
-        let mut old_act = old_act as *mut UserSigaction;
+        let old_act = old_act as *mut UserSigaction;
         let mut new_ka: Sigaction = Default::default();
         let mut old_sigaction: Sigaction = Default::default();
         // 如果传入的，新的sigaction不为空
         if !act.is_null() {
             // 如果参数的范围不在用户空间，则返回错误
-            let r = UserBufferWriter::new(act, core::mem::size_of::<Sigaction>(), from_user);
-            if r.is_err() {
-                return Err(SystemError::EFAULT);
-            }
-            let mask: SigSet = unsafe { (*act).mask };
-            let input_sighandler = unsafe { (*act).handler as u64 };
+            let reader =
+                UserBufferReader::new(act, core::mem::size_of::<UserSigaction>(), from_user)
+                    .map_err(|_| SystemError::EFAULT)?;
+            // 把用户传入的sigaction拷贝到内核空间
+            let mut user_act: UserSigaction = unsafe { core::mem::zeroed() };
+            reader.copy_one_from_user(&mut user_act, 0)?;
+
+            let mask: SigSet = user_act.mask;
+            let input_sighandler = user_act.handler as u64;
             match input_sighandler {
                 USER_SIG_DFL => {
                     new_ka = Sigaction::DEFAULT_SIGACTION.clone();
-                    *new_ka.flags_mut() = unsafe { (*act).flags };
+                    *new_ka.flags_mut() = user_act.flags;
                     new_ka.set_restorer(None);
                 }
 
                 USER_SIG_IGN => {
                     new_ka = Sigaction::DEFAULT_SIGACTION_IGNORE.clone();
-                    *new_ka.flags_mut() = unsafe { (*act).flags };
+                    *new_ka.flags_mut() = user_act.flags;
 
                     new_ka.set_restorer(None);
                 }
@@ -134,12 +138,12 @@ impl Syscall {
                     // 从用户空间获得sigaction结构体
                     // TODO mask是default还是用户空间传入
                     new_ka = Sigaction::new(
-                        SigactionType::SaHandler(SaHandlerType::SigCustomized(unsafe {
-                            VirtAddr::new((*act).handler as usize)
-                        })),
-                        unsafe { (*act).flags },
+                        SigactionType::SaHandler(SaHandlerType::SigCustomized(VirtAddr::new(
+                            user_act.handler as usize,
+                        ))),
+                        user_act.flags,
                         SigSet::default(),
-                        unsafe { Some(VirtAddr::new((*act).restorer as usize)) },
+                        Some(VirtAddr::new(user_act.restorer as usize)),
                     );
                 }
             }
@@ -182,11 +186,9 @@ impl Syscall {
 
         //
         if (retval == Ok(())) && (!old_act.is_null()) {
-            let r =
-                UserBufferWriter::new(old_act, core::mem::size_of::<UserSigaction>(), from_user);
-            if r.is_err() {
-                return Err(SystemError::EFAULT);
-            }
+            let mut writer =
+                UserBufferWriter::new(old_act, core::mem::size_of::<UserSigaction>(), from_user)
+                    .map_err(|_| SystemError::EFAULT)?;
 
             let sigaction_handler: VirtAddr;
             sigaction_handler = match old_sigaction.action() {
@@ -207,14 +209,16 @@ impl Syscall {
                 }
             };
 
-            unsafe {
-                (*old_act).handler = sigaction_handler.data() as *mut c_void;
-                (*old_act).flags = old_sigaction.flags();
-                (*old_act).mask = old_sigaction.mask();
-                if old_sigaction.restorer().is_some() {
-                    (*old_act).restorer = old_sigaction.restorer().unwrap().data() as *mut c_void;
-                }
-            }
+            let user_old_act = UserSigaction {
+                handler: sigaction_handler.data() as *mut c_void,
+                flags: old_sigaction.flags(),
+                restorer: old_sigaction
+                    .restorer()
+                    .map(|restorer| restorer.data() as *mut c_void)
+                    .unwrap_or(core::ptr::null_mut()),
+                mask: old_sigaction.mask(),
+            };
+            writer.copy_one_to_user(&user_old_act, 0)?;
         }
         return retval.map(|_| 0);
     }
@@ -0,0 +1,302 @@
+//! System V IPC：共享内存、信号量集与消息队列
+//!
+//! 三种IPC对象分别保存在IPC命名空间的一张ID表中，用户程序通过key找到对象的ID，然后通过ID操作对象。
+//! key为IPC_PRIVATE时，总是创建新的对象。
+//!
+//! 目前内核中没有用户的概念，所有进程都相当于root，因此只记录对象的权限，而不检查权限。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/ipc/util.c
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+use crate::{
+    arch::{sched::sched, CurrentIrqArch},
+    exception::InterruptArch,
+    libs::{spinlock::SpinLock, spinlock::SpinLockGuard, wait_queue::WaitQueue},
+    process::ProcessManager,
+    syscall::SystemError,
+    time::{timekeeping::getnstimeofday, timer::Timer, timer::WakeUpHelper},
+};
+
+use self::{msg::MsgQueue, sem::SemSet, shm::ShmSegment};
+
+pub mod msg;
+pub mod sem;
+pub mod shm;
+
+/// key为IPC_PRIVATE时，总是创建新的对象
+pub const IPC_PRIVATE: i32 = 0;
+
+bitflags! {
+    /// shmget()、semget()、msgget()等的flags中，除了权限位之外的部分
+    pub struct IpcFlags: u32 {
+        /// 对象不存在时创建它
+        const IPC_CREAT = 0o1000;
+        /// 与IPC_CREAT一起使用，对象已经存在时返回EEXIST
+        const IPC_EXCL = 0o2000;
+        /// 操作需要等待时，直接返回错误
+        const IPC_NOWAIT = 0o4000;
+    }
+}
+
+/// xxxctl()的命令：删除对象
+pub const IPC_RMID: i32 = 0;
+/// xxxctl()的命令：设置对象的属主与权限
+pub const IPC_SET: i32 = 1;
+/// xxxctl()的命令：获取对象的状态
+pub const IPC_STAT: i32 = 2;
+/// xxxctl()的命令：获取系统的限制
+pub const IPC_INFO: i32 = 3;
+/// 部分架构的libc会在命令中带上这个标志，表示使用64位的结构体。内核只支持64位的结构体，因此忽略它
+pub const IPC_64: i32 = 0x100;
+
+/// 对象的权限位
+const IPC_MODE_MASK: u32 = 0o777;
+
+/// 与Linux的`struct ipc64_perm`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixIpcPerm {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub cuid: u32,
+    pub cgid: u32,
+    pub mode: u32,
+    pub seq: u16,
+    _pad1: u16,
+    _pad2: u32,
+    _unused1: u64,
+    _unused2: u64,
+}
+
+/// IPC对象的属主与权限
+#[derive(Debug, Clone)]
+pub struct IpcPerm {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub cuid: u32,
+    pub cgid: u32,
+    /// 低9位为权限，其余的位由各种对象自行定义
+    pub mode: u32,
+}
+
+impl IpcPerm {
+    pub fn new(key: i32, flags: u32) -> Self {
+        return Self {
+            key,
+            uid: 0,
+            gid: 0,
+            cuid: 0,
+            cgid: 0,
+            mode: flags & IPC_MODE_MASK,
+        };
+    }
+
+    pub fn to_posix(&self) -> PosixIpcPerm {
+        return PosixIpcPerm {
+            key: self.key,
+            uid: self.uid,
+            gid: self.gid,
+            cuid: self.cuid,
+            cgid: self.cgid,
+            mode: self.mode,
+            ..Default::default()
+        };
+    }
+
+    /// IPC_SET：修改属主以及权限位
+    pub fn set(&mut self, perm: &PosixIpcPerm) {
+        self.uid = perm.uid;
+        self.gid = perm.gid;
+        self.mode = (self.mode & !IPC_MODE_MASK) | (perm.mode & IPC_MODE_MASK);
+    }
+}
+
+/// 一种IPC对象的ID表
+#[derive(Debug)]
+pub struct IpcIds<T> {
+    /// ID -> 对象
+    objects: BTreeMap<i32, Arc<T>>,
+    /// key -> ID，不包括key为IPC_PRIVATE的对象，以及已经被删除、但是还有进程在使用的对象
+    keys: BTreeMap<i32, i32>,
+    /// 下一个分配的ID
+    next_id: i32,
+    /// 对象数量的上限
+    max: usize,
+}
+
+impl<T> IpcIds<T> {
+    pub fn new(max: usize) -> Self {
+        return Self {
+            objects: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            next_id: 0,
+            max,
+        };
+    }
+
+    /// 根据key查找对象，必要时创建新的对象
+    ///
+    /// ## 参数
+    ///
+    /// - `key`：对象的key
+    /// - `flags`：xxxget()的flags
+    /// - `check`：对象已经存在时，检查它是否满足要求
+    /// - `create`：创建新的对象，参数为新对象的ID
+    ///
+    /// ## 返回值
+    ///
+    /// 对象的ID
+    ///
+    /// ## 错误
+    ///
+    /// - `EEXIST`：同时指定了IPC_CREAT与IPC_EXCL，但是对象已经存在
+    /// - `ENOENT`：对象不存在，且没有指定IPC_CREAT
+    /// - `ENOSPC`：对象的数量已经达到上限
+    pub fn get_or_create(
+        &mut self,
+        key: i32,
+        flags: IpcFlags,
+        check: impl FnOnce(&Arc<T>) -> Result<(), SystemError>,
+        create: impl FnOnce(i32) -> Result<Arc<T>, SystemError>,
+    ) -> Result<i32, SystemError> {
+        if key != IPC_PRIVATE {
+            if let Some(id) = self.keys.get(&key).cloned() {
+                if flags.contains(IpcFlags::IPC_CREAT | IpcFlags::IPC_EXCL) {
+                    return Err(SystemError::EEXIST);
+                }
+                check(&self.objects[&id])?;
+                return Ok(id);
+            }
+            if !flags.contains(IpcFlags::IPC_CREAT) {
+                return Err(SystemError::ENOENT);
+            }
+        }
+
+        if self.objects.len() >= self.max {
+            return Err(SystemError::ENOSPC);
+        }
+        let mut id = self.next_id;
+        while self.objects.contains_key(&id) {
+            id = id.checked_add(1).unwrap_or(0);
+        }
+        self.next_id = id.checked_add(1).unwrap_or(0);
+
+        let obj = create(id)?;
+        self.objects.insert(id, obj);
+        if key != IPC_PRIVATE {
+            self.keys.insert(key, id);
+        }
+        return Ok(id);
+    }
+
+    pub fn get(&self, id: i32) -> Option<Arc<T>> {
+        return self.objects.get(&id).cloned();
+    }
+
+    /// 使对象不能再通过key找到
+    pub fn hide_key(&mut self, id: i32) {
+        self.keys.retain(|_, v| *v != id);
+    }
+
+    /// 从ID表中删除对象
+    pub fn remove(&mut self, id: i32) -> Option<Arc<T>> {
+        self.hide_key(id);
+        return self.objects.remove(&id);
+    }
+
+    /// 所有对象，按照ID排序
+    pub fn objects(&self) -> Vec<(i32, Arc<T>)> {
+        return self
+            .objects
+            .iter()
+            .map(|(id, obj)| (*id, obj.clone()))
+            .collect();
+    }
+
+    /// 当前对象的数量
+    pub fn count(&self) -> usize {
+        return self.objects.len();
+    }
+
+    /// 当前最大的ID，没有对象时返回0
+    pub fn max_id(&self) -> i32 {
+        return self.objects.keys().next_back().cloned().unwrap_or(0);
+    }
+}
+
+/// IPC命名空间，保存三种IPC对象的ID表
+#[derive(Debug)]
+pub struct IpcNamespace {
+    pub shm_ids: SpinLock<IpcIds<ShmSegment>>,
+    pub sem_ids: SpinLock<IpcIds<SemSet>>,
+    pub msg_ids: SpinLock<IpcIds<MsgQueue>>,
+}
+
+lazy_static! {
+    /// 初始的IPC命名空间，目前所有进程都使用它
+    static ref INIT_IPC_NS: IpcNamespace = IpcNamespace {
+        shm_ids: SpinLock::new(IpcIds::new(shm::SHMMNI)),
+        sem_ids: SpinLock::new(IpcIds::new(sem::SEMMNI)),
+        msg_ids: SpinLock::new(IpcIds::new(msg::MSGMNI)),
+    };
+}
+
+/// 获取当前进程的IPC命名空间
+#[inline]
+pub fn current_ipc_ns() -> &'static IpcNamespace {
+    return &INIT_IPC_NS;
+}
+
+/// 当前时间，单位为秒
+fn ipc_now() -> i64 {
+    return getnstimeofday().tv_sec;
+}
+
+/// 当前进程的pid
+fn ipc_current_pid() -> i32 {
+    return ProcessManager::current_pcb().pid().data() as i32;
+}
+
+/// 释放IPC对象的锁，在等待队列上睡眠，直到被唤醒、超时或者收到信号
+///
+/// 被唤醒之后，调用者需要重新加锁，检查条件以及是否已经超时
+///
+/// ## 参数
+///
+/// - `wait_queue`：等待队列
+/// - `guard`：IPC对象的锁，在进程加入等待队列之后释放
+/// - `deadline`：超时的时刻(单位：jiffies)，为None时一直等待
+///
+/// ## 错误
+///
+/// - `EINTR`：收到了信号
+pub(super) fn ipc_wait<T>(
+    wait_queue: &WaitQueue,
+    guard: SpinLockGuard<T>,
+    deadline: Option<u64>,
+) -> Result<(), SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    if pcb.sig_info().has_pending_signal() {
+        return Err(SystemError::EINTR);
+    }
+
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    unsafe { wait_queue.sleep_without_schedule() };
+    drop(guard);
+    if let Some(deadline) = deadline {
+        let timer = Timer::new(WakeUpHelper::new(pcb.clone()), deadline);
+        timer.activate();
+    }
+    drop(irq_guard);
+    sched();
+
+    // 被定时器或者信号唤醒时，进程还在等待队列中
+    wait_queue.remove(&pcb);
+    if pcb.sig_info().has_pending_signal() {
+        return Err(SystemError::EINTR);
+    }
+    return Ok(());
+}
@@ -0,0 +1,480 @@
+//! System V消息队列
+//!
+//! 每条消息带有一个类型，msgrcv()可以按照类型选择要接收的消息。
+//! 队列中消息的总字节数不能超过队列的容量，队列满时发送者等待；没有满足条件的消息时接收者等待。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/ipc/msg.c
+
+use core::{fmt::Write, mem::size_of};
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter, UserPtr},
+        Syscall, SystemError,
+    },
+};
+
+use super::{
+    current_ipc_ns, ipc_current_pid, ipc_now, ipc_wait, IpcFlags, IpcPerm, PosixIpcPerm, IPC_64,
+    IPC_INFO, IPC_RMID, IPC_SET, IPC_STAT,
+};
+
+/// 一条消息的最大字节数
+pub const MSGMAX: usize = 8192;
+/// 队列的默认容量(字节)
+pub const MSGMNB: usize = 16384;
+/// 消息队列数量的上限
+pub const MSGMNI: usize = 32000;
+
+bitflags! {
+    /// msgsnd()、msgrcv()的flags中，除了IPC_NOWAIT之外的部分
+    pub struct MsgFlags: u32 {
+        /// 消息过长时截断它，而不是返回E2BIG
+        const MSG_NOERROR = 0o10000;
+        /// 接收第一条类型不等于msgtyp的消息
+        const MSG_EXCEPT = 0o20000;
+        /// 复制队列中第msgtyp条消息，而不把它从队列中移除
+        const MSG_COPY = 0o40000;
+    }
+}
+
+/// msgctl()的命令：与IPC_STAT相同，但是参数为ID表中的序号
+pub const MSG_STAT: i32 = 11;
+/// msgctl()的命令：获取消息队列的使用情况
+pub const MSG_INFO: i32 = 12;
+
+/// 与Linux的`struct msqid64_ds`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixMsqidDs {
+    pub msg_perm: PosixIpcPerm,
+    pub msg_stime: i64,
+    pub msg_rtime: i64,
+    pub msg_ctime: i64,
+    pub msg_cbytes: u64,
+    pub msg_qnum: u64,
+    pub msg_qbytes: u64,
+    pub msg_lspid: i32,
+    pub msg_lrpid: i32,
+    _unused4: u64,
+    _unused5: u64,
+}
+
+/// 与Linux的`struct msginfo`相同，用于IPC_INFO与MSG_INFO
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixMsgInfo {
+    pub msgpool: i32,
+    pub msgmap: i32,
+    pub msgmax: i32,
+    pub msgmnb: i32,
+    pub msgmni: i32,
+    pub msgssz: i32,
+    pub msgtql: i32,
+    pub msgseg: u16,
+}
+
+/// 队列中的一条消息
+#[derive(Debug)]
+struct Msg {
+    mtype: i64,
+    text: Vec<u8>,
+}
+
+/// 一个消息队列
+#[derive(Debug)]
+pub struct MsgQueue {
+    inner: SpinLock<InnerMsgQueue>,
+    /// 等待队列有空闲空间的发送者
+    send_wq: WaitQueue,
+    /// 等待消息的接收者
+    recv_wq: WaitQueue,
+}
+
+#[derive(Debug)]
+struct InnerMsgQueue {
+    perm: IpcPerm,
+    messages: VecDeque<Msg>,
+    /// 队列中消息的总字节数
+    cbytes: usize,
+    /// 队列的容量(字节)
+    qbytes: usize,
+    /// 最后一次msgsnd()的时间
+    stime: i64,
+    /// 最后一次msgrcv()的时间
+    rtime: i64,
+    /// 最后一次修改的时间
+    ctime: i64,
+    /// 最后一次msgsnd()的进程的pid
+    lspid: i32,
+    /// 最后一次msgrcv()的进程的pid
+    lrpid: i32,
+    /// 是否已经被IPC_RMID删除
+    removed: bool,
+}
+
+impl MsgQueue {
+    fn new(key: i32, flags: u32) -> Arc<Self> {
+        return Arc::new(Self {
+            inner: SpinLock::new(InnerMsgQueue {
+                perm: IpcPerm::new(key, flags),
+                messages: VecDeque::new(),
+                cbytes: 0,
+                qbytes: MSGMNB,
+                stime: 0,
+                rtime: 0,
+                ctime: ipc_now(),
+                lspid: 0,
+                lrpid: 0,
+                removed: false,
+            }),
+            send_wq: WaitQueue::INIT,
+            recv_wq: WaitQueue::INIT,
+        });
+    }
+
+    fn stat(&self) -> PosixMsqidDs {
+        let inner = self.inner.lock();
+        return PosixMsqidDs {
+            msg_perm: inner.perm.to_posix(),
+            msg_stime: inner.stime,
+            msg_rtime: inner.rtime,
+            msg_ctime: inner.ctime,
+            msg_cbytes: inner.cbytes as u64,
+            msg_qnum: inner.messages.len() as u64,
+            msg_qbytes: inner.qbytes as u64,
+            msg_lspid: inner.lspid,
+            msg_lrpid: inner.lrpid,
+            ..Default::default()
+        };
+    }
+}
+
+impl InnerMsgQueue {
+    /// 按照msgrcv()的规则查找消息
+    ///
+    /// ## 参数
+    ///
+    /// - `msgtyp`：为0时选择第一条消息；大于0时选择第一条类型为msgtyp的消息(指定MSG_EXCEPT时为第一条类型不为msgtyp的消息)；
+    ///   小于0时选择类型不超过|msgtyp|的消息中类型最小的第一条
+    /// - `except`：是否指定了MSG_EXCEPT
+    ///
+    /// ## 返回值
+    ///
+    /// 消息在队列中的序号
+    fn find(&self, msgtyp: i64, except: bool) -> Option<usize> {
+        if msgtyp == 0 {
+            return if self.messages.is_empty() {
+                None
+            } else {
+                Some(0)
+            };
+        }
+        if msgtyp > 0 {
+            return self
+                .messages
+                .iter()
+                .position(|msg| (msg.mtype == msgtyp) != except);
+        }
+
+        let limit = msgtyp.checked_neg().unwrap_or(i64::MAX);
+        let mut found: Option<(usize, i64)> = None;
+        for (i, msg) in self.messages.iter().enumerate() {
+            if msg.mtype <= limit && found.map_or(true, |(_, mtype)| msg.mtype < mtype) {
+                found = Some((i, msg.mtype));
+            }
+        }
+        return found.map(|(i, _)| i);
+    }
+}
+
+/// 生成/proc/sysvipc/msg的内容
+pub fn msg_show() -> String {
+    let mut s = String::from(
+        "       key      msqid perms      cbytes       qnum lspid lrpid   uid   gid  cuid  cgid      stime      rtime      ctime\n",
+    );
+    for (id, queue) in current_ipc_ns().msg_ids.lock().objects() {
+        let ds = queue.stat();
+        let p = &ds.msg_perm;
+        writeln!(
+            s,
+            "{:>10} {:>10}  {:>4o}  {:>10} {:>10} {:>5} {:>5} {:>5} {:>5} {:>5} {:>5} {:>10} {:>10} {:>10}",
+            p.key,
+            id,
+            p.mode & 0o7777,
+            ds.msg_cbytes,
+            ds.msg_qnum,
+            ds.msg_lspid,
+            ds.msg_lrpid,
+            p.uid,
+            p.gid,
+            p.cuid,
+            p.cgid,
+            ds.msg_stime,
+            ds.msg_rtime,
+            ds.msg_ctime
+        )
+        .ok();
+    }
+    return s;
+}
+
+impl Syscall {
+    /// ## msgget系统调用
+    ///
+    /// 根据key获取消息队列，必要时创建新的队列
+    ///
+    /// ## 参数
+    ///
+    /// - `key`：队列的key，为IPC_PRIVATE时总是创建新的队列
+    /// - `msgflg`：IPC_CREAT、IPC_EXCL以及权限位
+    ///
+    /// ## 返回值
+    ///
+    /// 队列的ID
+    pub fn msgget(key: i32, msgflg: u32) -> Result<usize, SystemError> {
+        let flags = IpcFlags::from_bits_truncate(msgflg);
+        return current_ipc_ns()
+            .msg_ids
+            .lock()
+            .get_or_create(key, flags, |_| Ok(()), |_| Ok(MsgQueue::new(key, msgflg)))
+            .map(|id| id as usize);
+    }
+
+    /// ## msgsnd系统调用
+    ///
+    /// 向消息队列发送一条消息，队列满时等待
+    ///
+    /// ## 参数
+    ///
+    /// - `msqid`：队列的ID
+    /// - `msgp`：用户空间的消息，开头是`long`类型的消息类型，接着是消息的内容
+    /// - `msgsz`：消息内容的字节数
+    /// - `msgflg`：IPC_NOWAIT
+    ///
+    /// ## 错误
+    ///
+    /// - `EAGAIN`：队列已满，且指定了IPC_NOWAIT
+    /// - `EIDRM`：队列已经被删除
+    /// - `EINVAL`：消息类型小于1，或者消息过长
+    pub fn msgsnd(
+        msqid: i32,
+        msgp: usize,
+        msgsz: usize,
+        msgflg: u32,
+    ) -> Result<usize, SystemError> {
+        if msgsz > MSGMAX {
+            return Err(SystemError::EINVAL);
+        }
+        let mtype = UserPtr::<i64>::new(msgp).read()?;
+        if mtype < 1 {
+            return Err(SystemError::EINVAL);
+        }
+        let mut text = vec![0u8; msgsz];
+        if msgsz > 0 {
+            let reader =
+                UserBufferReader::new((msgp + size_of::<i64>()) as *const u8, msgsz, true)?;
+            reader.copy_from_user(&mut text, 0)?;
+        }
+
+        let queue = current_ipc_ns()
+            .msg_ids
+            .lock()
+            .get(msqid)
+            .ok_or(SystemError::EINVAL)?;
+        loop {
+            let mut inner = queue.inner.lock();
+            if inner.removed {
+                return Err(SystemError::EIDRM);
+            }
+            if msgsz > inner.qbytes {
+                return Err(SystemError::EINVAL);
+            }
+            // 与Linux相同，消息的数量也不能超过队列的容量，防止大量的空消息占满内存
+            if inner.cbytes + msgsz <= inner.qbytes && inner.messages.len() < inner.qbytes {
+                inner.messages.push_back(Msg { mtype, text });
+                inner.cbytes += msgsz;
+                inner.stime = ipc_now();
+                inner.lspid = ipc_current_pid();
+                drop(inner);
+                queue.recv_wq.wakeup_all(None);
+                return Ok(0);
+            }
+
+            if msgflg & IpcFlags::IPC_NOWAIT.bits() != 0 {
+                return Err(SystemError::EAGAIN);
+            }
+            ipc_wait(&queue.send_wq, inner, None)?;
+        }
+    }
+
+    /// ## msgrcv系统调用
+    ///
+    /// 从消息队列接收一条消息，没有满足条件的消息时等待
+    ///
+    /// ## 参数
+    ///
+    /// - `msqid`：队列的ID
+    /// - `msgp`：用户空间的缓冲区，开头是`long`类型的消息类型，接着是消息的内容
+    /// - `msgsz`：缓冲区中用于保存消息内容的字节数
+    /// - `msgtyp`：选择消息的规则，见[`InnerMsgQueue::find`]。指定MSG_COPY时为消息在队列中的序号
+    /// - `msgflg`：IPC_NOWAIT、MSG_NOERROR、MSG_EXCEPT、MSG_COPY
+    ///
+    /// ## 返回值
+    ///
+    /// 复制到缓冲区中的消息内容的字节数
+    ///
+    /// ## 错误
+    ///
+    /// - `E2BIG`：消息比缓冲区长，且没有指定MSG_NOERROR
+    /// - `EIDRM`：队列已经被删除
+    /// - `ENOMSG`：没有满足条件的消息，且指定了IPC_NOWAIT
+    pub fn msgrcv(
+        msqid: i32,
+        msgp: usize,
+        msgsz: usize,
+        msgtyp: i64,
+        msgflg: u32,
+    ) -> Result<usize, SystemError> {
+        if (msgsz as isize) < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let flags = MsgFlags::from_bits_truncate(msgflg);
+        let nowait = msgflg & IpcFlags::IPC_NOWAIT.bits() != 0;
+        let copy = flags.contains(MsgFlags::MSG_COPY);
+        if copy && (!nowait || flags.contains(MsgFlags::MSG_EXCEPT)) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let queue = current_ipc_ns()
+            .msg_ids
+            .lock()
+            .get(msqid)
+            .ok_or(SystemError::EINVAL)?;
+        let (mtype, mut text) = loop {
+            let mut inner = queue.inner.lock();
+            if inner.removed {
+                return Err(SystemError::EIDRM);
+            }
+
+            let index = if copy {
+                (msgtyp >= 0 && (msgtyp as usize) < inner.messages.len()).then(|| msgtyp as usize)
+            } else {
+                inner.find(msgtyp, flags.contains(MsgFlags::MSG_EXCEPT))
+            };
+            if let Some(index) = index {
+                let len = inner.messages[index].text.len();
+                if len > msgsz && !flags.contains(MsgFlags::MSG_NOERROR) {
+                    return Err(SystemError::E2BIG);
+                }
+                if copy {
+                    let msg = &inner.messages[index];
+                    break (msg.mtype, msg.text.clone());
+                }
+
+                let msg = inner.messages.remove(index).unwrap();
+                inner.cbytes -= len;
+                inner.rtime = ipc_now();
+                inner.lrpid = ipc_current_pid();
+                drop(inner);
+                queue.send_wq.wakeup_all(None);
+                break (msg.mtype, msg.text);
+            }
+
+            if nowait {
+                return Err(SystemError::ENOMSG);
+            }
+            ipc_wait(&queue.recv_wq, inner, None)?;
+        };
+
+        text.truncate(msgsz);
+        UserPtr::<i64>::new(msgp).write(&mtype)?;
+        if !text.is_empty() {
+            let mut writer =
+                UserBufferWriter::new((msgp + size_of::<i64>()) as *mut u8, text.len(), true)?;
+            writer.copy_to_user(&text, 0)?;
+        }
+        return Ok(text.len());
+    }
+
+    /// ## msgctl系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `msqid`：队列的ID。对于IPC_INFO、MSG_INFO，忽略该参数
+    /// - `cmd`：命令
+    /// - `buf`：用户空间的缓冲区，结构体的类型由命令决定
+    ///
+    /// ## 返回值
+    ///
+    /// - IPC_INFO、MSG_INFO：ID表中最大的ID
+    /// - MSG_STAT：队列的ID
+    /// - 其他命令：0
+    pub fn msgctl(msqid: i32, cmd: i32, buf: usize) -> Result<usize, SystemError> {
+        let ns = current_ipc_ns();
+        let cmd = cmd & !IPC_64;
+        if cmd == IPC_INFO || cmd == MSG_INFO {
+            let ids = ns.msg_ids.lock();
+            let mut info = PosixMsgInfo {
+                msgpool: (MSGMNI * MSGMNB / 1024) as i32,
+                msgmap: MSGMNB as i32,
+                msgmax: MSGMAX as i32,
+                msgmnb: MSGMNB as i32,
+                msgmni: MSGMNI as i32,
+                msgssz: 16,
+                msgtql: MSGMNB as i32,
+                msgseg: 0xffff,
+            };
+            if cmd == MSG_INFO {
+                info.msgpool = ids.count() as i32;
+                info.msgmap = 0;
+                info.msgtql = 0;
+                for (_, queue) in ids.objects() {
+                    let inner = queue.inner.lock();
+                    info.msgmap += inner.messages.len() as i32;
+                    info.msgtql += inner.cbytes as i32;
+                }
+            }
+            let max_id = ids.max_id();
+            drop(ids);
+            UserPtr::<PosixMsgInfo>::new(buf).write(&info)?;
+            return Ok(max_id as usize);
+        }
+
+        let mut ids = ns.msg_ids.lock();
+        let queue = ids.get(msqid).ok_or(SystemError::EINVAL)?;
+        if cmd == IPC_RMID {
+            ids.remove(msqid);
+            queue.inner.lock().removed = true;
+            drop(ids);
+            queue.send_wq.wakeup_all(None);
+            queue.recv_wq.wakeup_all(None);
+            return Ok(0);
+        }
+        drop(ids);
+
+        match cmd {
+            IPC_STAT | MSG_STAT => {
+                UserPtr::<PosixMsqidDs>::new(buf).write(&queue.stat())?;
+                return Ok(if cmd == MSG_STAT { msqid as usize } else { 0 });
+            }
+            IPC_SET => {
+                let ds = UserPtr::<PosixMsqidDs>::new(buf).read()?;
+                let mut inner = queue.inner.lock();
+                inner.perm.set(&ds.msg_perm);
+                inner.qbytes = ds.msg_qbytes as usize;
+                inner.ctime = ipc_now();
+                drop(inner);
+                // 容量可能变大了
+                queue.send_wq.wakeup_all(None);
+                return Ok(0);
+            }
+            _ => {
+                return Err(SystemError::EINVAL);
+            }
+        }
+    }
+}
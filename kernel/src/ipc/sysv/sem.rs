@@ -0,0 +1,625 @@
+//! System V信号量集
+//!
+//! semop()中的多个操作要么全部完成，要么一个都不执行。无法完成时，进程在信号量集的等待队列上睡眠，
+//! 信号量的值发生变化时唤醒所有等待的进程，由它们重新尝试。
+//!
+//! 带有SEM_UNDO标志的操作会记录到进程的调整值中，进程退出时由[`exit_sem`]撤销。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/ipc/sem.c
+
+use core::fmt::Write;
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter, UserPtr},
+        Syscall, SystemError,
+    },
+    time::{
+        posix_timer::timespec_to_ns,
+        timer::{clock, next_n_us_timer_jiffies},
+        TimeSpec,
+    },
+};
+
+use super::{
+    current_ipc_ns, ipc_current_pid, ipc_now, ipc_wait, IpcFlags, IpcPerm, PosixIpcPerm, IPC_64,
+    IPC_INFO, IPC_RMID, IPC_SET, IPC_STAT,
+};
+
+/// 一个信号量集中信号量数量的上限
+pub const SEMMSL: usize = 32000;
+/// 信号量集数量的上限
+pub const SEMMNI: usize = 32000;
+/// 系统中信号量总数的上限
+pub const SEMMNS: usize = SEMMNI * SEMMSL;
+/// 一次semop()中操作数量的上限
+pub const SEMOPM: usize = 500;
+/// 信号量的最大值
+pub const SEMVMX: i32 = 32767;
+/// 进程退出时调整值的最大值
+pub const SEMAEM: i32 = SEMVMX;
+
+/// 操作的flags：进程退出时撤销该操作
+pub const SEM_UNDO: i16 = 0x1000;
+
+/// semctl()的命令：获取最后一次操作信号量的进程的pid
+pub const GETPID: i32 = 11;
+/// semctl()的命令：获取信号量的值
+pub const GETVAL: i32 = 12;
+/// semctl()的命令：获取所有信号量的值
+pub const GETALL: i32 = 13;
+/// semctl()的命令：获取等待信号量增加的进程的数量
+pub const GETNCNT: i32 = 14;
+/// semctl()的命令：获取等待信号量变为0的进程的数量
+pub const GETZCNT: i32 = 15;
+/// semctl()的命令：设置信号量的值
+pub const SETVAL: i32 = 16;
+/// semctl()的命令：设置所有信号量的值
+pub const SETALL: i32 = 17;
+/// semctl()的命令：与IPC_STAT相同，但是参数为ID表中的序号
+pub const SEM_STAT: i32 = 18;
+/// semctl()的命令：获取信号量的使用情况
+pub const SEM_INFO: i32 = 19;
+
+/// 与Linux的`struct sembuf`相同，表示semop()中的一个操作
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SemBuf {
+    /// 信号量在集合中的序号
+    pub sem_num: u16,
+    /// 为正数时增加信号量的值；为负数时减少信号量的值；为0时等待信号量变为0
+    pub sem_op: i16,
+    /// IPC_NOWAIT、SEM_UNDO
+    pub sem_flg: i16,
+}
+
+/// 与Linux的`struct semid64_ds`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixSemidDs {
+    pub sem_perm: PosixIpcPerm,
+    pub sem_otime: i64,
+    _unused1: u64,
+    pub sem_ctime: i64,
+    _unused2: u64,
+    pub sem_nsems: u64,
+    _unused3: u64,
+    _unused4: u64,
+}
+
+/// 与Linux的`struct seminfo`相同，用于IPC_INFO与SEM_INFO
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixSemInfo {
+    pub semmap: i32,
+    pub semmni: i32,
+    pub semmns: i32,
+    pub semmnu: i32,
+    pub semmsl: i32,
+    pub semopm: i32,
+    pub semume: i32,
+    pub semusz: i32,
+    pub semvmx: i32,
+    pub semaem: i32,
+}
+
+/// 一个信号量
+#[derive(Debug, Clone, Default)]
+struct Sem {
+    val: i32,
+    /// 最后一次操作它的进程的pid
+    pid: i32,
+    /// 等待它增加的进程的数量
+    ncnt: usize,
+    /// 等待它变为0的进程的数量
+    zcnt: usize,
+}
+
+/// 一个信号量集
+#[derive(Debug)]
+pub struct SemSet {
+    id: i32,
+    inner: SpinLock<InnerSemSet>,
+    /// 等待信号量变化的进程
+    wait_queue: WaitQueue,
+}
+
+#[derive(Debug)]
+struct InnerSemSet {
+    perm: IpcPerm,
+    sems: Vec<Sem>,
+    /// 最后一次semop()的时间
+    otime: i64,
+    /// 最后一次修改的时间
+    ctime: i64,
+    /// 是否已经被IPC_RMID删除
+    removed: bool,
+}
+
+/// 进程对一个信号量集的调整值，进程退出时加到信号量上
+#[derive(Debug)]
+struct SemUndo {
+    set: Weak<SemSet>,
+    adj: Vec<i32>,
+}
+
+lazy_static! {
+    /// pid -> (信号量集的ID -> 调整值)
+    ///
+    /// 加锁顺序：先锁信号量集，再锁这个表
+    static ref SEM_UNDO_LIST: SpinLock<BTreeMap<i32, BTreeMap<i32, SemUndo>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+impl SemSet {
+    fn new(id: i32, key: i32, nsems: usize, flags: u32) -> Arc<Self> {
+        return Arc::new(Self {
+            id,
+            inner: SpinLock::new(InnerSemSet {
+                perm: IpcPerm::new(key, flags),
+                sems: vec![Sem::default(); nsems],
+                otime: 0,
+                ctime: ipc_now(),
+                removed: false,
+            }),
+            wait_queue: WaitQueue::INIT,
+        });
+    }
+
+    fn stat(&self) -> PosixSemidDs {
+        let inner = self.inner.lock();
+        return PosixSemidDs {
+            sem_perm: inner.perm.to_posix(),
+            sem_otime: inner.otime,
+            sem_ctime: inner.ctime,
+            sem_nsems: inner.sems.len() as u64,
+            ..Default::default()
+        };
+    }
+
+    /// 清除所有进程对这个集合中的信号量的调整值。调用者需要持有集合的锁
+    ///
+    /// ## 参数
+    ///
+    /// - `semnum`：信号量的序号，为None时清除所有信号量的调整值
+    fn clear_undo(self: &Arc<Self>, semnum: Option<usize>) {
+        let weak = Arc::downgrade(self);
+        for undos in SEM_UNDO_LIST.lock().values_mut() {
+            if let Some(undo) = undos.get_mut(&self.id) {
+                if !undo.set.ptr_eq(&weak) {
+                    continue;
+                }
+                match semnum {
+                    Some(n) => undo.adj[n] = 0,
+                    None => undo.adj.fill(0),
+                }
+            }
+        }
+    }
+
+    /// 把SEM_UNDO操作记录到当前进程的调整值中。调用者需要持有集合的锁
+    fn record_undo(self: &Arc<Self>, nsems: usize, sops: &[SemBuf]) {
+        if sops.iter().all(|sop| sop.sem_flg & SEM_UNDO == 0) {
+            return;
+        }
+        let weak = Arc::downgrade(self);
+        let mut list = SEM_UNDO_LIST.lock();
+        let undo = list
+            .entry(ipc_current_pid())
+            .or_default()
+            .entry(self.id)
+            .or_insert_with(|| SemUndo {
+                set: weak.clone(),
+                adj: vec![0; nsems],
+            });
+        // ID被删除后又分配给了新的集合
+        if !undo.set.ptr_eq(&weak) {
+            undo.set = weak;
+            undo.adj = vec![0; nsems];
+        }
+        for sop in sops.iter().filter(|sop| sop.sem_flg & SEM_UNDO != 0) {
+            let adj = &mut undo.adj[sop.sem_num as usize];
+            *adj = (*adj - sop.sem_op as i32).clamp(-SEMAEM, SEMAEM);
+        }
+    }
+}
+
+impl InnerSemSet {
+    /// 尝试执行semop()中的所有操作
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(None)`：所有操作都已经完成
+    /// - `Ok(Some(i))`：第i个操作需要等待，没有执行任何操作
+    ///
+    /// ## 错误
+    ///
+    /// - `ERANGE`：信号量的值会超过SEMVMX
+    fn try_semop(&mut self, sops: &[SemBuf]) -> Result<Option<usize>, SystemError> {
+        let mut vals: Vec<i32> = self.sems.iter().map(|sem| sem.val).collect();
+        for (i, sop) in sops.iter().enumerate() {
+            let val = &mut vals[sop.sem_num as usize];
+            let op = sop.sem_op as i32;
+            if op == 0 {
+                if *val != 0 {
+                    return Ok(Some(i));
+                }
+            } else if *val + op < 0 {
+                return Ok(Some(i));
+            } else if *val + op > SEMVMX {
+                return Err(SystemError::ERANGE);
+            } else {
+                *val += op;
+            }
+        }
+
+        let pid = ipc_current_pid();
+        for sop in sops {
+            self.sems[sop.sem_num as usize].pid = pid;
+        }
+        for (sem, val) in self.sems.iter_mut().zip(vals) {
+            sem.val = val;
+        }
+        return Ok(None);
+    }
+}
+
+/// 进程退出时，撤销它的SEM_UNDO操作
+///
+/// ## 参数
+///
+/// - `pid`：退出的进程的pid
+pub fn exit_sem(pid: i32) {
+    let undos = match SEM_UNDO_LIST.lock().remove(&pid) {
+        Some(undos) => undos,
+        None => return,
+    };
+
+    for undo in undos.into_values() {
+        let set = match undo.set.upgrade() {
+            Some(set) => set,
+            None => continue,
+        };
+        let mut inner = set.inner.lock();
+        if inner.removed {
+            continue;
+        }
+        for (sem, adj) in inner.sems.iter_mut().zip(undo.adj) {
+            if adj != 0 {
+                sem.val = (sem.val + adj).clamp(0, SEMVMX);
+                sem.pid = pid;
+            }
+        }
+        inner.otime = ipc_now();
+        drop(inner);
+        set.wait_queue.wakeup_all(None);
+    }
+}
+
+/// 生成/proc/sysvipc/sem的内容
+pub fn sem_show() -> String {
+    let mut s = String::from(
+        "       key      semid perms      nsems   uid   gid  cuid  cgid      otime      ctime\n",
+    );
+    for (id, set) in current_ipc_ns().sem_ids.lock().objects() {
+        let ds = set.stat();
+        let p = &ds.sem_perm;
+        writeln!(
+            s,
+            "{:>10} {:>10}  {:>4o} {:>10} {:>5} {:>5} {:>5} {:>5} {:>10} {:>10}",
+            p.key,
+            id,
+            p.mode & 0o7777,
+            ds.sem_nsems,
+            p.uid,
+            p.gid,
+            p.cuid,
+            p.cgid,
+            ds.sem_otime,
+            ds.sem_ctime
+        )
+        .ok();
+    }
+    return s;
+}
+
+impl Syscall {
+    /// ## semget系统调用
+    ///
+    /// 根据key获取信号量集，必要时创建新的集合
+    ///
+    /// ## 参数
+    ///
+    /// - `key`：集合的key，为IPC_PRIVATE时总是创建新的集合
+    /// - `nsems`：集合中信号量的数量。获取已有的集合时可以为0
+    /// - `semflg`：IPC_CREAT、IPC_EXCL以及权限位
+    ///
+    /// ## 返回值
+    ///
+    /// 集合的ID
+    pub fn semget(key: i32, nsems: i32, semflg: u32) -> Result<usize, SystemError> {
+        if nsems < 0 || nsems as usize > SEMMSL {
+            return Err(SystemError::EINVAL);
+        }
+        let nsems = nsems as usize;
+        let flags = IpcFlags::from_bits_truncate(semflg);
+        return current_ipc_ns()
+            .sem_ids
+            .lock()
+            .get_or_create(
+                key,
+                flags,
+                |set| {
+                    if set.inner.lock().sems.len() < nsems {
+                        return Err(SystemError::EINVAL);
+                    }
+                    return Ok(());
+                },
+                |id| {
+                    if nsems == 0 {
+                        return Err(SystemError::EINVAL);
+                    }
+                    return Ok(SemSet::new(id, key, nsems, semflg));
+                },
+            )
+            .map(|id| id as usize);
+    }
+
+    /// ## semtimedop系统调用
+    ///
+    /// 原子地执行一组信号量操作，无法完成时等待
+    ///
+    /// ## 参数
+    ///
+    /// - `semid`：信号量集的ID
+    /// - `sops`：用户空间的`struct sembuf`数组
+    /// - `nsops`：操作的数量
+    /// - `timeout`：用户空间的`struct timespec`，等待的时长。为0时一直等待
+    ///
+    /// ## 错误
+    ///
+    /// - `EAGAIN`：操作需要等待，但是指定了IPC_NOWAIT，或者等待超时
+    /// - `EFBIG`：信号量的序号超出了集合的范围
+    /// - `EIDRM`：信号量集已经被删除
+    /// - `E2BIG`：操作的数量超过了SEMOPM
+    pub fn semtimedop(
+        semid: i32,
+        sops: usize,
+        nsops: usize,
+        timeout: usize,
+    ) -> Result<usize, SystemError> {
+        if nsops == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if nsops > SEMOPM {
+            return Err(SystemError::E2BIG);
+        }
+        let reader = UserBufferReader::new(
+            sops as *const SemBuf,
+            nsops * core::mem::size_of::<SemBuf>(),
+            true,
+        )?;
+        let mut ops = vec![
+            SemBuf {
+                sem_num: 0,
+                sem_op: 0,
+                sem_flg: 0
+            };
+            nsops
+        ];
+        reader.copy_from_user(&mut ops, 0)?;
+
+        let deadline = if timeout != 0 {
+            let ts = UserPtr::<TimeSpec>::new(timeout).read()?;
+            Some(next_n_us_timer_jiffies(timespec_to_ns(&ts)? / 1000))
+        } else {
+            None
+        };
+
+        let set = current_ipc_ns()
+            .sem_ids
+            .lock()
+            .get(semid)
+            .ok_or(SystemError::EINVAL)?;
+
+        loop {
+            let mut inner = set.inner.lock();
+            if inner.removed {
+                return Err(SystemError::EIDRM);
+            }
+            let nsems = inner.sems.len();
+            if ops.iter().any(|sop| sop.sem_num as usize >= nsems) {
+                return Err(SystemError::EFBIG);
+            }
+
+            let blocked = match inner.try_semop(&ops)? {
+                Some(i) => ops[i],
+                None => {
+                    inner.otime = ipc_now();
+                    set.record_undo(nsems, &ops);
+                    drop(inner);
+                    set.wait_queue.wakeup_all(None);
+                    return Ok(0);
+                }
+            };
+
+            if blocked.sem_flg & IpcFlags::IPC_NOWAIT.bits() as i16 != 0 {
+                return Err(SystemError::EAGAIN);
+            }
+            if deadline.map_or(false, |d| clock() >= d) {
+                return Err(SystemError::EAGAIN);
+            }
+
+            let sem = &mut inner.sems[blocked.sem_num as usize];
+            if blocked.sem_op == 0 {
+                sem.zcnt += 1;
+            } else {
+                sem.ncnt += 1;
+            }
+            let r = ipc_wait(&set.wait_queue, inner, deadline);
+
+            let mut inner = set.inner.lock();
+            let sem = &mut inner.sems[blocked.sem_num as usize];
+            if blocked.sem_op == 0 {
+                sem.zcnt -= 1;
+            } else {
+                sem.ncnt -= 1;
+            }
+            drop(inner);
+            r?;
+        }
+    }
+
+    /// ## semctl系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `semid`：信号量集的ID。对于IPC_INFO、SEM_INFO，忽略该参数
+    /// - `semnum`：信号量的序号，仅用于操作单个信号量的命令
+    /// - `cmd`：命令
+    /// - `arg`：`union semun`，根据命令的不同，为整数值或者用户空间的指针
+    ///
+    /// ## 返回值
+    ///
+    /// - IPC_INFO、SEM_INFO：ID表中最大的ID
+    /// - SEM_STAT：集合的ID
+    /// - GETVAL、GETPID、GETNCNT、GETZCNT：获取到的值
+    /// - 其他命令：0
+    pub fn semctl(semid: i32, semnum: i32, cmd: i32, arg: usize) -> Result<usize, SystemError> {
+        let ns = current_ipc_ns();
+        let cmd = cmd & !IPC_64;
+        if cmd == IPC_INFO || cmd == SEM_INFO {
+            let ids = ns.sem_ids.lock();
+            let mut info = PosixSemInfo {
+                semmap: SEMMNS as i32,
+                semmni: SEMMNI as i32,
+                semmns: SEMMNS as i32,
+                semmnu: SEMMNS as i32,
+                semmsl: SEMMSL as i32,
+                semopm: SEMOPM as i32,
+                semume: SEMOPM as i32,
+                semusz: 0,
+                semvmx: SEMVMX,
+                semaem: SEMAEM,
+            };
+            if cmd == SEM_INFO {
+                info.semusz = ids.count() as i32;
+                info.semaem = ids
+                    .objects()
+                    .iter()
+                    .map(|(_, set)| set.inner.lock().sems.len() as i32)
+                    .sum();
+            }
+            let max_id = ids.max_id();
+            drop(ids);
+            UserPtr::<PosixSemInfo>::new(arg).write(&info)?;
+            return Ok(max_id as usize);
+        }
+
+        let mut ids = ns.sem_ids.lock();
+        let set = ids.get(semid).ok_or(SystemError::EINVAL)?;
+        if cmd == IPC_RMID {
+            ids.remove(semid);
+            set.inner.lock().removed = true;
+            drop(ids);
+            set.wait_queue.wakeup_all(None);
+            return Ok(0);
+        }
+        drop(ids);
+
+        match cmd {
+            IPC_STAT | SEM_STAT => {
+                UserPtr::<PosixSemidDs>::new(arg).write(&set.stat())?;
+                return Ok(if cmd == SEM_STAT { semid as usize } else { 0 });
+            }
+            IPC_SET => {
+                let ds = UserPtr::<PosixSemidDs>::new(arg).read()?;
+                let mut inner = set.inner.lock();
+                inner.perm.set(&ds.sem_perm);
+                inner.ctime = ipc_now();
+                return Ok(0);
+            }
+            GETALL => {
+                let vals: Vec<u16> = set
+                    .inner
+                    .lock()
+                    .sems
+                    .iter()
+                    .map(|sem| sem.val as u16)
+                    .collect();
+                let mut writer = UserBufferWriter::new(
+                    arg as *mut u16,
+                    vals.len() * core::mem::size_of::<u16>(),
+                    true,
+                )?;
+                writer.copy_to_user(&vals, 0)?;
+                return Ok(0);
+            }
+            SETALL => {
+                let nsems = set.inner.lock().sems.len();
+                let reader = UserBufferReader::new(
+                    arg as *const u16,
+                    nsems * core::mem::size_of::<u16>(),
+                    true,
+                )?;
+                let mut vals = vec![0u16; nsems];
+                reader.copy_from_user(&mut vals, 0)?;
+                if vals.iter().any(|val| *val as i32 > SEMVMX) {
+                    return Err(SystemError::ERANGE);
+                }
+
+                let mut inner = set.inner.lock();
+                let pid = ipc_current_pid();
+                for (sem, val) in inner.sems.iter_mut().zip(vals) {
+                    sem.val = val as i32;
+                    sem.pid = pid;
+                }
+                inner.ctime = ipc_now();
+                set.clear_undo(None);
+                drop(inner);
+                set.wait_queue.wakeup_all(None);
+                return Ok(0);
+            }
+            GETVAL | GETPID | GETNCNT | GETZCNT | SETVAL => {}
+            _ => {
+                return Err(SystemError::EINVAL);
+            }
+        }
+
+        // 以下的命令操作单个信号量
+        let mut inner = set.inner.lock();
+        if semnum < 0 || semnum as usize >= inner.sems.len() {
+            return Err(SystemError::EINVAL);
+        }
+        let semnum = semnum as usize;
+        let sem = &mut inner.sems[semnum];
+        match cmd {
+            GETVAL => return Ok(sem.val as usize),
+            GETPID => return Ok(sem.pid as usize),
+            GETNCNT => return Ok(sem.ncnt),
+            GETZCNT => return Ok(sem.zcnt),
+            _ => {}
+        }
+
+        // SETVAL
+        let val = arg as i32;
+        if !(0..=SEMVMX).contains(&val) {
+            return Err(SystemError::ERANGE);
+        }
+        sem.val = val;
+        sem.pid = ipc_current_pid();
+        inner.ctime = ipc_now();
+        set.clear_undo(Some(semnum));
+        drop(inner);
+        set.wait_queue.wakeup_all(None);
+        return Ok(0);
+    }
+}
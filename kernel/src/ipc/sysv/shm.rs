@@ -0,0 +1,480 @@
+//! System V共享内存
+//!
+//! 共享内存段的数据保存在[`ShmemPages`]中，shmat()把数据页映射到进程的地址空间。
+//! 映射由VMA持有的[`ShmAttachment`]记录，解除映射时减少段的连接数。
+//! 段被IPC_RMID删除之后，直到最后一个连接断开才会被真正释放。
+//!
+//! 目前fork()出来的子进程不会继承父进程连接的共享内存段。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/ipc/shm.c
+
+use core::{any::Any, fmt::Write};
+
+use alloc::{string::String, sync::Arc};
+
+use crate::{
+    arch::MMArch,
+    libs::{align::page_align_up, spinlock::SpinLock},
+    mm::{
+        allocator::page_frame::{PageFrameCount, VirtPageFrame},
+        shmem::ShmemPages,
+        syscall::{MapFlags, ProtFlags},
+        ucontext::AddressSpace,
+        MemoryManagementArch, VirtAddr,
+    },
+    syscall::{user_access::UserPtr, Syscall, SystemError},
+};
+
+use super::{
+    current_ipc_ns, ipc_current_pid, ipc_now, IpcFlags, IpcPerm, PosixIpcPerm, IPC_64, IPC_INFO,
+    IPC_RMID, IPC_SET, IPC_STAT,
+};
+
+/// 共享内存段的最小字节数
+pub const SHMMIN: usize = 1;
+/// 共享内存段的最大字节数
+pub const SHMMAX: usize = usize::MAX - (1 << 24);
+/// 共享内存段数量的上限
+pub const SHMMNI: usize = 4096;
+/// 共享内存的总页数的上限
+pub const SHMALL: usize = usize::MAX - (1 << 24);
+/// shmat()的地址需要对齐到的边界
+pub const SHMLBA: usize = MMArch::PAGE_SIZE;
+
+bitflags! {
+    /// shmat()的flags
+    pub struct ShmAtFlags: u32 {
+        /// 只读连接
+        const SHM_RDONLY = 0o10000;
+        /// 把地址向下对齐到SHMLBA
+        const SHM_RND = 0o20000;
+        /// 替换掉地址范围内已有的映射
+        const SHM_REMAP = 0o40000;
+        /// 可执行
+        const SHM_EXEC = 0o100000;
+    }
+}
+
+/// 段的mode中的标志：段已经被删除，最后一个连接断开时释放
+const SHM_DEST: u32 = 0o1000;
+/// 段的mode中的标志：段被锁定在内存中
+const SHM_LOCKED: u32 = 0o2000;
+
+/// shmctl()的命令：锁定段
+pub const SHM_LOCK: i32 = 11;
+/// shmctl()的命令：解锁段
+pub const SHM_UNLOCK: i32 = 12;
+/// shmctl()的命令：与IPC_STAT相同，但是参数为ID表中的序号
+pub const SHM_STAT: i32 = 13;
+/// shmctl()的命令：获取共享内存的使用情况
+pub const SHM_INFO: i32 = 14;
+
+/// 与Linux的`struct shmid64_ds`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixShmidDs {
+    pub shm_perm: PosixIpcPerm,
+    pub shm_segsz: usize,
+    pub shm_atime: i64,
+    pub shm_dtime: i64,
+    pub shm_ctime: i64,
+    pub shm_cpid: i32,
+    pub shm_lpid: i32,
+    pub shm_nattch: u64,
+    _unused4: u64,
+    _unused5: u64,
+}
+
+/// 与Linux的`struct shminfo64`相同，用于IPC_INFO
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixShmInfo {
+    pub shmmax: u64,
+    pub shmmin: u64,
+    pub shmmni: u64,
+    pub shmseg: u64,
+    pub shmall: u64,
+    _unused: [u64; 4],
+}
+
+/// 与Linux的`struct shm_info`相同，用于SHM_INFO
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixShmUsage {
+    pub used_ids: i32,
+    _pad: i32,
+    pub shm_tot: u64,
+    pub shm_rss: u64,
+    pub shm_swp: u64,
+    pub swap_attempts: u64,
+    pub swap_successes: u64,
+}
+
+/// 一个共享内存段
+#[derive(Debug)]
+pub struct ShmSegment {
+    id: i32,
+    inner: SpinLock<InnerShmSegment>,
+}
+
+#[derive(Debug)]
+struct InnerShmSegment {
+    perm: IpcPerm,
+    pages: ShmemPages,
+    /// 最后一次连接的时间
+    atime: i64,
+    /// 最后一次断开连接的时间
+    dtime: i64,
+    /// 最后一次修改的时间
+    ctime: i64,
+    /// 创建者的pid
+    cpid: i32,
+    /// 最后一次连接或者断开连接的进程的pid
+    lpid: i32,
+    /// 连接的数量
+    nattch: usize,
+}
+
+impl ShmSegment {
+    fn new(id: i32, key: i32, size: usize, flags: u32) -> Arc<Self> {
+        let mut pages = ShmemPages::new();
+        pages.resize(size);
+        return Arc::new(Self {
+            id,
+            inner: SpinLock::new(InnerShmSegment {
+                perm: IpcPerm::new(key, flags),
+                pages,
+                atime: 0,
+                dtime: 0,
+                ctime: ipc_now(),
+                cpid: ipc_current_pid(),
+                lpid: 0,
+                nattch: 0,
+            }),
+        });
+    }
+
+    fn stat(&self) -> PosixShmidDs {
+        let inner = self.inner.lock();
+        return PosixShmidDs {
+            shm_perm: inner.perm.to_posix(),
+            shm_segsz: inner.pages.size(),
+            shm_atime: inner.atime,
+            shm_dtime: inner.dtime,
+            shm_ctime: inner.ctime,
+            shm_cpid: inner.cpid,
+            shm_lpid: inner.lpid,
+            shm_nattch: inner.nattch as u64,
+            ..Default::default()
+        };
+    }
+}
+
+/// 一次shmat()建立的连接，由映射它的VMA持有。被释放时断开连接
+#[derive(Debug)]
+struct ShmAttachment {
+    segment: Arc<ShmSegment>,
+    /// 映射期间保持数据页存活
+    _mapping: Arc<dyn Any + Send + Sync>,
+}
+
+impl Drop for ShmAttachment {
+    fn drop(&mut self) {
+        let mut inner = self.segment.inner.lock();
+        inner.nattch -= 1;
+        inner.dtime = ipc_now();
+        inner.lpid = ipc_current_pid();
+        let destroy = inner.nattch == 0 && inner.perm.mode & SHM_DEST != 0;
+        drop(inner);
+
+        if destroy {
+            current_ipc_ns().shm_ids.lock().remove(self.segment.id);
+        }
+    }
+}
+
+/// 生成/proc/sysvipc/shm的内容
+pub fn shm_show() -> String {
+    let mut s = String::from(
+        "       key      shmid perms                  size  cpid  lpid nattch   uid   gid  cuid  cgid      atime      dtime      ctime                   rss                  swap\n",
+    );
+    for (id, seg) in current_ipc_ns().shm_ids.lock().objects() {
+        let ds = seg.stat();
+        let p = &ds.shm_perm;
+        writeln!(
+            s,
+            "{:>10} {:>10}  {:>4o} {:>21} {:>5} {:>5}  {:>5} {:>5} {:>5} {:>5} {:>5} {:>10} {:>10} {:>10} {:>21} {:>21}",
+            p.key,
+            id,
+            p.mode & 0o7777,
+            ds.shm_segsz,
+            ds.shm_cpid,
+            ds.shm_lpid,
+            ds.shm_nattch,
+            p.uid,
+            p.gid,
+            p.cuid,
+            p.cgid,
+            ds.shm_atime,
+            ds.shm_dtime,
+            ds.shm_ctime,
+            page_align_up(ds.shm_segsz),
+            0
+        )
+        .ok();
+    }
+    return s;
+}
+
+impl Syscall {
+    /// ## shmget系统调用
+    ///
+    /// 根据key获取共享内存段，必要时创建新的段
+    ///
+    /// ## 参数
+    ///
+    /// - `key`：段的key，为IPC_PRIVATE时总是创建新的段
+    /// - `size`：段的字节数
+    /// - `shmflg`：IPC_CREAT、IPC_EXCL以及权限位
+    ///
+    /// ## 返回值
+    ///
+    /// 段的ID
+    pub fn shmget(key: i32, size: usize, shmflg: u32) -> Result<usize, SystemError> {
+        let flags = IpcFlags::from_bits_truncate(shmflg);
+        return current_ipc_ns()
+            .shm_ids
+            .lock()
+            .get_or_create(
+                key,
+                flags,
+                |seg| {
+                    // 已有的段比要求的小
+                    if seg.inner.lock().pages.size() < size {
+                        return Err(SystemError::EINVAL);
+                    }
+                    return Ok(());
+                },
+                |id| {
+                    if size < SHMMIN || size > SHMMAX {
+                        return Err(SystemError::EINVAL);
+                    }
+                    return Ok(ShmSegment::new(id, key, size, shmflg));
+                },
+            )
+            .map(|id| id as usize);
+    }
+
+    /// ## shmat系统调用
+    ///
+    /// 把共享内存段连接到当前进程的地址空间
+    ///
+    /// ## 参数
+    ///
+    /// - `shmid`：段的ID
+    /// - `shmaddr`：连接的地址，为0时由内核选择
+    /// - `shmflg`：SHM_RDONLY、SHM_RND等
+    ///
+    /// ## 返回值
+    ///
+    /// 连接的地址
+    pub fn shmat(shmid: i32, shmaddr: usize, shmflg: u32) -> Result<usize, SystemError> {
+        let flags = ShmAtFlags::from_bits_truncate(shmflg);
+        let mut addr = shmaddr;
+        if addr != 0 {
+            if flags.contains(ShmAtFlags::SHM_RND) {
+                addr &= !(SHMLBA - 1);
+            } else if addr & (SHMLBA - 1) != 0 {
+                return Err(SystemError::EINVAL);
+            }
+        }
+
+        let mut prot_flags = ProtFlags::PROT_READ;
+        if !flags.contains(ShmAtFlags::SHM_RDONLY) {
+            prot_flags |= ProtFlags::PROT_WRITE;
+        }
+        if flags.contains(ShmAtFlags::SHM_EXEC) {
+            prot_flags |= ProtFlags::PROT_EXEC;
+        }
+
+        let segment = current_ipc_ns()
+            .shm_ids
+            .lock()
+            .get(shmid)
+            .ok_or(SystemError::EINVAL)?;
+
+        let (frames, attachment) = {
+            let mut inner = segment.inner.lock();
+            let count = PageFrameCount::from_bytes(page_align_up(inner.pages.size())).unwrap();
+            let (frames, mapping) =
+                inner
+                    .pages
+                    .mmap_frames(0, count, prot_flags.contains(ProtFlags::PROT_WRITE))?;
+            inner.nattch += 1;
+            inner.atime = ipc_now();
+            inner.lpid = ipc_current_pid();
+            let attachment: Arc<dyn Any + Send + Sync> = Arc::new(ShmAttachment {
+                segment: segment.clone(),
+                _mapping: mapping,
+            });
+            (frames, attachment)
+        };
+
+        let address_space = AddressSpace::current()?;
+        let mut address_space = address_space.write();
+        let mut map_flags = MapFlags::MAP_SHARED;
+        if addr != 0 {
+            if flags.contains(ShmAtFlags::SHM_REMAP) {
+                address_space.munmap(
+                    VirtPageFrame::new(VirtAddr::new(addr)),
+                    PageFrameCount::new(frames.len()),
+                )?;
+            }
+            map_flags |= MapFlags::MAP_FIXED_NOREPLACE;
+        }
+        let start_page = address_space
+            .map_frames(
+                VirtAddr::new(addr),
+                frames,
+                prot_flags,
+                map_flags,
+                attachment,
+            )
+            .map_err(|e| match e {
+                SystemError::EEXIST => SystemError::EINVAL,
+                e => e,
+            })?;
+        return Ok(start_page.virt_address().data());
+    }
+
+    /// ## shmdt系统调用
+    ///
+    /// 断开shmat()在`shmaddr`处建立的连接
+    ///
+    /// ## 参数
+    ///
+    /// - `shmaddr`：shmat()返回的地址
+    pub fn shmdt(shmaddr: VirtAddr) -> Result<usize, SystemError> {
+        if !shmaddr.check_aligned(MMArch::PAGE_SIZE) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let address_space = AddressSpace::current()?;
+        let mut address_space = address_space.write();
+        let vma = address_space
+            .mappings
+            .contains(shmaddr)
+            .ok_or(SystemError::EINVAL)?;
+        let region = {
+            let guard = vma.lock();
+            let is_shm = guard
+                .backing()
+                .map_or(false, |b| b.downcast_ref::<ShmAttachment>().is_some());
+            if !is_shm || guard.region().start() != shmaddr {
+                return Err(SystemError::EINVAL);
+            }
+            *guard.region()
+        };
+
+        address_space.munmap(
+            VirtPageFrame::new(region.start()),
+            PageFrameCount::new(region.size() / MMArch::PAGE_SIZE),
+        )?;
+        return Ok(0);
+    }
+
+    /// ## shmctl系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `shmid`：段的ID。对于IPC_INFO、SHM_INFO，忽略该参数
+    /// - `cmd`：命令
+    /// - `buf`：用户空间的缓冲区，结构体的类型由命令决定
+    ///
+    /// ## 返回值
+    ///
+    /// - IPC_INFO、SHM_INFO：ID表中最大的ID
+    /// - SHM_STAT：段的ID
+    /// - 其他命令：0
+    pub fn shmctl(shmid: i32, cmd: i32, buf: usize) -> Result<usize, SystemError> {
+        let ns = current_ipc_ns();
+        match cmd & !IPC_64 {
+            IPC_INFO => {
+                let info = PosixShmInfo {
+                    shmmax: SHMMAX as u64,
+                    shmmin: SHMMIN as u64,
+                    shmmni: SHMMNI as u64,
+                    shmseg: SHMMNI as u64,
+                    shmall: SHMALL as u64,
+                    ..Default::default()
+                };
+                UserPtr::<PosixShmInfo>::new(buf).write(&info)?;
+                return Ok(ns.shm_ids.lock().max_id() as usize);
+            }
+            SHM_INFO => {
+                let ids = ns.shm_ids.lock();
+                let mut usage = PosixShmUsage {
+                    used_ids: ids.count() as i32,
+                    ..Default::default()
+                };
+                for (_, seg) in ids.objects() {
+                    let pages = page_align_up(seg.inner.lock().pages.size()) / MMArch::PAGE_SIZE;
+                    usage.shm_tot += pages as u64;
+                    usage.shm_rss += pages as u64;
+                }
+                let max_id = ids.max_id();
+                drop(ids);
+                UserPtr::<PosixShmUsage>::new(buf).write(&usage)?;
+                return Ok(max_id as usize);
+            }
+            _ => {}
+        }
+
+        let mut ids = ns.shm_ids.lock();
+        let segment = ids.get(shmid).ok_or(SystemError::EINVAL)?;
+        match cmd & !IPC_64 {
+            IPC_STAT | SHM_STAT => {
+                drop(ids);
+                UserPtr::<PosixShmidDs>::new(buf).write(&segment.stat())?;
+                return Ok(if cmd & !IPC_64 == SHM_STAT {
+                    shmid as usize
+                } else {
+                    0
+                });
+            }
+            IPC_SET => {
+                drop(ids);
+                let ds = UserPtr::<PosixShmidDs>::new(buf).read()?;
+                let mut inner = segment.inner.lock();
+                inner.perm.set(&ds.shm_perm);
+                inner.ctime = ipc_now();
+                return Ok(0);
+            }
+            IPC_RMID => {
+                let mut inner = segment.inner.lock();
+                if inner.nattch == 0 {
+                    drop(inner);
+                    ids.remove(shmid);
+                } else {
+                    // 还有进程连接着这个段，等最后一个连接断开时再释放
+                    inner.perm.mode |= SHM_DEST;
+                    ids.hide_key(shmid);
+                }
+                return Ok(0);
+            }
+            SHM_LOCK | SHM_UNLOCK => {
+                drop(ids);
+                let mut inner = segment.inner.lock();
+                if cmd & !IPC_64 == SHM_LOCK {
+                    inner.perm.mode |= SHM_LOCKED;
+                } else {
+                    inner.perm.mode &= !SHM_LOCKED;
+                }
+                return Ok(0);
+            }
+            _ => {
+                return Err(SystemError::EINVAL);
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! 内核测试框架
+//!
+//! 测试函数通过[`kernel_test!`](crate::kernel_test)登记，每次登记都会在`__ktest`段中放入一个[`KernelTest`]，
+//! 链接器把它们收集到一起。启动参数中有`ktest=`时，内核初始化完成之后会在`ktest`内核线程中依次运行选中的测试，
+//! 并把结果按下面的格式直接输出到串口，每行一条，便于在QEMU中自动化运行：
+//!
+//! ```text
+//! KTEST START <选中的测试数量>
+//! KTEST PASS <测试名> <耗时(us)>
+//! KTEST FAIL <测试名> <错误>
+//! KTEST SKIP <测试名> <没有满足的依赖>
+//! KTEST DONE pass=<数量> fail=<数量> skip=<数量>
+//! ```
+//!
+//! - `ktest=all`：运行所有测试
+//! - `ktest=ahci_read,cmdline_parse_param`：只运行指定的测试
+//! - `ktest.poweroff`：测试结束之后关机，QEMU会随之退出
+//!
+//! 测试可以声明依赖的子系统(例如`ahci`)，子系统初始化成功之后通过[`ktest_provide`]声明自己可用，
+//! 依赖没有满足的测试会被跳过。
+//!
+//! ```ignore
+//! fn ahci_read() -> Result<(), SystemError> {
+//!     let disk = get_disks_by_name("ahci_disk_0".to_string())?;
+//!     ktest_assert!(disk.read_at(0, 1, &mut buf)? == LBA_SIZE);
+//!     return Ok(());
+//! }
+//! kernel_test!(ahci_read, requires = ["ahci"]);
+//! ```
+
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::fmt;
+
+use crate::{
+    driver::tty::serial::serial8250::send_to_default_serial8250_port,
+    kernel_param, kwarn,
+    libs::spinlock::SpinLock,
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        reboot::kernel_power_off,
+    },
+    syscall::SystemError,
+    time::hrtimer::ktime_get,
+};
+
+/// 登记在`__ktest`段中的测试
+pub struct KernelTest {
+    /// 测试名，用于在启动参数中选择测试
+    pub name: &'static str,
+    /// 依赖的子系统
+    pub requires: &'static [&'static str],
+    /// 测试函数，返回错误表示测试失败
+    pub func: fn() -> Result<(), SystemError>,
+}
+
+/// 登记一个内核测试
+///
+/// ```ignore
+/// kernel_test!(cmdline_parse_param);
+/// kernel_test!(ahci_read, requires = ["ahci"]);
+/// ```
+#[macro_export]
+macro_rules! kernel_test {
+    ($func:ident $(, requires = [$($dep:literal),* $(,)?])?) => {
+        const _: () = {
+            #[link_section = "__ktest"]
+            #[used]
+            static __KTEST_ENTRY: $crate::ktest::KernelTest = $crate::ktest::KernelTest {
+                name: stringify!($func),
+                requires: &[$($($dep),*)?],
+                func: $func,
+            };
+        };
+    };
+}
+
+/// 条件不成立时，输出条件所在的位置，并让测试以EINVAL失败
+#[macro_export]
+macro_rules! ktest_assert {
+    ($cond:expr) => {
+        if !($cond) {
+            $crate::kerror!("ktest: assertion failed: {}", stringify!($cond));
+            return Err($crate::syscall::SystemError::EINVAL);
+        }
+    };
+}
+
+extern "C" {
+    static __start___ktest: KernelTest;
+    static __stop___ktest: KernelTest;
+}
+
+/// 获取链接器收集的所有测试
+fn kernel_tests() -> &'static [KernelTest] {
+    unsafe {
+        let start = &__start___ktest as *const KernelTest;
+        let end = &__stop___ktest as *const KernelTest;
+        let len = (end as usize - start as usize) / core::mem::size_of::<KernelTest>();
+        return core::slice::from_raw_parts(start, len);
+    }
+}
+
+kernel_param!(KTEST_PARAM: Vec<String> = "ktest");
+kernel_param!(KTEST_POWEROFF_PARAM: bool = "ktest.poweroff");
+
+/// 已经初始化成功的子系统
+static KTEST_PROVIDED: SpinLock<Vec<&'static str>> = SpinLock::new(Vec::new());
+
+/// 声明一个子系统已经初始化成功，依赖它的测试可以运行
+///
+/// ## 参数
+///
+/// - `name`: 子系统的名字，与测试声明的依赖相对应
+pub fn ktest_provide(name: &'static str) {
+    let mut provided = KTEST_PROVIDED.lock_irqsave();
+    if !provided.contains(&name) {
+        provided.push(name);
+    }
+}
+
+/// 输出一行测试结果。直接写入串口，不受日志级别的影响
+fn ktest_report(args: fmt::Arguments) {
+    let line = format!("KTEST {}\n", args);
+    send_to_default_serial8250_port(line.as_bytes());
+}
+
+/// 如果启动参数选择了测试，则创建`ktest`内核线程运行它们
+///
+/// 应当在内核初始化完成之后调用
+pub fn ktest_init() -> Result<(), SystemError> {
+    let selected = match KTEST_PARAM.get() {
+        Some(selected) => selected,
+        None => return Ok(()),
+    };
+    KernelThreadMechanism::create_and_run(
+        KernelThreadClosure::EmptyClosure((Box::new(move || ktest_run(&selected)), ())),
+        String::from("ktest"),
+    )
+    .ok_or(SystemError::ENOMEM)?;
+    return Ok(());
+}
+
+/// 依次运行选中的测试，并输出结果
+///
+/// ## 参数
+///
+/// - `selected`: 选中的测试的名字，包含`all`时运行所有测试
+fn ktest_run(selected: &[String]) -> i32 {
+    let all = selected.iter().any(|s| s == "all");
+    for name in selected.iter().filter(|s| *s != "all") {
+        if !kernel_tests().iter().any(|t| t.name == name) {
+            kwarn!("ktest: unknown test {}", name);
+        }
+    }
+
+    let tests: Vec<&KernelTest> = kernel_tests()
+        .iter()
+        .filter(|t| all || selected.iter().any(|s| s == t.name))
+        .collect();
+    ktest_report(format_args!("START {}", tests.len()));
+
+    let (mut pass, mut fail, mut skip) = (0, 0, 0);
+    for test in tests {
+        let missing = {
+            let provided = KTEST_PROVIDED.lock_irqsave();
+            test.requires
+                .iter()
+                .find(|dep| !provided.contains(*dep))
+                .copied()
+        };
+        if let Some(dep) = missing {
+            ktest_report(format_args!("SKIP {} {}", test.name, dep));
+            skip += 1;
+            continue;
+        }
+
+        let start = ktime_get();
+        match (test.func)() {
+            Ok(_) => {
+                let us = (ktime_get() - start) / 1000;
+                ktest_report(format_args!("PASS {} {}", test.name, us));
+                pass += 1;
+            }
+            Err(e) => {
+                ktest_report(format_args!("FAIL {} {:?}", test.name, e));
+                fail += 1;
+            }
+        }
+    }
+    ktest_report(format_args!(
+        "DONE pass={} fail={} skip={}",
+        pass, fail, skip
+    ));
+
+    if KTEST_POWEROFF_PARAM.get() == Some(true) {
+        kernel_power_off();
+    }
+    return 0;
+}
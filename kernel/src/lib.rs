@@ -10,6 +10,7 @@
 #![feature(c_void_variant)]
 #![feature(drain_filter)]
 #![feature(is_some_and)]
+#![feature(linkage)]
 #![feature(naked_functions)]
 #![feature(panic_info_message)]
 #![feature(ptr_internals)]
@@ -37,13 +38,20 @@ mod arch;
 mod libs;
 #[macro_use]
 mod include;
+mod audit;
+mod crypto;
+mod debug;
 mod driver; // 如果driver依赖了libs，应该在libs后面导出
 mod exception;
 mod filesystem;
 mod init;
+mod io_uring;
 mod ipc;
+mod ktest;
 mod mm;
+mod module;
 mod net;
+mod perf;
 mod process;
 mod sched;
 mod smp;
@@ -71,8 +79,6 @@ extern crate x86;
 
 use crate::mm::allocator::kernel_allocator::KernelAllocator;
 
-use crate::process::ProcessManager;
-
 // 声明全局的分配器
 #[cfg_attr(not(test), global_allocator)]
 pub static KERNEL_ALLOCATOR: KernelAllocator = KernelAllocator;
@@ -82,31 +88,5 @@ pub static KERNEL_ALLOCATOR: KernelAllocator = KernelAllocator;
 #[panic_handler]
 #[no_mangle]
 pub fn panic(info: &PanicInfo) -> ! {
-    kerror!("Kernel Panic Occurred.");
-
-    match info.location() {
-        Some(loc) => {
-            println!(
-                "Location:\n\tFile: {}\n\tLine: {}, Column: {}",
-                loc.file(),
-                loc.line(),
-                loc.column()
-            );
-        }
-        None => {
-            println!("No location info");
-        }
-    }
-
-    match info.message() {
-        Some(msg) => {
-            println!("Message:\n\t{}", msg);
-        }
-        None => {
-            println!("No panic message.");
-        }
-    }
-
-    println!("Current PCB:\n\t{:?}", *(ProcessManager::current_pcb()));
-    ProcessManager::exit(usize::MAX);
+    debug::panic::kernel_panic(info);
 }
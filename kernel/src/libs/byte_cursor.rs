@@ -0,0 +1,271 @@
+#![allow(dead_code)]
+
+use core::mem::size_of;
+
+use alloc::vec::Vec;
+
+use crate::{driver::base::block::SeekFrom, syscall::SystemError};
+
+/// @brief 本模块用于为字节缓冲区提供游标的功能，以简化磁盘上的结构、网络协议等二进制数据的解析与构造。
+///
+/// 游标可以建立在任何字节缓冲区之上：
+/// - [`VecCursor`]: 游标拥有数据，例如从磁盘读取的扇区
+/// - [`ByteReader`]: 只读地借用一个切片，不需要复制数据
+/// - [`ByteWriter`]: 可写地借用一个切片，直接修改其中的数据
+///
+/// 整数默认以小端字节序读写，以`_be`结尾的方法使用大端字节序。
+/// 所有的读写与移动都会检查边界，越界时返回错误，并且游标的位置不变。
+#[derive(Debug)]
+pub struct ByteCursor<T> {
+    /// 游标管理的数据
+    data: T,
+    /// 游标的位置
+    pos: usize,
+}
+
+/// 拥有数据的游标
+pub type VecCursor = ByteCursor<Vec<u8>>;
+/// 只读地借用切片的游标
+pub type ByteReader<'a> = ByteCursor<&'a [u8]>;
+/// 可写地借用切片的游标
+pub type ByteWriter<'a> = ByteCursor<&'a mut [u8]>;
+
+/// 生成读取整数的方法
+macro_rules! impl_read_int {
+    ($($ty:ty: $read_le:ident, $read_be:ident;)*) => {
+        $(
+            #[doc = concat!("@brief 读取一个", stringify!($ty), "的数据（小端对齐）")]
+            pub fn $read_le(&mut self) -> Result<$ty, SystemError> {
+                let mut bytes = [0u8; size_of::<$ty>()];
+                self.read_exact(&mut bytes)?;
+                return Ok(<$ty>::from_le_bytes(bytes));
+            }
+
+            #[doc = concat!("@brief 读取一个", stringify!($ty), "的数据（大端对齐）")]
+            pub fn $read_be(&mut self) -> Result<$ty, SystemError> {
+                let mut bytes = [0u8; size_of::<$ty>()];
+                self.read_exact(&mut bytes)?;
+                return Ok(<$ty>::from_be_bytes(bytes));
+            }
+        )*
+    };
+}
+
+/// 生成写入整数的方法
+macro_rules! impl_write_int {
+    ($($ty:ty: $write_le:ident, $write_be:ident;)*) => {
+        $(
+            #[doc = concat!("@brief 写入一个", stringify!($ty), "的数据（小端对齐）")]
+            pub fn $write_le(&mut self, value: $ty) -> Result<$ty, SystemError> {
+                self.write_exact(&value.to_le_bytes())?;
+                return Ok(value);
+            }
+
+            #[doc = concat!("@brief 写入一个", stringify!($ty), "的数据（大端对齐）")]
+            pub fn $write_be(&mut self, value: $ty) -> Result<$ty, SystemError> {
+                self.write_exact(&value.to_be_bytes())?;
+                return Ok(value);
+            }
+        )*
+    };
+}
+
+impl<T: AsRef<[u8]>> ByteCursor<T> {
+    /// @brief 新建一个游标
+    pub fn new(data: T) -> Self {
+        return Self { data, pos: 0 };
+    }
+
+    /// @brief 获取游标管理的数据的不可变引用
+    pub fn get_ref(&self) -> &T {
+        return &self.data;
+    }
+
+    /// @brief 获取游标管理的数据的可变引用
+    pub fn get_mut(&mut self) -> &mut T {
+        return &mut self.data;
+    }
+
+    /// @brief 取出游标管理的数据
+    pub fn into_inner(self) -> T {
+        return self.data;
+    }
+
+    /// @brief 检查从当前位置开始是否还有len字节的数据
+    ///
+    /// @return Ok(usize) 这些数据结束的位置
+    /// @return Err(SystemError::E2BIG) 没有这么多数据
+    fn check_remaining(&self, len: usize) -> Result<usize, SystemError> {
+        match self.pos.checked_add(len) {
+            Some(end) if end <= self.len() => return Ok(end),
+            _ => return Err(SystemError::E2BIG),
+        }
+    }
+
+    /// @brief 读取一个u8的数据
+    pub fn read_u8(&mut self) -> Result<u8, SystemError> {
+        let mut bytes = [0u8; 1];
+        self.read_exact(&mut bytes)?;
+        return Ok(bytes[0]);
+    }
+
+    impl_read_int! {
+        u16: read_u16, read_u16_be;
+        u32: read_u32, read_u32_be;
+        u64: read_u64, read_u64_be;
+        i16: read_i16, read_i16_be;
+        i32: read_i32, read_i32_be;
+        i64: read_i64, read_i64_be;
+    }
+
+    /// @brief 精确读取与buf同样大小的数据。
+    ///
+    /// @param buf 要读取到的目标缓冲区
+    ///
+    /// @return Ok(()) 成功读取
+    /// @return Err(SystemError::E2BIG) 没有这么多数据，读取失败
+    pub fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), SystemError> {
+        let end = self.check_remaining(buf.len())?;
+        buf.copy_from_slice(&self.data.as_ref()[self.pos..end]);
+        self.pos = end;
+        return Ok(());
+    }
+
+    /// @brief 读取len字节的数据，返回指向这些数据的切片，不复制数据
+    ///
+    /// @return Ok(&[u8]) 读取到的数据
+    /// @return Err(SystemError::E2BIG) 没有这么多数据，读取失败
+    pub fn read_slice(&mut self, len: usize) -> Result<&[u8], SystemError> {
+        let end = self.check_remaining(len)?;
+        let start = self.pos;
+        self.pos = end;
+        return Ok(&self.data.as_ref()[start..end]);
+    }
+
+    /// @brief 小端对齐，读取数据到u16数组.
+    ///
+    /// @param buf 目标u16数组
+    ///
+    /// @return Err(SystemError::E2BIG) 没有这么多数据，此时不会读取任何数据
+    pub fn read_u16_into(&mut self, buf: &mut [u16]) -> Result<(), SystemError> {
+        self.check_remaining(buf.len() * size_of::<u16>())?;
+        for item in buf.iter_mut() {
+            *item = self.read_u16()?;
+        }
+        return Ok(());
+    }
+
+    /// @brief 跳过len字节的数据
+    ///
+    /// @return Err(SystemError::E2BIG) 没有这么多数据，此时游标位置不变
+    pub fn skip(&mut self, len: usize) -> Result<(), SystemError> {
+        self.pos = self.check_remaining(len)?;
+        return Ok(());
+    }
+
+    /// @brief 调整游标的位置
+    ///
+    /// @param 调整的相对值
+    ///
+    /// @return Ok(新的游标位置) 调整成功，返回新的游标位置
+    /// @return Err(SystemError::EOVERFLOW) 调整失败，游标超出正确的范围。（失败时游标位置不变）
+    pub fn seek(&mut self, origin: SeekFrom) -> Result<usize, SystemError> {
+        let pos: Option<i64> = match origin {
+            SeekFrom::SeekSet(offset) => Some(offset),
+            SeekFrom::SeekCurrent(offset) => (self.pos as i64).checked_add(offset),
+            // 请注意，此处的offset应小于等于0，否则肯定是不合法的
+            SeekFrom::SeekEnd(offset) => (self.len() as i64).checked_add(offset),
+            SeekFrom::Invalid => {
+                return Err(SystemError::EINVAL);
+            }
+        };
+
+        match pos {
+            Some(pos) if pos >= 0 && pos <= self.len() as i64 => {
+                self.pos = pos as usize;
+                return Ok(self.pos);
+            }
+            _ => return Err(SystemError::EOVERFLOW),
+        }
+    }
+
+    /// @brief 获取当前的数据切片
+    pub fn as_slice(&self) -> &[u8] {
+        return self.data.as_ref();
+    }
+
+    /// @brief 获取还没有读取的数据
+    pub fn remaining_slice(&self) -> &[u8] {
+        return &self.data.as_ref()[self.pos..];
+    }
+
+    /// @brief 获取当前游标的位置
+    #[inline]
+    pub fn pos(&self) -> usize {
+        return self.pos;
+    }
+
+    /// @brief 获取缓冲区数据的大小
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.data.as_ref().len();
+    }
+
+    /// @brief 获取从当前位置到缓冲区末尾的字节数
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        return self.len() - self.pos;
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> ByteCursor<T> {
+    /// @brief 写入一个u8的数据
+    pub fn write_u8(&mut self, value: u8) -> Result<u8, SystemError> {
+        self.write_exact(&[value])?;
+        return Ok(value);
+    }
+
+    impl_write_int! {
+        u16: write_u16, write_u16_be;
+        u32: write_u32, write_u32_be;
+        u64: write_u64, write_u64_be;
+        i16: write_i16, write_i16_be;
+        i32: write_i32, write_i32_be;
+        i64: write_i64, write_i64_be;
+    }
+
+    /// @brief 精确写入与buf同样大小的数据。
+    ///
+    /// @param buf 要写入的数据
+    ///
+    /// @return Ok(()) 成功写入
+    /// @return Err(SystemError::E2BIG) 缓冲区没有这么多空间，写入失败
+    pub fn write_exact(&mut self, buf: &[u8]) -> Result<(), SystemError> {
+        let end = self.check_remaining(buf.len())?;
+        self.data.as_mut()[self.pos..end].copy_from_slice(buf);
+        self.pos = end;
+        return Ok(());
+    }
+
+    /// @brief 写入len个0
+    ///
+    /// @return Err(SystemError::E2BIG) 缓冲区没有这么多空间，此时不会写入任何数据
+    pub fn write_zeros(&mut self, len: usize) -> Result<(), SystemError> {
+        let end = self.check_remaining(len)?;
+        self.data.as_mut()[self.pos..end].fill(0);
+        self.pos = end;
+        return Ok(());
+    }
+
+    /// @brief 获取可变数据切片
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        return self.data.as_mut();
+    }
+}
+
+impl VecCursor {
+    /// @brief 创建一个全0的cursor
+    pub fn zerod(length: usize) -> Self {
+        return Self::new(vec![0u8; length]);
+    }
+}
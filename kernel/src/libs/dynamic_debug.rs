@@ -0,0 +1,225 @@
+//! 动态调试(dynamic debug)
+//!
+//! 每一处kdebug!/kinfo!调用都会在`__dyndbg`段中登记一个[`DynDebugSite`]，记录它所在的模块、文件和行号。
+//! 调用点被禁用时，日志既不会输出到控制台，也不会被记录到日志缓冲区。kinfo!默认启用，kdebug!默认禁用。
+//!
+//! 可以在运行时通过/proc/dynamic_debug/control查看和修改调用点的状态，例如：
+//!
+//! ```text
+//! echo "module ahci +p" > /proc/dynamic_debug/control
+//! echo "file mod.rs line 100-120 -p" > /proc/dynamic_debug/control
+//! ```
+//!
+//! 也可以通过启动参数`dyndbg=`在启动时执行命令，由于启动参数以空格分隔，命令中的空格需要写成逗号，
+//! 例如`dyndbg=module,ahci,+p`。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/lib/dynamic_debug.c
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{kernel_param, kwarn, syscall::SystemError};
+
+use super::log_buf::LogLevel;
+
+/// 一处kdebug!/kinfo!调用点
+#[repr(C)]
+#[derive(Debug)]
+pub struct DynDebugSite {
+    /// 调用点所在的模块，例如`dragonos_kernel::driver::disk::ahci`
+    module: &'static str,
+    file: &'static str,
+    line: u32,
+    level: LogLevel,
+    enabled: AtomicBool,
+}
+
+impl DynDebugSite {
+    pub const fn new(module: &'static str, file: &'static str, line: u32, level: LogLevel) -> Self {
+        Self {
+            module,
+            file,
+            line,
+            level,
+            enabled: AtomicBool::new(level as u8 != LogLevel::DEBUG as u8),
+        }
+    }
+
+    #[inline(always)]
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+extern "C" {
+    static __start___dyndbg: DynDebugSite;
+    static __stop___dyndbg: DynDebugSite;
+}
+
+/// 获取链接器收集的所有调用点
+fn dyndbg_sites() -> &'static [DynDebugSite] {
+    unsafe {
+        let start = &__start___dyndbg as *const DynDebugSite;
+        let end = &__stop___dyndbg as *const DynDebugSite;
+        let len = (end as usize - start as usize) / core::mem::size_of::<DynDebugSite>();
+        return core::slice::from_raw_parts(start, len);
+    }
+}
+
+/// 一条控制命令，没有指定的条件匹配所有的调用点
+#[derive(Debug, Default)]
+struct DynDebugQuery<'a> {
+    module: Option<&'a str>,
+    file: Option<&'a str>,
+    /// 行号的范围(包含两端)
+    lines: Option<(u32, u32)>,
+}
+
+impl DynDebugQuery<'_> {
+    fn matches(&self, site: &DynDebugSite) -> bool {
+        if let Some(module) = self.module {
+            // 可以写完整的模块路径，也可以只写路径中的一段，例如ahci
+            let path = site
+                .module
+                .split_once("::")
+                .map(|(_, p)| p)
+                .unwrap_or(site.module);
+            if site.module != module
+                && path != module
+                && !path.starts_with(&(String::from(module) + "::"))
+                && !path.split("::").any(|s| s == module)
+            {
+                return false;
+            }
+        }
+        if let Some(file) = self.file {
+            // 可以写完整的路径，也可以只写文件名
+            if site.file != file && !site.file.ends_with(&(String::from("/") + file)) {
+                return false;
+            }
+        }
+        if let Some((first, last)) = self.lines {
+            if site.line < first || site.line > last {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+/// 解析行号或者行号范围，例如`100`或`100-120`
+fn parse_lines(s: &str) -> Result<(u32, u32), SystemError> {
+    let (first, last) = s.split_once('-').unwrap_or((s, s));
+    let first = first.parse::<u32>().map_err(|_| SystemError::EINVAL)?;
+    let last = last.parse::<u32>().map_err(|_| SystemError::EINVAL)?;
+    if first > last {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok((first, last));
+}
+
+/// 解析标志，目前只支持p(打印)。由于只有一个标志，`+p`与`=p`等价，`-p`与`=_`等价
+///
+/// ## 返回值
+///
+/// 调用点是否启用
+fn parse_flags(s: &str) -> Result<bool, SystemError> {
+    return match s {
+        "+p" | "=p" => Ok(true),
+        "-p" | "=_" | "=" => Ok(false),
+        _ => Err(SystemError::EINVAL),
+    };
+}
+
+/// 执行一条命令，格式为`[module <name>] [file <name>] [line <n>[-<m>]] <+|-|=>[p]`
+///
+/// ## 返回值
+///
+/// 被匹配到的调用点的数量
+fn ddebug_exec_query(query: &str) -> Result<usize, SystemError> {
+    let words = query.split_ascii_whitespace().collect::<Vec<_>>();
+    let (flags, keywords) = words.split_last().ok_or(SystemError::EINVAL)?;
+    if keywords.len() % 2 != 0 {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut q = DynDebugQuery::default();
+    for pair in keywords.chunks(2) {
+        match pair[0] {
+            "module" => q.module = Some(pair[1]),
+            "file" => q.file = Some(pair[1]),
+            "line" => q.lines = Some(parse_lines(pair[1])?),
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
+    let enabled = parse_flags(flags)?;
+
+    let mut count = 0;
+    for site in dyndbg_sites().iter().filter(|s| q.matches(s)) {
+        site.set_enabled(enabled);
+        count += 1;
+    }
+    return Ok(count);
+}
+
+/// 执行以分号或者换行分隔的多条命令，以#开头的行是注释
+///
+/// ## 返回值
+///
+/// 被匹配到的调用点的总数
+pub fn ddebug_exec_queries(queries: &str) -> Result<usize, SystemError> {
+    let mut count = 0;
+    for query in queries.split(|c| c == ';' || c == '\n') {
+        let query = query.trim();
+        if query.is_empty() || query.starts_with('#') {
+            continue;
+        }
+        count += ddebug_exec_query(query)?;
+    }
+    return Ok(count);
+}
+
+/// 生成/proc/dynamic_debug/control的内容，每行一个调用点
+pub fn dynamic_debug_show() -> String {
+    let mut s = String::from("# filename:lineno [module] flags level\n");
+    for site in dyndbg_sites() {
+        writeln!(
+            s,
+            "{}:{} [{}] ={} {:?}",
+            site.file,
+            site.line,
+            site.module,
+            if site.enabled() { "p" } else { "_" },
+            site.level
+        )
+        .ok();
+    }
+    return s;
+}
+
+/// 写入/proc/dynamic_debug/control
+pub fn dynamic_debug_write(buf: &[u8]) -> Result<usize, SystemError> {
+    let queries = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+    ddebug_exec_queries(queries)?;
+    return Ok(buf.len());
+}
+
+kernel_param!(DYNDBG_PARAM: String = "dyndbg");
+
+/// 执行启动参数`dyndbg=`中的命令
+#[no_mangle]
+pub extern "C" fn rs_dynamic_debug_init() {
+    if let Some(queries) = DYNDBG_PARAM.get() {
+        if let Err(e) = ddebug_exec_queries(&queries.replace(',', " ")) {
+            kwarn!("dyndbg: invalid query '{}': {:?}", queries, e);
+        }
+    }
+}
@@ -14,6 +14,7 @@ use crate::{
     libs::align::page_align_up,
     mm::{
         allocator::page_frame::{PageFrameCount, VirtPageFrame},
+        aslr::{current_randomized, mmap_rnd},
         syscall::{MapFlags, ProtFlags},
         ucontext::InnerAddressSpace,
         MemoryManagementArch, VirtAddr,
@@ -43,6 +44,9 @@ impl ElfLoader {
     /// 读取文件的缓冲区大小
     pub const FILE_READ_BUF_SIZE: usize = 512 * 1024;
 
+    /// 初始线程的线程控制块(TCB)的大小，足以容纳libc放在`%fs:0x28`处的栈保护值
+    pub const STATIC_TLS_TCB_SIZE: usize = 64;
+
     pub const fn new() -> Self {
         Self
     }
@@ -65,8 +69,9 @@ impl ElfLoader {
 
         // 判断是否以可执行文件的形式加载
         if param.load_mode() == ExecLoadMode::Exec {
-            // 检查文件类型是否为可执行文件
-            if ElfType::from(ehdr.e_type) != ElfType::Executable {
+            // 检查文件类型是否为可执行文件，或者位置无关的可执行文件(PIE)
+            let elf_type = ElfType::from(ehdr.e_type);
+            if elf_type != ElfType::Executable && elf_type != ElfType::DSO {
                 return Err(ExecError::NotExecutable);
             }
         } else {
@@ -99,7 +104,7 @@ impl ElfLoader {
                 start,
                 end - start,
                 prot_flags,
-                MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED_NOREPLACE,
+                MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED_NOREPLACE | MapFlags::MAP_POPULATE,
                 false,
             );
             if r.is_err() {
@@ -125,6 +130,11 @@ impl ElfLoader {
         VirtAddr::new((addr.data() + Self::ELF_PAGE_SIZE - 1) & (!(Self::ELF_PAGE_SIZE - 1)))
     }
 
+    /// 位置无关的可执行文件(PIE)的默认加载地址，位于用户空间的2/3处，远离mmap区域的起始地址
+    fn elf_et_dyn_base(&self) -> VirtAddr {
+        self.elf_page_start(VirtAddr::new(MMArch::USER_END_VADDR.data() / 3 * 2))
+    }
+
     /// 根据ELF的p_flags生成对应的ProtFlags
     fn make_prot(&self, p_flags: u32, _has_interpreter: bool, _is_interpreter: bool) -> ProtFlags {
         let mut prot = ProtFlags::empty();
@@ -321,6 +331,71 @@ impl ElfLoader {
         return Ok(());
     }
 
+    /// 为初始线程建立静态TLS，返回线程指针
+    ///
+    /// x86_64使用第II种TLS布局：TLS块紧挨在线程指针之前，线程指针指向线程控制块(TCB)，
+    /// TCB的第一个字是指向自身的指针，使得程序可以通过`%fs:0`得到线程指针。
+    /// .tdata从文件中复制，.tbss由匿名映射保证为0。
+    ///
+    /// 参考 https://www.akkadia.org/drepper/tls.pdf
+    ///
+    /// ## 参数
+    ///
+    /// - `user_vm_guard` - 用户虚拟地址空间
+    /// - `tls_seg` - PT_TLS段
+    /// - `param` - 执行参数
+    fn setup_static_tls(
+        &self,
+        user_vm_guard: &mut RwLockWriteGuard<'_, InnerAddressSpace>,
+        tls_seg: &ProgramHeader,
+        param: &mut ExecParam,
+    ) -> Result<VirtAddr, ExecError> {
+        let align = core::cmp::max(tls_seg.p_align as usize, 1);
+        let memsz = tls_seg.p_memsz as usize;
+        let filesz = tls_seg.p_filesz as usize;
+        if !align.is_power_of_two() || filesz > memsz {
+            return Err(ExecError::ParseError);
+        }
+        // 匿名映射的起始地址按页对齐，因此只支持不超过一页的对齐要求
+        if align > Self::ELF_PAGE_SIZE {
+            return Err(ExecError::NotSupported);
+        }
+
+        // 线程指针到TLS块起始处的距离，需要让线程指针满足TLS段的对齐要求
+        let tls_offset = memsz.checked_add(align - 1).ok_or(ExecError::ParseError)? & !(align - 1);
+        let len = tls_offset
+            .checked_add(Self::STATIC_TLS_TCB_SIZE)
+            .ok_or(ExecError::ParseError)?;
+
+        let base = user_vm_guard
+            .map_anonymous(
+                VirtAddr::new(0),
+                page_align_up(len),
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_POPULATE,
+                false,
+            )
+            .map_err(|e| {
+                kerror!("setup_static_tls: map_anonymous failed, err={:?}", e);
+                ExecError::OutOfMemory
+            })?
+            .virt_address();
+        let tp = base + tls_offset;
+
+        // 复制.tdata
+        self.do_load_file(base, filesz, tls_seg.p_offset as usize, param)
+            .map_err(|e| match e {
+                SystemError::EFAULT => ExecError::BadAddress(Some(base)),
+                _ => ExecError::Other(format!("load tls image failed: {:?}", e)),
+            })?;
+
+        // TCB的第一个字指向自身
+        unsafe { copy_to_user(tp, &tp.data().to_ne_bytes()) }
+            .map_err(|_| ExecError::BadAddress(Some(tp)))?;
+
+        return Ok(tp);
+    }
+
     /// 我们需要显式的把数据段之后剩余的内存页都清零。
     fn pad_zero(&self, elf_bss: VirtAddr) -> Result<(), SystemError> {
         let nbyte = self.elf_page_offset(elf_bss);
@@ -499,9 +574,15 @@ impl BinaryLoader for ElfLoader {
         // kdebug!("to parse segments");
         // 加载ELF文件并映射到用户空间
         let mut phdr_buf = Vec::new();
-        let loadable_sections = Self::parse_segments(param, &ehdr, &mut phdr_buf)
+        let segments = Self::parse_segments(param, &ehdr, &mut phdr_buf)
             .map_err(|_| ExecError::ParseError)?
-            .ok_or(ExecError::ParseError)?
+            .ok_or(ExecError::ParseError)?;
+        let has_interpreter = segments.iter().any(|seg| seg.p_type == elf::abi::PT_INTERP);
+        // 目前只支持不需要动态链接器的PIE程序(static-pie)
+        if elf_type == ElfType::DSO && has_interpreter {
+            return Err(ExecError::NotSupported);
+        }
+        let loadable_sections = segments
             .iter()
             .filter(|seg| seg.p_type == elf::abi::PT_LOAD);
 
@@ -514,9 +595,8 @@ impl BinaryLoader for ElfLoader {
         let mut start_data: Option<VirtAddr> = None;
         let mut end_data: Option<VirtAddr> = None;
 
-        // 加载的时候的偏移量（这个偏移量在加载动态链接段的时候产生，由于还没有动态链接，因此暂时不可变。）
-        // 请不要删除load_bias! 以免到时候写动态链接的时候忘记了。
-        let load_bias = 0usize;
+        // 加载的时候的偏移量，只有PIE程序的偏移量不为0，在加载第一个段的时候确定
+        let mut load_bias = 0usize;
         let mut bss_prot_flags = ProtFlags::empty();
         // 是否是第一个加载的段
         let mut first_pt_load = true;
@@ -548,10 +628,10 @@ impl BinaryLoader for ElfLoader {
             }
 
             // 生成ProtFlags.
-            // TODO: 当有了动态链接之后，需要根据情况设置这里的has_interpreter
-            let elf_prot_flags = self.make_prot(seg_to_load.p_flags, false, false);
+            let elf_prot_flags = self.make_prot(seg_to_load.p_flags, has_interpreter, false);
 
-            let mut elf_map_flags = MapFlags::MAP_PRIVATE;
+            // 加载器在持有地址空间的锁时把文件内容写入段中，因此段需要立即分配物理页
+            let mut elf_map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_POPULATE;
 
             let vaddr = VirtAddr::new(seg_to_load.p_vaddr as usize);
 
@@ -565,8 +645,18 @@ impl BinaryLoader for ElfLoader {
                  */
                 elf_map_flags.insert(MapFlags::MAP_FIXED_NOREPLACE);
             } else if elf_type == ElfType::DSO {
-                // TODO: 支持动态链接
-                unimplemented!("DragonOS currently does not support dynamic linking!");
+                /*
+                 * PIE程序被加载到elf_et_dyn_base()处，地址空间布局随机化时，再加上一个随机的偏移量。
+                 * 段之间的相对位置保持不变，因此之后的段都加上同样的load_bias。
+                 */
+                let mut base = self.elf_et_dyn_base();
+                if current_randomized() {
+                    base = base + mmap_rnd();
+                }
+                load_bias = self
+                    .elf_page_start(base - self.elf_page_start(vaddr).data())
+                    .data();
+                elf_map_flags.insert(MapFlags::MAP_FIXED_NOREPLACE);
             }
 
             // 加载这个段到用户空间
@@ -593,13 +683,7 @@ impl BinaryLoader for ElfLoader {
                 return Err(ExecError::BadAddress(Some(e.0)));
             }
 
-            if first_pt_load {
-                first_pt_load = false;
-                if elf_type == ElfType::DSO {
-                    // todo: 在这里增加对load_bias和reloc_func_desc的更新代码
-                    todo!()
-                }
-            }
+            first_pt_load = false;
 
             // kdebug!("seg_to_load.p_offset={}", seg_to_load.p_offset);
             // kdebug!("e_phoff={}", ehdr.e_phoff);
@@ -696,6 +780,13 @@ impl BinaryLoader for ElfLoader {
             // kdebug!("elf_bss = {elf_bss:?}, elf_brk = {elf_brk:?}");
             return Err(ExecError::BadAddress(Some(elf_bss)));
         }
+        // 为初始线程建立静态TLS
+        let tls_seg = segments.iter().find(|seg| seg.p_type == elf::abi::PT_TLS);
+        let thread_pointer = match tls_seg {
+            Some(tls_seg) => Some(self.setup_static_tls(&mut user_vm, &tls_seg, param)?),
+            None => None,
+        };
+
         // todo: 动态链接：增加加载interpreter的代码
         // kdebug!("to create auxv");
 
@@ -707,7 +798,7 @@ impl BinaryLoader for ElfLoader {
         user_vm.start_data = start_data.unwrap_or(VirtAddr::new(0));
         user_vm.end_data = end_data.unwrap_or(VirtAddr::new(0));
 
-        let result = BinaryLoaderResult::new(program_entrypoint);
+        let result = BinaryLoaderResult::new(program_entrypoint, thread_pointer);
         // kdebug!("elf load OK!!!");
         return Ok(result);
     }
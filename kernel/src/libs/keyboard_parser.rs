@@ -1,6 +1,9 @@
 use alloc::sync::Arc;
 
-use crate::driver::tty::tty_device::TtyDevice;
+use crate::driver::tty::{
+    tty_device::TtyDevice,
+    vt::{vt_foreground, vt_scrollback, vt_switch, MAX_NR_CONSOLES},
+};
 
 #[allow(dead_code)]
 pub const NUM_SCAN_CODES: u8 = 0x80;
@@ -23,30 +26,33 @@ pub enum KeyFlag {
 }
 
 /// @brief A FSM to parse type one keyboard scan code
+///
+/// 解析得到的字符被送到前台的虚拟终端
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct TypeOneFSM {
     status: ScanCodeStatus,
     current_state: TypeOneFSMState,
-    tty: Arc<TtyDevice>,
 }
 
 impl TypeOneFSM {
     #[allow(dead_code)]
-    pub fn new(tty: Arc<TtyDevice>) -> Self {
+    pub fn new() -> Self {
         Self {
             status: ScanCodeStatus::new(),
             current_state: TypeOneFSMState::Start,
-            tty,
         }
     }
 
     /// @brief 解析扫描码
     #[allow(dead_code)]
     pub fn parse(&mut self, scancode: u8) -> TypeOneFSMState {
-        self.current_state = self
-            .current_state
-            .parse(scancode, &mut self.status, &self.tty);
+        let tty = match vt_foreground() {
+            Some(tty) => tty,
+            // 虚拟终端尚未初始化
+            None => return self.current_state,
+        };
+        self.current_state = self.current_state.parse(scancode, &mut self.status, &tty);
         self.current_state
     }
 }
@@ -208,6 +214,10 @@ impl TypeOneFSMState {
             }
             0x49 => {
                 scancode_status.pgup = true;
+                // Shift+PageUp 向上翻看回滚缓冲区
+                if scancode_status.shift_l || scancode_status.shift_r {
+                    vt_scrollback(true);
+                }
             }
             0xc9 => {
                 scancode_status.pgup = false;
@@ -227,6 +237,10 @@ impl TypeOneFSMState {
             }
             0x51 => {
                 scancode_status.pgdn = true;
+                // Shift+PageDown 向下翻看回滚缓冲区
+                if scancode_status.shift_l || scancode_status.shift_r {
+                    vt_scrollback(false);
+                }
             }
             0xd1 => {
                 scancode_status.pgdn = false;
@@ -322,7 +336,17 @@ impl TypeOneFSMState {
                 key = KeyFlag::NoneFlag;
             }
             0x38 => {
-                scancode_status.alt_r = flag_make;
+                scancode_status.alt_l = flag_make;
+                key = KeyFlag::NoneFlag;
+            }
+            // Alt+F1..Alt+Fn 切换虚拟终端
+            0x3b..=0x44
+                if (scancode_status.alt_l || scancode_status.alt_r)
+                    && ((index - 0x3b) as usize) < MAX_NR_CONSOLES =>
+            {
+                if flag_make {
+                    vt_switch((index - 0x3b) as usize).ok();
+                }
                 key = KeyFlag::NoneFlag;
             }
             0x3A => {
@@ -0,0 +1,334 @@
+//! VT100/ANSI转义序列的解析
+//!
+//! 解析器逐个接收输出的字符，把转义序列拆分为[`AnsiAction`]，由textui窗口执行具体的操作
+//! (移动光标、擦除、滚动、设置颜色等)。支持的序列与Linux的虚拟终端基本一致。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/vt/vt.c#do_con_trol
+
+use super::textui::FontColor;
+
+/// CSI序列最多支持的参数个数，超出的参数被忽略
+pub const ANSI_MAX_PARAMS: usize = 16;
+
+/// 解析器的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiState {
+    /// 普通字符
+    Normal,
+    /// 收到ESC
+    Escape,
+    /// ESC之后的中间字符(如选择字符集的`ESC (`)，忽略紧接着的一个字符
+    EscIntermediate,
+    /// 收到`ESC [`，正在解析CSI序列的参数
+    Csi,
+    /// 收到`ESC ]`，操作系统命令(如设置窗口标题)，忽略直到BEL或者ST
+    Osc,
+    /// OSC序列中收到ESC，等待ST(`ESC \`)
+    OscEscape,
+}
+
+/// @brief 一个完整的CSI序列: `ESC [ [?] 参数1;参数2;... 结束字符`
+#[derive(Debug, Clone, Copy)]
+pub struct CsiSequence {
+    params: [u16; ANSI_MAX_PARAMS],
+    nparams: usize,
+    /// 参数之前带有`?`等私有标记
+    pub private: bool,
+    /// 结束字符，决定序列的含义
+    pub action: char,
+}
+
+impl CsiSequence {
+    /// @brief 获取第i个参数，参数缺省或者为0时返回默认值
+    pub fn param(&self, i: usize, default: u16) -> u16 {
+        if i < self.nparams && self.params[i] != 0 {
+            return self.params[i];
+        }
+        return default;
+    }
+
+    /// @brief 获取所有参数的原始值，没有参数时返回空
+    pub fn params(&self) -> &[u16] {
+        return &self.params[..self.nparams];
+    }
+}
+
+/// 解析器对一个字符的解析结果
+#[derive(Debug, Clone, Copy)]
+pub enum AnsiAction {
+    /// 可显示的字符
+    Print(char),
+    /// C0控制字符，如`\n`、`\r`、`\x08`
+    Control(char),
+    /// `ESC 字符`形式的转义序列
+    Esc(char),
+    /// CSI序列
+    Csi(CsiSequence),
+}
+
+/// @brief VT100/ANSI转义序列的解析器，每个窗口拥有一个，保存未完成的序列
+#[derive(Debug, Clone)]
+pub struct AnsiParser {
+    state: AnsiState,
+    csi: CsiSequence,
+}
+
+impl AnsiParser {
+    pub const fn new() -> Self {
+        Self {
+            state: AnsiState::Normal,
+            csi: CsiSequence {
+                params: [0; ANSI_MAX_PARAMS],
+                nparams: 0,
+                private: false,
+                action: '\0',
+            },
+        }
+    }
+
+    /// @brief 解析一个字符
+    ///
+    /// @return 字符结束了一个序列或者是普通字符时，返回需要执行的操作；序列未完成时返回None
+    pub fn feed(&mut self, c: char) -> Option<AnsiAction> {
+        match self.state {
+            AnsiState::Normal => {
+                if c == '\x1b' {
+                    self.state = AnsiState::Escape;
+                    return None;
+                }
+                if Self::is_control(c) {
+                    return Some(AnsiAction::Control(c));
+                }
+                return Some(AnsiAction::Print(c));
+            }
+            AnsiState::Escape => match c {
+                '[' => {
+                    self.csi.params = [0; ANSI_MAX_PARAMS];
+                    self.csi.nparams = 0;
+                    self.csi.private = false;
+                    self.state = AnsiState::Csi;
+                    return None;
+                }
+                ']' => {
+                    self.state = AnsiState::Osc;
+                    return None;
+                }
+                '(' | ')' | '#' | '%' => {
+                    self.state = AnsiState::EscIntermediate;
+                    return None;
+                }
+                '\x1b' => return None,
+                _ => {
+                    self.state = AnsiState::Normal;
+                    return Some(AnsiAction::Esc(c));
+                }
+            },
+            AnsiState::EscIntermediate => {
+                self.state = AnsiState::Normal;
+                return None;
+            }
+            AnsiState::Csi => match c {
+                '0'..='9' => {
+                    if self.csi.nparams == 0 {
+                        self.csi.nparams = 1;
+                    }
+                    let p = &mut self.csi.params[self.csi.nparams - 1];
+                    *p = p.saturating_mul(10).saturating_add(c as u16 - '0' as u16);
+                    return None;
+                }
+                ';' => {
+                    if self.csi.nparams == 0 {
+                        self.csi.nparams = 1;
+                    }
+                    if self.csi.nparams < ANSI_MAX_PARAMS {
+                        self.csi.nparams += 1;
+                    }
+                    return None;
+                }
+                '?' | '>' | '=' => {
+                    self.csi.private = true;
+                    return None;
+                }
+                '\x1b' => {
+                    // 未完成的序列被新的转义序列打断
+                    self.state = AnsiState::Escape;
+                    return None;
+                }
+                '\x40'..='\x7e' => {
+                    self.state = AnsiState::Normal;
+                    self.csi.action = c;
+                    return Some(AnsiAction::Csi(self.csi));
+                }
+                // 与Linux一致，序列中间的控制字符立即执行
+                _ if Self::is_control(c) => return Some(AnsiAction::Control(c)),
+                // 忽略中间字符
+                _ => return None,
+            },
+            AnsiState::Osc => {
+                match c {
+                    '\x07' => self.state = AnsiState::Normal,
+                    '\x1b' => self.state = AnsiState::OscEscape,
+                    _ => {}
+                }
+                return None;
+            }
+            AnsiState::OscEscape => {
+                self.state = AnsiState::Normal;
+                return None;
+            }
+        }
+    }
+
+    #[inline]
+    fn is_control(c: char) -> bool {
+        return (c as u32) < 0x20 || c == '\x7f';
+    }
+}
+
+/// 颜色的表示方式
+#[derive(Debug, Clone, Copy)]
+enum AnsiColor {
+    /// 256色调色板中的下标
+    Indexed(u8),
+    /// 24位真彩色
+    Rgb(FontColor),
+}
+
+/// @brief 由SGR序列(`ESC [ ... m`)设置的字符属性
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SgrAttr {
+    /// 前景色，None表示使用输出者指定的颜色
+    fg: Option<AnsiColor>,
+    /// 背景色，None表示使用输出者指定的颜色
+    bg: Option<AnsiColor>,
+    bold: bool,
+    reverse: bool,
+}
+
+impl SgrAttr {
+    /// 16色调色板，与Linux虚拟终端的默认调色板一致
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/tty/vt/vt.c#default_red
+    const PALETTE: [FontColor; 16] = [
+        FontColor::new(0x00, 0x00, 0x00),
+        FontColor::new(0xaa, 0x00, 0x00),
+        FontColor::new(0x00, 0xaa, 0x00),
+        FontColor::new(0xaa, 0x55, 0x00),
+        FontColor::new(0x00, 0x00, 0xaa),
+        FontColor::new(0xaa, 0x00, 0xaa),
+        FontColor::new(0x00, 0xaa, 0xaa),
+        FontColor::new(0xaa, 0xaa, 0xaa),
+        FontColor::new(0x55, 0x55, 0x55),
+        FontColor::new(0xff, 0x55, 0x55),
+        FontColor::new(0x55, 0xff, 0x55),
+        FontColor::new(0xff, 0xff, 0x55),
+        FontColor::new(0x55, 0x55, 0xff),
+        FontColor::new(0xff, 0x55, 0xff),
+        FontColor::new(0x55, 0xff, 0xff),
+        FontColor::new(0xff, 0xff, 0xff),
+    ];
+
+    /// @brief 执行SGR序列，修改字符属性
+    pub fn apply(&mut self, seq: &CsiSequence) {
+        let params = seq.params();
+        if params.is_empty() {
+            *self = Self::default();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                p @ 30..=37 => self.fg = Some(AnsiColor::Indexed((p - 30) as u8)),
+                39 => self.fg = None,
+                p @ 40..=47 => self.bg = Some(AnsiColor::Indexed((p - 40) as u8)),
+                49 => self.bg = None,
+                p @ 90..=97 => self.fg = Some(AnsiColor::Indexed((p - 90 + 8) as u8)),
+                p @ 100..=107 => self.bg = Some(AnsiColor::Indexed((p - 100 + 8) as u8)),
+                p @ (38 | 48) => {
+                    // 扩展颜色: 38;5;n 或者 38;2;r;g;b
+                    let color = match params.get(i + 1) {
+                        Some(5) if i + 2 < params.len() => {
+                            let color = AnsiColor::Indexed(params[i + 2] as u8);
+                            i += 2;
+                            Some(color)
+                        }
+                        Some(2) if i + 4 < params.len() => {
+                            let color = AnsiColor::Rgb(FontColor::new(
+                                params[i + 2] as u8,
+                                params[i + 3] as u8,
+                                params[i + 4] as u8,
+                            ));
+                            i += 4;
+                            Some(color)
+                        }
+                        _ => None,
+                    };
+                    if color.is_none() {
+                        // 无法识别的扩展颜色，忽略剩余的参数
+                        return;
+                    }
+                    if p == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                // 下划线、闪烁等属性无法在帧缓冲区上表示，忽略
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// @brief 计算字符实际显示的颜色
+    ///
+    /// @param frcolor 输出者指定的前景色
+    /// @param bkcolor 输出者指定的背景色
+    ///
+    /// @return (前景色, 背景色)
+    pub fn colors(&self, frcolor: FontColor, bkcolor: FontColor) -> (FontColor, FontColor) {
+        let fg = match self.fg {
+            // 与Linux一致，粗体以高亮的颜色显示
+            Some(AnsiColor::Indexed(i)) if self.bold && i < 8 => Self::indexed_color(i + 8),
+            Some(color) => Self::color(color),
+            None => frcolor,
+        };
+        let bg = self.bg.map(Self::color).unwrap_or(bkcolor);
+        if self.reverse {
+            return (bg, fg);
+        }
+        return (fg, bg);
+    }
+
+    fn color(color: AnsiColor) -> FontColor {
+        match color {
+            AnsiColor::Indexed(i) => Self::indexed_color(i),
+            AnsiColor::Rgb(rgb) => rgb,
+        }
+    }
+
+    /// 256色调色板: 0~15为16色调色板，16~231为6x6x6的颜色立方体，232~255为灰度
+    fn indexed_color(i: u8) -> FontColor {
+        const LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+        match i {
+            0..=15 => Self::PALETTE[i as usize],
+            16..=231 => {
+                let i = i - 16;
+                FontColor::new(
+                    LEVELS[(i / 36) as usize],
+                    LEVELS[(i / 6 % 6) as usize],
+                    LEVELS[(i % 6) as usize],
+                )
+            }
+            _ => {
+                let level = 8 + (i - 232) * 10;
+                FontColor::new(level, level, level)
+            }
+        }
+    }
+}
@@ -20,6 +20,7 @@ use core::{
 };
 
 use super::{
+    ansi::{AnsiAction, AnsiParser, CsiSequence, SgrAttr},
     screen_manager::{
         scm_register, ScmBuffer, ScmBufferInfo, ScmFramworkType, ScmUiFramework,
         ScmUiFrameworkMetadata,
@@ -61,7 +62,7 @@ pub unsafe fn textui_framwork_init() {
         let chars_num = (metadata.buf_info().width() / TEXTUI_CHAR_WIDTH) as usize;
 
         let initial_window = TextuiWindow::new(
-            WindowFlag::TEXTUI_CHROMATIC,
+            WindowFlag::TEXTUI_CHROMATIC | WindowFlag::TEXTUI_VISIBLE,
             vlines_num as i32,
             chars_num as i32,
         );
@@ -96,6 +97,8 @@ bitflags! {
     pub struct WindowFlag: u8 {
         // 采用彩色字符
         const TEXTUI_CHROMATIC = 1 << 0;
+        // 窗口正显示在屏幕上。不可见的窗口只更新虚拟行，不渲染到帧缓冲区
+        const TEXTUI_VISIBLE = 1 << 1;
     }
 }
 
@@ -496,6 +499,9 @@ impl WindowId {
         return WindowId(MAX_ID.fetch_add(1, Ordering::SeqCst));
     }
 }
+/// 每个窗口在屏幕显示的行之外额外保留的虚拟行数，用于保存滚出屏幕的输出(回滚缓冲区)
+pub const TEXTUI_SCROLLBACK_LINES: i32 = 200;
+
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub struct TextuiWindow {
@@ -503,18 +509,33 @@ pub struct TextuiWindow {
     id: WindowId,
     // 虚拟行总数
     vline_sum: i32,
-    // 当前已经使用了的虚拟行总数（即在已经输入到缓冲区（之后显示在屏幕上）的虚拟行数量）
+    // 当前已经使用了的虚拟行总数（屏幕上的行，加上回滚缓冲区中保存的历史行）
     vlines_used: i32,
-    // 位于最顶上的那一个虚拟行的行号
+    // 位于屏幕最顶上的那一个虚拟行的行号
     top_vline: LineId,
     // 储存虚拟行的数组
     vlines: Vec<TextuiVline>,
-    // 正在操作的vline
+    // 光标所在的vline，光标的列号为这个vline的index
     vline_operating: LineId,
     // 每行最大容纳的字符数
     chars_per_line: i32,
     // 窗口flag
     flags: WindowFlag,
+    // 屏幕上显示的行数
+    rows: i32,
+    // 滚动区域的首行和末行（屏幕上的行号，包含两端）
+    scroll_top: i32,
+    scroll_bottom: i32,
+    // 在回滚缓冲区中向上翻看的行数，为0时显示最新的输出
+    view_offset: i32,
+    // 保存的光标位置（行，列）
+    saved_cursor: (i32, i32),
+    // 转义序列的解析器
+    ansi: AnsiParser,
+    // 由转义序列设置的字符属性
+    attr: SgrAttr,
+    // 输出者指定的前景色和背景色，字符属性中没有设置颜色时使用
+    default_color: (FontColor, FontColor),
 }
 
 impl TextuiWindow {
@@ -522,13 +543,14 @@ impl TextuiWindow {
     /// ## 参数
     ///
     /// -flags 标志位
-    /// -vlines_num 虚拟行的总数
+    /// -vlines_num 屏幕上显示的行数，窗口额外分配TEXTUI_SCROLLBACK_LINES个虚拟行作为回滚缓冲区
     /// -chars_num 每行最大的字符数
 
     pub fn new(flags: WindowFlag, vlines_num: i32, chars_num: i32) -> Self {
+        let vline_sum = vlines_num + TEXTUI_SCROLLBACK_LINES;
         let mut initial_vlines = Vec::new();
 
-        for _ in 0..vlines_num {
+        for _ in 0..vline_sum {
             let vline = TextuiVlineChromatic::new(chars_num as usize);
 
             initial_vlines.push(TextuiVline::Chromatic(vline));
@@ -536,13 +558,193 @@ impl TextuiWindow {
         TextuiWindow {
             id: WindowId::new(),
             flags,
-            vline_sum: vlines_num,
-            vlines_used: 1,
+            vline_sum,
+            vlines_used: vlines_num,
             top_vline: LineId::new(0),
             vlines: initial_vlines,
             vline_operating: LineId::new(0),
             chars_per_line: chars_num,
+            rows: vlines_num,
+            scroll_top: 0,
+            scroll_bottom: vlines_num - 1,
+            view_offset: 0,
+            saved_cursor: (0, 0),
+            ansi: AnsiParser::new(),
+            attr: SgrAttr::default(),
+            default_color: (FontColor::WHITE, FontColor::BLACK),
+        }
+    }
+
+    /// 屏幕上显示的行数
+    pub fn rows(&self) -> i32 {
+        self.rows
+    }
+
+    /// 屏幕上第row行对应的虚拟行
+    fn vline_of_row(&self, row: i32) -> LineId {
+        LineId::new((self.top_vline.data() + row).rem_euclid(self.vline_sum))
+    }
+
+    fn vline_mut(&mut self, vline_id: LineId) -> Option<&mut TextuiVlineChromatic> {
+        match self.vlines.get_mut(<LineId as Into<usize>>::into(vline_id)) {
+            Some(TextuiVline::Chromatic(vline)) => Some(vline),
+            _ => None,
+        }
+    }
+
+    /// 光标所在的行（屏幕上的行号）
+    fn cursor_row(&self) -> i32 {
+        (self.vline_operating.data() - self.top_vline.data()).rem_euclid(self.vline_sum)
+    }
+
+    /// 光标所在的列。上一个字符写在了行尾时，列号等于每行的字符数，下一个字符写入前才换行
+    fn cursor_col(&self) -> i32 {
+        match &self.vlines[<LineId as Into<usize>>::into(self.vline_operating)] {
+            TextuiVline::Chromatic(vline) => vline.index.into(),
+            TextuiVline::_Normal(_) => 0,
+        }
+    }
+
+    /// 不做范围检查地移动光标
+    fn move_cursor_raw(&mut self, row: i32, col: i32) {
+        self.vline_operating = self.vline_of_row(row);
+        let vline_id = self.vline_operating;
+        if let Some(vline) = self.vline_mut(vline_id) {
+            vline.index = LineIndex::new(col);
+        }
+    }
+
+    /// 移动光标，超出屏幕的位置被限制在屏幕的边缘
+    fn set_cursor(&mut self, row: i32, col: i32) {
+        self.move_cursor_raw(
+            row.clamp(0, self.rows - 1),
+            col.clamp(0, self.chars_per_line - 1),
+        );
+    }
+
+    /// 当前字符属性下的前景色和背景色
+    fn current_colors(&self) -> (FontColor, FontColor) {
+        self.attr.colors(self.default_color.0, self.default_color.1)
+    }
+
+    /// 用当前的背景色擦除屏幕上第row行的[start, end)列，不重新渲染
+    fn clear_cells(&mut self, row: i32, start: i32, end: i32) {
+        let start = start.clamp(0, self.chars_per_line);
+        let end = end.clamp(start, self.chars_per_line);
+        let (frcolor, bkcolor) = self.current_colors();
+        let vline_id = self.vline_of_row(row);
+        if let Some(vline) = self.vline_mut(vline_id) {
+            for v_char in &mut vline.chars[start as usize..end as usize] {
+                *v_char = TextuiCharChromatic::new(None, frcolor, bkcolor);
+            }
+        }
+    }
+
+    /// 擦除屏幕上第row行的[start, end)列，并重新渲染
+    fn erase_cells(&mut self, row: i32, start: i32, end: i32) -> Result<(), SystemError> {
+        self.clear_cells(row, start, end);
+        let start = start.clamp(0, self.chars_per_line);
+        let end = end.clamp(start, self.chars_per_line);
+        let vline_id = self.vline_of_row(row);
+        return self.textui_refresh_characters(vline_id, LineIndex::new(start), end - start);
+    }
+
+    /// 擦除屏幕上的[start, end)行，并重新渲染
+    fn erase_rows(&mut self, start: i32, end: i32) -> Result<(), SystemError> {
+        for row in start..end {
+            self.clear_cells(row, 0, self.chars_per_line);
+        }
+        return self.textui_refresh_rows(start, end);
+    }
+
+    /// 把屏幕上[top, bottom]行之间的内容向上滚动n行，底部空出的行被擦除
+    ///
+    /// ## 参数
+    /// - history 为true并且滚动的是整个屏幕时，滚出屏幕的行保存到回滚缓冲区中
+    fn scroll_up(
+        &mut self,
+        top: i32,
+        bottom: i32,
+        n: i32,
+        history: bool,
+    ) -> Result<(), SystemError> {
+        let n = n.min(bottom - top + 1);
+        if n <= 0 {
+            return Ok(());
         }
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        if history && top == 0 && bottom == self.rows - 1 {
+            // 只需要移动屏幕顶部在循环表中的位置，原来顶部的行成为历史行
+            self.top_vline = self.vline_of_row(n);
+            self.vlines_used = (self.vlines_used + n).min(self.vline_sum);
+        } else {
+            for r in top..=bottom - n {
+                let a: usize = self.vline_of_row(r).into();
+                let b: usize = self.vline_of_row(r + n).into();
+                self.vlines.swap(a, b);
+            }
+        }
+        for r in bottom - n + 1..=bottom {
+            self.clear_cells(r, 0, self.chars_per_line);
+        }
+        self.move_cursor_raw(row, col);
+        return self.textui_refresh_rows(top, bottom + 1);
+    }
+
+    /// 把屏幕上[top, bottom]行之间的内容向下滚动n行，顶部空出的行被擦除
+    fn scroll_down(&mut self, top: i32, bottom: i32, n: i32) -> Result<(), SystemError> {
+        let n = n.min(bottom - top + 1);
+        if n <= 0 {
+            return Ok(());
+        }
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        for r in (top + n..=bottom).rev() {
+            let a: usize = self.vline_of_row(r).into();
+            let b: usize = self.vline_of_row(r - n).into();
+            self.vlines.swap(a, b);
+        }
+        for r in top..top + n {
+            self.clear_cells(r, 0, self.chars_per_line);
+        }
+        self.move_cursor_raw(row, col);
+        return self.textui_refresh_rows(top, bottom + 1);
+    }
+
+    /// 光标移动到下一行，光标位于滚动区域的末行时，滚动区域向上滚动一行
+    fn line_feed(&mut self) -> Result<(), SystemError> {
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        if row == self.scroll_bottom {
+            self.scroll_up(self.scroll_top, self.scroll_bottom, 1, true)?;
+            self.set_cursor(row, col);
+        } else {
+            self.set_cursor(row + 1, col);
+        }
+        return Ok(());
+    }
+
+    /// 光标移动到上一行，光标位于滚动区域的首行时，滚动区域向下滚动一行
+    fn reverse_line_feed(&mut self) -> Result<(), SystemError> {
+        let (row, col) = (self.cursor_row(), self.cursor_col());
+        if row == self.scroll_top {
+            self.scroll_down(self.scroll_top, self.scroll_bottom, 1)?;
+            self.set_cursor(row, col);
+        } else {
+            self.set_cursor(row - 1, col);
+        }
+        return Ok(());
+    }
+
+    /// 在回滚缓冲区中翻看之前的输出，有新的输出时自动回到最新的内容
+    /// ## 参数
+    /// - lines 向上翻看的行数，为负数时向下翻看
+
+    pub fn scroll_view(&mut self, lines: i32) -> Result<(), SystemError> {
+        let offset = (self.view_offset + lines).clamp(0, self.vlines_used - self.rows);
+        if offset == self.view_offset {
+            return Ok(());
+        }
+        self.view_offset = offset;
+        return self.textui_refresh_screen();
     }
 
     /// 刷新某个窗口的缓冲区的某个虚拟行的连续n个字符对象
@@ -558,7 +760,10 @@ impl TextuiWindow {
         start: LineIndex,
         count: i32,
     ) -> Result<(), SystemError> {
-        let actual_line_sum = textui_framework().actual_line.load(Ordering::SeqCst);
+        // 不可见的窗口在切换到前台时整体重新渲染
+        if !self.flags.contains(WindowFlag::TEXTUI_VISIBLE) {
+            return Ok(());
+        }
 
         // 判断虚拟行参数是否合法
         if unlikely(
@@ -567,13 +772,14 @@ impl TextuiWindow {
         ) {
             return Err(SystemError::EINVAL);
         }
-        // 计算虚拟行对应的真实行（即要渲染的行）
-        let mut actual_line_id = vline_id - self.top_vline; //为正说明虚拟行不在真实行显示的区域上面
-
-        if <LineId as Into<i32>>::into(actual_line_id) < 0 {
-            //真实行数小于虚拟行数，则需要加上真实行数的位置，以便正确计算真实行
-            actual_line_id = actual_line_id + actual_line_sum;
+        // 计算虚拟行对应的真实行（即要渲染的行），翻看回滚缓冲区时屏幕顶部的行向上偏移
+        let view_top = self.top_vline.data() - self.view_offset;
+        let actual_line_id = (vline_id.data() - view_top).rem_euclid(self.vline_sum);
+        if actual_line_id >= self.rows {
+            // 虚拟行不在屏幕上
+            return Ok(());
         }
+        let actual_line_id = LineId::new(actual_line_id);
 
         // 将此窗口的某个虚拟行的连续n个字符对象往缓存区写入
         if self.flags.contains(WindowFlag::TEXTUI_CHROMATIC) {
@@ -633,98 +839,202 @@ impl TextuiWindow {
         return Ok(0);
     }
 
-    /// 往某个窗口的缓冲区的某个虚拟行插入换行
-    /// ## 参数
-    /// - window 窗口结构体
-    /// - vline_id 虚拟行号
+    /// 重新渲染屏幕上的[start, end)行
+    fn textui_refresh_rows(&mut self, start: i32, end: i32) -> Result<(), SystemError> {
+        for row in start..end {
+            let vline_id = self.vline_of_row(row);
+            self.textui_refresh_vline(vline_id)?;
+        }
+        return Ok(());
+    }
 
-    fn textui_new_line(&mut self) -> Result<i32, SystemError> {
-        // todo: 支持在两个虚拟行之间插入一个新行
-        let actual_line_sum = textui_framework().actual_line.load(Ordering::SeqCst);
-        self.vline_operating = self.vline_operating + 1;
-        //如果已经到了最大行数，则重新从0开始
-        if !self.vline_operating.check(self.vline_sum) {
-            self.vline_operating = LineId::new(0);
+    /// 重新渲染整个屏幕
+    fn textui_refresh_screen(&mut self) -> Result<(), SystemError> {
+        let view_top =
+            LineId::new((self.top_vline.data() - self.view_offset).rem_euclid(self.vline_sum));
+        self.textui_refresh_vlines(view_top, self.rows)?;
+        return Ok(());
+    }
+
+    /// 在光标处输出一个可显示的字符，并把光标后移一列
+    fn textui_put_printable(&mut self, character: char) -> Result<(), SystemError> {
+        if self.cursor_col() >= self.chars_per_line {
+            // 上一个字符写在了行尾，写入新的字符时才换行
+            self.line_feed()?;
+            self.set_cursor(self.cursor_row(), 0);
+        }
+        let (frcolor, bkcolor) = self.current_colors();
+        let col = self.cursor_col();
+        let vline_id = self.vline_operating;
+        if let Some(vline) = self.vline_mut(vline_id) {
+            if let Some(v_char) = vline.chars.get_mut(col as usize) {
+                *v_char = TextuiCharChromatic::new(Some(character), frcolor, bkcolor);
+            }
+            vline.index = LineIndex::new(col + 1);
         }
+        return self.textui_refresh_characters(vline_id, LineIndex::new(col), 1);
+    }
 
-        if let TextuiVline::Chromatic(vline) =
-            &mut (self.vlines[<LineId as Into<usize>>::into(self.vline_operating)])
-        {
-            for i in 0..self.chars_per_line {
-                if let Some(v_char) = vline.chars.get_mut(i as usize) {
-                    v_char.c = None;
-                    v_char.frcolor = FontColor::BLACK;
-                    v_char.bkcolor = FontColor::BLACK;
+    /// 执行控制字符
+    fn textui_do_control(&mut self, character: char) -> Result<(), SystemError> {
+        let row = self.cursor_row();
+        let col = self.cursor_col().min(self.chars_per_line - 1);
+        match character {
+            // 与之前的textui保持一致，换行时同时回到行首
+            '\n' | '\x0b' | '\x0c' => {
+                self.line_feed()?;
+                self.set_cursor(self.cursor_row(), 0);
+            }
+            '\r' => self.set_cursor(row, 0),
+            // 移动到下一个制表位（每8个字符一个制表位）
+            '\t' => self.set_cursor(row, (col / 8 + 1) * 8),
+            // 字符 '\x08' 代表 ASCII 码中的退格字符。与之前的textui保持一致，退格时擦除光标前的字符
+            '\x08' => {
+                let col = self.cursor_col().min(self.chars_per_line);
+                if col > 0 {
+                    let (_, bkcolor) = self.current_colors();
+                    let vline_id = self.vline_operating;
+                    if let Some(vline) = self.vline_mut(vline_id) {
+                        if let Some(v_char) = vline.chars.get_mut(col as usize - 1) {
+                            v_char.c = Some(' ');
+                            v_char.bkcolor = bkcolor;
+                        }
+                        vline.index = LineIndex::new(col - 1);
+                    }
+                    return self.textui_refresh_characters(vline_id, LineIndex::new(col - 1), 1);
+                } else if row > 0 {
+                    // 需要向上缩一行，回到上一行的末尾
+                    self.vline_operating = self.vline_of_row(row - 1);
                 }
             }
-            vline.index = LineIndex::new(0);
+            // 响铃等其他控制字符被忽略
+            _ => {}
         }
-        // 当已经使用的虚拟行总数等于真实行总数时，说明窗口中已经显示的文本行数已经达到了窗口的最大容量。这时，如果继续在窗口中添加新的文本，就会导致文本溢出窗口而无法显示。因此，需要往下滚动屏幕来显示更多的文本。
-
-        if self.vlines_used == actual_line_sum {
-            self.top_vline = self.top_vline + 1;
+        return Ok(());
+    }
 
-            if !self.top_vline.check(self.vline_sum) {
-                self.top_vline = LineId::new(0);
+    /// 执行`ESC 字符`形式的转义序列
+    fn textui_do_esc(&mut self, character: char) -> Result<(), SystemError> {
+        match character {
+            '7' => self.saved_cursor = (self.cursor_row(), self.cursor_col()),
+            '8' => self.set_cursor(self.saved_cursor.0, self.saved_cursor.1),
+            'D' => self.line_feed()?,
+            'E' => {
+                self.line_feed()?;
+                self.set_cursor(self.cursor_row(), 0);
             }
-
-            // 刷新所有行
-            self.textui_refresh_vlines(self.top_vline, actual_line_sum)?;
-        } else {
-            //换行说明上一行已经在缓冲区中，所以已经使用的虚拟行总数+1
-            self.vlines_used += 1;
+            'M' => self.reverse_line_feed()?,
+            // 重置终端
+            'c' => {
+                self.attr = SgrAttr::default();
+                self.scroll_top = 0;
+                self.scroll_bottom = self.rows - 1;
+                self.erase_rows(0, self.rows)?;
+                self.set_cursor(0, 0);
+            }
+            _ => {}
         }
-
-        return Ok(0);
+        return Ok(());
     }
 
-    /// 真正向窗口的缓冲区上输入字符的函数(位置为window.vline_operating，window.vline_operating.index)
-    /// ## 参数
-    /// - window
-    /// - character
-
-    fn true_textui_putchar_window(
-        &mut self,
-        character: char,
-        frcolor: FontColor,
-        bkcolor: FontColor,
-    ) -> Result<(), SystemError> {
-        // 启用彩色字符
-        if self.flags.contains(WindowFlag::TEXTUI_CHROMATIC) {
-            let mut line_index = LineIndex::new(0); //操作的列号
-            if let TextuiVline::Chromatic(vline) =
-                &mut (self.vlines[<LineId as Into<usize>>::into(self.vline_operating)])
-            {
-                let index = <LineIndex as Into<usize>>::into(vline.index);
-
-                if let Some(v_char) = vline.chars.get_mut(index) {
-                    v_char.c = Some(character);
-                    v_char.frcolor = frcolor;
-                    v_char.bkcolor = bkcolor;
+    /// 执行CSI序列
+    fn textui_do_csi(&mut self, seq: &CsiSequence) -> Result<(), SystemError> {
+        let row = self.cursor_row();
+        let col = self.cursor_col().min(self.chars_per_line - 1);
+        let n = seq.param(0, 1) as i32;
+        match seq.action {
+            // 移动光标
+            'A' => self.set_cursor(row - n, col),
+            'B' | 'e' => self.set_cursor(row + n, col),
+            'C' | 'a' => self.set_cursor(row, col + n),
+            'D' => self.set_cursor(row, col - n),
+            'E' => self.set_cursor(row + n, 0),
+            'F' => self.set_cursor(row - n, 0),
+            'G' | '`' => self.set_cursor(row, n - 1),
+            'd' => self.set_cursor(n - 1, col),
+            'H' | 'f' => {
+                self.set_cursor(seq.param(0, 1) as i32 - 1, seq.param(1, 1) as i32 - 1);
+            }
+            // 擦除屏幕：0 光标到屏幕末尾，1 屏幕开头到光标，2 整个屏幕
+            'J' => match seq.param(0, 0) {
+                0 => {
+                    self.erase_cells(row, col, self.chars_per_line)?;
+                    self.erase_rows(row + 1, self.rows)?;
+                }
+                1 => {
+                    self.erase_rows(0, row)?;
+                    self.erase_cells(row, 0, col + 1)?;
                 }
-                line_index = vline.index;
-                vline.index = vline.index + 1;
+                _ => self.erase_rows(0, self.rows)?,
+            },
+            // 擦除行：0 光标到行尾，1 行首到光标，2 整行
+            'K' => match seq.param(0, 0) {
+                0 => self.erase_cells(row, col, self.chars_per_line)?,
+                1 => self.erase_cells(row, 0, col + 1)?,
+                _ => self.erase_cells(row, 0, self.chars_per_line)?,
+            },
+            // 在光标所在行插入、删除n行，只影响滚动区域
+            'L' if row >= self.scroll_top && row <= self.scroll_bottom => {
+                self.scroll_down(row, self.scroll_bottom, n)?;
             }
-
-            self.textui_refresh_characters(self.vline_operating, line_index, 1)?;
-
-            // 加入光标后，因为会识别光标，所以需超过该行最大字符数才能创建新行
-            if !line_index.check(self.chars_per_line - 1) {
-                self.textui_new_line()?;
+            'M' if row >= self.scroll_top && row <= self.scroll_bottom => {
+                self.scroll_up(row, self.scroll_bottom, n, false)?;
             }
-        } else {
-            // todo: 支持纯文本字符
-            todo!();
+            // 删除、插入、擦除光标处的n个字符
+            'P' | '@' | 'X' => {
+                let n = n.min(self.chars_per_line - col);
+                let vline_id = self.vline_operating;
+                let chars_per_line = self.chars_per_line as usize;
+                let (c, n_usize) = (col as usize, n as usize);
+                if let Some(vline) = self.vline_mut(vline_id) {
+                    match seq.action {
+                        'P' => vline.chars.copy_within(c + n_usize..chars_per_line, c),
+                        '@' => vline
+                            .chars
+                            .copy_within(c..chars_per_line - n_usize, c + n_usize),
+                        _ => {}
+                    }
+                }
+                match seq.action {
+                    'P' => self.clear_cells(row, self.chars_per_line - n, self.chars_per_line),
+                    _ => self.clear_cells(row, col, col + n),
+                }
+                self.textui_refresh_characters(
+                    vline_id,
+                    LineIndex::new(col),
+                    self.chars_per_line - col,
+                )?;
+            }
+            // 滚动区域整体滚动n行
+            'S' => self.scroll_up(self.scroll_top, self.scroll_bottom, n, true)?,
+            'T' => self.scroll_down(self.scroll_top, self.scroll_bottom, n)?,
+            // 设置字符属性
+            'm' => self.attr.apply(seq),
+            // 设置滚动区域，光标回到左上角
+            'r' if !seq.private => {
+                let top = seq.param(0, 1) as i32 - 1;
+                let bottom = seq.param(1, self.rows as u16) as i32 - 1;
+                if top < bottom && bottom < self.rows {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.set_cursor(0, 0);
+                }
+            }
+            's' => self.saved_cursor = (row, col),
+            'u' => self.set_cursor(self.saved_cursor.0, self.saved_cursor.1),
+            // 终端模式(h/l)、状态查询(n)等序列不支持，与Linux的虚拟终端一样不支持备用屏幕
+            _ => {}
         }
         return Ok(());
     }
-    /// 根据输入的一个字符在窗口上输出
+
+    /// 根据输入的一个字符在窗口上输出，字符先经过转义序列的解析
     /// ## 参数
 
     /// - window 窗口
     /// - character 字符
-    /// - FRcolor 前景色（RGB）
-    /// - BKcolor 背景色（RGB）
+    /// - FRcolor 前景色（RGB），转义序列没有设置颜色时使用
+    /// - BKcolor 背景色（RGB），转义序列没有设置颜色时使用
 
     fn textui_putchar_window(
         &mut self,
@@ -733,122 +1043,44 @@ impl TextuiWindow {
         bkcolor: FontColor,
         is_enable_window: bool,
     ) -> Result<(), SystemError> {
-        let actual_line_sum = textui_framework().actual_line.load(Ordering::SeqCst);
-
         //字符'\0'代表ASCII码表中的空字符,表示字符串的结尾
         if unlikely(character == '\0') {
             return Ok(());
         }
 
-        if unlikely(character == '\r') {
-            return Ok(());
-        }
-
         // 暂不支持纯文本窗口
         if !self.flags.contains(WindowFlag::TEXTUI_CHROMATIC) {
             return Ok(());
         }
 
-        //进行换行操作
-        if character == '\n' {
-            // 换行时还需要输出\r
-            send_to_default_serial8250_port(&[b'\r']);
-            if is_enable_window == true {
-                self.textui_new_line()?;
-            }
-            return Ok(());
-        }
-        // 输出制表符
-        else if character == '\t' {
-            if is_enable_window == true {
-                if let TextuiVline::Chromatic(vline) =
-                    &self.vlines[<LineId as Into<usize>>::into(self.vline_operating)]
-                {
-                    //打印的空格数（注意将每行分成一个个表格，每个表格为8个字符）
-                    let mut space_to_print = 8 - <LineIndex as Into<usize>>::into(vline.index) % 8;
-                    while space_to_print > 0 {
-                        self.true_textui_putchar_window(' ', frcolor, bkcolor)?;
-                        space_to_print -= 1;
-                    }
-                }
+        // 只有显示在屏幕上的窗口的输出才同时发送到串口，转义序列原样发送，由串口另一端的终端解释
+        if self.flags.contains(WindowFlag::TEXTUI_VISIBLE) {
+            match character {
+                // 换行时还需要输出\r
+                '\n' => send_to_default_serial8250_port(&[b'\r']),
+                '\r' | '\t' | '\x08' => {}
+                _ => send_to_default_serial8250_port(&[character as u8]),
             }
         }
-        // 字符 '\x08' 代表 ASCII 码中的退格字符。它在输出中的作用是将光标向左移动一个位置，并在该位置上输出后续的字符，从而实现字符的删除或替换。
-        else if character == '\x08' {
-            if is_enable_window == true {
-                let mut tmp = LineIndex(0);
-                if let TextuiVline::Chromatic(vline) =
-                    &mut self.vlines[<LineId as Into<usize>>::into(self.vline_operating)]
-                {
-                    vline.index = vline.index - 1;
-                    tmp = vline.index;
-                }
-                if <LineIndex as Into<i32>>::into(tmp) >= 0 {
-                    if let TextuiVline::Chromatic(vline) =
-                        &mut self.vlines[<LineId as Into<usize>>::into(self.vline_operating)]
-                    {
-                        if let Some(v_char) =
-                            vline.chars.get_mut(<LineIndex as Into<usize>>::into(tmp))
-                        {
-                            v_char.c = Some(' ');
 
-                            v_char.bkcolor = bkcolor;
-                        }
-                    }
-                    return self.textui_refresh_characters(self.vline_operating, tmp, 1);
-                }
-                // 需要向上缩一行
-                if <LineIndex as Into<i32>>::into(tmp) < 0 {
-                    // 当前行为空,需要重新刷新
-                    if let TextuiVline::Chromatic(vline) =
-                        &mut self.vlines[<LineId as Into<usize>>::into(self.vline_operating)]
-                    {
-                        vline.index = LineIndex::new(0);
-                        for i in 0..self.chars_per_line {
-                            if let Some(v_char) = vline.chars.get_mut(i as usize) {
-                                v_char.c = None;
-                                v_char.frcolor = FontColor::BLACK;
-                                v_char.bkcolor = FontColor::BLACK;
-                            }
-                        }
-                    }
-                    // 上缩一行
-                    self.vline_operating = self.vline_operating - 1;
-                    if self.vline_operating.data() < 0 {
-                        self.vline_operating = LineId(self.vline_sum - 1);
-                    }
-
-                    // 考虑是否向上滚动（在top_vline上退格）
-                    if self.vlines_used > actual_line_sum {
-                        self.top_vline = self.top_vline - 1;
-                        if <LineId as Into<i32>>::into(self.top_vline) < 0 {
-                            self.top_vline = LineId(self.vline_sum - 1);
-                        }
-                    }
-                    //因为上缩一行所以显示在屏幕中的虚拟行少一
-                    self.vlines_used -= 1;
-                    self.textui_refresh_vlines(self.top_vline, actual_line_sum)?;
-                }
-            }
-        } else {
-            // 输出其他字符
-
-            send_to_default_serial8250_port(&[character as u8]);
-
-            if is_enable_window == true {
-                if let TextuiVline::Chromatic(vline) =
-                    &self.vlines[<LineId as Into<usize>>::into(self.vline_operating)]
-                {
-                    if !vline.index.check(self.chars_per_line) {
-                        self.textui_new_line()?;
-                    }
+        if is_enable_window == false {
+            return Ok(());
+        }
 
-                    return self.true_textui_putchar_window(character, frcolor, bkcolor);
-                }
-            }
+        self.default_color = (frcolor, bkcolor);
+        // 有新的输出时，回到最新的内容
+        if self.view_offset != 0 {
+            self.view_offset = 0;
+            self.textui_refresh_screen()?;
         }
 
-        return Ok(());
+        match self.ansi.feed(character) {
+            Some(AnsiAction::Print(c)) => self.textui_put_printable(c),
+            Some(AnsiAction::Control(c)) => self.textui_do_control(c),
+            Some(AnsiAction::Esc(c)) => self.textui_do_esc(c),
+            Some(AnsiAction::Csi(seq)) => self.textui_do_csi(&seq),
+            None => Ok(()),
+        }
     }
 }
 impl Default for TextuiWindow {
@@ -857,11 +1089,19 @@ impl Default for TextuiWindow {
             id: WindowId(0),
             flags: WindowFlag::TEXTUI_CHROMATIC,
             vline_sum: 0,
-            vlines_used: 1,
+            vlines_used: 0,
             top_vline: LineId::new(0),
             vlines: Vec::new(),
             vline_operating: LineId::new(0),
             chars_per_line: 0,
+            rows: 0,
+            scroll_top: 0,
+            scroll_bottom: 0,
+            view_offset: 0,
+            saved_cursor: (0, 0),
+            ansi: AnsiParser::new(),
+            attr: SgrAttr::default(),
+            default_color: (FontColor::WHITE, FontColor::BLACK),
         }
     }
 }
@@ -871,7 +1111,7 @@ pub struct TextUiFramework {
     metadata: RwLock<ScmUiFrameworkMetadata>,
     window_list: Arc<SpinLock<LinkedList<Arc<SpinLock<TextuiWindow>>>>>,
     actual_line: AtomicI32, // 真实行的数量（textui的帧缓冲区能容纳的内容的行数）
-    current_window: Arc<SpinLock<TextuiWindow>>, // 当前的主窗口
+    current_window: SpinLock<Arc<SpinLock<TextuiWindow>>>, // 当前的主窗口(显示在屏幕上的窗口)
     default_window: Arc<SpinLock<TextuiWindow>>, // 默认print到的窗口
 }
 
@@ -888,11 +1128,54 @@ impl TextUiFramework {
             metadata: RwLock::new(metadata),
             window_list,
             actual_line,
-            current_window,
+            current_window: SpinLock::new(current_window),
             default_window,
         };
         return inner;
     }
+
+    /// 获取当前显示在屏幕上的窗口
+    pub fn current_window(&self) -> Arc<SpinLock<TextuiWindow>> {
+        return self.current_window.lock_irqsave().clone();
+    }
+
+    /// 创建一个新的窗口，大小与屏幕一致。新窗口不可见，需要通过[`TextUiFramework::switch_window`]显示
+    pub fn new_window(&self) -> Arc<SpinLock<TextuiWindow>> {
+        let buf_info = self.metadata.read().buf_info();
+        let vlines_num = (buf_info.height() / TEXTUI_CHAR_HEIGHT) as i32;
+        let chars_num = (buf_info.width() / TEXTUI_CHAR_WIDTH) as i32;
+        let window = Arc::new(SpinLock::new(TextuiWindow::new(
+            WindowFlag::TEXTUI_CHROMATIC,
+            vlines_num,
+            chars_num,
+        )));
+        self.window_list.lock_irqsave().push_back(window.clone());
+        return window;
+    }
+
+    /// 把指定的窗口切换到屏幕上显示，并重新渲染整个窗口
+    ///
+    /// ## 参数
+    ///
+    /// - `window` 要显示的窗口，需要是由[`TextUiFramework::new_window`]创建的窗口
+    pub fn switch_window(&self, window: &Arc<SpinLock<TextuiWindow>>) -> Result<(), SystemError> {
+        let mut current = self.current_window.lock_irqsave();
+        if Arc::ptr_eq(&current, window) {
+            return Ok(());
+        }
+        current
+            .lock_irqsave()
+            .flags
+            .remove(WindowFlag::TEXTUI_VISIBLE);
+
+        let mut guard = window.lock_irqsave();
+        guard.flags.insert(WindowFlag::TEXTUI_VISIBLE);
+        guard.textui_refresh_screen()?;
+        drop(guard);
+
+        *current = window.clone();
+        return Ok(());
+    }
 }
 
 impl ScmUiFramework for TextUiFramework {
@@ -983,8 +1266,8 @@ pub fn textui_putchar(
 ) -> Result<(), SystemError> {
     if unsafe { TEXTUI_IS_INIT } {
         return textui_framework()
-            .current_window
-            .lock()
+            .current_window()
+            .lock_irqsave()
             .textui_putchar_window(
                 character,
                 fr_color,
@@ -1002,6 +1285,28 @@ pub fn textui_putchar(
     }
 }
 
+/// 在指定的窗口上输出一个字符，窗口不可见时只更新窗口的内容
+///
+/// ## 参数
+///
+/// - `window` 窗口
+/// - `character` 字符
+/// - `fr_color` 前景色（RGB）
+/// - `bk_color` 背景色（RGB）
+pub fn textui_putchar_to(
+    window: &Arc<SpinLock<TextuiWindow>>,
+    character: char,
+    fr_color: FontColor,
+    bk_color: FontColor,
+) -> Result<(), SystemError> {
+    return window.lock_irqsave().textui_putchar_window(
+        character,
+        fr_color,
+        bk_color,
+        ENABLE_PUT_TO_WINDOW.load(Ordering::SeqCst),
+    );
+}
+
 /// 向默认窗口输出一个字符串
 pub fn textui_putstr(
     string: &str,
@@ -1010,13 +1315,13 @@ pub fn textui_putstr(
 ) -> Result<(), SystemError> {
     let window = if unsafe { TEXTUI_IS_INIT } {
         let fw = textui_framework();
-        let w = fw.current_window.clone();
+        let w = fw.current_window();
         Some(w)
     } else {
         None
     };
 
-    let mut guard = window.as_ref().map(|w| w.lock());
+    let mut guard = window.as_ref().map(|w| w.lock_irqsave());
 
     for character in string.chars() {
         if unsafe { TEXTUI_IS_INIT } {
@@ -0,0 +1,307 @@
+//! 内核日志缓冲区
+//!
+//! 内核打印的每一行日志都作为一条记录保存在固定大小的环形缓冲区中，记录包含序号、日志级别和时间戳。
+//! 缓冲区满时，最早的记录会被覆盖。用户程序可以通过syslog系统调用或者/dev/kmsg读取日志。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/printk/printk.c
+
+use core::fmt::{self, Write};
+
+use alloc::{format, string::String};
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    time::hrtimer::ktime_get,
+};
+
+/// 缓冲区中记录的数量
+pub const LOG_BUF_RECORDS: usize = 512;
+/// 一条记录中消息的最大长度，超出的部分会被截断
+pub const LOG_LINE_MAX: usize = 256;
+
+/// 日志级别，数值越小越重要
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/kern_levels.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    EMERG = 0,
+    ALERT = 1,
+    /// kBUG!使用的级别
+    CRIT = 2,
+    ERR = 3,
+    WARNING = 4,
+    NOTICE = 5,
+    INFO = 6,
+    DEBUG = 7,
+}
+
+impl LogLevel {
+    /// 没有指定级别的消息(print!以及C代码中的printk)使用的级别
+    pub const DEFAULT: LogLevel = LogLevel::WARNING;
+
+    pub fn from_u8(level: u8) -> Option<Self> {
+        match level {
+            0 => Some(LogLevel::EMERG),
+            1 => Some(LogLevel::ALERT),
+            2 => Some(LogLevel::CRIT),
+            3 => Some(LogLevel::ERR),
+            4 => Some(LogLevel::WARNING),
+            5 => Some(LogLevel::NOTICE),
+            6 => Some(LogLevel::INFO),
+            7 => Some(LogLevel::DEBUG),
+            _ => None,
+        }
+    }
+}
+
+/// 一条日志记录
+#[derive(Clone, Copy)]
+pub struct LogRecord {
+    seq: u64,
+    /// 自启动以来的纳秒数
+    ts_ns: u64,
+    level: LogLevel,
+    /// 消息是否已经以换行结束。没有结束的记录可以继续追加内容，并且不会被读者看到
+    complete: bool,
+    len: usize,
+    text: [u8; LOG_LINE_MAX],
+}
+
+impl LogRecord {
+    const EMPTY: LogRecord = LogRecord {
+        seq: 0,
+        ts_ns: 0,
+        level: LogLevel::DEFAULT,
+        complete: true,
+        len: 0,
+        text: [0; LOG_LINE_MAX],
+    };
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    /// 消息的内容，不包含结尾的换行符
+    pub fn text(&self) -> &str {
+        // 截断消息时可能会截断一个多字节的字符，这里只取合法的部分
+        match core::str::from_utf8(&self.text[..self.len]) {
+            Ok(s) => s,
+            Err(e) => unsafe { core::str::from_utf8_unchecked(&self.text[..e.valid_up_to()]) },
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        let n = core::cmp::min(s.len(), LOG_LINE_MAX - self.len);
+        self.text[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+    }
+
+    /// 按照syslog的格式输出记录：`<level>[seconds.micros] message`
+    pub fn format_syslog(&self) -> String {
+        let us = self.ts_ns / 1000;
+        return format!(
+            "<{}>[{:5}.{:06}] {}\n",
+            self.level as u8,
+            us / 1000000,
+            us % 1000000,
+            self.text()
+        );
+    }
+
+    /// 按照/dev/kmsg的格式输出记录：`level,seq,timestamp_us,-;message`
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/Documentation/ABI/testing/dev-kmsg
+    pub fn format_kmsg(&self) -> String {
+        return format!(
+            "{},{},{},-;{}\n",
+            self.level as u8,
+            self.seq,
+            self.ts_ns / 1000,
+            self.text()
+        );
+    }
+}
+
+/// 环形的日志缓冲区。序号为seq的记录保存在`records[seq % LOG_BUF_RECORDS]`
+pub struct LogBuf {
+    records: [LogRecord; LOG_BUF_RECORDS],
+    /// 缓冲区中最早的记录的序号
+    first_seq: u64,
+    /// 下一条记录的序号
+    next_seq: u64,
+    /// syslog(SYSLOG_ACTION_READ)下一次读取的记录
+    syslog_seq: u64,
+    /// syslog(SYSLOG_ACTION_CLEAR)之后的第一条记录
+    clear_seq: u64,
+}
+
+impl LogBuf {
+    const fn new() -> Self {
+        Self {
+            records: [LogRecord::EMPTY; LOG_BUF_RECORDS],
+            first_seq: 0,
+            next_seq: 0,
+            syslog_seq: 0,
+            clear_seq: 0,
+        }
+    }
+
+    /// 最后一条记录还没有结束时，返回它
+    fn pending_mut(&mut self) -> Option<&mut LogRecord> {
+        if self.next_seq == self.first_seq {
+            return None;
+        }
+        let r = &mut self.records[((self.next_seq - 1) as usize) % LOG_BUF_RECORDS];
+        return (!r.complete).then_some(r);
+    }
+
+    fn new_record(&mut self, level: LogLevel) -> &mut LogRecord {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if self.next_seq - self.first_seq > LOG_BUF_RECORDS as u64 {
+            self.first_seq = self.next_seq - LOG_BUF_RECORDS as u64;
+        }
+
+        let r = &mut self.records[(seq as usize) % LOG_BUF_RECORDS];
+        *r = LogRecord::EMPTY;
+        r.seq = seq;
+        r.ts_ns = ktime_get();
+        r.level = level;
+        r.complete = false;
+        return r;
+    }
+
+    /// 追加一段日志
+    ///
+    /// ## 参数
+    ///
+    /// - `level`：新的记录使用的日志级别
+    /// - `new_record`：是否开始一条新的记录。为false时，内容被追加到还没有结束的最后一条记录中
+    /// - `text`：日志内容，每遇到一个换行就结束一条记录
+    ///
+    /// ## 返回值
+    ///
+    /// 是否有记录结束，也就是是否有新的记录可以被读取
+    fn append(&mut self, level: LogLevel, new_record: bool, text: &str) -> bool {
+        if new_record {
+            if let Some(r) = self.pending_mut() {
+                r.complete = true;
+            }
+        }
+
+        let mut completed = false;
+        for line in text.split_inclusive('\n') {
+            let (line, end) = match line.strip_suffix('\n') {
+                Some(l) => (l, true),
+                None => (line, false),
+            };
+            let r = match self.pending_mut() {
+                Some(r) => r,
+                None => self.new_record(level),
+            };
+            r.push(line);
+            if end {
+                r.complete = true;
+                completed = true;
+            }
+        }
+        return completed;
+    }
+
+    pub fn first_seq(&self) -> u64 {
+        self.first_seq
+    }
+
+    /// 可以被读取的记录的结束序号（不包含）
+    pub fn end_seq(&self) -> u64 {
+        if self.next_seq > self.first_seq
+            && !self.records[((self.next_seq - 1) as usize) % LOG_BUF_RECORDS].complete
+        {
+            return self.next_seq - 1;
+        }
+        return self.next_seq;
+    }
+
+    /// 获取指定序号的记录，记录已经被覆盖或者还不能被读取时返回None
+    pub fn record(&self, seq: u64) -> Option<&LogRecord> {
+        if seq < self.first_seq || seq >= self.end_seq() {
+            return None;
+        }
+        return Some(&self.records[(seq as usize) % LOG_BUF_RECORDS]);
+    }
+
+    pub fn syslog_seq(&self) -> u64 {
+        core::cmp::max(self.syslog_seq, self.first_seq)
+    }
+
+    pub fn set_syslog_seq(&mut self, seq: u64) {
+        self.syslog_seq = seq;
+    }
+
+    pub fn clear_seq(&self) -> u64 {
+        core::cmp::max(self.clear_seq, self.first_seq)
+    }
+
+    /// 清除缓冲区中已有的记录。被清除的记录不会再被SYSLOG_ACTION_READ_ALL读到
+    pub fn clear(&mut self) {
+        self.clear_seq = self.end_seq();
+    }
+}
+
+/// 内核日志缓冲区。打印日志时可能处于中断上下文，加锁时必须关中断
+pub static LOG_BUF: SpinLock<LogBuf> = SpinLock::new(LogBuf::new());
+/// 等待新日志的进程
+pub static LOG_WAIT: WaitQueue = WaitQueue::INIT;
+
+/// 获取日志缓冲区锁时最多尝试的次数。
+/// 持有锁的代码不会打印日志，但是发生panic时可能无法释放锁，此时放弃记录，以免死锁
+const LOG_BUF_LOCK_RETRIES: usize = 1 << 20;
+
+/// 把日志写入缓冲区，并唤醒等待新日志的进程
+///
+/// 参数与[`LogBuf::append`]相同
+pub fn log_store(level: LogLevel, new_record: bool, text: &str) {
+    let mut guard = None;
+    for _ in 0..LOG_BUF_LOCK_RETRIES {
+        if let Ok(g) = LOG_BUF.try_lock_irqsave() {
+            guard = Some(g);
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    let mut guard = match guard {
+        Some(g) => g,
+        None => return,
+    };
+    let completed = guard.append(level, new_record, text);
+    drop(guard);
+
+    if completed {
+        LOG_WAIT.wakeup_all(None);
+    }
+}
+
+/// 把格式化的日志写入缓冲区
+pub fn log_store_fmt(level: LogLevel, args: fmt::Arguments) {
+    let mut writer = LogRecordWriter { level, first: true };
+    writer.write_fmt(args).ok();
+}
+
+/// 把格式化的输出逐段写入缓冲区，第一段开始一条新的记录，之后的内容都追加到这条记录之后
+struct LogRecordWriter {
+    level: LogLevel,
+    first: bool,
+}
+
+impl Write for LogRecordWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        log_store(self.level, self.first, s);
+        self.first = false;
+        return Ok(());
+    }
+}
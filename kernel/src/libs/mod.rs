@@ -1,6 +1,8 @@
 pub mod align;
 pub mod atomic;
+pub mod byte_cursor;
 pub mod casting;
+pub mod dynamic_debug;
 pub mod elf;
 pub mod ffi_convert;
 #[macro_use]
@@ -8,17 +10,19 @@ pub mod int_like;
 pub mod keyboard_parser;
 pub mod lazy_init;
 pub mod lib_ui;
+pub mod log_buf;
 pub mod mutex;
 pub mod notifier;
+pub mod on_disk;
 pub mod once;
 #[macro_use]
 pub mod printk;
+pub mod random;
 pub mod rbtree;
 #[macro_use]
 pub mod rwlock;
 pub mod semaphore;
 pub mod spinlock;
-pub mod vec_cursor;
 #[macro_use]
 pub mod volatile;
 pub mod wait_queue;
@@ -0,0 +1,233 @@
+//! 声明磁盘上的数据结构
+//!
+//! 使用[`on_disk_struct!`](crate::on_disk_struct)声明结构体时，需要给出它在磁盘上的字节序。
+//! 字段按照声明的顺序紧密排列，中间没有任何填充，宏会为结构体实现[`OnDisk`]，生成解析与序列化的代码，
+//! 不需要再手动地逐个字段读写：
+//!
+//! ```ignore
+//! on_disk_struct! {
+//!     endian: Little;
+//!     /// MBR分区表项
+//!     #[derive(Debug, Clone, Copy, Default)]
+//!     pub struct MbrDiskPartitionTableEntry {
+//!         pub flags: u8,
+//!         ...
+//!         pub total_sectors: u32,
+//!     }
+//! }
+//!
+//! let entry = MbrDiskPartitionTableEntry::from_bytes(&buf[446..])?;
+//! ```
+//!
+//! 字段的类型可以是整数、元素可以序列化的数组，或者另一个用本宏声明的结构体(使用它自己的字节序)。
+//! 保留的区域可以声明为`[u8; N]`。字段必须实现`Copy`。
+
+use alloc::vec::Vec;
+
+use crate::{
+    libs::byte_cursor::{ByteReader, ByteWriter},
+    syscall::SystemError,
+};
+
+/// 字节序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// 可以作为磁盘上的结构体的字段的类型
+pub trait OnDiskField: Sized {
+    /// 在磁盘上占用的字节数
+    const SIZE: usize;
+
+    /// 从reader的当前位置解析
+    fn decode_field(reader: &mut ByteReader, endian: Endian) -> Result<Self, SystemError>;
+
+    /// 写入到writer的当前位置
+    fn encode_field(&self, writer: &mut ByteWriter, endian: Endian) -> Result<(), SystemError>;
+}
+
+/// 磁盘上的结构体，由[`on_disk_struct!`](crate::on_disk_struct)实现
+pub trait OnDisk: Sized {
+    /// 在磁盘上占用的字节数
+    const SIZE: usize;
+    /// 整数字段的字节序
+    const ENDIAN: Endian;
+
+    /// 从reader的当前位置解析
+    fn decode(reader: &mut ByteReader) -> Result<Self, SystemError>;
+
+    /// 写入到writer的当前位置
+    fn encode(&self, writer: &mut ByteWriter) -> Result<(), SystemError>;
+
+    /// 从buf的开头解析
+    ///
+    /// ## 错误
+    ///
+    /// - `E2BIG`: buf的长度小于[`OnDisk::SIZE`]
+    fn from_bytes(buf: &[u8]) -> Result<Self, SystemError> {
+        return Self::decode(&mut ByteReader::new(buf));
+    }
+
+    /// 写入到buf的开头，buf中其余的数据保持不变
+    ///
+    /// ## 错误
+    ///
+    /// - `E2BIG`: buf的长度小于[`OnDisk::SIZE`]
+    fn to_bytes(&self, buf: &mut [u8]) -> Result<(), SystemError> {
+        return self.encode(&mut ByteWriter::new(buf));
+    }
+
+    /// 序列化为长度为[`OnDisk::SIZE`]的字节数组
+    fn to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; Self::SIZE];
+        self.to_bytes(&mut buf)
+            .expect("on-disk struct is larger than its SIZE");
+        return buf;
+    }
+}
+
+impl OnDiskField for u8 {
+    const SIZE: usize = 1;
+
+    fn decode_field(reader: &mut ByteReader, _endian: Endian) -> Result<Self, SystemError> {
+        return reader.read_u8();
+    }
+
+    fn encode_field(&self, writer: &mut ByteWriter, _endian: Endian) -> Result<(), SystemError> {
+        writer.write_u8(*self)?;
+        return Ok(());
+    }
+}
+
+macro_rules! impl_on_disk_field_for_int {
+    ($($ty:ty: $read_le:ident, $read_be:ident, $write_le:ident, $write_be:ident;)*) => {
+        $(
+            impl OnDiskField for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+
+                fn decode_field(reader: &mut ByteReader, endian: Endian) -> Result<Self, SystemError> {
+                    match endian {
+                        Endian::Little => return reader.$read_le(),
+                        Endian::Big => return reader.$read_be(),
+                    }
+                }
+
+                fn encode_field(&self, writer: &mut ByteWriter, endian: Endian) -> Result<(), SystemError> {
+                    match endian {
+                        Endian::Little => writer.$write_le(*self)?,
+                        Endian::Big => writer.$write_be(*self)?,
+                    };
+                    return Ok(());
+                }
+            }
+        )*
+    };
+}
+
+impl_on_disk_field_for_int! {
+    u16: read_u16, read_u16_be, write_u16, write_u16_be;
+    u32: read_u32, read_u32_be, write_u32, write_u32_be;
+    u64: read_u64, read_u64_be, write_u64, write_u64_be;
+    i16: read_i16, read_i16_be, write_i16, write_i16_be;
+    i32: read_i32, read_i32_be, write_i32, write_i32_be;
+    i64: read_i64, read_i64_be, write_i64, write_i64_be;
+}
+
+impl<T: OnDiskField + Copy + Default, const N: usize> OnDiskField for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+
+    fn decode_field(reader: &mut ByteReader, endian: Endian) -> Result<Self, SystemError> {
+        let mut result = [T::default(); N];
+        for item in result.iter_mut() {
+            *item = T::decode_field(reader, endian)?;
+        }
+        return Ok(result);
+    }
+
+    fn encode_field(&self, writer: &mut ByteWriter, endian: Endian) -> Result<(), SystemError> {
+        for item in self.iter() {
+            item.encode_field(writer, endian)?;
+        }
+        return Ok(());
+    }
+}
+
+/// 声明一个磁盘上的结构体，并为它实现[`OnDisk`]
+///
+/// 用法参见[`crate::libs::on_disk`]
+#[macro_export]
+macro_rules! on_disk_struct {
+    (
+        endian: $endian:ident;
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::libs::on_disk::OnDisk for $name {
+            const SIZE: usize = 0 $(+ <$ty as $crate::libs::on_disk::OnDiskField>::SIZE)*;
+            const ENDIAN: $crate::libs::on_disk::Endian = $crate::libs::on_disk::Endian::$endian;
+
+            fn decode(
+                reader: &mut $crate::libs::byte_cursor::ByteReader,
+            ) -> Result<Self, $crate::syscall::SystemError> {
+                // 结构体表达式中的字段按照书写的顺序求值
+                return Ok(Self {
+                    $(
+                        $field: <$ty as $crate::libs::on_disk::OnDiskField>::decode_field(
+                            reader,
+                            <Self as $crate::libs::on_disk::OnDisk>::ENDIAN,
+                        )?,
+                    )*
+                });
+            }
+
+            fn encode(
+                &self,
+                writer: &mut $crate::libs::byte_cursor::ByteWriter,
+            ) -> Result<(), $crate::syscall::SystemError> {
+                $(
+                    // 先复制字段的值，避免对未对齐的字段取引用
+                    <$ty as $crate::libs::on_disk::OnDiskField>::encode_field(
+                        &{ self.$field },
+                        writer,
+                        <Self as $crate::libs::on_disk::OnDisk>::ENDIAN,
+                    )?;
+                )*
+                return Ok(());
+            }
+        }
+
+        impl $crate::libs::on_disk::OnDiskField for $name {
+            const SIZE: usize = <Self as $crate::libs::on_disk::OnDisk>::SIZE;
+
+            fn decode_field(
+                reader: &mut $crate::libs::byte_cursor::ByteReader,
+                _endian: $crate::libs::on_disk::Endian,
+            ) -> Result<Self, $crate::syscall::SystemError> {
+                return <Self as $crate::libs::on_disk::OnDisk>::decode(reader);
+            }
+
+            fn encode_field(
+                &self,
+                writer: &mut $crate::libs::byte_cursor::ByteWriter,
+                _endian: $crate::libs::on_disk::Endian,
+            ) -> Result<(), $crate::syscall::SystemError> {
+                return <Self as $crate::libs::on_disk::OnDisk>::encode(self, writer);
+            }
+        }
+    };
+}
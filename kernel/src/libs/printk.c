@@ -12,6 +12,9 @@
 #include <common/string.h>
 
 static spinlock_t __printk_lock = {1};
+
+// 把输出的内容记录到内核日志缓冲区（由rust实现）
+extern void rs_printk_log_store(const char *buf, size_t len);
 /**
  * @brief 将数字按照指定的要求转换成对应的字符串（2~36进制）
  *
@@ -603,6 +606,8 @@ int printk_color(unsigned int FRcolor, unsigned int BKcolor, const char *fmt, ..
     int len = vsprintf(buf, fmt, args);
 
     va_end(args);
+    if (len > 0)
+        rs_printk_log_store(buf, len);
     unsigned char current;
 
     int i; // 总共输出的字符数
@@ -1,6 +1,24 @@
-use core::fmt::{self, Write};
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicU8, Ordering},
+};
 
-use super::lib_ui::textui::{textui_putstr, FontColor};
+use alloc::{string::String, vec::Vec};
+use num_traits::FromPrimitive;
+
+use crate::{
+    export_symbol, kernel_param,
+    process::ProcessManager,
+    syscall::{user_access::UserBufferWriter, Syscall, SystemError},
+};
+
+use super::{
+    lib_ui::textui::{textui_putstr, FontColor},
+    log_buf::{
+        log_store, log_store_fmt, LogLevel, LogRecord, LOG_BUF, LOG_BUF_RECORDS, LOG_LINE_MAX,
+        LOG_WAIT,
+    },
+};
 
 #[macro_export]
 macro_rules! print {
@@ -27,42 +45,80 @@ macro_rules! printk_color {
     };
 }
 
+/// kdebug!和kinfo!的调用点可以通过动态调试在运行时启用或禁用，参见[`super::dynamic_debug`]
 #[macro_export]
 macro_rules! kdebug {
-    ($($arg:tt)*) => {
-        $crate::libs::printk::PrintkWriter.__write_fmt(format_args!("[ DEBUG ] ({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)))
-
-    }
+    ($($arg:tt)*) => {{
+        #[link_section = "__dyndbg"]
+        #[used]
+        static __DYNDBG_SITE: $crate::libs::dynamic_debug::DynDebugSite = $crate::libs::dynamic_debug::DynDebugSite::new(module_path!(), file!(), line!(), $crate::libs::log_buf::LogLevel::DEBUG);
+        if __DYNDBG_SITE.enabled() {
+            $crate::libs::printk::PrintkWriter.__log($crate::libs::log_buf::LogLevel::DEBUG, format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)))
+        }
+    }}
 }
 
 #[macro_export]
 macro_rules! kinfo {
-    ($($arg:tt)*) => {
-        $crate::libs::printk::PrintkWriter.__write_fmt(format_args!("[ INFO ] ({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)))
-    }
+    ($($arg:tt)*) => {{
+        #[link_section = "__dyndbg"]
+        #[used]
+        static __DYNDBG_SITE: $crate::libs::dynamic_debug::DynDebugSite = $crate::libs::dynamic_debug::DynDebugSite::new(module_path!(), file!(), line!(), $crate::libs::log_buf::LogLevel::INFO);
+        if __DYNDBG_SITE.enabled() {
+            $crate::libs::printk::PrintkWriter.__log($crate::libs::log_buf::LogLevel::INFO, format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)))
+        }
+    }}
 }
 
 #[macro_export]
 macro_rules! kwarn {
     ($($arg:tt)*) => {
-        $crate::libs::printk::PrintkWriter.__write_string_color($crate::libs::lib_ui::textui::FontColor::YELLOW, $crate::libs::lib_ui::textui::FontColor::BLACK, "[ WARN ] ");
-        $crate::libs::printk::PrintkWriter.__write_fmt(format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)));
+        $crate::libs::printk::PrintkWriter.__log($crate::libs::log_buf::LogLevel::WARNING, format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)))
     }
 }
 
 #[macro_export]
 macro_rules! kerror {
     ($($arg:tt)*) => {
-        $crate::libs::printk::PrintkWriter.__write_string_color($crate::libs::lib_ui::textui::FontColor::RED, $crate::libs::lib_ui::textui::FontColor::BLACK, "[ ERROR ] ");
-        $crate::libs::printk::PrintkWriter.__write_fmt(format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)));
+        $crate::libs::printk::PrintkWriter.__log($crate::libs::log_buf::LogLevel::ERR, format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)))
     }
 }
 
 #[macro_export]
 macro_rules! kBUG {
     ($($arg:tt)*) => {
-        $crate::libs::printk::PrintkWriter.__write_string_color($crate::libs::lib_ui::textui::FontColor::RED, $crate::libs::lib_ui::textui::FontColor::BLACK, "[ BUG ] ");
-        $crate::libs::printk::PrintkWriter.__write_fmt(format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)));
+        $crate::libs::printk::PrintkWriter.__log($crate::libs::log_buf::LogLevel::CRIT, format_args!("({}:{})\t {}\n", file!(), line!(),format_args!($($arg)*)))
+    }
+}
+
+/// 控制台日志级别的默认值：所有级别的日志都会输出到控制台
+const CONSOLE_LOGLEVEL_DEFAULT: u8 = 8;
+/// 控制台日志级别的最小值，此时只有EMERG级别的日志会输出到控制台
+pub const CONSOLE_LOGLEVEL_MIN: u8 = 1;
+
+/// 控制台日志级别，级别数值小于它的日志才会输出到控制台。
+/// 所有日志都会被记录在日志缓冲区中，不受它的影响
+static CONSOLE_LOGLEVEL: AtomicU8 = AtomicU8::new(CONSOLE_LOGLEVEL_DEFAULT);
+
+/// 获取控制台日志级别
+pub fn console_loglevel() -> u8 {
+    CONSOLE_LOGLEVEL.load(Ordering::SeqCst)
+}
+
+/// 设置控制台日志级别，返回原来的级别
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/printk/printk.c#1647
+pub fn set_console_loglevel(level: u8) -> u8 {
+    return CONSOLE_LOGLEVEL.swap(level.clamp(CONSOLE_LOGLEVEL_MIN, 8), Ordering::SeqCst);
+}
+
+kernel_param!(LOGLEVEL_PARAM: u8 = "loglevel");
+
+/// 根据启动参数`loglevel=`设置控制台日志级别，例如`loglevel=5`时只有WARNING及更严重的日志会输出到控制台
+#[no_mangle]
+pub extern "C" fn rs_printk_init() {
+    if let Some(level) = LOGLEVEL_PARAM.get() {
+        set_console_loglevel(level);
     }
 }
 
@@ -74,6 +130,27 @@ impl PrintkWriter {
         self.write_fmt(args).ok();
     }
 
+    /// 打印带有级别的日志：记录到日志缓冲区，级别足够高时再输出到控制台
+    pub fn __log(&mut self, level: LogLevel, args: fmt::Arguments) {
+        log_store_fmt(level, args);
+        if (level as u8) >= console_loglevel() {
+            return;
+        }
+
+        let (label, color) = match level {
+            LogLevel::EMERG => ("[ EMERG ] ", FontColor::RED),
+            LogLevel::ALERT => ("[ ALERT ] ", FontColor::RED),
+            LogLevel::CRIT => ("[ BUG ] ", FontColor::RED),
+            LogLevel::ERR => ("[ ERROR ] ", FontColor::RED),
+            LogLevel::WARNING => ("[ WARN ] ", FontColor::YELLOW),
+            LogLevel::NOTICE => ("[ NOTICE ] ", FontColor::WHITE),
+            LogLevel::INFO => ("[ INFO ] ", FontColor::WHITE),
+            LogLevel::DEBUG => ("[ DEBUG ] ", FontColor::WHITE),
+        };
+        self.__write_string_color(color, FontColor::BLACK, label);
+        ConsoleWriter.write_fmt(args).ok();
+    }
+
     /// 并输出白底黑字
     /// @param str: 要写入的字符
     pub fn __write_string(&mut self, s: &str) {
@@ -86,14 +163,219 @@ impl PrintkWriter {
 }
 
 /// 为Printk Writer实现core::fmt::Write, 使得能够借助Rust自带的格式化组件，格式化字符并输出
+///
+/// 输出的内容同时以默认级别追加到日志缓冲区中
 impl fmt::Write for PrintkWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
+        log_store(LogLevel::DEFAULT, false, s);
         self.__write_string(s);
         Ok(())
     }
 }
 
+/// 只输出到控制台，不记录到日志缓冲区
+struct ConsoleWriter;
+
+impl fmt::Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        textui_putstr(s, FontColor::WHITE, FontColor::BLACK).ok();
+        Ok(())
+    }
+}
+
+/// 把C代码中printk输出的内容追加到日志缓冲区。C代码的输出没有级别，总是会输出到控制台
+#[no_mangle]
+pub unsafe extern "C" fn rs_printk_log_store(buf: *const u8, len: usize) {
+    let bytes = core::slice::from_raw_parts(buf, len);
+    // 此时可能还没有初始化内存管理，不能分配内存，因此只记录合法的UTF-8部分
+    let s = match core::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]),
+    };
+    log_store(LogLevel::DEFAULT, false, s);
+}
+
+extern "C" {
+    /// C代码中的printk，可加载模块通过它输出日志
+    fn printk_color(fr_color: u32, bk_color: u32, fmt: *const u8, ...) -> i32;
+}
+export_symbol!(printk_color);
+
 #[doc(hidden)]
 pub fn __printk(args: fmt::Arguments) {
     PrintkWriter.write_fmt(args).unwrap();
 }
+
+/// syslog系统调用的命令
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/syslog.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[repr(i32)]
+pub enum SyslogAction {
+    Close = 0,
+    Open = 1,
+    /// 读取还没有被读取过的日志，没有日志时阻塞
+    Read = 2,
+    /// 读取缓冲区中最近的日志
+    ReadAll = 3,
+    /// 读取缓冲区中最近的日志，然后清空缓冲区
+    ReadClear = 4,
+    Clear = 5,
+    /// 关闭控制台输出，只保留EMERG级别的日志
+    ConsoleOff = 6,
+    /// 恢复ConsoleOff之前的控制台日志级别
+    ConsoleOn = 7,
+    /// 设置控制台日志级别
+    ConsoleLevel = 8,
+    /// 还没有被读取过的日志的字节数
+    SizeUnread = 9,
+    /// 日志缓冲区的大小
+    SizeBuffer = 10,
+}
+
+/// ConsoleOff之前的控制台日志级别，为0表示没有保存
+static SAVED_CONSOLE_LOGLEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// 复制序号在[start, end)之间的记录
+fn log_records(start: u64, end: u64) -> Vec<LogRecord> {
+    let guard = LOG_BUF.lock_irqsave();
+    let start = core::cmp::max(start, guard.first_seq());
+    let end = core::cmp::min(end, guard.end_seq());
+    return (start..end)
+        .filter_map(|seq| guard.record(seq).copied())
+        .collect();
+}
+
+/// 把内容复制到用户缓冲区
+fn copy_log_to_user(buf: *mut u8, text: &[u8]) -> Result<usize, SystemError> {
+    if text.is_empty() {
+        return Ok(0);
+    }
+    let mut writer = UserBufferWriter::new(buf, text.len(), true)?;
+    writer.copy_to_user(text, 0)?;
+    return Ok(text.len());
+}
+
+impl Syscall {
+    /// 读取内核日志以及设置控制台日志级别
+    ///
+    /// ## 参数
+    ///
+    /// - `action`：命令，见[`SyslogAction`]
+    /// - `buf`：读取日志时使用的用户缓冲区
+    /// - `len`：用户缓冲区的长度；对于ConsoleLevel命令，为新的控制台日志级别
+    ///
+    /// ## 返回值
+    ///
+    /// 读取命令返回读取的字节数，SizeUnread和SizeBuffer返回对应的大小，其余命令返回0
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/printk/printk.c#do_syslog
+    pub fn syslog(action: i32, buf: *mut u8, len: i32) -> Result<usize, SystemError> {
+        let action = SyslogAction::from_i32(action).ok_or(SystemError::EINVAL)?;
+        match action {
+            SyslogAction::Close | SyslogAction::Open => return Ok(0),
+            SyslogAction::Read => {
+                if buf.is_null() || len < 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                if len == 0 {
+                    return Ok(0);
+                }
+                loop {
+                    let guard = LOG_BUF.lock_irqsave();
+                    if guard.syslog_seq() < guard.end_seq() {
+                        break;
+                    }
+                    if ProcessManager::current_pcb()
+                        .sig_info()
+                        .has_pending_signal()
+                    {
+                        return Err(SystemError::EINTR);
+                    }
+                    LOG_WAIT.sleep_unlock_spinlock(guard);
+                }
+
+                // 读取尽可能多的完整记录，第一条记录放不下时截断它
+                let start = LOG_BUF.lock_irqsave().syslog_seq();
+                let mut text = String::new();
+                let mut next = start;
+                for r in log_records(start, u64::MAX) {
+                    let line = r.format_syslog();
+                    if text.len() + line.len() > len as usize {
+                        if text.is_empty() {
+                            let mut n = len as usize;
+                            while !line.is_char_boundary(n) {
+                                n -= 1;
+                            }
+                            text.push_str(&line[..n]);
+                            next = r.seq() + 1;
+                        }
+                        break;
+                    }
+                    text.push_str(&line);
+                    next = r.seq() + 1;
+                }
+                LOG_BUF.lock_irqsave().set_syslog_seq(next);
+                return copy_log_to_user(buf, text.as_bytes());
+            }
+            SyslogAction::ReadAll | SyslogAction::ReadClear => {
+                if buf.is_null() || len < 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let start = LOG_BUF.lock_irqsave().clear_seq();
+                let lines: Vec<String> = log_records(start, u64::MAX)
+                    .iter()
+                    .map(|r| r.format_syslog())
+                    .collect();
+                // 缓冲区放不下所有日志时，只读取最近的日志
+                let mut total = 0;
+                let mut first = lines.len();
+                while first > 0 && total + lines[first - 1].len() <= len as usize {
+                    total += lines[first - 1].len();
+                    first -= 1;
+                }
+                let text = lines[first..].concat();
+                if action == SyslogAction::ReadClear {
+                    LOG_BUF.lock_irqsave().clear();
+                }
+                return copy_log_to_user(buf, text.as_bytes());
+            }
+            SyslogAction::Clear => {
+                LOG_BUF.lock_irqsave().clear();
+                return Ok(0);
+            }
+            SyslogAction::ConsoleOff => {
+                if SAVED_CONSOLE_LOGLEVEL.load(Ordering::SeqCst) == 0 {
+                    let old = set_console_loglevel(CONSOLE_LOGLEVEL_MIN);
+                    SAVED_CONSOLE_LOGLEVEL.store(old, Ordering::SeqCst);
+                }
+                return Ok(0);
+            }
+            SyslogAction::ConsoleOn => {
+                let saved = SAVED_CONSOLE_LOGLEVEL.swap(0, Ordering::SeqCst);
+                if saved != 0 {
+                    set_console_loglevel(saved);
+                }
+                return Ok(0);
+            }
+            SyslogAction::ConsoleLevel => {
+                if !(1..=8).contains(&len) {
+                    return Err(SystemError::EINVAL);
+                }
+                set_console_loglevel(len as u8);
+                // 设置了新的级别之后，ConsoleOn不再恢复原来的级别
+                SAVED_CONSOLE_LOGLEVEL.store(0, Ordering::SeqCst);
+                return Ok(0);
+            }
+            SyslogAction::SizeUnread => {
+                let start = LOG_BUF.lock_irqsave().syslog_seq();
+                let size = log_records(start, u64::MAX)
+                    .iter()
+                    .map(|r| r.format_syslog().len())
+                    .sum();
+                return Ok(size);
+            }
+            SyslogAction::SizeBuffer => return Ok(LOG_BUF_RECORDS * LOG_LINE_MAX),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! 内核的密码学安全伪随机数生成器(CSPRNG)
+//!
+//! 生成器基于ChaCha20：每生成一个块，块的前32字节立即替换掉原来的密钥，剩下的32字节作为输出(fast key erasure)。
+//! 因此即使某一时刻的密钥泄露，也无法推算出之前输出过的随机数。
+//!
+//! 密钥由架构提供的熵源初始化，并且每生成一定数量的块之后，重新混入新的熵：
+//! 原来的密钥与新的熵一起经过SHA-256压缩，得到新的密钥。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/drivers/char/random.c
+
+use crate::{
+    arch::rand::{arch_get_random_seed, rand},
+    crypto::{
+        sha256::{Sha256, SHA256_DIGEST_SIZE},
+        Hash,
+    },
+    libs::spinlock::SpinLock,
+};
+
+/// ChaCha20的常量"expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+/// 每个块中用作输出的字节数(其余部分用于替换密钥)
+const OUTPUT_BYTES_PER_BLOCK: usize = 32;
+/// 每生成这么多个块之后，重新混入熵
+const RESEED_INTERVAL: u64 = 1024;
+
+lazy_static! {
+    static ref CRNG: SpinLock<ChaChaRng> = SpinLock::new(ChaChaRng::new());
+}
+
+#[derive(Debug)]
+struct ChaChaRng {
+    key: [u32; 8],
+    /// 已经生成的块的数量，同时作为ChaCha20的计数器
+    generation: u64,
+}
+
+impl ChaChaRng {
+    fn new() -> Self {
+        let mut rng = Self {
+            key: [0; 8],
+            generation: 0,
+        };
+        rng.reseed();
+        return rng;
+    }
+
+    /// 把架构提供的熵混入密钥
+    fn reseed(&mut self) {
+        let mut sha = Sha256::new();
+        for word in self.key.iter() {
+            sha.update(&word.to_le_bytes());
+        }
+        for _ in 0..self.key.len() {
+            let seed = arch_get_random_seed().unwrap_or(0) ^ rand() as u64;
+            sha.update(&seed.to_le_bytes());
+        }
+
+        let mut digest = [0u8; SHA256_DIGEST_SIZE];
+        sha.finalize_into(&mut digest);
+        for (word, bytes) in self.key.iter_mut().zip(digest.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        digest.fill(0);
+    }
+
+    /// 生成下一个块，并用块的前32字节替换密钥
+    fn next_block(&mut self, block: &mut [u32; 16]) {
+        chacha20_block(&self.key, self.generation, block);
+        self.key.copy_from_slice(&block[..8]);
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn fill_bytes(&mut self, buf: &mut [u8]) {
+        let mut block = [0u32; 16];
+        for chunk in buf.chunks_mut(OUTPUT_BYTES_PER_BLOCK) {
+            if self.generation % RESEED_INTERVAL == 0 {
+                self.reseed();
+            }
+            self.next_block(&mut block);
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                *byte = block[8 + i / 4].to_le_bytes()[i % 4];
+            }
+        }
+        block.fill(0);
+    }
+}
+
+#[inline(always)]
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// 计算ChaCha20的一个块(RFC 7539)，nonce固定为0
+fn chacha20_block(key: &[u32; 8], counter: u64, out: &mut [u32; 16]) {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    for i in 0..16 {
+        out[i] = x[i].wrapping_add(state[i]);
+    }
+}
+
+/// 用随机数填满缓冲区
+pub fn get_random_bytes(buf: &mut [u8]) {
+    CRNG.lock_irqsave().fill_bytes(buf);
+}
+
+/// 获取一个64位的随机数
+pub fn get_random_u64() -> u64 {
+    let mut buf = [0u8; 8];
+    get_random_bytes(&mut buf);
+    return u64::from_ne_bytes(buf);
+}
@@ -838,6 +838,9 @@ impl<K: Ord, V> IntoIterator for RBTree<K, V> {
     }
 }
 
+// 红黑树独占所有节点（与`Box`相同），因此只要键和值可以跨线程转移，整棵树就可以
+unsafe impl<K: Ord + Send, V: Send> Send for RBTree<K, V> {}
+
 impl<K: Ord, V> RBTree<K, V> {
     /// Creates an empty `RBTree`.
     pub fn new() -> RBTree<K, V> {
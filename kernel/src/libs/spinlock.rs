@@ -23,7 +23,10 @@ pub struct SpinLock<T> {
 /// SpinLock的守卫
 /// 该守卫没有构造器，并且其信息均为私有的。我们只能通过SpinLock的lock()方法获得一个守卫。
 /// 因此我们可以认为，只要能够获得一个守卫，那么数据就在自旋锁的保护之下。
+///
+/// 守卫被丢弃时就会解锁，因此不使用守卫(例如`lock();`)会产生警告。
 #[derive(Debug)]
+#[must_use = "if unused the SpinLock will immediately unlock"]
 pub struct SpinLockGuard<'a, T: 'a> {
     lock: &'a SpinLock<T>,
     data: *mut T,
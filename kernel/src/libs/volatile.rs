@@ -1,39 +1,128 @@
-macro_rules! volatile_read {
-    ($data: expr) => {
-        unsafe { core::ptr::read_volatile(core::ptr::addr_of!($data)) }
-    };
-}
+use core::ops::{BitAnd, BitOr, Not, Shl, Shr};
 
-macro_rules! volatile_write {
-    ($data: expr, $value: expr) => {
-        unsafe { core::ptr::write_volatile(core::ptr::addr_of_mut!($data), $value) }
-    };
+/// 可以被[`VolatileCell`]进行位操作的整数类型
+pub trait VolatileInt:
+    Copy
+    + Eq
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Not<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+{
+    const ZERO: Self;
+
+    fn trailing_zeros(self) -> u32;
 }
 
-/// @brief: 用于volatile设置某些bits
-/// @param val: 设置这些位
-/// @param flag: true表示设置这些位为1; false表示设置这些位为0;
-macro_rules! volatile_set_bit {
-    ($data: expr, $val: expr, $flag: expr) => {
-        volatile_write!(
-            $data,
-            match $flag {
-                true => core::ptr::read_volatile(core::ptr::addr_of!($data)) | $val,
-                false => core::ptr::read_volatile(core::ptr::addr_of!($data)) & (!$val),
+macro_rules! impl_volatile_int {
+    ($($t: ty),*) => {
+        $(
+            impl VolatileInt for $t {
+                const ZERO: Self = 0;
+
+                #[inline(always)]
+                fn trailing_zeros(self) -> u32 {
+                    <$t>::trailing_zeros(self)
+                }
             }
-        )
+        )*
     };
 }
 
-/// @param data: volatile变量
-/// @param bits: 置1的位才有效，表示写这些位
-/// @param val: 要写的值
-/// 比如: 写 x 的 2至8bit， 为 10, 可以这么写 volatile_write_bit(x, (1<<8)-(1<<2), 10<<2);    
-macro_rules! volatile_write_bit {
-    ($data: expr, $bits: expr, $val: expr) => {
-        volatile_set_bit!($data, $bits, false);
-        volatile_set_bit!($data, ($val) & ($bits), true);
-    };
+impl_volatile_int!(u8, u16, u32, u64);
+
+/// 对内存中的一个值进行volatile访问的封装
+///
+/// 与设备共享的数据结构（寄存器、命令表等），其字段的类型应当声明为`VolatileCell<T>`，
+/// 这样每次访问的宽度都由字段的类型在编译期确定，且不会被编译器优化掉。
+///
+/// 例：
+/// ```ignore
+/// #[repr(C)]
+/// struct Regs {
+///     ctrl: Mmio<u32>,
+///     status: Mmio<u32>,
+/// }
+///
+/// regs.ctrl.set_bits(1 << 3);
+/// let speed = regs.status.read_bits(0b11 << 6);
+/// ```
+#[derive(Default)]
+#[repr(transparent)]
+pub struct VolatileCell<T: Copy> {
+    value: T,
+}
+
+/// 设备的MMIO寄存器
+pub type Mmio<T> = VolatileCell<T>;
+
+#[allow(dead_code)]
+impl<T: Copy> VolatileCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    /// 读取当前的值
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        unsafe { core::ptr::read_volatile(&self.value) }
+    }
+
+    /// 写入新的值
+    #[inline(always)]
+    pub fn write(&mut self, value: T) {
+        unsafe { core::ptr::write_volatile(&mut self.value, value) }
+    }
+
+    /// 读取当前的值，经过f处理之后写回
+    #[inline(always)]
+    pub fn modify<F: FnOnce(T) -> T>(&mut self, f: F) {
+        let value = self.read();
+        self.write(f(value));
+    }
+}
+
+#[allow(dead_code)]
+impl<T: VolatileInt> VolatileCell<T> {
+    /// 把mask中为1的位设置为1
+    #[inline(always)]
+    pub fn set_bits(&mut self, mask: T) {
+        self.modify(|v| v | mask);
+    }
+
+    /// 把mask中为1的位设置为0
+    #[inline(always)]
+    pub fn clear_bits(&mut self, mask: T) {
+        self.modify(|v| v & !mask);
+    }
+
+    /// mask中为1的位是否全部为1
+    #[inline(always)]
+    pub fn test_bits(&self, mask: T) -> bool {
+        self.read() & mask == mask
+    }
+
+    /// 读取mask所表示的字段，返回值已经右移到最低位
+    ///
+    /// 例：`read_bits(0b111 << 4)` 返回第4~6位的值
+    #[inline(always)]
+    pub fn read_bits(&self, mask: T) -> T {
+        (self.read() & mask) >> mask.trailing_zeros()
+    }
+
+    /// 写入mask所表示的字段，其余的位保持不变
+    ///
+    /// ## 参数
+    ///
+    /// - `mask`：字段所占的位
+    /// - `value`：字段的值（从最低位开始，会被左移到mask的位置），超出字段宽度的部分会被丢弃
+    #[inline(always)]
+    pub fn write_bits(&mut self, mask: T, value: T) {
+        debug_assert!(mask != T::ZERO);
+        let value = (value << mask.trailing_zeros()) & mask;
+        self.modify(|v| (v & !mask) | value);
+    }
 }
 
 /// 以下代码来自于virtio-drivers 0.2.0
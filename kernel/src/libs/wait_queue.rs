@@ -225,6 +225,36 @@ impl WaitQueue {
         }
     }
 
+    /// @brief 把进程加入等待队列，但是不会让它睡眠。用于同时在多个等待队列上等待(如poll)，
+    /// 进程被唤醒后，调用者需要通过remove把它从其他等待队列中移除
+    pub fn add(&self, pcb: Arc<ProcessControlBlock>) {
+        self.0.lock_irqsave().wait_list.push_back(pcb);
+    }
+
+    /// @brief 把进程从等待队列中移除
+    ///
+    /// @return 进程是否在等待队列中
+    pub fn remove(&self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        let mut guard: SpinLockGuard<InnerWaitQueue> = self.0.lock_irqsave();
+        let len = guard.wait_list.len();
+        let wait_list = core::mem::take(&mut guard.wait_list);
+        guard.wait_list = wait_list
+            .into_iter()
+            .filter(|p| !Arc::ptr_eq(p, pcb))
+            .collect();
+        return guard.wait_list.len() != len;
+    }
+
+    /// @brief 判断进程是否在等待队列中
+    pub fn contains(&self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        return self
+            .0
+            .lock_irqsave()
+            .wait_list
+            .iter()
+            .any(|p| Arc::ptr_eq(p, pcb));
+    }
+
     /// @brief 获得当前等待队列的大小
     pub fn len(&self) -> usize {
         return self.0.lock().wait_list.len();
@@ -37,10 +37,17 @@ extern void rs_mm_init();
 extern void rs_kthread_init();
 extern void rs_init_intertrait();
 extern void rs_init_before_mem_init();
+extern void rs_dynamic_debug_init();
+extern void rs_panic_init();
+extern void rs_printk_init();
 extern int rs_setup_arch();
 extern int rs_hpet_init();
 extern int rs_hpet_enable();
 extern int rs_tsc_init();
+extern int rs_clockevent_init();
+extern int rs_cpuidle_init();
+extern int rs_cpufreq_init();
+extern void rs_cpu_idle();
 
 ul bsp_idt_size, bsp_gdt_size;
 
@@ -101,6 +108,10 @@ void system_initialize()
     rs_textui_init();
 
     rs_init_intertrait();
+    // 启动参数中的dyndbg=需要在驱动初始化之前生效
+    rs_dynamic_debug_init();
+    rs_panic_init();
+    rs_printk_init();
     // kinfo("vaddr:%#018lx", video_frame_buffer_info.vaddr);
     io_mfence();
     vfs_init();
@@ -144,6 +155,8 @@ void system_initialize()
 
     ps2_keyboard_init();
     io_mfence();
+    ps2_mouse_init();
+    io_mfence();
 
     rs_pci_init();
 
@@ -156,6 +169,9 @@ void system_initialize()
     rs_hpet_init();
     rs_hpet_enable();
     rs_tsc_init();
+    rs_clockevent_init();
+    rs_cpuidle_init();
+    rs_cpufreq_init();
 
     io_mfence();
 
@@ -168,7 +184,7 @@ void system_initialize()
     io_mfence();
     sti();
     while (1)
-        ;
+        rs_cpu_idle();
 }
 
 // 操作系统内核从这里开始执行
@@ -195,11 +211,10 @@ void Start_Kernel(void)
     // idle
     while (1)
     {
-        // 如果调用的时候，启用了中断，则hlt。否则认为是bug
+        // 如果调用的时候，启用了中断，则进入空闲状态。否则认为是bug
         if (get_rflags() & 0x200)
         {
-            // kdebug("hlt");
-            hlt();
+            rs_cpu_idle();
         }
         else
         {
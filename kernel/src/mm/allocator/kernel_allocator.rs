@@ -1,7 +1,10 @@
 use crate::{
     arch::mm::LockedFrameAllocator,
     libs::align::page_align_up,
-    mm::{MMArch, MemoryManagementArch, VirtAddr},
+    mm::{
+        kasan::{kasan_kfree, kasan_kmalloc, KASAN_REDZONE_SIZE},
+        MMArch, MemoryManagementArch, VirtAddr,
+    },
 };
 
 use core::{
@@ -22,9 +25,17 @@ pub trait LocalAlloc {
 pub struct KernelAllocator;
 
 impl KernelAllocator {
+    /// 计算分配指定的layout所需要的页数
+    ///
+    /// 开启kasan时，会在对象末尾额外预留红区
+    fn page_count(layout: Layout) -> usize {
+        let size = layout.size() + KASAN_REDZONE_SIZE;
+        // 向上取整
+        return (page_align_up(size) / MMArch::PAGE_SIZE).next_power_of_two();
+    }
+
     unsafe fn alloc_in_buddy(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        // 计算需要申请的页数，向上取整
-        let count = (page_align_up(layout.size()) / MMArch::PAGE_SIZE).next_power_of_two();
+        let count = Self::page_count(layout);
         let page_frame_count = PageFrameCount::new(count);
         let (phy_addr, allocated_frame_count) = LockedFrameAllocator
             .allocate(page_frame_count)
@@ -41,12 +52,19 @@ impl KernelAllocator {
                 allocated_frame_count.data() * MMArch::PAGE_SIZE,
             )
         };
+        kasan_kmalloc(slice.as_mut_ptr(), layout.size(), slice.len());
         return Ok(NonNull::from(slice));
     }
 
     unsafe fn free_in_buddy(&self, ptr: *mut u8, layout: Layout) {
         // 由于buddy分配的页数量是2的幂，因此释放的时候也需要按照2的幂向上取整。
-        let count = (page_align_up(layout.size()) / MMArch::PAGE_SIZE).next_power_of_two();
+        let count = Self::page_count(layout);
+        // 开启kasan时，对象会先进入隔离区，这里释放的是被挤出隔离区的对象
+        let (ptr, layout) = match kasan_kfree(ptr, layout, count * MMArch::PAGE_SIZE) {
+            Some(x) => x,
+            None => return,
+        };
+        let count = Self::page_count(layout);
         let page_frame_count = PageFrameCount::new(count);
         let phy_addr = MMArch::virt_2_phys(VirtAddr::new(ptr as usize)).unwrap();
         LockedFrameAllocator.free(phy_addr, page_frame_count);
@@ -3,3 +3,4 @@ pub mod bump;
 pub mod kernel_allocator;
 pub mod page_frame;
 pub mod slab;
+pub mod zone;
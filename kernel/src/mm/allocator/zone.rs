@@ -0,0 +1,183 @@
+//! 按照NUMA节点划分的物理内存区
+//!
+//! 每个内存区是属于同一个节点的一段连续的物理内存，由自己的伙伴分配器管理。
+//! 分配时先在首选节点的内存区中分配，不足时按照节点之间的距离，从近到远使用其他节点的内存区。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/mm/page_alloc.c#build_zonelists
+
+use crate::{
+    kdebug, kwarn,
+    mm::{
+        numa::{numa_fallback_order, numa_nr_nodes, phys_to_node, NodeId, MAX_NUMNODES},
+        MemoryManagementArch, PhysAddr, PhysMemoryArea,
+    },
+};
+
+use super::{
+    buddy::BuddyAllocator,
+    bump::BumpAllocator,
+    page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage},
+};
+
+/// 最多支持的内存区数量。分配器在内存管理初始化时位于BSP的栈上，因此不宜太大
+pub const MAX_ZONES: usize = 32;
+/// 内存区至少要有这么多页。太小的内存区大部分空间都会被伙伴分配器自身的空闲链表占用
+const ZONE_MIN_PAGES: usize = 512;
+
+/// 每个内存区的伙伴分配器所使用的物理内存区域。BumpAllocator要求区域的生命周期是'static
+static mut ZONE_AREAS: [PhysMemoryArea; MAX_ZONES] = [PhysMemoryArea {
+    base: PhysAddr::new(0),
+    size: 0,
+}; MAX_ZONES];
+
+/// 一个内存区
+pub struct MemZone<A> {
+    node: NodeId,
+    start: PhysAddr,
+    end: PhysAddr,
+    buddy: BuddyAllocator<A>,
+}
+
+impl<A> MemZone<A> {
+    #[inline(always)]
+    fn contains(&self, paddr: PhysAddr) -> bool {
+        paddr >= self.start && paddr < self.end
+    }
+}
+
+/// 按照节点划分内存区的页帧分配器
+pub struct ZonedAllocator<A> {
+    zones: [Option<MemZone<A>>; MAX_ZONES],
+    nr_zones: usize,
+    /// 每个节点分配内存时依次尝试的节点，见[`numa_fallback_order`]
+    fallback: [[NodeId; MAX_NUMNODES]; MAX_NUMNODES],
+    nr_nodes: usize,
+}
+
+impl<A: MemoryManagementArch> ZonedAllocator<A> {
+    /// 创建一个没有内存区的分配器。NUMA拓扑必须已经登记完毕
+    pub fn new() -> Self {
+        let nr_nodes = numa_nr_nodes();
+        let mut fallback = [[NodeId::new(0); MAX_NUMNODES]; MAX_NUMNODES];
+        for (node, order) in fallback.iter_mut().enumerate().take(nr_nodes) {
+            *order = numa_fallback_order(NodeId::new(node));
+        }
+        return Self {
+            zones: core::array::from_fn(|_| None),
+            nr_zones: 0,
+            fallback,
+            nr_nodes,
+        };
+    }
+
+    /// 把物理内存[start, end)作为节点`node`的一个内存区
+    ///
+    /// 内存区太小，或者内存区的数量已经达到上限时，这段内存会被丢弃
+    pub unsafe fn add_zone(&mut self, node: NodeId, start: PhysAddr, end: PhysAddr) {
+        let start = PhysAddr::new((start.data() + A::PAGE_SIZE - 1) & !(A::PAGE_SIZE - 1));
+        let end = PhysAddr::new(end.data() & !(A::PAGE_SIZE - 1));
+        if start >= end || (end.data() - start.data()) / A::PAGE_SIZE < ZONE_MIN_PAGES {
+            kdebug!("zone: ignore small range [{:?}, {:?})", start, end);
+            return;
+        }
+        if self.nr_zones >= MAX_ZONES {
+            kwarn!("zone: too many zones, ignore [{:?}, {:?})", start, end);
+            return;
+        }
+
+        let idx = self.nr_zones;
+        ZONE_AREAS[idx] = PhysMemoryArea {
+            base: start,
+            size: end.data() - start.data(),
+        };
+        let bump = BumpAllocator::<A>::new(&ZONE_AREAS[idx..idx + 1], start.data());
+        let buddy = match BuddyAllocator::<A>::new(bump) {
+            Some(buddy) => buddy,
+            None => {
+                kwarn!("zone: failed to create buddy for [{:?}, {:?})", start, end);
+                return;
+            }
+        };
+        kdebug!(
+            "zone {}: node {}, [{:?}, {:?})",
+            idx,
+            node.data(),
+            start,
+            end
+        );
+        self.zones[idx] = Some(MemZone {
+            node,
+            start,
+            end,
+            buddy,
+        });
+        self.nr_zones += 1;
+    }
+
+    fn zones(&self) -> impl Iterator<Item = &MemZone<A>> {
+        self.zones[0..self.nr_zones].iter().flatten()
+    }
+
+    fn zones_mut(&mut self) -> impl Iterator<Item = &mut MemZone<A>> {
+        self.zones[0..self.nr_zones].iter_mut().flatten()
+    }
+
+    /// 分配count个物理页帧，优先使用节点`node`的内存
+    pub unsafe fn allocate_node(
+        &mut self,
+        count: PageFrameCount,
+        node: NodeId,
+    ) -> Option<(PhysAddr, PageFrameCount)> {
+        let node = if node.data() < self.nr_nodes {
+            node
+        } else {
+            NodeId::new(0)
+        };
+        let order = self.fallback[node.data()];
+        for n in order.iter().take(self.nr_nodes) {
+            for zone in self.zones_mut().filter(|z| z.node == *n) {
+                if let Some(r) = zone.buddy.allocate(count) {
+                    return Some(r);
+                }
+            }
+        }
+        return None;
+    }
+}
+
+impl<A: MemoryManagementArch> Default for ZonedAllocator<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: MemoryManagementArch> FrameAllocator for ZonedAllocator<A> {
+    unsafe fn allocate(&mut self, count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
+        return self.allocate_node(count, NodeId::new(0));
+    }
+
+    unsafe fn free(&mut self, address: PhysAddr, count: PageFrameCount) {
+        if let Some(zone) = self.zones_mut().find(|z| z.contains(address)) {
+            return zone.buddy.free(address, count);
+        }
+
+        // 不是从伙伴分配器分配的页(例如初始化时由bump分配器分配的页表)，归还给所属节点的内存区
+        let node = phys_to_node(address);
+        let zone = self.zones().position(|z| z.node == node).unwrap_or(0);
+        match self.zones_mut().nth(zone) {
+            Some(zone) => zone.buddy.free(address, count),
+            None => kwarn!("zone: no zone to free {:?}", address),
+        }
+    }
+
+    unsafe fn usage(&self) -> PageFrameUsage {
+        let mut used = 0;
+        let mut total = 0;
+        for zone in self.zones() {
+            let usage = zone.buddy.usage();
+            used += usage.used().data();
+            total += usage.total().data();
+        }
+        return PageFrameUsage::new(PageFrameCount::new(used), PageFrameCount::new(total));
+    }
+}
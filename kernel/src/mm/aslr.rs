@@ -0,0 +1,87 @@
+//! 用户地址空间布局随机化(ASLR)
+//!
+//! execve时，如果进程的personality中没有ADDR_NO_RANDOMIZE，并且`/proc/sys/kernel/randomize_va_space`不为0，
+//! 那么进程会被标记为ProcessFlags::RANDOMIZE，新的地址空间中，用户栈的起始地址、mmap区域的起始地址
+//! 以及PIE程序的加载地址都会加上一个随机的偏移量。randomize_va_space为2时，堆的起始地址也会被随机化。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/mm/mmap.c
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{format, string::String};
+
+use crate::{
+    arch::MMArch,
+    libs::random::get_random_u64,
+    process::{Personality, ProcessFlags, ProcessManager},
+    syscall::SystemError,
+};
+
+use super::MemoryManagementArch;
+
+/// 0：不进行随机化；1：随机化用户栈、mmap区域以及PIE程序的加载地址；2：在1的基础上，随机化堆的起始地址
+static RANDOMIZE_VA_SPACE: AtomicUsize = AtomicUsize::new(2);
+
+/// 堆的起始地址的随机偏移量的上限(32M)
+const BRK_RND_SIZE: usize = 0x0200_0000;
+
+/// 获取randomize_va_space的值
+pub fn randomize_va_space() -> usize {
+    return RANDOMIZE_VA_SPACE.load(Ordering::SeqCst);
+}
+
+/// 判断具有给定personality的进程，在execve时是否需要随机化地址空间的布局
+pub fn should_randomize(personality: u32) -> bool {
+    return randomize_va_space() != 0
+        && !Personality::from_bits_truncate(personality).contains(Personality::ADDR_NO_RANDOMIZE);
+}
+
+/// 当前进程新建的地址空间是否需要随机化
+pub fn current_randomized() -> bool {
+    return ProcessManager::initialized()
+        && ProcessManager::current_pcb()
+            .flags()
+            .contains(ProcessFlags::RANDOMIZE);
+}
+
+/// 生成一个页对齐的随机偏移量，偏移的页数在[0, 2^bits)之间
+fn rnd_pages(bits: usize) -> usize {
+    return (get_random_u64() as usize & ((1 << bits) - 1)) << MMArch::PAGE_SHIFT;
+}
+
+/// 用户栈的起始地址向下偏移的随机量
+pub fn stack_rnd() -> usize {
+    return rnd_pages(MMArch::USER_STACK_RND_BITS);
+}
+
+/// mmap区域的起始地址以及PIE程序的加载地址向上偏移的随机量
+pub fn mmap_rnd() -> usize {
+    return rnd_pages(MMArch::USER_MMAP_RND_BITS);
+}
+
+/// 堆的起始地址向上偏移的随机量。randomize_va_space小于2时为0
+pub fn brk_rnd() -> usize {
+    if randomize_va_space() < 2 {
+        return 0;
+    }
+    return (get_random_u64() as usize % (BRK_RND_SIZE >> MMArch::PAGE_SHIFT))
+        << MMArch::PAGE_SHIFT;
+}
+
+/// 生成`/proc/sys/kernel/randomize_va_space`的内容
+pub fn randomize_va_space_show() -> String {
+    return format!("{}\n", randomize_va_space());
+}
+
+/// 写入`/proc/sys/kernel/randomize_va_space`，只接受0、1、2。修改只对之后的execve生效
+pub fn randomize_va_space_write(buf: &[u8]) -> Result<usize, SystemError> {
+    let s = core::str::from_utf8(buf)
+        .map_err(|_| SystemError::EINVAL)?
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    let value = s.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+    if value > 2 {
+        return Err(SystemError::EINVAL);
+    }
+    RANDOMIZE_VA_SPACE.store(value, Ordering::SeqCst);
+    return Ok(buf.len());
+}
@@ -7,6 +7,7 @@ use hashbrown::HashMap;
 
 use crate::{
     arch::mm::LowAddressRemapping,
+    export_symbol,
     include::bindings::bindings::{gfp_t, vm_flags_t, PAGE_U_S},
     kerror,
     libs::{align::page_align_up, spinlock::SpinLock},
@@ -41,7 +42,7 @@ pub unsafe extern "C" fn rs_map_phys(vaddr: usize, paddr: usize, size: usize, fl
     let count = PageFrameCount::new(page_align_up(size) / MMArch::PAGE_SIZE);
     // kdebug!("rs_map_phys: vaddr: {vaddr:?}, paddr: {paddr:?}, count: {count:?}, flags: {flags:?}");
 
-    let mut page_flags: PageFlags<MMArch> = PageFlags::new().set_execute(true).set_write(true);
+    let mut page_flags: PageFlags<MMArch> = PageFlags::new().set_write(true);
     if flags & PAGE_U_S as usize != 0 {
         page_flags = page_flags.set_user(true);
     }
@@ -68,6 +69,7 @@ pub unsafe extern "C" fn kzalloc(size: usize, _gfp: gfp_t) -> usize {
     // kdebug!("kzalloc: size: {size}");
     return do_kmalloc(size, true);
 }
+export_symbol!(kzalloc);
 
 #[no_mangle]
 pub unsafe extern "C" fn kmalloc(size: usize, _gfp: gfp_t) -> usize {
@@ -75,6 +77,7 @@ pub unsafe extern "C" fn kmalloc(size: usize, _gfp: gfp_t) -> usize {
     // 由于C代码不规范，因此都全部清空
     return do_kmalloc(size, true);
 }
+export_symbol!(kmalloc);
 
 fn do_kmalloc(size: usize, _zero: bool) -> usize {
     let space: Vec<u8> = vec![0u8; size];
@@ -119,6 +122,7 @@ pub unsafe extern "C" fn kfree(vaddr: usize) -> usize {
     drop(Vec::from_raw_parts(vaddr.data() as *mut u8, len, cap));
     return 0;
 }
+export_symbol!(kfree);
 
 #[no_mangle]
 pub unsafe extern "C" fn rs_unmap_at_low_addr() -> usize {
@@ -0,0 +1,186 @@
+//! 缺页异常处理：零页与匿名页的写时复制，以及用户栈的自动扩展
+//!
+//! 匿名映射创建时不分配物理页，所有的页都只读地映射到同一个全为0的零页，第一次写入某一页时才为它分配私有的页。
+//! fork时，子进程与父进程共享匿名页，两边都只读地映射这些页，任何一方写入时再复制出一份私有的页。
+//!
+//! 被多个映射共享的匿名页记录在一张表中，表中保存映射这个页的次数。不在表中的匿名页只被一个映射使用，
+//! 写入时直接恢复写权限即可，解除映射时直接释放。零页不在表中，并且永远不会被释放。
+//!
+//! 用户栈的下方是不映射任何页帧的保护页。访问保护页或者用户栈下方的空闲区域时，只要扩展后的用户栈
+//! 不超过RLIMIT_STACK，并且不会与其它映射重叠，就扩展用户栈；否则这是一次真正的非法访问。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/mm/memory.c
+
+use hashbrown::HashMap;
+
+use crate::{
+    arch::MMArch, libs::spinlock::SpinLock, process::ProcessManager, syscall::SystemError,
+};
+
+use super::{
+    allocator::page_frame::{
+        allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
+    },
+    page::{PageFlags, PageFlush},
+    MemoryManagementArch, PhysAddr, VirtAddr,
+};
+
+bitflags! {
+    /// 缺页异常的原因
+    pub struct FaultFlags: u32 {
+        /// 写操作导致的异常
+        const FAULT_FLAG_WRITE = 1 << 0;
+        /// 用户态发生的异常
+        const FAULT_FLAG_USER = 1 << 1;
+    }
+}
+
+lazy_static! {
+    /// 全为0的零页，所有的进程共享它
+    static ref ZERO_PAGE: PhysAddr = {
+        let (paddr, _) = unsafe { allocate_page_frames(PageFrameCount::new(1)) }
+            .expect("Failed to allocate zero page");
+        unsafe { MMArch::write_bytes(MMArch::phys_2_virt(paddr).unwrap(), 0, MMArch::PAGE_SIZE) };
+        paddr
+    };
+
+    /// 被多个映射共享的匿名页 -> 映射这个页的次数(至少为2)
+    static ref SHARED_ANON_PAGES: SpinLock<HashMap<PhysAddr, usize>> =
+        SpinLock::new(HashMap::new());
+}
+
+/// 获取零页的物理地址
+#[inline]
+pub fn zero_page() -> PhysAddr {
+    return *ZERO_PAGE;
+}
+
+/// 匿名页多了一个映射(fork时子进程共享父进程的页)
+pub(super) fn anon_page_dup(paddr: PhysAddr) {
+    if paddr == zero_page() {
+        return;
+    }
+    *SHARED_ANON_PAGES.lock().entry(paddr).or_insert(1) += 1;
+}
+
+/// 一个映射不再使用匿名页。没有其它映射使用这个页时，释放它
+pub(super) fn anon_page_release(paddr: PhysAddr) {
+    if paddr == zero_page() {
+        return;
+    }
+
+    let mut shared = SHARED_ANON_PAGES.lock();
+    if let Some(count) = shared.get_mut(&paddr) {
+        *count -= 1;
+        if *count == 1 {
+            // 剩下的那个映射独占这个页
+            shared.remove(&paddr);
+        }
+        return;
+    }
+    drop(shared);
+
+    unsafe { deallocate_page_frames(PhysPageFrame::new(paddr), PageFrameCount::new(1)) };
+}
+
+/// 匿名页是否被多个映射共享。零页总是被共享的
+pub(super) fn anon_page_shared(paddr: PhysAddr) -> bool {
+    return paddr == zero_page() || SHARED_ANON_PAGES.lock().contains_key(&paddr);
+}
+
+/// 计算匿名页的页表项实际使用的标志
+///
+/// 共享的页必须只读地映射，写入时才能触发写时复制
+pub(super) fn anon_page_flags(paddr: PhysAddr, flags: PageFlags<MMArch>) -> PageFlags<MMArch> {
+    if flags.has_write() && anon_page_shared(paddr) {
+        return flags.set_write(false);
+    }
+    return flags;
+}
+
+/// 处理当前进程的缺页异常
+///
+/// 目前VMA内所有的页在映射时都已经存在于页表中，因此只需要处理写入只读的匿名页导致的异常，
+/// 以及访问不在任何VMA中的地址(或者保护页)时，扩展用户栈。
+/// 内核访问用户内存时也可能触发这种异常，因此调用者在持有当前进程的地址空间的锁时，不能访问用户内存。
+///
+/// ## 参数
+///
+/// - `address`：导致异常的虚拟地址
+/// - `flags`：异常的原因
+///
+/// ## 错误
+///
+/// - `EFAULT`：地址不在任何VMA中并且用户栈无法扩展到这个地址，或者VMA不允许这样访问。调用者需要把它当作非法访问处理
+/// - `ENOMEM`：没有内存用于复制页
+pub fn handle_mm_fault(address: VirtAddr, flags: FaultFlags) -> Result<(), SystemError> {
+    if !address.check_user() {
+        return Err(SystemError::EFAULT);
+    }
+
+    let address_space = ProcessManager::current_pcb()
+        .basic()
+        .user_vm()
+        .ok_or(SystemError::EFAULT)?;
+    let mut guard = address_space.write();
+    let vma = match guard.mappings.contains(address) {
+        Some(vma) if !vma.lock().is_guard() => vma,
+        // 访问了保护页或者用户栈下方的空闲区域
+        _ => return guard.expand_stack(address),
+    };
+    let vma_guard = vma.lock();
+
+    let page = VirtAddr::new(address.data() & MMArch::PAGE_MASK);
+    let mapper = &mut guard.user_mapper.utable;
+    if !flags.contains(FaultFlags::FAULT_FLAG_WRITE) {
+        // VMA内的页都存在于页表中，读取时发生异常，说明当前cpu的TLB中还是旧的页表项(例如其它线程刚刚扩展了用户栈)
+        mapper.translate(page).ok_or(SystemError::EFAULT)?;
+        PageFlush::<MMArch>::new(page).flush();
+        return Ok(());
+    }
+
+    if !vma_guard.flags().has_write() || !vma_guard.owns_frames() {
+        return Err(SystemError::EFAULT);
+    }
+
+    let (paddr, pte_flags) = mapper.translate(page).ok_or(SystemError::EFAULT)?;
+    if pte_flags.has_write() {
+        // 这个页已经被其它线程处理了，只是当前cpu的TLB中还是旧的页表项
+        PageFlush::<MMArch>::new(page).flush();
+        return Ok(());
+    }
+
+    if !anon_page_shared(paddr) {
+        // 其它映射都已经复制出了自己的页，当前映射独占这个页，直接恢复写权限
+        unsafe { mapper.remap(page, vma_guard.flags()) }
+            .ok_or(SystemError::EFAULT)?
+            .flush();
+        return Ok(());
+    }
+
+    // 复制出一份私有的页
+    let (new_paddr, _) =
+        unsafe { allocate_page_frames(PageFrameCount::new(1)) }.ok_or(SystemError::ENOMEM)?;
+    unsafe {
+        let dst = MMArch::phys_2_virt(new_paddr).unwrap();
+        if paddr == zero_page() {
+            MMArch::write_bytes(dst, 0, MMArch::PAGE_SIZE);
+        } else {
+            let src = MMArch::phys_2_virt(paddr).unwrap();
+            (dst.data() as *mut u8)
+                .copy_from_nonoverlapping(src.data() as *const u8, MMArch::PAGE_SIZE);
+        }
+
+        let (_, _, flush) = mapper
+            .unmap_phys(page, false)
+            .expect("Failed to unmap, beacuse of some page is not mapped");
+        flush.ignore();
+        mapper
+            .map_phys(page, new_paddr, vma_guard.flags())
+            .expect("Failed to map phys, may be OOM error")
+            .flush();
+    }
+    anon_page_release(paddr);
+
+    return Ok(());
+}
@@ -0,0 +1,374 @@
+//! 软件实现的内核地址检查器(Kernel Address SANitizer)
+//!
+//! 开启`kasan`特性后，内核会为线性映射区中每8字节的内存，维护1字节的影子内存(shadow memory)，
+//! 用于记录这段内存当前是否允许被访问：
+//!
+//! - 堆分配器会在每个对象的末尾放置红区(redzone)，用于发现越界访问；
+//! - 被释放的对象会先放入隔离区(quarantine)，在此期间对它的访问会被报告为释放后使用；
+//! - 页帧分配器会毒化被归还的物理页。
+//!
+//! 检查只在调用了[`kasan_check_read`]/[`kasan_check_write`]的访问辅助函数中进行，
+//! 例如用户空间数据拷贝函数、以及把缓冲区交给设备进行DMA之前。
+//!
+//! 未开启`kasan`特性时，本模块的函数均为空函数，不会带来额外开销。
+//! 开启方式：`make KERNEL_FEATURES=kasan`
+
+#[cfg(feature = "kasan")]
+pub use self::kasan_impl::*;
+#[cfg(not(feature = "kasan"))]
+pub use self::kasan_stub::*;
+
+/// 影子内存的值：该段内存对应的物理页已经被归还给页帧分配器
+pub const KASAN_PAGE_FREE: u8 = 0xff;
+/// 影子内存的值：堆对象的红区
+pub const KASAN_KMALLOC_REDZONE: u8 = 0xfc;
+/// 影子内存的值：已经被释放的堆对象
+pub const KASAN_KMALLOC_FREE: u8 = 0xfb;
+
+#[cfg(feature = "kasan")]
+mod kasan_impl {
+    use core::{
+        alloc::Layout,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{
+        arch::{mm::LockedFrameAllocator, MMArch},
+        kerror, kinfo,
+        libs::spinlock::SpinLock,
+        mm::{
+            allocator::page_frame::{FrameAllocator, PageFrameCount},
+            MemoryManagementArch, PhysAddr, VirtAddr,
+        },
+    };
+
+    use super::{KASAN_KMALLOC_FREE, KASAN_KMALLOC_REDZONE, KASAN_PAGE_FREE};
+
+    /// 每个影子字节所描述的内存大小的位数
+    const KASAN_SHADOW_SCALE_SHIFT: usize = 3;
+    /// 每个影子字节所描述的内存大小(8字节)
+    pub const KASAN_GRANULE_SIZE: usize = 1 << KASAN_SHADOW_SCALE_SHIFT;
+    /// 堆对象末尾至少保留的红区大小
+    pub const KASAN_REDZONE_SIZE: usize = 64;
+
+    /// 每个影子块所描述的物理内存大小的位数(2M)
+    const CHUNK_SHIFT: usize = 21;
+    const CHUNK_SIZE: usize = 1 << CHUNK_SHIFT;
+    /// 每个影子块的大小(256K)
+    const SHADOW_CHUNK_SIZE: usize = CHUNK_SIZE >> KASAN_SHADOW_SCALE_SHIFT;
+    /// 最多支持检查的物理内存大小(64G)
+    const MAX_PHYS_SIZE: usize = 64 << 30;
+    const CHUNK_NUM: usize = MAX_PHYS_SIZE >> CHUNK_SHIFT;
+
+    /// 隔离区能容纳的对象数量
+    const QUARANTINE_SIZE: usize = 256;
+
+    const SHADOW_CHUNK_INIT: AtomicUsize = AtomicUsize::new(0);
+    /// 影子块的虚拟地址。影子块按需分配，值为0表示这段内存还没有被毒化过（全部可访问）
+    static SHADOW_CHUNKS: [AtomicUsize; CHUNK_NUM] = [SHADOW_CHUNK_INIT; CHUNK_NUM];
+
+    static QUARANTINE: SpinLock<Quarantine> = SpinLock::new(Quarantine::new());
+
+    /// 被释放的堆对象的隔离区
+    ///
+    /// 对象被释放后不会立即归还给页帧分配器，而是在隔离区中停留一段时间，
+    /// 从而让释放后使用的访问能够被发现。
+    struct Quarantine {
+        entries: [Option<(usize, Layout)>; QUARANTINE_SIZE],
+        /// 下一个要写入的位置
+        head: usize,
+    }
+
+    impl Quarantine {
+        const fn new() -> Self {
+            return Self {
+                entries: [None; QUARANTINE_SIZE],
+                head: 0,
+            };
+        }
+
+        /// 把对象放入隔离区，返回被挤出隔离区的对象
+        fn put(&mut self, ptr: usize, layout: Layout) -> Option<(usize, Layout)> {
+            let evicted = self.entries[self.head].replace((ptr, layout));
+            self.head = (self.head + 1) % QUARANTINE_SIZE;
+            return evicted;
+        }
+    }
+
+    pub fn kasan_init() {
+        kinfo!(
+            "KASAN enabled: granule={}, redzone={}, quarantine={}",
+            KASAN_GRANULE_SIZE,
+            KASAN_REDZONE_SIZE,
+            QUARANTINE_SIZE
+        );
+    }
+
+    /// 获取虚拟地址在线性映射区中对应的物理地址。不在线性映射区的地址不进行检查
+    #[inline(always)]
+    fn linear_offset(addr: usize) -> Option<usize> {
+        if addr < MMArch::PHYS_OFFSET {
+            return None;
+        }
+        let offset = addr - MMArch::PHYS_OFFSET;
+        if offset >= MAX_PHYS_SIZE {
+            return None;
+        }
+        return Some(offset);
+    }
+
+    /// 获取物理地址所在的影子块
+    ///
+    /// ## 参数
+    ///
+    /// - `offset`：物理地址
+    /// - `create`：影子块不存在时，是否分配新的影子块
+    fn shadow_chunk(offset: usize, create: bool) -> Option<*mut u8> {
+        let index = offset >> CHUNK_SHIFT;
+        let chunk = SHADOW_CHUNKS[index].load(Ordering::Acquire);
+        if chunk != 0 || !create {
+            return if chunk == 0 {
+                None
+            } else {
+                Some(chunk as *mut u8)
+            };
+        }
+
+        // 分配新的影子块。新的影子块全部为0，表示这段内存都可以访问
+        let count = PageFrameCount::from_bytes(SHADOW_CHUNK_SIZE).unwrap();
+        let (paddr, count) = unsafe { LockedFrameAllocator.allocate(count)? };
+        let vaddr = unsafe { MMArch::phys_2_virt(paddr)? };
+        unsafe { core::ptr::write_bytes(vaddr.as_ptr::<u8>(), 0, SHADOW_CHUNK_SIZE) };
+
+        match SHADOW_CHUNKS[index].compare_exchange(
+            0,
+            vaddr.data(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => return Some(vaddr.as_ptr()),
+            Err(other) => {
+                // 其他cpu已经分配了影子块
+                unsafe { LockedFrameAllocator.free(paddr, count) };
+                return Some(other as *mut u8);
+            }
+        }
+    }
+
+    /// 读取一个字节所对应的影子字节
+    #[inline(always)]
+    fn shadow_byte(offset: usize) -> u8 {
+        match shadow_chunk(offset, false) {
+            Some(chunk) => unsafe {
+                *chunk.add((offset & (CHUNK_SIZE - 1)) >> KASAN_SHADOW_SCALE_SHIFT)
+            },
+            None => 0,
+        }
+    }
+
+    /// 设置[offset, offset+size)范围内的影子字节（offset与size都已经按照粒度对齐）
+    fn set_shadow(mut offset: usize, size: usize, value: u8) {
+        let end = offset + size;
+        while offset < end {
+            let chunk_end = core::cmp::min((offset & !(CHUNK_SIZE - 1)) + CHUNK_SIZE, end);
+            // 把影子内存全部设为0时，不需要为不存在的影子块分配内存
+            if let Some(chunk) = shadow_chunk(offset, value != 0) {
+                let start = (offset & (CHUNK_SIZE - 1)) >> KASAN_SHADOW_SCALE_SHIFT;
+                let len = (chunk_end - offset) >> KASAN_SHADOW_SCALE_SHIFT;
+                unsafe { core::ptr::write_bytes(chunk.add(start), value, len) };
+            }
+            offset = chunk_end;
+        }
+    }
+
+    /// 毒化一段内存，之后对这段内存的检查都会失败
+    pub fn kasan_poison(addr: VirtAddr, size: usize, value: u8) {
+        if let Some(offset) = linear_offset(addr.data()) {
+            let size = core::cmp::min(size, MAX_PHYS_SIZE - offset);
+            let start = offset & !(KASAN_GRANULE_SIZE - 1);
+            let end = (offset + size + KASAN_GRANULE_SIZE - 1) & !(KASAN_GRANULE_SIZE - 1);
+            set_shadow(start, end - start, value);
+        }
+    }
+
+    /// 解除一段内存的毒化状态（addr需要按照粒度对齐）
+    pub fn kasan_unpoison(addr: VirtAddr, size: usize) {
+        if let Some(offset) = linear_offset(addr.data()) {
+            let size = core::cmp::min(size, MAX_PHYS_SIZE - offset);
+            let aligned = size & !(KASAN_GRANULE_SIZE - 1);
+            set_shadow(offset, aligned, 0);
+            // 最后一个不完整的粒度，影子字节记录其中可访问的字节数
+            let tail = size - aligned;
+            if tail != 0 {
+                if let Some(chunk) = shadow_chunk(offset + aligned, true) {
+                    let index = ((offset + aligned) & (CHUNK_SIZE - 1)) >> KASAN_SHADOW_SCALE_SHIFT;
+                    unsafe { *chunk.add(index) = tail as u8 };
+                }
+            }
+        }
+    }
+
+    /// 检查一次内存访问是否合法
+    fn check_access(addr: usize, size: usize, write: bool) {
+        if size == 0 {
+            return;
+        }
+        let offset = match linear_offset(addr) {
+            Some(offset) => offset,
+            None => return,
+        };
+        let end = core::cmp::min(offset + size, MAX_PHYS_SIZE);
+        let mut cur = offset;
+        while cur < end {
+            let granule = cur & !(KASAN_GRANULE_SIZE - 1);
+            let granule_end = core::cmp::min(granule + KASAN_GRANULE_SIZE, end);
+            let shadow = shadow_byte(cur);
+            // 影子字节为1~7时，表示该粒度的前shadow个字节可以访问
+            let bad = match shadow {
+                0 => false,
+                1..=7 => granule_end - granule > shadow as usize,
+                _ => true,
+            };
+            if bad {
+                report(addr, size, write, MMArch::PHYS_OFFSET + cur, shadow);
+            }
+            cur = granule_end;
+        }
+    }
+
+    fn report(addr: usize, size: usize, write: bool, bad_addr: usize, shadow: u8) -> ! {
+        let bug_type = match shadow {
+            KASAN_KMALLOC_FREE => "use-after-free",
+            KASAN_PAGE_FREE => "use-after-free (page)",
+            KASAN_KMALLOC_REDZONE | 1..=7 => "slab-out-of-bounds",
+            _ => "wild-memory-access",
+        };
+        kerror!("==================================================================");
+        kerror!("BUG: KASAN: {} in access of addr {:#x}", bug_type, addr);
+        kerror!(
+            "{} of size {} at addr {:#x}, first bad addr {:#x}, shadow byte {:#04x}",
+            if write { "Write" } else { "Read" },
+            size,
+            addr,
+            bad_addr,
+            shadow
+        );
+        kerror!("==================================================================");
+        panic!("KASAN: {}", bug_type);
+    }
+
+    /// 检查对[addr, addr+size)的读操作是否合法
+    #[inline(always)]
+    pub fn kasan_check_read(addr: usize, size: usize) {
+        check_access(addr, size, false);
+    }
+
+    /// 检查对[addr, addr+size)的写操作是否合法
+    #[inline(always)]
+    pub fn kasan_check_write(addr: usize, size: usize) {
+        check_access(addr, size, true);
+    }
+
+    /// 堆分配器分配对象后调用：解除对象的毒化，并把对象之后的空间标记为红区
+    ///
+    /// ## 参数
+    ///
+    /// - `ptr`：对象的起始地址
+    /// - `size`：用户申请的大小
+    /// - `total`：实际分配的大小（包括红区）
+    pub fn kasan_kmalloc(ptr: *mut u8, size: usize, total: usize) {
+        let addr = VirtAddr::new(ptr as usize);
+        let redzone_start = (size + KASAN_GRANULE_SIZE - 1) & !(KASAN_GRANULE_SIZE - 1);
+        kasan_unpoison(addr, size);
+        if total > redzone_start {
+            kasan_poison(
+                addr + redzone_start,
+                total - redzone_start,
+                KASAN_KMALLOC_REDZONE,
+            );
+        }
+    }
+
+    /// 堆分配器释放对象时调用
+    ///
+    /// 对象会被毒化并放入隔离区。返回被挤出隔离区的对象，调用者需要真正地释放它
+    pub fn kasan_kfree(ptr: *mut u8, layout: Layout, total: usize) -> Option<(*mut u8, Layout)> {
+        if let Some(offset) = linear_offset(ptr as usize) {
+            let shadow = shadow_byte(offset);
+            if shadow == KASAN_KMALLOC_FREE {
+                kerror!("BUG: KASAN: double-free of object {:p}", ptr);
+                panic!("KASAN: double-free");
+            } else if shadow >= 0x80 {
+                kerror!(
+                    "BUG: KASAN: invalid-free of object {:p}, shadow byte {:#04x}",
+                    ptr,
+                    shadow
+                );
+                panic!("KASAN: invalid-free");
+            }
+        } else {
+            return Some((ptr, layout));
+        }
+
+        kasan_poison(VirtAddr::new(ptr as usize), total, KASAN_KMALLOC_FREE);
+        return QUARANTINE
+            .lock_irqsave()
+            .put(ptr as usize, layout)
+            .map(|(ptr, layout)| (ptr as *mut u8, layout));
+    }
+
+    /// 页帧分配器分配物理页之后调用
+    pub fn kasan_alloc_pages(paddr: PhysAddr, count: PageFrameCount) {
+        if let Some(vaddr) = unsafe { MMArch::phys_2_virt(paddr) } {
+            kasan_unpoison(vaddr, count.data() * MMArch::PAGE_SIZE);
+        }
+    }
+
+    /// 物理页被归还给页帧分配器之前调用
+    pub fn kasan_free_pages(paddr: PhysAddr, count: PageFrameCount) {
+        if let Some(vaddr) = unsafe { MMArch::phys_2_virt(paddr) } {
+            kasan_poison(vaddr, count.data() * MMArch::PAGE_SIZE, KASAN_PAGE_FREE);
+        }
+    }
+}
+
+/// 未开启`kasan`特性时使用的空实现
+#[cfg(not(feature = "kasan"))]
+#[allow(dead_code)]
+mod kasan_stub {
+    use core::alloc::Layout;
+
+    use crate::mm::{allocator::page_frame::PageFrameCount, PhysAddr, VirtAddr};
+
+    pub const KASAN_GRANULE_SIZE: usize = 8;
+    pub const KASAN_REDZONE_SIZE: usize = 0;
+
+    #[inline(always)]
+    pub fn kasan_init() {}
+
+    #[inline(always)]
+    pub fn kasan_poison(_addr: VirtAddr, _size: usize, _value: u8) {}
+
+    #[inline(always)]
+    pub fn kasan_unpoison(_addr: VirtAddr, _size: usize) {}
+
+    #[inline(always)]
+    pub fn kasan_check_read(_addr: usize, _size: usize) {}
+
+    #[inline(always)]
+    pub fn kasan_check_write(_addr: usize, _size: usize) {}
+
+    #[inline(always)]
+    pub fn kasan_kmalloc(_ptr: *mut u8, _size: usize, _total: usize) {}
+
+    #[inline(always)]
+    pub fn kasan_kfree(ptr: *mut u8, layout: Layout, _total: usize) -> Option<(*mut u8, Layout)> {
+        return Some((ptr, layout));
+    }
+
+    #[inline(always)]
+    pub fn kasan_alloc_pages(_paddr: PhysAddr, _count: PageFrameCount) {}
+
+    #[inline(always)]
+    pub fn kasan_free_pages(_paddr: PhysAddr, _count: PageFrameCount) {}
+}
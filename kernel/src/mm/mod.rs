@@ -1,6 +1,6 @@
 use alloc::sync::Arc;
 
-use crate::{arch::MMArch, include::bindings::bindings::PAGE_OFFSET, syscall::SystemError};
+use crate::{arch::MMArch, syscall::SystemError};
 
 use core::{
     cmp,
@@ -18,12 +18,18 @@ use self::{
 };
 
 pub mod allocator;
+pub mod aslr;
 pub mod c_adapter;
+pub mod fault;
+pub mod kasan;
 pub mod kernel_mapper;
 pub mod mmio_buddy;
 pub mod no_init;
+pub mod numa;
 pub mod page;
 pub mod percpu;
+pub mod set_memory;
+pub mod shmem;
 pub mod syscall;
 pub mod ucontext;
 
@@ -58,13 +64,13 @@ pub unsafe fn set_INITIAL_PROCESS_ADDRESS_SPACE(address_space: Arc<AddressSpace>
 /// @brief 将内核空间的虚拟地址转换为物理地址
 #[inline(always)]
 pub fn virt_2_phys(addr: usize) -> usize {
-    addr - PAGE_OFFSET as usize
+    addr - MMArch::PHYS_OFFSET
 }
 
 /// @brief 将物理地址转换为内核空间的虚拟地址
 #[inline(always)]
 pub fn phys_2_virt(addr: usize) -> usize {
-    addr + PAGE_OFFSET as usize
+    addr + MMArch::PHYS_OFFSET
 }
 
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
@@ -397,6 +403,10 @@ pub trait MemoryManagementArch: Clone + Copy + Debug {
     const USER_BRK_START: VirtAddr;
     /// 用户栈起始地址（向下生长，不包含该值）
     const USER_STACK_START: VirtAddr;
+    /// 地址空间布局随机化时，用户栈的起始地址向下偏移的页数的位数
+    const USER_STACK_RND_BITS: usize;
+    /// 地址空间布局随机化时，mmap区域的起始地址以及PIE程序的加载地址向上偏移的页数的位数
+    const USER_MMAP_RND_BITS: usize;
 
     /// @brief 用于初始化内存管理模块与架构相关的信息。
     /// 该函数应调用其他模块的接口，生成内存区域结构体，提供给BumpAllocator使用
@@ -463,6 +473,20 @@ pub trait MemoryManagementArch: Clone + Copy + Debug {
         }
     }
 
+    /// 根据物理地址与标志位，生成页表项的值
+    ///
+    /// 默认物理地址直接存放在页表项中(如x86_64)，物理地址需要移位后才能存放的架构(如RISC-V)需要重写这个函数
+    #[inline(always)]
+    fn make_entry(paddr: PhysAddr, page_flags: usize) -> usize {
+        return paddr.data() | page_flags;
+    }
+
+    /// 从页表项的值中取出物理地址，与[`MemoryManagementArch::make_entry`]对应
+    #[inline(always)]
+    fn entry_address(entry: usize) -> PhysAddr {
+        return PhysAddr::new(entry & Self::PAGE_ADDRESS_MASK);
+    }
+
     /// @brief 判断指定的虚拟地址是否正确（符合规范）
     fn virt_is_valid(virt: VirtAddr) -> bool;
 
@@ -67,7 +67,7 @@ pub unsafe fn pseudo_map_phys(vaddr: VirtAddr, paddr: PhysAddr, count: PageFrame
         &mut pseudo_allocator,
     );
 
-    let flags: PageFlags<MMArch> = PageFlags::new().set_write(true).set_execute(true);
+    let flags: PageFlags<MMArch> = PageFlags::new().set_write(true);
 
     for i in 0..count.data() {
         let vaddr = vaddr + i * MMArch::PAGE_SIZE;
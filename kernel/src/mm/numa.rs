@@ -0,0 +1,396 @@
+//! NUMA(非一致性内存访问)支持
+//!
+//! 内存管理初始化时，在建立伙伴分配器之前，架构相关的代码解析固件提供的亲和性信息(x86_64上是ACPI的SRAT与SLIT)，
+//! 通过本模块登记：
+//!
+//! - 每段物理内存所属的节点，以及这段内存是否支持热插拔；
+//! - 每个CPU所属的节点；
+//! - 节点之间的距离。
+//!
+//! 页帧分配器据此把物理内存按照节点划分为多个内存区(见[`crate::mm::allocator::zone`])，
+//! 分配时优先使用当前CPU所在节点的内存，不足时按照距离从近到远使用其他节点的内存。
+//! 也可以使用[`numa_prefer_node`]临时指定节点，用于为其他CPU分配per-cpu的数据结构。
+//!
+//! 固件没有提供亲和性信息时，所有内存与CPU都属于0号节点，页帧的分配行为与没有NUMA支持时一致。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/mm/numa.c
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{
+    int_like, kernel_test, kinfo, ktest_assert, kwarn, libs::spinlock::SpinLock,
+    smp::core::smp_get_processor_id, syscall::SystemError,
+};
+
+use super::{percpu::PerCpu, PhysAddr};
+
+/// 最多支持的节点数量
+pub const MAX_NUMNODES: usize = 8;
+/// 内存表最多能容纳的内存块数量
+const MAX_NUMA_MEMBLKS: usize = 64;
+/// 节点到自身的距离
+pub const LOCAL_DISTANCE: u8 = 10;
+/// 固件没有提供距离时，节点到其他节点的距离
+pub const REMOTE_DISTANCE: u8 = 20;
+
+int_like!(NodeId, usize);
+
+/// 带有节点信息的物理内存块
+#[derive(Debug, Clone, Copy)]
+pub struct NumaMemblock {
+    /// 物理基地址
+    pub base: PhysAddr,
+    /// 内存块的大小
+    pub size: usize,
+    /// 内存块所属的节点
+    pub node: NodeId,
+    /// 内存块是否支持热插拔
+    pub hotpluggable: bool,
+}
+
+impl NumaMemblock {
+    const EMPTY: Self = Self {
+        base: PhysAddr::new(0),
+        size: 0,
+        node: NodeId::new(0),
+        hotpluggable: false,
+    };
+
+    #[inline(always)]
+    pub fn end(&self) -> PhysAddr {
+        self.base + self.size
+    }
+
+    #[inline(always)]
+    fn contains(&self, paddr: PhysAddr) -> bool {
+        paddr >= self.base && paddr < self.end()
+    }
+}
+
+/// 固件提供的NUMA拓扑
+///
+/// 拓扑在伙伴分配器建立之前登记，因此不能使用堆内存
+#[derive(Debug)]
+struct NumaTopology {
+    memblks: [NumaMemblock; MAX_NUMA_MEMBLKS],
+    nr_memblks: usize,
+    /// 节点的数量，节点号是[0, nr_nodes)
+    nr_nodes: usize,
+    /// 节点之间的距离，为0表示固件没有提供
+    distance: [[u8; MAX_NUMNODES]; MAX_NUMNODES],
+}
+
+impl NumaTopology {
+    const fn new() -> Self {
+        Self {
+            memblks: [NumaMemblock::EMPTY; MAX_NUMA_MEMBLKS],
+            nr_memblks: 0,
+            nr_nodes: 1,
+            distance: [[0; MAX_NUMNODES]; MAX_NUMNODES],
+        }
+    }
+
+    fn memblks(&self) -> &[NumaMemblock] {
+        &self.memblks[0..self.nr_memblks]
+    }
+
+    fn distance(&self, from: NodeId, to: NodeId) -> u8 {
+        let d = self.distance[from.data()][to.data()];
+        if d != 0 {
+            return d;
+        }
+        if from == to {
+            return LOCAL_DISTANCE;
+        }
+        return REMOTE_DISTANCE;
+    }
+}
+
+static NUMA_TOPOLOGY: SpinLock<NumaTopology> = SpinLock::new(NumaTopology::new());
+
+/// 是否存在多个节点。为false时，不需要在分配路径上查询当前CPU所在的节点
+static NUMA_ENABLED: AtomicBool = AtomicBool::new(false);
+
+const NODE_INIT: AtomicUsize = AtomicUsize::new(0);
+/// 每个CPU所属的节点，下标是cpu id
+static CPU_TO_NODE: [AtomicUsize; PerCpu::MAX_CPU_NUM] = [NODE_INIT; PerCpu::MAX_CPU_NUM];
+
+const PREFERRED_INIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// 每个CPU上临时指定的分配节点，见[`numa_prefer_node`]
+static PREFERRED_NODE: [AtomicUsize; PerCpu::MAX_CPU_NUM] = [PREFERRED_INIT; PerCpu::MAX_CPU_NUM];
+
+/// 登记一段属于`node`的物理内存
+///
+/// ## 错误
+///
+/// - `EINVAL`：节点号超出范围，或者大小为0
+/// - `ENOSPC`：内存表已满
+pub fn numa_add_memblk(
+    base: PhysAddr,
+    size: usize,
+    node: NodeId,
+    hotpluggable: bool,
+) -> Result<(), SystemError> {
+    if size == 0 || node.data() >= MAX_NUMNODES {
+        return Err(SystemError::EINVAL);
+    }
+    let mut topo = NUMA_TOPOLOGY.lock_irqsave();
+    if topo.nr_memblks >= MAX_NUMA_MEMBLKS {
+        return Err(SystemError::ENOSPC);
+    }
+    let idx = topo.nr_memblks;
+    topo.memblks[idx] = NumaMemblock {
+        base,
+        size,
+        node,
+        hotpluggable,
+    };
+    topo.nr_memblks += 1;
+    topo.nr_nodes = core::cmp::max(topo.nr_nodes, node.data() + 1);
+    return Ok(());
+}
+
+/// 设置节点`from`到节点`to`的距离
+pub fn numa_set_distance(from: NodeId, to: NodeId, distance: u8) {
+    if from.data() >= MAX_NUMNODES || to.data() >= MAX_NUMNODES {
+        return;
+    }
+    if (from == to && distance != LOCAL_DISTANCE) || (from != to && distance <= LOCAL_DISTANCE) {
+        kwarn!(
+            "numa: invalid distance {} between node {} and node {}",
+            distance,
+            from.data(),
+            to.data()
+        );
+        return;
+    }
+    NUMA_TOPOLOGY.lock_irqsave().distance[from.data()][to.data()] = distance;
+}
+
+/// 设置CPU所属的节点
+pub fn numa_set_cpu_node(cpu: usize, node: NodeId) {
+    if cpu < PerCpu::MAX_CPU_NUM && node.data() < MAX_NUMNODES {
+        CPU_TO_NODE[cpu].store(node.data(), Ordering::SeqCst);
+    }
+}
+
+/// 固件的亲和性信息登记完毕，检查拓扑是否可用
+///
+/// 没有登记任何内存时，认为固件没有提供可用的亲和性信息，所有内存与CPU都属于0号节点
+pub fn numa_register_nodes() {
+    if NUMA_TOPOLOGY.lock_irqsave().nr_memblks == 0 {
+        numa_reset();
+    }
+    let nr_nodes = NUMA_TOPOLOGY.lock_irqsave().nr_nodes;
+    NUMA_ENABLED.store(nr_nodes > 1, Ordering::SeqCst);
+}
+
+/// 丢弃已经登记的拓扑，所有内存与CPU都属于0号节点
+pub fn numa_reset() {
+    *NUMA_TOPOLOGY.lock_irqsave() = NumaTopology::new();
+    for node in CPU_TO_NODE.iter() {
+        node.store(0, Ordering::SeqCst);
+    }
+    NUMA_ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// 是否存在多个节点
+#[inline]
+pub fn numa_enabled() -> bool {
+    NUMA_ENABLED.load(Ordering::Relaxed)
+}
+
+/// 节点的数量
+pub fn numa_nr_nodes() -> usize {
+    NUMA_TOPOLOGY.lock_irqsave().nr_nodes
+}
+
+/// 获取节点`from`到节点`to`的距离
+pub fn numa_distance(from: NodeId, to: NodeId) -> u8 {
+    if from.data() >= MAX_NUMNODES || to.data() >= MAX_NUMNODES {
+        return REMOTE_DISTANCE;
+    }
+    return NUMA_TOPOLOGY.lock_irqsave().distance(from, to);
+}
+
+/// 获取CPU所属的节点
+#[inline]
+pub fn cpu_to_node(cpu: usize) -> NodeId {
+    if cpu >= PerCpu::MAX_CPU_NUM {
+        return NodeId::new(0);
+    }
+    NodeId::new(CPU_TO_NODE[cpu].load(Ordering::Relaxed))
+}
+
+/// 获取物理地址所属的节点。不在任何内存块中的地址属于0号节点
+pub fn phys_to_node(paddr: PhysAddr) -> NodeId {
+    NUMA_TOPOLOGY
+        .lock_irqsave()
+        .memblks()
+        .iter()
+        .find(|b| b.contains(paddr))
+        .map(|b| b.node)
+        .unwrap_or(NodeId::new(0))
+}
+
+/// 把物理地址范围[start, end)按照所属的节点切分，对每一段调用`f(start, end, node)`
+///
+/// 不在任何内存块中的部分属于0号节点
+pub fn numa_split_range(
+    start: PhysAddr,
+    end: PhysAddr,
+    mut f: impl FnMut(PhysAddr, PhysAddr, NodeId),
+) {
+    let topo = NUMA_TOPOLOGY.lock_irqsave();
+    let mut cur = start;
+    while cur < end {
+        let (part_end, node) = match topo.memblks().iter().find(|b| b.contains(cur)) {
+            Some(b) => (core::cmp::min(b.end(), end), b.node),
+            None => {
+                // 延伸到下一个内存块的起始地址为止
+                let next = topo
+                    .memblks()
+                    .iter()
+                    .filter(|b| b.base > cur && b.base < end)
+                    .map(|b| b.base)
+                    .min()
+                    .unwrap_or(end);
+                (next, NodeId::new(0))
+            }
+        };
+        f(cur, part_end, node);
+        cur = part_end;
+    }
+}
+
+/// 获取在节点`node`上分配内存时，依次尝试的节点
+///
+/// 按照到`node`的距离从近到远排序，距离相同的按照节点号排序。返回的数组中，前[`numa_nr_nodes`]项有效
+pub fn numa_fallback_order(node: NodeId) -> [NodeId; MAX_NUMNODES] {
+    let topo = NUMA_TOPOLOGY.lock_irqsave();
+    let mut order: [NodeId; MAX_NUMNODES] = core::array::from_fn(NodeId::new);
+    let node = if node.data() < topo.nr_nodes {
+        node
+    } else {
+        NodeId::new(0)
+    };
+    order[0..topo.nr_nodes].sort_unstable_by_key(|n| (topo.distance(node, *n), n.data()));
+    return order;
+}
+
+/// 在当前CPU上临时指定分配内存时使用的节点
+///
+/// 返回的守卫被drop时，恢复原来的设置。常用于为其他CPU分配per-cpu的数据结构：
+///
+/// ```ignore
+/// let _guard = numa_prefer_node(cpu_to_node(cpu));
+/// let stack = KernelStack::new()?;
+/// ```
+pub fn numa_prefer_node(node: NodeId) -> NumaPreferGuard {
+    let cpu = smp_get_processor_id() as usize % PerCpu::MAX_CPU_NUM;
+    let prev = PREFERRED_NODE[cpu].swap(node.data(), Ordering::SeqCst);
+    return NumaPreferGuard { cpu, prev };
+}
+
+#[must_use]
+#[derive(Debug)]
+pub struct NumaPreferGuard {
+    cpu: usize,
+    prev: usize,
+}
+
+impl Drop for NumaPreferGuard {
+    fn drop(&mut self) {
+        PREFERRED_NODE[self.cpu].store(self.prev, Ordering::SeqCst);
+    }
+}
+
+/// 获取当前CPU分配内存时应当使用的节点
+pub fn numa_node_id() -> NodeId {
+    // 只有一个节点时，不需要获取当前的cpu id
+    if !numa_enabled() {
+        return NodeId::new(0);
+    }
+    let cpu = smp_get_processor_id() as usize;
+    let preferred = PREFERRED_NODE
+        .get(cpu)
+        .map(|x| x.load(Ordering::Relaxed))
+        .unwrap_or(usize::MAX);
+    if preferred != usize::MAX {
+        return NodeId::new(preferred);
+    }
+    return cpu_to_node(cpu);
+}
+
+/// 打印NUMA拓扑
+pub fn numa_dump() {
+    let topo = NUMA_TOPOLOGY.lock_irqsave();
+    kinfo!("numa: {} node(s)", topo.nr_nodes);
+    for block in topo.memblks() {
+        kinfo!(
+            "numa: node {} [{:#x}, {:#x}){}",
+            block.node.data(),
+            block.base.data(),
+            block.end().data(),
+            if block.hotpluggable { " hotplug" } else { "" }
+        );
+    }
+    for from in 0..topo.nr_nodes {
+        let mut line = [0u8; MAX_NUMNODES];
+        for (to, d) in line.iter_mut().enumerate().take(topo.nr_nodes) {
+            *d = topo.distance(NodeId::new(from), NodeId::new(to));
+        }
+        kinfo!(
+            "numa: distance from node {}: {:?}",
+            from,
+            &line[0..topo.nr_nodes]
+        );
+    }
+}
+
+/// 检查分配时的节点顺序，以及物理地址范围按照节点的切分
+fn numa_topology() -> Result<(), SystemError> {
+    let nr_nodes = numa_nr_nodes();
+    for node in (0..nr_nodes).map(NodeId::new) {
+        let order = numa_fallback_order(node);
+        ktest_assert!(order[0] == node);
+        for w in order[0..nr_nodes].windows(2) {
+            ktest_assert!(numa_distance(node, w[0]) <= numa_distance(node, w[1]));
+        }
+        for n in (0..nr_nodes).map(NodeId::new) {
+            ktest_assert!(order[0..nr_nodes].contains(&n));
+        }
+    }
+
+    let start = PhysAddr::new(0);
+    let end = PhysAddr::new(1 << 40);
+    let mut expected = start;
+    let mut ok = true;
+    numa_split_range(start, end, |s, e, _| {
+        ok &= s == expected && s < e;
+        expected = e;
+    });
+    ktest_assert!(ok && expected == end);
+    // 回调时持有拓扑的锁，因此在回调之外检查每一段所属的节点
+    let mut pieces = [(PhysAddr::new(0), NodeId::new(0)); MAX_NUMA_MEMBLKS * 2 + 1];
+    let mut nr_pieces = 0;
+    numa_split_range(start, end, |s, _, node| {
+        if nr_pieces < pieces.len() {
+            pieces[nr_pieces] = (s, node);
+            nr_pieces += 1;
+        }
+    });
+    for (s, node) in pieces[0..nr_pieces].iter() {
+        ktest_assert!(phys_to_node(*s) == *node);
+    }
+
+    let node = NodeId::new(nr_nodes - 1);
+    let before = numa_node_id();
+    {
+        let _guard = numa_prefer_node(node);
+        ktest_assert!(!numa_enabled() || numa_node_id() == node);
+    }
+    ktest_assert!(numa_node_id() == before);
+    return Ok(());
+}
+kernel_test!(numa_topology);
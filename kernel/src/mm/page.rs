@@ -198,7 +198,7 @@ impl<Arch: MemoryManagementArch> PageEntry<Arch> {
     /// - Err(PhysAddr) 如果当前页表项不存在, 返回物理地址
     #[inline(always)]
     pub fn address(&self) -> Result<PhysAddr, PhysAddr> {
-        let paddr = PhysAddr::new(self.data & Arch::PAGE_ADDRESS_MASK);
+        let paddr = Arch::entry_address(self.data);
 
         if self.present() {
             Ok(paddr)
@@ -289,6 +289,10 @@ impl<Arch: MemoryManagementArch> PageFlags<Arch> {
     /// - no exec
     #[inline(always)]
     pub fn new_page_table(user: bool) -> Self {
+        // RISC-V中，R/W/X位全为0的页表项才指向下一级页表，并且这样的页表项的U位必须为0
+        if cfg!(target_arch = "riscv64") {
+            return unsafe { Self::from_data(Arch::ENTRY_FLAG_DEFAULT_TABLE) };
+        }
         return unsafe {
             let r = Self::from_data(Arch::ENTRY_FLAG_DEFAULT_TABLE | Arch::ENTRY_FLAG_READWRITE);
             if user {
@@ -441,7 +445,6 @@ impl<Arch: MemoryManagementArch> PageFlags<Arch> {
         return Self::new()
             .set_user(false)
             .set_write(true)
-            .set_execute(true)
             .set_page_cache_disable(true)
             .set_page_write_through(true);
     }
@@ -574,7 +577,7 @@ impl<Arch: MemoryManagementArch, F: FrameAllocator> PageMapper<Arch, F> {
         // TODO： 验证flags是否合法
 
         // 创建页表项
-        let entry = PageEntry::new(phys.data() | flags.data());
+        let entry = PageEntry::new(Arch::make_entry(phys, flags.data()));
         let mut table = self.table();
         loop {
             let i = table.index_of(virt)?;
@@ -616,7 +619,7 @@ impl<Arch: MemoryManagementArch, F: FrameAllocator> PageMapper<Arch, F> {
                     // kdebug!("Flags: {:?}", flags);
 
                     // 把新分配的页表映射到当前页表
-                    table.set_entry(i, PageEntry::new(frame.data() | flags.data()));
+                    table.set_entry(i, PageEntry::new(MMArch::make_entry(frame, flags.data())));
 
                     // 获取新分配的页表
                     table = table.next_level_table(i)?;
@@ -0,0 +1,76 @@
+//! 修改内核空间中已经映射的页的权限
+//!
+//! 内核的直接映射区在初始化完成之后遵循W^X：只有内核代码可以执行，可以执行的页都是只读的。
+//! 需要执行动态加载的代码(例如内核模块)时，使用这里的函数把对应的页改为只读、可执行，
+//! 释放之前再改回可写、不可执行。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/arch/x86/mm/pat/set_memory.c
+
+use crate::{arch::MMArch, syscall::SystemError};
+
+use super::{
+    allocator::page_frame::PageFrameCount,
+    kernel_mapper::KernelMapper,
+    page::{Flusher, InactiveFlusher, PageFlags, PageFlushAll},
+    MemoryManagementArch, VirtAddr,
+};
+
+/// 修改内核空间中一段连续的页的标志
+///
+/// ## 参数
+///
+/// - `addr`：起始地址，必须按页对齐
+/// - `count`：页的数量
+/// - `f`：根据页原来的标志计算新的标志
+///
+/// ## 错误
+///
+/// - `EINVAL`：地址没有按页对齐，或者其中有页没有被映射
+/// - `EAGAIN_OR_EWOULDBLOCK`：当前cpu已经持有内核映射器的锁
+fn change_page_flags(
+    addr: VirtAddr,
+    count: PageFrameCount,
+    f: impl Fn(PageFlags<MMArch>) -> PageFlags<MMArch>,
+) -> Result<(), SystemError> {
+    if !addr.check_aligned(MMArch::PAGE_SIZE) {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut kernel_mapper = KernelMapper::lock();
+    let mapper = kernel_mapper
+        .as_mut()
+        .ok_or(SystemError::EAGAIN_OR_EWOULDBLOCK)?;
+    let mut flusher: PageFlushAll<MMArch> = PageFlushAll::new();
+    for i in 0..count.data() {
+        let vaddr = addr + i * MMArch::PAGE_SIZE;
+        let (_, flags) = mapper.translate(vaddr).ok_or(SystemError::EINVAL)?;
+        let flush = unsafe { mapper.remap(vaddr, f(flags)) }.ok_or(SystemError::EINVAL)?;
+        flusher.consume(flush);
+    }
+    drop(flusher);
+    drop(kernel_mapper);
+
+    // 其它cpu的TLB中可能还缓存着旧的页表项
+    drop(InactiveFlusher::new());
+    return Ok(());
+}
+
+/// 把内核空间中的一段页设为只读
+pub fn set_memory_ro(addr: VirtAddr, count: PageFrameCount) -> Result<(), SystemError> {
+    return change_page_flags(addr, count, |flags| flags.set_write(false));
+}
+
+/// 把内核空间中的一段页设为可写
+pub fn set_memory_rw(addr: VirtAddr, count: PageFrameCount) -> Result<(), SystemError> {
+    return change_page_flags(addr, count, |flags| flags.set_write(true));
+}
+
+/// 把内核空间中的一段页设为可执行
+pub fn set_memory_x(addr: VirtAddr, count: PageFrameCount) -> Result<(), SystemError> {
+    return change_page_flags(addr, count, |flags| flags.set_execute(true));
+}
+
+/// 把内核空间中的一段页设为不可执行
+pub fn set_memory_nx(addr: VirtAddr, count: PageFrameCount) -> Result<(), SystemError> {
+    return change_page_flags(addr, count, |flags| flags.set_execute(false));
+}
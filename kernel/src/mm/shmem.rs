@@ -0,0 +1,211 @@
+//! 共享内存对象
+//!
+//! 以页为单位保存数据的内存区域，数据页可以被多个进程同时映射。
+//! ramfs的普通文件(包括/dev/shm下的文件以及memfd)使用它保存数据。
+//!
+//! 数据页在第一次被写入或者映射的时候才分配，还没有分配的页读出来全为0。
+//! 映射期间，VMA持有被映射的页，因此即使文件被截断或者删除，这些页也不会被提前释放。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/mm/shmem.c
+
+use core::any::Any;
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{arch::MMArch, libs::align::page_align_up, syscall::SystemError};
+
+use super::{
+    allocator::page_frame::{
+        allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
+    },
+    MemoryManagementArch,
+};
+
+/// 共享内存的一个数据页，被释放时把页帧归还给页帧分配器
+#[derive(Debug)]
+struct ShmemPage(PhysPageFrame);
+
+impl ShmemPage {
+    /// 分配一个全为0的数据页
+    fn new() -> Result<Arc<Self>, SystemError> {
+        let (paddr, _) =
+            unsafe { allocate_page_frames(PageFrameCount::new(1)) }.ok_or(SystemError::ENOMEM)?;
+        unsafe { MMArch::write_bytes(MMArch::phys_2_virt(paddr).unwrap(), 0, MMArch::PAGE_SIZE) };
+        return Ok(Arc::new(Self(PhysPageFrame::new(paddr))));
+    }
+
+    fn as_ptr(&self) -> *mut u8 {
+        return unsafe { MMArch::phys_2_virt(self.0.phys_address()) }
+            .unwrap()
+            .data() as *mut u8;
+    }
+}
+
+impl Drop for ShmemPage {
+    fn drop(&mut self) {
+        unsafe { deallocate_page_frames(self.0, PageFrameCount::new(1)) };
+    }
+}
+
+/// 映射共享内存时交给VMA持有的对象，映射期间数据页不会被释放
+#[derive(Debug)]
+struct ShmemMapping {
+    _pages: Vec<Arc<ShmemPage>>,
+    /// 可写的映射持有一个引用，用于统计可写映射的数量
+    _writable: Option<Arc<()>>,
+}
+
+/// 以页为单位保存数据的共享内存对象
+#[derive(Debug)]
+pub struct ShmemPages {
+    /// 数据页，为None的页还没有分配。页的数量可能超过size，多出来的页是被映射到文件末尾之后的页
+    pages: Vec<Option<Arc<ShmemPage>>>,
+    /// 数据的字节数
+    size: usize,
+    /// 每个可写的映射持有一个引用
+    writable_maps: Arc<()>,
+}
+
+impl ShmemPages {
+    pub fn new() -> Self {
+        return Self {
+            pages: Vec::new(),
+            size: 0,
+            writable_maps: Arc::new(()),
+        };
+    }
+
+    /// 数据的字节数
+    #[inline]
+    pub fn size(&self) -> usize {
+        return self.size;
+    }
+
+    /// 当前可写的映射的数量
+    #[inline]
+    pub fn writable_mappings(&self) -> usize {
+        return Arc::strong_count(&self.writable_maps) - 1;
+    }
+
+    /// 获取第`index`页，如果还没有分配，则分配它
+    fn page_or_alloc(&mut self, index: usize) -> Result<Arc<ShmemPage>, SystemError> {
+        if self.pages.len() <= index {
+            self.pages.resize(index + 1, None);
+        }
+        if self.pages[index].is_none() {
+            self.pages[index] = Some(ShmemPage::new()?);
+        }
+        return Ok(self.pages[index].clone().unwrap());
+    }
+
+    /// 调整数据的大小
+    ///
+    /// 变大时，新增的部分全为0；变小时，释放不再需要的数据页(仍然被映射的页在解除映射后释放)
+    pub fn resize(&mut self, size: usize) {
+        if size < self.size {
+            let page_count = page_align_up(size) / MMArch::PAGE_SIZE;
+            self.pages.truncate(page_count);
+            // 把最后一页中位于新的末尾之后的部分清零，使得再次变大时读出来的是0
+            let in_page = size % MMArch::PAGE_SIZE;
+            if in_page != 0 {
+                if let Some(Some(page)) = self.pages.last() {
+                    unsafe {
+                        page.as_ptr()
+                            .add(in_page)
+                            .write_bytes(0, MMArch::PAGE_SIZE - in_page)
+                    };
+                }
+            }
+        }
+        self.size = size;
+    }
+
+    /// 从`offset`处读取数据，最多读取`buf.len()`字节
+    ///
+    /// ## 返回值
+    ///
+    /// 读取的字节数。`offset`超过数据末尾时返回0
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if offset >= self.size {
+            return 0;
+        }
+        let len = core::cmp::min(buf.len(), self.size - offset);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let in_page = pos % MMArch::PAGE_SIZE;
+            let n = core::cmp::min(len - done, MMArch::PAGE_SIZE - in_page);
+            let dst = &mut buf[done..done + n];
+            match self.pages.get(pos / MMArch::PAGE_SIZE) {
+                Some(Some(page)) => unsafe {
+                    dst.as_mut_ptr()
+                        .copy_from_nonoverlapping(page.as_ptr().add(in_page), n)
+                },
+                _ => dst.fill(0),
+            }
+            done += n;
+        }
+        return len;
+    }
+
+    /// 把`buf`写入到`offset`处，写入的位置超过数据末尾时，数据的大小随之增长
+    ///
+    /// ## 返回值
+    ///
+    /// 写入的字节数
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOMEM`：无法分配数据页
+    pub fn write_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, SystemError> {
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let in_page = pos % MMArch::PAGE_SIZE;
+            let n = core::cmp::min(buf.len() - done, MMArch::PAGE_SIZE - in_page);
+            let page = match self.page_or_alloc(pos / MMArch::PAGE_SIZE) {
+                Ok(page) => page,
+                Err(e) if done == 0 => return Err(e),
+                Err(_) => break,
+            };
+            unsafe {
+                page.as_ptr()
+                    .add(in_page)
+                    .copy_from_nonoverlapping(buf[done..].as_ptr(), n)
+            };
+            done += n;
+        }
+        self.size = core::cmp::max(self.size, offset + done);
+        return Ok(done);
+    }
+
+    /// 获取用于mmap的页帧
+    ///
+    /// ## 参数
+    ///
+    /// - `offset`：映射的起始位置的偏移量，已经按页对齐
+    /// - `count`：要映射的页数
+    /// - `writable`：映射是否可写
+    ///
+    /// ## 返回值
+    ///
+    /// 页帧，以及需要由VMA持有的所有者对象
+    pub fn mmap_frames(
+        &mut self,
+        offset: usize,
+        count: PageFrameCount,
+        writable: bool,
+    ) -> Result<(Vec<PhysPageFrame>, Arc<dyn Any + Send + Sync>), SystemError> {
+        let first = offset / MMArch::PAGE_SIZE;
+        let mut pages = Vec::with_capacity(count.data());
+        for index in first..first + count.data() {
+            pages.push(self.page_or_alloc(index)?);
+        }
+        let frames = pages.iter().map(|page| page.0).collect();
+        let backing: Arc<dyn Any + Send + Sync> = Arc::new(ShmemMapping {
+            _pages: pages,
+            _writable: writable.then(|| self.writable_maps.clone()),
+        });
+        return Ok((frames, backing));
+    }
+}
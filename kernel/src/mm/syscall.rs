@@ -7,6 +7,7 @@ use crate::{
     kerror,
     libs::align::{check_aligned, page_align_up},
     mm::MemoryManagementArch,
+    process::ProcessManager,
     syscall::{Syscall, SystemError},
 };
 
@@ -107,8 +108,8 @@ impl Syscall {
     /// - `len`：映射的长度
     /// - `prot`：保护标志
     /// - `flags`：映射标志
-    /// - `fd`：文件描述符（暂时不支持）
-    /// - `offset`：文件偏移量 （暂时不支持）
+    /// - `fd`：文件描述符，仅用于文件映射
+    /// - `offset`：文件偏移量，必须按页对齐，仅用于文件映射
     ///
     /// ## 返回值
     ///
@@ -118,8 +119,8 @@ impl Syscall {
         len: usize,
         prot_flags: usize,
         map_flags: usize,
-        _fd: i32,
-        _offset: usize,
+        fd: i32,
+        offset: usize,
     ) -> Result<usize, SystemError> {
         let map_flags = MapFlags::from_bits_truncate(map_flags as u64);
         let prot_flags = ProtFlags::from_bits_truncate(prot_flags as u64);
@@ -133,18 +134,39 @@ impl Syscall {
             );
             return Err(SystemError::EINVAL);
         }
-        // 暂时不支持除匿名页以外的映射
-        if !map_flags.contains(MapFlags::MAP_ANONYMOUS) {
-            kerror!("mmap: not support file mapping");
-            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
-        }
-
         // 暂时不支持巨页映射
         if map_flags.contains(MapFlags::MAP_HUGETLB) {
             kerror!("mmap: not support huge page mapping");
             return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
         }
         let current_address_space = AddressSpace::current()?;
+
+        // 文件映射：由文件提供物理页帧
+        if !map_flags.contains(MapFlags::MAP_ANONYMOUS) {
+            if !check_aligned(offset, MMArch::PAGE_SIZE) {
+                return Err(SystemError::EINVAL);
+            }
+            let count = PageFrameCount::from_bytes(page_align_up(len)).unwrap();
+            if count.data() == 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let file = ProcessManager::current_pcb()
+                .fd_table()
+                .read()
+                .get_file_by_fd(fd)
+                .ok_or(SystemError::EBADF)?;
+            let inode = file.lock_no_preempt().inode();
+            let (frames, backing) = inode.mmap_frames(offset, count, prot_flags)?;
+            let start_page = current_address_space.write().map_frames(
+                start_vaddr,
+                frames,
+                prot_flags,
+                map_flags,
+                backing,
+            )?;
+            return Ok(start_page.virt_address().data());
+        }
+
         let start_page = current_address_space.write().map_anonymous(
             start_vaddr,
             len,
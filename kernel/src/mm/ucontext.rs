@@ -1,6 +1,7 @@
 // 进程的用户空间内存管理
 
 use core::{
+    any::Any,
     cmp,
     hash::Hasher,
     intrinsics::unlikely,
@@ -20,17 +21,23 @@ use crate::{
     exception::InterruptArch,
     libs::{
         align::page_align_up,
-        rwlock::{RwLock, RwLockWriteGuard},
+        rwlock::RwLock,
         spinlock::{SpinLock, SpinLockGuard},
     },
-    process::ProcessManager,
+    process::{
+        resource::{current_rlimit, RLimitID},
+        ProcessManager,
+    },
     syscall::SystemError,
+    time::vsyscall::vvar_map,
 };
 
 use super::{
     allocator::page_frame::{
         deallocate_page_frames, PageFrameCount, PhysPageFrame, VirtPageFrame, VirtPageFrameIter,
     },
+    aslr::{brk_rnd, current_randomized, mmap_rnd, stack_rnd},
+    fault::{anon_page_dup, anon_page_flags, anon_page_release, zero_page},
     page::{Flusher, InactiveFlusher, PageFlags, PageFlushAll},
     syscall::{MapFlags, ProtFlags},
     MemoryManagementArch, PageTableKind, VirtAddr, VirtRegion,
@@ -103,6 +110,8 @@ pub struct InnerAddressSpace {
     pub user_mapper: UserMapper,
     pub mappings: UserMappings,
     pub mmap_min: VirtAddr,
+    /// 没有指定映射的地址时，从这个地址开始寻找空闲的区域。地址空间布局随机化时，它高于mmap_min
+    pub mmap_base: VirtAddr,
     /// 用户栈信息结构体
     pub user_stack: Option<UserStack>,
 
@@ -126,6 +135,7 @@ impl InnerAddressSpace {
             user_mapper: MMArch::setup_new_usermapper()?,
             mappings: UserMappings::new(),
             mmap_min: VirtAddr(DEFAULT_MMAP_MIN_ADDR),
+            mmap_base: VirtAddr(DEFAULT_MMAP_MIN_ADDR),
             elf_brk_start: VirtAddr::new(0),
             elf_brk: VirtAddr::new(0),
             brk_start: MMArch::USER_BRK_START,
@@ -136,9 +146,20 @@ impl InnerAddressSpace {
             start_data: VirtAddr(0),
             end_data: VirtAddr(0),
         };
+        // 映射用户态读取时钟所需的vvar页
+        vvar_map(&mut result.user_mapper.utable)?;
+
         if create_stack {
+            // execve时，根据当前进程的标志决定是否随机化新的地址空间的布局
+            let mut stack_bottom = None;
+            if current_randomized() {
+                result.mmap_base = result.mmap_min + mmap_rnd();
+                result.brk_start = MMArch::USER_BRK_START + brk_rnd();
+                result.brk = result.brk_start;
+                stack_bottom = Some(UserStack::DEFAULT_USER_STACK_BOTTOM - stack_rnd());
+            }
             // kdebug!("to create user stack.");
-            result.new_user_stack(UserStack::DEFAULT_USER_STACK_SIZE)?;
+            result.new_user_stack(stack_bottom, UserStack::INITIAL_STACK_SIZE)?;
         }
 
         return Ok(result);
@@ -146,6 +167,8 @@ impl InnerAddressSpace {
 
     /// 尝试克隆当前进程的地址空间，包括这些映射都会被克隆
     ///
+    /// 匿名页不会被复制，而是由父子进程共享，任何一方写入时再进行写时复制
+    ///
     /// # Returns
     ///
     /// 返回克隆后的，新的地址空间的Arc指针
@@ -154,68 +177,52 @@ impl InnerAddressSpace {
         let new_addr_space = AddressSpace::new(false)?;
         let mut new_guard = new_addr_space.write();
 
-        // 拷贝用户栈的结构体信息，但是不拷贝用户栈的内容（因为后面VMA的拷贝会拷贝用户栈的内容）
+        // 子进程的地址空间布局与父进程相同
+        new_guard.mmap_base = self.mmap_base;
+        new_guard.elf_brk_start = self.elf_brk_start;
+        new_guard.elf_brk = self.elf_brk;
+        new_guard.brk_start = self.brk_start;
+        new_guard.brk = self.brk;
+        new_guard.start_code = self.start_code;
+        new_guard.end_code = self.end_code;
+        new_guard.start_data = self.start_data;
+        new_guard.end_data = self.end_data;
+
+        // 拷贝用户栈的结构体信息，但是不拷贝用户栈的内容（因为后面VMA的拷贝会共享用户栈的内容）
         unsafe {
             new_guard.user_stack = Some(self.user_stack.as_ref().unwrap().clone_info_only());
         }
-        let _current_stack_size = self.user_stack.as_ref().unwrap().stack_size();
 
         let current_mapper = &mut self.user_mapper.utable;
+        let mut flusher: PageFlushAll<MMArch> = PageFlushAll::new();
 
         for vma in self.mappings.vmas.iter() {
-            // TODO: 增加对VMA是否为文件映射的判断，如果是的话，就跳过
-
             let vma_guard: SpinLockGuard<'_, VMA> = vma.lock();
-            let old_flags = vma_guard.flags();
-            let tmp_flags: PageFlags<MMArch> = PageFlags::new().set_write(true);
-
-            // 分配内存页并创建新的VMA
-            let new_vma = VMA::zeroed(
-                VirtPageFrame::new(vma_guard.region.start()),
-                PageFrameCount::new(vma_guard.region.size() / MMArch::PAGE_SIZE),
-                tmp_flags,
+            if vma_guard.is_guard() {
+                let page_count = PageFrameCount::new(vma_guard.region.size() / MMArch::PAGE_SIZE);
+                let new_vma = VMA::guard(
+                    VirtPageFrame::new(vma_guard.region.start()),
+                    page_count,
+                    vma_guard.flags,
+                );
+                drop(vma_guard);
+                new_guard.mappings.insert_vma(new_vma);
+                continue;
+            }
+            // 映射的是别处的页帧(例如文件映射)，不拷贝到子进程
+            if !vma_guard.owns_frames {
+                continue;
+            }
+
+            let new_vma = vma_guard.share_anon_pages(
+                current_mapper,
+                &mut flusher,
                 &mut new_guard.user_mapper.utable,
-                (),
             )?;
-            new_guard.mappings.vmas.insert(new_vma.clone());
-            // kdebug!("new vma: {:x?}", new_vma);
-            let mut new_vma_guard = new_vma.lock();
-            for page in new_vma_guard.pages().map(|p| p.virt_address()) {
-                // kdebug!("page: {:x?}", page);
-                let current_frame = unsafe {
-                    MMArch::phys_2_virt(
-                        current_mapper
-                            .translate(page)
-                            .expect("VMA page not mapped")
-                            .0,
-                    )
-                }
-                .expect("Phys2Virt: vaddr overflow.")
-                .data() as *mut u8;
-
-                let new_frame = unsafe {
-                    MMArch::phys_2_virt(
-                        new_guard
-                            .user_mapper
-                            .utable
-                            .translate(page)
-                            .expect("VMA page not mapped")
-                            .0,
-                    )
-                }
-                .expect("Phys2Virt: vaddr overflow.")
-                .data() as *mut u8;
-
-                unsafe {
-                    // 拷贝数据
-                    new_frame.copy_from_nonoverlapping(current_frame, MMArch::PAGE_SIZE);
-                }
-            }
             drop(vma_guard);
-
-            new_vma_guard.remap(old_flags, &mut new_guard.user_mapper.utable, ())?;
-            drop(new_vma_guard);
+            new_guard.mappings.insert_vma(new_vma);
         }
+        drop(flusher);
         drop(new_guard);
         drop(irq_guard);
         return Ok(new_addr_space);
@@ -275,7 +282,53 @@ impl InnerAddressSpace {
             prot_flags,
             map_flags,
             move |page, count, flags, mapper, flusher| {
-                Ok(VMA::zeroed(page, count, flags, mapper, flusher)?)
+                if map_flags.contains(MapFlags::MAP_POPULATE) {
+                    Ok(VMA::zeroed(page, count, flags, mapper, flusher)?)
+                } else {
+                    // 先映射到零页，第一次写入时再分配物理页
+                    Ok(VMA::zero_mapped(page, count, flags, mapper, flusher)?)
+                }
+            },
+        )?;
+
+        return Ok(start_page);
+    }
+
+    /// 把已有的物理页帧映射到进程的地址空间，用于文件映射
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：映射的起始地址，为0时由内核选择
+    /// - `frames`：要映射的页帧，按顺序映射到连续的虚拟页
+    /// - `prot_flags`：保护标志
+    /// - `map_flags`：映射标志
+    /// - `backing`：页帧的所有者。VMA会持有它直到解除映射，防止页帧被提前释放
+    ///
+    /// ## 返回
+    ///
+    /// 返回映射的起始虚拟页帧
+    pub fn map_frames(
+        &mut self,
+        start_vaddr: VirtAddr,
+        frames: Vec<PhysPageFrame>,
+        prot_flags: ProtFlags,
+        map_flags: MapFlags,
+        backing: Arc<dyn Any + Send + Sync>,
+    ) -> Result<VirtPageFrame, SystemError> {
+        let hint = match start_vaddr.data() & (!MMArch::PAGE_OFFSET_MASK) {
+            0 => None,
+            addr => Some(VirtAddr::new(addr)),
+        };
+
+        let start_page: VirtPageFrame = self.mmap(
+            hint,
+            PageFrameCount::new(frames.len()),
+            prot_flags,
+            map_flags,
+            move |page, _count, flags, mapper, flusher| {
+                Ok(VMA::map_frames(
+                    &frames, page, flags, mapper, flusher, backing,
+                )?)
             },
         )?;
 
@@ -324,11 +377,11 @@ impl InnerAddressSpace {
         let region = match addr {
             Some(vaddr) => {
                 self.mappings
-                    .find_free_at(self.mmap_min, vaddr, page_count.bytes(), map_flags)?
+                    .find_free_at(self.mmap_base, vaddr, page_count.bytes(), map_flags)?
             }
             None => self
                 .mappings
-                .find_free(self.mmap_min, page_count.bytes())
+                .find_free(self.mmap_base, page_count.bytes())
                 .ok_or(SystemError::ENOMEM)?,
         };
 
@@ -472,10 +525,15 @@ impl InnerAddressSpace {
     ///
     /// ## 参数
     ///
+    /// - `stack_bottom`：栈底地址，为None时使用默认的栈底地址
     /// - `size`：栈的大小
-    pub fn new_user_stack(&mut self, size: usize) -> Result<(), SystemError> {
+    pub fn new_user_stack(
+        &mut self,
+        stack_bottom: Option<VirtAddr>,
+        size: usize,
+    ) -> Result<(), SystemError> {
         assert!(self.user_stack.is_none(), "User stack already exists");
-        let stack = UserStack::new(self, None, size)?;
+        let stack = UserStack::new(self, stack_bottom, size)?;
         self.user_stack = Some(stack);
         return Ok(());
     }
@@ -485,6 +543,28 @@ impl InnerAddressSpace {
         return self.user_stack.as_mut();
     }
 
+    /// 访问用户栈下方的地址导致缺页异常时，扩展用户栈，使它包含这个地址
+    ///
+    /// ## 参数
+    ///
+    /// - `address`：导致异常的虚拟地址
+    ///
+    /// ## 错误
+    ///
+    /// - `EFAULT`：地址不在用户栈的下方，或者用户栈无法扩展到这个地址。调用者需要把它当作非法访问处理
+    pub fn expand_stack(&mut self, address: VirtAddr) -> Result<(), SystemError> {
+        let mut stack = self.user_stack.take().ok_or(SystemError::EFAULT)?;
+        let stack_start = stack.stack_start();
+        let r = if address < stack_start {
+            let bytes = stack_start.data() - (address.data() & MMArch::PAGE_MASK);
+            stack.extend(self, bytes).map_err(|_| SystemError::EFAULT)
+        } else {
+            Err(SystemError::EFAULT)
+        };
+        self.user_stack = Some(stack);
+        return r;
+    }
+
     /// 取消用户空间内的所有映射
     pub unsafe fn unmap_all(&mut self) {
         let mut flusher: PageFlushAll<MMArch> = PageFlushAll::new();
@@ -513,7 +593,7 @@ impl InnerAddressSpace {
 
         if new_brk > self.brk {
             let len = new_brk - self.brk;
-            let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC;
+            let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
             let map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED;
             self.map_anonymous(old_brk, len, prot_flags, map_flags, true)?;
 
@@ -608,7 +688,6 @@ impl UserMappings {
     /// 判断当前进程的VMA内，是否有包含指定的虚拟地址的VMA。
     ///
     /// 如果有，返回包含指定虚拟地址的VMA的Arc指针，否则返回None。
-    #[allow(dead_code)]
     pub fn contains(&self, vaddr: VirtAddr) -> Option<Arc<LockedVMA>> {
         for v in self.vmas.iter() {
             let guard = v.lock();
@@ -831,13 +910,18 @@ impl LockedVMA {
         mut flusher: impl Flusher<MMArch>,
     ) -> Result<(), SystemError> {
         let mut guard = self.lock();
-        assert!(guard.mapped);
+        // 保护页没有映射页帧，只需要修改标志
+        if guard.is_guard() {
+            guard.flags = flags;
+            return Ok(());
+        }
         for page in guard.region.pages() {
             // 暂时要求所有的页帧都已经映射到页表
             // TODO: 引入Lazy Mapping, 通过缺页中断来映射页帧，这里就不必要求所有的页帧都已经映射到页表了
+            let page_flags = guard.pte_flags(mapper, page.virt_address(), flags);
             let r = unsafe {
                 mapper
-                    .remap(page.virt_address(), flags)
+                    .remap(page.virt_address(), page_flags)
                     .expect("Failed to remap, beacuse of some page is not mapped")
             };
             flusher.consume(r);
@@ -850,24 +934,24 @@ impl LockedVMA {
         // todo: 如果当前vma与文件相关，完善文件相关的逻辑
 
         let mut guard = self.lock();
-        assert!(guard.mapped);
+        if guard.is_guard() {
+            return;
+        }
         for page in guard.region.pages() {
             let (paddr, _, flush) = unsafe { mapper.unmap_phys(page.virt_address(), true) }
                 .expect("Failed to unmap, beacuse of some page is not mapped");
+            flusher.consume(flush);
 
-            // todo: 获取物理页的anon_vma的守卫
-
-            // todo: 从anon_vma中删除当前VMA
-
-            // todo: 如果物理页的anon_vma链表长度为0，则释放物理页.
-
-            // 目前由于还没有实现共享页，所以直接释放物理页也没问题。
-            // 但是在实现共享页之后，就不能直接释放物理页了，需要在anon_vma链表长度为0的时候才能释放物理页
-            unsafe { deallocate_page_frames(PhysPageFrame::new(paddr), PageFrameCount::new(1)) };
+            // 页帧不属于这个VMA，由它的所有者负责释放
+            if !guard.owns_frames {
+                continue;
+            }
 
-            flusher.consume(flush);
+            // 匿名页可能还被fork出的其它进程共享，最后一个映射解除时才会被释放
+            anon_page_release(paddr);
         }
         guard.mapped = false;
+        guard.backing = None;
     }
 
     pub fn mapped(&self) -> bool {
@@ -946,6 +1030,10 @@ pub struct VMA {
     /// VMA所属的用户地址空间
     user_address_space: Option<Weak<AddressSpace>>,
     self_ref: Weak<LockedVMA>,
+    /// VMA内的页帧是否由VMA自己分配。为false时，解除映射不会释放页帧
+    owns_frames: bool,
+    /// 页帧的所有者，在解除映射之前保持它存活
+    backing: Option<Arc<dyn Any + Send + Sync>>,
 }
 
 impl core::hash::Hash for VMA {
@@ -962,6 +1050,11 @@ impl VMA {
         return &self.region;
     }
 
+    /// 页帧的所有者，只有映射外部页帧的VMA才有
+    pub fn backing(&self) -> Option<&Arc<dyn Any + Send + Sync>> {
+        return self.backing.as_ref();
+    }
+
     /// # 拷贝当前VMA的内容
     ///
     /// ### 安全性
@@ -974,6 +1067,8 @@ impl VMA {
             mapped: self.mapped,
             user_address_space: self.user_address_space.clone(),
             self_ref: self.self_ref.clone(),
+            owns_frames: self.owns_frames,
+            backing: self.backing.clone(),
         };
     }
 
@@ -982,6 +1077,36 @@ impl VMA {
         return self.flags;
     }
 
+    /// VMA内的页帧是否由VMA自己分配(匿名映射)
+    #[inline(always)]
+    pub fn owns_frames(&self) -> bool {
+        return self.owns_frames;
+    }
+
+    /// VMA是否为保护页。地址空间中只有保护页不映射任何页帧
+    #[inline(always)]
+    pub fn is_guard(&self) -> bool {
+        return !self.mapped;
+    }
+
+    /// 计算VMA内的某一页的页表项实际使用的标志
+    ///
+    /// 对于匿名映射，被共享的页(包括零页)需要保持只读，写入时进行写时复制
+    fn pte_flags(
+        &self,
+        mapper: &PageMapper,
+        vaddr: VirtAddr,
+        flags: PageFlags<MMArch>,
+    ) -> PageFlags<MMArch> {
+        if !self.owns_frames {
+            return flags;
+        }
+        let (paddr, _) = mapper
+            .translate(vaddr)
+            .expect("Failed to remap, beacuse of some page is not mapped");
+        return anon_page_flags(paddr, flags);
+    }
+
     pub fn pages(&self) -> VirtPageFrameIter {
         return VirtPageFrameIter::new(
             VirtPageFrame::new(self.region.start()),
@@ -995,14 +1120,18 @@ impl VMA {
         mapper: &mut PageMapper,
         mut flusher: impl Flusher<MMArch>,
     ) -> Result<(), SystemError> {
-        assert!(self.mapped);
+        if self.is_guard() {
+            self.flags = flags;
+            return Ok(());
+        }
         for page in self.region.pages() {
             // kdebug!("remap page {:?}", page.virt_address());
             // 暂时要求所有的页帧都已经映射到页表
             // TODO: 引入Lazy Mapping, 通过缺页中断来映射页帧，这里就不必要求所有的页帧都已经映射到页表了
+            let page_flags = self.pte_flags(mapper, page.virt_address(), flags);
             let r = unsafe {
                 mapper
-                    .remap(page.virt_address(), flags)
+                    .remap(page.virt_address(), page_flags)
                     .expect("Failed to remap, beacuse of some page is not mapped")
             };
             // kdebug!("consume page {:?}", page.virt_address());
@@ -1070,6 +1199,168 @@ impl VMA {
             mapped: true,
             user_address_space: None,
             self_ref: Weak::default(),
+            owns_frames: false,
+            backing: None,
+        });
+        return Ok(r);
+    }
+
+    /// 把一组不一定连续的物理页帧依次映射到虚拟地址
+    ///
+    /// 页帧仍然属于`backing`，解除映射时不会释放
+    ///
+    /// @param frames 要映射的物理页帧
+    /// @param destination 要映射到的虚拟地址
+    /// @param flags 页面标志位
+    /// @param mapper 页表映射器
+    /// @param flusher 页表项刷新器
+    /// @param backing 页帧的所有者
+    ///
+    /// @return 返回映射后的虚拟内存区域
+    pub fn map_frames(
+        frames: &[PhysPageFrame],
+        destination: VirtPageFrame,
+        flags: PageFlags<MMArch>,
+        mapper: &mut PageMapper,
+        mut flusher: impl Flusher<MMArch>,
+        backing: Arc<dyn Any + Send + Sync>,
+    ) -> Result<Arc<LockedVMA>, SystemError> {
+        let mut cur_dest = destination;
+        for frame in frames.iter() {
+            let r =
+                unsafe { mapper.map_phys(cur_dest.virt_address(), frame.phys_address(), flags) }
+                    .expect("Failed to map phys, may be OOM error");
+            flusher.consume(r);
+            cur_dest = cur_dest.next();
+        }
+
+        let r: Arc<LockedVMA> = LockedVMA::new(VMA {
+            region: VirtRegion::new(destination.virt_address(), frames.len() * MMArch::PAGE_SIZE),
+            flags,
+            mapped: true,
+            user_address_space: None,
+            self_ref: Weak::default(),
+            owns_frames: false,
+            backing: Some(backing),
+        });
+        return Ok(r);
+    }
+
+    /// 把指定的虚拟地址只读地映射到零页，然后创建VMA
+    ///
+    /// 第一次写入某一页时，缺页异常处理函数才会为它分配物理页
+    ///
+    /// @param destination 要映射到的虚拟地址
+    /// @param count 要映射的页帧数量
+    /// @param flags 页面标志位
+    /// @param mapper 页表映射器
+    /// @param flusher 页表项刷新器
+    ///
+    /// @return 返回映射后的虚拟内存区域
+    pub fn zero_mapped(
+        destination: VirtPageFrame,
+        page_count: PageFrameCount,
+        flags: PageFlags<MMArch>,
+        mapper: &mut PageMapper,
+        mut flusher: impl Flusher<MMArch>,
+    ) -> Result<Arc<LockedVMA>, SystemError> {
+        let pte_flags = anon_page_flags(zero_page(), flags);
+        let mut cur_dest: VirtPageFrame = destination;
+        for _ in 0..page_count.data() {
+            let r = unsafe { mapper.map_phys(cur_dest.virt_address(), zero_page(), pte_flags) }
+                .expect("Failed to map zero page, may be OOM error");
+            flusher.consume(r);
+            cur_dest = cur_dest.next();
+        }
+
+        let r = LockedVMA::new(VMA {
+            region: VirtRegion::new(
+                destination.virt_address(),
+                page_count.data() * MMArch::PAGE_SIZE,
+            ),
+            flags,
+            mapped: true,
+            user_address_space: None,
+            self_ref: Weak::default(),
+            owns_frames: true,
+            backing: None,
+        });
+        return Ok(r);
+    }
+
+    /// 创建一段不映射任何页帧的保护页
+    ///
+    /// 保护页占据用户栈下方的地址，使其它映射不会紧挨着用户栈。访问保护页总是会触发缺页异常，
+    /// 由缺页异常处理函数决定扩展用户栈，还是把它当作非法访问
+    ///
+    /// @param destination 保护页的起始地址
+    /// @param page_count 保护页的数量
+    /// @param flags 页面标志位
+    ///
+    /// @return 返回保护页对应的虚拟内存区域
+    pub fn guard(
+        destination: VirtPageFrame,
+        page_count: PageFrameCount,
+        flags: PageFlags<MMArch>,
+    ) -> Arc<LockedVMA> {
+        return LockedVMA::new(VMA {
+            region: VirtRegion::new(
+                destination.virt_address(),
+                page_count.data() * MMArch::PAGE_SIZE,
+            ),
+            flags,
+            mapped: false,
+            user_address_space: None,
+            self_ref: Weak::default(),
+            owns_frames: false,
+            backing: None,
+        });
+    }
+
+    /// fork时，让子进程共享这个匿名映射内的页
+    ///
+    /// 父子进程都只读地映射这些页，任何一方写入时再进行写时复制
+    ///
+    /// @param mapper 当前VMA所在的页表
+    /// @param flusher 当前VMA所在的页表的刷新器
+    /// @param new_mapper 子进程的页表
+    ///
+    /// @return 返回子进程中对应的虚拟内存区域
+    pub fn share_anon_pages(
+        &self,
+        mapper: &mut PageMapper,
+        mut flusher: impl Flusher<MMArch>,
+        new_mapper: &mut PageMapper,
+    ) -> Result<Arc<LockedVMA>, SystemError> {
+        assert!(self.mapped && self.owns_frames);
+        let cow_flags = self.flags.set_write(false);
+        for page in self.region.pages() {
+            let vaddr = page.virt_address();
+            let (paddr, _) = mapper.translate(vaddr).expect("VMA page not mapped");
+            anon_page_dup(paddr);
+
+            unsafe {
+                let r = mapper
+                    .remap(vaddr, cow_flags)
+                    .expect("Failed to remap, beacuse of some page is not mapped");
+                flusher.consume(r);
+
+                // 子进程的页表还没有被加载，不需要刷新TLB
+                new_mapper
+                    .map_phys(vaddr, paddr, cow_flags)
+                    .expect("Failed to map phys, may be OOM error")
+                    .ignore();
+            }
+        }
+
+        let r = LockedVMA::new(VMA {
+            region: self.region,
+            flags: self.flags,
+            mapped: true,
+            user_address_space: None,
+            self_ref: Weak::default(),
+            owns_frames: true,
+            backing: None,
         });
         return Ok(r);
     }
@@ -1118,6 +1409,8 @@ impl VMA {
             mapped: true,
             user_address_space: None,
             self_ref: Weak::default(),
+            owns_frames: true,
+            backing: None,
         });
         drop(flusher);
         // kdebug!("VMA::zeroed: flusher dropped");
@@ -1180,10 +1473,14 @@ impl UserStack {
     pub const DEFAULT_USER_STACK_BOTTOM: VirtAddr = MMArch::USER_STACK_START;
     /// 默认的用户栈大小为8MB
     pub const DEFAULT_USER_STACK_SIZE: usize = 8 * 1024 * 1024;
-    /// 用户栈的保护页数量
+    /// execve时预先分配的用户栈大小，之后用户栈在访问保护页时自动扩展
+    pub const INITIAL_STACK_SIZE: usize = 256 * 1024;
+    /// 用户栈下方的保护页数量
     pub const GUARD_PAGES_NUM: usize = 4;
 
     /// 创建一个用户栈
+    ///
+    /// 用户栈占据`[stack_bottom - stack_size, stack_bottom)`，它的下方紧挨着保护页
     pub fn new(
         vm: &mut InnerAddressSpace,
         stack_bottom: Option<VirtAddr>,
@@ -1192,55 +1489,16 @@ impl UserStack {
         let stack_bottom = stack_bottom.unwrap_or(Self::DEFAULT_USER_STACK_BOTTOM);
         assert!(stack_bottom.check_aligned(MMArch::PAGE_SIZE));
 
-        // 分配用户栈的保护页
-        let guard_size = Self::GUARD_PAGES_NUM * MMArch::PAGE_SIZE;
-        let actual_stack_bottom = stack_bottom - guard_size;
-
-        let mut prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
-        let map_flags =
-            MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED_NOREPLACE;
-        // kdebug!(
-        //     "map anonymous stack: {:?} {}",
-        //     actual_stack_bottom,
-        //     guard_size
-        // );
-        vm.map_anonymous(
-            actual_stack_bottom,
-            guard_size,
-            prot_flags,
-            map_flags,
-            false,
-        )?;
-        // test_buddy();
-        // 设置保护页只读
-        prot_flags.remove(ProtFlags::PROT_WRITE);
-        // kdebug!(
-        //     "to mprotect stack guard pages: {:?} {}",
-        //     actual_stack_bottom,
-        //     guard_size
-        // );
-        vm.mprotect(
-            VirtPageFrame::new(actual_stack_bottom),
-            PageFrameCount::new(Self::GUARD_PAGES_NUM),
-            prot_flags,
-        )?;
-
-        // kdebug!(
-        //     "mprotect stack guard pages done: {:?} {}",
-        //     actual_stack_bottom,
-        //     guard_size
-        // );
-
         let mut user_stack = UserStack {
-            stack_bottom: actual_stack_bottom,
-            mapped_size: guard_size,
-            current_sp: actual_stack_bottom - guard_size,
+            stack_bottom,
+            mapped_size: 0,
+            current_sp: stack_bottom,
         };
 
-        // kdebug!("extend user stack: {:?} {}", stack_bottom, stack_size);
         // 分配用户栈
         user_stack.initial_extend(vm, stack_size)?;
-        // kdebug!("user stack created: {:?} {}", stack_bottom, stack_size);
+        // 分配用户栈的保护页
+        user_stack.map_guard(vm)?;
         return Ok(user_stack);
     }
 
@@ -1249,8 +1507,9 @@ impl UserStack {
         vm: &mut InnerAddressSpace,
         mut bytes: usize,
     ) -> Result<(), SystemError> {
-        let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC;
-        let map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS;
+        let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+        // execve在持有地址空间的锁时把参数写到用户栈上，因此用户栈需要立即分配物理页
+        let map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_POPULATE;
 
         bytes = page_align_up(bytes);
         self.mapped_size += bytes;
@@ -1266,8 +1525,35 @@ impl UserStack {
         return Ok(());
     }
 
+    /// 用户栈当前的最低地址
+    pub fn stack_start(&self) -> VirtAddr {
+        return self.stack_bottom - self.mapped_size;
+    }
+
+    /// 保护页占据的区域，它紧挨着用户栈的最低地址
+    fn guard_region(&self) -> VirtRegion {
+        let guard_size = Self::GUARD_PAGES_NUM * MMArch::PAGE_SIZE;
+        return VirtRegion::new(self.stack_start() - guard_size, guard_size);
+    }
+
+    /// 在用户栈的下方映射保护页
+    fn map_guard(&self, vm: &mut InnerAddressSpace) -> Result<(), SystemError> {
+        let map_flags =
+            MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED_NOREPLACE;
+        vm.mmap(
+            Some(self.guard_region().start()),
+            PageFrameCount::new(Self::GUARD_PAGES_NUM),
+            ProtFlags::empty(),
+            map_flags,
+            |page, count, flags, _mapper, _flusher| Ok(VMA::guard(page, count, flags)),
+        )?;
+        return Ok(());
+    }
+
     /// 扩展用户栈
     ///
+    /// 扩展出的部分先映射到零页，第一次写入时才分配物理页。保护页随之下移，继续紧挨着用户栈
+    ///
     /// ## 参数
     ///
     /// - `vm` 用户地址空间结构体
@@ -1277,26 +1563,43 @@ impl UserStack {
     ///
     /// - **Ok(())** 扩展成功
     /// - **Err(SystemError)** 扩展失败
-    #[allow(dead_code)]
-    pub fn extend(
-        &mut self,
-        vm: &mut RwLockWriteGuard<InnerAddressSpace>,
-        mut bytes: usize,
-    ) -> Result<(), SystemError> {
-        let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC;
-        let map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS;
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOMEM`：扩展后的大小超过了当前进程的RLIMIT_STACK，或者用户栈会与其它映射重叠
+    pub fn extend(&mut self, vm: &mut InnerAddressSpace, bytes: usize) -> Result<(), SystemError> {
+        let bytes = page_align_up(bytes);
+        let guard_size = Self::GUARD_PAGES_NUM * MMArch::PAGE_SIZE;
+        let new_size = self.mapped_size + bytes;
+        if new_size as u64 > current_rlimit(RLimitID::Stack)
+            || self.stack_bottom.data() < vm.mmap_min.data() + new_size + guard_size
+        {
+            return Err(SystemError::ENOMEM);
+        }
 
-        bytes = page_align_up(bytes);
-        self.mapped_size += bytes;
+        // 用户栈和保护页新占据的区域，不能与其它映射重叠
+        let old_guard = self.guard_region();
+        let new_start = self.stack_bottom - new_size;
+        let grown = VirtRegion::new(
+            new_start - guard_size,
+            old_guard.start().data() - (new_start.data() - guard_size),
+        );
+        if vm.mappings.conflicts(grown).next().is_some() {
+            return Err(SystemError::ENOMEM);
+        }
 
-        vm.map_anonymous(
-            self.stack_bottom - self.mapped_size,
-            bytes,
-            prot_flags,
-            map_flags,
-            false,
+        vm.munmap(
+            VirtPageFrame::new(old_guard.start()),
+            PageFrameCount::new(Self::GUARD_PAGES_NUM),
         )?;
 
+        let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+        let map_flags =
+            MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED_NOREPLACE;
+        vm.map_anonymous(new_start, bytes, prot_flags, map_flags, false)?;
+        self.mapped_size = new_size;
+
+        self.map_guard(vm)?;
         return Ok(());
     }
 
@@ -1322,6 +1625,6 @@ impl UserStack {
 
     /// 获取当前用户栈的大小（不包括保护页）
     pub fn stack_size(&self) -> usize {
-        return self.mapped_size - Self::GUARD_PAGES_NUM * MMArch::PAGE_SIZE;
+        return self.mapped_size;
     }
 }
@@ -0,0 +1,74 @@
+//! 内核导出给可加载模块的符号表
+//!
+//! 模块只能引用通过[`export_symbol!`](crate::export_symbol)导出的符号。每次导出都会在`__ksymtab`段中登记一个
+//! [`KernelSymbol`]，链接器把它们收集到一起。被导出的函数应当使用C调用约定，并且不能被内联，
+//! 因为模块中的代码按照符号名，以C调用约定调用它们。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/export.h
+
+/// 一个导出的符号
+#[repr(C)]
+#[derive(Debug)]
+pub struct KernelSymbol {
+    name: &'static str,
+    addr: *const (),
+}
+
+// 符号表在链接之后就不会再被修改
+unsafe impl Sync for KernelSymbol {}
+
+impl KernelSymbol {
+    pub const fn new(name: &'static str, addr: *const ()) -> Self {
+        Self { name, addr }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr as usize
+    }
+}
+
+/// 导出一个函数或者静态变量，供可加载模块使用
+///
+/// ```ignore
+/// #[no_mangle]
+/// pub unsafe extern "C" fn kmalloc(size: usize, gfp: gfp_t) -> usize { ... }
+/// export_symbol!(kmalloc);
+/// ```
+#[macro_export]
+macro_rules! export_symbol {
+    ($sym:ident) => {
+        const _: () = {
+            #[link_section = "__ksymtab"]
+            #[used]
+            static __KSYMTAB_ENTRY: $crate::module::ksymtab::KernelSymbol =
+                $crate::module::ksymtab::KernelSymbol::new(stringify!($sym), $sym as *const ());
+        };
+    };
+}
+
+extern "C" {
+    static __start___ksymtab: KernelSymbol;
+    static __stop___ksymtab: KernelSymbol;
+}
+
+/// 获取链接器收集的所有导出符号
+pub fn kernel_symbols() -> &'static [KernelSymbol] {
+    unsafe {
+        let start = &__start___ksymtab as *const KernelSymbol;
+        let end = &__stop___ksymtab as *const KernelSymbol;
+        let len = (end as usize - start as usize) / core::mem::size_of::<KernelSymbol>();
+        return core::slice::from_raw_parts(start, len);
+    }
+}
+
+/// 在内核导出的符号中查找符号的地址
+pub fn find_kernel_symbol(name: &str) -> Option<usize> {
+    return kernel_symbols()
+        .iter()
+        .find(|sym| sym.name() == name)
+        .map(|sym| sym.addr());
+}
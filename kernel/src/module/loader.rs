@@ -0,0 +1,292 @@
+//! 把模块的ELF文件加载到内存中，并完成重定位
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/module/main.c
+
+use core::sync::atomic::AtomicUsize;
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use elf::{
+    abi::{
+        ET_REL, SHF_ALLOC, SHF_EXECINSTR, SHN_ABS, SHN_COMMON, SHN_UNDEF, SHT_NOBITS, SHT_REL,
+        SHT_RELA, STB_LOCAL, STB_WEAK,
+    },
+    endian::AnyEndian,
+    file::Class,
+    section::SectionHeader,
+    ElfBytes,
+};
+
+use crate::{
+    arch::{
+        module::{apply_relocate_add, MODULE_ELF_MACHINE, MODULE_PHYS_LIMIT},
+        MMArch,
+    },
+    kerror,
+    libs::spinlock::SpinLock,
+    mm::{
+        allocator::page_frame::{
+            allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
+        },
+        MemoryManagementArch,
+    },
+    syscall::SystemError,
+};
+
+use super::{find_symbol, Module, ModuleMemory, ModuleState};
+
+/// 分配到的内存超出[`MODULE_PHYS_LIMIT`]时，最多重试的次数
+const MODULE_ALLOC_RETRIES: usize = 64;
+
+/// 分配存放模块的内存，物理地址低于[`MODULE_PHYS_LIMIT`]，并且已经清零
+fn module_alloc(size: usize) -> Result<ModuleMemory, SystemError> {
+    let pages = (size + MMArch::PAGE_SIZE - 1) / MMArch::PAGE_SIZE;
+    let count = PageFrameCount::new(pages.next_power_of_two());
+    let mut rejected = Vec::new();
+    let mut result = Err(SystemError::ENOMEM);
+    for _ in 0..MODULE_ALLOC_RETRIES {
+        let (paddr, _) = match unsafe { allocate_page_frames(count) } {
+            Some(r) => r,
+            None => break,
+        };
+        if paddr.data() + count.data() * MMArch::PAGE_SIZE > MODULE_PHYS_LIMIT {
+            rejected.push(paddr);
+            continue;
+        }
+        let mem = ModuleMemory {
+            paddr,
+            count,
+            text_pages: PageFrameCount::new(0),
+        };
+        unsafe {
+            core::ptr::write_bytes(mem.base() as *mut u8, 0, count.data() * MMArch::PAGE_SIZE)
+        };
+        result = Ok(mem);
+        break;
+    }
+    for paddr in rejected {
+        unsafe { deallocate_page_frames(PhysPageFrame::new(paddr), count) };
+    }
+    return result;
+}
+
+/// 获取段的内容
+fn section_data<'a>(
+    elf: &ElfBytes<'a, AnyEndian>,
+    shdr: &SectionHeader,
+) -> Result<&'a [u8], SystemError> {
+    match elf.section_data(shdr) {
+        Ok((data, None)) => Ok(data),
+        _ => Err(SystemError::ENOEXEC),
+    }
+}
+
+/// 把'\0'分隔的字符串表拆分成字符串
+fn split_strings(data: &[u8]) -> impl Iterator<Item = &str> {
+    return data
+        .split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| core::str::from_utf8(s).ok());
+}
+
+impl Module {
+    /// 加载模块，得到一个还没有初始化的模块
+    ///
+    /// ## 参数
+    ///
+    /// - `image`：模块的ELF文件
+    /// - `modules`：已经加载的模块，用于解析模块引用的符号
+    pub(super) fn load(image: &[u8], modules: &[Arc<Module>]) -> Result<Self, SystemError> {
+        let elf = ElfBytes::<AnyEndian>::minimal_parse(image).map_err(|_| SystemError::ENOEXEC)?;
+        if elf.ehdr.class != Class::ELF64
+            || elf.ehdr.e_type != ET_REL
+            || elf.ehdr.e_machine != MODULE_ELF_MACHINE
+        {
+            return Err(SystemError::ENOEXEC);
+        }
+        let (shdrs, shstrtab) = match elf.section_headers_with_strtab() {
+            Ok((Some(shdrs), Some(shstrtab))) => (shdrs, shstrtab),
+            _ => return Err(SystemError::ENOEXEC),
+        };
+        let shdrs: Vec<SectionHeader> = shdrs.iter().collect();
+        let find_section = |name: &str| {
+            shdrs
+                .iter()
+                .find(|shdr| shstrtab.get(shdr.sh_name as usize) == Ok(name))
+        };
+
+        let modinfo = find_section(".modinfo").ok_or(SystemError::ENOEXEC)?;
+        let name = split_strings(section_data(&elf, modinfo)?)
+            .find_map(|s| s.strip_prefix("name="))
+            .ok_or(SystemError::ENOEXEC)?
+            .to_string();
+        if modules.iter().any(|m| m.name == name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        // 把所有SHF_ALLOC段依次排列在一块内存中。代码段排在前面，与其余的段不共用页，
+        // 这样重定位之后可以只把代码段所在的页设为可执行
+        let mut offsets = vec![None; shdrs.len()];
+        let mut size = 0;
+        let mut text_size = 0;
+        for exec in [true, false] {
+            for (i, shdr) in shdrs.iter().enumerate() {
+                if shdr.sh_flags & SHF_ALLOC as u64 == 0 || shdr.sh_size == 0 {
+                    continue;
+                }
+                if (shdr.sh_flags & SHF_EXECINSTR as u64 != 0) != exec {
+                    continue;
+                }
+                let align = (shdr.sh_addralign as usize).max(1);
+                if !align.is_power_of_two() || align > MMArch::PAGE_SIZE {
+                    return Err(SystemError::ENOEXEC);
+                }
+                size = (size + align - 1) & !(align - 1);
+                offsets[i] = Some(size);
+                size += shdr.sh_size as usize;
+            }
+            if exec {
+                size = (size + MMArch::PAGE_SIZE - 1) & !(MMArch::PAGE_SIZE - 1);
+                text_size = size;
+            }
+        }
+        if size == 0 {
+            return Err(SystemError::ENOEXEC);
+        }
+        let mut mem = module_alloc(size)?;
+        let base = mem.base();
+        let mut addrs = vec![0usize; shdrs.len()];
+        for (i, shdr) in shdrs.iter().enumerate() {
+            let offset = match offsets[i] {
+                Some(offset) => offset,
+                None => continue,
+            };
+            addrs[i] = base + offset;
+            if shdr.sh_type != SHT_NOBITS {
+                let data = section_data(&elf, shdr)?;
+                unsafe {
+                    core::ptr::copy_nonoverlapping(data.as_ptr(), addrs[i] as *mut u8, data.len())
+                };
+            }
+        }
+
+        // 解析所有符号的地址
+        let (symtab, strtab) = match elf.symbol_table() {
+            Ok(Some(r)) => r,
+            _ => return Err(SystemError::ENOEXEC),
+        };
+        let mut values = Vec::with_capacity(symtab.len());
+        let mut uses: Vec<Arc<Module>> = Vec::new();
+        for sym in symtab.iter() {
+            let sym_name = strtab
+                .get(sym.st_name as usize)
+                .map_err(|_| SystemError::ENOEXEC)?;
+            let value = match sym.st_shndx {
+                SHN_UNDEF if sym.st_name == 0 => 0,
+                SHN_UNDEF => match find_symbol(modules, sym_name) {
+                    Some((addr, owner)) => {
+                        if let Some(owner) = owner {
+                            if !uses.iter().any(|m| Arc::ptr_eq(m, &owner)) {
+                                uses.push(owner);
+                            }
+                        }
+                        addr
+                    }
+                    None if sym.st_bind() == STB_WEAK => 0,
+                    None => {
+                        kerror!("module {}: unknown symbol {}", name, sym_name);
+                        return Err(SystemError::ENOENT);
+                    }
+                },
+                SHN_ABS => sym.st_value as usize,
+                SHN_COMMON => {
+                    kerror!(
+                        "module {}: common symbol {}, compile with -fno-common",
+                        name,
+                        sym_name
+                    );
+                    return Err(SystemError::ENOEXEC);
+                }
+                shndx => {
+                    let base = *addrs.get(shndx as usize).ok_or(SystemError::ENOEXEC)?;
+                    base + sym.st_value as usize
+                }
+            };
+            values.push(value);
+        }
+
+        // 应用重定位，只处理SHF_ALLOC段的重定位
+        for shdr in shdrs.iter() {
+            if shdr.sh_type == SHT_REL {
+                return Err(SystemError::ENOEXEC);
+            }
+            if shdr.sh_type != SHT_RELA {
+                continue;
+            }
+            let target = shdr.sh_info as usize;
+            if target >= shdrs.len() || offsets[target].is_none() {
+                continue;
+            }
+            let relas = elf
+                .section_data_as_relas(shdr)
+                .map_err(|_| SystemError::ENOEXEC)?;
+            for rela in relas {
+                if rela.r_offset >= shdrs[target].sh_size {
+                    return Err(SystemError::ENOEXEC);
+                }
+                let sym = *values
+                    .get(rela.r_sym as usize)
+                    .ok_or(SystemError::ENOEXEC)?;
+                let loc = addrs[target] + rela.r_offset as usize;
+                let val = (sym as u64).wrapping_add(rela.r_addend as u64);
+                unsafe { apply_relocate_add(rela.r_type, loc, val)? };
+            }
+        }
+
+        // 重定位完成之后，代码段不再需要被修改
+        if text_size != 0 {
+            mem.protect_text(PageFrameCount::new(text_size / MMArch::PAGE_SIZE))?;
+        }
+
+        // 模块自己定义的全局符号
+        let find_defined = |target: &str| {
+            symtab
+                .iter()
+                .zip(values.iter())
+                .find(|(sym, _)| {
+                    sym.st_shndx != SHN_UNDEF
+                        && sym.st_bind() != STB_LOCAL
+                        && strtab.get(sym.st_name as usize) == Ok(target)
+                })
+                .map(|(_, value)| *value)
+        };
+
+        let mut exports = Vec::new();
+        if let Some(ksymtab) = find_section("__ksymtab_strings") {
+            for export in split_strings(section_data(&elf, ksymtab)?) {
+                let addr = find_defined(export).ok_or(SystemError::ENOEXEC)?;
+                if find_symbol(modules, export).is_some() {
+                    kerror!("module {}: exports duplicate symbol {}", name, export);
+                    return Err(SystemError::ENOEXEC);
+                }
+                exports.push((String::from(export), addr));
+            }
+        }
+
+        return Ok(Self {
+            init: find_defined("init_module"),
+            exit: find_defined("cleanup_module"),
+            name,
+            mem,
+            size,
+            exports,
+            uses,
+            refcnt: AtomicUsize::new(0),
+            state: SpinLock::new(ModuleState::Coming),
+        });
+    }
+}
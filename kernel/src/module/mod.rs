@@ -0,0 +1,271 @@
+//! 可加载的内核模块
+//!
+//! 模块是可重定位的ELF目标文件（与Linux的.ko相同，`ld -r`的输出），通过init_module系统调用加载：
+//! 内核把它的所有SHF_ALLOC段放到一块连续的内存中，解析它引用的符号，应用重定位，
+//! 然后调用它的`init_module`函数。代码段位于这块内存的开头，重定位之后被设为只读、可执行，
+//! 其余的段不可执行。卸载时调用它的`cleanup_module`函数，没有这个函数的模块不能被卸载。
+//!
+//! - 模块的名字由`.modinfo`段中的`name=<名字>`给出
+//! - 模块可以引用内核通过[`export_symbol!`](crate::export_symbol)导出的符号，以及其他模块导出的符号
+//! - 模块在`__ksymtab_strings`段中列出自己导出的符号的名字，每个名字以'\0'结尾
+//!
+//! 一个模块引用了另一个模块的符号时，会增加被引用模块的引用计数，被引用的模块在此期间不能被卸载。
+//! 已加载的模块列在/proc/modules中。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/module/main.c
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use crate::{
+    arch::MMArch,
+    kinfo, kwarn,
+    libs::{mutex::Mutex, spinlock::SpinLock},
+    mm::{
+        allocator::page_frame::{deallocate_page_frames, PageFrameCount, PhysPageFrame},
+        set_memory::{set_memory_nx, set_memory_ro, set_memory_rw, set_memory_x},
+        MemoryManagementArch, PhysAddr, VirtAddr,
+    },
+    syscall::SystemError,
+};
+
+use self::ksymtab::find_kernel_symbol;
+
+pub mod ksymtab;
+mod loader;
+pub mod syscall;
+
+/// 已经加载的模块。加载与卸载的整个过程都持有这个锁，模块的初始化函数中不能再加载或者卸载模块
+static MODULES: Mutex<Vec<Arc<Module>>> = Mutex::new(Vec::new());
+
+/// 模块的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleState {
+    /// 正在执行初始化函数
+    Coming,
+    /// 已经初始化完成
+    Live,
+    /// 正在卸载
+    Going,
+}
+
+/// 存放模块的代码与数据的连续物理页
+#[derive(Debug)]
+struct ModuleMemory {
+    paddr: PhysAddr,
+    count: PageFrameCount,
+    /// 开头的多少页是只读、可执行的代码
+    text_pages: PageFrameCount,
+}
+
+impl ModuleMemory {
+    /// 在直接映射区中的起始地址
+    fn base(&self) -> usize {
+        return unsafe { MMArch::phys_2_virt(self.paddr) }.unwrap().data();
+    }
+
+    /// 把开头的`pages`页设为只读、可执行
+    fn protect_text(&mut self, pages: PageFrameCount) -> Result<(), SystemError> {
+        let base = VirtAddr::new(self.base());
+        set_memory_ro(base, pages)?;
+        self.text_pages = pages;
+        set_memory_x(base, pages)?;
+        return Ok(());
+    }
+}
+
+impl Drop for ModuleMemory {
+    fn drop(&mut self) {
+        if self.text_pages.data() != 0 {
+            // 还给页帧分配器之前，恢复直接映射区默认的权限
+            let base = VirtAddr::new(self.base());
+            if set_memory_nx(base, self.text_pages)
+                .and_then(|_| set_memory_rw(base, self.text_pages))
+                .is_err()
+            {
+                // 无法恢复权限时不释放这些页，避免它们被当作普通内存使用
+                kwarn!("failed to restore module memory permissions, leaking it");
+                return;
+            }
+        }
+        unsafe { deallocate_page_frames(PhysPageFrame::new(self.paddr), self.count) };
+    }
+}
+
+/// 一个已经加载的模块
+#[derive(Debug)]
+pub struct Module {
+    name: String,
+    mem: ModuleMemory,
+    /// 所有SHF_ALLOC段的总字节数
+    size: usize,
+    /// 初始化函数`init_module`的地址
+    init: Option<usize>,
+    /// 退出函数`cleanup_module`的地址
+    exit: Option<usize>,
+    /// 模块导出的符号：(名字, 地址)
+    exports: Vec<(String, usize)>,
+    /// 这个模块引用了哪些模块的符号
+    uses: Vec<Arc<Module>>,
+    /// 引用计数：引用了这个模块的符号的模块数量，加上[`try_module_get`]的次数
+    refcnt: AtomicUsize,
+    state: SpinLock<ModuleState>,
+}
+
+impl Module {
+    pub fn state(&self) -> ModuleState {
+        *self.state.lock()
+    }
+
+    /// 释放[`try_module_get`]获取的引用
+    #[allow(dead_code)]
+    pub fn put(&self) {
+        self.refcnt.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// 查找模块导出的符号
+    fn find_export(&self, name: &str) -> Option<usize> {
+        return self
+            .exports
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, addr)| *addr);
+    }
+}
+
+/// 在内核与已加载的模块导出的符号中查找符号
+///
+/// ## 返回值
+///
+/// 符号的地址，以及导出它的模块（内核导出的符号为None）
+fn find_symbol(modules: &[Arc<Module>], name: &str) -> Option<(usize, Option<Arc<Module>>)> {
+    if let Some(addr) = find_kernel_symbol(name) {
+        return Some((addr, None));
+    }
+    for module in modules.iter() {
+        if module.state() != ModuleState::Live {
+            continue;
+        }
+        if let Some(addr) = module.find_export(name) {
+            return Some((addr, Some(module.clone())));
+        }
+    }
+    return None;
+}
+
+/// 获取模块的引用，持有引用期间模块不能被卸载，使用完之后调用[`Module::put`]
+#[allow(dead_code)]
+pub fn try_module_get(name: &str) -> Result<Arc<Module>, SystemError> {
+    let modules = MODULES.lock();
+    let module = modules
+        .iter()
+        .find(|m| m.name == name && m.state() == ModuleState::Live)
+        .ok_or(SystemError::ENOENT)?;
+    module.refcnt.fetch_add(1, Ordering::SeqCst);
+    return Ok(module.clone());
+}
+
+/// 加载并初始化一个模块
+///
+/// ## 参数
+///
+/// - `image`：模块的ELF文件
+/// - `args`：模块的参数（暂不支持，会被忽略）
+pub fn load_module(image: &[u8], args: &str) -> Result<(), SystemError> {
+    let mut modules = MODULES.lock();
+    let module = Arc::new(Module::load(image, &modules)?);
+    if !args.trim().is_empty() {
+        kwarn!("module {}: parameters '{}' ignored", module.name, args);
+    }
+
+    if let Some(init) = module.init {
+        let init: extern "C" fn() -> i32 = unsafe { core::mem::transmute(init) };
+        let ret = init();
+        if ret != 0 {
+            kwarn!("module {}: init_module returned {}", module.name, ret);
+            return Err(SystemError::from_posix_errno(ret).unwrap_or(SystemError::EINVAL));
+        }
+    }
+
+    for used in module.uses.iter() {
+        used.refcnt.fetch_add(1, Ordering::SeqCst);
+    }
+    *module.state.lock() = ModuleState::Live;
+    kinfo!("module {} loaded at {:#x}", module.name, module.mem.base());
+    modules.push(module);
+    return Ok(());
+}
+
+/// 卸载一个模块
+///
+/// ## 返回值
+///
+/// - `ENOENT`：模块不存在
+/// - `EAGAIN_OR_EWOULDBLOCK`：模块正在被使用
+/// - `EBUSY`：模块没有退出函数，不能被卸载
+pub fn unload_module(name: &str) -> Result<(), SystemError> {
+    let mut modules = MODULES.lock();
+    let index = modules
+        .iter()
+        .position(|m| m.name == name)
+        .ok_or(SystemError::ENOENT)?;
+    let module = modules[index].clone();
+    if module.refcnt.load(Ordering::SeqCst) != 0 {
+        return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    }
+    let exit = module.exit.ok_or(SystemError::EBUSY)?;
+
+    *module.state.lock() = ModuleState::Going;
+    let exit: extern "C" fn() = unsafe { core::mem::transmute(exit) };
+    exit();
+
+    modules.remove(index);
+    for used in module.uses.iter() {
+        used.refcnt.fetch_sub(1, Ordering::SeqCst);
+    }
+    kinfo!("module {} unloaded", module.name);
+    return Ok(());
+}
+
+/// /proc/modules的内容，格式与Linux相同：名字、大小、引用计数、依赖它的模块、状态、地址
+pub fn modules_show() -> String {
+    let modules = MODULES.lock();
+    let mut s = String::new();
+    for module in modules.iter() {
+        let users: Vec<String> = modules
+            .iter()
+            .filter(|m| m.uses.iter().any(|u| Arc::ptr_eq(u, module)))
+            .map(|m| m.name.to_string() + ",")
+            .collect();
+        let users = if users.is_empty() {
+            "-".to_string()
+        } else {
+            users.concat()
+        };
+        let state = match module.state() {
+            ModuleState::Coming => "Loading",
+            ModuleState::Live => "Live",
+            ModuleState::Going => "Unloading",
+        };
+        writeln!(
+            s,
+            "{} {} {} {} {} {:#x}",
+            module.name,
+            module.size,
+            module.refcnt.load(Ordering::SeqCst),
+            users,
+            state,
+            module.mem.base()
+        )
+        .ok();
+    }
+    return s;
+}
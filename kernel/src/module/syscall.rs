@@ -0,0 +1,56 @@
+use alloc::vec;
+
+use crate::syscall::{
+    user_access::{check_and_clone_cstr, UserBufferReader},
+    Syscall, SystemError,
+};
+
+use super::{load_module, unload_module};
+
+/// 模块文件的最大大小
+const MODULE_IMAGE_MAX: usize = 64 * 1024 * 1024;
+/// 模块名的最大长度
+const MODULE_NAME_LEN: usize = 56;
+/// 模块参数的最大长度
+const MODULE_ARGS_MAX: usize = 4096;
+
+impl Syscall {
+    /// ## init_module系统调用
+    ///
+    /// 加载并初始化一个模块
+    ///
+    /// ## 参数
+    ///
+    /// - `umod`：模块的ELF文件在用户空间中的地址
+    /// - `len`：模块文件的长度
+    /// - `uargs`：模块的参数（暂不支持，会被忽略）
+    pub fn init_module(
+        umod: *const u8,
+        len: usize,
+        uargs: *const u8,
+    ) -> Result<usize, SystemError> {
+        if len == 0 || len > MODULE_IMAGE_MAX {
+            return Err(SystemError::EINVAL);
+        }
+        let reader = UserBufferReader::new(umod, len, true)?;
+        let mut image = vec![0u8; len];
+        reader.copy_from_user(&mut image, 0)?;
+        let args = check_and_clone_cstr(uargs, Some(MODULE_ARGS_MAX))?;
+        load_module(&image, &args)?;
+        return Ok(0);
+    }
+
+    /// ## delete_module系统调用
+    ///
+    /// 卸载一个模块
+    ///
+    /// ## 参数
+    ///
+    /// - `name`：模块的名字
+    /// - `_flags`：暂不支持强制卸载，会被忽略
+    pub fn delete_module(name: *const u8, _flags: u32) -> Result<usize, SystemError> {
+        let name = check_and_clone_cstr(name, Some(MODULE_NAME_LEN))?;
+        unload_module(&name)?;
+        return Ok(0);
+    }
+}
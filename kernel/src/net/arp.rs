@@ -0,0 +1,291 @@
+//! ARP缓存
+//!
+//! 内核通过[`neigh_output`]发送IPv4包时，在这里查找下一跳的MAC地址。还没有解析的地址先发送ARP请求，
+//! 数据包暂存在表项中，收到应答后再发出。
+//!
+//! 以太网卡收到的ARP包都经过[`arp_rcv`]：发送方的地址用于更新缓存；询问本机地址的请求由内核应答，
+//! 不再交给smoltcp。其他的ARP包(例如smoltcp自己发出的请求的应答)仍然交给smoltcp，它维护自己的邻居缓存。
+//! 缓存的内容展示在/proc/net/arp中。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/arp.c
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetProtocol, Ipv4Address,
+};
+
+use crate::{
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+    time::hrtimer::{ktime_get, Ktime},
+};
+
+use super::{
+    ipv4::inet_addr,
+    netdevice::{dev_hard_header_xmit, NetDevice, NetRxResult, ETH_HLEN},
+    skbuff::SkBuff,
+    socket::ARPHRD_ETHER,
+    NET_DRIVERS,
+};
+
+/// 解析完成的表项的有效期(ns)，与smoltcp的邻居缓存保持一致
+const ARP_REACHABLE_TIME: Ktime = 60 * 1000 * 1000 * 1000;
+/// 等待应答的表项的有效期(ns)，超时后丢弃暂存的数据包
+const ARP_INCOMPLETE_TIME: Ktime = 3 * 1000 * 1000 * 1000;
+/// 重发ARP请求的最小间隔(ns)
+const ARP_RETRANS_TIME: Ktime = 1000 * 1000 * 1000;
+/// 每个表项最多暂存的数据包数量，超出时丢弃最早的数据包
+const ARP_QUEUE_LEN: usize = 3;
+/// 最多缓存的邻居的数量
+const ARP_TABLE_SIZE: usize = 512;
+/// 表项已经完成解析(ATF_COM)
+const ATF_COM: u32 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArpState {
+    /// 已经发送ARP请求，还没有收到应答
+    Incomplete,
+    /// 已经知道MAC地址
+    Reachable,
+}
+
+#[derive(Debug)]
+struct ArpEntry {
+    state: ArpState,
+    mac: EthernetAddress,
+    /// 过期的时间(单调时间, ns)
+    expires: Ktime,
+    /// 上一次发送ARP请求的时间
+    probed: Option<Ktime>,
+    /// 等待解析完成的数据包，已经预留了以太网帧头的空间
+    queue: Vec<SkBuff>,
+}
+
+impl ArpEntry {
+    fn new_incomplete(now: Ktime) -> Self {
+        return Self {
+            state: ArpState::Incomplete,
+            mac: EthernetAddress([0; 6]),
+            expires: now + ARP_INCOMPLETE_TIME,
+            probed: None,
+            queue: Vec::new(),
+        };
+    }
+}
+
+/// ARP缓存，以(网卡的id, IPv4地址)为键
+static ARP_TABLE: SpinLock<BTreeMap<(usize, [u8; 4]), ArpEntry>> = SpinLock::new(BTreeMap::new());
+
+/// 为新的表项腾出空间，表已满并且没有过期的表项时返回false
+fn arp_table_reserve(table: &mut BTreeMap<(usize, [u8; 4]), ArpEntry>, now: Ktime) -> bool {
+    if table.len() >= ARP_TABLE_SIZE {
+        table.retain(|_, entry| entry.expires > now);
+    }
+    return table.len() < ARP_TABLE_SIZE;
+}
+
+/// 记录邻居的MAC地址，返回在等待这个地址的数据包
+///
+/// ## 参数
+///
+/// - `create`: 表中没有这个邻居时，是否加入新的表项
+fn arp_update(nic_id: usize, ip: Ipv4Address, mac: EthernetAddress, create: bool) -> Vec<SkBuff> {
+    let now = ktime_get();
+    let key = (nic_id, ip.0);
+    let mut table = ARP_TABLE.lock_irqsave();
+    if !table.contains_key(&key) {
+        if !create || !arp_table_reserve(&mut table, now) {
+            return Vec::new();
+        }
+        table.insert(key, ArpEntry::new_incomplete(now));
+    }
+    let entry = table.get_mut(&key).unwrap();
+    entry.state = ArpState::Reachable;
+    entry.mac = mac;
+    entry.expires = now + ARP_REACHABLE_TIME;
+    return core::mem::take(&mut entry.queue);
+}
+
+/// 发送一个ARP包
+///
+/// ## 参数
+///
+/// - `operation`: 请求或者应答
+/// - `target_ip`: 目标的IPv4地址
+/// - `target_mac`: 目标的MAC地址。请求以广播的方式发送，这时填全0
+/// - `sender_ip`: 本机的IPv4地址
+fn arp_send(
+    dev: &dyn NetDevice,
+    operation: ArpOperation,
+    target_ip: Ipv4Address,
+    target_mac: EthernetAddress,
+    sender_ip: Ipv4Address,
+) -> Result<(), SystemError> {
+    let repr = ArpRepr::EthernetIpv4 {
+        operation,
+        source_hardware_addr: dev.mac(),
+        source_protocol_addr: sender_ip,
+        target_hardware_addr: target_mac,
+        target_protocol_addr: target_ip,
+    };
+    let mut skb = SkBuff::new(ETH_HLEN, repr.buffer_len());
+    repr.emit(&mut ArpPacket::new_unchecked(skb.put(repr.buffer_len())?));
+    skb.protocol = EthernetProtocol::Arp;
+    let dst = match operation {
+        ArpOperation::Request => EthernetAddress::BROADCAST,
+        _ => target_mac,
+    };
+    return dev_hard_header_xmit(dev, skb, dst);
+}
+
+/// 处理以太网卡收到的ARP包
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/arp.c#arp_process
+pub fn arp_rcv(dev: &dyn NetDevice, skb: SkBuff) -> NetRxResult {
+    let repr = match ArpPacket::new_checked(skb.data()).and_then(|p| ArpRepr::parse(&p)) {
+        Ok(repr) => repr,
+        Err(_) => return NetRxResult::Pass,
+    };
+    let (operation, sender_mac, sender_ip, target_ip) = match repr {
+        ArpRepr::EthernetIpv4 {
+            operation,
+            source_hardware_addr,
+            source_protocol_addr,
+            target_protocol_addr,
+            ..
+        } => (
+            operation,
+            source_hardware_addr,
+            source_protocol_addr,
+            target_protocol_addr,
+        ),
+        #[allow(unreachable_patterns)]
+        _ => return NetRxResult::Pass,
+    };
+    if !sender_mac.is_unicast() {
+        return NetRxResult::Pass;
+    }
+
+    let local = inet_addr(dev.nic_id()).map(|cidr| cidr.address());
+    let for_us = local == Some(target_ip);
+    // 发送方地址为0的是地址冲突检测的探测包，不能用来更新缓存
+    if !sender_ip.is_unspecified() {
+        // 已经在缓存中的邻居总是更新；询问本机或者应答本机的邻居加入缓存
+        let pending = arp_update(dev.nic_id(), sender_ip, sender_mac, for_us);
+        for skb in pending {
+            dev_hard_header_xmit(dev, skb, sender_mac).ok();
+        }
+    }
+
+    if operation == ArpOperation::Request && for_us {
+        arp_send(dev, ArpOperation::Reply, sender_ip, sender_mac, target_ip).ok();
+        return NetRxResult::Consumed;
+    }
+    return NetRxResult::Pass;
+}
+
+/// 把IPv4包发送给下一跳，需要时先通过ARP解析下一跳的MAC地址
+///
+/// ## 参数
+///
+/// - `dev`: 发送的网卡
+/// - `next_hop`: 下一跳的IPv4地址
+/// - `skb`: 完整的IPv4包，前面至少预留[`ETH_HLEN`]字节
+///
+/// ## 错误
+///
+/// - `ENOBUFS`：ARP缓存已满
+/// - `EADDRNOTAVAIL`：网卡还没有IPv4地址，无法发送ARP请求
+pub fn neigh_output(
+    dev: &dyn NetDevice,
+    next_hop: Ipv4Address,
+    skb: SkBuff,
+) -> Result<(), SystemError> {
+    if next_hop.is_broadcast() {
+        return dev_hard_header_xmit(dev, skb, EthernetAddress::BROADCAST);
+    }
+
+    let now = ktime_get();
+    let key = (dev.nic_id(), next_hop.0);
+    let mut table = ARP_TABLE.lock_irqsave();
+    let probe = match table.get_mut(&key) {
+        Some(entry) if entry.expires > now => {
+            if entry.state == ArpState::Reachable {
+                let mac = entry.mac;
+                drop(table);
+                return dev_hard_header_xmit(dev, skb, mac);
+            }
+            if entry.queue.len() >= ARP_QUEUE_LEN {
+                entry.queue.remove(0);
+            }
+            entry.queue.push(skb);
+            let probe = entry.probed.map_or(true, |t| now - t >= ARP_RETRANS_TIME);
+            if probe {
+                entry.probed = Some(now);
+            }
+            probe
+        }
+        _ => {
+            // 没有表项，或者表项已经过期：重新解析，丢弃之前暂存的数据包
+            table.remove(&key);
+            if !arp_table_reserve(&mut table, now) {
+                return Err(SystemError::ENOBUFS);
+            }
+            let mut entry = ArpEntry::new_incomplete(now);
+            entry.probed = Some(now);
+            entry.queue.push(skb);
+            table.insert(key, entry);
+            true
+        }
+    };
+    drop(table);
+
+    if probe {
+        let src = inet_addr(dev.nic_id()).ok_or(SystemError::EADDRNOTAVAIL)?;
+        arp_send(
+            dev,
+            ArpOperation::Request,
+            next_hop,
+            EthernetAddress([0; 6]),
+            src.address(),
+        )?;
+    }
+    return Ok(());
+}
+
+/// 按照Linux的/proc/net/arp的格式输出ARP缓存
+pub fn arp_show() -> String {
+    let now = ktime_get();
+    let entries: Vec<((usize, [u8; 4]), ArpState, EthernetAddress)> = {
+        let mut table = ARP_TABLE.lock_irqsave();
+        table.retain(|_, entry| entry.expires > now);
+        table.iter().map(|(k, v)| (*k, v.state, v.mac)).collect()
+    };
+
+    let mut result = String::from(
+        "IP address       HW type     Flags       HW address            Mask     Device\n",
+    );
+    let drivers = NET_DRIVERS.read();
+    for ((nic_id, ip), state, mac) in entries {
+        let name = match drivers.get(&nic_id) {
+            Some(iface) => iface.name(),
+            None => continue,
+        };
+        let ip = format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]);
+        let flags = match state {
+            ArpState::Reachable => ATF_COM,
+            ArpState::Incomplete => 0,
+        };
+        let mac = mac.as_bytes();
+        // smoltcp输出的MAC地址以'-'分隔，这里与Linux保持一致，使用':'
+        let mac = format!(
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+        );
+        result.push_str(&format!(
+            "{:<16} 0x{:<10x}0x{:<10x}{:<17}     {:<8} {}\n",
+            ip, ARPHRD_ETHER, flags, mac, "*", name
+        ));
+    }
+    return result;
+}
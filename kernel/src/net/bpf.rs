@@ -0,0 +1,254 @@
+//! 经典BPF(cBPF)过滤程序的校验与解释执行
+//!
+//! 用户态通过`SO_ATTACH_FILTER`把过滤程序附加到packet socket上，
+//! 内核对每个捕获到的帧执行过滤程序，根据返回值决定是否把这个帧交给用户态，以及保留多少字节。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/core/filter.c
+
+use alloc::vec::Vec;
+
+use crate::syscall::SystemError;
+
+/// 过滤程序的最大指令数
+pub const BPF_MAXINSNS: usize = 4096;
+/// 暂存区(M[])的大小
+const BPF_MEMWORDS: usize = 16;
+
+// 指令的类别
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+// ld/ldx的数据宽度
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+
+// ld/ldx的寻址方式
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+const BPF_MSH: u16 = 0xa0;
+
+// alu/jmp的操作
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+// 操作数来源
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+// misc的操作
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// @brief 一条cBPF指令，与Linux的struct sock_filter一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// @brief 用户态传入的过滤程序，与Linux的struct sock_fprog一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: *const SockFilter,
+}
+
+/// @brief 经过校验的cBPF过滤程序
+#[derive(Debug, Clone)]
+pub struct BpfProgram {
+    insns: Vec<SockFilter>,
+}
+
+impl BpfProgram {
+    /// @brief 校验过滤程序，保证它在执行时一定会终止，且不会越界访问暂存区
+    ///
+    /// @return 程序不合法时，返回EINVAL
+    pub fn new(insns: Vec<SockFilter>) -> Result<Self, SystemError> {
+        let len = insns.len();
+        if len == 0 || len > BPF_MAXINSNS {
+            return Err(SystemError::EINVAL);
+        }
+
+        for (pc, insn) in insns.iter().enumerate() {
+            let class = insn.code & 0x07;
+            let ok = match class {
+                BPF_LD | BPF_LDX => match insn.code & 0xe0 {
+                    BPF_MEM => (insn.k as usize) < BPF_MEMWORDS,
+                    BPF_IMM | BPF_ABS | BPF_IND | BPF_LEN => true,
+                    BPF_MSH => class == BPF_LDX,
+                    _ => false,
+                },
+                BPF_ST | BPF_STX => (insn.k as usize) < BPF_MEMWORDS,
+                BPF_ALU => match insn.code & 0xf0 {
+                    // 除数为常数0的程序直接拒绝
+                    BPF_DIV | BPF_MOD => insn.code & BPF_X != 0 || insn.k != 0,
+                    BPF_ADD | BPF_SUB | BPF_MUL | BPF_OR | BPF_AND | BPF_LSH | BPF_RSH
+                    | BPF_NEG | BPF_XOR => true,
+                    _ => false,
+                },
+                // 只允许向前跳转，并且不能跳出程序
+                BPF_JMP => match insn.code & 0xf0 {
+                    BPF_JA => (insn.k as usize) < len - pc - 1,
+                    BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => {
+                        (insn.jt as usize) < len - pc - 1 && (insn.jf as usize) < len - pc - 1
+                    }
+                    _ => false,
+                },
+                BPF_RET => matches!(insn.code & 0x18, BPF_K | BPF_A),
+                BPF_MISC => matches!(insn.code & 0xf8, BPF_TAX | BPF_TXA),
+                _ => false,
+            };
+            if !ok {
+                return Err(SystemError::EINVAL);
+            }
+        }
+
+        // 最后一条指令必须是返回指令
+        if insns[len - 1].code & 0x07 != BPF_RET {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(Self { insns });
+    }
+
+    /// @brief 对数据包执行过滤程序
+    ///
+    /// @param packet 完整的帧
+    ///
+    /// @return 应当保留的字节数，为0表示丢弃这个包。越界访问数据包时返回0
+    pub fn run(&self, packet: &[u8]) -> u32 {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; BPF_MEMWORDS];
+        let mut pc = 0;
+
+        let load = |offset: u32, size: u16| -> Option<u32> {
+            let offset = offset as usize;
+            let width = match size {
+                BPF_W => 4,
+                BPF_H => 2,
+                _ => 1,
+            };
+            let bytes = packet.get(offset..offset.checked_add(width)?)?;
+            Some(bytes.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32))
+        };
+
+        // 校验保证了程序只会向前跳转，并且最后一条指令是返回指令
+        loop {
+            let insn = self.insns[pc];
+            pc += 1;
+            let k = insn.k;
+            match insn.code & 0x07 {
+                BPF_LD => {
+                    a = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_ABS => match load(k, insn.code & 0x18) {
+                            Some(v) => v,
+                            None => return 0,
+                        },
+                        BPF_IND => match load(x.wrapping_add(k), insn.code & 0x18) {
+                            Some(v) => v,
+                            None => return 0,
+                        },
+                        BPF_MEM => mem[k as usize],
+                        _ => packet.len() as u32,
+                    };
+                }
+                BPF_LDX => {
+                    x = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_MEM => mem[k as usize],
+                        BPF_LEN => packet.len() as u32,
+                        // 取IP头的长度: 4 * (P[k] & 0xf)
+                        _ => match load(k, BPF_B) {
+                            Some(v) => (v & 0xf) << 2,
+                            None => return 0,
+                        },
+                    };
+                }
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                BPF_ALU => {
+                    let src = if insn.code & BPF_X != 0 { x } else { k };
+                    a = match insn.code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(src),
+                        BPF_SUB => a.wrapping_sub(src),
+                        BPF_MUL => a.wrapping_mul(src),
+                        BPF_DIV => {
+                            if src == 0 {
+                                return 0;
+                            }
+                            a / src
+                        }
+                        BPF_MOD => {
+                            if src == 0 {
+                                return 0;
+                            }
+                            a % src
+                        }
+                        BPF_OR => a | src,
+                        BPF_AND => a & src,
+                        BPF_LSH => a.checked_shl(src).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(src).unwrap_or(0),
+                        BPF_NEG => a.wrapping_neg(),
+                        _ => a ^ src,
+                    };
+                }
+                BPF_JMP => {
+                    let src = if insn.code & BPF_X != 0 { x } else { k };
+                    let cond = match insn.code & 0xf0 {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == src,
+                        BPF_JGT => a > src,
+                        BPF_JGE => a >= src,
+                        _ => a & src != 0,
+                    };
+                    let offset = if cond { insn.jt } else { insn.jf };
+                    pc += offset as usize;
+                }
+                BPF_RET => {
+                    return if insn.code & 0x18 == BPF_A { a } else { k };
+                }
+                _ => {
+                    if insn.code & 0xf8 == BPF_TXA {
+                        a = x;
+                    } else {
+                        x = a;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -3,8 +3,16 @@ pub use smoltcp::wire::IpEndpoint;
 /// @brief 链路层端点
 #[derive(Debug, Clone)]
 pub struct LinkLayerEndpoint {
-    /// 网卡的接口号
+    /// 网卡的接口号(ifindex)，为0表示任意网卡
     pub interface: usize,
+    /// 以太网帧的协议类型(主机字节序)，为0表示未指定
+    pub protocol: u16,
+    /// 链路层地址的类型(ARPHRD_*)
+    pub hatype: u16,
+    /// 帧的类型(PACKET_HOST、PACKET_OUTGOING等)
+    pub pkttype: u8,
+    /// 链路层地址(MAC地址)
+    pub addr: [u8; 6],
 }
 
 impl LinkLayerEndpoint {
@@ -14,6 +22,33 @@ impl LinkLayerEndpoint {
     ///
     /// @return 返回创建的链路层端点
     pub fn new(interface: usize) -> Self {
-        Self { interface }
+        Self {
+            interface,
+            protocol: 0,
+            hatype: 0,
+            pkttype: 0,
+            addr: [0; 6],
+        }
+    }
+}
+
+/// @brief netlink端点
+#[derive(Debug, Clone)]
+pub struct NetlinkEndpoint {
+    /// 端口号(nl_pid)，内核的端口号为0
+    pub pid: u32,
+    /// 多播组的位图(nl_groups)
+    pub groups: u32,
+}
+
+impl NetlinkEndpoint {
+    /// @brief 创建一个netlink端点
+    ///
+    /// @param pid 端口号
+    /// @param groups 多播组的位图
+    ///
+    /// @return 返回创建的netlink端点
+    pub fn new(pid: u32, groups: u32) -> Self {
+        Self { pid, groups }
     }
 }
@@ -0,0 +1,192 @@
+//! ICMP回显请求与差错报文的处理
+//!
+//! 发给本机的回显请求由[`icmp_rcv`]应答，不再交给smoltcp。
+//!
+//! smoltcp收到ICMP差错报文(目的不可达、超时)时不会通知对应的socket。
+//! 这里在LOCAL_IN挂载点上注册一个只观察、不丢弃的过滤函数，从差错报文携带的原始IP头中
+//! 找出出错的连接，记录下对应的错误码。socket在等待连接建立、接收数据或者获取SO_ERROR时取出这个错误。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/icmp.c
+
+use alloc::{collections::VecDeque, sync::Arc};
+use smoltcp::{
+    phy::ChecksumCapabilities,
+    wire::{
+        Icmpv4Message, Icmpv4Packet, Icmpv4Repr, IpAddress, IpEndpoint, IpProtocol, Ipv4Address,
+        Ipv4Packet,
+    },
+};
+
+use crate::{libs::spinlock::SpinLock, syscall::SystemError};
+
+use super::{
+    ipv4::{ip_send, IPV4_HEADER_LEN},
+    netdevice::{NetRxResult, ETH_HLEN},
+    netfilter::{nf_register_hook, NfHook, NfHookOps, NfVerdict},
+    skbuff::SkBuff,
+};
+
+/// 最多记录的未取出的错误的数量，超出时丢弃最早的记录
+const SOCK_ERROR_QUEUE_LEN: usize = 64;
+/// 差错报文的过滤函数的优先级，在规则表之后调用，只记录没有被丢弃的报文
+const ICMP_HOOK_PRIORITY: i32 = 100;
+
+/// @brief 出错的连接，由原始数据包的协议、本地端口和远端端点确定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SockErrorKey {
+    protocol: IpProtocol,
+    local_port: u16,
+    remote: IpEndpoint,
+}
+
+/// 尚未被socket取出的错误
+static SOCK_ERRORS: SpinLock<VecDeque<(SockErrorKey, SystemError)>> =
+    SpinLock::new(VecDeque::new());
+
+/// 把ICMP目的不可达报文的代码转换为错误码
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/icmp.c#icmp_err_convert
+fn unreach_errno(code: u8) -> SystemError {
+    match code {
+        0 | 6 | 9 | 11 => SystemError::ENETUNREACH,
+        2 => SystemError::ENOPROTOOPT,
+        3 => SystemError::ECONNREFUSED,
+        4 => SystemError::EMSGSIZE,
+        5 => SystemError::EOPNOTSUPP_OR_ENOTSUP,
+        7 => SystemError::EHOSTDOWN,
+        8 => SystemError::ENONET,
+        _ => SystemError::EHOSTUNREACH,
+    }
+}
+
+/// 处理发给本机的ICMP报文，应答回显请求，其余的报文交给smoltcp
+///
+/// ## 参数
+///
+/// - `skb`: ICMP报文，数据从ICMP头部开始
+/// - `src`: IPv4包的源地址
+/// - `dst`: IPv4包的目的地址，是本机的地址
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/icmp.c#icmp_echo
+pub fn icmp_rcv(skb: SkBuff, src: Ipv4Address, dst: Ipv4Address) -> NetRxResult {
+    let checksum = ChecksumCapabilities::default();
+    let packet = match Icmpv4Packet::new_checked(skb.data()) {
+        Ok(packet) => packet,
+        Err(_) => return NetRxResult::Pass,
+    };
+    let reply = match Icmpv4Repr::parse(&packet, &checksum) {
+        Ok(Icmpv4Repr::EchoRequest {
+            ident,
+            seq_no,
+            data,
+        }) => Icmpv4Repr::EchoReply {
+            ident,
+            seq_no,
+            data,
+        },
+        _ => return NetRxResult::Pass,
+    };
+
+    let len = reply.buffer_len();
+    let mut out = SkBuff::new(ETH_HLEN + IPV4_HEADER_LEN, len);
+    if let Ok(buf) = out.put(len) {
+        reply.emit(&mut Icmpv4Packet::new_unchecked(buf), &checksum);
+        // 请求已经由内核处理，即使应答发送失败也不再交给smoltcp
+        ip_send(out, dst, src, IpProtocol::Icmp).ok();
+    }
+    return NetRxResult::Consumed;
+}
+
+/// 在LOCAL_IN挂载点上观察ICMP差错报文的过滤函数
+#[derive(Debug)]
+struct IcmpErrorHook;
+
+impl NfHookOps for IcmpErrorHook {
+    fn hook(&self, _hook: NfHook, packet: &Ipv4Packet<&[u8]>) -> NfVerdict {
+        if packet.next_header() == IpProtocol::Icmp {
+            icmp_record_error(packet.payload());
+        }
+        return NfVerdict::Accept;
+    }
+}
+
+/// 从ICMP差错报文中找出出错的连接，并记录错误码
+fn icmp_record_error(icmp: &[u8]) {
+    let icmp = match Icmpv4Packet::new_checked(icmp) {
+        Ok(icmp) => icmp,
+        Err(_) => return,
+    };
+    let errno = match icmp.msg_type() {
+        Icmpv4Message::DstUnreachable => unreach_errno(icmp.msg_code()),
+        Icmpv4Message::TimeExceeded => SystemError::EHOSTUNREACH,
+        _ => return,
+    };
+
+    // 差错报文中携带了原始数据包的IP头，以及传输层头部的前8个字节
+    let orig = icmp.data();
+    if orig.len() < 20 {
+        return;
+    }
+    let orig_ip = Ipv4Packet::new_unchecked(orig);
+    let header_len = orig_ip.header_len() as usize;
+    if orig.len() < header_len + 4 {
+        return;
+    }
+    let protocol = orig_ip.next_header();
+    if protocol != IpProtocol::Tcp && protocol != IpProtocol::Udp {
+        return;
+    }
+    let ports = &orig[header_len..];
+    let key = SockErrorKey {
+        protocol,
+        local_port: u16::from_be_bytes([ports[0], ports[1]]),
+        remote: IpEndpoint::new(
+            IpAddress::Ipv4(orig_ip.dst_addr()),
+            u16::from_be_bytes([ports[2], ports[3]]),
+        ),
+    };
+
+    let mut errors = SOCK_ERRORS.lock_irqsave();
+    errors.retain(|(k, _)| *k != key);
+    if errors.len() >= SOCK_ERROR_QUEUE_LEN {
+        errors.pop_front();
+    }
+    errors.push_back((key, errno));
+}
+
+/// @brief 取出连接上记录的ICMP差错，取出之后记录被清除，与SO_ERROR的语义一致
+///
+/// @param protocol 传输层协议
+/// @param local_port 本地端口
+/// @param remote 远端端点
+///
+/// @return 连接上没有错误时返回None
+pub fn sock_error_take(
+    protocol: IpProtocol,
+    local_port: u16,
+    remote: IpEndpoint,
+) -> Option<SystemError> {
+    let key = SockErrorKey {
+        protocol,
+        local_port,
+        remote,
+    };
+    let mut errors = SOCK_ERRORS.lock_irqsave();
+    let pos = errors.iter().position(|(k, _)| *k == key)?;
+    return errors.remove(pos).map(|(_, e)| e);
+}
+
+/// @brief 检查连接上是否记录了ICMP差错，不清除记录
+pub fn sock_error_pending(protocol: IpProtocol, local_port: u16, remote: IpEndpoint) -> bool {
+    let key = SockErrorKey {
+        protocol,
+        local_port,
+        remote,
+    };
+    return SOCK_ERRORS.lock_irqsave().iter().any(|(k, _)| *k == key);
+}
+
+/// @brief 注册观察ICMP差错报文的过滤函数，需要在数据包过滤框架初始化之后调用
+pub fn icmp_init() {
+    nf_register_hook(NfHook::LocalIn, ICMP_HOOK_PRIORITY, Arc::new(IcmpErrorHook));
+}
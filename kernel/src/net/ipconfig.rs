@@ -0,0 +1,255 @@
+//! 启动时的IP地址自动配置
+//!
+//! 根据内核启动参数`ip=`配置网卡的地址，支持以下格式：
+//!
+//! - `ip=dhcp`：通过DHCP获取地址(没有指定`ip=`参数时的默认行为)
+//! - `ip=off`或`ip=none`：不配置地址
+//! - `ip=<client-ip>:<server-ip>:<gw-ip>:<netmask>:<hostname>:<device>:<autoconf>`：
+//!   静态配置地址。除了`client-ip`之外的字段都可以为空，`server-ip`和`hostname`目前会被忽略。
+//!   `autoconf`为`dhcp`时，忽略前面的地址，在`device`上使用DHCP
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/ipconfig.c
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::str::FromStr;
+use smoltcp::{
+    iface::SocketHandle,
+    socket::dhcpv4,
+    wire::{self, Ipv4Address, Ipv4Cidr},
+};
+
+use crate::{
+    driver::net::{loopback::LOOPBACK_IFACE_NAME, NetDriver},
+    init::cmdline::ParamValue,
+    kdebug, kernel_param, kinfo, kwarn,
+    net::NET_DRIVERS,
+    syscall::SystemError,
+};
+
+use super::{
+    route::{ip_route_add, ip_route_del, Ipv4Route},
+    socket::SOCKET_SET,
+};
+
+/// 启动时的IP地址配置方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpConfig {
+    /// 不配置地址
+    Off,
+    /// 通过DHCP获取地址
+    Dhcp { device: Option<String> },
+    /// 静态配置地址
+    Static {
+        addr: Ipv4Cidr,
+        gateway: Option<Ipv4Address>,
+        device: Option<String>,
+    },
+}
+
+impl IpConfig {
+    /// 解析`ip=`启动参数的值
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：参数的格式不正确
+    pub fn parse(s: &str) -> Result<Self, SystemError> {
+        match s {
+            "" | "dhcp" | "on" | "any" => return Ok(Self::Dhcp { device: None }),
+            "off" | "none" => return Ok(Self::Off),
+            _ => {}
+        }
+
+        let mut fields = s.split(':');
+        let mut next = || fields.next().filter(|f| !f.is_empty());
+
+        let client = next();
+        let _server = next();
+        let gateway = next().map(parse_ipv4).transpose()?;
+        let netmask = next().map(parse_ipv4).transpose()?;
+        let _hostname = next();
+        let device = next().map(|d| d.to_string());
+        let autoconf = next();
+
+        match autoconf {
+            Some("dhcp") | Some("on") | Some("any") => return Ok(Self::Dhcp { device }),
+            Some("off") | Some("none") | None => {}
+            Some(_) => return Err(SystemError::EINVAL),
+        }
+
+        let client = parse_ipv4(client.ok_or(SystemError::EINVAL)?)?;
+        let addr = match netmask {
+            Some(mask) => Ipv4Cidr::from_netmask(client, mask).map_err(|_| SystemError::EINVAL)?,
+            None => Ipv4Cidr::new(client, default_prefix_len(client)),
+        };
+
+        return Ok(Self::Static {
+            addr,
+            gateway,
+            device,
+        });
+    }
+}
+
+fn parse_ipv4(s: &str) -> Result<Ipv4Address, SystemError> {
+    Ipv4Address::from_str(s).map_err(|_| SystemError::EINVAL)
+}
+
+/// 没有指定子网掩码时，根据地址的类别确定前缀长度
+fn default_prefix_len(addr: Ipv4Address) -> u8 {
+    match addr.as_bytes()[0] {
+        0..=127 => 8,
+        128..=191 => 16,
+        _ => 24,
+    }
+}
+
+/// 查找要配置的网卡，回环设备的地址是固定的，不会被选中
+///
+/// ## 参数
+///
+/// - `device`: 网卡的名字，为None或者找不到同名的网卡时，使用第一个网卡
+fn find_iface(device: Option<&str>) -> Result<Arc<dyn NetDriver>, SystemError> {
+    let drivers = NET_DRIVERS.read();
+    let mut ifaces = drivers
+        .values()
+        .filter(|iface| iface.name() != LOOPBACK_IFACE_NAME);
+    if let Some(name) = device {
+        if let Some(iface) = ifaces.clone().find(|iface| iface.name() == name) {
+            return Ok(iface.clone());
+        }
+        kwarn!("ipconfig: device {} not found, using the first one", name);
+    }
+    return ifaces.next().cloned().ok_or(SystemError::ENODEV);
+}
+
+/// 设置网卡的默认路由，`gateway`为None时删除默认路由
+///
+/// smoltcp的路由表与内核的路由表([`super::route`])同时更新，前者用于socket的收发，后者用于内核自己发出的包
+fn set_default_route(
+    iface: &Arc<dyn NetDriver>,
+    gateway: Option<Ipv4Address>,
+) -> Result<(), SystemError> {
+    let default_route = |gateway| Ipv4Route {
+        dst: Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0),
+        gateway: Some(gateway),
+        nic_id: iface.nic_id(),
+        metric: 0,
+    };
+
+    let mut inner = iface.inner_iface().lock();
+    let old = inner.routes_mut().remove_default_ipv4_route();
+    if let Some(wire::IpAddress::Ipv4(old)) = old.map(|route| route.via_router) {
+        ip_route_del(&default_route(old)).ok();
+    }
+    if let Some(gateway) = gateway {
+        inner
+            .routes_mut()
+            .add_default_ipv4_route(gateway)
+            .map_err(|_| SystemError::ENOMEM)?;
+        ip_route_add(default_route(gateway))?;
+    }
+    return Ok(());
+}
+
+impl ParamValue for IpConfig {
+    fn parse_param(value: &str) -> Option<Self> {
+        return Self::parse(value).ok();
+    }
+}
+
+kernel_param!(IP_PARAM: IpConfig = "ip");
+
+/// 根据启动参数配置网卡的IP地址。`ip=`的值不合法时使用DHCP
+pub fn ip_auto_config() -> Result<(), SystemError> {
+    let config = IP_PARAM.get().unwrap_or(IpConfig::Dhcp { device: None });
+
+    match config {
+        IpConfig::Off => return Ok(()),
+        IpConfig::Dhcp { device } => {
+            let iface = find_iface(device.as_deref())?;
+            return dhcp_query(iface);
+        }
+        IpConfig::Static {
+            addr,
+            gateway,
+            device,
+        } => {
+            let iface = find_iface(device.as_deref())?;
+            iface.update_ip_addrs(&[wire::IpCidr::Ipv4(addr)])?;
+            if let Some(gw) = gateway {
+                set_default_route(&iface, Some(gw))?;
+            }
+            kinfo!(
+                "ipconfig: {} configured statically, ip: {}, gateway: {:?}",
+                iface.name(),
+                addr,
+                gateway
+            );
+            return Ok(());
+        }
+    }
+}
+
+fn dhcp_query(net_face: Arc<dyn NetDriver>) -> Result<(), SystemError> {
+    let dhcp_handle = SOCKET_SET.lock().add(dhcpv4::Socket::new());
+    let result = dhcp_wait_configured(&net_face, dhcp_handle);
+    // 与Linux一样，启动时获取的地址不会续租。
+    // DHCP socket只能在以太网卡上轮询，不能留在与lo共用的SocketSet中
+    SOCKET_SET.lock().remove(dhcp_handle);
+    return result;
+}
+
+fn dhcp_wait_configured(
+    net_face: &Arc<dyn NetDriver>,
+    dhcp_handle: SocketHandle,
+) -> Result<(), SystemError> {
+    const DHCP_TRY_ROUND: u8 = 10;
+    for i in 0..DHCP_TRY_ROUND {
+        kdebug!("DHCP try round: {}", i);
+        net_face.poll(&mut SOCKET_SET.lock()).ok();
+        let mut binding = SOCKET_SET.lock();
+        let event = binding.get_mut::<dhcpv4::Socket>(dhcp_handle).poll();
+
+        match event {
+            None => {}
+
+            Some(dhcpv4::Event::Configured(config)) => {
+                // kdebug!("Find Config!! {config:?}");
+                // kdebug!("Find ip address: {}", config.address);
+                // kdebug!("iface.ip_addrs={:?}", net_face.inner_iface.ip_addrs());
+
+                net_face
+                    .update_ip_addrs(&[wire::IpCidr::Ipv4(config.address)])
+                    .ok();
+
+                if let Some(router) = config.router {
+                    set_default_route(net_face, Some(router)).unwrap();
+                    let cidr = net_face.inner_iface().lock().ip_addrs().first().cloned();
+                    if cidr.is_some() {
+                        let cidr = cidr.unwrap();
+                        kinfo!("Successfully allocated ip by Dhcpv4! Ip:{}", cidr);
+                        return Ok(());
+                    }
+                } else {
+                    set_default_route(net_face, None).ok();
+                }
+            }
+
+            Some(dhcpv4::Event::Deconfigured) => {
+                kdebug!("Dhcp v4 deconfigured");
+                net_face
+                    .update_ip_addrs(&[smoltcp::wire::IpCidr::Ipv4(wire::Ipv4Cidr::new(
+                        wire::Ipv4Address::UNSPECIFIED,
+                        0,
+                    ))])
+                    .ok();
+                set_default_route(net_face, None).ok();
+            }
+        }
+    }
+
+    return Err(SystemError::ETIMEDOUT);
+}
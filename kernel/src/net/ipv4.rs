@@ -0,0 +1,149 @@
+//! IPv4：网卡的地址，以及由内核处理的IPv4包的收发
+//!
+//! 网卡驱动在更新smoltcp的地址时通过[`inet_set_addr`]同步一份地址。
+//! 驱动收包时smoltcp的`Interface`被锁住，内核需要从这里知道哪些地址是本机的地址。
+//!
+//! 目前内核只处理发给本机的ICMP回显请求(见[`super::icmp::icmp_rcv`])，其余的IPv4包都交给smoltcp。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/ip_input.c
+
+use alloc::collections::BTreeMap;
+use smoltcp::{
+    phy::ChecksumCapabilities,
+    wire::{EthernetProtocol, IpProtocol, Ipv4Address, Ipv4Cidr, Ipv4Packet, Ipv4Repr},
+};
+
+use crate::{libs::rwlock::RwLock, syscall::SystemError};
+
+use super::{
+    arp::neigh_output,
+    icmp::icmp_rcv,
+    netdevice::{dev_get, NetRxResult},
+    route::{ip_route_add, ip_route_del, ip_route_lookup, Ipv4Route},
+    skbuff::SkBuff,
+};
+
+/// IPv4头部的长度(不带选项)
+pub const IPV4_HEADER_LEN: usize = 20;
+/// 内核发出的IPv4包的TTL
+const IP_DEFAULT_TTL: u8 = 64;
+
+/// 每张网卡的IPv4地址，以网卡的id为键
+static INET_ADDRS: RwLock<BTreeMap<usize, Ipv4Cidr>> = RwLock::new(BTreeMap::new());
+
+/// 设置网卡的IPv4地址，`addr`为None或者未指定的地址时清除网卡的地址
+///
+/// 旧地址所在子网的直连路由被删除，新地址所在子网的直连路由被加入
+pub fn inet_set_addr(nic_id: usize, addr: Option<Ipv4Cidr>) {
+    let addr = addr.filter(|cidr| !cidr.address().is_unspecified());
+    let connected = |cidr: Ipv4Cidr| Ipv4Route {
+        dst: cidr.network(),
+        gateway: None,
+        nic_id,
+        metric: 0,
+    };
+
+    let old = {
+        let mut addrs = INET_ADDRS.write();
+        match addr {
+            Some(cidr) => addrs.insert(nic_id, cidr),
+            None => addrs.remove(&nic_id),
+        }
+    };
+    if let Some(old) = old {
+        ip_route_del(&connected(old)).ok();
+    }
+    if let Some(cidr) = addr {
+        ip_route_add(connected(cidr)).ok();
+    }
+}
+
+/// 网卡的IPv4地址
+pub fn inet_addr(nic_id: usize) -> Option<Ipv4Cidr> {
+    return INET_ADDRS.read().get(&nic_id).copied();
+}
+
+/// `addr`是否是本机某张网卡的地址
+pub fn inet_addr_is_local(addr: Ipv4Address) -> bool {
+    return INET_ADDRS
+        .read()
+        .values()
+        .any(|cidr| cidr.address() == addr);
+}
+
+/// 处理以太网卡收到的IPv4包，`skb`的数据从IPv4头部开始
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/ip_input.c#ip_rcv_core
+pub fn ip_rcv(mut skb: SkBuff) -> NetRxResult {
+    let (src, dst, protocol, header_len, total_len) = {
+        let packet = match Ipv4Packet::new_checked(skb.data()) {
+            Ok(packet) => packet,
+            Err(_) => return NetRxResult::Pass,
+        };
+        // 不合法的包和分片交给smoltcp，由它丢弃或者重组
+        if packet.version() != 4
+            || !packet.verify_checksum()
+            || packet.more_frags()
+            || packet.frag_offset() != 0
+        {
+            return NetRxResult::Pass;
+        }
+        (
+            packet.src_addr(),
+            packet.dst_addr(),
+            packet.next_header(),
+            packet.header_len() as usize,
+            packet.total_len() as usize,
+        )
+    };
+    if protocol != IpProtocol::Icmp || !inet_addr_is_local(dst) {
+        return NetRxResult::Pass;
+    }
+
+    // 去掉以太网帧末尾的填充和IPv4头部
+    skb.trim(total_len);
+    if skb.pull(header_len).is_err() {
+        return NetRxResult::Pass;
+    }
+    return icmp_rcv(skb, src, dst);
+}
+
+/// 加上IPv4头部，按照路由表发送
+///
+/// ## 参数
+///
+/// - `skb`: IPv4包的负载，前面至少预留以太网帧头与[`IPV4_HEADER_LEN`]字节
+/// - `src`: 源地址
+/// - `dst`: 目的地址
+/// - `protocol`: 负载的协议
+///
+/// ## 错误
+///
+/// - `ENETUNREACH`：没有到`dst`的路由
+/// - `EMSGSIZE`：IPv4包超过了网卡的MTU
+pub fn ip_send(
+    mut skb: SkBuff,
+    src: Ipv4Address,
+    dst: Ipv4Address,
+    protocol: IpProtocol,
+) -> Result<(), SystemError> {
+    let route = ip_route_lookup(dst).ok_or(SystemError::ENETUNREACH)?;
+    let dev = dev_get(route.nic_id).ok_or(SystemError::ENETUNREACH)?;
+    if IPV4_HEADER_LEN + skb.len() > dev.mtu() {
+        return Err(SystemError::EMSGSIZE);
+    }
+
+    let repr = Ipv4Repr {
+        src_addr: src,
+        dst_addr: dst,
+        next_header: protocol,
+        payload_len: skb.len(),
+        hop_limit: IP_DEFAULT_TTL,
+    };
+    repr.emit(
+        &mut Ipv4Packet::new_unchecked(skb.push(IPV4_HEADER_LEN)?),
+        &ChecksumCapabilities::default(),
+    );
+    skb.protocol = EthernetProtocol::Ipv4;
+    return neigh_output(dev.as_ref(), route.next_hop(dst), skb);
+}
@@ -1,4 +1,5 @@
 use core::{
+    any::Any,
     fmt::{self, Debug},
     sync::atomic::AtomicUsize,
 };
@@ -8,10 +9,20 @@ use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 use crate::{driver::net::NetDriver, kwarn, libs::rwlock::RwLock, syscall::SystemError};
 use smoltcp::wire::IpEndpoint;
 
-use self::socket::SocketMetadata;
+use self::socket::{MessageFlag, SocketMetadata};
 
+pub mod arp;
+pub mod bpf;
 pub mod endpoints;
+pub mod icmp;
+pub mod ipconfig;
+pub mod ipv4;
 pub mod net_core;
+pub mod netdevice;
+pub mod netfilter;
+pub mod ping;
+pub mod route;
+pub mod skbuff;
 pub mod socket;
 pub mod syscall;
 
@@ -59,7 +70,10 @@ pub enum Endpoint {
     LinkLayer(endpoints::LinkLayerEndpoint),
     /// 网络层端点
     Ip(Option<IpEndpoint>),
-    // todo: 增加NetLink机制后，增加NetLink端点
+    /// 未命名的unix域端点，例如socketpair创建的socket
+    Unnamed,
+    /// netlink端点
+    Netlink(endpoints::NetlinkEndpoint),
 }
 
 pub trait Socket: Sync + Send + Debug {
@@ -69,7 +83,18 @@ pub trait Socket: Sync + Send + Debug {
     ///
     /// @return - 成功：(返回读取的数据的长度，读取数据的端点).
     ///         - 失败：错误码
-    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint);
+    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        return self.recv(buf, MessageFlag::empty());
+    }
+
+    /// @brief 从socket中读取数据，与read相同，但是可以通过flags指定MSG_PEEK、MSG_DONTWAIT等标志
+    ///
+    /// @param buf 读取到的数据存放的缓冲区
+    /// @param flags 消息标志
+    ///
+    /// @return - 成功：(返回读取的数据的长度，读取数据的端点).
+    ///         - 失败：错误码。指定了MSG_DONTWAIT或者等待超时(SO_RCVTIMEO)时，返回EAGAIN_OR_EWOULDBLOCK
+    fn recv(&self, buf: &mut [u8], flags: MessageFlag) -> (Result<usize, SystemError>, Endpoint);
 
     /// @brief 向socket中写入数据。如果socket是阻塞的，那么直到写入的数据全部写入socket中才返回
     ///
@@ -77,7 +102,23 @@ pub trait Socket: Sync + Send + Debug {
     /// @param to 要写入的目的端点，如果是None，那么写入的数据将会被丢弃
     ///
     /// @return 返回写入的数据的长度
-    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError>;
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        return self.send(buf, to, MessageFlag::empty());
+    }
+
+    /// @brief 向socket中写入数据，与write相同，但是可以通过flags指定MSG_DONTWAIT等标志
+    ///
+    /// @param buf 要写入的数据
+    /// @param to 要写入的目的端点
+    /// @param flags 消息标志
+    ///
+    /// @return 返回写入的数据的长度
+    fn send(
+        &self,
+        buf: &[u8],
+        to: Option<Endpoint>,
+        flags: MessageFlag,
+    ) -> Result<usize, SystemError>;
 
     /// @brief 对应于POSIX的connect函数，用于连接到指定的远程服务器端点
     ///
@@ -183,6 +224,9 @@ pub trait Socket: Sync + Send + Debug {
 
     fn box_clone(&self) -> Box<dyn Socket>;
 
+    /// @brief 转换为Any，用于获取socket的具体类型
+    fn as_any_ref(&self) -> &dyn Any;
+
     /// @brief 设置socket的选项
     ///
     /// @param level 选项的层次
@@ -191,7 +235,7 @@ pub trait Socket: Sync + Send + Debug {
     ///
     /// @return 返回设置是否成功, 如果不支持该选项，返回ENOSYS
     fn setsockopt(
-        &self,
+        &mut self,
         _level: usize,
         _optname: usize,
         _optval: &[u8],
@@ -199,6 +243,24 @@ pub trait Socket: Sync + Send + Debug {
         kwarn!("setsockopt is not implemented");
         return Err(SystemError::ENOSYS);
     }
+
+    /// @brief 获取socket的选项
+    ///
+    /// 默认只支持所有socket共有的SOL_SOCKET层的选项
+    ///
+    /// @param level 选项的层次
+    /// @param optname 选项的名称
+    /// @param optval 用于存放选项的值的缓冲区
+    ///
+    /// @return 返回写入optval的长度, 如果不支持该选项，返回ENOPROTOOPT
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        return self.metadata()?.getsockopt(level, optname, optval);
+    }
 }
 
 impl Clone for Box<dyn Socket> {
@@ -1,119 +1,137 @@
-use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
-use smoltcp::{socket::dhcpv4, wire};
+//! 网络核心：网卡的轮询、统计信息与网卡的选择
+//!
+//! 每张网卡([`NetDriver`])持有一个smoltcp的`Interface`，socket上的TCP、UDP等协议由smoltcp处理。
+//! 以太网卡同时是内核自己的网络设备([`super::netdevice::NetDevice`])：
+//!
+//! - ARP请求由内核的ARP缓存([`super::arp`])应答
+//! - 发给本机的ICMP回显请求由[`super::icmp`]应答，经过内核的IPv4路由表([`super::route`])发出
+//! - 其他的帧交给smoltcp，需要[`net_poll_thread`]持续轮询网卡
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/core/dev.c
+
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String, sync::Arc};
+use core::sync::atomic::{AtomicU64, Ordering};
+use smoltcp::wire;
 
 use crate::{
-    driver::net::NetDriver,
-    kdebug, kinfo, kwarn,
+    driver::net::{
+        loopback::{loopback_init, LOOPBACK_IFACE_NAME},
+        NetDriver,
+    },
+    kwarn,
     libs::rwlock::RwLockReadGuard,
     net::NET_DRIVERS,
-    syscall::SystemError,
-    time::timer::{next_n_ms_timer_jiffies, Timer, TimerFunction},
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    syscall::{user_access::UserPtr, SystemError},
+    time::{sleep::nanosleep, TimeSpec, NSEC_PER_MSEC},
 };
 
-use super::socket::{SOCKET_SET, SOCKET_WAITQUEUE};
+use super::{
+    icmp::icmp_init,
+    ipconfig::ip_auto_config,
+    netfilter::netfilter_init,
+    route::ip_route_lookup,
+    socket::{ARPHRD_ETHER, ARPHRD_LOOPBACK, SOCKET_SET, SOCKET_WAITQUEUE},
+};
 
-/// The network poll function, which will be called by timer.
-///
-/// The main purpose of this function is to poll all network interfaces.
-#[derive(Debug)]
-struct NetWorkPollFunc;
+/// 网络轮询线程的轮询周期(ms)
+const NET_POLL_INTERVAL_MS: i64 = 10;
+
+pub fn net_init() -> Result<(), SystemError> {
+    // 过滤框架需要在开始收发包之前初始化
+    netfilter_init();
+    icmp_init();
+
+    let has_nic = !NET_DRIVERS.read().is_empty();
 
-impl TimerFunction for NetWorkPollFunc {
-    fn run(&mut self) -> Result<(), SystemError> {
+    // 无论地址配置是否成功，都需要启动轮询线程，以便之后通过socket或者手动配置的地址进行通信
+    KernelThreadMechanism::create_and_run(
+        KernelThreadClosure::EmptyClosure((Box::new(net_poll_thread), ())),
+        String::from("netpoll"),
+    )
+    .ok_or(SystemError::ENOMEM)?;
+
+    let result = if has_nic {
+        ip_auto_config()
+    } else {
+        Err(SystemError::ENODEV)
+    };
+
+    // lo要在地址配置完成之后注册：DHCP socket只能在以太网卡上轮询
+    loopback_init();
+    return result;
+}
+
+/// 网络轮询线程：周期性地轮询所有网卡
+///
+/// 网卡驱动只在被轮询时从接收队列中取出帧，交给内核的ARP、ICMP处理或者交给smoltcp。
+/// 网卡的中断处理函数只会尝试轮询一次，加锁失败时收到的包会一直留在网卡的队列中；
+/// 而ARP请求的重发、TCP的重传等也依赖于定期的轮询。因此即使没有进程在使用socket，
+/// 也需要持续地轮询网卡，才能稳定地应答ARP请求和ping
+fn net_poll_thread() -> i32 {
+    let interval = TimeSpec::new(0, NET_POLL_INTERVAL_MS * NSEC_PER_MSEC as i64);
+    loop {
         poll_ifaces_try_lock(10).ok();
-        let next_time = next_n_ms_timer_jiffies(10);
-        let timer = Timer::new(Box::new(NetWorkPollFunc), next_time);
-        timer.activate();
-        return Ok(());
+        nanosleep(interval).ok();
     }
 }
 
-pub fn net_init() -> Result<(), SystemError> {
-    dhcp_query()?;
-    // Init poll timer function
-    // let next_time = next_n_ms_timer_jiffies(5);
-    // let timer = Timer::new(Box::new(NetWorkPollFunc), next_time);
-    // timer.activate();
-    return Ok(());
+/// 按照Linux的/proc/net/dev的格式输出所有网卡的收发统计信息
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/core/net-procfs.c#dev_seq_printf_stats
+pub fn dev_show() -> String {
+    let mut result = String::from(
+        "Inter-|   Receive                                                |  Transmit\n \
+         face |bytes    packets errs drop fifo frame compressed multicast|\
+         bytes    packets errs drop fifo colls carrier compressed\n",
+    );
+    for iface in NET_DRIVERS.read().values() {
+        let stats = iface.stats();
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        result.push_str(&format!(
+            "{:>6}:{:>8} {:>7} {:>4} {:>4} {:>4} {:>5} {:>10} {:>9} {:>8} {:>7} {:>4} {:>4} {:>4} {:>5} {:>7} {:>10}\n",
+            iface.name(),
+            get(&stats.rx_bytes),
+            get(&stats.rx_packets),
+            get(&stats.rx_errors),
+            get(&stats.rx_dropped),
+            0,
+            0,
+            0,
+            0,
+            get(&stats.tx_bytes),
+            get(&stats.tx_packets),
+            get(&stats.tx_errors),
+            get(&stats.tx_dropped),
+            0,
+            0,
+            0,
+            0
+        ));
+    }
+    return result;
 }
 
-fn dhcp_query() -> Result<(), SystemError> {
-    let binding = NET_DRIVERS.write();
-
-    let net_face = binding.get(&0).ok_or(SystemError::ENODEV)?.clone();
-
-    drop(binding);
-
-    // Create sockets
-    let mut dhcp_socket = dhcpv4::Socket::new();
-
-    // Set a ridiculously short max lease time to show DHCP renews work properly.
-    // This will cause the DHCP client to start renewing after 5 seconds, and give up the
-    // lease after 10 seconds if renew hasn't succeeded.
-    // IMPORTANT: This should be removed in production.
-    dhcp_socket.set_max_lease_duration(Some(smoltcp::time::Duration::from_secs(10)));
-
-    let dhcp_handle = SOCKET_SET.lock().add(dhcp_socket);
-
-    const DHCP_TRY_ROUND: u8 = 10;
-    for i in 0..DHCP_TRY_ROUND {
-        kdebug!("DHCP try round: {}", i);
-        net_face.poll(&mut SOCKET_SET.lock()).ok();
-        let mut binding = SOCKET_SET.lock();
-        let event = binding.get_mut::<dhcpv4::Socket>(dhcp_handle).poll();
-
-        match event {
-            None => {}
-
-            Some(dhcpv4::Event::Configured(config)) => {
-                // kdebug!("Find Config!! {config:?}");
-                // kdebug!("Find ip address: {}", config.address);
-                // kdebug!("iface.ip_addrs={:?}", net_face.inner_iface.ip_addrs());
-
-                net_face
-                    .update_ip_addrs(&[wire::IpCidr::Ipv4(config.address)])
-                    .ok();
-
-                if let Some(router) = config.router {
-                    net_face
-                        .inner_iface()
-                        .lock()
-                        .routes_mut()
-                        .add_default_ipv4_route(router)
-                        .unwrap();
-                    let cidr = net_face.inner_iface().lock().ip_addrs().first().cloned();
-                    if cidr.is_some() {
-                        let cidr = cidr.unwrap();
-                        kinfo!("Successfully allocated ip by Dhcpv4! Ip:{}", cidr);
-                        return Ok(());
-                    }
-                } else {
-                    net_face
-                        .inner_iface()
-                        .lock()
-                        .routes_mut()
-                        .remove_default_ipv4_route();
-                }
-            }
-
-            Some(dhcpv4::Event::Deconfigured) => {
-                kdebug!("Dhcp v4 deconfigured");
-                net_face
-                    .update_ip_addrs(&[smoltcp::wire::IpCidr::Ipv4(wire::Ipv4Cidr::new(
-                        wire::Ipv4Address::UNSPECIFIED,
-                        0,
-                    ))])
-                    .ok();
-                net_face
-                    .inner_iface()
-                    .lock()
-                    .routes_mut()
-                    .remove_default_ipv4_route();
-            }
+/// 根据目的地址选择网卡，用于确定发送时使用的源地址
+///
+/// 发往回环地址时选择lo；IPv4地址按照路由表选择网卡，没有路由的地址选择第一个以太网卡
+///
+/// ## 错误
+///
+/// - `ENETUNREACH`：没有可用的网卡
+pub fn route_iface(dst: &wire::IpAddress) -> Result<Arc<dyn NetDriver>, SystemError> {
+    let loopback = dst.is_loopback();
+    let drivers = NET_DRIVERS.read();
+    if let (false, wire::IpAddress::Ipv4(dst)) = (loopback, dst) {
+        if let Some(iface) = ip_route_lookup(*dst).and_then(|r| drivers.get(&r.nic_id)) {
+            return Ok(iface.clone());
         }
     }
-
-    return Err(SystemError::ETIMEDOUT);
+    return drivers
+        .values()
+        .find(|iface| (iface.name() == LOOPBACK_IFACE_NAME) == loopback)
+        .cloned()
+        .ok_or(SystemError::ENETUNREACH);
 }
 
 pub fn poll_ifaces() {
@@ -181,3 +199,111 @@ pub fn poll_ifaces_try_lock_onetime() -> Result<(), SystemError> {
     SOCKET_WAITQUEUE.wakeup_all(None);
     return Ok(());
 }
+
+// 获取网卡信息的ioctl命令
+// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/sockios.h
+const SIOCGIFNAME: u32 = 0x8910;
+const SIOCGIFFLAGS: u32 = 0x8913;
+const SIOCGIFHWADDR: u32 = 0x8927;
+const SIOCGIFINDEX: u32 = 0x8933;
+
+// 网卡的状态标志
+const IFF_UP: i16 = 0x1;
+const IFF_BROADCAST: i16 = 0x2;
+const IFF_LOOPBACK: i16 = 0x8;
+const IFF_RUNNING: i16 = 0x40;
+const IFF_MULTICAST: i16 = 0x1000;
+
+/// @brief 与Linux的struct ifreq一致，ifr_ifru是一个联合体
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct IfReq {
+    ifr_name: [u8; 16],
+    ifr_ifru: [u8; 24],
+}
+
+impl IfReq {
+    fn name(&self) -> &str {
+        let len = self
+            .ifr_name
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(self.ifr_name.len());
+        return core::str::from_utf8(&self.ifr_name[..len]).unwrap_or("");
+    }
+}
+
+/// @brief 根据网卡的id得到网卡的接口号(ifindex)，与Linux一样从1开始
+#[inline]
+pub fn iface_index(nic_id: usize) -> usize {
+    return nic_id + 1;
+}
+
+/// @brief 处理socket上获取网卡信息的ioctl命令
+///
+/// 支持SIOCGIFINDEX、SIOCGIFNAME、SIOCGIFHWADDR和SIOCGIFFLAGS
+///
+/// @param cmd ioctl命令
+/// @param data 用户空间的struct ifreq的地址
+///
+/// @return 不支持的命令返回ENOTTY，找不到网卡时返回ENODEV
+pub fn dev_ioctl(cmd: u32, data: usize) -> Result<usize, SystemError> {
+    if !matches!(
+        cmd,
+        SIOCGIFNAME | SIOCGIFFLAGS | SIOCGIFHWADDR | SIOCGIFINDEX
+    ) {
+        return Err(SystemError::ENOTTY);
+    }
+    let ptr = UserPtr::<IfReq>::new(data);
+    let mut ifreq = ptr.read()?;
+
+    let ifindex = i32::from_ne_bytes(ifreq.ifr_ifru[..4].try_into().unwrap());
+    let iface = NET_DRIVERS
+        .read()
+        .values()
+        .find(|iface| {
+            // SIOCGIFNAME根据接口号查找网卡，其余命令根据名字查找
+            if cmd == SIOCGIFNAME {
+                iface_index(iface.nic_id()) as i32 == ifindex
+            } else {
+                iface.name() == ifreq.name()
+            }
+        })
+        .cloned()
+        .ok_or(SystemError::ENODEV)?;
+
+    let loopback = iface.name() == LOOPBACK_IFACE_NAME;
+    match cmd {
+        SIOCGIFNAME => {
+            let name = iface.name();
+            let len = core::cmp::min(name.len(), ifreq.ifr_name.len() - 1);
+            ifreq.ifr_name = [0; 16];
+            ifreq.ifr_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        }
+        SIOCGIFINDEX => {
+            ifreq.ifr_ifru[..4]
+                .copy_from_slice(&(iface_index(iface.nic_id()) as i32).to_ne_bytes());
+        }
+        SIOCGIFHWADDR => {
+            // struct sockaddr { sa_family_t sa_family; char sa_data[14]; }
+            let hatype = if loopback {
+                ARPHRD_LOOPBACK
+            } else {
+                ARPHRD_ETHER
+            };
+            ifreq.ifr_ifru = [0; 24];
+            ifreq.ifr_ifru[..2].copy_from_slice(&hatype.to_ne_bytes());
+            ifreq.ifr_ifru[2..8].copy_from_slice(iface.mac().as_bytes());
+        }
+        _ => {
+            let flags = if loopback {
+                IFF_UP | IFF_LOOPBACK | IFF_RUNNING
+            } else {
+                IFF_UP | IFF_BROADCAST | IFF_RUNNING | IFF_MULTICAST
+            };
+            ifreq.ifr_ifru[..2].copy_from_slice(&flags.to_ne_bytes());
+        }
+    }
+    ptr.write(&ifreq)?;
+    return Ok(0);
+}
@@ -0,0 +1,130 @@
+//! 网络设备层
+//!
+//! 以太网卡除了作为smoltcp的`phy::Device`之外，还实现[`NetDevice`]，供内核自己的ARP与IPv4处理收发数据包：
+//!
+//! - 驱动每收到一个帧，在交给smoltcp之前调用[`netif_receive`]。内核已经处理完的帧(应答了的ARP请求、ICMP回显请求)
+//!   不再交给smoltcp，其余的帧照常由smoltcp处理
+//! - 内核发出的数据包通过[`NetDevice::start_xmit`]直接交给网卡
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/core/dev.c#__netif_receive_skb_core
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use core::fmt::Debug;
+use smoltcp::wire::{EthernetAddress, EthernetFrame, EthernetProtocol, EthernetRepr};
+
+use crate::{driver::net::NetDeviceStats, libs::rwlock::RwLock, syscall::SystemError};
+
+use super::{arp::arp_rcv, ipv4::ip_rcv, skbuff::SkBuff};
+
+/// 以太网帧头的长度
+pub const ETH_HLEN: usize = 14;
+/// 以太网的MTU
+pub const ETH_DATA_LEN: usize = 1500;
+
+/// 以太网卡
+pub trait NetDevice: Send + Sync + Debug {
+    /// 网卡的名字，例如"eth0"
+    fn name(&self) -> String;
+
+    /// 网卡的id，与[`NET_DRIVERS`](super::NET_DRIVERS)中的键相同
+    fn nic_id(&self) -> usize;
+
+    fn mac(&self) -> EthernetAddress;
+
+    /// 网卡能够发送的IP包的最大长度
+    fn mtu(&self) -> usize {
+        return ETH_DATA_LEN;
+    }
+
+    /// 发送一个完整的以太网帧
+    ///
+    /// 与smoltcp发出的帧一样，发送前经过LOCAL_OUT挂载点，并交给packet socket
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOBUFS`：网卡的发送队列已满
+    fn start_xmit(&self, skb: SkBuff) -> Result<(), SystemError>;
+
+    /// 网卡的收发统计信息
+    fn stats(&self) -> &NetDeviceStats;
+}
+
+/// 收到的帧的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetRxResult {
+    /// 内核已经处理完这个帧，不再交给smoltcp
+    Consumed,
+    /// 交给smoltcp处理
+    Pass,
+}
+
+/// 所有的以太网卡，以网卡的id为键
+static NET_DEVICES: RwLock<BTreeMap<usize, Arc<dyn NetDevice>>> = RwLock::new(BTreeMap::new());
+
+/// 登记一张以太网卡。驱动在把网卡加入[`NET_DRIVERS`](super::NET_DRIVERS)时调用
+pub fn register_netdev(dev: Arc<dyn NetDevice>) {
+    NET_DEVICES.write().insert(dev.nic_id(), dev);
+}
+
+/// 根据网卡的id查找以太网卡
+pub fn dev_get(nic_id: usize) -> Option<Arc<dyn NetDevice>> {
+    return NET_DEVICES.read().get(&nic_id).cloned();
+}
+
+/// 给数据包加上以太网帧头，然后从网卡发送
+///
+/// ## 参数
+///
+/// - `dev`: 发送的网卡
+/// - `skb`: 以太网帧的负载，前面至少预留[`ETH_HLEN`]字节
+/// - `dst`: 目的MAC地址
+pub fn dev_hard_header_xmit(
+    dev: &dyn NetDevice,
+    mut skb: SkBuff,
+    dst: EthernetAddress,
+) -> Result<(), SystemError> {
+    let repr = EthernetRepr {
+        src_addr: dev.mac(),
+        dst_addr: dst,
+        ethertype: skb.protocol,
+    };
+    repr.emit(&mut EthernetFrame::new_unchecked(skb.push(ETH_HLEN)?));
+    skb.nic_id = dev.nic_id();
+    return dev.start_xmit(skb);
+}
+
+/// 以太网卡的驱动在收到一个帧时调用，由内核处理ARP包和发给本机的ICMP回显请求
+///
+/// ## 参数
+///
+/// - `nic_id`: 收到这个帧的网卡的id
+/// - `frame`: 完整的以太网帧
+///
+/// ## 返回值
+///
+/// 返回[`NetRxResult::Consumed`]时，驱动应当回收这个帧，不再交给smoltcp
+pub fn netif_receive(nic_id: usize, frame: &[u8]) -> NetRxResult {
+    let dev = match dev_get(nic_id) {
+        Some(dev) => dev,
+        None => return NetRxResult::Pass,
+    };
+    let eth = match EthernetFrame::new_checked(frame) {
+        Ok(eth) => eth,
+        Err(_) => return NetRxResult::Pass,
+    };
+    // 不是发给本机的帧交给smoltcp，由它决定是否丢弃
+    let dst = eth.dst_addr();
+    if dst != dev.mac() && !dst.is_broadcast() {
+        return NetRxResult::Pass;
+    }
+
+    let mut skb = SkBuff::from_frame(nic_id, frame);
+    if skb.pull(ETH_HLEN).is_err() {
+        return NetRxResult::Pass;
+    }
+    match skb.protocol {
+        EthernetProtocol::Arp => return arp_rcv(dev.as_ref(), skb),
+        EthernetProtocol::Ipv4 => return ip_rcv(skb),
+        _ => return NetRxResult::Pass,
+    }
+}
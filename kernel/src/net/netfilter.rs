@@ -0,0 +1,514 @@
+//! 类似netfilter的数据包过滤框架
+//!
+//! 在IPv4的收发路径上提供以下挂载点，内核模块可以在这些挂载点上注册过滤函数，
+//! 对经过的每个IPv4包给出接受或者丢弃的判决：
+//!
+//! - PRE_ROUTING：网卡收到包之后，交给协议栈之前
+//! - LOCAL_IN：发往本机的包交给协议栈之前。本机不转发数据包，因此所有通过了PRE_ROUTING的包都会经过这里
+//! - LOCAL_OUT：本机发出的包交给网卡之前
+//!
+//! 在此之上实现了一个最小的规则表(相当于iptables的filter表)，
+//! 用户态可以通过`/proc/net/nf_rules`查看和修改规则，格式与iptables的命令类似：
+//!
+//! - `-A <链> [-p 协议] [-s 源地址[/前缀]] [-d 目的地址[/前缀]] [--sport 端口] [--dport 端口] -j ACCEPT|DROP`：在链的末尾添加规则
+//! - `-D <链> <序号>`：删除链中的第n条规则(从1开始)
+//! - `-F [链]`：清空链中的规则，不指定链时清空所有链
+//! - `-P <链> ACCEPT|DROP`：设置链的默认策略
+//!
+//! 其中链的名字为`PREROUTING`、`INPUT`或`OUTPUT`，分别对应上面的三个挂载点。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/netfilter/core.c
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    fmt::Debug,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use smoltcp::{
+    phy::Medium,
+    wire::{
+        EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Address, Ipv4Cidr, Ipv4Packet, TcpPacket,
+        UdpPacket, ETHERNET_HEADER_LEN,
+    },
+};
+
+use crate::{kinfo, libs::rwlock::RwLock, syscall::SystemError};
+
+/// 挂载点的数量
+const NF_HOOK_NUM: usize = 3;
+
+/// @brief 数据包过滤的挂载点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NfHook {
+    /// 网卡收到包之后，交给协议栈之前
+    PreRouting = 0,
+    /// 发往本机的包交给协议栈之前
+    LocalIn = 1,
+    /// 本机发出的包交给网卡之前
+    LocalOut = 2,
+}
+
+impl NfHook {
+    pub const ALL: [NfHook; NF_HOOK_NUM] = [NfHook::PreRouting, NfHook::LocalIn, NfHook::LocalOut];
+
+    /// 挂载点在规则表中对应的链的名字
+    pub fn chain_name(&self) -> &'static str {
+        match self {
+            NfHook::PreRouting => "PREROUTING",
+            NfHook::LocalIn => "INPUT",
+            NfHook::LocalOut => "OUTPUT",
+        }
+    }
+
+    fn from_chain_name(name: &str) -> Result<Self, SystemError> {
+        return Self::ALL
+            .iter()
+            .find(|hook| hook.chain_name() == name)
+            .copied()
+            .ok_or(SystemError::EINVAL);
+    }
+}
+
+/// @brief 过滤函数对数据包的判决
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NfVerdict {
+    /// 接受这个包，继续交给下一个过滤函数
+    Accept,
+    /// 丢弃这个包
+    Drop,
+}
+
+impl NfVerdict {
+    fn name(&self) -> &'static str {
+        match self {
+            NfVerdict::Accept => "ACCEPT",
+            NfVerdict::Drop => "DROP",
+        }
+    }
+}
+
+impl FromStr for NfVerdict {
+    type Err = SystemError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ACCEPT" => Ok(NfVerdict::Accept),
+            "DROP" => Ok(NfVerdict::Drop),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// @brief 注册在挂载点上的过滤函数
+pub trait NfHookOps: Send + Sync + Debug {
+    /// @brief 对经过挂载点的IPv4包进行过滤
+    ///
+    /// @param hook 包所在的挂载点
+    /// @param packet 完整的IPv4包(包括IP头)
+    ///
+    /// @return 对这个包的判决
+    fn hook(&self, hook: NfHook, packet: &Ipv4Packet<&[u8]>) -> NfVerdict;
+}
+
+#[derive(Debug)]
+struct NfHookEntry {
+    priority: i32,
+    ops: Arc<dyn NfHookOps>,
+}
+
+/// 每个挂载点上注册的过滤函数，按照优先级从小到大排列
+static NF_HOOKS: RwLock<[Vec<NfHookEntry>; NF_HOOK_NUM]> =
+    RwLock::new([Vec::new(), Vec::new(), Vec::new()]);
+
+/// @brief 在挂载点上注册过滤函数
+///
+/// @param hook 挂载点
+/// @param priority 优先级，数值越小越先被调用
+/// @param ops 过滤函数
+pub fn nf_register_hook(hook: NfHook, priority: i32, ops: Arc<dyn NfHookOps>) {
+    let mut hooks = NF_HOOKS.write_irqsave();
+    let list = &mut hooks[hook as usize];
+    let pos = list
+        .iter()
+        .position(|entry| entry.priority > priority)
+        .unwrap_or(list.len());
+    list.insert(pos, NfHookEntry { priority, ops });
+}
+
+/// @brief 从挂载点上注销过滤函数
+///
+/// @return 过滤函数没有注册在这个挂载点上时，返回ENOENT
+pub fn nf_unregister_hook(hook: NfHook, ops: &Arc<dyn NfHookOps>) -> Result<(), SystemError> {
+    let mut hooks = NF_HOOKS.write_irqsave();
+    let list = &mut hooks[hook as usize];
+    let pos = list
+        .iter()
+        .position(|entry| Arc::ptr_eq(&entry.ops, ops))
+        .ok_or(SystemError::ENOENT)?;
+    list.remove(pos);
+    return Ok(());
+}
+
+/// 依次调用挂载点上的过滤函数，任意一个函数丢弃了这个包，就不再继续调用
+fn nf_hook_run(hook: NfHook, packet: &[u8]) -> NfVerdict {
+    let hooks = NF_HOOKS.read();
+    let list = &hooks[hook as usize];
+    if list.is_empty() {
+        return NfVerdict::Accept;
+    }
+    let packet = match Ipv4Packet::new_checked(packet) {
+        Ok(packet) => packet,
+        // 不是合法的IPv4包，交给协议栈处理
+        Err(_) => return NfVerdict::Accept,
+    };
+    for entry in list.iter() {
+        if entry.ops.hook(hook, &packet) == NfVerdict::Drop {
+            return NfVerdict::Drop;
+        }
+    }
+    return NfVerdict::Accept;
+}
+
+/// 从网卡收发的帧中取出IPv4包，不是IPv4包时返回None
+fn ipv4_packet(frame: &[u8], medium: Medium) -> Option<&[u8]> {
+    match medium {
+        Medium::Ethernet => {
+            let eth = EthernetFrame::new_checked(frame).ok()?;
+            if eth.ethertype() != EthernetProtocol::Ipv4 {
+                return None;
+            }
+            return Some(&frame[ETHERNET_HEADER_LEN..]);
+        }
+        Medium::Ip => {
+            if frame.first()? >> 4 != 4 {
+                return None;
+            }
+            return Some(frame);
+        }
+        #[allow(unreachable_patterns)]
+        _ => return None,
+    }
+}
+
+/// @brief 网卡收到一个帧时调用，依次经过PRE_ROUTING和LOCAL_IN挂载点
+///
+/// @param frame 收到的帧
+/// @param medium 网卡的介质类型
+///
+/// @return 判决为Drop时，网卡驱动应当直接丢弃这个帧
+pub fn nf_hook_input(frame: &[u8], medium: Medium) -> NfVerdict {
+    let packet = match ipv4_packet(frame, medium) {
+        Some(packet) => packet,
+        None => return NfVerdict::Accept,
+    };
+    if nf_hook_run(NfHook::PreRouting, packet) == NfVerdict::Drop {
+        return NfVerdict::Drop;
+    }
+    return nf_hook_run(NfHook::LocalIn, packet);
+}
+
+/// @brief 网卡发送一个帧之前调用，经过LOCAL_OUT挂载点
+///
+/// @param frame 将要发送的帧
+/// @param medium 网卡的介质类型
+///
+/// @return 判决为Drop时，网卡驱动不应发送这个帧
+pub fn nf_hook_output(frame: &[u8], medium: Medium) -> NfVerdict {
+    match ipv4_packet(frame, medium) {
+        Some(packet) => nf_hook_run(NfHook::LocalOut, packet),
+        None => NfVerdict::Accept,
+    }
+}
+
+/// @brief 规则表中的一条规则，所有指定了的条件都满足时，规则才匹配
+#[derive(Debug)]
+struct NfRule {
+    protocol: Option<IpProtocol>,
+    src: Option<Ipv4Cidr>,
+    dst: Option<Ipv4Cidr>,
+    sport: Option<u16>,
+    dport: Option<u16>,
+    verdict: NfVerdict,
+    /// 匹配了这条规则的包的数量
+    packets: AtomicUsize,
+}
+
+impl NfRule {
+    /// 解析`-A`命令中链名之后的部分
+    fn parse<'a>(mut args: impl Iterator<Item = &'a str>) -> Result<Self, SystemError> {
+        let mut rule = NfRule {
+            protocol: None,
+            src: None,
+            dst: None,
+            sport: None,
+            dport: None,
+            verdict: NfVerdict::Accept,
+            packets: AtomicUsize::new(0),
+        };
+        let mut verdict = None;
+        while let Some(opt) = args.next() {
+            let val = args.next().ok_or(SystemError::EINVAL)?;
+            match opt {
+                "-p" => rule.protocol = parse_protocol(val)?,
+                "-s" => rule.src = Some(parse_cidr(val)?),
+                "-d" => rule.dst = Some(parse_cidr(val)?),
+                "--sport" => rule.sport = Some(val.parse().map_err(|_| SystemError::EINVAL)?),
+                "--dport" => rule.dport = Some(val.parse().map_err(|_| SystemError::EINVAL)?),
+                "-j" => verdict = Some(NfVerdict::from_str(val)?),
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        rule.verdict = verdict.ok_or(SystemError::EINVAL)?;
+
+        // 与iptables一致，只有指定了tcp或udp协议时，才能匹配端口
+        if (rule.sport.is_some() || rule.dport.is_some())
+            && !matches!(rule.protocol, Some(IpProtocol::Tcp) | Some(IpProtocol::Udp))
+        {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(rule);
+    }
+
+    fn matches(&self, packet: &Ipv4Packet<&[u8]>) -> bool {
+        if let Some(protocol) = self.protocol {
+            if packet.next_header() != protocol {
+                return false;
+            }
+        }
+        if let Some(src) = self.src {
+            if !src.contains_addr(&packet.src_addr()) {
+                return false;
+            }
+        }
+        if let Some(dst) = self.dst {
+            if !dst.contains_addr(&packet.dst_addr()) {
+                return false;
+            }
+        }
+        if self.sport.is_some() || self.dport.is_some() {
+            let (sport, dport) = match transport_ports(packet) {
+                Some(ports) => ports,
+                None => return false,
+            };
+            if self.sport.map_or(false, |p| p != sport) || self.dport.map_or(false, |p| p != dport)
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+/// 获取tcp/udp包的源端口和目的端口。分片中只有第一个分片包含端口
+fn transport_ports(packet: &Ipv4Packet<&[u8]>) -> Option<(u16, u16)> {
+    if packet.frag_offset() != 0 {
+        return None;
+    }
+    let payload = packet.payload();
+    match packet.next_header() {
+        IpProtocol::Tcp => {
+            let tcp = TcpPacket::new_checked(payload).ok()?;
+            Some((tcp.src_port(), tcp.dst_port()))
+        }
+        IpProtocol::Udp => {
+            let udp = UdpPacket::new_checked(payload).ok()?;
+            Some((udp.src_port(), udp.dst_port()))
+        }
+        _ => None,
+    }
+}
+
+fn parse_protocol(s: &str) -> Result<Option<IpProtocol>, SystemError> {
+    let protocol = match s {
+        "all" => return Ok(None),
+        "tcp" => IpProtocol::Tcp,
+        "udp" => IpProtocol::Udp,
+        "icmp" => IpProtocol::Icmp,
+        _ => IpProtocol::from(s.parse::<u8>().map_err(|_| SystemError::EINVAL)?),
+    };
+    return Ok(Some(protocol));
+}
+
+fn protocol_name(protocol: Option<IpProtocol>) -> String {
+    match protocol {
+        None => "all".to_string(),
+        Some(IpProtocol::Tcp) => "tcp".to_string(),
+        Some(IpProtocol::Udp) => "udp".to_string(),
+        Some(IpProtocol::Icmp) => "icmp".to_string(),
+        Some(p) => u8::from(p).to_string(),
+    }
+}
+
+/// 解析`a.b.c.d[/prefix]`形式的地址，没有指定前缀长度时，只匹配这一个地址
+fn parse_cidr(s: &str) -> Result<Ipv4Cidr, SystemError> {
+    if s.contains('/') {
+        return Ipv4Cidr::from_str(s).map_err(|_| SystemError::EINVAL);
+    }
+    let addr = Ipv4Address::from_str(s).map_err(|_| SystemError::EINVAL)?;
+    return Ok(Ipv4Cidr::new(addr, 32));
+}
+
+#[derive(Debug)]
+struct NfChain {
+    /// 没有规则匹配时的判决
+    policy: NfVerdict,
+    rules: Vec<NfRule>,
+}
+
+impl NfChain {
+    const fn new() -> Self {
+        Self {
+            policy: NfVerdict::Accept,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// @brief 可以由用户态配置的规则表，在每个挂载点上对应一条链
+#[derive(Debug)]
+pub struct NfRuleTable {
+    chains: RwLock<[NfChain; NF_HOOK_NUM]>,
+}
+
+impl NfRuleTable {
+    /// 规则表的过滤函数的优先级，与Linux的NF_IP_PRI_FILTER一致
+    pub const PRIORITY: i32 = 0;
+
+    const fn new() -> Self {
+        Self {
+            chains: RwLock::new([NfChain::new(), NfChain::new(), NfChain::new()]),
+        }
+    }
+
+    /// @brief 执行一条修改规则表的命令
+    fn exec(&self, cmd: &str) -> Result<(), SystemError> {
+        let mut args = cmd.split_whitespace();
+        let op = match args.next() {
+            Some(op) => op,
+            // 忽略空行
+            None => return Ok(()),
+        };
+        let hook = args.next().map(NfHook::from_chain_name).transpose()?;
+
+        match op {
+            "-A" => {
+                let hook = hook.ok_or(SystemError::EINVAL)?;
+                let rule = NfRule::parse(args)?;
+                self.chains.write_irqsave()[hook as usize].rules.push(rule);
+            }
+            "-D" => {
+                let hook = hook.ok_or(SystemError::EINVAL)?;
+                let num: usize = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or(SystemError::EINVAL)?;
+                let mut chains = self.chains.write_irqsave();
+                let rules = &mut chains[hook as usize].rules;
+                if num == 0 || num > rules.len() {
+                    return Err(SystemError::ENOENT);
+                }
+                rules.remove(num - 1);
+            }
+            "-F" => {
+                let mut chains = self.chains.write_irqsave();
+                match hook {
+                    Some(hook) => chains[hook as usize].rules.clear(),
+                    None => chains.iter_mut().for_each(|chain| chain.rules.clear()),
+                }
+            }
+            "-P" => {
+                let hook = hook.ok_or(SystemError::EINVAL)?;
+                let policy = NfVerdict::from_str(args.next().ok_or(SystemError::EINVAL)?)?;
+                self.chains.write_irqsave()[hook as usize].policy = policy;
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
+        return Ok(());
+    }
+
+    /// @brief 以文本的形式列出所有链中的规则
+    fn show(&self) -> String {
+        let chains = self.chains.read();
+        let mut result = String::new();
+        for hook in NfHook::ALL {
+            let chain = &chains[hook as usize];
+            result.push_str(&format!(
+                "Chain {} (policy {})\n",
+                hook.chain_name(),
+                chain.policy.name()
+            ));
+            result.push_str("num\tpkts\ttarget\tprot\tsource\t\tdestination\toptions\n");
+            for (i, rule) in chain.rules.iter().enumerate() {
+                let any = Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0);
+                let mut options = String::new();
+                if let Some(sport) = rule.sport {
+                    options.push_str(&format!("spt:{} ", sport));
+                }
+                if let Some(dport) = rule.dport {
+                    options.push_str(&format!("dpt:{} ", dport));
+                }
+                result.push_str(&format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    i + 1,
+                    rule.packets.load(Ordering::Relaxed),
+                    rule.verdict.name(),
+                    protocol_name(rule.protocol),
+                    rule.src.unwrap_or(any),
+                    rule.dst.unwrap_or(any),
+                    options.trim_end()
+                ));
+            }
+            result.push('\n');
+        }
+        return result;
+    }
+}
+
+impl NfHookOps for NfRuleTable {
+    fn hook(&self, hook: NfHook, packet: &Ipv4Packet<&[u8]>) -> NfVerdict {
+        let chains = self.chains.read();
+        let chain = &chains[hook as usize];
+        for rule in chain.rules.iter() {
+            if rule.matches(packet) {
+                rule.packets.fetch_add(1, Ordering::Relaxed);
+                return rule.verdict;
+            }
+        }
+        return chain.policy;
+    }
+}
+
+lazy_static! {
+    /// 用户态可配置的规则表
+    static ref NF_RULE_TABLE: Arc<NfRuleTable> = Arc::new(NfRuleTable::new());
+}
+
+/// @brief 以文本的形式列出规则表中的规则，供`/proc/net/nf_rules`读取
+pub fn nf_rules_show() -> String {
+    return NF_RULE_TABLE.show();
+}
+
+/// @brief 执行用户态写入`/proc/net/nf_rules`的命令，每行一条命令
+///
+/// @return 成功时返回写入的长度；任意一条命令出错时，返回EINVAL或ENOENT，之前的命令仍然生效
+pub fn nf_rules_write(buf: &[u8]) -> Result<usize, SystemError> {
+    let s = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+    for line in s.split(|c| c == '\n' || c == '\0') {
+        NF_RULE_TABLE.exec(line)?;
+    }
+    return Ok(buf.len());
+}
+
+/// @brief 初始化数据包过滤框架，在所有挂载点上注册规则表
+pub fn netfilter_init() {
+    for hook in NfHook::ALL {
+        nf_register_hook(hook, NfRuleTable::PRIORITY, NF_RULE_TABLE.clone());
+    }
+    kinfo!("Netfilter initialized");
+}
@@ -0,0 +1,214 @@
+//! 内核中的ping工具，用于检查网络协议栈是否工作正常
+//!
+//! 向`/proc/net/ping`写入`<IPv4地址> [次数]`即可发起ping，写操作在ping结束之后返回。
+//! 读取`/proc/net/ping`得到最近一次ping的结果，格式与用户态的ping命令一致，结果同时会输出到内核日志中。
+
+use alloc::{format, string::String, vec};
+use smoltcp::{
+    phy::ChecksumCapabilities,
+    socket::icmp,
+    wire::{Icmpv4Packet, Icmpv4Repr, IpAddress, Ipv4Address},
+};
+
+use crate::{
+    arch::rand::rand,
+    kinfo,
+    libs::spinlock::SpinLock,
+    syscall::SystemError,
+    time::hrtimer::{ktime_get, Ktime},
+};
+
+use super::{
+    net_core::poll_ifaces,
+    socket::{GlobalSocketHandle, SOCKET_SET, SOCKET_WAITQUEUE},
+};
+
+/// 回显请求中携带的数据的长度，与用户态的ping命令一致
+const PING_DATA_LEN: usize = 56;
+/// 默认发送的回显请求的数量
+const PING_DEFAULT_COUNT: u16 = 4;
+/// 一次最多发送的回显请求的数量，避免写入者被长时间阻塞
+const PING_MAX_COUNT: u16 = 32;
+/// 两次回显请求之间的间隔(ns)
+const PING_INTERVAL: Ktime = 1000 * 1000 * 1000;
+/// 等待回显应答的时间(ns)
+const PING_TIMEOUT: Ktime = 1000 * 1000 * 1000;
+/// icmp socket的缓冲区能容纳的包的数量
+const PING_BUF_PACKETS: usize = 4;
+
+/// 最近一次ping的结果
+static PING_REPORT: SpinLock<String> = SpinLock::new(String::new());
+
+/// 等待直到条件满足或者到达截止时间
+///
+/// @param deadline 截止时间(单调时间, ns)
+/// @param cond 每次轮询网卡之后检查的条件
+///
+/// @return 条件满足时返回条件的结果，超时返回None
+fn wait_until<T>(deadline: Ktime, mut cond: impl FnMut() -> Option<T>) -> Option<T> {
+    loop {
+        poll_ifaces();
+        if let Some(res) = cond() {
+            return Some(res);
+        }
+        if ktime_get() >= deadline {
+            return None;
+        }
+        // 网络轮询线程会定期唤醒等待队列
+        SOCKET_WAITQUEUE.sleep();
+    }
+}
+
+/// @brief 向目标主机发送ICMP回显请求，并统计应答
+///
+/// @param target 目标主机的地址
+/// @param count 发送的回显请求的数量
+///
+/// @return 与用户态ping命令格式一致的结果；发送失败时返回错误
+pub fn ping(target: Ipv4Address, count: u16) -> Result<String, SystemError> {
+    let rx_buffer = icmp::PacketBuffer::new(
+        vec![icmp::PacketMetadata::EMPTY; PING_BUF_PACKETS],
+        vec![0; PING_BUF_PACKETS * (PING_DATA_LEN + 64)],
+    );
+    let tx_buffer = icmp::PacketBuffer::new(
+        vec![icmp::PacketMetadata::EMPTY; PING_BUF_PACKETS],
+        vec![0; PING_BUF_PACKETS * (PING_DATA_LEN + 64)],
+    );
+    let mut socket = icmp::Socket::new(rx_buffer, tx_buffer);
+    let ident = rand() as u16;
+    socket
+        .bind(icmp::Endpoint::Ident(ident))
+        .map_err(|_| SystemError::EINVAL)?;
+    // 函数返回时，句柄被释放，socket随之从集合中删除
+    let handle = GlobalSocketHandle::new(SOCKET_SET.lock().add(socket));
+
+    let checksum = ChecksumCapabilities::default();
+    let data = [0xa5u8; PING_DATA_LEN];
+    let mut report = format!(
+        "PING {} ({}) {}({}) bytes of data.\n",
+        target,
+        target,
+        PING_DATA_LEN,
+        PING_DATA_LEN + 28
+    );
+    let mut received: u32 = 0;
+    let (mut rtt_min, mut rtt_max, mut rtt_sum): (Ktime, Ktime, Ktime) = (Ktime::MAX, 0, 0);
+
+    for seq_no in 0..count {
+        let repr = Icmpv4Repr::EchoRequest {
+            ident,
+            seq_no,
+            data: &data,
+        };
+        let sent_at = ktime_get();
+        {
+            let mut sockets = SOCKET_SET.lock();
+            let socket = sockets.get_mut::<icmp::Socket>(handle.smoltcp_handle());
+            let buf = socket
+                .send(repr.buffer_len(), IpAddress::Ipv4(target))
+                .map_err(|_| SystemError::ENOBUFS)?;
+            repr.emit(&mut Icmpv4Packet::new_unchecked(buf), &checksum);
+        }
+
+        let reply = wait_until(sent_at + PING_TIMEOUT, || {
+            let mut sockets = SOCKET_SET.lock();
+            let socket = sockets.get_mut::<icmp::Socket>(handle.smoltcp_handle());
+            while socket.can_recv() {
+                let (payload, _) = socket.recv().ok()?;
+                let packet = match Icmpv4Packet::new_checked(payload) {
+                    Ok(packet) => packet,
+                    Err(_) => continue,
+                };
+                // 丢弃之前的请求超时之后才到达的应答
+                if let Ok(Icmpv4Repr::EchoReply {
+                    ident: reply_ident,
+                    seq_no: reply_seq,
+                    ..
+                }) = Icmpv4Repr::parse(&packet, &checksum)
+                {
+                    if reply_ident == ident && reply_seq == seq_no {
+                        return Some(payload.len());
+                    }
+                }
+            }
+            None
+        });
+
+        match reply {
+            Some(len) => {
+                let rtt = ktime_get() - sent_at;
+                received += 1;
+                rtt_min = rtt_min.min(rtt);
+                rtt_max = rtt_max.max(rtt);
+                rtt_sum += rtt;
+                report.push_str(&format!(
+                    "{} bytes from {}: icmp_seq={} time={}.{:03} ms\n",
+                    len,
+                    target,
+                    seq_no + 1,
+                    rtt / 1000000,
+                    rtt / 1000 % 1000
+                ));
+            }
+            None => {
+                report.push_str(&format!("Request timeout for icmp_seq={}\n", seq_no + 1));
+            }
+        }
+
+        if seq_no + 1 < count {
+            wait_until::<()>(sent_at + PING_INTERVAL, || None);
+        }
+    }
+
+    let loss = (count as u32 - received) * 100 / count as u32;
+    report.push_str(&format!(
+        "--- {} ping statistics ---\n{} packets transmitted, {} received, {}% packet loss\n",
+        target, count, received, loss
+    ));
+    if received > 0 {
+        let rtt_avg = rtt_sum / received as Ktime;
+        report.push_str(&format!(
+            "rtt min/avg/max = {}.{:03}/{}.{:03}/{}.{:03} ms\n",
+            rtt_min / 1000000,
+            rtt_min / 1000 % 1000,
+            rtt_avg / 1000000,
+            rtt_avg / 1000 % 1000,
+            rtt_max / 1000000,
+            rtt_max / 1000 % 1000
+        ));
+    }
+    return Ok(report);
+}
+
+/// @brief 执行用户态写入`/proc/net/ping`的命令，格式为`<IPv4地址> [次数]`
+///
+/// @return 成功时返回写入的长度；命令格式错误时返回EINVAL
+pub fn ping_proc_write(buf: &[u8]) -> Result<usize, SystemError> {
+    let s = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+    let mut args = s
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .split_whitespace();
+    let target = args
+        .next()
+        .and_then(|addr| addr.parse::<Ipv4Address>().ok())
+        .ok_or(SystemError::EINVAL)?;
+    let count = match args.next() {
+        Some(count) => count.parse::<u16>().map_err(|_| SystemError::EINVAL)?,
+        None => PING_DEFAULT_COUNT,
+    };
+    if count == 0 || count > PING_MAX_COUNT || args.next().is_some() {
+        return Err(SystemError::EINVAL);
+    }
+
+    let report = ping(target, count)?;
+    for line in report.lines() {
+        kinfo!("{}", line);
+    }
+    *PING_REPORT.lock() = report;
+    return Ok(buf.len());
+}
+
+/// @brief 最近一次ping的结果，供`/proc/net/ping`读取
+pub fn ping_proc_show() -> String {
+    return PING_REPORT.lock().clone();
+}
@@ -0,0 +1,160 @@
+//! IPv4路由表
+//!
+//! 每条路由把一个目的子网指向一张网卡，目的地不在网卡直连的子网中时还要经过网关。
+//! 给网卡设置地址时加入地址所在子网的直连路由([`super::ipv4::inet_set_addr`])，默认路由由[`super::ipconfig`]加入。
+//! 查找时选择前缀最长的路由，前缀一样长时选择跃点数(metric)最小的路由。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/fib_trie.c
+
+use alloc::{format, string::String, vec::Vec};
+use core::cmp::Reverse;
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+
+use crate::{kernel_test, ktest_assert, libs::rwlock::RwLock, syscall::SystemError};
+
+use super::NET_DRIVERS;
+
+/// 路由可用(RTF_UP)
+const RTF_UP: u32 = 0x0001;
+/// 路由的目的地需要经过网关(RTF_GATEWAY)
+const RTF_GATEWAY: u32 = 0x0002;
+
+/// 一条IPv4路由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Route {
+    /// 目的子网
+    pub dst: Ipv4Cidr,
+    /// 网关，直连路由没有网关
+    pub gateway: Option<Ipv4Address>,
+    /// 发送时使用的网卡的id
+    pub nic_id: usize,
+    /// 跃点数，越小越优先
+    pub metric: u32,
+}
+
+impl Ipv4Route {
+    /// 发往`dst`的包的下一跳
+    pub fn next_hop(&self, dst: Ipv4Address) -> Ipv4Address {
+        return self.gateway.unwrap_or(dst);
+    }
+
+    fn flags(&self) -> u32 {
+        match self.gateway {
+            Some(_) => RTF_UP | RTF_GATEWAY,
+            None => RTF_UP,
+        }
+    }
+}
+
+static ROUTE_TABLE: RwLock<Vec<Ipv4Route>> = RwLock::new(Vec::new());
+
+/// 加入一条路由，目的子网中的主机位会被清零
+///
+/// ## 错误
+///
+/// - `EEXIST`：已经有目的子网、网卡和跃点数都相同的路由
+pub fn ip_route_add(mut route: Ipv4Route) -> Result<(), SystemError> {
+    route.dst = route.dst.network();
+    let mut table = ROUTE_TABLE.write();
+    if table
+        .iter()
+        .any(|r| r.dst == route.dst && r.nic_id == route.nic_id && r.metric == route.metric)
+    {
+        return Err(SystemError::EEXIST);
+    }
+    table.push(route);
+    return Ok(());
+}
+
+/// 删除与`route`的目的子网、网关和网卡都相同的路由
+///
+/// ## 错误
+///
+/// - `ESRCH`：没有这样的路由
+pub fn ip_route_del(route: &Ipv4Route) -> Result<(), SystemError> {
+    let dst = route.dst.network();
+    let mut table = ROUTE_TABLE.write();
+    let len = table.len();
+    table.retain(|r| !(r.dst == dst && r.gateway == route.gateway && r.nic_id == route.nic_id));
+    if table.len() == len {
+        return Err(SystemError::ESRCH);
+    }
+    return Ok(());
+}
+
+/// 在路由表中查找前缀最长、跃点数最小的路由
+fn fib_lookup(table: &[Ipv4Route], dst: Ipv4Address) -> Option<Ipv4Route> {
+    return table
+        .iter()
+        .filter(|r| r.dst.contains_addr(&dst))
+        .max_by_key(|r| (r.dst.prefix_len(), Reverse(r.metric)))
+        .copied();
+}
+
+/// 查找发往`dst`的路由
+pub fn ip_route_lookup(dst: Ipv4Address) -> Option<Ipv4Route> {
+    return fib_lookup(&ROUTE_TABLE.read(), dst);
+}
+
+/// 按照Linux的/proc/net/route的格式输出IPv4路由表，地址按照网络字节序以十六进制输出
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/fib_trie.c#fib_route_seq_show
+pub fn route_show() -> String {
+    // 与Linux相同，每一行都填充到127个字符
+    let mut result = format!(
+        "{:<127}\n",
+        "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT"
+    );
+    let hex = |addr: Ipv4Address| u32::from_le_bytes(addr.0);
+    let drivers = NET_DRIVERS.read();
+    for route in ROUTE_TABLE.read().iter() {
+        let name = match drivers.get(&route.nic_id) {
+            Some(iface) => iface.name(),
+            None => continue,
+        };
+        let line = format!(
+            "{}\t{:08X}\t{:08X}\t{:04X}\t{}\t{}\t{}\t{:08X}\t{}\t{}\t{}",
+            name,
+            hex(route.dst.address()),
+            hex(route.gateway.unwrap_or(Ipv4Address::UNSPECIFIED)),
+            route.flags(),
+            0,
+            0,
+            route.metric,
+            hex(route.dst.netmask()),
+            0,
+            0,
+            0
+        );
+        result.push_str(&format!("{:<127}\n", line));
+    }
+    return result;
+}
+
+/// 检查最长前缀匹配与跃点数的选择
+fn route_lookup() -> Result<(), SystemError> {
+    let route = |a, b, c, d, prefix, gateway, metric| Ipv4Route {
+        dst: Ipv4Cidr::new(Ipv4Address::new(a, b, c, d), prefix),
+        gateway,
+        nic_id: 0,
+        metric,
+    };
+    let gw = Some(Ipv4Address::new(10, 0, 2, 2));
+    let table = [
+        route(0, 0, 0, 0, 0, gw, 0),
+        route(10, 0, 2, 0, 24, None, 100),
+        route(10, 0, 2, 0, 24, None, 10),
+        route(10, 0, 2, 128, 25, None, 200),
+    ];
+
+    let other = Ipv4Address::new(192, 168, 1, 1);
+    ktest_assert!(fib_lookup(&table, other) == Some(table[0]));
+    ktest_assert!(table[0].next_hop(other) == gw.unwrap());
+    let host = Ipv4Address::new(10, 0, 2, 15);
+    ktest_assert!(fib_lookup(&table, host) == Some(table[2]));
+    ktest_assert!(table[2].next_hop(host) == host);
+    ktest_assert!(fib_lookup(&table, Ipv4Address::new(10, 0, 2, 200)) == Some(table[3]));
+    ktest_assert!(fib_lookup(&table[1..], other).is_none());
+    return Ok(());
+}
+kernel_test!(route_lookup);
@@ -0,0 +1,133 @@
+//! 网络数据包的缓冲区
+//!
+//! [`SkBuff`]在数据的前面预留一段空间(headroom)。发送时先放入负载，再由各层协议在前面依次加上自己的头部；
+//! 接收时各层协议依次剥去自己的头部。两种情况下都不需要移动数据。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/skbuff.h
+
+use alloc::{vec, vec::Vec};
+use smoltcp::wire::{EthernetFrame, EthernetProtocol};
+
+use crate::syscall::SystemError;
+
+/// 一个网络数据包
+///
+/// 缓冲区的布局如下，有效的数据位于`data`与`tail`之间：
+///
+/// ```ignore
+/// 0          data             tail            buf.len()
+/// | headroom |      数据       |    tailroom    |
+/// ```
+#[derive(Debug, Clone)]
+pub struct SkBuff {
+    buf: Vec<u8>,
+    data: usize,
+    tail: usize,
+    /// 收到这个包的网卡的id，或者发送这个包的网卡的id
+    pub nic_id: usize,
+    /// 以太网帧的类型
+    pub protocol: EthernetProtocol,
+}
+
+impl SkBuff {
+    /// 创建一个空的数据包，前面预留`headroom`字节，后面最多可以放入`size`字节
+    pub fn new(headroom: usize, size: usize) -> Self {
+        return Self {
+            buf: vec![0; headroom + size],
+            data: headroom,
+            tail: headroom,
+            nic_id: 0,
+            protocol: EthernetProtocol::Unknown(0),
+        };
+    }
+
+    /// 复制网卡收到的一个完整的以太网帧
+    pub fn from_frame(nic_id: usize, frame: &[u8]) -> Self {
+        let mut skb = Self::new(0, frame.len());
+        skb.put(frame.len()).unwrap().copy_from_slice(frame);
+        skb.nic_id = nic_id;
+        if let Ok(eth) = EthernetFrame::new_checked(frame) {
+            skb.protocol = eth.ethertype();
+        }
+        return skb;
+    }
+
+    /// 有效数据的长度
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.tail - self.data;
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// 数据前面还可以加入的字节数
+    #[inline]
+    pub fn headroom(&self) -> usize {
+        return self.data;
+    }
+
+    /// 数据后面还可以放入的字节数
+    #[inline]
+    pub fn tailroom(&self) -> usize {
+        return self.buf.len() - self.tail;
+    }
+
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        return &self.buf[self.data..self.tail];
+    }
+
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        return &mut self.buf[self.data..self.tail];
+    }
+
+    /// 在数据的末尾加入`len`字节，返回加入的部分
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOBUFS`：后面的空间不足
+    pub fn put(&mut self, len: usize) -> Result<&mut [u8], SystemError> {
+        if len > self.tailroom() {
+            return Err(SystemError::ENOBUFS);
+        }
+        self.tail += len;
+        return Ok(&mut self.buf[self.tail - len..self.tail]);
+    }
+
+    /// 在数据的前面加入`len`字节(例如协议头)，返回加入的部分
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOBUFS`：前面预留的空间不足
+    pub fn push(&mut self, len: usize) -> Result<&mut [u8], SystemError> {
+        if len > self.headroom() {
+            return Err(SystemError::ENOBUFS);
+        }
+        self.data -= len;
+        return Ok(&mut self.buf[self.data..self.data + len]);
+    }
+
+    /// 从数据的前面去掉`len`字节(例如已经处理完的协议头)
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：数据的长度小于`len`
+    pub fn pull(&mut self, len: usize) -> Result<(), SystemError> {
+        if len > self.len() {
+            return Err(SystemError::EINVAL);
+        }
+        self.data += len;
+        return Ok(());
+    }
+
+    /// 把数据截断为`len`字节，例如去掉以太网帧末尾的填充。数据本来就不超过`len`字节时什么也不做
+    pub fn trim(&mut self, len: usize) {
+        if len < self.len() {
+            self.tail = self.data + len;
+        }
+    }
+}
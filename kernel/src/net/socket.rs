@@ -1,8 +1,18 @@
 #![allow(dead_code)]
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use hashbrown::HashMap;
 use smoltcp::{
     iface::{SocketHandle, SocketSet},
+    phy::Medium,
     socket::{raw, tcp, udp},
     wire,
 };
@@ -10,16 +20,31 @@ use smoltcp::{
 use crate::{
     arch::rand::rand,
     driver::net::NetDriver,
-    filesystem::vfs::{syscall::ModeType, FileType, IndexNode, Metadata, PollStatus},
+    filesystem::vfs::{
+        file::FileMode, syscall::ModeType, FilePrivateData, FileType, IndexNode, Metadata,
+        PollStatus, PollTable,
+    },
     kerror, kwarn,
     libs::{
         spinlock::{SpinLock, SpinLockGuard},
         wait_queue::WaitQueue,
     },
-    syscall::SystemError,
+    process::ProcessManager,
+    syscall::{user_access::UserBufferReader, SystemError},
+    time::{
+        hrtimer::{ktime_get, Ktime},
+        Duration,
+    },
 };
 
-use super::{net_core::poll_ifaces, Endpoint, Protocol, Socket, NET_DRIVERS};
+use super::{
+    bpf::{BpfProgram, SockFilter, SockFprog, BPF_MAXINSNS},
+    endpoints::{LinkLayerEndpoint, NetlinkEndpoint},
+    icmp::{sock_error_pending, sock_error_take},
+    net_core::{dev_ioctl, iface_index, poll_ifaces, route_iface},
+    syscall::{PosixIpProtocol, PosixSocketOption, PosixTcpSocketOptions},
+    Endpoint, Protocol, ShutdownType, Socket, NET_DRIVERS,
+};
 
 lazy_static! {
     /// 所有socket的集合
@@ -74,7 +99,12 @@ impl PortManager {
             let listen_table_guard = match socket_type {
                 SocketType::UdpSocket => self.udp_port_table.lock(),
                 SocketType::TcpSocket => self.tcp_port_table.lock(),
-                SocketType::RawSocket => panic!("RawSocket cann't get a port"),
+                SocketType::RawSocket
+                | SocketType::UnixSocket
+                | SocketType::PacketSocket
+                | SocketType::NetlinkSocket => {
+                    panic!("{:?} cann't get a port", socket_type)
+                }
             };
             if let None = listen_table_guard.get(&port) {
                 drop(listen_table_guard);
@@ -87,34 +117,68 @@ impl PortManager {
 
     /// @brief 检测给定端口是否已被占用，如果未被占用则在 TCP/UDP 对应的表中记录
     ///
-    /// TODO: 增加支持端口复用的逻辑
+    /// @param reuse_addr 是否设置了SO_REUSEADDR。设置了SO_REUSEADDR时，
+    /// 只要占用端口的不是正在监听的tcp socket，仍然允许绑定，端口记录表中的记录会被替换为新的socket
+    ///
+    /// 设置了reuse_addr且端口已被占用时会对SOCKET_SET加锁，调用者不能持有SOCKET_SET的锁
     pub fn bind_port(
         &self,
         socket_type: SocketType,
         port: u16,
         handle: Arc<GlobalSocketHandle>,
+        reuse_addr: bool,
     ) -> Result<(), SystemError> {
         if port > 0 {
             let mut listen_table_guard = match socket_type {
                 SocketType::UdpSocket => self.udp_port_table.lock(),
                 SocketType::TcpSocket => self.tcp_port_table.lock(),
-                SocketType::RawSocket => panic!("RawSocket cann't bind a port"),
-            };
-            match listen_table_guard.get(&port) {
-                Some(_) => return Err(SystemError::EADDRINUSE),
-                None => listen_table_guard.insert(port, handle),
+                SocketType::RawSocket
+                | SocketType::UnixSocket
+                | SocketType::PacketSocket
+                | SocketType::NetlinkSocket => {
+                    panic!("{:?} cann't bind a port", socket_type)
+                }
             };
+            if let Some(old) = listen_table_guard.get(&port).cloned() {
+                if !reuse_addr {
+                    return Err(SystemError::EADDRINUSE);
+                }
+                // 其他地方会在持有SOCKET_SET的锁时获取端口记录表的锁，这里不能反过来
+                drop(listen_table_guard);
+                if Self::is_listening(socket_type, &old) {
+                    return Err(SystemError::EADDRINUSE);
+                }
+                listen_table_guard = match socket_type {
+                    SocketType::UdpSocket => self.udp_port_table.lock(),
+                    _ => self.tcp_port_table.lock(),
+                };
+            }
+            listen_table_guard.insert(port, handle);
             drop(listen_table_guard);
         }
         return Ok(());
     }
 
+    /// 占用端口的socket是否正在监听
+    fn is_listening(socket_type: SocketType, handle: &GlobalSocketHandle) -> bool {
+        match socket_type {
+            SocketType::TcpSocket => SOCKET_SET
+                .lock()
+                .get::<tcp::Socket>(handle.0)
+                .is_listening(),
+            _ => false,
+        }
+    }
+
     /// @brief 在对应的端口记录表中将端口和 socket 解绑
     pub fn unbind_port(&self, socket_type: SocketType, port: u16) -> Result<(), SystemError> {
         let mut listen_table_guard = match socket_type {
             SocketType::UdpSocket => self.udp_port_table.lock(),
             SocketType::TcpSocket => self.tcp_port_table.lock(),
-            SocketType::RawSocket => return Ok(()),
+            SocketType::RawSocket
+            | SocketType::UnixSocket
+            | SocketType::PacketSocket
+            | SocketType::NetlinkSocket => return Ok(()),
         };
         listen_table_guard.remove(&port);
         drop(listen_table_guard);
@@ -125,6 +189,9 @@ impl PortManager {
 /* For setsockopt(2) */
 // See: linux-5.19.10/include/uapi/asm-generic/socket.h#9
 pub const SOL_SOCKET: u8 = 1;
+// See: linux-6.1.9/include/linux/socket.h#356
+pub const SOL_PACKET: usize = 263;
+pub const SOL_NETLINK: usize = 270;
 
 /// @brief socket的句柄管理组件。
 /// 它在smoltcp的SocketHandle上封装了一层，增加更多的功能。
@@ -136,6 +203,11 @@ impl GlobalSocketHandle {
     pub fn new(handle: SocketHandle) -> Arc<Self> {
         return Arc::new(Self(handle));
     }
+
+    /// @brief 获取smoltcp的socket句柄
+    pub fn smoltcp_handle(&self) -> SocketHandle {
+        return self.0;
+    }
 }
 
 impl Clone for GlobalSocketHandle {
@@ -162,6 +234,12 @@ pub enum SocketType {
     TcpSocket,
     /// 用于Udp通信的 Socket
     UdpSocket,
+    /// 本地通信的Unix域socket
+    UnixSocket,
+    /// 捕获链路层帧的packet socket
+    PacketSocket,
+    /// 内核与用户程序通信的netlink socket
+    NetlinkSocket,
 }
 
 bitflags! {
@@ -181,6 +259,73 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// @brief send/recv系列系统调用的消息标志
+    ///
+    /// 参考：https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/socket.h#299
+    pub struct MessageFlag: u32 {
+        /// 带外数据
+        const OOB = 0x1;
+        /// 读取数据，但不把数据从接收队列中移除
+        const PEEK = 0x2;
+        /// 不经过路由，直接发送
+        const DONTROUTE = 0x4;
+        /// 数据报被截断时，返回数据报的实际长度
+        const TRUNC = 0x20;
+        /// 本次操作不阻塞
+        const DONTWAIT = 0x40;
+        /// 等待直到读取到请求的全部数据
+        const WAITALL = 0x100;
+        /// 对端关闭时不发送SIGPIPE
+        const NOSIGNAL = 0x4000;
+        /// 为本次accept得到的文件描述符设置close-on-exec
+        const CMSG_CLOEXEC = 0x40000000;
+    }
+}
+
+/// 从setsockopt的optval中读取一个int
+fn sockopt_int(optval: &[u8]) -> Result<i32, SystemError> {
+    if optval.len() < core::mem::size_of::<i32>() {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(i32::from_ne_bytes(optval[..4].try_into().unwrap()));
+}
+
+/// 把int写入getsockopt的optval中，optval不够长时截断
+///
+/// @return 写入的长度
+fn put_sockopt_int(optval: &mut [u8], val: i32) -> usize {
+    let bytes = val.to_ne_bytes();
+    let len = core::cmp::min(optval.len(), bytes.len());
+    optval[..len].copy_from_slice(&bytes[..len]);
+    return len;
+}
+
+/// SO_ERROR中返回的错误码(正数)，没有错误时为0
+fn errno_of(err: Option<SystemError>) -> i32 {
+    return err.map_or(0, |e| -e.to_posix_errno());
+}
+
+/// 阻塞的socket操作在数据未就绪时调用，等待网卡收到新的数据
+///
+/// @param flags 消息标志，设置了MSG_DONTWAIT时不等待
+/// @param deadline 等待的截止时间(单调时间, ns)，由SO_RCVTIMEO决定
+///
+/// @return 可以继续等待时返回Ok，否则返回EAGAIN_OR_EWOULDBLOCK
+fn socket_wait(flags: MessageFlag, deadline: Option<Ktime>) -> Result<(), SystemError> {
+    if flags.contains(MessageFlag::DONTWAIT) {
+        return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    }
+    if let Some(deadline) = deadline {
+        if ktime_get() >= deadline {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+    }
+    // 网络轮询线程会定期唤醒等待队列，因此超时能够被及时地检查到
+    SOCKET_WAITQUEUE.sleep();
+    return Ok(());
+}
+
 #[derive(Debug, Clone)]
 /// @brief 在trait Socket的metadata函数中返回该结构体供外部使用
 pub struct SocketMetadata {
@@ -194,6 +339,8 @@ pub struct SocketMetadata {
     pub metadata_buf_size: usize,
     /// socket的选项
     pub options: SocketOptions,
+    /// 接收数据的超时时间(SO_RCVTIMEO)，None表示永不超时
+    pub recv_timeout: Option<Duration>,
 }
 
 impl SocketMetadata {
@@ -210,7 +357,112 @@ impl SocketMetadata {
             recv_buf_size,
             metadata_buf_size,
             options,
+            recv_timeout: None,
+        }
+    }
+
+    /// 本次接收数据的截止时间(单调时间, ns)
+    fn recv_deadline(&self) -> Option<Ktime> {
+        return self
+            .recv_timeout
+            .map(|t| ktime_get() + t.total_micros() * 1000);
+    }
+
+    /// socket对应的posix套接字类型
+    fn posix_type(&self) -> PosixSocketType {
+        match self.socket_type {
+            SocketType::RawSocket | SocketType::PacketSocket | SocketType::NetlinkSocket => {
+                PosixSocketType::Raw
+            }
+            SocketType::TcpSocket | SocketType::UnixSocket => PosixSocketType::Stream,
+            SocketType::UdpSocket => PosixSocketType::Datagram,
+        }
+    }
+
+    /// @brief 设置所有socket共有的SOL_SOCKET层的选项
+    ///
+    /// 支持SO_REUSEADDR、SO_REUSEPORT、SO_BROADCAST和SO_RCVTIMEO
+    ///
+    /// @return 不支持该选项时，返回ENOPROTOOPT
+    pub fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level as u8 != SOL_SOCKET {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+        let optname =
+            PosixSocketOption::try_from(optname as i32).map_err(|_| SystemError::ENOPROTOOPT)?;
+        let flag = match optname {
+            PosixSocketOption::SO_REUSEADDR => SocketOptions::REUSEADDR,
+            PosixSocketOption::SO_REUSEPORT => SocketOptions::REUSEPORT,
+            PosixSocketOption::SO_BROADCAST => SocketOptions::BROADCAST,
+            PosixSocketOption::SO_RCVTIMEO_OLD | PosixSocketOption::SO_RCVTIMEO_NEW => {
+                // struct timeval { long tv_sec; long tv_usec; }
+                if optval.len() < 2 * core::mem::size_of::<i64>() {
+                    return Err(SystemError::EINVAL);
+                }
+                let sec = i64::from_ne_bytes(optval[..8].try_into().unwrap());
+                let usec = i64::from_ne_bytes(optval[8..16].try_into().unwrap());
+                if sec < 0 || !(0..1000000).contains(&usec) {
+                    return Err(SystemError::EDOM);
+                }
+                let micros = (sec as u64) * 1000000 + usec as u64;
+                self.recv_timeout = if micros == 0 {
+                    None
+                } else {
+                    Some(Duration::from_micros(micros))
+                };
+                return Ok(());
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        };
+        self.options.set(flag, sockopt_int(optval)? != 0);
+        return Ok(());
+    }
+
+    /// @brief 获取所有socket共有的SOL_SOCKET层的选项
+    ///
+    /// @return 返回写入optval的长度，不支持该选项时，返回ENOPROTOOPT
+    pub fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level as u8 != SOL_SOCKET {
+            return Err(SystemError::ENOPROTOOPT);
         }
+        let optname =
+            PosixSocketOption::try_from(optname as i32).map_err(|_| SystemError::ENOPROTOOPT)?;
+        let val = match optname {
+            PosixSocketOption::SO_TYPE => self.posix_type() as i32,
+            PosixSocketOption::SO_ERROR => 0,
+            PosixSocketOption::SO_SNDBUF => self.send_buf_size as i32,
+            PosixSocketOption::SO_RCVBUF => self.recv_buf_size as i32,
+            PosixSocketOption::SO_REUSEADDR => {
+                self.options.contains(SocketOptions::REUSEADDR) as i32
+            }
+            PosixSocketOption::SO_REUSEPORT => {
+                self.options.contains(SocketOptions::REUSEPORT) as i32
+            }
+            PosixSocketOption::SO_BROADCAST => {
+                self.options.contains(SocketOptions::BROADCAST) as i32
+            }
+            PosixSocketOption::SO_RCVTIMEO_OLD | PosixSocketOption::SO_RCVTIMEO_NEW => {
+                let micros = self.recv_timeout.map_or(0, |t| t.total_micros());
+                let mut tv = [0u8; 16];
+                tv[..8].copy_from_slice(&((micros / 1000000) as i64).to_ne_bytes());
+                tv[8..].copy_from_slice(&((micros % 1000000) as i64).to_ne_bytes());
+                let len = core::cmp::min(optval.len(), tv.len());
+                optval[..len].copy_from_slice(&tv[..len]);
+                return Ok(len);
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        };
+        return Ok(put_sockopt_int(optval, val));
     }
 }
 
@@ -280,7 +532,12 @@ impl RawSocket {
 }
 
 impl Socket for RawSocket {
-    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+    fn recv(&self, buf: &mut [u8], flags: MessageFlag) -> (Result<usize, SystemError>, Endpoint) {
+        if flags.contains(MessageFlag::PEEK) {
+            // smoltcp的原始socket不支持只读取、不移除数据包
+            return (Err(SystemError::EOPNOTSUPP_OR_ENOTSUP), Endpoint::Ip(None));
+        }
+        let deadline = self.metadata.recv_deadline();
         poll_ifaces();
         loop {
             // 如何优化这里？
@@ -307,11 +564,18 @@ impl Socket for RawSocket {
             }
             drop(socket);
             drop(socket_set_guard);
-            SOCKET_WAITQUEUE.sleep();
+            if let Err(e) = socket_wait(flags, deadline) {
+                return (Err(e), Endpoint::Ip(None));
+            }
         }
     }
 
-    fn write(&self, buf: &[u8], to: Option<super::Endpoint>) -> Result<usize, SystemError> {
+    fn send(
+        &self,
+        buf: &[u8],
+        to: Option<super::Endpoint>,
+        _flags: MessageFlag,
+    ) -> Result<usize, SystemError> {
         // 如果用户发送的数据包，包含IP头，则直接发送
         if self.header_included {
             let mut socket_set_guard = SOCKET_SET.lock();
@@ -332,8 +596,7 @@ impl Socket for RawSocket {
                 let socket: &mut raw::Socket =
                     socket_set_guard.get_mut::<raw::Socket>(self.handle.0);
 
-                // 暴力解决方案：只考虑0号网卡。 TODO：考虑多网卡的情况！！！
-                let iface = NET_DRIVERS.read().get(&0).unwrap().clone();
+                let iface = route_iface(&endpoint.addr)?;
 
                 // 构造IP头
                 let ipv4_src_addr: Option<smoltcp::wire::Ipv4Address> =
@@ -392,6 +655,15 @@ impl Socket for RawSocket {
         return Ok(());
     }
 
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        return self.metadata.setsockopt(level, optname, optval);
+    }
+
     fn metadata(&self) -> Result<SocketMetadata, SystemError> {
         Ok(self.metadata.clone())
     }
@@ -399,6 +671,10 @@ impl Socket for RawSocket {
     fn box_clone(&self) -> alloc::boxed::Box<dyn Socket> {
         return Box::new(self.clone());
     }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
 }
 
 /// @brief 表示udp socket
@@ -455,10 +731,28 @@ impl UdpSocket {
         };
     }
 
+    /// 已连接的socket的(本地端口, 远端端点)，用于查找ICMP差错。与Linux一致，未连接的udp socket不报告ICMP差错
+    fn connected_pair(&self, socket: &udp::Socket) -> Option<(u16, wire::IpEndpoint)> {
+        let remote = match self.remote_endpoint {
+            Some(Endpoint::Ip(Some(remote))) => remote,
+            _ => return None,
+        };
+        let port = socket.endpoint().port;
+        if port == 0 {
+            return None;
+        }
+        return Some((port, remote));
+    }
+
     fn do_bind(&self, socket: &mut udp::Socket, endpoint: Endpoint) -> Result<(), SystemError> {
         if let Endpoint::Ip(Some(ip)) = endpoint {
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port, self.handle.clone())?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                ip.port,
+                self.handle.clone(),
+                self.metadata.options.contains(SocketOptions::REUSEADDR),
+            )?;
 
             let bind_res = if ip.addr.is_unspecified() {
                 socket.bind(ip.port)
@@ -477,8 +771,9 @@ impl UdpSocket {
 }
 
 impl Socket for UdpSocket {
-    /// @brief 在read函数执行之前，请先bind到本地的指定端口
-    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+    /// @brief 在recv函数执行之前，请先bind到本地的指定端口
+    fn recv(&self, buf: &mut [u8], flags: MessageFlag) -> (Result<usize, SystemError>, Endpoint) {
+        let deadline = self.metadata.recv_deadline();
         loop {
             // kdebug!("Wait22 to Read");
             poll_ifaces();
@@ -488,23 +783,41 @@ impl Socket for UdpSocket {
             // kdebug!("Wait to Read");
 
             if socket.can_recv() {
-                if let Ok((size, remote_endpoint)) = socket.recv_slice(buf) {
+                let res = if flags.contains(MessageFlag::PEEK) {
+                    socket
+                        .peek_slice(buf)
+                        .map(|(size, meta)| (size, meta.endpoint))
+                } else {
+                    socket
+                        .recv_slice(buf)
+                        .map(|(size, meta)| (size, meta.endpoint))
+                };
+                if let Ok((size, remote_endpoint)) = res {
                     drop(socket);
                     drop(socket_set_guard);
                     poll_ifaces();
                     return (Ok(size), Endpoint::Ip(Some(remote_endpoint)));
                 }
-            } else {
-                // 如果socket没有连接，则忙等
-                // return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
+            } else if let Some((port, remote)) = self.connected_pair(socket) {
+                // 没有数据可读时，报告对端返回的ICMP差错(如端口不可达)
+                if let Some(e) = sock_error_take(wire::IpProtocol::Udp, port, remote) {
+                    return (Err(e), Endpoint::Ip(None));
+                }
             }
             drop(socket);
             drop(socket_set_guard);
-            SOCKET_WAITQUEUE.sleep();
+            if let Err(e) = socket_wait(flags, deadline) {
+                return (Err(e), Endpoint::Ip(None));
+            }
         }
     }
 
-    fn write(&self, buf: &[u8], to: Option<super::Endpoint>) -> Result<usize, SystemError> {
+    fn send(
+        &self,
+        buf: &[u8],
+        to: Option<super::Endpoint>,
+        _flags: MessageFlag,
+    ) -> Result<usize, SystemError> {
         // kdebug!("udp to send: {:?}, len={}", to, buf.len());
         let remote_endpoint: &wire::IpEndpoint = {
             if let Some(Endpoint::Ip(Some(ref endpoint))) = to {
@@ -527,6 +840,10 @@ impl Socket for UdpSocket {
             let local_ep = match remote_endpoint.addr {
                 // 远程remote endpoint使用什么协议，发送的时候使用的协议是一样的吧
                 // 否则就用 self.endpoint().addr.unwrap()
+                // 发往回环地址时，源地址也使用回环地址，使得对方的回复能够经由lo送回
+                wire::IpAddress::Ipv4(addr) if addr.is_loopback() => Endpoint::Ip(Some(
+                    wire::IpEndpoint::new(wire::IpAddress::v4(127, 0, 0, 1), temp_port),
+                )),
                 wire::IpAddress::Ipv4(_) => Endpoint::Ip(Some(wire::IpEndpoint::new(
                     smoltcp::wire::IpAddress::Ipv4(wire::Ipv4Address::UNSPECIFIED),
                     temp_port,
@@ -568,11 +885,44 @@ impl Socket for UdpSocket {
         return self.do_bind(socket, endpoint);
     }
 
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        return self.metadata.setsockopt(level, optname, optval);
+    }
+
     fn poll(&self) -> (bool, bool, bool) {
         let sockets = SOCKET_SET.lock();
         let socket = sockets.get::<udp::Socket>(self.handle.0);
+        let error = self.connected_pair(socket).map_or(false, |(port, remote)| {
+            sock_error_pending(wire::IpProtocol::Udp, port, remote)
+        });
+
+        return (socket.can_send(), socket.can_recv(), error);
+    }
 
-        return (socket.can_send(), socket.can_recv(), false);
+    /// @brief 获取udp socket的选项
+    ///
+    /// SO_ERROR返回并清除已连接的socket上记录的ICMP差错，
+    /// 其余选项由[`SocketMetadata::getsockopt`]处理
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level as u8 == SOL_SOCKET && optname == PosixSocketOption::SO_ERROR as usize {
+            let sockets = SOCKET_SET.lock();
+            let pair = self.connected_pair(sockets.get::<udp::Socket>(self.handle.0));
+            drop(sockets);
+            let err = pair
+                .and_then(|(port, remote)| sock_error_take(wire::IpProtocol::Udp, port, remote));
+            return Ok(put_sockopt_int(optval, errno_of(err)));
+        }
+        return self.metadata.getsockopt(level, optname, optval);
     }
 
     /// @brief
@@ -602,6 +952,10 @@ impl Socket for UdpSocket {
         return Box::new(self.clone());
     }
 
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
     fn endpoint(&self) -> Option<Endpoint> {
         let sockets = SOCKET_SET.lock();
         let socket = sockets.get::<udp::Socket>(self.handle.0);
@@ -634,8 +988,13 @@ impl Socket for UdpSocket {
 #[derive(Debug, Clone)]
 pub struct TcpSocket {
     handle: Arc<GlobalSocketHandle>,
+    /// 监听时，除了`handle`之外额外创建的监听socket，用于同时接受多个连接(backlog)
+    backlog_handles: Vec<Arc<GlobalSocketHandle>>,
     local_endpoint: Option<wire::IpEndpoint>, // save local endpoint for bind()
     is_listening: bool,
+    /// 是否已经通过shutdown(SHUT_RD)关闭了接收方向。smoltcp不支持单独关闭接收方向，
+    /// 因此由这里记录，所有克隆出的TcpSocket共享它
+    read_shutdown: Arc<AtomicBool>,
     metadata: SocketMetadata,
 }
 
@@ -646,6 +1005,11 @@ impl TcpSocket {
     pub const DEFAULT_RX_BUF_SIZE: usize = 512 * 1024;
     /// 默认的接收缓冲区的大小 receive
     pub const DEFAULT_TX_BUF_SIZE: usize = 512 * 1024;
+    /// listen的backlog的上限。每个等待accept的连接都需要一个完整的smoltcp socket(包括收发缓冲区)，
+    /// 因此这个值不能太大
+    pub const MAX_BACKLOG: usize = 8;
+    /// 开启SO_KEEPALIVE时，默认的空闲时间(s)，与Linux的tcp_keepalive_time一致
+    pub const DEFAULT_KEEPALIVE_SECS: u64 = 7200;
 
     /// @brief 创建一个原始的socket
     ///
@@ -672,11 +1036,67 @@ impl TcpSocket {
 
         return Self {
             handle,
+            backlog_handles: Vec::new(),
             local_endpoint: None,
             is_listening: false,
+            read_shutdown: Arc::new(AtomicBool::new(false)),
             metadata,
         };
     }
+
+    /// 创建一个新的smoltcp的tcp socket
+    fn new_inner_socket() -> tcp::Socket<'static> {
+        let tx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_TX_BUF_SIZE]);
+        let rx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_RX_BUF_SIZE]);
+        return tcp::Socket::new(rx_buffer, tx_buffer);
+    }
+
+    /// 把通过setsockopt设置的选项从一个smoltcp socket复制到另一个，
+    /// 使得新创建的监听socket、accept得到的socket都继承监听socket的选项
+    fn copy_options(from: &tcp::Socket, to: &mut tcp::Socket) {
+        to.set_nagle_enabled(from.nagle_enabled());
+        to.set_keep_alive(from.keep_alive());
+        to.set_timeout(from.timeout());
+    }
+
+    /// 所有的监听socket的句柄
+    fn listen_handles(&self) -> impl Iterator<Item = &Arc<GlobalSocketHandle>> {
+        core::iter::once(&self.handle).chain(self.backlog_handles.iter())
+    }
+
+    /// 设置tcp层的选项
+    fn set_tcp_option(
+        socket: &mut tcp::Socket,
+        optname: PosixTcpSocketOptions,
+        val: i32,
+    ) -> Result<(), SystemError> {
+        match optname {
+            PosixTcpSocketOptions::NoDelay => socket.set_nagle_enabled(val == 0),
+            PosixTcpSocketOptions::KeepIdle => {
+                if val <= 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                // 与Linux一致，只有在开启了SO_KEEPALIVE时才生效
+                if socket.keep_alive().is_some() {
+                    socket.set_keep_alive(Some(smoltcp::time::Duration::from_secs(val as u64)));
+                }
+            }
+            PosixTcpSocketOptions::UserTimeout => {
+                if val < 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let timeout = if val == 0 {
+                    None
+                } else {
+                    Some(smoltcp::time::Duration::from_millis(val as u64))
+                };
+                socket.set_timeout(timeout);
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        }
+        return Ok(());
+    }
+
     fn do_listen(
         &mut self,
         socket: &mut smoltcp::socket::tcp::Socket,
@@ -706,8 +1126,9 @@ impl TcpSocket {
 }
 
 impl Socket for TcpSocket {
-    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+    fn recv(&self, buf: &mut [u8], flags: MessageFlag) -> (Result<usize, SystemError>, Endpoint) {
         // kdebug!("tcp socket: read, buf len={}", buf.len());
+        let deadline = self.metadata.recv_deadline();
 
         loop {
             poll_ifaces();
@@ -720,8 +1141,13 @@ impl Socket for TcpSocket {
                 return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
             }
 
-            if socket.may_recv() {
-                let recv_res = socket.recv_slice(buf);
+            let read_shutdown = self.read_shutdown.load(Ordering::SeqCst);
+            if socket.may_recv() || read_shutdown {
+                let recv_res = if flags.contains(MessageFlag::PEEK) {
+                    socket.peek_slice(buf)
+                } else {
+                    socket.recv_slice(buf)
+                };
 
                 if let Ok(size) = recv_res {
                     if size > 0 {
@@ -738,6 +1164,9 @@ impl Socket for TcpSocket {
                     }
                 } else {
                     let err = recv_res.unwrap_err();
+                    if read_shutdown {
+                        return (Ok(0), Endpoint::Ip(socket.remote_endpoint()));
+                    }
                     match err {
                         tcp::RecvError::InvalidState => {
                             kwarn!("Tcp Socket Read Error, InvalidState");
@@ -748,16 +1177,27 @@ impl Socket for TcpSocket {
                         }
                     }
                 }
+                // 接收方向已经关闭：已经收到的数据读完之后，读到EOF而不是继续等待
+                if read_shutdown {
+                    return (Ok(0), Endpoint::Ip(socket.remote_endpoint()));
+                }
             } else {
                 return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
             }
             drop(socket);
             drop(socket_set_guard);
-            SOCKET_WAITQUEUE.sleep();
+            if let Err(e) = socket_wait(flags, deadline) {
+                return (Err(e), Endpoint::Ip(None));
+            }
         }
     }
 
-    fn write(&self, buf: &[u8], _to: Option<super::Endpoint>) -> Result<usize, SystemError> {
+    fn send(
+        &self,
+        buf: &[u8],
+        _to: Option<super::Endpoint>,
+        _flags: MessageFlag,
+    ) -> Result<usize, SystemError> {
         let mut socket_set_guard = SOCKET_SET.lock();
         let socket = socket_set_guard.get_mut::<tcp::Socket>(self.handle.0);
 
@@ -785,17 +1225,22 @@ impl Socket for TcpSocket {
 
     fn poll(&self) -> (bool, bool, bool) {
         let mut socket_set_guard = SOCKET_SET.lock();
+        if self.is_listening {
+            // 任意一个监听socket上有连接到来，都可以accept
+            let ready = self
+                .listen_handles()
+                .any(|h| socket_set_guard.get::<tcp::Socket>(h.0).is_active());
+            return (ready, false, false);
+        }
         let socket = socket_set_guard.get_mut::<tcp::Socket>(self.handle.0);
 
         let mut input = false;
         let mut output = false;
         let mut error = false;
-        if self.is_listening && socket.is_active() {
-            input = true;
-        } else if !socket.is_open() {
+        if !socket.is_open() {
             error = true;
         } else {
-            if socket.may_recv() {
+            if socket.may_recv() || self.read_shutdown.load(Ordering::SeqCst) {
                 input = true;
             }
             if socket.can_send() {
@@ -813,14 +1258,19 @@ impl Socket for TcpSocket {
         if let Endpoint::Ip(Some(ip)) = endpoint {
             let temp_port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
             // 检测端口是否被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, temp_port, self.handle.clone())?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                temp_port,
+                self.handle.clone(),
+                false,
+            )?;
 
             // kdebug!("temp_port: {}", temp_port);
-            let iface: Arc<dyn NetDriver> = NET_DRIVERS.write().get(&0).unwrap().clone();
+            let iface: Arc<dyn NetDriver> = route_iface(&ip.addr)?;
             let mut inner_iface = iface.inner_iface().lock();
             // kdebug!("to connect: {ip:?}");
 
-            match socket.connect(&mut inner_iface.context(), ip, temp_port) {
+            match socket.connect(inner_iface.context(), ip, temp_port) {
                 Ok(()) => {
                     // avoid deadlock
                     drop(inner_iface);
@@ -837,6 +1287,13 @@ impl Socket for TcpSocket {
                                 return Ok(());
                             }
                             tcp::State::SynSent => {
+                                // 对端返回了ICMP差错(如主机不可达)时，放弃建立连接
+                                if let Some(e) =
+                                    sock_error_take(wire::IpProtocol::Tcp, temp_port, ip)
+                                {
+                                    socket.abort();
+                                    return Err(e);
+                                }
                                 drop(socket);
                                 drop(sockets);
                                 SOCKET_WAITQUEUE.sleep();
@@ -862,8 +1319,10 @@ impl Socket for TcpSocket {
 
     /// @brief tcp socket 监听 local_endpoint 端口
     ///
-    /// @param backlog 未处理的连接队列的最大长度. 由于smoltcp不支持backlog，所以这个参数目前无效
-    fn listen(&mut self, _backlog: usize) -> Result<(), SystemError> {
+    /// @param backlog 未处理的连接队列的最大长度.
+    /// smoltcp的每个监听socket只能接受一个连接，因此为backlog中的每个连接都创建一个监听socket，
+    /// backlog最大为MAX_BACKLOG
+    fn listen(&mut self, backlog: usize) -> Result<(), SystemError> {
         if self.is_listening {
             return Ok(());
         }
@@ -877,7 +1336,16 @@ impl Socket for TcpSocket {
             return Ok(());
         }
         // kdebug!("Tcp Socket  before listen, open={}", socket.is_open());
-        return self.do_listen(socket, local_endpoint);
+        self.do_listen(socket, local_endpoint)?;
+
+        for _ in 1..backlog.clamp(1, Self::MAX_BACKLOG) {
+            let mut tcp_socket = Self::new_inner_socket();
+            Self::copy_options(sockets.get::<tcp::Socket>(self.handle.0), &mut tcp_socket);
+            self.do_listen(&mut tcp_socket, local_endpoint)?;
+            self.backlog_handles
+                .push(GlobalSocketHandle::new(sockets.add(tcp_socket)));
+        }
+        return Ok(());
     }
 
     fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
@@ -887,7 +1355,12 @@ impl Socket for TcpSocket {
             }
 
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port, self.handle.clone())?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                ip.port,
+                self.handle.clone(),
+                self.metadata.options.contains(SocketOptions::REUSEADDR),
+            )?;
 
             self.local_endpoint = Some(ip);
             self.is_listening = false;
@@ -896,9 +1369,18 @@ impl Socket for TcpSocket {
         return Err(SystemError::EINVAL);
     }
 
-    fn shutdown(&self, _type: super::ShutdownType) -> Result<(), SystemError> {
+    fn shutdown(&self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        if shutdown_type != ShutdownType::ShutWr {
+            // smoltcp不支持单独关闭接收方向，只在这里记录，之后的读操作在读完已收到的数据后返回EOF
+            self.read_shutdown.store(true, Ordering::SeqCst);
+            SOCKET_WAITQUEUE.wakeup_all(None);
+        }
+        if shutdown_type == ShutdownType::ShutRd {
+            return Ok(());
+        }
         let mut sockets = SOCKET_SET.lock();
         let socket = sockets.get_mut::<tcp::Socket>(self.handle.0);
+        // 发送FIN，之后仍然可以接收对端的数据
         socket.close();
         return Ok(());
     }
@@ -911,19 +1393,21 @@ impl Socket for TcpSocket {
 
             let mut sockets = SOCKET_SET.lock();
 
-            let socket = sockets.get_mut::<tcp::Socket>(self.handle.0);
+            // 找到一个已经有连接到来的监听socket
+            let ready = self
+                .listen_handles()
+                .position(|h| sockets.get::<tcp::Socket>(h.0).is_active());
 
-            if socket.is_active() {
+            if let Some(index) = ready {
                 // kdebug!("tcp accept: socket.is_active()");
+                let ready_handle = self.listen_handles().nth(index).unwrap().clone();
+                let socket = sockets.get::<tcp::Socket>(ready_handle.0);
                 let remote_ep = socket.remote_endpoint().ok_or(SystemError::ENOTCONN)?;
-                drop(socket);
 
                 let new_socket = {
-                    // Initialize the TCP socket's buffers.
-                    let rx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_RX_BUF_SIZE]);
-                    let tx_buffer = tcp::SocketBuffer::new(vec![0; Self::DEFAULT_TX_BUF_SIZE]);
                     // The new TCP socket used for sending and receiving data.
-                    let mut tcp_socket = tcp::Socket::new(rx_buffer, tx_buffer);
+                    let mut tcp_socket = Self::new_inner_socket();
+                    Self::copy_options(socket, &mut tcp_socket);
                     self.do_listen(&mut tcp_socket, endpoint)
                         .expect("do_listen failed");
 
@@ -932,30 +1416,39 @@ impl Socket for TcpSocket {
                     // 之所以把old_handle存入new_socket, 是因为当前时刻，smoltcp已经把old_handle对应的socket与远程的endpoint关联起来了
                     // 因此需要再为当前的socket分配一个新的handle
                     let new_handle = GlobalSocketHandle::new(sockets.add(tcp_socket));
-                    let old_handle = ::core::mem::replace(&mut self.handle, new_handle.clone());
-
-                    // 更新端口与 handle 的绑定
-                    if let Some(Endpoint::Ip(Some(ip))) = self.endpoint() {
-                        PORT_MANAGER.unbind_port(self.metadata.socket_type, ip.port)?;
-                        PORT_MANAGER.bind_port(
-                            self.metadata.socket_type,
-                            ip.port,
-                            new_handle.clone(),
-                        )?;
-                    }
+                    let old_handle = if index == 0 {
+                        let old_handle = ::core::mem::replace(&mut self.handle, new_handle.clone());
+
+                        // 更新端口与 handle 的绑定
+                        if let Some(Endpoint::Ip(Some(ip))) = self.endpoint() {
+                            PORT_MANAGER.unbind_port(self.metadata.socket_type, ip.port)?;
+                            PORT_MANAGER.bind_port(
+                                self.metadata.socket_type,
+                                ip.port,
+                                new_handle.clone(),
+                                false,
+                            )?;
+                        }
+                        old_handle
+                    } else {
+                        ::core::mem::replace(&mut self.backlog_handles[index - 1], new_handle)
+                    };
 
-                    let metadata = SocketMetadata::new(
+                    let mut metadata = SocketMetadata::new(
                         SocketType::TcpSocket,
                         Self::DEFAULT_RX_BUF_SIZE,
                         Self::DEFAULT_TX_BUF_SIZE,
                         Self::DEFAULT_METADATA_BUF_SIZE,
                         self.metadata.options,
                     );
+                    metadata.recv_timeout = self.metadata.recv_timeout;
 
                     Box::new(TcpSocket {
                         handle: old_handle,
+                        backlog_handles: Vec::new(),
                         local_endpoint: self.local_endpoint,
                         is_listening: false,
+                        read_shutdown: Arc::new(AtomicBool::new(false)),
                         metadata,
                     })
                 };
@@ -965,12 +1458,119 @@ impl Socket for TcpSocket {
 
                 return Ok((new_socket, Endpoint::Ip(Some(remote_ep))));
             }
-            drop(socket);
             drop(sockets);
             SOCKET_WAITQUEUE.sleep();
         }
     }
 
+    /// @brief 设置tcp socket的选项
+    ///
+    /// 支持SO_KEEPALIVE，以及TCP_NODELAY、TCP_KEEPIDLE、TCP_USER_TIMEOUT，
+    /// 其余SOL_SOCKET层的选项由[`SocketMetadata::setsockopt`]处理
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level as u8 == SOL_SOCKET && optname != PosixSocketOption::SO_KEEPALIVE as usize {
+            return self.metadata.setsockopt(level, optname, optval);
+        }
+
+        let mut sockets = SOCKET_SET.lock();
+        // 对于监听中的socket，所有的监听socket都需要设置
+        let handles: Vec<Arc<GlobalSocketHandle>> = self.listen_handles().cloned().collect();
+        let val = sockopt_int(optval)?;
+
+        if level as u8 == SOL_SOCKET {
+            let optname = PosixSocketOption::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            match optname {
+                PosixSocketOption::SO_KEEPALIVE => {
+                    let keep_alive = if val != 0 {
+                        Some(smoltcp::time::Duration::from_secs(
+                            Self::DEFAULT_KEEPALIVE_SECS,
+                        ))
+                    } else {
+                        None
+                    };
+                    for h in handles.iter() {
+                        sockets
+                            .get_mut::<tcp::Socket>(h.0)
+                            .set_keep_alive(keep_alive);
+                    }
+                    return Ok(());
+                }
+                _ => return Err(SystemError::ENOPROTOOPT),
+            }
+        }
+
+        let protocol =
+            PosixIpProtocol::try_from(level as u16).map_err(|_| SystemError::ENOPROTOOPT)?;
+        if protocol != PosixIpProtocol::TCP {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+        let optname = PosixTcpSocketOptions::try_from(optname as i32)
+            .map_err(|_| SystemError::ENOPROTOOPT)?;
+        for h in handles.iter() {
+            Self::set_tcp_option(sockets.get_mut::<tcp::Socket>(h.0), optname, val)?;
+        }
+        return Ok(());
+    }
+
+    /// @brief 获取tcp socket的选项
+    ///
+    /// 支持SO_KEEPALIVE、SO_ERROR，以及TCP_NODELAY、TCP_KEEPIDLE、TCP_USER_TIMEOUT，
+    /// 其余SOL_SOCKET层的选项由[`SocketMetadata::getsockopt`]处理
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let sockets = SOCKET_SET.lock();
+        let socket = sockets.get::<tcp::Socket>(self.handle.0);
+        if level as u8 == SOL_SOCKET {
+            if optname == PosixSocketOption::SO_KEEPALIVE as usize {
+                return Ok(put_sockopt_int(
+                    optval,
+                    socket.keep_alive().is_some() as i32,
+                ));
+            }
+            if optname == PosixSocketOption::SO_ERROR as usize {
+                let err = match (socket.local_endpoint(), socket.remote_endpoint()) {
+                    (Some(local), Some(remote)) => {
+                        sock_error_take(wire::IpProtocol::Tcp, local.port, remote)
+                    }
+                    _ => None,
+                };
+                return Ok(put_sockopt_int(optval, errno_of(err)));
+            }
+            drop(sockets);
+            return self.metadata.getsockopt(level, optname, optval);
+        }
+
+        let protocol =
+            PosixIpProtocol::try_from(level as u16).map_err(|_| SystemError::ENOPROTOOPT)?;
+        if protocol != PosixIpProtocol::TCP {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+        let optname = PosixTcpSocketOptions::try_from(optname as i32)
+            .map_err(|_| SystemError::ENOPROTOOPT)?;
+        let val = match optname {
+            PosixTcpSocketOptions::NoDelay => !socket.nagle_enabled() as i32,
+            PosixTcpSocketOptions::KeepIdle => socket
+                .keep_alive()
+                .map_or(Self::DEFAULT_KEEPALIVE_SECS, |d| d.secs())
+                as i32,
+            PosixTcpSocketOptions::UserTimeout => {
+                socket.timeout().map_or(0, |d| d.total_millis()) as i32
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        };
+        return Ok(put_sockopt_int(optval, val));
+    }
+
     fn endpoint(&self) -> Option<Endpoint> {
         let mut result: Option<Endpoint> =
             self.local_endpoint.clone().map(|x| Endpoint::Ip(Some(x)));
@@ -998,69 +1598,1125 @@ impl Socket for TcpSocket {
     fn box_clone(&self) -> alloc::boxed::Box<dyn Socket> {
         return Box::new(self.clone());
     }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
 }
 
-/// @brief 地址族的枚举
+/// 把IPv4端点格式化为/proc/net/tcp中的形式：地址以主机字节序的十六进制输出
+fn proc_tcp_endpoint(addr: Option<wire::IpAddress>, port: u16) -> String {
+    let addr = match addr {
+        Some(wire::IpAddress::Ipv4(addr)) => u32::from_le_bytes(addr.0),
+        _ => 0,
+    };
+    return format!("{:08X}:{:04X}", addr, port);
+}
+
+/// 按照Linux的/proc/net/tcp的格式输出所有的tcp socket
 ///
-/// 参考：https://opengrok.ringotek.cn/xref/linux-5.19.10/include/linux/socket.h#180
-#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
-pub enum AddressFamily {
-    /// AF_UNSPEC 表示地址族未指定
-    Unspecified = 0,
-    /// AF_UNIX 表示Unix域的socket (与AF_LOCAL相同)
-    Unix = 1,
-    ///  AF_INET 表示IPv4的socket
-    INet = 2,
-    /// AF_AX25 表示AMPR AX.25的socket
-    AX25 = 3,
-    /// AF_IPX 表示IPX的socket
-    IPX = 4,
-    /// AF_APPLETALK 表示Appletalk的socket
-    Appletalk = 5,
-    /// AF_NETROM 表示AMPR NET/ROM的socket
-    Netrom = 6,
-    /// AF_BRIDGE 表示多协议桥接的socket
-    Bridge = 7,
-    /// AF_ATMPVC 表示ATM PVCs的socket
-    Atmpvc = 8,
-    /// AF_X25 表示X.25的socket
-    X25 = 9,
-    /// AF_INET6 表示IPv6的socket
-    INet6 = 10,
-    /// AF_ROSE 表示AMPR ROSE的socket
-    Rose = 11,
-    /// AF_DECnet Reserved for DECnet project
-    Decnet = 12,
-    /// AF_NETBEUI Reserved for 802.2LLC project
-    Netbeui = 13,
-    /// AF_SECURITY 表示Security callback的伪AF
-    Security = 14,
-    /// AF_KEY 表示Key management API
-    Key = 15,
-    /// AF_NETLINK 表示Netlink的socket
-    Netlink = 16,
-    /// AF_PACKET 表示Low level packet interface
-    Packet = 17,
-    /// AF_ASH 表示Ash
-    Ash = 18,
-    /// AF_ECONET 表示Acorn Econet
-    Econet = 19,
-    /// AF_ATMSVC 表示ATM SVCs
-    Atmsvc = 20,
-    /// AF_RDS 表示Reliable Datagram Sockets
-    Rds = 21,
-    /// AF_SNA 表示Linux SNA Project
-    Sna = 22,
-    /// AF_IRDA 表示IRDA sockets
-    Irda = 23,
-    /// AF_PPPOX 表示PPPoX sockets
-    Pppox = 24,
-    /// AF_WANPIPE 表示WANPIPE API sockets
-    WanPipe = 25,
-    /// AF_LLC 表示Linux LLC
-    Llc = 26,
-    /// AF_IB 表示Native InfiniBand address
-    /// 介绍：https://access.redhat.com/documentation/en-us/red_hat_enterprise_linux/9/html-single/configuring_infiniband_and_rdma_networks/index#understanding-infiniband-and-rdma_configuring-infiniband-and-rdma-networks
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/net/ipv4/tcp_ipv4.c#get_tcp4_sock
+pub fn tcp_show() -> String {
+    // 每一行都被填充到同样的长度，与Linux保持一致
+    const TMPSZ: usize = 150;
+    let mut result = format!(
+        "{:<width$}\n",
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode",
+        width = TMPSZ - 1
+    );
+
+    let sockets = SOCKET_SET.lock();
+    let mut sl = 0;
+    for (_, socket) in sockets.iter() {
+        let socket = match socket {
+            smoltcp::socket::Socket::Tcp(socket) => socket,
+            _ => continue,
+        };
+        // 与Linux中tcp_states.h的定义一致
+        let state: u8 = match socket.state() {
+            tcp::State::Closed => continue,
+            tcp::State::Established => 1,
+            tcp::State::SynSent => 2,
+            tcp::State::SynReceived => 3,
+            tcp::State::FinWait1 => 4,
+            tcp::State::FinWait2 => 5,
+            tcp::State::TimeWait => 6,
+            tcp::State::CloseWait => 8,
+            tcp::State::LastAck => 9,
+            tcp::State::Listen => 10,
+            tcp::State::Closing => 11,
+        };
+        let (local, remote) = match (socket.local_endpoint(), socket.remote_endpoint()) {
+            (Some(local), Some(remote)) => (
+                proc_tcp_endpoint(Some(local.addr), local.port),
+                proc_tcp_endpoint(Some(remote.addr), remote.port),
+            ),
+            _ => {
+                let listen = socket.listen_endpoint();
+                (
+                    proc_tcp_endpoint(listen.addr, listen.port),
+                    proc_tcp_endpoint(None, 0),
+                )
+            }
+        };
+        let line = format!(
+            "{:>4}: {} {} {:02X} {:08X}:{:08X} 00:00000000 00000000 {:>5} {:>8} 0",
+            sl,
+            local,
+            remote,
+            state,
+            socket.send_queue(),
+            socket.recv_queue(),
+            0,
+            0
+        );
+        result.push_str(&format!("{:<width$}\n", line, width = TMPSZ - 1));
+        sl += 1;
+    }
+    return result;
+}
+
+/// @brief unix域socket的一个方向上的数据缓冲区
+#[derive(Debug, Default)]
+struct UnixSocketBuffer {
+    /// 缓冲区中的数据。数据报socket中每一项是一个数据报，流socket中每一项是一次写入的数据
+    packets: VecDeque<Vec<u8>>,
+    /// 缓冲区中数据的总长度
+    len: usize,
+    /// 写端已经关闭，不会再有新的数据写入
+    write_shutdown: bool,
+    /// 读端已经关闭，写入的数据不会再被读取
+    read_shutdown: bool,
+}
+
+impl UnixSocketBuffer {
+    /// @brief 从缓冲区中读取数据，调用者需要保证缓冲区不为空
+    ///
+    /// @param datagram 是否按照数据报的方式读取(每次只读取一个数据报，超出buf的部分被丢弃)
+    ///
+    /// @return 读取的长度。对于数据报，设置了MSG_TRUNC时返回数据报的实际长度
+    fn read(&mut self, buf: &mut [u8], datagram: bool, flags: MessageFlag) -> usize {
+        let peek = flags.contains(MessageFlag::PEEK);
+        if datagram {
+            let packet = self.packets.front().unwrap();
+            let packet_len = packet.len();
+            let len = core::cmp::min(buf.len(), packet_len);
+            buf[..len].copy_from_slice(&packet[..len]);
+            if !peek {
+                self.packets.pop_front();
+                self.len -= packet_len;
+            }
+            if flags.contains(MessageFlag::TRUNC) {
+                return packet_len;
+            }
+            return len;
+        }
+
+        let mut read = 0;
+        for packet in self.packets.iter() {
+            if read == buf.len() {
+                break;
+            }
+            let len = core::cmp::min(buf.len() - read, packet.len());
+            buf[read..read + len].copy_from_slice(&packet[..len]);
+            read += len;
+        }
+        if !peek {
+            let mut to_consume = read;
+            while to_consume > 0 {
+                let front = self.packets.front_mut().unwrap();
+                if front.len() <= to_consume {
+                    to_consume -= front.len();
+                    self.packets.pop_front();
+                } else {
+                    front.drain(..to_consume);
+                    to_consume = 0;
+                }
+            }
+            self.len -= read;
+        }
+        return read;
+    }
+}
+
+/// @brief unix域socket的一个方向上的数据通道，由发送端和接收端共享
+#[derive(Debug)]
+struct UnixSocketChannel {
+    buffer: SpinLock<UnixSocketBuffer>,
+    /// 等待数据或者等待缓冲区空间的进程
+    wait_queue: WaitQueue,
+}
+
+impl UnixSocketChannel {
+    fn new() -> Arc<Self> {
+        return Arc::new(Self {
+            buffer: SpinLock::new(UnixSocketBuffer::default()),
+            wait_queue: WaitQueue::INIT,
+        });
+    }
+
+    fn shutdown_read(&self) {
+        self.buffer.lock().read_shutdown = true;
+        self.wait_queue.wakeup_all(None);
+    }
+
+    fn shutdown_write(&self) {
+        self.buffer.lock().write_shutdown = true;
+        self.wait_queue.wakeup_all(None);
+    }
+}
+
+/// @brief unix域socket的一端，所有克隆出的UnixSocket共享它。
+/// 它被释放时(即这一端的所有文件描述符都被关闭时)，对端会读到EOF，写入会返回EPIPE
+#[derive(Debug)]
+struct UnixSocketEnd {
+    rx: Arc<UnixSocketChannel>,
+    tx: Arc<UnixSocketChannel>,
+}
+
+impl Drop for UnixSocketEnd {
+    fn drop(&mut self) {
+        self.rx.shutdown_read();
+        self.tx.shutdown_write();
+    }
+}
+
+/// @brief 表示通过socketpair创建的、未命名的unix域socket
+///
+/// https://man7.org/linux/man-pages/man7/unix.7.html
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    end: Arc<UnixSocketEnd>,
+    /// 是否是数据报socket(SOCK_DGRAM)，否则为流socket(SOCK_STREAM)
+    datagram: bool,
+    metadata: SocketMetadata,
+}
+
+impl UnixSocket {
+    /// 默认的缓冲区的大小
+    pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+    /// @brief 创建一对互相连接的unix域socket
+    ///
+    /// @param options socket的选项
+    /// @param datagram 是否是数据报socket
+    ///
+    /// @return 返回创建的两个socket
+    pub fn new_pair(options: SocketOptions, datagram: bool) -> (Self, Self) {
+        let a_to_b = UnixSocketChannel::new();
+        let b_to_a = UnixSocketChannel::new();
+        let metadata = SocketMetadata::new(
+            SocketType::UnixSocket,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_BUF_SIZE,
+            0,
+            options,
+        );
+
+        let a = Self {
+            end: Arc::new(UnixSocketEnd {
+                rx: b_to_a.clone(),
+                tx: a_to_b.clone(),
+            }),
+            datagram,
+            metadata: metadata.clone(),
+        };
+        let b = Self {
+            end: Arc::new(UnixSocketEnd {
+                rx: a_to_b,
+                tx: b_to_a,
+            }),
+            datagram,
+            metadata,
+        };
+        return (a, b);
+    }
+
+    /// 本次操作是否不应该阻塞
+    fn nonblocking(&self, flags: MessageFlag) -> bool {
+        return !self.metadata.options.contains(SocketOptions::BLOCK)
+            || flags.contains(MessageFlag::DONTWAIT);
+    }
+}
+
+impl Socket for UnixSocket {
+    fn recv(&self, buf: &mut [u8], flags: MessageFlag) -> (Result<usize, SystemError>, Endpoint) {
+        let deadline = self.metadata.recv_deadline();
+        let channel = &self.end.rx;
+        loop {
+            let mut buffer = channel.buffer.lock();
+            if !buffer.packets.is_empty() {
+                let len = buffer.read(buf, self.datagram, flags);
+                drop(buffer);
+                if !flags.contains(MessageFlag::PEEK) {
+                    // 唤醒等待缓冲区空间的发送者
+                    channel.wait_queue.wakeup_all(None);
+                }
+                return (Ok(len), Endpoint::Unnamed);
+            }
+            if buffer.write_shutdown || buffer.read_shutdown {
+                return (Ok(0), Endpoint::Unnamed);
+            }
+            if self.nonblocking(flags) {
+                return (Err(SystemError::EAGAIN_OR_EWOULDBLOCK), Endpoint::Unnamed);
+            }
+            if deadline.is_none() {
+                channel.wait_queue.sleep_unlock_spinlock(buffer);
+            } else {
+                // 设置了超时时间时，依靠网络轮询线程定期唤醒来检查是否超时
+                drop(buffer);
+                if let Err(e) = socket_wait(flags, deadline) {
+                    return (Err(e), Endpoint::Unnamed);
+                }
+            }
+        }
+    }
+
+    fn send(
+        &self,
+        buf: &[u8],
+        _to: Option<Endpoint>,
+        flags: MessageFlag,
+    ) -> Result<usize, SystemError> {
+        if self.datagram && buf.len() > Self::DEFAULT_BUF_SIZE {
+            return Err(SystemError::EMSGSIZE);
+        }
+        let channel = &self.end.tx;
+        let mut written = 0;
+        loop {
+            let mut buffer = channel.buffer.lock();
+            if buffer.read_shutdown || buffer.write_shutdown {
+                if written > 0 {
+                    return Ok(written);
+                }
+                return Err(SystemError::EPIPE);
+            }
+
+            let space = Self::DEFAULT_BUF_SIZE - buffer.len;
+            if self.datagram {
+                if space >= buf.len() {
+                    buffer.packets.push_back(buf.to_vec());
+                    buffer.len += buf.len();
+                    drop(buffer);
+                    channel.wait_queue.wakeup_all(None);
+                    return Ok(buf.len());
+                }
+            } else {
+                let len = core::cmp::min(space, buf.len() - written);
+                if len > 0 {
+                    buffer
+                        .packets
+                        .push_back(buf[written..written + len].to_vec());
+                    buffer.len += len;
+                    written += len;
+                    channel.wait_queue.wakeup_all(None);
+                }
+                if written == buf.len() {
+                    return Ok(written);
+                }
+            }
+
+            if self.nonblocking(flags) {
+                if written > 0 {
+                    return Ok(written);
+                }
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            channel.wait_queue.sleep_unlock_spinlock(buffer);
+        }
+    }
+
+    fn connect(&mut self, _endpoint: Endpoint) -> Result<(), SystemError> {
+        return Err(SystemError::EISCONN);
+    }
+
+    fn shutdown(&self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        if shutdown_type != ShutdownType::ShutWr {
+            self.end.rx.shutdown_read();
+        }
+        if shutdown_type != ShutdownType::ShutRd {
+            self.end.tx.shutdown_write();
+        }
+        return Ok(());
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        return Some(Endpoint::Unnamed);
+    }
+
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        return Some(Endpoint::Unnamed);
+    }
+
+    fn poll(&self) -> (bool, bool, bool) {
+        let rx = self.end.rx.buffer.lock();
+        let input = !rx.packets.is_empty() || rx.write_shutdown || rx.read_shutdown;
+        drop(rx);
+        let tx = self.end.tx.buffer.lock();
+        let output = tx.len < Self::DEFAULT_BUF_SIZE && !tx.read_shutdown && !tx.write_shutdown;
+        return (input, output, false);
+    }
+
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        return self.metadata.setsockopt(level, optname, optval);
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level as u8 == SOL_SOCKET && optname == PosixSocketOption::SO_TYPE as usize {
+            let socket_type = if self.datagram {
+                PosixSocketType::Datagram
+            } else {
+                PosixSocketType::Stream
+            };
+            return Ok(put_sockopt_int(optval, socket_type as i32));
+        }
+        return self.metadata.getsockopt(level, optname, optval);
+    }
+
+    fn metadata(&self) -> Result<SocketMetadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn box_clone(&self) -> alloc::boxed::Box<dyn Socket> {
+        return Box::new(self.clone());
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+/// packet socket的协议号，表示接收所有协议的帧
+pub const ETH_P_ALL: u16 = 0x0003;
+
+/// 以太网设备的链路层地址类型
+pub const ARPHRD_ETHER: u16 = 1;
+/// 回环设备的链路层地址类型
+pub const ARPHRD_LOOPBACK: u16 = 772;
+
+/// 以太网头部的长度
+const ETH_HLEN: usize = 14;
+
+// SOL_PACKET层的选项
+// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/if_packet.h
+const PACKET_ADD_MEMBERSHIP: usize = 1;
+const PACKET_DROP_MEMBERSHIP: usize = 2;
+const PACKET_STATISTICS: usize = 6;
+
+// 帧的类型(sll_pkttype)
+const PACKET_HOST: u8 = 0;
+const PACKET_BROADCAST: u8 = 1;
+const PACKET_MULTICAST: u8 = 2;
+const PACKET_OUTGOING: u8 = 4;
+
+/// 所有packet socket的接收状态，网卡收发的每一帧都会被复制给匹配的socket
+static PACKET_SOCKETS: SpinLock<Vec<Weak<SpinLock<PacketSocketState>>>> = SpinLock::new(Vec::new());
+
+/// @brief 把网卡收发的一帧复制给所有匹配的packet socket
+///
+/// 网卡驱动在收到帧之后、经过netfilter之前，以及发送的帧通过netfilter之后调用本函数，
+/// 因此被过滤规则丢弃的入站帧仍然能够被捕获
+///
+/// @param nic_id 网卡的id
+/// @param frame 完整的帧，对于没有链路层头部的设备(如lo)是IP包
+/// @param medium 网卡的介质类型
+/// @param outgoing 是否是本机发送的帧
+pub fn packet_tap(nic_id: usize, frame: &[u8], medium: Medium, outgoing: bool) {
+    let sockets: Vec<Arc<SpinLock<PacketSocketState>>> = {
+        let mut list = PACKET_SOCKETS.lock_irqsave();
+        list.retain(|s| s.strong_count() > 0);
+        list.iter().filter_map(|s| s.upgrade()).collect()
+    };
+    if sockets.is_empty() {
+        return;
+    }
+
+    // 没有链路层头部的设备，补上一个地址全为0的以太网头部，与Linux的lo保持一致
+    let synthesized: Vec<u8>;
+    let (frame, hatype) = match medium {
+        Medium::Ethernet => (frame, ARPHRD_ETHER),
+        _ => {
+            let ethertype = match frame.first().map(|b| b >> 4) {
+                Some(6) => wire::EthernetProtocol::Ipv6,
+                _ => wire::EthernetProtocol::Ipv4,
+            };
+            let mut buf = vec![0u8; ETH_HLEN + frame.len()];
+            buf[12..ETH_HLEN].copy_from_slice(&u16::from(ethertype).to_be_bytes());
+            buf[ETH_HLEN..].copy_from_slice(frame);
+            synthesized = buf;
+            (synthesized.as_slice(), ARPHRD_LOOPBACK)
+        }
+    };
+    if frame.len() < ETH_HLEN {
+        return;
+    }
+
+    let protocol = u16::from_be_bytes([frame[12], frame[13]]);
+    let pkttype = if outgoing {
+        PACKET_OUTGOING
+    } else if frame[..6] == [0xff; 6] {
+        PACKET_BROADCAST
+    } else if frame[0] & 1 != 0 {
+        PACKET_MULTICAST
+    } else {
+        PACKET_HOST
+    };
+    let mut src = [0u8; 6];
+    src.copy_from_slice(&frame[6..12]);
+    let mut endpoint = LinkLayerEndpoint::new(iface_index(nic_id));
+    endpoint.protocol = protocol;
+    endpoint.hatype = hatype;
+    endpoint.pkttype = pkttype;
+    endpoint.addr = src;
+
+    for socket in sockets {
+        socket.lock_irqsave().deliver(frame, &endpoint);
+    }
+}
+
+/// @brief packet socket捕获到的一帧
+#[derive(Debug)]
+struct CapturedFrame {
+    /// 帧的数据，可能被过滤程序截断
+    data: Vec<u8>,
+    /// 帧的实际长度
+    len: usize,
+    /// 帧的来源
+    endpoint: LinkLayerEndpoint,
+}
+
+/// @brief packet socket的接收状态，由socket和网卡驱动共享
+#[derive(Debug, Default)]
+struct PacketSocketState {
+    /// 绑定的网卡的接口号，为0表示所有网卡
+    ifindex: usize,
+    /// 接收的帧的协议类型(主机字节序)，为0表示不接收任何帧
+    protocol: u16,
+    /// 通过SO_ATTACH_FILTER附加的过滤程序
+    filter: Option<BpfProgram>,
+    /// 接收队列
+    frames: VecDeque<CapturedFrame>,
+    /// 接收队列中数据的总长度
+    len: usize,
+    /// 接收到的帧的数量(包括被丢弃的)，读取PACKET_STATISTICS时清零
+    packets: u32,
+    /// 由于接收队列已满而被丢弃的帧的数量，读取PACKET_STATISTICS时清零
+    drops: u32,
+}
+
+impl PacketSocketState {
+    /// @brief 如果帧与socket匹配，并且通过了过滤程序，就把它放入接收队列
+    fn deliver(&mut self, frame: &[u8], endpoint: &LinkLayerEndpoint) {
+        if self.protocol == 0 || (self.ifindex != 0 && self.ifindex != endpoint.interface) {
+            return;
+        }
+        if self.protocol != ETH_P_ALL && self.protocol != endpoint.protocol {
+            return;
+        }
+        let snaplen = match &self.filter {
+            Some(filter) => filter.run(frame) as usize,
+            None => frame.len(),
+        };
+        if snaplen == 0 {
+            return;
+        }
+
+        self.packets = self.packets.wrapping_add(1);
+        let data_len = core::cmp::min(snaplen, frame.len());
+        if self.len + data_len > PacketSocket::DEFAULT_RX_BUF_SIZE {
+            self.drops = self.drops.wrapping_add(1);
+            return;
+        }
+        self.frames.push_back(CapturedFrame {
+            data: frame[..data_len].to_vec(),
+            len: frame.len(),
+            endpoint: endpoint.clone(),
+        });
+        self.len += data_len;
+    }
+}
+
+/// @brief 表示packet socket，用于捕获网卡收发的链路层帧(如tcpdump)
+///
+/// 目前只支持接收，不支持通过packet socket发送帧
+///
+/// https://man7.org/linux/man-pages/man7/packet.7.html
+#[derive(Debug, Clone)]
+pub struct PacketSocket {
+    state: Arc<SpinLock<PacketSocketState>>,
+    /// 是否是SOCK_DGRAM类型，读取时去掉链路层头部
+    cooked: bool,
+    metadata: SocketMetadata,
+}
+
+impl PacketSocket {
+    /// 默认的接收缓冲区的大小
+    pub const DEFAULT_RX_BUF_SIZE: usize = 256 * 1024;
+
+    /// @brief 创建一个packet socket
+    ///
+    /// @param cooked 是否是SOCK_DGRAM类型
+    /// @param protocol 接收的帧的协议类型(主机字节序)，为0时在bind指定协议之前不接收任何帧
+    /// @param options socket的选项
+    ///
+    /// @return 返回创建的packet socket
+    pub fn new(cooked: bool, protocol: u16, options: SocketOptions) -> Self {
+        let state = Arc::new(SpinLock::new(PacketSocketState {
+            protocol,
+            ..Default::default()
+        }));
+        PACKET_SOCKETS.lock_irqsave().push(Arc::downgrade(&state));
+
+        let metadata = SocketMetadata::new(
+            SocketType::PacketSocket,
+            0,
+            Self::DEFAULT_RX_BUF_SIZE,
+            0,
+            options,
+        );
+        return Self {
+            state,
+            cooked,
+            metadata,
+        };
+    }
+
+    /// @brief 附加用户传入的过滤程序(struct sock_fprog)
+    fn attach_filter(&self, optval: &[u8]) -> Result<(), SystemError> {
+        if optval.len() < core::mem::size_of::<SockFprog>() {
+            return Err(SystemError::EINVAL);
+        }
+        let fprog = unsafe { (optval.as_ptr() as *const SockFprog).read_unaligned() };
+        let len = fprog.len as usize;
+        if len == 0 || len > BPF_MAXINSNS {
+            return Err(SystemError::EINVAL);
+        }
+        let reader =
+            UserBufferReader::new(fprog.filter, len * core::mem::size_of::<SockFilter>(), true)?;
+        let mut insns = vec![SockFilter::default(); len];
+        reader.copy_from_user(&mut insns, 0)?;
+        let program = BpfProgram::new(insns)?;
+        self.state.lock_irqsave().filter = Some(program);
+        return Ok(());
+    }
+}
+
+impl Socket for PacketSocket {
+    fn recv(&self, buf: &mut [u8], flags: MessageFlag) -> (Result<usize, SystemError>, Endpoint) {
+        let deadline = self.metadata.recv_deadline();
+        poll_ifaces();
+        loop {
+            let mut state = self.state.lock_irqsave();
+            if let Some(frame) = state.frames.front() {
+                // SOCK_DGRAM类型的socket不返回链路层头部
+                let skip = if self.cooked { ETH_HLEN } else { 0 };
+                let data = &frame.data[core::cmp::min(skip, frame.data.len())..];
+                let len = core::cmp::min(buf.len(), data.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                let frame_len = frame.len.saturating_sub(skip);
+                let endpoint = Endpoint::LinkLayer(frame.endpoint.clone());
+                if !flags.contains(MessageFlag::PEEK) {
+                    let frame = state.frames.pop_front().unwrap();
+                    state.len -= frame.data.len();
+                }
+                if flags.contains(MessageFlag::TRUNC) {
+                    return (Ok(frame_len), endpoint);
+                }
+                return (Ok(len), endpoint);
+            }
+            drop(state);
+
+            if !self.metadata.options.contains(SocketOptions::BLOCK) {
+                return (
+                    Err(SystemError::EAGAIN_OR_EWOULDBLOCK),
+                    Endpoint::LinkLayer(LinkLayerEndpoint::new(0)),
+                );
+            }
+            if let Err(e) = socket_wait(flags, deadline) {
+                return (Err(e), Endpoint::LinkLayer(LinkLayerEndpoint::new(0)));
+            }
+        }
+    }
+
+    fn send(
+        &self,
+        _buf: &[u8],
+        _to: Option<Endpoint>,
+        _flags: MessageFlag,
+    ) -> Result<usize, SystemError> {
+        // todo: 支持通过packet socket发送帧
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    fn connect(&mut self, _endpoint: Endpoint) -> Result<(), SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// @brief 绑定到指定的网卡，接口号为0表示所有网卡。指定了协议类型时，同时修改接收的协议类型
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let endpoint = match endpoint {
+            Endpoint::LinkLayer(endpoint) => endpoint,
+            _ => return Err(SystemError::EINVAL),
+        };
+        if endpoint.interface != 0
+            && !NET_DRIVERS
+                .read()
+                .values()
+                .any(|iface| iface_index(iface.nic_id()) == endpoint.interface)
+        {
+            return Err(SystemError::ENODEV);
+        }
+        let mut state = self.state.lock_irqsave();
+        state.ifindex = endpoint.interface;
+        if endpoint.protocol != 0 {
+            state.protocol = endpoint.protocol;
+        }
+        return Ok(());
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        let state = self.state.lock_irqsave();
+        let mut endpoint = LinkLayerEndpoint::new(state.ifindex);
+        endpoint.protocol = state.protocol;
+        return Some(Endpoint::LinkLayer(endpoint));
+    }
+
+    fn poll(&self) -> (bool, bool, bool) {
+        return (!self.state.lock_irqsave().frames.is_empty(), false, false);
+    }
+
+    /// @brief 设置packet socket的选项
+    ///
+    /// 支持SO_ATTACH_FILTER、SO_DETACH_FILTER，以及SOL_PACKET层的PACKET_ADD_MEMBERSHIP
+    /// 和PACKET_DROP_MEMBERSHIP(packet socket总是能收到网卡收发的所有帧，因此它们不做任何事)
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level == SOL_PACKET {
+            return match optname {
+                PACKET_ADD_MEMBERSHIP | PACKET_DROP_MEMBERSHIP => Ok(()),
+                _ => Err(SystemError::ENOPROTOOPT),
+            };
+        }
+        if level as u8 == SOL_SOCKET {
+            if optname == PosixSocketOption::SO_ATTACH_FILTER as usize {
+                return self.attach_filter(optval);
+            }
+            if optname == PosixSocketOption::SO_DETACH_FILTER as usize {
+                return match self.state.lock_irqsave().filter.take() {
+                    Some(_) => Ok(()),
+                    None => Err(SystemError::ENOENT),
+                };
+            }
+        }
+        return self.metadata.setsockopt(level, optname, optval);
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level == SOL_PACKET {
+            if optname != PACKET_STATISTICS {
+                return Err(SystemError::ENOPROTOOPT);
+            }
+            // struct tpacket_stats { unsigned int tp_packets; unsigned int tp_drops; }
+            let mut state = self.state.lock_irqsave();
+            let mut stats = [0u8; 8];
+            stats[..4].copy_from_slice(&state.packets.to_ne_bytes());
+            stats[4..].copy_from_slice(&state.drops.to_ne_bytes());
+            state.packets = 0;
+            state.drops = 0;
+            drop(state);
+            let len = core::cmp::min(optval.len(), stats.len());
+            optval[..len].copy_from_slice(&stats[..len]);
+            return Ok(len);
+        }
+        if level as u8 == SOL_SOCKET && optname == PosixSocketOption::SO_TYPE as usize {
+            let socket_type = if self.cooked {
+                PosixSocketType::Datagram
+            } else {
+                PosixSocketType::Raw
+            };
+            return Ok(put_sockopt_int(optval, socket_type as i32));
+        }
+        return self.metadata.getsockopt(level, optname, optval);
+    }
+
+    fn metadata(&self) -> Result<SocketMetadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn box_clone(&self) -> alloc::boxed::Box<dyn Socket> {
+        return Box::new(self.clone());
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+/// 路由信息的netlink协议号。目前只用于接收内核单播的消息，例如mq_notify()的SIGEV_THREAD通知
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/netlink.h
+pub const NETLINK_ROUTE: usize = 0;
+/// 内核对象事件(uevent)的netlink协议号
+pub const NETLINK_KOBJECT_UEVENT: usize = 15;
+
+// SOL_NETLINK层的选项
+const NETLINK_ADD_MEMBERSHIP: usize = 1;
+const NETLINK_DROP_MEMBERSHIP: usize = 2;
+
+/// 所有netlink socket的接收状态，内核发出的多播消息会被复制给加入了对应多播组的socket
+static NETLINK_SOCKETS: SpinLock<Vec<Weak<SpinLock<NetlinkSocketState>>>> =
+    SpinLock::new(Vec::new());
+
+/// @brief 把内核发出的一条消息复制给所有加入了多播组的netlink socket
+///
+/// @param protocol netlink协议号，例如NETLINK_KOBJECT_UEVENT
+/// @param group 多播组的编号，从1开始
+/// @param data 消息的内容
+///
+/// @return 收到消息的socket的数量
+pub fn netlink_broadcast(protocol: usize, group: u32, data: &[u8]) -> usize {
+    if group == 0 || group > 32 {
+        return 0;
+    }
+    let mask = 1u32 << (group - 1);
+    let sockets: Vec<Arc<SpinLock<NetlinkSocketState>>> = {
+        let mut list = NETLINK_SOCKETS.lock_irqsave();
+        list.retain(|s| s.strong_count() > 0);
+        list.iter().filter_map(|s| s.upgrade()).collect()
+    };
+
+    let mut delivered = 0;
+    for socket in sockets {
+        let mut state = socket.lock_irqsave();
+        if state.protocol != protocol || state.groups & mask == 0 {
+            continue;
+        }
+        if state.len + data.len() > NetlinkSocket::DEFAULT_RX_BUF_SIZE {
+            state.drops = state.drops.wrapping_add(1);
+            continue;
+        }
+        state.messages.push_back((data.to_vec(), mask));
+        state.len += data.len();
+        delivered += 1;
+    }
+    if delivered != 0 {
+        SOCKET_WAITQUEUE.wakeup_all(None);
+    }
+    return delivered;
+}
+
+/// @brief netlink socket的接收状态，由socket和发送消息的内核模块共享
+#[derive(Debug, Default)]
+struct NetlinkSocketState {
+    /// netlink协议号
+    protocol: usize,
+    /// 绑定的端口号(nl_pid)，为0表示还没有绑定
+    portid: u32,
+    /// 加入的多播组的位图，第n位表示第n+1个多播组
+    groups: u32,
+    /// 接收队列，每一项是消息的内容以及它所属的多播组
+    messages: VecDeque<(Vec<u8>, u32)>,
+    /// 接收队列中数据的总长度
+    len: usize,
+    /// 由于接收队列已满而被丢弃的消息的数量
+    drops: u32,
+}
+
+/// @brief 表示netlink socket
+///
+/// 目前只支持NETLINK_KOBJECT_UEVENT和NETLINK_ROUTE协议，并且只能接收内核发出的消息。
+/// 用户态的设备管理程序(如mdev、udev)通过NETLINK_KOBJECT_UEVENT得知设备的添加与移除；
+/// libc通过NETLINK_ROUTE接收mq_notify()的SIGEV_THREAD通知
+///
+/// https://man7.org/linux/man-pages/man7/netlink.7.html
+#[derive(Debug, Clone)]
+pub struct NetlinkSocket {
+    state: Arc<SpinLock<NetlinkSocketState>>,
+    /// 用户创建socket时指定的类型(SOCK_RAW或SOCK_DGRAM)
+    posix_type: PosixSocketType,
+    metadata: SocketMetadata,
+}
+
+impl NetlinkSocket {
+    /// 默认的接收缓冲区的大小
+    pub const DEFAULT_RX_BUF_SIZE: usize = 128 * 1024;
+
+    /// @brief 创建一个netlink socket
+    ///
+    /// @param posix_type socket的类型
+    /// @param protocol netlink协议号
+    /// @param options socket的选项
+    ///
+    /// @return 成功时返回创建的netlink socket，协议不受支持时返回EPROTONOSUPPORT
+    pub fn new(
+        posix_type: PosixSocketType,
+        protocol: usize,
+        options: SocketOptions,
+    ) -> Result<Self, SystemError> {
+        if protocol != NETLINK_KOBJECT_UEVENT && protocol != NETLINK_ROUTE {
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+        let state = Arc::new(SpinLock::new(NetlinkSocketState {
+            protocol,
+            ..Default::default()
+        }));
+        NETLINK_SOCKETS.lock_irqsave().push(Arc::downgrade(&state));
+
+        let metadata = SocketMetadata::new(
+            SocketType::NetlinkSocket,
+            0,
+            Self::DEFAULT_RX_BUF_SIZE,
+            0,
+            options,
+        );
+        return Ok(Self {
+            state,
+            posix_type,
+            metadata,
+        });
+    }
+
+    /// @brief 内核向这个socket发送一条单播消息
+    ///
+    /// @param data 消息的内容
+    ///
+    /// @return 成功：Ok(())
+    ///         失败：Err(ENOBUFS) 接收队列已满，消息被丢弃
+    pub fn unicast(&self, data: &[u8]) -> Result<(), SystemError> {
+        let mut state = self.state.lock_irqsave();
+        if state.len + data.len() > Self::DEFAULT_RX_BUF_SIZE {
+            state.drops = state.drops.wrapping_add(1);
+            return Err(SystemError::ENOBUFS);
+        }
+        state.messages.push_back((data.to_vec(), 0));
+        state.len += data.len();
+        drop(state);
+        SOCKET_WAITQUEUE.wakeup_all(None);
+        return Ok(());
+    }
+}
+
+impl Socket for NetlinkSocket {
+    fn recv(&self, buf: &mut [u8], flags: MessageFlag) -> (Result<usize, SystemError>, Endpoint) {
+        let deadline = self.metadata.recv_deadline();
+        loop {
+            let mut state = self.state.lock_irqsave();
+            if let Some((data, group)) = state.messages.front() {
+                let len = core::cmp::min(buf.len(), data.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                let msg_len = data.len();
+                // 内核发出的消息的端口号总是0
+                let endpoint = Endpoint::Netlink(NetlinkEndpoint::new(0, *group));
+                if !flags.contains(MessageFlag::PEEK) {
+                    let (data, _) = state.messages.pop_front().unwrap();
+                    state.len -= data.len();
+                }
+                if flags.contains(MessageFlag::TRUNC) {
+                    return (Ok(msg_len), endpoint);
+                }
+                return (Ok(len), endpoint);
+            }
+            drop(state);
+
+            if !self.metadata.options.contains(SocketOptions::BLOCK) {
+                return (
+                    Err(SystemError::EAGAIN_OR_EWOULDBLOCK),
+                    Endpoint::Netlink(NetlinkEndpoint::new(0, 0)),
+                );
+            }
+            if let Err(e) = socket_wait(flags, deadline) {
+                return (Err(e), Endpoint::Netlink(NetlinkEndpoint::new(0, 0)));
+            }
+        }
+    }
+
+    fn send(
+        &self,
+        _buf: &[u8],
+        _to: Option<Endpoint>,
+        _flags: MessageFlag,
+    ) -> Result<usize, SystemError> {
+        // todo: 支持用户程序之间通过netlink发送消息
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    fn connect(&mut self, _endpoint: Endpoint) -> Result<(), SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// @brief 绑定端口号，并加入指定的多播组。端口号为0时，使用当前进程的pid
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let endpoint = match endpoint {
+            Endpoint::Netlink(endpoint) => endpoint,
+            _ => return Err(SystemError::EINVAL),
+        };
+        let portid = if endpoint.pid != 0 {
+            endpoint.pid
+        } else {
+            ProcessManager::current_pcb().pid().data() as u32
+        };
+        let mut state = self.state.lock_irqsave();
+        state.portid = portid;
+        state.groups = endpoint.groups;
+        return Ok(());
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        let state = self.state.lock_irqsave();
+        return Some(Endpoint::Netlink(NetlinkEndpoint::new(
+            state.portid,
+            state.groups,
+        )));
+    }
+
+    fn poll(&self) -> (bool, bool, bool) {
+        return (!self.state.lock_irqsave().messages.is_empty(), false, false);
+    }
+
+    /// @brief 设置netlink socket的选项
+    ///
+    /// 支持SOL_NETLINK层的NETLINK_ADD_MEMBERSHIP和NETLINK_DROP_MEMBERSHIP。
+    /// 接收缓冲区的大小是固定的，SO_RCVBUF、SO_RCVBUFFORCE和SO_PASSCRED被忽略
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level == SOL_NETLINK {
+            let group = sockopt_int(optval)?;
+            if !(1..=32).contains(&group) {
+                return Err(SystemError::EINVAL);
+            }
+            let mask = 1u32 << (group - 1);
+            let mut state = self.state.lock_irqsave();
+            return match optname {
+                NETLINK_ADD_MEMBERSHIP => {
+                    state.groups |= mask;
+                    Ok(())
+                }
+                NETLINK_DROP_MEMBERSHIP => {
+                    state.groups &= !mask;
+                    Ok(())
+                }
+                _ => Err(SystemError::ENOPROTOOPT),
+            };
+        }
+        if level as u8 == SOL_SOCKET
+            && (optname == PosixSocketOption::SO_RCVBUF as usize
+                || optname == PosixSocketOption::SO_RCVBUFFORCE as usize
+                || optname == PosixSocketOption::SO_PASSCRED as usize)
+        {
+            return Ok(());
+        }
+        return self.metadata.setsockopt(level, optname, optval);
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level as u8 == SOL_SOCKET && optname == PosixSocketOption::SO_TYPE as usize {
+            return Ok(put_sockopt_int(optval, self.posix_type as i32));
+        }
+        return self.metadata.getsockopt(level, optname, optval);
+    }
+
+    fn metadata(&self) -> Result<SocketMetadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn box_clone(&self) -> alloc::boxed::Box<dyn Socket> {
+        return Box::new(self.clone());
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+/// @brief 地址族的枚举
+///
+/// 参考：https://opengrok.ringotek.cn/xref/linux-5.19.10/include/linux/socket.h#180
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum AddressFamily {
+    /// AF_UNSPEC 表示地址族未指定
+    Unspecified = 0,
+    /// AF_UNIX 表示Unix域的socket (与AF_LOCAL相同)
+    Unix = 1,
+    ///  AF_INET 表示IPv4的socket
+    INet = 2,
+    /// AF_AX25 表示AMPR AX.25的socket
+    AX25 = 3,
+    /// AF_IPX 表示IPX的socket
+    IPX = 4,
+    /// AF_APPLETALK 表示Appletalk的socket
+    Appletalk = 5,
+    /// AF_NETROM 表示AMPR NET/ROM的socket
+    Netrom = 6,
+    /// AF_BRIDGE 表示多协议桥接的socket
+    Bridge = 7,
+    /// AF_ATMPVC 表示ATM PVCs的socket
+    Atmpvc = 8,
+    /// AF_X25 表示X.25的socket
+    X25 = 9,
+    /// AF_INET6 表示IPv6的socket
+    INet6 = 10,
+    /// AF_ROSE 表示AMPR ROSE的socket
+    Rose = 11,
+    /// AF_DECnet Reserved for DECnet project
+    Decnet = 12,
+    /// AF_NETBEUI Reserved for 802.2LLC project
+    Netbeui = 13,
+    /// AF_SECURITY 表示Security callback的伪AF
+    Security = 14,
+    /// AF_KEY 表示Key management API
+    Key = 15,
+    /// AF_NETLINK 表示Netlink的socket
+    Netlink = 16,
+    /// AF_PACKET 表示Low level packet interface
+    Packet = 17,
+    /// AF_ASH 表示Ash
+    Ash = 18,
+    /// AF_ECONET 表示Acorn Econet
+    Econet = 19,
+    /// AF_ATMSVC 表示ATM SVCs
+    Atmsvc = 20,
+    /// AF_RDS 表示Reliable Datagram Sockets
+    Rds = 21,
+    /// AF_SNA 表示Linux SNA Project
+    Sna = 22,
+    /// AF_IRDA 表示IRDA sockets
+    Irda = 23,
+    /// AF_PPPOX 表示PPPoX sockets
+    Pppox = 24,
+    /// AF_WANPIPE 表示WANPIPE API sockets
+    WanPipe = 25,
+    /// AF_LLC 表示Linux LLC
+    Llc = 26,
+    /// AF_IB 表示Native InfiniBand address
+    /// 介绍：https://access.redhat.com/documentation/en-us/red_hat_enterprise_linux/9/html-single/configuring_infiniband_and_rdma_networks/index#understanding-infiniband-and-rdma_configuring-infiniband-and-rdma-networks
     Ib = 27,
     /// AF_MPLS 表示MPLS
     Mpls = 28,
@@ -1131,6 +2787,27 @@ impl TryFrom<u8> for PosixSocketType {
     }
 }
 
+/// @brief socket文件的私有信息
+#[derive(Debug, Clone)]
+pub struct SocketFilePrivateData {
+    /// 打开文件的模式，以O_NONBLOCK打开时，read、write不会阻塞
+    mode: FileMode,
+}
+
+impl SocketFilePrivateData {
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+
+    /// @brief 根据文件的打开模式，得到read、write时使用的消息标志
+    fn message_flags(&self) -> MessageFlag {
+        if self.mode.contains(FileMode::O_NONBLOCK) {
+            return MessageFlag::DONTWAIT;
+        }
+        return MessageFlag::empty();
+    }
+}
+
 /// @brief Socket在文件系统中的inode封装
 #[derive(Debug)]
 pub struct SocketInode(SpinLock<Box<dyn Socket>>);
@@ -1147,18 +2824,12 @@ impl SocketInode {
 }
 
 impl IndexNode for SocketInode {
-    fn open(
-        &self,
-        _data: &mut crate::filesystem::vfs::FilePrivateData,
-        _mode: &crate::filesystem::vfs::file::FileMode,
-    ) -> Result<(), SystemError> {
+    fn open(&self, data: &mut FilePrivateData, mode: &FileMode) -> Result<(), SystemError> {
+        *data = FilePrivateData::Socket(SocketFilePrivateData { mode: *mode });
         return Ok(());
     }
 
-    fn close(
-        &self,
-        _data: &mut crate::filesystem::vfs::FilePrivateData,
-    ) -> Result<(), SystemError> {
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
         let socket = self.0.lock();
         if let Some(Endpoint::Ip(Some(ip))) = socket.endpoint() {
             PORT_MANAGER.unbind_port(socket.metadata().unwrap().socket_type, ip.port)?;
@@ -1171,8 +2842,11 @@ impl IndexNode for SocketInode {
         _offset: usize,
         len: usize,
         buf: &mut [u8],
-        _data: &mut crate::filesystem::vfs::FilePrivateData,
+        data: &mut FilePrivateData,
     ) -> Result<usize, SystemError> {
+        if let FilePrivateData::Socket(p) = data {
+            return self.0.lock().recv(&mut buf[0..len], p.message_flags()).0;
+        }
         return self.0.lock().read(&mut buf[0..len]).0;
     }
 
@@ -1181,12 +2855,21 @@ impl IndexNode for SocketInode {
         _offset: usize,
         len: usize,
         buf: &[u8],
-        _data: &mut crate::filesystem::vfs::FilePrivateData,
+        data: &mut FilePrivateData,
     ) -> Result<usize, SystemError> {
+        if let FilePrivateData::Socket(p) = data {
+            return self.0.lock().send(&buf[0..len], None, p.message_flags());
+        }
         return self.0.lock().write(&buf[0..len], None);
     }
 
-    fn poll(&self) -> Result<crate::filesystem::vfs::PollStatus, SystemError> {
+    fn ioctl(&self, cmd: u32, data: usize) -> Result<usize, SystemError> {
+        return dev_ioctl(cmd, data);
+    }
+
+    fn poll(&self, table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        // 网卡收到数据之后会唤醒所有socket共用的等待队列
+        table.wait(&SOCKET_WAITQUEUE);
         let (read, write, error) = self.0.lock().poll();
         let mut result = PollStatus::empty();
         if read {
@@ -9,18 +9,33 @@ use crate::{
         file::{File, FileMode},
         syscall::{IoVec, IoVecs},
     },
-    include::bindings::bindings::verify_area,
     libs::spinlock::SpinLockGuard,
-    net::socket::{AddressFamily, SOL_SOCKET},
+    mm::VirtAddr,
+    net::socket::AddressFamily,
     process::ProcessManager,
-    syscall::{Syscall, SystemError},
+    syscall::{
+        user_access::{copy_to_user, UserBufferWriter, UserPtr},
+        Syscall, SystemError,
+    },
 };
 
 use super::{
-    socket::{PosixSocketType, RawSocket, SocketInode, SocketOptions, TcpSocket, UdpSocket},
+    endpoints::{LinkLayerEndpoint, NetlinkEndpoint},
+    net_core::poll_ifaces,
+    socket::{
+        MessageFlag, NetlinkSocket, PacketSocket, PosixSocketType, RawSocket, SocketInode,
+        SocketOptions, TcpSocket, UdpSocket, UnixSocket,
+    },
     Endpoint, Protocol, ShutdownType, Socket,
 };
 
+/// socket类型中，表示非阻塞的标志(SOCK_NONBLOCK)
+const SOCK_NONBLOCK: usize = 0o4000;
+/// socket类型中，表示执行exec时关闭的标志(SOCK_CLOEXEC)
+const SOCK_CLOEXEC: usize = 0o2000000;
+/// getsockopt的optval缓冲区的最大长度
+const MAX_SOCKOPT_LEN: usize = 4096;
+
 impl Syscall {
     /// @brief sys_socket系统调用的实际执行函数
     ///
@@ -33,6 +48,7 @@ impl Syscall {
         protocol: usize,
     ) -> Result<usize, SystemError> {
         let address_family = AddressFamily::try_from(address_family as u16)?;
+        let nonblock = socket_type & SOCK_NONBLOCK != 0;
         let socket_type = PosixSocketType::try_from((socket_type & 0xf) as u8)?;
         // kdebug!("do_socket: address_family: {address_family:?}, socket_type: {socket_type:?}, protocol: {protocol}");
         // 根据地址族和socket类型创建socket
@@ -49,6 +65,27 @@ impl Syscall {
                     return Err(SystemError::EINVAL);
                 }
             },
+            AddressFamily::Packet => {
+                // 协议号是网络字节序的以太网协议类型，例如ETH_P_ALL
+                let protocol = u16::from_be(protocol as u16);
+                let cooked = match socket_type {
+                    PosixSocketType::Raw => false,
+                    PosixSocketType::Datagram => true,
+                    _ => return Err(SystemError::ESOCKTNOSUPPORT),
+                };
+                let mut options = SocketOptions::empty();
+                options.set(SocketOptions::BLOCK, !nonblock);
+                Box::new(PacketSocket::new(cooked, protocol, options))
+            }
+            AddressFamily::Netlink => {
+                match socket_type {
+                    PosixSocketType::Raw | PosixSocketType::Datagram => {}
+                    _ => return Err(SystemError::ESOCKTNOSUPPORT),
+                }
+                let mut options = SocketOptions::empty();
+                options.set(SocketOptions::BLOCK, !nonblock);
+                Box::new(NetlinkSocket::new(socket_type, protocol, options)?)
+            }
             _ => {
                 // kdebug!("do_socket: EAFNOSUPPORT");
                 return Err(SystemError::EAFNOSUPPORT);
@@ -56,7 +93,9 @@ impl Syscall {
         };
         // kdebug!("do_socket: socket: {socket:?}");
         let socketinode: Arc<SocketInode> = SocketInode::new(socket);
-        let f = File::new(socketinode, FileMode::O_RDWR)?;
+        let mut mode = FileMode::O_RDWR;
+        mode.set(FileMode::O_NONBLOCK, nonblock);
+        let f = File::new(socketinode, mode)?;
         // kdebug!("do_socket: f: {f:?}");
         // 把socket添加到当前进程的文件描述符表中
         let binding = ProcessManager::current_pcb().fd_table();
@@ -68,6 +107,69 @@ impl Syscall {
         return fd;
     }
 
+    /// @brief sys_socketpair系统调用的实际执行函数
+    ///
+    /// 目前只支持AF_UNIX地址族的流socket和数据报socket
+    ///
+    /// @param address_family 地址族
+    /// @param socket_type socket类型
+    /// @param protocol 传输协议，必须为0
+    /// @param fds 用于返回两个文件描述符的数组
+    ///
+    /// @return 成功返回0，失败返回错误码
+    pub fn socketpair(
+        address_family: usize,
+        socket_type: usize,
+        protocol: usize,
+        fds: *mut i32,
+    ) -> Result<usize, SystemError> {
+        let address_family = AddressFamily::try_from(address_family as u16)?;
+        if address_family != AddressFamily::Unix {
+            return Err(SystemError::EAFNOSUPPORT);
+        }
+        let datagram = match PosixSocketType::try_from((socket_type & 0xf) as u8)? {
+            PosixSocketType::Stream => false,
+            PosixSocketType::Datagram => true,
+            _ => return Err(SystemError::ESOCKTNOSUPPORT),
+        };
+        if protocol != 0 {
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+
+        let mut user_buffer = UserBufferWriter::new(fds, core::mem::size_of::<[i32; 2]>(), true)?;
+        let mut options = SocketOptions::empty();
+        options.set(SocketOptions::BLOCK, socket_type & SOCK_NONBLOCK == 0);
+        let (a, b) = UnixSocket::new_pair(options, datagram);
+
+        let mut mode = FileMode::O_RDWR;
+        mode.set(FileMode::O_NONBLOCK, socket_type & SOCK_NONBLOCK != 0);
+        let mut files = [
+            File::new(SocketInode::new(Box::new(a)), mode)?,
+            File::new(SocketInode::new(Box::new(b)), mode)?,
+        ];
+        if socket_type & SOCK_CLOEXEC != 0 {
+            for f in files.iter_mut() {
+                f.set_close_on_exec(true);
+            }
+        }
+        let [file_a, file_b] = files;
+
+        let binding = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = binding.write();
+        let fd_a = fd_table_guard.alloc_fd(file_a, None)?;
+        let fd_b = match fd_table_guard.alloc_fd(file_b, None) {
+            Ok(fd) => fd,
+            Err(e) => {
+                fd_table_guard.drop_fd(fd_a).ok();
+                return Err(e);
+            }
+        };
+        drop(fd_table_guard);
+
+        user_buffer.copy_to_user(&[fd_a, fd_b], 0)?;
+        return Ok(0);
+    }
+
     /// @brief sys_setsockopt系统调用的实际执行函数
     ///
     /// @param fd 文件描述符
@@ -85,7 +187,7 @@ impl Syscall {
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         // 获取内层的socket（真正的数据）
-        let socket: SpinLockGuard<Box<dyn Socket>> = socket_inode.inner();
+        let mut socket: SpinLockGuard<Box<dyn Socket>> = socket_inode.inner();
         return socket.setsockopt(level, optname, optval).map(|_| 0);
     }
 
@@ -105,60 +207,27 @@ impl Syscall {
         optval: *mut u8,
         optlen: *mut u32,
     ) -> Result<usize, SystemError> {
+        let optlen = UserPtr::<u32>::new(optlen as usize);
+        let len = optlen.read()? as usize;
+        if len > MAX_SOCKOPT_LEN {
+            return Err(SystemError::EINVAL);
+        }
         // 获取socket
-        let optval = optval as *mut u32;
         let binding: Arc<SocketInode> = ProcessManager::current_pcb()
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
-        let socket = binding.inner();
 
-        if level as u8 == SOL_SOCKET {
-            let optname = PosixSocketOption::try_from(optname as i32)
-                .map_err(|_| SystemError::ENOPROTOOPT)?;
-            match optname {
-                PosixSocketOption::SO_SNDBUF => {
-                    // 返回发送缓冲区大小
-                    unsafe {
-                        *optval = socket.metadata()?.send_buf_size as u32;
-                        *optlen = core::mem::size_of::<u32>() as u32;
-                    }
-                    return Ok(0);
-                }
-                PosixSocketOption::SO_RCVBUF => {
-                    let optval = optval as *mut u32;
-                    // 返回默认的接收缓冲区大小
-                    unsafe {
-                        *optval = socket.metadata()?.recv_buf_size as u32;
-                        *optlen = core::mem::size_of::<u32>() as u32;
-                    }
-                    return Ok(0);
-                }
-                _ => {
-                    return Err(SystemError::ENOPROTOOPT);
-                }
-            }
-        }
+        let mut buf = vec![0u8; len];
+        let socket = binding.inner();
+        let written = socket.getsockopt(level, optname, &mut buf)?;
         drop(socket);
 
-        // To manipulate options at any other level the
-        // protocol number of the appropriate protocol controlling the
-        // option is supplied.  For example, to indicate that an option is
-        // to be interpreted by the TCP protocol, level should be set to the
-        // protocol number of TCP.
-
-        let posix_protocol =
-            PosixIpProtocol::try_from(level as u16).map_err(|_| SystemError::ENOPROTOOPT)?;
-        if posix_protocol == PosixIpProtocol::TCP {
-            let optname = PosixTcpSocketOptions::try_from(optname as i32)
-                .map_err(|_| SystemError::ENOPROTOOPT)?;
-            match optname {
-                PosixTcpSocketOptions::Congestion => return Ok(0),
-                _ => {
-                    return Err(SystemError::ENOPROTOOPT);
-                }
-            }
+        if written > 0 {
+            let mut user_buffer = UserBufferWriter::new(optval, written, true)?;
+            user_buffer.copy_to_user(&buf[..written], 0)?;
         }
-        return Err(SystemError::ENOPROTOOPT);
+        optlen.write(&(written as u32))?;
+        return Ok(0);
     }
 
     /// @brief sys_connect系统调用的实际执行函数
@@ -208,7 +277,7 @@ impl Syscall {
     pub fn sendto(
         fd: usize,
         buf: &[u8],
-        _flags: u32,
+        flags: u32,
         addr: *const SockAddr,
         addrlen: usize,
     ) -> Result<usize, SystemError> {
@@ -222,7 +291,7 @@ impl Syscall {
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         let socket = socket.inner();
-        return socket.write(buf, endpoint);
+        return socket.send(buf, endpoint, socket_message_flags(fd, flags));
     }
 
     /// @brief sys_recvfrom系统调用的实际执行函数
@@ -237,7 +306,7 @@ impl Syscall {
     pub fn recvfrom(
         fd: usize,
         buf: &mut [u8],
-        _flags: u32,
+        flags: u32,
         addr: *mut SockAddr,
         addrlen: *mut u32,
     ) -> Result<usize, SystemError> {
@@ -246,7 +315,7 @@ impl Syscall {
             .ok_or(SystemError::EBADF)?;
         let socket = socket.inner();
 
-        let (n, endpoint) = socket.read(buf);
+        let (n, endpoint) = socket.recv(buf, socket_message_flags(fd, flags));
         drop(socket);
 
         let n: usize = n?;
@@ -264,13 +333,15 @@ impl Syscall {
     /// @brief sys_recvmsg系统调用的实际执行函数
     ///
     /// @param fd 文件描述符
-    /// @param msg MsgHdr
-    /// @param flags 标志，暂时未使用
+    /// @param msg 用户空间的MsgHdr
+    /// @param flags 标志
     ///
     /// @return 成功返回接收的字节数，失败返回错误码
-    pub fn recvmsg(fd: usize, msg: &mut MsgHdr, _flags: u32) -> Result<usize, SystemError> {
+    pub fn recvmsg(fd: usize, msg: UserPtr<MsgHdr>, flags: u32) -> Result<usize, SystemError> {
+        // 把MsgHdr拷贝到内核空间，之后对用户内存的访问都通过拷贝函数进行
+        let hdr = msg.read()?;
         // 检查每个缓冲区地址是否合法，生成iovecs
-        let mut iovs = unsafe { IoVecs::from_user(msg.msg_iov, msg.msg_iovlen, true)? };
+        let mut iovs = unsafe { IoVecs::from_user(hdr.msg_iov, hdr.msg_iovlen, true)? };
 
         let socket: Arc<SocketInode> = ProcessManager::current_pcb()
             .get_socket(fd as i32)
@@ -279,18 +350,21 @@ impl Syscall {
 
         let mut buf = iovs.new_buf(true);
         // 从socket中读取数据
-        let (n, endpoint) = socket.read(&mut buf);
+        let (n, endpoint) = socket.recv(&mut buf, socket_message_flags(fd, flags));
         drop(socket);
 
         let n: usize = n?;
 
         // 将数据写入用户空间的iovecs
-        iovs.scatter(&buf[..n]);
+        iovs.scatter(&buf[..n])?;
 
         let sockaddr_in = SockAddr::from(endpoint);
+        let namelen = hdr.user_field(msg, &hdr.msg_namelen);
         unsafe {
-            sockaddr_in.write_to_user(msg.msg_name, &mut msg.msg_namelen)?;
+            sockaddr_in.write_to_user(hdr.msg_name, namelen.addr().data() as *mut u32)?;
         }
+        // 目前不会设置任何接收标志(如MSG_TRUNC)
+        hdr.user_field(msg, &hdr.msg_flags).write(&0)?;
         return Ok(n);
     }
 
@@ -337,6 +411,13 @@ impl Syscall {
             .ok_or(SystemError::EBADF)?;
         // kdebug!("accept: socket={:?}", socket);
         let mut socket = socket.inner();
+        // 以O_NONBLOCK打开的socket上没有连接到来时，直接返回
+        if socket_file_nonblock(fd) {
+            poll_ifaces();
+            if !socket.poll().0 {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+        }
         // 从socket中接收连接
         let (new_socket, remote_endpoint) = socket.accept()?;
         drop(socket);
@@ -412,7 +493,7 @@ impl Syscall {
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         let socket = socket.inner();
-        let endpoint: Endpoint = socket.peer_endpoint().ok_or(SystemError::EINVAL)?;
+        let endpoint: Endpoint = socket.peer_endpoint().ok_or(SystemError::ENOTCONN)?;
         drop(socket);
 
         let sockaddr_in = SockAddr::from(endpoint);
@@ -423,6 +504,24 @@ impl Syscall {
     }
 }
 
+/// @brief 判断socket文件是否以O_NONBLOCK打开(创建时指定了SOCK_NONBLOCK，或者被fcntl设置)
+fn socket_file_nonblock(fd: usize) -> bool {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd as i32);
+    return file.map_or(false, |f| f.lock().mode().contains(FileMode::O_NONBLOCK));
+}
+
+/// @brief 得到socket收发数据时使用的消息标志。socket文件以O_NONBLOCK打开时，加上MSG_DONTWAIT
+fn socket_message_flags(fd: usize, flags: u32) -> MessageFlag {
+    let mut flags = MessageFlag::from_bits_truncate(flags);
+    if socket_file_nonblock(fd) {
+        flags.insert(MessageFlag::DONTWAIT);
+    }
+    return flags;
+}
+
 // 参考资料： https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/netinet_in.h.html#tag_13_32
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -482,17 +581,8 @@ pub union SockAddr {
 impl SockAddr {
     /// @brief 把用户传入的SockAddr转换为Endpoint结构体
     pub fn to_endpoint(addr: *const SockAddr, len: usize) -> Result<Endpoint, SystemError> {
-        if unsafe {
-            verify_area(
-                addr as usize as u64,
-                core::mem::size_of::<SockAddr>() as u64,
-            )
-        } == false
-        {
-            return Err(SystemError::EFAULT);
-        }
-
-        let addr = unsafe { addr.as_ref() }.ok_or(SystemError::EFAULT)?;
+        // 把用户传入的地址拷贝到内核空间
+        let addr: SockAddr = UserPtr::<SockAddr>::from(addr).read()?;
         if len < addr.len()? {
             return Err(SystemError::EINVAL);
         }
@@ -509,12 +599,17 @@ impl SockAddr {
                     return Ok(Endpoint::Ip(Some(wire::IpEndpoint::new(ip, port))));
                 }
                 AddressFamily::Packet => {
-                    // TODO: support packet socket
-                    return Err(SystemError::EINVAL);
+                    let addr_ll: SockAddrLl = addr.addr_ll;
+                    let mut endpoint = LinkLayerEndpoint::new(addr_ll.sll_ifindex as usize);
+                    endpoint.protocol = u16::from_be(addr_ll.sll_protocol);
+                    return Ok(Endpoint::LinkLayer(endpoint));
                 }
                 AddressFamily::Netlink => {
-                    // TODO: support netlink socket
-                    return Err(SystemError::EINVAL);
+                    let addr_nl: SockAddrNl = addr.addr_nl;
+                    return Ok(Endpoint::Netlink(NetlinkEndpoint::new(
+                        addr_nl.nl_pid,
+                        addr_nl.nl_groups,
+                    )));
                 }
                 AddressFamily::Unix => {
                     return Err(SystemError::EINVAL);
@@ -532,7 +627,8 @@ impl SockAddr {
             AddressFamily::INet => Ok(core::mem::size_of::<SockAddrIn>()),
             AddressFamily::Packet => Ok(core::mem::size_of::<SockAddrLl>()),
             AddressFamily::Netlink => Ok(core::mem::size_of::<SockAddrNl>()),
-            AddressFamily::Unix => Err(SystemError::EINVAL),
+            // 目前只支持未命名的unix域地址，只有sun_family字段
+            AddressFamily::Unix => Ok(core::mem::size_of::<u16>()),
             _ => Err(SystemError::EINVAL),
         };
 
@@ -554,24 +650,15 @@ impl SockAddr {
         if addr.is_null() || addr_len.is_null() {
             return Ok(0);
         }
-        // 检查用户传入的地址是否合法
-        if !verify_area(
-            addr as usize as u64,
-            core::mem::size_of::<SockAddr>() as u64,
-        ) || !verify_area(addr_len as usize as u64, core::mem::size_of::<u32>() as u64)
-        {
-            return Err(SystemError::EFAULT);
-        }
-
-        let to_write = min(self.len()?, *addr_len as usize);
+        let addr_len = UserPtr::<u32>::from(addr_len);
+        let to_write = min(self.len()?, addr_len.read()? as usize);
         if to_write > 0 {
-            let buf = core::slice::from_raw_parts_mut(addr as *mut u8, to_write);
-            buf.copy_from_slice(core::slice::from_raw_parts(
-                self as *const SockAddr as *const u8,
-                to_write,
-            ));
+            copy_to_user(
+                VirtAddr::new(addr as usize),
+                core::slice::from_raw_parts(self as *const SockAddr as *const u8, to_write),
+            )?;
         }
-        *addr_len = self.len()? as u32;
+        addr_len.write(&(self.len()? as u32))?;
         return Ok(to_write);
     }
 }
@@ -609,21 +696,40 @@ impl From<Endpoint> for SockAddr {
             }
 
             Endpoint::LinkLayer(link_endpoint) => {
+                let mut sll_addr = [0u8; 8];
+                sll_addr[..6].copy_from_slice(&link_endpoint.addr);
                 let addr_ll = SockAddrLl {
                     sll_family: AddressFamily::Packet as u16,
-                    sll_protocol: 0,
+                    sll_protocol: link_endpoint.protocol.to_be(),
                     sll_ifindex: link_endpoint.interface as u32,
-                    sll_hatype: 0,
-                    sll_pkttype: 0,
-                    sll_halen: 0,
-                    sll_addr: [0; 8],
+                    sll_hatype: link_endpoint.hatype,
+                    sll_pkttype: link_endpoint.pkttype,
+                    sll_halen: link_endpoint.addr.len() as u8,
+                    sll_addr,
                 };
 
                 return SockAddr { addr_ll };
-            } // _ => {
-              //     // todo: support other endpoint, like Netlink...
-              //     unimplemented!("not support {value:?}");
-              // }
+            }
+
+            Endpoint::Unnamed => {
+                let addr_un = SockAddrUn {
+                    sun_family: AddressFamily::Unix as u16,
+                    sun_path: [0; 108],
+                };
+
+                return SockAddr { addr_un };
+            }
+
+            Endpoint::Netlink(netlink_endpoint) => {
+                let addr_nl = SockAddrNl {
+                    nl_family: AddressFamily::Netlink as u16,
+                    nl_pad: 0,
+                    nl_pid: netlink_endpoint.pid,
+                    nl_groups: netlink_endpoint.groups,
+                };
+
+                return SockAddr { addr_nl };
+            }
         }
     }
 }
@@ -647,6 +753,17 @@ pub struct MsgHdr {
     pub msg_flags: u32,
 }
 
+impl MsgHdr {
+    /// @brief 获取MsgHdr的某个字段在用户空间中的地址
+    ///
+    /// @param msg 用户空间的MsgHdr
+    /// @param field 从用户空间拷贝得到的MsgHdr(即self)中的字段
+    fn user_field<T>(&self, msg: UserPtr<MsgHdr>, field: &T) -> UserPtr<T> {
+        let offset = field as *const T as usize - self as *const MsgHdr as usize;
+        return UserPtr::new(msg.addr().data() + offset);
+    }
+}
+
 #[derive(Debug, Clone, Copy, FromPrimitive, ToPrimitive, PartialEq, Eq)]
 pub enum PosixIpProtocol {
     /// Dummy protocol for TCP.
@@ -0,0 +1,198 @@
+//! perf事件的采样缓冲区
+//!
+//! 缓冲区由用户程序通过mmap映射到自己的地址空间，布局与Linux相同：
+//! 第一页是元数据页([`PerfEventMmapPage`])，之后的2^n页是环形的数据区。
+//!
+//! 内核在计数器溢出时向数据区写入记录，然后更新`data_head`；
+//! 用户程序读取记录之后更新`data_tail`。剩余空间不够时，记录被丢弃，
+//! 丢弃的数量在下一次有空间时以`PERF_RECORD_LOST`记录告诉用户程序。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/events/ring_buffer.c
+
+use core::{
+    mem::size_of,
+    sync::atomic::{fence, AtomicU64, Ordering},
+};
+
+use alloc::vec::Vec;
+
+use crate::{
+    arch::MMArch,
+    mm::{
+        allocator::page_frame::{
+            allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
+        },
+        MemoryManagementArch,
+    },
+    syscall::SystemError,
+};
+
+/// 记录的类型：丢弃的记录的数量
+pub const PERF_RECORD_LOST: u32 = 2;
+/// 记录的类型：一次采样
+pub const PERF_RECORD_SAMPLE: u32 = 9;
+
+/// 元数据页，与Linux的`struct perf_event_mmap_page`相同
+#[allow(dead_code)]
+#[repr(C)]
+struct PerfEventMmapPage {
+    version: u32,
+    compat_version: u32,
+    lock: u32,
+    index: u32,
+    offset: i64,
+    time_enabled: u64,
+    time_running: u64,
+    capabilities: u64,
+    pmc_width: u16,
+    time_shift: u16,
+    time_mult: u32,
+    time_offset: u64,
+    time_zero: u64,
+    size: u32,
+    _reserved_1: u32,
+    time_cycles: u64,
+    time_mask: u64,
+    _reserved: [u8; 116 * 8],
+    /// 数据区中下一条记录写入的位置，只增不减，由内核更新
+    data_head: AtomicU64,
+    /// 用户程序已经读取到的位置，由用户程序更新
+    data_tail: AtomicU64,
+    data_offset: u64,
+    data_size: u64,
+}
+
+// data_head必须位于元数据页的第1024字节处
+const _: () = assert!(size_of::<PerfEventMmapPage>() == 1024 + 4 * 8);
+
+/// 一个事件的采样缓冲区
+#[derive(Debug)]
+pub struct PerfBuffer {
+    /// 第一页是元数据页，之后是数据区
+    pages: Vec<PhysPageFrame>,
+    /// 数据区的字节数
+    data_size: usize,
+    /// 还没有告诉用户程序的、被丢弃的记录的数量
+    lost: AtomicU64,
+}
+
+impl PerfBuffer {
+    /// 创建缓冲区
+    ///
+    /// ## 参数
+    ///
+    /// - `data_pages`：数据区的页数，必须是2的幂
+    pub fn new(data_pages: usize) -> Result<Self, SystemError> {
+        if !data_pages.is_power_of_two() {
+            return Err(SystemError::EINVAL);
+        }
+        // 数据区由用户程序按页映射，不要求物理上连续，逐页分配
+        let mut r = Self {
+            pages: Vec::with_capacity(data_pages + 1),
+            data_size: data_pages * MMArch::PAGE_SIZE,
+            lost: AtomicU64::new(0),
+        };
+        for _ in 0..=data_pages {
+            let (paddr, _) = unsafe { allocate_page_frames(PageFrameCount::new(1)) }
+                .ok_or(SystemError::ENOMEM)?;
+            unsafe {
+                MMArch::write_bytes(MMArch::phys_2_virt(paddr).unwrap(), 0, MMArch::PAGE_SIZE)
+            };
+            r.pages.push(PhysPageFrame::new(paddr));
+        }
+
+        let meta = unsafe { &mut *r.meta_ptr() };
+        meta.size = size_of::<PerfEventMmapPage>() as u32;
+        meta.data_offset = MMArch::PAGE_SIZE as u64;
+        meta.data_size = r.data_size as u64;
+        return Ok(r);
+    }
+
+    /// 缓冲区的所有页，包括元数据页
+    pub fn pages(&self) -> &[PhysPageFrame] {
+        return &self.pages;
+    }
+
+    fn meta_ptr(&self) -> *mut PerfEventMmapPage {
+        return unsafe { MMArch::phys_2_virt(self.pages[0].phys_address()) }
+            .unwrap()
+            .data() as *mut PerfEventMmapPage;
+    }
+
+    fn meta(&self) -> &PerfEventMmapPage {
+        return unsafe { &*self.meta_ptr() };
+    }
+
+    /// 从数据区的`pos`处开始写入，超出数据区末尾的部分回到开头
+    fn write_bytes(&self, mut pos: usize, mut data: &[u8]) {
+        while !data.is_empty() {
+            pos &= self.data_size - 1;
+            let page = &self.pages[1 + pos / MMArch::PAGE_SIZE];
+            let page_offset = pos % MMArch::PAGE_SIZE;
+            let len = data.len().min(MMArch::PAGE_SIZE - page_offset);
+            unsafe {
+                let vaddr = MMArch::phys_2_virt(page.phys_address()).unwrap();
+                core::ptr::copy_nonoverlapping(
+                    data.as_ptr(),
+                    (vaddr.data() + page_offset) as *mut u8,
+                    len,
+                );
+            }
+            pos += len;
+            data = &data[len..];
+        }
+    }
+
+    /// 写入一条记录，返回下一条记录的位置
+    fn write_record(&self, head: u64, type_: u32, misc: u16, body: &[u64]) -> u64 {
+        let size = (8 + body.len() * 8) as u64;
+        let header = type_ as u64 | (misc as u64) << 32 | size << 48;
+        self.write_bytes(head as usize, &header.to_ne_bytes());
+        for (i, v) in body.iter().enumerate() {
+            self.write_bytes(head as usize + 8 * (i + 1), &v.to_ne_bytes());
+        }
+        return head + size;
+    }
+
+    /// 写入一条采样记录。可能在NMI中调用，不会分配内存
+    ///
+    /// ## 参数
+    ///
+    /// - `misc`：记录头中的misc字段
+    /// - `body`：记录的内容，由事件的sample_type决定
+    pub fn output_sample(&self, misc: u16, body: &[u64]) {
+        let meta = self.meta();
+        let head = meta.data_head.load(Ordering::Relaxed);
+        let tail = meta.data_tail.load(Ordering::Acquire);
+        let free = self.data_size as u64 - head.wrapping_sub(tail);
+
+        let lost = self.lost.load(Ordering::Relaxed);
+        let mut need = 8 + body.len() as u64 * 8;
+        if lost != 0 {
+            need += 8 + 2 * 8;
+        }
+        if need > free {
+            self.lost.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let mut head = head;
+        if lost != 0 {
+            head = self.write_record(head, PERF_RECORD_LOST, 0, &[0, lost]);
+            self.lost.store(0, Ordering::Relaxed);
+        }
+        head = self.write_record(head, PERF_RECORD_SAMPLE, misc, body);
+
+        // 记录的内容必须在data_head更新之前对用户程序可见
+        fence(Ordering::Release);
+        meta.data_head.store(head, Ordering::Release);
+    }
+}
+
+impl Drop for PerfBuffer {
+    fn drop(&mut self) {
+        for page in self.pages.iter() {
+            unsafe { deallocate_page_frames(*page, PageFrameCount::new(1)) };
+        }
+    }
+}
@@ -0,0 +1,446 @@
+//! 性能计数器(perf events)
+//!
+//! 用户程序通过perf_event_open系统调用创建事件，得到一个文件描述符：
+//! - read读出事件的计数(一个u64)
+//! - ioctl启用、关闭事件，或者把计数清零
+//! - 指定了采样周期的事件，每经过这么多次事件产生一条采样记录，
+//!   写入通过mmap映射的缓冲区，见[`buffer`]
+//!
+//! 事件可以跟随一个进程(pid)，也可以统计一个cpu上的所有进程(cpu)。
+//! 硬件计数器的数量有限，每次进程切换时，停止当前cpu上的所有计数器，把计数累加到事件上，
+//! 然后为即将运行的进程重新分配计数器。计数器不够用时，多出来的事件这段时间内不计数。
+//!
+//! 启用、关闭、清零事件时，只有当前cpu上的计数器立刻更新；
+//! 其他cpu上的计数器在它们下一次切换进程时更新，在此之前读出的计数可能略微滞后。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/events/core.c
+
+pub mod buffer;
+pub mod syscall;
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    arch::{
+        pmu::{arch_pmu, X86Pmu},
+        CurrentIrqArch,
+    },
+    exception::InterruptArch,
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    mm::percpu::PerCpu,
+    process::{Pid, ProcessManager},
+    smp::core::smp_get_processor_id,
+    syscall::SystemError,
+    time::hrtimer::ktime_get,
+};
+
+use self::buffer::PerfBuffer;
+
+/// 事件类型：通用的硬件事件
+pub const PERF_TYPE_HARDWARE: u32 = 0;
+
+/// 采样记录中包含指令地址
+pub const PERF_SAMPLE_IP: u64 = 1 << 0;
+/// 采样记录中包含pid与tid
+pub const PERF_SAMPLE_TID: u64 = 1 << 1;
+/// 采样记录中包含时间戳
+pub const PERF_SAMPLE_TIME: u64 = 1 << 2;
+/// 采样记录中包含cpu编号
+pub const PERF_SAMPLE_CPU: u64 = 1 << 7;
+/// 采样记录中包含采样周期
+pub const PERF_SAMPLE_PERIOD: u64 = 1 << 8;
+/// 支持的采样记录内容
+const PERF_SAMPLE_SUPPORTED: u64 =
+    PERF_SAMPLE_IP | PERF_SAMPLE_TID | PERF_SAMPLE_TIME | PERF_SAMPLE_CPU | PERF_SAMPLE_PERIOD;
+
+/// 采样发生在内核态
+const PERF_RECORD_MISC_KERNEL: u16 = 1;
+/// 采样发生在用户态
+const PERF_RECORD_MISC_USER: u16 = 2;
+
+/// 每个cpu最多同时使用的计数器数量
+const PERF_MAX_COUNTERS: usize = 8;
+
+/// 通用的硬件事件，即`perf_event_attr.config`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerfHwId {
+    CpuCycles,
+    Instructions,
+    CacheReferences,
+    CacheMisses,
+    BranchInstructions,
+    BranchMisses,
+}
+
+impl PerfHwId {
+    fn from_config(config: u64) -> Option<Self> {
+        match config {
+            0 => Some(Self::CpuCycles),
+            1 => Some(Self::Instructions),
+            2 => Some(Self::CacheReferences),
+            3 => Some(Self::CacheMisses),
+            4 => Some(Self::BranchInstructions),
+            5 => Some(Self::BranchMisses),
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    /// `perf_event_attr`中的标志位
+    pub struct PerfAttrFlags: u64 {
+        /// 创建之后处于关闭状态
+        const DISABLED = 1 << 0;
+        /// 子进程继承事件
+        const INHERIT = 1 << 1;
+        /// 不统计用户态
+        const EXCLUDE_USER = 1 << 4;
+        /// 不统计内核态
+        const EXCLUDE_KERNEL = 1 << 5;
+        /// sample_period实际上是采样频率
+        const FREQ = 1 << 10;
+    }
+}
+
+/// 与Linux的`struct perf_event_attr`的第一个版本(PERF_ATTR_SIZE_VER0)相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerfEventAttr {
+    pub type_: u32,
+    /// 结构体的大小，用于兼容不同版本
+    pub size: u32,
+    pub config: u64,
+    /// 采样周期，为0时不采样
+    pub sample_period: u64,
+    pub sample_type: u64,
+    pub read_format: u64,
+    pub flags: u64,
+    pub wakeup_events: u32,
+    pub bp_type: u32,
+    pub config1: u64,
+}
+
+/// 一个性能计数事件
+#[derive(Debug)]
+pub struct PerfEvent {
+    attr: PerfEventAttr,
+    /// [`X86Pmu::hw_event`]返回的事件编码
+    hw_event: u64,
+    /// 只统计这个进程，为None时统计cpu上的所有进程
+    pid: Option<Pid>,
+    /// 只统计这个cpu，为None时统计进程在所有cpu上的运行
+    cpu: Option<usize>,
+    enabled: AtomicBool,
+    /// 从计数器上累加的计数，不包括正在运行的计数器上的部分
+    count: AtomicU64,
+    /// 采样缓冲区，在第一次mmap时创建
+    buffer: SpinLock<Option<Arc<PerfBuffer>>>,
+}
+
+impl PerfEvent {
+    #[inline]
+    fn flags(&self) -> PerfAttrFlags {
+        return PerfAttrFlags::from_bits_truncate(self.attr.flags);
+    }
+
+    #[inline]
+    fn sampling(&self) -> bool {
+        return self.attr.sample_period != 0;
+    }
+
+    /// 计数器的初始值。采样时为采样周期的相反数，溢出时恰好经过一个采样周期
+    fn initial_count(&self, pmu: &X86Pmu) -> u64 {
+        if self.sampling() {
+            return self.attr.sample_period.wrapping_neg() & pmu.counter_mask();
+        }
+        return 0;
+    }
+
+    /// 判断在`cpu`上运行`pid`时是否需要统计这个事件
+    fn matches(&self, cpu: usize, pid: Pid) -> bool {
+        return self.enabled.load(Ordering::Relaxed)
+            && self.cpu.map_or(true, |c| c == cpu)
+            && self.pid.map_or(true, |p| p == pid);
+    }
+
+    /// 读取事件的计数
+    pub fn read_count(self: &Arc<Self>) -> u64 {
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        let mut count = self.count.load(Ordering::SeqCst);
+        if let Some(pmu) = arch_pmu() {
+            let ctx = perf_cpu_context();
+            for (idx, slot) in ctx.slots.iter().enumerate() {
+                if let Some(slot) = slot {
+                    if Arc::ptr_eq(&slot.event, self) {
+                        count += pmu.read(idx).wrapping_sub(slot.prev) & pmu.counter_mask();
+                    }
+                }
+            }
+        }
+        drop(irq_guard);
+        return count;
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+        perf_resched_local(|| {});
+    }
+
+    /// 把计数清零
+    pub fn reset(&self) {
+        perf_resched_local(|| self.count.store(0, Ordering::SeqCst));
+    }
+
+    /// 获取采样缓冲区，不存在时创建
+    ///
+    /// ## 参数
+    ///
+    /// - `data_pages`：数据区的页数。缓冲区已经存在时必须与之前相同
+    pub fn buffer(&self, data_pages: usize) -> Result<Arc<PerfBuffer>, SystemError> {
+        if let Some(buffer) = self.buffer.lock_irqsave().as_ref() {
+            if buffer.pages().len() != data_pages + 1 {
+                return Err(SystemError::EINVAL);
+            }
+            return Ok(buffer.clone());
+        }
+        // 分配内存时不能持有关中断的锁
+        let buffer = Arc::new(PerfBuffer::new(data_pages)?);
+        let mut guard = self.buffer.lock_irqsave();
+        if let Some(old) = guard.as_ref() {
+            if old.pages().len() != data_pages + 1 {
+                return Err(SystemError::EINVAL);
+            }
+            return Ok(old.clone());
+        }
+        *guard = Some(buffer.clone());
+        return Ok(buffer);
+    }
+
+    /// 计数器溢出时写入一条采样记录。在NMI中调用
+    fn output_sample(&self, ip: usize, user: bool, cpu: usize) {
+        let buffer = match self.buffer.try_lock() {
+            Ok(guard) => match guard.as_ref() {
+                Some(buffer) => buffer.clone(),
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        let sample_type = self.attr.sample_type;
+        let mut body = [0u64; 5];
+        let mut len = 0;
+        let mut push = |v: u64| {
+            body[len] = v;
+            len += 1;
+        };
+        if sample_type & PERF_SAMPLE_IP != 0 {
+            push(ip as u64);
+        }
+        if sample_type & PERF_SAMPLE_TID != 0 {
+            let pid = ProcessManager::current_pcb().pid().data() as u64;
+            push(pid | pid << 32);
+        }
+        if sample_type & PERF_SAMPLE_TIME != 0 {
+            push(ktime_get() as u64);
+        }
+        if sample_type & PERF_SAMPLE_CPU != 0 {
+            push(cpu as u64);
+        }
+        if sample_type & PERF_SAMPLE_PERIOD != 0 {
+            push(self.attr.sample_period);
+        }
+
+        let misc = if user {
+            PERF_RECORD_MISC_USER
+        } else {
+            PERF_RECORD_MISC_KERNEL
+        };
+        buffer.output_sample(misc, &body[..len]);
+    }
+}
+
+/// 正在使用一个硬件计数器的事件
+#[derive(Debug)]
+struct PerfSlot {
+    event: Arc<PerfEvent>,
+    /// 上一次把计数累加到事件上时，计数器的值
+    prev: u64,
+}
+
+/// 一个cpu上的硬件计数器的使用情况，第i项对应第i个计数器
+#[derive(Debug)]
+struct PerfCpuContext {
+    slots: [Option<PerfSlot>; PERF_MAX_COUNTERS],
+}
+
+impl PerfCpuContext {
+    const fn new() -> Self {
+        const NONE: Option<PerfSlot> = None;
+        Self {
+            slots: [NONE; PERF_MAX_COUNTERS],
+        }
+    }
+}
+
+/// 所有的事件
+static PERF_EVENTS: SpinLock<Vec<Arc<PerfEvent>>> = SpinLock::new(Vec::new());
+/// 事件的数量。没有事件时，进程切换不需要做任何事
+static PERF_EVENT_NR: AtomicUsize = AtomicUsize::new(0);
+
+const PERF_CPU_CONTEXT_INIT: SpinLock<PerfCpuContext> = SpinLock::new(PerfCpuContext::new());
+static PERF_CPU_CONTEXTS: [SpinLock<PerfCpuContext>; PerCpu::MAX_CPU_NUM] =
+    [PERF_CPU_CONTEXT_INIT; PerCpu::MAX_CPU_NUM];
+/// cpu上是否有正在使用的计数器
+const PERF_CPU_ACTIVE_INIT: AtomicBool = AtomicBool::new(false);
+static PERF_CPU_ACTIVE: [AtomicBool; PerCpu::MAX_CPU_NUM] =
+    [PERF_CPU_ACTIVE_INIT; PerCpu::MAX_CPU_NUM];
+
+/// 获取当前cpu的计数器，调用者需要关中断
+fn perf_cpu_context() -> SpinLockGuard<'static, PerfCpuContext> {
+    return PERF_CPU_CONTEXTS[smp_get_processor_id() as usize].lock();
+}
+
+/// 停止cpu上的所有计数器，把计数累加到事件上
+fn perf_sched_out(pmu: &X86Pmu, ctx: &mut PerfCpuContext) {
+    for (idx, slot) in ctx.slots.iter_mut().enumerate() {
+        if let Some(slot) = slot.take() {
+            unsafe { pmu.stop(idx) };
+            let delta = pmu.read(idx).wrapping_sub(slot.prev) & pmu.counter_mask();
+            slot.event.count.fetch_add(delta, Ordering::SeqCst);
+        }
+    }
+}
+
+/// 为即将在cpu上运行的进程分配计数器
+fn perf_sched_in(pmu: &X86Pmu, ctx: &mut PerfCpuContext, cpu: usize, pid: Pid) {
+    let nr = pmu.num_counters().min(PERF_MAX_COUNTERS);
+    let mut idx = 0;
+    for event in PERF_EVENTS.lock_irqsave().iter() {
+        if idx >= nr {
+            break;
+        }
+        if !event.matches(cpu, pid) {
+            continue;
+        }
+        let flags = event.flags();
+        let initial = event.initial_count(pmu);
+        unsafe {
+            pmu.start(
+                idx,
+                event.hw_event,
+                !flags.contains(PerfAttrFlags::EXCLUDE_USER),
+                !flags.contains(PerfAttrFlags::EXCLUDE_KERNEL),
+                event.sampling(),
+                initial,
+            )
+        };
+        ctx.slots[idx] = Some(PerfSlot {
+            event: event.clone(),
+            prev: initial,
+        });
+        idx += 1;
+    }
+    PERF_CPU_ACTIVE[cpu].store(idx != 0, Ordering::SeqCst);
+}
+
+/// 重新分配当前cpu上的计数器
+///
+/// ## 参数
+///
+/// - `pid`：即将在当前cpu上运行的进程
+/// - `between`：在停止计数器之后、重新分配之前执行
+fn perf_resched(pid: Pid, between: impl FnOnce()) {
+    let pmu = match arch_pmu() {
+        Some(pmu) => pmu,
+        None => return,
+    };
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let cpu = smp_get_processor_id() as usize;
+    let mut ctx = PERF_CPU_CONTEXTS[cpu].lock();
+    perf_sched_out(pmu, &mut ctx);
+    between();
+    if PERF_EVENT_NR.load(Ordering::SeqCst) != 0 {
+        perf_sched_in(pmu, &mut ctx, cpu, pid);
+    } else {
+        PERF_CPU_ACTIVE[cpu].store(false, Ordering::SeqCst);
+    }
+    drop(ctx);
+    drop(irq_guard);
+}
+
+/// 事件发生变化之后，重新分配当前cpu上的计数器，使变化立即生效
+fn perf_resched_local(between: impl FnOnce()) {
+    perf_resched(ProcessManager::current_pcb().pid(), between);
+}
+
+/// 进程切换时调用，`next`是即将运行的进程
+#[inline(always)]
+pub fn perf_event_task_sched(next: Pid) {
+    let cpu = smp_get_processor_id() as usize;
+    if PERF_EVENT_NR.load(Ordering::Relaxed) == 0 && !PERF_CPU_ACTIVE[cpu].load(Ordering::Relaxed) {
+        return;
+    }
+    perf_resched(next, || {});
+}
+
+/// 加入一个新创建的事件
+fn perf_event_install(event: Arc<PerfEvent>) {
+    PERF_EVENTS.lock_irqsave().push(event);
+    PERF_EVENT_NR.fetch_add(1, Ordering::SeqCst);
+    perf_resched_local(|| {});
+}
+
+/// 移除一个事件。其他cpu上正在使用的计数器在它们下一次切换进程时释放
+fn perf_event_remove(event: &Arc<PerfEvent>) {
+    event.enabled.store(false, Ordering::SeqCst);
+    let mut events = PERF_EVENTS.lock_irqsave();
+    let len = events.len();
+    events.retain(|e| !Arc::ptr_eq(e, event));
+    let removed = len - events.len();
+    drop(events);
+    PERF_EVENT_NR.fetch_sub(removed, Ordering::SeqCst);
+    perf_resched_local(|| {});
+}
+
+/// 计数器溢出时，在NMI处理函数中调用
+///
+/// ## 参数
+///
+/// - `ip`：被中断的指令地址
+/// - `user`：被中断时是否处于用户态
+///
+/// ## 返回值
+///
+/// 当前cpu上有正在使用的计数器时返回true，表示NMI已经被处理
+pub fn perf_event_overflow(ip: usize, user: bool) -> bool {
+    let cpu = smp_get_processor_id() as usize;
+    if !PERF_CPU_ACTIVE[cpu].load(Ordering::SeqCst) {
+        return false;
+    }
+    let pmu = match arch_pmu() {
+        Some(pmu) => pmu,
+        None => return false,
+    };
+    // 被中断的代码可能正持有锁，此时计数器马上就会被重新分配，这次溢出直接忽略
+    let mut ctx = match PERF_CPU_CONTEXTS[cpu].try_lock() {
+        Ok(ctx) => ctx,
+        Err(_) => return true,
+    };
+    for (idx, slot) in ctx.slots.iter_mut().enumerate() {
+        let slot = match slot {
+            Some(slot) if slot.event.sampling() => slot,
+            _ => continue,
+        };
+        if !pmu.overflowed(idx) {
+            continue;
+        }
+        let delta = pmu.read(idx).wrapping_sub(slot.prev) & pmu.counter_mask();
+        slot.event.count.fetch_add(delta, Ordering::SeqCst);
+        slot.prev = slot.event.initial_count(pmu);
+        unsafe { pmu.write(idx, slot.prev) };
+        slot.event.output_sample(ip, user, cpu);
+    }
+    return true;
+}
@@ -0,0 +1,283 @@
+use core::{
+    any::Any,
+    mem::size_of,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+use crate::{
+    arch::{pmu::arch_pmu, MMArch},
+    filesystem::{
+        anon_inode::anon_inode_fs,
+        vfs::{
+            core::generate_inode_id,
+            file::{File, FileMode},
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus, PollTable,
+        },
+    },
+    libs::spinlock::SpinLock,
+    mm::{
+        allocator::page_frame::{PageFrameCount, PhysPageFrame},
+        syscall::ProtFlags,
+        MemoryManagementArch,
+    },
+    process::{Pid, ProcessManager},
+    smp::core::smp_cpu_count,
+    syscall::{user_access::UserBufferReader, Syscall, SystemError},
+    time::TimeSpec,
+};
+
+use super::{
+    perf_event_install, perf_event_remove, PerfAttrFlags, PerfEvent, PerfEventAttr, PerfHwId,
+    PERF_SAMPLE_SUPPORTED, PERF_TYPE_HARDWARE,
+};
+
+/// 启用事件
+pub const PERF_EVENT_IOC_ENABLE: u32 = 0x2400;
+/// 关闭事件
+pub const PERF_EVENT_IOC_DISABLE: u32 = 0x2401;
+/// 把计数清零
+pub const PERF_EVENT_IOC_RESET: u32 = 0x2403;
+
+/// 为文件描述符设置close-on-exec
+const PERF_FLAG_FD_CLOEXEC: usize = 1 << 3;
+
+/// perf_event_open返回的文件描述符对应的inode
+#[derive(Debug)]
+pub struct PerfEventInode {
+    event: Arc<PerfEvent>,
+    /// 打开这个inode的文件的数量，全部关闭时移除事件
+    opened: AtomicUsize,
+    metadata: Metadata,
+}
+
+impl PerfEventInode {
+    fn new(event: Arc<PerfEvent>) -> Arc<Self> {
+        return Arc::new(Self {
+            event,
+            opened: AtomicUsize::new(0),
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                // 读取时不使用偏移量，可以一直读下去
+                size: i64::MAX,
+                blk_size: 0,
+                blocks: 0,
+                atime: TimeSpec::default(),
+                mtime: TimeSpec::default(),
+                ctime: TimeSpec::default(),
+                file_type: FileType::File,
+                mode: ModeType::from_bits_truncate(0o600),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: 0,
+            },
+        });
+    }
+}
+
+impl IndexNode for PerfEventInode {
+    fn open(&self, _data: &mut FilePrivateData, _mode: &FileMode) -> Result<(), SystemError> {
+        self.opened.fetch_add(1, Ordering::SeqCst);
+        return Ok(());
+    }
+
+    fn close(&self, _data: &mut FilePrivateData) -> Result<(), SystemError> {
+        if self.opened.fetch_sub(1, Ordering::SeqCst) == 1 {
+            perf_event_remove(&self.event);
+        }
+        return Ok(());
+    }
+
+    /// @brief 读取事件的计数，不使用偏移量
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        if len < size_of::<u64>() {
+            return Err(SystemError::ENOSPC);
+        }
+        let count = self.event.read_count();
+        buf[..size_of::<u64>()].copy_from_slice(&count.to_ne_bytes());
+        return Ok(size_of::<u64>());
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: &mut FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.clone());
+    }
+
+    fn ioctl(&self, cmd: u32, _data: usize) -> Result<usize, SystemError> {
+        match cmd {
+            PERF_EVENT_IOC_ENABLE => self.event.set_enabled(true),
+            PERF_EVENT_IOC_DISABLE => self.event.set_enabled(false),
+            PERF_EVENT_IOC_RESET => self.event.reset(),
+            _ => return Err(SystemError::ENOTTY),
+        }
+        return Ok(0);
+    }
+
+    /// @brief 映射采样缓冲区：1个元数据页加上2^n个数据页，偏移量必须为0
+    fn mmap_frames(
+        &self,
+        offset: usize,
+        count: PageFrameCount,
+        _prot_flags: ProtFlags,
+    ) -> Result<(Vec<PhysPageFrame>, Arc<dyn Any + Send + Sync>), SystemError> {
+        if offset != 0 || count.data() < 2 {
+            return Err(SystemError::EINVAL);
+        }
+        let buffer = self.event.buffer(count.data() - 1)?;
+        let frames = buffer.pages().to_vec();
+        let backing: Arc<dyn Any + Send + Sync> = buffer;
+        return Ok((frames, backing));
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return anon_inode_fs();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        return Err(SystemError::ENOTDIR);
+    }
+}
+
+/// 从用户空间读取`perf_event_attr`
+///
+/// 用户程序的结构体可能比内核的更新。多出来的部分全为0时可以忽略，否则返回E2BIG
+fn perf_copy_attr(attr: *const PerfEventAttr) -> Result<PerfEventAttr, SystemError> {
+    let reader = UserBufferReader::new(attr, size_of::<u32>() * 2, true)?;
+    let mut size = 0u32;
+    reader.copy_one_from_user(&mut size, size_of::<u32>())?;
+    let size = match size as usize {
+        0 => size_of::<PerfEventAttr>(),
+        size => size,
+    };
+    if size < size_of::<PerfEventAttr>() || size > MMArch::PAGE_SIZE {
+        return Err(SystemError::E2BIG);
+    }
+    let reader = UserBufferReader::new(attr, size, true)?;
+    if size > size_of::<PerfEventAttr>() {
+        let mut extra = vec![0u8; size - size_of::<PerfEventAttr>()];
+        reader.copy_from_user(&mut extra, size_of::<PerfEventAttr>())?;
+        if extra.iter().any(|b| *b != 0) {
+            return Err(SystemError::E2BIG);
+        }
+    }
+    let mut attr = PerfEventAttr::default();
+    reader.copy_one_from_user(&mut attr, 0)?;
+    attr.size = size_of::<PerfEventAttr>() as u32;
+    return Ok(attr);
+}
+
+impl Syscall {
+    /// ## perf_event_open系统调用
+    ///
+    /// 只支持通用的硬件事件(PERF_TYPE_HARDWARE)，不支持事件组与子进程继承
+    ///
+    /// ## 参数
+    ///
+    /// - `attr`：事件的属性
+    /// - `pid`：要统计的进程。0表示当前进程，-1表示cpu上的所有进程
+    /// - `cpu`：要统计的cpu，-1表示所有cpu。`pid`与`cpu`不能同时为-1
+    /// - `group_fd`：必须为-1
+    /// - `flags`：只支持PERF_FLAG_FD_CLOEXEC
+    ///
+    /// ## 返回值
+    ///
+    /// 成功时返回事件的文件描述符
+    pub fn perf_event_open(
+        attr: *const PerfEventAttr,
+        pid: i32,
+        cpu: i32,
+        group_fd: i32,
+        flags: usize,
+    ) -> Result<usize, SystemError> {
+        let attr = perf_copy_attr(attr)?;
+        if group_fd != -1 || flags & !PERF_FLAG_FD_CLOEXEC != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if attr.type_ != PERF_TYPE_HARDWARE {
+            return Err(SystemError::ENOENT);
+        }
+        let attr_flags = PerfAttrFlags::from_bits_truncate(attr.flags);
+        if attr_flags.intersects(PerfAttrFlags::INHERIT | PerfAttrFlags::FREQ)
+            || attr.sample_type & !PERF_SAMPLE_SUPPORTED != 0
+            || attr.read_format != 0
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pmu = arch_pmu().ok_or(SystemError::ENODEV)?;
+        let hw_event = PerfHwId::from_config(attr.config)
+            .and_then(|id| pmu.hw_event(id))
+            .ok_or(SystemError::ENOENT)?;
+        if attr.sample_period > pmu.max_period() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pid = match pid {
+            -1 => None,
+            0 => Some(ProcessManager::current_pcb().pid()),
+            pid if pid > 0 => {
+                let pcb =
+                    ProcessManager::find_vpid(Pid::new(pid as usize)).ok_or(SystemError::ESRCH)?;
+                Some(pcb.pid())
+            }
+            _ => return Err(SystemError::EINVAL),
+        };
+        let cpu = match cpu {
+            -1 => None,
+            cpu if cpu >= 0 && (cpu as usize) < smp_cpu_count() => Some(cpu as usize),
+            _ => return Err(SystemError::EINVAL),
+        };
+        if pid.is_none() && cpu.is_none() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let event = Arc::new(PerfEvent {
+            attr,
+            hw_event,
+            pid,
+            cpu,
+            enabled: AtomicBool::new(!attr_flags.contains(PerfAttrFlags::DISABLED)),
+            count: AtomicU64::new(0),
+            buffer: SpinLock::new(None),
+        });
+
+        let mut file = File::new(PerfEventInode::new(event.clone()), FileMode::O_RDONLY)?;
+        if flags & PERF_FLAG_FD_CLOEXEC != 0 {
+            file.set_close_on_exec(true);
+        }
+        perf_event_install(event);
+        let r = ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(file, None)
+            .map(|fd| fd as usize);
+        return r;
+    }
+}
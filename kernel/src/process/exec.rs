@@ -3,6 +3,7 @@ use core::{fmt::Debug, ptr::null};
 use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 
 use crate::{
+    arch::mm::UserAccessGuard,
     driver::base::block::SeekFrom,
     filesystem::vfs::{
         file::{File, FileMode},
@@ -19,6 +20,12 @@ use crate::{
 /// 系统支持的所有二进制文件加载器的列表
 const BINARY_LOADERS: [&'static dyn BinaryLoader; 1] = [&ELF_LOADER];
 
+/// execve的参数和环境变量(包括字符串的结尾和指针)的总大小的上限
+///
+/// 参数在持有地址空间的锁时被写到用户栈上，这时不能通过缺页异常扩展用户栈，
+/// 因此这个值必须小于预先分配的用户栈的大小(`UserStack::INITIAL_STACK_SIZE`)
+pub const ARG_MAX: usize = 128 * 1024;
+
 pub trait BinaryLoader: 'static + Debug {
     /// 检查二进制文件是否为当前加载器支持的格式
     fn probe(self: &'static Self, param: &ExecParam, buf: &[u8]) -> Result<(), ExecError>;
@@ -35,16 +42,25 @@ pub trait BinaryLoader: 'static + Debug {
 pub struct BinaryLoaderResult {
     /// 程序入口地址
     entry_point: VirtAddr,
+    /// 初始线程的线程指针，程序没有线程本地存储时为None
+    thread_pointer: Option<VirtAddr>,
 }
 
 impl BinaryLoaderResult {
-    pub fn new(entry_point: VirtAddr) -> Self {
-        Self { entry_point }
+    pub fn new(entry_point: VirtAddr, thread_pointer: Option<VirtAddr>) -> Self {
+        Self {
+            entry_point,
+            thread_pointer,
+        }
     }
 
     pub fn entry_point(&self) -> VirtAddr {
         self.entry_point
     }
+
+    pub fn thread_pointer(&self) -> Option<VirtAddr> {
+        self.thread_pointer
+    }
 }
 
 #[allow(dead_code)]
@@ -273,8 +289,10 @@ impl ProcInitInfo {
         sp -= slice.len() * core::mem::size_of::<T>();
         sp -= sp.data() % core::mem::align_of::<T>();
 
+        let guard = UserAccessGuard::new();
         unsafe { core::slice::from_raw_parts_mut(sp.data() as *mut T, slice.len()) }
             .copy_from_slice(slice);
+        drop(guard);
         unsafe {
             ustack.set_sp(sp);
         }
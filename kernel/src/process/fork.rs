@@ -28,6 +28,29 @@ bitflags! {
         const CLONE_THREAD = (1 << 5);
         /// 共享打开的文件
         const CLONE_FILES = (1 << 6);
+        /// 为子进程设置线程本地存储(TLS)的线程指针(与Linux的取值相同)
+        const CLONE_SETTLS = 0x0008_0000;
+        /// 为新进程创建新的挂载命名空间(与Linux的取值相同)
+        const CLONE_NEWNS = 0x0002_0000;
+        /// 为新进程创建新的PID命名空间(与Linux的取值相同)
+        const CLONE_NEWPID = 0x2000_0000;
+    }
+}
+
+/// 创建子进程时的参数
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/linux/sched/task.h
+#[derive(Debug, Clone, Copy)]
+pub struct KernelCloneArgs {
+    /// 进程克隆标志
+    pub flags: CloneFlags,
+    /// 子进程的线程指针，仅在设置了`CLONE_SETTLS`时有效
+    pub tls: usize,
+}
+
+impl KernelCloneArgs {
+    pub fn new(flags: CloneFlags) -> Self {
+        return Self { flags, tls: 0 };
     }
 }
 
@@ -51,10 +74,33 @@ impl ProcessManager {
         current_trapframe: &mut TrapFrame,
         clone_flags: CloneFlags,
     ) -> Result<Pid, SystemError> {
+        return Self::kernel_clone(current_trapframe, KernelCloneArgs::new(clone_flags));
+    }
+
+    /// 按照给定的参数创建一个新进程
+    ///
+    /// ## 参数
+    ///
+    /// - `current_trapframe`: 当前进程的trapframe
+    /// - `clone_args`: 克隆标志，以及标志所需的参数
+    ///
+    /// ## 返回值
+    ///
+    /// - 成功：返回新进程的pid
+    /// - 失败：返回Err(SystemError)，fork失败的话，子线程不会执行。
+    pub fn kernel_clone(
+        current_trapframe: &mut TrapFrame,
+        clone_args: KernelCloneArgs,
+    ) -> Result<Pid, SystemError> {
+        let clone_flags = clone_args.flags;
         let current_pcb = ProcessManager::current_pcb();
         let new_kstack = KernelStack::new()?;
         let name = current_pcb.basic().name().to_string();
-        let pcb = ProcessControlBlock::new(name, new_kstack);
+        // 继承父进程的命名空间，或者按照克隆标志创建新的命名空间
+        let nsproxy = current_pcb
+            .nsproxy()
+            .copy(&clone_flags, current_pcb.pid_ns())?;
+        let pcb = ProcessControlBlock::new(name, new_kstack, nsproxy)?;
 
         // 克隆架构相关信息
         *pcb.arch_info() = current_pcb.arch_info_irqsave().clone();
@@ -72,6 +118,9 @@ impl ProcessManager {
             )
         });
 
+        // 子进程继承seccomp模式与过滤器
+        *pcb.seccomp() = current_pcb.seccomp().clone();
+
         // 拷贝用户地址空间
         ProcessManager::copy_mm(&clone_flags, &current_pcb, &pcb).unwrap_or_else(|e| {
             panic!(
@@ -97,7 +146,7 @@ impl ProcessManager {
         });
 
         // 拷贝线程
-        ProcessManager::copy_thread(&clone_flags, &current_pcb, &pcb, &current_trapframe).unwrap_or_else(|e| {
+        ProcessManager::copy_thread(&clone_args, &current_pcb, &pcb, &current_trapframe).unwrap_or_else(|e| {
             panic!(
                 "fork: Failed to copy thread from current process, current pid: [{:?}], new pid: [{:?}]. Error: {:?}",
                 current_pcb.pid(), pcb.pid(), e
@@ -0,0 +1,131 @@
+//! 进程的冻结
+//!
+//! 系统挂起之前冻结所有的用户进程，让它们在设备睡眠期间不再运行。
+//!
+//! 冻结开始之后，用户进程在返回用户态之前进入"冰箱"睡眠，直到被解冻。在内核中睡眠的进程被看作已经冻结：
+//! 它被唤醒之后，同样会在返回用户态之前进入冰箱。内核线程与带有`NOFREEZE`标志的进程不会被冻结。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/freezer.c
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/power/process.c
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    arch::CurrentIrqArch,
+    exception::InterruptArch,
+    kerror, kinfo,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    syscall::SystemError,
+    time::{sleep::nanosleep, TimeSpec},
+};
+
+use super::{Pid, ProcessControlBlock, ProcessFlags, ProcessManager};
+
+/// 等待所有进程被冻结的最长时间(ms)，与Linux的freeze_timeout_msecs相同
+const FREEZE_TIMEOUT_MS: i64 = 20000;
+/// 检查进程是否都已冻结的间隔(ms)
+const FREEZE_POLL_MS: i64 = 10;
+
+/// 系统是否正在冻结进程
+static SYSTEM_FREEZING: AtomicBool = AtomicBool::new(false);
+/// 修改`SYSTEM_FREEZING`以及进入冰箱时持有，防止进入冰箱的进程错过解冻时的唤醒
+static FREEZER_LOCK: SpinLock<()> = SpinLock::new(());
+/// 被冻结的进程在这里睡眠
+static REFRIGERATOR_WAIT_QUEUE: WaitQueue = WaitQueue::INIT;
+
+/// 进程是否可以被冻结
+fn freezable(pcb: &Arc<ProcessControlBlock>) -> bool {
+    return !pcb
+        .flags()
+        .intersects(ProcessFlags::KTHREAD | ProcessFlags::NOFREEZE);
+}
+
+/// 在返回用户态之前调用：系统正在冻结进程时，让当前进程进入冰箱，直到被解冻
+///
+/// 调用时中断必须已经关闭，返回时中断仍然是关闭的
+pub fn try_to_freeze() {
+    if !SYSTEM_FREEZING.load(Ordering::SeqCst) {
+        return;
+    }
+    let pcb = ProcessManager::current_pcb();
+    if !freezable(&pcb) {
+        return;
+    }
+
+    unsafe { CurrentIrqArch::interrupt_enable() };
+    pcb.flags().insert(ProcessFlags::FROZEN);
+    loop {
+        let guard = FREEZER_LOCK.lock_irqsave();
+        if !SYSTEM_FREEZING.load(Ordering::SeqCst) {
+            break;
+        }
+        REFRIGERATOR_WAIT_QUEUE.sleep_uninterruptible_unlock_spinlock(guard);
+    }
+    pcb.flags().remove(ProcessFlags::FROZEN);
+    unsafe { CurrentIrqArch::interrupt_disable() };
+}
+
+/// 获取还没有被冻结的用户进程
+fn unfrozen_processes() -> Vec<Pid> {
+    let current = ProcessManager::current_pcb().pid();
+    return ProcessManager::get_all_pids()
+        .into_iter()
+        .filter(|pid| *pid != current && *pid != Pid::new(0))
+        .filter(|pid| {
+            let pcb = match ProcessManager::find(*pid) {
+                Some(pcb) => pcb,
+                None => return false,
+            };
+            if !freezable(&pcb) || pcb.flags().contains(ProcessFlags::FROZEN) {
+                return false;
+            }
+            let state = pcb.sched_info().state();
+            return !(state.is_blocked() || state.is_stopped() || state.is_exited());
+        })
+        .collect();
+}
+
+/// 冻结除当前进程之外的所有用户进程
+///
+/// ## 错误
+///
+/// - `EBUSY`：超时之后仍有进程没有被冻结，此时已经冻结的进程会被解冻
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/power/process.c?fi=freeze_processes#freeze_processes
+pub fn freeze_processes() -> Result<(), SystemError> {
+    kinfo!("Freezing user space processes ...");
+    {
+        let _guard = FREEZER_LOCK.lock_irqsave();
+        SYSTEM_FREEZING.store(true, Ordering::SeqCst);
+    }
+
+    for _ in 0..(FREEZE_TIMEOUT_MS / FREEZE_POLL_MS) {
+        if unfrozen_processes().is_empty() {
+            kinfo!("Freezing user space processes completed.");
+            return Ok(());
+        }
+        nanosleep(TimeSpec::new(0, FREEZE_POLL_MS * 1000000)).ok();
+    }
+
+    kerror!(
+        "Freezing user space processes failed after {} ms, processes refusing to freeze: {:?}",
+        FREEZE_TIMEOUT_MS,
+        unfrozen_processes()
+    );
+    thaw_processes();
+    return Err(SystemError::EBUSY);
+}
+
+/// 解冻所有被冻结的进程
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/power/process.c?fi=thaw_processes#thaw_processes
+pub fn thaw_processes() {
+    {
+        let _guard = FREEZER_LOCK.lock_irqsave();
+        SYSTEM_FREEZING.store(false, Ordering::SeqCst);
+    }
+    REFRIGERATOR_WAIT_QUEUE.wakeup_all(None);
+    kinfo!("Restarting user space processes.");
+}
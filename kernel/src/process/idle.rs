@@ -6,7 +6,11 @@ use core::{
 use alloc::{sync::Arc, vec::Vec};
 
 use crate::{
-    mm::{percpu::PerCpu, VirtAddr, INITIAL_PROCESS_ADDRESS_SPACE},
+    mm::{
+        numa::{cpu_to_node, numa_prefer_node},
+        percpu::PerCpu,
+        VirtAddr, INITIAL_PROCESS_ADDRESS_SPACE,
+    },
     process::KernelStack,
     smp::core::smp_get_processor_id,
 };
@@ -40,6 +44,8 @@ impl ProcessManager {
                 unsafe { KernelStack::from_existed(stack_ptr) }
                     .expect("Failed to create kernel stack struct for BSP.")
             } else {
+                // AP的idle进程的栈从AP所在的NUMA节点分配
+                let _node = numa_prefer_node(cpu_to_node(i));
                 KernelStack::new().unwrap_or_else(|e| {
                     panic!("Failed to create kernel stack struct for AP {}: {:?}", i, e)
                 })
@@ -70,6 +76,20 @@ impl ProcessManager {
     fn stack_ptr() -> VirtAddr {
         #[cfg(target_arch = "x86_64")]
         return VirtAddr::new(x86::current::registers::rsp() as usize);
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            let sp: usize;
+            unsafe { core::arch::asm!("mv {}, sp", out(reg) sp, options(nomem, nostack)) };
+            return VirtAddr::new(sp);
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            let sp: usize;
+            unsafe { core::arch::asm!("mov {}, sp", out(reg) sp, options(nomem, nostack)) };
+            return VirtAddr::new(sp);
+        }
     }
 
     /// 获取idle进程数组的引用
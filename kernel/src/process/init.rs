@@ -4,33 +4,96 @@ use alloc::string::String;
 
 use crate::{
     arch::process::arch_switch_to_user,
+    crypto::crypto_init,
     driver::{
-        disk::ahci::ahci_init, net::e1000e::e1000e::e1000e_init, virtio::virtio::virtio_probe,
+        acpi::{ac::acpi_ac_init, battery::acpi_battery_init},
+        base::{
+            block::writeback::writeback_init,
+            device::{dd::device_probe_worker_init, driver::driver_manager},
+        },
+        disk::ahci::ahci_init,
+        iommu::iommu_init,
+        md::dm::dm_init,
+        net::e1000e::e1000e::e1000e_init,
+        usb::usb_init,
+        virtio::virtio::virtio_probe,
     },
-    filesystem::vfs::core::mount_root_fs,
+    filesystem::vfs::core::{mount_root_fs, root_is_nfs},
     kdebug, kerror,
+    ktest::{ktest_init, ktest_provide},
     net::net_core::net_init,
-    process::{kthread::KernelThreadMechanism, process::stdio_init},
+    process::{kthread::KernelThreadMechanism, process::stdio_init, suspend::suspend_init},
 };
 
 pub fn initial_kernel_thread() -> i32 {
     KernelThreadMechanism::init_stage2();
+    device_probe_worker_init().expect("Failed to start probe worker");
     // 由于目前加锁，速度过慢，所以先不开启双缓冲
     // scm_enable_double_buffer().expect("Failed to enable double buffer");
     stdio_init().expect("Failed to initialize stdio");
 
-    ahci_init().expect("Failed to initialize AHCI");
+    iommu_init().unwrap_or_else(|err| {
+        kdebug!("IOMMU not enabled: {:?}", err);
+    });
+
+    writeback_init().expect("Failed to start writeback thread");
+    crypto_init();
+
+    // 没有AHCI磁盘时，根文件系统可以位于USB磁盘上
+    match ahci_init() {
+        Ok(_) => ktest_provide("ahci"),
+        Err(err) => kdebug!("AHCI not initialized: {:?}", err),
+    }
+
+    acpi_ac_init().unwrap_or_else(|err| {
+        kdebug!("ACPI AC adapter not initialized: {:?}", err);
+    });
+    acpi_battery_init().unwrap_or_else(|err| {
+        kdebug!("ACPI battery not initialized: {:?}", err);
+    });
+
+    #[cfg(feature = "blk_bench")]
+    crate::driver::base::block::bench::blk_bench_init();
 
-    mount_root_fs().expect("Failed to mount root fs");
+    usb_init().unwrap_or_else(|err| {
+        kdebug!("USB not initialized: {:?}", err);
+    });
+
+    // 根文件系统所在的磁盘可能正在被异步探测
+    driver_manager().wait_for_device_probe();
+    // NFS根文件系统需要在网络初始化之后挂载
+    if !root_is_nfs() {
+        mount_root_fs().expect("Failed to mount root fs");
+    }
+    dm_init().unwrap_or_else(|err| {
+        kerror!("Failed to initialize device mapper: {:?}", err);
+    });
 
     virtio_probe();
     e1000e_init();
-    net_init().unwrap_or_else(|err| {
-        kerror!("Failed to initialize network: {:?}", err);
+    match net_init() {
+        Ok(_) => ktest_provide("net"),
+        Err(err) => kerror!("Failed to initialize network: {:?}", err),
+    }
+    if root_is_nfs() {
+        mount_root_fs().expect("Failed to mount root fs");
+    }
+    ktest_provide("rootfs");
+
+    suspend_init().unwrap_or_else(|err| {
+        kerror!("Failed to initialize suspend: {:?}", err);
+    });
+
+    ktest_init().unwrap_or_else(|err| {
+        kerror!("Failed to start kernel tests: {:?}", err);
     });
 
     kdebug!("initial kernel thread done.");
 
+    // 初始化已经完成，内核代码不再需要被修改
+    #[cfg(target_arch = "x86_64")]
+    crate::arch::mm::mark_rodata_ro();
+
     switch_to_user();
 
     loop {}
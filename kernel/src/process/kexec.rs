@@ -0,0 +1,425 @@
+//! kexec：不经过固件，直接从当前内核启动一个新内核
+//!
+//! 新内核必须是带有multiboot2头的ELF64文件，按照程序头中的物理地址加载。
+//! 加载时先把新内核复制到暂存页中，执行时再由跳板复制到目标地址，
+//! 因此暂存页、页列表、启动信息与控制页都不能与目标地址重叠，并且都位于4GB以下，
+//! 以便在关闭分页之后访问。
+//!
+//! 传给新内核的multiboot2启动信息复制自当前内核启动时的启动信息（内存布局、帧缓冲区、ACPI等），
+//! 只替换其中的命令行。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/kexec_file.c
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
+use elf::{abi::PT_LOAD, endian::AnyEndian, file::Class, ElfBytes};
+
+use crate::{
+    arch::{
+        kexec::{machine_kexec, KEXEC_CONTROL_PAGES, KEXEC_ELF_MACHINE},
+        MMArch,
+    },
+    driver::{base::block::SeekFrom, pci::pci::PCI_DEVICE_LINKEDLIST},
+    kinfo,
+    libs::spinlock::SpinLock,
+    mm::{
+        allocator::page_frame::{
+            allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
+        },
+        MemoryManagementArch, PhysAddr,
+    },
+    syscall::{user_access::UserBufferReader, Syscall, SystemError},
+};
+
+use super::{reboot::kernel_shutdown_prepare, ProcessManager};
+
+extern "C" {
+    fn multiboot2_raw_info(size: *mut u32) -> *const u8;
+}
+
+/// 卸载已经加载的新内核
+pub const KEXEC_FILE_UNLOAD: usize = 0x1;
+/// 加载崩溃时使用的内核（暂不支持）
+pub const KEXEC_FILE_ON_CRASH: usize = 0x2;
+/// 不加载initramfs
+pub const KEXEC_FILE_NO_INITRAMFS: usize = 0x4;
+
+/// 命令行的最大长度，包括结尾的'\0'
+const KEXEC_CMDLINE_MAX: usize = 4096;
+/// 新内核文件的最大大小
+const KEXEC_KERNEL_MAX: usize = 256 * 1024 * 1024;
+/// 跳板关闭了分页，只能访问4GB以下的物理内存
+const KEXEC_ADDR_LIMIT: usize = 1 << 32;
+/// 分配到与目标地址重叠的页时，最多重试的次数
+const KEXEC_ALLOC_RETRIES: usize = 4096;
+
+/// multiboot2头的魔数
+const MULTIBOOT2_HEADER_MAGIC: u32 = 0xe852_50d6;
+/// multiboot2头必须位于文件的前32KB中
+const MULTIBOOT2_SEARCH: usize = 32768;
+/// 启动信息中的标签类型：结束
+const MULTIBOOT_TAG_TYPE_END: u32 = 0;
+/// 启动信息中的标签类型：命令行
+const MULTIBOOT_TAG_TYPE_CMDLINE: u32 = 1;
+/// 启动信息中的标签类型：引导程序加载的模块，kexec时不会保留它们的内存
+const MULTIBOOT_TAG_TYPE_MODULE: u32 = 3;
+
+/// 已经加载、等待执行的新内核
+static KEXEC_IMAGE: SpinLock<Option<KexecImage>> = SpinLock::new(None);
+
+/// 加载到内存中的新内核
+#[derive(Debug)]
+struct KexecImage {
+    /// 新内核的入口的物理地址
+    entry: u32,
+    /// 传给新内核的启动信息的物理地址
+    mbi: PhysAddr,
+    /// 页列表的物理地址
+    page_list: PhysAddr,
+    /// 页列表的项数
+    nr_pages: usize,
+    /// 体系结构相关的控制页
+    control_pages: Vec<PhysAddr>,
+    /// 所有分配的页，释放镜像时一起释放
+    allocations: Vec<(PhysAddr, PageFrameCount)>,
+}
+
+impl KexecImage {
+    fn new() -> Self {
+        return Self {
+            entry: 0,
+            mbi: PhysAddr::new(0),
+            page_list: PhysAddr::new(0),
+            nr_pages: 0,
+            control_pages: Vec::new(),
+            allocations: Vec::new(),
+        };
+    }
+
+    /// 分配连续的、已清零的物理页，它们位于4GB以下，且不与新内核的目标地址重叠
+    ///
+    /// ## 参数
+    ///
+    /// - `pages`：页数，会被向上对齐到2的幂
+    /// - `dests`：新内核的所有目标页的物理地址
+    fn alloc_pages(
+        &mut self,
+        pages: usize,
+        dests: &BTreeSet<usize>,
+    ) -> Result<PhysAddr, SystemError> {
+        let count = PageFrameCount::new(pages.next_power_of_two());
+        let size = count.data() * MMArch::PAGE_SIZE;
+        let mut rejected = Vec::new();
+        let mut result = Err(SystemError::ENOMEM);
+        for _ in 0..KEXEC_ALLOC_RETRIES {
+            let (paddr, _) = match unsafe { allocate_page_frames(count) } {
+                Some(r) => r,
+                None => break,
+            };
+            let range = paddr.data()..paddr.data() + size;
+            if range.end > KEXEC_ADDR_LIMIT || dests.range(range).next().is_some() {
+                rejected.push(paddr);
+                continue;
+            }
+            unsafe { MMArch::write_bytes(MMArch::phys_2_virt(paddr).unwrap(), 0, size) };
+            self.allocations.push((paddr, count));
+            result = Ok(paddr);
+            break;
+        }
+        for paddr in rejected {
+            unsafe { deallocate_page_frames(PhysPageFrame::new(paddr), count) };
+        }
+        return result;
+    }
+
+    /// 分配页并写入数据
+    fn alloc_with_data(
+        &mut self,
+        data: &[u8],
+        dests: &BTreeSet<usize>,
+    ) -> Result<PhysAddr, SystemError> {
+        let pages = (data.len() + MMArch::PAGE_SIZE - 1) / MMArch::PAGE_SIZE;
+        let paddr = self.alloc_pages(pages.max(1), dests)?;
+        unsafe {
+            let vaddr = MMArch::phys_2_virt(paddr).unwrap();
+            core::ptr::copy_nonoverlapping(data.as_ptr(), vaddr.data() as *mut u8, data.len());
+        }
+        return Ok(paddr);
+    }
+
+    /// 从内核文件与命令行构造镜像
+    ///
+    /// ## 参数
+    ///
+    /// - `kernel`：新内核的ELF文件
+    /// - `cmdline`：新内核的命令行，以'\0'结尾
+    fn load(kernel: &[u8], cmdline: &[u8]) -> Result<Self, SystemError> {
+        let elf = ElfBytes::<AnyEndian>::minimal_parse(kernel).map_err(|_| SystemError::ENOEXEC)?;
+        if elf.ehdr.class != Class::ELF64 || elf.ehdr.e_machine != KEXEC_ELF_MACHINE {
+            return Err(SystemError::ENOEXEC);
+        }
+        if elf.ehdr.e_entry as usize >= KEXEC_ADDR_LIMIT {
+            return Err(SystemError::ENOEXEC);
+        }
+        if !multiboot2_header_present(kernel) {
+            return Err(SystemError::ENOEXEC);
+        }
+
+        // 检查所有需要加载的段，收集它们占用的目标页
+        let mut segments = Vec::new();
+        let mut dests = BTreeSet::new();
+        for phdr in elf.segments().ok_or(SystemError::ENOEXEC)?.iter() {
+            if phdr.p_type != PT_LOAD || phdr.p_memsz == 0 {
+                continue;
+            }
+            let (paddr, offset) = (phdr.p_paddr as usize, phdr.p_offset as usize);
+            let (filesz, memsz) = (phdr.p_filesz as usize, phdr.p_memsz as usize);
+            if filesz > memsz
+                || offset
+                    .checked_add(filesz)
+                    .map_or(true, |end| end > kernel.len())
+                || paddr
+                    .checked_add(memsz)
+                    .map_or(true, |end| end > KEXEC_ADDR_LIMIT)
+            {
+                return Err(SystemError::ENOEXEC);
+            }
+            let first = paddr & !(MMArch::PAGE_SIZE - 1);
+            dests.extend((first..paddr + memsz).step_by(MMArch::PAGE_SIZE));
+            segments.push((paddr, &kernel[offset..offset + filesz]));
+        }
+        if segments.is_empty() {
+            return Err(SystemError::ENOEXEC);
+        }
+
+        let mut image = Self::new();
+        image.entry = elf.ehdr.e_entry as u32;
+
+        // 为每个目标页分配一个暂存页，多个段可能共用同一个目标页。段中超出文件内容的部分保持为0
+        let mut staging: BTreeMap<usize, PhysAddr> = BTreeMap::new();
+        for dest in dests.iter() {
+            let page = image.alloc_pages(1, &dests)?;
+            staging.insert(*dest, page);
+        }
+        for (paddr, data) in segments {
+            let mut done = 0;
+            while done < data.len() {
+                let dest = paddr + done;
+                let page_offset = dest % MMArch::PAGE_SIZE;
+                let len = (MMArch::PAGE_SIZE - page_offset).min(data.len() - done);
+                let page = staging[&(dest - page_offset)];
+                unsafe {
+                    let vaddr = MMArch::phys_2_virt(page).unwrap();
+                    core::ptr::copy_nonoverlapping(
+                        data[done..].as_ptr(),
+                        (vaddr.data() + page_offset) as *mut u8,
+                        len,
+                    );
+                }
+                done += len;
+            }
+        }
+
+        let mut page_list = Vec::with_capacity(staging.len() * 8);
+        for (dest, page) in staging.iter() {
+            page_list.extend_from_slice(&(*dest as u32).to_ne_bytes());
+            page_list.extend_from_slice(&(page.data() as u32).to_ne_bytes());
+        }
+        image.nr_pages = staging.len();
+        image.page_list = image.alloc_with_data(&page_list, &dests)?;
+
+        let mbi = build_multiboot2_info(cmdline);
+        image.mbi = image.alloc_with_data(&mbi, &dests)?;
+
+        for _ in 0..KEXEC_CONTROL_PAGES {
+            let page = image.alloc_pages(1, &dests)?;
+            image.control_pages.push(page);
+        }
+        return Ok(image);
+    }
+}
+
+impl Drop for KexecImage {
+    fn drop(&mut self) {
+        for (paddr, count) in self.allocations.iter() {
+            unsafe { deallocate_page_frames(PhysPageFrame::new(*paddr), *count) };
+        }
+    }
+}
+
+/// 检查文件的前32KB中是否有合法的multiboot2头
+fn multiboot2_header_present(kernel: &[u8]) -> bool {
+    let read_u32 = |off: usize| u32::from_ne_bytes(kernel[off..off + 4].try_into().unwrap());
+    let end = kernel.len().min(MULTIBOOT2_SEARCH);
+    // multiboot2头按8字节对齐，由魔数、架构、头的长度与校验和组成，四者之和为0
+    return (0..end.saturating_sub(15)).step_by(8).any(|off| {
+        read_u32(off) == MULTIBOOT2_HEADER_MAGIC
+            && (0..4)
+                .map(|i| read_u32(off + i * 4))
+                .fold(0u32, |sum, v| sum.wrapping_add(v))
+                == 0
+    });
+}
+
+/// 向启动信息中添加一个标签，标签按8字节对齐
+fn multiboot2_push_tag(mbi: &mut Vec<u8>, tag: &[u8]) {
+    mbi.extend_from_slice(tag);
+    mbi.resize((mbi.len() + 7) & !7, 0);
+}
+
+/// 构造传给新内核的multiboot2启动信息：复制当前内核的启动信息，替换其中的命令行
+fn build_multiboot2_info(cmdline: &[u8]) -> Vec<u8> {
+    let mut size = 0u32;
+    let raw = unsafe {
+        let ptr = multiboot2_raw_info(&mut size);
+        core::slice::from_raw_parts(ptr, size as usize)
+    };
+    let read_u32 = |off: usize| u32::from_ne_bytes(raw[off..off + 4].try_into().unwrap());
+
+    // 前8字节是启动信息的总大小与保留的0
+    let mut mbi = vec![0u8; 8];
+    let mut off = 8;
+    while off + 8 <= raw.len() {
+        let (type_, tag_size) = (read_u32(off), read_u32(off + 4) as usize);
+        if type_ == MULTIBOOT_TAG_TYPE_END || tag_size < 8 || off + tag_size > raw.len() {
+            break;
+        }
+        if type_ != MULTIBOOT_TAG_TYPE_CMDLINE && type_ != MULTIBOOT_TAG_TYPE_MODULE {
+            multiboot2_push_tag(&mut mbi, &raw[off..off + tag_size]);
+        }
+        off += (tag_size + 7) & !7;
+    }
+
+    let mut tag = Vec::with_capacity(8 + cmdline.len());
+    tag.extend_from_slice(&MULTIBOOT_TAG_TYPE_CMDLINE.to_ne_bytes());
+    tag.extend_from_slice(&((8 + cmdline.len()) as u32).to_ne_bytes());
+    tag.extend_from_slice(cmdline);
+    multiboot2_push_tag(&mut mbi, &tag);
+
+    let mut end = MULTIBOOT_TAG_TYPE_END.to_ne_bytes().to_vec();
+    end.extend_from_slice(&8u32.to_ne_bytes());
+    multiboot2_push_tag(&mut mbi, &end);
+
+    let total = mbi.len() as u32;
+    mbi[..4].copy_from_slice(&total.to_ne_bytes());
+    return mbi;
+}
+
+/// 读取整个内核文件
+fn kexec_read_file(fd: i32) -> Result<Vec<u8>, SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let mut file = file.lock_no_preempt();
+    let size = file.inode().metadata()?.size;
+    if size <= 0 {
+        return Err(SystemError::ENOEXEC);
+    }
+    if size as usize > KEXEC_KERNEL_MAX {
+        return Err(SystemError::EFBIG);
+    }
+
+    let size = size as usize;
+    let mut buf = vec![0u8; size];
+    file.lseek(SeekFrom::SeekSet(0))?;
+    let mut done = 0;
+    while done < size {
+        let len = file.read(size - done, &mut buf[done..])?;
+        if len == 0 {
+            break;
+        }
+        done += len;
+    }
+    buf.truncate(done);
+    return Ok(buf);
+}
+
+/// 执行已经加载的新内核
+///
+/// ## 返回值
+///
+/// 没有加载新内核时返回EINVAL，否则不会返回
+pub fn kernel_kexec() -> Result<usize, SystemError> {
+    let image = KEXEC_IMAGE.lock().take().ok_or(SystemError::EINVAL)?;
+    kernel_shutdown_prepare();
+
+    // 新内核会重新初始化所有设备，在此之前设备不能再向内存中写入数据
+    for device in PCI_DEVICE_LINKEDLIST.write().iter_mut() {
+        device.disable_master();
+    }
+
+    kinfo!("Starting new kernel");
+    unsafe {
+        machine_kexec(
+            &image.control_pages,
+            image.entry,
+            image.mbi.data() as u32,
+            image.page_list.data() as u32,
+            image.nr_pages as u32,
+        )
+    };
+}
+
+impl Syscall {
+    /// ## kexec_file_load系统调用
+    ///
+    /// 加载新内核，之后通过reboot系统调用的`RebootCommand::Kexec`命令执行。再次加载时替换之前加载的内核
+    ///
+    /// ## 参数
+    ///
+    /// - `kernel_fd`：新内核文件的文件描述符
+    /// - `_initrd_fd`：initramfs的文件描述符（暂不支持，必须设置`KEXEC_FILE_NO_INITRAMFS`）
+    /// - `cmdline_len`：命令行的长度，包括结尾的'\0'
+    /// - `cmdline_ptr`：新内核的命令行
+    /// - `flags`：`KEXEC_FILE_UNLOAD`表示卸载已经加载的内核
+    pub fn kexec_file_load(
+        kernel_fd: i32,
+        _initrd_fd: i32,
+        cmdline_len: usize,
+        cmdline_ptr: *const u8,
+        flags: usize,
+    ) -> Result<usize, SystemError> {
+        if flags & !(KEXEC_FILE_UNLOAD | KEXEC_FILE_NO_INITRAMFS) != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if flags & KEXEC_FILE_UNLOAD != 0 {
+            KEXEC_IMAGE.lock().take();
+            return Ok(0);
+        }
+        if flags & KEXEC_FILE_NO_INITRAMFS == 0 {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+
+        if cmdline_len > KEXEC_CMDLINE_MAX {
+            return Err(SystemError::EINVAL);
+        }
+        let cmdline = if cmdline_len == 0 {
+            vec![0u8]
+        } else {
+            let reader = UserBufferReader::new(cmdline_ptr, cmdline_len, true)?;
+            let mut cmdline = vec![0u8; cmdline_len];
+            reader.copy_from_user(&mut cmdline, 0)?;
+            if cmdline.last() != Some(&0) {
+                return Err(SystemError::EINVAL);
+            }
+            cmdline
+        };
+
+        let kernel = kexec_read_file(kernel_fd)?;
+        let image = KexecImage::load(&kernel, &cmdline)?;
+        kinfo!(
+            "kexec: loaded new kernel, entry {:#x}, {} pages",
+            image.entry,
+            image.nr_pages
+        );
+        // 旧的镜像在锁外释放
+        let old = KEXEC_IMAGE.lock().replace(image);
+        drop(old);
+        return Ok(0);
+    }
+}
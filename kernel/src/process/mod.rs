@@ -22,9 +22,12 @@ use crate::{
     exception::InterruptArch,
     filesystem::{
         procfs::procfs_unregister_pid,
-        vfs::{file::FileDescriptorVec, FileType},
+        vfs::{file::FileDescriptorVec, namespace::FsRoot, FileType},
+    },
+    ipc::{
+        signal_types::{SigInfo, SigPending, SignalStruct},
+        sysv::sem::exit_sem,
     },
-    ipc::signal_types::{SigInfo, SigPending, SignalStruct},
     kdebug, kinfo,
     libs::{
         align::AlignedBox,
@@ -41,19 +44,34 @@ use crate::{
     },
     smp::kick_cpu,
     syscall::{Syscall, SystemError},
+    time::posix_timer::ProcessTimers,
 };
 
-use self::kthread::WorkerPrivate;
+use self::{
+    kthread::WorkerPrivate,
+    namespace::{NsProxy, PidNamespace},
+    ptrace::{exit_ptrace, PtraceState},
+    resource::{default_rlimits, RLimit64, RLimitID, RLIM_NLIMITS},
+    seccomp::SeccompState,
+};
 
 pub mod abi;
 pub mod c_adapter;
 pub mod exec;
 pub mod fork;
+pub mod freezer;
 pub mod idle;
 pub mod init;
+pub mod kexec;
 pub mod kthread;
+pub mod namespace;
 pub mod pid;
 pub mod process;
+pub mod ptrace;
+pub mod reboot;
+pub mod resource;
+pub mod seccomp;
+pub mod suspend;
 pub mod syscall;
 
 /// 系统中所有进程的pcb
@@ -114,6 +132,12 @@ impl ProcessManager {
         kinfo!("Process Manager initialized.");
     }
 
+    /// 进程管理器是否已经初始化完成
+    #[inline(always)]
+    pub fn initialized() -> bool {
+        return unsafe { __PROCESS_MANAGEMENT_INIT_DONE };
+    }
+
     /// 获取当前进程的pcb
     pub fn current_pcb() -> Arc<ProcessControlBlock> {
         return ProcessControlBlock::arch_current_pcb();
@@ -148,6 +172,15 @@ impl ProcessManager {
         return ALL_PROCESS.lock().as_ref()?.get(&pid).cloned();
     }
 
+    /// 获取系统中所有进程的pid
+    pub fn get_all_pids() -> Vec<Pid> {
+        return ALL_PROCESS
+            .lock()
+            .as_ref()
+            .map(|map| map.keys().cloned().collect())
+            .unwrap_or_default();
+    }
+
     /// 向系统中添加一个进程的pcb
     ///
     /// ## 参数
@@ -269,6 +302,10 @@ impl ProcessManager {
     /// 当子进程退出后向父进程发送通知
     fn exit_notify() {
         let current = ProcessManager::current_pcb();
+        // PID命名空间的1号进程退出时，杀死命名空间中的其它进程
+        current.exit_pid_ns();
+        // 解除对子进程的跟踪，避免它们永远停下
+        exit_ptrace(&current);
         // 让INIT进程收养所有子进程
         if current.pid() != Pid(1) {
             unsafe {
@@ -299,9 +336,13 @@ impl ProcessManager {
     ///
     /// - `exit_code` : 进程的退出码
     pub fn exit(exit_code: usize) -> ! {
+        // 撤销进程的SEM_UNDO操作
+        exit_sem(ProcessManager::current_pcb().pid().data() as i32);
+
         // 关中断
         let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
         let pcb = ProcessManager::current_pcb();
+        pcb.posix_timers_irqsave().clear();
         pcb.sched_info
             .write()
             .set_state(ProcessState::Exited(exit_code));
@@ -374,7 +415,7 @@ impl ProcessManager {
 }
 
 /// 上下文切换的钩子函数,当这个函数return的时候,将会发生上下文切换
-pub unsafe extern "sysv64" fn switch_finish_hook() {
+pub unsafe extern "C" fn switch_finish_hook() {
     ProcessManager::switch_finish_hook();
 }
 
@@ -452,6 +493,32 @@ bitflags! {
         const SIGNALED = 1 << 6;
         /// 进程需要迁移到其他cpu上
         const NEED_MIGRATE = 1 << 7;
+        /// 进程的用户地址空间布局需要随机化
+        const RANDOMIZE = 1 << 8;
+        /// 进程及其子进程不能通过execve获得新的权限
+        const NO_NEW_PRIVS = 1 << 9;
+        /// 进程已经被冻结
+        const FROZEN = 1 << 10;
+    }
+}
+
+bitflags! {
+    /// 进程的执行域(personality)中的标志位，执行域的其余部分原样保存
+    ///
+    /// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/personality.h
+    pub struct Personality: u32 {
+        const UNAME26 = 0x0020000;
+        /// 禁止地址空间布局随机化
+        const ADDR_NO_RANDOMIZE = 0x0040000;
+        const FDPIC_FUNCPTRS = 0x0080000;
+        const MMAP_PAGE_ZERO = 0x0100000;
+        const ADDR_COMPAT_LAYOUT = 0x0200000;
+        const READ_IMPLIES_EXEC = 0x0400000;
+        const ADDR_LIMIT_32BIT = 0x0800000;
+        const SHORT_INODE = 0x1000000;
+        const WHOLE_SECONDS = 0x2000000;
+        const STICKY_TIMEOUTS = 0x4000000;
+        const ADDR_LIMIT_3GB = 0x8000000;
     }
 }
 
@@ -459,6 +526,12 @@ bitflags! {
 pub struct ProcessControlBlock {
     /// 当前进程的pid
     pid: Pid,
+    /// 当前进程所在的PID命名空间
+    pid_ns: Arc<PidNamespace>,
+    /// 当前进程在每一层PID命名空间中的pid，下标为命名空间的层数，第0项为全局pid
+    ns_pids: Vec<Pid>,
+    /// 当前进程使用的命名空间
+    nsproxy: RwLock<NsProxy>,
 
     basic: RwLock<ProcessBasicInfo>,
     /// 当前进程的自旋锁持有计数
@@ -478,6 +551,15 @@ pub struct ProcessControlBlock {
     /// 信号处理结构体
     sig_struct: SpinLock<SignalStruct>,
 
+    /// POSIX定时器和间隔定时器
+    posix_timers: SpinLock<ProcessTimers>,
+
+    /// 系统调用过滤器
+    seccomp: SpinLock<SeccompState>,
+
+    /// 进程被跟踪的状态
+    ptrace: SpinLock<PtraceState>,
+
     /// 父进程指针
     parent_pcb: RwLock<Weak<ProcessControlBlock>>,
 
@@ -495,12 +577,21 @@ impl ProcessControlBlock {
     ///
     /// - `name` : 进程的名字
     /// - `kstack` : 进程的内核栈
+    /// - `nsproxy` : 进程使用的命名空间，进程会被加入`nsproxy.pid_ns_for_children`
     ///
     /// ## 返回值
     ///
     /// 返回一个新的pcb
-    pub fn new(name: String, kstack: KernelStack) -> Arc<Self> {
-        return Self::do_create_pcb(name, kstack, false);
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOMEM`：进程要加入的PID命名空间的1号进程已经退出
+    pub fn new(
+        name: String,
+        kstack: KernelStack,
+        nsproxy: NsProxy,
+    ) -> Result<Arc<Self>, SystemError> {
+        return Self::do_create_pcb(name, kstack, false, nsproxy);
     }
 
     /// 创建一个新的idle进程
@@ -508,21 +599,69 @@ impl ProcessControlBlock {
     /// 请注意，这个函数只能在进程管理初始化的时候调用。
     pub fn new_idle(cpu_id: u32, kstack: KernelStack) -> Arc<Self> {
         let name = format!("idle-{}", cpu_id);
-        return Self::do_create_pcb(name, kstack, true);
+        return Self::do_create_pcb(name, kstack, true, NsProxy::init())
+            .expect("Failed to create idle pcb");
     }
 
-    fn do_create_pcb(name: String, kstack: KernelStack, is_idle: bool) -> Arc<Self> {
-        let (pid, ppid, cwd) = if is_idle {
-            (Pid(0), Pid(0), "/".to_string())
+    fn do_create_pcb(
+        name: String,
+        kstack: KernelStack,
+        is_idle: bool,
+        nsproxy: NsProxy,
+    ) -> Result<Arc<Self>, SystemError> {
+        let (pid, ppid, uid, cwd, fs_root, personality, rlimits) = if is_idle {
+            (
+                Pid(0),
+                Pid(0),
+                0,
+                "/".to_string(),
+                None,
+                0,
+                default_rlimits(),
+            )
         } else {
+            let parent = ProcessManager::current_pcb();
+            let parent_basic = parent.basic();
             (
                 Self::generate_pid(),
-                ProcessManager::current_pcb().pid(),
-                ProcessManager::current_pcb().basic().cwd(),
+                parent.pid(),
+                parent_basic.uid(),
+                parent_basic.cwd(),
+                parent_basic.fs_root(),
+                parent_basic.personality(),
+                parent_basic.rlimits,
             )
         };
+        // 子进程进入了新的挂载命名空间时，在新的挂载树中找到对应的根目录
+        let fs_root = match fs_root {
+            Some(root)
+                if !Arc::ptr_eq(
+                    &nsproxy.mnt_ns,
+                    &ProcessManager::current_pcb().nsproxy().mnt_ns,
+                ) =>
+            {
+                Some(root.in_namespace(&nsproxy.mnt_ns)?)
+            }
+            root => root,
+        };
+        let pid_ns = nsproxy.pid_ns_for_children.clone();
+        let ns_pids = if is_idle {
+            vec![pid]
+        } else {
+            pid_ns.alloc_pids(pid)?
+        };
 
-        let basic_info = ProcessBasicInfo::new(Pid(0), ppid, name, cwd, None);
+        let basic_info = ProcessBasicInfo::new(
+            Pid(0),
+            ppid,
+            uid,
+            name,
+            cwd,
+            fs_root,
+            personality,
+            rlimits,
+            None,
+        );
         let preempt_count = AtomicUsize::new(0);
         let flags = SpinLock::new(ProcessFlags::empty());
 
@@ -535,6 +674,9 @@ impl ProcessControlBlock {
 
         let pcb = Self {
             pid,
+            pid_ns,
+            ns_pids,
+            nsproxy: RwLock::new(nsproxy),
             basic: basic_info,
             preempt_count,
             flags,
@@ -544,6 +686,9 @@ impl ProcessControlBlock {
             arch_info,
             sig_info: RwLock::new(ProcessSignalInfo::default()),
             sig_struct: SpinLock::new(SignalStruct::default()),
+            posix_timers: SpinLock::new(ProcessTimers::default()),
+            seccomp: SpinLock::new(SeccompState::default()),
+            ptrace: SpinLock::new(PtraceState::default()),
             parent_pcb: RwLock::new(ppcb),
             children: RwLock::new(HashMap::new()),
             wait_queue: WaitQueue::INIT,
@@ -564,7 +709,7 @@ impl ProcessControlBlock {
             }
         }
 
-        return pcb;
+        return Ok(pcb);
     }
 
     /// 生成一个新的pid
@@ -696,9 +841,9 @@ impl ProcessControlBlock {
         return Some(socket);
     }
 
-    /// 当前进程退出时,让初始进程收养所有子进程
+    /// 当前进程退出时,让所在的PID命名空间的初始进程收养所有子进程
     unsafe fn adopt_childen(&self) -> Result<(), SystemError> {
-        match ProcessManager::find(Pid(1)) {
+        match self.find_child_reaper() {
             Some(init_pcb) => {
                 let mut childen_guard = self.children.write();
                 let mut init_childen_guard = init_pcb.children.write();
@@ -738,6 +883,35 @@ impl ProcessControlBlock {
     pub fn sig_struct_irq(&self) -> SpinLockGuard<SignalStruct> {
         self.sig_struct.lock_irqsave()
     }
+
+    /// 进程的定时器（会在时钟中断中访问，因此需要关中断）
+    pub fn posix_timers_irqsave(&self) -> SpinLockGuard<ProcessTimers> {
+        self.posix_timers.lock_irqsave()
+    }
+
+    /// 进程的seccomp模式与过滤器
+    pub fn seccomp(&self) -> SpinLockGuard<SeccompState> {
+        self.seccomp.lock()
+    }
+
+    /// 进程被跟踪的状态
+    pub fn ptrace(&self) -> SpinLockGuard<PtraceState> {
+        self.ptrace.lock_irqsave()
+    }
+
+    /// 进程所在的PID命名空间
+    pub fn pid_ns(&self) -> &Arc<PidNamespace> {
+        &self.pid_ns
+    }
+
+    /// 进程使用的命名空间
+    pub fn nsproxy(&self) -> RwLockReadGuard<NsProxy> {
+        self.nsproxy.read()
+    }
+
+    pub fn nsproxy_mut(&self) -> RwLockWriteGuard<NsProxy> {
+        self.nsproxy.write()
+    }
 }
 
 impl Drop for ProcessControlBlock {
@@ -750,6 +924,8 @@ impl Drop for ProcessControlBlock {
             ppcb.children.write().remove(&self.pid());
         }
 
+        self.pid_ns.free_pids(&self.ns_pids);
+
         unsafe { ProcessManager::release(self.pid()) };
     }
 }
@@ -765,9 +941,21 @@ pub struct ProcessBasicInfo {
     /// 进程的名字
     name: String,
 
-    /// 当前进程的工作目录
+    /// 进程的用户id。内核还没有完整的凭据模型，只区分root(0)与普通用户
+    uid: u32,
+
+    /// 当前进程的工作目录，是相对于进程的根目录的路径
     cwd: String,
 
+    /// 当前进程通过chroot设置的根目录，为None时是进程所在的挂载命名空间的根
+    fs_root: Option<FsRoot>,
+
+    /// 进程的执行域，execve之后保持不变
+    personality: u32,
+
+    /// 进程的资源限制
+    rlimits: [RLimit64; RLIM_NLIMITS],
+
     /// 用户地址空间
     user_vm: Option<Arc<AddressSpace>>,
 
@@ -776,19 +964,28 @@ pub struct ProcessBasicInfo {
 }
 
 impl ProcessBasicInfo {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         pgid: Pid,
         ppid: Pid,
+        uid: u32,
         name: String,
         cwd: String,
+        fs_root: Option<FsRoot>,
+        personality: u32,
+        rlimits: [RLimit64; RLIM_NLIMITS],
         user_vm: Option<Arc<AddressSpace>>,
     ) -> RwLock<Self> {
         let fd_table = Arc::new(RwLock::new(FileDescriptorVec::new()));
         return RwLock::new(Self {
             pgid,
             ppid,
+            uid,
             name,
             cwd,
+            fs_root,
+            personality,
+            rlimits,
             user_vm,
             fd_table: Some(fd_table),
         });
@@ -810,6 +1007,14 @@ impl ProcessBasicInfo {
         self.name = name;
     }
 
+    pub fn uid(&self) -> u32 {
+        return self.uid;
+    }
+
+    pub fn set_uid(&mut self, uid: u32) {
+        self.uid = uid;
+    }
+
     pub fn cwd(&self) -> String {
         return self.cwd.clone();
     }
@@ -817,6 +1022,30 @@ impl ProcessBasicInfo {
         return self.cwd = path;
     }
 
+    pub fn fs_root(&self) -> Option<FsRoot> {
+        return self.fs_root.clone();
+    }
+
+    pub fn set_fs_root(&mut self, fs_root: Option<FsRoot>) {
+        self.fs_root = fs_root;
+    }
+
+    pub fn personality(&self) -> u32 {
+        return self.personality;
+    }
+
+    pub fn set_personality(&mut self, personality: u32) {
+        self.personality = personality;
+    }
+
+    pub fn rlimit(&self, id: RLimitID) -> RLimit64 {
+        return self.rlimits[id as usize];
+    }
+
+    pub fn set_rlimit(&mut self, id: RLimitID, rlimit: RLimit64) {
+        self.rlimits[id as usize] = rlimit;
+    }
+
     pub fn user_vm(&self) -> Option<Arc<AddressSpace>> {
         return self.user_vm.clone();
     }
@@ -1084,6 +1313,21 @@ impl ProcessSignalInfo {
         &self.sig_shared_pending
     }
 
+    /// 判断是否有未被屏蔽的信号等待处理，用于在可被打断的等待中检查是否需要返回
+    pub fn has_pending_signal(&self) -> bool {
+        return self.sig_pending.next_signal(&self.sig_block) != Signal::INVALID
+            || self.sig_shared_pending.next_signal(&self.sig_block) != Signal::INVALID;
+    }
+
+    /// 判断进程是否已经被SIGKILL杀死，但还没有来得及处理这个信号
+    ///
+    /// SIGKILL不能被屏蔽，因此不需要检查sig_block
+    pub fn has_fatal_signal(&self) -> bool {
+        let kill = Signal::SIGKILL.into_sigset();
+        return self.sig_pending.signal().contains(kill)
+            || self.sig_shared_pending.signal().contains(kill);
+    }
+
     /// 从 pcb 的 siginfo中取出下一个要处理的信号，先处理线程信号，再处理进程信号
     ///
     /// ## 参数
@@ -0,0 +1,393 @@
+//! 进程的命名空间：PID命名空间与挂载命名空间
+//!
+//! 每个进程通过[`NsProxy`]引用它使用的命名空间。fork时子进程继承父进程的命名空间，
+//! clone、unshare可以通过CLONE_NEWPID、CLONE_NEWNS标志创建新的命名空间。
+//!
+//! PID命名空间可以嵌套。进程在它所在的命名空间以及所有的祖先命名空间中各有一个pid，
+//! 内核内部使用的是初始命名空间中的pid(全局pid)，系统调用与用户态交换pid时，按照调用者所在的命名空间进行转换。
+//! 新的PID命名空间中的第一个进程的pid为1，它收养命名空间中的孤儿进程。它退出时，命名空间中的其它进程都会被杀死，
+//! 并且之后不能再在这个命名空间中创建进程。
+//!
+//! 目前没有用户与权限的概念，因此创建命名空间不需要CAP_SYS_ADMIN。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/pid_namespace.c
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/nsproxy.c
+
+use alloc::{sync::Arc, vec::Vec};
+use hashbrown::HashMap;
+
+use crate::{
+    arch::{interrupt::TrapFrame, ipc::signal::Signal, MMArch},
+    filesystem::vfs::namespace::MntNamespace,
+    kwarn,
+    libs::spinlock::SpinLock,
+    mm::MemoryManagementArch,
+    syscall::{Syscall, SystemError},
+};
+
+use super::{
+    fork::{CloneFlags, KernelCloneArgs},
+    Pid, ProcessControlBlock, ProcessManager,
+};
+
+/// PID命名空间的最大嵌套层数，与Linux相同
+pub const MAX_PID_NS_LEVEL: usize = 32;
+
+/// clone的flags参数中，低8位是子进程退出时向父进程发送的信号
+const CSIGNAL: u64 = 0xff;
+
+lazy_static! {
+    /// 初始的命名空间，idle进程以及由它创建的进程都使用这些命名空间
+    static ref INIT_NSPROXY: NsProxy = NsProxy {
+        pid_ns_for_children: PidNamespace::new_init(),
+        mnt_ns: MntNamespace::new_init(),
+    };
+}
+
+/// 进程使用的命名空间
+#[derive(Debug, Clone)]
+pub struct NsProxy {
+    /// 子进程所在的PID命名空间。进程自己所在的PID命名空间在创建之后就不会改变，保存在pcb中
+    pub pid_ns_for_children: Arc<PidNamespace>,
+    /// 进程所在的挂载命名空间
+    pub mnt_ns: Arc<MntNamespace>,
+}
+
+impl NsProxy {
+    /// 获取初始的命名空间
+    pub fn init() -> Self {
+        return INIT_NSPROXY.clone();
+    }
+
+    /// 根据克隆标志，复制一份命名空间，需要的时候创建新的命名空间
+    ///
+    /// ## 参数
+    ///
+    /// - `clone_flags`：克隆标志，只有CLONE_NEWPID与CLONE_NEWNS会被处理
+    /// - `active_pid_ns`：当前进程所在的PID命名空间
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：要求创建新的PID命名空间，但是之前已经通过unshare为子进程创建过新的PID命名空间
+    /// - `ENOSPC`：PID命名空间的嵌套层数超过了限制
+    pub fn copy(
+        &self,
+        clone_flags: &CloneFlags,
+        active_pid_ns: &Arc<PidNamespace>,
+    ) -> Result<Self, SystemError> {
+        let mut new = self.clone();
+
+        if clone_flags.contains(CloneFlags::CLONE_NEWPID) {
+            if !Arc::ptr_eq(&self.pid_ns_for_children, active_pid_ns) {
+                return Err(SystemError::EINVAL);
+            }
+            new.pid_ns_for_children = PidNamespace::new_child(active_pid_ns)?;
+        }
+
+        if clone_flags.contains(CloneFlags::CLONE_NEWNS) {
+            new.mnt_ns = self.mnt_ns.copy()?;
+        }
+
+        return Ok(new);
+    }
+}
+
+/// PID命名空间
+#[derive(Debug)]
+pub struct PidNamespace {
+    /// 嵌套的层数，初始命名空间为0
+    level: usize,
+    /// 父命名空间
+    parent: Option<Arc<PidNamespace>>,
+    inner: SpinLock<InnerPidNamespace>,
+}
+
+#[derive(Debug)]
+struct InnerPidNamespace {
+    /// 下一个要分配的pid
+    next_pid: usize,
+    /// 命名空间中的pid到全局pid的映射
+    pids: HashMap<Pid, Pid>,
+    /// 命名空间的1号进程的全局pid
+    child_reaper: Option<Pid>,
+    /// 1号进程是否已经退出。退出之后不能再在命名空间中创建进程
+    dead: bool,
+}
+
+impl PidNamespace {
+    fn new(level: usize, parent: Option<Arc<PidNamespace>>) -> Arc<Self> {
+        return Arc::new(Self {
+            level,
+            parent,
+            inner: SpinLock::new(InnerPidNamespace {
+                next_pid: 1,
+                pids: HashMap::new(),
+                child_reaper: None,
+                dead: false,
+            }),
+        });
+    }
+
+    /// 创建初始的PID命名空间。初始命名空间中的pid就是全局pid
+    fn new_init() -> Arc<Self> {
+        return Self::new(0, None);
+    }
+
+    /// 创建一个子命名空间
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOSPC`：嵌套层数超过了[`MAX_PID_NS_LEVEL`]
+    pub fn new_child(parent: &Arc<Self>) -> Result<Arc<Self>, SystemError> {
+        let level = parent.level + 1;
+        if level > MAX_PID_NS_LEVEL {
+            return Err(SystemError::ENOSPC);
+        }
+        return Ok(Self::new(level, Some(parent.clone())));
+    }
+
+    /// 命名空间的嵌套层数，初始命名空间为0
+    pub fn level(&self) -> usize {
+        return self.level;
+    }
+
+    pub fn parent(&self) -> Option<&Arc<PidNamespace>> {
+        return self.parent.as_ref();
+    }
+
+    /// 命名空间的1号进程的全局pid。为None表示命名空间中还没有进程
+    pub fn child_reaper(&self) -> Option<Pid> {
+        if self.level == 0 {
+            return Some(Pid(1));
+        }
+        return self.inner.lock().child_reaper;
+    }
+
+    /// 把命名空间中的pid转换为全局pid
+    pub fn to_global(&self, pid: Pid) -> Option<Pid> {
+        if self.level == 0 {
+            return Some(pid);
+        }
+        return self.inner.lock().pids.get(&pid).cloned();
+    }
+
+    /// 为一个新的进程分配它在这个命名空间以及所有祖先命名空间中的pid
+    ///
+    /// ## 参数
+    ///
+    /// - `global_pid`：进程的全局pid
+    ///
+    /// ## 返回值
+    ///
+    /// 进程在每一层命名空间中的pid，下标为命名空间的层数，第0项为全局pid
+    ///
+    /// ## 错误
+    ///
+    /// - `ENOMEM`：某一层命名空间的1号进程已经退出
+    pub(super) fn alloc_pids(self: &Arc<Self>, global_pid: Pid) -> Result<Vec<Pid>, SystemError> {
+        let mut pids = vec![Pid(0); self.level + 1];
+        pids[0] = global_pid;
+
+        let mut ns = self;
+        while ns.level > 0 {
+            let mut inner = ns.inner.lock();
+            if inner.dead {
+                drop(inner);
+                self.free_pids(&pids);
+                return Err(SystemError::ENOMEM);
+            }
+            let pid = Pid(inner.next_pid);
+            inner.next_pid += 1;
+            if pid == Pid(1) {
+                inner.child_reaper = Some(global_pid);
+            }
+            inner.pids.insert(pid, global_pid);
+            pids[ns.level] = pid;
+            drop(inner);
+
+            ns = ns.parent.as_ref().unwrap();
+        }
+
+        return Ok(pids);
+    }
+
+    /// 释放进程在这个命名空间以及所有祖先命名空间中的pid
+    ///
+    /// ## 参数
+    ///
+    /// - `pids`：[`PidNamespace::alloc_pids`]返回的pid，为0的项表示还没有分配
+    pub(super) fn free_pids(&self, pids: &[Pid]) {
+        let mut ns = self;
+        while ns.level > 0 {
+            if pids[ns.level] != Pid(0) {
+                ns.inner.lock().pids.remove(&pids[ns.level]);
+            }
+            ns = ns.parent.as_ref().unwrap();
+        }
+    }
+
+    /// 命名空间的1号进程退出时，杀死命名空间中的其它进程，并且不再允许创建新的进程
+    fn zap_processes(&self) {
+        let mut inner = self.inner.lock();
+        inner.dead = true;
+        let reaper = inner.child_reaper;
+        let victims: Vec<Pid> = inner
+            .pids
+            .values()
+            .cloned()
+            .filter(|pid| Some(*pid) != reaper)
+            .collect();
+        drop(inner);
+
+        for pid in victims {
+            if Syscall::kill(pid, Signal::SIGKILL as i32).is_err() {
+                kwarn!("failed to kill {:?} when its pid namespace exits", pid);
+            }
+        }
+    }
+}
+
+impl ProcessControlBlock {
+    /// 获取进程在指定的PID命名空间中的pid
+    ///
+    /// ## 返回值
+    ///
+    /// 进程在这个命名空间中不可见(命名空间不是进程所在的命名空间，也不是它的祖先)时，返回None
+    pub fn pid_nr_ns(&self, ns: &PidNamespace) -> Option<Pid> {
+        if ns.level > self.pid_ns.level {
+            return None;
+        }
+        let mut cur = &self.pid_ns;
+        while cur.level > ns.level {
+            cur = cur.parent.as_ref().unwrap();
+        }
+        if !core::ptr::eq(cur.as_ref(), ns) {
+            return None;
+        }
+        return Some(self.ns_pids[ns.level]);
+    }
+
+    /// 获取进程在它自己所在的PID命名空间中的pid
+    pub fn vpid(&self) -> Pid {
+        return self.ns_pids[self.pid_ns.level];
+    }
+
+    /// 进程是否为某个非初始PID命名空间的1号进程
+    pub fn is_child_reaper(&self) -> bool {
+        return self.pid_ns.level > 0 && self.vpid() == Pid(1);
+    }
+
+    /// 找到收养当前进程的子进程的进程，也就是进程所在的PID命名空间的1号进程。
+    /// 如果当前进程就是1号进程，那么由上一层命名空间的1号进程收养
+    pub(super) fn find_child_reaper(&self) -> Option<Arc<ProcessControlBlock>> {
+        let mut ns = Some(&self.pid_ns);
+        while let Some(cur) = ns {
+            if let Some(reaper) = cur.child_reaper().filter(|pid| *pid != self.pid) {
+                if let Some(pcb) = ProcessManager::find(reaper) {
+                    return Some(pcb);
+                }
+            }
+            ns = cur.parent.as_ref();
+        }
+        return None;
+    }
+
+    /// 命名空间的1号进程退出时，杀死命名空间中的其它进程
+    pub(super) fn exit_pid_ns(&self) {
+        if self.is_child_reaper() {
+            self.pid_ns.zap_processes();
+        }
+    }
+}
+
+impl ProcessManager {
+    /// 根据当前进程所在的PID命名空间中的pid查找进程
+    pub fn find_vpid(pid: Pid) -> Option<Arc<ProcessControlBlock>> {
+        let global = ProcessManager::current_pcb().pid_ns().to_global(pid)?;
+        return ProcessManager::find(global);
+    }
+
+    /// 把全局pid转换为当前进程所在的PID命名空间中的pid
+    ///
+    /// ## 返回值
+    ///
+    /// 进程不存在，或者在当前进程所在的命名空间中不可见时，返回0
+    pub fn pid_vnr(pid: Pid) -> Pid {
+        if pid == Pid(0) {
+            return Pid(0);
+        }
+        let current = ProcessManager::current_pcb();
+        return ProcessManager::find(pid)
+            .and_then(|pcb| pcb.pid_nr_ns(current.pid_ns()))
+            .unwrap_or(Pid(0));
+    }
+}
+
+impl Syscall {
+    /// @brief 让当前进程脱离原来的命名空间，进入新创建的命名空间
+    ///
+    /// @param flags 要创建的命名空间，目前支持CLONE_NEWNS与CLONE_NEWPID。
+    /// CLONE_NEWPID不会改变当前进程所在的PID命名空间，而是让之后创建的子进程进入新的命名空间
+    ///
+    /// @return 成功时返回0
+    pub fn unshare(flags: usize) -> Result<usize, SystemError> {
+        let flags = CloneFlags::from_bits(flags as u32)
+            .filter(|f| (CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWPID).contains(*f))
+            .ok_or(SystemError::EINVAL)?;
+
+        let current = ProcessManager::current_pcb();
+        let new = current.nsproxy().copy(&flags, current.pid_ns())?;
+
+        // 进入新的挂载命名空间之后，在新的挂载树中找到与原来的根目录对应的目录
+        let fs_root = current.basic().fs_root();
+        if let Some(fs_root) = fs_root {
+            if !Arc::ptr_eq(&new.mnt_ns, &current.nsproxy().mnt_ns) {
+                let fs_root = fs_root.in_namespace(&new.mnt_ns)?;
+                current.basic_mut().set_fs_root(Some(fs_root));
+            }
+        }
+
+        *current.nsproxy_mut() = new;
+        return Ok(0);
+    }
+
+    /// @brief 创建子进程
+    ///
+    /// 目前只支持fork的语义，以及创建新命名空间的CLONE_NEWNS、CLONE_NEWPID标志，
+    /// 和设置子进程线程指针的CLONE_SETTLS标志
+    ///
+    /// @param flags 克隆标志，低8位是子进程退出时向父进程发送的信号，只能为0或SIGCHLD
+    /// @param newsp 子进程的用户栈，只能为0，表示使用与父进程相同的栈地址
+    /// @param tls 子进程的线程指针，只在设置了CLONE_SETTLS时使用
+    ///
+    /// @return 父进程中返回子进程在父进程所在的命名空间中的pid
+    pub fn clone(
+        frame: &mut TrapFrame,
+        flags: u64,
+        newsp: usize,
+        tls: usize,
+    ) -> Result<usize, SystemError> {
+        let exit_signal = flags & CSIGNAL;
+        if exit_signal != 0 && exit_signal != Signal::SIGCHLD as u64 {
+            return Err(SystemError::EINVAL);
+        }
+        if newsp != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let flags = u32::try_from(flags & !CSIGNAL)
+            .ok()
+            .and_then(CloneFlags::from_bits)
+            .filter(|f| {
+                (CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWPID | CloneFlags::CLONE_SETTLS)
+                    .contains(*f)
+            })
+            .ok_or(SystemError::EINVAL)?;
+        if flags.contains(CloneFlags::CLONE_SETTLS) && tls >= MMArch::USER_END_VADDR.data() {
+            return Err(SystemError::EPERM);
+        }
+
+        let clone_args = KernelCloneArgs { flags, tls };
+        return ProcessManager::kernel_clone(frame, clone_args)
+            .map(|pid| ProcessManager::pid_vnr(pid).into());
+    }
+}
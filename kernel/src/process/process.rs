@@ -1,20 +1,46 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
 use crate::{
     filesystem::vfs::{
         file::{File, FileMode},
         ROOT_INODE,
     },
+    kernel_param, kwarn,
     process::{Pid, ProcessManager},
     syscall::SystemError,
 };
 
+kernel_param!(CONSOLE_PARAM: String = "console");
+
 /// @brief 初始化pid=1的进程的stdio
+///
+/// 默认连接到tty0。可以通过启动参数`console=ttyS0`让init进程使用串口，从而通过串口登录，
+/// 参数中逗号之后的选项(如`console=ttyS0,115200`)会被忽略
 pub fn stdio_init() -> Result<(), SystemError> {
     if ProcessManager::current_pcb().pid() != Pid(1) {
         return Err(SystemError::EPERM);
     }
-    let tty_inode = ROOT_INODE()
-        .lookup("/dev/tty0")
-        .expect("Init stdio: can't find tty0");
+    let console = CONSOLE_PARAM
+        .get()
+        .map(|c| c.split(',').next().unwrap_or_default().to_string())
+        .filter(|c| !c.is_empty());
+    let tty_inode = console
+        .and_then(|c| {
+            ROOT_INODE()
+                .lookup(&format!("/dev/{}", c))
+                .map_err(|e| {
+                    kwarn!("Init stdio: can't find console {}: {:?}", c, e);
+                })
+                .ok()
+        })
+        .unwrap_or_else(|| {
+            ROOT_INODE()
+                .lookup("/dev/tty0")
+                .expect("Init stdio: can't find tty0")
+        });
     let stdin =
         File::new(tty_inode.clone(), FileMode::O_RDONLY).expect("Init stdio: can't create stdin");
     let stdout =
@@ -0,0 +1,386 @@
+//! 进程跟踪(ptrace)
+//!
+//! 目前只支持被跟踪者通过PTRACE_TRACEME让父进程跟踪自己，跟踪者可以：
+//!
+//! - PTRACE_SYSCALL：让被跟踪者在下一次系统调用的入口或出口停下，通过PTRACE_GETREGSET(NT_PRSTATUS)
+//!   读取此时的寄存器，得到系统调用号、参数与返回值
+//! - PTRACE_PEEKTEXT/PTRACE_PEEKDATA：读取被跟踪者的内存，例如系统调用的字符串参数
+//! - PTRACE_CONT/PTRACE_DETACH/PTRACE_KILL：让被跟踪者继续运行、解除跟踪或者杀死被跟踪者
+//!
+//! 被跟踪者的每一次停下都通过wait4报告给跟踪者，停止信号为SIGTRAP；设置了PTRACE_O_TRACESYSGOOD时，
+//! 系统调用处的停止信号为`SIGTRAP | 0x80`。被跟踪者成功地execve之后也会停下，停止信号为SIGTRAP。
+//!
+//! 信号不会让被跟踪者停下，而是照常投递。恢复运行时跟踪者给出的信号，会在被跟踪者恢复之后发送给它。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/ptrace.c
+
+use core::{intrinsics::unlikely, mem::size_of};
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    arch::{
+        interrupt::TrapFrame, ipc::signal::Signal, process::ptrace::UserRegsStruct, sched::sched,
+        CurrentIrqArch, MMArch,
+    },
+    exception::InterruptArch,
+    filesystem::vfs::syscall::IoVec,
+    mm::{MemoryManagementArch, VirtAddr},
+    syscall::{
+        user_access::{UserBufferWriter, UserPtr},
+        Syscall, SystemError,
+    },
+};
+
+use super::{Pid, ProcessControlBlock, ProcessManager, ProcessState};
+
+/// 让父进程跟踪自己
+pub const PTRACE_TRACEME: usize = 0;
+/// 读取被跟踪者的代码段中的一个字
+pub const PTRACE_PEEKTEXT: usize = 1;
+/// 读取被跟踪者的数据段中的一个字
+pub const PTRACE_PEEKDATA: usize = 2;
+/// 让被跟踪者继续运行
+pub const PTRACE_CONT: usize = 7;
+/// 杀死被跟踪者
+pub const PTRACE_KILL: usize = 8;
+/// 解除跟踪，让被跟踪者继续运行
+pub const PTRACE_DETACH: usize = 17;
+/// 让被跟踪者继续运行，并在下一次系统调用的入口或出口停下
+pub const PTRACE_SYSCALL: usize = 24;
+/// 设置跟踪选项
+pub const PTRACE_SETOPTIONS: usize = 0x4200;
+/// 读取被跟踪者的一组寄存器
+pub const PTRACE_GETREGSET: usize = 0x4204;
+
+/// PTRACE_GETREGSET：通用寄存器
+pub const NT_PRSTATUS: usize = 1;
+
+bitflags! {
+    /// 跟踪选项
+    pub struct PtraceOptions: usize {
+        /// 系统调用处的停止信号为`SIGTRAP | 0x80`，以便与真正的SIGTRAP区分
+        const PTRACE_O_TRACESYSGOOD = 1;
+    }
+}
+
+/// 被跟踪者停下的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtraceStop {
+    /// 系统调用的入口
+    SyscallEntry,
+    /// 系统调用的出口
+    SyscallExit,
+    /// execve成功之后
+    Exec,
+}
+
+/// 进程被跟踪的状态
+#[derive(Debug, Default)]
+pub struct PtraceState {
+    /// 跟踪者的pid
+    tracer: Option<Pid>,
+    options: PtraceOptions,
+    /// 是否在系统调用的入口与出口停下
+    syscall_trace: bool,
+    /// 当前停下的原因，正在运行时为None
+    stop: Option<PtraceStop>,
+    /// 当前的停止是否已经通过wait4报告给了跟踪者
+    reported: bool,
+    /// 停下时用户态的寄存器
+    regs: Option<UserRegsStruct>,
+    /// 恢复运行之后，要发送给自己的信号
+    resume_signal: Option<Signal>,
+}
+
+impl Default for PtraceOptions {
+    fn default() -> Self {
+        return Self::empty();
+    }
+}
+
+impl PtraceState {
+    /// 是否被跟踪
+    pub fn is_traced(&self) -> bool {
+        return self.tracer.is_some();
+    }
+
+    /// 解除跟踪，恢复为没有被跟踪的状态
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// 当前进程是否需要在系统调用的入口与出口停下
+#[inline(always)]
+pub fn ptrace_syscall_traced() -> bool {
+    return ProcessManager::current_pcb().ptrace().syscall_trace;
+}
+
+/// 在系统调用的入口或出口停下，直到跟踪者让当前进程继续运行
+///
+/// ## 参数
+///
+/// - `stop`：停下的原因
+/// - `frame`：当前进程进入内核时保存的栈帧
+/// - `syscall_nr`：正在执行的系统调用号
+pub fn ptrace_report_syscall(stop: PtraceStop, frame: &TrapFrame, syscall_nr: usize) {
+    ptrace_stop(stop, UserRegsStruct::from_current(frame, syscall_nr));
+}
+
+/// execve成功之后，如果当前进程被跟踪，就停下
+///
+/// ## 参数
+///
+/// - `frame`：已经被设置为新程序的入口的栈帧
+/// - `syscall_nr`：execve的系统调用号
+pub fn ptrace_report_exec(frame: &TrapFrame, syscall_nr: usize) {
+    if unlikely(ProcessManager::current_pcb().ptrace().is_traced()) {
+        ptrace_stop(
+            PtraceStop::Exec,
+            UserRegsStruct::from_current(frame, syscall_nr),
+        );
+    }
+}
+
+/// 当前进程停下，直到跟踪者让它继续运行、解除跟踪，或者它收到了SIGKILL
+fn ptrace_stop(stop: PtraceStop, regs: UserRegsStruct) {
+    let pcb = ProcessManager::current_pcb();
+    {
+        let mut state = pcb.ptrace();
+        if !state.is_traced() {
+            return;
+        }
+        state.stop = Some(stop);
+        state.reported = false;
+        state.regs = Some(regs);
+    }
+
+    loop {
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        // 跟踪者已经让当前进程继续运行
+        if pcb.ptrace().stop.is_none() {
+            break;
+        }
+        if pcb
+            .sig_info()
+            .sig_pending()
+            .signal()
+            .contains(Signal::SIGKILL.into_sigset())
+        {
+            break;
+        }
+        if ProcessManager::mark_stop().is_err() {
+            break;
+        }
+        // 唤醒在wait4中等待的跟踪者
+        pcb.wait_queue.wakeup(Some(ProcessState::Blocked(true)));
+        drop(irq_guard);
+        sched();
+    }
+
+    let resume_signal = {
+        let mut state = pcb.ptrace();
+        state.stop = None;
+        state.regs = None;
+        state.resume_signal.take()
+    };
+    if let Some(sig) = resume_signal {
+        Syscall::kill(pcb.pid(), sig as i32).ok();
+    }
+}
+
+/// 如果`child`被`tracer`跟踪，并且停下之后还没有报告，就返回wait4应当返回的状态，并把这次停止标记为已经报告
+pub fn ptrace_wait_status(
+    tracer: &ProcessControlBlock,
+    child: &ProcessControlBlock,
+) -> Option<i32> {
+    let mut state = child.ptrace();
+    if state.tracer != Some(tracer.pid()) || state.reported {
+        return None;
+    }
+    let stop = state.stop?;
+    if !child.sched_info().state().is_stopped() {
+        return None;
+    }
+    state.reported = true;
+
+    let mut sig = Signal::SIGTRAP as i32;
+    if stop != PtraceStop::Exec && state.options.contains(PtraceOptions::PTRACE_O_TRACESYSGOOD) {
+        sig |= 0x80;
+    }
+    return Some((sig << 8) | 0x7f);
+}
+
+/// `child`是否被`tracer`跟踪
+pub fn ptrace_is_tracer_of(tracer: &ProcessControlBlock, child: &ProcessControlBlock) -> bool {
+    return child.ptrace().tracer == Some(tracer.pid());
+}
+
+/// 跟踪者退出时，解除它对所有子进程的跟踪，让停下的子进程继续运行
+pub fn exit_ptrace(tracer: &ProcessControlBlock) {
+    let children: Vec<Arc<ProcessControlBlock>> =
+        tracer.children.read().values().cloned().collect();
+    for child in children {
+        if ptrace_is_tracer_of(tracer, &child) {
+            child.ptrace().clear();
+            ProcessManager::wakeup_stop(&child).ok();
+        }
+    }
+}
+
+/// 读取被跟踪者在`addr`处的一个字
+///
+/// ## 错误
+///
+/// - `EIO`：地址不在用户空间内，或者没有被映射
+fn peek_word(child: &ProcessControlBlock, addr: usize) -> Result<usize, SystemError> {
+    let vm = child.basic().user_vm().ok_or(SystemError::EIO)?;
+    let guard = vm.read();
+    let mut bytes = [0u8; size_of::<usize>()];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let vaddr = VirtAddr::new(addr.checked_add(i).ok_or(SystemError::EIO)?);
+        if !vaddr.check_user() {
+            return Err(SystemError::EIO);
+        }
+        let (paddr, flags) = guard
+            .user_mapper
+            .utable
+            .translate(vaddr)
+            .ok_or(SystemError::EIO)?;
+        if !flags.has_user() {
+            return Err(SystemError::EIO);
+        }
+        let offset = vaddr.data() & (MMArch::PAGE_SIZE - 1);
+        let kaddr = unsafe { MMArch::phys_2_virt(paddr + offset) }.ok_or(SystemError::EIO)?;
+        *byte = unsafe { *(kaddr.data() as *const u8) };
+    }
+    return Ok(usize::from_ne_bytes(bytes));
+}
+
+impl Syscall {
+    /// @brief 进程跟踪
+    ///
+    /// @param request 操作
+    /// @param pid 被跟踪者在当前进程所在的PID命名空间中的pid，PTRACE_TRACEME时忽略
+    /// @param addr 取决于具体的操作
+    /// @param data 取决于具体的操作
+    ///
+    /// @return Err(SystemError::ESRCH) 进程不存在、没有被当前进程跟踪，或者没有停下
+    /// @return Err(SystemError::EIO) PTRACE_PEEK*读取的地址不合法
+    pub fn ptrace(
+        request: usize,
+        pid: usize,
+        addr: usize,
+        data: usize,
+    ) -> Result<usize, SystemError> {
+        let current = ProcessManager::current_pcb();
+        if request == PTRACE_TRACEME {
+            let ppid = current.basic().ppid();
+            let mut state = current.ptrace();
+            if state.is_traced() {
+                return Err(SystemError::EPERM);
+            }
+            state.tracer = Some(ppid);
+            return Ok(0);
+        }
+
+        let child = ProcessManager::find_vpid(Pid::new(pid)).ok_or(SystemError::ESRCH)?;
+        if !ptrace_is_tracer_of(&current, &child) {
+            return Err(SystemError::ESRCH);
+        }
+        if request == PTRACE_KILL {
+            Syscall::kill(child.pid(), Signal::SIGKILL as i32)?;
+            child.ptrace().stop = None;
+            // 被跟踪者可能没有停下，此时不需要唤醒
+            ProcessManager::wakeup_stop(&child).ok();
+            return Ok(0);
+        }
+
+        // 其余的操作要求被跟踪者已经停下
+        if child.ptrace().stop.is_none() || !child.sched_info().state().is_stopped() {
+            return Err(SystemError::ESRCH);
+        }
+
+        match request {
+            PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+                let word = peek_word(&child, addr)?;
+                UserPtr::<usize>::new(data).write(&word)?;
+                return Ok(0);
+            }
+            PTRACE_CONT => {
+                Self::ptrace_resume(&child, false, data)?;
+                return Ok(0);
+            }
+            PTRACE_SYSCALL => {
+                Self::ptrace_resume(&child, true, data)?;
+                return Ok(0);
+            }
+            PTRACE_DETACH => {
+                let sig = Self::ptrace_resume_signal(data)?;
+                child.ptrace().clear();
+                child.ptrace().resume_signal = sig;
+                ProcessManager::wakeup_stop(&child)?;
+                return Ok(0);
+            }
+            PTRACE_SETOPTIONS => {
+                let options = PtraceOptions::from_bits(data).ok_or(SystemError::EINVAL)?;
+                child.ptrace().options = options;
+                return Ok(0);
+            }
+            PTRACE_GETREGSET => {
+                if addr != NT_PRSTATUS {
+                    return Err(SystemError::EINVAL);
+                }
+                let regs = child.ptrace().regs.ok_or(SystemError::ESRCH)?;
+                let iov_ptr = UserPtr::<IoVec>::new(data);
+                let mut iov = iov_ptr.read()?;
+                let len = iov.iov_len.min(size_of::<UserRegsStruct>());
+                let regs_bytes = unsafe {
+                    core::slice::from_raw_parts(&regs as *const UserRegsStruct as *const u8, len)
+                };
+                let mut writer = UserBufferWriter::new(iov.iov_base, len, true)?;
+                writer.copy_to_user(regs_bytes, 0)?;
+                iov.iov_len = len;
+                iov_ptr.write(&iov)?;
+                return Ok(0);
+            }
+            _ => return Err(SystemError::EIO),
+        }
+    }
+
+    /// 把PTRACE_CONT等操作的data参数转换为恢复运行之后要发送的信号，0表示不发送信号
+    fn ptrace_resume_signal(data: usize) -> Result<Option<Signal>, SystemError> {
+        if data == 0 {
+            return Ok(None);
+        }
+        let sig = Signal::from(data as i32);
+        if !sig.is_valid() {
+            return Err(SystemError::EIO);
+        }
+        return Ok(Some(sig));
+    }
+
+    /// 让停下的被跟踪者继续运行
+    ///
+    /// ## 参数
+    ///
+    /// - `child`：被跟踪者
+    /// - `syscall_trace`：是否在下一次系统调用的入口或出口停下
+    /// - `data`：恢复之后要发送给被跟踪者的信号，0表示不发送信号
+    fn ptrace_resume(
+        child: &Arc<ProcessControlBlock>,
+        syscall_trace: bool,
+        data: usize,
+    ) -> Result<(), SystemError> {
+        let sig = Self::ptrace_resume_signal(data)?;
+        {
+            let mut state = child.ptrace();
+            state.syscall_trace = syscall_trace;
+            state.resume_signal = sig;
+            state.stop = None;
+        }
+        ProcessManager::wakeup_stop(child)?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,154 @@
+//! 系统的重启与关机
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/reboot.c
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use num_traits::FromPrimitive;
+
+use crate::{
+    arch::{
+        cpu::{cpu_halt, cpu_reset},
+        ipc::signal::{SigCode, Signal},
+    },
+    driver::{acpi::acpi_manager, base::power::device_shutdown},
+    ipc::signal_types::{SigInfo, SigType},
+    kerror, kinfo,
+    syscall::{Syscall, SystemError},
+};
+
+use super::{kexec::kernel_kexec, Pid, ProcessFlags, ProcessManager};
+
+/// reboot系统调用的第一个魔数
+pub const LINUX_REBOOT_MAGIC1: u32 = 0xfee1dead;
+/// reboot系统调用的第二个魔数（任选其一）
+pub const LINUX_REBOOT_MAGIC2: u32 = 672274793;
+pub const LINUX_REBOOT_MAGIC2A: u32 = 85072278;
+pub const LINUX_REBOOT_MAGIC2B: u32 = 369367448;
+pub const LINUX_REBOOT_MAGIC2C: u32 = 537993216;
+
+/// reboot系统调用的命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[repr(u32)]
+pub enum RebootCommand {
+    /// 重启系统
+    Restart = 0x01234567,
+    /// 停机
+    Halt = 0xCDEF0123,
+    /// 允许Ctrl-Alt-Del直接重启
+    CadOn = 0x89ABCDEF,
+    /// 禁止Ctrl-Alt-Del直接重启
+    CadOff = 0x00000000,
+    /// 关机
+    PowerOff = 0x4321FEDC,
+    /// 重启系统（带有一个命令字符串参数）
+    Restart2 = 0xA1B2C3D4,
+    /// 执行由kexec_file_load加载的新内核
+    Kexec = 0x45584543,
+}
+
+/// 是否允许Ctrl-Alt-Del直接重启
+static C_A_D: AtomicBool = AtomicBool::new(true);
+
+/// Ctrl-Alt-Del是否会直接重启系统
+#[allow(dead_code)]
+pub fn ctrl_alt_del_enabled() -> bool {
+    C_A_D.load(Ordering::SeqCst)
+}
+
+impl Syscall {
+    /// reboot系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `magic1`: 必须为`LINUX_REBOOT_MAGIC1`
+    /// - `magic2`: 必须为`LINUX_REBOOT_MAGIC2`系列魔数之一
+    /// - `cmd`: 要执行的命令，见`RebootCommand`
+    /// - `_arg`: `Restart2`命令的参数（暂不支持，会被忽略）
+    ///
+    /// ## 返回值
+    ///
+    /// 重启/停机/关机成功的话，不会返回
+    pub fn reboot(magic1: u32, magic2: u32, cmd: u32, _arg: usize) -> Result<usize, SystemError> {
+        if magic1 != LINUX_REBOOT_MAGIC1
+            || !matches!(
+                magic2,
+                LINUX_REBOOT_MAGIC2
+                    | LINUX_REBOOT_MAGIC2A
+                    | LINUX_REBOOT_MAGIC2B
+                    | LINUX_REBOOT_MAGIC2C
+            )
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let cmd = RebootCommand::from_u32(cmd).ok_or(SystemError::EINVAL)?;
+        match cmd {
+            RebootCommand::CadOn => {
+                C_A_D.store(true, Ordering::SeqCst);
+                return Ok(0);
+            }
+            RebootCommand::CadOff => {
+                C_A_D.store(false, Ordering::SeqCst);
+                return Ok(0);
+            }
+            RebootCommand::Restart | RebootCommand::Restart2 => kernel_restart(),
+            RebootCommand::Halt => kernel_halt(),
+            RebootCommand::PowerOff => kernel_power_off(),
+            RebootCommand::Kexec => kernel_kexec(),
+        }
+    }
+}
+
+/// 关机前的准备工作：结束所有用户进程，然后让所有设备停止工作(磁盘的驱动会在这时把数据写回)
+pub(super) fn kernel_shutdown_prepare() {
+    kill_all_user_processes();
+    device_shutdown();
+}
+
+/// 向除当前进程之外的所有用户进程发送SIGKILL
+fn kill_all_user_processes() {
+    let current = ProcessManager::current_pcb().pid();
+    for pid in ProcessManager::get_all_pids() {
+        if pid == current || pid == Pid::new(0) {
+            continue;
+        }
+        let pcb = match ProcessManager::find(pid) {
+            Some(pcb) => pcb,
+            None => continue,
+        };
+        if pcb.flags().contains(ProcessFlags::KTHREAD) {
+            continue;
+        }
+        drop(pcb);
+
+        let mut info = SigInfo::new(Signal::SIGKILL, 0, SigCode::Kernel, SigType::Kill(pid));
+        Signal::SIGKILL.send_signal_info(Some(&mut info), pid).ok();
+    }
+}
+
+/// 重启系统
+pub fn kernel_restart() -> ! {
+    kernel_shutdown_prepare();
+    kinfo!("Restarting system.");
+    cpu_reset();
+}
+
+/// 停机
+pub fn kernel_halt() -> ! {
+    kernel_shutdown_prepare();
+    kinfo!("System halted.");
+    cpu_halt();
+}
+
+/// 关机
+///
+/// 如果ACPI S5失败，则退而重启系统
+pub fn kernel_power_off() -> ! {
+    kernel_shutdown_prepare();
+    kinfo!("Power down.");
+    if let Err(e) = acpi_manager().enter_s5() {
+        kerror!("ACPI power off failed: {:?}, fall back to restart", e);
+    }
+    cpu_reset();
+}
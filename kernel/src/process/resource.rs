@@ -0,0 +1,147 @@
+//! 进程的资源限制(rlimit)
+//!
+//! 每个进程保存一份资源限制，fork时复制给子进程，execve之后保持不变。
+//! 目前内核只根据RLIMIT_STACK限制用户栈的自动扩展，其余的资源限制只是被记录下来。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/sys.c
+
+use num_traits::FromPrimitive;
+
+use crate::{
+    filesystem::vfs::file::FileDescriptorVec,
+    mm::ucontext::UserStack,
+    syscall::{user_access::UserPtr, Syscall, SystemError},
+};
+
+use super::{Pid, ProcessManager};
+
+/// 资源不受限制
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// 资源的种类，与Linux的RLIMIT_*相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[repr(usize)]
+pub enum RLimitID {
+    /// CPU时间(秒)
+    Cpu = 0,
+    /// 文件的最大大小
+    Fsize = 1,
+    /// 数据段的最大大小
+    Data = 2,
+    /// 用户栈的最大大小
+    Stack = 3,
+    /// core文件的最大大小
+    Core = 4,
+    /// 常驻内存的最大大小
+    Rss = 5,
+    /// 进程的最大数量
+    Nproc = 6,
+    /// 打开的文件的最大数量
+    Nofile = 7,
+    /// 锁定的内存的最大大小
+    Memlock = 8,
+    /// 地址空间的最大大小
+    As = 9,
+    /// 文件锁的最大数量
+    Locks = 10,
+    /// 待处理的信号的最大数量
+    Sigpending = 11,
+    /// POSIX消息队列的最大字节数
+    Msgqueue = 12,
+    /// nice值的上限
+    Nice = 13,
+    /// 实时优先级的上限
+    Rtprio = 14,
+    /// 实时进程的CPU时间(微秒)
+    Rttime = 15,
+}
+
+/// 资源的种类的数量
+pub const RLIM_NLIMITS: usize = 16;
+
+/// 与Linux的`struct rlimit64`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RLimit64 {
+    /// 软限制
+    pub rlim_cur: u64,
+    /// 硬限制，软限制不能超过它
+    pub rlim_max: u64,
+}
+
+impl RLimit64 {
+    pub const fn new(rlim_cur: u64, rlim_max: u64) -> Self {
+        return Self { rlim_cur, rlim_max };
+    }
+}
+
+/// 1号进程的资源限制，其余进程从父进程继承
+pub fn default_rlimits() -> [RLimit64; RLIM_NLIMITS] {
+    let mut rlimits = [RLimit64::new(RLIM_INFINITY, RLIM_INFINITY); RLIM_NLIMITS];
+    rlimits[RLimitID::Stack as usize] =
+        RLimit64::new(UserStack::DEFAULT_USER_STACK_SIZE as u64, RLIM_INFINITY);
+    rlimits[RLimitID::Core as usize] = RLimit64::new(0, RLIM_INFINITY);
+    rlimits[RLimitID::Nofile as usize] = RLimit64::new(
+        FileDescriptorVec::PROCESS_MAX_FD as u64,
+        FileDescriptorVec::PROCESS_MAX_FD as u64,
+    );
+    rlimits[RLimitID::Nice as usize] = RLimit64::new(0, 0);
+    rlimits[RLimitID::Rtprio as usize] = RLimit64::new(0, 0);
+    return rlimits;
+}
+
+/// 获取当前进程的某一种资源的软限制
+pub fn current_rlimit(id: RLimitID) -> u64 {
+    return ProcessManager::current_pcb().basic().rlimit(id).rlim_cur;
+}
+
+impl Syscall {
+    /// @brief 获取或设置进程的资源限制
+    ///
+    /// @param pid 目标进程，为0时表示当前进程
+    /// @param resource 资源的种类
+    /// @param new_limit 新的资源限制，为空时不修改
+    /// @param old_limit 用于返回原来的资源限制，为空时不返回
+    ///
+    /// @return 成功时返回0
+    ///
+    /// 目前没有用户的概念，因此不检查提高硬限制的权限
+    pub fn prlimit64(
+        pid: Pid,
+        resource: usize,
+        new_limit: UserPtr<RLimit64>,
+        old_limit: UserPtr<RLimit64>,
+    ) -> Result<usize, SystemError> {
+        let id = RLimitID::from_usize(resource).ok_or(SystemError::EINVAL)?;
+        let new_limit = if new_limit.is_null() {
+            None
+        } else {
+            let limit = new_limit.read()?;
+            if limit.rlim_cur > limit.rlim_max {
+                return Err(SystemError::EINVAL);
+            }
+            // 文件描述符表的大小是固定的
+            if id == RLimitID::Nofile && limit.rlim_max > FileDescriptorVec::PROCESS_MAX_FD as u64 {
+                return Err(SystemError::EPERM);
+            }
+            Some(limit)
+        };
+
+        let pcb = if pid == Pid(0) {
+            ProcessManager::current_pcb()
+        } else {
+            ProcessManager::find_vpid(pid).ok_or(SystemError::ESRCH)?
+        };
+        let mut basic = pcb.basic_mut();
+        let old = basic.rlimit(id);
+        if let Some(limit) = new_limit {
+            basic.set_rlimit(id, limit);
+        }
+        drop(basic);
+
+        if !old_limit.is_null() {
+            old_limit.write(&old)?;
+        }
+        return Ok(0);
+    }
+}
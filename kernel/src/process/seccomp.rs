@@ -0,0 +1,613 @@
+//! 系统调用过滤(seccomp)
+//!
+//! 进程可以通过prctl(PR_SET_SECCOMP)或者seccomp系统调用限制自己之后能够使用的系统调用：
+//!
+//! - 严格模式(SECCOMP_MODE_STRICT)：只允许read、write、exit以及rt_sigreturn，其余的系统调用会杀死进程
+//! - 过滤器模式(SECCOMP_MODE_FILTER)：安装一个经典BPF(cBPF)程序，每次系统调用之前，以`SeccompData`为输入运行它，
+//!   根据它的返回值决定允许、返回错误码、发送SIGSYS或者杀死进程
+//!
+//! 过滤器只能增加，不能移除。进程可以多次安装过滤器，每次系统调用会运行所有的过滤器，取优先级最高的结果。
+//! fork时子进程继承父进程的模式与过滤器，execve之后保持不变。
+//!
+//! 目前内核没有权限(capability)的概念，因此安装过滤器时不要求设置了no_new_privs。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/seccomp.c
+
+use core::mem::size_of;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::{
+    arch::{
+        ipc::signal::{SigCode, Signal},
+        syscall::AUDIT_ARCH,
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    kinfo,
+    syscall::{
+        user_access::{UserBufferReader, UserPtr},
+        Syscall, SystemError, SYS_EXIT, SYS_READ, SYS_RT_SIGRETURN, SYS_WRITE,
+    },
+};
+
+use super::{ProcessFlags, ProcessManager};
+
+/// seccomp系统调用的操作：进入严格模式
+pub const SECCOMP_SET_MODE_STRICT: u32 = 0;
+/// seccomp系统调用的操作：安装过滤器
+pub const SECCOMP_SET_MODE_FILTER: u32 = 1;
+/// seccomp系统调用的操作：查询内核是否支持某个返回值
+pub const SECCOMP_GET_ACTION_AVAIL: u32 = 2;
+
+/// 安装过滤器的标志：记录除了ALLOW以外的所有结果
+pub const SECCOMP_FILTER_FLAG_LOG: u32 = 1 << 1;
+/// 安装过滤器的标志：不开启针对推测执行的缓解措施。内核没有这些措施，因此忽略
+pub const SECCOMP_FILTER_FLAG_SPEC_ALLOW: u32 = 1 << 2;
+
+/// 杀死整个进程
+pub const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+/// 杀死当前线程
+pub const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+/// 不执行系统调用，向进程发送SIGSYS
+pub const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+/// 不执行系统调用，返回低16位给出的错误码
+pub const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+/// 交给用户态的监听者处理。不支持，按照没有监听者处理
+pub const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+/// 交给ptrace的跟踪者处理。不支持，按照没有跟踪者处理
+pub const SECCOMP_RET_TRACE: u32 = 0x7ff0_0000;
+/// 记录之后执行系统调用
+pub const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+/// 执行系统调用
+pub const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+/// 返回值中表示结果的部分
+const SECCOMP_RET_ACTION_FULL: u32 = 0xffff_0000;
+/// 返回值中附带的数据
+const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+/// 一个过滤器的最大指令数
+const BPF_MAXINSNS: usize = 4096;
+/// 一个进程的所有过滤器的总指令数的上限，每个过滤器额外计4条指令
+const MAX_INSNS_PER_PATH: usize = 32768;
+/// 过滤器可以使用的临时存储单元的数量
+const BPF_MEMWORDS: usize = 16;
+
+/// 错误码的最大值
+const MAX_ERRNO: u32 = 4095;
+
+/// 进程的seccomp模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SeccompMode {
+    Disabled = 0,
+    Strict = 1,
+    Filter = 2,
+}
+
+/// 过滤器的输入，与Linux的`struct seccomp_data`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SeccompData {
+    /// 系统调用号
+    pub nr: i32,
+    /// 架构的标识(AUDIT_ARCH_*)
+    pub arch: u32,
+    /// 系统调用指令的地址
+    pub instruction_pointer: u64,
+    /// 系统调用的参数
+    pub args: [u64; 6],
+}
+
+impl SeccompData {
+    /// 读取偏移量为`offset`的32位字，`offset`已经在检查过滤器时验证过
+    fn load_word(&self, offset: usize) -> u32 {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>())
+        };
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        return u32::from_ne_bytes(word);
+    }
+}
+
+/// cBPF指令，与Linux的`struct sock_filter`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// cBPF程序，与Linux的`struct sock_fprog`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: *const SockFilter,
+}
+
+// cBPF指令的编码，参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/include/uapi/linux/bpf_common.h
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+const BPF_W: u16 = 0x00;
+
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+#[inline(always)]
+fn bpf_class(code: u16) -> u16 {
+    code & 0x07
+}
+
+#[inline(always)]
+fn bpf_op(code: u16) -> u16 {
+    code & 0xf0
+}
+
+#[inline(always)]
+fn bpf_src(code: u16) -> u16 {
+    code & 0x08
+}
+
+/// 一个已经安装的过滤器
+#[derive(Debug)]
+pub struct SeccompFilter {
+    prog: Vec<SockFilter>,
+    /// 是否记录除了ALLOW以外的结果
+    log: bool,
+    /// 在这个过滤器之前安装的过滤器
+    prev: Option<Arc<SeccompFilter>>,
+}
+
+impl SeccompFilter {
+    /// 检查过滤器，把长度的读取转换为立即数。只允许读取`SeccompData`中对齐的32位字
+    ///
+    /// ## 错误
+    ///
+    /// - `EINVAL`：过滤器为空、太长、包含不支持的指令或者跳转越界，或者最后一条指令不是返回
+    fn check(prog: &mut [SockFilter]) -> Result<(), SystemError> {
+        let len = prog.len();
+        if len == 0 || len > BPF_MAXINSNS {
+            return Err(SystemError::EINVAL);
+        }
+        for pc in 0..len {
+            let insn = &mut prog[pc];
+            let code = insn.code;
+            let k = insn.k as usize;
+            match bpf_class(code) {
+                BPF_LD | BPF_LDX => match code & !0x07 {
+                    c if c == BPF_W | BPF_ABS && bpf_class(code) == BPF_LD => {
+                        if k % 4 != 0 || k >= size_of::<SeccompData>() {
+                            return Err(SystemError::EINVAL);
+                        }
+                    }
+                    c if c == BPF_W | BPF_LEN => {
+                        insn.code = bpf_class(code) | BPF_W | BPF_IMM;
+                        insn.k = size_of::<SeccompData>() as u32;
+                    }
+                    c if c == BPF_W | BPF_IMM => {}
+                    c if c == BPF_W | BPF_MEM => {
+                        if k >= BPF_MEMWORDS {
+                            return Err(SystemError::EINVAL);
+                        }
+                    }
+                    _ => return Err(SystemError::EINVAL),
+                },
+                BPF_ST | BPF_STX => {
+                    if code & !0x07 != 0 || k >= BPF_MEMWORDS {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+                BPF_ALU => match bpf_op(code) {
+                    BPF_ADD | BPF_SUB | BPF_MUL | BPF_OR | BPF_AND | BPF_LSH | BPF_RSH
+                    | BPF_XOR => {}
+                    BPF_DIV | BPF_MOD => {
+                        if bpf_src(code) == BPF_K && k == 0 {
+                            return Err(SystemError::EINVAL);
+                        }
+                    }
+                    BPF_NEG => {}
+                    _ => return Err(SystemError::EINVAL),
+                },
+                BPF_JMP => {
+                    let remain = len - pc - 1;
+                    match bpf_op(code) {
+                        BPF_JA => {
+                            if k >= remain {
+                                return Err(SystemError::EINVAL);
+                            }
+                        }
+                        BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => {
+                            if insn.jt as usize >= remain || insn.jf as usize >= remain {
+                                return Err(SystemError::EINVAL);
+                            }
+                        }
+                        _ => return Err(SystemError::EINVAL),
+                    }
+                }
+                BPF_RET => match code & !0x07 {
+                    BPF_K | BPF_A => {}
+                    _ => return Err(SystemError::EINVAL),
+                },
+                BPF_MISC => match code & !0x07 {
+                    BPF_TAX | BPF_TXA => {}
+                    _ => return Err(SystemError::EINVAL),
+                },
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        if bpf_class(prog[len - 1].code) != BPF_RET {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(());
+    }
+
+    /// 运行过滤器，返回它的返回值。过滤器已经通过了检查，因此一定会结束
+    fn run(&self, data: &SeccompData) -> u32 {
+        let prog = &self.prog;
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; BPF_MEMWORDS];
+        let mut pc = 0;
+        loop {
+            let insn = prog[pc];
+            let k = insn.k;
+            pc += 1;
+            match bpf_class(insn.code) {
+                BPF_LD => {
+                    a = match insn.code & !0x07 {
+                        c if c == BPF_W | BPF_ABS => data.load_word(k as usize),
+                        c if c == BPF_W | BPF_MEM => mem[k as usize],
+                        _ => k,
+                    }
+                }
+                BPF_LDX => {
+                    x = match insn.code & !0x07 {
+                        c if c == BPF_W | BPF_MEM => mem[k as usize],
+                        _ => k,
+                    }
+                }
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                BPF_ALU => {
+                    let src = if bpf_src(insn.code) == BPF_X { x } else { k };
+                    a = match bpf_op(insn.code) {
+                        BPF_ADD => a.wrapping_add(src),
+                        BPF_SUB => a.wrapping_sub(src),
+                        BPF_MUL => a.wrapping_mul(src),
+                        BPF_DIV | BPF_MOD if src == 0 => return 0,
+                        BPF_DIV => a / src,
+                        BPF_MOD => a % src,
+                        BPF_OR => a | src,
+                        BPF_AND => a & src,
+                        BPF_LSH => a.checked_shl(src).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(src).unwrap_or(0),
+                        BPF_XOR => a ^ src,
+                        _ => a.wrapping_neg(),
+                    }
+                }
+                BPF_JMP => {
+                    let src = if bpf_src(insn.code) == BPF_X { x } else { k };
+                    let cond = match bpf_op(insn.code) {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == src,
+                        BPF_JGT => a > src,
+                        BPF_JGE => a >= src,
+                        _ => a & src != 0,
+                    };
+                    pc += if cond { insn.jt } else { insn.jf } as usize;
+                }
+                BPF_RET => {
+                    return if insn.code & !0x07 == BPF_A { a } else { k };
+                }
+                _ => {
+                    if insn.code & !0x07 == BPF_TAX {
+                        x = a;
+                    } else {
+                        a = x;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 这个过滤器与之前的所有过滤器的总指令数
+    fn path_len(&self) -> usize {
+        let mut total = 0;
+        let mut filter = Some(self);
+        while let Some(f) = filter {
+            total += f.prog.len() + 4;
+            filter = f.prev.as_deref();
+        }
+        return total;
+    }
+}
+
+/// 进程的seccomp状态
+#[derive(Debug, Clone)]
+pub struct SeccompState {
+    mode: SeccompMode,
+    /// 最后安装的过滤器
+    filter: Option<Arc<SeccompFilter>>,
+}
+
+impl Default for SeccompState {
+    fn default() -> Self {
+        Self {
+            mode: SeccompMode::Disabled,
+            filter: None,
+        }
+    }
+}
+
+impl SeccompState {
+    pub fn mode(&self) -> SeccompMode {
+        self.mode
+    }
+}
+
+/// 把返回值中表示结果的部分视为有符号数，值越小优先级越高
+#[inline(always)]
+fn action_only(ret: u32) -> i32 {
+    (ret & SECCOMP_RET_ACTION_FULL) as i32
+}
+
+/// 进入系统调用之前，检查当前进程是否允许执行这个系统调用
+///
+/// ## 参数
+///
+/// - `syscall_num`：系统调用号
+/// - `args`：系统调用的参数
+/// - `ip`：系统调用指令的地址
+///
+/// ## 返回值
+///
+/// - `None`：允许执行这个系统调用
+/// - `Some(ret)`：不执行这个系统调用，直接把`ret`作为它的返回值
+pub fn secure_computing(syscall_num: usize, args: &[usize; 6], ip: usize) -> Option<usize> {
+    let pcb = ProcessManager::current_pcb();
+    let state = pcb.seccomp().clone();
+    drop(pcb);
+
+    match state.mode {
+        SeccompMode::Disabled => return None,
+        SeccompMode::Strict => {
+            if matches!(
+                syscall_num,
+                SYS_READ | SYS_WRITE | SYS_EXIT | SYS_RT_SIGRETURN
+            ) {
+                return None;
+            }
+            drop(state);
+            ProcessManager::exit(Signal::SIGKILL as usize);
+        }
+        SeccompMode::Filter => {}
+    }
+
+    let data = SeccompData {
+        nr: syscall_num as i32,
+        arch: AUDIT_ARCH,
+        instruction_pointer: ip as u64,
+        args: args.map(|arg| arg as u64),
+    };
+
+    // 运行所有的过滤器，取优先级最高的结果
+    let mut ret = SECCOMP_RET_ALLOW;
+    let mut log = false;
+    let mut filter = state.filter.as_deref();
+    while let Some(f) = filter {
+        let cur = f.run(&data);
+        if action_only(cur) < action_only(ret) {
+            ret = cur;
+            log = f.log;
+        }
+        filter = f.prev.as_deref();
+    }
+    drop(state);
+
+    let action = ret & SECCOMP_RET_ACTION_FULL;
+    let value = ret & SECCOMP_RET_DATA;
+    if log && action != SECCOMP_RET_ALLOW {
+        kinfo!(
+            "seccomp: pid={:?} syscall={} action={:#x}",
+            ProcessManager::current_pcb().pid(),
+            syscall_num,
+            action
+        );
+    }
+    match action {
+        SECCOMP_RET_ALLOW | SECCOMP_RET_LOG => return None,
+        SECCOMP_RET_ERRNO => {
+            let errno = value.min(MAX_ERRNO) as isize;
+            return Some((-errno) as usize);
+        }
+        SECCOMP_RET_TRAP => {
+            let mut info = SigInfo::new(
+                Signal::SIGSYS,
+                value as i32,
+                SigCode::Kernel,
+                SigType::SigSys {
+                    call_addr: ip,
+                    syscall: syscall_num as i32,
+                    arch: AUDIT_ARCH,
+                },
+            );
+            Signal::SIGSYS
+                .send_signal_info(Some(&mut info), ProcessManager::current_pcb().pid())
+                .ok();
+            return Some(SystemError::ENOSYS.to_posix_errno() as usize);
+        }
+        // 没有跟踪者或者监听者
+        SECCOMP_RET_TRACE | SECCOMP_RET_USER_NOTIF => {
+            return Some(SystemError::ENOSYS.to_posix_errno() as usize);
+        }
+        // 其余的结果(包括未知的结果)都会杀死进程
+        _ => {
+            ProcessManager::exit(Signal::SIGSYS as usize);
+        }
+    }
+}
+
+/// 让当前进程进入严格模式
+///
+/// ## 错误
+///
+/// - `EINVAL`：当前进程已经处于过滤器模式
+pub fn seccomp_set_mode_strict() -> Result<usize, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    let mut state = pcb.seccomp();
+    match state.mode {
+        SeccompMode::Disabled | SeccompMode::Strict => {
+            state.mode = SeccompMode::Strict;
+            return Ok(0);
+        }
+        SeccompMode::Filter => return Err(SystemError::EINVAL),
+    }
+}
+
+/// 为当前进程安装一个过滤器
+///
+/// ## 参数
+///
+/// - `flags`：SECCOMP_FILTER_FLAG_*
+/// - `uprog`：用户空间的`SockFprog`
+///
+/// ## 错误
+///
+/// - `EINVAL`：不支持的标志，过滤器不合法，或者当前进程已经处于严格模式
+/// - `EFAULT`：用户空间的地址不合法
+/// - `ENOMEM`：所有过滤器的总指令数太多
+pub fn seccomp_set_mode_filter(
+    flags: u32,
+    uprog: UserPtr<SockFprog>,
+) -> Result<usize, SystemError> {
+    if flags & !(SECCOMP_FILTER_FLAG_LOG | SECCOMP_FILTER_FLAG_SPEC_ALLOW) != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    if uprog.is_null() {
+        return Err(SystemError::EFAULT);
+    }
+    let fprog = uprog.read()?;
+    let len = fprog.len as usize;
+    if len == 0 || len > BPF_MAXINSNS {
+        return Err(SystemError::EINVAL);
+    }
+    let mut prog = vec![SockFilter::default(); len];
+    let reader = UserBufferReader::new(fprog.filter, len * size_of::<SockFilter>(), true)?;
+    reader.copy_from_user(&mut prog, 0)?;
+    SeccompFilter::check(&mut prog)?;
+
+    let pcb = ProcessManager::current_pcb();
+    let mut state = pcb.seccomp();
+    if state.mode == SeccompMode::Strict {
+        return Err(SystemError::EINVAL);
+    }
+    let filter = SeccompFilter {
+        prog,
+        log: flags & SECCOMP_FILTER_FLAG_LOG != 0,
+        prev: state.filter.clone(),
+    };
+    if filter.path_len() > MAX_INSNS_PER_PATH {
+        return Err(SystemError::ENOMEM);
+    }
+    state.filter = Some(Arc::new(filter));
+    state.mode = SeccompMode::Filter;
+    return Ok(0);
+}
+
+/// 查询内核是否支持某个过滤器返回值
+fn seccomp_get_action_avail(uaction: UserPtr<u32>) -> Result<usize, SystemError> {
+    match uaction.read()? {
+        SECCOMP_RET_KILL_PROCESS
+        | SECCOMP_RET_KILL_THREAD
+        | SECCOMP_RET_TRAP
+        | SECCOMP_RET_ERRNO
+        | SECCOMP_RET_LOG
+        | SECCOMP_RET_ALLOW => return Ok(0),
+        _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+    }
+}
+
+impl Syscall {
+    /// @brief seccomp系统调用
+    ///
+    /// @param op 要执行的操作(SECCOMP_SET_MODE_*、SECCOMP_GET_ACTION_AVAIL)
+    /// @param flags 操作的标志
+    /// @param uargs 操作的参数
+    ///
+    /// @return 成功时返回0
+    pub fn seccomp(op: u32, flags: u32, uargs: usize) -> Result<usize, SystemError> {
+        match op {
+            SECCOMP_SET_MODE_STRICT => {
+                if flags != 0 || uargs != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                return seccomp_set_mode_strict();
+            }
+            SECCOMP_SET_MODE_FILTER => {
+                return seccomp_set_mode_filter(flags, UserPtr::new(uargs));
+            }
+            SECCOMP_GET_ACTION_AVAIL => {
+                if flags != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                return seccomp_get_action_avail(UserPtr::new(uargs));
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// 设置当前进程的no_new_privs标志。这个标志一旦设置就不能清除，并且会被子进程继承
+pub fn set_no_new_privs() {
+    ProcessManager::current_pcb()
+        .flags()
+        .insert(ProcessFlags::NO_NEW_PRIVS);
+}
+
+/// 当前进程是否设置了no_new_privs标志
+pub fn no_new_privs() -> bool {
+    return ProcessManager::current_pcb()
+        .flags()
+        .contains(ProcessFlags::NO_NEW_PRIVS);
+}
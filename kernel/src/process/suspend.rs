@@ -0,0 +1,144 @@
+//! 系统挂起到内存(ACPI S3)
+//!
+//! 向`/sys/power/state`写入`mem`时，依次：
+//! 1. 冻结所有用户进程
+//! 2. 按照子设备先于父设备的顺序让设备睡眠
+//! 3. 关闭中断，暂停计时，保存cpu的状态之后进入S3
+//! 4. 被唤醒之后按照相反的顺序恢复，最后解冻用户进程
+//!
+//! 目前只支持单个cpu的系统：AP在S3中断电，唤醒之后需要重新启动，而cpu热插拔还不能让AP重新上线。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/power/suspend.c
+
+use alloc::sync::Arc;
+
+use crate::{
+    arch::{suspend::arch_suspend_enter, CurrentIrqArch},
+    driver::{
+        acpi::acpi_manager,
+        base::{
+            kobject::KObject,
+            power::{dpm_resume, dpm_suspend, sys_power_kset},
+        },
+    },
+    exception::InterruptArch,
+    filesystem::{
+        sysfs::{
+            file::{sysfs_buf_to_str, sysfs_emit_str},
+            sysfs_instance, Attribute, SysFSOpsSupport,
+        },
+        vfs::syscall::ModeType,
+    },
+    kerror, kinfo,
+    libs::mutex::Mutex,
+    smp::core::smp_cpu_count,
+    syscall::SystemError,
+    time::{
+        hrtimer::hrtimer_resume,
+        timekeeping::{timekeeping_resume, timekeeping_suspend},
+    },
+};
+
+use super::freezer::{freeze_processes, thaw_processes};
+
+/// 同一时间只能有一个挂起流程
+static SUSPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// 检查系统是否支持挂起到内存
+///
+/// ## 错误
+///
+/// - `ENODEV`：固件不支持S3
+/// - `EOPNOTSUPP_OR_ENOTSUP`：系统中有多个cpu
+fn suspend_valid() -> Result<(), SystemError> {
+    if !acpi_manager().s3_supported() {
+        return Err(SystemError::ENODEV);
+    }
+    if smp_cpu_count() > 1 {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    return Ok(());
+}
+
+/// 把系统挂起到内存，被唤醒之后返回
+///
+/// ## 错误
+///
+/// - `EBUSY`：已经有挂起流程正在进行，或者有进程没能被冻结
+/// - `ENODEV`、`EOPNOTSUPP_OR_ENOTSUP`：系统不支持挂起到内存
+/// - 设备睡眠或者进入S3失败时返回相应的错误，此时系统已经恢复运行
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/power/suspend.c?fi=pm_suspend#pm_suspend
+pub fn pm_suspend() -> Result<(), SystemError> {
+    let _guard = SUSPEND_LOCK.try_lock().map_err(|_| SystemError::EBUSY)?;
+    suspend_valid()?;
+
+    kinfo!("PM: suspend entry (deep)");
+    freeze_processes()?;
+    let r = suspend_devices_and_enter();
+    thaw_processes();
+    kinfo!("PM: suspend exit");
+    return r;
+}
+
+/// 让设备睡眠，然后进入S3。被唤醒或者进入失败之后恢复设备
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/power/suspend.c?fi=suspend_devices_and_enter#suspend_devices_and_enter
+fn suspend_devices_and_enter() -> Result<(), SystemError> {
+    dpm_suspend()?;
+
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    timekeeping_suspend();
+    let r = unsafe { arch_suspend_enter() };
+    if r.is_ok() {
+        // 本地定时器在S3中断电，需要重新编程
+        hrtimer_resume();
+    } else {
+        kerror!("PM: failed to enter S3: {:?}", r);
+    }
+    timekeeping_resume();
+    drop(irq_guard);
+
+    dpm_resume();
+    return r;
+}
+
+/// `/sys/power/state`：读取时返回支持的睡眠状态，写入`mem`时挂起到内存
+#[derive(Debug)]
+struct AttrPowerState;
+
+impl Attribute for AttrPowerState {
+    fn name(&self) -> &str {
+        "state"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::from_bits_truncate(0o644)
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::SHOW | SysFSOpsSupport::STORE
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        if suspend_valid().is_ok() {
+            return sysfs_emit_str(buf, "mem\n");
+        }
+        return sysfs_emit_str(buf, "\n");
+    }
+
+    fn store(&self, _kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        match sysfs_buf_to_str(buf)? {
+            "mem" => pm_suspend()?,
+            _ => return Err(SystemError::EINVAL),
+        }
+        return Ok(buf.len());
+    }
+}
+
+/// 创建`/sys/power/state`
+pub fn suspend_init() -> Result<(), SystemError> {
+    let power_kobj = sys_power_kset() as Arc<dyn KObject>;
+    sysfs_instance().create_file(&power_kobj, &AttrPowerState)?;
+    return Ok(());
+}
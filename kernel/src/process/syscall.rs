@@ -1,24 +1,49 @@
 use core::ffi::c_void;
 
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
 
-use super::{abi::WaitOption, fork::CloneFlags, Pid, ProcessManager, ProcessState};
+use super::{
+    abi::WaitOption,
+    exec::ARG_MAX,
+    fork::CloneFlags,
+    ptrace::{ptrace_is_tracer_of, ptrace_report_exec, ptrace_wait_status},
+    seccomp::{no_new_privs, seccomp_set_mode_filter, seccomp_set_mode_strict, set_no_new_privs},
+    Pid, ProcessManager, ProcessState,
+};
 use crate::{
     arch::{interrupt::TrapFrame, sched::sched, CurrentIrqArch},
+    audit::{audit_log, AuditEvents},
     exception::InterruptArch,
     filesystem::vfs::MAX_PATHLEN,
+    libs::rwlock::RwLock,
     process::ProcessControlBlock,
     syscall::{
         user_access::{
             check_and_clone_cstr, check_and_clone_cstr_array, UserBufferReader, UserBufferWriter,
+            UserPtr,
         },
-        Syscall, SystemError,
+        Syscall, SystemError, SYS_EXECVE,
     },
 };
 
+/// prctl的操作：获取seccomp模式
+pub const PR_GET_SECCOMP: usize = 21;
+/// prctl的操作：设置seccomp模式
+pub const PR_SET_SECCOMP: usize = 22;
+/// prctl的操作：设置no_new_privs标志
+pub const PR_SET_NO_NEW_PRIVS: usize = 38;
+/// prctl的操作：获取no_new_privs标志
+pub const PR_GET_NO_NEW_PRIVS: usize = 39;
+
+/// PR_SET_SECCOMP的参数：严格模式
+const SECCOMP_MODE_STRICT: usize = 1;
+/// PR_SET_SECCOMP的参数：过滤器模式
+const SECCOMP_MODE_FILTER: usize = 2;
+
 impl Syscall {
     pub fn fork(frame: &mut TrapFrame) -> Result<usize, SystemError> {
-        let r = ProcessManager::fork(frame, CloneFlags::empty()).map(|pid| pid.into());
+        let r = ProcessManager::fork(frame, CloneFlags::empty())
+            .map(|pid| ProcessManager::pid_vnr(pid).into());
         return r;
     }
 
@@ -27,7 +52,7 @@ impl Syscall {
             frame,
             CloneFlags::CLONE_VM | CloneFlags::CLONE_FS | CloneFlags::CLONE_SIGNAL,
         )
-        .map(|pid| pid.into())
+        .map(|pid| ProcessManager::pid_vnr(pid).into())
     }
 
     pub fn execve(
@@ -57,15 +82,48 @@ impl Syscall {
             panic!("Failed to execve: {:?}", e);
         }
         let (path, argv, envp) = r.unwrap();
+        let args_size: usize = argv
+            .iter()
+            .chain(envp.iter())
+            .map(|s| s.len() + 1 + core::mem::size_of::<usize>())
+            .sum();
+        if args_size > ARG_MAX {
+            return Err(SystemError::E2BIG);
+        }
         ProcessManager::current_pcb()
             .basic_mut()
             .set_name(ProcessControlBlock::generate_name(&path, &argv));
 
-        Self::do_execve(path, argv, envp, frame)?;
+        let audit_path = path.clone();
+        let argc = argv.len();
+        let r = Self::do_execve(path, argv, envp, frame);
+        audit_log(
+            AuditEvents::EXECVE,
+            &r,
+            format_args!("path=\"{}\" argc={}", audit_path, argc),
+        );
+        r?;
 
-        // 关闭设置了O_CLOEXEC的文件描述符
-        let fd_table = ProcessManager::current_pcb().fd_table();
-        fd_table.write().close_on_exec();
+        // 文件描述符表被其他进程共享(CLONE_FILES)时，先复制一份，避免关闭其他进程的文件描述符
+        let pcb = ProcessManager::current_pcb();
+        let fd_table = pcb.fd_table();
+        if Arc::strong_count(&fd_table) > 2 {
+            let new_fd_table = fd_table.read().clone();
+            pcb.basic_mut()
+                .set_fd_table(Some(Arc::new(RwLock::new(new_fd_table))));
+        }
+        drop(fd_table);
+
+        // 关闭设置了FD_CLOEXEC的文件描述符
+        pcb.fd_table().write().close_on_exec();
+
+        // 删除通过timer_create创建的定时器
+        ProcessManager::current_pcb()
+            .posix_timers_irqsave()
+            .delete_posix_timers();
+
+        // 被跟踪时，在新程序开始执行之前停下
+        ptrace_report_exec(frame, SYS_EXECVE);
 
         return Ok(());
     }
@@ -94,11 +152,24 @@ impl Syscall {
         let rd_childen = cur_pcb.children.read();
 
         if pid > 0 {
+            // 用户传入的是子进程在当前进程所在的PID命名空间中的pid
             let pid = Pid(pid as usize);
-            let child_pcb = rd_childen.get(&pid).ok_or(SystemError::ECHILD)?.clone();
+            let global_pid = cur_pcb.pid_ns().to_global(pid).ok_or(SystemError::ECHILD)?;
+            let child_pcb = rd_childen
+                .get(&global_pid)
+                .ok_or(SystemError::ECHILD)?
+                .clone();
             drop(rd_childen);
+            let traced = ptrace_is_tracer_of(&cur_pcb, &child_pcb);
 
             loop {
+                // 被跟踪的子进程停下时，不需要指定WUNTRACED也要报告
+                if let Some(status) = ptrace_wait_status(&cur_pcb, &child_pcb) {
+                    if !wstatus.is_null() {
+                        wstatus_buf.copy_one_to_user(&status, 0)?;
+                    }
+                    return Ok(pid.into());
+                }
                 // 获取退出码
                 match child_pcb.sched_info().state() {
                     ProcessState::Runnable => {
@@ -111,6 +182,12 @@ impl Syscall {
                             return Ok(0);
                         }
                     }
+                    // 被跟踪的子进程只报告ptrace停止与退出
+                    ProcessState::Blocked(_) | ProcessState::Stopped if traced => {
+                        if options.contains(WaitOption::WNOHANG) {
+                            return Ok(0);
+                        }
+                    }
                     ProcessState::Blocked(_) | ProcessState::Stopped => {
                         // 指定WUNTRACED则等待暂停的进程，不指定则返回0
                         if !options.contains(WaitOption::WUNTRACED)
@@ -145,23 +222,40 @@ impl Syscall {
             // 暂时不支持
             return Err(SystemError::EINVAL);
         } else {
-            // 等待任意子进程(这两)
-            let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
-            for (pid, pcb) in rd_childen.iter() {
-                if pcb.sched_info().state().is_exited() {
-                    if !wstatus.is_null() {
-                        wstatus_buf.copy_one_to_user(&0, 0)?;
+            // 等待任意子进程
+            drop(rd_childen);
+            loop {
+                let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+                let rd_childen = cur_pcb.children.read();
+                if rd_childen.is_empty() {
+                    return Err(SystemError::ECHILD);
+                }
+                for (pid, pcb) in rd_childen.iter() {
+                    if let Some(status) = ptrace_wait_status(&cur_pcb, pcb) {
+                        if !wstatus.is_null() {
+                            wstatus_buf.copy_one_to_user(&status, 0)?;
+                        }
+                        return Ok(ProcessManager::pid_vnr(*pid).into());
+                    }
+                    if pcb.sched_info().state().is_exited() {
+                        if !wstatus.is_null() {
+                            wstatus_buf.copy_one_to_user(&0, 0)?;
+                        }
+                        return Ok(ProcessManager::pid_vnr(*pid).into());
                     }
-                    return Ok(pid.clone().into());
-                } else {
+                }
+                if options.contains(WaitOption::WNOHANG) {
+                    return Ok(0);
+                }
+                for pcb in rd_childen.values() {
                     unsafe { pcb.wait_queue.sleep_without_schedule() };
                 }
+                // 睡眠之前释放锁，子进程退出时需要获取它
+                drop(rd_childen);
+                drop(irq_guard);
+                sched();
             }
-            drop(irq_guard);
-            sched();
         }
-
-        return Ok(0);
     }
 
     /// # 退出进程
@@ -173,10 +267,10 @@ impl Syscall {
         ProcessManager::exit(status);
     }
 
-    /// @brief 获取当前进程的pid
+    /// @brief 获取当前进程在它所在的PID命名空间中的pid
     pub fn getpid() -> Result<Pid, SystemError> {
         let current_pcb = ProcessManager::current_pcb();
-        return Ok(current_pcb.pid());
+        return Ok(current_pcb.vpid());
     }
 
     /// @brief 获取指定进程的pgid
@@ -185,19 +279,101 @@ impl Syscall {
     ///
     /// @return 成功，指定进程的进程组id
     /// @return 错误，不存在该进程
-    pub fn getpgid(mut pid: Pid) -> Result<Pid, SystemError> {
-        if pid == Pid(0) {
-            let current_pcb = ProcessManager::current_pcb();
-            pid = current_pcb.pid();
-        }
-        let target_proc = ProcessManager::find(pid).ok_or(SystemError::ESRCH)?;
-        return Ok(target_proc.basic().pgid());
+    pub fn getpgid(pid: Pid) -> Result<Pid, SystemError> {
+        let target_proc = if pid == Pid(0) {
+            ProcessManager::current_pcb()
+        } else {
+            ProcessManager::find_vpid(pid).ok_or(SystemError::ESRCH)?
+        };
+        let pgid = target_proc.basic().pgid();
+        return Ok(ProcessManager::pid_vnr(pgid));
     }
     /// @brief 获取当前进程的父进程id
 
     /// 若为initproc则ppid设置为0   
+    /// 父进程在当前进程所在的PID命名空间中不可见时(例如命名空间的1号进程)，也返回0
     pub fn getppid() -> Result<Pid, SystemError> {
         let current_pcb = ProcessManager::current_pcb();
-        return Ok(current_pcb.basic().ppid());
+        let ppid = current_pcb.basic().ppid();
+        return Ok(ProcessManager::pid_vnr(ppid));
+    }
+
+    /// @brief 获取当前进程的用户id
+    ///
+    /// 内核还没有区分真实用户id与有效用户id，geteuid也使用这个函数
+    pub fn getuid() -> Result<usize, SystemError> {
+        return Ok(ProcessManager::current_pcb().basic().uid() as usize);
+    }
+
+    /// @brief 设置当前进程的用户id
+    ///
+    /// @param uid 新的用户id
+    ///
+    /// @return 成功返回0；非root进程把uid设置为其他用户时返回EPERM
+    pub fn setuid(uid: u32) -> Result<usize, SystemError> {
+        let current_pcb = ProcessManager::current_pcb();
+        let old_uid = current_pcb.basic().uid();
+        let r = if old_uid != 0 && uid != old_uid {
+            Err(SystemError::EPERM)
+        } else {
+            current_pcb.basic_mut().set_uid(uid);
+            Ok(0)
+        };
+        audit_log(
+            AuditEvents::SETUID,
+            &r,
+            format_args!("old_uid={} new_uid={}", old_uid, uid),
+        );
+        return r;
+    }
+
+    /// @brief 获取或设置当前进程的执行域(personality)
+    ///
+    /// @param personality 新的执行域，为0xffffffff时只获取而不修改
+    ///
+    /// @return 修改之前的执行域
+    pub fn personality(personality: u32) -> Result<usize, SystemError> {
+        let current_pcb = ProcessManager::current_pcb();
+        let mut basic = current_pcb.basic_mut();
+        let old = basic.personality();
+        if personality != u32::MAX {
+            basic.set_personality(personality);
+        }
+        return Ok(old as usize);
+    }
+
+    /// @brief 对当前进程进行一些控制操作
+    ///
+    /// @param option 操作(PR_*)，目前支持seccomp与no_new_privs相关的操作
+    /// @param args 操作的参数
+    ///
+    /// @return 取决于具体的操作
+    pub fn prctl(option: usize, args: [usize; 4]) -> Result<usize, SystemError> {
+        match option {
+            PR_GET_SECCOMP => {
+                return Ok(ProcessManager::current_pcb().seccomp().mode() as usize);
+            }
+            PR_SET_SECCOMP => match args[0] {
+                SECCOMP_MODE_STRICT => return seccomp_set_mode_strict(),
+                SECCOMP_MODE_FILTER => {
+                    return seccomp_set_mode_filter(0, UserPtr::new(args[1]));
+                }
+                _ => return Err(SystemError::EINVAL),
+            },
+            PR_SET_NO_NEW_PRIVS => {
+                if args != [1, 0, 0, 0] {
+                    return Err(SystemError::EINVAL);
+                }
+                set_no_new_privs();
+                return Ok(0);
+            }
+            PR_GET_NO_NEW_PRIVS => {
+                if args != [0; 4] {
+                    return Err(SystemError::EINVAL);
+                }
+                return Ok(no_new_privs() as usize);
+            }
+            _ => return Err(SystemError::EINVAL),
+        }
     }
 }
@@ -186,6 +186,16 @@ impl SchedulerCFS {
         let queue = self.cpu_queue[cpu_id as usize].locked_queue.lock();
         return CFSQueue::get_cfs_queue_size(&queue);
     }
+
+    /// @brief 取出某个cpu的运行队列中的所有进程（cpu下线时使用）
+    pub fn drain_cpu_queue(&mut self, cpu_id: u32) -> Vec<Arc<ProcessControlBlock>> {
+        let mut queue = self.cpu_queue[cpu_id as usize].locked_queue.lock_irqsave();
+        let mut result = Vec::with_capacity(queue.len());
+        while let Some((_, pcb)) = queue.pop_first() {
+            result.push(pcb);
+        }
+        return result;
+    }
 }
 
 impl Scheduler for SchedulerCFS {
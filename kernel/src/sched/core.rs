@@ -7,7 +7,7 @@ use crate::{
     kinfo,
     mm::percpu::PerCpu,
     process::{AtomicPid, Pid, ProcessControlBlock, ProcessFlags, ProcessManager, ProcessState},
-    smp::core::smp_get_processor_id,
+    smp::{core::smp_get_processor_id, hotplug::cpu_online},
 };
 
 use super::rt::{sched_rt_init, SchedulerRT, __get_rt_scheduler};
@@ -63,21 +63,36 @@ pub fn loads_balance(pcb: Arc<ProcessControlBlock>) {
     // 对pcb的迁移情况进行调整
     // 获取总的CPU数量
     let cpu_num = unsafe { smp_get_total_cpu() };
-    // 获取当前负载最小的CPU的id
+    // 获取当前负载最小的在线CPU的id（当前CPU可能已经下线）
     let mut min_loads_cpu_id = smp_get_processor_id();
-    let mut min_loads = get_cpu_loads(smp_get_processor_id());
+    let mut min_loads = if cpu_online(min_loads_cpu_id as usize) {
+        get_cpu_loads(min_loads_cpu_id)
+    } else {
+        u32::MAX
+    };
     for cpu_id in 0..cpu_num {
+        if !cpu_online(cpu_id as usize) {
+            continue;
+        }
         let tmp_cpu_loads = get_cpu_loads(cpu_id);
-        if min_loads - tmp_cpu_loads > 0 {
+        if tmp_cpu_loads < min_loads {
             min_loads_cpu_id = cpu_id;
             min_loads = tmp_cpu_loads;
         }
     }
 
     let pcb_cpu = pcb.sched_info().on_cpu();
+    // 迁移的目标CPU在设置之后下线了，需要重新选择
+    let migrate_to_offline = pcb.flags().contains(ProcessFlags::NEED_MIGRATE)
+        && !pcb
+            .sched_info()
+            .migrate_to()
+            .map_or(false, |cpu| cpu_online(cpu as usize));
     // 将当前pcb迁移到负载最小的CPU
-    // 如果当前pcb的PF_NEED_MIGRATE已经置位，则不进行迁移操作
+    // 如果当前pcb的PF_NEED_MIGRATE已经置位，则不进行迁移操作；pcb所在的CPU已经下线时，必须迁移
     if pcb_cpu.is_none()
+        || !cpu_online(pcb_cpu.unwrap() as usize)
+        || migrate_to_offline
         || (min_loads_cpu_id != pcb_cpu.unwrap()
             && !pcb.flags().contains(ProcessFlags::NEED_MIGRATE))
     {
@@ -86,6 +101,22 @@ pub fn loads_balance(pcb: Arc<ProcessControlBlock>) {
         // kdebug!("set migrating, pcb:{:?}", pcb);
     }
 }
+/// @brief 把某个cpu的运行队列中的进程迁移到其他在线的cpu上
+///
+/// @param cpu_id 已经下线的cpu
+///
+/// @return 迁移的进程数量
+pub fn sched_migrate_tasks_from(cpu_id: u32) -> usize {
+    let mut pcbs = __get_cfs_scheduler().drain_cpu_queue(cpu_id);
+    pcbs.append(&mut __get_rt_scheduler().drain_cpu_queue(cpu_id));
+    let count = pcbs.len();
+    for pcb in pcbs {
+        // on_cpu指向已经下线的cpu，负载均衡会为它选择新的cpu
+        sched_enqueue(pcb, true);
+    }
+    return count;
+}
+
 /// @brief 具体的调度器应当实现的trait
 pub trait Scheduler {
     /// @brief 使用该调度器发起调度的时候，要调用的函数
@@ -144,6 +144,18 @@ impl SchedulerRT {
         return sum as usize;
     }
 
+    /// @brief 取出某个cpu的所有优先级队列中的进程（cpu下线时使用）
+    pub fn drain_cpu_queue(&mut self, cpu_id: u32) -> Vec<Arc<ProcessControlBlock>> {
+        let mut result = Vec::new();
+        for prio in 0..SchedulerRT::MAX_RT_PRIO {
+            let queue: &mut RTQueue = self.cpu_queue[cpu_id as usize][prio as usize];
+            while let Some(pcb) = queue.dequeue() {
+                result.push(pcb);
+            }
+        }
+        return result;
+    }
+
     #[allow(dead_code)]
     #[inline]
     pub fn load_list_len(&mut self, cpu_id: u32) -> usize {
@@ -1,6 +1,8 @@
 use crate::{
     arch::CurrentIrqArch,
+    debug::trace_event::trace_sched_switch,
     exception::InterruptArch,
+    perf::perf_event_task_sched,
     process::ProcessManager,
     smp::core::smp_get_processor_id,
     syscall::{Syscall, SystemError},
@@ -28,6 +30,8 @@ impl Syscall {
 
             if current_pcb.pid() != next_pcb.pid() {
                 CPU_EXECUTING.set(smp_get_processor_id(), next_pcb.pid());
+                trace_sched_switch(&current_pcb, &next_pcb);
+                perf_event_task_sched(next_pcb.pid());
                 unsafe { ProcessManager::switch_process(current_pcb, next_pcb) };
             }
         }
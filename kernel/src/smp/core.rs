@@ -3,3 +3,9 @@
 pub fn smp_get_processor_id() -> u32 {
     return crate::arch::cpu::current_cpu_id();
 }
+
+/// @brief 获取系统中cpu的数量
+#[inline]
+pub fn smp_cpu_count() -> usize {
+    return crate::arch::smp::cpu_count();
+}
@@ -0,0 +1,130 @@
+//! CPU热插拔
+//!
+//! 下线的cpu不再运行进程，也不再处理外部中断：投递到它的中断被改为投递到其他cpu，它运行队列中的进程被迁移到其他cpu，
+//! 它的高精度定时器被迁移到发起下线的cpu上，然后它的空闲进程停在低功耗的循环中，直到cpu重新上线。
+//!
+//! 通过`/proc/cpu/<cpu>/online`控制cpu的上线与下线。0号cpu(BSP)不能下线。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/cpu.c
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    arch::{sched::sched, CurrentIrqArch},
+    driver::cpuidle::cpu_idle_deepest,
+    exception::{irqdesc::irq_manager, InterruptArch},
+    kinfo, kwarn,
+    libs::mutex::Mutex,
+    mm::percpu::PerCpu,
+    sched::core::sched_migrate_tasks_from,
+    syscall::SystemError,
+    time::hrtimer::{hrtimer_migrate_from, tick_nohz_idle_enter, tick_nohz_restart},
+};
+
+use super::{
+    core::{smp_cpu_count, smp_get_processor_id},
+    kick_cpu,
+};
+
+const ATOMIC_FALSE: AtomicBool = AtomicBool::new(false);
+/// cpu是否已经被要求下线
+static CPU_OFFLINE: [AtomicBool; PerCpu::MAX_CPU_NUM] = [ATOMIC_FALSE; PerCpu::MAX_CPU_NUM];
+/// cpu的空闲进程是否已经停在低功耗循环中
+static CPU_PARKED: [AtomicBool; PerCpu::MAX_CPU_NUM] = [ATOMIC_FALSE; PerCpu::MAX_CPU_NUM];
+/// 串行化上线与下线操作
+static CPU_HOTPLUG_LOCK: Mutex<()> = Mutex::new(());
+
+/// cpu是否在线
+#[inline]
+pub fn cpu_online(cpu: usize) -> bool {
+    cpu < smp_cpu_count() && !CPU_OFFLINE[cpu].load(Ordering::SeqCst)
+}
+
+/// 让cpu下线，等到它停下来之后才返回
+///
+/// ## 错误
+///
+/// - `EINVAL`：cpu不存在
+/// - `EBUSY`：cpu是BSP，不能下线
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/cpu.c?fi=cpu_down#cpu_down
+pub fn cpu_down(cpu: usize) -> Result<(), SystemError> {
+    if cpu >= smp_cpu_count() {
+        return Err(SystemError::EINVAL);
+    }
+    // BSP负责全局的时钟与大部分外部中断，并且保证了总有一个在线的cpu
+    if cpu == 0 {
+        return Err(SystemError::EBUSY);
+    }
+
+    let _guard = CPU_HOTPLUG_LOCK.lock();
+    if CPU_OFFLINE[cpu].swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    // 把中断改为投递到其他cpu。之后新加入调度队列的进程也不会再被分配到这个cpu上
+    for irq in irq_manager().active_irqs() {
+        if irq_manager().affinity(irq) == Some(cpu) {
+            if let Err(e) = irq_manager().set_affinity(irq, 0) {
+                kwarn!("cpu {}: failed to migrate irq {}: {:?}", cpu, irq, e);
+            }
+        }
+    }
+
+    // 让目标cpu放弃当前的进程。当前进程自己就在目标cpu上时，会在这里被迁移走
+    kick_cpu(cpu as u32)?;
+    while !CPU_PARKED[cpu].load(Ordering::SeqCst) {
+        sched();
+    }
+
+    hrtimer_migrate_from(cpu);
+    kinfo!("cpu {} is now offline", cpu);
+    return Ok(());
+}
+
+/// 让下线的cpu重新上线
+///
+/// ## 错误
+///
+/// - `EINVAL`：cpu不存在
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/cpu.c?fi=cpu_up#cpu_up
+pub fn cpu_up(cpu: usize) -> Result<(), SystemError> {
+    if cpu >= smp_cpu_count() {
+        return Err(SystemError::EINVAL);
+    }
+
+    let _guard = CPU_HOTPLUG_LOCK.lock();
+    if !CPU_OFFLINE[cpu].swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    kick_cpu(cpu as u32)?;
+    while CPU_PARKED[cpu].load(Ordering::SeqCst) {
+        core::hint::spin_loop();
+    }
+    kinfo!("cpu {} is now online", cpu);
+    return Ok(());
+}
+
+/// 当前cpu已经被要求下线时，由空闲进程调用：迁移走运行队列中剩余的进程，然后停在最深的空闲状态中，直到cpu重新上线
+///
+/// 调用时中断必须已经关闭，返回时中断是打开的
+pub fn cpu_park() {
+    let cpu = smp_get_processor_id() as usize;
+
+    tick_nohz_idle_enter();
+    sched_migrate_tasks_from(cpu as u32);
+    CPU_PARKED[cpu].store(true, Ordering::SeqCst);
+
+    while CPU_OFFLINE[cpu].load(Ordering::SeqCst) {
+        cpu_idle_deepest();
+        unsafe { CurrentIrqArch::interrupt_disable() };
+        // 与下线同时发生的入队操作，仍然可能把进程加入这个cpu的队列
+        sched_migrate_tasks_from(cpu as u32);
+    }
+
+    CPU_PARKED[cpu].store(false, Ordering::SeqCst);
+    tick_nohz_restart();
+    unsafe { CurrentIrqArch::interrupt_enable() };
+}
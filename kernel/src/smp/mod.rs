@@ -4,11 +4,16 @@ use crate::{
     syscall::SystemError,
 };
 
+use self::core::smp_cpu_count;
+
 pub mod c_adapter;
 pub mod core;
+pub mod hotplug;
 
 pub fn kick_cpu(cpu_id: u32) -> Result<(), SystemError> {
-    // todo: 增加对cpu_id的有效性检查
+    if cpu_id as usize >= smp_cpu_count() {
+        return Err(SystemError::EINVAL);
+    }
 
     send_ipi(IpiKind::KickCpu, IpiTarget::Specified(cpu_id as usize));
     return Ok(());
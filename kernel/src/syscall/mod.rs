@@ -1,5 +1,5 @@
 use core::{
-    ffi::{c_char, c_int, c_void, CStr},
+    ffi::{c_int, c_void},
     sync::atomic::{AtomicBool, Ordering},
 };
 
@@ -8,27 +8,38 @@ use crate::kdebug;
 use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::{
-    arch::{cpu::cpu_reset, interrupt::TrapFrame, MMArch},
+    arch::{interrupt::TrapFrame, ipc::signal::SigSet, MMArch},
     driver::base::{block::SeekFrom, device::DeviceNumber},
-    filesystem::vfs::{
-        fcntl::FcntlCommand,
-        file::FileMode,
-        syscall::{ModeType, PosixKstat, SEEK_CUR, SEEK_END, SEEK_MAX, SEEK_SET},
-        MAX_PATHLEN,
+    filesystem::{
+        ramfs::memfd::MFD_NAME_MAX_LEN,
+        vfs::{
+            fcntl::{FcntlCommand, AT_FDCWD, AT_SYMLINK_NOFOLLOW},
+            file::FileMode,
+            poll::PollFd,
+            syscall::{ModeType, PosixKstat, SEEK_CUR, SEEK_END, SEEK_MAX, SEEK_SET},
+            MAX_PATHLEN,
+        },
     },
-    include::bindings::bindings::{PAGE_2M_SIZE, PAGE_4K_SIZE},
+    include::bindings::bindings::PAGE_4K_SIZE,
+    io_uring::IoUringParams,
+    ipc::mqueue::MQUEUE_MAX_NAMELEN,
     kinfo,
     libs::align::page_align_up,
     mm::{verify_area, MemoryManagementArch, VirtAddr},
     net::syscall::SockAddr,
-    process::Pid,
+    perf::PerfEventAttr,
+    process::{resource::RLimit64, Pid, ProcessManager},
     time::{
+        ntp::PosixTimex,
+        posix_timer::{PosixItimerspec, PosixItimerval, PosixSigevent},
         syscall::{PosixTimeZone, PosixTimeval},
         TimeSpec,
     },
 };
 
-use self::user_access::{UserBufferReader, UserBufferWriter};
+use self::user_access::{
+    check_and_clone_cstr, copy_from_user, copy_to_user, UserBufferReader, UserBufferWriter, UserPtr,
+};
 
 pub mod user_access;
 
@@ -309,6 +320,8 @@ pub enum SystemError {
     EVMPRTLDFailed = 135,
     EVMLAUNCHFailed = 136,
     KVM_HVA_ERR_BAD = 137,
+    /// 驱动要求稍后重新探测设备（仅在内核内部使用） Driver requests probe retry
+    EPROBE_DEFER = 517,
 }
 
 impl SystemError {
@@ -332,11 +345,10 @@ pub const SYS_READ: usize = 0;
 pub const SYS_WRITE: usize = 1;
 pub const SYS_OPEN: usize = 2;
 pub const SYS_CLOSE: usize = 3;
-#[allow(dead_code)]
 pub const SYS_STAT: usize = 4;
 pub const SYS_FSTAT: usize = 5;
+pub const SYS_LSTAT: usize = 6;
 
-#[allow(dead_code)]
 pub const SYS_POLL: usize = 7;
 pub const SYS_LSEEK: usize = 8;
 pub const SYS_MMAP: usize = 9;
@@ -353,12 +365,22 @@ pub const SYS_IOCTL: usize = 16;
 #[allow(dead_code)]
 pub const SYS_WRITEV: usize = 20;
 
+pub const SYS_SELECT: usize = 23;
+
+pub const SYS_SHMGET: usize = 29;
+pub const SYS_SHMAT: usize = 30;
+pub const SYS_SHMCTL: usize = 31;
+
 pub const SYS_DUP: usize = 32;
 pub const SYS_DUP2: usize = 33;
 
 pub const SYS_NANOSLEEP: usize = 35;
+pub const SYS_GETITIMER: usize = 36;
+
+pub const SYS_SETITIMER: usize = 38;
 
 pub const SYS_GETPID: usize = 39;
+pub const SYS_SENDFILE: usize = 40;
 
 pub const SYS_SOCKET: usize = 41;
 pub const SYS_CONNECT: usize = 42;
@@ -372,11 +394,10 @@ pub const SYS_BIND: usize = 49;
 pub const SYS_LISTEN: usize = 50;
 pub const SYS_GETSOCKNAME: usize = 51;
 pub const SYS_GETPEERNAME: usize = 52;
-
+pub const SYS_SOCKETPAIR: usize = 53;
 pub const SYS_SETSOCKOPT: usize = 54;
 pub const SYS_GETSOCKOPT: usize = 55;
 
-#[allow(dead_code)]
 pub const SYS_CLONE: usize = 56;
 pub const SYS_FORK: usize = 57;
 pub const SYS_VFORK: usize = 58;
@@ -385,6 +406,15 @@ pub const SYS_EXIT: usize = 60;
 pub const SYS_WAIT4: usize = 61;
 pub const SYS_KILL: usize = 62;
 
+pub const SYS_SEMGET: usize = 64;
+pub const SYS_SEMOP: usize = 65;
+pub const SYS_SEMCTL: usize = 66;
+pub const SYS_SHMDT: usize = 67;
+pub const SYS_MSGGET: usize = 68;
+pub const SYS_MSGSND: usize = 69;
+pub const SYS_MSGRCV: usize = 70;
+pub const SYS_MSGCTL: usize = 71;
+
 pub const SYS_FCNTL: usize = 72;
 
 pub const SYS_FTRUNCATE: usize = 77;
@@ -397,20 +427,40 @@ pub const SYS_CHDIR: usize = 80;
 pub const SYS_MKDIR: usize = 83;
 
 pub const SYS_GETTIMEOFDAY: usize = 96;
+pub const SYS_GETRLIMIT: usize = 97;
+
+pub const SYS_PTRACE: usize = 101;
+pub const SYS_GETUID: usize = 102;
+pub const SYS_SYSLOG: usize = 103;
+pub const SYS_SETUID: usize = 105;
+pub const SYS_GETEUID: usize = 107;
 
 #[allow(dead_code)]
 pub const SYS_SIGALTSTACK: usize = 131;
 
+pub const SYS_PIVOT_ROOT: usize = 155;
+pub const SYS_PRCTL: usize = 157;
 #[allow(dead_code)]
 pub const SYS_ARCH_PRCTL: usize = 158;
+pub const SYS_ADJTIMEX: usize = 159;
+pub const SYS_SETRLIMIT: usize = 160;
+pub const SYS_CHROOT: usize = 161;
+
+pub const SYS_SETTIMEOFDAY: usize = 164;
+pub const SYS_MOUNT: usize = 165;
 
 pub const SYS_REBOOT: usize = 169;
 
+pub const SYS_INIT_MODULE: usize = 175;
+pub const SYS_DELETE_MODULE: usize = 176;
+
 pub const SYS_GETPPID: usize = 110;
 pub const SYS_GETPGID: usize = 121;
 
 pub const SYS_MKNOD: usize = 133;
 
+pub const SYS_PERSONALITY: usize = 135;
+
 #[allow(dead_code)]
 pub const SYS_TKILL: usize = 200;
 
@@ -421,12 +471,53 @@ pub const SYS_GET_DENTS_64: usize = 217;
 #[allow(dead_code)]
 pub const SYS_SET_TID_ADDR: usize = 218;
 
+pub const SYS_SEMTIMEDOP: usize = 220;
+
+pub const SYS_TIMER_CREATE: usize = 222;
+pub const SYS_TIMER_SETTIME: usize = 223;
+pub const SYS_TIMER_GETTIME: usize = 224;
+pub const SYS_TIMER_GETOVERRUN: usize = 225;
+pub const SYS_TIMER_DELETE: usize = 226;
+
+pub const SYS_CLOCK_SETTIME: usize = 227;
+pub const SYS_CLOCK_GETTIME: usize = 228;
+
+pub const SYS_MQ_OPEN: usize = 240;
+pub const SYS_MQ_UNLINK: usize = 241;
+pub const SYS_MQ_TIMEDSEND: usize = 242;
+pub const SYS_MQ_TIMEDRECEIVE: usize = 243;
+pub const SYS_MQ_NOTIFY: usize = 244;
+pub const SYS_MQ_GETSETATTR: usize = 245;
+
+pub const SYS_OPENAT: usize = 257;
+pub const SYS_MKDIRAT: usize = 258;
+pub const SYS_NEWFSTATAT: usize = 262;
 pub const SYS_UNLINK_AT: usize = 263;
 
+pub const SYS_PSELECT6: usize = 270;
+pub const SYS_PPOLL: usize = 271;
+pub const SYS_UNSHARE: usize = 272;
+
+pub const SYS_DUP3: usize = 292;
 pub const SYS_PIPE: usize = 293;
 
+pub const SYS_PERF_EVENT_OPEN: usize = 298;
+
+pub const SYS_PRLIMIT64: usize = 302;
+
+pub const SYS_SECCOMP: usize = 317;
 #[allow(dead_code)]
 pub const SYS_GET_RANDOM: usize = 318;
+pub const SYS_MEMFD_CREATE: usize = 319;
+
+pub const SYS_KEXEC_FILE_LOAD: usize = 320;
+
+pub const SYS_COPY_FILE_RANGE: usize = 326;
+
+pub const SYS_IO_URING_SETUP: usize = 425;
+pub const SYS_IO_URING_ENTER: usize = 426;
+
+pub const SYS_CLOSE_RANGE: usize = 436;
 
 // 与linux不一致的调用，在linux基础上累加
 pub const SYS_PUT_STRING: usize = 100000;
@@ -474,19 +565,16 @@ impl Syscall {
                 Self::put_string(args[0] as *const u8, args[1] as u32, args[2] as u32)
             }
             SYS_OPEN => {
-                let path: &CStr = unsafe { CStr::from_ptr(args[0] as *const c_char) };
-                let path: Result<&str, core::str::Utf8Error> = path.to_str();
-                let res = if path.is_err() {
-                    Err(SystemError::EINVAL)
-                } else {
-                    let path: &str = path.unwrap();
-
-                    let flags = args[1];
-                    let open_flags: FileMode = FileMode::from_bits_truncate(flags as u32);
-                    Self::open(path, open_flags)
-                };
-
-                res
+                let path = check_and_clone_cstr(args[0] as *const u8, Some(MAX_PATHLEN))?;
+                let flags = args[1];
+                let open_flags: FileMode = FileMode::from_bits_truncate(flags as u32);
+                Self::open(&path, open_flags)
+            }
+            SYS_OPENAT => {
+                let dirfd = args[0] as i32;
+                let path = check_and_clone_cstr(args[1] as *const u8, Some(MAX_PATHLEN))?;
+                let open_flags: FileMode = FileMode::from_bits_truncate(args[2] as u32);
+                Self::openat(dirfd, &path, open_flags)
             }
             SYS_CLOSE => {
                 let fd = args[0];
@@ -499,25 +587,28 @@ impl Syscall {
                 let fd = args[0] as i32;
                 let buf_vaddr = args[1];
                 let len = args[2];
-                let from_user = frame.from_user();
-                let mut user_buffer_writer =
-                    UserBufferWriter::new(buf_vaddr as *mut u8, len, from_user)?;
-
-                let user_buf = user_buffer_writer.buffer(0)?;
-                let res = Self::read(fd, user_buf);
-                res
+                if frame.from_user() {
+                    // 文件系统只读写内核缓冲区，再由copy_to_user拷贝到用户空间
+                    Self::read_to_user(fd, VirtAddr::new(buf_vaddr), len)
+                } else {
+                    let mut user_buffer_writer =
+                        UserBufferWriter::new(buf_vaddr as *mut u8, len, false)?;
+                    let user_buf = user_buffer_writer.buffer(0)?;
+                    Self::read(fd, user_buf)
+                }
             }
             SYS_WRITE => {
                 let fd = args[0] as i32;
                 let buf_vaddr = args[1];
                 let len = args[2];
-                let from_user = frame.from_user();
-                let user_buffer_reader =
-                    UserBufferReader::new(buf_vaddr as *const u8, len, from_user)?;
-
-                let user_buf = user_buffer_reader.read_from_user(0)?;
-                let res = Self::write(fd, user_buf);
-                res
+                if frame.from_user() {
+                    Self::write_from_user(fd, VirtAddr::new(buf_vaddr), len)
+                } else {
+                    let user_buffer_reader =
+                        UserBufferReader::new(buf_vaddr as *const u8, len, false)?;
+                    let user_buf = user_buffer_reader.read_from_user(0)?;
+                    Self::write(fd, user_buf)
+                }
             }
 
             SYS_LSEEK => {
@@ -543,8 +634,45 @@ impl Syscall {
                 Self::ioctl(fd, cmd as u32, data)
             }
 
+            SYS_POLL => {
+                let fds = UserPtr::<PollFd>::new(args[0]);
+                Self::poll(fds, args[1], args[2] as i32)
+            }
+
+            SYS_PPOLL => {
+                let fds = UserPtr::<PollFd>::new(args[0]);
+                let tsp = UserPtr::<TimeSpec>::new(args[2]);
+                let sigmask = UserPtr::<SigSet>::new(args[3]);
+                Self::ppoll(fds, args[1], tsp, sigmask, args[4])
+            }
+
+            SYS_SELECT => {
+                let timeout = UserPtr::<PosixTimeval>::new(args[4]);
+                Self::select(
+                    args[0] as i32,
+                    UserPtr::new(args[1]),
+                    UserPtr::new(args[2]),
+                    UserPtr::new(args[3]),
+                    timeout,
+                )
+            }
+
+            SYS_PSELECT6 => {
+                let tsp = UserPtr::<TimeSpec>::new(args[4]);
+                Self::pselect6(
+                    args[0] as i32,
+                    UserPtr::new(args[1]),
+                    UserPtr::new(args[2]),
+                    UserPtr::new(args[3]),
+                    tsp,
+                    UserPtr::new(args[5]),
+                )
+            }
+
             SYS_FORK => Self::fork(frame),
             SYS_VFORK => Self::vfork(frame),
+            SYS_CLONE => Self::clone(frame, args[0] as u64, args[1], args[4]),
+            SYS_UNSHARE => Self::unshare(args[0]),
 
             SYS_BRK => {
                 let new_brk = VirtAddr::new(args[0]);
@@ -556,7 +684,9 @@ impl Syscall {
                 Self::sbrk(increment).map(|vaddr: VirtAddr| vaddr.data())
             }
 
-            SYS_REBOOT => Self::reboot(),
+            SYS_REBOOT => Self::reboot(args[0] as u32, args[1] as u32, args[2] as u32, args[3]),
+
+            SYS_SYSLOG => Self::syslog(args[0] as i32, args[1] as *mut u8, args[2] as i32),
 
             SYS_CHDIR => {
                 // Closure for checking arguments
@@ -564,17 +694,7 @@ impl Syscall {
                     if arg0 == 0 {
                         return Err(SystemError::EFAULT);
                     }
-                    let path_ptr = arg0 as *const c_char;
-                    let virt_addr = VirtAddr::new(path_ptr as usize);
-                    // 权限校验
-                    if path_ptr.is_null()
-                        || (frame.from_user()
-                            && verify_area(virt_addr, PAGE_2M_SIZE as usize).is_err())
-                    {
-                        return Err(SystemError::EINVAL);
-                    }
-                    let dest_path: &CStr = unsafe { CStr::from_ptr(path_ptr) };
-                    let dest_path: &str = dest_path.to_str().map_err(|_| SystemError::EINVAL)?;
+                    let dest_path = check_and_clone_cstr(arg0 as *const u8, Some(MAX_PATHLEN + 1))?;
                     if dest_path.len() == 0 {
                         return Err(SystemError::EINVAL);
                     } else if dest_path.len() > MAX_PATHLEN as usize {
@@ -585,29 +705,52 @@ impl Syscall {
                 };
 
                 let r = chdir_check(args[0])?;
-                Self::chdir(r)
+                Self::chdir(&r)
+            }
+
+            SYS_CHROOT => {
+                let path = check_and_clone_cstr(args[0] as *const u8, Some(MAX_PATHLEN))?;
+                Self::chroot(&path)
+            }
+
+            SYS_MOUNT => {
+                let source = check_and_clone_cstr(args[0] as *const u8, Some(MAX_PATHLEN))?;
+                let target = check_and_clone_cstr(args[1] as *const u8, Some(MAX_PATHLEN))?;
+                let fstype = check_and_clone_cstr(args[2] as *const u8, Some(MAX_PATHLEN))?;
+                let data = if args[4] != 0 {
+                    Some(check_and_clone_cstr(
+                        args[4] as *const u8,
+                        Some(MAX_PATHLEN),
+                    )?)
+                } else {
+                    None
+                };
+                Self::mount(&source, &target, &fstype, data.as_deref())
+            }
+
+            SYS_PIVOT_ROOT => {
+                let new_root = check_and_clone_cstr(args[0] as *const u8, Some(MAX_PATHLEN))?;
+                let put_old = check_and_clone_cstr(args[1] as *const u8, Some(MAX_PATHLEN))?;
+                Self::pivot_root(&new_root, &put_old)
             }
 
             SYS_GET_DENTS | SYS_GET_DENTS_64 => {
                 let fd = args[0] as i32;
-
                 let buf_vaddr = args[1];
                 let len = args[2];
-                let virt_addr: VirtAddr = VirtAddr::new(buf_vaddr);
-                // 判断缓冲区是否来自用户态，进行权限校验
-                let res = if frame.from_user() && verify_area(virt_addr, len as usize).is_err() {
-                    // 来自用户态，而buffer在内核态，这样的操作不被允许
-                    Err(SystemError::EPERM)
-                } else if buf_vaddr == 0 {
-                    Err(SystemError::EFAULT)
+                let dirent64 = syscall_num == SYS_GET_DENTS_64;
+                if frame.from_user() {
+                    Self::getdents_to_user(fd, VirtAddr::new(buf_vaddr), len, dirent64)
                 } else {
-                    let buf: &mut [u8] = unsafe {
-                        core::slice::from_raw_parts_mut::<'static, u8>(buf_vaddr as *mut u8, len)
-                    };
-                    Self::getdents(fd, buf)
-                };
-
-                res
+                    let mut user_buffer_writer =
+                        UserBufferWriter::new(buf_vaddr as *mut u8, len, false)?;
+                    let user_buf = user_buffer_writer.buffer(0)?;
+                    if dirent64 {
+                        Self::getdents64(fd, user_buf)
+                    } else {
+                        Self::getdents(fd, user_buf)
+                    }
+                }
             }
 
             SYS_EXECVE => {
@@ -649,20 +792,14 @@ impl Syscall {
                 Self::exit(exit_code)
             }
             SYS_MKDIR => {
-                let path_ptr = args[0] as *const c_char;
+                let path_ptr = args[0] as *const u8;
                 let mode = args[1];
-                let virt_path_ptr = VirtAddr::new(path_ptr as usize);
                 let security_check = || {
-                    if path_ptr.is_null()
-                        || (frame.from_user()
-                            && verify_area(virt_path_ptr, PAGE_2M_SIZE as usize).is_err())
-                    {
+                    if path_ptr.is_null() {
                         return Err(SystemError::EINVAL);
                     }
-                    let path: &CStr = unsafe { CStr::from_ptr(path_ptr) };
-                    let path: &str = path.to_str().map_err(|_| SystemError::EINVAL)?.trim();
-
-                    if path == "" {
+                    let path = check_and_clone_cstr(path_ptr, Some(MAX_PATHLEN))?;
+                    if path.trim() == "" {
                         return Err(SystemError::EINVAL);
                     }
                     return Ok(path);
@@ -672,26 +809,64 @@ impl Syscall {
                 if path.is_err() {
                     Err(path.unwrap_err())
                 } else {
-                    Self::mkdir(path.unwrap(), mode)
+                    Self::mkdir(path.unwrap().trim(), mode)
                 }
             }
+            SYS_MKDIRAT => {
+                let dirfd = args[0] as i32;
+                let path = check_and_clone_cstr(args[1] as *const u8, Some(MAX_PATHLEN))?;
+                Self::mkdirat(dirfd, path.trim(), args[2])
+            }
 
             SYS_NANOSLEEP => {
-                let req = args[0] as *const TimeSpec;
-                let rem = args[1] as *mut TimeSpec;
-                let virt_req = VirtAddr::new(req as usize);
-                let virt_rem = VirtAddr::new(rem as usize);
-                if frame.from_user()
-                    && (verify_area(virt_req, core::mem::size_of::<TimeSpec>() as usize).is_err()
-                        || verify_area(virt_rem, core::mem::size_of::<TimeSpec>() as usize)
-                            .is_err())
-                {
-                    Err(SystemError::EFAULT)
-                } else {
-                    Self::nanosleep(req, rem)
-                }
+                let req = UserPtr::<TimeSpec>::new(args[0]);
+                let rem = UserPtr::<TimeSpec>::new(args[1]);
+                Self::nanosleep(req, rem)
             }
 
+            SYS_GETITIMER => {
+                let curr_value = UserPtr::<PosixItimerval>::new(args[1]);
+                Self::getitimer(args[0] as i32, curr_value)
+            }
+
+            SYS_SETITIMER => {
+                let new_value = UserPtr::<PosixItimerval>::new(args[1]);
+                let old_value = UserPtr::<PosixItimerval>::new(args[2]);
+                Self::setitimer(args[0] as i32, new_value, old_value)
+            }
+
+            SYS_TIMER_CREATE => {
+                let sevp = UserPtr::<PosixSigevent>::new(args[1]);
+                let timerid = UserPtr::<i32>::new(args[2]);
+                Self::timer_create(args[0] as i32, sevp, timerid)
+            }
+
+            SYS_TIMER_SETTIME => {
+                let new_value = UserPtr::<PosixItimerspec>::new(args[2]);
+                let old_value = UserPtr::<PosixItimerspec>::new(args[3]);
+                Self::timer_settime(args[0] as i32, args[1] as i32, new_value, old_value)
+            }
+
+            SYS_TIMER_GETTIME => {
+                let curr_value = UserPtr::<PosixItimerspec>::new(args[1]);
+                Self::timer_gettime(args[0] as i32, curr_value)
+            }
+
+            SYS_TIMER_GETOVERRUN => Self::timer_getoverrun(args[0] as i32),
+            SYS_TIMER_DELETE => Self::timer_delete(args[0] as i32),
+
+            SYS_CLOCK_GETTIME => {
+                let tp = UserPtr::<TimeSpec>::new(args[1]);
+                Self::clock_gettime(args[0] as i32, tp)
+            }
+
+            SYS_CLOCK_SETTIME => {
+                let tp = UserPtr::<TimeSpec>::new(args[1]);
+                Self::clock_settime(args[0] as i32, tp)
+            }
+
+            SYS_ADJTIMEX => Self::adjtimex(UserPtr::<PosixTimex>::new(args[0])),
+
             SYS_CLOCK => Self::clock(),
             SYS_PIPE => {
                 let pipefd: *mut i32 = args[0] as *mut c_int;
@@ -704,31 +879,57 @@ impl Syscall {
                 }
             }
 
+            SYS_PERF_EVENT_OPEN => Self::perf_event_open(
+                args[0] as *const PerfEventAttr,
+                args[1] as i32,
+                args[2] as i32,
+                args[3] as i32,
+                args[4],
+            ),
+
+            SYS_IO_URING_SETUP => {
+                Self::io_uring_setup(args[0] as u32, args[1] as *mut IoUringParams)
+            }
+            SYS_IO_URING_ENTER => Self::io_uring_enter(
+                args[0] as i32,
+                args[1] as u32,
+                args[2] as u32,
+                args[3] as u32,
+            ),
+
+            SYS_INIT_MODULE => {
+                Self::init_module(args[0] as *const u8, args[1], args[2] as *const u8)
+            }
+            SYS_DELETE_MODULE => Self::delete_module(args[0] as *const u8, args[1] as u32),
+
+            SYS_KEXEC_FILE_LOAD => Self::kexec_file_load(
+                args[0] as i32,
+                args[1] as i32,
+                args[2],
+                args[3] as *const u8,
+                args[4],
+            ),
+
             SYS_UNLINK_AT => {
                 let dirfd = args[0] as i32;
-                let pathname = args[1] as *const c_char;
+                let pathname = args[1] as *const u8;
                 let flags = args[2] as u32;
-                let virt_pathname = VirtAddr::new(pathname as usize);
-                if frame.from_user() && verify_area(virt_pathname, PAGE_4K_SIZE as usize).is_err() {
-                    Err(SystemError::EFAULT)
-                } else if pathname.is_null() {
+                if pathname.is_null() {
                     Err(SystemError::EFAULT)
                 } else {
                     let get_path = || {
-                        let pathname: &CStr = unsafe { CStr::from_ptr(pathname) };
-
-                        let pathname: &str = pathname.to_str().map_err(|_| SystemError::EINVAL)?;
+                        let pathname = check_and_clone_cstr(pathname, Some(MAX_PATHLEN))?;
                         if pathname.len() >= MAX_PATHLEN {
                             return Err(SystemError::ENAMETOOLONG);
                         }
-                        return Ok(pathname.trim());
+                        return Ok(pathname);
                     };
                     let pathname = get_path();
                     if pathname.is_err() {
                         Err(pathname.unwrap_err())
                     } else {
                         // kdebug!("sys unlinkat: dirfd: {}, pathname: {}", dirfd, pathname.as_ref().unwrap());
-                        Self::unlinkat(dirfd, pathname.unwrap(), flags)
+                        Self::unlinkat(dirfd, pathname.unwrap().trim(), flags)
                     }
                 }
             }
@@ -736,7 +937,15 @@ impl Syscall {
                 let pid = Pid::new(args[0]);
                 let sig = args[1] as c_int;
                 // kdebug!("KILL SYSCALL RECEIVED");
-                Self::kill(pid, sig)
+                // 把当前进程所在的PID命名空间中的pid转换为全局pid
+                if (args[0] as i64) > 0 {
+                    match ProcessManager::find_vpid(pid) {
+                        Some(pcb) => Self::kill(pcb.pid(), sig),
+                        None => Err(SystemError::ESRCH),
+                    }
+                } else {
+                    Self::kill(pid, sig)
+                }
             }
 
             SYS_SIGACTION => {
@@ -764,47 +973,101 @@ impl Syscall {
                 let newfd: i32 = args[1] as c_int;
                 Self::dup2(oldfd, newfd)
             }
+            SYS_DUP3 => {
+                let oldfd: i32 = args[0] as c_int;
+                let newfd: i32 = args[1] as c_int;
+                Self::dup3(oldfd, newfd, args[2] as u32)
+            }
+            SYS_CLOSE_RANGE => Self::close_range(args[0] as u32, args[1] as u32, args[2] as u32),
 
-            SYS_SOCKET => Self::socket(args[0], args[1], args[2]),
-            SYS_SETSOCKOPT => {
-                let optval = args[3] as *const u8;
-                let optlen = args[4] as usize;
-                let virt_optval = VirtAddr::new(optval as usize);
-                // 验证optval的地址是否合法
-                if verify_area(virt_optval, optlen as usize).is_err() {
-                    // 地址空间超出了用户空间的范围，不合法
+            SYS_MEMFD_CREATE => {
+                let name_ptr = args[0] as *const u8;
+                if name_ptr.is_null() {
                     Err(SystemError::EFAULT)
                 } else {
-                    let data: &[u8] = unsafe { core::slice::from_raw_parts(optval, optlen) };
-                    Self::setsockopt(args[0], args[1], args[2], data)
+                    // 多拷贝一个字节，用于判断名字是否过长
+                    let name = check_and_clone_cstr(name_ptr, Some(MFD_NAME_MAX_LEN + 1))?;
+                    Self::memfd_create(&name, args[1] as u32)
                 }
             }
-            SYS_GETSOCKOPT => {
-                let optval = args[3] as *mut u8;
-                let optlen = args[4] as *mut usize;
-                let virt_optval = VirtAddr::new(optval as usize);
-                let virt_optlen = VirtAddr::new(optlen as usize);
-                let security_check = || {
-                    // 验证optval的地址是否合法
-                    if verify_area(virt_optval, PAGE_4K_SIZE as usize).is_err() {
-                        // 地址空间超出了用户空间的范围，不合法
-                        return Err(SystemError::EFAULT);
-                    }
-
-                    // 验证optlen的地址是否合法
-                    if verify_area(virt_optlen, core::mem::size_of::<u32>() as usize).is_err() {
-                        // 地址空间超出了用户空间的范围，不合法
-                        return Err(SystemError::EFAULT);
-                    }
-                    return Ok(());
-                };
-                let r = security_check();
-                if r.is_err() {
-                    Err(r.unwrap_err())
+            SYS_SENDFILE => Self::sendfile(
+                args[0] as i32,
+                args[1] as i32,
+                UserPtr::new(args[2]),
+                args[3],
+            ),
+            SYS_COPY_FILE_RANGE => Self::copy_file_range(
+                args[0] as i32,
+                UserPtr::new(args[1]),
+                args[2] as i32,
+                UserPtr::new(args[3]),
+                args[4],
+                args[5] as u32,
+            ),
+
+            SYS_SHMGET => Self::shmget(args[0] as i32, args[1], args[2] as u32),
+            SYS_SHMAT => Self::shmat(args[0] as i32, args[1], args[2] as u32),
+            SYS_SHMDT => Self::shmdt(VirtAddr::new(args[0])),
+            SYS_SHMCTL => Self::shmctl(args[0] as i32, args[1] as i32, args[2]),
+            SYS_SEMGET => Self::semget(args[0] as i32, args[1] as i32, args[2] as u32),
+            SYS_SEMOP => Self::semtimedop(args[0] as i32, args[1], args[2], 0),
+            SYS_SEMTIMEDOP => Self::semtimedop(args[0] as i32, args[1], args[2], args[3]),
+            SYS_SEMCTL => Self::semctl(args[0] as i32, args[1] as i32, args[2] as i32, args[3]),
+            SYS_MSGGET => Self::msgget(args[0] as i32, args[1] as u32),
+            SYS_MSGSND => Self::msgsnd(args[0] as i32, args[1], args[2], args[3] as u32),
+            SYS_MSGRCV => Self::msgrcv(
+                args[0] as i32,
+                args[1],
+                args[2],
+                args[3] as i64,
+                args[4] as u32,
+            ),
+            SYS_MSGCTL => Self::msgctl(args[0] as i32, args[1] as i32, args[2]),
+
+            SYS_MQ_OPEN => {
+                let name_ptr = args[0] as *const u8;
+                if name_ptr.is_null() {
+                    Err(SystemError::EFAULT)
+                } else {
+                    // 多拷贝一个字节，用于判断名字是否过长
+                    let name = check_and_clone_cstr(name_ptr, Some(MQUEUE_MAX_NAMELEN + 1))?;
+                    Self::mq_open(&name, args[1] as u32, args[2] as u32, args[3])
+                }
+            }
+            SYS_MQ_UNLINK => {
+                let name_ptr = args[0] as *const u8;
+                if name_ptr.is_null() {
+                    Err(SystemError::EFAULT)
                 } else {
-                    Self::getsockopt(args[0], args[1], args[2], optval, optlen as *mut u32)
+                    let name = check_and_clone_cstr(name_ptr, Some(MQUEUE_MAX_NAMELEN + 1))?;
+                    Self::mq_unlink(&name)
                 }
             }
+            SYS_MQ_TIMEDSEND => {
+                Self::mq_timedsend(args[0] as i32, args[1], args[2], args[3] as u32, args[4])
+            }
+            SYS_MQ_TIMEDRECEIVE => {
+                Self::mq_timedreceive(args[0] as i32, args[1], args[2], args[3], args[4])
+            }
+            SYS_MQ_NOTIFY => Self::mq_notify(args[0] as i32, args[1]),
+            SYS_MQ_GETSETATTR => Self::mq_getsetattr(args[0] as i32, args[1], args[2]),
+
+            SYS_SOCKET => Self::socket(args[0], args[1], args[2]),
+            SYS_SETSOCKOPT => {
+                let optval = args[3];
+                let optlen = args[4] as usize;
+                // 把选项值拷贝到内核空间
+                let mut data = vec![0u8; optlen];
+                unsafe { copy_from_user(&mut data, VirtAddr::new(optval)) }?;
+                Self::setsockopt(args[0], args[1], args[2], &data)
+            }
+            SYS_GETSOCKOPT => {
+                // optval和optlen的地址在拷贝时检查
+                let optval = args[3] as *mut u8;
+                let optlen = args[4] as *mut u32;
+                Self::getsockopt(args[0], args[1], args[2], optval, optlen)
+            }
+            SYS_SOCKETPAIR => Self::socketpair(args[0], args[1], args[2], args[3] as *mut i32),
 
             SYS_CONNECT => {
                 let addr = args[1] as *const SockAddr;
@@ -837,18 +1100,16 @@ impl Syscall {
                 let flags = args[3] as u32;
                 let addr = args[4] as *const SockAddr;
                 let addrlen = args[5] as usize;
-                let virt_buf = VirtAddr::new(buf as usize);
                 let virt_addr = VirtAddr::new(addr as usize);
-                // 验证buf的地址是否合法
-                if verify_area(virt_buf, len as usize).is_err() {
-                    // 地址空间超出了用户空间的范围，不合法
-                    Err(SystemError::EFAULT)
-                } else if verify_area(virt_addr, addrlen as usize).is_err() {
+                // 验证addr的地址是否合法
+                if verify_area(virt_addr, addrlen as usize).is_err() {
                     // 地址空间超出了用户空间的范围，不合法
                     Err(SystemError::EFAULT)
                 } else {
-                    let data: &[u8] = unsafe { core::slice::from_raw_parts(buf, len) };
-                    Self::sendto(args[0], data, flags, addr, addrlen)
+                    // 把要发送的数据拷贝到内核空间
+                    let mut data = vec![0u8; len];
+                    unsafe { copy_from_user(&mut data, VirtAddr::new(buf as usize)) }?;
+                    Self::sendto(args[0], &data, flags, addr, addrlen)
                 }
             }
 
@@ -884,30 +1145,17 @@ impl Syscall {
                 if r.is_err() {
                     Err(r.unwrap_err())
                 } else {
-                    let buf = unsafe { core::slice::from_raw_parts_mut(buf, len) };
-                    Self::recvfrom(args[0], buf, flags, addr, addrlen as *mut u32)
+                    // 先接收到内核缓冲区，再拷贝到用户空间
+                    let mut kbuf = vec![0u8; len];
+                    Self::recvfrom(args[0], &mut kbuf, flags, addr, addrlen as *mut u32)
+                        .and_then(|n| unsafe { copy_to_user(virt_buf, &kbuf[..n]) })
                 }
             }
 
             SYS_RECVMSG => {
-                let msg = args[1] as *mut crate::net::syscall::MsgHdr;
+                let msg = UserPtr::<crate::net::syscall::MsgHdr>::new(args[1]);
                 let flags = args[2] as u32;
-                match UserBufferWriter::new(
-                    msg,
-                    core::mem::size_of::<crate::net::syscall::MsgHdr>(),
-                    true,
-                ) {
-                    Err(e) => Err(e),
-                    Ok(mut user_buffer_writer) => {
-                        match user_buffer_writer.buffer::<crate::net::syscall::MsgHdr>(0) {
-                            Err(e) => Err(e),
-                            Ok(buffer) => {
-                                let msg = &mut buffer[0];
-                                Self::recvmsg(args[0], msg, flags)
-                            }
-                        }
-                    }
-                }
+                Self::recvmsg(args[0], msg, flags)
             }
 
             SYS_LISTEN => Self::listen(args[0], args[1]),
@@ -924,6 +1172,11 @@ impl Syscall {
                 let timezone_ptr = args[1] as *mut PosixTimeZone;
                 Self::gettimeofday(timeval, timezone_ptr)
             }
+            SYS_SETTIMEOFDAY => {
+                let timeval = UserPtr::<PosixTimeval>::new(args[0]);
+                let timezone = UserPtr::<PosixTimeZone>::new(args[1]);
+                Self::settimeofday(timeval, timezone)
+            }
             SYS_MMAP => {
                 let len = page_align_up(args[1]);
                 let virt_addr = VirtAddr::new(args[0] as usize);
@@ -972,14 +1225,42 @@ impl Syscall {
                 if r.is_err() {
                     Err(r.unwrap_err())
                 } else {
-                    let buf = unsafe { core::slice::from_raw_parts_mut(buf, size) };
-                    Self::getcwd(buf).map(|ptr| ptr.data())
+                    let mut kbuf = vec![0u8; size];
+                    Self::getcwd(&mut kbuf)?;
+                    // 只拷贝路径字符串以及结尾的'\0'
+                    let len = kbuf.iter().position(|c| *c == 0).unwrap_or(size - 1) + 1;
+                    unsafe { copy_to_user(VirtAddr::new(buf as usize), &kbuf[..len]) }
+                        .map(|_| buf as usize)
                 }
             }
 
             SYS_GETPGID => Self::getpgid(Pid::new(args[0])).map(|pid| pid.into()),
 
             SYS_GETPPID => Self::getppid().map(|pid| pid.into()),
+            SYS_GETUID | SYS_GETEUID => Self::getuid(),
+            SYS_SETUID => Self::setuid(args[0] as u32),
+            SYS_PERSONALITY => Self::personality(args[0] as u32),
+            SYS_PRCTL => Self::prctl(args[0], [args[1], args[2], args[3], args[4]]),
+            SYS_SECCOMP => Self::seccomp(args[0] as u32, args[1] as u32, args[2]),
+            SYS_PTRACE => Self::ptrace(args[0], args[1], args[2], args[3]),
+            SYS_GETRLIMIT => Self::prlimit64(
+                Pid(0),
+                args[0],
+                UserPtr::new(0),
+                UserPtr::<RLimit64>::new(args[1]),
+            ),
+            SYS_SETRLIMIT => Self::prlimit64(
+                Pid(0),
+                args[0],
+                UserPtr::<RLimit64>::new(args[1]),
+                UserPtr::new(0),
+            ),
+            SYS_PRLIMIT64 => Self::prlimit64(
+                Pid::new(args[0]),
+                args[1],
+                UserPtr::<RLimit64>::new(args[2]),
+                UserPtr::<RLimit64>::new(args[3]),
+            ),
             SYS_FSTAT => {
                 let fd = args[0] as i32;
                 let kstat = args[1] as *mut PosixKstat;
@@ -991,6 +1272,21 @@ impl Syscall {
                     Err(e) => Err(e),
                 }
             }
+            SYS_STAT | SYS_LSTAT | SYS_NEWFSTATAT => {
+                // stat、lstat相当于dirfd为AT_FDCWD的newfstatat
+                let (dirfd, path_ptr, kstat, flags) = match syscall_num {
+                    SYS_STAT => (AT_FDCWD, args[0], args[1], 0),
+                    SYS_LSTAT => (AT_FDCWD, args[0], args[1], AT_SYMLINK_NOFOLLOW),
+                    _ => (args[0] as i32, args[1], args[2], args[3] as u32),
+                };
+                let path = check_and_clone_cstr(path_ptr as *const u8, Some(MAX_PATHLEN))?;
+                let kstat = kstat as *mut PosixKstat;
+                let vaddr = VirtAddr::new(kstat as usize);
+                match verify_area(vaddr, core::mem::size_of::<PosixKstat>()) {
+                    Ok(_) => Self::fstatat(dirfd, &path, kstat, flags),
+                    Err(e) => Err(e),
+                }
+            }
 
             SYS_FCNTL => {
                 let fd = args[0] as i32;
@@ -1023,6 +1319,9 @@ impl Syscall {
                 Self::mknod(path as *const i8, flags, DeviceNumber::from(dev_t))
             }
 
+            #[cfg(target_arch = "x86_64")]
+            SYS_ARCH_PRCTL => Self::arch_prctl(args[0], args[1]),
+
             _ => panic!("Unsupported syscall ID: {}", syscall_num),
         };
         return r;
@@ -1033,10 +1332,9 @@ impl Syscall {
         front_color: u32,
         back_color: u32,
     ) -> Result<usize, SystemError> {
-        return Ok(unsafe { do_put_string(s, front_color, back_color) });
-    }
-
-    pub fn reboot() -> Result<usize, SystemError> {
-        cpu_reset();
+        // 先把字符串拷贝到内核，do_put_string不能直接访问用户空间
+        let mut s = check_and_clone_cstr(s, None)?.into_bytes();
+        s.push(0);
+        return Ok(unsafe { do_put_string(s.as_ptr(), front_color, back_color) });
     }
 }
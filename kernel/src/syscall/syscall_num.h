@@ -12,8 +12,10 @@
 #define SYS_WRITE 1
 #define SYS_OPEN 2
 #define SYS_CLOSE 3
-
+#define SYS_STAT 4
 #define SYS_FSTAT 5
+#define SYS_LSTAT 6
+#define SYS_POLL 7
 #define SYS_LSEEK 8
 #define SYS_MMAP 9
 #define SYS_MPROTECT 10
@@ -24,12 +26,18 @@
 #define SYS_RT_SIGRETURN 15
 #define SYS_IOCTL 16
 
+#define SYS_SELECT 23
+
+#define SYS_SHMGET 29
+#define SYS_SHMAT 30
+#define SYS_SHMCTL 31
 #define SYS_DUP 32
 #define SYS_DUP2 33
 
 #define SYS_NANOSLEEP 35
 
 #define SYS_GETPID 39
+#define SYS_SENDFILE 40
 
 #define SYS_SOCKET 41
 #define SYS_CONNECT 42
@@ -54,6 +62,14 @@
 #define SYS_WAIT4 61
 #define SYS_KILL 62
 
+#define SYS_SEMGET 64
+#define SYS_SEMOP 65
+#define SYS_SEMCTL 66
+#define SYS_SHMDT 67
+#define SYS_MSGGET 68
+#define SYS_MSGSND 69
+#define SYS_MSGRCV 70
+#define SYS_MSGCTL 71
 #define SYS_FCNTL 72
 
 #define SYS_FTRUNCATE 77
@@ -66,24 +82,60 @@
 #define SYS_MKDIR 83
 
 #define SYS_GETTIMEOFDAY 96
+#define SYS_GETRLIMIT 97
+
+#define SYS_SYSLOG 103
 
 #define SYS_ARCH_PRCTL 158
+#define SYS_SETRLIMIT 160
 
 #define SYS_REBOOT 169
 
+#define SYS_INIT_MODULE 175
+#define SYS_DELETE_MODULE 176
+
 #define SYS_GETPPID 110
 #define SYS_GETPGID 121
 
 #define SYS_MKNOD 133
 
+#define SYS_PERSONALITY 135
+
 #define SYS_FUTEX 202
 
 #define SYS_SET_TID_ADDR 218
 
+#define SYS_SEMTIMEDOP 220
+
+#define SYS_MQ_OPEN 240
+#define SYS_MQ_UNLINK 241
+#define SYS_MQ_TIMEDSEND 242
+#define SYS_MQ_TIMEDRECEIVE 243
+#define SYS_MQ_NOTIFY 244
+#define SYS_MQ_GETSETATTR 245
+
+#define SYS_OPENAT 257
+#define SYS_MKDIRAT 258
+#define SYS_NEWFSTATAT 262
 #define SYS_UNLINK_AT 263
 
+#define SYS_PSELECT6 270
+#define SYS_PPOLL 271
+
+#define SYS_DUP3 292
 #define SYS_PIPE 293
 
+#define SYS_PERF_EVENT_OPEN 298
+
+#define SYS_PRLIMIT64 302
+
+#define SYS_MEMFD_CREATE 319
+#define SYS_KEXEC_FILE_LOAD 320
+
+#define SYS_COPY_FILE_RANGE 326
+
+#define SYS_CLOSE_RANGE 436
+
 #define SYS_WRITEV 20
 
 // 与linux不一致的调用，在linux基础上累加
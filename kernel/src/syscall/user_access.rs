@@ -1,16 +1,114 @@
 //! 这个文件用于放置一些内核态访问用户态数据的函数
+//!
+//! 内核不应该直接解引用用户态传入的指针。所有对用户内存的访问，都应该通过本文件提供的函数进行。
+//! 这些函数会先检查地址范围，然后使用登记在异常表中的指令完成拷贝，
+//! 因此即使用户传入了未映射的地址，也只会返回`EFAULT`，而不会导致内核崩溃。
 
 use core::{
-    mem::size_of,
+    marker::PhantomData,
+    mem::{size_of, MaybeUninit},
     slice::{from_raw_parts, from_raw_parts_mut},
 };
 
 use alloc::{string::String, vec::Vec};
 
-use crate::mm::{verify_area, VirtAddr};
+use crate::{
+    arch::mm::extable::{clear_user_generic, copy_user_generic},
+    mm::{
+        kasan::{kasan_check_read, kasan_check_write},
+        verify_area, VirtAddr,
+    },
+};
 
 use super::SystemError;
 
+/// 指向用户空间的指针（相当于Linux中带有`__user`标注的指针）
+///
+/// 内核不能直接解引用该指针，只能通过[`UserPtr::read`]、[`UserPtr::write`]等方法访问，
+/// 从而在编译期区分内核指针与用户指针。
+#[repr(transparent)]
+#[derive(Debug)]
+pub struct UserPtr<T> {
+    addr: VirtAddr,
+    phantom: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> UserPtr<T> {
+    pub const fn new(addr: usize) -> Self {
+        return Self {
+            addr: VirtAddr::new(addr),
+            phantom: PhantomData,
+        };
+    }
+
+    #[inline(always)]
+    pub fn addr(&self) -> VirtAddr {
+        return self.addr;
+    }
+
+    #[inline(always)]
+    pub fn is_null(&self) -> bool {
+        return self.addr.is_null();
+    }
+
+    /// 获取偏移`count`个元素之后的用户指针
+    #[inline(always)]
+    pub fn add(&self, count: usize) -> Self {
+        return Self::new(self.addr.data() + count * size_of::<T>());
+    }
+}
+
+impl<T: Copy> UserPtr<T> {
+    /// 从用户空间读取一个`T`类型的值
+    ///
+    /// ## 错误
+    ///
+    /// - `EFAULT`：指针为空，或者地址不合法
+    pub fn read(&self) -> Result<T, SystemError> {
+        if self.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let mut val = MaybeUninit::<T>::uninit();
+        let dst = unsafe { from_raw_parts_mut(val.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        unsafe { copy_from_user(dst, self.addr)? };
+        return Ok(unsafe { val.assume_init() });
+    }
+
+    /// 向用户空间写入一个`T`类型的值
+    ///
+    /// ## 错误
+    ///
+    /// - `EFAULT`：指针为空，或者地址不合法
+    pub fn write(&self, val: &T) -> Result<(), SystemError> {
+        if self.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let src = unsafe { from_raw_parts(val as *const T as *const u8, size_of::<T>()) };
+        unsafe { copy_to_user(self.addr, src)? };
+        return Ok(());
+    }
+}
+
+impl<T> From<*const T> for UserPtr<T> {
+    fn from(ptr: *const T) -> Self {
+        return Self::new(ptr as usize);
+    }
+}
+
+impl<T> From<*mut T> for UserPtr<T> {
+    fn from(ptr: *mut T) -> Self {
+        return Self::new(ptr as usize);
+    }
+}
+
 /// 清空用户空间指定范围内的数据
 ///
 /// ## 参数
@@ -28,28 +126,59 @@ use super::SystemError;
 pub unsafe fn clear_user(dest: VirtAddr, len: usize) -> Result<usize, SystemError> {
     verify_area(dest, len).map_err(|_| SystemError::EFAULT)?;
 
-    let p = dest.data() as *mut u8;
     // 清空用户空间的数据
-    p.write_bytes(0, len);
+    if clear_user_generic(dest.as_ptr::<u8>(), len) != 0 {
+        return Err(SystemError::EFAULT);
+    }
     return Ok(len);
 }
 
+/// 从内核空间拷贝数据到用户空间
+///
+/// ## 错误
+///
+/// - `EFAULT`：目标地址不合法，或者拷贝过程中访问到了未映射的用户内存
 pub unsafe fn copy_to_user(dest: VirtAddr, src: &[u8]) -> Result<usize, SystemError> {
     verify_area(dest, src.len()).map_err(|_| SystemError::EFAULT)?;
+    kasan_check_read(src.as_ptr() as usize, src.len());
 
-    let p = dest.data() as *mut u8;
     // 拷贝数据
-    p.copy_from_nonoverlapping(src.as_ptr(), src.len());
+    if copy_user_generic(dest.as_ptr::<u8>(), src.as_ptr(), src.len()) != 0 {
+        return Err(SystemError::EFAULT);
+    }
     return Ok(src.len());
 }
 
+/// 从内核空间拷贝数据到用户空间，拷贝过程中访问到未映射的用户内存时停止
+///
+/// ## 返回值
+///
+/// 返回成功拷贝的字节数，可能小于`src.len()`
+///
+/// ## 错误
+///
+/// - `EFAULT`：目标地址不合法
+pub unsafe fn copy_to_user_partial(dest: VirtAddr, src: &[u8]) -> Result<usize, SystemError> {
+    verify_area(dest, src.len()).map_err(|_| SystemError::EFAULT)?;
+    kasan_check_read(src.as_ptr() as usize, src.len());
+
+    let remain = copy_user_generic(dest.as_ptr::<u8>(), src.as_ptr(), src.len());
+    return Ok(src.len() - remain);
+}
+
 /// 从用户空间拷贝数据到内核空间
+///
+/// ## 错误
+///
+/// - `EFAULT`：源地址不合法，或者拷贝过程中访问到了未映射的用户内存
 pub unsafe fn copy_from_user(dst: &mut [u8], src: VirtAddr) -> Result<usize, SystemError> {
     verify_area(src, dst.len()).map_err(|_| SystemError::EFAULT)?;
+    kasan_check_write(dst.as_ptr() as usize, dst.len());
 
-    let src: &[u8] = core::slice::from_raw_parts(src.data() as *const u8, dst.len());
     // 拷贝数据
-    dst.copy_from_slice(&src);
+    if copy_user_generic(dst.as_mut_ptr(), src.as_ptr::<u8>(), dst.len()) != 0 {
+        return Err(SystemError::EFAULT);
+    }
 
     return Ok(dst.len());
 }
@@ -120,17 +249,8 @@ pub fn check_and_clone_cstr_array(user: *const *const u8) -> Result<Vec<String>,
         let mut buffer = Vec::new();
         for i in 0.. {
             let addr = unsafe { user.add(i) };
-            let str_ptr: *const u8;
             // 读取这个地址的值（这个值也是一个指针）
-            unsafe {
-                let dst = [0usize; 1];
-                let mut dst = core::mem::transmute::<[usize; 1], [u8; size_of::<usize>()]>(dst);
-                copy_from_user(&mut dst, VirtAddr::new(addr as usize))?;
-                let dst = core::mem::transmute::<[u8; size_of::<usize>()], [usize; 1]>(dst);
-                str_ptr = dst[0] as *const u8;
-
-                // kdebug!("str_ptr: {:p}, addr:{addr:?}\n", str_ptr);
-            }
+            let str_ptr = UserPtr::<usize>::new(addr as usize).read()? as *const u8;
 
             if str_ptr.is_null() {
                 break;
@@ -199,8 +319,15 @@ impl<'a> UserBufferReader<'a> {
         dst: &mut [T],
         offset: usize,
     ) -> Result<usize, SystemError> {
-        let data = self.convert_with_offset(&self.buffer, offset)?;
-        dst.copy_from_slice(data);
+        let data: &[T] = self.convert_with_offset(&self.buffer, offset)?;
+        if data.len() != dst.len() {
+            return Err(SystemError::EINVAL);
+        }
+        Self::copy_nofault(
+            dst.as_mut_ptr() as *mut u8,
+            data.as_ptr() as *const u8,
+            size_of::<T>() * dst.len(),
+        )?;
         return Ok(dst.len());
     }
 
@@ -215,7 +342,19 @@ impl<'a> UserBufferReader<'a> {
         offset: usize,
     ) -> Result<(), SystemError> {
         let data = self.convert_one_with_offset::<T>(&self.buffer, offset)?;
-        dst.clone_from(data);
+        Self::copy_nofault(
+            dst as *mut T as *mut u8,
+            data as *const T as *const u8,
+            size_of::<T>(),
+        )?;
+        return Ok(());
+    }
+
+    /// 使用登记在异常表中的指令拷贝数据，访问出错时返回`EFAULT`
+    fn copy_nofault(dst: *mut u8, src: *const u8, len: usize) -> Result<(), SystemError> {
+        if unsafe { copy_user_generic(dst, src, len) } != 0 {
+            return Err(SystemError::EFAULT);
+        }
         return Ok(());
     }
 
@@ -277,8 +416,15 @@ impl<'a> UserBufferWriter<'a> {
         src: &'a [T],
         offset: usize,
     ) -> Result<usize, SystemError> {
-        let dst = Self::convert_with_offset(self.buffer, offset)?;
-        dst.copy_from_slice(&src);
+        let dst: &mut [T] = Self::convert_with_offset(self.buffer, offset)?;
+        if dst.len() != src.len() {
+            return Err(SystemError::EINVAL);
+        }
+        UserBufferReader::copy_nofault(
+            dst.as_mut_ptr() as *mut u8,
+            src.as_ptr() as *const u8,
+            size_of::<T>() * src.len(),
+        )?;
         return Ok(src.len());
     }
 
@@ -294,7 +440,11 @@ impl<'a> UserBufferWriter<'a> {
         offset: usize,
     ) -> Result<(), SystemError> {
         let dst = Self::convert_one_with_offset::<T>(self.buffer, offset)?;
-        dst.clone_from(src);
+        UserBufferReader::copy_nofault(
+            dst as *mut T as *mut u8,
+            src as *const T as *const u8,
+            size_of::<T>(),
+        )?;
         return Ok(());
     }
 
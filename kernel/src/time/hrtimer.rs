@@ -0,0 +1,481 @@
+//! 高精度定时器(hrtimer)
+//!
+//! 每个cpu都有一棵按照到期时间排序的红黑树，由时钟事件设备（x86_64上为Local APIC定时器的
+//! TSC-deadline或单次触发模式）在最早的定时器到期时产生中断。
+//!
+//! 切换到单次触发模式之后，周期性的调度时钟由每个cpu上的一个hrtimer模拟，
+//! cpu空闲时可以停止它(nohz)，直到下一次中断到来。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/time/hrtimer.c
+
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    arch::{sched::sched, CurrentIrqArch, CurrentTimeArch},
+    driver::cpufreq::cpufreq_manager,
+    exception::InterruptArch,
+    kinfo,
+    libs::{rbtree::RBTree, rwlock::RwLock, spinlock::SpinLock},
+    mm::percpu::PerCpu,
+    process::ProcessManager,
+    sched::core::sched_update_jiffies,
+    smp::core::smp_get_processor_id,
+    syscall::SystemError,
+};
+
+use super::{posix_timer::account_process_tick, timer::WakeUpHelper, TimeArch};
+
+/// 单调时间（单位：纳秒）
+pub type Ktime = u64;
+
+/// 调度时钟的周期(ns)，与Local APIC定时器周期模式下的中断间隔一致
+pub const TICK_PERIOD_NS: u64 = 5 * 1000 * 1000;
+
+/// 定时器在红黑树中的键：(到期时间, 序号)，序号用于区分到期时间相同的定时器
+type HrTimerKey = (Ktime, u64);
+
+/// 获取当前的单调时间
+#[inline]
+pub fn ktime_get() -> Ktime {
+    CurrentTimeArch::cycles2ns(CurrentTimeArch::get_cycles()) as Ktime
+}
+
+/// 定时器函数执行完之后，是否需要重新启动定时器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HrTimerRestart {
+    NoRestart,
+    /// 以给定的到期时间重新启动
+    Restart(Ktime),
+}
+
+/// 高精度定时器到期时要执行的函数
+///
+/// 在硬中断上下文中执行
+pub trait HrTimerFunction: Send + Sync + Debug {
+    fn run(&mut self, now: Ktime) -> HrTimerRestart;
+}
+
+impl HrTimerFunction for WakeUpHelper {
+    fn run(&mut self, _now: Ktime) -> HrTimerRestart {
+        ProcessManager::wakeup(self.pcb()).ok();
+        return HrTimerRestart::NoRestart;
+    }
+}
+
+/// 时钟事件设备
+pub trait ClockEventDevice: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// 把当前cpu上的设备切换到单次触发模式
+    fn set_oneshot(&self);
+
+    /// 设置当前cpu上的设备下一次产生中断的时刻，`None`表示停止设备
+    fn set_next_event(&self, expires: Option<Ktime>);
+}
+
+#[derive(Debug)]
+pub struct HrTimer {
+    func: SpinLock<Box<dyn HrTimerFunction>>,
+    /// 定时器所在的队列：(cpu, 键)
+    state: SpinLock<Option<(usize, HrTimerKey)>>,
+    self_ref: Weak<HrTimer>,
+}
+
+impl HrTimer {
+    pub fn new(func: Box<dyn HrTimerFunction>) -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| HrTimer {
+            func: SpinLock::new(func),
+            state: SpinLock::new(None),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    /// 在当前cpu上启动定时器，如果定时器已经启动，则修改它的到期时间
+    ///
+    /// ## 参数
+    ///
+    /// - `expires`: 到期的时刻（单调时间）
+    ///
+    /// ## 错误
+    ///
+    /// - `ENODEV`：当前cpu还没有切换到高精度模式
+    pub fn start(&self, expires: Ktime) -> Result<(), SystemError> {
+        let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        let cpu = smp_get_processor_id() as usize;
+        let base = hrtimer_base(cpu).ok_or(SystemError::ENODEV)?;
+        if !base.active.load(Ordering::SeqCst) {
+            return Err(SystemError::ENODEV);
+        }
+
+        let mut state = self.state.lock_irqsave();
+        if let Some((old_cpu, key)) = state.take() {
+            if let Some(old_base) = hrtimer_base(old_cpu) {
+                old_base.queue.lock_irqsave().remove(&key);
+            }
+        }
+
+        let key = (expires, HRTIMER_SEQ.fetch_add(1, Ordering::Relaxed));
+        let is_first = {
+            let mut queue = base.queue.lock_irqsave();
+            queue.insert(key, self.self_ref.upgrade().unwrap());
+            queue.get_first().map(|(k, _)| *k) == Some(key)
+        };
+        *state = Some((cpu, key));
+        drop(state);
+
+        if is_first {
+            base.reprogram();
+        }
+        return Ok(());
+    }
+
+    /// 取消定时器
+    ///
+    /// ## 返回值
+    ///
+    /// 定时器在取消之前是否处于启动状态
+    pub fn cancel(&self) -> bool {
+        let mut state = self.state.lock_irqsave();
+        if let Some((cpu, key)) = state.take() {
+            if let Some(base) = hrtimer_base(cpu) {
+                base.queue.lock_irqsave().remove(&key);
+            }
+            return true;
+        }
+        return false;
+    }
+
+    /// 定时器是否处于启动状态
+    #[allow(dead_code)]
+    pub fn is_active(&self) -> bool {
+        self.state.lock_irqsave().is_some()
+    }
+}
+
+/// 每个cpu的定时器队列
+#[derive(Debug)]
+struct HrTimerCpuBase {
+    /// 该cpu是否已经切换到高精度模式
+    active: AtomicBool,
+    queue: SpinLock<RBTree<HrTimerKey, Arc<HrTimer>>>,
+    /// 已经设置到时钟事件设备的到期时刻，`u64::MAX`表示没有设置
+    next_event: AtomicU64,
+    /// 正在处理定时器中断，处理完之后会统一设置下一次中断
+    in_hrtirq: AtomicBool,
+    /// 调度时钟是否已经停止
+    tick_stopped: AtomicBool,
+    /// 正在处理的定时器中断是否发生在用户态
+    irq_from_user: AtomicBool,
+}
+
+impl HrTimerCpuBase {
+    fn new() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            queue: SpinLock::new(RBTree::new()),
+            next_event: AtomicU64::new(u64::MAX),
+            in_hrtirq: AtomicBool::new(false),
+            tick_stopped: AtomicBool::new(false),
+            irq_from_user: AtomicBool::new(false),
+        }
+    }
+
+    /// 最早到期的定时器的到期时间
+    fn first_expires(&self) -> Option<Ktime> {
+        self.queue.lock_irqsave().get_first().map(|(k, _)| k.0)
+    }
+
+    /// 根据最早到期的定时器，重新设置当前cpu的时钟事件设备
+    fn reprogram(&self) {
+        if self.in_hrtirq.load(Ordering::SeqCst) {
+            return;
+        }
+        let dev = match clockevent_device() {
+            Some(dev) => dev,
+            None => return,
+        };
+
+        let next = self.first_expires();
+        let next_val = next.unwrap_or(u64::MAX);
+        if self.next_event.swap(next_val, Ordering::SeqCst) != next_val {
+            dev.set_next_event(next);
+        }
+    }
+}
+
+static HRTIMER_SEQ: AtomicU64 = AtomicU64::new(0);
+static CLOCKEVENT_DEVICE: RwLock<Option<Arc<dyn ClockEventDevice>>> = RwLock::new(None);
+
+lazy_static! {
+    static ref HRTIMER_BASES: Vec<HrTimerCpuBase> =
+        (0..PerCpu::MAX_CPU_NUM).map(|_| HrTimerCpuBase::new()).collect();
+    /// 每个cpu上模拟调度时钟的定时器
+    static ref TICK_TIMERS: Vec<Arc<HrTimer>> = (0..PerCpu::MAX_CPU_NUM)
+        .map(|_| HrTimer::new(Box::new(TickSchedTimer)))
+        .collect();
+}
+
+#[inline]
+fn hrtimer_base(cpu: usize) -> Option<&'static HrTimerCpuBase> {
+    HRTIMER_BASES.get(cpu)
+}
+
+#[inline]
+fn clockevent_device() -> Option<Arc<dyn ClockEventDevice>> {
+    CLOCKEVENT_DEVICE.read().clone()
+}
+
+/// 调度时钟
+#[derive(Debug)]
+struct TickSchedTimer;
+
+impl HrTimerFunction for TickSchedTimer {
+    fn run(&mut self, now: Ktime) -> HrTimerRestart {
+        let user = hrtimer_base(smp_get_processor_id() as usize)
+            .map_or(false, |base| base.irq_from_user.load(Ordering::SeqCst));
+        account_process_tick(user);
+        sched_update_jiffies();
+        cpufreq_manager().tick();
+        return HrTimerRestart::Restart(now + TICK_PERIOD_NS);
+    }
+}
+
+/// 注册时钟事件设备
+///
+/// 注册之后，每个cpu在初始化本地定时器时切换到高精度模式
+pub fn clockevent_register(dev: Arc<dyn ClockEventDevice>) -> Result<(), SystemError> {
+    let mut guard = CLOCKEVENT_DEVICE.write_irqsave();
+    if guard.is_some() {
+        return Err(SystemError::EEXIST);
+    }
+    kinfo!("hrtimer: using clock event device '{}'", dev.name());
+    guard.replace(dev);
+    return Ok(());
+}
+
+/// 当前cpu是否已经切换到高精度模式
+pub fn hrtimer_active() -> bool {
+    hrtimer_base(smp_get_processor_id() as usize)
+        .map_or(false, |base| base.active.load(Ordering::SeqCst))
+}
+
+/// 把当前cpu切换到高精度模式，并启动调度时钟
+///
+/// ## 错误
+///
+/// - `ENODEV`：没有注册时钟事件设备
+pub fn hrtimer_cpu_init() -> Result<(), SystemError> {
+    let dev = clockevent_device().ok_or(SystemError::ENODEV)?;
+    let cpu = smp_get_processor_id() as usize;
+    let base = hrtimer_base(cpu).ok_or(SystemError::ENODEV)?;
+
+    let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    dev.set_oneshot();
+    base.next_event.store(u64::MAX, Ordering::SeqCst);
+    base.active.store(true, Ordering::SeqCst);
+    TICK_TIMERS[cpu].start(ktime_get() + TICK_PERIOD_NS)?;
+    kinfo!("hrtimer: cpu {} switched to high resolution mode", cpu);
+    return Ok(());
+}
+
+/// 时钟事件设备的中断处理函数，执行当前cpu上所有已经到期的定时器
+///
+/// ## 参数
+///
+/// - `user`: 中断是否发生在用户态
+///
+/// ## 返回值
+///
+/// 当前cpu是否处于高精度模式（否则由调用者按照周期模式处理）
+pub fn hrtimer_interrupt(user: bool) -> bool {
+    let cpu = smp_get_processor_id() as usize;
+    let base = match hrtimer_base(cpu) {
+        Some(base) if base.active.load(Ordering::SeqCst) => base,
+        _ => return false,
+    };
+
+    base.irq_from_user.store(user, Ordering::SeqCst);
+    base.in_hrtirq.store(true, Ordering::SeqCst);
+    loop {
+        let now = ktime_get();
+        let (key, timer) = {
+            let mut queue = base.queue.lock_irqsave();
+            match queue.get_first() {
+                Some((k, _)) if k.0 <= now => queue.pop_first().unwrap(),
+                _ => break,
+            }
+        };
+
+        // 定时器可能在出队之前被重新启动到了别的位置
+        {
+            let mut state = timer.state.lock_irqsave();
+            if *state != Some((cpu, key)) {
+                continue;
+            }
+            *state = None;
+        }
+
+        let restart = timer.func.lock_irqsave().run(now);
+        if let HrTimerRestart::Restart(expires) = restart {
+            timer.start(expires).ok();
+        }
+    }
+    base.in_hrtirq.store(false, Ordering::SeqCst);
+
+    // 单次触发模式下，设备已经停止，需要重新设置
+    base.next_event.store(u64::MAX, Ordering::SeqCst);
+    base.reprogram();
+    return true;
+}
+
+/// 把已经下线的cpu上的定时器迁移到当前cpu上
+///
+/// ## 参数
+///
+/// - `cpu`: 已经下线的cpu，它的调度时钟已经停止
+pub fn hrtimer_migrate_from(cpu: usize) {
+    let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let this_cpu = smp_get_processor_id() as usize;
+    let (old_base, new_base) = match (hrtimer_base(cpu), hrtimer_base(this_cpu)) {
+        (Some(old), Some(new)) if cpu != this_cpu && new.active.load(Ordering::SeqCst) => {
+            (old, new)
+        }
+        _ => return,
+    };
+
+    let timers: Vec<(HrTimerKey, Arc<HrTimer>)> = {
+        let mut queue = old_base.queue.lock_irqsave();
+        core::iter::from_fn(|| queue.pop_first()).collect()
+    };
+    for (key, timer) in timers {
+        let mut state = timer.state.lock_irqsave();
+        // 定时器可能在出队之前被取消或者重新启动了
+        if *state != Some((cpu, key)) {
+            continue;
+        }
+        if Arc::ptr_eq(&timer, &TICK_TIMERS[cpu]) {
+            *state = None;
+            continue;
+        }
+        new_base.queue.lock_irqsave().insert(key, timer.clone());
+        *state = Some((this_cpu, key));
+    }
+    new_base.reprogram();
+}
+
+/// 系统从睡眠中恢复之后调用，重新设置当前cpu的时钟事件设备
+///
+/// 睡眠时时钟事件设备断电，它的工作模式与下一次事件都需要重新设置。调用时中断必须已经关闭
+pub fn hrtimer_resume() {
+    let base = match hrtimer_base(smp_get_processor_id() as usize) {
+        Some(base) if base.active.load(Ordering::SeqCst) => base,
+        _ => return,
+    };
+    let dev = match clockevent_device() {
+        Some(dev) => dev,
+        None => return,
+    };
+    dev.set_oneshot();
+    base.next_event.store(u64::MAX, Ordering::SeqCst);
+    base.reprogram();
+}
+
+/// 距离当前cpu上最早的定时器到期还有多长时间(ns)，没有定时器时返回None
+pub fn hrtimer_next_event_delta() -> Option<u64> {
+    let base = hrtimer_base(smp_get_processor_id() as usize)?;
+    if !base.active.load(Ordering::SeqCst) {
+        return None;
+    }
+    let expires = base.first_expires()?;
+    return Some(expires.saturating_sub(ktime_get()));
+}
+
+/// cpu进入空闲状态之前调用，停止调度时钟
+///
+/// 调用时中断必须已经关闭
+pub fn tick_nohz_idle_enter() {
+    let cpu = smp_get_processor_id() as usize;
+    let base = match hrtimer_base(cpu) {
+        Some(base) if base.active.load(Ordering::SeqCst) => base,
+        _ => return,
+    };
+    if !base.tick_stopped.swap(true, Ordering::SeqCst) {
+        TICK_TIMERS[cpu].cancel();
+        base.reprogram();
+    }
+}
+
+/// 中断到来或者cpu退出空闲状态时调用，如果调度时钟已经停止，则重新启动它
+pub fn tick_nohz_restart() {
+    let cpu = smp_get_processor_id() as usize;
+    let base = match hrtimer_base(cpu) {
+        Some(base) if base.active.load(Ordering::SeqCst) => base,
+        _ => return,
+    };
+    if base.tick_stopped.swap(false, Ordering::SeqCst) {
+        TICK_TIMERS[cpu].start(ktime_get() + TICK_PERIOD_NS).ok();
+    }
+}
+
+/// 使用高精度定时器休眠
+///
+/// ## 参数
+///
+/// - `ns`: 休眠的时间(ns)
+///
+/// ## 返回值
+///
+/// 剩余的休眠时间(ns)，提前被唤醒时不为0
+///
+/// ## 错误
+///
+/// - `ENODEV`：当前cpu还没有切换到高精度模式
+pub fn hrtimer_nanosleep(ns: u64) -> Result<u64, SystemError> {
+    let timer = HrTimer::new(WakeUpHelper::new(ProcessManager::current_pcb()));
+
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    if !hrtimer_active() {
+        return Err(SystemError::ENODEV);
+    }
+    let expires = ktime_get() + ns;
+    // 中断已经关闭，定时器不会在进程被标记为睡眠之前到期
+    timer.start(expires)?;
+    ProcessManager::mark_sleep(true).ok();
+    drop(irq_guard);
+
+    sched();
+
+    timer.cancel();
+    return Ok(expires.saturating_sub(ktime_get()));
+}
+
+//===== 以下为提供给C的接口 =====
+
+/// 每个cpu初始化Local APIC定时器之后调用
+#[no_mangle]
+pub extern "C" fn rs_hrtimer_cpu_init() {
+    hrtimer_cpu_init().ok();
+}
+
+/// Local APIC定时器的中断处理函数中调用
+///
+/// 返回值为true时，表示已经在高精度模式下处理完毕
+#[no_mangle]
+pub extern "C" fn rs_hrtimer_interrupt(user: bool) -> bool {
+    hrtimer_interrupt(user)
+}
+
+/// 每次进入中断处理时调用
+#[no_mangle]
+pub extern "C" fn rs_tick_irq_enter() {
+    tick_nohz_restart();
+}
@@ -3,13 +3,17 @@ use core::{fmt, ops};
 use self::timekeep::ktime_get_real_ns;
 
 pub mod clocksource;
+pub mod hrtimer;
 pub mod jiffies;
+pub mod ntp;
+pub mod posix_timer;
 pub mod sleep;
 pub mod syscall;
 pub mod timeconv;
 pub mod timekeep;
 pub mod timekeeping;
 pub mod timer;
+pub mod vsyscall;
 /* Time structures. (Partitially taken from smoltcp)
 
 The `time` module contains structures used to represent both
@@ -374,4 +378,7 @@ impl Into<smoltcp::time::Duration> for Duration {
 pub trait TimeArch {
     /// Get CPU cycles (Read from register)
     fn get_cycles() -> usize;
+
+    /// 将CPU cycles转换为纳秒（时钟频率未知时返回0）
+    fn cycles2ns(cycles: usize) -> usize;
 }
@@ -0,0 +1,348 @@
+//! NTP时钟调整：由用户态的NTP守护进程通过adjtimex设置时钟的频率偏差和相位偏差，
+//! 内核在每次更新墙上时间时按照这些参数微调时钟的走速。
+//!
+//! 与Linux不同，这里没有实现PLL/FLL环路，相位偏差一律以不超过[`MAXFREQ_PPM`]的速率平滑地补偿。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/time/ntp.c
+
+use crate::{libs::spinlock::SpinLock, syscall::SystemError};
+
+use super::{
+    syscall::PosixTimeval,
+    timekeeping::{getnstimeofday, timekeeping_inject_offset},
+    NSEC_PER_SEC, NSEC_PER_USEC, USEC_PER_SEC,
+};
+
+/// adjtimex的modes
+pub const ADJ_OFFSET: u32 = 0x0001;
+pub const ADJ_FREQUENCY: u32 = 0x0002;
+pub const ADJ_MAXERROR: u32 = 0x0004;
+pub const ADJ_ESTERROR: u32 = 0x0008;
+pub const ADJ_STATUS: u32 = 0x0010;
+pub const ADJ_TIMECONST: u32 = 0x0020;
+pub const ADJ_TAI: u32 = 0x0080;
+pub const ADJ_SETOFFSET: u32 = 0x0100;
+pub const ADJ_MICRO: u32 = 0x1000;
+pub const ADJ_NANO: u32 = 0x2000;
+pub const ADJ_TICK: u32 = 0x4000;
+/// 旧式的adjtime()
+pub const ADJ_ADJTIME: u32 = 0x8000;
+pub const ADJ_OFFSET_SINGLESHOT: u32 = 0x8001;
+pub const ADJ_OFFSET_SS_READ: u32 = 0xa001;
+
+/// 时钟状态
+pub const STA_PLL: i32 = 0x0001;
+pub const STA_PPSFREQ: i32 = 0x0002;
+pub const STA_PPSTIME: i32 = 0x0004;
+pub const STA_FLL: i32 = 0x0008;
+pub const STA_INS: i32 = 0x0010;
+pub const STA_DEL: i32 = 0x0020;
+pub const STA_UNSYNC: i32 = 0x0040;
+pub const STA_FREQHOLD: i32 = 0x0080;
+pub const STA_PPSSIGNAL: i32 = 0x0100;
+pub const STA_PPSJITTER: i32 = 0x0200;
+pub const STA_PPSWANDER: i32 = 0x0400;
+pub const STA_PPSERROR: i32 = 0x0800;
+pub const STA_CLOCKERR: i32 = 0x1000;
+pub const STA_NANO: i32 = 0x2000;
+pub const STA_MODE: i32 = 0x4000;
+pub const STA_CLK: i32 = 0x8000;
+/// 只读的状态位
+const STA_RONLY: i32 = STA_PPSSIGNAL
+    | STA_PPSJITTER
+    | STA_PPSWANDER
+    | STA_PPSERROR
+    | STA_CLOCKERR
+    | STA_NANO
+    | STA_MODE
+    | STA_CLK;
+
+/// adjtimex的返回值：时钟已同步
+pub const TIME_OK: usize = 0;
+/// adjtimex的返回值：时钟未同步
+pub const TIME_ERROR: usize = 5;
+
+/// 用户态的时钟节拍频率
+const USER_HZ: i64 = 100;
+/// 每个用户态时钟节拍的默认长度(us)
+const NTP_TICK_DEFAULT: i64 = USEC_PER_SEC as i64 / USER_HZ;
+/// 允许的最大频率偏差(ppm)
+const MAXFREQ_PPM: i64 = 500;
+/// 以ppm<<16表示的最大频率偏差
+const MAXFREQ_SCALED: i64 = MAXFREQ_PPM << 16;
+/// 允许的最大相位偏差(ns)
+const MAXPHASE: i64 = 500000000;
+/// 时间常数的最大值
+const MAXTC: i64 = 10;
+/// 最大误差的上限(us)，超过之后认为时钟未同步
+const NTP_PHASE_LIMIT: i64 = 16000000;
+
+/// adjtimex使用的参数结构体，与Linux的struct timex一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PosixTimex {
+    pub modes: u32,
+    _pad0: i32,
+    /// 相位偏差(us或ns)
+    pub offset: i64,
+    /// 频率偏差(ppm<<16)
+    pub freq: i64,
+    /// 最大误差(us)
+    pub maxerror: i64,
+    /// 估计误差(us)
+    pub esterror: i64,
+    pub status: i32,
+    _pad1: i32,
+    pub constant: i64,
+    /// 时钟精度(us)，只读
+    pub precision: i64,
+    /// 允许的最大频率偏差(ppm<<16)，只读
+    pub tolerance: i64,
+    /// 当前时间，只读(ADJ_SETOFFSET时为要调整的时间)
+    pub time: PosixTimeval,
+    /// 每个时钟节拍的长度(us)
+    pub tick: i64,
+    pub ppsfreq: i64,
+    pub jitter: i64,
+    pub shift: i32,
+    _pad2: i32,
+    pub stabil: i64,
+    pub jitcnt: i64,
+    pub calcnt: i64,
+    pub errcnt: i64,
+    pub stbcnt: i64,
+    pub tai: i32,
+    _reserved: [i32; 11],
+}
+
+#[derive(Debug)]
+struct NtpData {
+    /// 剩余需要补偿的相位偏差(ns)
+    time_offset: i64,
+    /// 频率偏差(ppm<<16)
+    time_freq: i64,
+    time_status: i32,
+    /// 最大误差(us)
+    time_maxerror: i64,
+    /// 估计误差(us)
+    time_esterror: i64,
+    time_constant: i64,
+    /// 每个用户态时钟节拍的长度(us)
+    tick_usec: i64,
+    /// TAI与UTC的差值(s)
+    time_tai: i32,
+    /// 最大误差中不足1us的部分(ns)
+    maxerror_rem: u64,
+}
+
+impl NtpData {
+    const fn new() -> Self {
+        Self {
+            time_offset: 0,
+            time_freq: 0,
+            time_status: STA_UNSYNC,
+            time_maxerror: NTP_PHASE_LIMIT,
+            time_esterror: NTP_PHASE_LIMIT,
+            time_constant: 2,
+            tick_usec: NTP_TICK_DEFAULT,
+            time_tai: 0,
+            maxerror_rem: 0,
+        }
+    }
+}
+
+static NTP_DATA: SpinLock<NtpData> = SpinLock::new(NtpData::new());
+
+/// 清除NTP的状态，在时间被直接设置之后调用
+pub fn ntp_clear() {
+    let mut ntp = NTP_DATA.lock_irqsave();
+    ntp.time_offset = 0;
+    ntp.time_status |= STA_UNSYNC;
+    ntp.time_maxerror = NTP_PHASE_LIMIT;
+    ntp.time_esterror = NTP_PHASE_LIMIT;
+    ntp.maxerror_rem = 0;
+}
+
+/// 计算墙上时间前进`delta`纳秒时，需要额外调整的纳秒数
+///
+/// 在更新墙上时间时调用
+pub fn ntp_advance(delta: u64) -> i64 {
+    let mut ntp = NTP_DATA.lock_irqsave();
+    let delta = delta as i128;
+
+    // 频率偏差和节拍长度的调整
+    let mut adj = delta * ntp.time_freq as i128 / ((USEC_PER_SEC as i128) << 16);
+    adj += delta * (ntp.tick_usec - NTP_TICK_DEFAULT) as i128 / NTP_TICK_DEFAULT as i128;
+
+    // 以不超过最大频率偏差的速率补偿相位偏差
+    let max_slew = (delta * MAXFREQ_PPM as i128 / USEC_PER_SEC as i128) as i64;
+    let slew = ntp.time_offset.clamp(-max_slew, max_slew);
+    ntp.time_offset -= slew;
+    adj += slew as i128;
+
+    // 未同步期间，最大误差以最大频率偏差的速率增长
+    ntp.maxerror_rem += (delta * MAXFREQ_PPM as i128 / USEC_PER_SEC as i128) as u64;
+    ntp.time_maxerror += (ntp.maxerror_rem / NSEC_PER_USEC as u64) as i64;
+    ntp.maxerror_rem %= NSEC_PER_USEC as u64;
+    if ntp.time_maxerror >= NTP_PHASE_LIMIT {
+        ntp.time_maxerror = NTP_PHASE_LIMIT;
+        ntp.time_status |= STA_UNSYNC;
+    }
+
+    return adj as i64;
+}
+
+/// 检查adjtimex的参数是否合法
+fn timex_validate(txc: &PosixTimex) -> Result<(), SystemError> {
+    if txc.modes & ADJ_ADJTIME != 0 {
+        // 旧式的adjtime()不能与其他模式同时使用
+        if txc.modes & ADJ_OFFSET_SINGLESHOT == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if txc.modes != ADJ_OFFSET_SINGLESHOT && txc.modes != ADJ_OFFSET_SS_READ {
+            return Err(SystemError::EINVAL);
+        }
+    } else if txc.modes & ADJ_TICK != 0 {
+        // 节拍长度只允许在±10%之内调整
+        if txc.tick < 900000 / USER_HZ || txc.tick > 1100000 / USER_HZ {
+            return Err(SystemError::EINVAL);
+        }
+    }
+
+    if txc.modes & ADJ_SETOFFSET != 0 {
+        if txc.time.tv_usec < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let limit = if txc.modes & ADJ_NANO != 0 {
+            NSEC_PER_SEC as i64
+        } else {
+            USEC_PER_SEC as i64
+        };
+        if txc.time.tv_usec as i64 >= limit {
+            return Err(SystemError::EINVAL);
+        }
+    }
+
+    return Ok(());
+}
+
+/// 读取或调整NTP参数
+///
+/// ## 参数
+///
+/// - `txc`: 要设置的参数，返回时填入当前的参数
+///
+/// ## 返回值
+///
+/// 时钟的状态：[`TIME_OK`]或[`TIME_ERROR`]
+pub fn do_adjtimex(txc: &mut PosixTimex) -> Result<usize, SystemError> {
+    timex_validate(txc)?;
+
+    if txc.modes & ADJ_SETOFFSET != 0 {
+        let mut delta = txc.time.tv_sec.saturating_mul(NSEC_PER_SEC as i64);
+        if txc.modes & ADJ_NANO != 0 {
+            delta = delta.saturating_add(txc.time.tv_usec as i64);
+        } else {
+            delta = delta.saturating_add(txc.time.tv_usec as i64 * NSEC_PER_USEC as i64);
+        }
+        timekeeping_inject_offset(delta)?;
+    }
+
+    let mut ntp = NTP_DATA.lock_irqsave();
+    if txc.modes & ADJ_ADJTIME != 0 {
+        // adjtime()：以微秒为单位，返回之前尚未补偿完的偏差
+        let save_adjust = ntp.time_offset / NSEC_PER_USEC as i64;
+        if txc.modes & ADJ_OFFSET_SS_READ != ADJ_OFFSET_SS_READ {
+            ntp.time_offset = txc
+                .offset
+                .saturating_mul(NSEC_PER_USEC as i64)
+                .clamp(-MAXPHASE, MAXPHASE);
+        }
+        txc.offset = save_adjust;
+    } else {
+        if txc.modes & ADJ_STATUS != 0 {
+            if ntp.time_status & STA_PLL != 0 && txc.status & STA_PLL == 0 {
+                ntp.time_offset = 0;
+            }
+            ntp.time_status = (ntp.time_status & STA_RONLY) | (txc.status & !STA_RONLY);
+        }
+        if txc.modes & ADJ_NANO != 0 {
+            ntp.time_status |= STA_NANO;
+        }
+        if txc.modes & ADJ_MICRO != 0 {
+            ntp.time_status &= !STA_NANO;
+        }
+        if txc.modes & ADJ_FREQUENCY != 0 {
+            ntp.time_freq = txc.freq.clamp(-MAXFREQ_SCALED, MAXFREQ_SCALED);
+        }
+        if txc.modes & ADJ_MAXERROR != 0 {
+            ntp.time_maxerror = txc.maxerror.clamp(0, NTP_PHASE_LIMIT);
+            ntp.maxerror_rem = 0;
+        }
+        if txc.modes & ADJ_ESTERROR != 0 {
+            ntp.time_esterror = txc.esterror.clamp(0, NTP_PHASE_LIMIT);
+        }
+        if txc.modes & ADJ_TIMECONST != 0 {
+            ntp.time_constant = txc.constant.clamp(0, MAXTC);
+        }
+        if txc.modes & ADJ_TAI != 0 && txc.offset >= 0 {
+            ntp.time_tai = txc.offset as i32;
+        }
+        if txc.modes & ADJ_OFFSET != 0 && ntp.time_status & STA_PLL != 0 {
+            let offset = if ntp.time_status & STA_NANO != 0 {
+                txc.offset
+            } else {
+                txc.offset.saturating_mul(NSEC_PER_USEC as i64)
+            };
+            ntp.time_offset = offset.clamp(-MAXPHASE, MAXPHASE);
+        }
+        if txc.modes & ADJ_TICK != 0 {
+            ntp.tick_usec = txc.tick;
+        }
+
+        txc.offset = if ntp.time_status & STA_NANO != 0 {
+            ntp.time_offset
+        } else {
+            ntp.time_offset / NSEC_PER_USEC as i64
+        };
+    }
+
+    txc.freq = ntp.time_freq;
+    txc.maxerror = ntp.time_maxerror;
+    txc.esterror = ntp.time_esterror;
+    txc.status = ntp.time_status;
+    txc.constant = ntp.time_constant;
+    txc.precision = 1;
+    txc.tolerance = MAXFREQ_SCALED;
+    txc.tick = ntp.tick_usec;
+    txc.tai = ntp.time_tai;
+    // 不支持PPS
+    txc.ppsfreq = 0;
+    txc.jitter = 0;
+    txc.shift = 0;
+    txc.stabil = 0;
+    txc.jitcnt = 0;
+    txc.calcnt = 0;
+    txc.errcnt = 0;
+    txc.stbcnt = 0;
+
+    let state = if ntp.time_status & STA_UNSYNC != 0 {
+        TIME_ERROR
+    } else {
+        TIME_OK
+    };
+    let nano = ntp.time_status & STA_NANO != 0;
+    drop(ntp);
+
+    let now = getnstimeofday();
+    txc.time = PosixTimeval {
+        tv_sec: now.tv_sec,
+        tv_usec: if nano {
+            now.tv_nsec as i32
+        } else {
+            (now.tv_nsec / NSEC_PER_USEC as i64) as i32
+        },
+    };
+
+    return Ok(state);
+}
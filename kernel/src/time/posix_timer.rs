@@ -0,0 +1,526 @@
+//! POSIX定时器(timer_create)与间隔定时器(setitimer)
+//!
+//! timer_create创建的定时器和ITIMER_REAL基于高精度定时器实现；ITIMER_VIRTUAL和ITIMER_PROF
+//! 则在调度时钟中，按照进程消耗的cpu时间递减。
+//!
+//! 定时器在硬中断上下文中到期，为了避免在硬中断中获取进程的信号锁，信号统一在软中断中发送。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/time/posix-timers.c
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/time/itimer.c
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    exception::softirq::{softirq_vectors, SoftirqNumber, SoftirqVec},
+    ipc::signal_types::{SigInfo, SigType},
+    kinfo,
+    libs::spinlock::SpinLock,
+    process::{Pid, ProcessManager},
+    syscall::SystemError,
+};
+
+use super::{
+    hrtimer::{ktime_get, HrTimer, HrTimerFunction, HrTimerRestart, Ktime, TICK_PERIOD_NS},
+    syscall::PosixTimeval,
+    timekeeping::getnstimeofday,
+    TimeSpec, NSEC_PER_SEC, USEC_PER_SEC,
+};
+
+pub const CLOCK_REALTIME: i32 = 0;
+pub const CLOCK_MONOTONIC: i32 = 1;
+pub const CLOCK_MONOTONIC_RAW: i32 = 4;
+pub const CLOCK_BOOTTIME: i32 = 7;
+
+/// timer_settime的flags：到期时间为绝对时间
+pub const TIMER_ABSTIME: i32 = 1;
+
+/// 到期时发送信号
+pub const SIGEV_SIGNAL: i32 = 0;
+/// 到期时不通知
+pub const SIGEV_NONE: i32 = 1;
+/// 由libc在新的线程中调用通知函数。定时器不支持这种方式，mq_notify()通过netlink socket通知libc
+pub const SIGEV_THREAD: i32 = 2;
+/// 到期时向指定的线程发送信号
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+pub const ITIMER_REAL: i32 = 0;
+pub const ITIMER_VIRTUAL: i32 = 1;
+pub const ITIMER_PROF: i32 = 2;
+
+/// 每个进程最多能创建的定时器数量
+const POSIX_TIMER_MAX: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PosixItimerspec {
+    pub it_interval: TimeSpec,
+    pub it_value: TimeSpec,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PosixItimerval {
+    pub it_interval: PosixTimeval,
+    pub it_value: PosixTimeval,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PosixSigevent {
+    pub sigev_value: u64,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    /// SIGEV_THREAD_ID时，第一个元素为目标线程的tid
+    pub sigev_un: [i32; 12],
+}
+
+/// 把TimeSpec转换为纳秒
+pub fn timespec_to_ns(ts: &TimeSpec) -> Result<u64, SystemError> {
+    if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= NSEC_PER_SEC as i64 {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok((ts.tv_sec as u64)
+        .saturating_mul(NSEC_PER_SEC as u64)
+        .saturating_add(ts.tv_nsec as u64));
+}
+
+pub fn ns_to_timespec(ns: u64) -> TimeSpec {
+    TimeSpec::new(
+        (ns / NSEC_PER_SEC as u64) as i64,
+        (ns % NSEC_PER_SEC as u64) as i64,
+    )
+}
+
+/// 把PosixTimeval转换为纳秒
+pub fn timeval_to_ns(tv: &PosixTimeval) -> Result<u64, SystemError> {
+    if tv.tv_sec < 0 || tv.tv_usec < 0 || tv.tv_usec >= USEC_PER_SEC as i32 {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok((tv.tv_sec as u64)
+        .saturating_mul(NSEC_PER_SEC as u64)
+        .saturating_add(tv.tv_usec as u64 * 1000));
+}
+
+pub fn ns_to_timeval(ns: u64) -> PosixTimeval {
+    // 不足1us的剩余时间向上取整，避免把仍在运行的定时器报告为已停止
+    let us = (ns + 999) / 1000;
+    PosixTimeval {
+        tv_sec: (us / USEC_PER_SEC as u64) as i64,
+        tv_usec: (us % USEC_PER_SEC as u64) as i32,
+    }
+}
+
+/// 把给定时钟下的绝对时间转换为单调时间
+fn clock_to_ktime(clock: i32, ns: u64) -> Ktime {
+    match clock {
+        CLOCK_REALTIME => {
+            let now_real = timespec_to_ns(&getnstimeofday()).unwrap_or(0);
+            ktime_get().saturating_add(ns.saturating_sub(now_real))
+        }
+        _ => ns,
+    }
+}
+
+/// 定时器到期时发送的信号
+#[derive(Debug, Clone, Copy)]
+pub struct TimerNotify {
+    pid: Pid,
+    signal: Signal,
+    code: SigCode,
+}
+
+impl TimerNotify {
+    pub fn new(pid: Pid, signal: Signal, code: SigCode) -> Self {
+        Self { pid, signal, code }
+    }
+}
+
+#[derive(Debug)]
+pub struct PosixTimer {
+    id: i32,
+    clock: i32,
+    /// 为None时表示到期时不通知(SIGEV_NONE)
+    notify: Option<TimerNotify>,
+    hrtimer: Arc<HrTimer>,
+    inner: SpinLock<PosixTimerInner>,
+    self_ref: Weak<PosixTimer>,
+}
+
+#[derive(Debug, Default)]
+struct PosixTimerInner {
+    /// 下一次到期的时刻（单调时间），为0表示定时器未启动
+    expires: Ktime,
+    /// 到期之后重新启动的间隔(ns)，为0表示只触发一次
+    interval: u64,
+    /// 信号发送之前，定时器额外到期的次数
+    overrun: i32,
+    /// 上一次发送信号时的overrun
+    last_overrun: i32,
+    /// 信号是否正在等待发送
+    sigpending: bool,
+}
+
+impl PosixTimer {
+    fn new(id: i32, clock: i32, notify: Option<TimerNotify>) -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| PosixTimer {
+            id,
+            clock,
+            notify,
+            hrtimer: HrTimer::new(Box::new(PosixTimerFunction(self_ref.clone()))),
+            inner: SpinLock::new(PosixTimerInner::default()),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    /// 获取定时器的剩余时间和间隔
+    pub fn gettime(&self) -> PosixItimerspec {
+        let inner = self.inner.lock_irqsave();
+        let remaining = if inner.expires == 0 {
+            0
+        } else {
+            // 已经到期但还没有处理的定时器，报告为1ns
+            core::cmp::max(inner.expires.saturating_sub(ktime_get()), 1)
+        };
+        return PosixItimerspec {
+            it_interval: ns_to_timespec(inner.interval),
+            it_value: ns_to_timespec(remaining),
+        };
+    }
+
+    /// 设置定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `value`: 到期时间(ns)，为0表示停止定时器
+    /// - `interval`: 重新启动的间隔(ns)
+    /// - `absolute`: `value`是否为定时器所用时钟下的绝对时间
+    ///
+    /// ## 返回值
+    ///
+    /// 设置之前的剩余时间和间隔
+    pub fn settime(
+        &self,
+        value: u64,
+        interval: u64,
+        absolute: bool,
+    ) -> Result<PosixItimerspec, SystemError> {
+        let old = self.gettime();
+        self.hrtimer.cancel();
+
+        let mut inner = self.inner.lock_irqsave();
+        inner.interval = interval;
+        inner.overrun = 0;
+        if value == 0 {
+            inner.expires = 0;
+            return Ok(old);
+        }
+
+        let expires = if absolute {
+            clock_to_ktime(self.clock, value)
+        } else {
+            ktime_get().saturating_add(value)
+        };
+        inner.expires = core::cmp::max(expires, 1);
+        drop(inner);
+
+        if let Err(e) = self.hrtimer.start(expires) {
+            self.inner.lock_irqsave().expires = 0;
+            return Err(e);
+        }
+        return Ok(old);
+    }
+
+    /// 上一次发送信号时，定时器额外到期的次数
+    pub fn overrun(&self) -> i32 {
+        self.inner.lock_irqsave().last_overrun
+    }
+
+    /// 停止定时器
+    fn stop(&self) {
+        self.hrtimer.cancel();
+        self.inner.lock_irqsave().expires = 0;
+    }
+
+    /// 定时器到期时，在硬中断上下文中调用
+    fn expire(&self, now: Ktime) -> HrTimerRestart {
+        let mut inner = self.inner.lock_irqsave();
+        if inner.expires == 0 {
+            return HrTimerRestart::NoRestart;
+        }
+
+        let mut restart = HrTimerRestart::NoRestart;
+        if inner.interval > 0 {
+            // 跳过已经错过的周期，并计入overrun
+            let missed = now.saturating_sub(inner.expires) / inner.interval;
+            inner.overrun = inner
+                .overrun
+                .saturating_add(core::cmp::min(missed, i32::MAX as u64) as i32);
+            inner.expires += (missed + 1) * inner.interval;
+            restart = HrTimerRestart::Restart(inner.expires);
+        } else {
+            inner.expires = 0;
+        }
+
+        if let Some(notify) = self.notify {
+            if inner.sigpending {
+                inner.overrun = inner.overrun.saturating_add(1);
+            } else {
+                inner.sigpending = true;
+                queue_timer_signal(notify, self.self_ref.upgrade());
+            }
+        }
+        return restart;
+    }
+}
+
+#[derive(Debug)]
+struct PosixTimerFunction(Weak<PosixTimer>);
+
+impl HrTimerFunction for PosixTimerFunction {
+    fn run(&mut self, now: Ktime) -> HrTimerRestart {
+        match self.0.upgrade() {
+            Some(timer) => timer.expire(now),
+            None => HrTimerRestart::NoRestart,
+        }
+    }
+}
+
+/// 按照进程消耗的cpu时间递减的间隔定时器（ITIMER_VIRTUAL和ITIMER_PROF）
+#[derive(Debug, Default, Clone, Copy)]
+struct CpuItimer {
+    /// 剩余时间(ns)，为0表示未启动
+    value: u64,
+    interval: u64,
+}
+
+impl CpuItimer {
+    fn get(&self) -> PosixItimerval {
+        PosixItimerval {
+            it_interval: ns_to_timeval(self.interval),
+            it_value: ns_to_timeval(self.value),
+        }
+    }
+
+    /// 消耗了`ns`的cpu时间，返回定时器是否到期
+    fn account(&mut self, ns: u64) -> bool {
+        if self.value == 0 {
+            return false;
+        }
+        if self.value > ns {
+            self.value -= ns;
+            return false;
+        }
+        self.value = self.interval;
+        return true;
+    }
+}
+
+/// 进程的定时器
+#[derive(Debug, Default)]
+pub struct ProcessTimers {
+    /// timer_create创建的定时器
+    timers: BTreeMap<i32, Arc<PosixTimer>>,
+    itimer_real: Option<Arc<PosixTimer>>,
+    itimer_virtual: CpuItimer,
+    itimer_prof: CpuItimer,
+}
+
+impl ProcessTimers {
+    /// 创建一个定时器
+    ///
+    /// ## 返回值
+    ///
+    /// 定时器的id
+    ///
+    /// ## 错误
+    ///
+    /// - `EAGAIN`：定时器数量已经达到上限
+    pub fn create(&mut self, clock: i32, notify: Option<TimerNotify>) -> Result<i32, SystemError> {
+        if self.timers.len() >= POSIX_TIMER_MAX {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        let id = (0..POSIX_TIMER_MAX as i32)
+            .find(|id| !self.timers.contains_key(id))
+            .ok_or(SystemError::EAGAIN_OR_EWOULDBLOCK)?;
+        self.timers.insert(id, PosixTimer::new(id, clock, notify));
+        return Ok(id);
+    }
+
+    pub fn get(&self, id: i32) -> Result<Arc<PosixTimer>, SystemError> {
+        self.timers.get(&id).cloned().ok_or(SystemError::EINVAL)
+    }
+
+    /// 删除一个定时器
+    pub fn delete(&mut self, id: i32) -> Result<(), SystemError> {
+        let timer = self.timers.remove(&id).ok_or(SystemError::EINVAL)?;
+        timer.stop();
+        return Ok(());
+    }
+
+    /// 删除所有timer_create创建的定时器（execve时调用）
+    pub fn delete_posix_timers(&mut self) {
+        for (_, timer) in core::mem::take(&mut self.timers) {
+            timer.stop();
+        }
+    }
+
+    /// 停止所有定时器（进程退出时调用）
+    pub fn clear(&mut self) {
+        self.delete_posix_timers();
+        if let Some(timer) = self.itimer_real.take() {
+            timer.stop();
+        }
+        self.itimer_virtual = CpuItimer::default();
+        self.itimer_prof = CpuItimer::default();
+    }
+
+    /// 获取间隔定时器的值
+    pub fn getitimer(&self, which: i32) -> Result<PosixItimerval, SystemError> {
+        match which {
+            ITIMER_REAL => {
+                let spec = self
+                    .itimer_real
+                    .as_ref()
+                    .map(|t| t.gettime())
+                    .unwrap_or_default();
+                Ok(PosixItimerval {
+                    it_interval: ns_to_timeval(timespec_to_ns(&spec.it_interval)?),
+                    it_value: ns_to_timeval(timespec_to_ns(&spec.it_value)?),
+                })
+            }
+            ITIMER_VIRTUAL => Ok(self.itimer_virtual.get()),
+            ITIMER_PROF => Ok(self.itimer_prof.get()),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    /// 设置间隔定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `pid`: 定时器所属的进程
+    /// - `which`: 定时器的类型
+    /// - `value`: 到期时间(ns)，为0表示停止定时器
+    /// - `interval`: 重新启动的间隔(ns)
+    ///
+    /// ## 返回值
+    ///
+    /// 设置之前的值
+    pub fn setitimer(
+        &mut self,
+        pid: Pid,
+        which: i32,
+        value: u64,
+        interval: u64,
+    ) -> Result<PosixItimerval, SystemError> {
+        let old = self.getitimer(which)?;
+        match which {
+            ITIMER_REAL => {
+                let timer = self.itimer_real.get_or_insert_with(|| {
+                    let notify = TimerNotify::new(pid, Signal::SIGALRM, SigCode::Kernel);
+                    PosixTimer::new(0, CLOCK_MONOTONIC, Some(notify))
+                });
+                timer.settime(value, interval, false)?;
+            }
+            ITIMER_VIRTUAL => self.itimer_virtual = CpuItimer { value, interval },
+            ITIMER_PROF => self.itimer_prof = CpuItimer { value, interval },
+            _ => return Err(SystemError::EINVAL),
+        }
+        return Ok(old);
+    }
+}
+
+/// 等待在软中断中发送的信号
+#[derive(Debug)]
+struct PendingTimerSignal {
+    notify: TimerNotify,
+    timer: Option<Arc<PosixTimer>>,
+}
+
+static PENDING_TIMER_SIGNALS: SpinLock<Vec<PendingTimerSignal>> = SpinLock::new(Vec::new());
+
+fn queue_timer_signal(notify: TimerNotify, timer: Option<Arc<PosixTimer>>) {
+    PENDING_TIMER_SIGNALS
+        .lock_irqsave()
+        .push(PendingTimerSignal { notify, timer });
+    softirq_vectors().raise_softirq(SoftirqNumber::PosixTimer);
+}
+
+#[derive(Debug)]
+struct PosixTimerSoftirq;
+
+impl SoftirqVec for PosixTimerSoftirq {
+    fn run(&self) {
+        let pending = core::mem::take(&mut *PENDING_TIMER_SIGNALS.lock_irqsave());
+        for p in pending {
+            let (timerid, overrun) = match p.timer {
+                Some(timer) => {
+                    let mut inner = timer.inner.lock_irqsave();
+                    inner.sigpending = false;
+                    inner.last_overrun = inner.overrun;
+                    inner.overrun = 0;
+                    (timer.id, inner.last_overrun)
+                }
+                None => (0, 0),
+            };
+            let mut info = SigInfo::new(
+                p.notify.signal,
+                0,
+                p.notify.code,
+                SigType::Timer { timerid, overrun },
+            );
+            // 目标进程可能已经退出
+            p.notify
+                .signal
+                .send_signal_info(Some(&mut info), p.notify.pid)
+                .ok();
+        }
+    }
+}
+
+/// 调度时钟到来时调用，递减当前进程的ITIMER_VIRTUAL和ITIMER_PROF
+///
+/// ## 参数
+///
+/// - `user`: 时钟中断是否发生在用户态
+pub fn account_process_tick(user: bool) {
+    let pcb = ProcessManager::current_pcb();
+    if pcb.pid() == Pid::new(0) {
+        return;
+    }
+
+    let mut timers = pcb.posix_timers_irqsave();
+    if user && timers.itimer_virtual.account(TICK_PERIOD_NS) {
+        queue_timer_signal(
+            TimerNotify::new(pcb.pid(), Signal::SIGVTALRM, SigCode::Kernel),
+            None,
+        );
+    }
+    if timers.itimer_prof.account(TICK_PERIOD_NS) {
+        queue_timer_signal(
+            TimerNotify::new(pcb.pid(), Signal::SIGPROF, SigCode::Kernel),
+            None,
+        );
+    }
+}
+
+pub fn posix_timer_init() {
+    softirq_vectors()
+        .register_softirq(SoftirqNumber::PosixTimer, Arc::new(PosixTimerSoftirq))
+        .expect("Failed to register posix timer softirq");
+    kinfo!("posix timer initialized successfully");
+}
+
+//===== 以下为提供给C的接口 =====
+
+/// 周期模式下，Local APIC定时器的中断处理函数中调用
+#[no_mangle]
+pub extern "C" fn rs_account_process_tick(user: bool) {
+    account_process_tick(user);
+}
@@ -1,16 +1,17 @@
-use core::{arch::x86_64::_rdtsc, hint::spin_loop};
+use core::hint::spin_loop;
 
 use alloc::{boxed::Box, sync::Arc};
 
 use crate::{
     arch::{sched::sched, CurrentIrqArch},
     exception::InterruptArch,
-    include::bindings::bindings::{useconds_t, Cpu_tsc_freq},
+    include::bindings::bindings::useconds_t,
     process::ProcessManager,
     syscall::SystemError,
 };
 
 use super::{
+    hrtimer::{hrtimer_nanosleep, ktime_get},
     timer::{next_n_us_timer_jiffies, Timer, WakeUpHelper},
     TimeSpec,
 };
@@ -23,15 +24,30 @@ use super::{
 ///
 /// @return Err(SystemError) 错误码
 pub fn nanosleep(sleep_time: TimeSpec) -> Result<TimeSpec, SystemError> {
-    if sleep_time.tv_nsec < 0 || sleep_time.tv_nsec >= 1000000000 {
+    if sleep_time.tv_sec < 0 || sleep_time.tv_nsec < 0 || sleep_time.tv_nsec >= 1000000000 {
         return Err(SystemError::EINVAL);
     }
-    // 对于小于500us的时间，使用spin/rdtsc来进行定时
+    let total_ns = (sleep_time.tv_sec as u64)
+        .saturating_mul(1000000000)
+        .saturating_add(sleep_time.tv_nsec as u64);
 
-    if sleep_time.tv_nsec < 500000 {
-        let expired_tsc: u64 =
-            unsafe { _rdtsc() + (sleep_time.tv_nsec as u64 * Cpu_tsc_freq) / 1000000000 };
-        while unsafe { _rdtsc() } < expired_tsc {
+    // 优先使用高精度定时器
+    match hrtimer_nanosleep(total_ns) {
+        Ok(remain) => {
+            return Ok(TimeSpec {
+                tv_sec: (remain / 1000000000) as i64,
+                tv_nsec: (remain % 1000000000) as i64,
+            });
+        }
+        Err(SystemError::ENODEV) => {}
+        Err(e) => return Err(e),
+    }
+
+    // 对于小于500us的时间，通过架构的时钟周期计数器忙等待(时钟频率未知时，ktime_get()返回0，改用定时器)
+    let now = ktime_get();
+    if total_ns < 500000 && now != 0 {
+        let expires = now + total_ns;
+        while ktime_get() < expires {
             spin_loop()
         }
         return Ok(TimeSpec {
@@ -41,10 +57,7 @@ pub fn nanosleep(sleep_time: TimeSpec) -> Result<TimeSpec, SystemError> {
     }
     // 创建定时器
     let handler: Box<WakeUpHelper> = WakeUpHelper::new(ProcessManager::current_pcb());
-    let timer: Arc<Timer> = Timer::new(
-        handler,
-        next_n_us_timer_jiffies((sleep_time.tv_nsec / 1000) as u64),
-    );
+    let timer: Arc<Timer> = Timer::new(handler, next_n_us_timer_jiffies(total_ns / 1000));
 
     let irq_guard: crate::exception::IrqFlagsGuard =
         unsafe { CurrentIrqArch::save_and_disable_irq() };
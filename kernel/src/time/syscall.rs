@@ -1,14 +1,26 @@
-use core::{
-    ffi::{c_int, c_longlong},
-    ptr::null_mut,
-};
+use core::ffi::{c_int, c_longlong};
 
 use crate::{
-    syscall::{user_access::UserBufferWriter, Syscall, SystemError},
-    time::{sleep::nanosleep, TimeSpec},
+    arch::ipc::signal::{SigCode, Signal},
+    process::{Pid, ProcessManager},
+    syscall::{
+        user_access::{UserBufferWriter, UserPtr},
+        Syscall, SystemError,
+    },
+    time::{
+        hrtimer::ktime_get,
+        ntp::{do_adjtimex, PosixTimex},
+        posix_timer::{
+            ns_to_timespec, timespec_to_ns, timeval_to_ns, PosixItimerspec, PosixItimerval,
+            PosixSigevent, TimerNotify, CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_MONOTONIC_RAW,
+            CLOCK_REALTIME, SIGEV_NONE, SIGEV_SIGNAL, SIGEV_THREAD_ID, TIMER_ABSTIME,
+        },
+        sleep::nanosleep,
+        TimeSpec,
+    },
 };
 
-use super::timekeeping::do_gettimeofday;
+use super::timekeeping::{do_gettimeofday, do_settimeofday, getnstimeofday, ktime_get_boottime};
 
 pub type PosixTimeT = c_longlong;
 pub type PosixSusecondsT = c_int;
@@ -47,26 +59,20 @@ impl Syscall {
     ///
     /// @return Err(SystemError) 错误码
     pub fn nanosleep(
-        sleep_time: *const TimeSpec,
-        rm_time: *mut TimeSpec,
+        sleep_time: UserPtr<TimeSpec>,
+        rm_time: UserPtr<TimeSpec>,
     ) -> Result<usize, SystemError> {
-        if sleep_time == null_mut() {
+        if sleep_time.is_null() {
             return Err(SystemError::EFAULT);
         }
-        let slt_spec = TimeSpec {
-            tv_sec: unsafe { *sleep_time }.tv_sec,
-            tv_nsec: unsafe { *sleep_time }.tv_nsec,
-        };
+        let slt_spec: TimeSpec = sleep_time.read()?;
 
-        let r: Result<usize, SystemError> = nanosleep(slt_spec).map(|slt_spec| {
-            if rm_time != null_mut() {
-                unsafe { *rm_time }.tv_sec = slt_spec.tv_sec;
-                unsafe { *rm_time }.tv_nsec = slt_spec.tv_nsec;
-            }
-            0
-        });
+        let rm_spec = nanosleep(slt_spec)?;
+        if !rm_time.is_null() {
+            rm_time.write(&rm_spec)?;
+        }
 
-        return r;
+        return Ok(0);
     }
 
     /// 获取cpu时间
@@ -107,4 +113,254 @@ impl Syscall {
 
         return Ok(0);
     }
+
+    /// 设置墙上时间，同时写入RTC
+    ///
+    /// ## 参数
+    ///
+    /// - `tv`: 要设置的时间，为空时不设置
+    /// - `timezone`: 时区信息，暂不支持设置，会被忽略
+    pub fn settimeofday(
+        tv: UserPtr<PosixTimeval>,
+        _timezone: UserPtr<PosixTimeZone>,
+    ) -> Result<usize, SystemError> {
+        if tv.is_null() {
+            return Ok(0);
+        }
+        let ns = timeval_to_ns(&tv.read()?)?;
+        do_settimeofday(&ns_to_timespec(ns))?;
+        return Ok(0);
+    }
+
+    /// 获取时钟的当前时间
+    ///
+    /// 用户态的C库优先通过vvar页读取时间，只有在vvar页不可用时才会调用它
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id`: CLOCK_REALTIME、CLOCK_MONOTONIC、CLOCK_MONOTONIC_RAW或CLOCK_BOOTTIME
+    /// - `tp`: 传出参数，当前时间
+    pub fn clock_gettime(clock_id: i32, tp: UserPtr<TimeSpec>) -> Result<usize, SystemError> {
+        if tp.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let ts = match clock_id {
+            CLOCK_REALTIME => getnstimeofday(),
+            CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW => ns_to_timespec(ktime_get()),
+            CLOCK_BOOTTIME => ns_to_timespec(ktime_get_boottime()),
+            _ => return Err(SystemError::EINVAL),
+        };
+        tp.write(&ts)?;
+        return Ok(0);
+    }
+
+    /// 设置时钟的时间，只有CLOCK_REALTIME是可以设置的
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id`: 要设置的时钟
+    /// - `tp`: 要设置的时间
+    pub fn clock_settime(clock_id: i32, tp: UserPtr<TimeSpec>) -> Result<usize, SystemError> {
+        if tp.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        if clock_id != CLOCK_REALTIME {
+            return Err(SystemError::EINVAL);
+        }
+        let ts = tp.read()?;
+        do_settimeofday(&ts)?;
+        return Ok(0);
+    }
+
+    /// 读取或调整内核的时钟参数
+    ///
+    /// ## 参数
+    ///
+    /// - `txc`: 要设置的参数，返回时填入当前的参数
+    ///
+    /// ## 返回值
+    ///
+    /// 时钟的状态：TIME_OK或TIME_ERROR
+    pub fn adjtimex(txc: UserPtr<PosixTimex>) -> Result<usize, SystemError> {
+        if txc.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let mut timex = txc.read()?;
+        let state = do_adjtimex(&mut timex)?;
+        txc.write(&timex)?;
+        return Ok(state);
+    }
+
+    /// 创建一个POSIX定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `clockid`: 定时器使用的时钟，支持CLOCK_REALTIME和CLOCK_MONOTONIC
+    /// - `sevp`: 到期时的通知方式，为空时表示到期时向当前进程发送SIGALRM
+    /// - `timerid`: 传出参数，新创建的定时器的id
+    pub fn timer_create(
+        clockid: i32,
+        sevp: UserPtr<PosixSigevent>,
+        timerid: UserPtr<i32>,
+    ) -> Result<usize, SystemError> {
+        if clockid != CLOCK_REALTIME && clockid != CLOCK_MONOTONIC {
+            return Err(SystemError::EINVAL);
+        }
+        if timerid.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+
+        let current = ProcessManager::current_pcb();
+        let notify = if sevp.is_null() {
+            Some(TimerNotify::new(
+                current.pid(),
+                Signal::SIGALRM,
+                SigCode::Timer,
+            ))
+        } else {
+            let event = sevp.read()?;
+            let pid = match event.sigev_notify {
+                SIGEV_NONE => None,
+                SIGEV_SIGNAL => Some(current.pid()),
+                SIGEV_THREAD_ID => {
+                    let pid = Pid::new(event.sigev_un[0] as usize);
+                    let pcb = ProcessManager::find_vpid(pid).ok_or(SystemError::EINVAL)?;
+                    Some(pcb.pid())
+                }
+                _ => return Err(SystemError::EINVAL),
+            };
+            match pid {
+                Some(pid) => {
+                    let sig = Signal::from(event.sigev_signo);
+                    if sig == Signal::INVALID {
+                        return Err(SystemError::EINVAL);
+                    }
+                    Some(TimerNotify::new(pid, sig, SigCode::Timer))
+                }
+                None => None,
+            }
+        };
+
+        let id = current.posix_timers_irqsave().create(clockid, notify)?;
+        if let Err(e) = timerid.write(&id) {
+            current.posix_timers_irqsave().delete(id).ok();
+            return Err(e);
+        }
+        return Ok(0);
+    }
+
+    /// 启动或者停止POSIX定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `timerid`: 定时器的id
+    /// - `flags`: 为TIMER_ABSTIME时，`new_value`中的到期时间为绝对时间
+    /// - `new_value`: 到期时间和间隔，到期时间为0时停止定时器
+    /// - `old_value`: 传出参数，设置之前的剩余时间和间隔
+    pub fn timer_settime(
+        timerid: i32,
+        flags: i32,
+        new_value: UserPtr<PosixItimerspec>,
+        old_value: UserPtr<PosixItimerspec>,
+    ) -> Result<usize, SystemError> {
+        if new_value.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let spec = new_value.read()?;
+        let value = timespec_to_ns(&spec.it_value)?;
+        let interval = timespec_to_ns(&spec.it_interval)?;
+
+        let timer = ProcessManager::current_pcb()
+            .posix_timers_irqsave()
+            .get(timerid)?;
+        let old = timer.settime(value, interval, flags & TIMER_ABSTIME != 0)?;
+        if !old_value.is_null() {
+            old_value.write(&old)?;
+        }
+        return Ok(0);
+    }
+
+    /// 获取POSIX定时器的剩余时间和间隔
+    pub fn timer_gettime(
+        timerid: i32,
+        curr_value: UserPtr<PosixItimerspec>,
+    ) -> Result<usize, SystemError> {
+        if curr_value.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let timer = ProcessManager::current_pcb()
+            .posix_timers_irqsave()
+            .get(timerid)?;
+        curr_value.write(&timer.gettime())?;
+        return Ok(0);
+    }
+
+    /// 获取POSIX定时器上一次发送信号时，额外到期的次数
+    pub fn timer_getoverrun(timerid: i32) -> Result<usize, SystemError> {
+        let timer = ProcessManager::current_pcb()
+            .posix_timers_irqsave()
+            .get(timerid)?;
+        return Ok(timer.overrun() as usize);
+    }
+
+    /// 删除POSIX定时器
+    pub fn timer_delete(timerid: i32) -> Result<usize, SystemError> {
+        ProcessManager::current_pcb()
+            .posix_timers_irqsave()
+            .delete(timerid)?;
+        return Ok(0);
+    }
+
+    /// 获取间隔定时器的值
+    ///
+    /// ## 参数
+    ///
+    /// - `which`: ITIMER_REAL、ITIMER_VIRTUAL或ITIMER_PROF
+    /// - `curr_value`: 传出参数，定时器的剩余时间和间隔
+    pub fn getitimer(
+        which: i32,
+        curr_value: UserPtr<PosixItimerval>,
+    ) -> Result<usize, SystemError> {
+        if curr_value.is_null() {
+            return Err(SystemError::EFAULT);
+        }
+        let value = ProcessManager::current_pcb()
+            .posix_timers_irqsave()
+            .getitimer(which)?;
+        curr_value.write(&value)?;
+        return Ok(0);
+    }
+
+    /// 设置间隔定时器
+    ///
+    /// ## 参数
+    ///
+    /// - `which`: ITIMER_REAL、ITIMER_VIRTUAL或ITIMER_PROF
+    /// - `new_value`: 到期时间和间隔，为空或者到期时间为0时停止定时器
+    /// - `old_value`: 传出参数，设置之前的剩余时间和间隔
+    pub fn setitimer(
+        which: i32,
+        new_value: UserPtr<PosixItimerval>,
+        old_value: UserPtr<PosixItimerval>,
+    ) -> Result<usize, SystemError> {
+        let (value, interval) = if new_value.is_null() {
+            (0, 0)
+        } else {
+            let val = new_value.read()?;
+            (
+                timeval_to_ns(&val.it_value)?,
+                timeval_to_ns(&val.it_interval)?,
+            )
+        };
+
+        let current = ProcessManager::current_pcb();
+        let old =
+            current
+                .posix_timers_irqsave()
+                .setitimer(current.pid(), which, value, interval)?;
+        if !old_value.is_null() {
+            old_value.write(&old)?;
+        }
+        return Ok(0);
+    }
 }
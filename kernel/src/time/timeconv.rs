@@ -13,14 +13,14 @@ const MON_OF_YDAY: [[i64; 13]; 2] = [
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct CalendarTime {
-    tm_sec: i32,
-    tm_min: i32,
-    tm_hour: i32,
-    tm_mday: i32,
-    tm_mon: i32,
-    tm_wday: i32,
-    tm_yday: i32,
-    tm_year: i32,
+    pub tm_sec: i32,
+    pub tm_min: i32,
+    pub tm_hour: i32,
+    pub tm_mday: i32,
+    pub tm_mon: i32,
+    pub tm_wday: i32,
+    pub tm_yday: i32,
+    pub tm_year: i32,
 }
 impl CalendarTime {
     pub fn new() -> Self {
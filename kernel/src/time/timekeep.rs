@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use crate::driver::timers::rtc::rtc::RtcTime;
+use crate::{driver::timers::rtc::rtc::RtcTime, syscall::SystemError};
+
+use super::{timeconv::time_to_calendar, NSEC_PER_SEC};
+
+/// RTC中保存的时间相对于UTC的偏移(秒)，当前未引入时区，默认为UTC+8
+const RTC_UTC_OFFSET: i32 = 8 * 3600;
 
 #[allow(non_camel_case_types)]
 pub type ktime_t = i64;
@@ -37,7 +42,11 @@ fn ktime_get_real() -> ktime_t {
     for month in 1..rtc_time.month {
         match month {
             1 | 3 | 5 | 7 | 8 | 10 | 12 => day_count += 31,
-            2 => day_count += 28,
+            2 => {
+                let leap: bool = (rtc_time.year % 4 == 0) && (rtc_time.year % 100 != 0)
+                    || (rtc_time.year % 400 == 0);
+                day_count += if leap { 29 } else { 28 };
+            }
             4 | 6 | 9 | 11 => day_count += 30,
             _ => day_count += 0,
         }
@@ -46,9 +55,10 @@ fn ktime_get_real() -> ktime_t {
     day_count += rtc_time.day - 1;
     //转换成纳秒
     let timestamp: ktime_t = day_count as i64 * 86_400_000_000_000i64
-        + (rtc_time.hour - 8) as i64 * 3_600_000_000_000i64
+        + rtc_time.hour as i64 * 3_600_000_000_000i64
         + rtc_time.minute as i64 * 60_000_000_000i64
-        + rtc_time.second as i64 * 1_000_000_000u64 as ktime_t;
+        + rtc_time.second as i64 * 1_000_000_000u64 as ktime_t
+        - RTC_UTC_OFFSET as i64 * 1_000_000_000i64;
 
     return timestamp;
 }
@@ -59,3 +69,26 @@ pub fn ktime_get_real_ns() -> i64 {
     let kt: ktime_t = ktime_get_real();
     return ktime_to_ns(kt);
 }
+
+/// 把时间戳写入RTC
+///
+/// ## 参数
+///
+/// - `ns`: 从UTC+0 1970-01-01 00:00到要设置的时间所经过的纳秒数
+///
+/// ## 错误
+///
+/// - `EINVAL`：时间超出了RTC能够表示的范围
+pub fn ktime_set_real_ns(ns: i64) -> Result<(), SystemError> {
+    let secs = ns.div_euclid(NSEC_PER_SEC as i64);
+    let tm = time_to_calendar(secs, RTC_UTC_OFFSET);
+    let rtc_time = RtcTime {
+        second: tm.tm_sec,
+        minute: tm.tm_min,
+        hour: tm.tm_hour,
+        day: tm.tm_mday,
+        month: tm.tm_mon + 1,
+        year: tm.tm_year + 1900,
+    };
+    return rtc_time.set();
+}
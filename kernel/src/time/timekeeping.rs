@@ -1,19 +1,26 @@
 use alloc::sync::Arc;
 use core::sync::atomic::{compiler_fence, AtomicBool, AtomicI64, Ordering};
-use x86_64::align_up;
 
 use crate::{
     arch::CurrentIrqArch,
     exception::InterruptArch,
-    kdebug, kinfo,
+    kdebug, kinfo, kwarn,
     libs::rwlock::RwLock,
-    time::{jiffies::clocksource_default_clock, timekeep::ktime_get_real_ns, TimeSpec},
+    syscall::SystemError,
+    time::{
+        hrtimer::{ktime_get, Ktime},
+        jiffies::clocksource_default_clock,
+        ntp::{ntp_advance, ntp_clear},
+        timekeep::{ktime_get_real_ns, ktime_set_real_ns},
+        vsyscall::vsyscall_update,
+        TimeSpec,
+    },
 };
 
 use super::{
     clocksource::{clocksource_cyc2ns, Clocksource, CycleNum, HZ},
     syscall::PosixTimeval,
-    NSEC_PER_SEC, USEC_PER_SEC,
+    NSEC_PER_SEC,
 };
 /// NTP周期频率
 pub const NTP_INTERVAL_FREQ: u64 = HZ;
@@ -24,10 +31,6 @@ pub const NTP_SCALE_SHIFT: u32 = 32;
 
 /// timekeeping休眠标志，false为未休眠
 pub static TIMEKEEPING_SUSPENDED: AtomicBool = AtomicBool::new(false);
-/// 已经递增的微秒数
-static __ADDED_USEC: AtomicI64 = AtomicI64::new(0);
-/// 已经递增的秒数
-static __ADDED_SEC: AtomicI64 = AtomicI64::new(0);
 /// timekeeper全局变量，用于管理timekeeper模块
 static mut __TIMEKEEPER: Option<Timekeeper> = None;
 
@@ -58,9 +61,14 @@ pub struct TimekeeperData {
     /// NTP调整时钟乘法器
     mult: u32,
     raw_time: TimeSpec,
+    /// 单调时间与墙上时间的差值
     wall_to_monotonic: TimeSpec,
+    /// 系统休眠的总时间，CLOCK_BOOTTIME = 单调时间 + total_sleep_time
     total_sleep_time: TimeSpec,
+    /// 上一次更新时的墙上时间
     xtime: TimeSpec,
+    /// 上一次更新墙上时间时的单调时间(ns)，为0表示时钟源尚不可用
+    last_update: Ktime,
 }
 impl TimekeeperData {
     pub fn new() -> Self {
@@ -91,6 +99,7 @@ impl TimekeeperData {
                 tv_nsec: 0,
                 tv_sec: 0,
             },
+            last_update: 0,
         }
     }
 }
@@ -151,42 +160,50 @@ pub fn timekeeper_init() {
     unsafe { __TIMEKEEPER = Some(Timekeeper(RwLock::new(TimekeeperData::new()))) };
 }
 
+/// 时钟源不可用时，每次更新墙上时间递增的纳秒数(HPET中断的周期)
+const WALL_TIME_FALLBACK_NS: i64 = 500000;
+
+#[inline]
+fn timespec_to_ns_signed(ts: &TimeSpec) -> i64 {
+    ts.tv_sec
+        .saturating_mul(NSEC_PER_SEC as i64)
+        .saturating_add(ts.tv_nsec)
+}
+
+#[inline]
+fn ns_to_timespec_signed(ns: i64) -> TimeSpec {
+    TimeSpec::new(
+        ns.div_euclid(NSEC_PER_SEC as i64),
+        ns.rem_euclid(NSEC_PER_SEC as i64),
+    )
+}
+
+/// 读取timekeeper的数据
+///
+/// 墙上时间在中断上下文中更新，读者不能在持有读锁的同时被同一个cpu上的写者打断，
+/// 因此这里不阻塞地尝试获取读锁
+fn timekeeper_read<R>(f: impl Fn(&TimekeeperData) -> R) -> R {
+    loop {
+        if let Some(tk) = timekeeper().0.try_read() {
+            return f(&tk);
+        }
+        core::hint::spin_loop();
+    }
+}
+
 /// # 获取1970.1.1至今的UTC时间戳(最小单位:nsec)
 ///
 /// ## 返回值
 ///
 /// * 'TimeSpec' - 时间戳
 pub fn getnstimeofday() -> TimeSpec {
-    // kdebug!("enter getnstimeofday");
-
-    // let mut nsecs: u64 = 0;0
-    let mut _xtime = TimeSpec {
-        tv_nsec: 0,
-        tv_sec: 0,
-    };
-    loop {
-        match timekeeper().0.try_read() {
-            None => continue,
-            Some(tk) => {
-                _xtime = tk.xtime;
-                drop(tk);
-                // nsecs = timekeeper().tk_get_ns();
-                // TODO 不同架构可能需要加上不同的偏移量
-                break;
-            }
-        }
-    }
-    // xtime.tv_nsec += nsecs as i64;
-    let sec = __ADDED_SEC.load(Ordering::SeqCst);
-    _xtime.tv_sec += sec;
-    while _xtime.tv_nsec >= NSEC_PER_SEC.into() {
-        _xtime.tv_nsec -= NSEC_PER_SEC as i64;
-        _xtime.tv_sec += 1;
+    let (xtime, last_update) = timekeeper_read(|tk| (tk.xtime, tk.last_update));
+    let mut ns = timespec_to_ns_signed(&xtime);
+    if last_update != 0 {
+        // 加上距离上次更新墙上时间所经过的时间
+        ns = ns.saturating_add(ktime_get().saturating_sub(last_update) as i64);
     }
-
-    // TODO 将xtime和当前时间源的时间相加
-
-    return _xtime;
+    return ns_to_timespec_signed(ns);
 }
 
 /// # 获取1970.1.1至今的UTC时间戳(最小单位:usec)
@@ -202,14 +219,71 @@ pub fn do_gettimeofday() -> PosixTimeval {
     };
 }
 
+/// 获取包含系统休眠时间在内的单调时间(ns)，即CLOCK_BOOTTIME
+pub fn ktime_get_boottime() -> Ktime {
+    let sleep = timekeeper_read(|tk| tk.total_sleep_time);
+    return ktime_get().saturating_add(timespec_to_ns_signed(&sleep) as Ktime);
+}
+
+/// 把墙上时间设置为给定的纳秒数
+fn timekeeping_set_real(ns: i64) {
+    let mut tk = timekeeper().0.write_irqsave();
+    let now = ktime_get();
+    tk.xtime = ns_to_timespec_signed(ns);
+    tk.last_update = now;
+    tk.wall_to_monotonic = ns_to_timespec_signed((now as i64).saturating_sub(ns));
+    drop(tk);
+    vsyscall_update();
+}
+
+/// # 设置墙上时间，并写入RTC
+///
+/// ## 参数
+///
+/// - `ts`: 1970.1.1至今的UTC时间
+///
+/// ## 错误
+///
+/// - `EINVAL`：时间不合法
+pub fn do_settimeofday(ts: &TimeSpec) -> Result<(), SystemError> {
+    if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= NSEC_PER_SEC as i64 {
+        return Err(SystemError::EINVAL);
+    }
+    let ns = timespec_to_ns_signed(ts);
+    timekeeping_set_real(ns);
+    ntp_clear();
+
+    if let Err(e) = ktime_set_real_ns(ns) {
+        kwarn!("do_settimeofday: failed to write rtc: {:?}", e);
+    }
+    return Ok(());
+}
+
+/// # 把墙上时间调整给定的纳秒数，并写入RTC
+///
+/// ## 错误
+///
+/// - `EINVAL`：调整后的时间早于1970.1.1
+pub fn timekeeping_inject_offset(delta: i64) -> Result<(), SystemError> {
+    let now = timespec_to_ns_signed(&getnstimeofday());
+    let ns = now.checked_add(delta).ok_or(SystemError::EINVAL)?;
+    if ns < 0 {
+        return Err(SystemError::EINVAL);
+    }
+    timekeeping_set_real(ns);
+
+    if let Err(e) = ktime_set_real_ns(ns) {
+        kwarn!("timekeeping_inject_offset: failed to write rtc: {:?}", e);
+    }
+    return Ok(());
+}
+
 /// # 初始化timekeeping模块
 pub fn timekeeping_init() {
     kinfo!("Initializing timekeeping module...");
     let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
     timekeeper_init();
 
-    // TODO 有ntp模块后 在此初始化ntp模块
-
     let clock = clocksource_default_clock();
     clock
         .enable()
@@ -217,31 +291,22 @@ pub fn timekeeping_init() {
     timekeeper().timekeeper_setup_internals(clock);
     // 暂时不支持其他架构平台对时间的设置 所以使用x86平台对应值初始化
     let mut timekeeper = timekeeper().0.write();
-    timekeeper.xtime.tv_nsec = ktime_get_real_ns();
-
+    let real = ktime_get_real_ns();
+    let now = ktime_get();
+    timekeeper.xtime = ns_to_timespec_signed(real);
+    timekeeper.last_update = now;
     // 初始化wall time到monotonic的时间
-    let mut nsec = -timekeeper.xtime.tv_nsec;
-    let mut sec = -timekeeper.xtime.tv_sec;
-    // FIXME: 这里有个奇怪的奇怪的bug
-    let num = nsec % NSEC_PER_SEC as i64;
-    nsec += num * NSEC_PER_SEC as i64;
-    sec -= num;
-    timekeeper.wall_to_monotonic.tv_nsec = nsec;
-    timekeeper.wall_to_monotonic.tv_sec = sec;
-
-    __ADDED_USEC.store(0, Ordering::SeqCst);
-    __ADDED_SEC.store(0, Ordering::SeqCst);
+    timekeeper.wall_to_monotonic = ns_to_timespec_signed((now as i64).saturating_sub(real));
+    drop(timekeeper);
 
     drop(irq_guard);
     kinfo!("timekeeping_init successfully");
 }
 
 /// # 使用当前时钟源增加wall time
+///
+/// 在时钟中断中调用
 pub fn update_wall_time() {
-    let rsp = unsafe { crate::include::bindings::bindings::get_rsp() } as usize;
-    let _stack_use = align_up(rsp as u64, 32768) - rsp as u64;
-
-    // kdebug!("enter update_wall_time, stack_use = {:}",stack_use);
     compiler_fence(Ordering::SeqCst);
     let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
     // 如果在休眠那就不更新
@@ -249,63 +314,80 @@ pub fn update_wall_time() {
         return;
     }
 
-    // ===== 请不要删除这些注释 =====
-    // let clock = timekeeper.clock.clone().unwrap();
-    // let clock_data = clock.clocksource_data();
-    // let offset = (clock.read().div(clock_data.watchdog_last).data()) & clock_data.mask.bits();
-
-    // timekeeper.xtime_nsec = (timekeeper.xtime.tv_nsec as u64) << timekeeper.shift;
-    // // TODO 当有ntp模块之后 需要将timekeep与ntp进行同步并检查
-    // timekeeper.xtime.tv_nsec = ((timekeeper.xtime_nsec as i64) >> timekeeper.shift) + 1;
-    // timekeeper.xtime_nsec -= (timekeeper.xtime.tv_nsec as u64) << timekeeper.shift;
-
-    // timekeeper.xtime.tv_nsec += offset as i64;
-    // while unlikely(timekeeper.xtime.tv_nsec >= NSEC_PER_SEC.into()) {
-    //     timekeeper.xtime.tv_nsec -= NSEC_PER_SEC as i64;
-    //     timekeeper.xtime.tv_sec += 1;
-    //     // TODO 需要处理闰秒
-    // }
-    // ================
-    compiler_fence(Ordering::SeqCst);
-    // 一分钟同步一次
-    __ADDED_USEC.fetch_add(500, Ordering::SeqCst);
-    compiler_fence(Ordering::SeqCst);
-    let mut retry = 10;
+    // 当前cpu上可能有进程正在读取或设置时间，此时跳过本次更新，
+    // 由于是按照经过的时间累加的，下次更新时会把这段时间补上
+    let mut tk = match timekeeper().0.try_write() {
+        Some(tk) => tk,
+        None => return,
+    };
 
-    let usec = __ADDED_USEC.load(Ordering::SeqCst);
-    if usec % USEC_PER_SEC as i64 == 0 {
-        compiler_fence(Ordering::SeqCst);
+    let now = ktime_get();
+    let delta = if now == 0 {
+        // 时钟源尚不可用(例如TSC还没有校准)，按照中断的周期递增
+        WALL_TIME_FALLBACK_NS
+    } else if tk.last_update == 0 {
+        // 时钟源刚刚变得可用，从现在开始计时
+        0
+    } else {
+        now.saturating_sub(tk.last_update) as i64
+    };
+    tk.last_update = now;
+
+    // 按照NTP设置的频率偏差和相位偏差调整走速
+    let adj = if delta > 0 {
+        ntp_advance(delta as u64)
+    } else {
+        0
+    };
+    let real = timespec_to_ns_signed(&tk.xtime).saturating_add(delta + adj);
+    tk.xtime = ns_to_timespec_signed(real);
+    tk.wall_to_monotonic = ns_to_timespec_signed((now as i64).saturating_sub(real));
+    drop(tk);
 
-        __ADDED_SEC.fetch_add(1, Ordering::SeqCst);
-        compiler_fence(Ordering::SeqCst);
-    }
-    loop {
-        if (usec & !((1 << 26) - 1)) != 0 {
-            if __ADDED_USEC
-                .compare_exchange(usec, 0, Ordering::SeqCst, Ordering::SeqCst)
-                .is_ok()
-                || retry == 0
-            {
-                // 同步时间
-                // 我感觉这里会出问题：多个读者不退出的话，写者就无法写入
-                // 然后这里会超时，导致在中断返回之后，会不断的进入这个中断，最终爆栈。
-                let mut timekeeper = timekeeper().0.write();
-                timekeeper.xtime.tv_nsec = ktime_get_real_ns();
-                timekeeper.xtime.tv_sec = 0;
-                __ADDED_SEC.store(0, Ordering::SeqCst);
-                drop(timekeeper);
-                break;
-            }
-            retry -= 1;
-        } else {
-            break;
-        }
-    }
     // TODO 需要检查是否更新时间源
     compiler_fence(Ordering::SeqCst);
+    vsyscall_update();
     drop(irq_guard);
     compiler_fence(Ordering::SeqCst);
 }
+/// 进入睡眠时RTC中的时间(ns)
+static SUSPEND_RTC_NS: AtomicI64 = AtomicI64::new(0);
+
+/// 系统进入睡眠之前调用，停止更新墙上时间，并记录RTC中的时间
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/time/timekeeping.c?fi=timekeeping_suspend#timekeeping_suspend
+pub fn timekeeping_suspend() {
+    update_wall_time();
+    SUSPEND_RTC_NS.store(ktime_get_real_ns(), Ordering::SeqCst);
+    TIMEKEEPING_SUSPENDED.store(true, Ordering::SeqCst);
+}
+
+/// 系统从睡眠中恢复之后调用
+///
+/// 单调时间不包含睡眠的时间(与Linux的CLOCK_MONOTONIC相同)，根据RTC计算出睡眠的时长，
+/// 把它加到墙上时间与CLOCK_BOOTTIME上
+///
+/// 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/time/timekeeping.c?fi=timekeeping_resume#timekeeping_resume
+pub fn timekeeping_resume() {
+    let sleep_ns = ktime_get_real_ns()
+        .saturating_sub(SUSPEND_RTC_NS.load(Ordering::SeqCst))
+        .max(0);
+
+    let mut tk = timekeeper().0.write_irqsave();
+    let now = ktime_get();
+    let real = timespec_to_ns_signed(&tk.xtime).saturating_add(sleep_ns);
+    tk.xtime = ns_to_timespec_signed(real);
+    tk.last_update = now;
+    tk.wall_to_monotonic = ns_to_timespec_signed((now as i64).saturating_sub(real));
+    tk.total_sleep_time =
+        ns_to_timespec_signed(timespec_to_ns_signed(&tk.total_sleep_time).saturating_add(sleep_ns));
+    drop(tk);
+
+    TIMEKEEPING_SUSPENDED.store(false, Ordering::SeqCst);
+    vsyscall_update();
+    kinfo!("Suspended for {} ms", sleep_ns / 1000000);
+}
+
 // TODO timekeeping_adjust
 // TODO wall_to_monotic
 
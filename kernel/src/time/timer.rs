@@ -22,7 +22,7 @@ use crate::{
     syscall::SystemError,
 };
 
-use super::timekeeping::update_wall_time;
+use super::{posix_timer::posix_timer_init, timekeeping::update_wall_time};
 
 const MAX_TIMEOUT: i64 = i64::MAX;
 const TIMER_RUN_CYCLE_THRESHOLD: usize = 20;
@@ -47,6 +47,11 @@ impl WakeUpHelper {
     pub fn new(pcb: Arc<ProcessControlBlock>) -> Box<WakeUpHelper> {
         return Box::new(WakeUpHelper { pcb });
     }
+
+    /// 要唤醒的进程
+    pub fn pcb(&self) -> &Arc<ProcessControlBlock> {
+        &self.pcb
+    }
 }
 
 impl TimerFunction for WakeUpHelper {
@@ -72,6 +77,7 @@ impl Timer {
             expire_jiffies,
             timer_func,
             self_ref: Weak::default(),
+            cancelled: false,
         })));
 
         result.0.lock().self_ref = Arc::downgrade(&result);
@@ -110,9 +116,26 @@ impl Timer {
         drop(timer_list);
     }
 
+    /// @brief 取消定时器
+    ///
+    /// 定时器还在定时器链表中时，把它从链表中移除。
+    /// 定时器已经被取出、正准备执行时，它的函数也不会再被执行
+    pub fn cancel(&self) {
+        self.0.lock().cancelled = true;
+        // 不能在持有定时器的锁时获取链表的锁，activate()会在持有链表的锁时获取其他定时器的锁
+        TIMER_LIST
+            .lock()
+            .drain_filter(|timer| core::ptr::eq(timer.as_ref(), self));
+    }
+
     #[inline]
     fn run(&self) {
-        let r = self.0.lock().timer_func.run();
+        let mut inner_guard = self.0.lock();
+        if inner_guard.cancelled {
+            return;
+        }
+        let r = inner_guard.timer_func.run();
+        drop(inner_guard);
         if unlikely(r.is_err()) {
             kerror!(
                 "Failed to run timer function: {self:?} {:?}",
@@ -131,6 +154,8 @@ pub struct InnerTimer {
     pub timer_func: Box<dyn TimerFunction>,
     /// self_ref
     self_ref: Weak<Timer>,
+    /// 定时器是否已经被取消
+    cancelled: bool,
 }
 
 #[derive(Debug)]
@@ -214,6 +239,7 @@ pub fn timer_init() {
         .register_softirq(SoftirqNumber::TIMER, do_timer_softirq)
         .expect("Failed to register timer softirq");
     kinfo!("timer initialized successfully");
+    posix_timer_init();
 }
 
 /// 计算接下来n毫秒对应的定时器时间片
@@ -0,0 +1,136 @@
+//! 用户态读取时钟的快速路径(vDSO风格的vvar页)
+//!
+//! 内核在一个物理页中导出计算时间所需的数据，并把它只读地映射到每个进程的地址空间中的固定位置
+//! ([`VVAR_VADDR`]，紧接在用户地址空间之后，不属于任何VMA，因此fork和进程退出时都不会拷贝或释放它)。
+//!
+//! 用户态按照顺序锁的方式读取这些数据，结合rdtsc即可计算出CLOCK_MONOTONIC和CLOCK_REALTIME，
+//! 无需陷入内核。
+//!
+//! 参考 https://opengrok.ringotek.cn/xref/linux-6.1.9/kernel/time/vsyscall.c
+
+use core::sync::atomic::{compiler_fence, AtomicU32, AtomicU64, Ordering};
+
+use crate::{
+    arch::{mm::PageMapper, CurrentTimeArch, MMArch},
+    libs::spinlock::SpinLock,
+    mm::{
+        allocator::page_frame::{allocate_page_frames, PageFrameCount},
+        page::PageFlags,
+        MemoryManagementArch, PhysAddr, VirtAddr,
+    },
+    syscall::SystemError,
+};
+
+use super::{timekeeping::getnstimeofday, TimeArch, NSEC_PER_SEC};
+
+/// vvar页在用户地址空间中的地址
+pub const VVAR_VADDR: VirtAddr = VirtAddr::new(0x0000_7f00_0000_0000);
+
+/// vvar页数据格式的版本号，格式发生变化时需要递增
+const VDSO_DATA_VERSION: u32 = 1;
+/// TSC周期换算为纳秒时使用的移位值
+const VDSO_TSC_SHIFT: u32 = 32;
+
+/// 时钟数据不可用，用户态应当回退到系统调用
+pub const VDSO_CLOCK_MODE_NONE: u32 = 0;
+/// 可以使用TSC计算时间
+pub const VDSO_CLOCK_MODE_TSC: u32 = 1;
+
+/// vvar页中的数据，用户态的C库中有与之一致的定义
+///
+/// 读取方式：
+///
+/// 1. 读取`seq`，如果为奇数则重试
+/// 2. 读取其余字段，`ns = base_ns + ((rdtsc() - cycle_last) * mult >> shift)`
+/// 3. 再次读取`seq`，如果发生了变化则重试
+#[repr(C)]
+#[derive(Debug)]
+pub struct VdsoData {
+    /// 顺序锁，为奇数时表示内核正在更新数据
+    seq: AtomicU32,
+    version: AtomicU32,
+    clock_mode: AtomicU32,
+    shift: AtomicU32,
+    mult: AtomicU64,
+    /// 上一次更新时的TSC值
+    cycle_last: AtomicU64,
+    /// 上一次更新时的单调时间(ns)
+    mono_ns: AtomicU64,
+    /// 上一次更新时的实时时间(ns)
+    real_ns: AtomicU64,
+}
+
+/// vvar页的物理地址
+static VVAR_PAGE: SpinLock<Option<PhysAddr>> = SpinLock::new(None);
+
+/// 获取vvar页的物理地址，第一次调用时分配它
+fn vvar_page() -> Result<PhysAddr, SystemError> {
+    let mut guard = VVAR_PAGE.lock_irqsave();
+    if let Some(paddr) = *guard {
+        return Ok(paddr);
+    }
+
+    let (paddr, _) =
+        unsafe { allocate_page_frames(PageFrameCount::new(1)) }.ok_or(SystemError::ENOMEM)?;
+    let vaddr = unsafe { MMArch::phys_2_virt(paddr) }.ok_or(SystemError::EFAULT)?;
+    unsafe { core::ptr::write_bytes(vaddr.data() as *mut u8, 0, MMArch::PAGE_SIZE) };
+    guard.replace(paddr);
+    return Ok(paddr);
+}
+
+fn vdso_data() -> Option<&'static VdsoData> {
+    let paddr = (*VVAR_PAGE.lock_irqsave())?;
+    let vaddr = unsafe { MMArch::phys_2_virt(paddr) }?;
+    return Some(unsafe { &*(vaddr.data() as *const VdsoData) });
+}
+
+/// 把vvar页只读地映射到用户地址空间中
+pub fn vvar_map(mapper: &mut PageMapper) -> Result<(), SystemError> {
+    let paddr = vvar_page()?;
+    let flags: PageFlags<MMArch> = PageFlags::new().set_user(true);
+    let flush = unsafe { mapper.map_phys(VVAR_VADDR, paddr, flags) }.ok_or(SystemError::ENOMEM)?;
+    // 新创建的页表还没有被加载，不需要刷新TLB
+    unsafe { flush.ignore() };
+    return Ok(());
+}
+
+/// 更新vvar页中的时钟数据，在墙上时间更新时调用
+pub fn vsyscall_update() {
+    static WRITER: SpinLock<()> = SpinLock::new(());
+
+    let data = match vdso_data() {
+        Some(data) => data,
+        None => return,
+    };
+    let _guard = WRITER.lock_irqsave();
+
+    let cycles = CurrentTimeArch::get_cycles();
+    let mono_ns = CurrentTimeArch::cycles2ns(cycles) as u64;
+    let real = getnstimeofday();
+    let real_ns = (real.tv_sec as u64)
+        .wrapping_mul(NSEC_PER_SEC as u64)
+        .wrapping_add(real.tv_nsec as u64);
+    let mult = CurrentTimeArch::cycles2ns(1 << VDSO_TSC_SHIFT) as u64;
+
+    data.seq.fetch_add(1, Ordering::SeqCst);
+    compiler_fence(Ordering::SeqCst);
+
+    data.version.store(VDSO_DATA_VERSION, Ordering::Relaxed);
+    if mult == 0 {
+        // TSC的频率未知
+        data.clock_mode
+            .store(VDSO_CLOCK_MODE_NONE, Ordering::Relaxed);
+    } else {
+        // cycles2ns(2^shift)即为mult
+        data.shift.store(VDSO_TSC_SHIFT, Ordering::Relaxed);
+        data.mult.store(mult, Ordering::Relaxed);
+        data.cycle_last.store(cycles as u64, Ordering::Relaxed);
+        data.mono_ns.store(mono_ns, Ordering::Relaxed);
+        data.real_ns.store(real_ns, Ordering::Relaxed);
+        data.clock_mode
+            .store(VDSO_CLOCK_MODE_TSC, Ordering::Relaxed);
+    }
+
+    compiler_fence(Ordering::SeqCst);
+    data.seq.fetch_add(1, Ordering::SeqCst);
+}
@@ -1,7 +1,12 @@
 use super::{vcpu::Vcpu, vm};
 use crate::{
     kdebug,
-    mm::{kernel_mapper::KernelMapper, page::PageFlags, VirtAddr},
+    mm::{
+        fault::{handle_mm_fault, FaultFlags},
+        kernel_mapper::KernelMapper,
+        page::PageFlags,
+        VirtAddr,
+    },
     syscall::SystemError,
 };
 
@@ -158,6 +163,8 @@ fn hva_to_pfn(addr: u64, _atomic: bool, _writable: &mut bool) -> Result<u64, Sys
     }
     // let hpa = MMArch::virt_2_phys(VirtAddr::new(addr)).unwrap().data() as u64;
     let hva = VirtAddr::new(addr as usize);
+    // 客户机会写入这个页，先进行写时复制，避免把零页或者与其它进程共享的页交给客户机
+    handle_mm_fault(hva, FaultFlags::FAULT_FLAG_WRITE).ok();
     let mut mapper = KernelMapper::lock();
     let mapper = mapper.as_mut().unwrap();
     if let Some((hpa, _)) = mapper.translate(hva) {
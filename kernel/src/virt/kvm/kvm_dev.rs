@@ -2,7 +2,7 @@ use crate::filesystem::devfs::{DevFS, DeviceINode};
 use crate::filesystem::vfs::{
     core::generate_inode_id,
     file::{File, FileMode},
-    make_rawdev, FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus,
+    make_rawdev, FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus, PollTable,
 };
 use crate::process::ProcessManager;
 use crate::{arch::KVMArch, libs::spinlock::SpinLock, syscall::SystemError, time::TimeSpec};
@@ -119,7 +119,7 @@ impl IndexNode for LockedKvmInode {
         return Ok(());
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         return Ok(PollStatus::READ | PollStatus::WRITE);
     }
 
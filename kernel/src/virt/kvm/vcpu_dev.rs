@@ -3,7 +3,7 @@ use crate::arch::KVMArch;
 use crate::filesystem::devfs::DevFS;
 use crate::filesystem::vfs::{
     core::generate_inode_id, file::FileMode, make_rawdev, FilePrivateData, FileSystem, FileType,
-    IndexNode, Metadata, PollStatus,
+    IndexNode, Metadata, PollStatus, PollTable,
 };
 use crate::mm::VirtAddr;
 use crate::syscall::user_access::copy_from_user;
@@ -127,7 +127,7 @@ impl IndexNode for LockedVcpuInode {
         return Ok(());
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         return Ok(PollStatus::READ | PollStatus::WRITE);
     }
 
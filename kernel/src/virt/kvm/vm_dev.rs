@@ -2,7 +2,7 @@ use crate::filesystem::devfs::DevFS;
 use crate::filesystem::vfs::{
     core::generate_inode_id,
     file::{File, FileMode},
-    make_rawdev, FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus,
+    make_rawdev, FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollStatus, PollTable,
 };
 use crate::mm::VirtAddr;
 use crate::process::ProcessManager;
@@ -125,7 +125,7 @@ impl IndexNode for LockedVmInode {
         return Ok(());
     }
 
-    fn poll(&self) -> Result<PollStatus, SystemError> {
+    fn poll(&self, _table: &mut PollTable) -> Result<PollStatus, SystemError> {
         return Ok(PollStatus::READ | PollStatus::WRITE);
     }
 
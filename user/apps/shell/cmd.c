@@ -16,6 +16,23 @@
 
 #define MAX_PATH_LEN 4096
 
+// reboot系统调用的魔数与命令
+#define LINUX_REBOOT_MAGIC1 0xfee1dead
+#define LINUX_REBOOT_MAGIC2 672274793
+#define LINUX_REBOOT_CMD_RESTART 0x01234567
+#define LINUX_REBOOT_CMD_POWER_OFF 0x4321FEDC
+#define LINUX_REBOOT_CMD_KEXEC 0x45584543
+
+// kexec_file_load系统调用的标志位
+#define KEXEC_FILE_UNLOAD 0x1
+#define KEXEC_FILE_NO_INITRAMFS 0x4
+
+// syslog系统调用的命令
+#define SYSLOG_ACTION_READ_ALL 3
+#define SYSLOG_ACTION_READ_CLEAR 4
+#define SYSLOG_ACTION_CONSOLE_LEVEL 8
+#define SYSLOG_ACTION_SIZE_BUFFER 10
+
 // 当前工作目录（在main_loop中初始化）
 char *shell_current_path = NULL;
 
@@ -33,6 +50,10 @@ struct built_in_cmd_t shell_cmds[] = {
     {"rm", shell_cmd_rm},
     {"rmdir", shell_cmd_rmdir},
     {"reboot", shell_cmd_reboot},
+    {"poweroff", shell_cmd_poweroff},
+    {"kexec", shell_cmd_kexec},
+    {"insmod", shell_cmd_insmod},
+    {"rmmod", shell_cmd_rmmod},
     {"touch", shell_cmd_touch},
     {"about", shell_cmd_about},
     {"free", shell_cmd_free},
@@ -40,6 +61,7 @@ struct built_in_cmd_t shell_cmds[] = {
     {"pipe", shell_pipe_test},
     {"pipe2", shell_pipe2_test},
     {"kill", shell_cmd_kill},
+    {"dmesg", shell_cmd_dmesg},
 
 };
 // 总共的内建命令数量
@@ -586,7 +608,238 @@ out:;
  */
 int shell_cmd_reboot(int argc, char **argv)
 {
-    return syscall_invoke(SYS_REBOOT, 0, 0, 0, 0, 0, 0);
+    return syscall_invoke(SYS_REBOOT, LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2, LINUX_REBOOT_CMD_RESTART, 0, 0, 0);
+}
+
+/**
+ * @brief 关机命令
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_poweroff(int argc, char **argv)
+{
+    return syscall_invoke(SYS_REBOOT, LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2, LINUX_REBOOT_CMD_POWER_OFF, 0, 0, 0);
+}
+
+/**
+ * @brief 不经过固件，直接启动一个新内核
+ *
+ * kexec -l <kernel> [cmdline...]: 加载新内核，其余参数作为新内核的命令行
+ * kexec -e: 执行已经加载的新内核
+ * kexec -u: 卸载已经加载的新内核
+ * kexec <kernel> [cmdline...]: 加载并立即执行新内核
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_kexec(int argc, char **argv)
+{
+    int retval = 0;
+    bool load = true, exec = true;
+    int kernel_idx = 1;
+    if (argc >= 2 && strcmp("-l", argv[1]) == 0)
+    {
+        exec = false;
+        kernel_idx = 2;
+    }
+    else if (argc == 2 && strcmp("-e", argv[1]) == 0)
+        load = false;
+    else if (argc == 2 && strcmp("-u", argv[1]) == 0)
+    {
+        retval = syscall_invoke(SYS_KEXEC_FILE_LOAD, -1, -1, 0, 0, KEXEC_FILE_UNLOAD, 0);
+        goto out;
+    }
+
+    if (load && (argc <= kernel_idx || argv[kernel_idx][0] == '-'))
+    {
+        printf("Usage: kexec [-l] <kernel> [cmdline...] | kexec -e | kexec -u\n");
+        retval = -EINVAL;
+        goto out;
+    }
+
+    if (load)
+    {
+        // 剩余的参数以空格连接，作为新内核的命令行
+        int cmdline_len = 1;
+        for (int i = kernel_idx + 1; i < argc; ++i)
+            cmdline_len += strlen(argv[i]) + 1;
+        char *cmdline = (char *)malloc(cmdline_len);
+        cmdline[0] = '\0';
+        for (int i = kernel_idx + 1; i < argc; ++i)
+        {
+            if (i > kernel_idx + 1)
+                strcat(cmdline, " ");
+            strcat(cmdline, argv[i]);
+        }
+
+        int path_len = 0;
+        char *file_path = get_target_filepath(argv[kernel_idx], &path_len);
+        int fd = open(file_path, O_RDONLY);
+        if (fd < 0)
+        {
+            printf("ERROR: Cannot open file: %s, fd=%d\n", file_path, fd);
+            retval = -ENOENT;
+        }
+        else
+        {
+            retval = syscall_invoke(SYS_KEXEC_FILE_LOAD, fd, -1, strlen(cmdline) + 1, (uint64_t)cmdline,
+                                    KEXEC_FILE_NO_INITRAMFS, 0);
+            close(fd);
+            if (retval != 0)
+                printf("ERROR: Cannot load kernel: %s, retval=%d\n", file_path, retval);
+        }
+        free(cmdline);
+        free(file_path);
+        if (retval != 0)
+            goto out;
+    }
+
+    if (exec)
+        retval = syscall_invoke(SYS_REBOOT, LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2, LINUX_REBOOT_CMD_KEXEC, 0, 0, 0);
+out:;
+    free(argv);
+    return retval;
+}
+
+/**
+ * @brief 加载内核模块
+ *
+ * insmod <module> [params...]: 加载模块，其余参数作为模块的参数
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_insmod(int argc, char **argv)
+{
+    int retval = 0;
+    if (argc < 2)
+    {
+        printf("Usage: insmod <module> [params...]\n");
+        retval = -EINVAL;
+        goto out;
+    }
+
+    int path_len = 0;
+    char *file_path = get_target_filepath(argv[1], &path_len);
+    int fd = open(file_path, O_RDONLY);
+    if (fd < 0)
+    {
+        printf("ERROR: Cannot open file: %s, fd=%d\n", file_path, fd);
+        free(file_path);
+        retval = -ENOENT;
+        goto out;
+    }
+    int file_size = lseek(fd, 0, SEEK_END);
+    lseek(fd, 0, SEEK_SET);
+    char *image = (char *)malloc(file_size);
+    int done = 0;
+    while (done < file_size)
+    {
+        int l = read(fd, image + done, file_size - done);
+        if (l <= 0)
+            break;
+        done += l;
+    }
+    close(fd);
+
+    // 剩余的参数以空格连接，作为模块的参数
+    int params_len = 1;
+    for (int i = 2; i < argc; ++i)
+        params_len += strlen(argv[i]) + 1;
+    char *params = (char *)malloc(params_len);
+    params[0] = '\0';
+    for (int i = 2; i < argc; ++i)
+    {
+        if (i > 2)
+            strcat(params, " ");
+        strcat(params, argv[i]);
+    }
+
+    retval = syscall_invoke(SYS_INIT_MODULE, (uint64_t)image, done, (uint64_t)params, 0, 0, 0);
+    if (retval != 0)
+        printf("ERROR: Cannot load module: %s, retval=%d\n", file_path, retval);
+    free(params);
+    free(image);
+    free(file_path);
+out:;
+    free(argv);
+    return retval;
+}
+
+/**
+ * @brief 卸载内核模块
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_rmmod(int argc, char **argv)
+{
+    int retval = 0;
+    if (argc != 2)
+    {
+        printf("Usage: rmmod <name>\n");
+        retval = -EINVAL;
+        goto out;
+    }
+    retval = syscall_invoke(SYS_DELETE_MODULE, (uint64_t)argv[1], 0, 0, 0, 0, 0);
+    if (retval != 0)
+        printf("ERROR: Cannot unload module: %s, retval=%d\n", argv[1], retval);
+out:;
+    free(argv);
+    return retval;
+}
+
+/**
+ * @brief 打印内核日志缓冲区的内容
+ *
+ * dmesg -c: 打印之后清空缓冲区
+ * dmesg -n <level>: 设置输出到控制台的日志级别
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_dmesg(int argc, char **argv)
+{
+    int retval = 0;
+    int action = SYSLOG_ACTION_READ_ALL;
+    if (argc == 3 && strcmp("-n", argv[1]) == 0)
+    {
+        retval = syscall_invoke(SYS_SYSLOG, SYSLOG_ACTION_CONSOLE_LEVEL, 0, atoi(argv[2]), 0, 0, 0);
+        goto out;
+    }
+    else if (argc == 2 && strcmp("-c", argv[1]) == 0)
+        action = SYSLOG_ACTION_READ_CLEAR;
+    else if (argc != 1)
+    {
+        printf("Usage: dmesg [-c] [-n <level>]\n");
+        retval = -EINVAL;
+        goto out;
+    }
+
+    int size = syscall_invoke(SYS_SYSLOG, SYSLOG_ACTION_SIZE_BUFFER, 0, 0, 0, 0, 0);
+    if (size <= 0)
+    {
+        retval = size;
+        goto out;
+    }
+    char *buf = (char *)malloc(size + 1);
+    retval = syscall_invoke(SYS_SYSLOG, action, (uint64_t)buf, size, 0, 0, 0);
+    if (retval > 0)
+    {
+        buf[retval] = '\0';
+        printf("%s", buf);
+        retval = 0;
+    }
+    free(buf);
+out:;
+    free(argv);
+    return retval;
 }
 
 int shell_cmd_free(int argc, char **argv)
@@ -123,6 +123,42 @@ int shell_cmd_exec(int argc, char **argv);
  */
 int shell_cmd_reboot(int argc, char **argv);
 
+/**
+ * @brief 关机命令
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_poweroff(int argc, char **argv);
+
+/**
+ * @brief 通过kexec启动新内核
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_kexec(int argc, char **argv);
+
+/**
+ * @brief 加载内核模块
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_insmod(int argc, char **argv);
+
+/**
+ * @brief 卸载内核模块
+ *
+ * @param argc
+ * @param argv
+ * @return int
+ */
+int shell_cmd_rmmod(int argc, char **argv);
+
 /**
  * @brief 关于软件
  * 
@@ -151,4 +187,6 @@ int shell_cmd_free(int argc, char **argv);
  */
 int parse_command(char *buf, int *argc, char ***argv);
 
-int shell_cmd_kill(int argc, char **argv);
\ No newline at end of file
+int shell_cmd_kill(int argc, char **argv);
+
+int shell_cmd_dmesg(int argc, char **argv);
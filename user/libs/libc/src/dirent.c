@@ -28,7 +28,8 @@ struct DIR *opendir(const char *path)
     // printf("dirp = %#018lx", dirp);
     memset(dirp, 0, sizeof(struct DIR));
     dirp->fd = fd;
-    dirp->buf_len = DIR_BUF_SIZE;
+    // 缓冲区为空，第一次readdir时再读取目录项
+    dirp->buf_len = 0;
     dirp->buf_pos = 0;
 
     return dirp;
@@ -56,7 +57,8 @@ int closedir(struct DIR *dirp)
 
 int64_t getdents(int fd, struct dirent *dirent, long count)
 {
-    return syscall_invoke(SYS_GET_DENTS, fd, (uint64_t)dirent, count, 0, 0, 0);
+    // struct dirent的内存布局与linux_dirent64相同
+    return syscall_invoke(SYS_GET_DENTS_64, fd, (uint64_t)dirent, count, 0, 0, 0);
 }
 /**
  * @brief 从目录中读取数据
@@ -66,13 +68,18 @@ int64_t getdents(int fd, struct dirent *dirent, long count)
  */
 struct dirent *readdir(struct DIR *dir)
 {
-    // printf("dir->buf = %#018lx\n", (dir->buf));
-    memset((dir->buf), 0, DIR_BUF_SIZE);
-    // printf("memeset_ok\n");
-    int len = getdents(dir->fd, (struct dirent *)dir->buf, DIR_BUF_SIZE);
-    // printf("len=%d\n", len);
-    if (len > 0)
-        return (struct dirent *)dir->buf;
-    else
-        return NULL;
+    // 缓冲区中的目录项已经读完，从内核读取下一批目录项
+    if (dir->buf_pos >= dir->buf_len)
+    {
+        memset((dir->buf), 0, DIR_BUF_SIZE);
+        int len = getdents(dir->fd, (struct dirent *)dir->buf, DIR_BUF_SIZE);
+        if (len <= 0)
+            return NULL;
+        dir->buf_len = len;
+        dir->buf_pos = 0;
+    }
+
+    struct dirent *ent = (struct dirent *)(dir->buf + dir->buf_pos);
+    dir->buf_pos += ent->d_reclen;
+    return ent;
 }
\ No newline at end of file
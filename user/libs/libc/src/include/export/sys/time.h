@@ -0,0 +1,43 @@
+#pragma once
+
+#include <sys/types.h>
+
+#if defined(__cplusplus) 
+extern  "C"  { 
+#endif
+
+struct timeval
+{
+    long int tv_sec;  // 秒
+    int tv_usec;      // 微秒
+};
+
+struct timezone
+{
+    int tz_minuteswest; // 格林尼治相对于当前时区相差的分钟数
+    int tz_dsttime;     // DST矫正时差
+};
+
+/**
+ * @brief 获取当前的时间和时区
+ *
+ * 优先通过内核映射的vvar页读取，不需要进行系统调用
+ *
+ * @param tv 返回的时间
+ * @param tz 返回的时区（可以为NULL）
+ * @return int 成功返回0
+ */
+int gettimeofday(struct timeval *tv, struct timezone *tz);
+
+/**
+ * @brief 设置当前的时间，同时写入RTC
+ *
+ * @param tv 要设置的时间
+ * @param tz 时区（暂不支持，会被忽略）
+ * @return int 成功返回0
+ */
+int settimeofday(const struct timeval *tv, const struct timezone *tz);
+
+#if defined(__cplusplus) 
+}  /* extern "C" */ 
+#endif
@@ -28,6 +28,8 @@ typedef uint32_t dev_t;
 typedef uint16_t mode_t;
 typedef uint32_t nlink_t;
 
+typedef int clockid_t;
+
 typedef int64_t time_t;
 typedef uint32_t useconds_t;
 typedef int32_t suseconds_t;
@@ -1,6 +1,7 @@
 #pragma once
 
 #include <stddef.h>
+#include <sys/types.h>
 
 #if defined(__cplusplus) 
 extern  "C"  { 
@@ -9,6 +10,11 @@ extern  "C"  {
 // 操作系统定义时间以ns为单位
 #define CLOCKS_PER_SEC 1000000
 
+#define CLOCK_REALTIME 0
+#define CLOCK_MONOTONIC 1
+#define CLOCK_MONOTONIC_RAW 4
+#define CLOCK_BOOTTIME 7
+
 struct tm
 {
     int tm_sec;   /* Seconds.	[0-60] (1 leap second) */
@@ -48,6 +54,26 @@ int nanosleep(const struct timespec *rqtp, struct timespec *rmtp);
  */
 clock_t clock();
 
+/**
+ * @brief 获取指定时钟的当前时间
+ *
+ * 优先通过内核映射的vvar页读取，不需要进行系统调用
+ *
+ * @param clk_id 时钟（CLOCK_REALTIME或CLOCK_MONOTONIC）
+ * @param tp 返回的时间
+ * @return int 成功返回0
+ */
+int clock_gettime(clockid_t clk_id, struct timespec *tp);
+
+/**
+ * @brief 设置指定时钟的时间（只支持CLOCK_REALTIME），同时写入RTC
+ *
+ * @param clk_id 时钟
+ * @param tp 要设置的时间
+ * @return int 成功返回0
+ */
+int clock_settime(clockid_t clk_id, const struct timespec *tp);
+
 #if defined(__cplusplus) 
 }  /* extern "C" */ 
 #endif
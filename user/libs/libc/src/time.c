@@ -1,8 +1,71 @@
 #include <time.h>
 #include <errno.h>
 #include <unistd.h>
+#include <sys/time.h>
 #include <libsystem/syscall.h>
 
+// vvar页的地址和格式，需要与内核中的定义(kernel/src/time/vsyscall.rs)保持一致
+#define VVAR_ADDR 0x7f0000000000UL
+#define VDSO_CLOCK_MODE_TSC 1
+
+struct vdso_data
+{
+    volatile uint32_t seq; // 顺序锁，为奇数时表示内核正在更新数据
+    uint32_t version;
+    uint32_t clock_mode;
+    uint32_t shift;
+    uint64_t mult;
+    uint64_t cycle_last; // 上一次更新时的TSC值
+    uint64_t mono_ns;    // 上一次更新时的单调时间(ns)
+    uint64_t real_ns;    // 上一次更新时的实时时间(ns)
+};
+
+static inline uint64_t __rdtsc()
+{
+    uint32_t lo, hi;
+    __asm__ __volatile__("rdtsc"
+                         : "=a"(lo), "=d"(hi));
+    return ((uint64_t)hi << 32) | lo;
+}
+
+/**
+ * @brief 通过vvar页读取时间（单位：纳秒）
+ *
+ * @param clk_id 时钟
+ * @param ns 返回的时间
+ * @return int 成功返回0，vvar页不可用时返回-1
+ */
+static int __vdso_clock_gettime_ns(clockid_t clk_id, uint64_t *ns)
+{
+    const volatile struct vdso_data *vd = (const volatile struct vdso_data *)VVAR_ADDR;
+    while (1)
+    {
+        uint32_t seq = vd->seq;
+        __asm__ __volatile__("" ::
+                                 : "memory");
+        if (seq & 1)
+            continue;
+        if (vd->clock_mode != VDSO_CLOCK_MODE_TSC)
+            return -1;
+
+        uint64_t mult = vd->mult;
+        uint64_t delta = __rdtsc() - vd->cycle_last;
+        // 距离内核上一次更新的时间太长，计算会溢出
+        if (delta > (uint64_t)-1 / mult)
+            return -1;
+        uint64_t base = (clk_id == CLOCK_MONOTONIC) ? vd->mono_ns : vd->real_ns;
+        uint64_t result = base + ((delta * mult) >> vd->shift);
+
+        __asm__ __volatile__("" ::
+                                 : "memory");
+        if (vd->seq == seq)
+        {
+            *ns = result;
+            return 0;
+        }
+    }
+}
+
 /**
  * @brief 休眠指定时间
  *
@@ -39,4 +102,67 @@ int usleep(useconds_t usec)
 clock_t clock()
 {
     return (clock_t)syscall_invoke(SYS_CLOCK, 0, 0, 0, 0, 0, 0);
-}
\ No newline at end of file
+}
+
+/**
+ * @brief 获取指定时钟的当前时间
+ *
+ * @param clk_id 时钟（CLOCK_REALTIME或CLOCK_MONOTONIC）
+ * @param tp 返回的时间
+ * @return int 成功返回0
+ */
+int clock_gettime(clockid_t clk_id, struct timespec *tp)
+{
+    uint64_t ns;
+    if ((clk_id == CLOCK_REALTIME || clk_id == CLOCK_MONOTONIC) && tp != NULL && __vdso_clock_gettime_ns(clk_id, &ns) == 0)
+    {
+        tp->tv_sec = ns / 1000000000UL;
+        tp->tv_nsec = ns % 1000000000UL;
+        return 0;
+    }
+    return syscall_invoke(SYS_CLOCK_GETTIME, (uint64_t)clk_id, (uint64_t)tp, 0, 0, 0, 0);
+}
+
+/**
+ * @brief 设置指定时钟的时间
+ *
+ * @param clk_id 时钟（只支持CLOCK_REALTIME）
+ * @param tp 要设置的时间
+ * @return int 成功返回0
+ */
+int clock_settime(clockid_t clk_id, const struct timespec *tp)
+{
+    return syscall_invoke(SYS_CLOCK_SETTIME, (uint64_t)clk_id, (uint64_t)tp, 0, 0, 0, 0);
+}
+
+/**
+ * @brief 获取当前的时间和时区
+ *
+ * @param tv 返回的时间
+ * @param tz 返回的时区（可以为NULL）
+ * @return int 成功返回0
+ */
+int gettimeofday(struct timeval *tv, struct timezone *tz)
+{
+    uint64_t ns;
+    // 时区信息只能从内核获取
+    if (tv != NULL && tz == NULL && __vdso_clock_gettime_ns(CLOCK_REALTIME, &ns) == 0)
+    {
+        tv->tv_sec = ns / 1000000000UL;
+        tv->tv_usec = (ns % 1000000000UL) / 1000;
+        return 0;
+    }
+    return syscall_invoke(SYS_GETTIMEOFDAY, (uint64_t)tv, (uint64_t)tz, 0, 0, 0, 0);
+}
+
+/**
+ * @brief 设置当前的时间
+ *
+ * @param tv 要设置的时间
+ * @param tz 时区（会被忽略）
+ * @return int 成功返回0
+ */
+int settimeofday(const struct timeval *tv, const struct timezone *tz)
+{
+    return syscall_invoke(SYS_SETTIMEOFDAY, (uint64_t)tv, (uint64_t)tz, 0, 0, 0, 0);
+}
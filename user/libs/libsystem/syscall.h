@@ -38,7 +38,7 @@
 #define SYS_LISTEN 50
 #define SYS_GETSOCKNAME 51
 #define SYS_GETPEERNAME 52
-
+#define SYS_SOCKETPAIR 53
 #define SYS_SETSOCKOPT 54
 #define SYS_GETSOCKOPT 55
 #define SYS_CLONE 56
@@ -62,10 +62,17 @@
 
 #define SYS_GETTIMEOFDAY 96
 
+#define SYS_SYSLOG 103
+
 #define SYS_ARCH_PRCTL 158
 
+#define SYS_SETTIMEOFDAY 164
+
 #define SYS_REBOOT 169
 
+#define SYS_INIT_MODULE 175
+#define SYS_DELETE_MODULE 176
+
 #define SYS_GETPPID 110
 #define SYS_GETPGID 121
 
@@ -73,12 +80,20 @@
 
 #define SYS_FUTEX 202
 
+#define SYS_GET_DENTS_64 217
 #define SYS_SET_TID_ADDR 218
 
+#define SYS_CLOCK_SETTIME 227
+#define SYS_CLOCK_GETTIME 228
+
 #define SYS_UNLINK_AT 263
 
 #define SYS_PIPE 293
 
+#define SYS_PERF_EVENT_OPEN 298
+
+#define SYS_KEXEC_FILE_LOAD 320
+
 #define SYS_WRITEV 20
 
 // 与linux不一致的调用，在linux基础上累加